@@ -0,0 +1,337 @@
+//! A host's mounts, connections, watched processes and files at one point in time, as one
+//! JSON document, and what changed between two of them.
+//!
+//! Each section comes from its sensor's one-shot scan, configured as for monitoring, and two
+//! documents compare the way the sensors compare their ticks, so a diff lists the events the
+//! sensors would have fired had they been running in between:
+//!
+//! ```ignore
+//! let mut scanner = BaselineScanner::new().mounts(XMount::new(XMountConfig::default())).files(fs);
+//! let before = scanner.scan().await;
+//! // ... upgrade, incident, reboot ...
+//! let report = baseline::diff(&before, &scanner.scan().await);
+//! println!("{report}");
+//! ```
+//!
+//! The `omnitrace-baseline` binary does the same from the command line.
+
+use filescream::{FileScream, Replaced, events::FileScreamEvent, manifest::Manifest};
+use netpacket::{
+    NetNotify,
+    error::NetNotifyError,
+    events::{ConnKey, NetNotifyEvent},
+};
+use omnitrace_core::clock::{self, SharedClock};
+use procdog::{ProcDog, events::ProcDogEvent};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, io,
+    path::Path,
+    time::UNIX_EPOCH,
+};
+use xmount::{
+    XMount,
+    events::{MountInfo, XMountEvent},
+};
+
+/// Which host a baseline is of.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostInfo {
+    pub hostname: String,
+    pub os: String,
+    pub arch: String,
+    pub kernel: String,
+    /// Changes with every boot. Mount ids are only comparable within one boot.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub boot_id: Option<String>,
+}
+
+impl HostInfo {
+    /// This host, as `/proc/sys/kernel` tells. What cannot be read is left empty.
+    pub fn current() -> Self {
+        let kernel = |name: &str| std::fs::read_to_string(Path::new("/proc/sys/kernel").join(name)).map(|s| s.trim().to_string());
+        Self {
+            hostname: kernel("hostname").unwrap_or_default(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            kernel: kernel("osrelease").unwrap_or_default(),
+            boot_id: kernel("random/boot_id").ok(),
+        }
+    }
+}
+
+/// One sensor's part of a baseline.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Section<T> {
+    /// Unix seconds, when the scan finished.
+    pub taken_at: u64,
+    pub items: T,
+}
+
+/// Watched process names and their PIDs, see [`ProcDog::snapshot`].
+pub type Processes = BTreeMap<String, BTreeSet<i32>>;
+
+/// The document [`BaselineScanner::scan`] produces. Sections of sensors not configured are
+/// left out; `errors` names the sections whose scan failed or was incomplete, with the reason.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct HostBaseline {
+    pub host: HostInfo,
+    /// Unix seconds, when the scan started.
+    pub taken_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mounts: Option<Section<Vec<MountInfo>>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connections: Option<Section<Vec<ConnKey>>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub processes: Option<Section<Processes>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<Section<Manifest>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: BTreeMap<String, String>,
+}
+
+impl HostBaseline {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        serde_json::from_slice(&std::fs::read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Write the document to `path` (atomically, via a temp file).
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let data = serde_json::to_vec_pretty(self).map_err(io::Error::other)?;
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+/// Takes [`HostBaseline`]s with the sensors it is given. The sensors are only scanned, never
+/// started; the scanner can be reused for the baseline to compare with.
+pub struct BaselineScanner {
+    mounts: Option<XMount>,
+    connections: Option<NetNotify>,
+    processes: Option<ProcDog>,
+    files: Option<FileScream>,
+    host: Option<HostInfo>,
+    clock: SharedClock,
+}
+
+impl Default for BaselineScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BaselineScanner {
+    pub fn new() -> Self {
+        Self { mounts: None, connections: None, processes: None, files: None, host: None, clock: clock::system() }
+    }
+
+    /// Record the mount table, as far as `x` does not exclude it.
+    pub fn mounts(mut self, x: XMount) -> Self {
+        self.mounts = Some(x);
+        self
+    }
+
+    /// Record the connection table, as far as the address rules of `net` let it through.
+    pub fn connections(mut self, net: NetNotify) -> Self {
+        self.connections = Some(net);
+        self
+    }
+
+    /// Record the PIDs of the names `dog` watches.
+    pub fn processes(mut self, dog: ProcDog) -> Self {
+        self.processes = Some(dog);
+        self
+    }
+
+    /// Record every file under the roots `fs` watches, hashed as it is configured to.
+    pub fn files(mut self, fs: FileScream) -> Self {
+        self.files = Some(fs);
+        self
+    }
+
+    /// Host metadata to record instead of [`HostInfo::current`].
+    pub fn host(mut self, host: HostInfo) -> Self {
+        self.host = Some(host);
+        self
+    }
+
+    pub fn clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn now(&self) -> u64 {
+        self.clock.now_system().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+    }
+
+    /// Scan every configured sensor once, one after the other.
+    pub async fn scan(&mut self) -> HostBaseline {
+        let mut out = HostBaseline { host: self.host.clone().unwrap_or_else(HostInfo::current), taken_at: self.now(), ..Default::default() };
+        if let Some(x) = &mut self.mounts {
+            match x.snapshot() {
+                Ok(mounts) => out.mounts = Some(Section { taken_at: self.now(), items: mounts }),
+                Err(e) => _ = out.errors.insert("mounts".into(), e.to_string()),
+            }
+        }
+        if let Some(net) = &mut self.connections {
+            // a table that cannot be read leaves the others usable; a missing one (no IPv6) is fine
+            let conns = net.snapshot();
+            let failed: Vec<String> = net
+                .diagnostics()
+                .all()
+                .into_iter()
+                .filter(|d| !matches!(d.last.downcast_ref::<NetNotifyError>(), Some(NetNotifyError::TableMissing { .. })))
+                .map(|d| d.message)
+                .collect();
+            if !failed.is_empty() {
+                out.errors.insert("connections".into(), failed.join("; "));
+            }
+            out.connections = Some(Section { taken_at: self.now(), items: conns });
+        }
+        if let Some(dog) = &mut self.processes {
+            match dog.snapshot().await {
+                Ok(procs) => out.processes = Some(Section { taken_at: self.now(), items: procs }),
+                Err(e) => _ = out.errors.insert("processes".into(), e.to_string()),
+            }
+        }
+        if let Some(fs) = &mut self.files {
+            match fs.manifest().await {
+                Some(manifest) => out.files = Some(Section { taken_at: self.now(), items: manifest }),
+                None => _ = out.errors.insert("files".into(), "scan cancelled".into()),
+            }
+        }
+        out
+    }
+}
+
+/// A host field that differs between two baselines.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct HostChange {
+    pub field: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+/// What changed from one baseline to another, as the events the sensors would fire, each in
+/// the sensor's delivery order. Serializes for machine consumption, displays for people.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BaselineDiff {
+    pub old_taken_at: u64,
+    pub new_taken_at: u64,
+    pub host: Vec<HostChange>,
+    /// The boot ids differ, so mounts were compared without their ids: a remount is a change,
+    /// not a replacement.
+    pub rebooted: bool,
+    pub mounts: Vec<XMountEvent>,
+    pub connections: Vec<NetNotifyEvent>,
+    pub processes: Vec<ProcDogEvent>,
+    pub files: Vec<FileScreamEvent>,
+    /// Sections in only one of the baselines, not compared.
+    pub skipped: Vec<&'static str>,
+}
+
+impl BaselineDiff {
+    /// Nothing changed in the compared sections.
+    pub fn is_empty(&self) -> bool {
+        self.host.is_empty() && self.mounts.is_empty() && self.connections.is_empty() && self.processes.is_empty() && self.files.is_empty()
+    }
+}
+
+/// Compare two baselines, `old` first. Sections missing on either side are skipped.
+pub fn diff(old: &HostBaseline, new: &HostBaseline) -> BaselineDiff {
+    let mut out = BaselineDiff { old_taken_at: old.taken_at, new_taken_at: new.taken_at, ..Default::default() };
+    let fields: [(&'static str, &str, &str); 4] = [
+        ("hostname", &old.host.hostname, &new.host.hostname),
+        ("os", &old.host.os, &new.host.os),
+        ("arch", &old.host.arch, &new.host.arch),
+        ("kernel", &old.host.kernel, &new.host.kernel),
+    ];
+    out.host = fields.into_iter().filter(|(_, o, n)| o != n).map(|(field, o, n)| HostChange { field, old: o.into(), new: n.into() }).collect();
+    out.rebooted = matches!((&old.host.boot_id, &new.host.boot_id), (Some(o), Some(n)) if o != n);
+
+    let mut skip = |name: &'static str, old: bool, new: bool| {
+        if old != new {
+            out.skipped.push(name);
+        }
+    };
+    skip("mounts", old.mounts.is_some(), new.mounts.is_some());
+    skip("connections", old.connections.is_some(), new.connections.is_some());
+    skip("processes", old.processes.is_some(), new.processes.is_some());
+    skip("files", old.files.is_some(), new.files.is_some());
+
+    if let (Some(o), Some(n)) = (&old.mounts, &new.mounts) {
+        out.mounts = if out.rebooted {
+            let anonymous = |mounts: &[MountInfo]| mounts.iter().map(|mi| MountInfo { mount_id: 0, parent_id: 0, ..mi.clone() }).collect::<Vec<_>>();
+            XMount::diff_snapshots(&anonymous(&o.items), &anonymous(&n.items))
+        } else {
+            XMount::diff_snapshots(&o.items, &n.items)
+        };
+    }
+    if let (Some(o), Some(n)) = (&old.connections, &new.connections) {
+        out.connections = NetNotify::diff_snapshots(&o.items, &n.items);
+    }
+    if let (Some(o), Some(n)) = (&old.processes, &new.processes) {
+        out.processes = ProcDog::diff_snapshots(&o.items, &n.items);
+    }
+    if let (Some(o), Some(n)) = (&old.files, &new.files) {
+        out.files = FileScream::diff_manifests(&o.items, &n.items, Replaced::default());
+    }
+    out
+}
+
+impl fmt::Display for BaselineDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in &self.host {
+            writeln!(f, "host {}: {} -> {}", c.field, c.old, c.new)?;
+        }
+        if self.rebooted {
+            writeln!(f, "host rebooted")?;
+        }
+        for ev in &self.mounts {
+            let info = match ev {
+                XMountEvent::Mounted { info, .. } | XMountEvent::AutomountArmed { info, .. } => format!(" ({} {})", info.fstype, info.source),
+                XMountEvent::Changed { old, new, .. } if old.mount_opts != new.mount_opts => format!(" ({} -> {})", old.mount_opts, new.mount_opts),
+                _ => String::new(),
+            };
+            writeln!(f, "{:<24} {}{info}", ev.topic(), ev.target().display())?;
+        }
+        for ev in &self.connections {
+            if let NetNotifyEvent::Opened { conn, .. } | NetNotifyEvent::Closed { conn, .. } = ev {
+                let addr = |dec: &Option<String>, raw: &str| dec.clone().unwrap_or_else(|| raw.to_string());
+                let state = conn.state_dec.as_deref().map(|s| format!(" {s}")).unwrap_or_default();
+                writeln!(
+                    f,
+                    "{:<24} {} {} -> {}{state}",
+                    ev.topic(),
+                    conn.proto,
+                    addr(&conn.local_dec, &conn.local),
+                    addr(&conn.remote_dec, &conn.remote)
+                )?;
+            }
+        }
+        for ev in &self.processes {
+            match ev {
+                ProcDogEvent::Appeared { name, pid, .. } | ProcDogEvent::Disappeared { name, pid, .. } => {
+                    writeln!(f, "{:<24} {name} [{pid}]", ev.topic())?
+                }
+                ProcDogEvent::Missing { name, .. } => writeln!(f, "{:<24} {name}", ev.topic())?,
+                ProcDogEvent::Deviation { name, deviation, .. } => writeln!(f, "{:<24} {name}: {deviation}", ev.topic())?,
+                ProcDogEvent::GroupGone { name, pgid, .. } => writeln!(f, "{:<24} {name} [{pgid}]", ev.topic())?,
+            }
+        }
+        for ev in &self.files {
+            if let FileScreamEvent::Created { path, .. } | FileScreamEvent::Changed { path, .. } | FileScreamEvent::Removed { path, .. } = ev {
+                writeln!(f, "{:<24} {}", ev.topic(), path.display())?;
+            }
+        }
+        for name in &self.skipped {
+            writeln!(f, "{name}: in one baseline only, not compared")?;
+        }
+        if self.is_empty() {
+            writeln!(f, "no changes")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+use crate::baseline::{self, BaselineScanner, HostBaseline, HostInfo};
+use filescream::{FileScream, events::FileScreamEvent};
+use netpacket::{NetNotify, NetNotifyConfig, events::NetNotifyEvent};
+use procdog::{ProcDog, backends::script::ScriptBackend, events::ProcDogEvent};
+use std::path::{Path, PathBuf};
+use xmount::{XMount, XMountConfig, events::XMountEvent};
+
+const ROOT: &str = "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw";
+const DATA: &str = "40 22 8:17 / /data rw,relatime - ext4 /dev/sdb1 rw";
+const DATA_RO: &str = "40 22 8:17 / /data ro,relatime - ext4 /dev/sdb1 rw";
+const BACKUP: &str = "41 22 8:33 / /backup rw,relatime - xfs /dev/sdc1 rw";
+
+// 0.0.0.0:22 listening, 10.0.0.5:40000 -> 93.184.216.34:443 and 10.0.0.5:40002 -> 8.8.8.8:53
+const SSH: (&str, &str, &str) = ("00000000:0016", "00000000:0000", "0A");
+const HTTPS: (&str, &str, &str) = ("0500000A:9C40", "22D8B85D:01BB", "01");
+const DNS: (&str, &str, &str) = ("0500000A:9C42", "08080808:0035", "01");
+
+/// A host laid out under a temp dir: `mountinfo`, `net/tcp` and files under `files`.
+struct Fixture(PathBuf);
+
+impl Fixture {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("bridges-baseline-ut-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("net")).unwrap();
+        std::fs::create_dir_all(dir.join("files/etc")).unwrap();
+        Self(dir)
+    }
+
+    fn mounts(&self, lines: &[&str]) {
+        std::fs::write(self.0.join("mountinfo"), lines.iter().map(|l| format!("{l}\n")).collect::<String>()).unwrap();
+    }
+
+    fn tcp(&self, rows: &[(&str, &str, &str)]) {
+        let mut txt = String::from("  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n");
+        for (i, (local, remote, st)) in rows.iter().enumerate() {
+            txt.push_str(&format!("  {i}: {local} {remote} {st} 00000000:00000000 00:00000000 00000000  1000        0 {}\n", 1000 + i));
+        }
+        std::fs::write(self.0.join("net/tcp"), txt).unwrap();
+    }
+
+    fn file(&self, rel: &str) -> PathBuf {
+        self.0.join("files").join(rel)
+    }
+
+    /// A scanner over the fixture, with `procs` as the process lists of its scans in turn.
+    fn scanner(&self, procs: Vec<Vec<(i32, String)>>, boot_id: &str) -> BaselineScanner {
+        let mut dog = ProcDog::new(None);
+        dog.set_backend(ScriptBackend::new(procs));
+        dog.watch("sshd");
+        dog.watch("cron");
+        let mut fs = FileScream::new(None);
+        fs.watch(self.0.join("files")).unwrap();
+        BaselineScanner::new()
+            .host(HostInfo {
+                hostname: "web1".into(),
+                os: "linux".into(),
+                arch: "x86_64".into(),
+                kernel: "6.1.0".into(),
+                boot_id: Some(boot_id.into()),
+            })
+            .mounts(XMount::new(XMountConfig::default().mountinfo_path(self.0.join("mountinfo"))))
+            .connections(NetNotify::new(Some(NetNotifyConfig::default().proc_net(self.0.join("net")))))
+            .processes(dog)
+            .files(fs)
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn procs(list: &[(i32, &str)]) -> Vec<(i32, String)> {
+    list.iter().map(|(pid, name)| (*pid, name.to_string())).collect()
+}
+
+fn paths(events: &[FileScreamEvent]) -> Vec<(&'static str, &Path)> {
+    events
+        .iter()
+        .filter_map(|ev| match ev {
+            FileScreamEvent::Created { rel_path, .. } => Some(("created", rel_path.as_path())),
+            FileScreamEvent::Changed { rel_path, .. } => Some(("changed", rel_path.as_path())),
+            FileScreamEvent::Removed { rel_path, .. } => Some(("removed", rel_path.as_path())),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn regenerated_baseline_diffs_as_the_sensors_would_report() {
+    let fx = Fixture::new("diff");
+    fx.mounts(&[ROOT, DATA]);
+    fx.tcp(&[SSH, HTTPS]);
+    std::fs::write(fx.file("etc/passwd"), "root:x:0:0\n").unwrap();
+    std::fs::write(fx.file("etc/motd"), "hello\n").unwrap();
+    let mut scanner =
+        fx.scanner(vec![procs(&[(1, "init"), (10, "sshd"), (20, "cron")]), procs(&[(1, "init"), (10, "sshd"), (11, "sshd")])], "boot-a");
+
+    let before = scanner.scan().await;
+    assert!(before.errors.is_empty(), "{:?}", before.errors);
+    assert_eq!(before.mounts.as_ref().unwrap().items.len(), 2);
+    assert_eq!(before.connections.as_ref().unwrap().items.len(), 2);
+    assert_eq!(before.files.as_ref().unwrap().items.files.len(), 2);
+
+    fx.mounts(&[ROOT, DATA_RO, BACKUP]);
+    fx.tcp(&[SSH, DNS]);
+    std::fs::write(fx.file("etc/passwd"), "root:x:0:0\nmallory:x:0:0\n").unwrap();
+    std::fs::remove_file(fx.file("etc/motd")).unwrap();
+    std::fs::write(fx.file("etc/cron.d"), "* * * * * root /tmp/x\n").unwrap();
+    let after = scanner.scan().await;
+
+    let report = baseline::diff(&before, &after);
+    assert!(!report.rebooted && report.host.is_empty() && report.skipped.is_empty());
+    let mounts: Vec<_> = report.mounts.iter().map(|ev| (ev.topic(), ev.target().to_path_buf())).collect();
+    assert_eq!(mounts, vec![("mount.mounted", PathBuf::from("/backup")), ("mount.changed.remount", PathBuf::from("/data"))]);
+
+    let conns: Vec<_> = report
+        .connections
+        .iter()
+        .map(|ev| match ev {
+            NetNotifyEvent::Opened { conn, offline, .. } | NetNotifyEvent::Closed { conn, offline, .. } => {
+                assert!(offline);
+                (ev.topic(), conn.remote_dec.clone().unwrap())
+            }
+            other => panic!("{other:?}"),
+        })
+        .collect();
+    assert_eq!(conns, vec![("net.conn.opened", "8.8.8.8:53".to_string()), ("net.conn.closed", "93.184.216.34:443".to_string())]);
+
+    let procs: Vec<_> = report.processes.iter().map(|ev| (ev.topic(), ev.name().to_string())).collect();
+    assert_eq!(procs, vec![("proc.disappeared", "cron".into()), ("proc.missing", "cron".into()), ("proc.appeared", "sshd".into())]);
+    assert!(matches!(report.processes[2], ProcDogEvent::Appeared { pid: 11, .. }));
+
+    let files = paths(&report.files);
+    assert_eq!(files, vec![("created", Path::new("etc/cron.d")), ("removed", Path::new("etc/motd")), ("changed", Path::new("etc/passwd"))]);
+
+    let text = report.to_string();
+    assert!(text.contains("/backup (xfs /dev/sdc1)"), "{text}");
+    assert!(text.contains("(rw,relatime -> ro,relatime)"), "{text}");
+    assert!(text.contains("cron [20]"), "{text}");
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["files"].as_array().unwrap().len(), 3);
+
+    // nothing changed since
+    let again = scanner.scan().await;
+    let report = baseline::diff(&after, &again);
+    assert!(report.is_empty(), "{report}");
+    assert_eq!(report.to_string(), "no changes\n");
+}
+
+#[tokio::test]
+async fn saved_documents_diff_like_fresh_ones() {
+    let fx = Fixture::new("saved");
+    fx.mounts(&[ROOT, DATA]);
+    fx.tcp(&[SSH, HTTPS]);
+    std::fs::write(fx.file("etc/passwd"), "root:x:0:0\n").unwrap();
+    let mut scanner = fx.scanner(vec![procs(&[(10, "sshd")]), procs(&[(10, "sshd"), (30, "cron")])], "boot-a");
+
+    let before = scanner.scan().await;
+    let path = fx.0.join("before.json");
+    before.save(&path).unwrap();
+    fx.tcp(&[SSH]);
+    std::fs::write(fx.file("etc/shadow"), "root:!:1\n").unwrap();
+    let after = scanner.scan().await;
+
+    let loaded = HostBaseline::load(&path).unwrap();
+    assert_eq!(serde_json::to_value(&loaded).unwrap(), serde_json::to_value(&before).unwrap());
+    let (fresh, saved) = (baseline::diff(&before, &after), baseline::diff(&loaded, &after));
+    assert_eq!(serde_json::to_value(&fresh).unwrap(), serde_json::to_value(&saved).unwrap());
+    assert_eq!((saved.connections.len(), saved.processes.len(), saved.files.len()), (1, 1, 1));
+    assert!(saved.mounts.is_empty());
+}
+
+#[tokio::test]
+async fn mounts_compare_without_ids_across_a_reboot() {
+    let fx = Fixture::new("reboot");
+    fx.mounts(&[ROOT, DATA]);
+    fx.tcp(&[SSH]);
+    let before = fx.scanner(vec![procs(&[(10, "sshd")])], "boot-a").scan().await;
+
+    // same mounts with new ids after the reboot, /data now read-only
+    fx.mounts(&["23 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw", "47 23 8:17 / /data ro,relatime - ext4 /dev/sdb1 rw"]);
+    let after = fx.scanner(vec![procs(&[(10, "sshd")])], "boot-b").scan().await;
+
+    let report = baseline::diff(&before, &after);
+    assert!(report.rebooted);
+    assert_eq!(report.mounts.len(), 1, "{report}");
+    assert!(matches!(&report.mounts[0], XMountEvent::Changed { target, .. } if target == Path::new("/data")));
+
+    // within one boot, a new id is a replacement
+    let mut same_boot = after.clone();
+    same_boot.host.boot_id = before.host.boot_id.clone();
+    let report = baseline::diff(&before, &same_boot);
+    assert!(report.mounts.iter().any(|ev| matches!(ev, XMountEvent::Unmounted { reason: None, .. })), "{report}");
+}
+
+#[tokio::test]
+async fn sections_in_one_baseline_only_are_skipped() {
+    let fx = Fixture::new("skipped");
+    fx.mounts(&[ROOT]);
+    fx.tcp(&[SSH]);
+    let full = fx.scanner(vec![procs(&[(10, "sshd")])], "boot-a").scan().await;
+    let mut partial = full.clone();
+    partial.files = None;
+    partial.host.kernel = "6.2.0".into();
+
+    let report = baseline::diff(&full, &partial);
+    assert_eq!(report.skipped, vec!["files"]);
+    assert_eq!(report.host.len(), 1);
+    assert!(!report.is_empty());
+    assert!(report.to_string().contains("host kernel: 6.1.0 -> 6.2.0"));
+}
@@ -0,0 +1,114 @@
+use filescream::FileScream;
+use netpacket::{NetNotify, NetNotifyConfig};
+use omnitrace_bridges::baseline::{self, BaselineScanner, HostBaseline};
+use procdog::{ProcDog, backends::linuxps::LinuxPsBackend};
+use std::path::Path;
+use xmount::{XMount, XMountConfig};
+
+const USAGE: &str = "usage: omnitrace-baseline snapshot [--out FILE] [--no-mounts] [--mountinfo FILE] [--no-connections] [--proc-net DIR]
+                                   [--process NAME]... [--proc DIR] [--files ROOT]... [--ignore GLOB]...
+       omnitrace-baseline diff OLD.json NEW.json [--json]
+
+diff exits with 1 if the baselines differ";
+
+fn usage() -> ! {
+    eprintln!("{USAGE}");
+    std::process::exit(2);
+}
+
+fn fail(what: &str, e: impl std::fmt::Display) -> ! {
+    eprintln!("{what}: {e}");
+    std::process::exit(2);
+}
+
+async fn snapshot(mut args: impl Iterator<Item = String>) {
+    let (mut out, mut mounts, mut mountinfo, mut conns, mut proc_net) = (None, true, None, true, None);
+    let (mut names, mut proc_root, mut roots, mut ignores) = (Vec::new(), None, Vec::new(), Vec::new());
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--out" => out = Some(value()),
+            "--no-mounts" => mounts = false,
+            "--mountinfo" => mountinfo = Some(value()),
+            "--no-connections" => conns = false,
+            "--proc-net" => proc_net = Some(value()),
+            "--process" => names.push(value()),
+            "--proc" => proc_root = Some(value()),
+            "--files" => roots.push(value()),
+            "--ignore" => ignores.push(value()),
+            _ => usage(),
+        }
+    }
+
+    let mut scanner = BaselineScanner::new();
+    if mounts {
+        let cfg = XMountConfig::default();
+        scanner = scanner.mounts(XMount::new(match mountinfo {
+            Some(p) => cfg.mountinfo_path(p),
+            None => cfg,
+        }));
+    }
+    if conns {
+        let cfg = NetNotifyConfig::default();
+        scanner = scanner.connections(NetNotify::new(Some(match proc_net {
+            Some(dir) => cfg.proc_net(dir),
+            None => cfg,
+        })));
+    }
+    if !names.is_empty() {
+        let mut dog = ProcDog::new(None);
+        dog.set_backend(LinuxPsBackend::at(proc_root.unwrap_or_else(|| "/proc".into())));
+        names.into_iter().for_each(|n| dog.watch(n));
+        scanner = scanner.processes(dog);
+    }
+    if !roots.is_empty() {
+        let mut fs = FileScream::new(None);
+        for root in roots {
+            fs.watch(&root).unwrap_or_else(|e| fail(&root, e));
+        }
+        ignores.into_iter().for_each(|g| fs.ignore(g));
+        scanner = scanner.files(fs);
+    }
+
+    let doc = scanner.scan().await;
+    for (section, e) in &doc.errors {
+        eprintln!("{section}: {e}");
+    }
+    match out {
+        Some(path) => doc.save(Path::new(&path)).unwrap_or_else(|e| fail(&path, e)),
+        None => println!("{}", serde_json::to_string_pretty(&doc).unwrap_or_default()),
+    }
+}
+
+fn diff(args: impl Iterator<Item = String>) {
+    let (mut paths, mut json) = (Vec::new(), false);
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            _ if !arg.starts_with("--") => paths.push(arg),
+            _ => usage(),
+        }
+    }
+    let [old, new] = paths.as_slice() else { usage() };
+    let load = |p: &String| HostBaseline::load(Path::new(p)).unwrap_or_else(|e| fail(p, e));
+    let report = baseline::diff(&load(old), &load(new));
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default());
+    } else {
+        print!("{report}");
+    }
+    if !report.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some("snapshot") => snapshot(args).await,
+        Some("diff") => diff(args),
+        _ => usage(),
+    }
+}
@@ -0,0 +1,194 @@
+use filescream::FileScream;
+use netpacket::{NetNotify, NetNotifyConfig};
+use omnitrace_bridges::top::{self, Dashboard, EventLog, View};
+use omnitrace_core::{callbacks::CallbackHub, sensor::spawn_sensor, units};
+use procdog::{ProcDog, backends::linuxps::LinuxPsBackend};
+use std::{
+    io::{self, Read, Write},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::mpsc;
+use xmount::{XMount, XMountConfig};
+
+const USAGE: &str = "usage: omnitrace-top [--mount TARGET]... [--mountinfo FILE] [--process NAME]... [--proc DIR]
+                     [--files ROOT]... [--ignore GLOB]... [--net] [--proc-net DIR] [--refresh DURATION]
+
+keys: 1-9 show one sensor, 0 all, tab next sensor, p pause scrolling, q quit";
+
+fn usage() -> ! {
+    eprintln!("{USAGE}");
+    std::process::exit(2);
+}
+
+fn fail(what: &str, e: impl std::fmt::Display) -> ! {
+    eprintln!("{what}: {e}");
+    std::process::exit(2);
+}
+
+/// Puts stdin in non-canonical, no-echo mode for single key presses, restored on drop.
+struct RawMode(Option<libc::termios>);
+
+impl RawMode {
+    fn enable() -> Self {
+        // SAFETY: termios is plain data, filled by tcgetattr before it is used
+        unsafe {
+            let mut saved: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut saved) != 0 {
+                return Self(None);
+            }
+            let mut raw = saved;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw);
+            Self(Some(saved))
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if let Some(saved) = &self.0 {
+            // SAFETY: restores the attributes tcgetattr returned
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved) };
+        }
+    }
+}
+
+/// Terminal columns and rows, 80x24 if stdout is not a terminal.
+fn term_size() -> (usize, usize) {
+    // SAFETY: winsize is plain data, filled by the ioctl
+    unsafe {
+        let mut ws: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) == 0 && ws.ws_col > 0 {
+            return (ws.ws_col.into(), ws.ws_row.into());
+        }
+    }
+    (80, 24)
+}
+
+#[tokio::main]
+async fn main() {
+    let (mut targets, mut mountinfo, mut names, mut proc_root) = (Vec::new(), None, Vec::new(), None);
+    let (mut roots, mut ignores, mut net, mut proc_net, mut refresh) = (Vec::new(), Vec::new(), false, None, Duration::from_secs(1));
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--mount" => targets.push(value()),
+            "--mountinfo" => mountinfo = Some(value()),
+            "--process" => names.push(value()),
+            "--proc" => proc_root = Some(value()),
+            "--files" => roots.push(value()),
+            "--ignore" => ignores.push(value()),
+            "--net" => net = true,
+            "--proc-net" => proc_net = Some(value()),
+            // plain numbers are milliseconds, as before
+            "--refresh" => match units::parse_duration_or(&value(), Duration::from_millis(1)) {
+                Ok(d) if !d.is_zero() => refresh = d,
+                Ok(_) => usage(),
+                Err(e) => {
+                    eprintln!("--refresh: {e}");
+                    std::process::exit(2);
+                }
+            },
+            _ => usage(),
+        }
+    }
+
+    // results are not consumed here; the channel only shows the hubs' queue depth
+    let (tx, _rx) = mpsc::channel(0xfff);
+    let log = EventLog::default();
+    let mut dash = Dashboard::new(log.clone());
+    let mut handles = Vec::new();
+
+    if !targets.is_empty() {
+        let cfg = XMountConfig::default();
+        let mut xm = XMount::new(match mountinfo {
+            Some(p) => cfg.mountinfo_path(p),
+            None => cfg,
+        });
+        targets.iter().for_each(|t| xm.add(t));
+        let mut hub = CallbackHub::new();
+        hub.add(log.recorder("xmount"));
+        hub.set_result_channel(tx.clone());
+        let hub = Arc::new(hub);
+        dash = dash.pane(top::xmount_pane(&xm, hub.clone()));
+        handles.push(spawn_sensor(xm, hub).0);
+    }
+    if !names.is_empty() {
+        let mut dog = ProcDog::new(None);
+        dog.set_backend(LinuxPsBackend::at(proc_root.unwrap_or_else(|| "/proc".into())));
+        names.into_iter().for_each(|n| dog.watch(n));
+        let mut hub = CallbackHub::new();
+        hub.add(log.recorder("procdog"));
+        hub.set_result_channel(tx.clone());
+        let hub = Arc::new(hub);
+        dash = dash.pane(top::procdog_pane(&dog, hub.clone()));
+        handles.push(spawn_sensor(dog, hub).0);
+    }
+    if !roots.is_empty() {
+        let mut fs = FileScream::new(None);
+        for root in &roots {
+            fs.watch(root).unwrap_or_else(|e| fail(root, e));
+        }
+        ignores.into_iter().for_each(|g| fs.ignore(g));
+        let mut hub = CallbackHub::new();
+        hub.add(log.recorder("filescream"));
+        hub.set_result_channel(tx.clone());
+        let hub = Arc::new(hub);
+        dash = dash.pane(top::filescream_pane(&fs, hub.clone()));
+        handles.push(spawn_sensor(fs, hub).0);
+    }
+    if net {
+        let cfg = NetNotifyConfig::default();
+        let nn = NetNotify::new(Some(match proc_net {
+            Some(dir) => cfg.proc_net(dir),
+            None => cfg,
+        }));
+        let mut hub = CallbackHub::new();
+        hub.add(log.recorder("netnotify"));
+        hub.set_result_channel(tx.clone());
+        let hub = Arc::new(hub);
+        dash = dash.pane(top::netnotify_pane(&nn, hub.clone()));
+        handles.push(spawn_sensor(nn, hub).0);
+    }
+    if handles.is_empty() {
+        usage();
+    }
+
+    let (keys_tx, mut keys) = mpsc::unbounded_channel();
+    let _raw = RawMode::enable();
+    std::thread::spawn(move || {
+        for b in io::stdin().lock().bytes() {
+            if b.map(|b| keys_tx.send(b)).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut view = View::default();
+    let mut frame = dash.sample();
+    let mut tick = tokio::time::interval(refresh);
+    let mut out = io::stdout();
+    loop {
+        let (width, height) = term_size();
+        let lines = top::render(&frame, &view, width, height);
+        let _ = write!(out, "\x1b[H\x1b[2J{}", lines.join("\r\n"));
+        let _ = out.flush();
+
+        tokio::select! {
+            _ = tick.tick() => frame = dash.sample(),
+            Some(k) = keys.recv() => {
+                if !view.key(k, &frame) {
+                    break;
+                }
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    let _ = write!(out, "\x1b[H\x1b[2J");
+    handles.iter().for_each(|h| h.shutdown());
+}
@@ -0,0 +1,54 @@
+use bitflags::bitflags;
+use netpacket::events::ConnKey;
+use omnitrace_core::{
+    intern::Interned,
+    topics::{self, Topic, Topics},
+};
+use serde::{Deserialize, Serialize};
+
+/// Process owning a socket, as seen when the connection was attributed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcInfo {
+    pub pid: i32,
+    pub comm: Interned,
+    pub cmdline: String,
+    pub uid: u32,
+    /// ProcDog watch name the process is tracked under, if any.
+    #[serde(default)]
+    pub watched: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ProcConnEvent {
+    ProcessConnection { proc_info: ProcInfo, conn: ConnKey },
+}
+
+bitflags! {
+    #[derive(Copy, Clone, Debug)]
+    pub struct ProcConnMask: u64 {
+        const PROCESS_CONNECTION = 0b0001;
+    }
+}
+
+impl ProcConnEvent {
+    pub fn mask(&self) -> ProcConnMask {
+        match self {
+            ProcConnEvent::ProcessConnection { .. } => ProcConnMask::PROCESS_CONNECTION,
+        }
+    }
+
+    /// Dotted topic, see [`omnitrace_core::topics`].
+    pub fn topic(&self) -> &'static str {
+        topics::topic_of(self)
+    }
+}
+
+impl Topics for ProcConnEvent {
+    const TOPICS: &'static [Topic] = &[Topic::new("proc.connection", ProcConnMask::PROCESS_CONNECTION.bits())];
+
+    fn topic_index(&self) -> usize {
+        match self {
+            ProcConnEvent::ProcessConnection { .. } => 0,
+        }
+    }
+}
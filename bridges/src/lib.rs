@@ -0,0 +1,14 @@
+//! Components joining the events of several sensors into enriched events of their own.
+
+pub mod baseline;
+pub mod events;
+pub mod prelude;
+pub mod procconn;
+pub mod top;
+
+#[cfg(test)]
+mod baseline_ut;
+#[cfg(test)]
+mod procconn_ut;
+#[cfg(test)]
+mod top_ut;
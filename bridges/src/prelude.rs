@@ -0,0 +1,6 @@
+//! `use omnitrace_bridges::prelude::*;` brings in the bridges, their rules and events,
+//! and the core prelude (callbacks, hub, `spawn_sensor`).
+
+pub use crate::events::{ProcConnEvent, ProcConnMask, ProcInfo};
+pub use crate::procconn::{ProcConnBridge, ProcConnRule, ProcFs, ProcResolver};
+pub use omnitrace_core::prelude::*;
@@ -0,0 +1,304 @@
+use crate::events::{ProcConnEvent, ProcInfo};
+use async_trait::async_trait;
+use glob::{Pattern, PatternError};
+use netpacket::{
+    events::{ConnKey, NetNotifyEvent, NetNotifyMask},
+    netutil::{is_hostish, is_ipish},
+};
+use omnitrace_core::{
+    callbacks::{Callback, CallbackHub, CallbackResult},
+    intern::Interned,
+};
+use procdog::ProcDogState;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Resolves the process behind a connection.
+pub trait ProcResolver: Send + Sync {
+    /// PID owning the socket of `conn`, if it can still be found.
+    fn owner(&self, conn: &ConnKey) -> Option<i32>;
+
+    fn info(&self, pid: i32) -> Option<ProcInfo>;
+}
+
+/// Resolver reading procfs: the socket inode from `net/<proto>`, then the
+/// process holding `socket:[<inode>]` among `<pid>/fd/*`.
+///
+/// The fd scan walks every process, so it only runs for connections that
+/// already matched the connection part of some rule, and off the async runtime.
+pub struct ProcFs {
+    root: PathBuf,
+}
+
+impl Default for ProcFs {
+    fn default() -> Self {
+        Self::new("/proc")
+    }
+}
+
+impl ProcFs {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self { root: root.as_ref().to_path_buf() }
+    }
+
+    fn inode(&self, conn: &ConnKey) -> Option<String> {
+        let txt = std::fs::read_to_string(self.root.join("net").join(&conn.proto)).ok()?;
+        txt.lines().skip(1).find_map(|line| {
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            (cols.len() > 9 && cols[1] == conn.local && cols[2] == conn.remote && cols[9] != "0").then(|| cols[9].to_string())
+        })
+    }
+}
+
+impl ProcResolver for ProcFs {
+    fn owner(&self, conn: &ConnKey) -> Option<i32> {
+        let target = PathBuf::from(format!("socket:[{}]", self.inode(conn)?));
+        for entry in std::fs::read_dir(&self.root).ok()?.flatten() {
+            let Some(pid) = entry.file_name().to_str().and_then(|s| s.parse::<i32>().ok()) else {
+                continue;
+            };
+            let Ok(fds) = std::fs::read_dir(entry.path().join("fd")) else {
+                continue; // gone, or not ours to look at
+            };
+            if fds.flatten().any(|fd| std::fs::read_link(fd.path()).is_ok_and(|l| l == target)) {
+                return Some(pid);
+            }
+        }
+        None
+    }
+
+    fn info(&self, pid: i32) -> Option<ProcInfo> {
+        let dir = self.root.join(pid.to_string());
+        let comm = Interned::new(std::fs::read_to_string(dir.join("comm")).ok()?.trim_end());
+        let cmdline = std::fs::read(dir.join("cmdline"))
+            .map(|raw| raw.split(|b| *b == 0).filter(|a| !a.is_empty()).map(|a| String::from_utf8_lossy(a)).collect::<Vec<_>>().join(" "))
+            .unwrap_or_default();
+        let uid = std::fs::read_to_string(dir.join("status"))
+            .ok()?
+            .lines()
+            .find_map(|l| l.strip_prefix("Uid:"))
+            .and_then(|ids| ids.split_whitespace().next())
+            .and_then(|uid| uid.parse().ok())?;
+        Some(ProcInfo { pid, comm, cmdline, uid, watched: None })
+    }
+}
+
+/// One join: a process selector (ProcDog-style globs) and connection patterns
+/// (same syntax as `NetNotify::add()`). Unset selectors match anything.
+///
+/// ```json
+/// { "name": "curl", "uid": "10??", "conns": ["*:443", "*.example.com"] }
+/// { "name": "sshd", "watched": true, "conns": ["tcp * *:22"] }
+/// ```
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ProcConnRule {
+    /// Glob on the process name (`comm`).
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Glob on the space-joined command line.
+    #[serde(default)]
+    pub cmdline: Option<String>,
+    /// Glob on the numeric uid.
+    #[serde(default)]
+    pub uid: Option<String>,
+    /// Only processes ProcDog currently tracks.
+    #[serde(default)]
+    pub watched: bool,
+    #[serde(default)]
+    pub conns: Vec<String>,
+}
+
+impl ProcConnRule {
+    pub fn name<S: Into<String>>(mut self, glob: S) -> Self {
+        self.name = Some(glob.into());
+        self
+    }
+
+    pub fn cmdline<S: Into<String>>(mut self, glob: S) -> Self {
+        self.cmdline = Some(glob.into());
+        self
+    }
+
+    pub fn uid<S: Into<String>>(mut self, glob: S) -> Self {
+        self.uid = Some(glob.into());
+        self
+    }
+
+    /// Require the process to be in ProcDog's watched state, see [`ProcConnBridge::procdog`].
+    pub fn watched(mut self, on: bool) -> Self {
+        self.watched = on;
+        self
+    }
+
+    pub fn conn<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.conns.push(pattern.into());
+        self
+    }
+}
+
+#[derive(Default)]
+struct ConnFilter {
+    any: Vec<Pattern>,
+    ip: Vec<Pattern>,
+    host: Vec<Pattern>,
+}
+
+impl ConnFilter {
+    fn new(patterns: &[String]) -> Result<Self, PatternError> {
+        let mut f = Self::default();
+        for pat in patterns {
+            let p = Pattern::new(pat)?;
+            if is_hostish(pat) {
+                f.host.push(p);
+            } else if is_ipish(pat) {
+                f.ip.push(p);
+            } else {
+                f.any.push(p);
+            }
+        }
+        Ok(f)
+    }
+
+    /// Empty filter matches everything, otherwise any pattern has to.
+    fn matches(&self, c: &ConnKey) -> bool {
+        if self.any.is_empty() && self.ip.is_empty() && self.host.is_empty() {
+            return true;
+        }
+
+        let proto = c.proto.strip_suffix('6').unwrap_or(&c.proto);
+        let local = c.local_dec.as_deref().unwrap_or(&c.local);
+        let remote = c.remote_dec.as_deref().unwrap_or(&c.remote);
+        let simple = format!("{proto} {local} {remote}");
+        let remote_ip = c.remote_addr.map(|a| a.ip().to_string()).unwrap_or_default();
+
+        self.any.iter().any(|p| p.matches(&simple) || p.matches(remote))
+            || self.ip.iter().any(|p| p.matches(&remote_ip) || p.matches(remote))
+            || [&c.remote_host, &c.remote_sni].into_iter().flatten().any(|h| self.host.iter().any(|p| p.matches(h)))
+    }
+}
+
+struct CompiledRule {
+    name: Option<Pattern>,
+    cmdline: Option<Pattern>,
+    uid: Option<Pattern>,
+    watched: bool,
+    conns: ConnFilter,
+}
+
+impl CompiledRule {
+    fn new(rule: &ProcConnRule) -> Result<Self, PatternError> {
+        let glob = |g: &Option<String>| g.as_deref().map(Pattern::new).transpose();
+        Ok(Self {
+            name: glob(&rule.name)?,
+            cmdline: glob(&rule.cmdline)?,
+            uid: glob(&rule.uid)?,
+            watched: rule.watched,
+            conns: ConnFilter::new(&rule.conns)?,
+        })
+    }
+
+    fn matches_proc(&self, p: &ProcInfo) -> bool {
+        self.name.as_ref().is_none_or(|g| g.matches(&p.comm))
+            && self.cmdline.as_ref().is_none_or(|g| g.matches(&p.cmdline))
+            && self.uid.as_ref().is_none_or(|g| g.matches(&p.uid.to_string()))
+            && (!self.watched || p.watched.is_some())
+    }
+}
+
+/// Joins NetNotify `Opened` events with the owning process and fires one
+/// `ProcessConnection` per connection matching a rule into its own hub.
+///
+/// Register it as a NetNotify callback:
+///
+/// ```ignore
+/// let mut bridge = ProcConnBridge::new(proc_hub.clone()).procdog(dog.state_handle());
+/// bridge.add_rule(ProcConnRule::default().name("curl").conn("*:443"))?;
+/// net_hub.add(bridge);
+/// ```
+pub struct ProcConnBridge {
+    rules: Vec<CompiledRule>,
+    resolver: Arc<dyn ProcResolver>,
+    procdog: Option<ProcDogState>,
+    hub: Arc<CallbackHub<ProcConnEvent>>,
+}
+
+impl ProcConnBridge {
+    pub fn new(hub: Arc<CallbackHub<ProcConnEvent>>) -> Self {
+        Self { rules: Vec::new(), resolver: Arc::new(ProcFs::default()), procdog: None, hub }
+    }
+
+    pub fn resolver<R: ProcResolver + 'static>(mut self, resolver: R) -> Self {
+        self.resolver = Arc::new(resolver);
+        self
+    }
+
+    /// ProcDog state used to fill `ProcInfo.watched` and for `watched` rules.
+    /// Without it, `watched` rules never match.
+    pub fn procdog(mut self, state: ProcDogState) -> Self {
+        self.procdog = Some(state);
+        self
+    }
+
+    /// Add a rule. A connection is reported once, however many rules it matches.
+    pub fn add_rule(&mut self, rule: ProcConnRule) -> Result<(), PatternError> {
+        self.rules.push(CompiledRule::new(&rule)?);
+        Ok(())
+    }
+
+    /// Attribute `conn` to its process and return the joined event, if any rule matches.
+    /// Resolving the owner reads procfs, so this blocks; the callback runs it off the runtime.
+    pub fn join(&self, conn: &ConnKey) -> Option<ProcConnEvent> {
+        if !self.wants(conn) {
+            return None;
+        }
+        self.report(conn, lookup(&*self.resolver, conn)?)
+    }
+
+    /// Some rule matches the connection part of `conn`.
+    fn wants(&self, conn: &ConnKey) -> bool {
+        self.rules.iter().any(|r| r.conns.matches(conn))
+    }
+
+    fn report(&self, conn: &ConnKey, mut proc_info: ProcInfo) -> Option<ProcConnEvent> {
+        proc_info.watched = self.procdog.as_ref().and_then(|s| s.name_of(proc_info.pid));
+        self.rules
+            .iter()
+            .any(|r| r.conns.matches(conn) && r.matches_proc(&proc_info))
+            .then(|| ProcConnEvent::ProcessConnection { proc_info, conn: conn.clone() })
+    }
+}
+
+fn lookup(resolver: &dyn ProcResolver, conn: &ConnKey) -> Option<ProcInfo> {
+    resolver.info(resolver.owner(conn)?)
+}
+
+#[async_trait]
+impl Callback<NetNotifyEvent> for ProcConnBridge {
+    fn mask(&self) -> u64 {
+        NetNotifyMask::OPENED.bits()
+    }
+
+    async fn call(&self, ev: &NetNotifyEvent) -> Option<CallbackResult> {
+        let NetNotifyEvent::Opened { conn, .. } = ev else {
+            return None;
+        };
+        if !self.wants(conn) {
+            return None;
+        }
+        let (resolver, key) = (self.resolver.clone(), conn.clone());
+        let proc_info = match tokio::task::spawn_blocking(move || lookup(&*resolver, &key)).await {
+            Ok(found) => found?,
+            Err(e) => {
+                log::error!("procconn: owner lookup failed: {e}");
+                return None;
+            }
+        };
+        if let Some(ev) = self.report(conn, proc_info) {
+            self.hub.fire(ev.mask().bits(), &ev).await;
+        }
+        None
+    }
+}
@@ -0,0 +1,150 @@
+use crate::{
+    events::{ProcConnEvent, ProcInfo},
+    procconn::{ProcConnBridge, ProcConnRule, ProcFs, ProcResolver},
+};
+use async_trait::async_trait;
+use netpacket::events::{ConnKey, NetNotifyEvent};
+use omnitrace_core::callbacks::{Callback, CallbackHub, CallbackResult};
+use omnitrace_core::labels::Labels;
+use procdog::ProcDogState;
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::mpsc::channel;
+
+fn conn(local: &str, remote: &str, host: Option<&str>) -> ConnKey {
+    ConnKey {
+        proto: "tcp".into(),
+        local: local.into(),
+        remote: remote.into(),
+        state: Some("01".into()),
+        local_addr: local.parse().ok(),
+        remote_addr: remote.parse().ok(),
+        local_dec: Some(local.into()),
+        remote_dec: Some(remote.into()),
+        state_dec: Some("ESTABLISHED".into()),
+        local_host: None,
+        remote_host: host.map(Into::into),
+        remote_sni: None,
+    }
+}
+
+fn proc_info(pid: i32, comm: &str, cmdline: &str, uid: u32) -> ProcInfo {
+    ProcInfo { pid, comm: comm.into(), cmdline: cmdline.into(), uid, watched: None }
+}
+
+/// Owners keyed by the local address.
+#[derive(Default)]
+struct MockResolver {
+    owners: HashMap<String, i32>,
+    procs: HashMap<i32, ProcInfo>,
+}
+
+impl MockResolver {
+    fn with(mut self, local: &str, info: ProcInfo) -> Self {
+        self.owners.insert(local.into(), info.pid);
+        self.procs.insert(info.pid, info);
+        self
+    }
+}
+
+impl ProcResolver for MockResolver {
+    fn owner(&self, conn: &ConnKey) -> Option<i32> {
+        self.owners.get(&conn.local).copied()
+    }
+
+    fn info(&self, pid: i32) -> Option<ProcInfo> {
+        self.procs.get(&pid).cloned()
+    }
+}
+
+struct JsonCb;
+
+#[async_trait]
+impl Callback<ProcConnEvent> for JsonCb {
+    fn mask(&self) -> u64 {
+        u64::MAX
+    }
+
+    async fn call(&self, ev: &ProcConnEvent) -> Option<CallbackResult> {
+        serde_json::to_value(ev).ok()
+    }
+}
+
+#[tokio::test]
+async fn opened_connections_are_joined_with_their_process() {
+    let (tx, mut rx) = channel::<CallbackResult>(16);
+    let mut hub = CallbackHub::<ProcConnEvent>::new();
+    hub.add(JsonCb);
+    hub.set_result_channel(tx);
+
+    let resolver = MockResolver::default()
+        .with("10.0.0.1:40000", proc_info(200, "curl", "curl https://example.com", 1000))
+        .with("10.0.0.1:40001", proc_info(201, "curl", "curl http://example.com", 1000))
+        .with("10.0.0.1:40002", proc_info(300, "sshd", "sshd: alice", 0))
+        .with("10.0.0.1:40003", proc_info(301, "sshd", "sshd: bob", 0))
+        .with("10.0.0.1:40004", proc_info(400, "wget", "wget https://evil.example.org/x", 1001));
+
+    let dog = ProcDogState::default();
+    dog.set("sshd", [300].into());
+
+    let mut bridge = ProcConnBridge::new(Arc::new(hub)).resolver(resolver).procdog(dog);
+    bridge.add_rule(ProcConnRule::default().name("curl").uid("10??").conn("*:443")).unwrap();
+    bridge.add_rule(ProcConnRule::default().name("ssh*").watched(true)).unwrap();
+    bridge.add_rule(ProcConnRule::default().cmdline("wget *").conn("*.example.org")).unwrap();
+
+    let opened = [
+        conn("10.0.0.1:40000", "93.184.216.34:443", None),                   // curl to 443: joined
+        conn("10.0.0.1:40001", "93.184.216.34:80", None),                    // curl to 80: no conn match
+        conn("10.0.0.1:40002", "10.0.0.9:22", None),                         // watched sshd: joined
+        conn("10.0.0.1:40003", "10.0.0.9:22", None),                         // sshd, but not tracked by ProcDog
+        conn("10.0.0.1:40004", "203.0.113.7:443", Some("evil.example.org")), // host pattern: joined
+        conn("10.0.0.1:49999", "203.0.113.7:443", None),                     // owner unknown
+    ];
+    for c in opened {
+        bridge.call(&NetNotifyEvent::Opened { conn: c, offline: false, labels: Labels::default() }).await;
+    }
+    bridge.call(&NetNotifyEvent::Closed { conn: conn("10.0.0.1:40000", "93.184.216.34:443", None), offline: false, labels: Labels::default() }).await;
+
+    let mut joined = Vec::new();
+    while let Ok(ev) = rx.try_recv() {
+        joined.push(ev);
+    }
+
+    let pids: Vec<_> = joined.iter().map(|e| e["ProcessConnection"]["proc_info"]["pid"].as_i64().unwrap()).collect();
+    assert_eq!(pids, vec![200, 300, 400]);
+    assert_eq!(joined[0]["ProcessConnection"]["conn"]["remote"], "93.184.216.34:443");
+    assert_eq!(joined[1]["ProcessConnection"]["proc_info"]["watched"], "sshd");
+    assert!(joined[0]["ProcessConnection"]["proc_info"]["watched"].is_null());
+}
+
+#[test]
+fn invalid_rule_glob_is_rejected() {
+    let mut bridge = ProcConnBridge::new(Arc::new(CallbackHub::new()));
+    assert!(bridge.add_rule(ProcConnRule::default().name("[")).is_err());
+}
+
+#[test]
+fn procfs_resolves_socket_owner() {
+    let root = std::env::temp_dir().join(format!("bridges-ut-{}-procfs", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("net")).unwrap();
+    std::fs::create_dir_all(root.join("42/fd")).unwrap();
+    std::fs::write(
+        root.join("net/tcp"),
+        "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n   \
+         0: 0100007F:1F90 0100007F:D431 01 00000000:00000000 00:00000000 00000000  1000        0 4242 1 0 20 4 30 10 -1\n",
+    )
+    .unwrap();
+    std::fs::write(root.join("42/comm"), "nginx\n").unwrap();
+    std::fs::write(root.join("42/cmdline"), "nginx\0-g\0daemon off;\0").unwrap();
+    std::fs::write(root.join("42/status"), "Name:\tnginx\nUid:\t33\t33\t33\t33\n").unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink("socket:[4242]", root.join("42/fd/3")).unwrap();
+
+    let fs = ProcFs::new(&root);
+    let c = ConnKey { local: "0100007F:1F90".into(), remote: "0100007F:D431".into(), ..conn("", "", None) };
+    #[cfg(unix)]
+    assert_eq!(fs.owner(&c), Some(42));
+    assert_eq!(fs.info(42), Some(proc_info(42, "nginx", "nginx -g daemon off;", 33)));
+
+    let _ = std::fs::remove_dir_all(&root);
+}
@@ -0,0 +1,352 @@
+//! Live dashboard of running sensors, the model and renderer behind `omnitrace-top`.
+//!
+//! On every refresh a [`Dashboard`] samples its [`Pane`]s, each reading a sensor's debug
+//! handle, effective pulse and hub counters, and an [`EventLog`] callback added to each hub
+//! keeps the last events for the scrolling pane. Nothing is taken off the result channel, so
+//! the dashboard can sit next to the real consumers.
+//!
+//! [`render`] lays a [`Frame`] out as plain text lines of a given size. The binary only wraps
+//! it in an ANSI redraw and reads keys into a [`View`], so the layout is tested by rendering
+//! synthetic frames:
+//!
+//! ```ignore
+//! let lines = top::render(&frame, &View::default(), 100, 30);
+//! assert!(lines.iter().any(|l| l.contains("/data")));
+//! ```
+
+use async_trait::async_trait;
+use filescream::{FileScream, FileScreamDebug, events::FileScreamEvent};
+use netpacket::{NetNotify, NetNotifyDebug, events::NetNotifyEvent};
+use omnitrace_core::{
+    callbacks::{Callback, CallbackHub, CallbackResult},
+    debug::DebugCell,
+    fields::EventFields,
+    pulse::EffectivePulse,
+    units::HumanDuration,
+};
+use procdog::{ProcDog, ProcDogDebug, events::ProcDogEvent};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use xmount::{XMount, XMountDebug, events::XMountEvent};
+
+/// Events an [`EventLog`] keeps by default.
+pub const DEFAULT_EVENTS: usize = 500;
+
+/// Fields shown per event in the events pane.
+const DETAIL_FIELDS: usize = 4;
+
+/// One sensor's pane, as sampled.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PaneState {
+    pub sensor: String,
+    pub pulse: Duration,
+    /// Time the last tick took, where the sensor reports it (FileScream's scans).
+    pub tick: Option<Duration>,
+    /// Events fired since the sensor started.
+    pub events: u64,
+    /// Events per second since the previous sample.
+    pub rate: f64,
+    /// Results waiting in the hub's result channel, and its capacity.
+    pub queue: Option<(usize, usize)>,
+    /// What the sensor watches and how it stands, one entry per line.
+    pub lines: Vec<String>,
+}
+
+/// One event of the events pane.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EventLine {
+    /// Position in the [`EventLog`], counting from 1.
+    pub seq: u64,
+    pub at: SystemTime,
+    pub sensor: String,
+    pub topic: String,
+    /// A few of the event's fields, as `name=value`.
+    pub detail: String,
+}
+
+/// Everything one redraw shows.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Frame {
+    pub panes: Vec<PaneState>,
+    /// Oldest first.
+    pub events: Vec<EventLine>,
+}
+
+/// The last events of all sensors, filled by the callbacks [`EventLog::recorder`] makes.
+/// Cheap to clone.
+#[derive(Clone, Debug)]
+pub struct EventLog(Arc<Mutex<LogInner>>);
+
+#[derive(Debug)]
+struct LogInner {
+    capacity: usize,
+    seq: u64,
+    lines: VecDeque<EventLine>,
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENTS)
+    }
+}
+
+impl EventLog {
+    pub fn new(capacity: usize) -> Self {
+        Self(Arc::new(Mutex::new(LogInner { capacity: capacity.max(1), seq: 0, lines: VecDeque::new() })))
+    }
+
+    /// A callback taking every event of a hub into the log under `sensor`. It returns no result.
+    pub fn recorder<S: Into<String>>(&self, sensor: S) -> Recorder {
+        Recorder { log: self.clone(), sensor: sensor.into() }
+    }
+
+    pub fn push(&self, sensor: &str, topic: &str, detail: String) {
+        let Ok(mut log) = self.0.lock() else {
+            return;
+        };
+        log.seq += 1;
+        let line = EventLine { seq: log.seq, at: SystemTime::now(), sensor: sensor.to_string(), topic: topic.to_string(), detail };
+        if log.lines.len() == log.capacity {
+            log.lines.pop_front();
+        }
+        log.lines.push_back(line);
+    }
+
+    /// The events kept, oldest first.
+    pub fn lines(&self) -> Vec<EventLine> {
+        self.0.lock().map(|log| log.lines.iter().cloned().collect()).unwrap_or_default()
+    }
+}
+
+/// See [`EventLog::recorder`].
+pub struct Recorder {
+    log: EventLog,
+    sensor: String,
+}
+
+#[async_trait]
+impl<E: EventFields + Sync> Callback<E> for Recorder {
+    fn mask(&self) -> u64 {
+        u64::MAX
+    }
+
+    async fn call(&self, ev: &E) -> Option<CallbackResult> {
+        let detail = ev
+            .field_names()
+            .iter()
+            .filter_map(|name| {
+                let v = ev.field(name)?;
+                Some(format!("{name}={}", v.as_str().map(|s| s.into_owned()).unwrap_or_else(|| v.to_json().to_string())))
+            })
+            .take(DETAIL_FIELDS)
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.log.push(&self.sensor, ev.topic().unwrap_or_else(|| ev.kind()), detail);
+        None
+    }
+}
+
+/// A sensor as the dashboard sees it.
+pub trait Pane: Send {
+    fn sample(&mut self) -> PaneState;
+}
+
+/// A [`Pane`] over the handles every sensor has: its hub, pulse and debug summary. `describe`
+/// turns the summary into the tick time and the pane's lines.
+pub struct SensorPane<E, D> {
+    sensor: String,
+    hub: Arc<CallbackHub<E>>,
+    pulse: EffectivePulse,
+    debug: DebugCell<D>,
+    describe: fn(&D) -> (Option<Duration>, Vec<String>),
+    last: Option<(Instant, u64)>,
+}
+
+impl<E, D> SensorPane<E, D> {
+    pub fn new<S: Into<String>>(
+        sensor: S, hub: Arc<CallbackHub<E>>, pulse: EffectivePulse, debug: DebugCell<D>, describe: fn(&D) -> (Option<Duration>, Vec<String>),
+    ) -> Self {
+        Self { sensor: sensor.into(), hub, pulse, debug, describe, last: None }
+    }
+}
+
+impl<E: Send + Sync, D: Clone + Default + Send + Sync> Pane for SensorPane<E, D> {
+    fn sample(&mut self) -> PaneState {
+        let (now, events) = (Instant::now(), self.hub.fired());
+        let rate = match self.last {
+            Some((at, n)) if now > at => events.saturating_sub(n) as f64 / now.duration_since(at).as_secs_f64(),
+            _ => 0.0,
+        };
+        self.last = Some((now, events));
+        let (tick, lines) = (self.describe)(&self.debug.get());
+        let queue = self.hub.result_channel().map(|tx| (tx.max_capacity() - tx.capacity(), tx.max_capacity()));
+        PaneState { sensor: self.sensor.clone(), pulse: self.pulse.get(), tick, events, rate, queue, lines }
+    }
+}
+
+/// XMount's pane: the watched mountpoints, mounted or not, with their fstype and health.
+pub fn xmount_pane(sensor: &XMount, hub: Arc<CallbackHub<XMountEvent>>) -> SensorPane<XMountEvent, XMountDebug> {
+    SensorPane::new("xmount", hub, sensor.effective_pulse(), sensor.debug_handle(), |d| {
+        let lines = d
+            .targets
+            .iter()
+            .map(|t| match d.mounts.iter().find(|m| m.mount_point.to_string_lossy() == *t) {
+                Some(m) => {
+                    let health = d.health.get(t).map(|h| format!(" {h:?}")).unwrap_or_default();
+                    format!("{t} mounted {} {}{health}", m.fstype, m.source)
+                }
+                None => format!("{t} not mounted"),
+            })
+            .collect();
+        (None, lines)
+    })
+}
+
+/// ProcDog's pane: the PIDs of every watched name.
+pub fn procdog_pane(sensor: &ProcDog, hub: Arc<CallbackHub<ProcDogEvent>>) -> SensorPane<ProcDogEvent, ProcDogDebug> {
+    SensorPane::new("procdog", hub, sensor.effective_pulse(), sensor.debug_handle(), |d| {
+        let lines = d
+            .watched
+            .iter()
+            .map(|name| match d.pids.get(name).filter(|p| !p.is_empty()) {
+                Some(pids) => format!("{name} running {pids:?}"),
+                None => format!("{name} not running"),
+            })
+            .collect();
+        (None, lines)
+    })
+}
+
+/// FileScream's pane: files per root and suspended roots. The tick time is the last scan's.
+pub fn filescream_pane(sensor: &FileScream, hub: Arc<CallbackHub<FileScreamEvent>>) -> SensorPane<FileScreamEvent, FileScreamDebug> {
+    SensorPane::new("filescream", hub, sensor.effective_pulse(), sensor.debug_handle(), |d| {
+        let lines = d
+            .roots
+            .iter()
+            .map(|r| match d.suspended.contains(r) {
+                true => format!("{r} suspended"),
+                false => format!("{r} {} files", d.files_per_root.get(r).copied().unwrap_or(0)),
+            })
+            .collect();
+        (d.last_scan.map(|s| s.elapsed), lines)
+    })
+}
+
+/// NetNotify's pane: connections per state and listeners under backlog pressure.
+pub fn netnotify_pane(sensor: &NetNotify, hub: Arc<CallbackHub<NetNotifyEvent>>) -> SensorPane<NetNotifyEvent, NetNotifyDebug> {
+    SensorPane::new("netnotify", hub, sensor.effective_pulse(), sensor.debug_handle(), |d| {
+        let mut lines = vec![format!("{} connections", d.connections)];
+        lines.extend(d.by_state.iter().map(|(state, n)| format!("  {state} {n}")));
+        lines.extend(d.backlog_pressure.iter().map(|l| format!("{l} backlog pressure")));
+        (None, lines)
+    })
+}
+
+/// The panes and the event log of one dashboard.
+#[derive(Default)]
+pub struct Dashboard {
+    panes: Vec<Box<dyn Pane>>,
+    log: EventLog,
+}
+
+impl Dashboard {
+    pub fn new(log: EventLog) -> Self {
+        Self { panes: Vec::new(), log }
+    }
+
+    /// Add a sensor's pane, shown after those already there.
+    pub fn pane<P: Pane + 'static>(mut self, pane: P) -> Self {
+        self.panes.push(Box::new(pane));
+        self
+    }
+
+    pub fn log(&self) -> &EventLog {
+        &self.log
+    }
+
+    pub fn sample(&mut self) -> Frame {
+        Frame { panes: self.panes.iter_mut().map(|p| p.sample()).collect(), events: self.log.lines() }
+    }
+}
+
+/// What the keyboard toggled.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct View {
+    /// Only this sensor's pane and events.
+    pub only: Option<String>,
+    /// Scrolling stopped: events after this one are not shown.
+    pub paused_at: Option<u64>,
+}
+
+impl View {
+    /// Apply a key: `1`-`9` shows only that sensor, `0` or `a` all of them, Tab the next one,
+    /// `p` or space pauses and resumes scrolling. False for `q`, to quit.
+    pub fn key(&mut self, key: u8, frame: &Frame) -> bool {
+        match key {
+            b'q' | b'Q' => return false,
+            b'0' | b'a' => self.only = None,
+            b'1'..=b'9' => {
+                if let Some(p) = frame.panes.get(usize::from(key - b'1')) {
+                    self.only = Some(p.sensor.clone());
+                }
+            }
+            b'\t' => {
+                let pos = self.only.as_ref().and_then(|s| frame.panes.iter().position(|p| &p.sensor == s));
+                self.only = match pos {
+                    None => frame.panes.first(),
+                    Some(i) => frame.panes.get(i + 1),
+                }
+                .map(|p| p.sensor.clone());
+            }
+            b'p' | b' ' => {
+                self.paused_at = match self.paused_at {
+                    Some(_) => None,
+                    None => Some(frame.events.last().map_or(0, |e| e.seq)),
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn shows(&self, sensor: &str) -> bool {
+        self.only.as_deref().is_none_or(|s| s == sensor)
+    }
+}
+
+/// Lay `frame` out in at most `height` lines of at most `width` characters: a status line,
+/// the panes, then as many of the latest events as fit.
+pub fn render(frame: &Frame, view: &View, width: usize, height: usize) -> Vec<String> {
+    let status = format!(
+        "omnitrace top | {} | {} | 1-9 sensor, 0 all, tab next, p pause, q quit",
+        view.only.as_deref().unwrap_or("all sensors"),
+        if view.paused_at.is_some() { "paused" } else { "live" },
+    );
+    let mut out = vec![status];
+
+    for p in frame.panes.iter().filter(|p| view.shows(&p.sensor)) {
+        let tick = p.tick.map_or("-".to_string(), |t| HumanDuration(Duration::from_millis(t.as_millis() as u64)).to_string());
+        let queue = p.queue.map_or("-".to_string(), |(n, cap)| format!("{n}/{cap}"));
+        out.push(format!("[{}] pulse {}  tick {tick}  events {} ({:.1}/s)  queue {queue}", p.sensor, HumanDuration(p.pulse), p.events, p.rate));
+        out.extend(p.lines.iter().map(|l| format!("  {l}")));
+    }
+
+    let events: Vec<&EventLine> = frame.events.iter().filter(|e| view.shows(&e.sensor) && view.paused_at.is_none_or(|at| e.seq <= at)).collect();
+    out.push(format!("-- events ({}) --", events.len()));
+    let room = height.saturating_sub(out.len());
+    for e in &events[events.len().saturating_sub(room)..] {
+        let secs = e.at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        out.push(format!("{:02}:{:02}:{:02} {:<10} {:<24} {}", secs / 3600 % 24, secs / 60 % 60, secs % 60, e.sensor, e.topic, e.detail));
+    }
+
+    out.truncate(height);
+    for line in &mut out {
+        if let Some((cut, _)) = line.char_indices().nth(width) {
+            line.truncate(cut);
+        }
+    }
+    out
+}
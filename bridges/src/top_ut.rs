@@ -0,0 +1,121 @@
+use crate::top::{self, Dashboard, EventLine, EventLog, Frame, PaneState, View};
+use omnitrace_core::callbacks::CallbackHub;
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use xmount::{XMount, XMountConfig, events::XMountEvent};
+
+fn pane(sensor: &str, lines: &[&str]) -> PaneState {
+    PaneState {
+        sensor: sensor.to_string(),
+        pulse: Duration::from_millis(500),
+        tick: None,
+        events: 7,
+        rate: 1.5,
+        queue: Some((3, 4095)),
+        lines: lines.iter().map(|l| l.to_string()).collect(),
+    }
+}
+
+fn event(seq: u64, sensor: &str, topic: &str, detail: &str) -> EventLine {
+    EventLine { seq, at: SystemTime::now(), sensor: sensor.to_string(), topic: topic.to_string(), detail: detail.to_string() }
+}
+
+fn frame() -> Frame {
+    Frame {
+        panes: vec![pane("xmount", &["/data mounted ext4 /dev/sdb1"]), pane("procdog", &["sshd running [42]"])],
+        events: vec![
+            event(1, "xmount", "mount.mounted", "target=/data"),
+            event(2, "procdog", "proc.appeared", "name=sshd pid=42"),
+            event(3, "xmount", "mount.unmounted", "target=/data"),
+        ],
+    }
+}
+
+fn has(lines: &[String], s: &str) -> bool {
+    lines.iter().any(|l| l.contains(s))
+}
+
+#[test]
+fn renders_panes_stats_and_events() {
+    let lines = top::render(&frame(), &View::default(), 120, 40);
+
+    assert!(lines[0].contains("all sensors") && lines[0].contains("live"));
+    assert!(has(&lines, "[xmount] pulse 500ms  tick -  events 7 (1.5/s)  queue 3/4095"));
+    assert!(has(&lines, "/data mounted ext4 /dev/sdb1"));
+    assert!(has(&lines, "sshd running [42]"));
+    assert!(has(&lines, "-- events (3) --"));
+    assert!(has(&lines, "proc.appeared"));
+    assert!(has(&lines, "name=sshd pid=42"));
+}
+
+#[test]
+fn fits_the_screen_keeping_the_latest_events() {
+    let mut f = frame();
+    f.events = (1..=50).map(|i| event(i, "xmount", "mount.changed", &format!("n={i}"))).collect();
+    let lines = top::render(&f, &View::default(), 60, 12);
+
+    assert_eq!(lines.len(), 12);
+    assert!(lines.iter().all(|l| l.chars().count() <= 60));
+    assert!(has(&lines, "n=50"));
+    assert!(!has(&lines, "n=1 "));
+}
+
+#[test]
+fn keys_filter_by_sensor() {
+    let f = frame();
+    let mut view = View::default();
+
+    assert!(view.key(b'2', &f));
+    let lines = top::render(&f, &view, 120, 40);
+    assert!(lines[0].contains("procdog"));
+    assert!(has(&lines, "[procdog]") && !has(&lines, "[xmount]"));
+    assert!(has(&lines, "-- events (1) --") && !has(&lines, "mount.unmounted"));
+
+    view.key(b'\t', &f);
+    assert_eq!(view.only, None, "tab past the last sensor shows all");
+    view.key(b'\t', &f);
+    assert_eq!(view.only.as_deref(), Some("xmount"));
+    view.key(b'0', &f);
+    assert_eq!(view.only, None);
+    assert!(!view.key(b'q', &f));
+}
+
+#[test]
+fn pause_stops_scrolling() {
+    let mut f = frame();
+    let mut view = View::default();
+    view.key(b'p', &f);
+    f.events.push(event(4, "procdog", "proc.disappeared", "name=cron"));
+
+    let lines = top::render(&f, &view, 120, 40);
+    assert!(lines[0].contains("paused"));
+    assert!(!has(&lines, "name=cron"));
+
+    view.key(b'p', &f);
+    assert!(has(&top::render(&f, &view, 120, 40), "name=cron"));
+}
+
+#[tokio::test]
+async fn samples_sensor_handles_and_records_events() {
+    let log = EventLog::new(2);
+    let xm = XMount::new(XMountConfig::default());
+    let mut hub = CallbackHub::<XMountEvent>::new();
+    hub.add(log.recorder("xmount"));
+    let hub = Arc::new(hub);
+    let mut dash = Dashboard::new(log.clone()).pane(top::xmount_pane(&xm, hub.clone()));
+
+    for target in ["/a", "/b", "/c"] {
+        let ev = XMountEvent::test_mounted(target);
+        hub.fire(ev.mask().bits(), &ev).await;
+    }
+
+    let f = dash.sample();
+    assert_eq!(f.panes[0].sensor, "xmount");
+    assert_eq!(f.panes[0].events, 3);
+    assert_eq!(f.events.len(), 2, "the log keeps its capacity");
+    assert_eq!(f.events[1].seq, 3);
+    assert_eq!(f.events[1].topic, "mount.mounted");
+    assert!(f.events[1].detail.contains("target=/c"), "{}", f.events[1].detail);
+}
@@ -0,0 +1,44 @@
+use filescream::demo::{self, DemoConfig, Scratch};
+use filescream::prelude::*;
+use omnitrace_core::{
+    filter::Filter,
+    preflight::{self, PreflightArgs},
+};
+
+// FILESCREAM_ROOT=/etc FILESCREAM_IGNORE='*.swp' cargo run -p filescream --example filescream
+#[tokio::main]
+async fn main() {
+    let cfg = DemoConfig::from_env();
+    let (root, scratch) = match cfg.root.clone() {
+        Some(root) => (root, None),
+        None => match Scratch::create("example") {
+            Ok(s) => (s.path().to_path_buf(), Some(s)),
+            Err(e) => {
+                eprintln!("Cannot create a scratch directory: {e}");
+                return;
+            }
+        },
+    };
+    let fs = match demo::sensor(&cfg, &root) {
+        Ok(fs) => fs,
+        Err(e) => {
+            eprintln!("Cannot watch {}: {e}", root.display());
+            return;
+        }
+    };
+    preflight::gate("filescream", &fs, PreflightArgs::from_env_args()).await;
+
+    let churn = scratch.as_ref().map(|s| tokio::spawn(s.churn(cfg.pulse * 2)));
+    let mut hub = CallbackHub::<FileScreamEvent>::new();
+    if let Some(f) = Filter::from_env_args() {
+        hub.set_filter(f.predicate());
+    }
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+        println!("Shutting down on Ctrl-C...");
+    };
+    demo::run(fs, hub, ctrl_c).await;
+    if let Some(churn) = churn {
+        churn.abort();
+    }
+}
@@ -0,0 +1,566 @@
+use crate::{
+    FileRecord, Generation,
+    digest::{ContentHasher, Digest, HashAlgorithm},
+    error::FileScreamError,
+};
+use hashbrown::{HashMap, HashSet};
+use omnitrace_core::{
+    clock::{self, SharedClock},
+    memory,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio_util::sync::CancellationToken;
+
+/// Read size between throttle checks.
+const CHUNK: usize = 64 * 1024;
+
+/// Content hashing, see [`crate::FileScreamConfig::content_hashing`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContentHashing {
+    max_bytes_per_sec: Option<u64>,
+    max_concurrent_reads: usize,
+    mmap_threshold: Option<u64>,
+    algorithm: HashAlgorithm,
+    appends: Option<AppendAware>,
+}
+
+impl Default for ContentHashing {
+    fn default() -> Self {
+        Self { max_bytes_per_sec: None, max_concurrent_reads: 1, mmap_threshold: None, algorithm: HashAlgorithm::default(), appends: None }
+    }
+}
+
+impl ContentHashing {
+    /// Hash contents with `algorithm` (default: BLAKE3), see [`crate::digest`].
+    pub fn algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Hash only what was appended to files that grew, see [`AppendAware`] (default: off).
+    pub fn append_aware(mut self, appends: AppendAware) -> Self {
+        self.appends = Some(appends);
+        self
+    }
+
+    /// Cap the read rate of a scan, shared by all readers (default: unlimited).
+    pub fn max_bytes_per_sec(mut self, n: u64) -> Self {
+        self.max_bytes_per_sec = Some(n.max(1));
+        self
+    }
+
+    /// Hash up to this many files in parallel (default: 1).
+    pub fn max_concurrent_reads(mut self, n: usize) -> Self {
+        self.max_concurrent_reads = n.max(1);
+        self
+    }
+
+    /// Files of at least this size are hashed through mmap, smaller ones with buffered reads
+    /// (default: `None`, always buffered reads). A file truncated by another process while
+    /// it is mapped raises SIGBUS, which kills the process: only for trees nothing truncates.
+    pub fn mmap_threshold(mut self, bytes: Option<u64>) -> Self {
+        self.mmap_threshold = bytes;
+        self
+    }
+
+    pub(crate) fn strategy(&self, len: u64) -> ReadStrategy {
+        match self.mmap_threshold {
+            Some(t) if len >= t => ReadStrategy::Mmap,
+            _ => ReadStrategy::Buffered,
+        }
+    }
+}
+
+/// Hashing of files that grow at the end, such as logs, see [`ContentHashing::append_aware`].
+///
+/// A file of at least `min_len` bytes keeps the hash state at its end. When it grew (same
+/// inode, larger), the next scan resumes that state over the new bytes only, after checking
+/// that its first `head_check` bytes still hash the same. The digest is the one a full read
+/// gives, as long as only appends happened. Content modified before the old end is caught by
+/// the head check, or at the latest by the full read of every `verify_every`th growth, which
+/// also hashes the file the resumed way and compares. Either way the file is read in full and
+/// its Changed event says [`crate::events::FileChange::Rewritten`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AppendAware {
+    min_len: u64,
+    head_check: u64,
+    verify_every: u32,
+}
+
+impl Default for AppendAware {
+    fn default() -> Self {
+        Self { min_len: 1024 * 1024, head_check: 4096, verify_every: 16 }
+    }
+}
+
+impl AppendAware {
+    /// Files kept resumable, each costing a hash state (about 2 KiB with BLAKE3) (default: 1 MiB).
+    pub fn min_len(mut self, bytes: u64) -> Self {
+        self.min_len = bytes;
+        self
+    }
+
+    /// Leading bytes checked before resuming (default: 4 KiB); 0 skips the check.
+    pub fn head_check(mut self, bytes: u64) -> Self {
+        self.head_check = bytes;
+        self
+    }
+
+    /// Read a growing file in full every `n`th time (default: 16).
+    pub fn verify_every(mut self, n: u32) -> Self {
+        self.verify_every = n.max(1);
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ReadStrategy {
+    Buffered,
+    Mmap,
+}
+
+/// IO of one content scan.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanIoStats {
+    pub files_hashed: u64,
+    /// Of `files_hashed`, those hashed by their appended bytes only, see [`AppendAware`].
+    #[serde(default)]
+    pub files_appended: u64,
+    pub bytes_hashed: u64,
+    /// Wall time spent hashing.
+    pub elapsed: Duration,
+    /// Time readers spent waiting for the rate limit, summed over concurrent readers.
+    pub throttle_wait: Duration,
+}
+
+/// Shared handle on the IO stats of the last completed scan, e.g. to export them as
+/// pipeline gauges. Cheap to clone.
+#[derive(Clone, Debug, Default)]
+pub struct IoStats(Arc<Mutex<ScanIoStats>>);
+
+impl IoStats {
+    pub fn last_scan(&self) -> ScanIoStats {
+        self.0.lock().map(|s| *s).unwrap_or_default()
+    }
+
+    fn set(&self, stats: ScanIoStats) {
+        if let Ok(mut s) = self.0.lock() {
+            *s = stats;
+        }
+    }
+}
+
+/// Token bucket in bytes. Readers reserve before each chunk and sleep off any debt, so the
+/// long-run rate stays at the cap however many readers share it.
+pub(crate) struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    clock: SharedClock,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(bytes_per_sec: u64) -> Self {
+        Self::with_clock(bytes_per_sec, clock::system())
+    }
+
+    pub(crate) fn with_clock(bytes_per_sec: u64, clock: SharedClock) -> Self {
+        let rate = bytes_per_sec as f64;
+        let state = Mutex::new((0.0, clock.now_instant()));
+        // small burst: a scan starting after an idle pulse must not get seconds of credit
+        Self { rate, burst: (rate / 10.0).max(CHUNK as f64), clock, state }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn burst(&self) -> u64 {
+        self.burst as u64
+    }
+
+    /// Reserve `n` bytes. Returns how long to wait until they are covered.
+    pub(crate) fn reserve(&self, n: usize) -> Duration {
+        let mut st = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now = self.clock.now_instant();
+        let tokens = (st.0 + now.saturating_duration_since(st.1).as_secs_f64() * self.rate).min(self.burst) - n as f64;
+        *st = (tokens, now);
+        if tokens < 0.0 { Duration::from_secs_f64(-tokens / self.rate) } else { Duration::ZERO }
+    }
+
+    /// Reserve `n` bytes, block until they are covered. Returns the time waited.
+    pub(crate) fn take(&self, n: usize) -> Duration {
+        let wait = self.reserve(n);
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+        wait
+    }
+}
+
+/// Where an [`AppendAware`] hash stopped, to resume it once the file grew.
+#[derive(Clone)]
+pub(crate) struct Tail {
+    generation: Generation,
+    len: u64,
+    hasher: Box<dyn ContentHasher>,
+    /// Of the first `head_check` bytes, or fewer if the file was shorter.
+    head: Digest,
+    appends: u32,
+}
+
+/// How to hash one file.
+pub(crate) struct HashJob {
+    pub(crate) algorithm: HashAlgorithm,
+    pub(crate) appends: Option<AppendAware>,
+    pub(crate) generation: Generation,
+    /// The file's tail from the last scan, if it grew since.
+    pub(crate) resume: Option<Tail>,
+}
+
+impl HashJob {
+    /// A full read, keeping no tail.
+    #[cfg(test)]
+    pub(crate) fn full(algorithm: HashAlgorithm) -> Self {
+        Self { algorithm, appends: None, generation: Generation::default(), resume: None }
+    }
+}
+
+/// What hashing a file found.
+pub(crate) struct Hashed {
+    pub(crate) digest: Digest,
+    /// Bytes read.
+    pub(crate) bytes: u64,
+    /// Time waited for the rate limit.
+    pub(crate) waited: Duration,
+    pub(crate) tail: Option<Tail>,
+    /// Only the appended bytes were hashed.
+    pub(crate) appended: bool,
+    /// Content before the last hashed end changed, see [`AppendAware`].
+    pub(crate) rewritten: bool,
+}
+
+/// An open file, read in chunks, throttled and cancellable between them.
+struct Source<'a> {
+    file: File,
+    map: Option<memmap2::Mmap>,
+    bucket: Option<&'a TokenBucket>,
+    cancel: &'a CancellationToken,
+    read: u64,
+    waited: Duration,
+}
+
+impl<'a> Source<'a> {
+    fn open(path: &Path, strategy: ReadStrategy, bucket: Option<&'a TokenBucket>, cancel: &'a CancellationToken) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let map = match strategy {
+            // SAFETY: the map is only read and dropped before the file is. Content changing under
+            // us only spoils this hash, but a file truncated below the mapped length makes the
+            // read of the pages past its new end raise SIGBUS and kill the process; mmap is
+            // opt-in for that reason, see ContentHashing::mmap_threshold.
+            ReadStrategy::Mmap if file.metadata()?.len() > 0 => Some(unsafe { memmap2::Mmap::map(&file)? }),
+            _ => None,
+        };
+        Ok(Self { file, map, bucket, cancel, read: 0, waited: Duration::ZERO })
+    }
+
+    fn len(&self) -> io::Result<u64> {
+        match &self.map {
+            Some(map) => Ok(map.len() as u64),
+            None => Ok(self.file.metadata()?.len()),
+        }
+    }
+
+    /// Feed the bytes from offset `from` to `f`, up to `limit` of them or to the end. Returns
+    /// how many were fed.
+    fn feed(&mut self, from: u64, limit: Option<u64>, mut f: impl FnMut(&[u8])) -> io::Result<u64> {
+        let limit = limit.unwrap_or(u64::MAX);
+        let before = self.read;
+        let mut take = |src: &mut Self, chunk: &[u8]| {
+            if src.cancel.is_cancelled() {
+                return Err(io::Error::from(io::ErrorKind::Interrupted));
+            }
+            if let Some(b) = src.bucket {
+                src.waited += b.take(chunk.len());
+            }
+            src.read += chunk.len() as u64;
+            f(chunk);
+            Ok(())
+        };
+
+        if let Some(map) = self.map.take() {
+            let start = (from as usize).min(map.len());
+            let end = start.saturating_add(usize::try_from(limit).unwrap_or(usize::MAX)).min(map.len());
+            let res = map[start..end].chunks(CHUNK).try_for_each(|chunk| take(self, chunk));
+            self.map = Some(map);
+            res?;
+        } else {
+            self.file.seek(SeekFrom::Start(from))?;
+            let mut buf = vec![0u8; CHUNK];
+            while self.read - before < limit {
+                let want = CHUNK.min(usize::try_from(limit - (self.read - before)).unwrap_or(CHUNK));
+                let n = self.file.read(&mut buf[..want])?;
+                if n == 0 {
+                    break;
+                }
+                take(self, &buf[..n])?;
+            }
+        }
+        Ok(self.read - before)
+    }
+}
+
+/// Hash a file's content, throttled between chunks, resuming `job.resume` if it holds.
+/// Fails with `Interrupted` as soon as `cancel` fires.
+pub(crate) fn hash_file(
+    path: &Path, strategy: ReadStrategy, bucket: Option<&TokenBucket>, cancel: &CancellationToken, job: HashJob,
+) -> io::Result<Hashed> {
+    let mut src = Source::open(path, strategy, bucket, cancel)?;
+    let head_len = job.appends.map_or(0, |a| a.head_check);
+    let mut rewritten = false;
+    let mut verify = None;
+
+    if let (Some(appends), Some(tail)) = (job.appends, job.resume)
+        && src.len()? > tail.len
+    {
+        let mut head = job.algorithm.hasher();
+        if head_len > 0 {
+            src.feed(0, Some(head_len.min(tail.len)), |c| head.update(c))?;
+        }
+        if head_len > 0 && head.digest() != tail.head {
+            rewritten = true;
+        } else if tail.appends + 1 < appends.verify_every {
+            let mut hasher = tail.hasher;
+            let len = tail.len + src.feed(tail.len, None, |c| hasher.update(c))?;
+            let digest = hasher.digest();
+            let tail = Tail { generation: job.generation, len, hasher, head: tail.head, appends: tail.appends + 1 };
+            return Ok(Hashed { digest, bytes: src.read, waited: src.waited, tail: Some(tail), appended: true, rewritten: false });
+        } else {
+            verify = Some((tail.len, tail.hasher));
+        }
+    }
+
+    // in full, and on verification the resumed way alongside
+    let mut full = job.algorithm.hasher();
+    let mut head = job.algorithm.hasher();
+    let mut at = 0u64;
+    src.feed(0, None, |chunk| {
+        full.update(chunk);
+        if at < head_len {
+            head.update(&chunk[..chunk.len().min((head_len - at) as usize)]);
+        }
+        if let Some((from, resumed)) = &mut verify
+            && at + chunk.len() as u64 > *from
+        {
+            resumed.update(&chunk[from.saturating_sub(at) as usize..]);
+        }
+        at += chunk.len() as u64;
+    })?;
+
+    let digest = full.digest();
+    rewritten |= verify.is_some_and(|(_, resumed)| resumed.digest() != digest);
+    let tail =
+        job.appends.filter(|a| at >= a.min_len).map(|_| Tail { generation: job.generation, len: at, hasher: full, head: head.digest(), appends: 0 });
+    Ok(Hashed { digest, bytes: src.read, waited: src.waited, tail, appended: false, rewritten })
+}
+
+/// Content hashing state kept across scans: only files whose metadata changed are read again.
+pub(crate) struct ContentScanner {
+    opts: ContentHashing,
+    bucket: Option<Arc<TokenBucket>>,
+    cache: HashMap<PathBuf, Cached>,
+    // subtrees switched to metadata hashes to save memory, see `shed`
+    metadata_only: Vec<PathBuf>,
+    // FUSE mounts, never read: a hung daemon would hang the read
+    unread: Vec<PathBuf>,
+    // everything on metadata hashes while degraded, see `pause`
+    paused: bool,
+    // found by the last scan, see AppendAware
+    rewritten: HashSet<PathBuf>,
+    pub(crate) stats: IoStats,
+}
+
+/// A file's content hash and the metadata hash it was computed for.
+struct Cached {
+    meta: Digest,
+    content: Digest,
+    tail: Option<Tail>,
+}
+
+impl Cached {
+    fn bytes(&self, path: &Path) -> u64 {
+        let tail = self.tail.as_ref().map_or(0, |t| size_of_val(&*t.hasher) as u64);
+        memory::path_entry(path, size_of::<Self>()) + tail
+    }
+}
+
+impl ContentScanner {
+    pub(crate) fn new(opts: ContentHashing) -> Self {
+        let bucket = opts.max_bytes_per_sec.map(|n| Arc::new(TokenBucket::new(n)));
+        Self {
+            opts,
+            bucket,
+            cache: HashMap::new(),
+            metadata_only: Vec::new(),
+            unread: Vec::new(),
+            paused: false,
+            rewritten: HashSet::new(),
+            stats: IoStats::default(),
+        }
+    }
+
+    fn is_metadata_only(&self, path: &Path) -> bool {
+        self.metadata_only.iter().chain(&self.unread).any(|p| path.starts_with(p))
+    }
+
+    /// Subtrees compared by metadata hashes only, replacing the last ones.
+    pub(crate) fn set_unread(&mut self, subtrees: &[PathBuf]) {
+        self.unread = subtrees.to_vec();
+    }
+
+    /// Cached paths with the estimated bytes of their entries.
+    pub(crate) fn cached(&self) -> impl Iterator<Item = (&PathBuf, u64)> {
+        self.cache.iter().map(|(p, c)| (p, c.bytes(p)))
+    }
+
+    pub(crate) fn cache_bytes(&self) -> u64 {
+        self.cached().map(|(_, bytes)| bytes).sum()
+    }
+
+    pub(crate) fn algorithm(&self) -> HashAlgorithm {
+        self.opts.algorithm
+    }
+
+    /// The last scan found content before the old end of `path` modified while it grew.
+    pub(crate) fn rewritten(&self, path: &Path) -> bool {
+        self.rewritten.contains(path)
+    }
+
+    /// Stop content hashing under `subtree` and drop its cache entries. Their hashes in `files`
+    /// go back to the metadata hash, so the switch itself is not reported as a change.
+    pub(crate) fn shed(&mut self, subtree: &Path, files: &mut HashMap<PathBuf, FileRecord>) {
+        self.cache.retain(|path, c| {
+            if !path.starts_with(subtree) {
+                return true;
+            }
+            if let Some(rec) = files.get_mut(path) {
+                rec.hash = c.meta;
+            }
+            false
+        });
+        self.metadata_only.push(subtree.to_path_buf());
+    }
+
+    /// Stop content hashing until [`ContentScanner::resume`], dropping the cache. The hashes in
+    /// `files` go back to metadata hashes, so the switch itself is not reported as a change.
+    pub(crate) fn pause(&mut self, files: &mut HashMap<PathBuf, FileRecord>) {
+        for (path, c) in self.cache.drain() {
+            if let Some(rec) = files.get_mut(&path) {
+                rec.hash = c.meta;
+            }
+        }
+        self.paused = true;
+    }
+
+    /// Hash contents again from the next scan on. That scan reads every file; compare its
+    /// results against the paused ones with [`ContentScanner::metadata_hash`].
+    pub(crate) fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Metadata hash the cached content hash of `path` was computed for.
+    pub(crate) fn metadata_hash(&self, path: &Path) -> Option<Digest> {
+        self.cache.get(path).map(|c| c.meta)
+    }
+
+    /// Replace the metadata hashes in `files` by content hashes. `sizes` holds the length of every file.
+    /// Files that cannot be read keep their metadata hash and are pushed to `errors`. Returns false
+    /// if `cancel` cut it short, `files` is then half done and must be discarded.
+    pub(crate) fn rehash(
+        &mut self, files: &mut HashMap<PathBuf, FileRecord>, sizes: &HashMap<PathBuf, u64>, cancel: &CancellationToken,
+        errors: &mut Vec<FileScreamError>,
+    ) -> bool {
+        if self.paused {
+            return true;
+        }
+        let started = Instant::now();
+        self.rewritten.clear();
+        let mut todo = Vec::new();
+        for (path, rec) in files.iter_mut() {
+            if self.is_metadata_only(path) {
+                continue;
+            }
+            match self.cache.get_mut(path) {
+                Some(c) if c.meta == rec.hash => rec.hash = c.content,
+                cached => {
+                    // grown since: resume its tail
+                    let len = sizes.get(path).copied().unwrap_or(0);
+                    let resume = cached.and_then(|c| c.tail.take()).filter(|t| t.generation == rec.generation && len > t.len);
+                    todo.push((path.clone(), rec.hash, rec.generation, resume));
+                }
+            }
+        }
+
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(Vec::with_capacity(todo.len()));
+        let workers = self.opts.max_concurrent_reads.min(todo.len());
+        std::thread::scope(|s| {
+            for _ in 0..workers {
+                s.spawn(|| {
+                    while let Some((path, _, generation, resume)) = todo.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        if cancel.is_cancelled() {
+                            break;
+                        }
+                        let strategy = self.opts.strategy(sizes.get(path).copied().unwrap_or(0));
+                        let job =
+                            HashJob { algorithm: self.opts.algorithm, appends: self.opts.appends, generation: *generation, resume: resume.clone() };
+                        let res = hash_file(path, strategy, self.bucket.as_deref(), cancel, job);
+                        results.lock().unwrap_or_else(|e| e.into_inner()).push((path.clone(), res));
+                    }
+                });
+            }
+        });
+
+        if cancel.is_cancelled() {
+            return false;
+        }
+
+        let mut stats = ScanIoStats::default();
+        let meta: HashMap<PathBuf, Digest> = todo.into_iter().map(|(path, meta, ..)| (path, meta)).collect();
+        for (path, res) in results.into_inner().unwrap_or_else(|e| e.into_inner()) {
+            match res {
+                Ok(hashed) => {
+                    stats.files_hashed += 1;
+                    stats.files_appended += u64::from(hashed.appended);
+                    stats.bytes_hashed += hashed.bytes;
+                    stats.throttle_wait += hashed.waited;
+                    if hashed.rewritten {
+                        self.rewritten.insert(path.clone());
+                    }
+                    if let Some(rec) = files.get_mut(&path) {
+                        rec.hash = hashed.digest;
+                    }
+                    self.cache.insert(path.clone(), Cached { meta: meta[&path], content: hashed.digest, tail: hashed.tail });
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(source) => errors.push(FileScreamError::Hash { path, source }),
+            }
+        }
+
+        self.cache.retain(|p, _| files.contains_key(p));
+        stats.elapsed = started.elapsed();
+        self.stats.set(stats);
+        true
+    }
+}
@@ -0,0 +1,128 @@
+//! The wiring of `examples/filescream.rs` as functions a program or a test can call: the
+//! sensor configured from the environment, a scratch directory to watch that changes by
+//! itself, a callback printing events, and [`run`].
+//!
+//! - `FILESCREAM_ROOT`: the directory to watch (default: a [`Scratch`] directory under the
+//!   temp dir, removed on exit)
+//! - `FILESCREAM_IGNORE`: ignore globs, comma separated (default: none)
+//! - `FILESCREAM_PULSE_MS`: the pulse (default: 1000)
+
+use crate::events::{FileScreamEvent, FileScreamMask};
+use crate::{FileScream, FileScreamConfig};
+use async_trait::async_trait;
+use omnitrace_core::{
+    callbacks::{Callback, CallbackHub, CallbackResult},
+    format::Style,
+    sensor,
+};
+use std::{
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// What the example watches, see the [module docs](self).
+#[derive(Clone, Debug)]
+pub struct DemoConfig {
+    /// None for a [`Scratch`] directory.
+    pub root: Option<PathBuf>,
+    pub ignore: Vec<String>,
+    pub pulse: Duration,
+}
+
+impl Default for DemoConfig {
+    fn default() -> Self {
+        Self { root: None, ignore: Vec::new(), pulse: Duration::from_secs(1) }
+    }
+}
+
+impl DemoConfig {
+    /// The defaults, overridden by the variables that are set.
+    pub fn from_env() -> Self {
+        let var = |name| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let mut cfg = Self { root: var("FILESCREAM_ROOT").map(PathBuf::from), ..Self::default() };
+        if let Some(i) = var("FILESCREAM_IGNORE") {
+            cfg.ignore = i.split(',').map(str::trim).filter(|p| !p.is_empty()).map(str::to_string).collect();
+        }
+        if let Some(ms) = var("FILESCREAM_PULSE_MS").and_then(|v| v.parse().ok()) {
+            cfg.pulse = Duration::from_millis(ms);
+        }
+        cfg
+    }
+}
+
+/// A directory under the temp dir with a few files, removed on drop. [`Scratch::churn`]
+/// keeps creating, changing and removing files in it.
+pub struct Scratch {
+    path: PathBuf,
+}
+
+impl Scratch {
+    /// Create `<temp>/filescream-demo-<name>-<pid>` with two files.
+    pub fn create(name: &str) -> io::Result<Self> {
+        let path = std::env::temp_dir().join(format!("filescream-demo-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&path)?;
+        std::fs::write(path.join("config.toml"), "answer = 42\n")?;
+        std::fs::write(path.join("notes.txt"), "hello\n")?;
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Every `every`: add `file-<n>.log`, append to `notes.txt` and remove the file added
+    /// two steps before. Runs until dropped (spawn it), errors are ignored.
+    pub fn churn(&self, every: Duration) -> impl Future<Output = ()> + Send + 'static {
+        let path = self.path.clone();
+        async move {
+            for n in 0u64.. {
+                tokio::time::sleep(every).await;
+                let _ = std::fs::write(path.join(format!("file-{n}.log")), format!("{n}\n"));
+                let _ = std::fs::write(path.join("notes.txt"), format!("hello {n}\n"));
+                if let Some(old) = n.checked_sub(2) {
+                    let _ = std::fs::remove_file(path.join(format!("file-{old}.log")));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// The sensor watching `root`, as configured.
+pub fn sensor(cfg: &DemoConfig, root: &Path) -> io::Result<FileScream> {
+    let mut fs = FileScream::new(Some(FileScreamConfig::default().pulse(cfg.pulse)));
+    fs.watch(root)?;
+    for pat in &cfg.ignore {
+        fs.ignore(pat.as_str());
+    }
+    Ok(fs)
+}
+
+/// Prints every event, see [`omnitrace_core::format`].
+pub struct PrintCb;
+
+#[async_trait]
+impl Callback<FileScreamEvent> for PrintCb {
+    fn mask(&self) -> u64 {
+        (FileScreamMask::CREATED | FileScreamMask::CHANGED | FileScreamMask::REMOVED).bits()
+    }
+
+    async fn call(&self, ev: &FileScreamEvent) -> Option<CallbackResult> {
+        println!("{}", Style::for_stdout().short(ev));
+        None
+    }
+}
+
+/// Run `sensor` with [`PrintCb`] added to `hub`, printing the results, until `stop`
+/// resolves or the sensor ends, see [`sensor::run_until`].
+pub async fn run<F: Future>(sensor: FileScream, mut hub: CallbackHub<FileScreamEvent>, stop: F) -> bool {
+    hub.add(PrintCb);
+    sensor::run_until(sensor, hub, stop, |r| println!("RESULT: {r}")).await
+}
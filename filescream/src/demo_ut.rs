@@ -0,0 +1,52 @@
+use crate::{
+    demo::{self, DemoConfig, Scratch},
+    events::{FileScreamEvent, FileScreamMask},
+};
+use async_trait::async_trait;
+use omnitrace_core::{
+    callbacks::{Callback, CallbackHub, CallbackResult},
+    fields::EventFields,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+struct Recorder(Arc<Mutex<Vec<FileScreamEvent>>>);
+
+#[async_trait]
+impl Callback<FileScreamEvent> for Recorder {
+    fn mask(&self) -> u64 {
+        FileScreamMask::all().bits()
+    }
+
+    async fn call(&self, ev: &FileScreamEvent) -> Option<CallbackResult> {
+        self.0.lock().unwrap().push(ev.clone());
+        None
+    }
+}
+
+#[tokio::test]
+async fn example_wiring_reports_the_churn_and_stops_in_time() {
+    let scratch = Scratch::create("ut").unwrap();
+    let cfg = DemoConfig { pulse: Duration::from_millis(10), ..DemoConfig::default() };
+    let fs = demo::sensor(&cfg, scratch.path()).unwrap();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(seen.clone()));
+
+    let churn = tokio::spawn(scratch.churn(Duration::from_millis(30)));
+    let stop = tokio::time::sleep(Duration::from_millis(200));
+    let stopped = tokio::time::timeout(Duration::from_secs(5), demo::run(fs, hub, stop)).await;
+    churn.abort();
+    let path = scratch.path().to_path_buf();
+    drop(scratch);
+
+    assert!(stopped.expect("shut down in time"));
+    assert!(!path.exists(), "scratch directory left behind");
+    let seen = seen.lock().unwrap();
+    let kinds: Vec<&str> = seen.iter().map(|ev| ev.kind()).collect();
+    for kind in ["created", "changed", "removed"] {
+        assert!(kinds.contains(&kind), "no {kind} in {seen:?}");
+    }
+}
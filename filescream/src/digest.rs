@@ -0,0 +1,146 @@
+//! Content hash algorithms, see [`crate::content::ContentHashing::algorithm`].
+//!
+//! Content hashes are BLAKE3 unless configured otherwise, e.g. SHA-256 (feature `sha256`) where
+//! integrity reports must use it. Every [`crate::manifest::ManifestEntry`] names the algorithm
+//! of its hash, so a baseline taken with another one is told apart rather than trusted: see
+//! [`crate::FileScream::diff_manifests`]. Metadata hashes are always BLAKE3.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    io::{self, Read},
+};
+
+/// A hash, whatever the algorithm: both produce 32 bytes.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Digest([u8; 32]);
+
+impl Digest {
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    /// `None` unless `hex` is 64 hex digits, in either case.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        if hex.len() != 64 || !hex.is_ascii() {
+            return None;
+        }
+        let mut out = [0u8; 32];
+        for (i, b) in out.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+        }
+        Some(Self(out))
+    }
+}
+
+impl From<blake3::Hash> for Digest {
+    fn from(h: blake3::Hash) -> Self {
+        Self(*h.as_bytes())
+    }
+}
+
+impl fmt::Debug for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Digest({})", self.to_hex())
+    }
+}
+
+/// Which hash a content digest is, stored with it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Blake3,
+    #[cfg(feature = "sha256")]
+    Sha256,
+}
+
+impl HashAlgorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Blake3 => "blake3",
+            #[cfg(feature = "sha256")]
+            HashAlgorithm::Sha256 => "sha256",
+        }
+    }
+
+    pub fn hasher(self) -> Box<dyn ContentHasher> {
+        match self {
+            HashAlgorithm::Blake3 => Box::new(blake3::Hasher::new()),
+            #[cfg(feature = "sha256")]
+            HashAlgorithm::Sha256 => Box::new(<sha2::Sha256 as sha2::Digest>::new()),
+        }
+    }
+
+    /// Digest of everything `r` yields.
+    pub fn hash_reader<R: Read>(self, mut r: R) -> io::Result<Digest> {
+        let mut h = self.hasher();
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            match r.read(&mut buf)? {
+                0 => return Ok(h.digest()),
+                n => h.update(&buf[..n]),
+            }
+        }
+    }
+}
+
+/// An incremental hash of one algorithm. It can be cloned mid-way, which is what lets
+/// [`crate::content::AppendAware`] hashing resume where the last scan stopped.
+pub trait ContentHasher: Send + Sync {
+    fn algorithm(&self) -> HashAlgorithm;
+
+    fn update(&mut self, data: &[u8]);
+
+    /// The digest of what was fed so far; feeding can go on afterwards.
+    fn digest(&self) -> Digest;
+
+    fn boxed_clone(&self) -> Box<dyn ContentHasher>;
+}
+
+impl Clone for Box<dyn ContentHasher> {
+    fn clone(&self) -> Self {
+        self.boxed_clone()
+    }
+}
+
+impl ContentHasher for blake3::Hasher {
+    fn algorithm(&self) -> HashAlgorithm {
+        HashAlgorithm::Blake3
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        blake3::Hasher::update(self, data);
+    }
+
+    fn digest(&self) -> Digest {
+        self.finalize().into()
+    }
+
+    fn boxed_clone(&self) -> Box<dyn ContentHasher> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(feature = "sha256")]
+impl ContentHasher for sha2::Sha256 {
+    fn algorithm(&self) -> HashAlgorithm {
+        HashAlgorithm::Sha256
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        sha2::Digest::update(self, data);
+    }
+
+    fn digest(&self) -> Digest {
+        Digest(sha2::Digest::finalize(self.clone()).into())
+    }
+
+    fn boxed_clone(&self) -> Box<dyn ContentHasher> {
+        Box::new(self.clone())
+    }
+}
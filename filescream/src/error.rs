@@ -0,0 +1,35 @@
+use omnitrace_core::error::SensorError;
+use std::{io, path::PathBuf};
+
+/// What FileScream runs past, recorded in [`crate::FileScream::diagnostics`]. Files and
+/// directories vanishing mid-scan are not errors.
+#[derive(Debug, thiserror::Error)]
+pub enum FileScreamError {
+    /// The entry is left out of the scan, a directory with everything below it.
+    #[error("cannot read {}: {source}", path.display())]
+    Walk {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// The file keeps its size and mtime hash until it can be read again.
+    #[error("cannot hash {}: {source}", path.display())]
+    Hash {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// Nothing is ignored for it.
+    #[error("invalid ignore pattern {pattern:?}: {source}")]
+    Pattern {
+        pattern: String,
+        #[source]
+        source: globset::Error,
+    },
+}
+
+impl From<FileScreamError> for SensorError {
+    fn from(e: FileScreamError) -> Self {
+        SensorError::sensor("filescream", e)
+    }
+}
@@ -1,28 +1,484 @@
+use crate::modes::FileMode;
 use bitflags::bitflags;
-use std::path::PathBuf;
+use omnitrace_core::{
+    expected::Deviation,
+    fields::{EventFields, FieldValue},
+    labels::Labels,
+    topics::{self, Topic, Topics},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-#[derive(Clone, Debug)]
+// `root` is the watched root owning `path` (the innermost one when roots nest),
+// `rel_path` is `path` relative to it. Paths that are not UTF-8 serialize losslessly,
+// see `omnitrace_core::paths`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum FileScreamEvent {
-    Created { path: PathBuf },
-    Changed { path: PathBuf },
-    Removed { path: PathBuf },
+    Created {
+        #[serde(with = "omnitrace_core::paths")]
+        path: PathBuf,
+        #[serde(with = "omnitrace_core::paths")]
+        root: PathBuf,
+        #[serde(with = "omnitrace_core::paths")]
+        rel_path: PathBuf,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    Changed {
+        #[serde(with = "omnitrace_core::paths")]
+        path: PathBuf,
+        #[serde(with = "omnitrace_core::paths")]
+        root: PathBuf,
+        #[serde(with = "omnitrace_core::paths")]
+        rel_path: PathBuf,
+        #[serde(default)]
+        change: FileChange,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    Removed {
+        #[serde(with = "omnitrace_core::paths")]
+        path: PathBuf,
+        #[serde(with = "omnitrace_core::paths")]
+        root: PathBuf,
+        #[serde(with = "omnitrace_core::paths")]
+        rel_path: PathBuf,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    /// A watched root went away (missing, or its filesystem got unmounted). Its files are frozen
+    /// instead of being reported as removed. Only with `FileScreamConfig::mount_aware`.
+    RootUnavailable {
+        #[serde(with = "omnitrace_core::paths")]
+        root: PathBuf,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    /// A root is back. Changes made while it was away follow as regular events.
+    RootRestored {
+        #[serde(with = "omnitrace_core::paths")]
+        root: PathBuf,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    /// Unusually many files changed in one scan under `root`/`subtree` (`subtree` is empty unless
+    /// grouping by depth). `window` is the time since the previous scan, `baseline` the recent average
+    /// of changes per scan. The per-file events are fired as usual. Only with `FileScreamConfig::activity_spikes`.
+    ActivitySpike {
+        #[serde(with = "omnitrace_core::paths")]
+        root: PathBuf,
+        #[serde(with = "omnitrace_core::paths")]
+        subtree: PathBuf,
+        window: Duration,
+        created: usize,
+        changed: usize,
+        removed: usize,
+        baseline: f64,
+    },
+    /// Estimated memory use went over `FileScreamConfig::memory_budget`. `metadata_only` lists
+    /// the subtrees switched from content to metadata hashes to make room, `after_shedding` is
+    /// the estimate afterwards, still over budget if the file table itself is too large.
+    OverBudget { estimated_bytes: u64, after_shedding: u64, budget: u64, metadata_only: Vec<String> },
+    /// A file newly matches a mode rule set with `FileScream::alert_on`: it was created that way
+    /// (`old` is None) or chmod/chown made it match. `rules` names the rules it did not match before.
+    SuspiciousMode {
+        #[serde(with = "omnitrace_core::paths")]
+        path: PathBuf,
+        #[serde(with = "omnitrace_core::paths")]
+        root: PathBuf,
+        #[serde(with = "omnitrace_core::paths")]
+        rel_path: PathBuf,
+        rules: Vec<String>,
+        old: Option<FileMode>,
+        new: FileMode,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    /// The file at `path` is not as declared, see [`crate::FileScream::expect`]. Fired when the
+    /// sensor primes, before any change. `root` is empty for a path under no watched root.
+    Deviation {
+        #[serde(with = "omnitrace_core::paths")]
+        path: PathBuf,
+        #[serde(with = "omnitrace_core::paths")]
+        root: PathBuf,
+        #[serde(with = "omnitrace_core::paths")]
+        rel_path: PathBuf,
+        #[serde(flatten)]
+        deviation: Deviation,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    /// A call on the FUSE filesystem mounted at `subtree` (`op`: `metadata` or `read_dir`) did
+    /// not return within `after`, see [`crate::FileScreamConfig::fuse_timeout`]. Scans skip the
+    /// subtree and keep its files as they were until it answers again.
+    SubtreeStalled {
+        #[serde(with = "omnitrace_core::paths")]
+        root: PathBuf,
+        #[serde(with = "omnitrace_core::paths")]
+        subtree: PathBuf,
+        op: String,
+        after: Duration,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    /// The stalled call on `subtree` returned and it is scanned again. Changes made meanwhile
+    /// follow as regular events.
+    SubtreeResumed {
+        #[serde(with = "omnitrace_core::paths")]
+        root: PathBuf,
+        #[serde(with = "omnitrace_core::paths")]
+        subtree: PathBuf,
+        stalled_for: Duration,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+}
+
+/// What about a file changed, see [`FileScreamEvent::Changed`].
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileChange {
+    /// Its content: by size or mtime, or by content hash with
+    /// [`crate::FileScreamConfig::content_hashing`].
+    #[default]
+    Content,
+    /// Only its inode, e.g. a chmod, chown or touched ctime. Not reported when hashing content.
+    Metadata,
+    /// Another file took its place, see [`crate::FileScreamConfig::replaced_as`].
+    Replaced,
+    /// It grew, and content before its old end changed too, found by
+    /// [`crate::content::AppendAware`] hashing.
+    Rewritten,
+    /// Its content hashes are of two algorithms, so whether it changed is unknown. Only from
+    /// [`crate::FileScream::diff_manifests`].
+    Unverified,
+}
+
+impl FileChange {
+    pub fn name(self) -> &'static str {
+        match self {
+            FileChange::Content => "content",
+            FileChange::Metadata => "metadata",
+            FileChange::Replaced => "replaced",
+            FileChange::Rewritten => "rewritten",
+            FileChange::Unverified => "unverified",
+        }
+    }
 }
 
 bitflags! {
+    /// Kinds in the low 16 bits, sub-kinds of `Changed` from bit 16 (see
+    /// `omnitrace_core::callbacks::SUBKIND_BITS`). A `Changed` event has `CHANGED` and exactly
+    /// one of the `CHANGED_*` sub-kinds, after its [`FileChange`].
     #[derive(Copy, Clone, Debug)]
     pub struct FileScreamMask: u64 {
         const CREATED = 0b0001;
         const CHANGED = 0b0010;
         const REMOVED = 0b0100;
+        const ROOT_UNAVAILABLE = 0b1000;
+        const ROOT_RESTORED = 0b1_0000;
+        const ACTIVITY_SPIKE = 0b10_0000;
+        const OVER_BUDGET = 0b100_0000;
+        const SUSPICIOUS_MODE = 0b1000_0000;
+        const DEVIATION = 0b1_0000_0000;
+        const SUBTREE_STALLED = 0b10_0000_0000;
+        const SUBTREE_RESUMED = 0b100_0000_0000;
+
+        const CHANGED_CONTENT = 1 << 16;
+        const CHANGED_METADATA = 1 << 17;
+        const CHANGED_REPLACED = 1 << 18;
+        const CHANGED_REWRITTEN = 1 << 19;
+        const CHANGED_UNVERIFIED = 1 << 20;
     }
 }
 
 impl FileScreamEvent {
+    /// Synthetic `Created` event for `rel_path` under `root`, for injecting
+    /// (see `CallbackHub::inject`).
+    pub fn test_created<P: Into<PathBuf>, R: Into<PathBuf>>(root: P, rel_path: R) -> Self {
+        let (root, rel_path) = (root.into(), rel_path.into());
+        FileScreamEvent::Created { path: root.join(&rel_path), root, rel_path, labels: Labels::default() }
+    }
+
+    /// Synthetic `Changed` event, see [`FileScreamEvent::test_created`].
+    pub fn test_changed<P: Into<PathBuf>, R: Into<PathBuf>>(root: P, rel_path: R) -> Self {
+        let (root, rel_path) = (root.into(), rel_path.into());
+        FileScreamEvent::Changed { path: root.join(&rel_path), root, rel_path, change: FileChange::Content, labels: Labels::default() }
+    }
+
+    /// Synthetic `Removed` event, see [`FileScreamEvent::test_created`].
+    pub fn test_removed<P: Into<PathBuf>, R: Into<PathBuf>>(root: P, rel_path: R) -> Self {
+        let (root, rel_path) = (root.into(), rel_path.into());
+        FileScreamEvent::Removed { path: root.join(&rel_path), root, rel_path, labels: Labels::default() }
+    }
+
+    /// The file (or root, or stalled subtree) the event is about, None for ActivitySpike and
+    /// OverBudget.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            FileScreamEvent::Created { path, .. }
+            | FileScreamEvent::Changed { path, .. }
+            | FileScreamEvent::Removed { path, .. }
+            | FileScreamEvent::SuspiciousMode { path, .. }
+            | FileScreamEvent::Deviation { path, .. } => Some(path),
+            FileScreamEvent::RootUnavailable { root, .. } | FileScreamEvent::RootRestored { root, .. } => Some(root),
+            FileScreamEvent::SubtreeStalled { subtree, .. } | FileScreamEvent::SubtreeResumed { subtree, .. } => Some(subtree),
+            FileScreamEvent::ActivitySpike { .. } | FileScreamEvent::OverBudget { .. } => None,
+        }
+    }
+
+    /// The watched root the event is about, None for OverBudget.
+    pub fn root(&self) -> Option<&Path> {
+        match self {
+            FileScreamEvent::Created { root, .. }
+            | FileScreamEvent::Changed { root, .. }
+            | FileScreamEvent::Removed { root, .. }
+            | FileScreamEvent::RootUnavailable { root, .. }
+            | FileScreamEvent::RootRestored { root, .. }
+            | FileScreamEvent::ActivitySpike { root, .. }
+            | FileScreamEvent::SuspiciousMode { root, .. }
+            | FileScreamEvent::Deviation { root, .. }
+            | FileScreamEvent::SubtreeStalled { root, .. }
+            | FileScreamEvent::SubtreeResumed { root, .. } => Some(root),
+            FileScreamEvent::OverBudget { .. } => None,
+        }
+    }
+
+    /// Labels of the watched roots holding the path, see [`crate::FileScream::watch_with`].
+    pub fn labels(&self) -> &Labels {
+        match self {
+            FileScreamEvent::Created { labels, .. }
+            | FileScreamEvent::Changed { labels, .. }
+            | FileScreamEvent::Removed { labels, .. }
+            | FileScreamEvent::RootUnavailable { labels, .. }
+            | FileScreamEvent::RootRestored { labels, .. }
+            | FileScreamEvent::SuspiciousMode { labels, .. }
+            | FileScreamEvent::Deviation { labels, .. }
+            | FileScreamEvent::SubtreeStalled { labels, .. }
+            | FileScreamEvent::SubtreeResumed { labels, .. } => labels,
+            FileScreamEvent::ActivitySpike { .. } | FileScreamEvent::OverBudget { .. } => Labels::empty(),
+        }
+    }
+
+    /// The path whose roots label the event, and its labels. None for events about no one path.
+    pub(crate) fn labels_mut(&mut self) -> Option<(&Path, &mut Labels)> {
+        match self {
+            FileScreamEvent::Created { path, labels, .. }
+            | FileScreamEvent::Changed { path, labels, .. }
+            | FileScreamEvent::Removed { path, labels, .. }
+            | FileScreamEvent::SuspiciousMode { path, labels, .. }
+            | FileScreamEvent::Deviation { path, labels, .. } => Some((path, labels)),
+            FileScreamEvent::RootUnavailable { root, labels } | FileScreamEvent::RootRestored { root, labels } => Some((root, labels)),
+            FileScreamEvent::SubtreeStalled { subtree, labels, .. } | FileScreamEvent::SubtreeResumed { subtree, labels, .. } => {
+                Some((subtree, labels))
+            }
+            FileScreamEvent::ActivitySpike { .. } | FileScreamEvent::OverBudget { .. } => None,
+        }
+    }
+
     pub fn mask(&self) -> FileScreamMask {
         match self {
             FileScreamEvent::Created { .. } => FileScreamMask::CREATED,
-            FileScreamEvent::Changed { .. } => FileScreamMask::CHANGED,
+            FileScreamEvent::Changed { change, .. } => match change {
+                FileChange::Content => FileScreamMask::CHANGED | FileScreamMask::CHANGED_CONTENT,
+                FileChange::Metadata => FileScreamMask::CHANGED | FileScreamMask::CHANGED_METADATA,
+                FileChange::Replaced => FileScreamMask::CHANGED | FileScreamMask::CHANGED_REPLACED,
+                FileChange::Rewritten => FileScreamMask::CHANGED | FileScreamMask::CHANGED_REWRITTEN,
+                FileChange::Unverified => FileScreamMask::CHANGED | FileScreamMask::CHANGED_UNVERIFIED,
+            },
             FileScreamEvent::Removed { .. } => FileScreamMask::REMOVED,
+            FileScreamEvent::RootUnavailable { .. } => FileScreamMask::ROOT_UNAVAILABLE,
+            FileScreamEvent::RootRestored { .. } => FileScreamMask::ROOT_RESTORED,
+            FileScreamEvent::ActivitySpike { .. } => FileScreamMask::ACTIVITY_SPIKE,
+            FileScreamEvent::OverBudget { .. } => FileScreamMask::OVER_BUDGET,
+            FileScreamEvent::SuspiciousMode { .. } => FileScreamMask::SUSPICIOUS_MODE,
+            FileScreamEvent::Deviation { .. } => FileScreamMask::DEVIATION,
+            FileScreamEvent::SubtreeStalled { .. } => FileScreamMask::SUBTREE_STALLED,
+            FileScreamEvent::SubtreeResumed { .. } => FileScreamMask::SUBTREE_RESUMED,
+        }
+    }
+
+    /// Dotted topic, see [`omnitrace_core::topics`]. `Changed` is
+    /// `file.changed.<content|metadata|replaced|rewritten|unverified>`, `Deviation` is
+    /// `file.deviation.<missing|unexpected|mismatch>`.
+    pub fn topic(&self) -> &'static str {
+        topics::topic_of(self)
+    }
+}
+
+impl Topics for FileScreamEvent {
+    const TOPICS: &'static [Topic] = &[
+        Topic::new("file.created", FileScreamMask::CREATED.bits()),
+        Topic::new("file.changed.content", FileScreamMask::CHANGED.union(FileScreamMask::CHANGED_CONTENT).bits()),
+        Topic::new("file.changed.metadata", FileScreamMask::CHANGED.union(FileScreamMask::CHANGED_METADATA).bits()),
+        Topic::new("file.changed.replaced", FileScreamMask::CHANGED.union(FileScreamMask::CHANGED_REPLACED).bits()),
+        Topic::new("file.changed.rewritten", FileScreamMask::CHANGED.union(FileScreamMask::CHANGED_REWRITTEN).bits()),
+        Topic::new("file.changed.unverified", FileScreamMask::CHANGED.union(FileScreamMask::CHANGED_UNVERIFIED).bits()),
+        Topic::new("file.removed", FileScreamMask::REMOVED.bits()),
+        Topic::new("file.root.unavailable", FileScreamMask::ROOT_UNAVAILABLE.bits()),
+        Topic::new("file.root.restored", FileScreamMask::ROOT_RESTORED.bits()),
+        Topic::new("file.activity_spike", FileScreamMask::ACTIVITY_SPIKE.bits()),
+        Topic::new("file.over_budget", FileScreamMask::OVER_BUDGET.bits()),
+        Topic::new("file.suspicious_mode", FileScreamMask::SUSPICIOUS_MODE.bits()),
+        Topic::new("file.deviation.missing", FileScreamMask::DEVIATION.bits()),
+        Topic::new("file.deviation.unexpected", FileScreamMask::DEVIATION.bits()),
+        Topic::new("file.deviation.mismatch", FileScreamMask::DEVIATION.bits()),
+        Topic::new("file.subtree.stalled", FileScreamMask::SUBTREE_STALLED.bits()),
+        Topic::new("file.subtree.resumed", FileScreamMask::SUBTREE_RESUMED.bits()),
+    ];
+
+    fn topic_index(&self) -> usize {
+        match self {
+            FileScreamEvent::Created { .. } => 0,
+            FileScreamEvent::Changed { change, .. } => match change {
+                FileChange::Content => 1,
+                FileChange::Metadata => 2,
+                FileChange::Replaced => 3,
+                FileChange::Rewritten => 4,
+                FileChange::Unverified => 5,
+            },
+            FileScreamEvent::Removed { .. } => 6,
+            FileScreamEvent::RootUnavailable { .. } => 7,
+            FileScreamEvent::RootRestored { .. } => 8,
+            FileScreamEvent::ActivitySpike { .. } => 9,
+            FileScreamEvent::OverBudget { .. } => 10,
+            FileScreamEvent::SuspiciousMode { .. } => 11,
+            FileScreamEvent::Deviation { deviation, .. } => match deviation {
+                Deviation::Missing => 12,
+                Deviation::Unexpected => 13,
+                Deviation::Mismatch { .. } => 14,
+            },
+            FileScreamEvent::SubtreeStalled { .. } => 15,
+            FileScreamEvent::SubtreeResumed { .. } => 16,
+        }
+    }
+}
+
+/// `path`, `root` and `rel_path` where the event has them, the `change` of `Changed`
+/// (`content`, `metadata`, `replaced`, `rewritten`, `unverified`), the counters of `ActivitySpike`
+/// (its window as `window_ms`) and `OverBudget`, and for `SuspiciousMode` the new `mode`,
+/// `uid` and `gid`, also as `new.mode`, and the previous ones as `old.mode`. `Deviation` has its
+/// `deviation` (`missing`, `unexpected`, `mismatch`) and the mismatched `field` with `expected`
+/// and `actual`. `SubtreeStalled` and `SubtreeResumed` have the `subtree`, the stalled `op`
+/// and how long it was waited for as `after_ms`, or how long it stalled as `stalled_for_ms`.
+impl EventFields for FileScreamEvent {
+    fn kind(&self) -> &'static str {
+        match self {
+            FileScreamEvent::Created { .. } => "created",
+            FileScreamEvent::Changed { .. } => "changed",
+            FileScreamEvent::Removed { .. } => "removed",
+            FileScreamEvent::RootUnavailable { .. } => "root_unavailable",
+            FileScreamEvent::RootRestored { .. } => "root_restored",
+            FileScreamEvent::ActivitySpike { .. } => "activity_spike",
+            FileScreamEvent::OverBudget { .. } => "over_budget",
+            FileScreamEvent::SuspiciousMode { .. } => "suspicious_mode",
+            FileScreamEvent::Deviation { .. } => "deviation",
+            FileScreamEvent::SubtreeStalled { .. } => "subtree_stalled",
+            FileScreamEvent::SubtreeResumed { .. } => "subtree_resumed",
         }
     }
+
+    fn field(&self, name: &str) -> Option<FieldValue<'_>> {
+        if name == "root" {
+            return self.root().map(FieldValue::Path);
+        }
+        match self {
+            FileScreamEvent::Created { path, rel_path, .. } | FileScreamEvent::Removed { path, rel_path, .. } => match name {
+                "path" => Some(FieldValue::Path(path)),
+                "rel_path" => Some(FieldValue::Path(rel_path)),
+                _ => None,
+            },
+            FileScreamEvent::Changed { path, rel_path, change, .. } => match name {
+                "path" => Some(FieldValue::Path(path)),
+                "rel_path" => Some(FieldValue::Path(rel_path)),
+                "change" => Some(FieldValue::str(change.name())),
+                _ => None,
+            },
+            FileScreamEvent::RootUnavailable { .. } | FileScreamEvent::RootRestored { .. } => None,
+            FileScreamEvent::ActivitySpike { subtree, window, created, changed, removed, .. } => match name {
+                "subtree" => Some(FieldValue::Path(subtree)),
+                "window_ms" => Some(FieldValue::count(window.as_millis() as u64)),
+                "created" => Some(FieldValue::count(*created as u64)),
+                "changed" => Some(FieldValue::count(*changed as u64)),
+                "removed" => Some(FieldValue::count(*removed as u64)),
+                _ => None,
+            },
+            FileScreamEvent::OverBudget { estimated_bytes, after_shedding, budget, .. } => match name {
+                "estimated_bytes" => Some(FieldValue::count(*estimated_bytes)),
+                "after_shedding" => Some(FieldValue::count(*after_shedding)),
+                "budget" => Some(FieldValue::count(*budget)),
+                _ => None,
+            },
+            FileScreamEvent::Deviation { path, rel_path, deviation, .. } => match (name, deviation) {
+                ("path", _) => Some(FieldValue::Path(path)),
+                ("rel_path", _) => Some(FieldValue::Path(rel_path)),
+                ("deviation", d) => Some(FieldValue::str(d.name())),
+                ("field", Deviation::Mismatch { field, .. }) => Some(FieldValue::str(field)),
+                ("expected", Deviation::Mismatch { expected, .. }) => Some(FieldValue::str(expected)),
+                ("actual", Deviation::Mismatch { actual, .. }) => Some(FieldValue::str(actual)),
+                _ => None,
+            },
+            FileScreamEvent::SubtreeStalled { subtree, op, after, .. } => match name {
+                "subtree" => Some(FieldValue::Path(subtree)),
+                "op" => Some(FieldValue::str(op)),
+                "after_ms" => Some(FieldValue::count(after.as_millis() as u64)),
+                _ => None,
+            },
+            FileScreamEvent::SubtreeResumed { subtree, stalled_for, .. } => match name {
+                "subtree" => Some(FieldValue::Path(subtree)),
+                "stalled_for_ms" => Some(FieldValue::count(stalled_for.as_millis() as u64)),
+                _ => None,
+            },
+            FileScreamEvent::SuspiciousMode { path, rel_path, old, new, .. } => match name {
+                "path" => Some(FieldValue::Path(path)),
+                "rel_path" => Some(FieldValue::Path(rel_path)),
+                _ => match name.strip_prefix("old.") {
+                    Some(n) => old.as_ref()?.field(n),
+                    None => new.field(name.strip_prefix("new.").unwrap_or(name)),
+                },
+            },
+        }
+    }
+
+    fn field_names(&self) -> &'static [&'static str] {
+        match self {
+            FileScreamEvent::Created { .. } | FileScreamEvent::Removed { .. } => &["path", "root", "rel_path"],
+            FileScreamEvent::Changed { .. } => &["path", "root", "rel_path", "change"],
+            FileScreamEvent::RootUnavailable { .. } | FileScreamEvent::RootRestored { .. } => &["root"],
+            FileScreamEvent::ActivitySpike { .. } => &["root", "subtree", "window_ms", "created", "changed", "removed"],
+            FileScreamEvent::OverBudget { .. } => &["estimated_bytes", "after_shedding", "budget"],
+            FileScreamEvent::SuspiciousMode { .. } => &["path", "root", "rel_path", "mode", "uid", "gid", "new.mode", "old.mode"],
+            FileScreamEvent::Deviation { .. } => &["path", "root", "rel_path", "deviation", "field", "expected", "actual"],
+            FileScreamEvent::SubtreeStalled { .. } => &["root", "subtree", "op", "after_ms"],
+            FileScreamEvent::SubtreeResumed { .. } => &["root", "subtree", "stalled_for_ms"],
+        }
+    }
+
+    fn summary_fields(&self) -> &'static [&'static str] {
+        match self {
+            FileScreamEvent::Created { .. } | FileScreamEvent::Removed { .. } => &["path"],
+            FileScreamEvent::Changed { .. } => &["path", "change"],
+            FileScreamEvent::ActivitySpike { .. } => &["subtree", "created", "changed", "removed"],
+            FileScreamEvent::OverBudget { .. } => &["estimated_bytes", "budget"],
+            FileScreamEvent::SuspiciousMode { .. } => &["path", "old.mode", "new.mode"],
+            FileScreamEvent::Deviation { .. } => &["path", "deviation", "field", "expected", "actual"],
+            FileScreamEvent::SubtreeStalled { .. } => &["subtree", "op"],
+            FileScreamEvent::SubtreeResumed { .. } => &["subtree", "stalled_for_ms"],
+            _ => self.detail_fields(),
+        }
+    }
+
+    fn detail_fields(&self) -> &'static [&'static str] {
+        match self {
+            FileScreamEvent::SuspiciousMode { .. } => &["path", "root", "rel_path", "old.mode", "new.mode", "uid", "gid"],
+            _ => self.field_names(),
+        }
+    }
+
+    fn topic(&self) -> Option<&'static str> {
+        Some(FileScreamEvent::topic(self))
+    }
 }
@@ -0,0 +1,121 @@
+//! Files declared by configuration management, compared when the sensor primes. See
+//! [`crate::FileScream::expect`] and [`omnitrace_core::expected`].
+
+use crate::{FileScream, digest::HashAlgorithm, manifest::Manifest};
+use omnitrace_core::expected::Deviation;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+/// What should (or should not) be on disk.
+///
+/// ```json
+/// {
+///   "files": [
+///     { "path": "/etc/passwd", "hash": "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262" },
+///     { "path": "/etc/cron.d/backup" },
+///     { "path": "/etc/sudoers.d/temp", "absent": true }
+///   ],
+///   "exclusive": true
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExpectedFiles {
+    pub files: Vec<ExpectedFile>,
+    /// The files are all there should be under the watched roots: any other file found is Unexpected.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub exclusive: bool,
+}
+
+impl ExpectedFiles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn file(mut self, file: ExpectedFile) -> Self {
+        self.files.push(file);
+        self
+    }
+
+    pub fn exclusive(mut self, exclusive: bool) -> Self {
+        self.exclusive = exclusive;
+        self
+    }
+
+    /// Every file of `manifest` and nothing else, e.g. from a golden host. Hashes are only kept
+    /// when they are of content.
+    pub fn from_manifest(manifest: &Manifest) -> Self {
+        let files = manifest
+            .files
+            .iter()
+            .map(|e| ExpectedFile { hash: manifest.by_content.then(|| e.hash.clone()), algorithm: e.algorithm, ..ExpectedFile::new(&e.path) })
+            .collect();
+        Self { files, exclusive: true }
+    }
+
+    /// Declared paths, canonicalized like the scanned ones, with how each differs.
+    pub(crate) fn deviations(&self) -> Vec<(PathBuf, Deviation)> {
+        self.files.iter().filter_map(|f| f.deviation().map(|d| (FileScream::canonical(&f.path), d))).collect()
+    }
+}
+
+/// One path. Unset attributes are not checked.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExpectedFile {
+    #[serde(with = "omnitrace_core::paths")]
+    pub path: PathBuf,
+    /// Hex digest of the content.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// Of `hash`.
+    #[serde(default, skip_serializing_if = "is_blake3")]
+    pub algorithm: HashAlgorithm,
+    /// Nothing should be at `path`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub absent: bool,
+}
+
+impl ExpectedFile {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self { path: path.as_ref().to_path_buf(), hash: None, algorithm: HashAlgorithm::Blake3, absent: false }
+    }
+
+    /// Nothing should be at `path`.
+    pub fn absent<P: AsRef<Path>>(path: P) -> Self {
+        Self { absent: true, ..Self::new(path) }
+    }
+
+    pub fn hash(mut self, hex: &str) -> Self {
+        self.hash = Some(hex.to_ascii_lowercase());
+        self
+    }
+
+    /// A `hash` of another algorithm than BLAKE3.
+    pub fn algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// How the file at `path` differs, reading it if a hash is declared. An unreadable file is a
+    /// `hash` Mismatch with the error as `actual`.
+    pub(crate) fn deviation(&self) -> Option<Deviation> {
+        match (self.path.symlink_metadata().is_ok(), self.absent) {
+            (false, false) => return Some(Deviation::Missing),
+            (true, true) => return Some(Deviation::Unexpected),
+            (false, true) => return None,
+            (true, false) => {}
+        }
+        let expected = self.hash.as_ref()?;
+        let actual = match File::open(&self.path).and_then(|f| self.algorithm.hash_reader(f)) {
+            Ok(hash) => hash.to_hex(),
+            Err(e) => format!("unreadable ({e})"),
+        };
+        (*expected != actual).then(|| Deviation::mismatch("hash", expected, actual))
+    }
+}
+
+fn is_blake3(a: &HashAlgorithm) -> bool {
+    *a == HashAlgorithm::Blake3
+}
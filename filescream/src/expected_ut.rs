@@ -0,0 +1,128 @@
+use crate::{
+    FileScream, FileScreamConfig,
+    events::{FileScreamEvent, FileScreamMask},
+    expected::{ExpectedFile, ExpectedFiles},
+};
+use async_trait::async_trait;
+use omnitrace_core::{
+    callbacks::{Callback, CallbackHub, CallbackResult},
+    expected::{self, Deviation},
+    fields::{EventFields, FieldValue},
+    sensor::spawn_sensor,
+};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tempfile::TempDir;
+
+fn hex(content: &str) -> String {
+    blake3::hash(content.as_bytes()).to_hex().to_string()
+}
+
+struct Recorder(Arc<Mutex<Vec<FileScreamEvent>>>);
+
+#[async_trait]
+impl Callback<FileScreamEvent> for Recorder {
+    fn mask(&self) -> u64 {
+        FileScreamMask::all().bits()
+    }
+
+    async fn call(&self, ev: &FileScreamEvent) -> Option<CallbackResult> {
+        self.0.lock().unwrap().push(ev.clone());
+        None
+    }
+}
+
+#[test]
+fn specs_compare_presence_and_content() {
+    let tmp = TempDir::new().unwrap();
+    std::fs::create_dir(tmp.path().join("etc")).unwrap();
+    let dir = tmp.path().canonicalize().unwrap();
+    std::fs::write(dir.join("etc/passwd"), "root:x:0:0\n").unwrap();
+
+    assert_eq!(ExpectedFile::new(dir.join("etc/passwd")).hash(&hex("root:x:0:0\n").to_uppercase()).deviation(), None);
+    assert_eq!(ExpectedFile::new(dir.join("etc/shadow")).deviation(), Some(Deviation::Missing));
+    assert_eq!(ExpectedFile::absent(dir.join("etc/passwd")).deviation(), Some(Deviation::Unexpected));
+    assert_eq!(ExpectedFile::absent(dir.join("etc/shadow")).deviation(), None);
+    assert_eq!(
+        ExpectedFile::new(dir.join("etc/passwd")).hash(&hex("root:x:0:0\nmallory:x:0:0\n")).deviation(),
+        Some(Deviation::mismatch("hash", hex("root:x:0:0\nmallory:x:0:0\n"), hex("root:x:0:0\n")))
+    );
+    // a directory exists, but has no content to hash
+    assert!(matches!(
+        ExpectedFile::new(dir.join("etc")).hash(&hex("")).deviation(),
+        Some(Deviation::Mismatch { actual, .. }) if actual.starts_with("unreadable")
+    ));
+}
+
+#[test]
+fn specs_load_from_json() {
+    let path = std::env::temp_dir().join(format!("filescream-expected-ut-{}.json", std::process::id()));
+    std::fs::write(&path, r#"{ "files": [{ "path": "/etc/passwd", "hash": "00ff" }, { "path": "/etc/sudoers.d/temp", "absent": true }] }"#).unwrap();
+    let specs: ExpectedFiles = expected::load(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(specs, ExpectedFiles::new().file(ExpectedFile::new("/etc/passwd").hash("00FF")).file(ExpectedFile::absent("/etc/sudoers.d/temp")));
+    assert!(!specs.exclusive);
+}
+
+#[tokio::test]
+async fn deviations_fire_on_prime_before_changes() {
+    let tmp = TempDir::new().unwrap();
+    std::fs::create_dir(tmp.path().join("etc")).unwrap();
+    let dir = tmp.path().canonicalize().unwrap();
+    std::fs::write(dir.join("etc/passwd"), "root:x:0:0\nmallory:x:0:0\n").unwrap();
+    std::fs::write(dir.join("etc/hosts"), "127.0.0.1 localhost\n").unwrap();
+    std::fs::write(dir.join("etc/sudoers.tmp"), "mallory ALL=(ALL) ALL\n").unwrap();
+    std::fs::write(dir.join("etc/backdoor"), "#!/bin/sh\n").unwrap();
+
+    let mut fs = FileScream::new(Some(FileScreamConfig::default().pulse(Duration::from_millis(10))));
+    fs.watch(&dir).unwrap();
+    fs.expect(
+        ExpectedFiles::new()
+            .file(ExpectedFile::new(dir.join("etc/passwd")).hash(&hex("root:x:0:0\n")))
+            .file(ExpectedFile::new(dir.join("etc/hosts")).hash(&hex("127.0.0.1 localhost\n")))
+            .file(ExpectedFile::new(dir.join("etc/shadow")))
+            .file(ExpectedFile::absent(dir.join("etc/sudoers.tmp")))
+            .exclusive(true),
+    );
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(events.clone()));
+    let (handle, task) = spawn_sensor(fs, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    std::fs::write(dir.join("etc/shadow"), "root:!:1\n").unwrap();
+    tokio::time::sleep(Duration::from_millis(80)).await;
+    handle.shutdown();
+    let _ = task.await;
+
+    let events = events.lock().unwrap();
+    let found: Vec<(&Path, &Deviation)> = events
+        .iter()
+        .filter_map(|ev| match ev {
+            FileScreamEvent::Deviation { rel_path, deviation, .. } => Some((rel_path.as_path(), deviation)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (Path::new("etc/passwd"), &Deviation::mismatch("hash", hex("root:x:0:0\n"), hex("root:x:0:0\nmallory:x:0:0\n"))),
+            (Path::new("etc/shadow"), &Deviation::Missing),
+            (Path::new("etc/sudoers.tmp"), &Deviation::Unexpected),
+            (Path::new("etc/backdoor"), &Deviation::Unexpected),
+        ]
+    );
+    // then the live loop
+    assert!(matches!(&events[4..], [FileScreamEvent::Created { rel_path, .. }] if rel_path == Path::new("etc/shadow")));
+
+    let backdoor = &events[3];
+    assert_eq!(backdoor.topic(), "file.deviation.unexpected");
+    assert_eq!(backdoor.root(), Some(dir.as_path()));
+    assert_eq!(backdoor.field("deviation"), Some(FieldValue::str("unexpected")));
+    let json = serde_json::to_value(&events[0]).unwrap();
+    assert_eq!(json["Deviation"]["deviation"], "mismatch");
+    assert_eq!(json["Deviation"]["field"], "hash");
+}
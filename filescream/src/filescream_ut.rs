@@ -0,0 +1,1408 @@
+use crate::{
+    FileRecord, FileScream, FileScreamConfig, Replaced, ScanCtx, WatchOptions,
+    content::{AppendAware, ContentHashing, ContentScanner, HashJob, ReadStrategy, TokenBucket, hash_file},
+    digest::{Digest, HashAlgorithm},
+    error::FileScreamError,
+    events::{FileChange, FileScreamEvent, FileScreamMask},
+    fs::{Fs, RealFs},
+    fuse::FuseGuard,
+    health::ScanOutcome,
+    manifest::{Manifest, ManifestEntry},
+    modes::{FileMode, ModeRule},
+    preview::FileScreamRule,
+    spike::{ScanCounts, SpikeConfig, SpikeDetector},
+};
+use async_trait::async_trait;
+use hashbrown::HashMap;
+use omnitrace_core::{
+    callbacks::{Callback, CallbackHub, CallbackResult, KIND_BITS, RESERVED_BITS},
+    clock::ManualClock,
+    debug::Snapshots,
+    degrade::{Profile, ProfileSwitch},
+    expected::Deviation,
+    fields::{self, EventFields, FieldValue},
+    format::{format_long, format_short},
+    history::HistoryConfig,
+    labels::Labels,
+    sensor::{Sensor, spawn_sensor},
+    severity::Severity,
+    topics::Topics,
+};
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tempfile::TempDir;
+use tokio::sync::mpsc::channel;
+use tokio_util::sync::CancellationToken;
+
+struct JsonCb;
+
+#[async_trait]
+impl Callback<FileScreamEvent> for JsonCb {
+    fn mask(&self) -> u64 {
+        (FileScreamMask::CREATED | FileScreamMask::CHANGED | FileScreamMask::REMOVED).bits()
+    }
+
+    async fn call(&self, ev: &FileScreamEvent) -> Option<CallbackResult> {
+        serde_json::to_value(ev).ok()
+    }
+}
+
+#[test]
+fn duplicate_root_is_rejected() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path().canonicalize().unwrap();
+    let mut fs = FileScream::new(None);
+
+    fs.watch(&dir).unwrap();
+    let err = fs.watch(dir.join(".")).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+    // nesting is not a duplicate
+    std::fs::create_dir_all(dir.join("b")).unwrap();
+    fs.watch(dir.join("b")).unwrap();
+}
+
+#[tokio::test]
+async fn nested_roots_innermost_wins() {
+    let tmp = TempDir::new().unwrap();
+    let a = tmp.path().canonicalize().unwrap();
+    let b = a.join("b");
+    std::fs::create_dir_all(&b).unwrap();
+
+    let mut fs = FileScream::new(Some(FileScreamConfig::default().pulse(Duration::from_millis(10))));
+    fs.watch(&a).unwrap();
+    fs.watch(&b).unwrap();
+
+    let (tx, mut rx) = channel::<CallbackResult>(16);
+    let mut hub = CallbackHub::<FileScreamEvent>::new();
+    hub.add(JsonCb);
+    hub.set_result_channel(tx);
+
+    let (handle, task) = spawn_sensor(fs, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    std::fs::write(b.join("inner.txt"), "x").unwrap();
+    std::fs::write(a.join("outer.txt"), "x").unwrap();
+
+    let mut events = Vec::new();
+    while events.len() < 2 {
+        events.push(tokio::time::timeout(Duration::from_millis(500), rx.recv()).await.unwrap().unwrap());
+    }
+    handle.shutdown();
+    let _ = task.await;
+
+    events.sort_by_key(|e| e["Created"]["rel_path"].to_string());
+
+    // a/b/inner.txt is under both roots and belongs to the innermost one
+    assert_eq!(events[0]["Created"]["path"], b.join("inner.txt").to_str().unwrap());
+    assert_eq!(events[0]["Created"]["root"], b.to_str().unwrap());
+    assert_eq!(events[0]["Created"]["rel_path"], "inner.txt");
+
+    assert_eq!(events[1]["Created"]["root"], a.to_str().unwrap());
+    assert_eq!(events[1]["Created"]["rel_path"], "outer.txt");
+}
+
+#[tokio::test]
+async fn nested_roots_merge_their_labels() {
+    let tmp = TempDir::new().unwrap();
+    let a = tmp.path().canonicalize().unwrap();
+    let b = a.join("b");
+    std::fs::create_dir_all(&b).unwrap();
+
+    let mut fs = FileScream::new(Some(FileScreamConfig::default().pulse(Duration::from_millis(10))));
+    fs.watch_with(&a, WatchOptions::default().labels([("app", "nginx"), ("tier", "config")].into_iter().collect())).unwrap();
+    fs.watch_with(&b, WatchOptions::default().labels([("tier", "certs")].into_iter().collect())).unwrap();
+
+    let (tx, mut rx) = channel::<CallbackResult>(16);
+    let mut hub = CallbackHub::<FileScreamEvent>::new();
+    hub.add(JsonCb);
+    hub.set_result_channel(tx);
+
+    let (handle, task) = spawn_sensor(fs, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    std::fs::write(b.join("inner.pem"), "x").unwrap();
+    std::fs::write(a.join("outer.conf"), "x").unwrap();
+
+    let mut events = Vec::new();
+    while events.len() < 2 {
+        events.push(tokio::time::timeout(Duration::from_millis(500), rx.recv()).await.unwrap().unwrap());
+    }
+    handle.shutdown();
+    let _ = task.await;
+
+    events.sort_by_key(|e| e["Created"]["rel_path"].to_string());
+    // under both roots: the inner one's tier wins
+    assert_eq!(events[0]["Created"]["labels"], serde_json::json!({ "app": "nginx", "tier": "certs" }));
+    assert_eq!(events[1]["Created"]["labels"], serde_json::json!({ "app": "nginx", "tier": "config" }));
+}
+
+#[tokio::test]
+async fn unavailable_root_is_frozen_and_diffed_on_restore() {
+    let tmp = TempDir::new().unwrap();
+    let base = tmp.path().canonicalize().unwrap();
+    let root = base.join("backup");
+    let away = base.join("backup.away");
+    std::fs::create_dir_all(root.join("sub")).unwrap();
+    for name in ["keep.txt", "gone.txt", "sub/deep.txt"] {
+        std::fs::write(root.join(name), name).unwrap();
+    }
+
+    let mut fs = FileScream::new(Some(FileScreamConfig::default().pulse(Duration::from_millis(10)).mount_aware(true)));
+    fs.watch(&root).unwrap();
+
+    let (tx, mut rx) = channel::<CallbackResult>(64);
+    let mut hub = CallbackHub::<FileScreamEvent>::new();
+    hub.add(AllCb);
+    hub.set_result_channel(tx);
+    let (handle, task) = spawn_sensor(fs, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // the root disappears, and changes happen while it is away
+    std::fs::rename(&root, &away).unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    std::fs::remove_file(away.join("gone.txt")).unwrap();
+    std::fs::write(away.join("new.txt"), "new").unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    std::fs::rename(&away, &root).unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    handle.shutdown();
+    let _ = task.await;
+
+    let mut events = Vec::new();
+    while let Ok(ev) = rx.try_recv() {
+        events.push(ev);
+    }
+
+    assert_eq!(events[0], serde_json::json!({ "RootUnavailable": { "root": root } }));
+    assert_eq!(events[1], serde_json::json!({ "RootRestored": { "root": root } }));
+
+    // only the real offline changes, no Removed/Created flood for the whole tree
+    let mut rest: Vec<String> = events[2..].iter().map(|e| e.to_string()).collect();
+    rest.sort();
+    assert_eq!(rest.len(), 2, "{rest:?}");
+    assert!(rest[0].contains("Created") && rest[0].contains("new.txt"));
+    assert!(rest[1].contains("Removed") && rest[1].contains("gone.txt"));
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn non_utf8_names_are_matched_and_serialized_losslessly() {
+    use omnitrace_core::paths;
+    use std::os::unix::ffi::OsStrExt;
+
+    let tmp = TempDir::new().unwrap();
+    let root = tmp.path().canonicalize().unwrap();
+    let mut fs = FileScream::new(Some(FileScreamConfig::default().pulse(Duration::from_millis(10))));
+    fs.watch(&root).unwrap();
+    fs.ignore("*.tmp");
+
+    let (tx, mut rx) = channel::<CallbackResult>(16);
+    let mut hub = CallbackHub::<FileScreamEvent>::new();
+    hub.add(JsonCb);
+    hub.set_result_channel(tx);
+    let (handle, task) = spawn_sensor(fs, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let kept = root.join(std::ffi::OsStr::from_bytes(b"caf\xe9.txt"));
+    std::fs::write(&kept, "x").unwrap();
+    std::fs::write(root.join(std::ffi::OsStr::from_bytes(b"caf\xe9.tmp")), "x").unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    handle.shutdown();
+    let _ = task.await;
+
+    // the ignore pattern still applies to the non-UTF-8 .tmp file
+    let ev = rx.try_recv().unwrap();
+    assert!(rx.try_recv().is_err());
+
+    assert_eq!(ev["Created"]["rel_path"]["lossy"], "caf\u{fffd}.txt");
+    let FileScreamEvent::Created { path, rel_path, .. } = serde_json::from_value(ev).unwrap() else { panic!("wrong variant") };
+    assert_eq!(path, kept);
+    assert_eq!(paths::as_bytes(&rel_path).as_ref(), b"caf\xe9.txt");
+}
+
+/// Feed scripted per-scan totals (all as `changed`) for one group, return which scans spiked.
+fn spikes(d: &mut SpikeDetector, scans: &[usize]) -> Vec<usize> {
+    let key = (PathBuf::from("/srv"), PathBuf::new());
+    let mut fired = Vec::new();
+    for (i, &n) in scans.iter().enumerate() {
+        let counts = HashMap::from([(key.clone(), ScanCounts { changed: n, ..Default::default() })]);
+        if !d.observe(&counts, Duration::from_secs(1)).is_empty() {
+            fired.push(i);
+        }
+    }
+    fired
+}
+
+#[test]
+fn fixed_spike_threshold_with_cooldown() {
+    let mut d = SpikeDetector::new(SpikeConfig::fixed(10).cooldown(2));
+
+    // sustained spike: fires, stays quiet for 2 scans, fires again
+    assert_eq!(spikes(&mut d, &[3, 10, 50, 50, 50, 50, 0, 11]), vec![2, 5]);
+}
+
+#[test]
+fn adaptive_spike_baseline() {
+    let mut d = SpikeDetector::new(SpikeConfig::adaptive(4.0, 20).alpha(0.5).cooldown(0));
+
+    // baseline is 10/scan, so the limit is 40; spikes don't raise the baseline
+    assert_eq!(spikes(&mut d, &[10, 10, 10, 45, 45, 10, 40]), vec![3, 4]);
+
+    // the floor keeps a quiet group from alerting on a handful of files
+    let mut d = SpikeDetector::new(SpikeConfig::adaptive(4.0, 20));
+    assert_eq!(spikes(&mut d, &[0, 0, 1, 15, 21]), vec![4]);
+
+    let ev = d.observe(
+        &HashMap::from([((PathBuf::from("/srv"), PathBuf::new()), ScanCounts { created: 5, changed: 0, removed: 30 })]),
+        Duration::from_secs(3),
+    );
+    assert!(ev.is_empty(), "cooling down");
+}
+
+#[test]
+fn spike_groups_by_depth() {
+    let d = SpikeDetector::new(SpikeConfig::fixed(1).depth(1));
+    let root = Path::new("/srv/share");
+    assert_eq!(d.group(root, Path::new("alice/docs/a.txt")), (root.to_path_buf(), PathBuf::from("alice")));
+    assert_eq!(d.group(root, Path::new("top.txt")), (root.to_path_buf(), PathBuf::new()));
+}
+
+#[tokio::test]
+async fn mass_rewrite_fires_activity_spike() {
+    let tmp = TempDir::new().unwrap();
+    let root = tmp.path().canonicalize().unwrap();
+    for i in 0..30 {
+        std::fs::write(root.join(format!("{i}.doc")), "plain").unwrap();
+    }
+
+    let mut fs = FileScream::new(Some(FileScreamConfig::default().pulse(Duration::from_millis(50)).activity_spikes(SpikeConfig::fixed(20))));
+    fs.watch(&root).unwrap();
+
+    let (tx, mut rx) = channel::<CallbackResult>(128);
+    let mut hub = CallbackHub::<FileScreamEvent>::new();
+    hub.add(AllCb);
+    hub.set_result_channel(tx);
+    let (handle, task) = spawn_sensor(fs, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    for i in 0..30 {
+        std::fs::remove_file(root.join(format!("{i}.doc"))).unwrap();
+        std::fs::write(root.join(format!("{i}.doc.locked")), "encrypted").unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(150)).await;
+
+    handle.shutdown();
+    let _ = task.await;
+
+    let mut events = Vec::new();
+    while let Ok(ev) = rx.try_recv() {
+        events.push(ev);
+    }
+
+    // per-file events still flow, plus one spike
+    let spikes: Vec<_> = events.iter().filter_map(|e| e.get("ActivitySpike")).collect();
+    assert_eq!(spikes.len(), 1, "{spikes:?}");
+    assert_eq!(spikes[0]["root"], root.to_str().unwrap());
+    assert_eq!(spikes[0]["subtree"], "");
+    assert_eq!(spikes[0]["created"].as_u64().unwrap() + spikes[0]["removed"].as_u64().unwrap(), 60);
+    assert_eq!(events.len(), 61);
+}
+
+fn pattern_file(path: &Path, len: usize) {
+    let data: Vec<u8> = (0..len).map(|i| (i * 31 % 251) as u8).collect();
+    std::fs::write(path, data).unwrap();
+}
+
+#[test]
+fn read_strategies_hash_identically() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path().canonicalize().unwrap();
+    for len in [0, 1, 64 * 1024, 200_000] {
+        let path = dir.join(format!("{len}.bin"));
+        pattern_file(&path, len);
+        let expected = Digest::from(blake3::hash(&std::fs::read(&path).unwrap()));
+
+        let hash = |strategy| hash_file(&path, strategy, None, &CancellationToken::new(), HashJob::full(HashAlgorithm::Blake3)).unwrap();
+        let buffered = hash(ReadStrategy::Buffered);
+        assert_eq!((buffered.digest, buffered.bytes), (expected, len as u64));
+        assert_eq!(hash(ReadStrategy::Mmap).digest, expected);
+    }
+    assert_eq!(ContentHashing::default().mmap_threshold(Some(1000)).strategy(999), ReadStrategy::Buffered);
+    assert_eq!(ContentHashing::default().mmap_threshold(Some(1000)).strategy(1000), ReadStrategy::Mmap);
+    assert_eq!(ContentHashing::default().mmap_threshold(None).strategy(u64::MAX), ReadStrategy::Buffered);
+    assert_eq!(ContentHashing::default().strategy(u64::MAX), ReadStrategy::Buffered, "mmap is opt-in");
+}
+
+/// No faster than the burst plus the configured rate.
+fn assert_throttled(bytes: u64, elapsed: Duration, bucket: &TokenBucket, rate: u64) {
+    let least = bytes.saturating_sub(bucket.burst()) as f64 / rate as f64;
+    let got = elapsed.as_secs_f64();
+    assert!(got >= least, "{bytes} bytes took {got:.3}s, at least {least:.3}s expected");
+}
+
+#[test]
+fn token_bucket_waits_follow_its_clock() {
+    let clock = ManualClock::new();
+    let chunk = 64 * 1024;
+    let bucket = TokenBucket::with_clock(10 * chunk as u64, clock.shared());
+    assert_eq!(bucket.burst(), chunk as u64);
+
+    // no credit at the start, and readers at the same instant queue behind each other
+    assert_eq!(bucket.reserve(chunk), Duration::from_millis(100));
+    assert_eq!(bucket.reserve(chunk), Duration::from_millis(200));
+    clock.advance(Duration::from_millis(200));
+    assert_eq!(bucket.reserve(chunk), Duration::from_millis(100));
+
+    // idling earns the burst, no more
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(bucket.reserve(chunk), Duration::ZERO);
+    assert_eq!(bucket.reserve(chunk), Duration::from_millis(100));
+}
+
+#[test]
+fn throttle_caps_read_rate() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path().canonicalize().unwrap();
+    let path = dir.join("big.bin");
+    pattern_file(&path, 1024 * 1024);
+
+    let rate = 4 * 1024 * 1024;
+    for strategy in [ReadStrategy::Buffered, ReadStrategy::Mmap] {
+        let bucket = TokenBucket::new(rate);
+        let t = Instant::now();
+        let hashed = hash_file(&path, strategy, Some(&bucket), &CancellationToken::new(), HashJob::full(HashAlgorithm::Blake3)).unwrap();
+        assert_eq!(hashed.bytes, 1024 * 1024);
+        assert_throttled(hashed.bytes, t.elapsed(), &bucket, rate);
+    }
+}
+
+#[test]
+fn parallel_readers_share_the_budget() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path().canonicalize().unwrap();
+    let mut files = HashMap::new();
+    let mut sizes = HashMap::new();
+    for i in 0..4 {
+        let path = dir.join(format!("{i}.bin"));
+        pattern_file(&path, 256 * 1024);
+        files.insert(path.clone(), FileRecord { hash: blake3::hash(b"metadata").into(), generation: Default::default(), stamp: 1 });
+        sizes.insert(path, 256 * 1024);
+    }
+
+    let rate = 4 * 1024 * 1024;
+    let mut scanner = ContentScanner::new(ContentHashing::default().max_bytes_per_sec(rate).max_concurrent_reads(4));
+    assert!(scanner.rehash(&mut files, &sizes, &CancellationToken::new(), &mut Vec::new()));
+
+    let stats = scanner.stats.last_scan();
+    assert_eq!((stats.files_hashed, stats.bytes_hashed), (4, 1024 * 1024));
+    assert_throttled(stats.bytes_hashed, stats.elapsed, &TokenBucket::new(rate), rate);
+    assert!(files.values().all(|r| r.hash == blake3::hash(&std::fs::read(dir.join("0.bin")).unwrap()).into()));
+
+    // unchanged metadata: nothing is read again
+    for r in files.values_mut() {
+        r.hash = blake3::hash(b"metadata").into();
+    }
+    assert!(scanner.rehash(&mut files, &sizes, &CancellationToken::new(), &mut Vec::new()));
+    assert_eq!(scanner.stats.last_scan().files_hashed, 0);
+}
+
+fn edit(path: &Path, at: u64, grow: usize) {
+    use std::{
+        io::{Seek, SeekFrom, Write},
+        os::unix::fs::FileExt,
+    };
+    let mut f = std::fs::OpenOptions::new().write(true).open(path).unwrap();
+    if at > 0 {
+        f.write_all_at(b"!", at).unwrap();
+    }
+    f.seek(SeekFrom::End(0)).unwrap();
+    f.write_all(&vec![b'+'; grow]).unwrap();
+}
+
+#[test]
+fn appended_files_hash_their_new_bytes_until_verified() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path().canonicalize().unwrap();
+    let path = dir.join("app.log");
+    pattern_file(&path, 64 * 1024);
+    let appends = AppendAware::default().min_len(1024).head_check(100).verify_every(2);
+    let mut scanner = ContentScanner::new(ContentHashing::default().append_aware(appends));
+    let mut files = HashMap::new();
+    let mut sizes = HashMap::new();
+    let mut step = 0u64;
+    let mut scan = |files: &mut HashMap<PathBuf, FileRecord>, scanner: &mut ContentScanner| {
+        step += 1;
+        files.insert(path.clone(), FileRecord { hash: blake3::hash(&step.to_le_bytes()).into(), generation: Default::default(), stamp: step });
+        sizes.insert(path.clone(), std::fs::metadata(&path).unwrap().len());
+        assert!(scanner.rehash(files, &sizes, &CancellationToken::new(), &mut Vec::new()));
+        let stats = scanner.stats.last_scan();
+        let full = files[&path].hash == blake3::hash(&std::fs::read(&path).unwrap()).into();
+        (stats.files_appended, stats.bytes_hashed, scanner.rewritten(&path), full)
+    };
+
+    assert_eq!(scan(&mut files, &mut scanner), (0, 64 * 1024, false, true));
+    edit(&path, 0, 1000);
+    assert_eq!(scan(&mut files, &mut scanner), (1, 100 + 1000, false, true), "head and new bytes only");
+    edit(&path, 0, 1000);
+    assert_eq!(scan(&mut files, &mut scanner), (0, 100 + 64 * 1024 + 2000, false, true), "verified");
+
+    edit(&path, 10, 1000);
+    assert!(scan(&mut files, &mut scanner).2, "head check");
+
+    // past the head, an edit is missed until the next verification
+    edit(&path, 30_000, 1000);
+    assert_eq!(scan(&mut files, &mut scanner), (1, 100 + 1000, false, false));
+    edit(&path, 0, 1000);
+    let (appended, _, rewritten, full) = scan(&mut files, &mut scanner);
+    assert_eq!((appended, rewritten, full), (0, true, true));
+}
+
+fn entry(hash: Digest, algorithm: HashAlgorithm, ino: u64) -> ManifestEntry {
+    let path = PathBuf::from("/etc/passwd");
+    ManifestEntry { path: path.clone(), root: "/etc".into(), rel_path: "passwd".into(), hash: hash.to_hex(), algorithm, dev: 1, ino, stamp: 7 }
+}
+
+#[test]
+fn manifests_name_their_algorithm() {
+    let old = r#"{"path":"/etc/passwd","root":"/etc","rel_path":"passwd","hash":"HASH","dev":1,"ino":2,"stamp":7}"#;
+    let digest = Digest::from(blake3::hash(b"root:x:0:0"));
+    let old: ManifestEntry = serde_json::from_str(&old.replace("HASH", &digest.to_hex().to_uppercase())).unwrap();
+    assert_eq!((old.algorithm, old.record().map(|r| r.hash)), (HashAlgorithm::Blake3, Some(digest)));
+    let old = Manifest { by_content: true, files: vec![old] };
+    let new = Manifest { by_content: true, files: vec![entry(digest, HashAlgorithm::Blake3, 2)] };
+    assert!(FileScream::diff_manifests(&old, &new, Replaced::default()).is_empty());
+    assert_eq!(serde_json::to_value(&new.files[0]).unwrap()["algorithm"], "blake3");
+}
+
+#[cfg(feature = "sha256")]
+#[test]
+fn switched_algorithms_are_unverified_not_trusted() {
+    let sha = HashAlgorithm::Sha256.hash_reader(&b"abc"[..]).unwrap();
+    assert_eq!(sha.to_hex(), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+
+    let old = Manifest { by_content: true, files: vec![entry(blake3::hash(b"abc").into(), HashAlgorithm::Blake3, 2)] };
+    let changes = |ino| {
+        let new = Manifest { by_content: true, files: vec![entry(sha, HashAlgorithm::Sha256, ino)] };
+        FileScream::diff_manifests(&old, &new, Replaced::default())
+            .into_iter()
+            .map(|ev| match ev {
+                FileScreamEvent::Changed { change, .. } => change,
+                other => panic!("{other:?}"),
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(changes(2), [FileChange::Unverified]);
+    assert_eq!(changes(3), [FileChange::Replaced]);
+
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path().canonicalize().unwrap();
+    pattern_file(&dir.join("a.bin"), 300 * 1024);
+    let data = std::fs::read(dir.join("a.bin")).unwrap();
+    for strategy in [ReadStrategy::Buffered, ReadStrategy::Mmap] {
+        let hashed = hash_file(&dir.join("a.bin"), strategy, None, &CancellationToken::new(), HashJob::full(HashAlgorithm::Sha256)).unwrap();
+        assert_eq!(hashed.digest, HashAlgorithm::Sha256.hash_reader(&data[..]).unwrap());
+    }
+}
+
+#[tokio::test]
+async fn content_mode_ignores_identical_rewrites() {
+    let tmp = TempDir::new().unwrap();
+    let root = tmp.path().canonicalize().unwrap();
+    std::fs::write(root.join("a.conf"), "one").unwrap();
+
+    let mut fs = FileScream::new(Some(FileScreamConfig::default().pulse(Duration::from_millis(10)).content_hashing(ContentHashing::default())));
+    fs.watch(&root).unwrap();
+    let io = fs.io_stats();
+
+    let (tx, mut rx) = channel::<CallbackResult>(16);
+    let mut hub = CallbackHub::<FileScreamEvent>::new();
+    hub.add(JsonCb);
+    hub.set_result_channel(tx);
+    let (handle, task) = spawn_sensor(fs, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // same content, new mtime
+    std::fs::File::options()
+        .write(true)
+        .open(root.join("a.conf"))
+        .unwrap()
+        .set_modified(std::time::SystemTime::now() + Duration::from_secs(60))
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(rx.try_recv().is_err());
+    assert_eq!(io.last_scan().files_hashed, 0, "re-read once after the touch, then cached");
+
+    std::fs::write(root.join("a.conf"), "two").unwrap();
+    let ev = tokio::time::timeout(Duration::from_millis(500), rx.recv()).await.unwrap().unwrap();
+
+    handle.shutdown();
+    let _ = task.await;
+
+    assert_eq!(ev["Changed"]["rel_path"], "a.conf");
+}
+
+struct AllCb;
+
+#[async_trait]
+impl Callback<FileScreamEvent> for AllCb {
+    fn mask(&self) -> u64 {
+        u64::MAX
+    }
+
+    async fn call(&self, ev: &FileScreamEvent) -> Option<CallbackResult> {
+        serde_json::to_value(ev).ok()
+    }
+}
+
+/// `n` small files spread over a few hundred directories.
+fn big_tree(root: &Path, n: usize) {
+    for i in 0..n {
+        let dir = root.join(format!("d{}", i % 300));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(format!("f{i}")), i.to_le_bytes()).unwrap();
+    }
+}
+
+fn unguarded() -> FuseGuard {
+    FuseGuard::new(Arc::new(RealFs), None)
+}
+
+#[test]
+fn cancelled_scan_returns_nothing() {
+    let tmp = TempDir::new().unwrap();
+    let root = tmp.path().canonicalize().unwrap();
+    big_tree(&root, 3000);
+    let ignore = FileScream::default().im.clone();
+
+    let mut ctx = ScanCtx::new(vec![root.clone()], ignore.clone(), unguarded(), CancellationToken::new());
+    let full = FileScream::scan(&mut ctx).unwrap();
+    assert_eq!(full.len(), 3000);
+
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    let mut ctx = ScanCtx::new(vec![root.clone()], ignore, unguarded(), cancel);
+    assert!(FileScream::scan(&mut ctx).is_none());
+    let dirs = ctx.dir_state.len();
+    assert!(dirs < 300, "stopped within the first check interval, walked {dirs} dirs");
+}
+
+/// Events of a sensor watching `root/w` while `act` rewrites it, as `(kind, rel_path)`.
+async fn events_around<F: FnOnce(&Path)>(root: &Path, cfg: FileScreamConfig, act: F) -> Vec<(&'static str, String)> {
+    let mut fs = FileScream::new(Some(cfg.pulse(Duration::from_millis(10))));
+    fs.watch(root.join("w")).unwrap();
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(seen.clone()));
+    let (handle, task) = spawn_sensor(fs, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    act(root);
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    handle.shutdown();
+    let _ = task.await;
+
+    let events = seen.lock().unwrap().clone();
+    events
+        .into_iter()
+        .map(|ev| match ev {
+            FileScreamEvent::Created { rel_path, .. } => ("created", rel_path.display().to_string()),
+            FileScreamEvent::Changed { rel_path, .. } => ("changed", rel_path.display().to_string()),
+            FileScreamEvent::Removed { rel_path, .. } => ("removed", rel_path.display().to_string()),
+            other => panic!("unexpected {other:?}"),
+        })
+        .collect()
+}
+
+/// Delete and recreate `a.conf` with the same size and mtime, and move a same sized `b.conf`
+/// over the old one, as config management writes files.
+fn recreate_and_rename_over(root: &Path) {
+    let (a, b) = (root.join("w/a.conf"), root.join("w/b.conf"));
+    let mtime = std::fs::metadata(&a).unwrap().modified().unwrap();
+    // held open, so the new file cannot get the old inode number
+    let _old = std::fs::File::open(&a).unwrap();
+    std::fs::remove_file(&a).unwrap();
+    std::fs::write(&a, "same").unwrap();
+    std::fs::File::options().write(true).open(&a).unwrap().set_modified(mtime).unwrap();
+
+    let tmp = root.join("b.conf.tmp");
+    std::fs::write(&tmp, "new!").unwrap();
+    std::fs::File::options().write(true).open(&tmp).unwrap().set_modified(std::fs::metadata(&b).unwrap().modified().unwrap()).unwrap();
+    std::fs::rename(&tmp, &b).unwrap();
+}
+
+#[tokio::test]
+async fn replaced_files_are_reported_despite_same_size_and_mtime() {
+    for (replaced, want) in [
+        (Replaced::Changed, vec![("changed", "a.conf"), ("changed", "b.conf")]),
+        (Replaced::RemovedCreated, vec![("created", "a.conf"), ("created", "b.conf"), ("removed", "a.conf"), ("removed", "b.conf")]),
+    ] {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("w")).unwrap();
+        std::fs::write(root.join("w/a.conf"), "same").unwrap();
+        std::fs::write(root.join("w/b.conf"), "old!").unwrap();
+
+        let mut got = events_around(&root, FileScreamConfig::default().replaced_as(replaced), recreate_and_rename_over).await;
+        got.sort();
+        let want: Vec<(&str, String)> = want.into_iter().map(|(k, p)| (k, p.to_string())).collect();
+        assert_eq!(got, want, "{replaced:?}");
+    }
+
+    // by content, only the file whose content differs
+    let tmp = TempDir::new().unwrap();
+    let root = tmp.path().canonicalize().unwrap();
+    std::fs::create_dir(root.join("w")).unwrap();
+    std::fs::write(root.join("w/a.conf"), "same").unwrap();
+    std::fs::write(root.join("w/b.conf"), "old!").unwrap();
+    let got = events_around(&root, FileScreamConfig::default().content_hashing(ContentHashing::default()), recreate_and_rename_over).await;
+    assert_eq!(got, [("changed", "b.conf".to_string())]);
+}
+
+#[test]
+fn files_missed_by_the_walk_are_settled() {
+    let tmp = TempDir::new().unwrap();
+    let root = tmp.path().canonicalize().unwrap();
+    for name in ["a.conf", "b.conf", "gone.conf", "skip.tmp"] {
+        std::fs::write(root.join(name), name).unwrap();
+    }
+    let mut fs = FileScream::default();
+    fs.ignore("*.tmp");
+    let roots = [root.clone()];
+    let previous: HashMap<PathBuf, FileRecord> = ["a.conf", "b.conf", "gone.conf", "skip.tmp"]
+        .iter()
+        .map(|n| (root.join(n), FileRecord::of(&std::fs::metadata(root.join(n)).unwrap())))
+        .collect();
+    std::fs::remove_file(root.join("gone.conf")).unwrap();
+
+    // the walk saw only b.conf, a.conf was mid-swap
+    let found: HashMap<PathBuf, FileRecord> = previous.iter().filter(|(p, _)| p.ends_with("b.conf")).map(|(p, r)| (p.clone(), *r)).collect();
+    let settled: Vec<PathBuf> = FileScream::settle(&roots, &fs.im, &previous, &found, &mut unguarded()).into_iter().map(|(p, _)| p).collect();
+    assert_eq!(settled, [root.join("a.conf")]);
+
+    // the same file rewritten is Changed, another one at the path is Replaced unless its content is the same
+    let a = previous[&root.join("a.conf")];
+    let other = FileRecord { generation: previous[&root.join("b.conf")].generation, ..a };
+    let rewritten = FileRecord { hash: blake3::hash(b"x").into(), stamp: a.stamp + 1, ..a };
+    let chmodded = FileRecord { hash: blake3::hash(b"x").into(), ..a };
+    assert_eq!(FileScream::compare(&a, &a, false), None);
+    assert_eq!(FileScream::compare(&a, &rewritten, false), Some(FileChange::Content));
+    assert_eq!(FileScream::compare(&a, &chmodded, false), Some(FileChange::Metadata));
+    assert_eq!(FileScream::compare(&a, &chmodded, true), Some(FileChange::Content), "content hashes only move with the content");
+    assert_eq!(FileScream::compare(&a, &FileRecord { stamp: 0, ..chmodded }, false), Some(FileChange::Content), "unknown stamp");
+    assert_eq!(FileScream::compare(&a, &other, false), Some(FileChange::Replaced));
+    assert_eq!(FileScream::compare(&a, &other, true), None);
+}
+
+#[tokio::test]
+async fn bad_patterns_and_unreadable_entries_are_diagnosed() {
+    let tmp = TempDir::new().unwrap();
+    let root = tmp.path().canonicalize().unwrap();
+    std::fs::write(root.join("plain"), "x").unwrap();
+    let mut fs = FileScream::default();
+    fs.watch(&root).unwrap();
+    fs.ignore("[z-a]");
+    fs.ignore("*.tmp");
+    let diagnostics = fs.diagnostics();
+    let bad = diagnostics.get("pattern:[z-a]").unwrap();
+    assert!(matches!(bad.last.downcast_ref::<FileScreamError>(), Some(FileScreamError::Pattern { pattern, .. }) if pattern == "[z-a]"));
+    fs.unignore("[z-a]");
+    assert!(diagnostics.all().is_empty());
+
+    // a root below a file is ENOTDIR, not a vanished entry
+    fs.watch(root.join("plain/below")).unwrap();
+    fs.scan_blocking(&CancellationToken::new()).await.unwrap();
+    let walk = diagnostics.get("walk").unwrap();
+    match walk.last.downcast_ref::<FileScreamError>() {
+        Some(FileScreamError::Walk { path, source }) => {
+            assert_eq!((path.as_path(), source.kind()), (root.join("plain/below").as_path(), io::ErrorKind::NotADirectory))
+        }
+        other => panic!("{other:?}"),
+    }
+
+    fs.unwatch(root.join("plain/below"));
+    fs.scan_blocking(&CancellationToken::new()).await.unwrap();
+    assert!(diagnostics.get("walk").is_none());
+}
+
+#[tokio::test]
+async fn shutdown_does_not_wait_for_the_scan() {
+    let tmp = TempDir::new().unwrap();
+    let root = tmp.path().canonicalize().unwrap();
+    big_tree(&root, 3000);
+    for i in 0..8 {
+        pattern_file(&root.join(format!("big{i}.bin")), 256 * 1024);
+    }
+
+    // 2 MiB at 256 KiB/s: the priming scan alone takes ~8s
+    let opts = ContentHashing::default().max_bytes_per_sec(256 * 1024);
+    let mut fs = FileScream::new(Some(FileScreamConfig::default().pulse(Duration::from_millis(10)).content_hashing(opts)));
+    fs.watch(&root).unwrap();
+    let health = fs.health();
+
+    let (tx, mut rx) = channel::<CallbackResult>(16);
+    let mut hub = CallbackHub::<FileScreamEvent>::new();
+    hub.add(AllCb);
+    hub.set_result_channel(tx);
+    let (handle, task) = spawn_sensor(fs, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(health.last_scan().is_none(), "priming scan still running");
+
+    let t = Instant::now();
+    handle.shutdown();
+    task.await.unwrap();
+    let took = t.elapsed();
+
+    assert!(took < Duration::from_secs(1), "shutdown took {took:?}");
+    assert_eq!(health.last_scan().unwrap().outcome, ScanOutcome::Aborted);
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn debug_snapshot_reports_roots_and_scan() {
+    let tmp = TempDir::new().unwrap();
+    let a = tmp.path().canonicalize().unwrap();
+    let b = a.join("b");
+    std::fs::create_dir_all(&b).unwrap();
+    std::fs::write(a.join("one.txt"), "1").unwrap();
+    std::fs::write(b.join("two.txt"), "2").unwrap();
+    std::fs::write(b.join("three.txt"), "3").unwrap();
+
+    let mut fs = FileScream::new(Some(FileScreamConfig::default().pulse(Duration::from_millis(10))));
+    fs.watch(&a).unwrap();
+    fs.watch(&b).unwrap();
+    fs.ignore("*.swp");
+    let mut snapshots = Snapshots::new();
+    snapshots.register("filescream", fs.debug_handle());
+
+    let (handle, task) = spawn_sensor(fs, Arc::new(CallbackHub::<FileScreamEvent>::new()));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let dump = snapshots.dump();
+    handle.shutdown();
+    let _ = task.await;
+
+    let f = &dump["components"]["filescream"];
+    for key in [
+        "pulse_ms",
+        "mount_aware",
+        "content_hashing",
+        "activity_spikes",
+        "primed",
+        "roots",
+        "ignored",
+        "suspended",
+        "files_per_root",
+        "dirs_tracked",
+        "last_scan",
+        "io",
+        "stats",
+    ] {
+        assert!(f.get(key).is_some(), "missing {key} in {f}");
+    }
+    assert_eq!(f["primed"], true);
+    assert_eq!(f["ignored"], serde_json::json!(["*.swp"]));
+    assert_eq!(f["files_per_root"][a.display().to_string()], 1);
+    assert_eq!(f["files_per_root"][b.display().to_string()], 2);
+    assert_eq!(f["last_scan"]["outcome"], "Completed");
+    assert_eq!(f["stats"]["patterns"]["*.swp"], 0);
+}
+
+struct Recorder(Arc<std::sync::Mutex<Vec<FileScreamEvent>>>);
+
+#[async_trait]
+impl Callback<FileScreamEvent> for Recorder {
+    fn mask(&self) -> u64 {
+        FileScreamMask::all().bits()
+    }
+
+    async fn call(&self, ev: &FileScreamEvent) -> Option<CallbackResult> {
+        self.0.lock().unwrap().push(ev.clone());
+        None
+    }
+}
+
+#[tokio::test]
+async fn random_file_scripts_keep_per_path_order() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path().canonicalize().unwrap();
+    let names: Vec<PathBuf> = (0..6).map(|i| dir.join(format!("f{i}"))).collect();
+    let mut rng = fastrand::Rng::with_seed(715);
+
+    let mut fs = FileScream::new(Some(FileScreamConfig::default().pulse(Duration::from_millis(3))));
+    fs.watch(&dir).unwrap();
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(seen.clone()));
+    let (handle, task) = spawn_sensor(fs, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(30)).await;
+
+    for _ in 0..60 {
+        let f = &names[rng.usize(..names.len())];
+        if rng.u8(..3) == 0 {
+            let _ = std::fs::remove_file(f);
+        } else {
+            // a new length each time, so the change is visible whatever the mtime granularity
+            std::fs::write(f, "x".repeat(rng.usize(1..200))).unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(rng.u64(0..5))).await;
+    }
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    handle.shutdown();
+    let _ = task.await;
+
+    // every path alternates Created (Changed*) Removed, and the events add up to what is on disk
+    let mut model = HashSet::new();
+    for ev in seen.lock().unwrap().iter() {
+        match ev {
+            FileScreamEvent::Created { path, .. } => assert!(model.insert(path.clone()), "Created {} twice", path.display()),
+            FileScreamEvent::Changed { path, .. } => assert!(model.contains(path), "Changed {} while absent", path.display()),
+            FileScreamEvent::Removed { path, .. } => assert!(model.remove(path), "Removed {} while absent", path.display()),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+    let on_disk: HashSet<PathBuf> = names.iter().filter(|f| f.exists()).cloned().collect();
+    assert_eq!(model, on_disk);
+}
+
+/// Run a content-hashing sensor on `root` until primed plus a few ticks, return its memory report and events.
+async fn primed_memory(root: &Path, budget: Option<u64>) -> (omnitrace_core::memory::MemoryReport, Vec<FileScreamEvent>) {
+    let mut cfg = FileScreamConfig::default().pulse(Duration::from_millis(10)).content_hashing(ContentHashing::default());
+    if let Some(b) = budget {
+        cfg = cfg.memory_budget(b);
+    }
+    let mut fs = FileScream::new(Some(cfg));
+    fs.watch(root).unwrap();
+    let memory = fs.memory();
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(seen.clone()));
+    let (handle, task) = spawn_sensor(fs, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(80)).await;
+    handle.shutdown();
+    let _ = task.await;
+    let events = seen.lock().unwrap().clone();
+    (memory.report(), events)
+}
+
+#[tokio::test]
+async fn over_budget_sheds_largest_subtree_to_metadata_hashes() {
+    let tmp = TempDir::new().unwrap();
+    let root = tmp.path().canonicalize().unwrap();
+    for (dir, n) in [("big", 40), ("small", 3)] {
+        std::fs::create_dir_all(root.join(dir)).unwrap();
+        for i in 0..n {
+            std::fs::write(root.join(dir).join(format!("file-{i}.txt")), format!("{dir} {i}")).unwrap();
+        }
+    }
+
+    let (full, events) = primed_memory(&root, None).await;
+    assert!(events.is_empty());
+    assert_eq!(full.budget, None);
+    assert_eq!(full.sheds, 0);
+    let cache = full.parts["content_cache"];
+    assert!(full.parts["files"] > 0 && full.parts["dirs"] > 0 && cache > 0);
+    assert_eq!(full.estimated_bytes, full.parts.values().sum::<u64>());
+
+    // room for the small subtree's cache only
+    let budget = full.estimated_bytes - cache / 2;
+    let (shed, events) = primed_memory(&root, Some(budget)).await;
+
+    assert_eq!(events.len(), 1, "one OverBudget, no Changed from the hash switch: {events:?}");
+    let FileScreamEvent::OverBudget { estimated_bytes, after_shedding, budget: b, metadata_only } = &events[0] else {
+        panic!("unexpected {:?}", events[0]);
+    };
+    assert_eq!((*estimated_bytes, *b), (full.estimated_bytes, budget));
+    assert!(*after_shedding <= budget);
+    assert_eq!(metadata_only, &vec![root.join("big").display().to_string()]);
+
+    assert!(shed.parts["content_cache"] > 0 && shed.parts["content_cache"] < cache / 4);
+    assert_eq!(shed.parts["files"], full.parts["files"], "the file table is never shed");
+    assert!(!shed.over_budget);
+    assert!(shed.sheds >= 1);
+}
+
+fn touch(path: &Path, secs: u64) {
+    let f = std::fs::File::options().write(true).open(path).unwrap();
+    f.set_modified(std::time::SystemTime::now() + Duration::from_secs(secs)).unwrap();
+}
+
+#[tokio::test]
+async fn degraded_profile_pauses_content_hashing_without_spurious_changes() {
+    let tmp = TempDir::new().unwrap();
+    let root = tmp.path().canonicalize().unwrap();
+    for name in ["a.conf", "b.conf", "c.conf"] {
+        std::fs::write(root.join(name), name).unwrap();
+    }
+
+    let switch = ProfileSwitch::default();
+    let cfg = FileScreamConfig::default().pulse(Duration::from_millis(10)).content_hashing(ContentHashing::default()).profile_switch(switch.clone());
+    let mut fs = FileScream::new(Some(cfg));
+    fs.watch(&root).unwrap();
+    let debug = fs.debug_handle();
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(seen.clone()));
+    let (handle, task) = spawn_sensor(fs, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // degraded: metadata hashes, so an identical rewrite is a change
+    switch.set(Profile::Degraded);
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    let d = omnitrace_core::debug::Debuggable::debug_snapshot(&debug);
+    assert_eq!((d["profile"].as_str(), d["content_hashing"].as_bool(), d["pulse_ms"].as_u64()), (Some("degraded"), Some(false), Some(20)));
+    touch(&root.join("a.conf"), 60);
+    tokio::time::sleep(Duration::from_millis(60)).await;
+
+    // back to full: the first content scan reports nothing, then identical rewrites are ignored again
+    switch.set(Profile::Full);
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    touch(&root.join("b.conf"), 60);
+    std::fs::write(root.join("c.conf"), "changed").unwrap();
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    handle.shutdown();
+    let _ = task.await;
+
+    let changed: Vec<String> = seen
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|ev| match ev {
+            FileScreamEvent::Changed { rel_path, .. } => rel_path.display().to_string(),
+            other => panic!("unexpected {other:?}"),
+        })
+        .collect();
+    assert_eq!(changed, ["a.conf", "c.conf"]);
+}
+
+#[test]
+fn mode_rules_match_permission_bits() {
+    assert_eq!(ModeRule::matching(0o644), ModeRule::empty());
+    assert_eq!(ModeRule::matching(0o4755), ModeRule::SETUID);
+    assert_eq!(ModeRule::matching(0o2755), ModeRule::SETGID_EXECUTABLE);
+    assert_eq!(ModeRule::matching(0o2644), ModeRule::empty(), "setgid without execute is mandatory locking");
+    assert_eq!(ModeRule::matching(0o6777), ModeRule::all());
+    assert_eq!((ModeRule::SETUID | ModeRule::WORLD_WRITABLE).names(), ["setuid", "world_writable"]);
+}
+
+#[cfg(unix)]
+fn chmod(path: &Path, mode: u32) {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn chmod_to_suspicious_mode_fires_once() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path().canonicalize().unwrap();
+    let (tool, notes, old) = (dir.join("tool"), dir.join("notes.txt"), dir.join("old-suid"));
+    for f in [&tool, &notes, &old] {
+        std::fs::write(f, "x").unwrap();
+        chmod(f, 0o644);
+    }
+    // already there at startup: the baseline, not reported
+    chmod(&old, 0o4755);
+
+    let mut fs = FileScream::new(Some(FileScreamConfig::default().pulse(Duration::from_millis(10))));
+    fs.watch(&dir).unwrap();
+    fs.alert_on(ModeRule::SETUID | ModeRule::SETGID_EXECUTABLE | ModeRule::WORLD_WRITABLE, "*");
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(seen.clone()));
+    let (handle, task) = spawn_sensor(fs, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(40)).await;
+
+    chmod(&tool, 0o4755);
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    chmod(&notes, 0o666);
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    handle.shutdown();
+    let _ = task.await;
+
+    let alerts: Vec<_> = seen
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|ev| match ev {
+            FileScreamEvent::SuspiciousMode { path, rules, old, new, .. } => Some((path.clone(), rules.clone(), old.map(|o| o.mode), new.mode)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(alerts, [(tool, vec!["setuid".to_string()], Some(0o644), 0o4755), (notes, vec!["world_writable".to_string()], Some(0o644), 0o666)]);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn default_rules_ignore_world_writable_outside_system_dirs() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path().canonicalize().unwrap();
+    let mut fs = FileScream::new(Some(FileScreamConfig::default().pulse(Duration::from_millis(10))));
+    fs.watch(&dir).unwrap();
+    fs.alert_on_default_security_rules();
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(seen.clone()));
+    let (handle, task) = spawn_sensor(fs, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(40)).await;
+
+    let (shared, suid) = (dir.join("shared"), dir.join("suid"));
+    std::fs::write(&shared, "x").unwrap();
+    chmod(&shared, 0o666);
+    std::fs::write(&suid, "x").unwrap();
+    chmod(&suid, 0o2755);
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    handle.shutdown();
+    let _ = task.await;
+
+    // created that way: no old mode, and the Created event comes first
+    let events = seen.lock().unwrap().clone();
+    let alerts: Vec<_> = events.iter().filter(|ev| matches!(ev, FileScreamEvent::SuspiciousMode { .. })).collect();
+    assert_eq!(alerts.len(), 1, "{events:?}");
+    let FileScreamEvent::SuspiciousMode { path, rules, old, new, .. } = alerts[0] else { unreachable!() };
+    assert_eq!((path, rules.as_slice(), *old, new.mode), (&suid, ["setgid_executable".to_string()].as_slice(), None, 0o2755));
+    let created = events.iter().position(|ev| matches!(ev, FileScreamEvent::Created { path, .. } if *path == suid)).unwrap();
+    assert!(created < events.iter().position(|ev| matches!(ev, FileScreamEvent::SuspiciousMode { .. })).unwrap());
+}
+
+#[test]
+fn every_documented_field_resolves() {
+    let (root, path) = (PathBuf::from("/srv"), PathBuf::from("/srv/bin/tool"));
+    let mode = |mode| FileMode { mode, uid: 0, gid: 0 };
+    let deviation = |deviation| FileScreamEvent::Deviation {
+        path: path.clone(),
+        root: root.clone(),
+        rel_path: PathBuf::from("bin/tool"),
+        deviation,
+        labels: Labels::default(),
+    };
+    let changed = |change| FileScreamEvent::Changed {
+        path: path.clone(),
+        root: root.clone(),
+        rel_path: PathBuf::from("bin/tool"),
+        change,
+        labels: Labels::default(),
+    };
+    let samples = [
+        FileScreamEvent::test_created("/srv", "bin/tool"),
+        FileScreamEvent::test_changed("/srv", "bin/tool"),
+        changed(FileChange::Metadata),
+        changed(FileChange::Replaced),
+        changed(FileChange::Rewritten),
+        changed(FileChange::Unverified),
+        FileScreamEvent::test_removed("/srv", "bin/tool"),
+        FileScreamEvent::RootUnavailable { root: root.clone(), labels: Labels::default() },
+        FileScreamEvent::RootRestored { root: root.clone(), labels: Labels::default() },
+        FileScreamEvent::ActivitySpike {
+            root: root.clone(),
+            subtree: root.join("bin"),
+            window: Duration::from_secs(10),
+            created: 400,
+            changed: 2,
+            removed: 1,
+            baseline: 3.5,
+        },
+        FileScreamEvent::OverBudget { estimated_bytes: 9, after_shedding: 5, budget: 4, metadata_only: Vec::new() },
+        FileScreamEvent::SuspiciousMode {
+            path: path.clone(),
+            root: root.clone(),
+            rel_path: PathBuf::from("bin/tool"),
+            rules: vec!["setuid".to_string()],
+            old: Some(mode(0o755)),
+            new: mode(0o4755),
+            labels: Labels::default(),
+        },
+        deviation(Deviation::Missing),
+        deviation(Deviation::Unexpected),
+        deviation(Deviation::mismatch("hash", "00ff", "ff00")),
+        FileScreamEvent::SubtreeStalled {
+            root: root.clone(),
+            subtree: root.join("mnt"),
+            op: "read_dir".to_string(),
+            after: Duration::from_secs(5),
+            labels: Labels::default(),
+        },
+        FileScreamEvent::SubtreeResumed {
+            root: root.clone(),
+            subtree: root.join("mnt"),
+            stalled_for: Duration::from_secs(30),
+            labels: Labels::default(),
+        },
+    ];
+
+    for ev in &samples {
+        let json = serde_json::to_value(ev).unwrap();
+        assert!(fields::same_kind(ev.kind(), json.as_object().unwrap().keys().next().unwrap()));
+        // only a mismatch has field, expected and actual
+        let unset: &[&str] = match ev {
+            FileScreamEvent::Deviation { deviation: Deviation::Missing | Deviation::Unexpected, .. } => &["field", "expected", "actual"],
+            _ => &[],
+        };
+        for name in ev.field_names() {
+            assert_eq!(ev.field(name).is_some(), !unset.contains(name), "{}: {name}", ev.kind());
+        }
+        assert_eq!(FileScreamEvent::TOPICS[ev.topic_index()].mask, ev.mask().bits(), "{}", ev.topic());
+        assert_eq!((ev.mask().bits() & KIND_BITS).count_ones(), 1, "{}", ev.topic());
+        assert_eq!(ev.mask().bits() & RESERVED_BITS, 0);
+        assert_eq!(EventFields::topic(ev), Some(ev.topic()));
+    }
+    let topics: Vec<&str> = samples.iter().map(FileScreamEvent::topic).collect();
+    assert_eq!(topics, FileScreamEvent::TOPICS.iter().map(|t| t.name).collect::<Vec<_>>());
+
+    assert_eq!(samples[0].field("path"), Some(FieldValue::Path(&path)));
+    assert_eq!(samples[0].field("rel_path").unwrap().as_str().as_deref(), Some("bin/tool"));
+    assert_eq!(samples[2].field("change"), Some(FieldValue::str("metadata")));
+    assert_eq!(samples[9].field("window_ms"), Some(FieldValue::int(10_000)));
+    let suspicious = &samples[11];
+    assert_eq!(suspicious.field("mode"), Some(FieldValue::int(0o4755)));
+    assert_eq!(suspicious.field("new.mode"), suspicious.field("mode"));
+    assert_eq!(suspicious.field("old.mode"), Some(FieldValue::int(0o755)));
+    assert_eq!(samples[10].field("root"), None);
+    assert_eq!(samples[13].topic(), "file.deviation.unexpected");
+    assert_eq!(samples[14].field("expected"), Some(FieldValue::str("00ff")));
+}
+
+#[tokio::test]
+async fn preflight_checks_roots_and_patterns() {
+    async fn found(fs: &FileScream) -> Vec<(Severity, String)> {
+        fs.preflight().await.into_iter().map(|f| (f.severity, f.message)).collect()
+    }
+
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path().canonicalize().unwrap();
+    std::fs::write(dir.join("file"), "x").unwrap();
+    let (file, gone) = (dir.join("file"), dir.join("gone"));
+
+    let mut fs = FileScream::new(None);
+    assert_eq!(found(&fs).await, [(Severity::Warning, "no directories watched".to_string())]);
+    fs.watch(&dir).unwrap();
+    assert!(found(&fs).await.is_empty());
+
+    fs.watch(&file).unwrap();
+    fs.watch(&gone).unwrap();
+    fs.ignore("*.swp");
+    fs.ignore("[broken");
+    fs.alert_on(ModeRule::SETUID, "/usr/{bin");
+    let got = found(&fs).await;
+    assert_eq!(got.len(), 4, "{got:?}");
+    assert_eq!(got[0], (Severity::Critical, format!("watched {} is not a directory", file.display())));
+    assert_eq!(got[1], (Severity::Critical, format!("watched {} does not exist", gone.display())));
+    assert_eq!(got[2].0, Severity::Warning);
+    assert!(got[2].1.starts_with(r#"invalid ignore pattern "[broken""#), "{}", got[2].1);
+    assert!(got[3].1.starts_with(r#"invalid alert_on glob "/usr/{bin""#), "{}", got[3].1);
+
+    let mut aware = FileScream::new(Some(FileScreamConfig::default().mount_aware(true)));
+    aware.watch(&gone).unwrap();
+    assert_eq!(found(&aware).await, [(Severity::Warning, format!("watched {} does not exist yet", gone.display()))]);
+}
+
+#[tokio::test]
+async fn previews_ignore_patterns_against_the_last_scan_without_applying_them() {
+    let tmp = TempDir::new().unwrap();
+    let root = tmp.path().canonicalize().unwrap();
+    for dir in ["cache", "logs"] {
+        std::fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    for name in ["cache/a.bin", "cache/b.bin", "logs/x.log", "logs/y.tmp", "keep.txt"] {
+        std::fs::write(root.join(name), name).unwrap();
+    }
+    let mut fs = FileScream::new(Some(FileScreamConfig::default().pulse(Duration::from_millis(10))));
+    fs.watch(&root).unwrap();
+    fs.ignore("*.tmp");
+    let previews = fs.preview_handle();
+
+    let (tx, mut rx) = channel::<CallbackResult>(16);
+    let mut hub = CallbackHub::<FileScreamEvent>::new();
+    hub.add(JsonCb);
+    hub.set_result_channel(tx);
+    let (handle, task) = spawn_sensor(fs, Arc::new(hub));
+    let preview = async |rule: &str| previews.preview_rule(rule.parse::<FileScreamRule>().unwrap()).await.unwrap();
+
+    let cache = preview("ignore cache/").await;
+    assert_eq!((cache.would_match, cache.would_stop_matching), (2, 2));
+    assert!(cache.samples[0].ends_with("cache/a.bin") && cache.samples[1].ends_with("cache/b.bin"));
+    assert_eq!(preview("ignore *.log").await.would_match, 1);
+    // already left out
+    assert_eq!(preview("ignore *.tmp").await.would_match, 0);
+    // directory-only patterns do not match files
+    assert_eq!(preview("ignore keep.txt/").await.would_match, 0);
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    handle.shutdown();
+    let _ = task.await;
+    assert!(rx.try_recv().is_err(), "previews must not change what is reported");
+    assert!("ignore a[".parse::<FileScreamRule>().is_err());
+}
+
+#[test]
+fn events_format_as_pinned_lines() {
+    use std::os::unix::ffi::OsStrExt;
+
+    let root = PathBuf::from("/srv/www");
+    let at = |rel: &Path| (root.join(rel), root.clone(), rel.to_path_buf());
+    let (path, root_, rel_path) = at(Path::new(std::ffi::OsStr::from_bytes(b"caf\xe9.html")));
+    let changed = FileScreamEvent::Changed { path, root: root_, rel_path, change: FileChange::Rewritten, labels: Labels::default() };
+    let (path, root_, rel_path) = at(Path::new("new page.html"));
+    let created = FileScreamEvent::Created { path, root: root_, rel_path, labels: Labels::default() };
+    let (path, root_, rel_path) = at(Path::new("cgi-bin/run"));
+    let suspicious = FileScreamEvent::SuspiciousMode {
+        path,
+        root: root_,
+        rel_path,
+        rules: vec!["setuid".to_string()],
+        old: Some(FileMode { mode: 0o755, uid: 0, gid: 0 }),
+        new: FileMode { mode: 0o4755, uid: 0, gid: 0 },
+        labels: Labels::default(),
+    };
+    let lines: Vec<String> = [changed, created, suspicious].iter().flat_map(|ev| [format_short(ev), format_long(ev)]).collect();
+    assert_eq!(
+        lines,
+        [
+            // non-UTF-8 names lossily
+            "changed path=/srv/www/caf\u{fffd}.html change=rewritten",
+            "changed path=/srv/www/caf\u{fffd}.html root=/srv/www rel_path=caf\u{fffd}.html change=rewritten",
+            "created path=\"/srv/www/new page.html\"",
+            "created path=\"/srv/www/new page.html\" root=/srv/www rel_path=\"new page.html\"",
+            "suspicious_mode path=/srv/www/cgi-bin/run old.mode=493 new.mode=2541",
+            "suspicious_mode path=/srv/www/cgi-bin/run root=/srv/www rel_path=cgi-bin/run old.mode=493 new.mode=2541 uid=0 gid=0",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn history_keeps_the_last_events_per_file_and_root() {
+    let tmp = TempDir::new().unwrap();
+    let root = tmp.path().canonicalize().unwrap();
+    std::fs::write(root.join("a.txt"), "a").unwrap();
+    let cfg = FileScreamConfig::default().pulse(Duration::from_millis(10)).history(HistoryConfig::new(2, 100));
+    let mut fs = FileScream::new(Some(cfg));
+    fs.watch(&root).unwrap();
+    let history = fs.history().unwrap();
+    let (handle, task) = spawn_sensor(fs, Arc::new(CallbackHub::new()));
+    tokio::time::sleep(Duration::from_millis(40)).await;
+    for content in ["aa", "aaa", "aaaa"] {
+        std::fs::write(root.join("a.txt"), content).unwrap();
+        tokio::time::sleep(Duration::from_millis(40)).await;
+    }
+    std::fs::write(root.join("b.txt"), "b").unwrap();
+    tokio::time::sleep(Duration::from_millis(40)).await;
+    handle.shutdown();
+    let _ = task.await;
+
+    let a = history.history(&root.join("a.txt").display().to_string(), 10);
+    assert_eq!(a.len(), 2, "{a:?}");
+    assert!(a.iter().all(|e| matches!(e.event, FileScreamEvent::Changed { .. })));
+    let kinds: Vec<&str> = history.history(&root.display().to_string(), 10).iter().map(|e| e.event.kind()).collect();
+    assert_eq!(kinds, ["changed", "created"]);
+}
+
+/// The real filesystem, with every call under `fuse` hanging while the gate is closed.
+struct StallFs {
+    fuse: PathBuf,
+    open: Arc<(std::sync::Mutex<bool>, std::sync::Condvar)>,
+}
+
+impl StallFs {
+    fn wait(&self, path: &Path) {
+        if path.starts_with(&self.fuse) {
+            let (lock, cv) = &*self.open;
+            let _open = cv.wait_while(lock.lock().unwrap(), |open| !*open).unwrap();
+        }
+    }
+
+    fn set_open(&self, open: bool) {
+        *self.open.0.lock().unwrap() = open;
+        self.open.1.notify_all();
+    }
+}
+
+impl Fs for StallFs {
+    fn symlink_metadata(&self, path: &Path) -> io::Result<std::fs::Metadata> {
+        self.wait(path);
+        RealFs.symlink_metadata(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        self.wait(path);
+        RealFs.read_dir(path)
+    }
+
+    fn fuse_mounts(&self) -> io::Result<Vec<PathBuf>> {
+        Ok(vec![self.fuse.clone()])
+    }
+}
+
+#[tokio::test]
+async fn stalled_fuse_subtree_is_skipped_until_it_answers() {
+    let tmp = TempDir::new().unwrap();
+    let root = tmp.path().canonicalize().unwrap();
+    let fuse = root.join("mnt");
+    std::fs::create_dir_all(&fuse).unwrap();
+    for name in ["mnt/a", "mnt/b", "local"] {
+        std::fs::write(root.join(name), name).unwrap();
+    }
+    let stall = Arc::new(StallFs { fuse: fuse.clone(), open: Arc::new((std::sync::Mutex::new(true), std::sync::Condvar::new())) });
+    let cfg = FileScreamConfig::default()
+        .pulse(Duration::from_millis(10))
+        .content_hashing(ContentHashing::default())
+        .fs(stall.clone())
+        .fuse_timeout(Some(Duration::from_millis(50)));
+    let mut fs = FileScream::new(Some(cfg));
+    fs.watch(&root).unwrap();
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(seen.clone()));
+    let (handle, task) = spawn_sensor(fs, Arc::new(hub));
+    let kinds = || -> Vec<(&'static str, String)> {
+        seen.lock()
+            .unwrap()
+            .iter()
+            .map(|ev| (ev.kind(), ev.path().map(|p| p.strip_prefix(&root).unwrap().display().to_string()).unwrap_or_default()))
+            .collect()
+    };
+    tokio::time::sleep(Duration::from_millis(60)).await;
+
+    // the daemon hangs: the rest of the tree is still scanned, the subtree's files are kept
+    stall.set_open(false);
+    std::fs::write(root.join("local2"), "x").unwrap();
+    std::fs::remove_file(root.join("mnt/b")).unwrap();
+    std::fs::write(root.join("mnt/c"), "c").unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let stalled = kinds();
+    assert!(stalled.contains(&("subtree_stalled", "mnt".into())), "{stalled:?}");
+    assert!(stalled.contains(&("created", "local2".into())), "{stalled:?}");
+    assert!(!stalled.iter().any(|(_, p)| p.starts_with("mnt/")), "{stalled:?}");
+    assert_eq!(stalled.iter().filter(|(k, _)| *k == "subtree_stalled").count(), 1);
+    match seen.lock().unwrap().iter().find(|ev| ev.kind() == "subtree_stalled") {
+        Some(FileScreamEvent::SubtreeStalled { root: r, op, after, .. }) => {
+            assert_eq!((r, op.as_str(), *after), (&root, "metadata", Duration::from_millis(50)));
+        }
+        other => panic!("{other:?}"),
+    }
+
+    // it answers again: what changed meanwhile is reported
+    stall.set_open(true);
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    handle.shutdown();
+    let _ = task.await;
+    let resumed = kinds()[stalled.len()..].to_vec();
+    assert_eq!(resumed.first(), Some(&("subtree_resumed", "mnt".into())), "{resumed:?}");
+    let mut rest = resumed[1..].to_vec();
+    rest.sort();
+    assert_eq!(rest, [("created", "mnt/c".into()), ("removed", "mnt/b".into())]);
+}
+
+#[test]
+fn fuse_mounts_come_from_the_mount_table() {
+    let table = b"36 35 98:0 / /mnt/sshfs rw - fuse.sshfs user@host:/ rw\n\
+        37 35 98:0 / /media/usb\\040disk rw shared:1 - fuseblk /dev/sdb1 rw\n\
+        38 35 98:0 / /home rw - ext4 /dev/sda2 rw\n\
+        39 35 broken\n\
+        40 35 0:50 / /run/user/1000/doc rw - fuse portal rw\n";
+    assert_eq!(crate::fs::fuse_mounts(table), [PathBuf::from("/mnt/sshfs"), PathBuf::from("/media/usb disk"), PathBuf::from("/run/user/1000/doc")]);
+}
@@ -0,0 +1,77 @@
+//! The filesystem calls a scan makes, behind a trait so tests can slow them down or stall
+//! them, see [`crate::FileScreamConfig::fs`].
+
+use std::{
+    fs::Metadata,
+    io,
+    path::{Path, PathBuf},
+};
+
+pub trait Fs: Send + Sync {
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    /// Paths of the entries of a directory; those that cannot be read are left out.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+
+    /// Mount points of the FUSE filesystems. From the mount table: asking a FUSE filesystem
+    /// itself can hang as well.
+    fn fuse_mounts(&self) -> io::Result<Vec<PathBuf>>;
+}
+
+/// `std::fs`, and FUSE mounts from `/proc/self/mountinfo` (none on other systems).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RealFs;
+
+impl Fs for RealFs {
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        std::fs::symlink_metadata(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        Ok(std::fs::read_dir(path)?.flatten().map(|e| e.path()).collect())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn fuse_mounts(&self) -> io::Result<Vec<PathBuf>> {
+        Ok(fuse_mounts(&std::fs::read("/proc/self/mountinfo")?))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn fuse_mounts(&self) -> io::Result<Vec<PathBuf>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Mount points of the `fuse`, `fuseblk` and `fuse.*` mounts of a mountinfo file. Lines that
+/// do not parse are skipped.
+pub(crate) fn fuse_mounts(mountinfo: &[u8]) -> Vec<PathBuf> {
+    let mut out = Vec::new();
+    for line in mountinfo.split(|b| *b == b'\n') {
+        let mut fields = line.split(|b| *b == b' ');
+        let Some(mount_point) = fields.nth(4) else { continue };
+        let Some(fstype) = fields.skip_while(|f| *f != b"-").nth(1) else { continue };
+        if matches!(fstype, b"fuse" | b"fuseblk") || fstype.starts_with(b"fuse.") {
+            out.push(omnitrace_core::paths::from_bytes(unescape(mount_point)));
+        }
+    }
+    out
+}
+
+/// Undo the octal escapes (`\040` for a space) of a mountinfo field.
+fn unescape(s: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        if s[i] == b'\\'
+            && let Some(oct) = s.get(i + 1..i + 4)
+            && let Ok(b) = u8::from_str_radix(std::str::from_utf8(oct).unwrap_or("x"), 8)
+        {
+            out.push(b);
+            i += 4;
+        } else {
+            out.push(s[i]);
+            i += 1;
+        }
+    }
+    out
+}
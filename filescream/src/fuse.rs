@@ -0,0 +1,170 @@
+//! Timeouts for the calls a scan makes into FUSE filesystems.
+//!
+//! A FUSE daemon that hangs (a dead sshfs server, a wedged rclone) leaves every stat of its
+//! mount blocking, forever or until the kernel gives up. Each FUSE mount gets a worker thread
+//! the calls for its subtree run on; one that does not return within the timeout leaves the
+//! subtree stalled, and it is skipped until the call comes back.
+
+use crate::fs::Fs;
+use hashbrown::HashMap;
+use std::{
+    fs::Metadata,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    time::{Duration, Instant},
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// The thread running the calls into one FUSE mount; `busy` while one is running.
+struct Worker {
+    jobs: mpsc::Sender<Job>,
+    busy: Arc<AtomicBool>,
+}
+
+impl Worker {
+    fn spawn(mount: &Path) -> Option<Self> {
+        let (jobs, rx) = mpsc::channel::<Job>();
+        std::thread::Builder::new()
+            .name(format!("filescream-fuse:{}", mount.display()))
+            .spawn(move || {
+                for job in rx {
+                    job();
+                }
+            })
+            .ok()?;
+        Some(Self { jobs, busy: Arc::new(AtomicBool::new(false)) })
+    }
+}
+
+struct Stall {
+    since: Instant,
+}
+
+/// A subtree stalling or coming back, to be fired as an event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum StallChange {
+    Stalled { subtree: PathBuf, op: &'static str, after: Duration },
+    Resumed { subtree: PathBuf, stalled_for: Duration },
+}
+
+/// Runs the scan's filesystem calls, those into FUSE mounts on their workers with a timeout.
+/// Without a timeout every call goes straight to the [`Fs`].
+pub(crate) struct FuseGuard {
+    fs: Arc<dyn Fs>,
+    timeout: Option<Duration>,
+    mounts: Vec<PathBuf>,
+    workers: HashMap<PathBuf, Worker>,
+    stalls: HashMap<PathBuf, Stall>,
+    changes: Vec<StallChange>,
+}
+
+impl FuseGuard {
+    pub(crate) fn new(fs: Arc<dyn Fs>, timeout: Option<Duration>) -> Self {
+        Self { fs, timeout, mounts: Vec::new(), workers: HashMap::new(), stalls: HashMap::new(), changes: Vec::new() }
+    }
+
+    /// Read the FUSE mounts again, before a scan. A stalled mount that is gone (unmounted) is
+    /// no longer stalled; its worker stays stuck until the call returns, and then exits.
+    pub(crate) fn refresh(&mut self) {
+        if self.timeout.is_none() {
+            return;
+        }
+        // keep the last ones if the mount table cannot be read
+        if let Ok(mut mounts) = self.fs.fuse_mounts() {
+            mounts.sort();
+            mounts.dedup();
+            self.mounts = mounts;
+        }
+        let mounts = &self.mounts;
+        self.workers.retain(|m, _| mounts.contains(m));
+        let changes = &mut self.changes;
+        self.stalls.retain(|m, s| {
+            let kept = mounts.contains(m);
+            if !kept {
+                changes.push(StallChange::Resumed { subtree: m.clone(), stalled_for: s.since.elapsed() });
+            }
+            kept
+        });
+    }
+
+    /// The FUSE mount points, as of the last refresh.
+    pub(crate) fn mounts(&self) -> &[PathBuf] {
+        &self.mounts
+    }
+
+    pub(crate) fn is_stalled(&self, path: &Path) -> bool {
+        self.stalls.keys().any(|m| path.starts_with(m))
+    }
+
+    /// The stalls and recoveries since the last call.
+    pub(crate) fn take_changes(&mut self) -> Vec<StallChange> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// `None` if `path` is in a stalled subtree, or the call timed out and stalled it.
+    pub(crate) fn metadata(&mut self, path: &Path) -> Option<io::Result<Metadata>> {
+        self.call(path, "metadata", |fs, p| fs.symlink_metadata(p))
+    }
+
+    /// The entries of a directory, `None` like for [`FuseGuard::metadata`].
+    pub(crate) fn read_dir(&mut self, path: &Path) -> Option<io::Result<Vec<PathBuf>>> {
+        self.call(path, "read_dir", |fs, p| fs.read_dir(p))
+    }
+
+    fn call<T: Send + 'static>(&mut self, path: &Path, op: &'static str, f: fn(&dyn Fs, &Path) -> io::Result<T>) -> Option<io::Result<T>> {
+        // the innermost mount owns the path
+        let mount = self.mounts.iter().rev().find(|m| path.starts_with(m));
+        let (Some(timeout), Some(mount)) = (self.timeout, mount) else {
+            return Some(f(&*self.fs, path));
+        };
+        if self.is_stalled(path) {
+            // stalled through an enclosing mount, or still waiting for its call
+            let own = self.stalls.get(mount).filter(|_| !self.workers.get(mount).is_some_and(|w| w.busy.load(Ordering::Acquire)))?;
+            let stalled_for = own.since.elapsed();
+            self.stalls.remove(mount);
+            self.changes.push(StallChange::Resumed { subtree: mount.clone(), stalled_for });
+            if self.is_stalled(path) {
+                return None;
+            }
+        }
+
+        let mount = mount.clone();
+        if !self.workers.contains_key(&mount) {
+            match Worker::spawn(&mount) {
+                Some(w) => self.workers.insert(mount.clone(), w),
+                None => return Some(f(&*self.fs, path)),
+            };
+        }
+        let worker = &self.workers[&mount];
+        let (tx, rx) = mpsc::sync_channel(1);
+        let (fs, busy, owned) = (self.fs.clone(), worker.busy.clone(), path.to_path_buf());
+        busy.store(true, Ordering::Release);
+        let job: Job = Box::new(move || {
+            let _ = tx.send(f(&*fs, &owned));
+            busy.store(false, Ordering::Release);
+        });
+        if worker.jobs.send(job).is_err() {
+            self.workers.remove(&mount);
+            return Some(f(&*self.fs, path));
+        }
+        match rx.recv_timeout(timeout) {
+            Ok(res) => Some(res),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                self.stalls.insert(mount.clone(), Stall { since: Instant::now() });
+                self.changes.push(StallChange::Stalled { subtree: mount, op, after: timeout });
+                None
+            }
+            // the worker died with the call
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                self.workers.remove(&mount);
+                Some(Err(io::Error::other(format!("{op} of {} failed on its worker", path.display()))))
+            }
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScanOutcome {
+    Completed,
+    /// Cut short by shutdown: nothing was reported and the previous state was kept.
+    Aborted,
+}
+
+/// How the last scan went.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanReport {
+    pub outcome: ScanOutcome,
+    pub finished: SystemTime,
+    pub elapsed: Duration,
+    /// Files seen (so far, for an aborted scan).
+    pub files: usize,
+    /// Pulse the sensor was scanning at, see [`crate::FileScreamConfig::adaptive`].
+    pub pulse: Duration,
+}
+
+/// Shared handle on the last scan report, see [`crate::FileScream::health`]. Cheap to clone.
+#[derive(Clone, Debug, Default)]
+pub struct ScanHealth(Arc<Mutex<Option<ScanReport>>>);
+
+impl ScanHealth {
+    /// `None` until the first scan finished.
+    pub fn last_scan(&self) -> Option<ScanReport> {
+        self.0.lock().ok().and_then(|r| *r)
+    }
+
+    pub(crate) fn set(&self, report: ScanReport) {
+        if let Ok(mut r) = self.0.lock() {
+            *r = Some(report);
+        }
+    }
+}
@@ -1,43 +1,151 @@
-use blake3::{Hash, Hasher};
+use blake3::Hasher;
 use globset::{Glob, GlobSet, GlobSetBuilder};
 use hashbrown::HashMap;
 use omnitrace_core::{
     callbacks::CallbackHub,
+    clock::{self, SharedClock},
+    debug::DebugCell,
+    degrade::{Profile, ProfileSwitch},
+    entities::{self, EntityCounters},
+    error::Diagnostics,
+    expected::Deviation,
+    history::{History, HistoryConfig, HistoryHandle},
+    labels::Labels,
+    memory::{self, MemoryReport, MemoryStats},
+    preflight::PreflightFinding,
+    preview::{PreviewHandle, PreviewQueue, PreviewReport},
+    pulse::{AdaptivePulse, EffectivePulse, Pacer},
     sensor::{Sensor, SensorCtx},
 };
+use serde::Serialize;
 use std::{
-    collections::HashSet,
-    fs::{Metadata, read_dir},
+    collections::{BTreeMap, HashSet},
+    fs::Metadata,
+    io,
     path::{Path, PathBuf},
     pin::Pin,
+    sync::Arc,
     time::UNIX_EPOCH,
 };
-use tokio::{task::spawn_blocking, time::Duration};
+use tokio::{
+    task::spawn_blocking,
+    time::{Duration, Instant},
+};
+use tokio_util::sync::CancellationToken;
 
-use crate::events::FileScreamEvent;
+use crate::content::{ContentHashing, ContentScanner, IoStats, ScanIoStats};
+use crate::digest::{Digest, HashAlgorithm};
+use crate::error::FileScreamError;
+use crate::events::{FileChange, FileScreamEvent};
+use crate::expected::ExpectedFiles;
+use crate::fs::{Fs, RealFs};
+use crate::fuse::{FuseGuard, StallChange};
+use crate::health::{ScanHealth, ScanOutcome, ScanReport};
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::modes::{DEFAULT_SECURITY_RULES, FileMode, ModeRule, ModeWatch};
+use crate::preview::FileScreamRule;
+use crate::spike::{ScanCounts, SpikeConfig, SpikeDetector};
+use crate::stats::{Explanation, RootStats, ScanStats, ScanStatsSnapshot, WalkStats};
 
+pub mod content;
+pub mod demo;
+pub mod digest;
+pub mod error;
 pub mod events;
+pub mod expected;
+pub mod fs;
+mod fuse;
+pub mod health;
+pub mod manifest;
+pub mod modes;
+pub mod prelude;
+pub mod preview;
+pub mod spike;
+pub mod stats;
+
+#[cfg(test)]
+mod demo_ut;
+#[cfg(test)]
+mod expected_ut;
+#[cfg(test)]
+mod filescream_ut;
+#[cfg(all(test, feature = "soak"))]
+mod soak_ut;
+#[cfg(test)]
+mod stats_ut;
 
 #[derive(Clone)]
 struct PathGlobMatcher {
     any: GlobSet,
     dir_only: GlobSet,
+    /// The patterns as given: those of `any` by index, then those of `dir_only`.
+    rules: Vec<String>,
 }
 
 impl Default for PathGlobMatcher {
     fn default() -> Self {
         let empty = GlobSetBuilder::new().build().unwrap();
-        Self { any: empty.clone(), dir_only: empty }
+        Self { any: empty.clone(), dir_only: empty, rules: Vec::new() }
+    }
+}
+
+impl PathGlobMatcher {
+    fn is_match(&self, path: &Path, is_dir: bool) -> bool {
+        (is_dir && self.dir_only.is_match(path)) || self.any.is_match(path)
+    }
+
+    /// Indices into `rules` of the patterns matching `path`.
+    fn matching(&self, path: &Path, is_dir: bool) -> Vec<usize> {
+        let mut out = self.any.matches(path);
+        if is_dir {
+            out.extend(self.dir_only.matches(path).into_iter().map(|i| i + self.any.len()));
+        }
+        out
     }
 }
 
 pub struct FileScreamConfig {
     pulse: Duration,
+    adaptive: Option<AdaptivePulse>,
+    mount_aware: bool,
+    spikes: Option<SpikeConfig>,
+    content: Option<ContentHashing>,
+    memory_budget: Option<u64>,
+    history: Option<HistoryConfig>,
+    clock: SharedClock,
+    profile: Option<ProfileSwitch>,
+    replaced: Replaced,
+    fs: Arc<dyn Fs>,
+    fuse_timeout: Option<Duration>,
+}
+
+/// How a file replaced by another one at the same path is reported, see
+/// [`FileScreamConfig::replaced_as`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub enum Replaced {
+    /// One Changed event, as for a rewrite in place.
+    #[default]
+    Changed,
+    /// Removed, then Created.
+    RemovedCreated,
 }
 
 impl Default for FileScreamConfig {
     fn default() -> Self {
-        Self { pulse: Duration::from_secs(3) }
+        Self {
+            pulse: Duration::from_secs(3),
+            adaptive: None,
+            mount_aware: false,
+            spikes: None,
+            content: None,
+            memory_budget: None,
+            history: None,
+            clock: clock::system(),
+            profile: None,
+            replaced: Replaced::default(),
+            fs: Arc::new(RealFs),
+            fuse_timeout: Some(Duration::from_secs(5)),
+        }
     }
 }
 
@@ -47,25 +155,274 @@ impl FileScreamConfig {
         self
     }
 
+    /// Scan every `min` while files change, backing off up to `max` while nothing is reported,
+    /// instead of every `pulse`. See [`omnitrace_core::pulse`].
+    pub fn adaptive(self, min: Duration, max: Duration) -> Self {
+        self.adaptive_pulse(AdaptivePulse::new(min, max))
+    }
+
+    /// [`FileScreamConfig::adaptive`] with its own backoff policy.
+    pub fn adaptive_pulse(mut self, policy: AdaptivePulse) -> Self {
+        self.adaptive = Some(policy);
+        self
+    }
+
+    /// Check every watched root before scanning. A root that is missing, or that was a mountpoint
+    /// and no longer is (its filesystem got unmounted), is suspended: one RootUnavailable event is
+    /// fired and its tracked state is frozen, instead of a Removed event per file. When it comes back
+    /// a RootRestored event is fired and the root is diffed against the frozen state, so changes made
+    /// in the meantime are still reported.
+    pub fn mount_aware(mut self, on: bool) -> Self {
+        self.mount_aware = on;
+        self
+    }
+
+    /// Fire ActivitySpike when unusually many files are created, changed or removed in one scan,
+    /// e.g. `activity_spikes(SpikeConfig::adaptive(10.0, 50).depth(1))` for ransomware-style mass rewrites.
+    pub fn activity_spikes(mut self, cfg: SpikeConfig) -> Self {
+        self.spikes = Some(cfg);
+        self
+    }
+
+    /// Time source for the activity spike window and scan timestamps (default: the system clock).
+    pub fn clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Detect changes by file content instead of size and mtime: a touched but identical file is not
+    /// Changed. Only files whose metadata changed are read again. Reads can be rate-limited and
+    /// parallelized, see [`ContentHashing`], and their cost is reported by [`FileScream::io_stats`].
+    pub fn content_hashing(mut self, opts: ContentHashing) -> Self {
+        self.content = Some(opts);
+        self
+    }
+
+    /// Soft limit for the estimated memory use, see [`FileScream::memory`]. When over it, the
+    /// largest subtrees switch from content to metadata hashes (dropping their content cache)
+    /// and OverBudget is fired. The event history, if kept, is cleared first. The file table
+    /// itself is never shed.
+    pub fn memory_budget(mut self, bytes: u64) -> Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// Keep the last events of each file and root, see [`FileScream::history`].
+    pub fn history(mut self, config: HistoryConfig) -> Self {
+        self.history = Some(config);
+        self
+    }
+
+    /// Follow the switch of a [`omnitrace_core::degrade::DegradeController`], see
+    /// [`FileScream::apply_profile`].
+    pub fn profile_switch(mut self, switch: ProfileSwitch) -> Self {
+        self.profile = Some(switch);
+        self
+    }
+
+    /// How to report a path that holds another file (a new inode) than at the last scan, e.g.
+    /// after an atomic rename from a temp file or a delete and recreate. It is reported even if
+    /// size and timestamps match, unless content hashing finds the same content.
+    pub fn replaced_as(mut self, replaced: Replaced) -> Self {
+        self.replaced = replaced;
+        self
+    }
+
+    /// The filesystem scans go through (default: [`RealFs`]), e.g. one with injected latency
+    /// in tests.
+    pub fn fs(mut self, fs: Arc<dyn Fs>) -> Self {
+        self.fs = fs;
+        self
+    }
+
+    /// How long a stat or directory listing in a FUSE mount may take (default: 5s). One that
+    /// takes longer stalls the mount: SubtreeStalled is fired and scans skip it, keeping its
+    /// files as they were, until the call returns and SubtreeResumed is fired. FUSE files are
+    /// compared by metadata only, even with content hashing. `None` calls into FUSE mounts
+    /// like anywhere else, and a hung daemon hangs the scan.
+    pub fn fuse_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.fuse_timeout = timeout;
+        self
+    }
+
     fn get_pulse(&self) -> Duration {
         self.pulse
     }
 }
 
+/// How a root is watched, see [`FileScream::watch_with`].
+#[derive(Clone, Debug, Default)]
+pub struct WatchOptions {
+    labels: Labels,
+}
+
+impl WatchOptions {
+    /// Labels for the events about the files under the root, e.g. `{"app": "nginx"}`. A file
+    /// under nested roots gets the labels of all of them, the inner root's winning for a key
+    /// both set.
+    pub fn labels(mut self, labels: Labels) -> Self {
+        self.labels = labels;
+        self
+    }
+}
+
+/// Entries walked between two cancellation checks during a scan.
+const CANCEL_CHECK_EVERY: usize = 256;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 struct DirStamp {
     mtime_ns: u128,
 }
 
+/// Which file a path held when scanned. A different one means the file was replaced, whatever
+/// its size and timestamps.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Generation {
+    dev: u64,
+    ino: u64,
+}
+
+/// A tracked file: its hash (of metadata, or of content, see [`crate::content`]) and generation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct FileRecord {
+    pub(crate) hash: Digest,
+    pub(crate) generation: Generation,
+    /// Size and mtime folded together, to tell a content change from a metadata-only one when
+    /// hashing metadata. Never 0, which stands for unknown (manifests without it).
+    pub(crate) stamp: u64,
+}
+
+impl FileRecord {
+    /// Hash of size, mtime and ctime. A file deleted and recreated with the same size and mtime
+    /// still gets a new ctime, and so does one whose mtime was set back.
+    pub(crate) fn of(meta: &Metadata) -> Self {
+        let mut h = Hasher::new();
+        let mtime = FileScream::mtime_ns(meta);
+        h.update(&meta.len().to_le_bytes());
+        h.update(&mtime.to_le_bytes());
+        let stamp = (meta.len().rotate_left(32) ^ mtime as u64 ^ (mtime >> 64) as u64).max(1);
+        #[cfg(unix)]
+        let generation = {
+            use std::os::unix::fs::MetadataExt;
+            h.update(&meta.ctime().to_le_bytes());
+            h.update(&meta.ctime_nsec().to_le_bytes());
+            Generation { dev: meta.dev(), ino: meta.ino() }
+        };
+        #[cfg(not(unix))]
+        let generation = Generation::default();
+        Self { hash: h.finalize().into(), generation, stamp }
+    }
+}
+
+/// What [`FileScream::scan`] works on, moved to the blocking thread and back: the roots and
+/// the last scan's state, and what the scan learns besides the files.
+struct ScanCtx {
+    roots: Vec<PathBuf>,
+    ignore: PathGlobMatcher,
+    /// Files of the last scan.
+    previous: HashMap<PathBuf, FileRecord>,
+    dir_state: HashMap<PathBuf, DirStamp>,
+    content: Option<ContentScanner>,
+    /// Not fed when empty.
+    modes: ModeWatch,
+    fuse: FuseGuard,
+    cancel: CancellationToken,
+    /// Entries that could not be read.
+    errors: Vec<FileScreamError>,
+    stats: WalkStats,
+}
+
+impl ScanCtx {
+    /// A first scan: no previous state, no content hashing, no mode rules.
+    fn new(roots: Vec<PathBuf>, ignore: PathGlobMatcher, fuse: FuseGuard, cancel: CancellationToken) -> Self {
+        let stats = WalkStats::new(ignore.rules.len());
+        Self {
+            roots,
+            ignore,
+            previous: HashMap::new(),
+            dir_state: HashMap::new(),
+            content: None,
+            modes: ModeWatch::default(),
+            fuse,
+            cancel,
+            errors: Vec::new(),
+            stats,
+        }
+    }
+}
+
+/// What a watched root looked like the last time it was available.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct RootStamp {
+    /// The root is a mountpoint (on a different device than its parent)
+    own_mount: bool,
+}
+
+/// What [`FileScream::debug_handle`] reports, refreshed after every scan.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct FileScreamDebug {
+    pub pulse_ms: u64,
+    pub profile: Profile,
+    pub mount_aware: bool,
+    pub content_hashing: bool,
+    pub activity_spikes: bool,
+    pub primed: bool,
+    /// Watched roots, sorted.
+    pub roots: Vec<String>,
+    pub ignored: Vec<String>,
+    /// Roots suspended in mount-aware mode.
+    pub suspended: Vec<String>,
+    /// Tracked files per owning root.
+    pub files_per_root: BTreeMap<String, usize>,
+    pub dirs_tracked: usize,
+    pub last_scan: Option<ScanReport>,
+    pub io: ScanIoStats,
+    /// `rules:glob` per [`FileScream::alert_on`] call.
+    pub mode_rules: Vec<String>,
+    /// Events per root, see [`FileScream::entity_counters`].
+    pub entities: EntityCounters,
+    /// Recent events per file and root, if [`FileScreamConfig::history`] is set.
+    pub history: Option<HistoryHandle<FileScreamEvent>>,
+    /// See [`FileScream::scan_stats`].
+    pub stats: ScanStatsSnapshot,
+}
+
 pub struct FileScream {
     watched: HashSet<PathBuf>,
+    // per watched root, see WatchOptions::labels
+    labels: HashMap<PathBuf, Labels>,
     ignored: HashSet<String>, // glob patterns
-    fstate: HashMap<PathBuf, Hash>,
+    fstate: HashMap<PathBuf, FileRecord>,
     dstate: HashMap<PathBuf, DirStamp>,
     config: FileScreamConfig,
 
     is_primed: bool,
     im: PathGlobMatcher,
+    previews: PreviewQueue<FileScreamRule>,
+
+    // mount-aware mode
+    roots: HashMap<PathBuf, RootStamp>,
+    suspended: HashSet<PathBuf>,
+
+    spikes: Option<SpikeDetector>,
+    content: Option<ContentScanner>,
+    // taken into the scan task like `content`
+    fuse: Option<FuseGuard>,
+    stall_events: Vec<FileScreamEvent>,
+    modes: ModeWatch,
+    expected: ExpectedFiles,
+    entities: EntityCounters,
+    history: Option<History<FileScreamEvent>>,
+    health: ScanHealth,
+    stats: ScanStats,
+    debug: DebugCell<FileScreamDebug>,
+    diagnostics: Diagnostics,
+    memory: MemoryStats,
+    over_budget: bool,
+    profile: Profile,
+    pacer: Pacer,
+    // content hashing just resumed: the next scan is compared by metadata hash
+    content_resumed: bool,
 }
 
 impl Default for FileScream {
@@ -76,33 +433,66 @@ impl Default for FileScream {
 
 impl FileScream {
     pub fn new(config: Option<FileScreamConfig>) -> Self {
+        let config = config.unwrap_or_default();
         Self {
+            spikes: config.spikes.clone().map(SpikeDetector::new),
+            content: config.content.clone().map(ContentScanner::new),
+            fuse: Some(FuseGuard::new(config.fs.clone(), config.fuse_timeout)),
+            stall_events: Vec::new(),
+            history: config.history.map(History::new),
+            pacer: Pacer::new(config.get_pulse(), config.adaptive),
             watched: HashSet::new(),
+            labels: HashMap::new(),
             ignored: HashSet::new(),
             fstate: HashMap::new(),
             dstate: HashMap::new(),
 
-            config: config.unwrap_or_default(),
+            config,
             is_primed: false,
             im: PathGlobMatcher::default(),
+            previews: PreviewQueue::default(),
+            roots: HashMap::new(),
+            suspended: HashSet::new(),
+            modes: ModeWatch::default(),
+            expected: ExpectedFiles::default(),
+            entities: EntityCounters::default(),
+            health: ScanHealth::default(),
+            stats: ScanStats::default(),
+            debug: DebugCell::default(),
+            diagnostics: Diagnostics::new(),
+            memory: MemoryStats::default(),
+            over_budget: false,
+            profile: Profile::Full,
+            content_resumed: false,
         }
     }
 
     /// Add a directory to watch. Subdirectories will be watched as well.
-    pub fn watch<P: AsRef<Path>>(&mut self, path: P) {
-        if let Ok(p) = path.as_ref().canonicalize() {
-            self.watched.insert(p);
-        } else {
-            self.watched.insert(path.as_ref().to_path_buf());
+    /// Watching a directory that is nested in another watched one is fine: its files are then owned by
+    /// the innermost root. Watching the same directory twice (after canonicalization) is an error.
+    pub fn watch<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.watch_with(path, WatchOptions::default())
+    }
+
+    /// Add a directory to watch like [`FileScream::watch`], with `opts`.
+    pub fn watch_with<P: AsRef<Path>>(&mut self, path: P, opts: WatchOptions) -> io::Result<()> {
+        let p = path.as_ref().canonicalize().unwrap_or_else(|_| path.as_ref().to_path_buf());
+        if self.watched.contains(&p) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} is already watched", p.display())));
+        }
+
+        if !opts.labels.is_empty() {
+            self.labels.insert(p.clone(), opts.labels);
         }
+        self.watched.insert(p);
+        Ok(())
     }
+
     /// Remove a directory from being watched.
     pub fn unwatch<P: AsRef<Path>>(&mut self, path: P) {
-        if let Ok(p) = path.as_ref().canonicalize() {
-            self.watched.remove(&p);
-        } else {
-            self.watched.remove(path.as_ref());
-        }
+        let p = path.as_ref().canonicalize().unwrap_or_else(|_| path.as_ref().to_path_buf());
+        self.labels.remove(&p);
+        self.watched.remove(&p);
     }
 
     /// Add a glob pattern to ignore. Ignored paths will not be scanned or reported on.
@@ -114,145 +504,925 @@ impl FileScream {
     /// Remove a glob pattern from being ignored.
     pub fn unignore<S: AsRef<str>>(&mut self, pattern: S) {
         self.ignored.remove(pattern.as_ref());
+        self.diagnostics.clear(&format!("pattern:{}", pattern.as_ref()));
         self.im = self.get_glob_matchers(&self.ignored);
     }
 
+    /// Fire SuspiciousMode when a scan finds a file under `glob` newly matching one of `rules`,
+    /// e.g. `alert_on(ModeRule::SETUID | ModeRule::WORLD_WRITABLE, "/etc/**")`. Globs work as for
+    /// [`FileScream::ignore`]. Modes come from the metadata the scan reads anyway; files present
+    /// when the sensor starts are the baseline and not reported.
+    pub fn alert_on(&mut self, rules: ModeRule, glob: &str) {
+        self.modes.add(rules, glob);
+    }
+
+    /// Declare what should and should not be on disk, e.g. as loaded with
+    /// [`omnitrace_core::expected::load`]. When the sensor primes it fires a Deviation for each
+    /// difference, before reporting any change. Declared paths need not be under a watched root;
+    /// `exclusive` only covers the files the scan finds.
+    pub fn expect(&mut self, files: ExpectedFiles) {
+        self.expected.exclusive |= files.exclusive;
+        self.expected.files.extend(files.files);
+    }
+
+    /// Deviations of the primed state from [`FileScream::expect`]. Reads the declared files with
+    /// a hash.
+    async fn deviations(&self) -> Vec<FileScreamEvent> {
+        let expected = self.expected.clone();
+        let mut found = spawn_blocking(move || expected.deviations()).await.unwrap_or_default();
+        if self.expected.exclusive {
+            let declared: HashSet<PathBuf> = self.expected.files.iter().map(|f| Self::canonical(&f.path)).collect();
+            let mut extra: Vec<&PathBuf> = self.fstate.keys().filter(|p| !declared.contains(*p)).collect();
+            extra.sort();
+            found.extend(extra.into_iter().map(|p| (p.clone(), Deviation::Unexpected)));
+        }
+        found
+            .into_iter()
+            .map(|(path, deviation)| {
+                let (root, rel_path) = self.owner(&path);
+                FileScreamEvent::Deviation { path, root, rel_path, deviation, labels: Labels::default() }
+            })
+            .collect()
+    }
+
+    /// Enable [`modes::DEFAULT_SECURITY_RULES`]: new setuid and setgid executables anywhere,
+    /// world-writable files in system directories.
+    pub fn alert_on_default_security_rules(&mut self) {
+        for (rules, glob) in DEFAULT_SECURITY_RULES {
+            self.modes.add(*rules, glob);
+        }
+    }
+
+    /// IO stats of the last content scan (all zero unless content hashing is on).
+    pub fn io_stats(&self) -> IoStats {
+        self.content.as_ref().map(|c| c.stats.clone()).unwrap_or_default()
+    }
+
+    /// Outcome of the last scan, e.g. for a health endpoint.
+    pub fn health(&self) -> ScanHealth {
+        self.health.clone()
+    }
+
+    /// Per-root walk costs of the last scan and hits per ignore pattern, updated after every
+    /// completed scan.
+    pub fn scan_stats(&self) -> ScanStats {
+        self.stats.clone()
+    }
+
+    /// Whether `path` is scanned, and which root or ignore pattern decides it, with the roots
+    /// and patterns as they are now. Nothing is read but the path's own metadata, so this
+    /// answers for paths that do not exist yet as well.
+    pub fn explain<P: AsRef<Path>>(&self, path: P) -> Explanation {
+        let path = Self::canonical(path.as_ref());
+        let mut roots: Vec<&PathBuf> = self.watched.iter().filter(|r| path.starts_with(r)).collect();
+        roots.sort_by_key(|r| std::cmp::Reverse(r.components().count()));
+        let Some(&innermost) = roots.first() else {
+            return Explanation::NotWatched;
+        };
+        let meta = std::fs::symlink_metadata(&path).ok();
+
+        // the walk of any root containing the path finds it, unless it prunes the path or a parent
+        let mut pruned = None;
+        let mut walked = false;
+        for root in roots.into_iter().filter(|r| !self.suspended.contains(*r)) {
+            let mut at: Vec<&Path> = path.ancestors().take_while(|a| a.starts_with(root)).collect();
+            at.reverse();
+            let is_dir = |a: &Path| a != path || meta.as_ref().is_some_and(Metadata::is_dir);
+            match at.into_iter().find(|a| self.im.is_match(a, is_dir(a))) {
+                Some(a) => {
+                    let patterns = self.im.matching(a, is_dir(a)).into_iter().map(|i| self.im.rules[i].clone()).collect();
+                    pruned.get_or_insert(Explanation::Ignored { root: root.clone(), at: a.to_path_buf(), patterns });
+                }
+                None => {
+                    walked = true;
+                    break;
+                }
+            }
+        }
+
+        let root = innermost.clone();
+        match (walked, pruned) {
+            (false, Some(ignored)) => ignored,
+            (false, None) => Explanation::Suspended { root },
+            (true, _) if meta.is_some_and(|m| !m.is_file() && !m.is_dir()) => Explanation::Untracked { root },
+            (true, _) => Explanation::Scanned { root },
+        }
+    }
+
+    /// What adding `rule` would do, against the files of the last scan: the tracked files it
+    /// would prune, by themselves or by a directory above them within their root. They are
+    /// both `would_match` and `would_stop_matching`. Nothing is changed.
+    pub fn preview_rule(&self, rule: &FileScreamRule) -> PreviewReport {
+        let FileScreamRule::Ignore(pat) = rule;
+        let im = self.get_glob_matchers(&HashSet::from([pat.clone()]));
+        PreviewReport::tally(self.fstate.keys().map(|path| {
+            let (root, _) = self.owner(path);
+            let pruned = path.ancestors().take_while(|a| a.starts_with(&root)).any(|a| im.is_match(a, a != path));
+            (path.display().to_string(), pruned, pruned)
+        }))
+    }
+
+    /// Handle for previewing rules against the running sensor, answered on its next tick.
+    pub fn preview_handle(&self) -> PreviewHandle<FileScreamRule> {
+        self.previews.handle()
+    }
+
+    fn answer_previews(&self) {
+        for req in self.previews.take() {
+            let report = self.preview_rule(&req.rule);
+            req.answer(report);
+        }
+    }
+
+    /// Scan once and list every tracked file with its hash, for a baseline to compare against
+    /// later with [`FileScream::diff_manifests`]. For a sensor not started yet; the scan counts
+    /// in the stats and health like any other. `None` if the walk got cancelled.
+    pub async fn manifest(&mut self) -> Option<Manifest> {
+        let files = self.scan_blocking(&CancellationToken::new()).await?;
+        let by_content = self.content.as_ref().is_some_and(|c| !c.is_paused());
+        let algorithm = self.content.as_ref().filter(|_| by_content).map_or(HashAlgorithm::Blake3, ContentScanner::algorithm);
+        let mut entries: Vec<ManifestEntry> = files
+            .iter()
+            .map(|(path, rec)| {
+                let (root, rel_path) = self.owner(path);
+                ManifestEntry::new(path.clone(), root, rel_path, rec, algorithm)
+            })
+            .collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Some(Manifest { by_content, files: entries })
+    }
+
+    /// The events a scan would fire going from `old` to `new`: Created, Changed (or Removed and
+    /// Created for a replaced file, as `replaced` says) and Removed, sorted by path. Files
+    /// compare as in the sensor; between a content and a metadata manifest the hashes do not
+    /// compare, so only replaced files are found. Content hashes of different algorithms do not
+    /// compare either: such files are [`FileChange::Unverified`] unless replaced.
+    pub fn diff_manifests(old: &Manifest, new: &Manifest, replaced: Replaced) -> Vec<FileScreamEvent> {
+        let before: HashMap<&Path, &ManifestEntry> = old.files.iter().map(|e| (e.path.as_path(), e)).collect();
+        let after: HashMap<&Path, &ManifestEntry> = new.files.iter().map(|e| (e.path.as_path(), e)).collect();
+        let by_content = old.by_content && new.by_content;
+        let mut paths: Vec<&Path> = before.keys().chain(after.keys()).copied().collect();
+        paths.sort();
+        paths.dedup();
+
+        let mut out = Vec::new();
+        for path in paths {
+            let ev = |e: &ManifestEntry| (e.path.clone(), e.root.clone(), e.rel_path.clone());
+            match (before.get(path), after.get(path)) {
+                (None, Some(e)) => {
+                    let (path, root, rel_path) = ev(e);
+                    out.push(FileScreamEvent::Created { path, root, rel_path, labels: Labels::default() });
+                }
+                (Some(e), None) => {
+                    let (path, root, rel_path) = ev(e);
+                    out.push(FileScreamEvent::Removed { path, root, rel_path, labels: Labels::default() });
+                }
+                (Some(o), Some(n)) => {
+                    let change = match (o.record(), n.record()) {
+                        // hashes of two algorithms tell nothing about the content
+                        (Some(ro), Some(rn)) if by_content && o.algorithm != n.algorithm => {
+                            Some(if ro.generation != rn.generation { FileChange::Replaced } else { FileChange::Unverified })
+                        }
+                        (Some(o), Some(n)) if old.by_content == new.by_content => Self::compare(&o, &n, by_content),
+                        (Some(o), Some(n)) => (o.generation != n.generation).then_some(FileChange::Replaced),
+                        _ => Some(FileChange::Content),
+                    };
+                    let (path, root, rel_path) = ev(n);
+                    match change {
+                        None => {}
+                        Some(FileChange::Replaced) if replaced == Replaced::RemovedCreated => {
+                            out.push(FileScreamEvent::Removed {
+                                path: path.clone(),
+                                root: root.clone(),
+                                rel_path: rel_path.clone(),
+                                labels: Labels::default(),
+                            });
+                            out.push(FileScreamEvent::Created { path, root, rel_path, labels: Labels::default() });
+                        }
+                        Some(change) => out.push(FileScreamEvent::Changed { path, root, rel_path, change, labels: Labels::default() }),
+                    }
+                }
+                (None, None) => {}
+            }
+        }
+        out
+    }
+
+    /// `path` with its parent canonicalized like the watched roots. The last component is kept
+    /// as is: the walk sees a symlink, not its target, and the path may not exist.
+    pub(crate) fn canonical(path: &Path) -> PathBuf {
+        match (path.parent().and_then(|p| p.canonicalize().ok()), path.file_name()) {
+            (Some(parent), Some(name)) => parent.join(name),
+            _ => path.canonicalize().unwrap_or_else(|_| path.to_path_buf()),
+        }
+    }
+
+    /// The pulse in use, see [`FileScreamConfig::adaptive`].
+    pub fn effective_pulse(&self) -> EffectivePulse {
+        self.pacer.handle()
+    }
+
+    /// Handle on a summary of the sensor's state, for [`omnitrace_core::debug::Snapshots`].
+    pub fn debug_handle(&self) -> DebugCell<FileScreamDebug> {
+        self.debug.clone()
+    }
+
+    /// Events emitted per root and kind, with their recent rate. OverBudget is not counted.
+    pub fn entity_counters(&self) -> EntityCounters {
+        self.entities.clone()
+    }
+
+    /// The last events per file path and per watched root (e.g. `"/etc/passwd"` and `"/etc"`),
+    /// as of the previous scan. None unless [`FileScreamConfig::history`] is set.
+    pub fn history(&self) -> Option<HistoryHandle<FileScreamEvent>> {
+        self.history.as_ref().map(History::handle)
+    }
+
+    /// Invalid ignore patterns, and entries the scans could not read or hash.
+    pub fn diagnostics(&self) -> Diagnostics {
+        self.diagnostics.clone()
+    }
+
+    /// Estimated memory use of the file and directory tables and the content cache, updated after every scan.
+    pub fn memory(&self) -> MemoryStats {
+        self.memory.clone()
+    }
+
+    fn memory_report(&self) -> MemoryReport {
+        let mut r = MemoryReport::new(self.config.memory_budget);
+        r.add("files", self.fstate.keys().map(|p| memory::path_entry(p, size_of::<FileRecord>())).sum());
+        r.add("dirs", self.dstate.keys().map(|p| memory::path_entry(p, size_of::<DirStamp>())).sum());
+        r.add("content_cache", self.content.as_ref().map_or(0, ContentScanner::cache_bytes));
+        r.add("modes", self.modes.tracked().map(|p| memory::path_entry(p, size_of::<(FileMode, ModeRule)>())).sum());
+        if let Some(h) = &self.history {
+            r.add("history", h.bytes());
+        }
+        r
+    }
+
+    /// Account memory and shed content caches when over budget, see [`FileScreamConfig::memory_budget`].
+    async fn check_memory<R: Send + 'static>(&mut self, hub: &CallbackHub<FileScreamEvent, R>) {
+        let mut report = self.memory_report();
+        let Some(budget) = report.budget.filter(|_| report.exceeds()) else {
+            self.over_budget = false;
+            self.memory.publish(report, false);
+            return;
+        };
+
+        let estimated_bytes = report.estimated_bytes;
+        let history_shed = match &self.history {
+            Some(h) if report.parts.get("history").is_some_and(|b| *b > 0) => {
+                h.clear();
+                report = self.memory_report();
+                true
+            }
+            _ => false,
+        };
+        // cached bytes per subtree: the first directory under the owning root, or a file directly in it
+        let mut subtrees: HashMap<PathBuf, u64> = HashMap::new();
+        for (path, bytes) in self.content.iter().flat_map(ContentScanner::cached) {
+            let (root, rel_path) = self.owner(path);
+            let mut comps = rel_path.components();
+            let subtree = match (comps.next(), comps.next()) {
+                (Some(first), Some(_)) => root.join(first),
+                _ => path.clone(),
+            };
+            *subtrees.entry(subtree).or_default() += bytes;
+        }
+        let mut subtrees: Vec<(PathBuf, u64)> = subtrees.into_iter().collect();
+        subtrees.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let mut metadata_only = Vec::new();
+        if let Some(content) = &mut self.content {
+            let mut estimate = report.estimated_bytes;
+            for (subtree, bytes) in subtrees {
+                if estimate <= budget {
+                    break;
+                }
+                content.shed(&subtree, &mut self.fstate);
+                estimate -= bytes;
+                metadata_only.push(subtree.display().to_string());
+            }
+        }
+        if !metadata_only.is_empty() {
+            report = self.memory_report();
+        }
+
+        report.over_budget = report.exceeds();
+        let after_shedding = report.estimated_bytes;
+        self.memory.publish(report, history_shed || !metadata_only.is_empty());
+        if !self.over_budget {
+            self.over_budget = true;
+            Self::fire(
+                hub,
+                &self.entities,
+                &self.history,
+                &self.labels,
+                FileScreamEvent::OverBudget { estimated_bytes, after_shedding, budget, metadata_only },
+            )
+            .await;
+        }
+    }
+
+    fn publish_debug(&self) {
+        let sorted = |items: &mut dyn Iterator<Item = String>| {
+            let mut v: Vec<String> = items.collect();
+            v.sort();
+            v
+        };
+        let mut files_per_root = BTreeMap::new();
+        for path in self.fstate.keys() {
+            *files_per_root.entry(self.owner(path).0.display().to_string()).or_insert(0) += 1;
+        }
+
+        self.debug.update(|d| {
+            *d = FileScreamDebug {
+                pulse_ms: self.pulse().as_millis() as u64,
+                profile: self.profile,
+                mount_aware: self.config.mount_aware,
+                content_hashing: self.content.as_ref().is_some_and(|c| !c.is_paused()),
+                activity_spikes: self.spikes.is_some(),
+                primed: self.is_primed,
+                roots: sorted(&mut self.watched.iter().map(|p| p.display().to_string())),
+                ignored: sorted(&mut self.ignored.iter().cloned()),
+                suspended: sorted(&mut self.suspended.iter().map(|p| p.display().to_string())),
+                files_per_root,
+                dirs_tracked: self.dstate.len(),
+                last_scan: self.health.last_scan(),
+                io: self.io_stats().last_scan(),
+                mode_rules: self.modes.patterns(),
+                entities: self.entities.clone(),
+                history: self.history(),
+                stats: self.stats.snapshot(),
+            }
+        });
+        if let Some(h) = &self.history {
+            h.publish();
+        }
+    }
+
     fn mtime_ns(meta: &Metadata) -> u128 {
         meta.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_nanos()).unwrap_or(0)
     }
 
-    async fn fire(hub: &CallbackHub<FileScreamEvent>, ev: FileScreamEvent) {
+    /// Split `path` into the watched root owning it and the path relative to that root.
+    /// The innermost (longest) root wins. Paths no longer under any root (e.g. after `unwatch()`)
+    /// get an empty root and the full path as `rel_path`.
+    fn owner(&self, path: &Path) -> (PathBuf, PathBuf) {
+        match self.watched.iter().filter(|r| path.starts_with(r)).max_by_key(|r| r.components().count()) {
+            Some(root) => (root.clone(), path.strip_prefix(root).unwrap_or(path).to_path_buf()),
+            None => (PathBuf::new(), path.to_path_buf()),
+        }
+    }
+
+    /// Probe a root: `None` if it is missing, otherwise its stamp.
+    fn probe_root(root: &Path) -> Option<RootStamp> {
+        let meta = std::fs::metadata(root).ok()?;
+        if !meta.is_dir() {
+            return None;
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let parent_dev = root.parent().and_then(|p| std::fs::metadata(p).ok()).map(|m| m.dev());
+            Some(RootStamp { own_mount: parent_dev.is_some_and(|d| d != meta.dev()) })
+        }
+
+        #[cfg(not(unix))]
+        {
+            Some(RootStamp { own_mount: false })
+        }
+    }
+
+    /// Update the suspended set (mount-aware mode). Returns the events to fire.
+    fn check_roots(&mut self, quiet: bool) -> Vec<FileScreamEvent> {
+        let mut events = Vec::new();
+        let mut roots: Vec<PathBuf> = self.watched.iter().cloned().collect();
+        roots.sort();
+
+        for root in roots {
+            // probing a stalled FUSE root would hang as well
+            if self.fuse.as_ref().is_some_and(|f| f.is_stalled(&root)) {
+                continue;
+            }
+            let probe = Self::probe_root(&root);
+            let was_mount = self.roots.get(&root).is_some_and(|s| s.own_mount);
+            let available = probe.is_some_and(|p| p.own_mount || !was_mount);
+
+            if available {
+                self.roots.insert(root.clone(), probe.unwrap());
+                if self.suspended.remove(&root) && !quiet {
+                    events.push(FileScreamEvent::RootRestored { root, labels: Labels::default() });
+                }
+            } else if self.suspended.insert(root.clone()) && !quiet {
+                events.push(FileScreamEvent::RootUnavailable { root, labels: Labels::default() });
+            }
+        }
+
+        self.suspended.retain(|r| self.watched.contains(r));
+        events
+    }
+
+    fn is_suspended(&self, path: &Path) -> bool {
+        self.suspended.iter().any(|r| path.starts_with(r))
+    }
+
+    async fn fire<R: Send + 'static>(
+        hub: &CallbackHub<FileScreamEvent, R>, counters: &EntityCounters, history: &Option<History<FileScreamEvent>>,
+        labels: &HashMap<PathBuf, Labels>, mut ev: FileScreamEvent,
+    ) {
+        if !labels.is_empty()
+            && let Some((path, slot)) = ev.labels_mut()
+        {
+            // outermost root first, so inner roots win
+            let mut roots: Vec<(&PathBuf, &Labels)> = labels.iter().filter(|(root, _)| path.starts_with(root)).collect();
+            roots.sort_by_key(|(root, _)| root.components().count());
+            *slot = Labels::merged(roots.into_iter().map(|(_, l)| l));
+        }
+        if let Some(root) = ev.root() {
+            counters.record(&root.display().to_string(), entities::mask_name(ev.mask()));
+        }
+        if let Some(h) = history {
+            let root = ev.root().map(|r| r.display().to_string());
+            let path = ev.path().map(|p| p.display().to_string()).filter(|p| root.as_ref() != Some(p));
+            h.record(path.into_iter().chain(root), &ev);
+        }
         hub.fire(ev.mask().bits(), &ev).await;
     }
 
+    /// Every root must be a readable directory (a missing one is only a warning when mount
+    /// aware, it may not be mounted yet), and every pattern must compile.
+    fn preflight_findings(&self) -> Vec<PreflightFinding> {
+        let mut out = Vec::new();
+        let mut roots: Vec<&PathBuf> = self.watched.iter().collect();
+        roots.sort();
+        if roots.is_empty() {
+            out.push(PreflightFinding::warning("no directories watched").remedy("add them with FileScream::watch"));
+        }
+        for root in roots {
+            match std::fs::metadata(root) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound && self.config.mount_aware => {
+                    out.push(PreflightFinding::warning(format!("watched {} does not exist yet", root.display())))
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => out.push(
+                    PreflightFinding::critical(format!("watched {} does not exist", root.display()))
+                        .remedy("check the path, or enable FileScreamConfig::mount_aware for roots that come and go"),
+                ),
+                Err(e) => out.push(PreflightFinding::critical(format!("cannot stat {}: {e}", root.display()))),
+                Ok(m) if !m.is_dir() => out.push(PreflightFinding::critical(format!("watched {} is not a directory", root.display()))),
+                Ok(_) => {
+                    if let Err(e) = std::fs::read_dir(root) {
+                        out.push(
+                            PreflightFinding::critical(format!("cannot list {}: {e}", root.display()))
+                                .remedy("run with read and search permission on the watched tree"),
+                        );
+                    }
+                }
+            }
+        }
+
+        let mut ignored: Vec<&String> = self.ignored.iter().collect();
+        ignored.sort();
+        for raw in ignored {
+            if let Err(source) = ignore_glob(raw.trim_end_matches('/')) {
+                out.push(
+                    PreflightFinding::warning(FileScreamError::Pattern { pattern: raw.clone(), source }.to_string())
+                        .remedy("nothing is ignored for it"),
+                );
+            }
+        }
+        for (pattern, e) in self.modes.invalid() {
+            out.push(PreflightFinding::warning(format!("invalid alert_on glob {pattern:?}: {e}")).remedy("no mode alerts fire for it"));
+        }
+        out
+    }
+
     /// Compile glob patterns into matchers for efficient scanning.
     /// Patterns ending with '/' are treated as directory-only, others match files and directories.
     /// Leading '/' anchors the pattern to the filesystem root, otherwise it matches anywhere in the path.
-    /// Invalid patterns are ignored.
+    /// Invalid patterns are skipped and recorded in the diagnostics.
     fn get_glob_matchers(&self, patterns: &HashSet<String>) -> PathGlobMatcher {
         let mut all = GlobSetBuilder::new();
         let mut dirs = GlobSetBuilder::new();
+        let (mut any_rules, mut dir_rules) = (Vec::new(), Vec::new());
+        let mut patterns: Vec<&String> = patterns.iter().collect();
+        patterns.sort();
 
         for raw in patterns {
             let pat = raw.trim_end_matches('/');
 
-            // semantics:
-            //  - leading '/' => anchored at filesystem root (absolute path string starts with '/')
-            //  - no leading '/' => match anywhere in path => "**/<pat>"
-            let compiled = if pat.starts_with('/') { pat.to_string() } else { format!("**/{}", pat) };
-
-            // Compile glob
-            let g = match Glob::new(&compiled) {
+            let g = match ignore_glob(pat) {
                 Ok(g) => g,
-                Err(_) => continue, // ignore invalid patterns instead of panicking
+                Err(source) => {
+                    self.diagnostics.report_once(&format!("pattern:{raw}"), FileScreamError::Pattern { pattern: raw.clone(), source });
+                    continue;
+                }
             };
 
             if raw.ends_with('/') {
                 dirs.add(g);
+                dir_rules.push(raw.clone());
             } else {
                 all.add(g);
+                any_rules.push(raw.clone());
             }
         }
 
+        any_rules.extend(dir_rules);
         PathGlobMatcher {
             any: all.build().unwrap_or_else(|_| GlobSetBuilder::new().build().unwrap()),
             dir_only: dirs.build().unwrap_or_else(|_| GlobSetBuilder::new().build().unwrap()),
+            rules: any_rules,
         }
     }
 
-    fn scan(roots: &[PathBuf], ignore: &PathGlobMatcher, dir_state: &mut HashMap<PathBuf, DirStamp>) -> HashMap<PathBuf, Hash> {
+    /// Walk the roots. Returns `None` if `cancel` fired before the scan completed: a partial
+    /// file set would look like mass removal, so the caller must drop it without diffing.
+    /// Entries that cannot be read are skipped and pushed to `errors`. Files of `previous` the
+    /// walk did not find are looked at once more at its end, see [`FileScream::settle`].
+    /// Each root's walk and each pruning by an ignore rule is counted in `stats`. Filesystem
+    /// calls go through `fuse`, which skips stalled FUSE subtrees; the caller carries their
+    /// files over.
+    fn scan(ctx: &mut ScanCtx) -> Option<HashMap<PathBuf, FileRecord>> {
+        let ScanCtx { roots, ignore, previous, dir_state, content, modes, fuse, cancel, errors, stats } = ctx;
+        let mut content = content.as_mut();
+        let mut modes = (!modes.is_empty()).then_some(modes);
+        fuse.refresh();
+        if let Some(c) = content.as_deref_mut() {
+            c.set_unread(fuse.mounts());
+        }
         let mut out = HashMap::new();
+        let mut sizes = HashMap::new();
+        let mut walked = 0usize;
 
-        for root in roots {
+        for root in roots.iter() {
             let mut stack = vec![root.clone()]; // DFS
+            let started = Instant::now();
+            let mut rs = RootStats::default();
 
             while let Some(path) = stack.pop() {
-                let meta = match std::fs::symlink_metadata(&path) {
-                    Ok(m) => m,
-                    Err(_) => continue,
+                walked += 1;
+                if walked.is_multiple_of(CANCEL_CHECK_EVERY) && cancel.is_cancelled() {
+                    return None;
+                }
+
+                let meta = match fuse.metadata(&path) {
+                    None => continue,
+                    Some(Ok(m)) => m,
+                    Some(Err(e)) => {
+                        Self::walk_error(errors, path, e);
+                        continue;
+                    }
                 };
 
                 let is_dir = meta.is_dir();
-                let s = path.to_string_lossy();
 
-                // ignore pruning
-                if (is_dir && ignore.dir_only.is_match(&*s)) || ignore.any.is_match(&*s) {
+                // ignore pruning, on the path itself so non-UTF-8 names match like the rest
+                if ignore.is_match(&path, is_dir) {
+                    for i in ignore.matching(&path, is_dir) {
+                        stats.hits[i] += 1;
+                    }
                     continue;
                 }
 
                 if is_dir {
-                    dir_state.insert(path.clone(), DirStamp { mtime_ns: Self::mtime_ns(&meta) });
-
-                    let rd = match read_dir(&path) {
-                        Ok(rd) => rd,
-                        Err(_) => continue,
-                    };
+                    let stamp = DirStamp { mtime_ns: Self::mtime_ns(&meta) };
+                    rs.dirs_visited += 1;
+                    if dir_state.insert(path.clone(), stamp) == Some(stamp) {
+                        rs.dirs_unchanged += 1;
+                    }
 
-                    for ent in rd.flatten() {
-                        stack.push(ent.path());
+                    match fuse.read_dir(&path) {
+                        None => {}
+                        Some(Ok(entries)) => stack.extend(entries),
+                        Some(Err(e)) => Self::walk_error(errors, path, e),
                     }
                 } else if meta.is_file() {
-                    let mut h = Hasher::new();
-                    h.update(&meta.len().to_le_bytes());
-                    h.update(&Self::mtime_ns(&meta).to_le_bytes());
-                    out.insert(path, h.finalize());
+                    if content.is_some() {
+                        sizes.insert(path.clone(), meta.len());
+                    }
+                    if let Some(m) = modes.as_deref_mut() {
+                        m.observe(&path, &meta);
+                    }
+                    rs.files_hashed += 1;
+                    out.insert(path, FileRecord::of(&meta));
                 } else {
                     // XXX: ignore symlinks/devices/etc for now
                 }
             }
+
+            rs.elapsed = started.elapsed();
+            stats.roots.push((root.clone(), rs));
         }
 
-        out
+        for (path, meta) in Self::settle(roots, ignore, previous, &out, fuse) {
+            if content.is_some() {
+                sizes.insert(path.clone(), meta.len());
+            }
+            if let Some(m) = modes.as_deref_mut() {
+                m.observe(&path, &meta);
+            }
+            out.insert(path, FileRecord::of(&meta));
+        }
+
+        if let Some(c) = content
+            && !c.rehash(&mut out, &sizes, cancel, errors)
+        {
+            return None;
+        }
+
+        Some(out)
+    }
+
+    /// Files of `previous` under `roots` that the walk missed but that exist now. A file
+    /// replaced by a delete and a rename, or a directory rewritten while it was listed, can be
+    /// missing from the listing for a moment; reported as is, that is a Removed now and a Created
+    /// at the next scan, instead of one change.
+    fn settle(
+        roots: &[PathBuf], ignore: &PathGlobMatcher, previous: &HashMap<PathBuf, FileRecord>, found: &HashMap<PathBuf, FileRecord>,
+        fuse: &mut FuseGuard,
+    ) -> Vec<(PathBuf, Metadata)> {
+        previous
+            .keys()
+            .filter(|p| !found.contains_key(*p) && roots.iter().any(|r| p.starts_with(r)) && !ignore.is_match(p, false))
+            .filter_map(|p| Some((p.clone(), fuse.metadata(p)?.ok().filter(Metadata::is_file)?)))
+            .collect()
     }
 
-    async fn scan_blocking(&mut self) -> (HashMap<PathBuf, Hash>, HashMap<PathBuf, DirStamp>) {
-        let roots: Vec<PathBuf> = self.watched.iter().cloned().collect();
-        let ignore = self.im.clone();
-        let dir_state = std::mem::take(&mut self.dstate);
+    /// How a file present in both scans changed, if it did. A replaced file with the same
+    /// content (`by_content`: the hashes are content hashes) has not.
+    /// By metadata, only a moved size or mtime (or an unknown stamp) is a content change.
+    fn compare(old: &FileRecord, new: &FileRecord, by_content: bool) -> Option<FileChange> {
+        if old.generation != new.generation {
+            return (!by_content || old.hash != new.hash).then_some(FileChange::Replaced);
+        }
+        if old.hash == new.hash {
+            return None;
+        }
+        let metadata_only = !by_content && old.stamp == new.stamp && old.stamp != 0;
+        Some(if metadata_only { FileChange::Metadata } else { FileChange::Content })
+    }
 
-        spawn_blocking(move || {
-            let mut ds = dir_state;
-            let files = Self::scan(&roots, &ignore, &mut ds);
-            (files, ds)
+    fn walk_error(errors: &mut Vec<FileScreamError>, path: PathBuf, source: io::Error) {
+        // removed between listing its directory and reading it
+        if source.kind() != io::ErrorKind::NotFound {
+            errors.push(FileScreamError::Walk { path, source });
+        }
+    }
+
+    /// Scan off the runtime, returning `None` when cancelled midway (see [`FileScream::scan`]).
+    async fn scan_blocking(&mut self, cancel: &CancellationToken) -> Option<HashMap<PathBuf, FileRecord>> {
+        let roots: Vec<PathBuf> = self.watched.iter().filter(|r| !self.suspended.contains(*r)).cloned().collect();
+        let fuse = self.fuse.take().expect("fuse guard is put back after every scan");
+        let ctx = ScanCtx {
+            previous: std::mem::take(&mut self.fstate),
+            dir_state: std::mem::take(&mut self.dstate),
+            content: self.content.take(),
+            modes: std::mem::take(&mut self.modes),
+            ..ScanCtx::new(roots, self.im.clone(), fuse, cancel.clone())
+        };
+        let started = Instant::now();
+
+        let (files, ctx) = spawn_blocking(move || {
+            let mut ctx = ctx;
+            (Self::scan(&mut ctx), ctx)
         })
         .await
-        .expect("scan task panicked")
+        .expect("scan task panicked");
+        let ScanCtx { previous, dir_state: ds, content, mut modes, mut fuse, errors, stats: walk, .. } = ctx;
+        self.fstate = previous;
+        for change in fuse.take_changes() {
+            let ev = match change {
+                StallChange::Stalled { subtree, op, after } => {
+                    let root = self.owner(&subtree).0;
+                    FileScreamEvent::SubtreeStalled { root, subtree, op: op.to_string(), after, labels: Labels::default() }
+                }
+                StallChange::Resumed { subtree, stalled_for } => {
+                    let root = self.owner(&subtree).0;
+                    FileScreamEvent::SubtreeResumed { root, subtree, stalled_for, labels: Labels::default() }
+                }
+            };
+            self.stall_events.push(ev);
+        }
+        self.fuse = Some(fuse);
+        if files.is_some() {
+            self.stats.record(walk, &self.im.rules);
+        }
+
+        // an unreadable directory fails every scan: log each kind once until a scan gets through
+        let mut failed = Vec::new();
+        for e in errors {
+            let key = if matches!(e, FileScreamError::Hash { .. }) { "hash" } else { "walk" };
+            failed.push(key);
+            self.diagnostics.report_once(key, e);
+        }
+        if files.is_some() {
+            for key in ["walk", "hash"].into_iter().filter(|k| !failed.contains(k)) {
+                self.diagnostics.clear(key);
+            }
+        }
+
+        if files.is_none() {
+            modes.discard();
+        }
+        self.content = content;
+        self.modes = modes;
+        self.dstate = ds;
+        self.health.set(ScanReport {
+            outcome: if files.is_some() { ScanOutcome::Completed } else { ScanOutcome::Aborted },
+            finished: self.config.clock.now_system(),
+            elapsed: started.elapsed(),
+            files: files.as_ref().map_or(0, |f| f.len()),
+            pulse: self.pulse(),
+        });
+        files
+    }
+
+    /// Run at `profile`: degraded doubles the pulse and pauses content hashing, so changes are
+    /// detected by size and mtime only. Switching either way is not reported as changes.
+    pub fn apply_profile(&mut self, profile: Profile) {
+        if profile == self.profile {
+            return;
+        }
+        self.profile = profile;
+        self.pacer.set_profile(profile);
+        if let Some(content) = &mut self.content {
+            match profile {
+                Profile::Degraded => content.pause(&mut self.fstate),
+                Profile::Full => {
+                    content.resume();
+                    self.content_resumed = true;
+                }
+            }
+        }
+    }
+
+    fn pulse(&self) -> Duration {
+        self.pacer.pulse()
     }
 
-    pub async fn run(mut self, ctx: SensorCtx<FileScreamEvent>) {
-        let (files, dirs) = self.scan_blocking().await;
+    pub async fn run<R: Send + 'static>(mut self, ctx: SensorCtx<FileScreamEvent, R>) {
+        if self.config.mount_aware {
+            self.check_roots(true);
+        }
+
+        let Some(files) = self.scan_blocking(&ctx.cancel).await else {
+            return;
+        };
         self.fstate = files;
-        self.dstate = dirs;
+        for ev in std::mem::take(&mut self.stall_events) {
+            Self::fire(&ctx.hub, &self.entities, &self.history, &self.labels, ev).await;
+        }
+        self.modes.finish(true);
         self.is_primed = true;
+        for ev in self.deviations().await {
+            Self::fire(&ctx.hub, &self.entities, &self.history, &self.labels, ev).await;
+        }
+        self.check_memory(&ctx.hub).await;
+        self.publish_debug();
 
-        let mut ticker = tokio::time::interval(self.config.get_pulse());
+        let mut ticker = self.pacer.ticker();
+        let mut last_scan = self.config.clock.now_instant();
 
         loop {
+            ctx.beat();
+            if self.pacer.observe_fired(ctx.hub.fired()) {
+                self.pacer.restart(&mut ticker);
+            }
             tokio::select! {
                 _ = ctx.cancel.cancelled() => break,
                 _ = ticker.tick() => {}
             }
+            self.answer_previews();
+
+            if let Some(p) = self.config.profile.as_ref().and_then(|s| s.changed_from(self.profile)) {
+                self.apply_profile(p);
+                self.pacer.restart(&mut ticker);
+            }
+
+            if self.config.mount_aware {
+                for ev in self.check_roots(false) {
+                    Self::fire(&ctx.hub, &self.entities, &self.history, &self.labels, ev).await;
+                }
+            }
+
+            let Some(mut new_files) = self.scan_blocking(&ctx.cancel).await else {
+                break;
+            };
+            for ev in std::mem::take(&mut self.stall_events) {
+                Self::fire(&ctx.hub, &self.entities, &self.history, &self.labels, ev).await;
+            }
+
+            // Suspended roots keep their frozen state. Whatever an enclosing root's scan finds
+            // underneath (e.g. the bare mountpoint directory) is not theirs.
+            if !self.suspended.is_empty() {
+                new_files.retain(|p, _| !self.is_suspended(p));
+                for (p, rec) in &self.fstate {
+                    if self.is_suspended(p) {
+                        new_files.insert(p.clone(), *rec);
+                        self.modes.carry_over(p);
+                    }
+                }
+            }
+
+            // the same for stalled FUSE subtrees, until they answer again
+            if let Some(fuse) = self.fuse.as_ref().filter(|f| self.fstate.keys().any(|p| f.is_stalled(p))) {
+                new_files.retain(|p, _| !fuse.is_stalled(p));
+                for (p, rec) in &self.fstate {
+                    if fuse.is_stalled(p) {
+                        new_files.insert(p.clone(), *rec);
+                        self.modes.carry_over(p);
+                    }
+                }
+            }
 
-            let (new_files, new_dir_state) = self.scan_blocking().await;
-            self.dstate = new_dir_state;
+            // the first content scan after a pause: unchanged metadata means unchanged
+            let resumed = self.content.as_ref().filter(|_| std::mem::take(&mut self.content_resumed));
+            let by_content = self.content.as_ref().is_some_and(|c| !c.is_paused());
+            let mut counts: HashMap<(PathBuf, PathBuf), ScanCounts> = HashMap::new();
+            for (path, new) in &new_files {
+                let change = match self.fstate.get(path) {
+                    None => None,
+                    Some(old) if old.generation == new.generation && resumed.is_some_and(|c| c.metadata_hash(path) == Some(old.hash)) => {
+                        continue;
+                    }
+                    Some(old) => match Self::compare(old, new, by_content) {
+                        Some(FileChange::Content) if self.content.as_ref().is_some_and(|c| c.rewritten(path)) => Some(FileChange::Rewritten),
+                        Some(c) => Some(c),
+                        None => continue,
+                    },
+                };
 
-            for (path, new_hash) in &new_files {
-                if let Some(ev) = match self.fstate.get(path) {
-                    None => Some(FileScreamEvent::Created { path: path.clone() }),
-                    Some(old_hash) if old_hash != new_hash => Some(FileScreamEvent::Changed { path: path.clone() }),
-                    _ => None,
-                } {
-                    Self::fire(&ctx.hub, ev).await;
+                let (root, rel_path) = self.owner(path);
+                let replaced = change == Some(FileChange::Replaced) && self.config.replaced == Replaced::RemovedCreated;
+                if let Some(d) = &self.spikes {
+                    let c = counts.entry(d.group(&root, &rel_path)).or_default();
+                    match change {
+                        None => c.created += 1,
+                        Some(_) if replaced => (c.removed, c.created) = (c.removed + 1, c.created + 1),
+                        Some(_) => c.changed += 1,
+                    }
                 }
+                let path = path.clone();
+                let ev = match change {
+                    None => FileScreamEvent::Created { path, root, rel_path, labels: Labels::default() },
+                    Some(_) if replaced => {
+                        let removed = FileScreamEvent::Removed {
+                            path: path.clone(),
+                            root: root.clone(),
+                            rel_path: rel_path.clone(),
+                            labels: Labels::default(),
+                        };
+                        Self::fire(&ctx.hub, &self.entities, &self.history, &self.labels, removed).await;
+                        FileScreamEvent::Created { path, root, rel_path, labels: Labels::default() }
+                    }
+                    Some(change) => FileScreamEvent::Changed { path, root, rel_path, change, labels: Labels::default() },
+                };
+                Self::fire(&ctx.hub, &self.entities, &self.history, &self.labels, ev).await;
+            }
+
+            // after the Created event of a file that was created that way
+            for (path, old, new, rules) in self.modes.finish(false) {
+                let (root, rel_path) = self.owner(&path);
+                Self::fire(
+                    &ctx.hub,
+                    &self.entities,
+                    &self.history,
+                    &self.labels,
+                    FileScreamEvent::SuspiciousMode { path, root, rel_path, rules: rules.names(), old, new, labels: Labels::default() },
+                )
+                .await;
             }
 
             for path in self.fstate.keys() {
                 if !new_files.contains_key(path) {
-                    Self::fire(&ctx.hub, FileScreamEvent::Removed { path: path.clone() }).await;
+                    let (root, rel_path) = self.owner(path);
+                    if let Some(d) = &self.spikes {
+                        counts.entry(d.group(&root, &rel_path)).or_default().removed += 1;
+                    }
+                    Self::fire(
+                        &ctx.hub,
+                        &self.entities,
+                        &self.history,
+                        &self.labels,
+                        FileScreamEvent::Removed { path: path.clone(), root, rel_path, labels: Labels::default() },
+                    )
+                    .await;
                 }
             }
 
             self.fstate = new_files;
+            self.check_memory(&ctx.hub).await;
+            self.publish_debug();
+
+            let window = self.config.clock.now_instant().saturating_duration_since(last_scan);
+            last_scan = self.config.clock.now_instant();
+            if let Some(d) = &mut self.spikes {
+                for ev in d.observe(&counts, window) {
+                    Self::fire(&ctx.hub, &self.entities, &self.history, &self.labels, ev).await;
+                }
+            }
         }
     }
 }
@@ -260,9 +1430,20 @@ impl FileScream {
 impl Sensor for FileScream {
     type Event = FileScreamEvent;
 
-    fn run(self, ctx: SensorCtx<Self::Event>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+    fn run<R: Send + 'static>(self, ctx: SensorCtx<Self::Event, R>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
         Box::pin(async move {
             FileScream::run(self, ctx).await;
         })
     }
+
+    fn preflight(&self) -> Pin<Box<dyn Future<Output = Vec<PreflightFinding>> + Send + '_>> {
+        let findings = self.preflight_findings();
+        Box::pin(async move { findings })
+    }
+}
+
+/// An ignore pattern (without its trailing `/`) as a glob: a leading `/` anchors it at the
+/// filesystem root, anything else matches anywhere in the path (`**/<pat>`).
+fn ignore_glob(pat: &str) -> Result<Glob, globset::Error> {
+    Glob::new(&if pat.starts_with('/') { pat.to_string() } else { format!("**/{pat}") })
 }
@@ -0,0 +1,50 @@
+//! Every tracked file with its hash, from a one-shot scan, to compare a host against a
+//! baseline taken earlier. See [`crate::FileScream::manifest`] and [`crate::FileScream::diff_manifests`].
+
+use crate::{
+    FileRecord, Generation,
+    digest::{Digest, HashAlgorithm},
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// The files a scan found, sorted by path.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The hashes are of content, not of size and timestamps, see [`crate::FileScreamConfig::content_hashing`].
+    pub by_content: bool,
+    pub files: Vec<ManifestEntry>,
+}
+
+/// One file. `root` and `rel_path` are as in [`crate::events::FileScreamEvent`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    #[serde(with = "omnitrace_core::paths")]
+    pub path: PathBuf,
+    #[serde(with = "omnitrace_core::paths")]
+    pub root: PathBuf,
+    #[serde(with = "omnitrace_core::paths")]
+    pub rel_path: PathBuf,
+    /// Hex digest, of content with `algorithm` in a content manifest, else BLAKE3 of metadata.
+    pub hash: String,
+    /// BLAKE3 in manifests from before algorithms could be chosen.
+    #[serde(default)]
+    pub algorithm: HashAlgorithm,
+    pub dev: u64,
+    pub ino: u64,
+    /// Size and mtime folded together, to tell content from metadata changes. 0 if unknown.
+    #[serde(default)]
+    pub stamp: u64,
+}
+
+impl ManifestEntry {
+    pub(crate) fn new(path: PathBuf, root: PathBuf, rel_path: PathBuf, rec: &FileRecord, algorithm: HashAlgorithm) -> Self {
+        let Generation { dev, ino } = rec.generation;
+        Self { path, root, rel_path, hash: rec.hash.to_hex(), algorithm, dev, ino, stamp: rec.stamp }
+    }
+
+    /// The record as a scan holds it, `None` if the hash is not valid hex.
+    pub(crate) fn record(&self) -> Option<FileRecord> {
+        Some(FileRecord { hash: Digest::from_hex(&self.hash)?, generation: Generation { dev: self.dev, ino: self.ino }, stamp: self.stamp })
+    }
+}
@@ -0,0 +1,174 @@
+use bitflags::bitflags;
+use globset::{Glob, GlobMatcher};
+use hashbrown::HashMap;
+use omnitrace_core::fields::FieldValue;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::Metadata,
+    path::{Path, PathBuf},
+};
+
+bitflags! {
+    /// Permission patterns worth an alert, see [`crate::FileScream::alert_on`].
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+    pub struct ModeRule: u32 {
+        /// Set-user-ID bit.
+        const SETUID = 0b001;
+        /// Set-group-ID bit on a file executable by anyone. Without group execute the bit
+        /// means mandatory locking, which is harmless.
+        const SETGID_EXECUTABLE = 0b010;
+        /// Writable by others.
+        const WORLD_WRITABLE = 0b100;
+    }
+}
+
+impl ModeRule {
+    /// The rules `mode` matches.
+    pub fn matching(mode: u32) -> Self {
+        let mut out = ModeRule::empty();
+        out.set(ModeRule::SETUID, mode & 0o4000 != 0);
+        out.set(ModeRule::SETGID_EXECUTABLE, mode & 0o2000 != 0 && mode & 0o111 != 0);
+        out.set(ModeRule::WORLD_WRITABLE, mode & 0o002 != 0);
+        out
+    }
+
+    /// Rule names as in events: `setuid`, `setgid_executable`, `world_writable`.
+    pub fn names(self) -> Vec<String> {
+        self.iter_names().map(|(name, _)| name.to_ascii_lowercase()).collect()
+    }
+}
+
+/// Permission bits and owner of a file, as reported in SuspiciousMode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileMode {
+    /// Permission bits including setuid/setgid/sticky, e.g. `0o4755`.
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl FileMode {
+    #[cfg(unix)]
+    pub(crate) fn of(meta: &Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+        Self { mode: meta.mode() & 0o7777, uid: meta.uid(), gid: meta.gid() }
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn of(_meta: &Metadata) -> Self {
+        Self { mode: 0, uid: 0, gid: 0 }
+    }
+
+    /// `mode`, `uid` or `gid`, for `SuspiciousMode`'s event fields.
+    pub(crate) fn field(&self, name: &str) -> Option<FieldValue<'static>> {
+        match name {
+            "mode" => Some(FieldValue::int(self.mode)),
+            "uid" => Some(FieldValue::int(self.uid)),
+            "gid" => Some(FieldValue::int(self.gid)),
+            _ => None,
+        }
+    }
+}
+
+/// Rules enabled by [`crate::FileScream::alert_on_default_security_rules`]: setuid and
+/// setgid executables anywhere watched, world-writable files in system directories.
+pub const DEFAULT_SECURITY_RULES: &[(ModeRule, &str)] = &[
+    (ModeRule::SETUID.union(ModeRule::SETGID_EXECUTABLE), "*"),
+    (ModeRule::WORLD_WRITABLE, "/etc/**"),
+    (ModeRule::WORLD_WRITABLE, "/usr/**"),
+    (ModeRule::WORLD_WRITABLE, "/bin/**"),
+    (ModeRule::WORLD_WRITABLE, "/sbin/**"),
+    (ModeRule::WORLD_WRITABLE, "/lib/**"),
+    (ModeRule::WORLD_WRITABLE, "/lib64/**"),
+    (ModeRule::WORLD_WRITABLE, "/boot/**"),
+    (ModeRule::WORLD_WRITABLE, "/root/**"),
+    (ModeRule::WORLD_WRITABLE, "/var/spool/cron/**"),
+];
+
+#[derive(Clone, Copy, Debug)]
+struct Seen {
+    mode: FileMode,
+    /// Rules the mode matches, of those applying to the path.
+    hits: ModeRule,
+}
+
+/// Mode rules and the modes of the files they apply to, checked on the metadata every
+/// scan reads anyway.
+#[derive(Clone, Default)]
+pub(crate) struct ModeWatch {
+    rules: Vec<(ModeRule, GlobMatcher, String)>,
+    // globs that did not compile, for preflight
+    invalid: Vec<(String, globset::Error)>,
+    last: HashMap<PathBuf, Seen>,
+    current: HashMap<PathBuf, Seen>,
+}
+
+impl ModeWatch {
+    /// Glob semantics as for ignore patterns: a leading `/` anchors at the filesystem root,
+    /// anything else matches anywhere. Invalid globs are ignored, see [`ModeWatch::invalid`].
+    pub(crate) fn add(&mut self, rules: ModeRule, pattern: &str) {
+        let compiled = if pattern.starts_with('/') { pattern.to_string() } else { format!("**/{pattern}") };
+        match Glob::new(&compiled) {
+            Ok(g) if !rules.is_empty() => self.rules.push((rules, g.compile_matcher(), pattern.to_string())),
+            Ok(_) => {}
+            Err(e) => self.invalid.push((pattern.to_string(), e)),
+        }
+    }
+
+    pub(crate) fn invalid(&self) -> &[(String, globset::Error)] {
+        &self.invalid
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub(crate) fn patterns(&self) -> Vec<String> {
+        self.rules.iter().map(|(r, _, p)| format!("{}:{p}", r.names().join("|"))).collect()
+    }
+
+    /// Called by the scan for every regular file.
+    pub(crate) fn observe(&mut self, path: &Path, meta: &Metadata) {
+        let applicable = self.rules.iter().filter(|(_, g, _)| g.is_match(path)).fold(ModeRule::empty(), |acc, (r, _, _)| acc | *r);
+        if applicable.is_empty() {
+            return;
+        }
+        let mode = FileMode::of(meta);
+        self.current.insert(path.to_path_buf(), Seen { mode, hits: ModeRule::matching(mode.mode) & applicable });
+    }
+
+    /// Keep the last known mode of `path` through a scan that did not see it, e.g. a
+    /// file under a suspended root.
+    pub(crate) fn carry_over(&mut self, path: &Path) {
+        if let Some(seen) = self.last.get(path) {
+            self.current.insert(path.to_path_buf(), *seen);
+        }
+    }
+
+    /// Finish a scan: events for files matching a rule they did not match before, as
+    /// `(path, old, new, newly matched rules)`. Quiet when priming.
+    pub(crate) fn finish(&mut self, quiet: bool) -> Vec<(PathBuf, Option<FileMode>, FileMode, ModeRule)> {
+        let mut out = Vec::new();
+        if !quiet {
+            for (path, seen) in &self.current {
+                let old = self.last.get(path);
+                let new_hits = seen.hits - old.map_or(ModeRule::empty(), |o| o.hits);
+                if !new_hits.is_empty() {
+                    out.push((path.clone(), old.map(|o| o.mode), seen.mode, new_hits));
+                }
+            }
+        }
+        out.sort_by(|a, b| a.0.cmp(&b.0));
+        self.last = std::mem::take(&mut self.current);
+        out
+    }
+
+    /// Drop a scan that was cancelled midway.
+    pub(crate) fn discard(&mut self) {
+        self.current.clear();
+    }
+
+    pub(crate) fn tracked(&self) -> impl Iterator<Item = &PathBuf> {
+        self.last.keys()
+    }
+}
@@ -0,0 +1,6 @@
+//! `use filescream::prelude::*;` brings in the FileScream sensor, its config and events,
+//! and the core prelude (callbacks, hub, `spawn_sensor`).
+
+pub use crate::events::{FileScreamEvent, FileScreamMask};
+pub use crate::{FileScream, FileScreamConfig, Replaced, WatchOptions};
+pub use omnitrace_core::prelude::*;
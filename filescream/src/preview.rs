@@ -0,0 +1,25 @@
+//! Rules [`crate::FileScream::preview_rule`] answers for, see [`omnitrace_core::preview`].
+
+use omnitrace_core::preview::{RuleError, split_rule};
+use std::str::FromStr;
+
+/// An ignore pattern, as [`crate::FileScream::ignore`] takes it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileScreamRule {
+    /// `ignore <pattern>`
+    Ignore(String),
+}
+
+impl FromStr for FileScreamRule {
+    type Err = RuleError;
+
+    fn from_str(s: &str) -> Result<Self, RuleError> {
+        match split_rule(s)? {
+            ("ignore", pat) => match crate::ignore_glob(pat.trim_end_matches('/')) {
+                Ok(_) => Ok(Self::Ignore(pat.to_string())),
+                Err(e) => Err(RuleError::InvalidArgument { verb: "ignore".to_string(), reason: e.to_string() }),
+            },
+            (verb, _) => Err(RuleError::UnknownVerb { verb: verb.to_string(), expected: "ignore" }),
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! Soak run of FileScream over a churning tree, see [`omnitrace_core::soak`].
+
+use crate::{FileScream, FileScreamConfig, content::ContentHashing, events::FileScreamEvent};
+use omnitrace_core::{
+    callbacks::CallbackHub,
+    sensor::spawn_sensor,
+    soak::{self, Soak},
+};
+use std::{path::Path, sync::Arc, time::Duration};
+
+const PULSE: Duration = Duration::from_millis(10);
+
+/// Tick `n`: create a file in one of ten directories, remove the one from 100 ticks ago and
+/// rewrite one in between, so about 100 files live at a time.
+fn churn(root: &Path, n: u64) {
+    let file = |n: u64| root.join(format!("d{}", n % 10)).join(format!("f{n}"));
+    std::fs::write(file(n), n.to_string()).unwrap();
+    if n >= 100 {
+        let _ = std::fs::remove_file(file(n - 100));
+        std::fs::write(file(n - 50), format!("{n} again")).unwrap();
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn memory_stays_bounded_under_churn() {
+    let root = std::env::temp_dir().join(format!("filescream-soak-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    for d in 0..10 {
+        std::fs::create_dir_all(root.join(format!("d{d}"))).unwrap();
+    }
+
+    let cfg = FileScreamConfig::default().pulse(PULSE).content_hashing(ContentHashing::default());
+    let mut fs = FileScream::new(Some(cfg));
+    fs.watch(&root).unwrap();
+    let memory = fs.memory();
+    let (handle, task) = spawn_sensor(fs, Arc::new(CallbackHub::<FileScreamEvent>::new()));
+
+    let ticks = soak::ticks(5_000);
+    let mut soak = Soak::new(ticks / 10);
+    for n in 0..ticks {
+        churn(&root, n);
+        tokio::time::sleep(PULSE).await;
+        if n % 100 == 0 {
+            soak.sample(n, &memory.report());
+        }
+    }
+    handle.shutdown();
+    let _ = task.await;
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert!(soak.samples() > 0);
+    soak.check().unwrap_or_else(|leak| panic!("after {ticks} ticks: {leak}"));
+}
@@ -0,0 +1,141 @@
+use crate::events::FileScreamEvent;
+use hashbrown::HashMap;
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Limit {
+    Fixed(usize),
+    Adaptive { factor: f64, min: usize },
+}
+
+/// Rate-of-change anomaly detection, see [`crate::FileScreamConfig::activity_spikes`].
+///
+/// Every scan, the Created/Changed/Removed events are counted per group (a watched root, or
+/// a subtree of it with [`SpikeConfig::depth`]). A group whose total goes over the limit fires
+/// one ActivitySpike, then stays quiet for the cooldown.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpikeConfig {
+    limit: Limit,
+    alpha: f64,
+    depth: usize,
+    cooldown: u32,
+}
+
+impl SpikeConfig {
+    /// Spike when more than `n` files change in one scan.
+    pub fn fixed(n: usize) -> Self {
+        Self { limit: Limit::Fixed(n), alpha: 0.2, depth: 0, cooldown: 5 }
+    }
+
+    /// Spike when more than `factor` times the recent average (EWMA) of changes per scan
+    /// change in one scan, and at least `min` (so a quiet tree does not alert on two files).
+    pub fn adaptive(factor: f64, min: usize) -> Self {
+        Self { limit: Limit::Adaptive { factor, min }, ..Self::fixed(min) }
+    }
+
+    /// EWMA smoothing factor in (0, 1], higher follows recent scans more closely (default: 0.2).
+    /// Scans over the limit do not feed the average, so a sustained spike does not become normal.
+    pub fn alpha(mut self, alpha: f64) -> Self {
+        self.alpha = alpha.clamp(f64::EPSILON, 1.0);
+        self
+    }
+
+    /// Count per subtree this many directories below the root instead of per root (default: 0).
+    /// With 1, `/srv/share/alice/x` and `/srv/share/bob/y` are separate groups of `/srv/share`.
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Scans to stay quiet after a spike fired for a group (default: 5).
+    pub fn cooldown(mut self, scans: u32) -> Self {
+        self.cooldown = scans;
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ScanCounts {
+    pub(crate) created: usize,
+    pub(crate) changed: usize,
+    pub(crate) removed: usize,
+}
+
+impl ScanCounts {
+    fn total(&self) -> usize {
+        self.created + self.changed + self.removed
+    }
+}
+
+#[derive(Default)]
+struct GroupState {
+    ewma: Option<f64>,
+    quiet_for: u32,
+}
+
+pub(crate) struct SpikeDetector {
+    cfg: SpikeConfig,
+    groups: HashMap<(PathBuf, PathBuf), GroupState>,
+}
+
+impl SpikeDetector {
+    pub(crate) fn new(cfg: SpikeConfig) -> Self {
+        Self { cfg, groups: HashMap::new() }
+    }
+
+    /// Group key of a file: its root and the first `depth` directories of `rel_path`.
+    pub(crate) fn group(&self, root: &Path, rel_path: &Path) -> (PathBuf, PathBuf) {
+        let dirs = rel_path.parent().map(|p| p.components().take(self.cfg.depth).collect()).unwrap_or_default();
+        (root.to_path_buf(), dirs)
+    }
+
+    /// Feed one scan's counts. Groups not in `counts` had no activity.
+    pub(crate) fn observe(&mut self, counts: &HashMap<(PathBuf, PathBuf), ScanCounts>, window: Duration) -> Vec<FileScreamEvent> {
+        for key in counts.keys() {
+            self.groups.entry(key.clone()).or_default();
+        }
+
+        let mut keys: Vec<_> = self.groups.keys().cloned().collect();
+        keys.sort();
+
+        let mut events = Vec::new();
+        for key in keys {
+            let c = counts.get(&key).copied().unwrap_or_default();
+            let st = self.groups.get_mut(&key).expect("group registered above");
+            let baseline = st.ewma.unwrap_or(0.0);
+            let limit = match self.cfg.limit {
+                Limit::Fixed(n) => n as f64,
+                Limit::Adaptive { factor, min } => (factor * baseline).max(min as f64),
+            };
+
+            st.quiet_for = st.quiet_for.saturating_sub(1);
+            let total = c.total();
+            if total as f64 > limit {
+                if st.quiet_for == 0 {
+                    st.quiet_for = self.cfg.cooldown + 1;
+                    let (root, subtree) = key;
+                    events.push(FileScreamEvent::ActivitySpike {
+                        root,
+                        subtree,
+                        window,
+                        created: c.created,
+                        changed: c.changed,
+                        removed: c.removed,
+                        baseline,
+                    });
+                }
+                continue;
+            }
+
+            st.ewma = Some(match st.ewma {
+                Some(avg) => avg + self.cfg.alpha * (total as f64 - avg),
+                None => total as f64,
+            });
+        }
+
+        events
+    }
+}
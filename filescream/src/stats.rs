@@ -0,0 +1,100 @@
+//! Where the scans spend their time and what the ignore patterns prune, for tuning ignore
+//! lists, see [`crate::FileScream::scan_stats`] and [`crate::FileScream::explain`].
+
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// One root's walk in the last completed scan. Nested roots are walked on their own and as
+/// part of the roots containing them, so an outer root's counts include the inner ones.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct RootStats {
+    pub dirs_visited: u64,
+    /// Directories whose mtime is the same as at the previous scan: nothing was added, removed
+    /// or renamed in them, only their files may have changed.
+    pub dirs_unchanged: u64,
+    /// Files whose metadata hash was taken. Content reads are counted in [`crate::content::ScanIoStats`].
+    pub files_hashed: u64,
+    /// Wall time of the walk, without content hashing.
+    pub elapsed: Duration,
+}
+
+/// What [`ScanStats`] holds.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ScanStatsSnapshot {
+    /// Completed scans since the sensor started or the last [`ScanStats::reset`].
+    pub scans: u64,
+    /// Per root, of the last completed scan.
+    pub roots: BTreeMap<String, RootStats>,
+    /// Entries pruned per ignore pattern over `scans`. Every valid pattern is listed, so one at
+    /// zero prunes nothing. An ignored directory counts once, its contents are never walked; an
+    /// entry matching several patterns counts for each.
+    pub patterns: BTreeMap<String, u64>,
+}
+
+/// Shared handle on the scan stats, see [`crate::FileScream::scan_stats`]. Cheap to clone.
+#[derive(Clone, Debug, Default)]
+pub struct ScanStats(Arc<Mutex<ScanStatsSnapshot>>);
+
+impl ScanStats {
+    pub fn snapshot(&self) -> ScanStatsSnapshot {
+        self.0.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+
+    /// Start counting pattern hits and scans from zero again.
+    pub fn reset(&self) {
+        if let Ok(mut s) = self.0.lock() {
+            *s = ScanStatsSnapshot::default();
+        }
+    }
+
+    /// Add a completed walk. `rules` are the ignore patterns `walk.hits` is indexed by;
+    /// patterns no longer in use are dropped.
+    pub(crate) fn record(&self, walk: WalkStats, rules: &[String]) {
+        let Ok(mut s) = self.0.lock() else {
+            return;
+        };
+        s.scans += 1;
+        s.roots = walk.roots.into_iter().map(|(root, stats)| (root.display().to_string(), stats)).collect();
+        s.patterns.retain(|p, _| rules.contains(p));
+        for (rule, hits) in rules.iter().zip(walk.hits) {
+            *s.patterns.entry(rule.clone()).or_insert(0) += hits;
+        }
+    }
+}
+
+/// Counters of one scan, filled by the walk.
+#[derive(Debug, Default)]
+pub(crate) struct WalkStats {
+    pub(crate) roots: Vec<(PathBuf, RootStats)>,
+    /// Per ignore rule, see [`crate::FileScream::scan`].
+    pub(crate) hits: Vec<u64>,
+}
+
+impl WalkStats {
+    pub(crate) fn new(rules: usize) -> Self {
+        Self { roots: Vec::new(), hits: vec![0; rules] }
+    }
+}
+
+/// Why a path is scanned or not, see [`crate::FileScream::explain`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "verdict", rename_all = "snake_case")]
+pub enum Explanation {
+    /// Scanned, its events are reported under `root`, the innermost watched root containing it.
+    /// A directory is walked, so its files are scanned unless they are ignored themselves.
+    Scanned { root: PathBuf },
+    /// Pruned by the walk of `root`: `at`, the path itself or one of its parent directories,
+    /// matches `patterns`.
+    Ignored { root: PathBuf, at: PathBuf, patterns: Vec<String> },
+    /// Every root containing it is unavailable, see [`crate::FileScreamConfig::mount_aware`].
+    Suspended { root: PathBuf },
+    /// Walked but not tracked: symlinks, sockets, devices and the like are skipped.
+    Untracked { root: PathBuf },
+    /// Under no watched root.
+    NotWatched,
+}
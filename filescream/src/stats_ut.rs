@@ -0,0 +1,106 @@
+use crate::{
+    FileScream,
+    stats::{Explanation, RootStats},
+};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+use tokio_util::sync::CancellationToken;
+
+/// A small source tree and an ignore list with a dead pattern:
+///
+/// ```text
+/// src/a.rs  src/b.rs  src/b.rs.swp   *.swp, b.rs.*
+/// target/debug/x.o                   target/
+/// docs/build                         a file, not pruned by build/
+/// out/build/y.o                      build/
+/// ```
+fn fixture(name: &str) -> (PathBuf, FileScream) {
+    let root = std::env::temp_dir().join(format!("filescream-stats-ut-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    for dir in ["src", "target/debug", "docs", "out/build"] {
+        std::fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    for file in ["src/a.rs", "src/b.rs", "src/b.rs.swp", "target/debug/x.o", "docs/build", "out/build/y.o"] {
+        std::fs::write(root.join(file), file).unwrap();
+    }
+    let root = root.canonicalize().unwrap();
+
+    let mut fs = FileScream::default();
+    fs.watch(&root).unwrap();
+    for pattern in ["*.swp", "b.rs.*", "target/", "build/", "*.log"] {
+        fs.ignore(pattern);
+    }
+    (root, fs)
+}
+
+fn hits(pairs: &[(&str, u64)]) -> BTreeMap<String, u64> {
+    pairs.iter().map(|(p, n)| (p.to_string(), *n)).collect()
+}
+
+#[tokio::test]
+async fn scans_count_pattern_hits_and_root_costs() {
+    let (root, mut fs) = fixture("hits");
+    let stats = fs.scan_stats();
+    let files = fs.scan_blocking(&CancellationToken::new()).await.unwrap();
+    assert_eq!(files.len(), 3);
+
+    let s = stats.snapshot();
+    assert_eq!(s.scans, 1);
+    assert_eq!(s.patterns, hits(&[("*.swp", 1), ("b.rs.*", 1), ("target/", 1), ("build/", 1), ("*.log", 0)]));
+    let r = s.roots[&root.display().to_string()];
+    // root, src, docs and out: target and out/build are pruned
+    assert_eq!(r, RootStats { dirs_visited: 4, dirs_unchanged: 0, files_hashed: 3, elapsed: r.elapsed });
+
+    // hits add up, root costs are the last scan's
+    fs.scan_blocking(&CancellationToken::new()).await.unwrap();
+    let s = stats.snapshot();
+    assert_eq!(s.scans, 2);
+    assert_eq!(s.patterns["target/"], 2);
+    assert_eq!(s.roots[&root.display().to_string()].dirs_unchanged, 4);
+
+    // a removed pattern is dropped, the others start over after a reset
+    fs.unignore("*.log");
+    stats.reset();
+    assert_eq!(stats.snapshot().scans, 0);
+    fs.scan_blocking(&CancellationToken::new()).await.unwrap();
+    assert_eq!(stats.snapshot().patterns, hits(&[("*.swp", 1), ("b.rs.*", 1), ("target/", 1), ("build/", 1)]));
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn explain_names_the_root_or_the_deciding_patterns() {
+    let (root, mut fs) = fixture("explain");
+    let ignored = |at: &str, patterns: &[&str]| Explanation::Ignored {
+        root: root.clone(),
+        at: root.join(at),
+        patterns: patterns.iter().map(|p| p.to_string()).collect(),
+    };
+    let scanned = Explanation::Scanned { root: root.clone() };
+
+    assert_eq!(fs.explain(root.join("src/a.rs")), scanned);
+    assert_eq!(fs.explain(root.join("src/not-yet.rs")), scanned, "missing files are explained too");
+    assert_eq!(fs.explain(root.join("src/b.rs.swp")), ignored("src/b.rs.swp", &["*.swp", "b.rs.*"]));
+    assert_eq!(fs.explain(root.join("target/debug/x.o")), ignored("target", &["target/"]));
+    assert_eq!(fs.explain(root.join("docs/build")), scanned, "build/ only prunes directories");
+    assert_eq!(fs.explain(root.join("out/build/y.o")), ignored("out/build", &["build/"]));
+    assert_eq!(fs.explain(root.join("src/../src/a.rs")), scanned, "paths are canonicalized like roots");
+    assert_eq!(fs.explain(Path::new("/nonexistent/a.rs")), Explanation::NotWatched);
+
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(root.join("src/a.rs"), root.join("src/link.rs")).unwrap();
+        assert_eq!(fs.explain(root.join("src/link.rs")), Explanation::Untracked { root: root.clone() });
+    }
+
+    // a nested root owns its files, and is pruned like any other directory
+    fs.watch(root.join("docs")).unwrap();
+    assert_eq!(fs.explain(root.join("docs/build")), Explanation::Scanned { root: root.join("docs") });
+    fs.watch(root.join("out/build")).unwrap();
+    assert_eq!(
+        fs.explain(root.join("out/build/y.o")),
+        Explanation::Ignored { root: root.join("out/build"), at: root.join("out/build"), patterns: vec!["build/".to_string()] }
+    );
+    let _ = std::fs::remove_dir_all(&root);
+}
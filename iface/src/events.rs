@@ -1,4 +1,8 @@
 use bitflags::bitflags;
+use omnitrace_core::{
+    fields::{EventFields, FieldValue},
+    topics::{self, Topic, Topics},
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -34,4 +38,67 @@ impl IfaceEvent {
             IfaceEvent::AddrRemoved { .. } => IfaceMask::ADDR_REMOVED,
         }
     }
+
+    /// Dotted topic, see [`omnitrace_core::topics`].
+    pub fn topic(&self) -> &'static str {
+        topics::topic_of(self)
+    }
+}
+
+impl Topics for IfaceEvent {
+    const TOPICS: &'static [Topic] = &[
+        Topic::new("iface.added", IfaceMask::IFACE_ADDED.bits()),
+        Topic::new("iface.removed", IfaceMask::IFACE_REMOVED.bits()),
+        Topic::new("iface.link.up", IfaceMask::LINK_UP.bits()),
+        Topic::new("iface.link.down", IfaceMask::LINK_DOWN.bits()),
+        Topic::new("iface.addr.added", IfaceMask::ADDR_ADDED.bits()),
+        Topic::new("iface.addr.removed", IfaceMask::ADDR_REMOVED.bits()),
+    ];
+
+    fn topic_index(&self) -> usize {
+        match self {
+            IfaceEvent::IfaceAdded { .. } => 0,
+            IfaceEvent::IfaceRemoved { .. } => 1,
+            IfaceEvent::LinkUp { .. } => 2,
+            IfaceEvent::LinkDown { .. } => 3,
+            IfaceEvent::AddrAdded { .. } => 4,
+            IfaceEvent::AddrRemoved { .. } => 5,
+        }
+    }
+}
+
+/// `ifindex` and `ifname`.
+impl EventFields for IfaceEvent {
+    fn kind(&self) -> &'static str {
+        match self {
+            IfaceEvent::IfaceAdded { .. } => "iface_added",
+            IfaceEvent::IfaceRemoved { .. } => "iface_removed",
+            IfaceEvent::LinkUp { .. } => "link_up",
+            IfaceEvent::LinkDown { .. } => "link_down",
+            IfaceEvent::AddrAdded { .. } => "addr_added",
+            IfaceEvent::AddrRemoved { .. } => "addr_removed",
+        }
+    }
+
+    fn field(&self, name: &str) -> Option<FieldValue<'_>> {
+        let (IfaceEvent::IfaceAdded { ifindex, ifname }
+        | IfaceEvent::IfaceRemoved { ifindex, ifname }
+        | IfaceEvent::LinkUp { ifindex, ifname }
+        | IfaceEvent::LinkDown { ifindex, ifname }
+        | IfaceEvent::AddrAdded { ifindex, ifname }
+        | IfaceEvent::AddrRemoved { ifindex, ifname }) = self;
+        match name {
+            "ifindex" => Some(FieldValue::int(*ifindex)),
+            "ifname" => Some(FieldValue::str(ifname)),
+            _ => None,
+        }
+    }
+
+    fn field_names(&self) -> &'static [&'static str] {
+        &["ifindex", "ifname"]
+    }
+
+    fn topic(&self) -> Option<&'static str> {
+        Some(IfaceEvent::topic(self))
+    }
 }
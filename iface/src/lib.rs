@@ -1,5 +1,6 @@
 pub mod backends;
 pub mod events;
+pub mod prelude;
 
 use crate::events::IfaceEvent;
 use omnitrace_core::{
@@ -74,12 +75,13 @@ impl Iface {
         self.backend = Box::new(backend);
     }
 
-    async fn fire(hub: &CallbackHub<IfaceEvent>, ev: IfaceEvent) {
+    async fn fire<R: Send + 'static>(hub: &CallbackHub<IfaceEvent, R>, ev: IfaceEvent) {
         hub.fire(ev.mask().bits(), &ev).await;
     }
 
-    pub async fn run(mut self, ctx: SensorCtx<IfaceEvent>) {
+    pub async fn run<R: Send + 'static>(mut self, ctx: SensorCtx<IfaceEvent, R>) {
         loop {
+            ctx.beat();
             if ctx.cancel.is_cancelled() {
                 break;
             }
@@ -96,7 +98,7 @@ impl Iface {
 impl Sensor for Iface {
     type Event = IfaceEvent;
 
-    fn run(self, ctx: SensorCtx<Self::Event>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+    fn run<R: Send + 'static>(self, ctx: SensorCtx<Self::Event, R>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
         Box::pin(async move { Iface::run(self, ctx).await })
     }
 }
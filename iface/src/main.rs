@@ -1,8 +1,9 @@
-use async_trait::async_trait;
-use iface::events::{IfaceEvent, IfaceMask};
-use iface::{Iface, IfaceConfig};
-use omnitrace_core::callbacks::{Callback, CallbackHub, CallbackResult};
-use omnitrace_core::sensor::spawn_sensor;
+use iface::prelude::*;
+use omnitrace_core::{
+    filter::Filter,
+    format::Style,
+    preflight::{self, PreflightArgs},
+};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc::channel;
@@ -12,25 +13,25 @@ struct PrintCb;
 #[async_trait]
 impl Callback<IfaceEvent> for PrintCb {
     fn mask(&self) -> u64 {
-        (
-            IfaceMask::IFACE_ADDED
-                | IfaceMask::IFACE_REMOVED
-                | IfaceMask::LINK_UP
-                | IfaceMask::LINK_DOWN
-                | IfaceMask::ADDR_ADDED
-                | IfaceMask::ADDR_REMOVED
-        )
-        .bits()
+        (IfaceMask::IFACE_ADDED
+            | IfaceMask::IFACE_REMOVED
+            | IfaceMask::LINK_UP
+            | IfaceMask::LINK_DOWN
+            | IfaceMask::ADDR_ADDED
+            | IfaceMask::ADDR_REMOVED)
+            .bits()
     }
 
     async fn call(&self, ev: &IfaceEvent) -> Option<CallbackResult> {
-        println!("{ev:?}");
+        println!("{}", Style::for_stdout().short(ev));
         None
     }
 }
 
 #[tokio::main]
 async fn main() {
+    let filter = Filter::from_env_args();
+
     // Demo:
     // 1) Run: cargo run -p iface
     //
@@ -51,11 +52,16 @@ async fn main() {
     //   ifconfig lo1 destroy
     let sensor = Iface::new(Some(IfaceConfig::default().poll_timeout(Duration::from_millis(250))));
 
+    preflight::gate("iface", &sensor, PreflightArgs::from_env_args()).await;
+
     let (tx, mut rx) = channel::<CallbackResult>(0xfff);
 
     let mut hub = CallbackHub::<IfaceEvent>::new();
     hub.add(PrintCb);
     hub.set_result_channel(tx);
+    if let Some(f) = &filter {
+        hub.set_filter(f.predicate());
+    }
     let hub = Arc::new(hub);
 
     let rx_task = tokio::spawn(async move {
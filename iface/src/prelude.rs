@@ -0,0 +1,6 @@
+//! `use iface::prelude::*;` brings in the Iface sensor, its config and events,
+//! and the core prelude (callbacks, hub, `spawn_sensor`).
+
+pub use crate::events::{IfaceEvent, IfaceMask};
+pub use crate::{Iface, IfaceConfig};
+pub use omnitrace_core::prelude::*;
@@ -0,0 +1,108 @@
+//! Allocations per tick of the mount and connection table parsers over a large synthetic
+//! host: 5000 container mounts (the k8s node fixture, repeated) and 20000 connections to
+//! a few hundred remote hosts.
+//!
+//! `cargo bench -p omnitrace-loadgen --bench alloc [-- <ticks>]`
+
+use netpacket::{NetNotify, NetNotifyConfig};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fmt::Write,
+    hint::black_box,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+use xmount::{XMount, XMountConfig};
+
+struct Counting;
+
+static ALLOCS: AtomicU64 = AtomicU64::new(0);
+static BYTES: AtomicU64 = AtomicU64::new(0);
+
+// SAFETY: forwards to the system allocator, only counting
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn mountinfo(dir: &Path) -> std::path::PathBuf {
+    let fixture = std::fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("../xmount/fixtures/k8s-node.mountinfo")).unwrap();
+    let lines: Vec<&str> = fixture.lines().collect();
+    let mut out = String::new();
+    for i in 0..5000 {
+        let line = lines[i % lines.len()];
+        // unique ids and mount points, as on a node running many pods
+        let (_, rest) = line.split_once(' ').unwrap();
+        let (_, rest) = rest.split_once(' ').unwrap();
+        let (majmin, rest) = rest.split_once(' ').unwrap();
+        let (root, rest) = rest.split_once(' ').unwrap();
+        let (mp, rest) = rest.split_once(' ').unwrap();
+        let _ = writeln!(out, "{} 1 {majmin} {root} {}/{i} {rest}", 100 + i, mp.trim_end_matches('/'));
+    }
+    let path = dir.join("mountinfo");
+    std::fs::write(&path, out).unwrap();
+    path
+}
+
+fn tcp_tables(dir: &Path) {
+    let mut tcp = String::from("  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n");
+    for i in 0..20000u32 {
+        let state = ["01", "01", "01", "06", "0A", "08"][i as usize % 6];
+        let _ = writeln!(
+            tcp,
+            "{i:4}: 0500000A:{:04X} {:08X}:01BB {state} 00000000:00000000 02:0000A1F2 00000000  1000        0 {} 1 0000000000000000 20 4 30 10 -1",
+            1024 + i % 60000,
+            0x5D00_0000u32 + i % 300,
+            40000 + i,
+        );
+    }
+    std::fs::write(dir.join("tcp"), tcp).unwrap();
+}
+
+fn measure(name: &str, ticks: u64, lines: u64, mut tick: impl FnMut() -> usize) {
+    black_box(tick());
+    let (a0, b0, t0) = (ALLOCS.load(Ordering::Relaxed), BYTES.load(Ordering::Relaxed), Instant::now());
+    for _ in 0..ticks {
+        black_box(tick());
+    }
+    let (allocs, bytes) = (ALLOCS.load(Ordering::Relaxed) - a0, BYTES.load(Ordering::Relaxed) - b0);
+    println!(
+        "{name:<10} {:>9.1} allocs/tick  {:>6.2} allocs/line  {:>9} KiB/tick  {:>8.2?}/tick",
+        allocs as f64 / ticks as f64,
+        allocs as f64 / (ticks * lines) as f64,
+        bytes / ticks / 1024,
+        t0.elapsed() / ticks as u32,
+    );
+}
+
+fn main() {
+    let ticks: u64 = std::env::args().skip(1).find_map(|a| a.parse().ok()).unwrap_or(20);
+    let dir = std::env::temp_dir().join(format!("omnitrace-alloc-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut xm = XMount::new(XMountConfig::default().mountinfo_path(mountinfo(&dir)));
+    measure("mountinfo", ticks, 5000, || xm.snapshot().unwrap().len());
+
+    tcp_tables(&dir);
+    let mut nn = NetNotify::new(Some(NetNotifyConfig::default().proc_net(&dir)));
+    measure("tcp", ticks, 20000, || nn.snapshot().len());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,84 @@
+//! CPU cost of sink compression over a high-volume synthetic stream: batch bodies as a
+//! webhook would send them (`Compression::encode`), and the audit log appending record by
+//! record, plain and compressed.
+//!
+//! `cargo bench -p omnitrace-loadgen --bench compress [-- <events>]`
+
+use omnitrace_core::{
+    audit::AuditSink,
+    compress::{Compression, Encoded},
+};
+use omnitrace_loadgen::stream::Synthetic;
+use serde_json::Value;
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+use xmount::events::XMountEvent;
+
+const BATCH: usize = 500;
+
+fn events(n: u64) -> Vec<Value> {
+    (0..n)
+        .map(|i| serde_json::to_value(XMountEvent::synth((i % 1000) as u32, i / 1000, &format!("entity-{}", i % 1000))).unwrap_or_default())
+        .collect()
+}
+
+fn batches(events: &[Value]) -> Vec<Vec<u8>> {
+    events.chunks(BATCH).map(|c| c.iter().flat_map(|ev| format!("{ev}\n").into_bytes()).collect()).collect()
+}
+
+fn mb_per_s(bytes: usize, d: Duration) -> f64 {
+    bytes as f64 / d.as_secs_f64().max(f64::EPSILON) / 1e6
+}
+
+fn bench_batches(name: &str, c: Compression, batches: &[Vec<u8>]) {
+    let raw: usize = batches.iter().map(Vec::len).sum();
+    let start = Instant::now();
+    let out: usize = batches.iter().map(|b| black_box(c.encode(b.clone()).map(|Encoded { body, .. }| body.len()).unwrap_or(0))).sum();
+    let took = start.elapsed();
+    println!("batch {name:<8} {:>7.1} MB/s   {:>5.1}% of {raw} bytes", mb_per_s(raw, took), 100.0 * out as f64 / raw as f64);
+}
+
+fn bench_audit(name: &str, c: Option<Compression>, events: &[Value]) {
+    let dir = std::env::temp_dir().join(format!("omnitrace-bench-compress-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let _ = std::fs::create_dir_all(&dir);
+    let log = dir.join("audit.jsonl");
+    let mut sink = AuditSink::open(&log).expect("temp log").compression(c);
+
+    let start = Instant::now();
+    for ev in events {
+        sink.append(ev).expect("append");
+    }
+    let took = start.elapsed();
+    drop(sink);
+    let size = std::fs::metadata(&log).map(|m| m.len()).unwrap_or(0);
+    println!("audit {name:<8} {:>7.1} us/ev   {size:>10} bytes on disk", took.as_secs_f64() * 1e6 / events.len() as f64);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`
+    let n = std::env::args().skip(1).find_map(|a| a.parse().ok()).unwrap_or(100_000);
+    let events = events(n);
+    let batches = batches(&events);
+    let levels = [
+        ("zstd:1", Compression::zstd().level(1)),
+        ("zstd:3", Compression::zstd()),
+        ("zstd:9", Compression::zstd().level(9)),
+        ("gzip:1", Compression::gzip().level(1)),
+        ("gzip:6", Compression::gzip()),
+    ];
+
+    bench_batches("identity", Compression::zstd().threshold(usize::MAX), &batches);
+    for (name, c) in levels {
+        bench_batches(name, c, &batches);
+    }
+
+    let few = &events[..events.len().min(20_000)];
+    bench_audit("plain", None, few);
+    for (name, c) in levels {
+        bench_audit(name, Some(c), few);
+    }
+}
@@ -0,0 +1,53 @@
+//! Filter evaluation over a high-volume synthetic stream: reading fields off the typed events
+//! (`Filter::matches_fields`) against serializing each one first (`Filter::matches_event`).
+//!
+//! `cargo bench -p omnitrace-loadgen --bench filter [-- <events per type>]`
+
+use filescream::events::FileScreamEvent;
+use netpacket::events::NetNotifyEvent;
+use omnitrace_core::filter::Filter;
+use omnitrace_loadgen::stream::Synthetic;
+use procdog::events::ProcDogEvent;
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+use xmount::events::XMountEvent;
+
+/// `n` events about 1000 entities, as a mock sensor would emit them.
+fn stream<E: Synthetic>(n: u64) -> Vec<E> {
+    (0..n).map(|i| E::synth((i % 1000) as u32, i / 1000, &format!("entity-{}", i % 1000))).collect()
+}
+
+fn time<F: FnMut() -> usize>(mut f: F) -> (Duration, usize) {
+    let start = Instant::now();
+    let kept = f();
+    (start.elapsed(), kept)
+}
+
+fn bench<E: Synthetic>(sensor: &str, expr: &str, n: u64) {
+    let events = stream::<E>(n);
+    let filter = Filter::parse(expr).unwrap_or_else(|e| panic!("{expr}: {e}"));
+
+    let (typed, kept) = time(|| events.iter().filter(|ev| filter.matches_fields(black_box(*ev))).count());
+    let (json, kept_json) = time(|| events.iter().filter(|ev| filter.matches_event(black_box(*ev))).count());
+    assert_eq!(kept, kept_json, "{sensor}: both paths must agree");
+
+    let per = |d: Duration| d.as_nanos() as f64 / events.len() as f64;
+    println!(
+        "{sensor:<10} {:>8} events, {kept:>7} kept   fields {:>7.1} ns/ev   serde_json {:>7.1} ns/ev   {:>5.1}x",
+        events.len(),
+        per(typed),
+        per(json),
+        json.as_secs_f64() / typed.as_secs_f64().max(f64::EPSILON)
+    );
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`
+    let n = std::env::args().skip(1).find_map(|a| a.parse().ok()).unwrap_or(200_000);
+    bench::<XMountEvent>("xmount", r#"kind == "Mounted" && fstype == "ext4" && target ~ "/mnt/load/entity-1*""#, n);
+    bench::<ProcDogEvent>("procdog", r#"kind == "Appeared" && !(name ~ "entity-9*") && pid > 10100"#, n);
+    bench::<NetNotifyEvent>("netpacket", r#"remote_host ~ "entity-4*" || state_dec != "ESTABLISHED""#, n);
+    bench::<FileScreamEvent>("filescream", r#"kind == "Changed" && rel_path ~ "entity-5*""#, n);
+}
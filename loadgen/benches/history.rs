@@ -0,0 +1,68 @@
+//! Cost per event of recording the event history (`History::record`, published every 1000
+//! events as a tick would) against history off, the default, over a high-volume synthetic
+//! stream about 1000 entities.
+//!
+//! `cargo bench -p omnitrace-loadgen --bench history [-- <events per type>]`
+
+use filescream::events::FileScreamEvent;
+use netpacket::events::NetNotifyEvent;
+use omnitrace_core::history::{History, HistoryConfig};
+use omnitrace_loadgen::stream::Synthetic;
+use procdog::events::ProcDogEvent;
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+use xmount::events::XMountEvent;
+
+/// `n` events about 1000 entities with their keys, as a mock sensor would emit them.
+fn stream<E: Synthetic>(n: u64) -> Vec<(String, E)> {
+    (0..n)
+        .map(|i| {
+            let entity = format!("entity-{}", i % 1000);
+            let ev = E::synth((i % 1000) as u32, i / 1000, &entity);
+            (entity, ev)
+        })
+        .collect()
+}
+
+fn record<E: Synthetic + Clone>(history: Option<&History<E>>, events: &[(String, E)]) -> Duration {
+    let start = Instant::now();
+    for (i, (key, ev)) in events.iter().enumerate() {
+        if let Some(h) = black_box(history) {
+            h.record([key], ev);
+            if i % 1000 == 999 {
+                h.publish();
+            }
+        }
+    }
+    start.elapsed()
+}
+
+fn bench<E: Synthetic + Clone>(sensor: &str, n: u64) {
+    let events = stream::<E>(n);
+    let history = History::new(HistoryConfig::default());
+
+    let off = record(None, &events);
+    let on = record(Some(&history), &events);
+    history.publish();
+    let kept = history.handle().len();
+
+    let per = |d: Duration| d.as_nanos() as f64 / events.len() as f64;
+    println!(
+        "{sensor:<10} {:>8} events, {kept:>5} kept   off {:>6.1} ns/ev   on {:>7.1} ns/ev   {:>7} bytes",
+        events.len(),
+        per(off),
+        per(on),
+        history.bytes()
+    );
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`
+    let n = std::env::args().skip(1).find_map(|a| a.parse().ok()).unwrap_or(200_000);
+    bench::<XMountEvent>("xmount", n);
+    bench::<ProcDogEvent>("procdog", n);
+    bench::<NetNotifyEvent>("netpacket", n);
+    bench::<FileScreamEvent>("filescream", n);
+}
@@ -0,0 +1,56 @@
+//! Cost of a tick of several ProcDog instances listing `/proc`: each reading it on its own
+//! against sharing one [`SharedBackend`] listing per tick.
+//!
+//! `cargo bench -p omnitrace-loadgen --bench shared [-- <ticks>]`
+
+use procdog::{
+    ProcBackend,
+    backends::{linuxps::LinuxPsBackend, shared::SharedBackend},
+};
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+/// Time `ticks` ticks of `instances` listing through `backend`, with `between` run before each.
+async fn time<B: ProcBackend>(backend: &B, instances: usize, ticks: u32, between: impl AsyncFn()) -> Duration {
+    let mut total = Duration::ZERO;
+    for _ in 0..ticks {
+        between().await;
+        let start = Instant::now();
+        for _ in 0..instances {
+            black_box(backend.list().await.unwrap());
+        }
+        total += start.elapsed();
+    }
+    total / ticks
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    // `cargo bench` passes `--bench`
+    let ticks = std::env::args().skip(1).find_map(|a| a.parse().ok()).unwrap_or(50);
+    if !LinuxPsBackend::available() {
+        println!("no /proc, nothing to measure");
+        return;
+    }
+
+    let own = LinuxPsBackend::default();
+    // a base long enough that only the invalidation before each tick starts a new one
+    let shared = SharedBackend::new(LinuxPsBackend::default(), Duration::from_secs(3600));
+    let mut first = None;
+    for instances in [1, 2, 4] {
+        let separate = time(&own, instances, ticks, async || {}).await;
+        let together = time(&shared, instances, ticks, async || shared.source().invalidate().await).await;
+        let (one, one_shared) = *first.get_or_insert((separate, together));
+        let us = |d: Duration| d.as_secs_f64() * 1e6;
+        let extra = |d: Duration, one: Duration| us(d.saturating_sub(one)) / (instances - 1).max(1) as f64;
+        println!(
+            "{instances} instances   separate {:>9.1} us/tick ({:>8.1} per extra)   shared {:>9.1} us/tick ({:>8.1} per extra)",
+            us(separate),
+            extra(separate, one),
+            us(together),
+            extra(together, one_shared),
+        );
+    }
+}
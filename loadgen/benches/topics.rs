@@ -0,0 +1,88 @@
+//! Hub dispatch by topic subscription (`CallbackHub::subscribe`) against raw masks
+//! (`CallbackHub::add`), with the same selections, over a high-volume synthetic stream.
+//!
+//! `cargo bench -p omnitrace-loadgen --bench topics [-- <events per type>]`
+
+use async_trait::async_trait;
+use netpacket::events::NetNotifyEvent;
+use omnitrace_core::{
+    callbacks::{Callback, CallbackHub, CallbackResult},
+    topics::{self, Topics},
+};
+use omnitrace_loadgen::stream::Synthetic;
+use procdog::events::ProcDogEvent;
+use std::{
+    hint::black_box,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use xmount::events::XMountEvent;
+
+/// `n` events about 1000 entities, as a mock sensor would emit them.
+fn stream<E: Synthetic>(n: u64) -> Vec<E> {
+    (0..n).map(|i| E::synth((i % 1000) as u32, i / 1000, &format!("entity-{}", i % 1000))).collect()
+}
+
+struct Count(u64, Arc<AtomicU64>);
+
+#[async_trait]
+impl<E: Sync> Callback<E> for Count {
+    fn mask(&self) -> u64 {
+        self.0
+    }
+
+    async fn call(&self, _ev: &E) -> Option<CallbackResult> {
+        self.1.fetch_add(1, Ordering::Relaxed);
+        None
+    }
+}
+
+/// Fire every event through `hub`, returning the time taken and the callbacks called.
+async fn fire<E: Synthetic>(hub: &CallbackHub<E>, events: &[E], calls: &AtomicU64) -> (Duration, u64) {
+    calls.store(0, Ordering::Relaxed);
+    let start = Instant::now();
+    for ev in events {
+        hub.fire(ev.mask_bits(), black_box(ev)).await;
+    }
+    (start.elapsed(), calls.load(Ordering::Relaxed))
+}
+
+async fn bench<E: Synthetic + Topics>(sensor: &str, patterns: &[&str], n: u64) {
+    let events = stream::<E>(n);
+    let calls = Arc::new(AtomicU64::new(0));
+    let (mut masked, mut subscribed) = (CallbackHub::new(), CallbackHub::new());
+    for p in patterns {
+        let mask = topics::mask::<E>(p).unwrap_or_else(|e| panic!("{p}: {e}"));
+        masked.add(Count(mask, calls.clone()));
+        subscribed.subscribe(p, Count(0, calls.clone())).unwrap_or_else(|e| panic!("{p}: {e}"));
+    }
+
+    let (by_mask, called) = fire(&masked, &events, &calls).await;
+    let (by_topic, called_topics) = fire(&subscribed, &events, &calls).await;
+    assert_eq!(called, called_topics, "{sensor}: both hubs must call the same callbacks");
+
+    let per = |d: Duration| d.as_nanos() as f64 / events.len() as f64;
+    println!(
+        "{sensor:<10} {:>8} events, {} callbacks, {called:>8} calls   mask {:>6.1} ns/ev   topics {:>6.1} ns/ev   {:>5.2}x",
+        events.len(),
+        patterns.len(),
+        per(by_mask),
+        per(by_topic),
+        by_topic.as_secs_f64() / by_mask.as_secs_f64().max(f64::EPSILON)
+    );
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    // `cargo bench` passes `--bench`
+    let n = std::env::args().skip(1).find_map(|a| a.parse().ok()).unwrap_or(200_000);
+    let mount = ["mount.mounted", "mount.unmounted", "mount.changed", "mount.health", "mount.*", "*.armed", "mount.will_unmount", "mount"];
+    bench::<XMountEvent>("xmount", &mount, n).await;
+    let proc = ["proc.appeared", "proc.disappeared", "proc.missing", "proc.*", "{proc.missing,proc.disappeared}", "*.appeared", "proc", "proc.app*"];
+    bench::<ProcDogEvent>("procdog", &proc, n).await;
+    let net = ["net.conn", "net.conn.opened", "net.conn.closed", "net.watermark", "net.backlog", "net.summary", "*.spike", "net"];
+    bench::<NetNotifyEvent>("netpacket", &net, n).await;
+}
@@ -0,0 +1,166 @@
+//! Runs a scenario: mock sensors into real hubs, a router and sinks.
+
+use crate::{
+    metrics::{self, Drops, Histogram, Report, SinkReport, StreamReport},
+    scenario::{Scenario, SensorKind, SinkSpec, StreamSpec},
+    stream::{MockSensor, StreamStats, Synthetic},
+};
+use filescream::events::FileScreamEvent;
+use netpacket::events::NetNotifyEvent;
+use omnitrace_core::{
+    audit::AuditSink,
+    callbacks::{CallbackHub, CallbackResult},
+    router::Router,
+    sensor::{SensorHandle, spawn_sensor},
+};
+use procdog::events::ProcDogEvent;
+use std::{
+    sync::{Arc, atomic::Ordering},
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::mpsc::{self, Receiver},
+    task::JoinHandle,
+};
+use xmount::events::XMountEvent;
+
+struct Running {
+    spec: StreamSpec,
+    handle: SensorHandle,
+    task: JoinHandle<()>,
+    stats: Arc<StreamStats>,
+}
+
+fn spawn_stream<E: Synthetic>(spec: &StreamSpec, router: &Arc<Router>) -> Running {
+    let mut hub = CallbackHub::<E>::new();
+    hub.add(router.callback(&spec.name(), E::mask_bits));
+    let sensor = MockSensor::<E>::new(spec.clone());
+    let stats = sensor.stats();
+    let (handle, task) = spawn_sensor(sensor, Arc::new(hub));
+    Running { spec: spec.clone(), handle, task, stats }
+}
+
+fn spawn_sink(spec: SinkSpec, mut rx: Receiver<CallbackResult>) -> JoinHandle<SinkReport> {
+    tokio::spawn(async move {
+        let mut report = SinkReport { name: spec.name.clone(), ..Default::default() };
+        let mut audit = match &spec.path {
+            Some(path) => match AuditSink::open(path) {
+                Ok(sink) => Some(sink),
+                Err(e) => {
+                    log::error!("loadgen: cannot open sink {} at {}: {e}", spec.name, path.display());
+                    None
+                }
+            },
+            None => None,
+        };
+        let failing = spec.path.is_some() && audit.is_none();
+
+        while let Some(ev) = rx.recv().await {
+            if spec.delay_us > 0 {
+                tokio::time::sleep(Duration::from_micros(spec.delay_us)).await;
+            }
+            let ok = match &mut audit {
+                Some(sink) => sink.append(&ev).is_ok(),
+                None => !failing && serde_json::to_vec(&ev).is_ok(),
+            };
+            if ok {
+                report.written += 1;
+            } else {
+                report.failed += 1;
+            }
+        }
+        report
+    })
+}
+
+/// Run `scenario` for its duration and report what the pipeline achieved.
+pub async fn run(scenario: &Scenario) -> Report {
+    run_for(scenario, scenario.duration()).await
+}
+
+/// Run `scenario` for `duration` instead of its own.
+pub async fn run_for(scenario: &Scenario, duration: Duration) -> Report {
+    let mut router = match &scenario.router {
+        Some(cfg) => Router::from_config(cfg.clone()),
+        None => Router::new(),
+    };
+    let sinks = scenario.sinks();
+    let mut sink_tasks = Vec::new();
+    for spec in sinks.iter().cloned() {
+        let (tx, rx) = mpsc::channel(spec.capacity.max(1));
+        router.add_sink(spec.name.clone(), tx);
+        sink_tasks.push(spawn_sink(spec, rx));
+    }
+    if scenario.router.is_none() {
+        router.set_default(sinks.iter().map(|s| s.name.clone()).collect());
+    }
+    for name in router.unknown_sinks() {
+        log::warn!("loadgen: routes name unknown sink {name}");
+    }
+    let router = Arc::new(router);
+
+    let start = Instant::now();
+    let streams: Vec<Running> = scenario
+        .streams
+        .iter()
+        .map(|spec| match spec.sensor {
+            SensorKind::XMount => spawn_stream::<XMountEvent>(spec, &router),
+            SensorKind::ProcDog => spawn_stream::<ProcDogEvent>(spec, &router),
+            SensorKind::NetPacket => spawn_stream::<NetNotifyEvent>(spec, &router),
+            SensorKind::FileScream => spawn_stream::<FileScreamEvent>(spec, &router),
+        })
+        .collect();
+
+    tokio::time::sleep(duration).await;
+    for s in &streams {
+        s.handle.shutdown();
+    }
+    let mut reports = Vec::new();
+    let mut all = Histogram::default();
+    let mut drops = Drops::default();
+    for s in streams {
+        let _ = s.task.await;
+        let hist = s.stats.latency.lock().map(|h| h.clone()).unwrap_or_default();
+        all.merge(&hist);
+        let skipped = s.stats.skipped.load(Ordering::Relaxed);
+        drops.generator += skipped;
+        reports.push(StreamReport {
+            name: s.spec.name(),
+            target_per_sec: s.spec.rate_per_sec,
+            emitted: s.stats.emitted.load(Ordering::Relaxed),
+            achieved_per_sec: 0.0,
+            skipped,
+            latency: hist.summary(),
+        });
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    for r in &mut reports {
+        r.achieved_per_sec = r.emitted as f64 / elapsed;
+    }
+
+    // the hubs are gone with their sensors; dropping the router closes the sink channels
+    let routes = router.stats();
+    drops.router = routes.iter().map(|r| r.dropped).sum();
+    drop(router);
+    let mut sink_reports = Vec::new();
+    for t in sink_tasks {
+        if let Ok(r) = t.await {
+            drops.sink += r.failed;
+            sink_reports.push(r);
+        }
+    }
+
+    let emitted = reports.iter().map(|r| r.emitted).sum();
+    Report {
+        scenario: scenario.name.clone(),
+        elapsed_secs: elapsed,
+        emitted,
+        achieved_per_sec: emitted as f64 / elapsed,
+        latency: all.summary(),
+        drops,
+        streams: reports,
+        sinks: sink_reports,
+        routes,
+        peak_rss_bytes: metrics::peak_rss(),
+    }
+}
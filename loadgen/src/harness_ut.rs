@@ -0,0 +1,69 @@
+use crate::{harness, scenario::Scenario};
+use omnitrace_core::audit;
+use std::{path::Path, time::Duration};
+
+#[tokio::test]
+async fn small_scenario_completes_without_drops() {
+    let log = std::env::temp_dir().join(format!("omnitrace-loadgen-ut-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&log);
+    let scenario = Scenario::parse(&format!(
+        r#"{{
+            "name": "smoke",
+            "duration_secs": 0.3,
+            "streams": [
+                {{ "sensor": "netpacket", "rate_per_sec": 500, "keys": 50 }},
+                {{ "sensor": "filescream", "rate_per_sec": 1000, "burst": 100, "keys": 300, "payload_bytes": 64 }},
+                {{ "sensor": "xmount", "rate_per_sec": 40, "keys": 2 }},
+                {{ "sensor": "procdog", "rate_per_sec": 40, "keys": 3 }}
+            ],
+            "sinks": [{{ "name": "audit", "path": {:?} }}, {{ "name": "null" }}]
+        }}"#,
+        log
+    ))
+    .unwrap();
+
+    let report = harness::run(&scenario).await;
+    assert_eq!(report.drops.total(), 0, "{report:#?}");
+    assert_eq!(report.streams.len(), 4);
+    assert!(report.streams.iter().all(|s| s.emitted > 0), "{report:#?}");
+    // every event reached both sinks, and the audit log holds them all
+    assert!(report.sinks.iter().all(|s| s.written == report.emitted), "{report:#?}");
+    assert_eq!(audit::verify(&log).unwrap().records as u64, report.emitted);
+    assert!(report.latency.p99_us <= report.latency.max_us);
+
+    let _ = std::fs::remove_file(&log);
+}
+
+#[tokio::test]
+async fn drop_routes_and_slow_schedules_count_as_drops() {
+    let scenario = Scenario::parse(
+        r#"{
+            "duration_secs": 10,
+            "streams": [{ "sensor": "procdog", "rate_per_sec": 200, "keys": 4, "max_lag_ms": 50 }],
+            "sinks": [{ "name": "slow", "capacity": 1, "delay_us": 20000 }],
+            "router": { "routes": [{ "name": "drop-odd", "sensor": "procdog", "mask": 2 }], "default": ["slow"] }
+        }"#,
+    )
+    .unwrap();
+
+    let report = harness::run_for(&scenario, Duration::from_millis(300)).await;
+    let dropped: u64 = report.routes.iter().filter(|r| r.rule == "drop-odd").map(|r| r.dropped).sum();
+    assert!(dropped > 0 && report.drops.router == dropped, "{report:#?}");
+    // a 20ms sink cannot take 100 events/s: the stream falls behind and gives ticks up
+    assert!(report.drops.generator > 0, "{report:#?}");
+    assert!(report.latency.max_us >= 10_000, "{report:#?}");
+}
+
+#[test]
+fn example_scenarios_parse() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios");
+    let mut n = 0;
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        Scenario::load(&path).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+        n += 1;
+    }
+    assert!(n >= 2);
+    assert!(Scenario::parse(r#"{ "duration_secs": 1, "streams": [] }"#).is_err());
+    assert!(Scenario::parse(r#"{ "duration_secs": 1, "streams": [{ "sensor": "procdog", "rate_per_sec": 0 }] }"#).is_err());
+}
@@ -0,0 +1,280 @@
+//! Failure scenarios: a timeline of synthetic events played through real hubs, to rehearse
+//! what consumers downstream (alerting, runbooks) do when e.g. the root filesystem is
+//! remounted read-only or sshd goes missing.
+//!
+//! ```toml
+//! name = "sshd-missing"
+//! suppress_real = true
+//!
+//! [[events]]
+//! at_secs = 0
+//! sensor = "procdog"
+//! payload = { Disappeared = { name = "sshd", pid = 812 } }
+//!
+//! [[events]]
+//! at_secs = 5
+//! sensor = "procdog"
+//! payload = { Missing = { name = "sshd" } }
+//! ```
+//!
+//! Payloads are events as the sensors serialize them, `{ "<Variant>": { ... } }`. Strings in
+//! them may hold `{{hostname}}`, `{{now}}` (RFC 3339, UTC) and `{{now_unix}}`; a string that
+//! is only `{{now_unix}}` becomes a number. Before the first event is injected, every payload
+//! is rendered and decoded as its sensor's event type, so a typo fails the whole scenario
+//! instead of half of it playing. Events go through `CallbackHub::inject`, so results carry
+//! `"injected": true`.
+
+use crate::{scenario::SensorKind, stream::Synthetic};
+use filescream::events::FileScreamEvent;
+use netpacket::events::NetNotifyEvent;
+use omnitrace_core::{callbacks::CallbackHub, router::Router};
+use procdog::events::ProcDogEvent;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::{
+    io,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use xmount::events::XMountEvent;
+
+/// One event of the timeline.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    /// When to inject it, in seconds from the start, or as a duration like `"1m30s"`.
+    #[serde(deserialize_with = "omnitrace_core::units::secs::deserialize")]
+    pub at_secs: f64,
+    pub sensor: SensorKind,
+    /// The event, with placeholders, see the module docs.
+    pub payload: Value,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FailureScenario {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Drop the events of the real sensors on the same hubs while the scenario plays.
+    #[serde(default)]
+    pub suppress_real: bool,
+    pub events: Vec<Step>,
+}
+
+impl FailureScenario {
+    /// A TOML scenario, or a JSON one if the file name ends in `.json`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = std::fs::read_to_string(&path)?;
+        if path.as_ref().extension().is_some_and(|ext| ext == "json") { Self::parse_json(&text) } else { Self::parse(&text) }
+    }
+
+    /// Parse and check a TOML scenario.
+    pub fn parse(toml: &str) -> io::Result<Self> {
+        let s: FailureScenario = toml::from_str(toml).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        s.checked()
+    }
+
+    /// Parse and check a JSON scenario.
+    pub fn parse_json(json: &str) -> io::Result<Self> {
+        let s: FailureScenario = serde_json::from_str(json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        s.checked()
+    }
+
+    fn checked(self) -> io::Result<Self> {
+        if self.events.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no events"));
+        }
+        if let Some(i) = self.events.iter().position(|s| !(s.at_secs.is_finite() && s.at_secs >= 0.0)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("event {i}: at_secs must not be negative")));
+        }
+        self.validate(&Vars::current()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        Ok(self)
+    }
+
+    /// The events in the order they play, with their positions in the file: by `at_secs`,
+    /// ties in file order.
+    pub fn timeline(&self) -> Vec<(usize, &Step)> {
+        let mut steps: Vec<(usize, &Step)> = self.events.iter().enumerate().collect();
+        steps.sort_by(|a, b| a.1.at_secs.total_cmp(&b.1.at_secs));
+        steps
+    }
+
+    /// Render every payload with `vars` and check it decodes as an event of its sensor.
+    pub fn validate(&self, vars: &Vars) -> Result<(), String> {
+        for (i, step) in self.events.iter().enumerate() {
+            let payload = render(&step.payload, vars);
+            let decoded = match step.sensor {
+                SensorKind::XMount => decode::<XMountEvent>(payload).map(drop),
+                SensorKind::ProcDog => decode::<ProcDogEvent>(payload).map(drop),
+                SensorKind::NetPacket => decode::<NetNotifyEvent>(payload).map(drop),
+                SensorKind::FileScream => decode::<FileScreamEvent>(payload).map(drop),
+            };
+            decoded.map_err(|e| format!("event {i} ({} at {}s): {e}", step.sensor, step.at_secs))?;
+        }
+        Ok(())
+    }
+}
+
+fn decode<E: DeserializeOwned>(payload: Value) -> Result<E, String> {
+    serde_json::from_value(payload).map_err(|e| e.to_string())
+}
+
+/// What the placeholders stand for.
+#[derive(Clone, Debug)]
+pub struct Vars {
+    pub hostname: String,
+    pub now: SystemTime,
+}
+
+impl Vars {
+    /// This host and the wall clock now.
+    pub fn current() -> Self {
+        let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname").map(|s| s.trim().to_string()).unwrap_or_default();
+        Self { hostname: if hostname.is_empty() { "localhost".to_string() } else { hostname }, now: SystemTime::now() }
+    }
+}
+
+/// `payload` with the placeholders in its strings replaced.
+pub fn render(payload: &Value, vars: &Vars) -> Value {
+    let unix = vars.now.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    match payload {
+        Value::String(s) if s == "{{now_unix}}" => Value::from(unix),
+        Value::String(s) if s.contains("{{") => {
+            Value::String(s.replace("{{hostname}}", &vars.hostname).replace("{{now_unix}}", &unix.to_string()).replace("{{now}}", &rfc3339(unix)))
+        }
+        Value::Array(items) => items.iter().map(|v| render(v, vars)).collect(),
+        Value::Object(map) => Value::Object(map.iter().map(|(k, v)| (k.clone(), render(v, vars))).collect()),
+        other => other.clone(),
+    }
+}
+
+/// `2023-11-14T22:13:20Z` for `unix` seconds.
+fn rfc3339(unix: u64) -> String {
+    let (days, secs) = ((unix / 86_400) as i64, unix % 86_400);
+    // civil date from days since the epoch, after Howard Hinnant's `civil_from_days`
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z", secs / 3_600, secs / 60 % 60, secs % 60)
+}
+
+/// The hubs a scenario plays into, one per sensor it names.
+#[derive(Clone, Default)]
+pub struct Hubs {
+    pub xmount: Option<Arc<CallbackHub<XMountEvent>>>,
+    pub procdog: Option<Arc<CallbackHub<ProcDogEvent>>>,
+    pub netpacket: Option<Arc<CallbackHub<NetNotifyEvent>>>,
+    pub filescream: Option<Arc<CallbackHub<FileScreamEvent>>>,
+}
+
+impl Hubs {
+    /// A hub for every sensor, delivering to `router` under the sensor's name.
+    pub fn routed(router: &Arc<Router>) -> Self {
+        fn hub<E: Synthetic>(router: &Arc<Router>, kind: SensorKind) -> Option<Arc<CallbackHub<E>>> {
+            let mut hub = CallbackHub::new();
+            hub.add(router.callback(&kind.to_string(), E::mask_bits));
+            Some(Arc::new(hub))
+        }
+        Self {
+            xmount: hub(router, SensorKind::XMount),
+            procdog: hub(router, SensorKind::ProcDog),
+            netpacket: hub(router, SensorKind::NetPacket),
+            filescream: hub(router, SensorKind::FileScream),
+        }
+    }
+
+    fn has(&self, kind: SensorKind) -> bool {
+        match kind {
+            SensorKind::XMount => self.xmount.is_some(),
+            SensorKind::ProcDog => self.procdog.is_some(),
+            SensorKind::NetPacket => self.netpacket.is_some(),
+            SensorKind::FileScream => self.filescream.is_some(),
+        }
+    }
+
+    fn set_suppress_real(&self, on: bool) {
+        self.xmount.iter().for_each(|h| h.set_suppress_real(on));
+        self.procdog.iter().for_each(|h| h.set_suppress_real(on));
+        self.netpacket.iter().for_each(|h| h.set_suppress_real(on));
+        self.filescream.iter().for_each(|h| h.set_suppress_real(on));
+    }
+
+    async fn inject(&self, kind: SensorKind, payload: Value) -> Result<(), String> {
+        match kind {
+            SensorKind::XMount => inject_into(self.xmount.as_deref(), payload).await,
+            SensorKind::ProcDog => inject_into(self.procdog.as_deref(), payload).await,
+            SensorKind::NetPacket => inject_into(self.netpacket.as_deref(), payload).await,
+            SensorKind::FileScream => inject_into(self.filescream.as_deref(), payload).await,
+        }
+    }
+}
+
+async fn inject_into<E: Synthetic + DeserializeOwned>(hub: Option<&CallbackHub<E>>, payload: Value) -> Result<(), String> {
+    let Some(hub) = hub else {
+        return Err("no hub".to_string());
+    };
+    let ev = decode::<E>(payload)?;
+    hub.inject(ev.mask_bits(), &ev).await;
+    Ok(())
+}
+
+/// Switches suppression off again however playing ends.
+struct Suppressing<'a>(&'a Hubs);
+
+impl Drop for Suppressing<'_> {
+    fn drop(&mut self) {
+        self.0.set_suppress_real(false);
+    }
+}
+
+/// An event as it was played.
+#[derive(Clone, Debug, Serialize)]
+pub struct Played {
+    /// Position in the scenario file.
+    pub index: usize,
+    pub at_secs: f64,
+    pub sensor: SensorKind,
+    /// The event's variant, e.g. `"Missing"`.
+    pub event: String,
+}
+
+/// Play `scenario` into `hubs`, each event when due, until done or `cancel` fires. Nothing is
+/// injected if a payload does not validate or names a sensor `hubs` has no hub for. With
+/// `suppress_real`, the hubs drop the real sensors' events until this returns.
+pub async fn play(scenario: &FailureScenario, hubs: &Hubs, cancel: &CancellationToken) -> io::Result<Vec<Played>> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidInput, e);
+    if let Some(step) = scenario.events.iter().find(|s| !hubs.has(s.sensor)) {
+        return Err(invalid(format!("no hub for sensor {}", step.sensor)));
+    }
+    scenario.validate(&Vars::current()).map_err(invalid)?;
+
+    let _suppressing = scenario.suppress_real.then(|| {
+        hubs.set_suppress_real(true);
+        Suppressing(hubs)
+    });
+    log::info!("inject: playing scenario {:?}, {} events", scenario.name, scenario.events.len());
+    let start = Instant::now();
+    let mut played = Vec::new();
+    for (index, step) in scenario.timeline() {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = tokio::time::sleep_until(start + Duration::from_secs_f64(step.at_secs)) => {}
+        }
+        let payload = render(&step.payload, &Vars::current());
+        let event = payload.as_object().and_then(|m| m.keys().next()).cloned().unwrap_or_default();
+        hubs.inject(step.sensor, payload).await.map_err(|e| invalid(format!("event {index}: {e}")))?;
+        played.push(Played { index, at_secs: step.at_secs, sensor: step.sensor, event });
+    }
+    Ok(played)
+}
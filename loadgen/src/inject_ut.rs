@@ -0,0 +1,133 @@
+use crate::inject::{self, FailureScenario, Hubs, Vars, render};
+use omnitrace_core::{callbacks::CallbackResult, labels::Labels, router::Router};
+use procdog::events::ProcDogEvent;
+use serde_json::json;
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+use tokio::sync::mpsc::Receiver;
+use tokio_util::sync::CancellationToken;
+
+/// Hubs for every sensor, routed into one channel.
+fn routed() -> (Hubs, Receiver<CallbackResult>) {
+    let (tx, rx) = tokio::sync::mpsc::channel(64);
+    let mut router = Router::new();
+    router.add_sink("out", tx);
+    router.set_default(vec!["out"]);
+    (Hubs::routed(&Arc::new(router)), rx)
+}
+
+fn drain(rx: &mut Receiver<CallbackResult>) -> Vec<CallbackResult> {
+    std::iter::from_fn(|| rx.try_recv().ok()).collect()
+}
+
+const OUT_OF_ORDER: &str = r#"
+name = "ordering"
+
+[[events]]
+at_secs = 0.2
+sensor = "procdog"
+payload = { Missing = { name = "sshd" } }
+
+[[events]]
+at_secs = 0
+sensor = "xmount"
+payload = { FsHealthChanged = { target = "/", fstype = "ext4", old = "Healthy", new = "Faulted", details = ["{{hostname}}"] } }
+
+[[events]]
+at_secs = 0.1
+sensor = "procdog"
+payload = { Disappeared = { name = "sshd", pid = 812 } }
+
+[[events]]
+at_secs = 0.2
+sensor = "procdog"
+payload = { Appeared = { name = "sshd", pid = 901 } }
+"#;
+
+#[test]
+fn example_failure_scenarios_validate() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("failures");
+    let mut n = 0;
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        FailureScenario::load(&path).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+        n += 1;
+    }
+    assert!(n >= 2);
+}
+
+#[test]
+fn placeholders_render_in_strings() {
+    let vars = Vars { hostname: "web1".into(), now: UNIX_EPOCH + Duration::from_secs(1_700_000_000) };
+    let payload = json!({ "Missing": { "name": "{{hostname}}-agent", "at": "{{now}}", "unix": "{{now_unix}}", "ids": ["t{{now_unix}}", 3] } });
+    assert_eq!(
+        render(&payload, &vars),
+        json!({ "Missing": { "name": "web1-agent", "at": "2023-11-14T22:13:20Z", "unix": 1_700_000_000u64, "ids": ["t1700000000", 3] } })
+    );
+}
+
+#[test]
+fn payloads_are_checked_against_the_event_types() {
+    let err = FailureScenario::parse(
+        r#"
+        [[events]]
+        at_secs = 0
+        sensor = "procdog"
+        payload = { Vanished = { name = "sshd" } }
+        "#,
+    )
+    .unwrap_err();
+    assert!(err.to_string().starts_with("event 0 (procdog at 0s): unknown variant `Vanished`"), "{err}");
+    assert!(FailureScenario::parse("events = []").is_err());
+    assert!(FailureScenario::parse_json(r#"{ "events": [{ "at_secs": -1, "sensor": "xmount", "payload": {} }] }"#).is_err());
+}
+
+#[tokio::test]
+async fn events_play_in_timeline_order_flagged_as_injected() {
+    let scenario = FailureScenario::parse(OUT_OF_ORDER).unwrap();
+    let (hubs, mut rx) = routed();
+    let played = inject::play(&scenario, &hubs, &CancellationToken::new()).await.unwrap();
+
+    // by time, ties in file order
+    assert_eq!(played.iter().map(|p| p.index).collect::<Vec<_>>(), [1, 2, 0, 3]);
+    assert_eq!(played.iter().map(|p| p.event.as_str()).collect::<Vec<_>>(), ["FsHealthChanged", "Disappeared", "Missing", "Appeared"]);
+    let results = drain(&mut rx);
+    let variants: Vec<&str> = results.iter().filter_map(|r| r.as_object()?.keys().find(|k| *k != "injected")).map(String::as_str).collect();
+    assert_eq!(variants, ["FsHealthChanged", "Disappeared", "Missing", "Appeared"]);
+    assert!(results.iter().all(|r| r["injected"] == true), "{results:?}");
+}
+
+#[tokio::test]
+async fn real_events_are_suppressed_while_playing_only() {
+    let scenario = FailureScenario::parse(&format!("suppress_real = true\n{OUT_OF_ORDER}")).unwrap();
+    let (hubs, mut rx) = routed();
+    let procdog = hubs.procdog.clone().unwrap();
+    let real = ProcDogEvent::Disappeared { name: "cron".into(), pid: 77, group_leader: None, labels: Labels::default() };
+
+    let playing = {
+        let hubs = hubs.clone();
+        tokio::spawn(async move { inject::play(&scenario, &hubs, &CancellationToken::new()).await })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    procdog.fire(real.mask().bits(), &real).await;
+    playing.await.unwrap().unwrap();
+    procdog.fire(real.mask().bits(), &real).await;
+
+    let results = drain(&mut rx);
+    assert_eq!(results.len(), 5, "{results:?}");
+    assert!(results[..4].iter().all(|r| r["injected"] == true));
+    assert_eq!(results[4], json!({ "Disappeared": { "name": "cron", "pid": 77 } }));
+}
+
+#[tokio::test]
+async fn nothing_plays_without_a_hub_for_every_sensor() {
+    let scenario = FailureScenario::parse(OUT_OF_ORDER).unwrap();
+    let (routed, mut rx) = routed();
+    let hubs = Hubs { procdog: routed.procdog, ..Default::default() };
+    let err = inject::play(&scenario, &hubs, &CancellationToken::new()).await.unwrap_err();
+    assert_eq!(err.to_string(), "no hub for sensor xmount");
+    assert!(drain(&mut rx).is_empty());
+}
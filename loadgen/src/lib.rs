@@ -0,0 +1,22 @@
+//! Synthetic load for the whole pipeline.
+//!
+//! Mock sensors emit configurable streams of real event types (rate, burstiness, key
+//! cardinality, payload size) through real `CallbackHub`s, a `Router` and sinks, and the
+//! harness reports throughput, callback latency, drops per stage and peak RSS. Scenarios
+//! are JSON files, see [`scenario`]; `omnitrace-loadgen <scenario.json>` runs one.
+//!
+//! Failure scenarios play a timeline of hand-written events instead, see [`inject`];
+//! `omnitrace-loadgen inject --scenario <file.toml>` plays one and prints the results.
+
+pub mod harness;
+pub mod inject;
+pub mod metrics;
+pub mod scenario;
+pub mod stream;
+
+#[cfg(test)]
+mod harness_ut;
+#[cfg(test)]
+mod inject_ut;
+#[cfg(test)]
+mod metrics_ut;
@@ -0,0 +1,132 @@
+use omnitrace_core::{router::Router, units};
+use omnitrace_loadgen::{
+    harness,
+    inject::{self, FailureScenario, Hubs},
+    scenario::Scenario,
+};
+use std::{sync::Arc, time::Duration};
+use tokio_util::sync::CancellationToken;
+
+const USAGE: &str = "usage: omnitrace-loadgen <scenario.json> [--duration DURATION] [--json] [--fail-on-drops]
+       omnitrace-loadgen inject --scenario <file.toml> [--check]";
+
+fn usage() -> ! {
+    eprintln!("{USAGE}");
+    std::process::exit(2);
+}
+
+/// Play a failure scenario into hubs routed to stdout, one result per line.
+async fn inject_main(mut args: impl Iterator<Item = String>) {
+    let (mut path, mut check) = (None, false);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scenario" => path = Some(args.next().unwrap_or_else(|| usage())),
+            "--check" => check = true,
+            _ => usage(),
+        }
+    }
+    let Some(path) = path else { usage() };
+    let scenario = match FailureScenario::load(&path) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            std::process::exit(2);
+        }
+    };
+    if check {
+        println!("{path}: {} events, ok", scenario.events.len());
+        return;
+    }
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel(64);
+    let mut router = Router::new();
+    router.add_sink("stdout", tx);
+    router.set_default(vec!["stdout"]);
+    let router = Arc::new(router);
+    let printer = tokio::spawn(async move {
+        while let Some(res) = rx.recv().await {
+            println!("{res}");
+        }
+    });
+
+    let cancel = CancellationToken::new();
+    let on_ctrl_c = cancel.clone();
+    tokio::spawn(async move {
+        let _ = tokio::signal::ctrl_c().await;
+        on_ctrl_c.cancel();
+    });
+    let hubs = Hubs::routed(&router);
+    let played = inject::play(&scenario, &hubs, &cancel).await;
+    drop((hubs, router));
+    let _ = printer.await;
+    match played {
+        Ok(played) => eprintln!("scenario {} played {} of {} events", scenario.name, played.len(), scenario.events.len()),
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().is_some_and(|a| a == "inject") {
+        args.next();
+        return inject_main(args).await;
+    }
+    let (mut path, mut duration, mut json, mut fail_on_drops) = (None, None, false, false);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // plain numbers are seconds, as before
+            "--duration" => match units::parse_duration_or(&args.next().unwrap_or_else(|| usage()), Duration::from_secs(1)) {
+                Ok(d) if !d.is_zero() => duration = Some(d),
+                Ok(_) => usage(),
+                Err(e) => {
+                    eprintln!("--duration: {e}");
+                    std::process::exit(2);
+                }
+            },
+            "--json" => json = true,
+            "--fail-on-drops" => fail_on_drops = true,
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => usage(),
+        }
+    }
+    let Some(path) = path else { usage() };
+
+    let scenario = match Scenario::load(&path) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            std::process::exit(2);
+        }
+    };
+    let report = harness::run_for(&scenario, duration.unwrap_or_else(|| scenario.duration())).await;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default());
+    } else {
+        println!("scenario {} ran {:.1}s", report.scenario, report.elapsed_secs);
+        for s in &report.streams {
+            println!(
+                "  {:<12} {:>9} events  {:>9.0}/s of {:.0}/s  skipped {:>7}  p50 {}us  p99 {}us  max {}us",
+                s.name, s.emitted, s.achieved_per_sec, s.target_per_sec, s.skipped, s.latency.p50_us, s.latency.p99_us, s.latency.max_us
+            );
+        }
+        for s in &report.sinks {
+            println!("  sink {:<7} {:>9} written  {} failed", s.name, s.written, s.failed);
+        }
+        println!(
+            "total {} events, {:.0}/s, p99 {}us; drops: generator {}, router {}, sink {}",
+            report.emitted, report.achieved_per_sec, report.latency.p99_us, report.drops.generator, report.drops.router, report.drops.sink
+        );
+        if let Some(rss) = report.peak_rss_bytes {
+            println!("peak RSS {:.1} MiB", rss as f64 / (1024.0 * 1024.0));
+        }
+    }
+
+    if fail_on_drops && report.drops.total() > 0 {
+        std::process::exit(1);
+    }
+}
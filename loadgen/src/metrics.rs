@@ -0,0 +1,151 @@
+//! Pipeline metrics collected during a run.
+
+use omnitrace_core::router::RouteStats;
+use serde::Serialize;
+use std::time::Duration;
+
+// values below are kept exactly (in microseconds), above in 32 steps per power of two
+const LINEAR: u64 = 64;
+const STEPS: u64 = 32;
+
+/// Latency histogram with about 3% resolution, in microseconds.
+#[derive(Clone, Debug, Default)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+    max: u64,
+}
+
+impl Histogram {
+    fn bucket(us: u64) -> usize {
+        if us < LINEAR {
+            return us as usize;
+        }
+        let exp = 63 - u64::from(us.leading_zeros()) - STEPS.trailing_zeros() as u64;
+        let mantissa = us >> exp;
+        (LINEAR + (exp - 1) * STEPS + (mantissa - STEPS)) as usize
+    }
+
+    // upper bound of a bucket, so quantiles never understate
+    fn bound(idx: usize) -> u64 {
+        let idx = idx as u64;
+        if idx < LINEAR {
+            return idx;
+        }
+        let (exp, mantissa) = ((idx - LINEAR) / STEPS + 1, (idx - LINEAR) % STEPS + STEPS);
+        ((mantissa + 1) << exp) - 1
+    }
+
+    pub fn record(&mut self, d: Duration) {
+        let us = u64::try_from(d.as_micros()).unwrap_or(u64::MAX);
+        let idx = Self::bucket(us);
+        if self.counts.len() <= idx {
+            self.counts.resize(idx + 1, 0);
+        }
+        self.counts[idx] += 1;
+        self.total += 1;
+        self.max = self.max.max(us);
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        if self.counts.len() < other.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (a, b) in self.counts.iter_mut().zip(&other.counts) {
+            *a += b;
+        }
+        self.total += other.total;
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    /// Smallest recorded bucket bound at or above the `q` quantile (0.0..=1.0).
+    pub fn quantile(&self, q: f64) -> Duration {
+        if self.total == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (idx, n) in self.counts.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return Duration::from_micros(Self::bound(idx).min(self.max));
+            }
+        }
+        Duration::from_micros(self.max)
+    }
+
+    pub fn summary(&self) -> Latency {
+        Latency { p50_us: self.quantile(0.5).as_micros() as u64, p99_us: self.quantile(0.99).as_micros() as u64, max_us: self.max }
+    }
+}
+
+/// Callback latency: from handing an event to the hub until all its callbacks returned,
+/// including routing and waiting for room in the sink channels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Latency {
+    pub p50_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct StreamReport {
+    pub name: String,
+    pub target_per_sec: f64,
+    pub emitted: u64,
+    pub achieved_per_sec: f64,
+    /// Events not emitted because the stream fell more than `max_lag_ms` behind schedule.
+    pub skipped: u64,
+    pub latency: Latency,
+}
+
+/// Events lost, per stage of the pipeline.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Drops {
+    /// Mock sensors that could not keep up with their schedule.
+    pub generator: u64,
+    /// Router drops: drop routes, unknown or closed sinks, severity minimums.
+    pub router: u64,
+    /// Events a sink failed to write.
+    pub sink: u64,
+}
+
+impl Drops {
+    pub fn total(&self) -> u64 {
+        self.generator + self.router + self.sink
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct SinkReport {
+    pub name: String,
+    pub written: u64,
+    pub failed: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Report {
+    pub scenario: String,
+    pub elapsed_secs: f64,
+    pub emitted: u64,
+    pub achieved_per_sec: f64,
+    pub latency: Latency,
+    pub drops: Drops,
+    pub streams: Vec<StreamReport>,
+    pub sinks: Vec<SinkReport>,
+    /// Counters of each router rule.
+    pub routes: Vec<RouteStats>,
+    /// High-water mark of the resident set of this process, where the OS reports it.
+    pub peak_rss_bytes: Option<u64>,
+}
+
+/// Peak resident set size of this process (`VmHWM`), Linux only.
+pub fn peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kb = status.lines().find_map(|l| l.strip_prefix("VmHWM:"))?.trim().strip_suffix("kB")?.trim().parse::<u64>().ok()?;
+    Some(kb * 1024)
+}
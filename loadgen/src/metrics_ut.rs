@@ -0,0 +1,29 @@
+use crate::metrics::Histogram;
+use std::time::Duration;
+
+#[test]
+fn quantiles_stay_within_the_bucket_resolution() {
+    let mut h = Histogram::default();
+    for us in 1..=10_000u64 {
+        h.record(Duration::from_micros(us));
+    }
+    assert_eq!(h.count(), 10_000);
+    for (q, exact) in [(0.5, 5_000u64), (0.99, 9_900), (1.0, 10_000)] {
+        let got = h.quantile(q).as_micros() as u64;
+        assert!(got >= exact && got <= exact + exact / 32 + 1, "q{q}: {got} for {exact}");
+    }
+    // small values are exact
+    assert_eq!(h.quantile(0.001).as_micros(), 10);
+}
+
+#[test]
+fn merged_histograms_add_up() {
+    let (mut a, mut b) = (Histogram::default(), Histogram::default());
+    (0..99).for_each(|_| a.record(Duration::from_micros(20)));
+    b.record(Duration::from_millis(40));
+    a.merge(&b);
+
+    let s = a.summary();
+    assert_eq!((s.p50_us, s.p99_us, s.max_us), (20, 20, 40_000));
+    assert_eq!(a.quantile(1.0), Duration::from_millis(40));
+}
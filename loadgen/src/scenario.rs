@@ -0,0 +1,171 @@
+//! Scenario files: which synthetic streams to run, for how long, into which sinks.
+//!
+//! ```json
+//! {
+//!   "name": "busy-host",
+//!   "duration_secs": 60,
+//!   "streams": [
+//!     { "sensor": "netpacket", "rate_per_sec": 833, "keys": 5000 },
+//!     { "sensor": "filescream", "rate_per_sec": 2000, "burst": 10000, "keys": 50000, "payload_bytes": 120 },
+//!     { "sensor": "xmount", "rate_per_sec": 20, "keys": 4 }
+//!   ],
+//!   "sinks": [{ "name": "audit", "path": "/tmp/loadgen-audit.jsonl", "capacity": 4096 }]
+//! }
+//! ```
+
+use omnitrace_core::{router::RouterConfig, units};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Which sensor's event type a stream emits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SensorKind {
+    /// Mounted/Unmounted, alternating per mountpoint (flapping mounts).
+    XMount,
+    /// Appeared/Disappeared, alternating per process name.
+    ProcDog,
+    /// Opened/Closed, alternating per remote.
+    NetPacket,
+    /// Created once per path, Changed afterwards.
+    FileScream,
+}
+
+impl fmt::Display for SensorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SensorKind::XMount => "xmount",
+            SensorKind::ProcDog => "procdog",
+            SensorKind::NetPacket => "netpacket",
+            SensorKind::FileScream => "filescream",
+        })
+    }
+}
+
+/// One mock sensor and its event stream.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StreamSpec {
+    pub sensor: SensorKind,
+    /// Sensor name given to the router (default: the sensor kind).
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Average events per second, or a rate like `"600/m"`.
+    #[serde(deserialize_with = "units::per_sec::deserialize")]
+    pub rate_per_sec: f64,
+    /// Events emitted back to back per tick; ticks are spaced to keep the average rate, so
+    /// `"burst": 10000` at 2000/s is one scan's worth of changes every 5 seconds.
+    #[serde(default = "default_burst")]
+    pub burst: u32,
+    /// Distinct entities (paths, remotes, names, mountpoints) the events cycle through.
+    #[serde(default = "default_keys")]
+    pub keys: u32,
+    /// Minimum length of the entity strings, to grow the serialized events.
+    #[serde(default, deserialize_with = "units::bytes::deserialize")]
+    pub payload_bytes: usize,
+    /// How far the stream may fall behind schedule and still catch up; ticks missed beyond
+    /// that are given up and counted as generator drops.
+    #[serde(default = "default_max_lag_ms", deserialize_with = "units::ms::deserialize")]
+    pub max_lag_ms: u64,
+}
+
+impl StreamSpec {
+    pub fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.sensor.to_string())
+    }
+}
+
+fn default_burst() -> u32 {
+    1
+}
+
+fn default_keys() -> u32 {
+    100
+}
+
+fn default_max_lag_ms() -> u64 {
+    1000
+}
+
+/// A sink consuming routed events.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SinkSpec {
+    pub name: String,
+    /// Write a hash-chained audit log here; events are serialized and discarded otherwise.
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// Channel capacity between router and sink.
+    #[serde(default = "default_capacity")]
+    pub capacity: usize,
+    /// Extra time the sink spends per event, to model a slow consumer.
+    #[serde(default, deserialize_with = "units::us::deserialize")]
+    pub delay_us: u64,
+}
+
+fn default_capacity() -> usize {
+    1024
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default)]
+    pub name: String,
+    #[serde(deserialize_with = "units::secs::deserialize")]
+    pub duration_secs: f64,
+    pub streams: Vec<StreamSpec>,
+    /// Default: a single discarding sink named `null`.
+    #[serde(default)]
+    pub sinks: Vec<SinkSpec>,
+    /// Routing between streams and sinks (default: everything to every sink).
+    #[serde(default)]
+    pub router: Option<RouterConfig>,
+}
+
+impl Scenario {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parse and check a scenario.
+    pub fn parse(json: &str) -> io::Result<Self> {
+        let s: Scenario = serde_json::from_str(json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        s.check().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        Ok(s)
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.duration_secs.max(0.0))
+    }
+
+    /// The sinks, or the default discarding one.
+    pub fn sinks(&self) -> Vec<SinkSpec> {
+        if !self.sinks.is_empty() {
+            return self.sinks.clone();
+        }
+        vec![SinkSpec { name: "null".to_string(), path: None, capacity: default_capacity(), delay_us: 0 }]
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if !(self.duration_secs.is_finite() && self.duration_secs > 0.0) {
+            return Err(format!("duration_secs must be positive, got {}", self.duration_secs));
+        }
+        if self.streams.is_empty() {
+            return Err("no streams".to_string());
+        }
+        for s in &self.streams {
+            if !(s.rate_per_sec.is_finite() && s.rate_per_sec > 0.0) {
+                return Err(format!("stream {}: rate_per_sec must be positive", s.name()));
+            }
+            if s.burst == 0 || s.keys == 0 {
+                return Err(format!("stream {}: burst and keys must be at least 1", s.name()));
+            }
+        }
+        Ok(())
+    }
+}
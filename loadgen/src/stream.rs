@@ -0,0 +1,175 @@
+//! Mock sensors emitting synthetic events of the real sensors' types.
+
+use crate::{metrics::Histogram, scenario::StreamSpec};
+use filescream::events::FileScreamEvent;
+use netpacket::events::NetNotifyEvent;
+use omnitrace_core::{
+    fields::EventFields,
+    labels::Labels,
+    sensor::{Sensor, SensorCtx},
+};
+use procdog::events::ProcDogEvent;
+use serde::Serialize;
+use std::{
+    future::Future,
+    net::Ipv4Addr,
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::time::Instant;
+use xmount::events::{MountInfo, XMountEvent};
+
+/// Event type a mock sensor can make up.
+pub trait Synthetic: EventFields + Serialize + Send + Sync + Sized + 'static {
+    /// The `visit`-th event about entity number `key`, called `entity`.
+    fn synth(key: u32, visit: u64, entity: &str) -> Self;
+
+    fn mask_bits(&self) -> u64;
+}
+
+impl Synthetic for XMountEvent {
+    fn synth(_key: u32, visit: u64, entity: &str) -> Self {
+        let target = format!("/mnt/load/{entity}");
+        if visit.is_multiple_of(2) {
+            XMountEvent::Mounted { info: MountInfo::test(&target), target: target.into(), labels: Labels::default() }
+        } else {
+            XMountEvent::Unmounted { last: MountInfo::test(&target), target: target.into(), reason: None, labels: Labels::default() }
+        }
+    }
+
+    fn mask_bits(&self) -> u64 {
+        self.mask().bits()
+    }
+}
+
+impl Synthetic for ProcDogEvent {
+    fn synth(key: u32, visit: u64, entity: &str) -> Self {
+        let (name, pid) = (entity.to_string(), 10_000 + key as i32);
+        if visit.is_multiple_of(2) {
+            ProcDogEvent::Appeared { name, pid, group_leader: None, env: None, labels: Labels::default() }
+        } else {
+            ProcDogEvent::Disappeared { name, pid, group_leader: None, labels: Labels::default() }
+        }
+    }
+
+    fn mask_bits(&self) -> u64 {
+        self.mask().bits()
+    }
+}
+
+impl Synthetic for NetNotifyEvent {
+    fn synth(key: u32, visit: u64, entity: &str) -> Self {
+        let remote = format!("{}:443", Ipv4Addr::from(0x0a00_0000 | (key & 0x00ff_ffff)));
+        let local = format!("192.168.0.2:{}", 40_000 + key % 20_000);
+        let mut ev = if visit.is_multiple_of(2) {
+            NetNotifyEvent::test_opened("tcp", &local, &remote)
+        } else {
+            NetNotifyEvent::test_closed("tcp", &local, &remote)
+        };
+        if let NetNotifyEvent::Opened { conn, .. } | NetNotifyEvent::Closed { conn, .. } = &mut ev {
+            conn.remote_host = Some(entity.to_string());
+        }
+        ev
+    }
+
+    fn mask_bits(&self) -> u64 {
+        self.mask().bits()
+    }
+}
+
+impl Synthetic for FileScreamEvent {
+    fn synth(_key: u32, visit: u64, entity: &str) -> Self {
+        if visit == 0 { FileScreamEvent::test_created("/srv/load", entity) } else { FileScreamEvent::test_changed("/srv/load", entity) }
+    }
+
+    fn mask_bits(&self) -> u64 {
+        self.mask().bits()
+    }
+}
+
+/// Counters of one stream, shared between its mock sensor and the harness.
+#[derive(Debug, Default)]
+pub struct StreamStats {
+    pub emitted: AtomicU64,
+    pub skipped: AtomicU64,
+    pub latency: Mutex<Histogram>,
+}
+
+/// Sensor emitting `spec`'s stream of `E` events until cancelled. Events go through
+/// `CallbackHub::inject`, so results carry `"injected": true`.
+pub struct MockSensor<E> {
+    spec: StreamSpec,
+    stats: Arc<StreamStats>,
+    _event: std::marker::PhantomData<fn() -> E>,
+}
+
+impl<E: Synthetic> MockSensor<E> {
+    pub fn new(spec: StreamSpec) -> Self {
+        Self { spec, stats: Arc::new(StreamStats::default()), _event: std::marker::PhantomData }
+    }
+
+    pub fn stats(&self) -> Arc<StreamStats> {
+        self.stats.clone()
+    }
+
+    fn entities(&self) -> Vec<String> {
+        (0..self.spec.keys)
+            .map(|k| {
+                let mut s = format!("{}-{k:06}", self.spec.name());
+                if s.len() < self.spec.payload_bytes {
+                    s.extend(std::iter::repeat_n('x', self.spec.payload_bytes - s.len()));
+                }
+                s
+            })
+            .collect()
+    }
+}
+
+impl<E: Synthetic> Sensor for MockSensor<E> {
+    type Event = E;
+
+    fn run<R: Send + 'static>(self, ctx: SensorCtx<Self::Event, R>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async move {
+            let entities = self.entities();
+            let (burst, keys) = (u64::from(self.spec.burst), u64::from(self.spec.keys));
+            let period = Duration::from_secs_f64(burst as f64 / self.spec.rate_per_sec);
+            let max_lag = Duration::from_millis(self.spec.max_lag_ms);
+            let start = Instant::now();
+            let (mut tick, mut n) = (0u64, 0u64);
+
+            loop {
+                ctx.beat();
+                let due = start + period.mul_f64(tick as f64);
+                tokio::select! {
+                    _ = ctx.cancel.cancelled() => break,
+                    _ = tokio::time::sleep_until(due) => {}
+                }
+
+                // too far behind: give the missed ticks up instead of catching up
+                let lag = Instant::now() - due;
+                if lag > max_lag {
+                    let missed = (lag.as_secs_f64() / period.as_secs_f64()) as u64;
+                    tick += missed;
+                    self.stats.skipped.fetch_add(missed * burst, Ordering::Relaxed);
+                }
+
+                for _ in 0..burst {
+                    let key = (n % keys) as u32;
+                    let ev = E::synth(key, n / keys, &entities[key as usize]);
+                    let t = Instant::now();
+                    ctx.hub.inject(ev.mask_bits(), &ev).await;
+                    if let Ok(mut h) = self.stats.latency.lock() {
+                        h.record(t.elapsed());
+                    }
+                    n += 1;
+                }
+                self.stats.emitted.fetch_add(burst, Ordering::Relaxed);
+                tick += 1;
+            }
+        })
+    }
+}
@@ -0,0 +1,28 @@
+use netpacket::demo::{self, DemoConfig};
+use netpacket::prelude::*;
+use omnitrace_core::{
+    filter::Filter,
+    preflight::{self, PreflightArgs},
+};
+
+// NETPACKET_PROC_NET=/proc/net SNI_IFACE=eth0 cargo run -p netpacket --example netpacket
+#[tokio::main]
+async fn main() {
+    let cfg = DemoConfig::from_env();
+    match &cfg.sni_interface {
+        Some(iface) => println!("SNI capture interface: {iface}"),
+        None => println!("SNI capture interface: auto (all UP non-loopback interfaces)"),
+    }
+    let sensor = demo::sensor(&cfg);
+    preflight::gate("netpacket", &sensor, PreflightArgs::from_env_args()).await;
+
+    let mut hub = CallbackHub::<NetNotifyEvent>::new();
+    if let Some(f) = Filter::from_env_args() {
+        hub.set_filter(f.predicate());
+    }
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+        println!("\nStopping...");
+    };
+    demo::run(sensor, hub, ctrl_c).await;
+}
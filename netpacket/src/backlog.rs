@@ -0,0 +1,315 @@
+//! Accept queue pressure of listening sockets, from sock_diag.
+//!
+//! `/proc/net/netstat` only counts `ListenOverflows` kernel-wide, and `/proc/net/tcp` does not
+//! show a listener's backlog. An `inet_diag` dump does: for a socket in LISTEN, `rqueue` is the
+//! accept queue depth and `wqueue` the backlog passed to `listen()` (capped by `somaxconn`).
+//! Listeners matching [`crate::NetNotify::watch_listen`] are sampled every tick; see
+//! [`BacklogThreshold`] for when pressure is reported and cleared.
+
+use crate::events::NetNotifyEvent;
+use glob::Pattern;
+use std::{
+    collections::BTreeMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::{Path, PathBuf},
+};
+
+const NLMSG_HDRLEN: usize = 16;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+const SOCK_DIAG_BY_FAMILY: u16 = 20;
+/// `struct inet_diag_msg`.
+const INET_DIAG_MSG_LEN: usize = 72;
+const AF_INET: u8 = 2;
+const AF_INET6: u8 = 10;
+
+/// A listening socket and its accept queue.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ListenerQueue {
+    /// `"tcp"` or `"tcp6"`.
+    pub proto: String,
+    pub local: SocketAddr,
+    /// Connections completed but not yet accepted.
+    pub depth: u32,
+    /// Maximum accept queue length.
+    pub backlog: u32,
+    pub uid: u32,
+    pub inode: u64,
+}
+
+impl ListenerQueue {
+    /// Name used in events and matched by `watch_listen()`, e.g. `"tcp 0.0.0.0:443"` or
+    /// `"tcp [::]:443"`. The protocol is normalized like in `add()` patterns.
+    pub fn name(&self) -> String {
+        format!("{} {}", self.proto.strip_suffix('6').unwrap_or(&self.proto), self.local)
+    }
+
+    /// Full by the kernel's own measure (`sk_acceptq_is_full`): the next completed handshake is
+    /// dropped and counted in `TcpExt.ListenOverflows`.
+    pub fn saturated(&self) -> bool {
+        self.depth > self.backlog
+    }
+}
+
+/// Decode a sock_diag dump (netlink messages up to `NLMSG_DONE`) into its listeners.
+/// `NLMSG_ERROR` is returned as the OS error it carries.
+pub fn decode(buf: &[u8]) -> io::Result<Vec<ListenerQueue>> {
+    let mut out = Vec::new();
+    decode_into(buf, &mut out)?;
+    Ok(out)
+}
+
+/// Returns true once `NLMSG_DONE` was seen.
+fn decode_into(mut buf: &[u8], out: &mut Vec<ListenerQueue>) -> io::Result<bool> {
+    let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, format!("sock_diag: {what}"));
+    let u32_at = |b: &[u8], at: usize| u32::from_ne_bytes(b[at..at + 4].try_into().unwrap());
+
+    while !buf.is_empty() {
+        if buf.len() < NLMSG_HDRLEN {
+            return Err(invalid("truncated header"));
+        }
+        let len = u32_at(buf, 0) as usize;
+        let kind = u16::from_ne_bytes([buf[4], buf[5]]);
+        if len < NLMSG_HDRLEN || len > buf.len() {
+            return Err(invalid("bad message length"));
+        }
+        let body = &buf[NLMSG_HDRLEN..len];
+        buf = &buf[len.next_multiple_of(4).min(buf.len())..];
+
+        match kind {
+            NLMSG_DONE => return Ok(true),
+            NLMSG_ERROR => {
+                let errno = body.get(..4).map(|_| u32_at(body, 0) as i32).ok_or_else(|| invalid("truncated error"))?;
+                if errno != 0 {
+                    return Err(io::Error::from_raw_os_error(-errno));
+                }
+            }
+            SOCK_DIAG_BY_FAMILY => {
+                if body.len() < INET_DIAG_MSG_LEN {
+                    return Err(invalid("truncated inet_diag_msg"));
+                }
+                let port = u16::from_be_bytes([body[4], body[5]]);
+                let src = &body[8..24];
+                let (proto, ip) = match body[0] {
+                    AF_INET => ("tcp", IpAddr::V4(Ipv4Addr::new(src[0], src[1], src[2], src[3]))),
+                    AF_INET6 => ("tcp6", IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(src).unwrap()))),
+                    _ => continue,
+                };
+                out.push(ListenerQueue {
+                    proto: proto.to_string(),
+                    local: SocketAddr::new(ip, port),
+                    depth: u32_at(body, 56),
+                    backlog: u32_at(body, 60),
+                    uid: u32_at(body, 64),
+                    inode: u32_at(body, 68) as u64,
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(false)
+}
+
+/// Where listeners come from. The default is [`SockDiag`]; tests script their own.
+pub trait ListenerSource: Send + Sync {
+    fn listeners(&self) -> io::Result<Vec<ListenerQueue>>;
+}
+
+/// TCP listeners of both families, dumped over a `NETLINK_SOCK_DIAG` socket.
+pub struct SockDiag;
+
+impl ListenerSource for SockDiag {
+    #[cfg(target_os = "linux")]
+    fn listeners(&self) -> io::Result<Vec<ListenerQueue>> {
+        let mut out = Vec::new();
+        for family in [AF_INET, AF_INET6] {
+            dump(family, &mut out)?;
+        }
+        Ok(out)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn listeners(&self) -> io::Result<Vec<ListenerQueue>> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "sock_diag is Linux only"))
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn dump(family: u8, out: &mut Vec<ListenerQueue>) -> io::Result<()> {
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    const TCP_LISTEN: u32 = 10;
+    let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, libc::NETLINK_SOCK_DIAG) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    // nlmsghdr, then inet_diag_req_v2: family, protocol, ext, pad, states, and a zeroed inet_diag_sockid
+    let mut req = Vec::with_capacity(NLMSG_HDRLEN + 56);
+    req.extend_from_slice(&((NLMSG_HDRLEN + 56) as u32).to_ne_bytes());
+    req.extend_from_slice(&SOCK_DIAG_BY_FAMILY.to_ne_bytes());
+    req.extend_from_slice(&((libc::NLM_F_REQUEST | libc::NLM_F_DUMP) as u16).to_ne_bytes());
+    req.extend_from_slice(&1u32.to_ne_bytes());
+    req.extend_from_slice(&0u32.to_ne_bytes());
+    req.extend_from_slice(&[family, libc::IPPROTO_TCP as u8, 0, 0]);
+    req.extend_from_slice(&(1u32 << TCP_LISTEN).to_ne_bytes());
+    req.resize(NLMSG_HDRLEN + 56, 0);
+
+    let mut kernel: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+    kernel.nl_family = libc::AF_NETLINK as _;
+    let sent = unsafe {
+        libc::sendto(
+            fd.as_raw_fd(),
+            req.as_ptr().cast(),
+            req.len(),
+            0,
+            (&kernel as *const libc::sockaddr_nl).cast(),
+            size_of::<libc::sockaddr_nl>() as _,
+        )
+    };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut buf = vec![0u8; 32 * 1024];
+    loop {
+        let n = unsafe { libc::recv(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+        if n < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e);
+        }
+        if n == 0 || decode_into(&buf[..n as usize], out)? {
+            return Ok(());
+        }
+    }
+}
+
+/// Pressure levels as fractions of the backlog: reported when the depth goes above `high`,
+/// cleared at or below `low`. Pressure is also reported when `TcpExt.ListenOverflows` grew
+/// while the listener was [saturated](ListenerQueue::saturated), whatever `high` is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BacklogThreshold {
+    pub high: f64,
+    pub low: f64,
+}
+
+impl Default for BacklogThreshold {
+    fn default() -> Self {
+        Self { high: 0.8, low: 0.5 }
+    }
+}
+
+/// A listener entering (`pressure`) or leaving pressure. `drops_delta` is the growth of
+/// the kernel-wide `ListenOverflows` since the previous tick.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Crossing {
+    pub(crate) listener: ListenerQueue,
+    pub(crate) drops_delta: u64,
+    pub(crate) pressure: bool,
+}
+
+impl Crossing {
+    pub(crate) fn into_event(self, owner: Option<(i32, String)>) -> NetNotifyEvent {
+        let (pid, comm) = owner.unzip();
+        let (listener, depth, backlog) = (self.listener.name(), self.listener.depth, self.listener.backlog);
+        if self.pressure {
+            NetNotifyEvent::BacklogPressure { listener, depth, backlog, drops_delta: self.drops_delta, pid, comm }
+        } else {
+            NetNotifyEvent::BacklogCleared { listener, depth, backlog, pid, comm }
+        }
+    }
+}
+
+pub(crate) struct BacklogWatch {
+    rules: Vec<Pattern>,
+    threshold: BacklogThreshold,
+    /// Listeners under pressure, by name, as last seen.
+    pressured: BTreeMap<String, ListenerQueue>,
+    overflows: Option<u64>,
+}
+
+impl BacklogWatch {
+    pub(crate) fn new(threshold: BacklogThreshold) -> Self {
+        Self { rules: Vec::new(), threshold, pressured: BTreeMap::new(), overflows: None }
+    }
+
+    pub(crate) fn add(&mut self, rule: Pattern) {
+        self.rules.push(rule);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub(crate) fn rules(&self) -> Vec<String> {
+        self.rules.iter().map(|p| p.as_str().to_string()).collect()
+    }
+
+    pub(crate) fn pressured(&self) -> Vec<String> {
+        self.pressured.keys().cloned().collect()
+    }
+
+    /// Feed one tick's listeners and `TcpExt.ListenOverflows` (None if unreadable). The first
+    /// counter reading only primes it. A watched listener that closes under pressure is
+    /// cleared with depth 0.
+    pub(crate) fn update(&mut self, listeners: Vec<ListenerQueue>, overflows: Option<u64>) -> Vec<Crossing> {
+        let drops_delta = match (self.overflows, overflows) {
+            (Some(prev), Some(cur)) => cur.saturating_sub(prev),
+            _ => 0,
+        };
+        self.overflows = overflows;
+
+        let mut out = Vec::new();
+        let mut seen = Vec::new();
+        for l in listeners {
+            let name = l.name();
+            if !self.rules.iter().any(|p| p.matches(&name)) {
+                continue;
+            }
+            seen.push(name.clone());
+            let of = |frac: f64| l.backlog as f64 * frac;
+            if self.pressured.contains_key(&name) {
+                if l.depth as f64 <= of(self.threshold.low) {
+                    self.pressured.remove(&name);
+                    out.push(Crossing { listener: l, drops_delta, pressure: false });
+                } else {
+                    self.pressured.insert(name, l);
+                }
+            } else if l.depth as f64 > of(self.threshold.high) || (drops_delta > 0 && l.saturated()) {
+                self.pressured.insert(name, l.clone());
+                out.push(Crossing { listener: l, drops_delta, pressure: true });
+            }
+        }
+
+        let gone: Vec<String> = self.pressured.keys().filter(|n| !seen.contains(n)).cloned().collect();
+        for name in gone {
+            if let Some(l) = self.pressured.remove(&name) {
+                out.push(Crossing { listener: ListenerQueue { depth: 0, ..l }, drops_delta, pressure: false });
+            }
+        }
+        out
+    }
+}
+
+/// Pid and `comm` of the process holding socket `inode`, found among `<proc>/<pid>/fd/*`.
+pub(crate) fn owner(proc_root: &Path, inode: u64) -> Option<(i32, String)> {
+    let target = PathBuf::from(format!("socket:[{inode}]"));
+    for entry in std::fs::read_dir(proc_root).ok()?.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|s| s.parse::<i32>().ok()) else {
+            continue;
+        };
+        let Ok(fds) = std::fs::read_dir(entry.path().join("fd")) else {
+            continue; // gone, or not ours to look at
+        };
+        if fds.flatten().any(|fd| std::fs::read_link(fd.path()).is_ok_and(|l| l == target)) {
+            let comm = std::fs::read_to_string(entry.path().join("comm")).ok()?;
+            return Some((pid, comm.trim_end().to_string()));
+        }
+    }
+    None
+}
@@ -0,0 +1,183 @@
+use crate::{
+    NetNotify, NetNotifyConfig,
+    backlog::{self, BacklogThreshold, BacklogWatch, Crossing, ListenerQueue, ListenerSource},
+    events::NetNotifyEvent,
+};
+use async_trait::async_trait;
+use glob::Pattern;
+use omnitrace_core::{
+    callbacks::{Callback, CallbackHub, CallbackResult},
+    sensor::spawn_sensor,
+};
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::mpsc::channel;
+
+/// sock_diag dumps captured while 5 clients waited on a `listen(4)` socket on
+/// 127.0.0.1:8443 and 3 on `listen(16)` on [::1]:8443.
+fn captured() -> Vec<ListenerQueue> {
+    let mut out = backlog::decode(include_bytes!("../fixtures/sock_diag_v4")).unwrap();
+    out.extend(backlog::decode(include_bytes!("../fixtures/sock_diag_v6")).unwrap());
+    out
+}
+
+fn at(l: &ListenerQueue, depth: u32) -> ListenerQueue {
+    ListenerQueue { depth, ..l.clone() }
+}
+
+fn watch(threshold: BacklogThreshold, rule: &str) -> BacklogWatch {
+    let mut w = BacklogWatch::new(threshold);
+    w.add(Pattern::new(rule).unwrap());
+    w
+}
+
+fn kinds(out: &[Crossing]) -> Vec<(String, u32, bool)> {
+    out.iter().map(|c| (c.listener.name(), c.listener.depth, c.pressure)).collect()
+}
+
+#[test]
+fn decodes_captured_dumps() {
+    let got = captured();
+    let names: Vec<String> = got.iter().map(ListenerQueue::name).collect();
+    assert_eq!(names, ["tcp 127.0.0.1:8443", "tcp 127.0.0.1:48271", "tcp 127.0.0.1:8080", "tcp 0.0.0.0:2024", "tcp [::1]:8443"]);
+
+    let full = &got[0];
+    assert_eq!((full.proto.as_str(), full.depth, full.backlog, full.uid, full.inode), ("tcp", 5, 4, 0, 240257));
+    assert!(full.saturated());
+    assert_eq!((got[2].depth, got[2].backlog), (2, 128));
+    assert_eq!((got[4].proto.as_str(), got[4].depth, got[4].backlog), ("tcp6", 3, 16));
+    assert!(!got[4].saturated());
+}
+
+#[test]
+fn malformed_dumps_are_errors() {
+    let v4 = include_bytes!("../fixtures/sock_diag_v4");
+    assert_eq!(backlog::decode(&v4[..40]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    // NLMSG_ERROR carrying -EPERM
+    let mut err = Vec::new();
+    err.extend_from_slice(&36u32.to_ne_bytes());
+    err.extend_from_slice(&2u16.to_ne_bytes());
+    err.extend_from_slice(&[0; 10]);
+    err.extend_from_slice(&(-libc::EPERM).to_ne_bytes());
+    err.extend_from_slice(&[0; 16]);
+    assert_eq!(backlog::decode(&err).unwrap_err().raw_os_error(), Some(libc::EPERM));
+}
+
+#[test]
+fn pressure_and_clear_follow_the_fractions() {
+    let l = captured().remove(4); // [::1]:8443, backlog 16
+    let mut w = watch(BacklogThreshold::default(), "tcp *:8443");
+
+    let mut script = Vec::new();
+    for depth in [3, 12, 13, 16, 9, 8, 14] {
+        script.push(kinds(&w.update(vec![at(&l, depth)], Some(100))));
+    }
+    let pressure = |d| vec![("tcp [::1]:8443".to_string(), d, true)];
+    let cleared = |d| vec![("tcp [::1]:8443".to_string(), d, false)];
+    // above 12.8 to enter, at or below 8 to leave
+    assert_eq!(script, [vec![], vec![], pressure(13), vec![], vec![], cleared(8), pressure(14)]);
+    assert_eq!(w.pressured(), ["tcp [::1]:8443"]);
+
+    // closing under pressure clears it
+    assert_eq!(kinds(&w.update(Vec::new(), Some(100))), cleared(0));
+    assert!(w.pressured().is_empty());
+}
+
+#[test]
+fn overflows_count_only_for_saturated_listeners() {
+    let got = captured();
+    // fractions out of reach: only overflows while saturated report pressure
+    let mut w = watch(BacklogThreshold { high: 10.0, low: 0.5 }, "tcp 127.0.0.1:*");
+
+    assert!(w.update(got.clone(), Some(5)).is_empty()); // primes the counter
+    assert!(w.update(got.clone(), Some(5)).is_empty()); // saturated, no drops
+    let out = w.update(got.clone(), Some(12));
+    assert_eq!(kinds(&out), [("tcp 127.0.0.1:8443".to_string(), 5, true)]);
+    assert_eq!(out[0].drops_delta, 7);
+
+    // 8080 has 2 of 128 and never saturates; unwatched [::1]:8443 is never reported
+    let drained: Vec<ListenerQueue> = got.iter().map(|l| at(l, 0)).collect();
+    assert_eq!(kinds(&w.update(drained, Some(30))), [("tcp 127.0.0.1:8443".to_string(), 0, false)]);
+}
+
+/// Hands out one scripted dump per tick, the last one forever.
+struct Scripted(Mutex<VecDeque<Vec<ListenerQueue>>>);
+
+impl ListenerSource for Scripted {
+    fn listeners(&self) -> io::Result<Vec<ListenerQueue>> {
+        let mut q = self.0.lock().unwrap();
+        if q.len() > 1 { Ok(q.pop_front().unwrap()) } else { Ok(q.front().cloned().unwrap_or_default()) }
+    }
+}
+
+struct JsonCb;
+
+#[async_trait]
+impl Callback<NetNotifyEvent> for JsonCb {
+    fn mask(&self) -> u64 {
+        u64::MAX
+    }
+
+    async fn call(&self, ev: &NetNotifyEvent) -> Option<CallbackResult> {
+        serde_json::to_value(ev).ok()
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn sensor_reports_pressure_with_the_owner() {
+    let root = std::env::temp_dir().join(format!("netpacket-backlog-ut-{}", std::process::id()));
+    let proc_net = root.join("net");
+    std::fs::create_dir_all(&proc_net).unwrap();
+    std::fs::create_dir_all(root.join("4242/fd")).unwrap();
+    std::fs::write(root.join("4242/comm"), "nginx\n").unwrap();
+    std::os::unix::fs::symlink("socket:[240257]", root.join("4242/fd/6")).unwrap();
+    std::fs::write(proc_net.join("netstat"), include_str!("../fixtures/netstat")).unwrap();
+
+    let got = captured();
+    let drained: Vec<ListenerQueue> = got.iter().map(|l| at(l, 0)).collect();
+    let script = VecDeque::from([got.clone(), got, drained]);
+    let cfg = NetNotifyConfig::default().pulse(Duration::from_millis(10)).proc_net(&proc_net).resolve_listener_owners(true);
+    let mut sensor = NetNotify::new(Some(cfg));
+    sensor.watch_listen("tcp *:8443");
+    sensor.set_listener_source(Scripted(Mutex::new(script)));
+    assert!(sensor.watermark_only());
+
+    let (tx, mut rx) = channel::<CallbackResult>(16);
+    let mut hub = CallbackHub::<NetNotifyEvent>::new();
+    hub.add(JsonCb);
+    hub.set_result_channel(tx);
+    let (handle, task) = spawn_sensor(sensor, Arc::new(hub));
+    let mut events = Vec::new();
+    for _ in 0..2 {
+        events.push(tokio::time::timeout(Duration::from_millis(500), rx.recv()).await.unwrap().unwrap());
+    }
+    handle.shutdown();
+    let _ = task.await;
+    let _ = std::fs::remove_dir_all(&root);
+
+    let p = &events[0]["BacklogPressure"];
+    assert_eq!((p["listener"].as_str(), p["depth"].as_u64(), p["backlog"].as_u64()), (Some("tcp 127.0.0.1:8443"), Some(5), Some(4)));
+    assert_eq!((p["drops_delta"].as_u64(), p["pid"].as_i64(), p["comm"].as_str()), (Some(0), Some(4242), Some("nginx")));
+    let c = &events[1]["BacklogCleared"];
+    assert_eq!((c["listener"].as_str(), c["depth"].as_u64(), c["pid"].as_i64()), (Some("tcp 127.0.0.1:8443"), Some(0), Some(4242)));
+    assert!(rx.try_recv().is_err(), "the [::1]:8443 listener stays below 80%");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn sock_diag_sees_a_live_listener() {
+    use crate::backlog::SockDiag;
+
+    let l = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = l.local_addr().unwrap().port();
+    let _waiting = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+    let found = SockDiag.listeners().unwrap().into_iter().find(|q| q.local.port() == port).unwrap();
+    assert_eq!((found.proto.as_str(), found.depth), ("tcp", 1));
+    assert!(found.backlog > 0);
+}
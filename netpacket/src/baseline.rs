@@ -0,0 +1,95 @@
+use crate::events::ConnKey;
+use crate::netutil::{decode_addr, decode_tcp_state};
+use omnitrace_core::intern::Interned;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    io,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Raw `/proc/net/*` columns of one connection. Everything else is derived on load.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CompactConn {
+    proto: Interned,
+    local: String,
+    remote: Interned,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    state: Option<Interned>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BaselineFile {
+    saved_at: u64, // unix seconds
+    conns: Vec<CompactConn>,
+}
+
+/// Connection set persisted across restarts, so the first tick after startup
+/// can report what changed while the sensor was down.
+#[derive(Debug)]
+pub struct Baseline {
+    pub saved_at: SystemTime,
+    pub conns: HashSet<ConnKey>,
+}
+
+impl Baseline {
+    /// Age of the baseline; `None` if it was saved "in the future" (clock moved back).
+    pub fn age(&self) -> Option<Duration> {
+        self.age_at(SystemTime::now())
+    }
+
+    /// Age as of `now`, see [`Baseline::age`].
+    pub fn age_at(&self, now: SystemTime) -> Option<Duration> {
+        now.duration_since(self.saved_at).ok()
+    }
+}
+
+/// Decode a raw table row into a `ConnKey`, the same way the live reader does.
+pub(crate) fn conn_key(proto: &str, local: &str, remote: &str, state: Option<Interned>) -> ConnKey {
+    let is_v6 = proto.ends_with('6');
+    let state_dec = if proto.starts_with("tcp") { decode_tcp_state(state.as_deref()).map(Interned::new) } else { None };
+    let local_addr = decode_addr(local, is_v6);
+    let remote_addr = decode_addr(remote, is_v6);
+
+    ConnKey {
+        proto: Interned::new(proto),
+        local: local.to_string(),
+        remote: Interned::new(remote),
+        state,
+        local_addr,
+        remote_addr,
+        local_dec: local_addr.map(|a| a.to_string()),
+        remote_dec: remote_addr.map(|a| a.to_string()),
+        state_dec,
+        local_host: None,
+        remote_host: None,
+        remote_sni: None,
+    }
+}
+
+/// Write the compact connection set to `path` (atomically, via a temp file).
+pub fn save(path: &Path, conns: &HashSet<ConnKey>, saved_at: SystemTime) -> io::Result<()> {
+    let file = BaselineFile {
+        saved_at: saved_at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        conns: conns
+            .iter()
+            .map(|c| CompactConn { proto: c.proto.clone(), local: c.local.clone(), remote: c.remote.clone(), state: c.state.clone() })
+            .collect(),
+    };
+
+    let data = serde_json::to_vec(&file).map_err(io::Error::other)?;
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Load a baseline written by [`save`].
+pub fn load(path: &Path) -> io::Result<Baseline> {
+    let file: BaselineFile = serde_json::from_slice(&std::fs::read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    Ok(Baseline {
+        saved_at: UNIX_EPOCH + Duration::from_secs(file.saved_at),
+        conns: file.conns.into_iter().map(|c| conn_key(&c.proto, &c.local, &c.remote, c.state)).collect(),
+    })
+}
@@ -0,0 +1,132 @@
+use crate::events::NetNotifyEvent;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// Kernel-wide counters per table and field, e.g. `["TcpExt"]["ListenDrops"]`.
+pub type Counters = BTreeMap<String, BTreeMap<String, u64>>;
+
+/// Files holding the counters, relative to the `proc_net` directory.
+const FILES: [&str; 2] = ["snmp", "netstat"];
+
+/// Fire CounterSpike when a kernel counter from `/proc/net/snmp` or `/proc/net/netstat`
+/// grows faster than `max_per_sec` between two ticks.
+///
+/// `table` is the line prefix without the colon, e.g. `"Tcp"` (`RetransSegs`, `InCsumErrors`),
+/// `"TcpExt"` (`ListenOverflows`, `ListenDrops`) or `"Udp"` (`RcvbufErrors`).
+///
+/// ```json
+/// { "table": "TcpExt", "field": "ListenDrops", "max_per_sec": 10 }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CounterRule {
+    pub table: String,
+    pub field: String,
+    pub max_per_sec: f64,
+}
+
+impl CounterRule {
+    pub fn new<T: Into<String>, F: Into<String>>(table: T, field: F, max_per_sec: f64) -> Self {
+        Self { table: table.into(), field: field.into(), max_per_sec }
+    }
+
+    /// Name used in logs, e.g. `"TcpExt.ListenDrops"`.
+    pub fn name(&self) -> String {
+        format!("{}.{}", self.table, self.field)
+    }
+}
+
+/// Parse the `/proc/net/snmp` / `/proc/net/netstat` format: pairs of lines with the same
+/// `Table:` prefix, field names first, then values. Non-numeric or negative values
+/// (e.g. `Tcp: MaxConn -1`) and unpaired lines are skipped.
+pub fn parse(txt: &str, out: &mut Counters) {
+    let mut lines = txt.lines().filter_map(|l| l.split_once(':'));
+    while let Some((table, names)) = lines.next() {
+        let Some((vtable, values)) = lines.next() else {
+            break;
+        };
+        if vtable != table {
+            log::debug!("netnotify: unpaired counter line for {table}");
+            continue;
+        }
+
+        let fields = out.entry(table.to_string()).or_default();
+        for (name, value) in names.split_whitespace().zip(values.split_whitespace()) {
+            if let Ok(v) = value.parse() {
+                fields.insert(name.to_string(), v);
+            }
+        }
+    }
+}
+
+/// Read and merge every counter file under `proc_net`. Missing files are skipped.
+pub fn read(proc_net: &Path) -> Counters {
+    let mut out = Counters::new();
+    for f in FILES {
+        if let Ok(txt) = std::fs::read_to_string(proc_net.join(f)) {
+            parse(&txt, &mut out);
+        }
+    }
+    out
+}
+
+pub(crate) struct CounterWatch {
+    rules: Vec<CounterRule>,
+    last: Option<(Counters, Instant)>,
+}
+
+impl CounterWatch {
+    pub(crate) fn new(rules: Vec<CounterRule>) -> Self {
+        Self { rules, last: None }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub(crate) fn is_primed(&self) -> bool {
+        self.last.is_some()
+    }
+
+    /// Feed one tick's counters. The first tick only primes; a counter going backwards
+    /// (wrap or reset) is taken as the new reference without an event.
+    pub(crate) fn update(&mut self, now: Counters, at: Instant) -> Vec<NetNotifyEvent> {
+        let mut events = Vec::new();
+        if let Some((prev, then)) = &self.last {
+            let elapsed = at.duration_since(*then).max(Duration::from_millis(1));
+            for rule in &self.rules {
+                let get = |c: &Counters| c.get(&rule.table).and_then(|t| t.get(&rule.field)).copied();
+                let (Some(previous), Some(current)) = (get(prev), get(&now)) else {
+                    continue;
+                };
+                let Some(delta) = current.checked_sub(previous) else {
+                    continue;
+                };
+
+                let rate = delta as f64 / elapsed.as_secs_f64();
+                if rate > rule.max_per_sec {
+                    events.push(NetNotifyEvent::CounterSpike {
+                        table: rule.table.clone(),
+                        field: rule.field.clone(),
+                        previous,
+                        current,
+                        delta,
+                        rate,
+                        max_per_sec: rule.max_per_sec,
+                    });
+                }
+            }
+        }
+
+        self.last = Some((now, at));
+        events
+    }
+
+    /// Rules naming a counter missing from `counters`, e.g. a typo or an older kernel.
+    pub(crate) fn unknown(&self, counters: &Counters) -> Vec<String> {
+        self.rules.iter().filter(|r| !counters.get(&r.table).is_some_and(|f| f.contains_key(&r.field))).map(CounterRule::name).collect()
+    }
+}
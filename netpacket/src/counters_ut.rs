@@ -0,0 +1,90 @@
+use crate::{
+    counters::{self, CounterRule, CounterWatch, Counters},
+    events::NetNotifyEvent,
+};
+use std::time::{Duration, Instant};
+
+fn fixtures() -> Counters {
+    let mut out = Counters::new();
+    counters::parse(include_str!("../fixtures/snmp"), &mut out);
+    counters::parse(include_str!("../fixtures/netstat"), &mut out);
+    out
+}
+
+fn with(mut c: Counters, table: &str, field: &str, v: u64) -> Counters {
+    c.get_mut(table).unwrap().insert(field.to_string(), v);
+    c
+}
+
+#[test]
+fn parses_snmp_and_netstat() {
+    let c = fixtures();
+    assert_eq!(c["Tcp"]["RetransSegs"], 52817);
+    assert_eq!(c["Tcp"]["InCsumErrors"], 2);
+    assert_eq!(c["Udp"]["IgnoredMulti"], 6321);
+    assert_eq!(c["IcmpMsg"]["OutType3"], 1429);
+    assert_eq!(c["TcpExt"]["ListenDrops"], 5);
+    assert_eq!(c["IpExt"]["InOctets"], 36011873220);
+    assert_eq!(c["MPTcpExt"].len(), 2);
+
+    // MaxConn is -1, not a counter
+    assert!(!c["Tcp"].contains_key("MaxConn"));
+    assert_eq!(c["Tcp"]["RtoMax"], 120000);
+}
+
+#[test]
+fn skips_unpaired_and_short_lines() {
+    let mut c = Counters::new();
+    counters::parse("Ip: Forwarding DefaultTTL\nTcp: 1 200\nUdp: InDatagrams NoPorts\nUdp: 7\n\n", &mut c);
+    assert!(!c.contains_key("Ip"));
+    assert!(!c.contains_key("Tcp"));
+    assert_eq!(c["Udp"].len(), 1);
+    assert_eq!(c["Udp"]["InDatagrams"], 7);
+}
+
+#[test]
+fn reports_rate_over_threshold() {
+    let mut w = CounterWatch::new(vec![CounterRule::new("TcpExt", "ListenDrops", 10.0), CounterRule::new("Tcp", "RetransSegs", 1000.0)]);
+    let t0 = Instant::now();
+    let base = fixtures();
+    assert!(w.update(base.clone(), t0).is_empty());
+
+    // 15 drops in 2s is under 10/s, retransmits still under 1000/s
+    let next = with(with(base.clone(), "TcpExt", "ListenDrops", 20), "Tcp", "RetransSegs", 53817);
+    assert!(w.update(next.clone(), t0 + Duration::from_secs(2)).is_empty());
+
+    let spike = with(next, "TcpExt", "ListenDrops", 45);
+    let ev = w.update(spike, t0 + Duration::from_secs(3));
+    let [NetNotifyEvent::CounterSpike { table, field, previous, current, delta, rate, max_per_sec }] = ev.as_slice() else {
+        panic!("expected one spike, got {ev:?}");
+    };
+    assert_eq!((table.as_str(), field.as_str()), ("TcpExt", "ListenDrops"));
+    assert_eq!((*previous, *current, *delta), (20, 45, 25));
+    assert_eq!((*rate, *max_per_sec), (25.0, 10.0));
+}
+
+#[test]
+fn counter_reset_rebases_silently() {
+    let mut w = CounterWatch::new(vec![CounterRule::new("Tcp", "RetransSegs", 1.0)]);
+    let t0 = Instant::now();
+    w.update(fixtures(), t0);
+    assert!(w.update(with(fixtures(), "Tcp", "RetransSegs", 3), t0 + Duration::from_secs(1)).is_empty());
+    assert_eq!(w.update(with(fixtures(), "Tcp", "RetransSegs", 13), t0 + Duration::from_secs(2)).len(), 1);
+}
+
+#[test]
+fn unknown_counters_are_listed() {
+    let w = CounterWatch::new(vec![
+        CounterRule::new("TcpExt", "ListenDrops", 1.0),
+        CounterRule::new("TcpExt", "ListenDorps", 1.0),
+        CounterRule::new("Sctp", "InSCTPPacks", 1.0),
+    ]);
+    assert_eq!(w.unknown(&fixtures()), vec!["TcpExt.ListenDorps", "Sctp.InSCTPPacks"]);
+}
+
+#[test]
+fn rules_deserialize() {
+    let r: CounterRule = serde_json::from_str(r#"{ "table": "TcpExt", "field": "ListenDrops", "max_per_sec": 10 }"#).unwrap();
+    assert_eq!(r, CounterRule::new("TcpExt", "ListenDrops", 10.0));
+    assert_eq!(r.name(), "TcpExt.ListenDrops");
+}
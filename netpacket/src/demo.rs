@@ -0,0 +1,130 @@
+//! The wiring of `examples/netpacket.rs` as functions a program or a test can call: the
+//! sensor configured from the environment, a callback printing events, and [`run`].
+//!
+//! - `NETPACKET_PROC_NET`: the directory with the connection tables (default: the bundled
+//!   `fixtures`, `/proc/net` watches this machine)
+//! - `NETPACKET_WATCH` and `NETPACKET_IGNORE`: patterns for [`NetNotify::add`] and
+//!   [`NetNotify::ignore`], comma separated (default: `*`, and `udp * *`)
+//! - `NETPACKET_DNS`: `1` turns on reverse DNS (default: off)
+//! - `NETPACKET_PULSE_MS`: the pulse (default: 1000)
+//! - `SNI_IFACE`: the interface to sniff TLS SNI on (default: all UP non-loopback ones)
+//!
+//! Swap the `tcp` table of a copy of the fixtures while the example runs to see events.
+
+use crate::events::{NetNotifyEvent, NetNotifyMask};
+use crate::{NetNotify, NetNotifyConfig};
+use async_trait::async_trait;
+use omnitrace_core::{
+    callbacks::{Callback, CallbackHub, CallbackResult},
+    format::Style,
+    sensor,
+};
+use std::{future::Future, path::PathBuf, time::Duration};
+
+/// Where the example reads from and what it watches, see the [module docs](self).
+#[derive(Clone, Debug)]
+pub struct DemoConfig {
+    pub proc_net: PathBuf,
+    pub watch: Vec<String>,
+    pub ignore: Vec<String>,
+    pub dns: bool,
+    pub pulse: Duration,
+    pub sni_interface: Option<String>,
+}
+
+impl Default for DemoConfig {
+    fn default() -> Self {
+        Self {
+            proc_net: PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures")),
+            watch: vec!["*".to_string()],
+            ignore: vec!["udp * *".to_string()],
+            dns: false,
+            pulse: Duration::from_secs(1),
+            sni_interface: None,
+        }
+    }
+}
+
+impl DemoConfig {
+    /// The defaults, overridden by the variables that are set.
+    pub fn from_env() -> Self {
+        let var = |name| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let list = |v: String| v.split(',').map(str::trim).filter(|p| !p.is_empty()).map(str::to_string).collect();
+        let mut cfg = Self::default();
+        if let Some(p) = var("NETPACKET_PROC_NET") {
+            cfg.proc_net = PathBuf::from(p);
+        }
+        if let Some(w) = var("NETPACKET_WATCH") {
+            cfg.watch = list(w);
+        }
+        if let Some(i) = var("NETPACKET_IGNORE") {
+            cfg.ignore = list(i);
+        }
+        if let Some(d) = var("NETPACKET_DNS") {
+            cfg.dns = d == "1";
+        }
+        if let Some(ms) = var("NETPACKET_PULSE_MS").and_then(|v| v.parse().ok()) {
+            cfg.pulse = Duration::from_millis(ms);
+        }
+        cfg.sni_interface = var("SNI_IFACE");
+        cfg
+    }
+}
+
+/// The sensor as configured. Host patterns turn DNS on regardless of `dns`.
+pub fn sensor(cfg: &DemoConfig) -> NetNotify {
+    let mut nc = NetNotifyConfig::default().pulse(cfg.pulse).proc_net(&cfg.proc_net);
+    if let Some(iface) = &cfg.sni_interface {
+        nc = nc.sni_interface(iface);
+    }
+    let mut sensor = NetNotify::new(Some(nc)).dns(cfg.dns).dns_ttl(Duration::from_secs(5));
+    for p in &cfg.watch {
+        sensor.add(p);
+    }
+    for p in &cfg.ignore {
+        sensor.ignore(p);
+    }
+    sensor
+}
+
+/// Prints events one per line (see [`omnitrace_core::format`]) and returns connection events
+/// as JSON, other events as they serialize.
+pub struct JsonCb;
+
+#[async_trait]
+impl Callback<NetNotifyEvent> for JsonCb {
+    fn mask(&self) -> u64 {
+        (NetNotifyMask::OPENED | NetNotifyMask::CLOSED).bits()
+    }
+
+    async fn call(&self, ev: &NetNotifyEvent) -> Option<CallbackResult> {
+        println!("{}", Style::for_stdout().short(ev));
+        let (evname, conn, offline) = match ev {
+            NetNotifyEvent::Opened { conn, offline, .. } => ("opened", conn, *offline),
+            NetNotifyEvent::Closed { conn, offline, .. } => ("closed", conn, *offline),
+            other => return serde_json::to_value(other).ok(),
+        };
+
+        Some(serde_json::json!({
+            "event": evname,
+            "offline": offline,
+            "conn": {
+                "proto": conn.proto,
+                "local_raw": conn.local,
+                "remote_raw": conn.remote,
+                "local": conn.local_dec,
+                "remote": conn.remote_dec,
+                "remote_host": conn.remote_host,
+                "state": conn.state_dec,
+                "remote_sni": conn.remote_sni,
+            }
+        }))
+    }
+}
+
+/// Run `sensor` with [`JsonCb`] added to `hub`, printing the results, until `stop`
+/// resolves or the sensor ends, see [`sensor::run_until`].
+pub async fn run<F: Future>(sensor: NetNotify, mut hub: CallbackHub<NetNotifyEvent>, stop: F) -> bool {
+    hub.add(JsonCb);
+    sensor::run_until(sensor, hub, stop, |r| println!("RESULT: {r}")).await
+}
@@ -0,0 +1,56 @@
+use crate::{
+    demo::{self, DemoConfig},
+    events::{NetNotifyEvent, NetNotifyMask},
+};
+use async_trait::async_trait;
+use omnitrace_core::callbacks::{Callback, CallbackHub, CallbackResult};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+struct Recorder(Arc<Mutex<Vec<NetNotifyEvent>>>);
+
+#[async_trait]
+impl Callback<NetNotifyEvent> for Recorder {
+    fn mask(&self) -> u64 {
+        NetNotifyMask::all().bits()
+    }
+
+    async fn call(&self, ev: &NetNotifyEvent) -> Option<CallbackResult> {
+        self.0.lock().unwrap().push(ev.clone());
+        None
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn example_wiring_reports_a_new_connection_and_stops_in_time() {
+    let proc_net = std::env::temp_dir().join(format!("netpacket-demo-ut-{}", std::process::id()));
+    std::fs::create_dir_all(&proc_net).unwrap();
+    let table = std::fs::read_to_string(DemoConfig::default().proc_net.join("tcp")).unwrap();
+    std::fs::write(proc_net.join("tcp"), &table).unwrap();
+
+    let cfg = DemoConfig { proc_net: proc_net.clone(), pulse: Duration::from_millis(10), ..DemoConfig::default() };
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(seen.clone()));
+
+    // 10.0.0.5:40001 -> 8.8.8.8:53 opens
+    let dir = proc_net.clone();
+    let stop = async move {
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let row = "   3: 0500000A:9C41 08080808:0035 01 00000000:00000000 00:00000000 00000000  1000        0 48999 1\n";
+        std::fs::write(dir.join("tcp.tmp"), table + row).unwrap();
+        std::fs::rename(dir.join("tcp.tmp"), dir.join("tcp")).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    };
+    let stopped = tokio::time::timeout(Duration::from_secs(5), demo::run(demo::sensor(&cfg), hub, stop)).await;
+    let _ = std::fs::remove_dir_all(&proc_net);
+
+    assert!(stopped.expect("shut down in time"));
+    let seen = seen.lock().unwrap();
+    let opened: Vec<_> =
+        seen.iter().filter_map(|ev| if let NetNotifyEvent::Opened { conn, .. } = ev { conn.remote_dec.as_deref() } else { None }).collect();
+    assert_eq!(opened, vec!["8.8.8.8:53"], "{seen:?}");
+}
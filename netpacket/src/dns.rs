@@ -0,0 +1,172 @@
+//! Which remotes get reverse-resolved. On an internet-facing host every scanner probing a port
+//! is a new remote address, and resolving them all loads the resolver and fills the cache with
+//! names nobody asked for. See [`crate::NetNotifyConfig::dns_policy`].
+
+use crate::events::ConnKey;
+use std::{collections::HashSet, net::IpAddr, str::FromStr};
+
+/// Ranges that are never routed on the internet: private, loopback, link-local, CGNAT,
+/// documentation, multicast and reserved, IPv4 and IPv6. Their PTR records, if any, are local
+/// knowledge. See [`DnsPolicy::never_bogons`].
+pub const BOGONS: &[&str] = &[
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.0.0.0/24",
+    "192.0.2.0/24",
+    "192.168.0.0/16",
+    "198.18.0.0/15",
+    "198.51.100.0/24",
+    "203.0.113.0/24",
+    "224.0.0.0/3",
+    "::/128",
+    "::1/128",
+    "100::/64",
+    "2001:db8::/32",
+    "fc00::/7",
+    "fe80::/10",
+    "ff00::/8",
+];
+
+/// An address range, e.g. `"10.0.0.0/8"` or `"2001:db8::/32"`. A bare address is a single host.
+/// IPv4-mapped IPv6 addresses are matched as IPv4.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cidr {
+    net: IpAddr,
+    prefix: u8,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid CIDR {0:?}")]
+pub struct InvalidCidr(pub String);
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.net, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                Self::masked(net.to_bits().into(), self.prefix, 32) == Self::masked(ip.to_bits().into(), self.prefix, 32)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => Self::masked(net.to_bits(), self.prefix, 128) == Self::masked(ip.to_bits(), self.prefix, 128),
+            _ => false,
+        }
+    }
+
+    /// The top `prefix` bits of a `width`-bit address.
+    fn masked(bits: u128, prefix: u8, width: u8) -> u128 {
+        if prefix == 0 { 0 } else { bits >> (width - prefix) }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = InvalidCidr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidCidr(s.to_string());
+        let (addr, prefix) = s.trim().split_once('/').map_or((s.trim(), None), |(a, p)| (a, Some(p)));
+        let net = addr.parse::<IpAddr>().map_err(|_| invalid())?.to_canonical();
+        let width = if net.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p.parse::<u8>().ok().filter(|p| *p <= width).ok_or_else(invalid)?,
+            None => width,
+        };
+        Ok(Self { net, prefix })
+    }
+}
+
+/// Which side opened a connection, judged from the table: inbound if its local port has a
+/// listening socket (a TCP listener, or an unconnected UDP socket) of the same protocol.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// Options for [`crate::NetNotifyConfig::dns_policy`]. The default resolves every remote.
+/// The policy only decides about remotes: local addresses are resolved as selected by
+/// [`crate::DnsTargets`].
+#[derive(Clone, Debug, Default)]
+pub struct DnsPolicy {
+    outbound_only: bool,
+    host_rules_only: bool,
+    never: Vec<Cidr>,
+}
+
+impl DnsPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve the remotes of outbound connections only. Inbound ones go out with their
+    /// address alone and do not match host patterns.
+    pub fn outbound_only(mut self, on: bool) -> Self {
+        self.outbound_only = on;
+        self
+    }
+
+    /// Resolve only when a remote host pattern (`add("*.example.com")`, `ignore("*.cdn.net")`)
+    /// has to be decided. Without host patterns nothing is resolved, so events carry no
+    /// `remote_host` unless the name is already cached.
+    pub fn host_rules_only(mut self, on: bool) -> Self {
+        self.host_rules_only = on;
+        self
+    }
+
+    /// Never resolve remotes in `cidr`, e.g. own ranges whose names are known anyway.
+    pub fn never(mut self, cidr: Cidr) -> Self {
+        self.never.push(cidr);
+        self
+    }
+
+    /// Never resolve remotes in [`BOGONS`].
+    pub fn never_bogons(mut self) -> Self {
+        self.never.extend(BOGONS.iter().map(|c| c.parse::<Cidr>().expect("valid bogon range")));
+        self
+    }
+
+    /// Whether the remote `ip` of a connection going `direction` is resolved; `host_rules`
+    /// tells if any remote host pattern is configured.
+    pub(crate) fn resolves(&self, ip: IpAddr, direction: Direction, host_rules: bool) -> bool {
+        (!self.outbound_only || direction == Direction::Outbound)
+            && (!self.host_rules_only || host_rules)
+            && !self.never.iter().any(|c| c.contains(ip))
+    }
+}
+
+/// Local ports with a listening socket, per protocol (`true` for TCP).
+#[derive(Debug, Default)]
+pub(crate) struct Listening(HashSet<(bool, u16)>);
+
+impl Listening {
+    pub(crate) fn of<'a>(conns: impl Iterator<Item = &'a ConnKey>) -> Self {
+        Self(
+            conns
+                .filter(|c| c.remote_addr.is_some_and(|a| a.port() == 0))
+                .filter_map(|c| Some((c.proto.starts_with("tcp"), c.local_addr?.port())))
+                .collect(),
+        )
+    }
+
+    pub(crate) fn direction(&self, c: &ConnKey) -> Direction {
+        match c.local_addr {
+            Some(local) if self.0.contains(&(c.proto.starts_with("tcp"), local.port())) => Direction::Inbound,
+            _ => Direction::Outbound,
+        }
+    }
+}
+
+/// Reverse DNS lookups. The default is [`SystemResolver`]; tests count their lookups.
+pub trait Resolver: Send + Sync {
+    fn reverse(&self, ip: IpAddr) -> Option<String>;
+}
+
+/// `getnameinfo(3)`, blocking.
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn reverse(&self, ip: IpAddr) -> Option<String> {
+        crate::netutil::reverse_dns(ip)
+    }
+}
@@ -0,0 +1,136 @@
+use crate::{
+    NetNotify, NetNotifyConfig,
+    dns::{BOGONS, Cidr, Direction, DnsPolicy, Listening, Resolver},
+    events::{ConnKey, NetNotifyEvent},
+};
+use std::{
+    collections::HashSet,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+
+/// Records every lookup; `93.184.216.34` is `edge.example.com`, anything else a made-up ISP name.
+#[derive(Clone, Default)]
+struct Counting(Arc<Mutex<Vec<IpAddr>>>);
+
+impl Resolver for Counting {
+    fn reverse(&self, ip: IpAddr) -> Option<String> {
+        self.0.lock().unwrap().push(ip);
+        Some(if ip.to_string() == "93.184.216.34" { "edge.example.com".to_string() } else { format!("{ip}.dyn.isp.net") })
+    }
+}
+
+impl Counting {
+    fn lookups(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+}
+
+/// Listeners on 22 and 443, three outbound connections (one to a private address) and, per
+/// tick, 40 scanners probing ssh from fresh addresses.
+fn table(tick: u8) -> HashSet<ConnKey> {
+    let mut t: HashSet<ConnKey> =
+        [ConnKey::test("tcp", "0.0.0.0:22", "0.0.0.0:0"), ConnKey::test("tcp", "0.0.0.0:443", "0.0.0.0:0")].into_iter().collect();
+    if tick > 0 {
+        t.insert(ConnKey::test("tcp", "10.0.0.5:40000", "93.184.216.34:443"));
+        t.insert(ConnKey::test("tcp", "10.0.0.5:40001", "10.0.0.9:5432"));
+        t.insert(ConnKey::test("tcp", "10.0.0.5:40002", "140.82.112.3:443"));
+    }
+    for i in 0..40 {
+        t.insert(ConnKey::test("tcp", "10.0.0.5:22", &format!("45.155.{tick}.{}:{}", i + 1, 50000 + i as u16)));
+    }
+    t
+}
+
+/// Run three ticks of scanning with `setup` applied; lookups made and connections opened.
+fn scan_heavy(policy: DnsPolicy, setup: impl FnOnce(&mut NetNotify)) -> (usize, Vec<String>) {
+    let resolver = Counting::default();
+    let mut sensor = NetNotify::new(Some(NetNotifyConfig::default().dns_policy(policy))).dns(true);
+    sensor.set_resolver(resolver.clone());
+    setup(&mut sensor);
+    sensor.last = table(0);
+    sensor.is_primed = true;
+
+    let mut opened = Vec::new();
+    for tick in 1..=3 {
+        let now = table(tick);
+        for ev in sensor.changes(&now, false) {
+            if let NetNotifyEvent::Opened { conn, .. } = ev {
+                opened.push(conn.remote_host.unwrap_or_else(|| conn.remote_addr.unwrap().ip().to_string()));
+            }
+        }
+        sensor.last = now;
+    }
+    opened.sort();
+    (resolver.lookups(), opened)
+}
+
+#[test]
+fn every_remote_is_resolved_by_default() {
+    let (lookups, opened) = scan_heavy(DnsPolicy::default(), |_| {});
+    // 3 × 40 scanners resolved when opened (closing hits the cache), the 40 of the first table
+    // when closed, and 3 outbound
+    assert_eq!(lookups, 163);
+    assert_eq!(opened.len(), 123);
+    assert!(opened.contains(&"edge.example.com".to_string()));
+}
+
+#[test]
+fn outbound_only_skips_the_scanners() {
+    let (lookups, opened) = scan_heavy(DnsPolicy::new().outbound_only(true), |_| {});
+    assert_eq!(lookups, 3);
+    assert_eq!(opened.len(), 123, "inbound connections are still reported, by address");
+    assert!(opened.contains(&"45.155.1.1".to_string()));
+
+    let (lookups, _) = scan_heavy(DnsPolicy::new().outbound_only(true).never("10.0.0.0/8".parse().unwrap()), |_| {});
+    assert_eq!(lookups, 2);
+    let (lookups, _) = scan_heavy(DnsPolicy::new().never_bogons(), |_| {});
+    assert_eq!(lookups, 162);
+}
+
+#[test]
+fn host_rules_only_resolves_the_candidates_of_host_rules() {
+    // nothing needs a name
+    let (lookups, opened) = scan_heavy(DnsPolicy::new().host_rules_only(true), |_| {});
+    assert_eq!((lookups, opened.len()), (0, 123));
+
+    // the scanners are dropped by an address rule before anything is resolved
+    let host_rule = |s: &mut NetNotify| {
+        s.add("*.example.com");
+        s.ignore("tcp *:22 *");
+    };
+    let (lookups, opened) = scan_heavy(DnsPolicy::new().host_rules_only(true), host_rule);
+    assert_eq!((lookups, opened), (3, vec!["edge.example.com".to_string()]));
+    // whatever the policy
+    let (lookups, _) = scan_heavy(DnsPolicy::default(), host_rule);
+    assert_eq!(lookups, 3);
+}
+
+#[test]
+fn direction_comes_from_the_listeners_of_the_same_protocol() {
+    let t = table(1);
+    let listening = Listening::of(t.iter());
+    assert_eq!(listening.direction(&ConnKey::test("tcp", "10.0.0.5:22", "45.155.1.1:50000")), Direction::Inbound);
+    assert_eq!(listening.direction(&ConnKey::test("tcp", "10.0.0.5:40000", "93.184.216.34:443")), Direction::Outbound);
+    assert_eq!(listening.direction(&ConnKey::test("udp", "10.0.0.5:22", "45.155.1.1:50000")), Direction::Outbound);
+}
+
+#[test]
+fn cidrs_match_by_prefix() {
+    let c: Cidr = "10.0.0.0/8".parse().unwrap();
+    assert!(c.contains("10.200.1.1".parse().unwrap()));
+    assert!(c.contains("::ffff:10.1.2.3".parse().unwrap()), "IPv4-mapped addresses match as IPv4");
+    assert!(!c.contains("11.0.0.1".parse().unwrap()));
+    assert!(!c.contains("::a00:1".parse().unwrap()));
+
+    let host: Cidr = "192.0.2.7".parse().unwrap();
+    assert!(host.contains("192.0.2.7".parse().unwrap()) && !host.contains("192.0.2.8".parse().unwrap()));
+    assert!("::/0".parse::<Cidr>().unwrap().contains("2001:db8::1".parse().unwrap()));
+    let v6: Cidr = "2001:db8::/32".parse().unwrap();
+    assert!(v6.contains("2001:db8:ffff::1".parse().unwrap()) && !v6.contains("2001:db9::1".parse().unwrap()));
+
+    for bad in ["10.0.0.0/33", "10.0.0/8", "example.com", "::/129", ""] {
+        assert!(bad.parse::<Cidr>().is_err(), "{bad}");
+    }
+    assert!(BOGONS.iter().all(|b| b.parse::<Cidr>().is_ok()));
+}
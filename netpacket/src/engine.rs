@@ -0,0 +1,89 @@
+//! Connection change detection without a runtime: read the connection tables and diff them
+//! against the last read.
+//!
+//! [`crate::NetNotify`] runs this on every tick, then filters and enriches what it found. A
+//! program without tokio (built with `default-features = false`) drives a [`ConnDiffer`] from
+//! its own loop instead and gets the same `Opened` and `Closed` events, for every connection
+//! and without host names:
+//!
+//! ```ignore
+//! let mut tables = TableReader::default();
+//! let mut differ = ConnDiffer::new();
+//! loop {
+//!     let (conns, _errors) = tables.read(Path::new("/proc/net"));
+//!     for ev in differ.feed(conns) {
+//!         println!("{}", ev.topic());
+//!     }
+//!     std::thread::sleep(Duration::from_secs(1));
+//! }
+//! ```
+
+use crate::{
+    events::{ConnKey, NetNotifyEvent},
+    snapshot,
+};
+use omnitrace_core::labels::Labels;
+use std::collections::HashSet;
+
+/// Diffs successive connection tables, see the [module docs](self).
+#[derive(Default)]
+pub struct ConnDiffer {
+    // last table, and whether that is a baseline to diff against yet
+    last: HashSet<ConnKey>,
+    primed: bool,
+    suppressed: u64,
+}
+
+impl ConnDiffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take the next table as a new baseline instead of diffing it, e.g. after a suspend.
+    pub fn reprime(&mut self) {
+        self.primed = false;
+    }
+
+    /// Opened/Closed pairs dropped as read-skew artifacts so far, see
+    /// [`crate::NetNotify::skew_stats`].
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed
+    }
+
+    /// The events for the table going from the last one fed to `now`, as read by
+    /// [`snapshot::TableReader`]. The first table is the baseline and yields none.
+    pub fn feed(&mut self, now: HashSet<ConnKey>) -> Vec<NetNotifyEvent> {
+        let mut out = Vec::new();
+        if self.primed {
+            let (opened, closed, suppressed) = changes(&self.last, &now);
+            self.suppressed += suppressed as u64;
+            out = events(opened, closed, false);
+        }
+        self.last = now;
+        self.primed = true;
+        out
+    }
+}
+
+/// Connections opened and closed going from `last` to `now`, each sorted, without the pairs
+/// that are read skew (see [`snapshot::reconcile`]), and the number of those pairs.
+pub(crate) fn changes(last: &HashSet<ConnKey>, now: &HashSet<ConnKey>) -> (Vec<ConnKey>, Vec<ConnKey>, usize) {
+    let mut opened: Vec<ConnKey> = now.difference(last).cloned().collect();
+    let mut closed: Vec<ConnKey> = last.difference(now).cloned().collect();
+    let suppressed = snapshot::reconcile(&mut opened, &mut closed);
+    for conns in [&mut opened, &mut closed] {
+        conns.sort_by(|a, b| (&a.proto, &a.local, &a.remote).cmp(&(&b.proto, &b.local, &b.remote)));
+    }
+    (opened, closed, suppressed)
+}
+
+/// Opened for the `opened` connections but those going away anyway, then Closed for `closed`.
+pub(crate) fn events(opened: Vec<ConnKey>, closed: Vec<ConnKey>, offline: bool) -> Vec<NetNotifyEvent> {
+    let opened = opened.into_iter().filter(|c| !is_time_wait(c)).map(|conn| NetNotifyEvent::Opened { conn, offline, labels: Labels::default() });
+    opened.chain(closed.into_iter().map(|conn| NetNotifyEvent::Closed { conn, offline, labels: Labels::default() })).collect()
+}
+
+/// A closing TCP connection lingering in the table, never reported as opened.
+pub(crate) fn is_time_wait(c: &ConnKey) -> bool {
+    c.proto.starts_with("tcp") && c.state_dec.as_deref() == Some("TIME_WAIT")
+}
@@ -0,0 +1,107 @@
+use crate::{
+    baseline,
+    engine::ConnDiffer,
+    events::{ConnKey, NetNotifyEvent},
+    netutil::encode_addr,
+};
+use std::collections::HashSet;
+
+/// A `/proc/net/tcp` row: local, remote and state, as in the table.
+pub(crate) type Row = (String, String, String);
+
+/// Six sockets to 93.184.216.34:8080 (not HTTPS, so no SNI is looked up), each absent, ESTABLISHED, CLOSE_WAIT or TIME_WAIT.
+pub(crate) fn random_rows(rng: &mut fastrand::Rng) -> Vec<Row> {
+    (0..6)
+        .filter_map(|i| {
+            let st = ["01", "08", "06"].get(rng.usize(..4))?;
+            Some((format!("0500000A:{:04X}", 40000 + i), "22D8B85D:1F90".to_string(), st.to_string()))
+        })
+        .collect()
+}
+
+/// The connections of `rows` as read from a tcp table.
+pub(crate) fn conns(rows: &[Row]) -> HashSet<ConnKey> {
+    rows.iter().map(|(l, r, st)| baseline::conn_key("tcp", l, r, Some(st.into()))).collect()
+}
+
+fn tcp(local: &str, remote: &str, state: &str) -> ConnKey {
+    let raw = |a: &str| encode_addr(a.parse().unwrap());
+    baseline::conn_key("tcp", &raw(local), &raw(remote), Some(state.into()))
+}
+
+/// The events as (topic, local address).
+fn summary(events: &[NetNotifyEvent]) -> Vec<(&'static str, String)> {
+    events
+        .iter()
+        .map(|ev| match ev {
+            NetNotifyEvent::Opened { conn, offline, .. } | NetNotifyEvent::Closed { conn, offline, .. } => {
+                assert!(!offline);
+                (ev.topic(), conn.local_dec.clone().unwrap())
+            }
+            other => panic!("unexpected {other:?}"),
+        })
+        .collect()
+}
+
+#[test]
+fn feed_reports_opened_then_closed_in_order() {
+    let mut differ = ConnDiffer::new();
+    let first = HashSet::from([tcp("10.0.0.5:40001", "1.1.1.1:443", "01"), tcp("10.0.0.5:40003", "1.1.1.1:443", "01")]);
+    assert!(differ.feed(first).is_empty(), "the baseline yields nothing");
+
+    let now = HashSet::from([tcp("10.0.0.5:40004", "1.1.1.1:443", "01"), tcp("10.0.0.5:40002", "1.1.1.1:443", "01")]);
+    assert_eq!(
+        summary(&differ.feed(now)),
+        [
+            ("net.conn.opened", "10.0.0.5:40002".to_string()),
+            ("net.conn.opened", "10.0.0.5:40004".to_string()),
+            ("net.conn.closed", "10.0.0.5:40001".to_string()),
+            ("net.conn.closed", "10.0.0.5:40003".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn state_changes_and_time_wait_are_not_openings() {
+    let mut differ = ConnDiffer::new();
+    differ.feed(HashSet::from([tcp("10.0.0.5:40001", "1.1.1.1:443", "01")]));
+
+    // ESTABLISHED -> CLOSE_WAIT is the same connection, one that opens in TIME_WAIT is none
+    let now = HashSet::from([tcp("10.0.0.5:40001", "1.1.1.1:443", "08"), tcp("10.0.0.5:40002", "1.1.1.1:443", "06")]);
+    assert!(differ.feed(now).is_empty());
+    assert_eq!(differ.suppressed(), 1);
+
+    let now = HashSet::from([tcp("10.0.0.5:40002", "1.1.1.1:443", "06")]);
+    assert_eq!(summary(&differ.feed(now)), [("net.conn.closed", "10.0.0.5:40001".to_string())]);
+}
+
+#[test]
+fn reprimed_tables_are_a_new_baseline() {
+    let mut differ = ConnDiffer::new();
+    differ.feed(HashSet::new());
+    differ.reprime();
+    assert!(differ.feed(HashSet::from([tcp("10.0.0.5:40001", "1.1.1.1:443", "01")])).is_empty());
+    assert_eq!(summary(&differ.feed(HashSet::new())), [("net.conn.closed", "10.0.0.5:40001".to_string())]);
+}
+
+#[test]
+fn random_tables_diff_against_the_last_one() {
+    let mut rng = fastrand::Rng::with_seed(759);
+    let mut differ = ConnDiffer::new();
+    let mut last = conns(&random_rows(&mut rng));
+    differ.feed(last.clone());
+    for _ in 0..200 {
+        let now = conns(&random_rows(&mut rng));
+        for ev in differ.feed(now.clone()) {
+            match ev {
+                NetNotifyEvent::Opened { conn, .. } => {
+                    assert!(now.contains(&conn) && !last.contains(&conn) && conn.state_dec.as_deref() != Some("TIME_WAIT"), "{conn:?}")
+                }
+                NetNotifyEvent::Closed { conn, .. } => assert!(last.contains(&conn) && !now.contains(&conn), "{conn:?}"),
+                other => panic!("unexpected {other:?}"),
+            }
+        }
+        assert!(differ.feed(now.clone()).is_empty(), "the same table again");
+        last = now;
+    }
+}
@@ -0,0 +1,41 @@
+use omnitrace_core::error::SensorError;
+use std::{io, path::PathBuf, time::Duration};
+
+/// What NetNotify runs past, recorded in [`crate::NetNotify::diagnostics`].
+#[derive(Debug, thiserror::Error)]
+pub enum NetNotifyError {
+    /// Normal for `tcp6` and `udp6` on kernels without IPv6, so only logged once.
+    #[error("{} is missing", path.display())]
+    TableMissing { path: PathBuf },
+    #[error("cannot read {}: {source}", path.display())]
+    TableRead {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// Lines without a local and a remote address, skipped.
+    #[error("{}: {malformed} malformed line(s), first at line {line}", path.display())]
+    TableParse { path: PathBuf, line: usize, malformed: usize },
+    #[error("cannot read listeners: {0}")]
+    Listeners(#[source] io::Error),
+    #[error("baseline {}: {source}", path.display())]
+    Baseline {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("learned connections {}: {source}", path.display())]
+    Learned {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("baseline {} is stale (age {age:?}, max {max:?}), priming from scratch", path.display())]
+    StaleBaseline { path: PathBuf, age: Option<Duration>, max: Duration },
+}
+
+impl From<NetNotifyError> for SensorError {
+    fn from(e: NetNotifyError) -> Self {
+        SensorError::sensor("netnotify", e)
+    }
+}
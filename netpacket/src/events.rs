@@ -1,44 +1,625 @@
+use crate::learn::Tuple;
+use crate::netutil::encode_addr;
+use crate::summary::{Dimension, DimensionSummary};
 use bitflags::bitflags;
+use omnitrace_core::{
+    anomaly::ParseAnomaly,
+    fields::{EventFields, FieldValue},
+    intern::Interned,
+    labels::Labels,
+    topics::{self, Topic, Topics},
+};
 use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, net::SocketAddr, time::Duration};
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ConnKey {
-    pub proto: String, // "tcp","udp","tcp6","udp6"
+    pub proto: Interned, // "tcp","udp","tcp6","udp6"
 
     // Raw data
-    pub local: String,         // "ip:port"
-    pub remote: String,        // "ip:port"
-    pub state: Option<String>, // tcp state; udp None
+    pub local: String,           // "ip:port"
+    pub remote: Interned,        // "ip:port"
+    pub state: Option<Interned>, // tcp state; udp None
 
     // decoded (best-effort)
-    pub local_dec: Option<String>,  // "192.168.2.136:57843"
-    pub remote_dec: Option<String>, // "172.64.155.209:443"
-    pub state_dec: Option<String>,  // "ESTABLISHED" etc (tcp only)
+    #[serde(default)]
+    pub local_addr: Option<SocketAddr>,
+    #[serde(default)]
+    pub remote_addr: Option<SocketAddr>,
+    pub local_dec: Option<String>,   // local_addr as a string, "192.168.2.136:57843" or "[::1]:443"
+    pub remote_dec: Option<String>,  // remote_addr as a string, "172.64.155.209:443"
+    pub state_dec: Option<Interned>, // "ESTABLISHED" etc (tcp only)
 
     pub local_host: Option<String>,
     pub remote_host: Option<String>,
     pub remote_sni: Option<String>,
 }
 
+// Reconnected carries two connections; boxing them would only make matching on it clumsier.
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum NetNotifyEvent {
-    Opened { conn: ConnKey },
-    Closed { conn: ConnKey },
+    // `offline` is set on changes that happened while the sensor was down,
+    // detected by diffing against the persisted baseline on startup.
+    Opened {
+        conn: ConnKey,
+        #[serde(default)]
+        offline: bool,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    Closed {
+        conn: ConnKey,
+        #[serde(default)]
+        offline: bool,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    // `counts` holds all socket counts per "<proto>:<state>" at the time of the event.
+    WatermarkExceeded {
+        watermark: String,
+        count: usize,
+        threshold: usize,
+        counts: BTreeMap<String, usize>,
+    },
+    WatermarkCleared {
+        watermark: String,
+        count: usize,
+        threshold: usize,
+        counts: BTreeMap<String, usize>,
+    },
+    /// A watched /proc/sys value changed, e.g. `net/ipv4/tcp_mem`.
+    LimitChanged {
+        name: String,
+        old: String,
+        new: String,
+    },
+    /// A kernel counter from /proc/net/snmp or /proc/net/netstat grew faster than
+    /// `max_per_sec` since the previous tick, e.g. `TcpExt.ListenDrops`.
+    CounterSpike {
+        table: String,
+        field: String,
+        previous: u64,
+        current: u64,
+        delta: u64,
+        /// Per second, over the time between the two reads.
+        rate: f64,
+        max_per_sec: f64,
+    },
+    /// Estimated memory use went over [`crate::NetNotifyConfig::memory_budget`]. `shed` lists
+    /// what was dropped to make room (`"dns_cache"`, `"sni_cache"`), `after_shedding` is the
+    /// estimate afterwards, still over budget if the connection set itself is too large.
+    OverBudget {
+        estimated_bytes: u64,
+        after_shedding: u64,
+        budget: u64,
+        shed: Vec<String>,
+    },
+    /// With [`crate::NetNotifyConfig::session_stitching`]: `new_conn` opened within the window
+    /// after `old_conn` closed and continues its session, e.g. after a switch from Wi-Fi to
+    /// Ethernet or from IPv4 to IPv6. `session_id` stays the same across repeated reconnects.
+    /// `gap_unreliable` is set when a suspend or clock step happened in between, so `gap`
+    /// may be far off.
+    Reconnected {
+        old_conn: ConnKey,
+        new_conn: ConnKey,
+        gap: Duration,
+        session_id: String,
+        #[serde(default)]
+        gap_unreliable: bool,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    /// A listener matching [`crate::NetNotify::watch_listen`] has its accept queue filling up,
+    /// see [`crate::backlog`]. `drops_delta` is how much the kernel-wide `ListenOverflows`
+    /// grew over the last tick. `pid` and `comm` are set with
+    /// [`crate::NetNotifyConfig::resolve_listener_owners`].
+    BacklogPressure {
+        listener: String,
+        depth: u32,
+        backlog: u32,
+        drops_delta: u64,
+        #[serde(default)]
+        pid: Option<i32>,
+        #[serde(default)]
+        comm: Option<String>,
+    },
+    BacklogCleared {
+        listener: String,
+        depth: u32,
+        backlog: u32,
+        #[serde(default)]
+        pid: Option<i32>,
+        #[serde(default)]
+        comm: Option<String>,
+    },
+    /// With [`crate::NetNotifyConfig::summary_dimensions`], once per window: the connections
+    /// opened over the last `window` (`ticks` table reads), counted by the configured
+    /// dimensions, see [`crate::summary`]. `connections` is the size of the table at the end.
+    Summary {
+        window: Duration,
+        ticks: u32,
+        connections: usize,
+        new_connections: u64,
+        dimensions: Vec<DimensionSummary>,
+    },
+    /// A connection table line that did not parse and was skipped, with
+    /// [`crate::NetNotifyConfig::capture_parse_anomalies`].
+    ParseAnomaly {
+        #[serde(flatten)]
+        anomaly: ParseAnomaly,
+    },
+    /// With [`crate::NetNotifyConfig::learn`], after the Opened of a connection whose `tuple`
+    /// was not learned: `nearest` are the closest learned ones, see [`crate::learn`].
+    NovelConnection {
+        conn: ConnKey,
+        tuple: Tuple,
+        nearest: Vec<Tuple>,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
 }
 
 bitflags! {
+    /// Kinds in the low 16 bits, sub-kinds from bit 16 (see
+    /// `omnitrace_core::callbacks::SUBKIND_BITS`): a TCP `Opened` has `OPENED` and one of the
+    /// `OPENED_*` state classes for the state it was first seen in, a TCP `Closed` has `CLOSED`
+    /// and one of the `CLOSED_*` classes for the state it was last seen in. UDP connections and
+    /// unknown states have the kind bit only.
     #[derive(Copy, Clone, Debug)]
     pub struct NetNotifyMask: u64 {
         const OPENED = 0b0001;
         const CLOSED = 0b0010;
+        const WATERMARK_EXCEEDED = 0b0100;
+        const WATERMARK_CLEARED = 0b1000;
+        const LIMIT_CHANGED = 0b1_0000;
+        const COUNTER_SPIKE = 0b10_0000;
+        const OVER_BUDGET = 0b100_0000;
+        const RECONNECTED = 0b1000_0000;
+        const BACKLOG_PRESSURE = 0b1_0000_0000;
+        const BACKLOG_CLEARED = 0b10_0000_0000;
+        const SUMMARY = 0b100_0000_0000;
+        const PARSE_ANOMALY = 0b1000_0000_0000;
+        const NOVEL_CONNECTION = 0b1_0000_0000_0000;
+
+        /// `LISTEN`.
+        const OPENED_LISTEN = 1 << 16;
+        /// `ESTABLISHED`.
+        const OPENED_ESTABLISHED = 1 << 17;
+        /// `SYN_SENT`, `SYN_RECV`.
+        const OPENED_HANDSHAKE = 1 << 18;
+        /// `FIN_WAIT1`, `FIN_WAIT2`, `CLOSE_WAIT`, `LAST_ACK`, `CLOSING`, `TIME_WAIT`, `CLOSE`.
+        const OPENED_CLOSING = 1 << 19;
+        const CLOSED_LISTEN = 1 << 20;
+        /// Gone straight from `ESTABLISHED`, e.g. reset, or closed within a tick.
+        const CLOSED_ESTABLISHED = 1 << 21;
+        const CLOSED_HANDSHAKE = 1 << 22;
+        const CLOSED_CLOSING = 1 << 23;
+    }
+}
+
+/// Position of the state class of a TCP connection among the `OPENED_*` (and `CLOSED_*`)
+/// bits, from its raw `/proc/net/tcp` state.
+fn state_class(conn: &ConnKey) -> Option<u32> {
+    match conn.state.as_deref()? {
+        "0A" => Some(0),
+        "01" => Some(1),
+        "02" | "03" => Some(2),
+        "04" | "05" | "06" | "07" | "08" | "09" | "0B" => Some(3),
+        _ => None,
+    }
+}
+
+impl ConnKey {
+    /// Connection as the live reader would decode it, for injecting synthetic events
+    /// (see `CallbackHub::inject`). `proto` is "tcp" or "udp", "6" is appended for IPv6
+    /// addresses. TCP connections are ESTABLISHED.
+    ///
+    /// Panics if `local` or `remote` is not an `ip:port` address.
+    pub fn test(proto: &str, local: &str, remote: &str) -> Self {
+        let parse = |a: &str| a.parse::<SocketAddr>().unwrap_or_else(|_| panic!("not an ip:port address: {a}"));
+        let (local, remote) = (parse(local), parse(remote));
+        let proto = if local.is_ipv6() { format!("{proto}6") } else { proto.to_string() };
+        let state = proto.starts_with("tcp").then(|| "01".into());
+        crate::baseline::conn_key(&proto, &encode_addr(local), &encode_addr(remote), state)
     }
 }
 
 impl NetNotifyEvent {
+    /// Synthetic `Opened` event, see [`ConnKey::test`].
+    pub fn test_opened(proto: &str, local: &str, remote: &str) -> Self {
+        NetNotifyEvent::Opened { conn: ConnKey::test(proto, local, remote), offline: false, labels: Labels::default() }
+    }
+
+    /// Synthetic `Closed` event, see [`ConnKey::test`].
+    pub fn test_closed(proto: &str, local: &str, remote: &str) -> Self {
+        NetNotifyEvent::Closed { conn: ConnKey::test(proto, local, remote), offline: false, labels: Labels::default() }
+    }
+
+    /// Key the event is counted under in `NetNotify::entity_counters`: the rule for
+    /// watermark, limit and counter events, the listener for backlog events, the remote host for connection events (SNI or
+    /// resolved name if known, else the address without port). None for OverBudget, Summary
+    /// and ParseAnomaly.
+    pub fn entity(&self) -> Option<String> {
+        let remote = |c: &ConnKey| {
+            c.remote_sni
+                .clone()
+                .or_else(|| c.remote_host.clone())
+                .or_else(|| c.remote_addr.map(|a| a.ip().to_string()))
+                .unwrap_or_else(|| c.remote.to_string())
+        };
+        match self {
+            NetNotifyEvent::Opened { conn, .. } | NetNotifyEvent::Closed { conn, .. } | NetNotifyEvent::NovelConnection { conn, .. } => {
+                Some(remote(conn))
+            }
+            NetNotifyEvent::Reconnected { new_conn, .. } => Some(remote(new_conn)),
+            NetNotifyEvent::WatermarkExceeded { watermark, .. } | NetNotifyEvent::WatermarkCleared { watermark, .. } => Some(watermark.clone()),
+            NetNotifyEvent::LimitChanged { name, .. } => Some(name.clone()),
+            NetNotifyEvent::CounterSpike { table, field, .. } => Some(format!("{table}.{field}")),
+            NetNotifyEvent::BacklogPressure { listener, .. } | NetNotifyEvent::BacklogCleared { listener, .. } => Some(listener.clone()),
+            NetNotifyEvent::OverBudget { .. } | NetNotifyEvent::Summary { .. } | NetNotifyEvent::ParseAnomaly { .. } => None,
+        }
+    }
+
+    /// Keys the event is kept under in `NetNotify::history`: its [`NetNotifyEvent::entity`]
+    /// and, for connection events, the connection as `"<proto> <local> -> <remote>"`, with
+    /// the decoded addresses if known (for Reconnected, both connections).
+    pub fn history_keys(&self) -> Vec<String> {
+        let conn = |c: &ConnKey| {
+            let local = c.local_dec.as_deref().unwrap_or(&c.local);
+            format!("{} {local} -> {}", c.proto, c.remote_dec.as_deref().unwrap_or(&c.remote))
+        };
+        let mut keys: Vec<String> = self.entity().into_iter().collect();
+        match self {
+            NetNotifyEvent::Opened { conn: c, .. } | NetNotifyEvent::Closed { conn: c, .. } | NetNotifyEvent::NovelConnection { conn: c, .. } => {
+                keys.push(conn(c))
+            }
+            NetNotifyEvent::Reconnected { old_conn, new_conn, .. } => keys.extend([conn(old_conn), conn(new_conn)]),
+            _ => {}
+        }
+        keys
+    }
+
+    /// Labels of the watch patterns selecting the connection (for Reconnected, either of
+    /// them), see [`crate::NetNotify::add_labeled`].
+    pub fn labels(&self) -> &Labels {
+        match self {
+            NetNotifyEvent::Opened { labels, .. }
+            | NetNotifyEvent::Closed { labels, .. }
+            | NetNotifyEvent::Reconnected { labels, .. }
+            | NetNotifyEvent::NovelConnection { labels, .. } => labels,
+            _ => Labels::empty(),
+        }
+    }
+
+    #[cfg(feature = "runtime")]
+    pub(crate) fn labels_mut(&mut self) -> Option<&mut Labels> {
+        match self {
+            NetNotifyEvent::Opened { labels, .. }
+            | NetNotifyEvent::Closed { labels, .. }
+            | NetNotifyEvent::Reconnected { labels, .. }
+            | NetNotifyEvent::NovelConnection { labels, .. } => Some(labels),
+            _ => None,
+        }
+    }
+
     pub fn mask(&self) -> NetNotifyMask {
         match self {
-            NetNotifyEvent::Opened { .. } => NetNotifyMask::OPENED,
-            NetNotifyEvent::Closed { .. } => NetNotifyMask::CLOSED,
+            NetNotifyEvent::Opened { conn, .. } => match state_class(conn) {
+                Some(c) => NetNotifyMask::OPENED | NetNotifyMask::from_bits_retain(NetNotifyMask::OPENED_LISTEN.bits() << c),
+                None => NetNotifyMask::OPENED,
+            },
+            NetNotifyEvent::Closed { conn, .. } => match state_class(conn) {
+                Some(c) => NetNotifyMask::CLOSED | NetNotifyMask::from_bits_retain(NetNotifyMask::CLOSED_LISTEN.bits() << c),
+                None => NetNotifyMask::CLOSED,
+            },
+            NetNotifyEvent::WatermarkExceeded { .. } => NetNotifyMask::WATERMARK_EXCEEDED,
+            NetNotifyEvent::WatermarkCleared { .. } => NetNotifyMask::WATERMARK_CLEARED,
+            NetNotifyEvent::LimitChanged { .. } => NetNotifyMask::LIMIT_CHANGED,
+            NetNotifyEvent::CounterSpike { .. } => NetNotifyMask::COUNTER_SPIKE,
+            NetNotifyEvent::OverBudget { .. } => NetNotifyMask::OVER_BUDGET,
+            NetNotifyEvent::Reconnected { .. } => NetNotifyMask::RECONNECTED,
+            NetNotifyEvent::BacklogPressure { .. } => NetNotifyMask::BACKLOG_PRESSURE,
+            NetNotifyEvent::BacklogCleared { .. } => NetNotifyMask::BACKLOG_CLEARED,
+            NetNotifyEvent::Summary { .. } => NetNotifyMask::SUMMARY,
+            NetNotifyEvent::ParseAnomaly { .. } => NetNotifyMask::PARSE_ANOMALY,
+            NetNotifyEvent::NovelConnection { .. } => NetNotifyMask::NOVEL_CONNECTION,
         }
     }
+
+    /// Dotted topic, see [`omnitrace_core::topics`].
+    pub fn topic(&self) -> &'static str {
+        topics::topic_of(self)
+    }
+}
+
+impl Topics for NetNotifyEvent {
+    const TOPICS: &'static [Topic] = &[
+        Topic::new("net.conn.opened", NetNotifyMask::OPENED.bits()),
+        Topic::new("net.conn.closed", NetNotifyMask::CLOSED.bits()),
+        Topic::new("net.conn.reconnected", NetNotifyMask::RECONNECTED.bits()),
+        Topic::new("net.conn.novel", NetNotifyMask::NOVEL_CONNECTION.bits()),
+        Topic::new("net.watermark.exceeded", NetNotifyMask::WATERMARK_EXCEEDED.bits()),
+        Topic::new("net.watermark.cleared", NetNotifyMask::WATERMARK_CLEARED.bits()),
+        Topic::new("net.limit.changed", NetNotifyMask::LIMIT_CHANGED.bits()),
+        Topic::new("net.counter.spike", NetNotifyMask::COUNTER_SPIKE.bits()),
+        Topic::new("net.backlog.pressure", NetNotifyMask::BACKLOG_PRESSURE.bits()),
+        Topic::new("net.backlog.cleared", NetNotifyMask::BACKLOG_CLEARED.bits()),
+        Topic::new("net.over_budget", NetNotifyMask::OVER_BUDGET.bits()),
+        Topic::new("net.summary", NetNotifyMask::SUMMARY.bits()),
+        Topic::new("net.parse_anomaly", NetNotifyMask::PARSE_ANOMALY.bits()),
+    ];
+
+    fn topic_index(&self) -> usize {
+        match self {
+            NetNotifyEvent::Opened { .. } => 0,
+            NetNotifyEvent::Closed { .. } => 1,
+            NetNotifyEvent::Reconnected { .. } => 2,
+            NetNotifyEvent::NovelConnection { .. } => 3,
+            NetNotifyEvent::WatermarkExceeded { .. } => 4,
+            NetNotifyEvent::WatermarkCleared { .. } => 5,
+            NetNotifyEvent::LimitChanged { .. } => 6,
+            NetNotifyEvent::CounterSpike { .. } => 7,
+            NetNotifyEvent::BacklogPressure { .. } => 8,
+            NetNotifyEvent::BacklogCleared { .. } => 9,
+            NetNotifyEvent::OverBudget { .. } => 10,
+            NetNotifyEvent::Summary { .. } => 11,
+            NetNotifyEvent::ParseAnomaly { .. } => 12,
+        }
+    }
+}
+
+impl ConnKey {
+    /// `name`, bare (`remote`) or under `prefix` (`conn.remote`).
+    fn field_in(&self, prefix: &str, name: &str) -> Option<FieldValue<'_>> {
+        let name = name.strip_prefix(prefix).and_then(|n| n.strip_prefix('.')).unwrap_or(name);
+        match name {
+            "proto" => Some(FieldValue::str(&self.proto)),
+            "local" => Some(FieldValue::str(&self.local)),
+            "remote" => Some(FieldValue::str(&self.remote)),
+            "state" => self.state.as_deref().map(FieldValue::str),
+            "local_dec" => self.local_dec.as_deref().map(FieldValue::str),
+            "remote_dec" => self.remote_dec.as_deref().map(FieldValue::str),
+            "state_dec" => self.state_dec.as_deref().map(FieldValue::str),
+            "local.ip" => self.local_addr.map(|a| FieldValue::str(a.ip().to_string())),
+            "local.port" => self.local_addr.map(|a| FieldValue::int(a.port())),
+            "remote.ip" => self.remote_addr.map(|a| FieldValue::str(a.ip().to_string())),
+            "remote.port" => self.remote_addr.map(|a| FieldValue::int(a.port())),
+            "local_addr" => self.local_addr.map(|a| FieldValue::str(a.to_string())),
+            "remote_addr" => self.remote_addr.map(|a| FieldValue::str(a.to_string())),
+            "local_host" => self.local_host.as_deref().map(FieldValue::str),
+            "remote_host" => self.remote_host.as_deref().map(FieldValue::str),
+            "remote_sni" => self.remote_sni.as_deref().map(FieldValue::str),
+            _ => None,
+        }
+    }
+}
+
+/// Connections have the fields of [`ConnKey`] (`local` and `remote` as read from the table,
+/// `remote_dec`, `state_dec`, `remote_host`, ...) plus the decoded address as `local.ip`,
+/// `local.port`, `remote.ip` and `remote.port`, also under `conn.` (`conn.remote_host`). `Reconnected` has those of the new
+/// connection, and both under `old_conn.` and `new_conn.`. Other kinds have their own
+/// scalar fields by name; durations are in milliseconds (`gap_ms`) and flags are 0 or 1.
+/// NovelConnection has those of its connection, its `tuple` and the `nearest` learned ones
+/// as text. Summary has, per configured dimension, its most frequent value and that value's count
+/// (`remote_ip.top`, `remote_ip.top_count`) and the distinct values (`remote_ip.distinct`).
+impl EventFields for NetNotifyEvent {
+    fn kind(&self) -> &'static str {
+        match self {
+            NetNotifyEvent::Opened { .. } => "opened",
+            NetNotifyEvent::Closed { .. } => "closed",
+            NetNotifyEvent::WatermarkExceeded { .. } => "watermark_exceeded",
+            NetNotifyEvent::WatermarkCleared { .. } => "watermark_cleared",
+            NetNotifyEvent::LimitChanged { .. } => "limit_changed",
+            NetNotifyEvent::CounterSpike { .. } => "counter_spike",
+            NetNotifyEvent::OverBudget { .. } => "over_budget",
+            NetNotifyEvent::Reconnected { .. } => "reconnected",
+            NetNotifyEvent::BacklogPressure { .. } => "backlog_pressure",
+            NetNotifyEvent::BacklogCleared { .. } => "backlog_cleared",
+            NetNotifyEvent::Summary { .. } => "summary",
+            NetNotifyEvent::ParseAnomaly { .. } => "parse_anomaly",
+            NetNotifyEvent::NovelConnection { .. } => "novel_connection",
+        }
+    }
+
+    fn field(&self, name: &str) -> Option<FieldValue<'_>> {
+        match self {
+            NetNotifyEvent::Opened { conn, offline, .. } | NetNotifyEvent::Closed { conn, offline, .. } => match name {
+                "offline" => Some((*offline).into()),
+                _ => conn.field_in("conn", name),
+            },
+            NetNotifyEvent::WatermarkExceeded { watermark, count, threshold, .. }
+            | NetNotifyEvent::WatermarkCleared { watermark, count, threshold, .. } => match name {
+                "watermark" => Some(FieldValue::str(watermark)),
+                "count" => Some(FieldValue::count(*count as u64)),
+                "threshold" => Some(FieldValue::count(*threshold as u64)),
+                _ => None,
+            },
+            NetNotifyEvent::LimitChanged { name: limit, old, new } => match name {
+                "name" => Some(FieldValue::str(limit)),
+                "old" => Some(FieldValue::str(old)),
+                "new" => Some(FieldValue::str(new)),
+                _ => None,
+            },
+            NetNotifyEvent::CounterSpike { table, field, previous, current, delta, .. } => match name {
+                "table" => Some(FieldValue::str(table)),
+                "field" => Some(FieldValue::str(field)),
+                "previous" => Some(FieldValue::count(*previous)),
+                "current" => Some(FieldValue::count(*current)),
+                "delta" => Some(FieldValue::count(*delta)),
+                _ => None,
+            },
+            NetNotifyEvent::OverBudget { estimated_bytes, after_shedding, budget, .. } => match name {
+                "estimated_bytes" => Some(FieldValue::count(*estimated_bytes)),
+                "after_shedding" => Some(FieldValue::count(*after_shedding)),
+                "budget" => Some(FieldValue::count(*budget)),
+                _ => None,
+            },
+            NetNotifyEvent::Reconnected { old_conn, new_conn, gap, session_id, gap_unreliable, .. } => match name {
+                "session_id" => Some(FieldValue::str(session_id)),
+                "gap_ms" => Some(FieldValue::count(gap.as_millis() as u64)),
+                "gap_unreliable" => Some((*gap_unreliable).into()),
+                _ => match name.strip_prefix("old_conn.") {
+                    Some(n) => old_conn.field_in("", n),
+                    None => new_conn.field_in("new_conn", name),
+                },
+            },
+            NetNotifyEvent::BacklogPressure { listener, depth, backlog, drops_delta, pid, comm } => match name {
+                "drops_delta" => Some(FieldValue::count(*drops_delta)),
+                _ => backlog_field(name, listener, *depth, *backlog, *pid, comm),
+            },
+            NetNotifyEvent::BacklogCleared { listener, depth, backlog, pid, comm } => backlog_field(name, listener, *depth, *backlog, *pid, comm),
+            NetNotifyEvent::Summary { window, ticks, connections, new_connections, dimensions } => match name {
+                "window_ms" => Some(FieldValue::count(window.as_millis() as u64)),
+                "ticks" => Some(FieldValue::int(*ticks)),
+                "connections" => Some(FieldValue::count(*connections as u64)),
+                "new_connections" => Some(FieldValue::count(*new_connections)),
+                _ => {
+                    let (dim, field) = name.split_once('.')?;
+                    let d = dimensions.iter().find(|d| Some(d.dimension) == Dimension::from_name(dim))?;
+                    match field {
+                        "top" => d.top.first().map(|t| FieldValue::str(&t.value)),
+                        "top_count" => d.top.first().map(|t| FieldValue::count(t.count)),
+                        "distinct" => Some(FieldValue::count(d.distinct)),
+                        _ => None,
+                    }
+                }
+            },
+            NetNotifyEvent::ParseAnomaly { anomaly } => anomaly.field(name),
+            NetNotifyEvent::NovelConnection { conn, tuple, nearest, .. } => match name {
+                "tuple" => Some(FieldValue::str(tuple.to_string())),
+                "nearest" => Some(FieldValue::str(nearest.iter().map(Tuple::to_string).collect::<Vec<_>>().join(", "))),
+                _ => conn.field_in("conn", name),
+            },
+        }
+    }
+
+    fn field_names(&self) -> &'static [&'static str] {
+        match self {
+            NetNotifyEvent::Opened { .. } | NetNotifyEvent::Closed { .. } => &[
+                "proto",
+                "local",
+                "remote",
+                "state",
+                "local_dec",
+                "remote_dec",
+                "state_dec",
+                "local_addr",
+                "remote_addr",
+                "local_host",
+                "remote_host",
+                "remote_sni",
+                "local.ip",
+                "local.port",
+                "remote.ip",
+                "remote.port",
+                "conn.proto",
+                "offline",
+            ],
+            NetNotifyEvent::WatermarkExceeded { .. } | NetNotifyEvent::WatermarkCleared { .. } => &["watermark", "count", "threshold"],
+            NetNotifyEvent::LimitChanged { .. } => &["name", "old", "new"],
+            NetNotifyEvent::CounterSpike { .. } => &["table", "field", "previous", "current", "delta"],
+            NetNotifyEvent::OverBudget { .. } => &["estimated_bytes", "after_shedding", "budget"],
+            NetNotifyEvent::Reconnected { .. } => &[
+                "proto",
+                "remote",
+                "state_dec",
+                "remote.ip",
+                "remote.port",
+                "old_conn.local_dec",
+                "new_conn.local_dec",
+                "session_id",
+                "gap_ms",
+                "gap_unreliable",
+            ],
+            NetNotifyEvent::BacklogPressure { .. } => &["listener", "depth", "backlog", "drops_delta", "pid", "comm"],
+            NetNotifyEvent::BacklogCleared { .. } => &["listener", "depth", "backlog", "pid", "comm"],
+            NetNotifyEvent::Summary { .. } => &[
+                "window_ms",
+                "ticks",
+                "connections",
+                "new_connections",
+                "remote_ip.top",
+                "remote_ip.top_count",
+                "remote_ip.distinct",
+                "remote_host.top",
+                "remote_host.top_count",
+                "remote_host.distinct",
+                "local_port.top",
+                "local_port.top_count",
+                "local_port.distinct",
+                "uid.top",
+                "uid.top_count",
+                "uid.distinct",
+            ],
+            NetNotifyEvent::ParseAnomaly { .. } => ParseAnomaly::FIELDS,
+            NetNotifyEvent::NovelConnection { .. } => &[
+                "proto",
+                "local_dec",
+                "remote_dec",
+                "state_dec",
+                "local.port",
+                "remote.ip",
+                "remote.port",
+                "remote_host",
+                "remote_sni",
+                "tuple",
+                "nearest",
+            ],
+        }
+    }
+
+    fn summary_fields(&self) -> &'static [&'static str] {
+        match self {
+            NetNotifyEvent::Opened { .. } | NetNotifyEvent::Closed { .. } => {
+                &["proto", "local_dec|local", "remote_dec|remote", "state_dec|state", "remote_host", "remote_sni"]
+            }
+            NetNotifyEvent::Reconnected { .. } => {
+                &["proto", "remote_dec|remote", "old_conn.local_dec|old_conn.local", "new_conn.local_dec|new_conn.local", "gap_ms"]
+            }
+            NetNotifyEvent::Summary { .. } => &["window_ms", "connections", "new_connections"],
+            NetNotifyEvent::ParseAnomaly { .. } => &["source", "column", "reason"],
+            NetNotifyEvent::NovelConnection { .. } => &["local_dec|local", "remote_dec|remote", "remote_host", "tuple"],
+            _ => self.detail_fields(),
+        }
+    }
+
+    fn detail_fields(&self) -> &'static [&'static str] {
+        match self {
+            NetNotifyEvent::Opened { .. } | NetNotifyEvent::Closed { .. } => {
+                &["proto", "local", "remote", "state", "local_dec", "remote_dec", "state_dec", "local_host", "remote_host", "remote_sni", "offline"]
+            }
+            NetNotifyEvent::Reconnected { .. } => &[
+                "proto",
+                "remote",
+                "remote_dec",
+                "remote_host",
+                "remote_sni",
+                "old_conn.local",
+                "old_conn.local_dec",
+                "new_conn.local",
+                "new_conn.local_dec",
+                "state_dec",
+                "session_id",
+                "gap_ms",
+                "gap_unreliable",
+            ],
+            _ => self.field_names(),
+        }
+    }
+
+    fn topic(&self) -> Option<&'static str> {
+        Some(NetNotifyEvent::topic(self))
+    }
+}
+
+fn backlog_field<'a>(name: &str, listener: &'a str, depth: u32, backlog: u32, pid: Option<i32>, comm: &'a Option<String>) -> Option<FieldValue<'a>> {
+    match name {
+        "listener" => Some(FieldValue::str(listener)),
+        "depth" => Some(FieldValue::int(depth)),
+        "backlog" => Some(FieldValue::int(backlog)),
+        "pid" => pid.map(FieldValue::int),
+        "comm" => comm.as_deref().map(FieldValue::str),
+        _ => None,
+    }
 }
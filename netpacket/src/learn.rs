@@ -0,0 +1,450 @@
+//! Learned connection baselines: what a host normally talks to, to alert on the rest.
+//!
+//! While training, every connection in the table (as far as the address rules select it) is
+//! recorded as a [`Tuple`]: protocol, local port and remote network. [`Aggregation`] keeps
+//! the set small, by default a /24 (IPv4) or /64 (IPv6) per remote and the ephemeral port
+//! range as one bucket. When the training window is over the set is saved and enforced:
+//! Opened and Closed of connections whose tuple was learned are dropped, or carry the label
+//! `known: true` with [`LearnConfig::tag_known`], and an Opened outside the set is followed by
+//! a NovelConnection naming the nearest learned tuples.
+//!
+//! ```ignore
+//! let cfg = NetNotifyConfig::default().learn(LearnConfig::new(Duration::from_secs(7 * 86400)).path("/var/lib/omnitrace/learned.json"));
+//! let sensor = NetNotify::new(Some(cfg));
+//! let learned = sensor.learned().unwrap();
+//! // later: learn a week more on top of what is known, e.g. after a deployment
+//! learned.train(Duration::from_secs(7 * 86400));
+//! ```
+//!
+//! A saved set is enforced right away on the next start. One saved by a sensor stopped
+//! mid-training is trained on for another full window, keeping what it holds.
+
+// training and enforcing run in the sensor; without it only the types and files are used
+#![cfg_attr(not(feature = "runtime"), allow(dead_code))]
+
+use crate::events::ConnKey;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    fmt, io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// How connections are aggregated into tuples.
+///
+/// ```json
+/// { "v4_prefix": 24, "v6_prefix": 64, "ephemeral_from": 32768, "port_width": 1 }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Aggregation {
+    /// Prefix length IPv4 remotes are cut to.
+    #[serde(default = "default_v4_prefix")]
+    pub v4_prefix: u8,
+    #[serde(default = "default_v6_prefix")]
+    pub v6_prefix: u8,
+    /// Local ports from this one up are one bucket: the range outgoing connections get their
+    /// port from. `None`: no such bucket.
+    #[serde(default = "default_ephemeral_from")]
+    pub ephemeral_from: Option<u16>,
+    /// Other local ports go in buckets this wide; 1 keeps each port apart.
+    #[serde(default = "default_port_width")]
+    pub port_width: u16,
+}
+
+impl Default for Aggregation {
+    fn default() -> Self {
+        Self {
+            v4_prefix: default_v4_prefix(),
+            v6_prefix: default_v6_prefix(),
+            ephemeral_from: default_ephemeral_from(),
+            port_width: default_port_width(),
+        }
+    }
+}
+
+fn default_v4_prefix() -> u8 {
+    24
+}
+
+fn default_v6_prefix() -> u8 {
+    64
+}
+
+fn default_ephemeral_from() -> Option<u16> {
+    Some(32768)
+}
+
+fn default_port_width() -> u16 {
+    1
+}
+
+impl Aggregation {
+    /// The tuple a connection falls in; `None` without a remote address (listeners, unconnected
+    /// UDP sockets).
+    pub fn tuple(&self, conn: &ConnKey) -> Option<Tuple> {
+        let (local, remote) = (conn.local_addr?, conn.remote_addr?);
+        if remote.ip().is_unspecified() {
+            return None;
+        }
+        let (first_port, last_port) = self.ports(local.port());
+        let (net, prefix) = self.network(remote.ip());
+        Some(Tuple { proto: conn.proto.trim_end_matches('6').to_string(), net, prefix, first_port, last_port })
+    }
+
+    fn ports(&self, port: u16) -> (u16, u16) {
+        match self.ephemeral_from {
+            Some(from) if port >= from => (from, u16::MAX),
+            from => {
+                let width = self.port_width.max(1);
+                let first = port / width * width;
+                let last = first.saturating_add(width - 1);
+                (first, from.map_or(last, |f| last.min(f - 1)))
+            }
+        }
+    }
+
+    fn network(&self, ip: IpAddr) -> (IpAddr, u8) {
+        match ip {
+            IpAddr::V4(a) => {
+                let prefix = self.v4_prefix.min(32);
+                let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+                (IpAddr::from((u32::from(a) & mask).to_be_bytes()), prefix)
+            }
+            IpAddr::V6(a) => {
+                let prefix = self.v6_prefix.min(128);
+                let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+                (IpAddr::from((u128::from(a) & mask).to_be_bytes()), prefix)
+            }
+        }
+    }
+}
+
+/// Protocol (`tcp` or `udp`, for both address families), local port bucket and remote
+/// network of a connection. Displayed as `tcp :443 10.1.2.0/24`, or with the bucket as
+/// `tcp :32768-65535 10.1.2.0/24`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Tuple {
+    pub proto: String,
+    pub net: IpAddr,
+    pub prefix: u8,
+    pub first_port: u16,
+    pub last_port: u16,
+}
+
+impl Tuple {
+    /// How close `other` is, for [`LearnedBaseline::nearest`]: the same protocol and port
+    /// bucket first, then the longest common address prefix, then the closest ports.
+    fn closeness(&self, other: &Tuple) -> (bool, bool, u32, std::cmp::Reverse<u32>) {
+        let common = match (self.net, other.net) {
+            (IpAddr::V4(a), IpAddr::V4(b)) => (u32::from(a) ^ u32::from(b)).leading_zeros(),
+            (IpAddr::V6(a), IpAddr::V6(b)) => (u128::from(a) ^ u128::from(b)).leading_zeros(),
+            _ => 0,
+        };
+        let common = common.min(u32::from(self.prefix.min(other.prefix)));
+        let ports = u32::from(self.first_port.abs_diff(other.first_port));
+        (self.proto == other.proto, (self.first_port, self.last_port) == (other.first_port, other.last_port), common, std::cmp::Reverse(ports))
+    }
+}
+
+impl fmt::Display for Tuple {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} :{}", self.proto, self.first_port)?;
+        if self.last_port != self.first_port {
+            write!(f, "-{}", self.last_port)?;
+        }
+        write!(f, " {}/{}", self.net, self.prefix)
+    }
+}
+
+/// The learned tuples, all aggregated the same way.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LearnedBaseline {
+    aggregation: Aggregation,
+    tuples: BTreeSet<Tuple>,
+}
+
+impl LearnedBaseline {
+    pub fn new(aggregation: Aggregation) -> Self {
+        Self { aggregation, tuples: BTreeSet::new() }
+    }
+
+    pub fn aggregation(&self) -> Aggregation {
+        self.aggregation
+    }
+
+    pub fn len(&self) -> usize {
+        self.tuples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tuples.is_empty()
+    }
+
+    pub fn tuples(&self) -> impl Iterator<Item = &Tuple> {
+        self.tuples.iter()
+    }
+
+    /// The tuple of `conn` if it is in the set, else `Err` with the tuple; `None` for
+    /// connections without a tuple.
+    pub fn lookup(&self, conn: &ConnKey) -> Option<Result<Tuple, Tuple>> {
+        let t = self.aggregation.tuple(conn)?;
+        Some(if self.tuples.contains(&t) { Ok(t) } else { Err(t) })
+    }
+
+    /// Record the tuple of `conn`; true if it is new.
+    pub fn observe(&mut self, conn: &ConnKey) -> bool {
+        self.aggregation.tuple(conn).is_some_and(|t| self.tuples.insert(t))
+    }
+
+    /// Add the tuples of `other`, returning how many were new. Sets aggregated another way
+    /// do not mix.
+    pub fn merge(&mut self, other: &LearnedBaseline) -> io::Result<usize> {
+        if other.aggregation != self.aggregation {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("aggregated as {:?}, not {:?}", other.aggregation, self.aggregation)));
+        }
+        let before = self.tuples.len();
+        self.tuples.extend(other.tuples.iter().cloned());
+        Ok(self.tuples.len() - before)
+    }
+
+    /// Up to `n` learned tuples closest to `t`, closest first.
+    pub fn nearest(&self, t: &Tuple, n: usize) -> Vec<Tuple> {
+        let mut all: Vec<&Tuple> = self.tuples.iter().collect();
+        all.sort_by(|a, b| t.closeness(b).cmp(&t.closeness(a)).then_with(|| a.cmp(b)));
+        all.into_iter().take(n).cloned().collect()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct LearnedFile {
+    saved_at: u64, // unix seconds
+    /// False while training: loaded again, it is trained on further.
+    trained: bool,
+    #[serde(flatten)]
+    baseline: LearnedBaseline,
+}
+
+/// Write `baseline` to `path` (atomically, via a temp file).
+pub fn save(path: &Path, baseline: &LearnedBaseline, trained: bool, saved_at: SystemTime) -> io::Result<()> {
+    let file = LearnedFile { saved_at: saved_at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0), trained, baseline: baseline.clone() };
+    let data = serde_json::to_vec(&file).map_err(io::Error::other)?;
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Load a set written by [`save`], and whether its training was complete.
+pub fn load(path: &Path) -> io::Result<(LearnedBaseline, bool)> {
+    let file: LearnedFile = serde_json::from_slice(&std::fs::read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok((file.baseline, file.trained))
+}
+
+/// What happens to connections the baseline knows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KnownConnections {
+    /// Their Opened and Closed are not fired.
+    #[default]
+    Suppress,
+    /// They are fired with the label `known: true`.
+    Tag,
+}
+
+/// See the module docs and [`crate::NetNotifyConfig::learn`].
+#[derive(Clone, Debug)]
+pub struct LearnConfig {
+    pub(crate) training: Duration,
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) aggregation: Aggregation,
+    pub(crate) known: KnownConnections,
+    pub(crate) nearest: usize,
+}
+
+impl LearnConfig {
+    /// Train for `training` from the start, unless a trained set is loaded from the
+    /// [`LearnConfig::path`].
+    pub fn new(training: Duration) -> Self {
+        Self { training, path: None, aggregation: Aggregation::default(), known: KnownConnections::default(), nearest: 3 }
+    }
+
+    /// Keep the set in this file across restarts.
+    pub fn path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn aggregation(mut self, aggregation: Aggregation) -> Self {
+        self.aggregation = aggregation;
+        self
+    }
+
+    /// Fire known connections with the label `known: true` instead of dropping them.
+    pub fn tag_known(mut self) -> Self {
+        self.known = KnownConnections::Tag;
+        self
+    }
+
+    /// Learned tuples listed in a NovelConnection (default 3).
+    pub fn nearest(mut self, n: usize) -> Self {
+        self.nearest = n;
+        self
+    }
+}
+
+/// Where a [`LearnHandle`]'s sensor is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LearnMode {
+    /// Recording tuples for `left` more (the whole window until the sensor's next tick).
+    Training {
+        left: Duration,
+    },
+    Enforcing,
+}
+
+#[derive(Debug)]
+struct Shared {
+    baseline: LearnedBaseline,
+    /// Training until then; a window not started yet has no end.
+    training: Option<(Duration, Option<Instant>)>,
+    /// Training was asked for through the handle, a trained set loaded at startup does not
+    /// end it.
+    requested: bool,
+    /// The set changed since it was saved.
+    dirty: bool,
+}
+
+/// Cloneable handle on the learned set of a (possibly running) NetNotify, see
+/// [`crate::NetNotify::learned`]. Changes take effect on the sensor's next tick.
+#[derive(Clone, Debug)]
+pub struct LearnHandle(Arc<Mutex<Shared>>);
+
+impl LearnHandle {
+    pub(crate) fn new(baseline: LearnedBaseline, training: Option<Duration>) -> Self {
+        Self(Arc::new(Mutex::new(Shared { baseline, training: training.map(|w| (w, None)), requested: false, dirty: false })))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Shared> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Tuples in the set.
+    pub fn len(&self) -> usize {
+        self.lock().baseline.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn mode(&self) -> LearnMode {
+        self.mode_at(Instant::now())
+    }
+
+    pub(crate) fn mode_at(&self, now: Instant) -> LearnMode {
+        match self.lock().training {
+            Some((window, None)) => LearnMode::Training { left: window },
+            Some((_, Some(until))) => LearnMode::Training { left: until.saturating_duration_since(now) },
+            None => LearnMode::Enforcing,
+        }
+    }
+
+    /// A copy of the set as it is now.
+    pub fn baseline(&self) -> LearnedBaseline {
+        self.lock().baseline.clone()
+    }
+
+    /// Train for `window` from the next tick, adding what is seen to the tuples already
+    /// learned. Nothing is enforced meanwhile.
+    pub fn train(&self, window: Duration) {
+        let mut s = self.lock();
+        s.training = Some((window, None));
+        s.requested = true;
+    }
+
+    /// Forget the learned tuples and train for `window` from scratch.
+    pub fn retrain(&self, window: Duration) {
+        let mut s = self.lock();
+        let aggregation = s.baseline.aggregation();
+        s.baseline = LearnedBaseline::new(aggregation);
+        s.training = Some((window, None));
+        s.requested = true;
+        s.dirty = true;
+    }
+
+    /// Add the tuples of `other`, e.g. learned on another host; see [`LearnedBaseline::merge`].
+    pub fn merge(&self, other: &LearnedBaseline) -> io::Result<usize> {
+        let mut s = self.lock();
+        let added = s.baseline.merge(other)?;
+        s.dirty |= added > 0;
+        Ok(added)
+    }
+}
+
+/// What a tick's learning did, for the sensor to log and save.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct LearnTick {
+    /// Training ended with this tick.
+    pub(crate) trained: bool,
+    /// The set changed and should be saved.
+    pub(crate) save: bool,
+}
+
+impl LearnHandle {
+    /// Start a pending training window, record `conns` while training, and end it once due.
+    pub(crate) fn tick<'a, I: IntoIterator<Item = &'a ConnKey>>(&self, conns: I, now: Instant) -> LearnTick {
+        let mut s = self.lock();
+        let mut out = LearnTick::default();
+        let Some((window, until)) = s.training else {
+            out.save = std::mem::take(&mut s.dirty);
+            return out;
+        };
+        let until = until.unwrap_or(now + window);
+        if now >= until {
+            s.training = None;
+            s.dirty = false;
+            out.trained = true;
+            out.save = true;
+            return out;
+        }
+        s.training = Some((window, Some(until)));
+        for c in conns {
+            s.dirty |= s.baseline.observe(c);
+        }
+        out
+    }
+
+    /// Whether the set is enforced, and the tuple of `conn` if that is known or new.
+    pub(crate) fn check(&self, conn: &ConnKey, nearest: usize) -> Option<Result<Tuple, (Tuple, Vec<Tuple>)>> {
+        let s = self.lock();
+        if s.training.is_some() {
+            return None;
+        }
+        Some(match s.baseline.lookup(conn)? {
+            Ok(t) => Ok(t),
+            Err(t) => {
+                let near = s.baseline.nearest(&t, nearest);
+                Err((t, near))
+            }
+        })
+    }
+
+    /// Add a set loaded at startup; one that is trained is enforced right away.
+    pub(crate) fn restore(&self, loaded: &LearnedBaseline, trained: bool) -> io::Result<()> {
+        let mut s = self.lock();
+        s.baseline.merge(loaded)?;
+        if trained && !s.requested {
+            s.training = None;
+        }
+        Ok(())
+    }
+
+    /// The set for saving, and whether it is trained.
+    pub(crate) fn snapshot(&self) -> (LearnedBaseline, bool) {
+        let s = self.lock();
+        (s.baseline.clone(), s.training.is_none())
+    }
+}
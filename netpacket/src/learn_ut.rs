@@ -0,0 +1,73 @@
+use crate::{
+    baseline,
+    events::ConnKey,
+    learn::{self, Aggregation, LearnedBaseline},
+    netutil::encode_addr,
+};
+use std::time::UNIX_EPOCH;
+use tempfile::TempDir;
+
+fn conn(proto: &str, local: &str, remote: &str) -> ConnKey {
+    let raw = |a: &str| encode_addr(a.parse().unwrap());
+    baseline::conn_key(proto, &raw(local), &raw(remote), proto.starts_with("tcp").then(|| "01".into()))
+}
+
+fn tuple(agg: &Aggregation, local: &str, remote: &str) -> String {
+    let proto = if local.starts_with('[') { "tcp6" } else { "tcp" };
+    agg.tuple(&conn(proto, local, remote)).unwrap().to_string()
+}
+
+#[test]
+fn connections_aggregate_into_tuples() {
+    let agg = Aggregation::default();
+    assert_eq!(tuple(&agg, "10.0.0.5:40000", "93.184.216.34:443"), "tcp :32768-65535 93.184.216.0/24");
+    assert_eq!(tuple(&agg, "10.0.0.5:22", "10.0.0.9:51000"), "tcp :22 10.0.0.0/24");
+    assert_eq!(tuple(&agg, "[fd00::5]:22", "[2001:db8:1:2:3::9]:51000"), "tcp :22 2001:db8:1:2::/64");
+    // tcp6 and tcp are one protocol
+    assert_eq!(agg.tuple(&conn("tcp6", "[::ffff:10.0.0.5]:22", "[::ffff:10.0.0.9]:1")).unwrap().proto, "tcp");
+
+    let wide = Aggregation { v4_prefix: 16, ephemeral_from: Some(1024), port_width: 1000, ..agg };
+    assert_eq!(tuple(&wide, "10.0.0.5:22", "10.1.2.3:1"), "tcp :0-999 10.1.0.0/16");
+    assert_eq!(tuple(&wide, "10.0.0.5:1000", "10.1.2.3:1"), "tcp :1000-1023 10.1.0.0/16", "buckets stop at the ephemeral range");
+    let exact = Aggregation { v4_prefix: 32, ephemeral_from: None, ..agg };
+    assert_eq!(tuple(&exact, "10.0.0.5:40000", "10.1.2.3:1"), "tcp :40000 10.1.2.3/32");
+
+    // nothing to aggregate without a remote
+    assert_eq!(agg.tuple(&conn("tcp", "0.0.0.0:22", "0.0.0.0:0")), None);
+}
+
+#[test]
+fn nearest_prefers_the_same_port_then_the_closest_network() {
+    let agg = Aggregation::default();
+    let mut learned = LearnedBaseline::new(agg);
+    for (local, remote) in
+        [("10.0.0.5:22", "10.0.0.9:1"), ("10.0.0.5:22", "192.168.1.1:1"), ("10.0.0.5:443", "10.0.1.1:1"), ("10.0.0.5:40000", "10.0.1.1:443")]
+    {
+        assert!(learned.observe(&conn("tcp", local, remote)));
+    }
+    assert!(!learned.observe(&conn("tcp", "10.0.0.5:22", "10.0.0.77:2")), "already learned");
+    assert_eq!(learned.len(), 4);
+
+    let novel = agg.tuple(&conn("tcp", "10.0.0.5:22", "10.0.1.20:1")).unwrap();
+    assert!(matches!(learned.lookup(&conn("tcp", "10.0.0.5:22", "10.0.1.20:1")), Some(Err(t)) if t == novel));
+    let nearest: Vec<String> = learned.nearest(&novel, 3).iter().map(ToString::to_string).collect();
+    assert_eq!(nearest, ["tcp :22 10.0.0.0/24", "tcp :22 192.168.1.0/24", "tcp :443 10.0.1.0/24"]);
+}
+
+#[test]
+fn saved_sets_load_and_merge_only_alike() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("learned.json");
+    let mut learned = LearnedBaseline::new(Aggregation::default());
+    learned.observe(&conn("udp", "10.0.0.5:40000", "10.0.0.1:53"));
+    learn::save(&path, &learned, false, UNIX_EPOCH).unwrap();
+    let (loaded, trained) = learn::load(&path).unwrap();
+    assert_eq!((loaded.tuples().collect::<Vec<_>>(), trained), (learned.tuples().collect(), false));
+
+    let mut other = LearnedBaseline::new(Aggregation { v6_prefix: 48, ..Aggregation::default() });
+    assert!(other.merge(&loaded).is_err());
+    assert_eq!(LearnedBaseline::new(Aggregation::default()).merge(&loaded).unwrap(), 1);
+
+    std::fs::write(&path, b"{\"trained\": true}").unwrap();
+    assert!(learn::load(&path).is_err());
+}
@@ -1,148 +1,1113 @@
+#[cfg(feature = "runtime")]
+pub mod backlog;
+pub mod baseline;
+#[cfg(feature = "runtime")]
+pub mod counters;
+#[cfg(feature = "runtime")]
+pub mod demo;
+#[cfg(feature = "runtime")]
+pub mod dns;
+pub mod engine;
+pub mod error;
 pub mod events;
+pub mod learn;
 pub mod netutil;
+#[cfg(feature = "runtime")]
+pub mod prelude;
+#[cfg(feature = "runtime")]
+pub mod preview;
+pub mod snapshot;
+#[cfg(feature = "runtime")]
+pub mod stitch;
+pub mod summary;
+#[cfg(feature = "runtime")]
 pub mod tls_sni;
-
+#[cfg(feature = "runtime")]
+pub mod watermark;
+
+#[cfg(all(test, feature = "runtime"))]
+mod backlog_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod counters_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod demo_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod dns_ut;
+#[cfg(test)]
+mod engine_ut;
+#[cfg(test)]
+mod learn_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod netpacket_ut;
 #[cfg(test)]
 mod netutil_ut;
-
-use crate::events::{ConnKey, NetNotifyEvent};
-use crate::netutil::{decode_tcp_state, is_hostish, is_ipish, reverse_dns};
+#[cfg(test)]
+mod snapshot_ut;
+#[cfg(all(test, feature = "soak", target_os = "linux"))]
+mod soak_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod stitch_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod summary_ut;
+
+#[cfg(feature = "runtime")]
+use crate::{
+    backlog::{BacklogThreshold, BacklogWatch, ListenerSource, SockDiag},
+    counters::{CounterRule, CounterWatch},
+    dns::{DnsPolicy, Listening, Resolver, SystemResolver},
+    engine::is_time_wait,
+    error::NetNotifyError,
+    events::{ConnKey, NetNotifyEvent},
+    learn::{KnownConnections, LearnConfig, LearnHandle, LearnMode, LearnedBaseline},
+    netutil::{is_hostish, is_ipish},
+    preview::NetNotifyRule,
+    snapshot::{SkewStats, TableReader},
+    stitch::{SessionStitching, Stitcher},
+    summary::{Dimension, Summarizer, SummaryDimensions},
+    watermark::{StateFilter, Threshold, Watermark},
+};
+#[cfg(feature = "runtime")]
 use glob::Pattern;
-use omnitrace_core::sensor::{Sensor, SensorCtx};
-use std::collections::HashMap;
-use std::time::Instant;
-use std::{collections::HashSet, future::Future, io, pin::Pin, time::Duration};
-use tokio::time;
+#[cfg(feature = "runtime")]
+use omnitrace_core::{
+    anomaly::{AnomalyCapture, ParseAnomaly, ParseStats},
+    clock::{self, SharedClock},
+    debug::DebugCell,
+    degrade::{Profile, ProfileSwitch},
+    entities::{self, EntityCounters},
+    error::Diagnostics,
+    history::{History, HistoryConfig, HistoryHandle},
+    labels::Labels,
+    memory::{self, MemoryReport, MemoryStats},
+    preflight::PreflightFinding,
+    preview::{PreviewHandle, PreviewQueue, PreviewReport},
+    pulse::{AdaptivePulse, EffectivePulse, Pacer, TimeAnomaly, TimeGaps},
+    sensor::{Sensor, SensorCtx},
+    tombstones::{self, Tombstones},
+};
+#[cfg(feature = "runtime")]
+use serde::Serialize;
+#[cfg(feature = "runtime")]
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Which side(s) of a connection get reverse-DNS enrichment.
+#[cfg(feature = "runtime")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DnsTargets {
+    pub remote: bool,
+    pub local: bool,
+}
 
+#[cfg(feature = "runtime")]
+impl Default for DnsTargets {
+    fn default() -> Self {
+        Self { remote: true, local: false }
+    }
+}
+
+#[cfg(feature = "runtime")]
 pub struct NetNotifyConfig {
     pulse: Duration,
+    adaptive: Option<AdaptivePulse>,
     dns: bool,
     dns_ttl: Duration,
+    dns_targets: DnsTargets,
+    dns_policy: DnsPolicy,
     sni_interface: Option<String>,
+    proc_net: PathBuf,
+    proc_sys: PathBuf,
+    baseline_path: Option<PathBuf>,
+    max_baseline_age: Duration,
+    counters: Vec<CounterRule>,
+    memory_budget: Option<u64>,
+    history: Option<HistoryConfig>,
+    capture_parse_anomalies: Option<usize>,
+    session_stitching: Option<SessionStitching>,
+    summary: Option<SummaryDimensions>,
+    clock: SharedClock,
+    time_gaps: TimeGaps,
+    profile: Option<ProfileSwitch>,
+    backlog: BacklogThreshold,
+    resolve_listener_owners: bool,
+    unwatched_ttl: Option<Duration>,
+    learn: Option<LearnConfig>,
 }
 
+#[cfg(feature = "runtime")]
 impl Default for NetNotifyConfig {
     fn default() -> Self {
         Self {
             pulse: Duration::from_secs(1),
+            adaptive: None,
             dns: false,
             dns_ttl: Duration::from_secs(60),
+            dns_targets: DnsTargets::default(),
+            dns_policy: DnsPolicy::default(),
             sni_interface: None,
+            proc_net: PathBuf::from("/proc/net"),
+            proc_sys: PathBuf::from("/proc/sys"),
+            baseline_path: None,
+            max_baseline_age: Duration::from_secs(3600),
+            counters: Vec::new(),
+            memory_budget: None,
+            history: None,
+            capture_parse_anomalies: None,
+            session_stitching: None,
+            summary: None,
+            clock: clock::system(),
+            time_gaps: TimeGaps::default(),
+            profile: None,
+            backlog: BacklogThreshold::default(),
+            resolve_listener_owners: false,
+            unwatched_ttl: None,
+            learn: None,
         }
     }
 }
 
+#[cfg(feature = "runtime")]
 impl NetNotifyConfig {
     pub fn pulse(mut self, d: Duration) -> Self {
         self.pulse = d;
         self
     }
 
+    /// Poll every `min` while connections change, backing off up to `max` while nothing is
+    /// reported, instead of every `pulse`. See [`omnitrace_core::pulse`].
+    pub fn adaptive(self, min: Duration, max: Duration) -> Self {
+        self.adaptive_pulse(AdaptivePulse::new(min, max))
+    }
+
+    /// [`NetNotifyConfig::adaptive`] with its own backoff policy.
+    pub fn adaptive_pulse(mut self, policy: AdaptivePulse) -> Self {
+        self.adaptive = Some(policy);
+        self
+    }
+
+    /// Select which addresses get resolved when DNS enrichment is on (default: remote only).
+    /// Local resolution fills `ConnKey.local_host`, which is what inbound monitoring usually wants.
+    pub fn dns_targets(mut self, targets: DnsTargets) -> Self {
+        self.dns_targets = targets;
+        self
+    }
+
+    /// Limit which remotes get resolved when DNS enrichment is on (default: all), e.g.
+    /// `DnsPolicy::new().outbound_only(true).never_bogons()` on an internet-facing host, so
+    /// scanners probing open ports cost no lookups. Rules that need no host name are checked
+    /// before anything is resolved, whatever the policy.
+    pub fn dns_policy(mut self, policy: DnsPolicy) -> Self {
+        self.dns_policy = policy;
+        self
+    }
+
     /// Select a specific interface for TLS SNI sniffing (e.g. "eth0").
     /// If unset, netpacket sniffs on all UP non-loopback interfaces.
     pub fn sni_interface<S: Into<String>>(mut self, iface: S) -> Self {
         self.sni_interface = Some(iface.into());
         self
     }
+
+    /// Directory holding the `tcp`, `tcp6`, `udp` and `udp6` tables and the `snmp` and
+    /// `netstat` counters (default: `/proc/net`).
+    pub fn proc_net<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.proc_net = dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// Root for limits watched with [`NetNotify::watch_limit`] (default: `/proc/sys`).
+    pub fn proc_sys<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.proc_sys = dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// Persist the connection set to this file on shutdown and diff against it on startup.
+    /// Changes that happened while the sensor was down are reported with `offline: true`
+    /// instead of being silently absorbed into the initial baseline.
+    pub fn baseline_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.baseline_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Discard a persisted baseline older than this and prime normally (default: 1h).
+    pub fn max_baseline_age(mut self, d: Duration) -> Self {
+        self.max_baseline_age = d;
+        self
+    }
+
+    /// Time source for the DNS cache TTL, counter rates, the stitching window and the
+    /// baseline age (default: the system clock). Tests pass a `ManualClock`.
+    pub fn clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Gaps between ticks (suspend and resume, NTP steps) are recorded in
+    /// [`NetNotify::diagnostics`] as `"time"`, and Reconnected events whose gap spans one are
+    /// flagged `gap_unreliable`. With `reprime`, the connections after the gap are taken as the
+    /// new baseline instead of reporting every connection opened or closed meanwhile, at once.
+    pub fn time_gaps(mut self, gaps: TimeGaps) -> Self {
+        self.time_gaps = gaps;
+        self
+    }
+
+    /// Watch kernel-wide protocol counters and fire CounterSpike when one grows too fast,
+    /// e.g. `CounterRule::new("TcpExt", "ListenDrops", 10.0)`. These catch SYN backlog
+    /// overflows, retransmit storms and checksum errors that never show up as connections.
+    pub fn counters(mut self, rules: &[CounterRule]) -> Self {
+        self.counters.extend_from_slice(rules);
+        self
+    }
+
+    /// Soft limit for the estimated memory use, see [`NetNotify::memory`]. When over it, the
+    /// sensor drops the event history and the reverse-DNS cache first, then SNI entries of
+    /// connections no longer open, and fires OverBudget. The connection set itself is never shed.
+    pub fn memory_budget(mut self, bytes: u64) -> Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// Keep the last events of each connection and remote host or rule, see [`NetNotify::history`].
+    pub fn history(mut self, config: HistoryConfig) -> Self {
+        self.history = Some(config);
+        self
+    }
+
+    /// Fire the first `per_tick` connection table lines of a tick that do not parse as
+    /// ParseAnomaly, with the raw line. Skipped lines are counted in the debug snapshot's
+    /// `parse` either way. See [`omnitrace_core::anomaly`].
+    pub fn capture_parse_anomalies(mut self, per_tick: usize) -> Self {
+        self.capture_parse_anomalies = Some(per_tick);
+        self
+    }
+
+    /// Report a watched connection that closes and comes back within the window from another
+    /// local address (roaming, VPN reconnect, IPv4/IPv6 switch) as one Reconnected event with
+    /// a stable session ID, see [`stitch`]. Off by default. Closed events of connections that
+    /// could still be stitched are delayed by up to the window.
+    pub fn session_stitching(mut self, opts: SessionStitching) -> Self {
+        self.session_stitching = Some(opts);
+        self
+    }
+
+    /// Fire a Summary every window with the top talkers among new connections and the number
+    /// of distinct values per dimension, e.g. the 10 remote IPs and local ports with the most
+    /// new connections, see [`summary`]. Counts the whole table, patterns given to `add()`
+    /// and `ignore()` do not apply.
+    pub fn summary_dimensions(mut self, dims: SummaryDimensions) -> Self {
+        self.summary = Some(dims);
+        self
+    }
+
+    /// When listeners watched with [`NetNotify::watch_listen`] are under pressure (default:
+    /// above 80% of the backlog, cleared at or below 50%).
+    pub fn backlog_threshold(mut self, threshold: BacklogThreshold) -> Self {
+        self.backlog = threshold;
+        self
+    }
+
+    /// Attach the pid and `comm` owning the socket to backlog events. Walks the fds of every
+    /// process under the parent of `proc_net`, once per event.
+    pub fn resolve_listener_owners(mut self, on: bool) -> Self {
+        self.resolve_listener_owners = on;
+        self
+    }
+
+    /// Follow the switch of a [`omnitrace_core::degrade::DegradeController`], see
+    /// [`NetNotify::apply_profile`].
+    pub fn profile_switch(mut self, switch: ProfileSwitch) -> Self {
+        self.profile = Some(switch);
+        self
+    }
+
+    /// Keep the connections a pattern removed through a [`NetNotifyControl`] selected for
+    /// `ttl`: added back within it, the connections opened and closed meanwhile are reported,
+    /// flagged `offline`, instead of taking the table then as the baseline. See
+    /// [`omnitrace_core::tombstones`].
+    pub fn report_unwatched(mut self, ttl: Duration) -> Self {
+        self.unwatched_ttl = Some(ttl);
+        self
+    }
+
+    /// Learn the usual connections, then drop (or tag) those and fire NovelConnection for the
+    /// rest, see [`learn`]. [`NetNotify::learned`] inspects and retrains the set.
+    pub fn learn(mut self, cfg: LearnConfig) -> Self {
+        self.learn = Some(cfg);
+        self
+    }
+}
+
+/// An edit queued through a [`NetNotifyControl`].
+#[cfg(feature = "runtime")]
+#[derive(Clone, Debug)]
+enum PatternEdit {
+    Add(String),
+    Ignore(String),
+    Remove(String),
+}
+
+/// Cloneable handle to the patterns of a (possibly already running) NetNotify.
+///
+/// Obtained via [`NetNotify::control`] before the sensor is spawned. Edits through it are
+/// applied in order on the next tick, before the table is diffed.
+#[cfg(feature = "runtime")]
+#[derive(Clone, Debug, Default)]
+pub struct NetNotifyControl(Arc<Mutex<Vec<PatternEdit>>>);
+
+#[cfg(feature = "runtime")]
+impl NetNotifyControl {
+    /// See [`NetNotify::add`].
+    pub fn add(&self, pat: &str) {
+        self.0.lock().unwrap().push(PatternEdit::Add(pat.to_string()));
+    }
+
+    /// See [`NetNotify::ignore`].
+    pub fn ignore(&self, pat: &str) {
+        self.0.lock().unwrap().push(PatternEdit::Ignore(pat.to_string()));
+    }
+
+    /// See [`NetNotify::remove`].
+    pub fn remove(&self, pat: &str) {
+        self.0.lock().unwrap().push(PatternEdit::Remove(pat.to_string()));
+    }
+
+    fn take(&self) -> Vec<PatternEdit> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+#[cfg(feature = "runtime")]
+const DNS_SWEEP_MIN: usize = 256;
+
+/// Pattern prefix selecting the local-host matcher in `add()`/`ignore()`, e.g. `"local-host:vip-*.corp"`.
+#[cfg(feature = "runtime")]
+pub const LOCAL_HOST_PREFIX: &str = "local-host:";
+
+/// What a watch pattern is matched against, after its form, see [`NetNotify::add`].
+#[cfg(feature = "runtime")]
+#[derive(Clone, Copy, Debug)]
+enum Matcher {
+    Target,
+    Ip,
+    Host,
+    LocalHost,
 }
 
+/// A watch pattern given labels with [`NetNotify::add_labeled`].
+#[cfg(feature = "runtime")]
+struct LabeledPattern {
+    pat: String,
+    matcher: Matcher,
+    pattern: Pattern,
+    labels: Labels,
+}
+
+/// Patterns given to [`NetNotify::add`] and [`NetNotify::ignore`], by matcher.
+#[cfg(feature = "runtime")]
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct NetNotifyPatterns {
+    pub watch: Vec<String>,
+    pub ignore: Vec<String>,
+    pub watch_ip: Vec<String>,
+    pub ignore_ip: Vec<String>,
+    pub watch_host: Vec<String>,
+    pub ignore_host: Vec<String>,
+    pub watch_local_host: Vec<String>,
+    pub ignore_local_host: Vec<String>,
+}
+
+/// What [`NetNotify::debug_handle`] reports, refreshed every tick.
+#[cfg(feature = "runtime")]
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct NetNotifyDebug {
+    pub pulse_ms: u64,
+    pub profile: Profile,
+    pub proc_net: String,
+    pub dns: bool,
+    pub watermark_only: bool,
+    pub primed: bool,
+    /// Connections in the last table snapshot, and their count per state.
+    pub connections: usize,
+    pub by_state: BTreeMap<String, usize>,
+    pub patterns: NetNotifyPatterns,
+    pub watermarks: Vec<String>,
+    pub counters: Vec<String>,
+    /// `watch_listen()` patterns, and the listeners currently under backlog pressure.
+    pub listen: Vec<String>,
+    pub backlog_pressure: Vec<String>,
+    /// Last value seen per watched limit.
+    pub limits: BTreeMap<String, Option<String>>,
+    pub dns_cache_entries: usize,
+    pub sni_cache_entries: usize,
+    pub skew_suppressed: u64,
+    /// Malformed table lines skipped since the start, and captured, see
+    /// [`NetNotifyConfig::capture_parse_anomalies`].
+    pub parse: ParseStats,
+    /// Closed connections waiting to be stitched to a new one.
+    pub stitch_pending: usize,
+    /// Events per rule and remote host, see [`NetNotify::entity_counters`].
+    pub entities: EntityCounters,
+    /// Recent events per connection, remote host and rule, if [`NetNotifyConfig::history`] is set.
+    pub history: Option<HistoryHandle<NetNotifyEvent>>,
+    /// Training or enforcing, and the tuples learned, with [`NetNotifyConfig::learn`].
+    pub learn: Option<LearnMode>,
+    pub learned_tuples: usize,
+}
+
+#[cfg(feature = "runtime")]
 pub struct NetNotify {
     cfg: NetNotifyConfig,
     last: HashSet<ConnKey>,
     is_primed: bool,
+    control: NetNotifyControl,
+    previews: PreviewQueue<NetNotifyRule>,
+    // connections selected through removed patterns, see NetNotifyConfig::report_unwatched
+    tombstones: Option<Tombstones<String, HashSet<ConnKey>>>,
     watch: Vec<Pattern>,
     ignore: Vec<Pattern>,
     dns_cache: HashMap<std::net::IpAddr, (String, Instant)>,
+    // cache size at which inserting sweeps expired names first
+    dns_sweep_at: usize,
     watch_ip: Vec<Pattern>,
     watch_host: Vec<Pattern>,
     ignore_ip: Vec<Pattern>,
     ignore_host: Vec<Pattern>,
+    watch_local_host: Vec<Pattern>,
+    ignore_local_host: Vec<Pattern>,
+    // in the order added, see NetNotify::add_labeled
+    labeled: Vec<LabeledPattern>,
     sni_cache: tls_sni::SniCache,
+    watermarks: Vec<Watermark>,
+    limits: BTreeMap<String, Option<String>>,
+    counters: CounterWatch,
+    backlog: BacklogWatch,
+    listeners: Arc<dyn ListenerSource>,
+    resolver: Arc<dyn Resolver>,
+    diagnostics: Diagnostics,
+    tables: TableReader,
+    parse: AnomalyCapture,
+    // malformed lines of the last read to fire
+    anomalies: Vec<ParseAnomaly>,
+    skew: SkewStats,
+    entities: EntityCounters,
+    history: Option<History<NetNotifyEvent>>,
+    debug: DebugCell<NetNotifyDebug>,
+    memory: MemoryStats,
+    over_budget: bool,
+    stitcher: Option<Stitcher>,
+    summary: Option<Summarizer>,
+    profile: Profile,
+    pacer: Pacer,
+    learned: Option<LearnHandle>,
 }
 
+#[cfg(feature = "runtime")]
 impl Default for NetNotify {
     fn default() -> Self {
         Self::new(None)
     }
 }
 
+#[cfg(feature = "runtime")]
 impl NetNotify {
     pub fn new(cfg: Option<NetNotifyConfig>) -> Self {
+        let cfg = cfg.unwrap_or_default();
         Self {
-            cfg: cfg.unwrap_or_default(),
+            counters: CounterWatch::new(cfg.counters.clone()),
+            backlog: BacklogWatch::new(cfg.backlog),
+            stitcher: cfg.session_stitching.clone().map(Stitcher::new),
+            summary: cfg.summary.clone().map(Summarizer::new),
+            tables: if cfg.summary.as_ref().is_some_and(|s| s.dimensions().contains(&Dimension::Uid)) {
+                TableReader::with_uids()
+            } else {
+                TableReader::default()
+            },
+            pacer: Pacer::new(cfg.pulse, cfg.adaptive).detect_gaps(cfg.clock.clone(), cfg.time_gaps.threshold),
+            tombstones: cfg.unwatched_ttl.map(|ttl| Tombstones::new(ttl, tombstones::DEFAULT_CAPACITY)),
+            history: cfg.history.map(History::new),
+            parse: AnomalyCapture::new(cfg.capture_parse_anomalies),
+            anomalies: Vec::new(),
+            learned: cfg.learn.as_ref().map(|l| LearnHandle::new(LearnedBaseline::new(l.aggregation), Some(l.training))),
+            cfg,
             last: HashSet::new(),
             is_primed: false,
+            control: NetNotifyControl::default(),
+            previews: PreviewQueue::default(),
             watch: Vec::new(),
             ignore: Vec::new(),
             dns_cache: HashMap::new(),
+            dns_sweep_at: DNS_SWEEP_MIN,
             watch_ip: Vec::new(),
             watch_host: Vec::new(),
             ignore_ip: Vec::new(),
             ignore_host: Vec::new(),
+            watch_local_host: Vec::new(),
+            ignore_local_host: Vec::new(),
+            labeled: Vec::new(),
             sni_cache: tls_sni::sni_cache(),
+            watermarks: Vec::new(),
+            limits: BTreeMap::new(),
+            listeners: Arc::new(SockDiag),
+            resolver: Arc::new(SystemResolver),
+            diagnostics: Diagnostics::new(),
+            skew: SkewStats::default(),
+            entities: EntityCounters::default(),
+            debug: DebugCell::default(),
+            memory: MemoryStats::default(),
+            over_budget: false,
+            profile: Profile::Full,
+        }
+    }
+
+    /// Fire WatermarkExceeded when the number of sockets selected by `filter` stays above
+    /// `threshold.high` for `sustain_ticks` ticks in a row, and WatermarkCleared once it stays
+    /// at or below `threshold.low` as long. E.g. `watermark(StateFilter::state("SYN_RECV"), above(1000), 3)`.
+    ///
+    /// Counts come from the table the sensor reads anyway. If only watermarks, counters, listeners or summaries (and limits)
+    /// are configured, with no `add()`/`ignore()` patterns, the sensor runs in watermark-only mode and
+    /// skips per-connection diffing and enrichment altogether.
+    pub fn watermark(&mut self, filter: StateFilter, threshold: Threshold, sustain_ticks: u32) {
+        self.watermarks.push(Watermark::new(filter, threshold, sustain_ticks));
+    }
+
+    /// Fire LimitChanged when the value of a /proc/sys entry changes,
+    /// e.g. `"net/ipv4/tcp_mem"` (or `"net.ipv4.tcp_mem"`).
+    pub fn watch_limit(&mut self, name: &str) {
+        self.limits.insert(name.replace('.', "/"), None);
+    }
+
+    /// Watch the accept queue of TCP listeners matching `pat`, a glob on `"tcp <addr>:<port>"`
+    /// (IPv6 included), e.g. `"tcp *:443"`. Fires BacklogPressure and BacklogCleared, see
+    /// [`backlog`]. Listeners are read over sock_diag, which is Linux only.
+    pub fn watch_listen(&mut self, pat: &str) {
+        let Ok(p) = Pattern::new(pat) else {
+            return;
+        };
+        self.backlog.add(p);
+    }
+
+    /// Replace the sock_diag listener source.
+    pub fn set_listener_source<S>(&mut self, source: S)
+    where
+        S: ListenerSource + 'static,
+    {
+        self.listeners = Arc::new(source);
+    }
+
+    /// Replace the `getnameinfo` resolver.
+    pub fn set_resolver<R>(&mut self, resolver: R)
+    where
+        R: Resolver + 'static,
+    {
+        self.resolver = Arc::new(resolver);
+    }
+
+    fn watermark_only(&self) -> bool {
+        (!self.watermarks.is_empty() || !self.counters.is_empty() || !self.backlog.is_empty() || self.summary.is_some())
+            && [
+                &self.watch,
+                &self.ignore,
+                &self.watch_ip,
+                &self.watch_host,
+                &self.ignore_ip,
+                &self.ignore_host,
+                &self.watch_local_host,
+                &self.ignore_local_host,
+            ]
+            .iter()
+            .all(|v| v.is_empty())
+    }
+
+    async fn check_watermarks<R: Send + 'static>(&mut self, hub: &omnitrace_core::callbacks::CallbackHub<NetNotifyEvent, R>, now: &HashSet<ConnKey>) {
+        if self.watermarks.is_empty() {
+            return;
+        }
+
+        let counts = watermark::state_counts(now);
+        let mut events = Vec::new();
+        for wm in &mut self.watermarks {
+            let count = wm.filter.count(&counts);
+            let watermark = wm.filter.name();
+            let counts = counts.clone();
+            match wm.update(count) {
+                Some(true) => events.push(NetNotifyEvent::WatermarkExceeded { watermark, count, threshold: wm.threshold.high, counts }),
+                Some(false) => events.push(NetNotifyEvent::WatermarkCleared { watermark, count, threshold: wm.threshold.low, counts }),
+                None => {}
+            }
+        }
+
+        for ev in events {
+            Self::fire(hub, &self.entities, &self.history, ev).await;
+        }
+    }
+
+    async fn check_limits<R: Send + 'static>(&mut self, hub: &omnitrace_core::callbacks::CallbackHub<NetNotifyEvent, R>) {
+        let mut events = Vec::new();
+        for (name, last) in &mut self.limits {
+            let Ok(txt) = std::fs::read_to_string(self.cfg.proc_sys.join(name)) else {
+                continue;
+            };
+            // normalize whitespace, tcp_mem & friends are tab separated
+            let new = txt.split_whitespace().collect::<Vec<_>>().join(" ");
+            if let Some(old) = last.replace(new.clone())
+                && old != new
+            {
+                events.push(NetNotifyEvent::LimitChanged { name: name.clone(), old, new });
+            }
+        }
+
+        for ev in events {
+            Self::fire(hub, &self.entities, &self.history, ev).await;
+        }
+    }
+
+    async fn check_counters<R: Send + 'static>(&mut self, hub: &omnitrace_core::callbacks::CallbackHub<NetNotifyEvent, R>) {
+        if self.counters.is_empty() {
+            return;
+        }
+
+        let now = counters::read(&self.cfg.proc_net);
+        if !self.counters.is_primed() {
+            for name in self.counters.unknown(&now) {
+                log::warn!("netnotify: counter {name} not found in {}", self.cfg.proc_net.display());
+            }
+        }
+
+        for ev in self.counters.update(now, self.cfg.clock.now_instant()) {
+            Self::fire(hub, &self.entities, &self.history, ev).await;
+        }
+    }
+
+    async fn check_backlog<R: Send + 'static>(&mut self, hub: &omnitrace_core::callbacks::CallbackHub<NetNotifyEvent, R>) {
+        if self.backlog.is_empty() {
+            return;
+        }
+
+        let listeners = match self.listeners.listeners() {
+            Ok(l) => {
+                self.diagnostics.clear("listeners");
+                l
+            }
+            Err(e) => {
+                self.diagnostics.report_once("listeners", NetNotifyError::Listeners(e));
+                return;
+            }
+        };
+        let overflows = counters::read(&self.cfg.proc_net).get("TcpExt").and_then(|t| t.get("ListenOverflows")).copied();
+
+        let proc_root = self.cfg.proc_net.parent().unwrap_or(Path::new("/proc"));
+        for crossing in self.backlog.update(listeners, overflows) {
+            let owner = if self.cfg.resolve_listener_owners { backlog::owner(proc_root, crossing.listener.inode) } else { None };
+            Self::fire(hub, &self.entities, &self.history, crossing.into_event(owner)).await;
+        }
+    }
+
+    /// Count the connections new since the last table, and fire the summary at the end of
+    /// the window. Names come from the DNS cache only, it is not worth a lookup per remote.
+    async fn check_summary<R: Send + 'static>(&mut self, hub: &omnitrace_core::callbacks::CallbackHub<NetNotifyEvent, R>, now: &HashSet<ConnKey>) {
+        let Some(sm) = self.summary.as_mut() else {
+            return;
+        };
+        let at = self.cfg.clock.now_instant();
+        if !self.is_primed {
+            sm.restart(at);
+            return;
+        }
+
+        let new = summary::new_connections(now, &self.last);
+        let (dns_cache, tables) = (&self.dns_cache, &self.tables);
+        let value = |d: Dimension, c: &ConnKey| match d {
+            Dimension::RemoteHost => {
+                let (name, exp) = dns_cache.get(&c.remote_addr?.ip())?;
+                (*exp > at).then(|| name.clone())
+            }
+            Dimension::Uid => tables.uid(c).map(|u| u.to_string()),
+            d => d.of(c),
+        };
+        if let Some(ev) = sm.tick(&new, value, now.len(), at) {
+            Self::fire(hub, &self.entities, &self.history, ev).await;
+        }
+    }
+
+    /// Estimated memory use of the connection set and the caches, updated every tick.
+    pub fn memory(&self) -> MemoryStats {
+        self.memory.clone()
+    }
+
+    fn memory_report(&self) -> MemoryReport {
+        let mut r = MemoryReport::new(self.cfg.memory_budget);
+        r.add("connections", self.last.iter().map(conn_bytes).sum());
+        let dns_entry = size_of::<(std::net::IpAddr, (String, Instant))>();
+        r.add("dns_cache", self.dns_cache.values().map(|(name, _)| memory::entry(dns_entry, name.len())).sum());
+        let sni_entry = size_of::<(tls_sni::SniKey, tls_sni::SniVal)>();
+        let sni = self.sni_cache.lock().map(|m| m.values().map(|(name, _)| memory::entry(sni_entry, name.len())).sum());
+        r.add("sni_cache", sni.unwrap_or(0));
+        if let Some(h) = &self.history {
+            r.add("history", h.bytes());
+        }
+        r
+    }
+
+    /// Account memory and shed caches when over budget, see [`NetNotifyConfig::memory_budget`].
+    async fn check_memory<R: Send + 'static>(&mut self, hub: &omnitrace_core::callbacks::CallbackHub<NetNotifyEvent, R>) {
+        let mut report = self.memory_report();
+        let Some(budget) = report.budget.filter(|_| report.exceeds()) else {
+            self.over_budget = false;
+            self.memory.publish(report, false);
+            return;
+        };
+
+        let estimated_bytes = report.estimated_bytes;
+        let mut shed = Vec::new();
+        if let Some(h) = &self.history
+            && report.parts.get("history").is_some_and(|b| *b > 0)
+        {
+            h.clear();
+            shed.push("history".to_string());
+            report = self.memory_report();
+        }
+        if report.exceeds() && !self.dns_cache.is_empty() {
+            self.dns_cache.clear();
+            shed.push("dns_cache".to_string());
+            report = self.memory_report();
+        }
+        if report.exceeds() {
+            let open: HashSet<tls_sni::SniKey> = self
+                .last
+                .iter()
+                .filter_map(|c| Some((c.local_addr?.ip(), c.local_addr?.port(), c.remote_addr?.ip(), c.remote_addr?.port())))
+                .collect();
+            let dropped = self.sni_cache.lock().map(|mut m| {
+                let n = m.len();
+                m.retain(|k, _| open.contains(k));
+                n - m.len()
+            });
+            if dropped.unwrap_or(0) > 0 {
+                shed.push("sni_cache".to_string());
+                report = self.memory_report();
+            }
+        }
+
+        report.over_budget = report.exceeds();
+        let after_shedding = report.estimated_bytes;
+        self.memory.publish(report, !shed.is_empty());
+        if !self.over_budget {
+            self.over_budget = true;
+            log::warn!("netnotify: estimated memory {estimated_bytes} bytes over budget {budget}, {after_shedding} after shedding {shed:?}");
+            Self::fire(hub, &self.entities, &self.history, NetNotifyEvent::OverBudget { estimated_bytes, after_shedding, budget, shed }).await;
         }
     }
 
-    async fn fire(hub: &omnitrace_core::callbacks::CallbackHub<NetNotifyEvent>, ev: NetNotifyEvent) {
+    async fn fire<R: Send + 'static>(
+        hub: &omnitrace_core::callbacks::CallbackHub<NetNotifyEvent, R>, counters: &EntityCounters, history: &Option<History<NetNotifyEvent>>,
+        ev: NetNotifyEvent,
+    ) {
+        if let Some(entity) = ev.entity() {
+            counters.record(&entity, entities::mask_name(ev.mask()));
+        }
+        if let Some(h) = history {
+            h.record(ev.history_keys(), &ev);
+        }
         hub.fire(ev.mask().bits(), &ev).await;
     }
 
+    /// A missing table is reported once (no IPv6 is fine), read and parse errors every tick.
+    /// Malformed lines to capture are kept for the tick to fire.
     #[cfg(target_os = "linux")]
-    fn read_table() -> io::Result<HashSet<ConnKey>> {
-        fn parse_file(proto: &str, path: &str, is_tcp: bool, out: &mut HashSet<ConnKey>) -> io::Result<()> {
-            let txt = std::fs::read_to_string(path)?;
-            for (i, line) in txt.lines().enumerate() {
-                use crate::netutil::decode_addr;
-
-                if i == 0 {
-                    continue;
-                } // header
-                let cols: Vec<&str> = line.split_whitespace().collect();
-                if cols.len() < 3 {
-                    continue;
+    fn read_table(&mut self) -> HashSet<ConnKey> {
+        let (conns, errors) = self.tables.read(&self.cfg.proc_net);
+        self.anomalies = self.parse.read(self.tables.malformed()).into_iter().cloned().collect();
+        for (table, _) in snapshot::TABLES {
+            if !errors.iter().any(|(t, _)| *t == table) {
+                self.diagnostics.clear(&format!("table:{table}"));
+            }
+        }
+        for (table, e) in errors {
+            let key = format!("table:{table}");
+            match e {
+                NetNotifyError::TableMissing { .. } => self.diagnostics.report_once(&key, e),
+                e => self.diagnostics.report(&key, e),
+            }
+        }
+        conns
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn read_table(&mut self) -> HashSet<ConnKey> {
+        HashSet::new()
+    }
+
+    /// Every connection table must be readable (the IPv6 ones may be missing), and the
+    /// counters and limits watched must exist.
+    fn preflight_findings(&self) -> Vec<PreflightFinding> {
+        let proc_net = &self.cfg.proc_net;
+        let mut out = Vec::new();
+
+        #[cfg(target_os = "linux")]
+        {
+            let (_, errors) = TableReader::default().read(proc_net);
+            let missing = errors.iter().filter(|(_, e)| matches!(e, NetNotifyError::TableMissing { .. })).count();
+            if missing == snapshot::TABLES.len() {
+                out.push(
+                    PreflightFinding::critical(format!("no connection tables in {}", proc_net.display()))
+                        .remedy("mount /proc, or point NetNotifyConfig::proc_net at the tables"),
+                );
+            } else {
+                for (table, e) in errors {
+                    out.push(match e {
+                        NetNotifyError::TableMissing { .. } if table.ends_with('6') => PreflightFinding::info(format!("{e} (no IPv6)")),
+                        NetNotifyError::TableMissing { .. } | NetNotifyError::TableParse { .. } => PreflightFinding::warning(e.to_string()),
+                        e => {
+                            PreflightFinding::critical(e.to_string()).remedy("run with read access to procfs; in a sandbox, allow reading /proc/net")
+                        }
+                    });
                 }
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        out.push(PreflightFinding::warning("connection tables are only read on Linux, Opened/Closed will not fire"));
+
+        if !self.counters.is_empty() {
+            for rule in self.counters.unknown(&counters::read(proc_net)) {
+                out.push(
+                    PreflightFinding::warning(format!("counter {rule} not found in {}", proc_net.display()))
+                        .remedy("check the table and field name against /proc/net/snmp and /proc/net/netstat"),
+                );
+            }
+        }
+        for name in self.limits.keys() {
+            let path = self.cfg.proc_sys.join(name);
+            if let Err(e) = std::fs::read_to_string(&path) {
+                out.push(PreflightFinding::warning(format!("cannot read limit {}: {e}", path.display())));
+            }
+        }
+        out
+    }
 
-                let local = cols[1];
-                let remote = cols[2];
-                let state = if is_tcp { cols.get(3).map(|s| s.to_string()) } else { None };
-
-                let is_v6 = proto.ends_with('6');
-
-                let local_dec = decode_addr(local, is_v6);
-                let remote_dec = decode_addr(remote, is_v6);
-                let state_dec = if is_tcp { decode_tcp_state(&state) } else { None };
-
-                out.insert(ConnKey {
-                    proto: proto.to_string(),
-                    local: local.to_string(),
-                    remote: remote.to_string(),
-                    state,
-                    local_dec,
-                    remote_dec,
-                    state_dec,
-                    local_host: None,
-                    remote_host: None,
-                    remote_sni: None,
-                });
+    /// Events emitted per rule or remote host and kind, with their recent rate. Remotes are
+    /// many, the table keeps the most active ones (see [`omnitrace_core::entities`]).
+    pub fn entity_counters(&self) -> EntityCounters {
+        self.entities.clone()
+    }
+
+    /// The last events per key of [`NetNotifyEvent::history_keys`], e.g. `"example.com"` or
+    /// `"tcp 10.0.0.5:51234 -> 93.184.216.34:443"`, as of the previous tick. None unless
+    /// [`NetNotifyConfig::history`] is set.
+    pub fn history(&self) -> Option<HistoryHandle<NetNotifyEvent>> {
+        self.history.as_ref().map(History::handle)
+    }
+
+    /// Errors the sensor ran past (missing or unreadable tables, malformed lines, the listener
+    /// source, the baseline file), by kind. Register it in [`omnitrace_core::debug::Snapshots`].
+    pub fn diagnostics(&self) -> Diagnostics {
+        self.diagnostics.clone()
+    }
+
+    /// The pulse in use, see [`NetNotifyConfig::adaptive`].
+    pub fn effective_pulse(&self) -> EffectivePulse {
+        self.pacer.handle()
+    }
+
+    /// Opened/Closed pairs dropped as read-skew artifacts so far.
+    pub fn skew_stats(&self) -> SkewStats {
+        self.skew.clone()
+    }
+
+    /// Handle on a summary of the sensor's state, for [`omnitrace_core::debug::Snapshots`].
+    pub fn debug_handle(&self) -> DebugCell<NetNotifyDebug> {
+        self.debug.clone()
+    }
+
+    /// The connection table now, listeners included, as far as the address rules let it
+    /// through: a one-shot read for baselines, without starting the sensor. TIME_WAIT entries
+    /// are left out and names are not resolved. Read errors go to [`NetNotify::diagnostics`].
+    pub fn snapshot(&mut self) -> Vec<ConnKey> {
+        let mut out: Vec<ConnKey> = self.read_table().into_iter().filter(|c| !is_time_wait(c) && self.matches_addresses(c)).collect();
+        out.sort_by(|a, b| (&a.proto, &a.local, &a.remote).cmp(&(&b.proto, &b.local, &b.remote)));
+        out
+    }
+
+    /// What a tick reports for the table going from `old` to `new`, both as returned by
+    /// [`NetNotify::snapshot`]: Opened and Closed, marked `offline`, without connections that
+    /// only changed state.
+    pub fn diff_snapshots(old: &[ConnKey], new: &[ConnKey]) -> Vec<NetNotifyEvent> {
+        let (old, new): (HashSet<ConnKey>, HashSet<ConnKey>) = (old.iter().cloned().collect(), new.iter().cloned().collect());
+        let (opened, closed, _) = engine::changes(&old, &new);
+        engine::events(opened, closed, true)
+    }
+
+    fn publish_debug(&self) {
+        let strs = |v: &[Pattern]| v.iter().map(|p| p.as_str().to_string()).collect();
+        let patterns = NetNotifyPatterns {
+            watch: strs(&self.watch),
+            ignore: strs(&self.ignore),
+            watch_ip: strs(&self.watch_ip),
+            ignore_ip: strs(&self.ignore_ip),
+            watch_host: strs(&self.watch_host),
+            ignore_host: strs(&self.ignore_host),
+            watch_local_host: strs(&self.watch_local_host),
+            ignore_local_host: strs(&self.ignore_local_host),
+        };
+        let sni_cache_entries = self.sni_cache.lock().map(|m| m.len()).unwrap_or(0);
+
+        self.debug.update(|d| {
+            *d = NetNotifyDebug {
+                pulse_ms: self.pulse().as_millis() as u64,
+                profile: self.profile,
+                proc_net: self.cfg.proc_net.display().to_string(),
+                dns: self.cfg.dns,
+                watermark_only: self.watermark_only(),
+                primed: self.is_primed,
+                connections: self.last.len(),
+                by_state: watermark::state_counts(&self.last),
+                patterns,
+                watermarks: self.watermarks.iter().map(|w| w.filter.name()).collect(),
+                counters: self.cfg.counters.iter().map(CounterRule::name).collect(),
+                listen: self.backlog.rules(),
+                backlog_pressure: self.backlog.pressured(),
+                limits: self.limits.clone(),
+                dns_cache_entries: self.dns_cache.len(),
+                sni_cache_entries,
+                skew_suppressed: self.skew.suppressed(),
+                parse: self.parse.stats(),
+                stitch_pending: self.stitcher.as_ref().map_or(0, Stitcher::pending),
+                entities: self.entities.clone(),
+                history: self.history(),
+                learn: self.learned.as_ref().map(|l| l.mode_at(self.cfg.clock.now_instant())),
+                learned_tuples: self.learned.as_ref().map_or(0, LearnHandle::len),
             }
-            Ok(())
+        });
+        if let Some(h) = &self.history {
+            h.publish();
         }
+    }
 
-        let mut out = HashSet::new();
-        let _ = parse_file("tcp", "/proc/net/tcp", true, &mut out);
-        let _ = parse_file("tcp6", "/proc/net/tcp6", true, &mut out);
-        let _ = parse_file("udp", "/proc/net/udp", false, &mut out);
-        let _ = parse_file("udp6", "/proc/net/udp6", false, &mut out);
-        Ok(out)
+    /// Load the persisted baseline, if configured and still fresh enough to diff against.
+    fn load_baseline(&self) -> Option<HashSet<ConnKey>> {
+        let path = self.cfg.baseline_path.as_deref()?;
+        let b = match baseline::load(path) {
+            Ok(b) => b,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(source) => {
+                self.diagnostics.report("baseline", NetNotifyError::Baseline { path: path.to_path_buf(), source });
+                return None;
+            }
+        };
+
+        match b.age_at(self.cfg.clock.now_system()) {
+            Some(age) if age <= self.cfg.max_baseline_age => Some(b.conns),
+            age => {
+                let max = self.cfg.max_baseline_age;
+                self.diagnostics.report("baseline", NetNotifyError::StaleBaseline { path: path.to_path_buf(), age, max });
+                None
+            }
+        }
     }
 
-    #[cfg(not(target_os = "linux"))]
-    fn read_table() -> io::Result<HashSet<ConnKey>> {
-        Ok(HashSet::new())
+    fn save_baseline(&self) {
+        let Some(path) = self.cfg.baseline_path.as_deref() else {
+            return;
+        };
+        if !self.is_primed {
+            return;
+        }
+        if let Err(source) = baseline::save(path, &self.last, self.cfg.clock.now_system()) {
+            self.diagnostics.report("baseline", NetNotifyError::Baseline { path: path.to_path_buf(), source });
+        }
+    }
+
+    /// The learned connections, with [`NetNotifyConfig::learn`]: their count and mode, and
+    /// training again.
+    pub fn learned(&self) -> Option<LearnHandle> {
+        self.learned.clone()
+    }
+
+    /// Add the set saved by an earlier run, if there is one made the same way.
+    fn load_learned(&self) {
+        let (Some(learned), Some(path)) = (&self.learned, self.cfg.learn.as_ref().and_then(|l| l.path.as_deref())) else {
+            return;
+        };
+        let res = learn::load(path).and_then(|(loaded, trained)| learned.restore(&loaded, trained));
+        match res {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(source) => self.diagnostics.report("learn", NetNotifyError::Learned { path: path.to_path_buf(), source }),
+            Ok(()) => log::info!("netnotify: loaded {} learned connection tuples from {}", learned.len(), path.display()),
+        }
     }
 
-    pub async fn run(mut self, ctx: SensorCtx<NetNotifyEvent>) {
-        let mut ticker = time::interval(self.cfg.pulse);
+    fn save_learned(&self) {
+        let (Some(learned), Some(path)) = (&self.learned, self.cfg.learn.as_ref().and_then(|l| l.path.as_deref())) else {
+            return;
+        };
+        let (baseline, trained) = learned.snapshot();
+        if let Err(source) = learn::save(path, &baseline, trained, self.cfg.clock.now_system()) {
+            self.diagnostics.report("learn", NetNotifyError::Learned { path: path.to_path_buf(), source });
+        }
+    }
+
+    /// Record the table while training, and save the set when training ends or it changed.
+    fn learn_tick(&self, now: &HashSet<ConnKey>) {
+        let Some(learned) = &self.learned else {
+            return;
+        };
+        let conns = now.iter().filter(|c| !is_time_wait(c) && self.matches_addresses(c));
+        let tick = learned.tick(conns, self.cfg.clock.now_instant());
+        if tick.trained {
+            log::info!("netnotify: training done, enforcing {} learned connection tuples", learned.len());
+        }
+        if tick.save {
+            self.save_learned();
+        }
+    }
+
+    /// `ev` as the learned set has it: a known connection's Opened or Closed dropped or
+    /// tagged, a novel Opened followed by NovelConnection.
+    fn learned_events(&self, mut ev: NetNotifyEvent) -> Vec<NetNotifyEvent> {
+        let (Some(learned), Some(cfg)) = (&self.learned, &self.cfg.learn) else {
+            return vec![ev];
+        };
+        let (conn, opened) = match &ev {
+            NetNotifyEvent::Opened { conn, .. } => (conn, true),
+            NetNotifyEvent::Closed { conn, .. } => (conn, false),
+            _ => return vec![ev],
+        };
+        match learned.check(conn, cfg.nearest) {
+            Some(Ok(_)) if cfg.known == KnownConnections::Suppress => Vec::new(),
+            Some(Ok(_)) => {
+                if let Some(labels) = ev.labels_mut() {
+                    labels.merge(&Labels::new(serde_json::Map::from_iter([("known".to_string(), true.into())])));
+                }
+                vec![ev]
+            }
+            Some(Err((tuple, nearest))) if opened => {
+                let novel = NetNotifyEvent::NovelConnection { conn: conn.clone(), tuple, nearest, labels: ev.labels().clone() };
+                vec![ev, novel]
+            }
+            _ => vec![ev],
+        }
+    }
+
+    /// Run at `profile`: degraded doubles the pulse and answers reverse DNS from the cache
+    /// only, so connections to hosts not resolved before go out with their address alone (and
+    /// do not match host patterns).
+    pub fn apply_profile(&mut self, profile: Profile) {
+        self.profile = profile;
+        self.pacer.set_profile(profile);
+    }
+
+    fn pulse(&self) -> Duration {
+        self.pacer.pulse()
+    }
+
+    pub async fn run<R: Send + 'static>(mut self, ctx: SensorCtx<NetNotifyEvent, R>) {
+        let mut ticker = self.pacer.ticker();
 
         // Start continuous SNI sniffer (MUST NOT block tokio).
         // NOTE: if you ever create multiple NetNotify instances, make this "spawn once" globally.
@@ -154,52 +1119,149 @@ impl NetNotify {
             });
         }
 
+        // Diff the first tick against the persisted set, if there is a usable one.
+        let mut offline = false;
+        self.load_learned();
+        if let Some(conns) = self.load_baseline() {
+            self.last = conns;
+            self.is_primed = true;
+            offline = true;
+        }
+
         loop {
+            ctx.beat();
+            if self.pacer.observe_fired(ctx.hub.fired()) {
+                self.pacer.restart(&mut ticker);
+            }
+            self.pacer.waiting();
             tokio::select! {
                 _ = ctx.cancel.cancelled() => break,
                 _ = ticker.tick() => {}
             }
+            if let Some(gap) = self.pacer.check_gap() {
+                self.on_time_gap(gap);
+            }
 
-            let now = match Self::read_table() {
-                Ok(v) => v,
-                Err(e) => {
-                    log::error!("netnotify: read_table failed: {e}");
-                    continue;
+            if let Some(p) = self.cfg.profile.as_ref().and_then(|s| s.changed_from(self.profile)) {
+                self.apply_profile(p);
+                self.pacer.restart(&mut ticker);
+            }
+
+            let now = self.read_table();
+            for anomaly in std::mem::take(&mut self.anomalies) {
+                Self::fire(&ctx.hub, &self.entities, &self.history, NetNotifyEvent::ParseAnomaly { anomaly }).await;
+            }
+            for ev in self.apply_pattern_edits() {
+                for ev in self.learned_events(self.labeled(ev)) {
+                    Self::fire(&ctx.hub, &self.entities, &self.history, ev).await;
                 }
-            };
+            }
+            self.answer_previews(&now);
+
+            self.check_limits(&ctx.hub).await;
+            self.check_counters(&ctx.hub).await;
+            self.check_backlog(&ctx.hub).await;
+            self.check_watermarks(&ctx.hub, &now).await;
+            self.check_memory(&ctx.hub).await;
+            self.check_summary(&ctx.hub, &now).await;
+            self.learn_tick(&now);
+
+            if self.watermark_only() {
+                self.last = now;
+                self.is_primed = true;
+                offline = false;
+                self.publish_debug();
+                continue;
+            }
 
             if !self.is_primed {
                 self.last = now;
                 self.is_primed = true;
+                self.publish_debug();
                 continue;
             }
 
-            let opened: Vec<ConnKey> = now.difference(&self.last).cloned().collect();
-            let closed: Vec<ConnKey> = self.last.difference(&now).cloned().collect();
+            let mut events = self.changes(&now, offline);
 
-            for mut c in opened {
-                if c.proto.starts_with("tcp") && c.state_dec.as_deref() == Some("TIME_WAIT") {
-                    continue;
+            // Offline changes are not stitched: there is no telling how far apart they were.
+            if let Some(st) = self.stitcher.as_mut()
+                && !offline
+            {
+                events = st.tick(events, &now, self.cfg.clock.now_instant());
+            }
+            for ev in events {
+                for ev in self.learned_events(self.labeled(ev)) {
+                    Self::fire(&ctx.hub, &self.entities, &self.history, ev).await;
                 }
+            }
 
-                self.enrich_dns(&mut c);
-                self.enrich_sni_from_cache(&mut c); // <-- THIS is the missing piece
+            self.last = now;
+            offline = false;
+            self.publish_debug();
+        }
 
-                if self.matches(&c) {
-                    Self::fire(&ctx.hub, NetNotifyEvent::Opened { conn: c }).await;
-                }
+        let held = self.stitcher.as_mut().map(Stitcher::drain).unwrap_or_default();
+        for ev in held {
+            for ev in self.learned_events(self.labeled(ev)) {
+                Self::fire(&ctx.hub, &self.entities, &self.history, ev).await;
             }
+        }
+        self.save_learned();
 
-            for mut c in closed {
-                self.enrich_dns(&mut c);
-                self.enrich_sni_from_cache(&mut c); // optional, but helpful
+        // Stopped before the first tick: keep the loaded file as is, rather than re-stamping a stale set.
+        if !offline {
+            self.save_baseline();
+        }
+    }
 
-                if self.matches(&c) {
-                    Self::fire(&ctx.hub, NetNotifyEvent::Closed { conn: c }).await;
-                }
+    /// Opened and Closed events between the last table and `now`. Connections failing a rule
+    /// that needs no host name are dropped before anything is resolved for them.
+    fn changes(&mut self, now: &HashSet<ConnKey>, offline: bool) -> Vec<NetNotifyEvent> {
+        let (opened, closed, suppressed) = engine::changes(&self.last, now);
+        self.skew.add(suppressed);
+        // a closed connection's listener may be gone by now
+        let listening = Listening::of(now.iter().chain(self.last.iter()));
+
+        let mut events = Vec::new();
+        for mut c in opened {
+            if is_time_wait(&c) {
+                continue;
             }
 
-            self.last = now;
+            self.enrich_sni_from_cache(&mut c); // <-- THIS is the missing piece
+            if !self.matches_addresses(&c) {
+                continue;
+            }
+            self.enrich_dns(&mut c, &listening);
+
+            if self.matches_hosts(&c) {
+                events.push(NetNotifyEvent::Opened { conn: c, offline, labels: Labels::default() });
+            }
+        }
+
+        for mut c in closed {
+            self.enrich_sni_from_cache(&mut c); // optional, but helpful
+            if !self.matches_addresses(&c) {
+                continue;
+            }
+            self.enrich_dns(&mut c, &listening);
+
+            if self.matches_hosts(&c) {
+                events.push(NetNotifyEvent::Closed { conn: c, offline, labels: Labels::default() });
+            }
+        }
+        events
+    }
+
+    /// Record the gap, flag the stitching candidates waiting across it and, with
+    /// [`NetNotifyConfig::time_gaps`] `reprime`, diff the next table against nothing.
+    fn on_time_gap(&mut self, gap: TimeAnomaly) {
+        self.diagnostics.report("time", gap);
+        if let Some(st) = self.stitcher.as_mut() {
+            st.time_gap();
+        }
+        if self.cfg.time_gaps.reprime {
+            self.is_primed = false;
         }
     }
 
@@ -213,26 +1275,17 @@ impl NetNotify {
             return;
         }
 
-        let Some(local_dec) = c.local_dec.as_deref() else {
-            return;
-        };
-        let Some(remote_dec) = c.remote_dec.as_deref() else {
-            return;
-        };
-
-        let Some((lip, lport)) = netutil::split_ip_port(local_dec) else {
-            return;
-        };
-        let Some((rip, rport)) = netutil::split_ip_port(remote_dec) else {
+        let (Some(local), Some(remote)) = (c.local_addr, c.remote_addr) else {
             return;
         };
+        let (lip, lport, rip, rport) = (local.ip(), local.port(), remote.ip(), remote.port());
 
         // only HTTPS
         if rport != 443 {
             return;
         }
 
-        // lookup from your sniffer cache
+        // lookup from your sniffer cache; the sniffer thread stamps entries with the real clock
         let key = (lip, lport, rip, rport);
         let now = Instant::now();
         let ttl = Duration::from_secs(300);
@@ -247,12 +1300,17 @@ impl NetNotify {
         }
     }
     pub fn add(&mut self, pat: &str) {
+        if let Some(local) = pat.strip_prefix(LOCAL_HOST_PREFIX) {
+            self.add_local_host(local);
+            return;
+        }
+
         let Ok(p) = Pattern::new(pat) else {
             return;
         };
 
         if is_hostish(pat) {
-            self.cfg.dns = true; // auto-enable rDNS
+            self.enable_remote_dns();
             self.watch_host.push(p);
         } else if is_ipish(pat) {
             self.watch_ip.push(p);
@@ -261,13 +1319,222 @@ impl NetNotify {
         }
     }
 
+    /// Watch connections matching `pat` like [`NetNotify::add`], with `labels` on their
+    /// Opened, Closed and Reconnected events, e.g. `{"peer": "payments-api"}`. A connection
+    /// selected by several labeled patterns gets the labels of all of them, merged in the order
+    /// they were added (a Reconnected those of both connections, the new one last); labeling a
+    /// pattern again merges into its labels. A pattern labels what it matches on its own, so
+    /// labels come along only with connections the rules as a whole select.
+    pub fn add_labeled(&mut self, pat: &str, labels: Labels) {
+        let (matcher, glob) = match pat.strip_prefix(LOCAL_HOST_PREFIX) {
+            Some(local) => (Matcher::LocalHost, local),
+            None if is_hostish(pat) => (Matcher::Host, pat),
+            None if is_ipish(pat) => (Matcher::Ip, pat),
+            None => (Matcher::Target, pat),
+        };
+        let Ok(pattern) = Pattern::new(glob) else {
+            return;
+        };
+        self.add(pat);
+        match self.labeled.iter_mut().find(|l| l.pat == pat) {
+            Some(l) => l.labels.merge(&labels),
+            None => self.labeled.push(LabeledPattern { pat: pat.to_string(), matcher, pattern, labels }),
+        }
+    }
+
+    /// Drop `pat`, as given to [`NetNotify::add`] or [`NetNotify::ignore`]. Connections it
+    /// selected are no longer reported, whether open or not. Added back later, the connections
+    /// open then are the baseline: nothing opened or closed while it was not watched is
+    /// reported, unless [`NetNotifyConfig::report_unwatched`] is set. Removing the last watch
+    /// pattern of its kind selects every connection again, as before any was added; name
+    /// resolution turned on by a host pattern stays on.
+    pub fn remove(&mut self, pat: &str) {
+        self.labeled.retain(|l| l.pat != pat);
+        let (pat, lists) = match pat.strip_prefix(LOCAL_HOST_PREFIX) {
+            Some(local) => (local, [&mut self.watch_local_host, &mut self.ignore_local_host]),
+            None if is_hostish(pat) => (pat, [&mut self.watch_host, &mut self.ignore_host]),
+            None if is_ipish(pat) => (pat, [&mut self.watch_ip, &mut self.ignore_ip]),
+            None => (pat, [&mut self.watch, &mut self.ignore]),
+        };
+        for list in lists {
+            list.retain(|p| p.as_str() != pat);
+        }
+    }
+
+    /// Get a handle for editing the patterns after the sensor was spawned.
+    pub fn control(&self) -> NetNotifyControl {
+        self.control.clone()
+    }
+
+    /// What `rule` would do, against the last table read (without TIME_WAIT entries) and the
+    /// patterns as they are: `would_match` counts the connections the pattern names on its own
+    /// (what it selects as the only watch, or drops as the only ignore), `would_stop_matching`
+    /// those selected now and no longer with the pattern added or ignored; a first watch
+    /// pattern of its kind narrows the selection. Host patterns are resolved as on a tick.
+    /// Nothing is changed.
+    pub fn preview_rule(&mut self, rule: &NetNotifyRule) -> PreviewReport {
+        let last = std::mem::take(&mut self.last);
+        let report = self.preview_against(&last, rule);
+        self.last = last;
+        report
+    }
+
+    fn preview_against(&mut self, table: &HashSet<ConnKey>, rule: &NetNotifyRule) -> PreviewReport {
+        let table: HashSet<ConnKey> = table.iter().filter(|c| !is_time_wait(c)).cloned().collect();
+        let apply = |nn: &mut Self| match rule {
+            NetNotifyRule::Add(p) => nn.add(p),
+            NetNotifyRule::Ignore(p) => nn.ignore(p),
+        };
+        let named: HashSet<ConnKey> = self.with_patterns(|nn| {
+            for list in nn.pattern_lists() {
+                list.clear();
+            }
+            apply(nn);
+            let selected = nn.selected(&table);
+            match rule {
+                NetNotifyRule::Add(_) => selected,
+                NetNotifyRule::Ignore(_) => table.difference(&selected).cloned().collect(),
+            }
+        });
+        let before = self.selected(&table);
+        let after = self.with_patterns(|nn| {
+            apply(nn);
+            nn.selected(&table)
+        });
+        PreviewReport::tally(table.iter().map(|c| {
+            let local = c.local_dec.as_deref().unwrap_or(&c.local);
+            let remote = c.remote_dec.as_deref().unwrap_or(&c.remote);
+            (format!("{} {local} {remote}", c.proto), named.contains(c), before.contains(c) && !after.contains(c))
+        }))
+    }
+
+    /// Handle for previewing rules against the running sensor, answered on its next tick.
+    pub fn preview_handle(&self) -> PreviewHandle<NetNotifyRule> {
+        self.previews.handle()
+    }
+
+    fn answer_previews(&mut self, table: &HashSet<ConnKey>) {
+        for req in self.previews.take() {
+            let report = self.preview_against(table, &req.rule);
+            req.answer(report);
+        }
+    }
+
+    fn pattern_lists(&mut self) -> [&mut Vec<Pattern>; 8] {
+        [
+            &mut self.watch,
+            &mut self.ignore,
+            &mut self.watch_ip,
+            &mut self.watch_host,
+            &mut self.ignore_ip,
+            &mut self.ignore_host,
+            &mut self.watch_local_host,
+            &mut self.ignore_local_host,
+        ]
+    }
+
+    /// Run `f`, then put the patterns and the name resolution they turned on back as they were.
+    fn with_patterns<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let saved = self.pattern_lists().map(|list| list.clone());
+        let dns = (self.cfg.dns, self.cfg.dns_targets);
+        let out = f(self);
+        for (list, was) in self.pattern_lists().into_iter().zip(saved) {
+            *list = was;
+        }
+        (self.cfg.dns, self.cfg.dns_targets) = dns;
+        out
+    }
+
+    /// Apply the edits queued through the control. With tombstones kept, a removed pattern
+    /// buries the connections that only it selected, and adding it back reports the difference
+    /// to the table before this tick: Opened for what it selects now and did not then, Closed
+    /// for what it selected then and is gone.
+    fn apply_pattern_edits(&mut self) -> Vec<NetNotifyEvent> {
+        let edits = self.control.take();
+        let track = self.is_primed && self.tombstones.is_some() && edits.iter().any(|e| !matches!(e, PatternEdit::Ignore(_)));
+        let last = if track { self.last.clone() } else { HashSet::new() };
+        let before = self.selected(&last);
+        for edit in &edits {
+            match edit {
+                PatternEdit::Add(p) => self.add(p),
+                PatternEdit::Ignore(p) => self.ignore(p),
+                PatternEdit::Remove(p) => self.remove(p),
+            }
+        }
+        if !track {
+            return Vec::new();
+        }
+
+        let after = self.selected(&last);
+        let at = self.cfg.clock.now_instant();
+        let (mut opened, mut closed) = (Vec::new(), Vec::new());
+        for edit in edits {
+            let Some(t) = self.tombstones.as_mut() else { break };
+            match edit {
+                PatternEdit::Remove(p) => t.bury(p, before.difference(&after).cloned().collect(), at),
+                PatternEdit::Add(p) => {
+                    if let Some(then) = t.take(&p, at) {
+                        opened.extend(after.iter().filter(|c| !before.contains(*c) && !then.contains(*c) && !is_time_wait(c)).cloned());
+                        closed.extend(then.into_iter().filter(|c| !last.contains(c)));
+                    }
+                }
+                PatternEdit::Ignore(_) => {}
+            }
+        }
+
+        let listening = Listening::of(last.iter());
+        let mut events = Vec::new();
+        for conns in [&mut opened, &mut closed] {
+            conns.sort_by(|a, b| (&a.proto, &a.local, &a.remote).cmp(&(&b.proto, &b.local, &b.remote)));
+        }
+        for (conns, is_open) in [(opened, true), (closed, false)] {
+            for mut conn in conns {
+                self.enrich_sni_from_cache(&mut conn);
+                self.enrich_dns(&mut conn, &listening);
+                events.push(if is_open {
+                    NetNotifyEvent::Opened { conn, offline: true, labels: Labels::default() }
+                } else {
+                    NetNotifyEvent::Closed { conn, offline: true, labels: Labels::default() }
+                });
+            }
+        }
+        events
+    }
+
+    /// The connections of `conns` the rules select, resolved as [`NetNotify::changes`] does.
+    fn selected(&mut self, conns: &HashSet<ConnKey>) -> HashSet<ConnKey> {
+        let listening = Listening::of(conns.iter());
+        let mut out = HashSet::new();
+        for c in conns {
+            let mut e = c.clone();
+            self.enrich_sni_from_cache(&mut e);
+            if !self.matches_addresses(&e) {
+                continue;
+            }
+            self.enrich_dns(&mut e, &listening);
+            if self.matches_hosts(&e) {
+                out.insert(c.clone());
+            }
+        }
+        out
+    }
+
     pub fn ignore(&mut self, pat: &str) {
+        if let Some(local) = pat.strip_prefix(LOCAL_HOST_PREFIX) {
+            let Ok(p) = Pattern::new(local) else {
+                return;
+            };
+            self.enable_local_dns();
+            self.ignore_local_host.push(p);
+            return;
+        }
+
         let Ok(p) = Pattern::new(pat) else {
             return;
         };
 
         if is_hostish(pat) {
-            self.cfg.dns = true; // still needed, because ignore can require host
+            self.enable_remote_dns();
             self.ignore_host.push(p);
         } else if is_ipish(pat) {
             self.ignore_ip.push(p);
@@ -276,6 +1543,31 @@ impl NetNotify {
         }
     }
 
+    /// Watch connections whose *local* address resolves to a host matching `pat` (glob).
+    /// Same as `add("local-host:<pat>")`. Turns on DNS enrichment for the local side only.
+    pub fn add_local_host(&mut self, pat: &str) {
+        let Ok(p) = Pattern::new(pat) else {
+            return;
+        };
+        self.enable_local_dns();
+        self.watch_local_host.push(p);
+    }
+
+    fn enable_local_dns(&mut self) {
+        if !self.cfg.dns {
+            // off until now: remote names were not asked for
+            self.cfg.dns = true;
+            self.cfg.dns_targets.remote = false;
+        }
+        self.cfg.dns_targets.local = true;
+    }
+
+    /// Host rules need the remote names.
+    fn enable_remote_dns(&mut self) {
+        self.cfg.dns = true;
+        self.cfg.dns_targets.remote = true;
+    }
+
     pub fn dns(mut self, on: bool) -> Self {
         self.cfg.dns = on;
         self
@@ -287,8 +1579,14 @@ impl NetNotify {
     }
 
     fn dns_cached(&mut self, ip: std::net::IpAddr) -> Option<String> {
-        use std::time::Instant;
+        let resolver = self.resolver.clone();
+        self.dns_cached_with(ip, |ip| resolver.reverse(ip))
+    }
 
+    pub(crate) fn dns_cached_with<F>(&mut self, ip: std::net::IpAddr, resolve: F) -> Option<String>
+    where
+        F: FnOnce(std::net::IpAddr) -> Option<String>,
+    {
         // skip junk
         if matches!(ip, std::net::IpAddr::V4(v4) if v4.octets() == [0,0,0,0]) {
             return None;
@@ -297,100 +1595,131 @@ impl NetNotify {
             return None;
         }
 
-        let now = Instant::now();
+        let now = self.cfg.clock.now_instant();
         if let Some((name, exp)) = self.dns_cache.get(&ip)
             && *exp > now
         {
             return Some(name.clone());
         }
+        if self.profile == Profile::Degraded {
+            return None;
+        }
 
-        let name = reverse_dns(ip)?;
+        let name = resolve(ip)?;
+        // expired names of remotes not seen again would stay forever; sweeping once the cache
+        // doubled since the last sweep keeps inserts amortized O(1)
+        if self.dns_cache.len() >= self.dns_sweep_at {
+            self.dns_cache.retain(|_, (_, exp)| *exp > now);
+            self.dns_sweep_at = (self.dns_cache.len() * 2).max(DNS_SWEEP_MIN);
+        }
         self.dns_cache.insert(ip, (name.clone(), now + self.cfg.dns_ttl));
         Some(name)
     }
 
-    async fn enrich_sni(&mut self, c: &mut ConnKey) {
-        if c.remote_sni.is_some() {
+    fn enrich_dns(&mut self, c: &mut ConnKey, listening: &Listening) {
+        if !self.cfg.dns {
             return;
         }
 
-        // only tcp/tcp6
-        if c.proto != "tcp" && c.proto != "tcp6" {
-            return;
+        // cache is keyed by IP only, so both sides share it
+        let host_rules = !self.watch_host.is_empty() || !self.ignore_host.is_empty();
+        if self.cfg.dns_targets.remote
+            && let Some(ip) = c.remote_addr.map(|a| a.ip())
+            && self.cfg.dns_policy.resolves(ip, listening.direction(c), host_rules)
+        {
+            c.remote_host = self.dns_cached(ip);
         }
 
-        let Some(local_dec) = c.local_dec.as_deref() else {
-            return;
-        };
-        let Some(remote_dec) = c.remote_dec.as_deref() else {
-            return;
-        };
-
-        let Some((lip, lport)) = netutil::split_ip_port(local_dec) else {
-            return;
-        };
-        let Some((rip, rport)) = netutil::split_ip_port(remote_dec) else {
-            return;
-        };
-
-        // only HTTPS
-        if rport != 443 {
-            return;
+        if self.cfg.dns_targets.local
+            && let Some(ip) = c.local_addr.map(|a| a.ip())
+        {
+            c.local_host = self.dns_cached(ip);
         }
-
-        // read from shared cache filled by run_sni_sniffer
-        c.remote_sni = crate::tls_sni::lookup_sni((lip, lport, rip, rport), Duration::from_secs(300));
     }
 
-    fn enrich_dns(&mut self, c: &mut ConnKey) {
-        if !self.cfg.dns {
-            return;
-        }
+    /// All rules, as [`NetNotify::changes`] applies them around resolution.
+    #[cfg(test)]
+    fn matches(&self, c: &ConnKey) -> bool {
+        self.matches_addresses(c) && self.matches_hosts(c)
+    }
 
-        fn ip_only(dec: &Option<String>) -> Option<std::net::IpAddr> {
-            let s = dec.as_deref()?;
-            let (ip, _) = s.rsplit_once(':')?;
-            ip.parse().ok()
+    /// `ev` with the labels of the patterns selecting its connections, see
+    /// [`NetNotify::add_labeled`].
+    fn labeled(&self, mut ev: NetNotifyEvent) -> NetNotifyEvent {
+        if self.labeled.is_empty() {
+            return ev;
         }
-
-        if let Some(ip) = ip_only(&c.remote_dec) {
-            c.remote_host = self.dns_cached(ip);
+        let labels = match &ev {
+            NetNotifyEvent::Opened { conn, .. } | NetNotifyEvent::Closed { conn, .. } => self.labels_of(conn),
+            NetNotifyEvent::Reconnected { old_conn, new_conn, .. } => Labels::merged([&self.labels_of(old_conn), &self.labels_of(new_conn)]),
+            _ => return ev,
+        };
+        if let Some(slot) = ev.labels_mut() {
+            *slot = labels;
         }
+        ev
     }
 
-    fn matches(&self, c: &ConnKey) -> bool {
-        // ----- decode/normalize -----
-        let local = c.local_dec.as_deref().unwrap_or(&c.local);
-        let remote = c.remote_dec.as_deref().unwrap_or(&c.remote);
-
-        // normalize proto so "udp * *" matches udp6 too
-        let proto = c.proto.strip_suffix('6').unwrap_or(&c.proto);
-
-        // DSL-friendly target: "<proto> <local> <remote>"
-        let simple = format!("{} {} {}", proto, local, remote);
+    fn labels_of(&self, c: &ConnKey) -> Labels {
+        let (simple, remote_ip) = address_forms(c);
+        let target = target_form(c);
+        let (remote_host, local_host) = host_forms(c);
+        Labels::merged(self.labeled.iter().filter_map(|l| {
+            let p = &l.pattern;
+            let hit = match l.matcher {
+                Matcher::Target => p.matches(&simple) && p.matches(&target),
+                Matcher::Ip => p.matches(&remote_ip),
+                Matcher::Host => !remote_host.is_empty() && p.matches(remote_host),
+                Matcher::LocalHost => !local_host.is_empty() && p.matches(local_host),
+            };
+            hit.then_some(&l.labels)
+        }))
+    }
 
-        // Precompute remote ip/host for the typed matchers
-        let remote_dec = c.remote_dec.as_deref().unwrap_or("-");
-        let remote_ip = remote_dec.rsplit_once(':').map(|(ip, _)| ip).unwrap_or(remote_dec);
-
-        let mut remote_host = c.remote_host.as_deref().unwrap_or("");
-        if remote_host.is_empty() {
-            remote_host = c.remote_sni.as_deref().or(c.remote_host.as_deref()).unwrap_or("");
-        }
+    /// The rules that need no host name: generic, IP and raw target patterns.
+    fn matches_addresses(&self, c: &ConnKey) -> bool {
+        let (simple, remote_ip) = address_forms(c);
 
         // generic ignore (DSL: "udp * *", "tcp * 1.2.3.4:*", etc)
         if self.ignore.iter().any(|p| p.matches(&simple)) {
             return false;
         }
+        if self.ignore_ip.iter().any(|p| p.matches(&remote_ip)) {
+            return false;
+        }
 
-        if !remote_host.is_empty() && self.ignore_host.iter().any(|p| p.matches(remote_host)) {
+        if !self.watch.is_empty() && !self.watch.iter().any(|p| p.matches(&simple)) {
             return false;
         }
-        if self.ignore_ip.iter().any(|p| p.matches(remote_ip)) {
+
+        // IP watch: if configured, require match
+        if !self.watch_ip.is_empty() && !self.watch_ip.iter().any(|p| p.matches(&remote_ip)) {
             return false;
         }
 
-        if !self.watch.is_empty() && !self.watch.iter().any(|p| p.matches(&simple)) {
+        if !self.watch.is_empty() || !self.ignore.is_empty() {
+            let target = target_form(c);
+
+            if !self.watch.is_empty() && !self.watch.iter().any(|p| p.matches(&target)) {
+                return false;
+            }
+
+            if self.ignore.iter().any(|p| p.matches(&target)) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// The host rules, on the resolved names (or the TLS SNI when the remote has none).
+    fn matches_hosts(&self, c: &ConnKey) -> bool {
+        let (remote_host, local_host) = host_forms(c);
+
+        if !remote_host.is_empty() && self.ignore_host.iter().any(|p| p.matches(remote_host)) {
+            return false;
+        }
+        if !local_host.is_empty() && self.ignore_local_host.iter().any(|p| p.matches(local_host)) {
             return false;
         }
 
@@ -404,40 +1733,71 @@ impl NetNotify {
             }
         }
 
-        // IP watch: if configured, require match
-        if !self.watch_ip.is_empty() && !self.watch_ip.iter().any(|p| p.matches(remote_ip)) {
+        // Local host watch: same as host watch, but for the local side
+        if !self.watch_local_host.is_empty() && (local_host.is_empty() || !self.watch_local_host.iter().any(|p| p.matches(local_host))) {
             return false;
         }
 
-        if !self.watch.is_empty() || !self.ignore.is_empty() {
-            let target = format!(
-                "{} raw:{}->{} dec:{}->{} state:{}:{}",
-                proto,
-                c.local,
-                c.remote,
-                c.local_dec.as_deref().unwrap_or("-"),
-                c.remote_dec.as_deref().unwrap_or("-"),
-                c.state.as_deref().unwrap_or("-"),
-                c.state_dec.as_deref().unwrap_or("-"),
-            );
+        true
+    }
+}
 
-            if !self.watch.is_empty() && !self.watch.iter().any(|p| p.matches(&target)) {
-                return false;
-            }
+/// What generic and IP patterns match: `"<proto> <local> <remote>"` (decoded where possible,
+/// `udp6` as `udp` so `"udp * *"` matches both) and the remote IP, `-` if unknown.
+#[cfg(feature = "runtime")]
+fn address_forms(c: &ConnKey) -> (String, String) {
+    let local = c.local_dec.as_deref().unwrap_or(&c.local);
+    let remote = c.remote_dec.as_deref().unwrap_or(&c.remote);
+    let proto = c.proto.strip_suffix('6').unwrap_or(&c.proto);
+    let remote_ip = c.remote_addr.map(|a| a.ip().to_string()).unwrap_or_else(|| "-".to_string());
+    (format!("{proto} {local} {remote}"), remote_ip)
+}
 
-            if self.ignore.iter().any(|p| p.matches(&target)) {
-                return false;
-            }
-        }
+/// The raw target generic patterns must match as well.
+#[cfg(feature = "runtime")]
+fn target_form(c: &ConnKey) -> String {
+    format!(
+        "{} raw:{}->{} dec:{}->{} state:{}:{}",
+        c.proto.strip_suffix('6').unwrap_or(&c.proto),
+        c.local,
+        c.remote,
+        c.local_dec.as_deref().unwrap_or("-"),
+        c.remote_dec.as_deref().unwrap_or("-"),
+        c.state.as_deref().unwrap_or("-"),
+        c.state_dec.as_deref().unwrap_or("-"),
+    )
+}
 
-        true
+/// What host patterns match: the remote's resolved name (or its TLS SNI when it has none) and
+/// the local name, empty if unknown.
+#[cfg(feature = "runtime")]
+fn host_forms(c: &ConnKey) -> (&str, &str) {
+    let mut remote_host = c.remote_host.as_deref().unwrap_or("");
+    if remote_host.is_empty() {
+        remote_host = c.remote_sni.as_deref().or(c.remote_host.as_deref()).unwrap_or("");
     }
+    (remote_host, c.local_host.as_deref().unwrap_or(""))
 }
 
+/// Estimated size of a connection set entry. Interned fields are shared between entries and
+/// not counted.
+#[cfg(feature = "runtime")]
+fn conn_bytes(c: &ConnKey) -> u64 {
+    let opt = |s: &Option<String>| s.as_ref().map_or(0, String::len);
+    let heap = c.local.len() + opt(&c.local_dec) + opt(&c.remote_dec) + opt(&c.local_host) + opt(&c.remote_host) + opt(&c.remote_sni);
+    memory::entry(size_of::<ConnKey>(), heap)
+}
+
+#[cfg(feature = "runtime")]
 impl Sensor for NetNotify {
     type Event = NetNotifyEvent;
 
-    fn run(self, ctx: SensorCtx<Self::Event>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+    fn run<R: Send + 'static>(self, ctx: SensorCtx<Self::Event, R>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
         Box::pin(async move { NetNotify::run(self, ctx).await })
     }
+
+    fn preflight(&self) -> Pin<Box<dyn Future<Output = Vec<PreflightFinding>> + Send + '_>> {
+        let findings = self.preflight_findings();
+        Box::pin(async move { findings })
+    }
 }
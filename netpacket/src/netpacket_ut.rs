@@ -0,0 +1,1339 @@
+use crate::{
+    DnsTargets, NetNotify, NetNotifyConfig, baseline,
+    counters::CounterRule,
+    engine::ConnDiffer,
+    engine_ut::{conns, random_rows},
+    error::NetNotifyError,
+    events::{ConnKey, NetNotifyEvent, NetNotifyMask},
+    learn::{self, Aggregation, LearnConfig, LearnMode},
+    preview::NetNotifyRule,
+    summary::{Dimension, DimensionSummary, TopEntry},
+    watermark::{self, StateFilter, Watermark, above},
+};
+use async_trait::async_trait;
+use omnitrace_core::{
+    anomaly::{ParseAnomaly, ParseFailure},
+    callbacks::{Callback, CallbackHub, CallbackResult, KIND_BITS, RESERVED_BITS, SUBKIND_BITS},
+    clock::{Clock, ManualClock},
+    debug::Snapshots,
+    fields::{self, EventFields, FieldValue},
+    format::{format_long, format_short},
+    history::HistoryConfig,
+    labels::Labels,
+    sensor::{Sensor, spawn_sensor},
+    severity::Severity,
+    topics::Topics,
+};
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tempfile::TempDir;
+use tokio::sync::mpsc::channel;
+
+fn conn(local: &str, remote: &str, local_host: Option<&str>, remote_host: Option<&str>) -> ConnKey {
+    ConnKey {
+        proto: "tcp".into(),
+        local: "-".to_string(),
+        remote: "-".into(),
+        state: Some("01".into()),
+        local_addr: local.parse().ok(),
+        remote_addr: remote.parse().ok(),
+        local_dec: Some(local.to_string()),
+        remote_dec: Some(remote.to_string()),
+        state_dec: Some("ESTABLISHED".into()),
+        local_host: local_host.map(str::to_string),
+        remote_host: remote_host.map(str::to_string),
+        remote_sni: None,
+    }
+}
+
+#[test]
+fn dns_targets_default_to_remote_only() {
+    assert_eq!(DnsTargets::default(), DnsTargets { remote: true, local: false });
+}
+
+#[test]
+fn local_host_prefix_enables_local_resolution() {
+    let mut sensor = NetNotify::new(Some(NetNotifyConfig::default().dns_targets(DnsTargets { remote: false, local: false })));
+    sensor.add("local-host:vip-*.corp");
+
+    assert!(sensor.cfg.dns);
+    assert!(sensor.cfg.dns_targets.local);
+    assert!(!sensor.cfg.dns_targets.remote);
+    assert_eq!(sensor.watch_local_host.len(), 1);
+    assert!(sensor.watch_host.is_empty());
+
+    // DNS was off: local names do not bring remote lookups along, remote host rules do
+    let mut sensor = NetNotify::new(None);
+    sensor.add_local_host("vip-*.corp");
+    assert_eq!((sensor.cfg.dns, sensor.cfg.dns_targets), (true, DnsTargets { remote: false, local: true }));
+    sensor.ignore("*.example.com");
+    assert_eq!(sensor.cfg.dns_targets, DnsTargets { remote: true, local: true });
+
+    // DNS was on: remote lookups stay
+    let mut sensor = NetNotify::new(None).dns(true);
+    sensor.add_local_host("vip-*.corp");
+    assert_eq!(sensor.cfg.dns_targets, DnsTargets { remote: true, local: true });
+}
+
+#[test]
+fn matches_local_host_watch() {
+    let mut sensor = NetNotify::new(None);
+    sensor.add_local_host("vip-*.corp");
+
+    assert!(sensor.matches(&conn("10.0.0.10:443", "203.0.113.7:51000", Some("vip-web.corp"), None)));
+    assert!(!sensor.matches(&conn("10.0.0.11:443", "203.0.113.7:51000", Some("db.corp"), None)));
+    // not resolved (yet) => no match
+    assert!(!sensor.matches(&conn("10.0.0.12:443", "203.0.113.7:51000", None, None)));
+}
+
+#[test]
+fn matches_local_host_ignore() {
+    let mut sensor = NetNotify::new(None);
+    sensor.add("*");
+    sensor.ignore("local-host:localhost*");
+
+    assert!(!sensor.matches(&conn("127.0.0.1:8080", "127.0.0.1:40000", Some("localhost"), Some("localhost"))));
+    assert!(sensor.matches(&conn("10.0.0.10:443", "203.0.113.7:51000", Some("vip-web.corp"), None)));
+}
+
+#[test]
+fn local_and_remote_host_rules_combine() {
+    let mut sensor = NetNotify::new(None);
+    sensor.add("*.example.com");
+    sensor.add_local_host("vip-*.corp");
+
+    assert!(sensor.matches(&conn("10.0.0.10:443", "93.184.216.34:51000", Some("vip-web.corp"), Some("edge.example.com"))));
+    assert!(!sensor.matches(&conn("10.0.0.10:443", "93.184.216.34:51000", Some("vip-web.corp"), Some("other.net"))));
+    assert!(!sensor.matches(&conn("10.0.0.10:443", "93.184.216.34:51000", Some("db.corp"), Some("edge.example.com"))));
+}
+
+#[test]
+fn ipv6_conn_keys_carry_structured_addresses() {
+    let c = baseline::conn_key("tcp6", "00000000000000000000000000000001:01BB", "00000000000000000000000000000001:9C40", Some("01".into()));
+    assert_eq!(c.local_addr, Some("[::1]:443".parse().unwrap()));
+    assert_eq!(c.remote_addr, Some("[::1]:40000".parse().unwrap()));
+    assert_eq!(c.local_dec.as_deref(), Some("[::1]:443"));
+    assert_eq!(c.remote_dec.as_deref(), Some("[::1]:40000"));
+}
+
+#[test]
+fn ipv6_ip_rules_use_the_address_not_the_string() {
+    let mut sensor = NetNotify::new(None);
+    sensor.add("::1");
+
+    assert!(sensor.matches(&conn("[::1]:40000", "[::1]:443", None, None)));
+    // the address ::1:443 (port 80) used to be indistinguishable from ::1 port 443
+    assert!(!sensor.matches(&conn("[::1]:40000", "[::1:443]:80", None, None)));
+
+    let mut sensor = NetNotify::new(None);
+    sensor.add("*");
+    sensor.ignore("2001:470::*");
+    assert!(!sensor.matches(&conn("[2001:470::5]:40000", "[2001:470::1]:443", None, None)));
+    assert!(sensor.matches(&conn("[2001:470::5]:40000", "[2001:471::1]:443", None, None)));
+}
+
+// -------------------------
+// persisted baseline across restarts
+// -------------------------
+
+struct JsonCb;
+
+#[async_trait]
+impl Callback<NetNotifyEvent> for JsonCb {
+    fn mask(&self) -> u64 {
+        (NetNotifyMask::OPENED | NetNotifyMask::CLOSED).bits()
+    }
+
+    async fn call(&self, ev: &NetNotifyEvent) -> Option<CallbackResult> {
+        serde_json::to_value(ev).ok()
+    }
+}
+
+/// Write a `/proc/net/tcp`-shaped table with the given (local, remote, state) rows.
+fn write_tcp_table(dir: &Path, rows: &[(&str, &str, &str)]) {
+    let mut txt = String::from("  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n");
+    for (i, (local, remote, st)) in rows.iter().enumerate() {
+        txt.push_str(&format!("  {i}: {local} {remote} {st} 00000000:00000000 00:00000000 00000000  1000        0 {}\n", 1000 + i));
+    }
+    std::fs::write(dir.join("tcp"), txt).unwrap();
+}
+
+// 10.0.0.5:40000 -> 93.184.216.34:443 and friends, in /proc/net/tcp encoding
+const KEEP: (&str, &str, &str) = ("0500000A:9C40", "22D8B85D:01BB", "01");
+const GONE: (&str, &str, &str) = ("0500000A:9C41", "22D8B85D:01BB", "01");
+const NEW: (&str, &str, &str) = ("0500000A:9C42", "08080808:0035", "01");
+
+fn scripted_baseline(path: &Path, saved_at: SystemTime) {
+    let conns: HashSet<ConnKey> = [KEEP, GONE].iter().map(|(l, r, st)| baseline::conn_key("tcp", l, r, Some((*st).into()))).collect();
+    baseline::save(path, &conns, saved_at).unwrap();
+}
+
+async fn run_sensor(cfg: NetNotifyConfig, settle: Duration) -> Vec<CallbackResult> {
+    let (tx, mut rx) = channel::<CallbackResult>(16);
+    let mut hub = CallbackHub::<NetNotifyEvent>::new();
+    hub.add(JsonCb);
+    hub.set_result_channel(tx);
+
+    let (handle, task) = spawn_sensor(NetNotify::new(Some(cfg)), Arc::new(hub));
+    tokio::time::sleep(settle).await;
+    handle.shutdown();
+    let _ = task.await;
+
+    let mut out = Vec::new();
+    while let Ok(r) = rx.try_recv() {
+        out.push(r);
+    }
+    out
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn restart_reports_offline_changes() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let state = dir.join("baseline.json");
+    scripted_baseline(&state, SystemTime::now());
+
+    // "restart" with a different table: GONE closed and NEW opened while we were down
+    write_tcp_table(dir, &[KEEP, NEW]);
+
+    let cfg = NetNotifyConfig::default().pulse(Duration::from_millis(10)).proc_net(dir).baseline_path(&state);
+    let mut events = run_sensor(cfg, Duration::from_millis(100)).await;
+    events.sort_by_key(|e| e.to_string());
+
+    assert_eq!(events.len(), 2, "{events:?}");
+    assert_eq!(events[0]["Closed"]["conn"]["local_dec"], "10.0.0.5:40001");
+    assert_eq!(events[0]["Closed"]["offline"], true);
+    assert_eq!(events[1]["Opened"]["conn"]["local_dec"], "10.0.0.5:40002");
+    assert_eq!(events[1]["Opened"]["conn"]["remote_dec"], "8.8.8.8:53");
+    assert_eq!(events[1]["Opened"]["offline"], true);
+
+    // shutdown persisted the current set for the next start
+    let saved = baseline::load(&state).unwrap();
+    let locals: HashSet<_> = saved.conns.iter().filter_map(|c| c.local_dec.clone()).collect();
+    assert_eq!(locals, HashSet::from(["10.0.0.5:40000".to_string(), "10.0.0.5:40002".to_string()]));
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn read_skew_artifacts_are_suppressed() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let state = dir.join("baseline.json");
+    scripted_baseline(&state, SystemTime::now());
+
+    // GONE seen again in another state, as when a socket changes between two table reads
+    let (l, r, _) = GONE;
+    write_tcp_table(dir, &[KEEP, (l, r, "08"), NEW]);
+
+    let sensor = NetNotify::new(Some(NetNotifyConfig::default().pulse(Duration::from_millis(10)).proc_net(dir).baseline_path(&state)));
+    let skew = sensor.skew_stats();
+    let (tx, mut rx) = channel::<CallbackResult>(16);
+    let mut hub = CallbackHub::<NetNotifyEvent>::new();
+    hub.add(JsonCb);
+    hub.set_result_channel(tx);
+    let (handle, task) = spawn_sensor(sensor, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    handle.shutdown();
+    let _ = task.await;
+
+    let ev = rx.try_recv().unwrap();
+    assert_eq!(ev["Opened"]["conn"]["local_dec"], "10.0.0.5:40002");
+    assert!(rx.try_recv().is_err(), "no Opened/Closed pair for 10.0.0.5:40001");
+    assert_eq!(skew.suppressed(), 1);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn events_carry_the_labels_of_their_patterns() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let state = dir.join("baseline.json");
+    scripted_baseline(&state, SystemTime::now());
+    write_tcp_table(dir, &[KEEP, NEW]);
+
+    let mut sensor = NetNotify::new(Some(NetNotifyConfig::default().pulse(Duration::from_millis(10)).proc_net(dir).baseline_path(&state)));
+    sensor.add_labeled("tcp *", [("proto", "tcp"), ("tier", "any")].into_iter().collect());
+    sensor.add_labeled("93.184.216.34", [("tier", "web")].into_iter().collect());
+    sensor.add("8.8.8.8");
+    let (tx, mut rx) = channel::<CallbackResult>(16);
+    let mut hub = CallbackHub::<NetNotifyEvent>::new();
+    hub.add(JsonCb);
+    hub.set_result_channel(tx);
+    let (handle, task) = spawn_sensor(sensor, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    handle.shutdown();
+    let _ = task.await;
+
+    let mut events = Vec::new();
+    while let Ok(r) = rx.try_recv() {
+        events.push(r);
+    }
+    events.sort_by_key(|e| e.to_string());
+    assert_eq!(events.len(), 2, "{events:?}");
+    assert_eq!(events[0]["Closed"]["labels"], serde_json::json!({"proto": "tcp", "tier": "web"}));
+    assert_eq!(events[1]["Opened"]["labels"], serde_json::json!({"proto": "tcp", "tier": "any"}));
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn stale_baseline_is_discarded() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let state = dir.join("baseline.json");
+    scripted_baseline(&state, SystemTime::now() - Duration::from_secs(7200));
+    write_tcp_table(dir, &[KEEP, NEW]);
+
+    let cfg =
+        NetNotifyConfig::default().pulse(Duration::from_millis(10)).proc_net(dir).baseline_path(&state).max_baseline_age(Duration::from_secs(3600));
+
+    // primes silently, as without a baseline
+    assert!(run_sensor(cfg, Duration::from_millis(100)).await.is_empty());
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn missing_tables_and_bad_baselines_surface_in_diagnostics() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let state = dir.join("baseline.json");
+    std::fs::write(&state, "not json").unwrap();
+    write_tcp_table(dir, &[KEEP]);
+
+    let sensor = NetNotify::new(Some(NetNotifyConfig::default().pulse(Duration::from_millis(10)).proc_net(dir).baseline_path(&state)));
+    let diag = sensor.diagnostics();
+    let (handle, task) = spawn_sensor(sensor, Arc::new(CallbackHub::<NetNotifyEvent>::new()));
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    handle.shutdown();
+    let _ = task.await;
+
+    // no IPv6 here: counted every tick, logged once
+    let tcp6 = diag.get("table:tcp6").unwrap();
+    assert!(tcp6.count > 1);
+    assert!(matches!(tcp6.last.downcast_ref::<NetNotifyError>(), Some(NetNotifyError::TableMissing { .. })));
+    assert!(diag.get("table:tcp").is_none());
+    let baseline = diag.get("baseline").unwrap();
+    assert!(matches!(baseline.last.downcast_ref::<NetNotifyError>(), Some(NetNotifyError::Baseline { .. })), "{}", baseline.message);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn malformed_table_lines_are_captured_raw() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    write_tcp_table(dir, &[KEEP, ("0500000A:9C41", "22D8B85D:01BB", "Z1"), NEW]);
+    let garbage = "  9: 0500000A:9C43 nowhere";
+    let tcp = std::fs::read_to_string(dir.join("tcp")).unwrap() + garbage + "\n";
+    std::fs::write(dir.join("tcp"), tcp).unwrap();
+
+    let sensor = NetNotify::new(Some(NetNotifyConfig::default().pulse(Duration::from_millis(10)).proc_net(dir).capture_parse_anomalies(8)));
+    let debug = sensor.debug_handle();
+    let mut hub = CallbackHub::<NetNotifyEvent>::new();
+    hub.add_fn(NetNotifyMask::PARSE_ANOMALY.bits(), |ev| {
+        let ev = serde_json::to_value(ev).unwrap();
+        async move { Some(ev) }
+    });
+    let (tx, mut rx) = channel(64);
+    hub.set_result_channel(tx);
+    let (handle, task) = spawn_sensor(sensor, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(35)).await;
+    handle.shutdown();
+    let _ = task.await;
+
+    let path = dir.join("tcp").display().to_string();
+    let bad_state = "  1: 0500000A:9C41 22D8B85D:01BB Z1 00000000:00000000 00:00000000 00000000  1000        0 1001";
+    let expected = [
+        serde_json::json!({ "ParseAnomaly": { "source": path, "raw": bad_state, "column": 4, "reason": "state \"Z1\" is not two hex digits" } }),
+        serde_json::json!({ "ParseAnomaly": { "source": path, "raw": garbage, "column": 3, "reason": "remote address \"nowhere\" is not <hex ip>:<hex port>" } }),
+    ];
+    let mut reads = 0;
+    while let Ok(first) = rx.try_recv() {
+        assert_eq!([first, rx.try_recv().unwrap()], expected);
+        reads += 1;
+    }
+    let d = debug.get();
+    assert!(reads >= 2);
+    assert_eq!(d.connections, 2, "the good lines still parse");
+    assert_eq!((d.parse.failures, d.parse.captured, d.parse.dropped), (2 * reads, 2 * reads, 0));
+}
+
+// -------------------------
+// simulated time
+// -------------------------
+
+#[test]
+fn dns_ttl_holds_over_hours_of_simulated_time() {
+    let clock = ManualClock::new();
+    let mut sensor = NetNotify::new(Some(NetNotifyConfig::default().clock(clock.shared()))).dns_ttl(Duration::from_secs(60));
+    let ip = "93.184.216.34".parse().unwrap();
+    let mut lookups = 0;
+
+    // a connection seen every 250ms for six hours: one lookup per TTL, not per sighting
+    for _ in 0..(6 * 3600 * 4) {
+        let name = sensor.dns_cached_with(ip, |_| {
+            lookups += 1;
+            Some("example.com".to_string())
+        });
+        assert_eq!(name.as_deref(), Some("example.com"));
+        clock.advance(Duration::from_millis(250));
+    }
+    assert_eq!(lookups, 6 * 60);
+}
+
+#[test]
+fn baseline_age_follows_the_clock() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let state = dir.join("baseline.json");
+    // on a whole second, as saved
+    let clock = ManualClock::at(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    scripted_baseline(&state, clock.now_system());
+
+    let sensor =
+        NetNotify::new(Some(NetNotifyConfig::default().baseline_path(&state).max_baseline_age(Duration::from_secs(3600)).clock(clock.shared())));
+    clock.advance(Duration::from_secs(3600));
+    assert_eq!(sensor.load_baseline().map(|b| b.len()), Some(2));
+    clock.advance(Duration::from_secs(1));
+    assert!(sensor.load_baseline().is_none());
+}
+
+// -------------------------
+// watermarks and limits
+// -------------------------
+
+#[test]
+fn watermark_sweep_with_hysteresis_and_sustain() {
+    let mut wm = Watermark::new(StateFilter::state("TIME_WAIT"), above(10).clear_at(5), 2);
+
+    let fed: Vec<Option<bool>> = [5, 11, 9, 11, 12, 13, 9, 6, 4, 7, 3, 2].iter().map(|n| wm.update(*n)).collect();
+    assert_eq!(
+        fed,
+        vec![
+            None,        // 5: below
+            None,        // 11: above, 1 tick
+            None,        // 9: streak broken
+            None,        // 11: 1 tick
+            Some(true),  // 12: 2 ticks above => exceeded
+            None,        // 13: still exceeded
+            None,        // 9: under high, but not at/below low
+            None,        // 6: same
+            None,        // 4: at/below low, 1 tick
+            None,        // 7: streak broken
+            None,        // 3: 1 tick
+            Some(false)  // 2: 2 ticks => cleared
+        ]
+    );
+}
+
+#[test]
+fn state_filter_counts() {
+    let conns: HashSet<ConnKey> = [
+        baseline::conn_key("tcp", "0500000A:9C40", "22D8B85D:01BB", Some("06".into())),
+        baseline::conn_key("tcp", "0500000A:9C41", "22D8B85D:01BB", Some("06".into())),
+        baseline::conn_key("tcp6", "00000000000000000000000001000000:0050", "00000000000000000000000001000000:9C40", Some("06".into())),
+        baseline::conn_key("tcp", "0500000A:0050", "22D8B85D:9C42", Some("03".into())),
+        baseline::conn_key("udp", "0500000A:0035", "00000000:0000", None),
+    ]
+    .into_iter()
+    .collect();
+    let counts = watermark::state_counts(&conns);
+
+    assert_eq!(counts["tcp:TIME_WAIT"], 3);
+    assert_eq!(counts["tcp:SYN_RECV"], 1);
+    assert_eq!(counts["udp:-"], 1);
+    assert_eq!(StateFilter::state("TIME_WAIT").count(&counts), 3);
+    assert_eq!(StateFilter::any().proto("tcp").count(&counts), 4);
+    assert_eq!(StateFilter::any().count(&counts), 5);
+    assert_eq!(StateFilter::state("TIME_WAIT").proto("udp").count(&counts), 0);
+}
+
+fn time_wait_rows(n: usize) -> Vec<(String, String, String)> {
+    (0..n).map(|i| (format!("0500000A:{:04X}", 40000 + i), "22D8B85D:01BB".to_string(), "06".to_string())).collect()
+}
+
+fn write_rows(dir: &Path, rows: &[(String, String, String)]) {
+    let rows: Vec<(&str, &str, &str)> = rows.iter().map(|(l, r, s)| (l.as_str(), r.as_str(), s.as_str())).collect();
+    write_tcp_table(dir, &rows);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn watermark_only_mode_reports_crossings_and_limits() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let sys = dir.join("sys");
+    std::fs::create_dir_all(sys.join("net/ipv4")).unwrap();
+    std::fs::write(sys.join("net/ipv4/tcp_mem"), "188418\t251224\t376836\n").unwrap();
+    write_rows(dir, &time_wait_rows(3));
+
+    let mut sensor = NetNotify::new(Some(NetNotifyConfig::default().pulse(Duration::from_millis(10)).proc_net(dir).proc_sys(&sys)));
+    sensor.watermark(StateFilter::state("TIME_WAIT").proto("tcp"), above(10), 1);
+    sensor.watch_limit("net.ipv4.tcp_mem");
+    assert!(sensor.watermark_only());
+
+    let (tx, mut rx) = channel::<CallbackResult>(16);
+    let mut hub = CallbackHub::<NetNotifyEvent>::new();
+    hub.add(WatermarkCb);
+    hub.set_result_channel(tx);
+    let (handle, task) = spawn_sensor(sensor, Arc::new(hub));
+
+    tokio::time::sleep(Duration::from_millis(40)).await;
+    write_rows(dir, &time_wait_rows(12));
+    let exceeded = tokio::time::timeout(Duration::from_millis(500), rx.recv()).await.unwrap().unwrap();
+
+    std::fs::write(sys.join("net/ipv4/tcp_mem"), "94209\t125612\t188418\n").unwrap();
+    let limit = tokio::time::timeout(Duration::from_millis(500), rx.recv()).await.unwrap().unwrap();
+
+    write_rows(dir, &time_wait_rows(2));
+    let cleared = tokio::time::timeout(Duration::from_millis(500), rx.recv()).await.unwrap().unwrap();
+
+    handle.shutdown();
+    let _ = task.await;
+
+    assert_eq!(exceeded["WatermarkExceeded"]["watermark"], "tcp:TIME_WAIT");
+    assert_eq!(exceeded["WatermarkExceeded"]["count"], 12);
+    assert_eq!(exceeded["WatermarkExceeded"]["threshold"], 10);
+    assert_eq!(exceeded["WatermarkExceeded"]["counts"]["tcp:TIME_WAIT"], 12);
+
+    assert_eq!(limit["LimitChanged"]["name"], "net/ipv4/tcp_mem");
+    assert_eq!(limit["LimitChanged"]["old"], "188418 251224 376836");
+    assert_eq!(limit["LimitChanged"]["new"], "94209 125612 188418");
+
+    assert_eq!(cleared["WatermarkCleared"]["count"], 2);
+    assert_eq!(cleared["WatermarkCleared"]["threshold"], 9);
+
+    // no per-connection events in watermark-only mode
+    assert!(rx.try_recv().is_err());
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn counters_only_mode_reports_spikes() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let netstat = include_str!("../fixtures/netstat");
+    std::fs::write(dir.join("snmp"), include_str!("../fixtures/snmp")).unwrap();
+    std::fs::write(dir.join("netstat"), netstat).unwrap();
+    write_tcp_table(dir, &[KEEP]);
+
+    let rules = [CounterRule::new("TcpExt", "ListenDrops", 100.0)];
+    let sensor = NetNotify::new(Some(NetNotifyConfig::default().pulse(Duration::from_millis(10)).proc_net(dir).counters(&rules)));
+    assert!(sensor.watermark_only());
+
+    let (tx, mut rx) = channel::<CallbackResult>(16);
+    let mut hub = CallbackHub::<NetNotifyEvent>::new();
+    hub.add(WatermarkCb);
+    hub.set_result_channel(tx);
+    let (handle, task) = spawn_sensor(sensor, Arc::new(hub));
+
+    tokio::time::sleep(Duration::from_millis(40)).await;
+    write_tcp_table(dir, &[KEEP, NEW]);
+    // ListenOverflows and ListenDrops go from 5 to 5005
+    let bumped = netstat.replacen("3901 5 5 ", "3901 5005 5005 ", 1);
+    assert_ne!(bumped, netstat);
+    std::fs::write(dir.join("netstat"), bumped).unwrap();
+    let spike = tokio::time::timeout(Duration::from_millis(500), rx.recv()).await.unwrap().unwrap();
+
+    tokio::time::sleep(Duration::from_millis(40)).await;
+    handle.shutdown();
+    let _ = task.await;
+
+    assert_eq!(spike["CounterSpike"]["table"], "TcpExt");
+    assert_eq!(spike["CounterSpike"]["field"], "ListenDrops");
+    assert_eq!(spike["CounterSpike"]["previous"], 5);
+    assert_eq!(spike["CounterSpike"]["current"], 5005);
+    assert_eq!(spike["CounterSpike"]["delta"], 5000);
+
+    // one spike, and no per-connection events without patterns
+    assert!(rx.try_recv().is_err());
+}
+
+/// Passes every event through, so the test would see stray Opened/Closed.
+struct WatermarkCb;
+
+#[async_trait]
+impl Callback<NetNotifyEvent> for WatermarkCb {
+    fn mask(&self) -> u64 {
+        u64::MAX
+    }
+
+    async fn call(&self, ev: &NetNotifyEvent) -> Option<CallbackResult> {
+        serde_json::to_value(ev).ok()
+    }
+}
+
+#[test]
+fn test_conn_keys_decode_like_live_ones() {
+    let c = ConnKey::test("tcp", "10.0.0.2:51000", "93.184.216.34:443");
+    assert_eq!(c.proto, "tcp");
+    assert_eq!(c.local, "0200000A:C738");
+    assert_eq!(c.remote_dec.as_deref(), Some("93.184.216.34:443"));
+    assert_eq!(c.state_dec.as_deref(), Some("ESTABLISHED"));
+
+    let NetNotifyEvent::Opened { conn, offline: false, .. } = NetNotifyEvent::test_opened("udp", "[::1]:5353", "[2001:db8::1]:53") else {
+        panic!("expected Opened");
+    };
+    assert_eq!(conn.proto, "udp6");
+    assert_eq!(conn.remote_addr, Some("[2001:db8::1]:53".parse().unwrap()));
+    assert_eq!(conn.state, None);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn debug_snapshot_reports_table_and_rules() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    write_tcp_table(dir, &[KEEP, NEW, ("0500000A:9C43", "22D8B85D:01BB", "06")]);
+
+    let rules = [CounterRule::new("TcpExt", "ListenDrops", 100.0)];
+    let mut sensor = NetNotify::new(Some(NetNotifyConfig::default().pulse(Duration::from_millis(10)).proc_net(dir).counters(&rules)));
+    sensor.add("8.8.8.8");
+    sensor.watermark(StateFilter::state("TIME_WAIT"), above(10), 1);
+    let mut snapshots = Snapshots::new();
+    snapshots.register("netpacket", sensor.debug_handle());
+
+    let (handle, task) = spawn_sensor(sensor, Arc::new(CallbackHub::<NetNotifyEvent>::new()));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let dump = snapshots.dump();
+    let redacted = snapshots.redact().dump();
+    handle.shutdown();
+    let _ = task.await;
+
+    let n = &dump["components"]["netpacket"];
+    for key in [
+        "pulse_ms",
+        "proc_net",
+        "dns",
+        "watermark_only",
+        "primed",
+        "connections",
+        "by_state",
+        "patterns",
+        "watermarks",
+        "counters",
+        "limits",
+        "dns_cache_entries",
+        "sni_cache_entries",
+        "skew_suppressed",
+    ] {
+        assert!(n.get(key).is_some(), "missing {key} in {n}");
+    }
+    assert_eq!(n["primed"], true);
+    assert_eq!(n["connections"], 3);
+    assert_eq!(n["by_state"]["tcp:ESTABLISHED"], 2);
+    assert_eq!(n["by_state"]["tcp:TIME_WAIT"], 1);
+    assert_eq!(n["patterns"]["watch_ip"], serde_json::json!(["8.8.8.8"]));
+    assert_eq!(n["watermarks"], serde_json::json!(["*:TIME_WAIT"]));
+    assert_eq!(n["counters"], serde_json::json!(["TcpExt.ListenDrops"]));
+
+    assert_eq!(redacted["components"]["netpacket"]["patterns"]["watch_ip"][0], omnitrace_core::debug::REDACTED);
+}
+
+struct Recorder(Arc<std::sync::Mutex<Vec<NetNotifyEvent>>>);
+
+#[async_trait]
+impl Callback<NetNotifyEvent> for Recorder {
+    fn mask(&self) -> u64 {
+        (NetNotifyMask::OPENED | NetNotifyMask::CLOSED).bits()
+    }
+
+    async fn call(&self, ev: &NetNotifyEvent) -> Option<CallbackResult> {
+        self.0.lock().unwrap().push(ev.clone());
+        None
+    }
+}
+
+/// Replace the table atomically, so a tick never reads a half-written file.
+fn swap_tcp_table(dir: &Path, rows: &[(String, String, String)]) {
+    let tmp = dir.join("tmp");
+    std::fs::create_dir_all(&tmp).unwrap();
+    write_rows(&tmp, rows);
+    std::fs::rename(tmp.join("tcp"), dir.join("tcp")).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn random_table_scripts_keep_per_connection_order() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let mut rng = fastrand::Rng::with_seed(715);
+    // six sockets to 93.184.216.34:443, each absent, ESTABLISHED or CLOSE_WAIT
+    let mut random_rows = || -> Vec<(String, String, String)> {
+        (0..6)
+            .filter_map(|i| match rng.u8(..3) {
+                0 => None,
+                st => Some((format!("0500000A:{:04X}", 40000 + i), "22D8B85D:01BB".to_string(), if st == 1 { "01" } else { "08" }.to_string())),
+            })
+            .collect()
+    };
+    let start = random_rows();
+    swap_tcp_table(dir, &start);
+
+    let sensor = NetNotify::new(Some(NetNotifyConfig::default().pulse(Duration::from_millis(3)).proc_net(dir)));
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(seen.clone()));
+    let (handle, task) = spawn_sensor(sensor, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(30)).await;
+
+    let mut script = start.clone();
+    for _ in 0..40 {
+        script = random_rows();
+        swap_tcp_table(dir, &script);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    handle.shutdown();
+    let _ = task.await;
+
+    // a state change is not a close and reopen; per 4-tuple, Opened and Closed alternate
+    let tuple = |c: &ConnKey| (c.local_dec.clone().unwrap(), c.remote_dec.clone().unwrap());
+    let mut model: HashSet<(String, String)> = start.iter().map(|(l, r, st)| tuple(&baseline::conn_key("tcp", l, r, Some(st.into())))).collect();
+    for ev in seen.lock().unwrap().iter() {
+        match ev {
+            NetNotifyEvent::Opened { conn, .. } => assert!(model.insert(tuple(conn)), "Opened {:?} twice", tuple(conn)),
+            NetNotifyEvent::Closed { conn, .. } => assert!(model.remove(&tuple(conn)), "Closed {:?} while closed", tuple(conn)),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+    let want: HashSet<_> = script.iter().map(|(l, r, st)| tuple(&baseline::conn_key("tcp", l, r, Some(st.into())))).collect();
+    assert_eq!(model, want);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(start_paused = true)]
+async fn sensor_reports_what_the_engine_does() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let mut rng = fastrand::Rng::with_seed(759);
+    let script: Vec<_> = (0..40).map(|_| random_rows(&mut rng)).collect();
+    swap_tcp_table(dir, &script[0]);
+
+    let clock = ManualClock::new();
+    let sensor = NetNotify::new(Some(NetNotifyConfig::default().pulse(Duration::from_millis(10)).proc_net(dir).clock(clock.shared())));
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(seen.clone()));
+    let (handle, task) = spawn_sensor(sensor, Arc::new(hub));
+
+    // one table per tick: swapped in halfway between two ticks
+    let mut differ = ConnDiffer::new();
+    let mut want = differ.feed(conns(&script[0]));
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    for rows in &script[1..] {
+        swap_tcp_table(dir, rows);
+        want.extend(differ.feed(conns(rows)));
+        clock.advance(Duration::from_millis(10));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    handle.shutdown();
+    let _ = task.await;
+
+    let json = |evs: &[NetNotifyEvent]| -> Vec<serde_json::Value> { evs.iter().map(|ev| serde_json::to_value(ev).unwrap()).collect() };
+    assert!(!want.is_empty());
+    assert_eq!(json(&seen.lock().unwrap()), json(&want));
+}
+
+#[derive(Clone, Copy)]
+enum Edit {
+    Keep,
+    Remove,
+    Add,
+}
+
+/// Drive the pattern `93.184.216.34` through `steps`, one tick each: an edit through the
+/// control, and whether 10.0.0.5:40000 -> 93.184.216.34:443 is open. The first step is the
+/// primed table. `8.8.8.8` stays watched with a connection open throughout, so removing the
+/// pattern does not select everything. Returns the events as `(topic, offline)`.
+async fn pattern_edits(cfg: NetNotifyConfig, steps: &[(Edit, bool)]) -> Vec<(&'static str, bool)> {
+    const PATTERN: &str = "93.184.216.34";
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let table = |open: bool| {
+        let rows: Vec<(String, String, String)> =
+            [(NEW, true), (KEEP, open)].iter().filter(|(_, on)| *on).map(|((l, r, st), _)| (l.to_string(), r.to_string(), st.to_string())).collect();
+        swap_tcp_table(dir, &rows);
+    };
+    table(steps[0].1);
+    let clock = ManualClock::new();
+    let mut sensor = NetNotify::new(Some(cfg.pulse(Duration::from_millis(10)).proc_net(dir).clock(clock.shared())));
+    sensor.add("8.8.8.8");
+    let control = sensor.control();
+    control.add(PATTERN);
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(seen.clone()));
+    let (handle, task) = spawn_sensor(sensor, Arc::new(hub));
+
+    for (edit, open) in steps {
+        match edit {
+            Edit::Keep => {}
+            Edit::Remove => control.remove(PATTERN),
+            Edit::Add => control.add(PATTERN),
+        }
+        table(*open);
+        clock.advance(Duration::from_millis(10));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    handle.shutdown();
+    let _ = task.await;
+
+    let seen = seen.lock().unwrap();
+    seen.iter()
+        .map(|ev| match ev {
+            NetNotifyEvent::Opened { conn, offline, .. } | NetNotifyEvent::Closed { conn, offline, .. } => {
+                assert_eq!(conn.remote_dec.as_deref(), Some("93.184.216.34:443"));
+                (ev.topic(), *offline)
+            }
+            other => panic!("unexpected {other:?}"),
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(start_paused = true)]
+async fn removed_patterns_stop_reporting_and_come_back_silently() {
+    use Edit::*;
+    let cfg = NetNotifyConfig::default;
+    // closed while not watched: no Closed, then or when watched again
+    let steps = [(Keep, true), (Remove, true), (Keep, false), (Add, false), (Keep, true)];
+    assert_eq!(pattern_edits(cfg(), &steps).await, [("net.conn.opened", false)]);
+
+    // opened while not watched: watched again, it is part of the baseline
+    let steps = [(Keep, false), (Remove, false), (Keep, true), (Add, true), (Keep, false)];
+    assert_eq!(pattern_edits(cfg(), &steps).await, [("net.conn.closed", false)]);
+
+    let steps = [(Keep, true), (Remove, true), (Add, true), (Keep, false)];
+    assert_eq!(pattern_edits(cfg(), &steps).await, [("net.conn.closed", false)]);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(start_paused = true)]
+async fn tombstones_report_connections_opened_and_closed_while_unwatched() {
+    use Edit::*;
+    let cfg = || NetNotifyConfig::default().report_unwatched(Duration::from_millis(100));
+    let steps = [(Keep, true), (Remove, true), (Keep, false), (Add, false), (Keep, true)];
+    assert_eq!(pattern_edits(cfg(), &steps).await, [("net.conn.closed", true), ("net.conn.opened", false)]);
+
+    let steps = [(Keep, false), (Remove, false), (Keep, true), (Add, true), (Keep, false)];
+    assert_eq!(pattern_edits(cfg(), &steps).await, [("net.conn.opened", true), ("net.conn.closed", false)]);
+
+    let steps = [(Keep, true), (Remove, true), (Keep, true), (Add, true)];
+    assert!(pattern_edits(cfg(), &steps).await.is_empty());
+
+    // past the ttl it is a silent re-prime
+    let mut steps = vec![(Keep, true), (Remove, true), (Keep, false)];
+    steps.extend([(Keep, false); 12]);
+    steps.push((Add, false));
+    assert!(pattern_edits(cfg(), &steps).await.is_empty());
+}
+
+struct OverBudgets(Arc<std::sync::Mutex<Vec<NetNotifyEvent>>>);
+
+#[async_trait]
+impl Callback<NetNotifyEvent> for OverBudgets {
+    fn mask(&self) -> u64 {
+        NetNotifyMask::OVER_BUDGET.bits()
+    }
+
+    async fn call(&self, ev: &NetNotifyEvent) -> Option<CallbackResult> {
+        self.0.lock().unwrap().push(ev.clone());
+        None
+    }
+}
+
+/// Fill the reverse-DNS cache and 100 SNI entries, one of them for the open connection.
+fn fill_caches(sensor: &mut NetNotify) {
+    let open = conn("10.0.0.5:40000", "93.184.216.34:443", None, None);
+    let now = std::time::Instant::now();
+    for i in 0..200u32 {
+        sensor.dns_cache.insert(std::net::Ipv4Addr::from(0x0a01_0000 + i).into(), (format!("host-{i}.example.internal"), now));
+    }
+    let mut sni = sensor.sni_cache.lock().unwrap();
+    for i in 0..100u16 {
+        let key = (open.local_addr.unwrap().ip(), 40000 + i, open.remote_addr.unwrap().ip(), 443);
+        sni.insert(key, (format!("sni-{i}.example.com"), now));
+    }
+    drop(sni);
+    sensor.last.insert(open);
+}
+
+#[tokio::test]
+async fn over_budget_sheds_dns_then_closed_sni_entries() {
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(OverBudgets(seen.clone()));
+
+    let mut sensor = NetNotify::new(None);
+    let memory = sensor.memory();
+    fill_caches(&mut sensor);
+    sensor.check_memory(&hub).await;
+    let full = memory.report();
+    assert_eq!((full.budget, full.sheds, full.over_budget), (None, 0, false));
+    assert!(full.parts["connections"] > 0 && full.parts["dns_cache"] > full.parts["sni_cache"]);
+    assert_eq!(full.estimated_bytes, full.parts.values().sum::<u64>());
+
+    // dropping the DNS cache is not enough, the SNI entries of closed connections go too
+    let budget = full.parts["connections"] + full.parts["sni_cache"] / 10;
+    sensor.cfg.memory_budget = Some(budget);
+    sensor.check_memory(&hub).await;
+    let shed = memory.report();
+    assert_eq!(sensor.dns_cache.len(), 0);
+    assert_eq!(sensor.sni_cache.lock().unwrap().len(), 1, "the open connection keeps its SNI");
+    assert_eq!((shed.parts["dns_cache"], shed.sheds, shed.over_budget), (0, 1, false));
+    assert_eq!(shed.parts["connections"], full.parts["connections"]);
+    assert!(shed.estimated_bytes <= budget);
+
+    // refilled before the next tick: shed again, but the episode was reported already
+    fill_caches(&mut sensor);
+    sensor.check_memory(&hub).await;
+    assert_eq!(memory.report().sheds, 2);
+
+    let events = seen.lock().unwrap().clone();
+    assert_eq!(events.len(), 1);
+    let NetNotifyEvent::OverBudget { estimated_bytes, after_shedding, budget: b, shed: what } = &events[0] else {
+        panic!("unexpected {:?}", events[0]);
+    };
+    assert_eq!((*estimated_bytes, *after_shedding, *b), (full.estimated_bytes, shed.estimated_bytes, budget));
+    assert_eq!(what, &vec!["dns_cache".to_string(), "sni_cache".to_string()]);
+}
+
+#[tokio::test]
+async fn history_keeps_connections_and_hosts_and_is_shed_first() {
+    let hub: CallbackHub<NetNotifyEvent> = CallbackHub::new();
+    let mut sensor = NetNotify::new(Some(NetNotifyConfig::default().history(HistoryConfig::new(4, 64))));
+    let history = sensor.history().unwrap();
+    for port in 0..6u16 {
+        let c = conn(&format!("10.0.0.5:{}", 40000 + port), "93.184.216.34:443", None, Some("example.com"));
+        for ev in [
+            NetNotifyEvent::Opened { conn: c.clone(), offline: false, labels: Labels::default() },
+            NetNotifyEvent::Closed { conn: c, offline: false, labels: Labels::default() },
+        ] {
+            NetNotify::fire(&hub, &sensor.entities, &sensor.history, ev).await;
+        }
+    }
+    assert!(history.is_empty(), "published at the end of the tick");
+    sensor.publish_debug();
+
+    let ports = |key: &str| -> Vec<u16> {
+        let port = |ev: &NetNotifyEvent| match ev {
+            NetNotifyEvent::Opened { conn, .. } | NetNotifyEvent::Closed { conn, .. } => conn.local_addr.unwrap().port(),
+            other => panic!("unexpected {other:?}"),
+        };
+        history.history(key, 10).iter().map(|e| port(&e.event)).collect()
+    };
+    assert_eq!(ports("example.com"), [40004, 40004, 40005, 40005]);
+    assert_eq!(ports("tcp 10.0.0.5:40001 -> 93.184.216.34:443"), [40001, 40001]);
+    assert_eq!(history.keys()[..2], ["tcp 10.0.0.5:40005 -> 93.184.216.34:443", "example.com"]);
+
+    // over budget, the history goes before the caches
+    let memory = sensor.memory();
+    fill_caches(&mut sensor);
+    sensor.check_memory(&hub).await;
+    let full = memory.report();
+    assert!(full.parts["history"] > 0);
+    sensor.cfg.memory_budget = Some(full.estimated_bytes - 1);
+    sensor.check_memory(&hub).await;
+    assert_eq!(memory.report().parts["history"], 0);
+    assert_eq!(sensor.dns_cache.len(), 200, "dropping the history was enough");
+    sensor.publish_debug();
+    assert!(history.is_empty());
+
+    // off by default: no handle and nothing accounted
+    let sensor = NetNotify::new(None);
+    assert!(sensor.history().is_none());
+    assert!(!sensor.memory_report().parts.contains_key("history"));
+}
+
+#[test]
+fn every_documented_field_resolves() {
+    let full = || ConnKey {
+        remote_sni: Some("api.example.com".to_string()),
+        ..conn("10.0.0.2:50000", "93.184.216.34:443", Some("me"), Some("example.com"))
+    };
+    let counts = [("tcp:ESTABLISHED".to_string(), 3)].into_iter().collect();
+    let samples = [
+        NetNotifyEvent::Opened { conn: full(), offline: false, labels: Labels::default() },
+        NetNotifyEvent::Closed { conn: full(), offline: true, labels: Labels::default() },
+        NetNotifyEvent::WatermarkExceeded { watermark: "est".to_string(), count: 3, threshold: 2, counts: Default::default() },
+        NetNotifyEvent::WatermarkCleared { watermark: "est".to_string(), count: 1, threshold: 2, counts },
+        NetNotifyEvent::LimitChanged { name: "somaxconn".to_string(), old: "128".to_string(), new: "4096".to_string() },
+        NetNotifyEvent::CounterSpike {
+            table: "TcpExt".to_string(),
+            field: "ListenDrops".to_string(),
+            previous: 1,
+            current: 9,
+            delta: 8,
+            rate: 8.0,
+            max_per_sec: 1.0,
+        },
+        NetNotifyEvent::OverBudget { estimated_bytes: 9, after_shedding: 5, budget: 4, shed: Vec::new() },
+        NetNotifyEvent::Reconnected {
+            old_conn: full(),
+            new_conn: conn("10.0.0.2:50001", "93.184.216.34:443", None, None),
+            gap: Duration::from_millis(1500),
+            session_id: "s1".to_string(),
+            gap_unreliable: false,
+            labels: Labels::default(),
+        },
+        NetNotifyEvent::BacklogPressure {
+            listener: "0.0.0.0:80".to_string(),
+            depth: 9,
+            backlog: 10,
+            drops_delta: 2,
+            pid: Some(42),
+            comm: Some("nginx".to_string()),
+        },
+        NetNotifyEvent::BacklogCleared { listener: "0.0.0.0:80".to_string(), depth: 1, backlog: 10, pid: Some(42), comm: Some("nginx".to_string()) },
+        NetNotifyEvent::Summary {
+            window: Duration::from_secs(60),
+            ticks: 60,
+            connections: 12,
+            new_connections: 7,
+            dimensions: Dimension::ALL
+                .into_iter()
+                .map(|dimension| DimensionSummary {
+                    dimension,
+                    top: vec![TopEntry { value: "x".to_string(), count: 5, error: 0 }],
+                    exact: true,
+                    counted: 7,
+                    distinct: 3,
+                    distinct_exact: true,
+                })
+                .collect(),
+        },
+        NetNotifyEvent::ParseAnomaly { anomaly: ParseAnomaly::new("/proc/net/tcp", "   1: garbage", ParseFailure::new(2, "bad local address")) },
+        NetNotifyEvent::NovelConnection {
+            conn: full(),
+            tuple: Aggregation::default().tuple(&full()).unwrap(),
+            nearest: vec![Aggregation::default().tuple(&conn("10.0.0.2:50000", "93.184.217.1:443", None, None)).unwrap()],
+            labels: Labels::default(),
+        },
+    ];
+
+    let mut kinds = HashSet::new();
+    for ev in &samples {
+        assert!(kinds.insert(ev.kind()), "{} twice", ev.kind());
+        assert!(fields::same_kind(ev.kind(), serde_json::to_value(ev).unwrap().as_object().unwrap().keys().next().unwrap()));
+        for name in ev.field_names() {
+            assert!(fields::get(ev, name).is_some(), "{}: {name}", ev.kind());
+        }
+        assert_eq!(fields::get(ev, "nosuch"), None);
+        assert_eq!(NetNotifyEvent::TOPICS[ev.topic_index()].mask, ev.mask().bits() & KIND_BITS, "{}", ev.topic());
+        assert_eq!(EventFields::topic(ev), Some(ev.topic()));
+    }
+    let mut topics: Vec<&str> = samples.iter().map(NetNotifyEvent::topic).collect();
+    topics.sort();
+    let mut table: Vec<&str> = NetNotifyEvent::TOPICS.iter().map(|t| t.name).collect();
+    table.sort();
+    assert_eq!(topics, table, "one sample per topic");
+
+    let opened = &samples[0];
+    assert_eq!(fields::get(opened, "remote.ip"), Some(FieldValue::str("93.184.216.34")));
+    assert_eq!(fields::get(opened, "remote.port"), Some(FieldValue::int(443)));
+    assert_eq!(opened.field("state"), Some(FieldValue::str("01")));
+    assert_eq!(opened.field("state_dec"), Some(FieldValue::str("ESTABLISHED")));
+    assert_eq!(opened.field("offline"), Some(FieldValue::Int(0)));
+    let reconnected = &samples[7];
+    assert_eq!(reconnected.field("local_dec"), Some(FieldValue::str("10.0.0.2:50001")));
+    assert_eq!(reconnected.field("old_conn.local_dec"), Some(FieldValue::str("10.0.0.2:50000")));
+    assert_eq!(reconnected.field("new_conn.local.port"), Some(FieldValue::int(50001)));
+    assert_eq!(reconnected.field("gap_ms"), Some(FieldValue::int(1500)));
+    let summary = &samples[10];
+    assert_eq!(summary.field("local_port.top_count"), Some(FieldValue::int(5)));
+    assert_eq!(summary.field("remote_ip.nosuch"), None);
+    let novel = &samples[12];
+    assert_eq!(novel.field("tuple"), Some(FieldValue::str("tcp :32768-65535 93.184.216.0/24")));
+    assert_eq!(novel.field("nearest"), Some(FieldValue::str("tcp :32768-65535 93.184.217.0/24")));
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn preflight_checks_tables_counters_and_limits() {
+    async fn severities(s: &NetNotify) -> Vec<(Severity, String)> {
+        s.preflight().await.into_iter().map(|f| (f.severity, f.message)).collect()
+    }
+
+    let empty = TempDir::new().unwrap();
+    let found = severities(&NetNotify::new(Some(NetNotifyConfig::default().proc_net(empty.path())))).await;
+    assert_eq!(found, [(Severity::Critical, format!("no connection tables in {}", empty.path().display()))]);
+
+    // tcp is fine, udp unreadable, no IPv6
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    write_tcp_table(dir, &[KEEP]);
+    std::fs::create_dir_all(dir.join("udp")).unwrap();
+    std::fs::copy(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/snmp"), dir.join("snmp")).unwrap();
+    let cfg = NetNotifyConfig::default()
+        .proc_net(dir)
+        .proc_sys(dir)
+        .counters(&[CounterRule::new("Tcp", "RetransSegs", 1.0), CounterRule::new("Tcp", "NoSuch", 1.0)]);
+    let mut sensor = NetNotify::new(Some(cfg));
+    sensor.watch_limit("net.core.somaxconn");
+    let found = severities(&sensor).await;
+
+    let path = |f: &str| dir.join(f).display().to_string();
+    assert_eq!(found.len(), 5, "{found:?}");
+    assert_eq!(found[0], (Severity::Info, format!("{} is missing (no IPv6)", path("tcp6"))));
+    assert_eq!(found[1].0, Severity::Critical);
+    assert!(found[1].1.starts_with(&format!("cannot read {}", path("udp"))));
+    assert_eq!(found[2], (Severity::Info, format!("{} is missing (no IPv6)", path("udp6"))));
+    assert_eq!(found[3], (Severity::Warning, format!("counter Tcp.NoSuch not found in {}", dir.display())));
+    assert!(found[4].1.starts_with(&format!("cannot read limit {}", path("net/core/somaxconn"))));
+}
+
+#[test]
+fn connection_masks_carry_the_state_class() {
+    let with_state = |opened: bool, state: Option<&str>| {
+        let mut conn = ConnKey::test("tcp", "10.0.0.2:50000", "93.184.216.34:443");
+        conn.state = state.map(Into::into);
+        if opened {
+            NetNotifyEvent::Opened { conn, offline: false, labels: Labels::default() }
+        } else {
+            NetNotifyEvent::Closed { conn, offline: false, labels: Labels::default() }
+        }
+    };
+    let cases = [
+        (with_state(true, Some("0A")), NetNotifyMask::OPENED | NetNotifyMask::OPENED_LISTEN),
+        (with_state(true, Some("01")), NetNotifyMask::OPENED | NetNotifyMask::OPENED_ESTABLISHED),
+        (with_state(true, Some("02")), NetNotifyMask::OPENED | NetNotifyMask::OPENED_HANDSHAKE),
+        (with_state(true, Some("03")), NetNotifyMask::OPENED | NetNotifyMask::OPENED_HANDSHAKE),
+        (with_state(true, Some("06")), NetNotifyMask::OPENED | NetNotifyMask::OPENED_CLOSING),
+        (with_state(true, Some("ZZ")), NetNotifyMask::OPENED),
+        (with_state(false, Some("0A")), NetNotifyMask::CLOSED | NetNotifyMask::CLOSED_LISTEN),
+        (with_state(false, Some("01")), NetNotifyMask::CLOSED | NetNotifyMask::CLOSED_ESTABLISHED),
+        (with_state(false, Some("02")), NetNotifyMask::CLOSED | NetNotifyMask::CLOSED_HANDSHAKE),
+        (with_state(false, Some("08")), NetNotifyMask::CLOSED | NetNotifyMask::CLOSED_CLOSING),
+        (with_state(false, Some("0B")), NetNotifyMask::CLOSED | NetNotifyMask::CLOSED_CLOSING),
+        (NetNotifyEvent::test_opened("udp", "10.0.0.2:5353", "224.0.0.251:5353"), NetNotifyMask::OPENED),
+        (NetNotifyEvent::test_closed("udp", "10.0.0.2:5353", "224.0.0.251:5353"), NetNotifyMask::CLOSED),
+    ];
+
+    for (ev, want) in &cases {
+        let bits = ev.mask().bits();
+        assert_eq!(bits, want.bits(), "{:?}", ev);
+        assert_eq!((bits & KIND_BITS).count_ones(), 1);
+        assert!((bits & SUBKIND_BITS).count_ones() <= 1);
+        assert_eq!(bits & RESERVED_BITS, 0);
+    }
+    assert_eq!(NetNotifyMask::all().bits() & RESERVED_BITS, 0);
+    assert_eq!(NetNotifyMask::CLOSED_CLOSING.bits() & !SUBKIND_BITS, 0);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn previews_patterns_against_the_last_table_without_applying_them() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    std::fs::write(dir.join("tcp"), include_str!("../fixtures/tcp")).unwrap();
+    let sensor = NetNotify::new(Some(NetNotifyConfig::default().pulse(Duration::from_millis(10)).proc_net(dir)));
+    let (debug, previews) = (sensor.debug_handle(), sensor.preview_handle());
+    let (handle, task) = spawn_sensor(sensor, Arc::new(CallbackHub::<NetNotifyEvent>::new()));
+    let preview = async |rule: &str| previews.preview_rule(rule.parse::<NetNotifyRule>().unwrap()).await.unwrap();
+
+    // everything is selected so far: the ssh listener, the web connection and the ssh session
+    let web = preview("ignore 93.184.216.34").await;
+    assert_eq!((web.would_match, web.would_stop_matching), (1, 1));
+    assert_eq!(web.samples, ["tcp 10.0.0.5:40000 93.184.216.34:443"]);
+
+    // a first IP watch narrows the selection to what it names
+    let lan = preview("add 10.0.0.100").await;
+    assert_eq!((lan.would_match, lan.would_stop_matching), (1, 2));
+    assert_eq!(lan.samples, ["tcp 10.0.0.5:22 10.0.0.100:55000"]);
+
+    let https = preview("ignore tcp * *:443").await;
+    assert_eq!((https.would_match, https.would_stop_matching), (1, 1));
+
+    // nothing was applied
+    assert_eq!(preview("ignore 93.184.216.34").await, web);
+    handle.shutdown();
+    let _ = task.await;
+    assert!(omnitrace_core::debug::Debuggable::debug_snapshot(&debug)["patterns"]["watch_ip"].as_array().unwrap().is_empty());
+    assert!("add [".parse::<NetNotifyRule>().is_err());
+}
+
+#[test]
+fn events_format_as_pinned_lines() {
+    let enriched = ConnKey {
+        local: "0200000A:C350".to_string(),
+        remote: "22D8B85D:01BB".into(),
+        remote_sni: Some("api.example.com".to_string()),
+        ..conn("10.0.0.2:50000", "93.184.216.34:443", None, Some("example.com"))
+    };
+    // /proc/net/tcp6 rows the sensor could not decode
+    let raw = ConnKey {
+        proto: "tcp6".into(),
+        local: "00000000000000000000000001000000:1F90".to_string(),
+        remote: "0000000000000000FFFF00000100007F:D431".into(),
+        state: Some("0A".into()),
+        local_addr: None,
+        remote_addr: None,
+        local_dec: None,
+        remote_dec: None,
+        state_dec: None,
+        local_host: None,
+        remote_host: None,
+        remote_sni: None,
+    };
+    let samples = [
+        NetNotifyEvent::Opened { conn: enriched.clone(), offline: false, labels: Labels::default() },
+        NetNotifyEvent::Closed { conn: raw, offline: true, labels: Labels::default() },
+        NetNotifyEvent::Reconnected {
+            old_conn: enriched,
+            new_conn: conn("10.0.0.2:50001", "93.184.216.34:443", None, None),
+            gap: Duration::from_millis(1500),
+            session_id: "s1".to_string(),
+            gap_unreliable: false,
+            labels: Labels::default(),
+        },
+    ];
+    let lines: Vec<String> = samples.iter().flat_map(|ev| [format_short(ev), format_long(ev)]).collect();
+    assert_eq!(
+        lines,
+        [
+            "opened proto=tcp local_dec=10.0.0.2:50000 remote_dec=93.184.216.34:443 state_dec=ESTABLISHED remote_host=example.com \
+             remote_sni=api.example.com",
+            "opened proto=tcp local=0200000A:C350 remote=22D8B85D:01BB state=01 local_dec=10.0.0.2:50000 \
+             remote_dec=93.184.216.34:443 state_dec=ESTABLISHED remote_host=example.com remote_sni=api.example.com offline=0",
+            "closed proto=tcp6 local=00000000000000000000000001000000:1F90 remote=0000000000000000FFFF00000100007F:D431 state=0A",
+            "closed proto=tcp6 local=00000000000000000000000001000000:1F90 remote=0000000000000000FFFF00000100007F:D431 state=0A \
+             offline=1",
+            "reconnected proto=tcp remote_dec=93.184.216.34:443 old_conn.local_dec=10.0.0.2:50000 \
+             new_conn.local_dec=10.0.0.2:50001 gap_ms=1500",
+            "reconnected proto=tcp remote=- remote_dec=93.184.216.34:443 old_conn.local=0200000A:C350 \
+             old_conn.local_dec=10.0.0.2:50000 new_conn.local=- new_conn.local_dec=10.0.0.2:50001 state_dec=ESTABLISHED \
+             session_id=s1 gap_ms=1500 gap_unreliable=0",
+        ]
+    );
+}
+
+// -------------------------
+// learned connections
+// -------------------------
+
+struct AllRecorder(Arc<std::sync::Mutex<Vec<NetNotifyEvent>>>);
+
+#[async_trait]
+impl Callback<NetNotifyEvent> for AllRecorder {
+    fn mask(&self) -> u64 {
+        u64::MAX
+    }
+
+    async fn call(&self, ev: &NetNotifyEvent) -> Option<CallbackResult> {
+        self.0.lock().unwrap().push(ev.clone());
+        None
+    }
+}
+
+/// Run a learning sensor over `tables`, one per tick with `step` of clock time between them,
+/// returning its events as `(kind, local port, known label)` and its handle. `prepare` gets
+/// the handle before the sensor starts.
+async fn learn_script<F: FnOnce(&learn::LearnHandle)>(
+    dir: &Path, cfg: LearnConfig, tables: &[&[(&str, &str, &str)]], step: Duration, prepare: F,
+) -> (Vec<(String, u16, bool)>, learn::LearnHandle) {
+    write_tcp_table(dir, tables[0]);
+    let clock = ManualClock::new();
+    let sensor = NetNotify::new(Some(NetNotifyConfig::default().pulse(Duration::from_millis(10)).proc_net(dir).clock(clock.shared()).learn(cfg)));
+    let learned = sensor.learned().unwrap();
+    prepare(&learned);
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(AllRecorder(seen.clone()));
+    let (handle, task) = spawn_sensor(sensor, Arc::new(hub));
+
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    for rows in &tables[1..] {
+        let rows: Vec<(String, String, String)> = rows.iter().map(|(l, r, s)| (l.to_string(), r.to_string(), s.to_string())).collect();
+        swap_tcp_table(dir, &rows);
+        clock.advance(step);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    handle.shutdown();
+    let _ = task.await;
+
+    let events = seen
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|ev| {
+            let port = match fields::get(ev, "local.port") {
+                Some(fields::FieldValue::Int(p)) => p as u16,
+                _ => 0,
+            };
+            (ev.kind().to_string(), port, ev.labels().get("known") == Some(&serde_json::Value::Bool(true)))
+        })
+        .collect();
+    (events, learned)
+}
+
+// 10.0.0.5:40000 -> 93.184.216.34:443 is KEEP; the same /24 from another ephemeral port
+const SAME_NET: (&str, &str, &str) = ("0500000A:9C43", "63D8B85D:01BB", "01");
+// 10.0.0.5:40004 -> 93.184.217.1:443, a /24 not learned
+const OTHER_NET: (&str, &str, &str) = ("0500000A:9C44", "01D9B85D:01BB", "01");
+// 10.0.0.5:22 <- 10.0.0.9:51000, and from 10.0.0.77
+const SSH: (&str, &str, &str) = ("0500000A:0016", "0900000A:C738", "01");
+const SSH_OTHER: (&str, &str, &str) = ("0500000A:0016", "4D00000A:CB20", "01");
+
+#[cfg(target_os = "linux")]
+#[tokio::test(start_paused = true)]
+async fn learning_trains_persists_and_flags_novel_tuples() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let path = dir.join("learned.json");
+    let _ = std::fs::remove_file(&path);
+    let cfg = LearnConfig::new(Duration::from_secs(60)).path(&path);
+    let (events, learned) = learn_script(
+        dir,
+        cfg.clone(),
+        &[
+            &[KEEP, SSH],
+            // training: reported as usual
+            &[KEEP, SSH, SAME_NET],
+            // trained: the same /24 and another ssh client are known, the other /24 is not
+            &[KEEP, SSH_OTHER, OTHER_NET],
+            &[KEEP, SSH_OTHER, OTHER_NET],
+        ],
+        Duration::from_secs(40),
+        |_| {},
+    )
+    .await;
+    let ev = |kind: &str, port: u16| (kind.to_string(), port, false);
+    assert_eq!(events, [ev("opened", 40003), ev("opened", 40004), ev("novel_connection", 40004)]);
+    assert_eq!((learned.len(), learned.mode()), (2, LearnMode::Enforcing));
+
+    // saved at the end of training, with the local port and remote aggregated
+    let (saved, trained) = learn::load(&path).unwrap();
+    assert!(trained);
+    let tuples: Vec<String> = saved.tuples().map(ToString::to_string).collect();
+    assert_eq!(tuples, ["tcp :22 10.0.0.0/24", "tcp :32768-65535 93.184.216.0/24"]);
+
+    // a restart enforces the saved set at once; known connections can be tagged instead
+    let (events, learned) =
+        learn_script(dir, cfg.clone().tag_known(), &[&[KEEP], &[KEEP, SAME_NET, OTHER_NET]], Duration::from_secs(1), |_| {}).await;
+    let known = ("opened".to_string(), 40003, true);
+    assert_eq!(events, [known, ev("opened", 40004), ev("novel_connection", 40004)]);
+    assert_eq!(learned.len(), 2);
+
+    // training again adds to the set; stopped halfway, the next start trains on
+    let train = |l: &learn::LearnHandle| l.train(Duration::from_secs(60));
+    let (events, learned) = learn_script(dir, cfg.clone(), &[&[KEEP], &[KEEP, OTHER_NET]], Duration::from_secs(1), train).await;
+    assert_eq!(events, [ev("opened", 40004)]);
+    assert_eq!(learned.len(), 3);
+    assert_eq!(learn::load(&path).unwrap().0.len(), 3);
+    let (events, learned) = learn_script(dir, cfg.clone(), &[&[KEEP], &[KEEP, SAME_NET]], Duration::from_secs(1), |_| {}).await;
+    assert_eq!(events, [ev("opened", 40003)]);
+    assert!(matches!(learned.mode(), LearnMode::Training { .. }));
+
+    // merged from elsewhere, or forgotten
+    let mut other = learn::LearnedBaseline::new(Aggregation::default());
+    other.observe(&baseline::conn_key("tcp", SSH.0, SSH.1, Some("01".into())));
+    other.observe(&baseline::conn_key("udp", "0500000A:0035", "0900000A:C738", None));
+    assert_eq!(learned.merge(&other).unwrap(), 1);
+    assert!(learned.merge(&learn::LearnedBaseline::new(Aggregation { v4_prefix: 16, ..Aggregation::default() })).is_err());
+    learned.retrain(Duration::from_secs(60));
+    assert!(learned.is_empty());
+}
@@ -19,20 +19,24 @@ pub(crate) fn dec_ipv6(hex_be: &str) -> Option<std::net::Ipv6Addr> {
     Some(std::net::Ipv6Addr::from(b))
 }
 
-pub(crate) fn decode_addr(raw: &str, v6: bool) -> Option<String> {
+pub(crate) fn decode_addr(raw: &str, v6: bool) -> Option<std::net::SocketAddr> {
     let (ip_hex, port_hex) = raw.split_once(':')?;
     let port = hex_port(port_hex)?;
-    if v6 {
-        let ip = dec_ipv6(ip_hex)?;
-        Some(format!("{ip}:{port}"))
-    } else {
-        let ip = dec_ipv4(ip_hex)?;
-        Some(format!("{ip}:{port}"))
-    }
+    let ip: std::net::IpAddr = if v6 { dec_ipv6(ip_hex)?.into() } else { dec_ipv4(ip_hex)?.into() };
+    Some(std::net::SocketAddr::new(ip, port))
+}
+
+/// Inverse of [`decode_addr`]: the raw /proc/net table form of `addr`.
+pub(crate) fn encode_addr(addr: std::net::SocketAddr) -> String {
+    let ip = match addr.ip() {
+        std::net::IpAddr::V4(ip) => format!("{:08X}", u32::from(ip).swap_bytes()),
+        std::net::IpAddr::V6(ip) => ip.octets().iter().map(|b| format!("{b:02X}")).collect(),
+    };
+    format!("{ip}:{:04X}", addr.port())
 }
 
-pub(crate) fn decode_tcp_state(s: &Option<String>) -> Option<String> {
-    let code = s.as_deref()?;
+pub(crate) fn decode_tcp_state(code: Option<&str>) -> Option<&'static str> {
+    let code = code?;
     let name = match code {
         "01" => "ESTABLISHED",
         "02" => "SYN_SENT",
@@ -47,7 +51,7 @@ pub(crate) fn decode_tcp_state(s: &Option<String>) -> Option<String> {
         "0B" => "CLOSING",
         _ => "UNKNOWN",
     };
-    Some(name.to_string())
+    Some(name)
 }
 
 pub fn reverse_dns(ip: std::net::IpAddr) -> Option<String> {
@@ -101,6 +105,7 @@ pub fn reverse_dns(ip: std::net::IpAddr) -> Option<String> {
     }
 }
 
+#[allow(dead_code)] // DSL expansion, only exercised by tests for now
 pub(crate) fn expand_pat(pat: &str) -> String {
     let p = pat.trim();
     if p.is_empty() {
@@ -140,7 +145,7 @@ pub(crate) fn expand_pat(pat: &str) -> String {
     format!("*host:{p}*")
 }
 
-pub(crate) fn is_ipish(p: &str) -> bool {
+pub fn is_ipish(p: &str) -> bool {
     let p = p.trim();
     if p.split_whitespace().count() != 1 {
         return false;
@@ -155,7 +160,7 @@ pub(crate) fn is_ipish(p: &str) -> bool {
         && (p.contains('.') || p.contains(':'))
 }
 
-pub(crate) fn is_hostish(p: &str) -> bool {
+pub fn is_hostish(p: &str) -> bool {
     let p = p.trim();
     // multi-token patterns like "udp * *" are NOT host-ish
     if p.split_whitespace().count() != 1 {
@@ -168,9 +173,9 @@ pub(crate) fn is_hostish(p: &str) -> bool {
         || (p.contains('*') && p.contains('.'))
 }
 
-pub(crate) fn split_ip_port(s: &str) -> Option<(std::net::IpAddr, u16)> {
-    let (ip, port) = s.rsplit_once(':')?;
-    let ip: std::net::IpAddr = ip.parse().ok()?;
-    let port: u16 = port.parse().ok()?;
-    Some((ip, port))
+/// Parse an "ip:port" string, e.g. `local_dec` of events recorded before `local_addr` existed.
+/// IPv6 has to be bracketed ("[::1]:443"): "::1:443" could be either address and port.
+pub fn split_ip_port(s: &str) -> Option<(std::net::IpAddr, u16)> {
+    let sa: std::net::SocketAddr = s.parse().ok()?;
+    Some((sa.ip(), sa.port()))
 }
@@ -1,7 +1,7 @@
 #[cfg(test)]
 mod tests {
-    use crate::netutil::{dec_ipv4, dec_ipv6, decode_addr, decode_tcp_state, expand_pat, hex_port, is_hostish, is_ipish, reverse_dns};
-    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use crate::netutil::{dec_ipv4, dec_ipv6, decode_addr, decode_tcp_state, expand_pat, hex_port, is_hostish, is_ipish, reverse_dns, split_ip_port};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 
     // -------------------------
     // hex_port
@@ -81,7 +81,7 @@ mod tests {
     #[test]
     fn decode_addr_ipv4() {
         // /proc style: 0100007F => 127.0.0.1
-        assert_eq!(decode_addr("0100007F:01BB", false).as_deref(), Some("127.0.0.1:443"));
+        assert_eq!(decode_addr("0100007F:01BB", false), Some("127.0.0.1:443".parse().unwrap()));
 
         // NOTE: if you want to lock a second example, compute it from your actual logs.
         // Keeping this one minimal avoids chasing swapped expectations.
@@ -89,9 +89,11 @@ mod tests {
 
     #[test]
     fn decode_addr_ipv6() {
-        // ::1:443
+        // [::1]:443
         let ip_hex = "00000000000000000000000000000001";
-        assert_eq!(decode_addr(&format!("{ip_hex}:01BB"), true).as_deref(), Some("::1:443"));
+        let addr = decode_addr(&format!("{ip_hex}:01BB"), true).unwrap();
+        assert_eq!(addr, SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 443));
+        assert_eq!(addr.to_string(), "[::1]:443");
     }
 
     #[test]
@@ -112,24 +114,24 @@ mod tests {
 
     #[test]
     fn decode_tcp_state_maps_known_codes() {
-        assert_eq!(decode_tcp_state(&Some("01".into())).as_deref(), Some("ESTABLISHED"));
-        assert_eq!(decode_tcp_state(&Some("02".into())).as_deref(), Some("SYN_SENT"));
-        assert_eq!(decode_tcp_state(&Some("03".into())).as_deref(), Some("SYN_RECV"));
-        assert_eq!(decode_tcp_state(&Some("04".into())).as_deref(), Some("FIN_WAIT1"));
-        assert_eq!(decode_tcp_state(&Some("05".into())).as_deref(), Some("FIN_WAIT2"));
-        assert_eq!(decode_tcp_state(&Some("06".into())).as_deref(), Some("TIME_WAIT"));
-        assert_eq!(decode_tcp_state(&Some("07".into())).as_deref(), Some("CLOSE"));
-        assert_eq!(decode_tcp_state(&Some("08".into())).as_deref(), Some("CLOSE_WAIT"));
-        assert_eq!(decode_tcp_state(&Some("09".into())).as_deref(), Some("LAST_ACK"));
-        assert_eq!(decode_tcp_state(&Some("0A".into())).as_deref(), Some("LISTEN"));
-        assert_eq!(decode_tcp_state(&Some("0B".into())).as_deref(), Some("CLOSING"));
+        assert_eq!(decode_tcp_state(Some("01")), Some("ESTABLISHED"));
+        assert_eq!(decode_tcp_state(Some("02")), Some("SYN_SENT"));
+        assert_eq!(decode_tcp_state(Some("03")), Some("SYN_RECV"));
+        assert_eq!(decode_tcp_state(Some("04")), Some("FIN_WAIT1"));
+        assert_eq!(decode_tcp_state(Some("05")), Some("FIN_WAIT2"));
+        assert_eq!(decode_tcp_state(Some("06")), Some("TIME_WAIT"));
+        assert_eq!(decode_tcp_state(Some("07")), Some("CLOSE"));
+        assert_eq!(decode_tcp_state(Some("08")), Some("CLOSE_WAIT"));
+        assert_eq!(decode_tcp_state(Some("09")), Some("LAST_ACK"));
+        assert_eq!(decode_tcp_state(Some("0A")), Some("LISTEN"));
+        assert_eq!(decode_tcp_state(Some("0B")), Some("CLOSING"));
     }
 
     #[test]
     fn decode_tcp_state_unknown_and_none() {
-        assert_eq!(decode_tcp_state(&None), None);
-        assert_eq!(decode_tcp_state(&Some("FF".into())).as_deref(), Some("UNKNOWN"));
-        assert_eq!(decode_tcp_state(&Some("".into())).as_deref(), Some("UNKNOWN"));
+        assert_eq!(decode_tcp_state(None), None);
+        assert_eq!(decode_tcp_state(Some("FF")), Some("UNKNOWN"));
+        assert_eq!(decode_tcp_state(Some("")), Some("UNKNOWN"));
     }
 
     // -------------------------
@@ -261,11 +263,33 @@ mod tests {
         assert!(!is_hostish("1.2.3.4:443"));
     }
 
+    // -------------------------
+    // split_ip_port
+    // -------------------------
+
+    #[test]
+    fn split_ip_port_handles_both_sides() {
+        assert_eq!(split_ip_port("192.168.2.136:57843"), Some((IpAddr::V4(Ipv4Addr::new(192, 168, 2, 136)), 57843)));
+        assert_eq!(split_ip_port("10.0.0.1:443"), Some((IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 443)));
+        assert_eq!(split_ip_port("[::1]:443"), Some((IpAddr::V6(Ipv6Addr::LOCALHOST), 443)));
+        assert_eq!(split_ip_port("[2001:db8::1]:8443"), Some(("2001:db8::1".parse().unwrap(), 8443)));
+    }
+
+    #[test]
+    fn split_ip_port_rejects_garbage() {
+        assert_eq!(split_ip_port(""), None);
+        assert_eq!(split_ip_port("10.0.0.1"), None);
+        assert_eq!(split_ip_port("host:443"), None);
+        assert_eq!(split_ip_port("::1:443"), None, "ambiguous without brackets");
+        assert_eq!(split_ip_port("10.0.0.1:99999"), None);
+    }
+
     // -------------------------
     // sanity checks
     // -------------------------
 
     #[test]
+    #[allow(clippy::assertions_on_constants)] // a runtime check on purpose, failing the test rather than the build
     fn sanity_target_endianness() {
         assert!(cfg!(target_endian = "little"), "you are on big-endian, welcome to 1993");
     }
@@ -274,7 +298,7 @@ mod tests {
     fn sanity_dec_ipv4_matches_decode_addr() {
         // Whatever dec_ipv4 does, decode_addr must use the same logic.
         let ip = dec_ipv4("0100007F").unwrap().to_string();
-        let got = decode_addr("0100007F:01BB", false).unwrap();
+        let got = decode_addr("0100007F:01BB", false).unwrap().to_string();
         assert!(got.starts_with(&format!("{ip}:")), "decode_addr mismatch: {got} vs {ip}");
     }
 }
@@ -0,0 +1,6 @@
+//! `use netpacket::prelude::*;` brings in the NetNotify sensor, its config and events,
+//! and the core prelude (callbacks, hub, `spawn_sensor`).
+
+pub use crate::events::{ConnKey, NetNotifyEvent, NetNotifyMask};
+pub use crate::{NetNotify, NetNotifyConfig};
+pub use omnitrace_core::prelude::*;
@@ -0,0 +1,32 @@
+//! Rules [`crate::NetNotify::preview_rule`] answers for, see [`omnitrace_core::preview`].
+
+use crate::LOCAL_HOST_PREFIX;
+use omnitrace_core::preview::{RuleError, split_rule};
+use std::str::FromStr;
+
+/// A pattern as [`crate::NetNotify::add`] and [`crate::NetNotify::ignore`] take it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetNotifyRule {
+    /// `add <pattern>`
+    Add(String),
+    /// `ignore <pattern>`
+    Ignore(String),
+}
+
+impl FromStr for NetNotifyRule {
+    type Err = RuleError;
+
+    fn from_str(s: &str) -> Result<Self, RuleError> {
+        let (verb, pat) = split_rule(s)?;
+        let rule = match verb {
+            "add" => Self::Add(pat.to_string()),
+            "ignore" => Self::Ignore(pat.to_string()),
+            _ => return Err(RuleError::UnknownVerb { verb: verb.to_string(), expected: "add, ignore" }),
+        };
+        // add() and ignore() skip patterns that do not compile
+        match glob::Pattern::new(pat.strip_prefix(LOCAL_HOST_PREFIX).unwrap_or(pat)) {
+            Ok(_) => Ok(rule),
+            Err(e) => Err(RuleError::InvalidArgument { verb: verb.to_string(), reason: e.to_string() }),
+        }
+    }
+}
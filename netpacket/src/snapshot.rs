@@ -0,0 +1,175 @@
+use crate::{baseline, error::NetNotifyError, events::ConnKey};
+use omnitrace_core::{
+    anomaly::{ParseAnomaly, ParseFailure},
+    intern::Interned,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{self, Read},
+    net::SocketAddr,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+/// Tables read every tick, and whether they carry a tcp state column.
+pub(crate) const TABLES: [(&str, bool); 4] = [("tcp", true), ("tcp6", true), ("udp", false), ("udp6", false)];
+
+/// Reads the connection tables as one snapshot, as far as procfs allows: all files are
+/// opened first, then read back to back into buffers kept across ticks, and only parsed
+/// once everything is in memory. That keeps the skew between the first and the last
+/// table down to the raw read time.
+#[derive(Default)]
+pub struct TableReader {
+    bufs: [Vec<u8>; 4],
+    uids: Option<HashMap<RawKey, u32>>,
+    malformed: Vec<ParseAnomaly>,
+}
+
+/// Protocol and raw local and remote columns.
+type RawKey = (Interned, String, Interned);
+
+impl TableReader {
+    /// Also keep the uid owning each socket of the last read, see [`TableReader::uid`].
+    #[cfg(feature = "runtime")]
+    pub(crate) fn with_uids() -> Self {
+        Self { uids: Some(HashMap::new()), ..Self::default() }
+    }
+
+    /// The lines skipped as malformed in the last read, over all tables.
+    pub fn malformed(&self) -> &[ParseAnomaly] {
+        &self.malformed
+    }
+
+    /// The uid of `c` as of the last read, if uids are kept.
+    #[cfg(feature = "runtime")]
+    pub(crate) fn uid(&self, c: &ConnKey) -> Option<u32> {
+        self.uids.as_ref()?.get(&(c.proto.clone(), c.local.clone(), c.remote.clone())).copied()
+    }
+
+    /// Missing or unreadable tables count as empty; their errors come back by table name,
+    /// along with those of malformed lines, which are skipped.
+    pub fn read(&mut self, root: &Path) -> (HashSet<ConnKey>, Vec<(&'static str, NetNotifyError)>) {
+        let mut errors = Vec::new();
+        let files: Vec<io::Result<File>> = TABLES.iter().map(|(name, _)| File::open(root.join(name))).collect();
+        for (((name, _), buf), file) in TABLES.iter().zip(self.bufs.iter_mut()).zip(files) {
+            buf.clear();
+            let path = root.join(name);
+            let read = file.and_then(|mut f| f.read_to_end(buf));
+            match read {
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => errors.push((*name, NetNotifyError::TableMissing { path })),
+                Err(source) => {
+                    buf.clear();
+                    errors.push((*name, NetNotifyError::TableRead { path, source }));
+                }
+            }
+        }
+
+        let mut out = HashSet::new();
+        if let Some(uids) = self.uids.as_mut() {
+            uids.clear();
+        }
+        self.malformed.clear();
+        for ((proto, is_tcp), buf) in TABLES.iter().zip(&self.bufs) {
+            let txt = String::from_utf8_lossy(buf);
+            let bad = parse_table(proto, *is_tcp, &txt, &mut out, self.uids.as_mut());
+            let path = root.join(proto);
+            if let Some((line, _, _)) = bad.first() {
+                errors.push((*proto, NetNotifyError::TableParse { path: path.clone(), line: *line, malformed: bad.len() }));
+            }
+            self.malformed.extend(bad.into_iter().map(|(_, raw, failure)| ParseAnomaly::new(&path, raw, failure)));
+        }
+        (out, errors)
+    }
+}
+
+/// Parse one table into `out`, and the `uid` column into `uids` if given. Returns the lines
+/// skipped as malformed: their 1-based number, the line, and why.
+pub(crate) fn parse_table<'a>(
+    proto: &str, is_tcp: bool, txt: &'a str, out: &mut HashSet<ConnKey>, mut uids: Option<&mut HashMap<RawKey, u32>>,
+) -> Vec<(usize, &'a str, ParseFailure)> {
+    let mut bad = Vec::new();
+    for (i, line) in txt.lines().enumerate().skip(1) {
+        let cols: Vec<&str> = line.split_whitespace().collect();
+        if let Err(failure) = check_line(&cols, is_tcp) {
+            bad.push((i + 1, line, failure));
+            continue;
+        }
+
+        let state = if is_tcp { Some(Interned::new(cols[3])) } else { None };
+        let c = baseline::conn_key(proto, cols[1], cols[2], state);
+        if let Some(uids) = uids.as_deref_mut()
+            && let Some(uid) = cols.get(7).and_then(|u| u.parse().ok())
+        {
+            uids.insert((c.proto.clone(), c.local.clone(), c.remote.clone()), uid);
+        }
+        out.insert(c);
+    }
+    bad
+}
+
+/// Whether the columns of a table line hold the slot, the local and remote address as
+/// `<hex ip>:<hex port>`, and for tcp the state in two hex digits.
+fn check_line(cols: &[&str], is_tcp: bool) -> Result<(), ParseFailure> {
+    let hex = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_hexdigit());
+    let col = |n: usize, what: &str| cols.get(n - 1).copied().ok_or_else(|| ParseFailure::new(n, format!("missing {what}")));
+    if !col(1, "slot")?.ends_with(':') {
+        return Err(ParseFailure::new(1, "slot without ':'"));
+    }
+    for (n, what) in [(2, "local address"), (3, "remote address")] {
+        let addr = col(n, what)?;
+        if !addr.split_once(':').is_some_and(|(ip, port)| hex(ip) && hex(port)) {
+            return Err(ParseFailure::new(n, format!("{what} {addr:?} is not <hex ip>:<hex port>")));
+        }
+    }
+    if is_tcp {
+        let state = col(4, "state")?;
+        if state.len() != 2 || !hex(state) {
+            return Err(ParseFailure::new(4, format!("state {state:?} is not two hex digits")));
+        }
+    }
+    Ok(())
+}
+
+/// Protocol family and both endpoints, with IPv4-mapped IPv6 addresses folded to IPv4,
+/// so the same socket seen in `tcp` and `tcp6`, or in two states, has one identity.
+pub(crate) fn four_tuple(c: &ConnKey) -> Option<(bool, SocketAddr, SocketAddr)> {
+    let canonical = |a: SocketAddr| SocketAddr::new(a.ip().to_canonical(), a.port());
+    Some((c.proto.starts_with("tcp"), canonical(c.local_addr?), canonical(c.remote_addr?)))
+}
+
+/// Drop Opened/Closed pairs for the same 4-tuple within one tick: a connection does not
+/// really close and reopen between two table reads, it was seen twice because of read skew
+/// (or moved between tables or states). Returns the number of pairs dropped.
+pub(crate) fn reconcile(opened: &mut Vec<ConnKey>, closed: &mut Vec<ConnKey>) -> usize {
+    let closed_tuples: HashSet<_> = closed.iter().filter_map(four_tuple).collect();
+    let paired: HashSet<_> = opened.iter().filter_map(four_tuple).filter(|t| closed_tuples.contains(t)).collect();
+    if paired.is_empty() {
+        return 0;
+    }
+
+    let is_paired = |c: &ConnKey| four_tuple(c).is_some_and(|t| paired.contains(&t));
+    opened.retain(|c| !is_paired(c));
+    closed.retain(|c| !is_paired(c));
+    paired.len()
+}
+
+/// Counts Opened/Closed pairs suppressed as read-skew artifacts, see [`crate::NetNotify::skew_stats`].
+/// Cheap to clone.
+#[derive(Clone, Debug, Default)]
+pub struct SkewStats(Arc<AtomicU64>);
+
+impl SkewStats {
+    pub fn suppressed(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    #[cfg(feature = "runtime")]
+    pub(crate) fn add(&self, n: usize) {
+        self.0.fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
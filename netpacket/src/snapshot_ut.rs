@@ -0,0 +1,98 @@
+use crate::{
+    baseline,
+    error::NetNotifyError,
+    events::ConnKey,
+    netutil::encode_addr,
+    snapshot::{TableReader, reconcile},
+};
+
+fn raw(addr: &str) -> String {
+    encode_addr(addr.parse().unwrap())
+}
+
+fn tcp(local: &str, remote: &str, state: &str) -> ConnKey {
+    baseline::conn_key("tcp", &raw(local), &raw(remote), Some(state.into()))
+}
+
+/// The same socket as an IPv4-mapped entry of tcp6.
+fn tcp6_mapped(local: &str, remote: &str, state: &str) -> ConnKey {
+    let mapped = |a: &str| {
+        let a: std::net::SocketAddrV4 = a.parse().unwrap();
+        encode_addr(std::net::SocketAddr::new(a.ip().to_ipv6_mapped().into(), a.port()))
+    };
+    baseline::conn_key("tcp6", &mapped(local), &mapped(remote), Some(state.into()))
+}
+
+#[test]
+fn pairs_for_one_socket_cancel_out() {
+    let moved_from = tcp("10.0.0.5:40000", "93.184.216.34:443", "01");
+    let moved_to = tcp6_mapped("10.0.0.5:40000", "93.184.216.34:443", "01");
+    let real_open = tcp("10.0.0.5:40001", "8.8.8.8:53", "01");
+    let real_close = tcp("10.0.0.5:40002", "1.1.1.1:443", "01");
+
+    let mut opened = vec![moved_to, real_open.clone()];
+    let mut closed = vec![moved_from, real_close.clone()];
+    assert_eq!(reconcile(&mut opened, &mut closed), 1);
+    assert_eq!(opened, vec![real_open]);
+    assert_eq!(closed, vec![real_close]);
+}
+
+#[test]
+fn state_flip_is_not_a_new_connection() {
+    let mut opened = vec![tcp("10.0.0.5:40000", "93.184.216.34:443", "08")];
+    let mut closed = vec![tcp("10.0.0.5:40000", "93.184.216.34:443", "01")];
+    assert_eq!(reconcile(&mut opened, &mut closed), 1);
+    assert!(opened.is_empty() && closed.is_empty());
+}
+
+#[test]
+fn tcp_and_udp_on_the_same_ports_stay_apart() {
+    let udp = baseline::conn_key("udp", &raw("10.0.0.5:5353"), &raw("10.0.0.9:5353"), None);
+    let mut opened = vec![udp];
+    let mut closed = vec![tcp("10.0.0.5:5353", "10.0.0.9:5353", "01")];
+    assert_eq!(reconcile(&mut opened, &mut closed), 0);
+    assert_eq!((opened.len(), closed.len()), (1, 1));
+}
+
+#[test]
+fn reader_reuses_buffers_and_tolerates_missing_tables() {
+    let dir = std::env::temp_dir().join(format!("netpacket-ut-{}-snapshot", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let header = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n";
+    let row =
+        |local: &str, remote: &str| format!("   0: {} {} 01 00000000:00000000 00:00000000 00000000  1000        0 1000\n", raw(local), raw(remote));
+    std::fs::write(dir.join("tcp"), format!("{header}{}{}", row("10.0.0.5:40000", "1.1.1.1:443"), row("10.0.0.5:40001", "1.1.1.1:443"))).unwrap();
+    std::fs::write(dir.join("udp"), format!("{header}{}", row("10.0.0.5:5353", "0.0.0.0:0"))).unwrap();
+
+    let mut reader = TableReader::default();
+    let (conns, errors) = reader.read(&dir);
+    assert_eq!(conns.len(), 3);
+    assert_eq!(conns.iter().filter(|c| c.proto == "udp").count(), 1);
+    let missing: Vec<&str> = errors.iter().filter(|(_, e)| matches!(e, NetNotifyError::TableMissing { .. })).map(|(t, _)| *t).collect();
+    assert_eq!((missing, errors.len()), (vec!["tcp6", "udp6"], 2));
+
+    std::fs::write(dir.join("tcp"), header).unwrap();
+    assert_eq!(reader.read(&dir).0.len(), 1, "old buffer content must not leak into the next read");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn unreadable_and_malformed_tables_are_errors() {
+    let dir = std::env::temp_dir().join(format!("netpacket-ut-{}-snapshot-errors", std::process::id()));
+    std::fs::create_dir_all(dir.join("tcp6")).unwrap(); // a directory: opens, but does not read
+    let header = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n";
+    let good = format!("   0: {} {} 01 00000000:00000000 00:00000000 00000000  1000        0 1000\n", raw("10.0.0.5:40000"), raw("1.1.1.1:443"));
+    std::fs::write(dir.join("tcp"), format!("{header}{good}   1: garbage\n   2:\n")).unwrap();
+
+    let mut reader = TableReader::default();
+    let (conns, errors) = reader.read(&dir);
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(conns.len(), 1);
+    let malformed: Vec<_> = reader.malformed().iter().map(|a| (a.raw.as_str(), a.column, a.reason.as_str())).collect();
+    assert_eq!(malformed, [("   1: garbage", 2, "local address \"garbage\" is not <hex ip>:<hex port>"), ("   2:", 2, "missing local address"),]);
+    assert_eq!(reader.malformed()[0].source, dir.join("tcp"));
+    let find = |table: &str| errors.iter().find(|(t, _)| *t == table).map(|(_, e)| e);
+    assert!(matches!(find("tcp6"), Some(NetNotifyError::TableRead { .. })), "{errors:?}");
+    assert!(matches!(find("tcp"), Some(NetNotifyError::TableParse { line: 3, malformed: 2, .. })), "{errors:?}");
+    assert!(matches!(find("udp"), Some(NetNotifyError::TableMissing { .. })));
+}
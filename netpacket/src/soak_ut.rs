@@ -0,0 +1,82 @@
+//! Soak run of NetNotify over a churning connection table, see [`omnitrace_core::soak`].
+
+use crate::{
+    NetNotify, NetNotifyConfig,
+    dns::Resolver,
+    events::NetNotifyEvent,
+    stitch::SessionStitching,
+    summary::{Dimension, SummaryDimensions},
+};
+use omnitrace_core::{
+    callbacks::CallbackHub,
+    clock::ManualClock,
+    sensor::spawn_sensor,
+    soak::{self, Soak},
+};
+use std::{net::IpAddr, path::Path, sync::Arc, time::Duration};
+
+const PULSE: Duration = Duration::from_millis(10);
+
+/// Names every address, as a resolver on a busy host sees a stream of new ones.
+struct Numbered;
+
+impl Resolver for Numbered {
+    fn reverse(&self, ip: IpAddr) -> Option<String> {
+        Some(format!("host-{}.soak.example", ip.to_string().replace('.', "-")))
+    }
+}
+
+/// Tick `n`'s table: 100 connections from a sliding window of local ports, each to a remote
+/// of its own, so every tick one opens to a new remote, one closes and a few change state.
+fn write_table(dir: &Path, n: u64) {
+    let mut txt = String::from("  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n");
+    for (i, port) in (n..n + 100).enumerate() {
+        let local = format!("0500000A:{:04X}", 1024 + port % 60000);
+        let k = port % 60000;
+        let remote = format!("{:08X}:01BB", u32::from_le_bytes([93, 184, (k >> 8) as u8, k as u8]));
+        let st = if (port + n).is_multiple_of(7) { "08" } else { "01" };
+        txt.push_str(&format!("  {i}: {local} {remote} {st} 00000000:00000000 00:00000000 00000000  1000        0 {}\n", 1000 + port));
+    }
+    let tmp = dir.join("tcp.tmp");
+    std::fs::write(&tmp, txt).unwrap();
+    std::fs::rename(&tmp, dir.join("tcp")).unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn memory_stays_bounded_under_churn() {
+    let dir = std::env::temp_dir().join(format!("netpacket-soak-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    write_table(&dir, 0);
+    let clock = ManualClock::new();
+
+    let cfg = NetNotifyConfig::default()
+        .pulse(PULSE)
+        .proc_net(&dir)
+        .session_stitching(SessionStitching::new(PULSE * 5))
+        .summary_dimensions(SummaryDimensions::new(&[Dimension::RemoteIp, Dimension::LocalPort], 10))
+        .report_unwatched(PULSE * 100)
+        .clock(clock.shared());
+    let mut sensor = NetNotify::new(Some(cfg)).dns(true).dns_ttl(PULSE * 50);
+    sensor.set_resolver(Numbered);
+    sensor.add("tcp *");
+    let memory = sensor.memory();
+    let (handle, task) = spawn_sensor(sensor, Arc::new(CallbackHub::<NetNotifyEvent>::new()));
+
+    let ticks = soak::ticks(10_000);
+    // the DNS cache saws between its live names and twice as many before a sweep
+    let mut soak = Soak::new(ticks / 10).slack(0.5, 4096);
+    for n in 1..=ticks {
+        write_table(&dir, n);
+        clock.advance(PULSE);
+        tokio::time::sleep(PULSE).await;
+        if n % 100 == 0 {
+            soak.sample(n, &memory.report());
+        }
+    }
+    handle.shutdown();
+    let _ = task.await;
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(soak.samples() > 0);
+    soak.check().unwrap_or_else(|leak| panic!("after {ticks} ticks: {leak}"));
+}
@@ -0,0 +1,233 @@
+//! Session stitching: link a Closed connection and an Opened one that continue the same
+//! logical session after a roam (Wi-Fi to Ethernet, VPN reconnect, IPv4 to IPv6).
+//!
+//! Two connections belong together when they share the protocol family, the remote port,
+//! the remote peer and, by default, the local port. The peer is the remote IP (IPv4-mapped
+//! addresses folded) or, when known, the remote host name or TLS SNI, which is what survives
+//! a switch between IPv4 and IPv6. The local address is not compared, it is what changes.
+
+use crate::events::{ConnKey, NetNotifyEvent};
+use crate::snapshot::four_tuple;
+use omnitrace_core::labels::Labels;
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+    net::SocketAddr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Options for [`crate::NetNotifyConfig::session_stitching`].
+#[derive(Clone, Debug)]
+pub struct SessionStitching {
+    window: Duration,
+    max_pending: usize,
+    match_local_port: bool,
+    keep_raw: bool,
+}
+
+impl SessionStitching {
+    /// Stitch an Opened to a Closed seen at most `window` earlier.
+    pub fn new(window: Duration) -> Self {
+        Self { window, max_pending: 256, match_local_port: true, keep_raw: false }
+    }
+
+    /// Closed connections waiting for a successor, at most. When full, the oldest one is
+    /// given up (default 256).
+    pub fn max_pending(mut self, n: usize) -> Self {
+        self.max_pending = n.max(1);
+        self
+    }
+
+    /// Require the same local port (default). Fits UDP tunnels (WireGuard, OpenVPN, QUIC)
+    /// that keep their socket port across address changes; turn it off to also stitch TCP
+    /// reconnects from a new ephemeral port to the same remote service.
+    pub fn match_local_port(mut self, on: bool) -> Self {
+        self.match_local_port = on;
+        self
+    }
+
+    /// Also fire the raw Closed and Opened. By default a stitched pair is reported as one
+    /// Reconnected event, and the Closed of a candidate is held back until it either gets
+    /// stitched or its window runs out.
+    pub fn keep_raw(mut self, on: bool) -> Self {
+        self.keep_raw = on;
+        self
+    }
+}
+
+/// What identifies the far end of a session, see the module docs.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+enum Peer {
+    Ip(std::net::IpAddr),
+    Name(String),
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct StitchKey {
+    tcp: bool,
+    peer: Peer,
+    remote_port: u16,
+    local_port: Option<u16>,
+}
+
+type Tuple = (bool, SocketAddr, SocketAddr);
+
+struct Pending {
+    conn: ConnKey,
+    keys: Vec<StitchKey>,
+    closed_at: Instant,
+    session_id: Option<String>,
+    // a time gap happened since it closed
+    spans_gap: bool,
+}
+
+pub(crate) struct Stitcher {
+    cfg: SessionStitching,
+    /// Oldest first
+    pending: Vec<Pending>,
+    /// Session IDs of open connections that were stitched at least once.
+    sessions: HashMap<Tuple, String>,
+}
+
+impl Stitcher {
+    pub(crate) fn new(cfg: SessionStitching) -> Self {
+        Self { cfg, pending: Vec::new(), sessions: HashMap::new() }
+    }
+
+    fn keys(&self, c: &ConnKey) -> Vec<StitchKey> {
+        let (Some(local), Some(remote)) = (c.local_addr, c.remote_addr) else {
+            return Vec::new();
+        };
+        let key = |peer| StitchKey {
+            tcp: c.proto.starts_with("tcp"),
+            peer,
+            remote_port: remote.port(),
+            local_port: self.cfg.match_local_port.then_some(local.port()),
+        };
+        let mut keys = vec![key(Peer::Ip(remote.ip().to_canonical()))];
+        for name in [&c.remote_sni, &c.remote_host].into_iter().flatten() {
+            keys.push(key(Peer::Name(name.to_ascii_lowercase())));
+        }
+        keys
+    }
+
+    /// Rewrite one tick's Opened and Closed events: expire old candidates, then take the
+    /// Closed ones as candidates before matching the Opened ones against them, so a roam
+    /// seen within a single tick is stitched too. `open` is the current connection set.
+    pub(crate) fn tick(&mut self, events: Vec<NetNotifyEvent>, open: &HashSet<ConnKey>, now: Instant) -> Vec<NetNotifyEvent> {
+        let mut out = self.expire(now);
+        let (closed, opened): (Vec<_>, Vec<_>) = events.into_iter().partition(|ev| matches!(ev, NetNotifyEvent::Closed { .. }));
+        for ev in closed {
+            if let NetNotifyEvent::Closed { conn, .. } = ev {
+                out.extend(self.closed(conn, now));
+            }
+        }
+        // after the Closed ones took their session IDs along
+        if !self.sessions.is_empty() {
+            let open: HashSet<Tuple> = open.iter().filter_map(four_tuple).collect();
+            self.sessions.retain(|t, _| open.contains(t));
+        }
+        for ev in opened {
+            match ev {
+                NetNotifyEvent::Opened { conn, .. } => out.extend(self.opened(conn, now)),
+                ev => out.push(ev),
+            }
+        }
+        out
+    }
+
+    /// A connection closed. Returns what to fire now: the Closed itself unless it is held back
+    /// as a stitch candidate, and the Closed of a pending entry pushed out by the size limit.
+    pub(crate) fn closed(&mut self, conn: ConnKey, now: Instant) -> Vec<NetNotifyEvent> {
+        let keys = self.keys(&conn);
+        let mut out = Vec::new();
+        if keys.is_empty() {
+            out.push(NetNotifyEvent::Closed { conn, offline: false, labels: Labels::default() });
+            return out;
+        }
+        if self.pending.len() >= self.cfg.max_pending {
+            let evicted = self.pending.remove(0);
+            if !self.cfg.keep_raw {
+                out.push(NetNotifyEvent::Closed { conn: evicted.conn, offline: false, labels: Labels::default() });
+            }
+        }
+        if self.cfg.keep_raw {
+            out.push(NetNotifyEvent::Closed { conn: conn.clone(), offline: false, labels: Labels::default() });
+        }
+        let session_id = four_tuple(&conn).and_then(|t| self.sessions.remove(&t));
+        self.pending.push(Pending { conn, keys, closed_at: now, session_id, spans_gap: false });
+        out
+    }
+
+    /// A connection opened. Returns the Opened, a Reconnected if it continues a pending
+    /// session, or both with `keep_raw`.
+    pub(crate) fn opened(&mut self, conn: ConnKey, now: Instant) -> Vec<NetNotifyEvent> {
+        let keys = self.keys(&conn);
+        let found = self.pending.iter().position(|p| p.keys.iter().any(|k| keys.contains(k)));
+        let Some(idx) = found else {
+            return vec![NetNotifyEvent::Opened { conn, offline: false, labels: Labels::default() }];
+        };
+
+        let old = self.pending.remove(idx);
+        let session_id = old.session_id.unwrap_or_else(|| new_session_id(&old.conn));
+        if let Some(t) = four_tuple(&conn) {
+            self.sessions.insert(t, session_id.clone());
+        }
+
+        let mut out = Vec::new();
+        if self.cfg.keep_raw {
+            out.push(NetNotifyEvent::Opened { conn: conn.clone(), offline: false, labels: Labels::default() });
+        }
+        let gap = now.saturating_duration_since(old.closed_at);
+        out.push(NetNotifyEvent::Reconnected {
+            old_conn: old.conn,
+            new_conn: conn,
+            gap,
+            session_id,
+            gap_unreliable: old.spans_gap,
+            labels: Labels::default(),
+        });
+        out
+    }
+
+    /// Give up on candidates older than the window, returning their held-back Closed events.
+    pub(crate) fn expire(&mut self, now: Instant) -> Vec<NetNotifyEvent> {
+        let window = self.cfg.window;
+        let (expired, keep): (Vec<Pending>, Vec<Pending>) =
+            std::mem::take(&mut self.pending).into_iter().partition(|p| now.saturating_duration_since(p.closed_at) > window);
+        self.pending = keep;
+        self.flush(expired)
+    }
+
+    /// A suspend or clock step: the gaps of the candidates waiting now cannot be trusted.
+    pub(crate) fn time_gap(&mut self) {
+        for p in &mut self.pending {
+            p.spans_gap = true;
+        }
+    }
+
+    /// Held-back Closed events of all candidates, e.g. on shutdown.
+    pub(crate) fn drain(&mut self) -> Vec<NetNotifyEvent> {
+        let all = std::mem::take(&mut self.pending);
+        self.flush(all)
+    }
+
+    fn flush(&self, gone: Vec<Pending>) -> Vec<NetNotifyEvent> {
+        if self.cfg.keep_raw {
+            return Vec::new();
+        }
+        gone.into_iter().map(|p| NetNotifyEvent::Closed { conn: p.conn, offline: false, labels: Labels::default() }).collect()
+    }
+
+    pub(crate) fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+/// Stable for the whole session: derived from its first connection and when it closed.
+fn new_session_id(first: &ConnKey) -> String {
+    let mut h = DefaultHasher::new();
+    four_tuple(first).hash(&mut h);
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0).hash(&mut h);
+    format!("{:016x}", h.finish())
+}
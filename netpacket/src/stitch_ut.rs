@@ -0,0 +1,348 @@
+use crate::{
+    NetNotify, NetNotifyConfig,
+    events::{ConnKey, NetNotifyEvent, NetNotifyMask},
+    stitch::{SessionStitching, Stitcher},
+};
+use async_trait::async_trait;
+use omnitrace_core::{
+    callbacks::{Callback, CallbackHub, CallbackResult},
+    clock::{Clock, ManualClock},
+    labels::Labels,
+    sensor::spawn_sensor,
+};
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tempfile::TempDir;
+
+fn closed(c: &ConnKey) -> NetNotifyEvent {
+    NetNotifyEvent::Closed { conn: c.clone(), offline: false, labels: Labels::default() }
+}
+
+fn opened(c: &ConnKey) -> NetNotifyEvent {
+    NetNotifyEvent::Opened { conn: c.clone(), offline: false, labels: Labels::default() }
+}
+
+fn kinds(events: &[NetNotifyEvent]) -> Vec<&'static str> {
+    events
+        .iter()
+        .map(|ev| match ev {
+            NetNotifyEvent::Opened { .. } => "Opened",
+            NetNotifyEvent::Closed { .. } => "Closed",
+            NetNotifyEvent::Reconnected { .. } => "Reconnected",
+            _ => "other",
+        })
+        .collect()
+}
+
+fn session_id(ev: &NetNotifyEvent) -> String {
+    match ev {
+        NetNotifyEvent::Reconnected { session_id, .. } => session_id.clone(),
+        other => panic!("not a Reconnected: {other:?}"),
+    }
+}
+
+fn open_set(conns: &[&ConnKey]) -> HashSet<ConnKey> {
+    conns.iter().map(|c| (*c).clone()).collect()
+}
+
+#[test]
+fn closed_then_opened_from_another_address_is_one_reconnect() {
+    let mut st = Stitcher::new(SessionStitching::new(Duration::from_secs(5)));
+    let wifi = ConnKey::test("udp", "192.168.1.20:51820", "198.51.100.7:51820");
+    let eth = ConnKey::test("udp", "10.0.0.5:51820", "198.51.100.7:51820");
+    let t0 = Instant::now();
+
+    // the Closed is held back while it may still be stitched
+    assert!(st.tick(vec![closed(&wifi)], &HashSet::new(), t0).is_empty());
+    assert_eq!(st.pending(), 1);
+
+    let out = st.tick(vec![opened(&eth)], &open_set(&[&eth]), t0 + Duration::from_millis(800));
+    assert_eq!(kinds(&out), ["Reconnected"]);
+    let NetNotifyEvent::Reconnected { old_conn, new_conn, gap, .. } = &out[0] else { unreachable!() };
+    assert_eq!((old_conn, new_conn, *gap), (&wifi, &eth, Duration::from_millis(800)));
+    assert_eq!(st.pending(), 0);
+}
+
+#[test]
+fn gaps_across_a_time_gap_are_flagged() {
+    let mut st = Stitcher::new(SessionStitching::new(Duration::from_secs(5)));
+    let wifi = ConnKey::test("udp", "192.168.1.20:51820", "198.51.100.7:51820");
+    let eth = ConnKey::test("udp", "10.0.0.5:51820", "198.51.100.7:51820");
+    let t0 = Instant::now();
+
+    st.tick(vec![closed(&wifi)], &HashSet::new(), t0);
+    // closed before the lid shut, reopened after resume: the monotonic clock saw 1s
+    st.time_gap();
+    let out = st.tick(vec![opened(&eth)], &open_set(&[&eth]), t0 + Duration::from_secs(1));
+    assert!(matches!(out[..], [NetNotifyEvent::Reconnected { gap_unreliable: true, .. }]));
+
+    // later candidates are not affected
+    st.tick(vec![closed(&eth)], &HashSet::new(), t0 + Duration::from_secs(2));
+    let out = st.tick(vec![opened(&wifi)], &open_set(&[&wifi]), t0 + Duration::from_secs(3));
+    assert!(matches!(out[..], [NetNotifyEvent::Reconnected { gap_unreliable: false, .. }]));
+}
+
+#[test]
+fn session_id_survives_repeated_reconnects() {
+    let mut st = Stitcher::new(SessionStitching::new(Duration::from_secs(5)));
+    let a = ConnKey::test("udp", "192.168.1.20:51820", "198.51.100.7:51820");
+    let b = ConnKey::test("udp", "10.0.0.5:51820", "198.51.100.7:51820");
+    let c = ConnKey::test("udp", "172.16.0.9:51820", "198.51.100.7:51820");
+    let t0 = Instant::now();
+
+    // a roam within one tick: Closed and Opened of the same read
+    let first = st.tick(vec![opened(&b), closed(&a)], &open_set(&[&b]), t0);
+    let second = st.tick(vec![closed(&b), opened(&c)], &open_set(&[&c]), t0 + Duration::from_secs(1));
+    assert_eq!(session_id(&first[0]), session_id(&second[0]));
+
+    // an unrelated session gets its own ID
+    let x = ConnKey::test("udp", "192.168.1.20:40000", "203.0.113.9:443");
+    let y = ConnKey::test("udp", "10.0.0.5:40000", "203.0.113.9:443");
+    let other = st.tick(vec![closed(&x), opened(&y)], &open_set(&[&c, &y]), t0 + Duration::from_secs(2));
+    assert_ne!(session_id(&other[0]), session_id(&first[0]));
+}
+
+#[test]
+fn unmatched_candidates_close_late_on_expiry() {
+    let mut st = Stitcher::new(SessionStitching::new(Duration::from_secs(5)));
+    let a = ConnKey::test("tcp", "10.0.0.5:40000", "93.184.216.34:443");
+    let other = ConnKey::test("tcp", "10.0.0.6:40000", "198.51.100.1:443");
+    let t0 = Instant::now();
+
+    assert!(st.tick(vec![closed(&a)], &HashSet::new(), t0).is_empty());
+    // a different remote is not a successor
+    assert_eq!(kinds(&st.tick(vec![opened(&other)], &open_set(&[&other]), t0 + Duration::from_secs(1))), ["Opened"]);
+    assert!(st.tick(Vec::new(), &open_set(&[&other]), t0 + Duration::from_secs(5)).is_empty());
+
+    let late = st.tick(Vec::new(), &open_set(&[&other]), t0 + Duration::from_secs(6));
+    assert_eq!(kinds(&late), ["Closed"]);
+    assert_eq!(st.pending(), 0);
+
+    // too late to be stitched now
+    let back = ConnKey::test("tcp", "192.168.1.20:40000", "93.184.216.34:443");
+    assert_eq!(kinds(&st.tick(vec![opened(&back)], &open_set(&[&other, &back]), t0 + Duration::from_secs(7))), ["Opened"]);
+}
+
+#[test]
+fn pending_candidates_are_bounded() {
+    let mut st = Stitcher::new(SessionStitching::new(Duration::from_secs(60)).max_pending(3));
+    let t0 = Instant::now();
+    let conns: Vec<ConnKey> = (0..5).map(|i| ConnKey::test("tcp", "10.0.0.5:40000", &format!("198.51.100.{i}:443"))).collect();
+
+    let mut out = Vec::new();
+    for c in &conns {
+        out.extend(st.tick(vec![closed(c)], &HashSet::new(), t0));
+    }
+    assert_eq!(st.pending(), 3);
+    // the oldest two were given up and reported as plain closes
+    let evicted: Vec<ConnKey> = out.into_iter().map(|ev| if let NetNotifyEvent::Closed { conn, .. } = ev { conn } else { panic!() }).collect();
+    assert_eq!(evicted, conns[..2]);
+
+    assert_eq!(kinds(&st.drain()), ["Closed", "Closed", "Closed"]);
+}
+
+#[test]
+fn keep_raw_fires_both_and_local_port_is_optional() {
+    let a = ConnKey::test("tcp", "10.0.0.5:40000", "93.184.216.34:443");
+    let b = ConnKey::test("tcp", "192.168.1.20:51234", "93.184.216.34:443");
+    let t0 = Instant::now();
+
+    // a new ephemeral port is a different session by default
+    let mut st = Stitcher::new(SessionStitching::new(Duration::from_secs(5)));
+    assert_eq!(kinds(&st.tick(vec![closed(&a), opened(&b)], &open_set(&[&b]), t0)), ["Opened"]);
+
+    let mut st = Stitcher::new(SessionStitching::new(Duration::from_secs(5)).match_local_port(false).keep_raw(true));
+    assert_eq!(kinds(&st.tick(vec![closed(&a), opened(&b)], &open_set(&[&b]), t0)), ["Closed", "Opened", "Reconnected"]);
+    // raw Closed events went out already, nothing is held back
+    st.tick(vec![closed(&b)], &HashSet::new(), t0);
+    assert!(st.drain().is_empty());
+}
+
+#[test]
+fn window_edge_holds_over_hours_of_simulated_time() {
+    let window = Duration::from_secs(5);
+    let mut st = Stitcher::new(SessionStitching::new(window));
+    let clock = ManualClock::new();
+    let mut rng = fastrand::Rng::with_seed(725);
+    let pulse = Duration::from_millis(500);
+
+    // about ten hours of roams with gaps on both sides of the window, ticking every pulse
+    for i in 0..7200u32 {
+        let remote = format!("198.51.{}.{}:443", i / 250, i % 250);
+        let old = ConnKey::test("tcp", "192.168.1.20:40000", &remote);
+        let new = ConnKey::test("tcp", "10.0.0.5:40000", &remote);
+        let gap = match i % 100 {
+            0 => window,
+            1 => window + Duration::from_millis(1),
+            _ => Duration::from_millis(rng.u64(0..=10_000)),
+        };
+
+        let mut out = st.tick(vec![closed(&old)], &HashSet::new(), clock.now_instant());
+        let mut left = gap;
+        while left > pulse {
+            clock.advance(pulse);
+            left -= pulse;
+            out.extend(st.tick(Vec::new(), &HashSet::new(), clock.now_instant()));
+        }
+        clock.advance(left);
+        out.extend(st.tick(vec![opened(&new)], &open_set(&[&new]), clock.now_instant()));
+
+        let want: &[&str] = if gap <= window { &["Reconnected"] } else { &["Closed", "Opened"] };
+        assert_eq!(kinds(&out), want, "roam {i} with a gap of {gap:?}");
+        clock.advance(pulse);
+    }
+    assert_eq!(st.pending(), 0);
+}
+
+// -------------------------
+// scripted tables through the sensor
+// -------------------------
+
+struct Recorder(Arc<Mutex<Vec<NetNotifyEvent>>>);
+
+#[async_trait]
+impl Callback<NetNotifyEvent> for Recorder {
+    fn mask(&self) -> u64 {
+        (NetNotifyMask::OPENED | NetNotifyMask::CLOSED | NetNotifyMask::RECONNECTED).bits()
+    }
+
+    async fn call(&self, ev: &NetNotifyEvent) -> Option<CallbackResult> {
+        self.0.lock().unwrap().push(ev.clone());
+        None
+    }
+}
+
+/// Replace one table (`tcp` or `tcp6`) atomically with ESTABLISHED rows for `conns`.
+fn swap_table(dir: &Path, table: &str, conns: &[&ConnKey]) {
+    let mut txt = String::from("  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n");
+    for (i, c) in conns.iter().enumerate() {
+        txt.push_str(&format!("  {i}: {} {} 01 00000000:00000000 00:00000000 00000000  1000        0 {}\n", c.local, c.remote, 1000 + i));
+    }
+    let tmp = dir.join("tmp").join(table);
+    std::fs::write(&tmp, txt).unwrap();
+    std::fs::rename(tmp, dir.join(table)).unwrap();
+}
+
+/// Seed the SNI cache as the sniffer would have for a TLS handshake on `c`.
+fn seed_sni(sensor: &NetNotify, c: &ConnKey, sni: &str) {
+    let (l, r) = (c.local_addr.unwrap(), c.remote_addr.unwrap());
+    sensor.sni_cache.lock().unwrap().insert((l.ip(), l.port(), r.ip(), r.port()), (sni.to_string(), Instant::now()));
+}
+
+/// Run the sensor over `steps` table states, each held for a few ticks.
+async fn run_script(dir: &Path, sensor: NetNotify, steps: &[(&[&ConnKey], &[&ConnKey])]) -> Vec<NetNotifyEvent> {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(seen.clone()));
+
+    let (first, rest) = steps.split_first().unwrap();
+    swap_table(dir, "tcp", first.0);
+    swap_table(dir, "tcp6", first.1);
+    let (handle, task) = spawn_sensor(sensor, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(40)).await;
+
+    for (v4, v6) in rest {
+        // IPv4 first: a tick in between sees the old connection gone, not both at once
+        swap_table(dir, "tcp", v4);
+        swap_table(dir, "tcp6", v6);
+        tokio::time::sleep(Duration::from_millis(40)).await;
+    }
+    handle.shutdown();
+    let _ = task.await;
+    seen.lock().unwrap().clone()
+}
+
+fn stitching_config(dir: &Path) -> NetNotifyConfig {
+    NetNotifyConfig::default().pulse(Duration::from_millis(5)).proc_net(dir).session_stitching(SessionStitching::new(Duration::from_secs(5)))
+}
+
+fn stitching_sensor(dir: &Path) -> NetNotify {
+    NetNotify::new(Some(stitching_config(dir)))
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn ipv4_to_ipv6_flip_is_stitched_by_sni() {
+    let tmp = TempDir::new().unwrap();
+    std::fs::create_dir(tmp.path().join("tmp")).unwrap();
+    let dir = tmp.path();
+    let v4 = ConnKey::test("tcp", "10.0.0.5:40000", "93.184.216.34:443");
+    let v6 = ConnKey::test("tcp", "[2001:db8::5]:40000", "[2001:db8::34]:443");
+    let sensor = stitching_sensor(dir);
+    seed_sni(&sensor, &v4, "vpn.example.com");
+    seed_sni(&sensor, &v6, "vpn.example.com");
+
+    let events = run_script(dir, sensor, &[(&[&v4], &[]), (&[], &[&v6])]).await;
+    assert_eq!(kinds(&events), ["Reconnected"], "{events:?}");
+    let NetNotifyEvent::Reconnected { old_conn, new_conn, gap, .. } = &events[0] else { unreachable!() };
+    assert_eq!((old_conn.local_dec.as_deref(), old_conn.remote_sni.as_deref()), (Some("10.0.0.5:40000"), Some("vpn.example.com")));
+    assert_eq!((new_conn.proto.as_str(), new_conn.remote_dec.as_deref()), ("tcp6", Some("[2001:db8::34]:443")));
+    assert!(*gap < Duration::from_secs(5));
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn interface_address_change_keeps_the_session() {
+    let tmp = TempDir::new().unwrap();
+    std::fs::create_dir(tmp.path().join("tmp")).unwrap();
+    let dir = tmp.path();
+    let wifi = ConnKey::test("tcp", "10.0.0.5:40000", "93.184.216.34:443");
+    let eth = ConnKey::test("tcp", "192.168.1.20:40000", "93.184.216.34:443");
+    let back = ConnKey::test("tcp", "10.0.0.5:40000", "93.184.216.34:443");
+    let unrelated = ConnKey::test("tcp", "10.0.0.5:40001", "8.8.8.8:853");
+
+    let steps: &[(&[&ConnKey], &[&ConnKey])] = &[(&[&wifi, &unrelated], &[]), (&[&eth], &[]), (&[&back], &[])];
+    let events = run_script(dir, stitching_sensor(dir), steps).await;
+
+    // the unrelated connection closes late, on shutdown, since it had no successor
+    assert_eq!(kinds(&events), ["Reconnected", "Reconnected", "Closed"], "{events:?}");
+    assert_eq!(session_id(&events[0]), session_id(&events[1]));
+    let NetNotifyEvent::Closed { conn, .. } = &events[2] else { unreachable!() };
+    assert_eq!(conn, &unrelated);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(start_paused = true)]
+async fn suspend_between_close_and_reopen_is_diagnosed_and_flagged() {
+    use omnitrace_core::pulse::{TimeAnomaly, TimeGapKind};
+
+    let tmp = TempDir::new().unwrap();
+    std::fs::create_dir(tmp.path().join("tmp")).unwrap();
+    let dir = tmp.path();
+    let wifi = ConnKey::test("tcp", "10.0.0.5:40000", "93.184.216.34:443");
+    let eth = ConnKey::test("tcp", "192.168.1.20:40000", "93.184.216.34:443");
+    let clock = ManualClock::new();
+    let sensor = NetNotify::new(Some(stitching_config(dir).clock(clock.shared())));
+    let diagnostics = sensor.diagnostics();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(seen.clone()));
+
+    swap_table(dir, "tcp", &[&wifi]);
+    swap_table(dir, "tcp6", &[]);
+    let (handle, task) = spawn_sensor(sensor, Arc::new(hub));
+    let tick = || async {
+        clock.advance(Duration::from_millis(5));
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    };
+    for _ in 0..3 {
+        tick().await;
+    }
+    swap_table(dir, "tcp", &[]);
+    tick().await;
+    clock.suspend(Duration::from_secs(9 * 3600));
+    swap_table(dir, "tcp", &[&eth]);
+    tick().await;
+    handle.shutdown();
+    let _ = task.await;
+
+    let events = seen.lock().unwrap().clone();
+    assert!(matches!(events[..], [NetNotifyEvent::Reconnected { gap_unreliable: true, .. }]), "{events:?}");
+    let gap = diagnostics.get("time").unwrap();
+    let want = TimeAnomaly { kind: TimeGapKind::Suspend, magnitude: Duration::from_secs(9 * 3600), backward: false };
+    assert_eq!(gap.last.downcast_ref::<TimeAnomaly>(), Some(&want));
+}
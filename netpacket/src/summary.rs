@@ -0,0 +1,342 @@
+//! Periodic summaries of the connection table: who opens the most connections, and how
+//! many distinct remotes, ports and users there are.
+//!
+//! Every tick, the connections in the table that were not there the tick before (by
+//! 4-tuple, so a state change is not a new connection) are counted per value of each
+//! configured [`Dimension`]. Connections that opened and went to TIME_WAIT between two
+//! ticks count too, listening and unconnected sockets do not. Once per window, a Summary
+//! event reports the top K values per dimension and the number of distinct values.
+//!
+//! Within one tick the counts are exact, the snapshot holds them all. Across a window of
+//! several ticks each dimension keeps at most `capacity` values (a space-saving counter):
+//! when a new value comes in at capacity, it replaces the one with the lowest count and
+//! takes over that count as its `error`. A reported count is then at most `error` above
+//! the real one, `error` is at most the window's total divided by the capacity, and every
+//! value seen more often than that is in the table. Lists where nothing was replaced are
+//! flagged `exact`.
+//!
+//! Distinct values are counted exactly up to [`EXACT_DISTINCT`], and estimated above that
+//! with a HyperLogLog sketch of 1024 registers (about 3% standard error).
+
+// the summarizer runs in the sensor; without it only the event types are used
+#![cfg_attr(not(feature = "runtime"), allow(dead_code))]
+
+use crate::events::{ConnKey, NetNotifyEvent};
+use crate::snapshot::four_tuple;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+    time::{Duration, Instant},
+};
+
+/// Distinct values counted exactly per dimension and window, before switching to the estimate.
+pub const EXACT_DISTINCT: usize = 1024;
+
+const REGISTER_BITS: u32 = 10;
+const REGISTERS: usize = 1 << REGISTER_BITS;
+
+/// What new connections are grouped by.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dimension {
+    /// The remote address without port, IPv4-mapped IPv6 addresses as IPv4.
+    RemoteIp,
+    /// The reverse-DNS name of the remote, for those already in the DNS cache. The
+    /// summary never resolves names itself; connections without one are not counted.
+    RemoteHost,
+    /// The local port; inbound connections pile up on the listening port.
+    LocalPort,
+    /// The uid owning the socket, from the `uid` column of the table.
+    Uid,
+}
+
+impl Dimension {
+    pub const ALL: [Dimension; 4] = [Dimension::RemoteIp, Dimension::RemoteHost, Dimension::LocalPort, Dimension::Uid];
+
+    /// `"remote_ip"`, `"remote_host"`, `"local_port"` or `"uid"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Dimension::RemoteIp => "remote_ip",
+            Dimension::RemoteHost => "remote_host",
+            Dimension::LocalPort => "local_port",
+            Dimension::Uid => "uid",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|d| d.name() == name)
+    }
+
+    /// The value of `c`, as far as the connection itself tells: the host only if it was
+    /// resolved into `remote_host`, never the uid.
+    pub fn of(self, c: &ConnKey) -> Option<String> {
+        match self {
+            Dimension::RemoteIp => four_tuple(c).map(|(_, _, remote)| remote.ip().to_string()),
+            Dimension::RemoteHost => c.remote_host.clone(),
+            Dimension::LocalPort => c.local_addr.map(|a| a.port().to_string()),
+            Dimension::Uid => None,
+        }
+    }
+}
+
+/// Options for [`crate::NetNotifyConfig::summary_dimensions`].
+#[derive(Clone, Debug)]
+pub struct SummaryDimensions {
+    dimensions: Vec<Dimension>,
+    k: usize,
+    window: Duration,
+    capacity: Option<usize>,
+}
+
+impl SummaryDimensions {
+    /// Report the top `k` values of each of `dimensions`, e.g.
+    /// `SummaryDimensions::new(&[Dimension::RemoteIp, Dimension::LocalPort], 10)`.
+    pub fn new(dimensions: &[Dimension], k: usize) -> Self {
+        let mut dims = Vec::new();
+        for d in dimensions {
+            if !dims.contains(d) {
+                dims.push(*d);
+            }
+        }
+        Self { dimensions: dims, k: k.max(1), window: Duration::from_secs(60), capacity: None }
+    }
+
+    /// Fire a summary once this much time has passed since the previous one (default: 60s).
+    /// At or below the pulse, every tick is summarized on its own, exactly.
+    pub fn window(mut self, d: Duration) -> Self {
+        self.window = d;
+        self
+    }
+
+    /// Values kept per dimension across the ticks of a window, see the [module docs](self)
+    /// (default: 20 × k). Never below k.
+    pub fn capacity(mut self, n: usize) -> Self {
+        self.capacity = Some(n);
+        self
+    }
+
+    pub fn dimensions(&self) -> &[Dimension] {
+        &self.dimensions
+    }
+
+    fn capacity_or_default(&self) -> usize {
+        self.capacity.unwrap_or(self.k * 20).max(self.k)
+    }
+}
+
+/// One value in a top-K list. The real count is between `count - error` and `count`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TopEntry {
+    pub value: String,
+    pub count: u64,
+    #[serde(default)]
+    pub error: u64,
+}
+
+/// A dimension in a Summary event.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DimensionSummary {
+    pub dimension: Dimension,
+    /// Most frequent values first, ties by value.
+    pub top: Vec<TopEntry>,
+    /// No count in `top` is approximate.
+    pub exact: bool,
+    /// New connections that had a value for this dimension.
+    pub counted: u64,
+    /// Distinct values seen, estimated unless `distinct_exact`.
+    pub distinct: u64,
+    pub distinct_exact: bool,
+}
+
+/// Connections of `now` whose 4-tuple is not in `last`, once per 4-tuple. Listening and
+/// unconnected sockets (remote port 0) are left out.
+pub(crate) fn new_connections<'a>(now: &'a HashSet<ConnKey>, last: &HashSet<ConnKey>) -> Vec<&'a ConnKey> {
+    let mut seen: HashSet<_> = last.iter().filter_map(four_tuple).collect();
+    now.iter().filter(|c| four_tuple(c).is_some_and(|t| t.2.port() != 0 && seen.insert(t))).collect()
+}
+
+/// Top-K counting and distinct counts over summary windows, see the module docs.
+pub(crate) struct Summarizer {
+    opts: SummaryDimensions,
+    started: Option<Instant>,
+    ticks: u32,
+    new_connections: u64,
+    dims: Vec<DimensionState>,
+}
+
+struct DimensionState {
+    dimension: Dimension,
+    /// Exact counts of the last tick, reported as they are for a window of one tick.
+    tick: HashMap<String, u64>,
+    window: SpaceSaving,
+    distinct: Distinct,
+    counted: u64,
+}
+
+impl Summarizer {
+    pub(crate) fn new(opts: SummaryDimensions) -> Self {
+        let capacity = opts.capacity_or_default();
+        let dims = opts
+            .dimensions
+            .iter()
+            .map(|&dimension| DimensionState {
+                dimension,
+                tick: HashMap::new(),
+                window: SpaceSaving::new(capacity),
+                distinct: Distinct::default(),
+                counted: 0,
+            })
+            .collect();
+        Self { opts, started: None, ticks: 0, new_connections: 0, dims }
+    }
+
+    /// Start a new window at `at`, dropping what was counted so far (e.g. when the table
+    /// is taken as a new baseline).
+    pub(crate) fn restart(&mut self, at: Instant) {
+        self.started = Some(at);
+        self.ticks = 0;
+        self.new_connections = 0;
+        for d in &mut self.dims {
+            d.tick.clear();
+            d.window = SpaceSaving::new(d.window.capacity);
+            d.distinct = Distinct::default();
+            d.counted = 0;
+        }
+    }
+
+    /// Count the connections that are new at `at`, with `value` giving each one's value per
+    /// dimension, and return the summary if the window is over. `connections` is the size
+    /// of the table.
+    pub(crate) fn tick<F>(&mut self, new: &[&ConnKey], value: F, connections: usize, at: Instant) -> Option<NetNotifyEvent>
+    where
+        F: Fn(Dimension, &ConnKey) -> Option<String>,
+    {
+        let started = *self.started.get_or_insert(at);
+        self.ticks += 1;
+        self.new_connections += new.len() as u64;
+        for d in &mut self.dims {
+            d.tick.clear();
+            for c in new {
+                if let Some(v) = value(d.dimension, c) {
+                    *d.tick.entry(v).or_default() += 1;
+                }
+            }
+            // heavy values first, so the light ones are the ones replaced at capacity
+            let mut counts: Vec<(&String, &u64)> = d.tick.iter().collect();
+            counts.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+            for (v, n) in counts {
+                d.window.add(v, *n);
+                d.distinct.add(v);
+                d.counted += n;
+            }
+        }
+
+        let window = at.saturating_duration_since(started);
+        if window < self.opts.window {
+            return None;
+        }
+
+        let k = self.opts.k;
+        let single = self.ticks == 1;
+        let dimensions = self
+            .dims
+            .iter()
+            .map(|d| {
+                let (top, exact) = if single { (top_k(d.tick.iter().map(|(v, n)| (v, *n, 0)), k), true) } else { d.window.top(k) };
+                let (distinct, distinct_exact) = d.distinct.estimate();
+                DimensionSummary { dimension: d.dimension, top, exact, counted: d.counted, distinct, distinct_exact }
+            })
+            .collect();
+        let ev = NetNotifyEvent::Summary { window, ticks: self.ticks, connections, new_connections: self.new_connections, dimensions };
+        self.restart(at);
+        Some(ev)
+    }
+}
+
+fn top_k<'a>(entries: impl Iterator<Item = (&'a String, u64, u64)>, k: usize) -> Vec<TopEntry> {
+    let mut all: Vec<TopEntry> = entries.map(|(v, count, error)| TopEntry { value: v.clone(), count, error }).collect();
+    all.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+    all.truncate(k);
+    all
+}
+
+/// Counts of at most `capacity` values, see the module docs.
+pub(crate) struct SpaceSaving {
+    capacity: usize,
+    counts: HashMap<String, (u64, u64)>,
+    replaced: bool,
+}
+
+impl SpaceSaving {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self { capacity: capacity.max(1), counts: HashMap::new(), replaced: false }
+    }
+
+    pub(crate) fn add(&mut self, value: &str, n: u64) {
+        if let Some((count, _)) = self.counts.get_mut(value) {
+            *count += n;
+            return;
+        }
+        if self.counts.len() < self.capacity {
+            self.counts.insert(value.to_string(), (n, 0));
+            return;
+        }
+        let Some((min_value, min)) = self.counts.iter().min_by(|a, b| a.1.0.cmp(&b.1.0).then_with(|| b.0.cmp(a.0))).map(|(v, c)| (v.clone(), c.0))
+        else {
+            return;
+        };
+        self.counts.remove(&min_value);
+        self.counts.insert(value.to_string(), (min + n, min));
+        self.replaced = true;
+    }
+
+    /// The `k` highest counts, and whether they are exact.
+    pub(crate) fn top(&self, k: usize) -> (Vec<TopEntry>, bool) {
+        (top_k(self.counts.iter().map(|(v, (count, error))| (v, *count, *error)), k), !self.replaced)
+    }
+}
+
+/// Distinct values: exact while few, else a HyperLogLog estimate.
+pub(crate) struct Distinct {
+    exact: Option<HashSet<u64>>,
+    registers: Box<[u8; REGISTERS]>,
+}
+
+impl Default for Distinct {
+    fn default() -> Self {
+        Self { exact: Some(HashSet::new()), registers: Box::new([0; REGISTERS]) }
+    }
+}
+
+impl Distinct {
+    pub(crate) fn add(&mut self, value: &str) {
+        let mut h = DefaultHasher::new();
+        value.hash(&mut h);
+        let h = h.finish();
+
+        if let Some(set) = self.exact.as_mut() {
+            set.insert(h);
+            if set.len() > EXACT_DISTINCT {
+                self.exact = None;
+            }
+        }
+        let idx = (h >> (64 - REGISTER_BITS)) as usize;
+        let rank = ((h << REGISTER_BITS).leading_zeros() + 1).min(64 - REGISTER_BITS + 1) as u8;
+        self.registers[idx] = self.registers[idx].max(rank);
+    }
+
+    /// The count, and whether it is exact.
+    pub(crate) fn estimate(&self) -> (u64, bool) {
+        if let Some(set) = &self.exact {
+            return (set.len() as u64, true);
+        }
+        let m = REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let raw = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        let est = if raw <= 2.5 * m && zeros > 0 { m * (m / zeros as f64).ln() } else { raw };
+        (est.round() as u64, false)
+    }
+}
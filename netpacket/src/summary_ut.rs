@@ -0,0 +1,206 @@
+use crate::{
+    NetNotify, NetNotifyConfig, baseline,
+    events::{ConnKey, NetNotifyEvent, NetNotifyMask},
+    summary::{self, Dimension, DimensionSummary, Distinct, SpaceSaving, Summarizer, SummaryDimensions, TopEntry},
+};
+use async_trait::async_trait;
+use omnitrace_core::{
+    callbacks::{Callback, CallbackHub, CallbackResult},
+    sensor::spawn_sensor,
+};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// `n` connections from `remote` to local port `port`, from distinct ephemeral ports starting at `first`.
+fn from(remote: &str, port: u16, n: usize, first: u16) -> Vec<ConnKey> {
+    (0..n).map(|i| ConnKey::test("tcp", &format!("10.0.0.1:{}", first as usize + i), &format!("{remote}:{port}"))).collect()
+}
+
+/// One tick of a skewed mix: a hot client, a warm one, a few cold ones and a scan of 40
+/// addresses with one connection each, `tick` keeping the ephemeral ports apart.
+fn skewed_tick(tick: u16) -> Vec<ConnKey> {
+    let base = 10000 + tick * 1000;
+    let mut out = from("203.0.113.1", 443, 50, base);
+    out.extend(from("203.0.113.2", 443, 30, base + 100));
+    out.extend(from("203.0.113.3", 22, 10, base + 200));
+    out.extend(from("203.0.113.4", 22, 5, base + 300));
+    for i in 0..40 {
+        out.extend(from(&format!("198.51.100.{}", i + tick * 40), 8080, 1, base + 400 + i));
+    }
+    out
+}
+
+fn top(ev: &NetNotifyEvent, d: Dimension) -> DimensionSummary {
+    let NetNotifyEvent::Summary { dimensions, .. } = ev else {
+        panic!("not a summary: {ev:?}");
+    };
+    dimensions.iter().find(|s| s.dimension == d).cloned().unwrap()
+}
+
+fn entries(s: &DimensionSummary) -> Vec<(&str, u64)> {
+    s.top.iter().map(|t| (t.value.as_str(), t.count)).collect()
+}
+
+#[test]
+fn one_tick_windows_are_exact() {
+    let mut sm = Summarizer::new(SummaryDimensions::new(&[Dimension::RemoteIp, Dimension::LocalPort], 3).window(Duration::ZERO));
+    let conns = skewed_tick(0);
+    let new: Vec<&ConnKey> = conns.iter().collect();
+    let ev = sm.tick(&new, |d, c| d.of(c), 200, Instant::now()).expect("a summary per tick");
+
+    let NetNotifyEvent::Summary { ticks, connections, new_connections, .. } = &ev else { unreachable!() };
+    assert_eq!((*ticks, *connections, *new_connections), (1, 200, 135));
+
+    let ips = top(&ev, Dimension::RemoteIp);
+    assert_eq!(entries(&ips), vec![("203.0.113.1", 50), ("203.0.113.2", 30), ("203.0.113.3", 10)]);
+    assert!(ips.exact && ips.top.iter().all(|t| t.error == 0));
+    assert_eq!((ips.distinct, ips.distinct_exact, ips.counted), (44, true, 135));
+
+    let ports = top(&ev, Dimension::LocalPort);
+    assert_eq!(ports.top.len(), 3);
+    assert!(ports.top.iter().all(|t| t.count == 1), "ephemeral ports are all distinct: {:?}", ports.top);
+    assert_eq!(ports.distinct, 135);
+}
+
+#[test]
+fn multi_tick_windows_keep_the_heavy_hitters_within_the_error_bound() {
+    let capacity = 16;
+    let opts = SummaryDimensions::new(&[Dimension::RemoteIp], 3).window(Duration::from_secs(10)).capacity(capacity);
+    let mut sm = Summarizer::new(opts);
+    let t0 = Instant::now();
+
+    let mut summary = None;
+    for tick in 0..5u16 {
+        let conns = skewed_tick(tick);
+        let new: Vec<&ConnKey> = conns.iter().collect();
+        summary = sm.tick(&new, |d, c| d.of(c), conns.len(), t0 + Duration::from_millis(2500 * tick as u64));
+        assert_eq!(summary.is_some(), tick == 4, "tick {tick}");
+    }
+    let ev = summary.unwrap();
+    let NetNotifyEvent::Summary { ticks, window, new_connections, .. } = &ev else { unreachable!() };
+    assert_eq!((*ticks, *window, *new_connections), (5, Duration::from_secs(10), 5 * 135));
+
+    let ips = top(&ev, Dimension::RemoteIp);
+    assert!(!ips.exact, "the scan does not fit in {capacity} slots");
+    let values: Vec<&str> = ips.top.iter().map(|t| t.value.as_str()).collect();
+    assert_eq!(values, vec!["203.0.113.1", "203.0.113.2", "203.0.113.3"]);
+    let bound = ips.counted / capacity as u64;
+    for (t, real) in ips.top.iter().zip([250, 150, 50]) {
+        assert!(t.count >= real && t.count - t.error <= real, "{t:?} vs {real}");
+        assert!(t.error <= bound, "{t:?} over {bound}");
+    }
+    // 4 clients and 5 × 40 scanned addresses
+    assert_eq!((ips.distinct, ips.distinct_exact), (204, true));
+
+    // the next window starts empty
+    let conns = from("203.0.113.9", 443, 2, 1000);
+    let new: Vec<&ConnKey> = conns.iter().collect();
+    let ev = sm.tick(&new, |d, c| d.of(c), 2, t0 + Duration::from_secs(20)).unwrap();
+    assert_eq!(entries(&top(&ev, Dimension::RemoteIp)), vec![("203.0.113.9", 2)]);
+}
+
+#[test]
+fn space_saving_takes_over_the_evicted_count_as_error() {
+    let mut ss = SpaceSaving::new(2);
+    ss.add("a", 5);
+    ss.add("b", 2);
+    assert_eq!(ss.top(2), (vec![TopEntry { value: "a".into(), count: 5, error: 0 }, TopEntry { value: "b".into(), count: 2, error: 0 }], true));
+    ss.add("c", 1);
+    assert_eq!(ss.top(2), (vec![TopEntry { value: "a".into(), count: 5, error: 0 }, TopEntry { value: "c".into(), count: 3, error: 2 }], false));
+}
+
+#[test]
+fn distinct_counts_are_exact_when_few_and_estimated_when_many() {
+    let mut d = Distinct::default();
+    for i in 0..summary::EXACT_DISTINCT {
+        d.add(&format!("10.{}.{}.1", i / 256, i % 256));
+        d.add(&format!("10.{}.{}.1", i / 256, i % 256));
+    }
+    assert_eq!(d.estimate(), (summary::EXACT_DISTINCT as u64, true));
+
+    for i in summary::EXACT_DISTINCT..50_000 {
+        d.add(&format!("10.{}.{}.1", i / 256, i % 256));
+    }
+    let (n, exact) = d.estimate();
+    assert!(!exact);
+    assert!((47_000..53_000).contains(&n), "estimate {n} for 50000");
+}
+
+#[test]
+fn new_connections_skip_state_changes_listeners_and_known_tuples() {
+    let est = ConnKey::test("tcp", "10.0.0.1:40000", "203.0.113.1:443");
+    let last: HashSet<ConnKey> = [est.clone()].into_iter().collect();
+    let close_wait = baseline::conn_key("tcp", &est.local, &est.remote, Some("08".into()));
+    let listen = baseline::conn_key("tcp", "00000000:01BB", "00000000:0000", Some("0A".into()));
+    let fresh = ConnKey::test("tcp", "10.0.0.1:40001", "203.0.113.1:443");
+    let mapped = ConnKey::test("tcp", "[::ffff:10.0.0.1]:40001", "[::ffff:203.0.113.1]:443");
+    let now: HashSet<ConnKey> = [close_wait, listen, fresh.clone(), mapped].into_iter().collect();
+
+    let new = summary::new_connections(&now, &last);
+    assert_eq!(new.len(), 1);
+    assert_eq!(Dimension::RemoteIp.of(new[0]).as_deref(), Some("203.0.113.1"));
+}
+
+struct Summaries(Arc<Mutex<Vec<NetNotifyEvent>>>);
+
+#[async_trait]
+impl Callback<NetNotifyEvent> for Summaries {
+    fn mask(&self) -> u64 {
+        (NetNotifyMask::SUMMARY | NetNotifyMask::OPENED).bits()
+    }
+
+    async fn call(&self, ev: &NetNotifyEvent) -> Option<CallbackResult> {
+        self.0.lock().unwrap().push(ev.clone());
+        None
+    }
+}
+
+/// Write a tcp table with (local, remote, uid) rows, ESTABLISHED, swapped in atomically.
+fn swap_table(dir: &std::path::Path, rows: &[(String, &str, u32)]) {
+    let mut txt = String::from("  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n");
+    for (i, (local, remote, uid)) in rows.iter().enumerate() {
+        txt.push_str(&format!("  {i}: {local} {remote} 01 00000000:00000000 00:00000000 00000000 {uid:5}        0 {}\n", 1000 + i));
+    }
+    let tmp = dir.join("tcp.tmp");
+    std::fs::write(&tmp, txt).unwrap();
+    std::fs::rename(&tmp, dir.join("tcp")).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn sensor_summarizes_the_table_by_uid_without_connection_events() {
+    let dir = std::env::temp_dir().join(format!("netpacket-ut-{}-summary", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // 10.0.0.5:<port> -> 93.184.216.34:443
+    let row = |port: u16, uid: u32| (format!("0500000A:{port:04X}"), "22D8B85D:01BB", uid);
+    swap_table(&dir, &[row(40000, 0)]);
+
+    let dims = SummaryDimensions::new(&[Dimension::Uid, Dimension::RemoteIp], 2).window(Duration::ZERO);
+    let cfg = NetNotifyConfig::default().pulse(Duration::from_millis(5)).proc_net(&dir).summary_dimensions(dims);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Summaries(seen.clone()));
+    let (handle, task) = spawn_sensor(NetNotify::new(Some(cfg)), Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(40)).await;
+
+    let mut rows = vec![row(40000, 0)];
+    rows.extend((1..=6).map(|i| row(40000 + i, 1000)));
+    rows.extend((7..=8).map(|i| row(40000 + i, 33)));
+    swap_table(&dir, &rows);
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    handle.shutdown();
+    let _ = task.await;
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let seen = seen.lock().unwrap();
+    assert!(seen.iter().all(|ev| matches!(ev, NetNotifyEvent::Summary { .. })), "summaries only, no Opened");
+    let busy: Vec<&NetNotifyEvent> =
+        seen.iter().filter(|ev| matches!(ev, NetNotifyEvent::Summary { new_connections, .. } if *new_connections > 0)).collect();
+    assert_eq!(busy.len(), 1, "{seen:?}");
+    let uids = top(busy[0], Dimension::Uid);
+    assert_eq!(entries(&uids), vec![("1000", 6), ("33", 2)]);
+    assert_eq!(entries(&top(busy[0], Dimension::RemoteIp)), vec![("93.184.216.34", 8)]);
+}
@@ -46,10 +46,7 @@ pub fn lookup_sni(key: SniKey, ttl: Duration) -> Option<String> {
     map.get(&key).map(|(s, _)| s.clone())
 }
 
-pub fn run_sni_sniffer(
-    cache: SniCache,
-    iface_name: Option<String>,
-) {
+pub fn run_sni_sniffer(cache: SniCache, iface_name: Option<String>) {
     use std::collections::HashMap;
     use std::net::IpAddr;
     use std::time::{Duration, Instant};
@@ -61,11 +58,7 @@ pub fn run_sni_sniffer(
                 return false;
             }
 
-            if let Some(name) = iface_name.as_deref() {
-                i.name == name
-            } else {
-                !i.is_loopback()
-            }
+            if let Some(name) = iface_name.as_deref() { i.name == name } else { !i.is_loopback() }
         })
         .collect();
 
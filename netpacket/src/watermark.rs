@@ -0,0 +1,104 @@
+use crate::events::ConnKey;
+use std::collections::{BTreeMap, HashSet};
+
+/// Selects which sockets a watermark counts, by protocol and decoded state.
+/// Protocols are normalized, so `"tcp"` covers tcp6 as well.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateFilter {
+    proto: Option<String>,
+    state: Option<String>,
+}
+
+impl StateFilter {
+    /// All sockets.
+    pub fn any() -> Self {
+        Self::default()
+    }
+
+    /// Sockets in this state, e.g. `"TIME_WAIT"` or `"SYN_RECV"`.
+    pub fn state<S: Into<String>>(state: S) -> Self {
+        Self { proto: None, state: Some(state.into()) }
+    }
+
+    /// Only count this protocol ("tcp" or "udp").
+    pub fn proto<S: Into<String>>(mut self, proto: S) -> Self {
+        self.proto = Some(proto.into());
+        self
+    }
+
+    /// Name used in events, e.g. `"tcp:TIME_WAIT"` or `"*:*"`.
+    pub fn name(&self) -> String {
+        format!("{}:{}", self.proto.as_deref().unwrap_or("*"), self.state.as_deref().unwrap_or("*"))
+    }
+
+    fn matches(&self, proto: &str, state: &str) -> bool {
+        self.proto.as_deref().is_none_or(|p| p == proto) && self.state.as_deref().is_none_or(|s| s == state)
+    }
+
+    pub(crate) fn count(&self, counts: &BTreeMap<String, usize>) -> usize {
+        counts.iter().filter(|(k, _)| k.split_once(':').is_some_and(|(proto, state)| self.matches(proto, state))).map(|(_, n)| n).sum()
+    }
+}
+
+/// Watermark levels: exceeded above `high`, cleared again at or below `low` (hysteresis).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Threshold {
+    pub high: usize,
+    pub low: usize,
+}
+
+/// Exceeded when the count goes above `n`, cleared at or below 90% of `n`.
+pub fn above(n: usize) -> Threshold {
+    Threshold { high: n, low: n - n / 10 }
+}
+
+impl Threshold {
+    /// Clear only when the count drops to `n` or below.
+    pub fn clear_at(mut self, n: usize) -> Self {
+        self.low = n.min(self.high);
+        self
+    }
+}
+
+pub(crate) struct Watermark {
+    pub(crate) filter: StateFilter,
+    pub(crate) threshold: Threshold,
+    sustain: u32,
+    streak: u32,
+    exceeded: bool,
+}
+
+impl Watermark {
+    pub(crate) fn new(filter: StateFilter, threshold: Threshold, sustain_ticks: u32) -> Self {
+        Self { filter, threshold, sustain: sustain_ticks.max(1), streak: 0, exceeded: false }
+    }
+
+    /// Feed one tick's count. Returns `Some(true)` when the watermark just got exceeded,
+    /// `Some(false)` when it just cleared, both only after `sustain` consecutive ticks.
+    pub(crate) fn update(&mut self, count: usize) -> Option<bool> {
+        let flipping = if self.exceeded { count <= self.threshold.low } else { count > self.threshold.high };
+        if !flipping {
+            self.streak = 0;
+            return None;
+        }
+
+        self.streak += 1;
+        if self.streak < self.sustain {
+            return None;
+        }
+
+        self.streak = 0;
+        self.exceeded = !self.exceeded;
+        Some(self.exceeded)
+    }
+}
+
+/// Socket counts per `"<proto>:<state>"` (e.g. `"tcp:ESTABLISHED"`, `"udp:-"`).
+pub(crate) fn state_counts(conns: &HashSet<ConnKey>) -> BTreeMap<String, usize> {
+    let mut out = BTreeMap::new();
+    for c in conns {
+        let proto = c.proto.strip_suffix('6').unwrap_or(&c.proto);
+        *out.entry(format!("{proto}:{}", c.state_dec.as_deref().unwrap_or("-"))).or_insert(0) += 1;
+    }
+    out
+}
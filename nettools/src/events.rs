@@ -1,4 +1,5 @@
 use bitflags::bitflags;
+use omnitrace_core::topics::{self, Topic, Topics};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
@@ -177,4 +178,59 @@ impl NetToolsEvent {
             NetToolsEvent::WifiChanged { .. } => NetToolsMask::WIFI_CHANGED,
         }
     }
+
+    /// Dotted topic, see [`omnitrace_core::topics`].
+    pub fn topic(&self) -> &'static str {
+        topics::topic_of(self)
+    }
+}
+
+impl Topics for NetToolsEvent {
+    const TOPICS: &'static [Topic] = &[
+        Topic::new("net.hostname.changed", NetToolsMask::HOSTNAME_CHANGED.bits()),
+        Topic::new("net.route.added", NetToolsMask::ROUTE_ADDED.bits()),
+        Topic::new("net.route.removed", NetToolsMask::ROUTE_REMOVED.bits()),
+        Topic::new("net.route.changed", NetToolsMask::ROUTE_CHANGED.bits()),
+        Topic::new("net.route.default.added", NetToolsMask::DEFAULT_ROUTE_ADDED.bits()),
+        Topic::new("net.route.default.removed", NetToolsMask::DEFAULT_ROUTE_REMOVED.bits()),
+        Topic::new("net.route.default.changed", NetToolsMask::DEFAULT_ROUTE_CHANGED.bits()),
+        Topic::new("net.health.changed", NetToolsMask::NETHEALTH_CHANGED.bits()),
+        Topic::new("net.socket.added", NetToolsMask::SOCKET_ADDED.bits()),
+        Topic::new("net.socket.removed", NetToolsMask::SOCKET_REMOVED.bits()),
+        Topic::new("net.neighbour.added", NetToolsMask::NEIGHBOUR_ADDED.bits()),
+        Topic::new("net.neighbour.removed", NetToolsMask::NEIGHBOUR_REMOVED.bits()),
+        Topic::new("net.neighbour.changed", NetToolsMask::NEIGHBOUR_CHANGED.bits()),
+        Topic::new("net.route_lookup.added", NetToolsMask::ROUTE_LOOKUP_ADDED.bits()),
+        Topic::new("net.route_lookup.removed", NetToolsMask::ROUTE_LOOKUP_REMOVED.bits()),
+        Topic::new("net.route_lookup.changed", NetToolsMask::ROUTE_LOOKUP_CHANGED.bits()),
+        Topic::new("net.throughput.updated", NetToolsMask::THROUGHPUT_UPDATED.bits()),
+        Topic::new("net.wifi.added", NetToolsMask::WIFI_ADDED.bits()),
+        Topic::new("net.wifi.removed", NetToolsMask::WIFI_REMOVED.bits()),
+        Topic::new("net.wifi.changed", NetToolsMask::WIFI_CHANGED.bits()),
+    ];
+
+    fn topic_index(&self) -> usize {
+        match self {
+            NetToolsEvent::HostnameChanged { .. } => 0,
+            NetToolsEvent::RouteAdded { .. } => 1,
+            NetToolsEvent::RouteRemoved { .. } => 2,
+            NetToolsEvent::RouteChanged { .. } => 3,
+            NetToolsEvent::DefaultRouteAdded { .. } => 4,
+            NetToolsEvent::DefaultRouteRemoved { .. } => 5,
+            NetToolsEvent::DefaultRouteChanged { .. } => 6,
+            NetToolsEvent::NetHealthChanged { .. } => 7,
+            NetToolsEvent::SocketAdded { .. } => 8,
+            NetToolsEvent::SocketRemoved { .. } => 9,
+            NetToolsEvent::NeighbourAdded { .. } => 10,
+            NetToolsEvent::NeighbourRemoved { .. } => 11,
+            NetToolsEvent::NeighbourChanged { .. } => 12,
+            NetToolsEvent::RouteLookupAdded { .. } => 13,
+            NetToolsEvent::RouteLookupRemoved { .. } => 14,
+            NetToolsEvent::RouteLookupChanged { .. } => 15,
+            NetToolsEvent::ThroughputUpdated { .. } => 16,
+            NetToolsEvent::WifiAdded { .. } => 17,
+            NetToolsEvent::WifiRemoved { .. } => 18,
+            NetToolsEvent::WifiChanged { .. } => 19,
+        }
+    }
 }
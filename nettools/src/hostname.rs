@@ -32,7 +32,7 @@ impl NetTools {
         self.last_hostname = Some(hostname);
     }
 
-    pub(crate) async fn handle_hostname_poll(&mut self, hub: &CallbackHub<events::NetToolsEvent>) {
+    pub(crate) async fn handle_hostname_poll<R: Send + 'static>(&mut self, hub: &CallbackHub<events::NetToolsEvent, R>) {
         let cur = match self.poll_hostname() {
             Ok(cur) => cur,
             Err(err) => {
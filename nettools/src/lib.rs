@@ -2,6 +2,7 @@ pub mod events;
 mod hostname;
 mod neighbour;
 mod nethealth;
+pub mod prelude;
 mod route;
 mod socket;
 mod throughput;
@@ -26,8 +27,10 @@ use crate::events::NetToolsEvent;
 use async_trait::async_trait;
 use omnitrace_core::{
     callbacks::CallbackHub,
+    debug::DebugCell,
     sensor::{Sensor, SensorCtx},
 };
+use serde::Serialize;
 use std::{
     collections::{HashMap, HashSet, VecDeque},
     future::Future,
@@ -191,6 +194,23 @@ impl NetToolsConfig {
     }
 }
 
+/// What [`NetTools::debug_handle`] reports, refreshed every tick.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct NetToolsDebug {
+    pub pulse_ms: u64,
+    /// Enabled checks, e.g. `"routes"` or `"wifi"`.
+    pub checks: Vec<String>,
+    pub hostname: Option<String>,
+    pub routes: usize,
+    pub nethealth_targets: Vec<events::NetHealthTarget>,
+    pub nethealth: Option<events::NetHealthState>,
+    pub sockets: usize,
+    pub neighbours: usize,
+    pub route_lookup_targets: Vec<String>,
+    pub throughput_interfaces: usize,
+    pub wifi_interfaces: usize,
+}
+
 pub struct NetTools {
     cfg: NetToolsConfig,
     hostname_backend: Arc<dyn HostnameBackend>,
@@ -211,6 +231,7 @@ pub struct NetTools {
     last_route_lookups: HashMap<RouteLookupKey, events::RouteLookupEntry>,
     last_throughput: Option<ThroughputState>,
     last_wifi: HashMap<String, events::WifiDetails>,
+    debug: DebugCell<NetToolsDebug>,
 }
 
 impl Default for NetTools {
@@ -241,6 +262,7 @@ impl NetTools {
             last_route_lookups: HashMap::new(),
             last_throughput: None,
             last_wifi: HashMap::new(),
+            debug: DebugCell::default(),
         }
     }
 
@@ -310,11 +332,46 @@ impl NetTools {
         self.route_lookup_targets.push(target.into());
     }
 
-    async fn fire(hub: &CallbackHub<NetToolsEvent>, ev: NetToolsEvent) {
+    /// Handle on a summary of the sensor's state, for [`omnitrace_core::debug::Snapshots`].
+    pub fn debug_handle(&self) -> DebugCell<NetToolsDebug> {
+        self.debug.clone()
+    }
+
+    fn publish_debug(&self) {
+        let checks = [
+            ("hostname", self.cfg.hostname),
+            ("routes", self.cfg.routes),
+            ("default_routes", self.cfg.default_routes),
+            ("route_lookups", self.cfg.route_lookups),
+            ("nethealth", self.cfg.nethealth),
+            ("sockets", self.cfg.sockets),
+            ("neighbours", self.cfg.neighbours),
+            ("throughput", self.cfg.throughput),
+            ("wifi", self.cfg.wifi),
+        ];
+
+        self.debug.update(|d| {
+            *d = NetToolsDebug {
+                pulse_ms: self.cfg.get_pulse().as_millis() as u64,
+                checks: checks.iter().filter(|(_, on)| *on).map(|(name, _)| name.to_string()).collect(),
+                hostname: self.last_hostname.clone(),
+                routes: self.last_routes.len(),
+                nethealth_targets: self.nethealth_targets.clone(),
+                nethealth: self.last_nethealth.clone(),
+                sockets: self.last_sockets.len(),
+                neighbours: self.last_neighbours.len(),
+                route_lookup_targets: self.route_lookup_targets.clone(),
+                throughput_interfaces: self.last_throughput.as_ref().map_or(0, |t| t.counters.len()),
+                wifi_interfaces: self.last_wifi.len(),
+            }
+        });
+    }
+
+    async fn fire<R: Send + 'static>(hub: &CallbackHub<NetToolsEvent, R>, ev: NetToolsEvent) {
         hub.fire(ev.mask().bits(), &ev).await;
     }
 
-    pub async fn run(mut self, ctx: SensorCtx<NetToolsEvent>) {
+    pub async fn run<R: Send + 'static>(mut self, ctx: SensorCtx<NetToolsEvent, R>) {
         if self.cfg.hostname {
             self.handle_hostname_poll(&ctx.hub).await;
         }
@@ -347,9 +404,11 @@ impl NetTools {
             self.last_wifi = self.poll_wifi().unwrap_or_default();
         }
 
+        self.publish_debug();
         let mut ticker = tokio::time::interval(self.cfg.get_pulse());
 
         loop {
+            ctx.beat();
             tokio::select! {
                 _ = ctx.cancel.cancelled() => break,
                 _ = ticker.tick() => {
@@ -380,6 +439,8 @@ impl NetTools {
                     if self.cfg.wifi {
                         self.handle_wifi_poll(&ctx.hub).await;
                     }
+
+                    self.publish_debug();
                 },
             }
         }
@@ -389,7 +450,7 @@ impl NetTools {
 impl Sensor for NetTools {
     type Event = NetToolsEvent;
 
-    fn run(self, ctx: SensorCtx<Self::Event>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+    fn run<R: Send + 'static>(self, ctx: SensorCtx<Self::Event, R>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
         Box::pin(async move { self.run(ctx).await })
     }
 }
@@ -133,7 +133,7 @@ impl NetTools {
         self.neighbour_backend.list()
     }
 
-    pub(crate) async fn handle_neighbour_poll(&mut self, hub: &CallbackHub<events::NetToolsEvent>) {
+    pub(crate) async fn handle_neighbour_poll<R: Send + 'static>(&mut self, hub: &CallbackHub<events::NetToolsEvent, R>) {
         let cur = match self.poll_neighbours() {
             Ok(cur) => cur,
             Err(err) => {
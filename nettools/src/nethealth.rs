@@ -105,7 +105,7 @@ impl NetTools {
         }
     }
 
-    pub(crate) async fn handle_nethealth_poll(&mut self, hub: &CallbackHub<events::NetToolsEvent>) {
+    pub(crate) async fn handle_nethealth_poll<R: Send + 'static>(&mut self, hub: &CallbackHub<events::NetToolsEvent, R>) {
         if self.nethealth_targets.is_empty() {
             return;
         }
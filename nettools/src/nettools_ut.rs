@@ -2,14 +2,16 @@ use crate::{
     HostnameBackend, NeighbourBackend, NetHealthBackend, NetTools, NetToolsConfig, RouteBackend, SocketBackend,
     ThroughputBackend, WifiBackend,
     events::{
-        InterfaceCounters, NetHealthLevel, NetHealthTarget, NetToolsEvent, NeighbourEntry, RouteEntry, RouteFamily,
+        InterfaceCounters, NetHealthLevel, NetHealthTarget, NetToolsEvent, NetToolsMask, NeighbourEntry, RouteEntry, RouteFamily,
         SocketEntry, SocketKind, WifiDetails,
     },
 };
 use async_trait::async_trait;
 use omnitrace_core::{
     callbacks::{Callback, CallbackHub, CallbackResult},
+    debug::Snapshots,
     sensor::spawn_sensor,
+    topics::{self, Topics},
 };
 use std::{
     collections::{HashMap, HashSet, VecDeque},
@@ -937,3 +939,62 @@ async fn emits_wifi_changed_event() {
     assert_eq!(event["old"]["iface"], "wlan0");
     assert_eq!(event["new"]["signal_level_dbm"], -77.0);
 }
+
+#[tokio::test]
+async fn debug_snapshot_reports_enabled_checks_and_state() {
+    let mut sensor = NetTools::new(Some(NetToolsConfig::default().pulse(Duration::from_millis(10)).routes(true)));
+    sensor.set_hostname_backend(SequenceBackend::new(vec![Ok("alpha")]));
+    sensor.set_route_backend(SequenceRouteBackend::new(vec![Ok(vec![route("default", "10.0.0.1", "em0"), route("10.1.0.0/16", "10.0.0.2", "em0")])]));
+    let mut snapshots = Snapshots::new();
+    snapshots.register("nettools", sensor.debug_handle());
+
+    let (handle, sensor_task) = spawn_sensor(sensor, Arc::new(CallbackHub::<NetToolsEvent>::new()));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let dump = snapshots.dump();
+    let redacted = snapshots.redact().dump();
+    handle.shutdown();
+    let _ = sensor_task.await;
+
+    let n = &dump["components"]["nettools"];
+    for key in [
+        "pulse_ms",
+        "checks",
+        "hostname",
+        "routes",
+        "nethealth_targets",
+        "nethealth",
+        "sockets",
+        "neighbours",
+        "route_lookup_targets",
+        "throughput_interfaces",
+        "wifi_interfaces",
+    ] {
+        assert!(n.get(key).is_some(), "missing {key} in {n}");
+    }
+    assert_eq!(n["checks"], serde_json::json!(["hostname", "routes", "default_routes"]));
+    assert_eq!(n["hostname"], "alpha");
+    assert_eq!(n["routes"], 2);
+    assert_eq!(redacted["components"]["nettools"]["hostname"], omnitrace_core::debug::REDACTED);
+}
+
+#[test]
+fn every_mask_bit_has_one_topic() {
+    let mut seen = 0;
+    for t in NetToolsEvent::TOPICS {
+        assert_eq!(t.mask.count_ones(), 1, "{}", t.name);
+        assert_eq!(seen & t.mask, 0, "{} shares its bit", t.name);
+        seen |= t.mask;
+    }
+    assert_eq!(seen, NetToolsMask::all().bits());
+
+    let ev = NetToolsEvent::HostnameChanged { old: "a".into(), new: "b".into() };
+    assert_eq!(ev.topic(), "net.hostname.changed");
+    assert_eq!(NetToolsEvent::TOPICS[ev.topic_index()].mask, ev.mask().bits());
+    let routes = NetToolsMask::ROUTE_ADDED
+        | NetToolsMask::ROUTE_REMOVED
+        | NetToolsMask::ROUTE_CHANGED
+        | NetToolsMask::DEFAULT_ROUTE_ADDED
+        | NetToolsMask::DEFAULT_ROUTE_REMOVED
+        | NetToolsMask::DEFAULT_ROUTE_CHANGED;
+    assert_eq!(topics::mask::<NetToolsEvent>("net.route").unwrap(), routes.bits());
+}
@@ -0,0 +1,6 @@
+//! `use nettools::prelude::*;` brings in the NetTools sensor, its config and events,
+//! and the core prelude (callbacks, hub, `spawn_sensor`).
+
+pub use crate::events::{NetToolsEvent, NetToolsMask};
+pub use crate::{NetTools, NetToolsConfig};
+pub use omnitrace_core::prelude::*;
@@ -199,7 +199,7 @@ impl NetTools {
             .collect()
     }
 
-    pub(crate) async fn handle_route_poll(&mut self, hub: &CallbackHub<events::NetToolsEvent>) {
+    pub(crate) async fn handle_route_poll<R: Send + 'static>(&mut self, hub: &CallbackHub<events::NetToolsEvent, R>) {
         let cur = match self.poll_routes() {
             Ok(cur) => Self::route_map(cur),
             Err(err) => {
@@ -118,7 +118,7 @@ impl NetTools {
         self.socket_backend.list()
     }
 
-    pub(crate) async fn handle_socket_poll(&mut self, hub: &CallbackHub<events::NetToolsEvent>) {
+    pub(crate) async fn handle_socket_poll<R: Send + 'static>(&mut self, hub: &CallbackHub<events::NetToolsEvent, R>) {
         let cur = match self.poll_sockets() {
             Ok(cur) => cur,
             Err(err) => {
@@ -118,7 +118,7 @@ impl NetTools {
         })
     }
 
-    pub(crate) async fn handle_throughput_poll(&mut self, hub: &CallbackHub<events::NetToolsEvent>) {
+    pub(crate) async fn handle_throughput_poll<R: Send + 'static>(&mut self, hub: &CallbackHub<events::NetToolsEvent, R>) {
         let cur = match self.poll_throughput() {
             Ok(counters) => ThroughputState {
                 at: Instant::now(),
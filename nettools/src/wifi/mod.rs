@@ -106,7 +106,7 @@ impl NetTools {
         self.wifi_backend.list()
     }
 
-    pub(crate) async fn handle_wifi_poll(&mut self, hub: &CallbackHub<events::NetToolsEvent>) {
+    pub(crate) async fn handle_wifi_poll<R: Send + 'static>(&mut self, hub: &CallbackHub<events::NetToolsEvent, R>) {
         let cur = match self.poll_wifi() {
             Ok(cur) => cur,
             Err(err) => {
@@ -0,0 +1,23 @@
+use omnitrace_core::{
+    filter::Filter,
+    preflight::{self, PreflightArgs},
+};
+use procdog::demo::{self, DemoConfig};
+use procdog::prelude::*;
+
+// PROCDOG_LIVE=1 PROCDOG_WATCH=sshd cargo run -p procdog --example procdog
+#[tokio::main]
+async fn main() {
+    let dog = demo::sensor(&DemoConfig::from_env());
+    preflight::gate("procdog", &dog, PreflightArgs::from_env_args()).await;
+
+    let mut hub = CallbackHub::<ProcDogEvent>::new();
+    if let Some(f) = Filter::from_env_args() {
+        hub.set_filter(f.predicate());
+    }
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+        println!("\nShutting down on Ctrl-C...");
+    };
+    demo::run(dog, hub, ctrl_c).await;
+}
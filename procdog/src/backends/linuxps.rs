@@ -1,20 +1,51 @@
-use std::{io, path::Path};
+use crate::{
+    ProcBackend,
+    groups::{ProcInfo, parse_stat},
+};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
 use tokio::fs;
 
-pub struct LinuxPsBackend;
+/// Reads `/proc`, or a tree laid out like it (see [`LinuxPsBackend::at`]).
+pub struct LinuxPsBackend {
+    root: PathBuf,
+}
+
+impl Default for LinuxPsBackend {
+    fn default() -> Self {
+        Self::at("/proc")
+    }
+}
 
 impl LinuxPsBackend {
     pub fn available() -> bool {
         Path::new("/proc").is_dir()
     }
+
+    /// Backend reading `<root>/<pid>/comm`, `stat` and `environ` instead of `/proc`.
+    pub fn at<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+/// `KEY=VALUE` pairs of a NUL-separated environ block, in order. Entries without `=` are skipped.
+pub fn parse_environ(raw: &[u8]) -> Vec<(String, String)> {
+    raw.split(|b| *b == 0)
+        .filter_map(|entry| {
+            let eq = entry.iter().position(|b| *b == b'=')?;
+            Some((String::from_utf8_lossy(&entry[..eq]).into_owned(), String::from_utf8_lossy(&entry[eq + 1..]).into_owned()))
+        })
+        .collect()
 }
 
 #[async_trait::async_trait]
-impl crate::ProcBackend for LinuxPsBackend {
+impl ProcBackend for LinuxPsBackend {
     async fn list(&self) -> io::Result<Vec<(i32, String)>> {
         let mut out = Vec::new();
 
-        let mut rd = fs::read_dir("/proc").await?;
+        let mut rd = fs::read_dir(&self.root).await?;
         while let Some(ent) = rd.next_entry().await? {
             let name = ent.file_name();
             let name = name.to_string_lossy();
@@ -25,8 +56,7 @@ impl crate::ProcBackend for LinuxPsBackend {
             };
 
             // /proc/<pid>/comm is short + stable (not cmdline)
-            let comm_path = format!("/proc/{}/comm", pid);
-            let Ok(comm) = fs::read_to_string(comm_path).await else {
+            let Ok(comm) = fs::read_to_string(self.root.join(pid.to_string()).join("comm")).await else {
                 continue;
             };
 
@@ -40,4 +70,22 @@ impl crate::ProcBackend for LinuxPsBackend {
 
         Ok(out)
     }
+
+    async fn list_info(&self) -> io::Result<Vec<ProcInfo>> {
+        let mut out = Vec::new();
+        for (pid, name) in self.list().await? {
+            let Ok(stat) = fs::read_to_string(self.root.join(pid.to_string()).join("stat")).await else {
+                continue;
+            };
+            if let Some((pgid, sid)) = parse_stat(&stat) {
+                out.push(ProcInfo { pid, name, pgid, sid });
+            }
+        }
+        Ok(out)
+    }
+
+    async fn environ(&self, pid: i32) -> io::Result<Vec<(String, String)>> {
+        // only readable by the owner (and root); EACCES for everyone else
+        Ok(parse_environ(&fs::read(self.root.join(pid.to_string()).join("environ")).await?))
+    }
 }
@@ -3,4 +3,6 @@ pub mod linuxps;
 #[cfg(target_os = "netbsd")]
 pub mod netbsd_sysctl;
 
+pub mod script;
+pub mod shared;
 pub mod stps;
@@ -22,15 +22,7 @@ impl crate::ProcBackend for NetBsdSysctlBackend {
 
             // Probe required size
             let mut size: libc::size_t = 0;
-            if libc::sysctl(
-                mib.as_mut_ptr(),
-                mib.len() as libc::c_uint,
-                std::ptr::null_mut(),
-                &mut size as *mut _,
-                std::ptr::null_mut(),
-                0,
-            ) != 0
-            {
+            if libc::sysctl(mib.as_mut_ptr(), mib.len() as libc::c_uint, std::ptr::null_mut(), &mut size as *mut _, std::ptr::null_mut(), 0) != 0 {
                 return Err(io::Error::last_os_error());
             }
 
@@ -95,11 +87,9 @@ impl crate::ProcBackend for NetBsdSysctlBackend {
 
                 let pid = kp.p_pid as i32;
 
-                let comm_ptr = kp.p_comm.as_ptr() as *const libc::c_char;
-                let comm = std::ffi::CStr::from_ptr(comm_ptr)
-                    .to_string_lossy()
-                    .trim()
-                    .to_string();
+                // as bytes and within the field: c_char is signed on some targets only
+                let comm = std::slice::from_raw_parts(kp.p_comm.as_ptr().cast::<u8>(), kp.p_comm.len());
+                let comm = std::ffi::CStr::from_bytes_until_nul(comm).map(|c| c.to_string_lossy().trim().to_string()).unwrap_or_default();
 
                 if !comm.is_empty() {
                     out.push((pid, comm));
@@ -0,0 +1,41 @@
+//! A backend that plays back process lists instead of reading the system, for examples
+//! and tests.
+
+use crate::ProcBackend;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Returns the scripted lists one per poll, then repeats the last one (or starts over,
+/// with [`ScriptBackend::cycle`]).
+pub struct ScriptBackend {
+    script: Vec<Vec<(i32, String)>>,
+    polls: AtomicUsize,
+    cycle: bool,
+}
+
+impl ScriptBackend {
+    pub fn new(script: Vec<Vec<(i32, String)>>) -> Self {
+        Self { script, polls: AtomicUsize::new(0), cycle: false }
+    }
+
+    pub fn cycle(mut self, on: bool) -> Self {
+        self.cycle = on;
+        self
+    }
+
+    /// Polls answered so far.
+    pub fn polls(&self) -> usize {
+        self.polls.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait::async_trait]
+impl ProcBackend for ScriptBackend {
+    async fn list(&self) -> std::io::Result<Vec<(i32, String)>> {
+        let n = self.polls.fetch_add(1, Ordering::SeqCst);
+        if self.script.is_empty() {
+            return Ok(Vec::new());
+        }
+        let i = if self.cycle { n % self.script.len() } else { n.min(self.script.len() - 1) };
+        Ok(self.script[i].clone())
+    }
+}
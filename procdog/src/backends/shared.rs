@@ -0,0 +1,48 @@
+//! A backend several ProcDog instances share, listing processes once per tick for all of
+//! them, see [`omnitrace_core::shared`].
+
+use crate::{ProcBackend, groups::ProcInfo};
+use omnitrace_core::shared::SharedSource;
+use std::{sync::Arc, time::Duration};
+
+/// Wraps a backend so instances polling every `base` (or a multiple of it) share its
+/// listings. Environments are read per instance, they are asked for per process, and so are
+/// listings with process groups.
+///
+/// ```ignore
+/// let ps = SharedBackend::new(LinuxPsBackend, Duration::from_secs(1));
+/// security.set_backend(ps.clone()); // interval 1s
+/// metrics.set_backend(ps.clone()); // interval 10s, lists for free
+/// ```
+#[derive(Clone)]
+pub struct SharedBackend {
+    inner: Arc<dyn ProcBackend>,
+    source: SharedSource<Arc<Vec<(i32, String)>>>,
+}
+
+impl SharedBackend {
+    pub fn new<B: ProcBackend + 'static>(backend: B, base: Duration) -> Self {
+        Self { inner: Arc::new(backend), source: SharedSource::new(base) }
+    }
+
+    /// The listings' cache, for its read and sharing counts.
+    pub fn source(&self) -> &SharedSource<Arc<Vec<(i32, String)>>> {
+        &self.source
+    }
+}
+
+#[async_trait::async_trait]
+impl ProcBackend for SharedBackend {
+    async fn list(&self) -> std::io::Result<Vec<(i32, String)>> {
+        let listing = self.source.get(|| async { self.inner.list().await.map(Arc::new) }).await?;
+        Ok(listing.as_ref().clone())
+    }
+
+    async fn environ(&self, pid: i32) -> std::io::Result<Vec<(String, String)>> {
+        self.inner.environ(pid).await
+    }
+
+    async fn list_info(&self) -> std::io::Result<Vec<ProcInfo>> {
+        self.inner.list_info().await
+    }
+}
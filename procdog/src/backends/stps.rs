@@ -6,10 +6,7 @@ impl ProcBackend for PsBackend {
     async fn list(&self) -> std::io::Result<Vec<(i32, String)>> {
         use tokio::process::Command;
 
-        let out = Command::new("ps")
-            .args(["-ax", "-o", "pid=", "-o", "comm="])
-            .output()
-            .await?;
+        let out = Command::new("ps").args(["-ax", "-o", "pid=", "-o", "comm="]).output().await?;
 
         let stdout = String::from_utf8_lossy(&out.stdout);
         let mut result = Vec::new();
@@ -17,9 +14,10 @@ impl ProcBackend for PsBackend {
         for line in stdout.lines().skip(1) {
             let mut parts = line.split_whitespace();
             if let (Some(pid), Some(name)) = (parts.next(), parts.next())
-                && let Ok(pid) = pid.parse::<i32>() {
-                    result.push((pid, name.to_string()));
-                }
+                && let Ok(pid) = pid.parse::<i32>()
+            {
+                result.push((pid, name.to_string()));
+            }
         }
 
         Ok(result)
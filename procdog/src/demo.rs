@@ -0,0 +1,100 @@
+//! The wiring of `examples/procdog.rs` as functions a program or a test can call: the
+//! sensor configured from the environment, a callback printing events, and [`run`].
+//!
+//! - `PROCDOG_WATCH`: process names to watch, comma separated (default: `perl`)
+//! - `PROCDOG_LIVE`: `1` lists the processes of this machine; by default a
+//!   [`ScriptBackend`] starts and stops a `perl` every few polls
+//! - `PROCDOG_INTERVAL_MS`: the poll interval (default: 1000)
+
+use crate::backends::script::ScriptBackend;
+use crate::events::{ProcDogEvent, ProcDogMask};
+use crate::{ProcDog, ProcDogConfig};
+use async_trait::async_trait;
+use omnitrace_core::{
+    callbacks::{Callback, CallbackHub, CallbackResult},
+    format::Style,
+    sensor,
+};
+use std::{future::Future, time::Duration};
+
+/// What the example watches and where it looks, see the [module docs](self).
+#[derive(Clone, Debug)]
+pub struct DemoConfig {
+    pub watch: Vec<String>,
+    pub live: bool,
+    pub interval: Duration,
+}
+
+impl Default for DemoConfig {
+    fn default() -> Self {
+        Self { watch: vec!["perl".to_string()], live: false, interval: Duration::from_secs(1) }
+    }
+}
+
+impl DemoConfig {
+    /// The defaults, overridden by the variables that are set.
+    pub fn from_env() -> Self {
+        let var = |name| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let mut cfg = Self::default();
+        if let Some(w) = var("PROCDOG_WATCH") {
+            cfg.watch = w.split(',').map(str::trim).filter(|n| !n.is_empty()).map(str::to_string).collect();
+        }
+        if let Some(l) = var("PROCDOG_LIVE") {
+            cfg.live = l == "1";
+        }
+        if let Some(ms) = var("PROCDOG_INTERVAL_MS").and_then(|v| v.parse().ok()) {
+            cfg.interval = Duration::from_millis(ms);
+        }
+        cfg
+    }
+}
+
+/// Polls of the scripted backend: `perl` is missing, runs for two polls, exits, repeat.
+pub fn script() -> ScriptBackend {
+    let perl = vec![(1, "init".to_string()), (4242, "perl".to_string())];
+    let idle = vec![(1, "init".to_string())];
+    ScriptBackend::new(vec![idle.clone(), perl.clone(), perl, idle]).cycle(true)
+}
+
+/// The sensor as configured, with the platform backend when `live`.
+pub fn sensor(cfg: &DemoConfig) -> ProcDog {
+    let mut dog = ProcDog::new(Some(ProcDogConfig::default().interval(cfg.interval).emit_on_start(true)));
+    if !cfg.live {
+        dog.set_backend(script());
+    } else {
+        #[cfg(target_os = "linux")]
+        dog.set_backend(crate::backends::linuxps::LinuxPsBackend::default());
+
+        #[cfg(target_os = "netbsd")]
+        dog.set_backend(crate::backends::netbsd_sysctl::NetBsdSysctlBackend);
+
+        #[cfg(all(not(target_os = "linux"), not(target_os = "netbsd")))]
+        dog.set_backend(crate::backends::stps::PsBackend);
+    }
+    for name in &cfg.watch {
+        dog.watch(name.as_str());
+    }
+    dog
+}
+
+/// Prints every event, see [`omnitrace_core::format`].
+pub struct PrintCb;
+
+#[async_trait]
+impl Callback<ProcDogEvent> for PrintCb {
+    fn mask(&self) -> u64 {
+        (ProcDogMask::APPEARED | ProcDogMask::MISSING | ProcDogMask::DISAPPEARED).bits()
+    }
+
+    async fn call(&self, ev: &ProcDogEvent) -> Option<CallbackResult> {
+        println!("{}", Style::for_stdout().short(ev));
+        None
+    }
+}
+
+/// Run `sensor` with [`PrintCb`] added to `hub`, printing the results, until `stop`
+/// resolves or the sensor ends, see [`sensor::run_until`].
+pub async fn run<F: Future>(sensor: ProcDog, mut hub: CallbackHub<ProcDogEvent>, stop: F) -> bool {
+    hub.add(PrintCb);
+    sensor::run_until(sensor, hub, stop, |r| println!("RESULT: {r}")).await
+}
@@ -0,0 +1,63 @@
+use crate::{
+    ProcBackend,
+    backends::script::ScriptBackend,
+    demo::{self, DemoConfig},
+    events::{ProcDogEvent, ProcDogMask},
+};
+use async_trait::async_trait;
+use omnitrace_core::callbacks::{Callback, CallbackHub, CallbackResult};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+struct Recorder(Arc<Mutex<Vec<ProcDogEvent>>>);
+
+#[async_trait]
+impl Callback<ProcDogEvent> for Recorder {
+    fn mask(&self) -> u64 {
+        ProcDogMask::all().bits()
+    }
+
+    async fn call(&self, ev: &ProcDogEvent) -> Option<CallbackResult> {
+        self.0.lock().unwrap().push(ev.clone());
+        None
+    }
+}
+
+#[tokio::test]
+async fn script_backend_repeats_the_last_list_or_cycles() {
+    let once = ScriptBackend::new(vec![vec![(1, "a".to_string())], vec![]]);
+    let looped = ScriptBackend::new(vec![vec![(1, "a".to_string())], vec![]]).cycle(true);
+    let mut lens = Vec::new();
+    for _ in 0..3 {
+        lens.push((once.list().await.unwrap().len(), looped.list().await.unwrap().len()));
+    }
+    assert_eq!(lens, vec![(1, 1), (0, 0), (0, 1)]);
+    assert_eq!(once.polls(), 3);
+}
+
+#[tokio::test]
+async fn example_wiring_reports_the_scripted_process_and_stops_in_time() {
+    let cfg = DemoConfig { interval: Duration::from_millis(10), ..DemoConfig::default() };
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(seen.clone()));
+
+    let stop = tokio::time::sleep(Duration::from_millis(120));
+    let stopped = tokio::time::timeout(Duration::from_secs(5), demo::run(demo::sensor(&cfg), hub, stop)).await;
+
+    assert!(stopped.expect("shut down in time"));
+    let seen = seen.lock().unwrap();
+    let kinds: Vec<&str> = seen
+        .iter()
+        .take(3)
+        .map(|ev| match ev {
+            ProcDogEvent::Missing { .. } => "missing",
+            ProcDogEvent::Appeared { pid: 4242, .. } => "appeared",
+            ProcDogEvent::Disappeared { pid: 4242, .. } => "disappeared",
+            other => panic!("unexpected {other:?}"),
+        })
+        .collect();
+    assert_eq!(kinds, vec!["missing", "appeared", "disappeared"], "{seen:?}");
+}
@@ -0,0 +1,162 @@
+//! Process change detection without a runtime: list processes, match them against the
+//! watched names and diff the PIDs against the last listing.
+//!
+//! [`crate::ProcDog`] runs this on every poll, after narrowing the matches down by
+//! environment. A program without tokio (built with `default-features = false`) drives a
+//! [`ProcDiffer`] from its own loop instead and gets the same `Appeared` and `Disappeared`
+//! events (without captured environments):
+//!
+//! ```ignore
+//! let mut differ = ProcDiffer::new();
+//! differ.watch("sshd");
+//! loop {
+//!     for ev in differ.feed(&engine::list_proc(Path::new("/proc"))?) {
+//!         println!("{} {}", ev.topic(), ev.name());
+//!     }
+//!     std::thread::sleep(Duration::from_secs(1));
+//! }
+//! ```
+
+use crate::events::ProcDogEvent;
+use omnitrace_core::labels::Labels;
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    path::Path,
+};
+
+/// Diffs successive process listings, see the [module docs](self).
+#[derive(Default)]
+pub struct ProcDiffer {
+    pub(crate) watched: HashSet<String>,
+    pub(crate) ignored: HashSet<String>,
+    // names watched since the last listing
+    added: HashSet<String>,
+    // name -> active PIDs, and whether that is a baseline to diff against yet
+    pub(crate) state: HashMap<String, HashSet<i32>>,
+    primed: bool,
+}
+
+impl ProcDiffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Report processes named `name`. Takes effect with the next listing, without events for
+    /// the processes running then.
+    pub fn watch<S: Into<String>>(&mut self, name: S) {
+        let name = name.into();
+        if self.watched.insert(name.clone()) {
+            self.added.insert(name);
+        }
+    }
+
+    /// Stop reporting `name`; its PIDs are dropped without events.
+    pub fn unwatch(&mut self, name: &str) {
+        self.watched.remove(name);
+        self.added.remove(name);
+        self.state.remove(name);
+    }
+
+    pub fn ignore<S: Into<String>>(&mut self, name: S) {
+        self.ignored.insert(name.into());
+    }
+
+    /// PIDs currently tracked for `name`.
+    pub fn pids(&self, name: &str) -> HashSet<i32> {
+        self.state.get(name).cloned().unwrap_or_default()
+    }
+
+    /// The events for the processes going from the last listing fed to `procs`, as `(pid,
+    /// name)` like [`list_proc`] returns them. The first listing is the baseline and yields none.
+    pub fn feed(&mut self, procs: &[(i32, String)]) -> Vec<ProcDogEvent> {
+        let mut matched = self.matching(procs);
+        if !self.primed {
+            self.state = matched;
+            self.primed = true;
+            self.added.clear();
+            return Vec::new();
+        }
+        for name in self.added.drain() {
+            self.state.insert(name.clone(), matched.get(&name).cloned().unwrap_or_default());
+        }
+        self.changes(&mut matched)
+    }
+
+    /// PIDs per watched, not ignored name.
+    pub(crate) fn matching(&self, procs: &[(i32, String)]) -> HashMap<String, HashSet<i32>> {
+        let mut out: HashMap<String, HashSet<i32>> =
+            self.watched.iter().filter(|n| !self.ignored.contains(*n)).map(|n| (n.clone(), HashSet::new())).collect();
+        for (pid, name) in procs {
+            if let Some(pids) = out.get_mut(name) {
+                pids.insert(*pid);
+            }
+        }
+        out
+    }
+
+    /// Appeared and Disappeared for the `matched` PIDs against the state, by name, taking them
+    /// as the new state. Appeared events carry no environment.
+    pub(crate) fn changes(&mut self, matched: &mut HashMap<String, HashSet<i32>>) -> Vec<ProcDogEvent> {
+        let mut names: Vec<&String> = self.watched.iter().filter(|n| !self.ignored.contains(*n)).collect();
+        names.sort();
+        let mut out = Vec::new();
+        for name in names {
+            let current = matched.remove(name).unwrap_or_default();
+            let previous = self.state.get(name).cloned().unwrap_or_default();
+            let (appeared, disappeared) = pid_changes(&previous, &current);
+            out.extend(appeared.into_iter().map(|pid| ProcDogEvent::Appeared {
+                name: name.clone(),
+                pid,
+                group_leader: None,
+                env: None,
+                labels: Labels::default(),
+            }));
+            out.extend(disappeared.into_iter().map(|pid| ProcDogEvent::Disappeared {
+                name: name.clone(),
+                pid,
+                group_leader: None,
+                labels: Labels::default(),
+            }));
+            self.state.insert(name.clone(), current);
+        }
+        out
+    }
+}
+
+/// PIDs that appeared and disappeared between two listings, each sorted.
+pub(crate) fn pid_changes(previous: &HashSet<i32>, current: &HashSet<i32>) -> (Vec<i32>, Vec<i32>) {
+    let sorted = |mut v: Vec<i32>| {
+        v.sort_unstable();
+        v
+    };
+    (sorted(current.difference(previous).copied().collect()), sorted(previous.difference(current).copied().collect()))
+}
+
+/// `(pid, comm)` of the processes under `root`, laid out like `/proc`. Processes that exit
+/// while listed, or have an empty `comm`, are left out.
+pub fn list_proc(root: &Path) -> io::Result<Vec<(i32, String)>> {
+    let mut out = Vec::new();
+    for ent in std::fs::read_dir(root)? {
+        let ent = ent?;
+        let name = ent.file_name();
+
+        // /proc/<pid>
+        let Ok(pid) = name.to_string_lossy().parse::<i32>() else {
+            continue;
+        };
+
+        // /proc/<pid>/comm is short + stable (not cmdline)
+        let Ok(comm) = std::fs::read_to_string(root.join(pid.to_string()).join("comm")) else {
+            continue;
+        };
+
+        let comm = comm.trim().to_string();
+        if comm.is_empty() {
+            continue;
+        }
+
+        out.push((pid, comm));
+    }
+    Ok(out)
+}
@@ -0,0 +1,133 @@
+use crate::{
+    engine::{self, ProcDiffer},
+    events::ProcDogEvent,
+};
+use std::collections::HashSet;
+
+pub(crate) const NAMES: [&str; 3] = ["nginx", "postgres", "sshd"];
+
+pub(crate) type Snapshot = Vec<(i32, String)>;
+
+pub(crate) fn random_snapshot(rng: &mut fastrand::Rng) -> Snapshot {
+    let mut out = Vec::new();
+    for name in NAMES {
+        for pid in 100..104 {
+            if rng.bool() {
+                out.push((pid + 10 * name.len() as i32, name.to_string()));
+            }
+        }
+    }
+    rng.shuffle(&mut out);
+    out
+}
+
+fn pids(s: &Snapshot) -> HashSet<(String, i32)> {
+    s.iter().map(|(pid, name)| (name.clone(), *pid)).collect()
+}
+
+/// Appeared/Disappeared as (name, pid, appeared) triples, order-insensitive.
+pub(crate) fn expected(prev: &Snapshot, now: &Snapshot) -> HashSet<(String, i32, bool)> {
+    let (prev, now) = (pids(prev), pids(now));
+    let appeared = now.difference(&prev).map(|(n, p)| (n.clone(), *p, true));
+    let disappeared = prev.difference(&now).map(|(n, p)| (n.clone(), *p, false));
+    appeared.chain(disappeared).collect()
+}
+
+/// A `/proc`-like tree under the temp dir: `(pid, comm, environ)`, `None` leaving environ out.
+pub(crate) fn fixture_proc(name: &str, procs: &[(i32, &str, Option<&[&str]>)]) -> std::path::PathBuf {
+    let root = std::env::temp_dir().join(format!("procdog-ut-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    for (pid, comm, environ) in procs {
+        let dir = root.join(pid.to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("comm"), format!("{comm}\n")).unwrap();
+        if let Some(vars) = environ {
+            let mut raw: Vec<u8> = vars.join("\0").into_bytes();
+            raw.push(0);
+            std::fs::write(dir.join("environ"), raw).unwrap();
+        }
+    }
+    root
+}
+
+/// The events as (name, pid, appeared) triples.
+fn triples(events: &[ProcDogEvent]) -> Vec<(String, i32, bool)> {
+    events
+        .iter()
+        .map(|ev| match ev {
+            ProcDogEvent::Appeared { name, pid, env, .. } => {
+                assert!(env.is_none());
+                (name.clone(), *pid, true)
+            }
+            ProcDogEvent::Disappeared { name, pid, .. } => (name.clone(), *pid, false),
+            other => panic!("unexpected {other:?}"),
+        })
+        .collect()
+}
+
+fn listing(procs: &[(i32, &str)]) -> Snapshot {
+    procs.iter().map(|(pid, name)| (*pid, name.to_string())).collect()
+}
+
+#[test]
+fn feed_diffs_each_listing_against_the_last() {
+    for seed in 0..20 {
+        let mut rng = fastrand::Rng::with_seed(seed);
+        let mut differ = ProcDiffer::new();
+        for name in NAMES {
+            differ.watch(name);
+        }
+        let mut prev = random_snapshot(&mut rng);
+        assert!(differ.feed(&prev).is_empty(), "seed {seed}: the baseline yields nothing");
+        for _ in 0..30 {
+            let now = random_snapshot(&mut rng);
+            let got = triples(&differ.feed(&now));
+            assert_eq!(got.iter().cloned().collect::<HashSet<_>>(), expected(&prev, &now), "seed {seed}");
+            assert_eq!(got.len(), expected(&prev, &now).len(), "seed {seed}: duplicates");
+            // by name, each name's Appeared before its Disappeared
+            let mut sorted = got.clone();
+            sorted.sort_by(|a, b| a.0.cmp(&b.0).then(b.2.cmp(&a.2)).then(a.1.cmp(&b.1)));
+            assert_eq!(got, sorted, "seed {seed}");
+            prev = now;
+        }
+    }
+}
+
+#[test]
+fn watch_and_unwatch_take_effect_quietly() {
+    let mut differ = ProcDiffer::new();
+    differ.watch("sshd");
+    differ.feed(&listing(&[(1, "sshd"), (2, "nginx")]));
+
+    // nginx running by the next listing is its baseline, no Appeared for it
+    differ.watch("nginx");
+    assert!(differ.feed(&listing(&[(1, "sshd"), (2, "nginx"), (3, "nginx")])).is_empty());
+    assert_eq!(differ.pids("nginx"), HashSet::from([2, 3]));
+    let got = triples(&differ.feed(&listing(&[(1, "sshd"), (3, "nginx"), (4, "nginx")])));
+    assert_eq!(got, [("nginx".to_string(), 4, true), ("nginx".to_string(), 2, false)]);
+
+    differ.unwatch("sshd");
+    assert!(differ.pids("sshd").is_empty());
+    assert!(differ.feed(&listing(&[(3, "nginx"), (4, "nginx")])).is_empty());
+}
+
+#[test]
+fn ignored_names_are_not_reported() {
+    let mut differ = ProcDiffer::new();
+    differ.watch("sshd");
+    differ.watch("cron");
+    differ.ignore("cron");
+    differ.feed(&[]);
+    let got = triples(&differ.feed(&listing(&[(1, "sshd"), (2, "cron")])));
+    assert_eq!(got, [("sshd".to_string(), 1, true)]);
+}
+
+#[test]
+fn lists_a_proc_tree() {
+    let root = fixture_proc("engine-list", &[(1, "init", None), (42, "sshd", None), (43, "", None)]);
+    std::fs::create_dir_all(root.join("self")).unwrap();
+    let mut procs = engine::list_proc(&root).unwrap();
+    procs.sort();
+    assert_eq!(procs, listing(&[(1, "init"), (42, "sshd")]));
+    let _ = std::fs::remove_dir_all(root);
+}
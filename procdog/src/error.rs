@@ -0,0 +1,23 @@
+use omnitrace_core::error::SensorError;
+use std::io;
+
+/// What ProcDog runs past, recorded in [`crate::ProcDog::diagnostics`].
+#[derive(Debug, thiserror::Error)]
+pub enum ProcDogError {
+    /// The poll is skipped and the tracked PIDs stay as they were.
+    #[error("cannot list processes: {0}")]
+    List(#[source] io::Error),
+    /// The process is tracked as if it had no environment.
+    #[error("cannot read the environment of pid {pid}: {source}")]
+    Environ {
+        pid: i32,
+        #[source]
+        source: io::Error,
+    },
+}
+
+impl From<ProcDogError> for SensorError {
+    fn from(e: ProcDogError) -> Self {
+        SensorError::sensor("procdog", e)
+    }
+}
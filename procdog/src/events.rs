@@ -1,27 +1,208 @@
 use bitflags::bitflags;
+use omnitrace_core::{
+    expected::Deviation,
+    fields::{EventFields, FieldValue},
+    labels::Labels,
+    topics::{self, Topic, Topics},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ProcDogEvent {
-    Appeared { name: String, pid: i32 },
-    Disappeared { name: String, pid: i32 },
-    Missing { name: String },
+    Appeared {
+        name: String,
+        pid: i32,
+        /// The process group, for members of a group watched with
+        /// [`crate::ProcDog::watch_pgid_of`].
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        group_leader: Option<i32>,
+        /// Captured environment, when [`crate::ProcDog::capture_env`] names any keys.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        env: Option<ProcEnv>,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    Disappeared {
+        name: String,
+        pid: i32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        group_leader: Option<i32>,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    Missing {
+        name: String,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    /// The number of processes of `name` is not as declared, see [`crate::ProcDog::expect`].
+    /// Fired when the sensor primes, before any change. `pids` are those running.
+    Deviation {
+        name: String,
+        #[serde(flatten)]
+        deviation: Deviation,
+        pids: Vec<i32>,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    /// The leader and all members of process group `pgid`, watched with
+    /// [`crate::ProcDog::watch_pgid_of`], have exited.
+    GroupGone {
+        name: String,
+        pgid: i32,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+}
+
+/// Environment variables captured from a process when it appeared.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcEnv {
+    /// The captured keys the process has set, raw or hashed; unset keys are left out.
+    Vars(BTreeMap<String, String>),
+    /// The environ could not be read (another user's process, already gone, or a backend
+    /// without environ support), with the reason.
+    Unavailable(String),
 }
 
 bitflags! {
+    /// Kinds in the low 16 bits (see `omnitrace_core::callbacks::KIND_BITS`), no sub-kinds yet.
     #[derive(Copy, Clone, Debug)]
     pub struct ProcDogMask: u64 {
         const APPEARED    = 0b0001;
         const DISAPPEARED = 0b0010;
         const MISSING     = 0b0100;
+        const DEVIATION   = 0b1000;
+        const GROUP_GONE  = 0b1_0000;
     }
 }
 
 impl ProcDogEvent {
+    /// The watched name the event is about.
+    pub fn name(&self) -> &str {
+        match self {
+            ProcDogEvent::Appeared { name, .. }
+            | ProcDogEvent::Disappeared { name, .. }
+            | ProcDogEvent::Missing { name, .. }
+            | ProcDogEvent::Deviation { name, .. }
+            | ProcDogEvent::GroupGone { name, .. } => name,
+        }
+    }
+
+    /// Labels of the watched name, see [`crate::ProcDog::watch_labeled`].
+    pub fn labels(&self) -> &Labels {
+        match self {
+            ProcDogEvent::Appeared { labels, .. }
+            | ProcDogEvent::Disappeared { labels, .. }
+            | ProcDogEvent::Missing { labels, .. }
+            | ProcDogEvent::Deviation { labels, .. }
+            | ProcDogEvent::GroupGone { labels, .. } => labels,
+        }
+    }
+
+    #[cfg(feature = "runtime")]
+    pub(crate) fn labels_mut(&mut self) -> &mut Labels {
+        match self {
+            ProcDogEvent::Appeared { labels, .. }
+            | ProcDogEvent::Disappeared { labels, .. }
+            | ProcDogEvent::Missing { labels, .. }
+            | ProcDogEvent::Deviation { labels, .. }
+            | ProcDogEvent::GroupGone { labels, .. } => labels,
+        }
+    }
+
     pub fn mask(&self) -> ProcDogMask {
         match self {
             ProcDogEvent::Appeared { .. } => ProcDogMask::APPEARED,
             ProcDogEvent::Disappeared { .. } => ProcDogMask::DISAPPEARED,
             ProcDogEvent::Missing { .. } => ProcDogMask::MISSING,
+            ProcDogEvent::Deviation { .. } => ProcDogMask::DEVIATION,
+            ProcDogEvent::GroupGone { .. } => ProcDogMask::GROUP_GONE,
+        }
+    }
+
+    /// Dotted topic, see [`omnitrace_core::topics`]. `Deviation` is
+    /// `proc.deviation.<missing|unexpected|mismatch>`.
+    pub fn topic(&self) -> &'static str {
+        topics::topic_of(self)
+    }
+}
+
+impl Topics for ProcDogEvent {
+    const TOPICS: &'static [Topic] = &[
+        Topic::new("proc.appeared", ProcDogMask::APPEARED.bits()),
+        Topic::new("proc.disappeared", ProcDogMask::DISAPPEARED.bits()),
+        Topic::new("proc.missing", ProcDogMask::MISSING.bits()),
+        Topic::new("proc.deviation.missing", ProcDogMask::DEVIATION.bits()),
+        Topic::new("proc.deviation.unexpected", ProcDogMask::DEVIATION.bits()),
+        Topic::new("proc.deviation.mismatch", ProcDogMask::DEVIATION.bits()),
+        Topic::new("proc.group_gone", ProcDogMask::GROUP_GONE.bits()),
+    ];
+
+    fn topic_index(&self) -> usize {
+        match self {
+            ProcDogEvent::Appeared { .. } => 0,
+            ProcDogEvent::Disappeared { .. } => 1,
+            ProcDogEvent::Missing { .. } => 2,
+            ProcDogEvent::Deviation { deviation, .. } => match deviation {
+                Deviation::Missing => 3,
+                Deviation::Unexpected => 4,
+                Deviation::Mismatch { .. } => 5,
+            },
+            ProcDogEvent::GroupGone { .. } => 6,
+        }
+    }
+}
+
+/// `name` and `pid` (not in `Missing`), and the captured variables as `env.<VAR>`. Members of
+/// a watched process group have their `group_leader`, `GroupGone` the group's `pgid`.
+/// `Deviation` has its `deviation` (`missing`, `unexpected`, `mismatch`), the mismatched
+/// `field` with `expected` and `actual`.
+impl EventFields for ProcDogEvent {
+    fn kind(&self) -> &'static str {
+        match self {
+            ProcDogEvent::Appeared { .. } => "appeared",
+            ProcDogEvent::Disappeared { .. } => "disappeared",
+            ProcDogEvent::Missing { .. } => "missing",
+            ProcDogEvent::Deviation { .. } => "deviation",
+            ProcDogEvent::GroupGone { .. } => "group_gone",
         }
     }
+
+    fn field(&self, name: &str) -> Option<FieldValue<'_>> {
+        match (self, name) {
+            (_, "name") => Some(FieldValue::str(self.name())),
+            (ProcDogEvent::Appeared { pid, .. } | ProcDogEvent::Disappeared { pid, .. }, "pid") => Some(FieldValue::int(*pid)),
+            (ProcDogEvent::Appeared { group_leader, .. } | ProcDogEvent::Disappeared { group_leader, .. }, "group_leader") => {
+                group_leader.map(FieldValue::int)
+            }
+            (ProcDogEvent::GroupGone { pgid, .. }, "pgid") => Some(FieldValue::int(*pgid)),
+            (ProcDogEvent::Appeared { env: Some(ProcEnv::Vars(vars)), .. }, _) => {
+                name.strip_prefix("env.").and_then(|var| vars.get(var)).map(FieldValue::str)
+            }
+            (ProcDogEvent::Deviation { deviation, .. }, _) => match (name, deviation) {
+                ("deviation", d) => Some(FieldValue::str(d.name())),
+                ("field", Deviation::Mismatch { field, .. }) => Some(FieldValue::str(field)),
+                ("expected", Deviation::Mismatch { expected, .. }) => Some(FieldValue::str(expected)),
+                ("actual", Deviation::Mismatch { actual, .. }) => Some(FieldValue::str(actual)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn field_names(&self) -> &'static [&'static str] {
+        match self {
+            ProcDogEvent::Appeared { .. } | ProcDogEvent::Disappeared { .. } => &["name", "pid", "group_leader"],
+            ProcDogEvent::Missing { .. } => &["name"],
+            ProcDogEvent::GroupGone { .. } => &["name", "pgid"],
+            ProcDogEvent::Deviation { .. } => &["name", "deviation", "field", "expected", "actual"],
+        }
+    }
+
+    fn topic(&self) -> Option<&'static str> {
+        Some(ProcDogEvent::topic(self))
+    }
 }
@@ -0,0 +1,71 @@
+//! Processes declared by configuration management, compared when the sensor primes. See
+//! [`crate::ProcDog::expect`] and [`omnitrace_core::expected`].
+
+use omnitrace_core::expected::Deviation;
+use serde::{Deserialize, Serialize};
+
+/// How many processes named `name` should run: at least `min` (default 1), at most `max` if
+/// set. `max: 0` declares the name must not run at all.
+///
+/// ```json
+/// [
+///   { "name": "sshd" },
+///   { "name": "nginx", "min": 2, "max": 8 },
+///   { "name": "telnetd", "min": 0, "max": 0 }
+/// ]
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExpectedProcess {
+    pub name: String,
+    #[serde(default = "one")]
+    pub min: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<usize>,
+}
+
+fn one() -> usize {
+    1
+}
+
+impl ExpectedProcess {
+    /// At least one process.
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self { name: name.into(), min: 1, max: None }
+    }
+
+    /// No process at all.
+    pub fn absent<S: Into<String>>(name: S) -> Self {
+        Self { name: name.into(), min: 0, max: Some(0) }
+    }
+
+    pub fn min(mut self, n: usize) -> Self {
+        self.min = n;
+        self
+    }
+
+    pub fn max(mut self, n: usize) -> Self {
+        self.max = Some(n);
+        self
+    }
+
+    /// The declared count, e.g. `"2"`, `"at least 1"`, `"2 to 8"`.
+    fn count(&self) -> String {
+        match self.max {
+            Some(max) if max == self.min => max.to_string(),
+            Some(max) if self.min == 0 => format!("at most {max}"),
+            Some(max) => format!("{} to {max}", self.min),
+            None => format!("at least {}", self.min),
+        }
+    }
+
+    /// How `running` processes of the name differ: Missing if none runs but some should,
+    /// Unexpected if some run but none should, otherwise a `count` Mismatch if out of range.
+    pub(crate) fn deviation(&self, running: usize) -> Option<Deviation> {
+        match running {
+            0 if self.min > 0 => Some(Deviation::Missing),
+            n if n > 0 && self.max == Some(0) => Some(Deviation::Unexpected),
+            n if n < self.min || self.max.is_some_and(|max| n > max) => Some(Deviation::mismatch("count", self.count(), n.to_string())),
+            _ => None,
+        }
+    }
+}
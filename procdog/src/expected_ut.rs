@@ -0,0 +1,109 @@
+use crate::{
+    ProcDog, ProcDogConfig,
+    backends::script::ScriptBackend,
+    events::{ProcDogEvent, ProcDogMask},
+    expected::ExpectedProcess,
+};
+use async_trait::async_trait;
+use omnitrace_core::{
+    callbacks::{Callback, CallbackHub, CallbackResult},
+    expected::{self, Deviation},
+    sensor::spawn_sensor,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+struct Recorder(Arc<Mutex<Vec<ProcDogEvent>>>);
+
+#[async_trait]
+impl Callback<ProcDogEvent> for Recorder {
+    fn mask(&self) -> u64 {
+        ProcDogMask::all().bits()
+    }
+
+    async fn call(&self, ev: &ProcDogEvent) -> Option<CallbackResult> {
+        self.0.lock().unwrap().push(ev.clone());
+        None
+    }
+}
+
+fn list(procs: &[(i32, &str)]) -> Vec<(i32, String)> {
+    procs.iter().map(|(pid, name)| (*pid, name.to_string())).collect()
+}
+
+#[test]
+fn counts_map_to_deviation_categories() {
+    let sshd = ExpectedProcess::new("sshd");
+    assert_eq!(sshd.deviation(0), Some(Deviation::Missing));
+    assert_eq!(sshd.deviation(3), None);
+
+    let telnetd = ExpectedProcess::absent("telnetd");
+    assert_eq!(telnetd.deviation(0), None);
+    assert_eq!(telnetd.deviation(1), Some(Deviation::Unexpected));
+
+    let nginx = ExpectedProcess::new("nginx").min(2).max(4);
+    assert_eq!(nginx.deviation(0), Some(Deviation::Missing));
+    assert_eq!(nginx.deviation(1), Some(Deviation::mismatch("count", "2 to 4", "1")));
+    assert_eq!(nginx.deviation(3), None);
+    assert_eq!(nginx.deviation(5), Some(Deviation::mismatch("count", "2 to 4", "5")));
+    assert_eq!(ExpectedProcess::new("a").min(2).deviation(1), Some(Deviation::mismatch("count", "at least 2", "1")));
+    assert_eq!(ExpectedProcess::new("a").min(0).max(1).deviation(2), Some(Deviation::mismatch("count", "at most 1", "2")));
+    assert_eq!(ExpectedProcess::new("a").min(1).max(1).deviation(2), Some(Deviation::mismatch("count", "1", "2")));
+}
+
+#[test]
+fn specs_load_from_json() {
+    let path = std::env::temp_dir().join(format!("procdog-expected-ut-{}.json", std::process::id()));
+    std::fs::write(&path, r#"[{ "name": "sshd" }, { "name": "nginx", "min": 2, "max": 8 }, { "name": "telnetd", "min": 0, "max": 0 }]"#).unwrap();
+    let specs: Vec<ExpectedProcess> = expected::load(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(specs, vec![ExpectedProcess::new("sshd"), ExpectedProcess::new("nginx").min(2).max(8), ExpectedProcess::absent("telnetd")]);
+}
+
+#[tokio::test]
+async fn deviations_fire_on_prime_before_changes() {
+    let mut dog = ProcDog::new(Some(ProcDogConfig::default().interval(Duration::from_millis(10))));
+    dog.set_backend(ScriptBackend::new(vec![
+        list(&[(1, "init"), (23, "telnetd"), (80, "nginx"), (200, "cron")]),
+        list(&[(1, "init"), (80, "nginx"), (81, "nginx"), (22, "sshd"), (200, "cron")]),
+    ]));
+    dog.expect(vec![
+        ExpectedProcess::new("sshd"),
+        ExpectedProcess::new("nginx").min(2),
+        ExpectedProcess::absent("telnetd"),
+        ExpectedProcess::new("cron").max(1),
+    ]);
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(events.clone()));
+    let (handle, task) = spawn_sensor(dog, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    handle.shutdown();
+    let _ = task.await;
+
+    let events = events.lock().unwrap();
+    let deviations: Vec<_> = events[..3]
+        .iter()
+        .map(|ev| match ev {
+            ProcDogEvent::Deviation { name, deviation, pids, .. } => (name.as_str(), deviation.clone(), pids.clone()),
+            other => panic!("{other:?} before the deviations"),
+        })
+        .collect();
+    assert_eq!(
+        deviations,
+        vec![
+            ("sshd", Deviation::Missing, vec![]),
+            ("nginx", Deviation::mismatch("count", "at least 2", "1"), vec![80]),
+            ("telnetd", Deviation::Unexpected, vec![23]),
+        ]
+    );
+    assert_eq!(events[1].topic(), "proc.deviation.mismatch");
+
+    // then the live loop, with the declared names watched
+    let mut rest: Vec<_> = events[3..].iter().map(|ev| (ev.topic(), ev.name().to_string())).collect();
+    rest.sort();
+    assert_eq!(rest, vec![("proc.appeared", "nginx".into()), ("proc.appeared", "sshd".into()), ("proc.disappeared", "telnetd".into())]);
+}
@@ -0,0 +1,129 @@
+//! Process groups of daemons that fork their workers into them (postgres, nginx, uwsgi),
+//! tracked by group instead of by the name of every worker.
+//!
+//! A [`GroupTracker`] resolves the group leaders by name on every listing, processes whose
+//! name matches its glob and whose PID is their process group ID, and tracks all members of
+//! their groups, whatever their names. A group stays tracked while it has members, also
+//! after its leader exited; a restarted leader is a new group. Members are reported as
+//! `Appeared` and `Disappeared` under the glob with the group's `group_leader`, and a group
+//! whose last process exited as `GroupGone`.
+
+use crate::{engine, events::ProcDogEvent};
+use globset::{Glob, GlobMatcher};
+use omnitrace_core::labels::Labels;
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    path::Path,
+};
+
+/// A listed process with its process group and session.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProcInfo {
+    pub pid: i32,
+    pub name: String,
+    /// Process group ID, the PID of the group's leader.
+    pub pgid: i32,
+    /// Session ID, the PID of the session's leader.
+    pub sid: i32,
+}
+
+impl ProcInfo {
+    pub fn new<S: Into<String>>(pid: i32, name: S, pgid: i32, sid: i32) -> Self {
+        Self { pid, name: name.into(), pgid, sid }
+    }
+}
+
+/// `(pgid, sid)` from the contents of `/proc/<pid>/stat`, fields 5 and 6. The name in
+/// field 2 may hold spaces and parentheses, the fields are counted after its last `)`.
+pub fn parse_stat(stat: &str) -> Option<(i32, i32)> {
+    let mut rest = stat[stat.rfind(')')? + 1..].split_whitespace().skip(2);
+    Some((rest.next()?.parse().ok()?, rest.next()?.parse().ok()?))
+}
+
+/// [`engine::list_proc`] with the process group and session of each process, from
+/// `<root>/<pid>/stat`.
+pub fn list_proc_info(root: &Path) -> io::Result<Vec<ProcInfo>> {
+    let mut out = Vec::new();
+    for (pid, name) in engine::list_proc(root)? {
+        let Ok(stat) = std::fs::read_to_string(root.join(pid.to_string()).join("stat")) else {
+            continue;
+        };
+        if let Some((pgid, sid)) = parse_stat(&stat) {
+            out.push(ProcInfo { pid, name, pgid, sid });
+        }
+    }
+    Ok(out)
+}
+
+/// Tracks the process groups whose leaders match a name glob, see the [module docs](self).
+pub struct GroupTracker {
+    pattern: String,
+    glob: GlobMatcher,
+    // pgid -> member PIDs, of the groups with members
+    groups: HashMap<i32, HashSet<i32>>,
+    primed: bool,
+}
+
+impl GroupTracker {
+    pub fn new(name_glob: &str) -> Result<Self, globset::Error> {
+        Ok(Self { pattern: name_glob.to_string(), glob: Glob::new(name_glob)?.compile_matcher(), groups: HashMap::new(), primed: false })
+    }
+
+    /// The glob, the `name` of the events.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Member PIDs per tracked process group.
+    pub fn groups(&self) -> &HashMap<i32, HashSet<i32>> {
+        &self.groups
+    }
+
+    /// The events for the groups going from the last listing fed to `procs`: per group by
+    /// pgid, its members' Appeared then Disappeared, each by PID, and GroupGone for a group
+    /// without members now. The first listing is the baseline and yields none.
+    pub fn feed(&mut self, procs: &[ProcInfo]) -> Vec<ProcDogEvent> {
+        let leaders = procs.iter().filter(|p| p.pid == p.pgid && self.glob.is_match(&p.name)).map(|p| p.pgid);
+        let mut current: HashMap<i32, HashSet<i32>> = leaders.chain(self.groups.keys().copied()).map(|pgid| (pgid, HashSet::new())).collect();
+        for p in procs {
+            if let Some(members) = current.get_mut(&p.pgid) {
+                members.insert(p.pid);
+            }
+        }
+        current.retain(|_, members| !members.is_empty());
+
+        let previous = std::mem::replace(&mut self.groups, current);
+        if !self.primed {
+            self.primed = true;
+            return Vec::new();
+        }
+
+        let mut pgids: Vec<i32> = previous.keys().chain(self.groups.keys()).copied().collect::<HashSet<_>>().into_iter().collect();
+        pgids.sort_unstable();
+        let none = HashSet::new();
+        let mut out = Vec::new();
+        for pgid in pgids {
+            let (before, now) = (previous.get(&pgid).unwrap_or(&none), self.groups.get(&pgid).unwrap_or(&none));
+            let (appeared, disappeared) = engine::pid_changes(before, now);
+            let name = &self.pattern;
+            out.extend(appeared.into_iter().map(|pid| ProcDogEvent::Appeared {
+                name: name.clone(),
+                pid,
+                group_leader: Some(pgid),
+                env: None,
+                labels: Labels::default(),
+            }));
+            out.extend(disappeared.into_iter().map(|pid| ProcDogEvent::Disappeared {
+                name: name.clone(),
+                pid,
+                group_leader: Some(pgid),
+                labels: Labels::default(),
+            }));
+            if now.is_empty() {
+                out.push(ProcDogEvent::GroupGone { name: name.clone(), pgid, labels: Labels::default() });
+            }
+        }
+        out
+    }
+}
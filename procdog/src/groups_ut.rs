@@ -0,0 +1,70 @@
+use crate::{
+    events::ProcDogEvent,
+    groups::{self, GroupTracker, ProcInfo},
+};
+
+fn pg(pid: i32, name: &str, pgid: i32) -> ProcInfo {
+    ProcInfo::new(pid, name, pgid, 1)
+}
+
+/// The events as (kind, pid or pgid, group leader).
+fn seen(events: &[ProcDogEvent]) -> Vec<(&'static str, i32, Option<i32>)> {
+    events
+        .iter()
+        .map(|ev| match ev {
+            ProcDogEvent::Appeared { pid, group_leader, .. } => ("appeared", *pid, *group_leader),
+            ProcDogEvent::Disappeared { pid, group_leader, .. } => ("disappeared", *pid, *group_leader),
+            ProcDogEvent::GroupGone { pgid, .. } => ("group_gone", *pgid, None),
+            other => panic!("unexpected {other:?}"),
+        })
+        .collect()
+}
+
+#[test]
+fn stat_fields_after_the_name() {
+    assert_eq!(groups::parse_stat("812 (sshd) S 1 812 812 0 -1 4194560 1234"), Some((812, 812)));
+    // names may hold spaces and parentheses
+    assert_eq!(groups::parse_stat("4711 (tmux: server (1)) S 1 4700 4699 0"), Some((4700, 4699)));
+    assert_eq!(groups::parse_stat("4711 (cut"), None);
+    assert_eq!(groups::parse_stat("4711 (x) S 1"), None);
+}
+
+#[test]
+fn proc_info_is_read_from_stat() {
+    let root = std::env::temp_dir().join(format!("procdog-ut-{}-groups", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    for (pid, comm, stat) in [(10, "nginx", "10 (nginx) S 1 10 10"), (11, "nginx", "11 (nginx) S 10 10 10"), (12, "gone", "")] {
+        let dir = root.join(pid.to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("comm"), format!("{comm}\n")).unwrap();
+        std::fs::write(dir.join("stat"), stat).unwrap();
+    }
+    let mut info = groups::list_proc_info(&root).unwrap();
+    info.sort_by_key(|p| p.pid);
+    assert_eq!(info, [ProcInfo::new(10, "nginx", 10, 10), ProcInfo::new(11, "nginx", 10, 10)]);
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn groups_outlive_their_leader_and_restarts_are_new_groups() {
+    let mut t = GroupTracker::new("uwsgi*").unwrap();
+    assert!(t.feed(&[pg(10, "uwsgi", 10), pg(11, "uwsgi", 10), pg(20, "uwsgi-worker", 10), pg(30, "uwsgi", 29)]).is_empty());
+    assert_eq!(t.groups().keys().collect::<Vec<_>>(), [&10]);
+
+    assert_eq!(seen(&t.feed(&[pg(11, "uwsgi", 10), pg(20, "uwsgi-worker", 10)])), [("disappeared", 10, Some(10))]);
+    // a process joining the group after its leader left is still a member
+    assert_eq!(seen(&t.feed(&[pg(11, "uwsgi", 10), pg(20, "uwsgi-worker", 10), pg(21, "sh", 10)])), [("appeared", 21, Some(10))]);
+    assert_eq!(
+        seen(&t.feed(&[pg(40, "uwsgi", 40)])),
+        [
+            ("disappeared", 11, Some(10)),
+            ("disappeared", 20, Some(10)),
+            ("disappeared", 21, Some(10)),
+            ("group_gone", 10, None),
+            ("appeared", 40, Some(40))
+        ]
+    );
+    assert!(t.feed(&[pg(40, "uwsgi", 40)]).is_empty());
+    assert!(matches!(&t.feed(&[])[..], [_, ProcDogEvent::GroupGone { name, pgid: 40, .. }] if name == "uwsgi*"));
+    assert!(t.groups().is_empty());
+}
@@ -1,41 +1,134 @@
+#[cfg(feature = "runtime")]
 pub mod backends;
+#[cfg(feature = "runtime")]
+pub mod demo;
+pub mod engine;
+pub mod error;
 pub mod events;
-
-use crate::events::ProcDogEvent;
+#[cfg(feature = "runtime")]
+pub mod expected;
+pub mod groups;
+#[cfg(feature = "runtime")]
+pub mod prelude;
+#[cfg(feature = "runtime")]
+pub mod preview;
+
+#[cfg(all(test, feature = "runtime"))]
+mod demo_ut;
+#[cfg(test)]
+mod engine_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod expected_ut;
+#[cfg(test)]
+mod groups_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod procdog_ut;
+
+#[cfg(feature = "runtime")]
+use crate::{
+    engine::ProcDiffer,
+    error::ProcDogError,
+    events::{ProcDogEvent, ProcEnv},
+    expected::ExpectedProcess,
+    groups::{GroupTracker, ProcInfo},
+    preview::ProcDogRule,
+};
+#[cfg(feature = "runtime")]
+use globset::{Glob, GlobMatcher};
+#[cfg(feature = "runtime")]
 use omnitrace_core::{
     callbacks::CallbackHub,
+    clock::{self, SharedClock},
+    debug::DebugCell,
+    degrade::{Profile, ProfileSwitch},
+    entities::{self, EntityCounters},
+    error::Diagnostics,
+    history::{History, HistoryConfig, HistoryHandle},
+    labels::Labels,
+    preflight::PreflightFinding,
+    preview::{PreviewHandle, PreviewQueue, PreviewReport},
+    pulse::{AdaptivePulse, EffectivePulse, Pacer},
     sensor::{Sensor, SensorCtx},
+    state::Published,
+    tombstones::{self, Tombstones},
 };
+#[cfg(feature = "runtime")]
+use serde::Serialize;
+#[cfg(feature = "runtime")]
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     future::Future,
     pin::Pin,
-    sync::Arc,
+    sync::{Arc, Mutex},
     time::Duration,
 };
 
+#[cfg(feature = "runtime")]
 #[async_trait::async_trait]
 pub trait ProcBackend: Send + Sync {
     async fn list(&self) -> std::io::Result<Vec<(i32, String)>>;
+
+    /// Environment of `pid` as `KEY`/`VALUE` pairs. Backends that cannot read it return
+    /// `Unsupported`.
+    async fn environ(&self, _pid: i32) -> std::io::Result<Vec<(String, String)>> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+
+    /// The listing with the process group and session of each process, used instead of
+    /// [`ProcBackend::list`] while process groups are watched. Backends that cannot read
+    /// them return `Unsupported`.
+    async fn list_info(&self) -> std::io::Result<Vec<ProcInfo>> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
 }
 
+#[cfg(feature = "runtime")]
 pub struct ProcDogConfig {
     interval: Duration,
     emit_missing_on_start: bool,
+    hash_env_values: bool,
+    profile: Option<ProfileSwitch>,
+    adaptive: Option<AdaptivePulse>,
+    unwatched_ttl: Option<Duration>,
+    history: Option<HistoryConfig>,
+    clock: SharedClock,
 }
 
+#[cfg(feature = "runtime")]
 impl Default for ProcDogConfig {
     fn default() -> Self {
-        Self { interval: Duration::from_secs(1), emit_missing_on_start: false }
+        Self {
+            interval: Duration::from_secs(1),
+            emit_missing_on_start: false,
+            hash_env_values: false,
+            profile: None,
+            adaptive: None,
+            unwatched_ttl: None,
+            history: None,
+            clock: clock::system(),
+        }
     }
 }
 
+#[cfg(feature = "runtime")]
 impl ProcDogConfig {
     pub fn interval(mut self, d: Duration) -> Self {
         self.interval = d;
         self
     }
 
+    /// Poll every `min` while processes come and go, backing off up to `max` while nothing
+    /// changes, instead of every `interval`. See [`omnitrace_core::pulse`].
+    pub fn adaptive(self, min: Duration, max: Duration) -> Self {
+        self.adaptive_pulse(AdaptivePulse::new(min, max))
+    }
+
+    /// [`ProcDogConfig::adaptive`] with its own backoff policy.
+    pub fn adaptive_pulse(mut self, policy: AdaptivePulse) -> Self {
+        self.adaptive = Some(policy);
+        self
+    }
+
     fn get_interval(&self) -> Duration {
         self.interval
     }
@@ -44,26 +137,201 @@ impl ProcDogConfig {
         self.emit_missing_on_start = on;
         self
     }
+
+    /// Report captured environment values as `blake3:<hex>` instead of in clear, so events
+    /// can tell values apart without carrying tokens or credentials. Short or guessable values
+    /// stay guessable from their hash.
+    pub fn hash_env_values(mut self, on: bool) -> Self {
+        self.hash_env_values = on;
+        self
+    }
+
+    /// Follow the switch of a [`omnitrace_core::degrade::DegradeController`], see
+    /// [`ProcDog::apply_profile`].
+    pub fn profile_switch(mut self, switch: ProfileSwitch) -> Self {
+        self.profile = Some(switch);
+        self
+    }
+
+    /// Keep the PIDs of a name unwatched through a [`ProcDogControl`] for `ttl`: watched again
+    /// within it, the processes that came and went meanwhile are reported, instead of taking the
+    /// PIDs then as the baseline. See [`omnitrace_core::tombstones`].
+    pub fn report_unwatched(mut self, ttl: Duration) -> Self {
+        self.unwatched_ttl = Some(ttl);
+        self
+    }
+
+    /// Keep the last events of each watched name and PID, see [`ProcDog::history`].
+    pub fn history(mut self, config: HistoryConfig) -> Self {
+        self.history = Some(config);
+        self
+    }
+
+    /// Read the time from `clock` instead of the system clock, see [`omnitrace_core::clock`].
+    pub fn clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn env_value(&self, v: &str) -> String {
+        if self.hash_env_values { format!("blake3:{}", blake3::hash(v.as_bytes()).to_hex()) } else { v.to_string() }
+    }
 }
 
-pub struct ProcDog {
-    watched: HashSet<String>,
-    ignored: HashSet<String>,
+/// Environment selector added by [`ProcDog::watch_env`].
+#[cfg(feature = "runtime")]
+struct EnvSelector {
+    key: String,
+    pattern: String,
+    glob: GlobMatcher,
+}
+
+/// What the environ of a tracked-name PID said, read once per PID.
+#[cfg(feature = "runtime")]
+struct EnvSeen {
+    selected: bool,
+    capture: Option<ProcEnv>,
+}
+
+/// Shared, read-only view of the PIDs ProcDog currently tracks per watched name.
+/// Cheap to clone; other components (e.g. bridges) use it to ask "is this pid watched?".
+///
+/// A state handle (see [`omnitrace_core::state`]): reads never block, also from ProcDog's own
+/// callbacks, and see the PIDs as of the previous completed poll.
+#[cfg(feature = "runtime")]
+#[derive(Clone, Debug, Default)]
+pub struct ProcDogState(Published<HashMap<String, HashSet<i32>>>);
+
+#[cfg(feature = "runtime")]
+impl ProcDogState {
+    /// Watched name the pid belongs to, if ProcDog tracks it.
+    pub fn name_of(&self, pid: i32) -> Option<String> {
+        self.0.load().iter().find(|(_, pids)| pids.contains(&pid)).map(|(name, _)| name.clone())
+    }
 
-    // name -> active PIDs
-    state: HashMap<String, HashSet<i32>>,
+    pub fn pids(&self, name: &str) -> HashSet<i32> {
+        self.0.load().get(name).cloned().unwrap_or_default()
+    }
+
+    /// Replace the PIDs tracked for `name`.
+    pub fn set<S: Into<String>>(&self, name: S, pids: HashSet<i32>) {
+        let name = name.into();
+        self.0.update(|m| {
+            m.insert(name.clone(), pids.clone());
+        });
+    }
+
+    fn replace(&self, state: &HashMap<String, HashSet<i32>>) {
+        self.0.publish(state.clone());
+    }
+}
+
+/// Cloneable handle to the watched names of a (possibly already running) ProcDog.
+///
+/// Obtained via [`ProcDog::control`] before the sensor is spawned. Names watched or unwatched
+/// through it are picked up on the next poll.
+#[cfg(feature = "runtime")]
+#[derive(Clone, Debug, Default)]
+pub struct ProcDogControl(Arc<Mutex<HashSet<String>>>);
+
+#[cfg(feature = "runtime")]
+impl ProcDogControl {
+    /// See [`ProcDog::watch`].
+    pub fn watch<S: Into<String>>(&self, name: S) {
+        self.0.lock().unwrap().insert(name.into());
+    }
+
+    /// See [`ProcDog::unwatch`].
+    pub fn unwatch(&self, name: &str) {
+        self.0.lock().unwrap().remove(name);
+    }
+
+    /// Copy of the currently watched names.
+    pub fn watched(&self) -> HashSet<String> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// What [`ProcDog::debug_handle`] reports, refreshed after every poll.
+#[cfg(feature = "runtime")]
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ProcDogDebug {
+    pub interval_ms: u64,
+    pub profile: Profile,
+    pub primed: bool,
+    pub watched: Vec<String>,
+    pub ignored: Vec<String>,
+    /// Environment selectors as `KEY=glob`.
+    pub watch_env: Vec<String>,
+    pub capture_env: Vec<String>,
+    /// Active PIDs per watched name, sorted.
+    pub pids: BTreeMap<String, Vec<i32>>,
+    /// Member PIDs per process group, per glob watched with [`ProcDog::watch_pgid_of`].
+    pub groups: BTreeMap<String, BTreeMap<i32, Vec<i32>>>,
+    /// Events per watched name, see [`ProcDog::entity_counters`].
+    pub entities: EntityCounters,
+    /// Recent events per name and PID, if [`ProcDogConfig::history`] is set.
+    pub history: Option<HistoryHandle<ProcDogEvent>>,
+}
+
+#[cfg(feature = "runtime")]
+pub struct ProcDog {
+    // the names polled (the names to poll from the next poll on are the control's), the
+    // ignored names and the PIDs per name
+    engine: ProcDiffer,
+    control: ProcDogControl,
+    // PIDs of names unwatched through the control, see ProcDogConfig::report_unwatched
+    tombstones: Option<Tombstones<String, HashSet<i32>>>,
+    env_select: Vec<EnvSelector>,
+    env_capture: Vec<String>,
+    // pid -> environ verdict, for PIDs of watched names
+    env_seen: HashMap<i32, EnvSeen>,
+    // declared processes, compared once when the sensor primes
+    expected: Vec<ExpectedProcess>,
+    // per watched name, see ProcDog::watch_labeled
+    labels: HashMap<String, Labels>,
+    // process groups by leader name, see ProcDog::watch_pgid_of
+    groups: Vec<GroupTracker>,
+
+    // the last listing, for previews
+    listed: Vec<(i32, String)>,
+    previews: PreviewQueue<ProcDogRule>,
+    shared: ProcDogState,
+    entities: EntityCounters,
+    history: Option<History<ProcDogEvent>>,
+    debug: DebugCell<ProcDogDebug>,
+    diagnostics: Diagnostics,
 
     config: ProcDogConfig,
+    profile: Profile,
+    pacer: Pacer,
     backend: Arc<dyn ProcBackend>,
 }
 
+#[cfg(feature = "runtime")]
 impl ProcDog {
     pub fn new(cfg: Option<ProcDogConfig>) -> Self {
+        let config = cfg.unwrap_or_default();
         Self {
-            watched: HashSet::new(),
-            ignored: HashSet::new(),
-            state: HashMap::new(),
-            config: cfg.unwrap_or_default(),
+            engine: ProcDiffer::new(),
+            control: ProcDogControl::default(),
+            tombstones: config.unwatched_ttl.map(|ttl| Tombstones::new(ttl, tombstones::DEFAULT_CAPACITY)),
+            history: config.history.map(History::new),
+            env_select: Vec::new(),
+            env_capture: Vec::new(),
+            env_seen: HashMap::new(),
+            expected: Vec::new(),
+            labels: HashMap::new(),
+            groups: Vec::new(),
+            listed: Vec::new(),
+            previews: PreviewQueue::default(),
+            shared: ProcDogState::default(),
+            entities: EntityCounters::default(),
+            debug: DebugCell::default(),
+            diagnostics: Diagnostics::new(),
+            pacer: Pacer::new(config.get_interval(), config.adaptive),
+            config,
+            profile: Profile::Full,
             backend: Arc::new(backends::stps::PsBackend),
         }
     }
@@ -75,93 +343,508 @@ impl ProcDog {
         self.backend = Arc::new(backend);
     }
 
+    /// Handle on the tracked PIDs, updated after every poll.
+    pub fn state_handle(&self) -> ProcDogState {
+        self.shared.clone()
+    }
+
+    /// Handle on a summary of the sensor's state, for [`omnitrace_core::debug::Snapshots`].
+    pub fn debug_handle(&self) -> DebugCell<ProcDogDebug> {
+        self.debug.clone()
+    }
+
+    /// Failed listings and unreadable environments the sensor ran past.
+    pub fn diagnostics(&self) -> Diagnostics {
+        self.diagnostics.clone()
+    }
+
+    /// Events emitted per watched name and kind, with their recent rate.
+    pub fn entity_counters(&self) -> EntityCounters {
+        self.entities.clone()
+    }
+
+    /// The last events per watched name and per PID (as `"pid:<pid>"`), as of the previous
+    /// poll. None unless [`ProcDogConfig::history`] is set.
+    pub fn history(&self) -> Option<HistoryHandle<ProcDogEvent>> {
+        self.history.as_ref().map(History::handle)
+    }
+
+    /// Run at `profile`: degraded doubles the polling interval.
+    pub fn apply_profile(&mut self, profile: Profile) {
+        self.profile = profile;
+        self.pacer.set_profile(profile);
+    }
+
+    fn interval(&self) -> Duration {
+        self.pacer.pulse()
+    }
+
+    /// The polling interval in use, see [`ProcDogConfig::adaptive`].
+    pub fn effective_pulse(&self) -> EffectivePulse {
+        self.pacer.handle()
+    }
+
+    /// Get a handle for watching and unwatching names after the sensor was spawned.
+    pub fn control(&self) -> ProcDogControl {
+        self.control.clone()
+    }
+
     pub fn watch<S: Into<String>>(&mut self, name: S) {
-        self.watched.insert(name.into());
+        let name = name.into();
+        self.control.watch(name.clone());
+        self.engine.watched.insert(name);
+    }
+
+    /// Watch `name`, like [`ProcDog::watch`], with `labels` on all its events, e.g.
+    /// `{"service": "billing"}`. Labeling a name again merges the labels, the new values
+    /// replacing those of keys set before; unwatching it drops them.
+    pub fn watch_labeled<S: Into<String>>(&mut self, name: S, labels: Labels) {
+        let name = name.into();
+        self.labels.entry(name.clone()).or_default().merge(&labels);
+        self.watch(name);
+    }
+
+    /// Stop watching `name`. Its PIDs are dropped without any event. Watched again later, its
+    /// PIDs then are the new baseline: processes that came and went while it was not watched
+    /// are not reported, unless [`ProcDogConfig::report_unwatched`] is set.
+    pub fn unwatch(&mut self, name: &str) {
+        self.control.unwatch(name);
+        let previous = self.engine.watched.clone();
+        self.engine.watched.remove(name);
+        self.apply_watch_edits(&previous, &HashMap::new());
     }
 
     pub fn ignore<S: Into<String>>(&mut self, pattern: S) {
-        self.ignored.insert(pattern.into());
+        self.engine.ignored.insert(pattern.into());
     }
 
-    async fn fire(hub: &CallbackHub<ProcDogEvent>, ev: ProcDogEvent) {
+    /// Declare how many processes of each name should run, e.g. as loaded with
+    /// [`omnitrace_core::expected::load`]. The names get watched. When the sensor primes it
+    /// fires a Deviation for each name off its count, before reporting any change; if that
+    /// first listing fails, nothing is compared.
+    pub fn expect(&mut self, procs: Vec<ExpectedProcess>) {
+        for p in &procs {
+            self.watch(p.name.clone());
+        }
+        self.expected.extend(procs);
+    }
+
+    /// Only track processes whose environment sets `key` to a value matching `value_glob`.
+    /// Selectors add up: a process must satisfy all of them. The environ is read once per PID,
+    /// and only for PIDs whose name is watched; processes whose environ cannot be read are not
+    /// tracked while selectors are set.
+    pub fn watch_env<K: Into<String>>(&mut self, key: K, value_glob: &str) -> Result<(), globset::Error> {
+        let glob = Glob::new(value_glob)?.compile_matcher();
+        self.env_select.push(EnvSelector { key: key.into(), pattern: value_glob.to_string(), glob });
+        Ok(())
+    }
+
+    /// Track the process groups of the processes named like `name_glob`, leading their group
+    /// (PID = PGID), with all their members whatever their names. Members are reported as
+    /// `Appeared` and `Disappeared` named `name_glob`, with their `group_leader`, and a group
+    /// whose leader and members all exited as `GroupGone`. Leaders are looked up again on
+    /// every poll, so a restarted daemon is tracked as a new group. Needs a backend with
+    /// [`ProcBackend::list_info`]; environment selectors do not apply to members.
+    pub fn watch_pgid_of(&mut self, name_glob: &str) -> Result<(), globset::Error> {
+        self.groups.push(GroupTracker::new(name_glob)?);
+        Ok(())
+    }
+
+    /// Attach these environment variables to `Appeared` events, see [`ProcEnv`] and
+    /// [`ProcDogConfig::hash_env_values`].
+    pub fn capture_env(&mut self, keys: &[&str]) {
+        for k in keys {
+            if !self.env_capture.iter().any(|c| c == k) {
+                self.env_capture.push(k.to_string());
+            }
+        }
+    }
+
+    async fn fire<R: Send + 'static>(&self, hub: &CallbackHub<ProcDogEvent, R>, mut ev: ProcDogEvent) {
+        if let Some(labels) = self.labels.get(ev.name()) {
+            *ev.labels_mut() = labels.clone();
+        }
+        self.entities.record(ev.name(), entities::mask_name(ev.mask()));
+        if let Some(h) = &self.history {
+            let pid = match &ev {
+                ProcDogEvent::Appeared { pid, .. } | ProcDogEvent::Disappeared { pid, .. } => Some(format!("pid:{pid}")),
+                _ => None,
+            };
+            h.record(std::iter::once(ev.name().to_string()).chain(pid), &ev);
+        }
         hub.fire(ev.mask().bits(), &ev).await;
     }
 
-    async fn prime(&mut self, hub: &CallbackHub<ProcDogEvent>) {
-        if let Ok(procs) = self.backend.list().await {
-            for name in &self.watched {
-                if self.ignored.contains(name) {
-                    continue;
+    fn publish_debug(&self, primed: bool) {
+        let sorted = |set: &HashSet<String>| {
+            let mut v: Vec<String> = set.iter().cloned().collect();
+            v.sort();
+            v
+        };
+        let pids = self
+            .engine
+            .state
+            .iter()
+            .map(|(name, pids)| {
+                let mut v: Vec<i32> = pids.iter().copied().collect();
+                v.sort();
+                (name.clone(), v)
+            })
+            .collect();
+        let groups = self
+            .groups
+            .iter()
+            .map(|g| {
+                let members = g
+                    .groups()
+                    .iter()
+                    .map(|(pgid, pids)| {
+                        let mut v: Vec<i32> = pids.iter().copied().collect();
+                        v.sort();
+                        (*pgid, v)
+                    })
+                    .collect();
+                (g.pattern().to_string(), members)
+            })
+            .collect();
+
+        self.debug.update(|d| {
+            *d = ProcDogDebug {
+                interval_ms: self.interval().as_millis() as u64,
+                profile: self.profile,
+                primed,
+                watched: sorted(&self.engine.watched),
+                ignored: sorted(&self.engine.ignored),
+                watch_env: self.env_select.iter().map(|s| format!("{}={}", s.key, s.pattern)).collect(),
+                capture_env: self.env_capture.clone(),
+                pids,
+                groups,
+                entities: self.entities.clone(),
+                history: self.history(),
+            }
+        });
+        if let Some(h) = &self.history {
+            h.publish();
+        }
+    }
+
+    async fn read_env(&self, pid: i32) -> EnvSeen {
+        match self.backend.environ(pid).await {
+            Ok(vars) => {
+                // first definition wins, as with getenv()
+                let get = |key: &str| vars.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+                let selected = self.env_select.iter().all(|s| get(&s.key).is_some_and(|v| s.glob.is_match(v)));
+                let capture = (!self.env_capture.is_empty())
+                    .then(|| ProcEnv::Vars(self.env_capture.iter().filter_map(|k| Some((k.clone(), self.config.env_value(get(k)?)))).collect()));
+                EnvSeen { selected, capture }
+            }
+            Err(e) => {
+                let kind = e.kind();
+                if kind != std::io::ErrorKind::Unsupported {
+                    self.diagnostics.report_once("environ", ProcDogError::Environ { pid, source: e });
+                }
+                EnvSeen {
+                    selected: self.env_select.is_empty(),
+                    capture: (!self.env_capture.is_empty()).then(|| ProcEnv::Unavailable(kind.to_string())),
                 }
+            }
+        }
+    }
 
-                let pids: HashSet<i32> = procs.iter().filter(|(_, n)| n == name).map(|(pid, _)| *pid).collect();
+    /// PIDs per watched, not ignored name, narrowed down by the environment selectors.
+    async fn matching(&mut self, procs: &[(i32, String)]) -> HashMap<String, HashSet<i32>> {
+        let mut out = self.engine.matching(procs);
+        if self.env_select.is_empty() && self.env_capture.is_empty() {
+            return out;
+        }
 
-                if self.config.emit_missing_on_start && pids.is_empty() {
-                    Self::fire(hub, ProcDogEvent::Missing { name: name.clone() }).await;
-                }
+        let live: HashSet<i32> = out.values().flatten().copied().collect();
+        self.env_seen.retain(|pid, _| live.contains(pid));
+        for pid in live {
+            if !self.env_seen.contains_key(&pid) {
+                let seen = self.read_env(pid).await;
+                self.env_seen.insert(pid, seen);
+            }
+        }
+        for pids in out.values_mut() {
+            pids.retain(|pid| self.env_seen.get(pid).is_some_and(|s| s.selected));
+        }
+        out
+    }
 
-                self.state.insert(name.clone(), pids);
+    /// PIDs per watched name now, as a tick would see them: a one-shot listing for baselines,
+    /// without starting the sensor. Watched names without a process are there with no PIDs.
+    pub async fn snapshot(&mut self) -> Result<BTreeMap<String, BTreeSet<i32>>, ProcDogError> {
+        let procs = self.backend.list().await.map_err(ProcDogError::List)?;
+        Ok(self.matching(&procs).await.into_iter().map(|(name, pids)| (name, pids.into_iter().collect())).collect())
+    }
+
+    /// What adding `rule` would do, against the last listing and the PIDs tracked now:
+    /// `would_match` counts the processes of the listing the rule names (for a watch, those
+    /// that are not ignored), `would_stop_matching` the tracked ones an ignore would drop.
+    /// Environment selectors are not applied. Nothing is changed.
+    pub fn preview_rule(&self, rule: &ProcDogRule) -> PreviewReport {
+        let (name, matches) = match rule {
+            ProcDogRule::Watch(name) => (name, !self.engine.ignored.contains(name)),
+            ProcDogRule::Ignore(name) => (name, true),
+        };
+        let mut report = PreviewReport::tally(self.listed.iter().map(|(pid, n)| (format!("{n}[{pid}]"), matches && n == name, false)));
+        if let ProcDogRule::Ignore(name) = rule {
+            report.would_stop_matching = self.engine.state.get(name).map_or(0, HashSet::len);
+        }
+        report
+    }
+
+    /// Handle for previewing rules against the running sensor, answered on its next poll.
+    pub fn preview_handle(&self) -> PreviewHandle<ProcDogRule> {
+        self.previews.handle()
+    }
+
+    fn answer_previews(&self) {
+        for req in self.previews.take() {
+            let report = self.preview_rule(&req.rule);
+            req.answer(report);
+        }
+    }
+
+    /// What ticks report for the PIDs going from `old` to `new`, both as returned by
+    /// [`ProcDog::snapshot`]: Appeared and Disappeared per PID (without environment), and
+    /// Missing for a name whose last process is gone.
+    pub fn diff_snapshots(old: &BTreeMap<String, BTreeSet<i32>>, new: &BTreeMap<String, BTreeSet<i32>>) -> Vec<ProcDogEvent> {
+        let none = BTreeSet::new();
+        let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+        let mut out = Vec::new();
+        for name in names {
+            let (previous, current) = (old.get(name).unwrap_or(&none), new.get(name).unwrap_or(&none));
+            let (appeared, disappeared) = engine::pid_changes(&previous.iter().copied().collect(), &current.iter().copied().collect());
+            out.extend(appeared.into_iter().map(|pid| ProcDogEvent::Appeared {
+                name: name.clone(),
+                pid,
+                group_leader: None,
+                env: None,
+                labels: Labels::default(),
+            }));
+            out.extend(disappeared.into_iter().map(|pid| ProcDogEvent::Disappeared {
+                name: name.clone(),
+                pid,
+                group_leader: None,
+                labels: Labels::default(),
+            }));
+            if new.contains_key(name) && current.is_empty() && !previous.is_empty() {
+                out.push(ProcDogEvent::Missing { name: name.clone(), labels: Labels::default() });
             }
         }
+        out
     }
 
-    async fn tick_once(&mut self, hub: &CallbackHub<ProcDogEvent>) {
-        let procs = match self.backend.list().await {
-            Ok(p) => p,
-            Err(_) => return,
+    /// The backend's listing, with process groups while any are watched, or None with the
+    /// failure recorded in the diagnostics.
+    async fn list(&self) -> Option<(Vec<(i32, String)>, Vec<ProcInfo>)> {
+        let listed = if self.groups.is_empty() {
+            self.backend.list().await.map(|procs| (procs, Vec::new()))
+        } else {
+            self.backend.list_info().await.map(|info| (info.iter().map(|p| (p.pid, p.name.clone())).collect(), info))
         };
+        match listed {
+            Ok(listed) => {
+                self.diagnostics.clear("list");
+                Some(listed)
+            }
+            Err(e) => {
+                self.diagnostics.report("list", ProcDogError::List(e));
+                None
+            }
+        }
+    }
 
-        for name in &self.watched {
-            if self.ignored.contains(name) {
-                continue;
+    /// Bring the state in line with the names watched and unwatched through the control since
+    /// the `previous` poll. The PIDs of unwatched names are dropped without events, into the
+    /// tombstones if kept. Names watched again start from their tombstone, if any, or else from
+    /// their PIDs in `matched`.
+    fn apply_watch_edits(&mut self, previous: &HashSet<String>, matched: &HashMap<String, HashSet<i32>>) {
+        let at = self.config.clock.now_instant();
+        for name in previous.difference(&self.engine.watched) {
+            let pids = self.engine.state.remove(name).unwrap_or_default();
+            self.labels.remove(name);
+            if let Some(t) = self.tombstones.as_mut() {
+                t.bury(name.clone(), pids, at);
             }
+        }
+        for name in self.engine.watched.difference(previous) {
+            let pids = match self.tombstones.as_mut().and_then(|t| t.take(name, at)) {
+                Some(pids) => pids,
+                None => matched.get(name).cloned().unwrap_or_default(),
+            };
+            self.engine.state.insert(name.clone(), pids);
+        }
+    }
 
-            let current: HashSet<i32> = procs.iter().filter(|(_, n)| n == name).map(|(pid, _)| *pid).collect();
+    /// Publish the PIDs per watched name, and the members of the watched groups under their
+    /// glob, for [`ProcDogState`].
+    fn share_state(&self) {
+        if self.groups.is_empty() {
+            self.shared.replace(&self.engine.state);
+            return;
+        }
+        let mut state = self.engine.state.clone();
+        for g in &self.groups {
+            state.entry(g.pattern().to_string()).or_default().extend(g.groups().values().flatten());
+        }
+        self.shared.replace(&state);
+    }
 
-            let previous = self.state.get(name).cloned().unwrap_or_default();
+    async fn prime<R: Send + 'static>(&mut self, hub: &CallbackHub<ProcDogEvent, R>) {
+        self.engine.watched = self.control.watched();
+        if let Some((procs, info)) = self.list().await {
+            for g in &mut self.groups {
+                // the baseline, no events
+                g.feed(&info);
+            }
+            let mut matched = self.matching(&procs).await;
+            self.listed = procs;
+            for name in &self.engine.watched {
+                if self.engine.ignored.contains(name) {
+                    continue;
+                }
 
-            // Determine diffs without holding mutable borrow
-            let appeared: Vec<i32> = current.difference(&previous).copied().collect();
+                let pids = matched.remove(name).unwrap_or_default();
 
-            let disappeared: Vec<i32> = previous.difference(&current).copied().collect();
+                if self.config.emit_missing_on_start && pids.is_empty() {
+                    self.fire(hub, ProcDogEvent::Missing { name: name.clone(), labels: Labels::default() }).await;
+                }
 
-            // Fire events
-            for pid in &appeared {
-                Self::fire(hub, ProcDogEvent::Appeared { name: name.clone(), pid: *pid }).await;
+                self.engine.state.insert(name.clone(), pids);
             }
 
-            for pid in &disappeared {
-                Self::fire(hub, ProcDogEvent::Disappeared { name: name.clone(), pid: *pid }).await;
+            for e in &self.expected {
+                let mut pids: Vec<i32> = self.engine.state.get(&e.name).map(|p| p.iter().copied().collect()).unwrap_or_default();
+                if let Some(deviation) = e.deviation(pids.len()) {
+                    pids.sort_unstable();
+                    self.fire(hub, ProcDogEvent::Deviation { name: e.name.clone(), deviation, pids, labels: Labels::default() }).await;
+                }
             }
+            // once every event of the poll fired, see ProcDogState
+            self.share_state();
+        }
+        self.publish_debug(true);
+    }
 
-            // Now update state
-            self.state.insert(name.clone(), current);
+    async fn tick_once<R: Send + 'static>(&mut self, hub: &CallbackHub<ProcDogEvent, R>) {
+        let Some((procs, info)) = self.list().await else {
+            return;
+        };
+
+        let previous = std::mem::replace(&mut self.engine.watched, self.control.watched());
+        let mut matched = self.matching(&procs).await;
+        self.listed = procs;
+        if previous != self.engine.watched {
+            self.apply_watch_edits(&previous, &matched);
         }
+        for mut ev in self.engine.changes(&mut matched) {
+            if let ProcDogEvent::Appeared { pid, env, .. } = &mut ev {
+                *env = self.env_seen.get(pid).and_then(|s| s.capture.clone());
+            }
+            self.fire(hub, ev).await;
+        }
+        let group_events: Vec<ProcDogEvent> = self.groups.iter_mut().flat_map(|g| g.feed(&info)).collect();
+        for ev in group_events {
+            self.fire(hub, ev).await;
+        }
+        self.share_state();
+        self.publish_debug(true);
     }
 
-    pub async fn run(mut self, ctx: SensorCtx<ProcDogEvent>) {
+    pub async fn run<R: Send + 'static>(mut self, ctx: SensorCtx<ProcDogEvent, R>) {
         self.prime(&ctx.hub).await;
 
-        let mut ticker = tokio::time::interval(self.config.get_interval());
+        let mut ticker = self.pacer.ticker();
 
         loop {
+            ctx.beat();
+            if self.pacer.observe_fired(ctx.hub.fired()) {
+                self.pacer.restart(&mut ticker);
+            }
             tokio::select! {
                 _ = ctx.cancel.cancelled() => break,
                 _ = ticker.tick() => {}
             }
 
+            if let Some(p) = self.config.profile.as_ref().and_then(|s| s.changed_from(self.profile)) {
+                self.apply_profile(p);
+                self.pacer.restart(&mut ticker);
+            }
+
             self.tick_once(&ctx.hub).await;
+            self.answer_previews();
         }
     }
 }
 
 /// Interface implementation
+#[cfg(feature = "runtime")]
 impl Sensor for ProcDog {
     type Event = ProcDogEvent;
 
-    fn run(self, ctx: SensorCtx<Self::Event>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+    fn run<R: Send + 'static>(self, ctx: SensorCtx<Self::Event, R>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
         Box::pin(async move {
             ProcDog::run(self, ctx).await;
         })
     }
+
+    /// One listing from the backend, and the environment of this process when selectors or
+    /// captures need environments. Watched names not running are only noted.
+    fn preflight(&self) -> Pin<Box<dyn Future<Output = Vec<PreflightFinding>> + Send + '_>> {
+        let backend = self.backend.clone();
+        let mut watched: Vec<String> = self.engine.watched.iter().cloned().collect();
+        watched.sort();
+        let ignored = self.engine.ignored.clone();
+        let (selectors, captures) = (!self.env_select.is_empty(), !self.env_capture.is_empty());
+        let groups = !self.groups.is_empty();
+
+        Box::pin(async move {
+            let mut out = Vec::new();
+            if watched.is_empty() {
+                out.push(PreflightFinding::warning("no process names watched").remedy("add them with ProcDog::watch"));
+            }
+            for name in watched.iter().filter(|n| ignored.contains(*n)) {
+                out.push(PreflightFinding::warning(format!("{name} is both watched and ignored, it is never reported")));
+            }
+
+            match backend.list().await {
+                Err(e) => out.push(
+                    PreflightFinding::critical(format!("cannot list processes: {e}"))
+                        .remedy("install the tool the backend runs (ps), or set another one with ProcDog::set_backend"),
+                ),
+                Ok(procs) if procs.is_empty() => out.push(
+                    PreflightFinding::critical("the process list is empty").remedy("check that /proc is mounted and not restricted with hidepid"),
+                ),
+                Ok(procs) => {
+                    for name in watched.iter().filter(|n| !ignored.contains(*n) && !procs.iter().any(|(_, p)| p == *n)) {
+                        out.push(PreflightFinding::info(format!("{name} is not running now")));
+                    }
+                }
+            }
+
+            if groups && let Err(e) = backend.list_info().await {
+                // every poll lists with process groups, and fails
+                out.push(
+                    PreflightFinding::critical(format!("process groups are watched, but the backend cannot list them: {e}"))
+                        .remedy("use a backend reading /proc/<pid>/stat, e.g. LinuxPsBackend"),
+                );
+            }
+
+            if (selectors || captures)
+                && let Err(e) = backend.environ(std::process::id() as i32).await
+            {
+                // without environments, selectors match nothing and captures are Unavailable
+                let finding = if selectors {
+                    PreflightFinding::critical(format!("environment selectors are set, but the backend cannot read environments: {e}"))
+                } else {
+                    PreflightFinding::warning(format!("environment captures are set, but the backend cannot read environments: {e}"))
+                };
+                out.push(finding.remedy("use a backend reading /proc/<pid>/environ, e.g. LinuxPsBackend"));
+            }
+            out
+        })
+    }
 }
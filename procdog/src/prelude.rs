@@ -0,0 +1,6 @@
+//! `use procdog::prelude::*;` brings in the ProcDog sensor, its config and events,
+//! and the core prelude (callbacks, hub, `spawn_sensor`).
+
+pub use crate::events::{ProcDogEvent, ProcDogMask};
+pub use crate::{ProcDog, ProcDogConfig, ProcDogState};
+pub use omnitrace_core::prelude::*;
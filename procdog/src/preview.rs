@@ -0,0 +1,26 @@
+//! Rules [`crate::ProcDog::preview_rule`] answers for, see [`omnitrace_core::preview`].
+
+use omnitrace_core::preview::{RuleError, split_rule};
+use std::str::FromStr;
+
+/// A process name to watch or ignore, as [`crate::ProcDog::watch`] and
+/// [`crate::ProcDog::ignore`] take it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProcDogRule {
+    /// `watch <name>`
+    Watch(String),
+    /// `ignore <name>`
+    Ignore(String),
+}
+
+impl FromStr for ProcDogRule {
+    type Err = RuleError;
+
+    fn from_str(s: &str) -> Result<Self, RuleError> {
+        match split_rule(s)? {
+            ("watch", name) => Ok(Self::Watch(name.to_string())),
+            ("ignore", name) => Ok(Self::Ignore(name.to_string())),
+            (verb, _) => Err(RuleError::UnknownVerb { verb: verb.to_string(), expected: "watch, ignore" }),
+        }
+    }
+}
@@ -0,0 +1,956 @@
+use crate::{
+    ProcBackend, ProcDog, ProcDogConfig,
+    backends::shared::SharedBackend,
+    engine::ProcDiffer,
+    engine_ut::{NAMES, Snapshot, expected, fixture_proc, random_snapshot},
+    error::ProcDogError,
+    events::{ProcDogEvent, ProcDogMask, ProcEnv},
+    groups::ProcInfo,
+    preview::ProcDogRule,
+};
+use async_trait::async_trait;
+use omnitrace_core::{
+    callbacks::{Callback, CallbackHub, CallbackResult, KIND_BITS, RESERVED_BITS},
+    clock::ManualClock,
+    expected::Deviation,
+    fields::{EventFields, FieldValue},
+    format::{format_long, format_short},
+    history::HistoryConfig,
+    labels::Labels,
+    pulse::AdaptivePulse,
+    sensor::{Sensor, spawn_sensor},
+    severity::Severity,
+    topics::Topics,
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+/// Returns the scripted snapshots one per call, then repeats the last one.
+struct ScriptBackend {
+    script: Vec<Snapshot>,
+    calls: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl ProcBackend for ScriptBackend {
+    async fn list(&self) -> std::io::Result<Vec<(i32, String)>> {
+        let n = self.calls.fetch_add(1, Ordering::SeqCst);
+        Ok(self.script[n.min(self.script.len() - 1)].clone())
+    }
+}
+
+/// Slow callback noting how many polls had happened when it ran.
+struct Recorder {
+    calls: Arc<AtomicUsize>,
+    seen: Arc<Mutex<Vec<(usize, ProcDogEvent)>>>,
+}
+
+#[async_trait]
+impl Callback<ProcDogEvent> for Recorder {
+    fn mask(&self) -> u64 {
+        ProcDogMask::all().bits()
+    }
+
+    async fn call(&self, ev: &ProcDogEvent) -> Option<CallbackResult> {
+        let at = self.calls.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        self.seen.lock().unwrap().push((at, ev.clone()));
+        None
+    }
+}
+
+#[tokio::test]
+async fn each_poll_is_delivered_before_the_next_one() {
+    for seed in 0..5 {
+        let mut rng = fastrand::Rng::with_seed(seed);
+        let script: Vec<Snapshot> = (0..15).map(|_| random_snapshot(&mut rng)).collect();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let seen = Arc::new(Mutex::new(Vec::new()));
+
+        let mut dog = ProcDog::new(Some(ProcDogConfig::default().interval(Duration::from_millis(1))));
+        dog.set_backend(ScriptBackend { script: script.clone(), calls: calls.clone() });
+        for name in NAMES {
+            dog.watch(name);
+        }
+        let mut hub = CallbackHub::new();
+        hub.add(Recorder { calls: calls.clone(), seen: seen.clone() });
+        let (handle, task) = spawn_sensor(dog, Arc::new(hub));
+
+        while calls.load(Ordering::SeqCst) <= script.len() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        handle.shutdown();
+        let _ = task.await;
+
+        // Poll n (1-based) diffs script[n - 2] against script[n - 1]. Its events must all be
+        // delivered while the poll count is still n, i.e. before the next poll.
+        let mut by_poll: HashMap<usize, HashSet<(String, i32, bool)>> = HashMap::new();
+        for (at, ev) in seen.lock().unwrap().iter() {
+            let key = match ev {
+                ProcDogEvent::Appeared { name, pid, .. } => (name.clone(), *pid, true),
+                ProcDogEvent::Disappeared { name, pid, .. } => (name.clone(), *pid, false),
+                ProcDogEvent::Missing { .. } | ProcDogEvent::Deviation { .. } | ProcDogEvent::GroupGone { .. } => continue,
+            };
+            assert!(by_poll.entry(*at).or_default().insert(key), "seed {seed}: duplicate event at poll {at}");
+        }
+        for n in 2..=script.len() {
+            let want = expected(&script[n - 2], &script[n - 1]);
+            assert_eq!(by_poll.remove(&n).unwrap_or_default(), want, "seed {seed}, poll {n}");
+        }
+        assert!(by_poll.is_empty(), "seed {seed}: events after the script ended: {by_poll:?}");
+    }
+}
+
+#[tokio::test]
+async fn sensor_reports_what_the_engine_does() {
+    let mut rng = fastrand::Rng::with_seed(759);
+    let script: Vec<Snapshot> = (0..20).map(|_| random_snapshot(&mut rng)).collect();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let seen = Arc::new(Mutex::new(Vec::new()));
+
+    let mut dog = ProcDog::new(Some(ProcDogConfig::default().interval(Duration::from_millis(1))));
+    dog.set_backend(ScriptBackend { script: script.clone(), calls: calls.clone() });
+    let mut differ = ProcDiffer::new();
+    for name in NAMES {
+        dog.watch(name);
+        differ.watch(name);
+    }
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder { calls: calls.clone(), seen: seen.clone() });
+    let (handle, task) = spawn_sensor(dog, Arc::new(hub));
+    while calls.load(Ordering::SeqCst) <= script.len() {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    handle.shutdown();
+    let _ = task.await;
+
+    let seen = seen.lock().unwrap();
+    for (n, listing) in script.iter().enumerate() {
+        let sensor: Vec<_> = seen.iter().filter(|(at, _)| *at == n + 1).map(|(_, ev)| serde_json::to_value(ev).unwrap()).collect();
+        let engine: Vec<_> = differ.feed(listing).iter().map(|ev| serde_json::to_value(ev).unwrap()).collect();
+        assert_eq!(sensor, engine, "poll {}", n + 1);
+    }
+}
+
+#[tokio::test]
+async fn entity_counters_follow_a_burst() {
+    // nginx restarts its 20 workers, sshd gets one session
+    let workers = |base: i32| -> Snapshot { (0..20).map(|i| (base + i, "nginx".to_string())).collect() };
+    let mut restarted = workers(2000);
+    restarted.push((300, "sshd".to_string()));
+    let script = vec![workers(1000), restarted];
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    let mut dog = ProcDog::new(Some(ProcDogConfig::default().interval(Duration::from_millis(1))));
+    dog.set_backend(ScriptBackend { script: script.clone(), calls: calls.clone() });
+    for name in NAMES {
+        dog.watch(name);
+    }
+    let counters = dog.entity_counters();
+    let debug = dog.debug_handle();
+    let (handle, task) = spawn_sensor(dog, Arc::new(CallbackHub::new()));
+    while calls.load(Ordering::SeqCst) <= script.len() {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    handle.shutdown();
+    let _ = task.await;
+
+    let r = counters.report();
+    assert_eq!(r.entities.iter().map(|e| e.entity.as_str()).collect::<Vec<_>>(), ["nginx", "sshd"]);
+    let nginx = r.get("nginx").unwrap();
+    assert_eq!((nginx.total, nginx.by_kind["appeared"], nginx.by_kind["disappeared"]), (40, 20, 20));
+    assert_eq!(r.get("sshd").unwrap().by_kind["appeared"], 1);
+    assert!(r.get("postgres").is_none(), "no events, no entry");
+    assert_eq!(omnitrace_core::debug::Debuggable::debug_snapshot(&debug)["entities"]["entities"][0]["total"], 40);
+}
+
+/// Returns whatever the test put in last.
+struct LiveBackend(Arc<Mutex<Snapshot>>);
+
+#[async_trait]
+impl ProcBackend for LiveBackend {
+    async fn list(&self) -> std::io::Result<Vec<(i32, String)>> {
+        Ok(self.0.lock().unwrap().clone())
+    }
+}
+
+#[tokio::test]
+async fn standby_keeps_the_baseline_and_promotion_emits_at_once() {
+    use omnitrace_core::{
+        router::Router,
+        standby::{ROLE_CHANGED, Role, RoleSwitch},
+    };
+
+    let procs = Arc::new(Mutex::new(vec![(10, "sshd".to_string())]));
+    let mut dog = ProcDog::new(Some(ProcDogConfig::default().interval(Duration::from_millis(2))));
+    dog.set_backend(LiveBackend(procs.clone()));
+    dog.watch("sshd");
+    dog.watch("nginx");
+    let state = dog.state_handle();
+
+    let role = RoleSwitch::new("node-b", Role::Standby);
+    let (tx, mut rx) = tokio::sync::mpsc::channel(64);
+    let mut router = Router::new();
+    router.add_sink("jsonl", tx);
+    router.set_default(vec!["jsonl"]);
+    router.set_role_switch(role.clone());
+    let router = Arc::new(router);
+    let mut hub = CallbackHub::new();
+    hub.add(router.callback("procdog", |ev: &ProcDogEvent| ev.mask().bits()));
+    let (handle, task) = spawn_sensor(dog, Arc::new(hub));
+
+    // the sensor tracks changes while standby, nothing leaves
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    procs.lock().unwrap().push((20, "nginx".to_string()));
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(rx.try_recv().is_err());
+    assert!(router.suppressed() >= 1);
+    assert_eq!(state.pids("nginx"), HashSet::from([20]), "baseline kept warm");
+
+    // no re-prime: nginx 20 is not reported again, the next change goes out right away
+    role.promote("api");
+    procs.lock().unwrap().push((21, "nginx".to_string()));
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    handle.shutdown();
+    let _ = task.await;
+
+    let mut out = Vec::new();
+    while let Ok(v) = rx.try_recv() {
+        out.push(v);
+    }
+    assert_eq!(out.len(), 2, "{out:?}");
+    assert_eq!(out[0][ROLE_CHANGED]["role"], "active");
+    assert_eq!(out[1], serde_json::json!({ "Appeared": { "name": "nginx", "pid": 21 } }));
+}
+
+/// Fixture-tree backend remembering which environs were read.
+struct CountingEnv {
+    inner: crate::backends::linuxps::LinuxPsBackend,
+    reads: Arc<Mutex<Vec<i32>>>,
+}
+
+#[async_trait]
+impl ProcBackend for CountingEnv {
+    async fn list(&self) -> std::io::Result<Vec<(i32, String)>> {
+        self.inner.list().await
+    }
+
+    async fn environ(&self, pid: i32) -> std::io::Result<Vec<(String, String)>> {
+        self.reads.lock().unwrap().push(pid);
+        self.inner.environ(pid).await
+    }
+}
+
+struct Collect(Arc<Mutex<Vec<ProcDogEvent>>>);
+
+#[async_trait]
+impl Callback<ProcDogEvent> for Collect {
+    fn mask(&self) -> u64 {
+        ProcDogMask::all().bits()
+    }
+
+    async fn call(&self, ev: &ProcDogEvent) -> Option<CallbackResult> {
+        self.0.lock().unwrap().push(ev.clone());
+        None
+    }
+}
+
+/// Appeared events as (pid, env), sorted by pid.
+fn appeared(seen: &Mutex<Vec<ProcDogEvent>>) -> Vec<(i32, Option<ProcEnv>)> {
+    let mut out: Vec<_> = seen
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|ev| match ev {
+            ProcDogEvent::Appeared { pid, env, .. } => Some((*pid, env.clone())),
+            _ => None,
+        })
+        .collect();
+    out.sort_by_key(|(pid, _)| *pid);
+    out
+}
+
+#[tokio::test]
+async fn env_selectors_narrow_watched_names_and_capture_values() {
+    let root = fixture_proc(
+        "select",
+        &[
+            (100, "worker", Some(&["APP_ENV=prod-eu", "TOKEN=s3cret", "APP_ENV=shadowed"])),
+            (101, "worker", Some(&["APP_ENV=staging"])),
+            (102, "worker", None),
+            (103, "other", Some(&["APP_ENV=prod"])),
+        ],
+    );
+    let reads = Arc::new(Mutex::new(Vec::new()));
+    let mut dog = ProcDog::new(None);
+    dog.set_backend(CountingEnv { inner: crate::backends::linuxps::LinuxPsBackend::at(&root), reads: reads.clone() });
+    dog.watch("worker");
+    dog.watch_env("APP_ENV", "prod*").unwrap();
+    dog.capture_env(&["APP_ENV", "TOKEN", "HOME"]);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Collect(seen.clone()));
+
+    dog.tick_once(&hub).await;
+    let vars = BTreeMap::from([("APP_ENV".to_string(), "prod-eu".to_string()), ("TOKEN".to_string(), "s3cret".to_string())]);
+    assert_eq!(appeared(&seen), [(100, Some(ProcEnv::Vars(vars)))], "staging and unreadable workers are not tracked");
+    assert_eq!(dog.state_handle().pids("worker"), HashSet::from([100]));
+
+    // environ is read once per watched-name PID, never for other names
+    dog.tick_once(&hub).await;
+    let mut r = reads.lock().unwrap().clone();
+    r.sort();
+    assert_eq!(r, [100, 101, 102]);
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn captured_env_can_be_hashed_and_unreadable_is_reported() {
+    let root = fixture_proc("capture", &[(200, "worker", Some(&["TOKEN=s3cret"])), (201, "worker", None)]);
+    let mut dog = ProcDog::new(Some(ProcDogConfig::default().hash_env_values(true)));
+    dog.set_backend(crate::backends::linuxps::LinuxPsBackend::at(&root));
+    dog.watch("worker");
+    dog.capture_env(&["TOKEN"]);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Collect(seen.clone()));
+
+    dog.tick_once(&hub).await;
+    let hashed = format!("blake3:{}", blake3::hash(b"s3cret").to_hex());
+    assert_eq!(
+        appeared(&seen),
+        [
+            (200, Some(ProcEnv::Vars(BTreeMap::from([("TOKEN".to_string(), hashed.clone())])))),
+            (201, Some(ProcEnv::Unavailable("entity not found".to_string()))),
+        ],
+        "without selectors, unreadable processes are still tracked"
+    );
+    let ev = seen.lock().unwrap().iter().find(|ev| matches!(ev, ProcDogEvent::Appeared { pid: 200, .. })).cloned();
+    let json = serde_json::to_value(ev).unwrap();
+    assert_eq!(json["Appeared"]["env"]["vars"]["TOKEN"], hashed.as_str());
+    let environ = dog.diagnostics().get("environ").unwrap();
+    assert!(matches!(environ.last.downcast_ref::<ProcDogError>(), Some(ProcDogError::Environ { pid: 201, .. })));
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+/// Fails until the test says otherwise.
+struct Flaky(Arc<Mutex<Option<Snapshot>>>);
+
+#[async_trait]
+impl ProcBackend for Flaky {
+    async fn list(&self) -> std::io::Result<Vec<(i32, String)>> {
+        self.0.lock().unwrap().clone().ok_or_else(|| std::io::Error::from(std::io::ErrorKind::PermissionDenied))
+    }
+}
+
+#[tokio::test]
+async fn failed_listings_keep_the_state_and_are_diagnosed() {
+    let procs = Arc::new(Mutex::new(Some(vec![(10, "sshd".to_string())])));
+    let mut dog = ProcDog::new(None);
+    dog.set_backend(Flaky(procs.clone()));
+    dog.watch("sshd");
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Collect(seen.clone()));
+    let diagnostics = dog.diagnostics();
+
+    dog.tick_once(&hub).await;
+    *procs.lock().unwrap() = None;
+    dog.tick_once(&hub).await;
+    dog.tick_once(&hub).await;
+    assert_eq!(seen.lock().unwrap().len(), 1, "a failed listing is not everything disappearing");
+    assert_eq!(dog.state_handle().pids("sshd"), HashSet::from([10]));
+    let list = diagnostics.get("list").unwrap();
+    assert_eq!((list.count, list.message.as_str()), (2, "procdog: cannot list processes: permission denied"));
+
+    *procs.lock().unwrap() = Some(Vec::new());
+    dog.tick_once(&hub).await;
+    assert!(diagnostics.get("list").is_none());
+    assert!(matches!(seen.lock().unwrap()[1], ProcDogEvent::Disappeared { pid: 10, .. }));
+}
+
+#[derive(Clone, Copy)]
+enum Edit {
+    Keep,
+    Unwatch,
+    Watch,
+}
+
+/// Poll `sshd` through `steps`, one poll each: an edit through the control, and the sshd PIDs
+/// running. The first step is the primed state; `cron` (PID 1) stays watched throughout.
+/// Returns the events for sshd as `(kind, pid)`.
+async fn watch_edits(cfg: ProcDogConfig, steps: &[(Edit, &[i32])]) -> Vec<(&'static str, i32)> {
+    let clock = ManualClock::new();
+    let procs = Arc::new(Mutex::new(None));
+    let set = |pids: &[i32]| {
+        let mut list = vec![(1, "cron".to_string())];
+        list.extend(pids.iter().map(|pid| (*pid, "sshd".to_string())));
+        *procs.lock().unwrap() = Some(list);
+    };
+    let mut dog = ProcDog::new(Some(cfg.clock(clock.shared())));
+    dog.set_backend(Flaky(procs.clone()));
+    dog.watch("cron");
+    let control = dog.control();
+    control.watch("sshd");
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Collect(seen.clone()));
+
+    set(steps[0].1);
+    dog.prime(&hub).await;
+    for (edit, pids) in &steps[1..] {
+        match edit {
+            Edit::Keep => {}
+            Edit::Unwatch => control.unwatch("sshd"),
+            Edit::Watch => control.watch("sshd"),
+        }
+        set(pids);
+        clock.advance(Duration::from_secs(10));
+        dog.tick_once(&hub).await;
+    }
+    let seen = seen.lock().unwrap();
+    seen.iter()
+        .map(|ev| match ev {
+            ProcDogEvent::Appeared { name, pid, .. } if name == "sshd" => ("appeared", *pid),
+            ProcDogEvent::Disappeared { name, pid, .. } if name == "sshd" => ("disappeared", *pid),
+            other => panic!("{other:?}"),
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn unwatching_drops_the_pids_without_events() {
+    use Edit::*;
+    // gone while not watched: no Disappeared, then or when watched again
+    let steps: [(Edit, &[i32]); 5] = [(Keep, &[10]), (Unwatch, &[10]), (Keep, &[]), (Watch, &[]), (Keep, &[11])];
+    assert_eq!(watch_edits(ProcDogConfig::default(), &steps).await, [("appeared", 11)]);
+
+    // started while not watched: watched again, that is the baseline
+    let steps: [(Edit, &[i32]); 5] = [(Keep, &[]), (Unwatch, &[]), (Keep, &[10]), (Watch, &[10]), (Keep, &[])];
+    assert_eq!(watch_edits(ProcDogConfig::default(), &steps).await, [("disappeared", 10)]);
+
+    // unwatched and watched again between two polls: nothing happened
+    let steps: [(Edit, &[i32]); 4] = [(Keep, &[10]), (Unwatch, &[10]), (Watch, &[10, 12]), (Keep, &[12])];
+    assert_eq!(watch_edits(ProcDogConfig::default(), &steps).await, [("disappeared", 10)]);
+}
+
+#[tokio::test]
+async fn events_carry_the_labels_of_their_name() {
+    let procs = Arc::new(Mutex::new(Some(vec![(1, "cron".to_string())])));
+    let mut dog = ProcDog::new(None);
+    dog.set_backend(Flaky(procs.clone()));
+    dog.watch_labeled("billing", [("service", "billing"), ("team", "payments")].into_iter().collect());
+    // labeled again: merged, the new team wins
+    dog.watch_labeled("billing", [("team", serde_json::json!("finance")), ("tier", serde_json::json!(1))].into_iter().collect());
+    dog.watch("cron");
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Collect(seen.clone()));
+    let json = |seen: &Mutex<Vec<ProcDogEvent>>| -> Vec<serde_json::Value> {
+        seen.lock().unwrap().drain(..).map(|ev| serde_json::to_value(ev).unwrap()).collect()
+    };
+
+    dog.prime(&hub).await;
+    *procs.lock().unwrap() = Some(vec![(10, "billing".to_string())]);
+    dog.tick_once(&hub).await;
+    assert_eq!(
+        json(&seen),
+        [
+            serde_json::json!({ "Appeared": { "name": "billing", "pid": 10, "labels": { "service": "billing", "team": "finance", "tier": 1 } } }),
+            serde_json::json!({ "Disappeared": { "name": "cron", "pid": 1 } }),
+        ]
+    );
+
+    // unwatched, the labels go
+    let control = dog.control();
+    control.unwatch("billing");
+    dog.tick_once(&hub).await;
+    control.watch("billing");
+    dog.tick_once(&hub).await;
+    *procs.lock().unwrap() = Some(vec![]);
+    dog.tick_once(&hub).await;
+    assert_eq!(json(&seen), [serde_json::json!({ "Disappeared": { "name": "billing", "pid": 10 } })]);
+}
+
+#[tokio::test]
+async fn tombstones_report_processes_that_came_and_went_while_unwatched() {
+    use Edit::*;
+    let cfg = || ProcDogConfig::default().report_unwatched(Duration::from_secs(60));
+    let steps: [(Edit, &[i32]); 5] = [(Keep, &[10]), (Unwatch, &[10]), (Keep, &[11]), (Watch, &[11]), (Keep, &[])];
+    assert_eq!(watch_edits(cfg(), &steps).await, [("appeared", 11), ("disappeared", 10), ("disappeared", 11)]);
+
+    let steps: [(Edit, &[i32]); 4] = [(Keep, &[10]), (Unwatch, &[10]), (Keep, &[10]), (Watch, &[10])];
+    assert!(watch_edits(cfg(), &steps).await.is_empty());
+
+    // polled 10s apart, the tombstone expires before sshd is watched again
+    let steps: [(Edit, &[i32]); 9] =
+        [(Keep, &[10]), (Unwatch, &[10]), (Keep, &[]), (Keep, &[]), (Keep, &[]), (Keep, &[]), (Keep, &[]), (Keep, &[]), (Watch, &[11])];
+    assert!(watch_edits(cfg(), &steps).await.is_empty());
+}
+
+/// Notes when it was polled, then returns whatever the test put in last.
+struct Timed {
+    procs: Arc<Mutex<Snapshot>>,
+    polls: Arc<Mutex<Vec<tokio::time::Instant>>>,
+}
+
+#[async_trait]
+impl ProcBackend for Timed {
+    async fn list(&self) -> std::io::Result<Vec<(i32, String)>> {
+        self.polls.lock().unwrap().push(tokio::time::Instant::now());
+        Ok(self.procs.lock().unwrap().clone())
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn adaptive_interval_backs_off_and_snaps_back() {
+    let ms = Duration::from_millis(1);
+    let procs = Arc::new(Mutex::new(vec![(10, "sshd".to_string())]));
+    let polls = Arc::new(Mutex::new(Vec::new()));
+    let mut dog = ProcDog::new(Some(ProcDogConfig::default().adaptive_pulse(AdaptivePulse::new(ms * 10, ms * 80).idle_ticks(2))));
+    dog.set_backend(Timed { procs: procs.clone(), polls: polls.clone() });
+    dog.watch("sshd");
+    let pulse = dog.effective_pulse();
+    let (handle, task) = spawn_sensor(dog, Arc::new(CallbackHub::new()));
+
+    let gaps = |polls: &[tokio::time::Instant]| polls.windows(2).map(|w| (w[1] - w[0]).as_millis()).collect::<Vec<_>>();
+    tokio::time::sleep(ms * 500).await;
+    // priming poll, the ticker's immediate first tick, then doubling after every two idle ticks
+    assert_eq!(gaps(&polls.lock().unwrap())[..10], [0, 10, 20, 20, 40, 40, 80, 80, 80, 80]);
+    assert_eq!(pulse.get(), ms * 80);
+
+    polls.lock().unwrap().clear();
+    procs.lock().unwrap().clear();
+    tokio::time::sleep(ms * 200).await;
+    handle.shutdown();
+    let _ = task.await;
+    // the poll that saw sshd go fired Disappeared: the next one comes after 10ms
+    assert_eq!(gaps(&polls.lock().unwrap())[..5], [10, 10, 20, 20, 40]);
+}
+
+#[test]
+fn every_documented_field_resolves() {
+    let env = ProcEnv::Vars([("LANG".to_string(), "C".to_string())].into_iter().collect());
+    let samples = [
+        ProcDogEvent::Appeared { name: "sshd".to_string(), pid: 812, group_leader: None, env: Some(env), labels: Labels::default() },
+        ProcDogEvent::Disappeared { name: "sshd".to_string(), pid: 812, group_leader: None, labels: Labels::default() },
+        ProcDogEvent::Missing { name: "sshd".to_string(), labels: Labels::default() },
+        ProcDogEvent::Deviation { name: "sshd".to_string(), deviation: Deviation::Missing, pids: vec![], labels: Labels::default() },
+        ProcDogEvent::Deviation { name: "telnetd".to_string(), deviation: Deviation::Unexpected, pids: vec![23], labels: Labels::default() },
+        ProcDogEvent::Deviation {
+            name: "nginx".to_string(),
+            deviation: Deviation::mismatch("count", "at least 2", "1"),
+            pids: vec![80],
+            labels: Labels::default(),
+        },
+        ProcDogEvent::GroupGone { name: "postgres".to_string(), pgid: 100, labels: Labels::default() },
+    ];
+
+    for ev in &samples {
+        let json = serde_json::to_value(ev).unwrap();
+        let (variant, body) = json.as_object().unwrap().iter().next().unwrap();
+        assert!(omnitrace_core::fields::same_kind(ev.kind(), variant));
+        for name in ev.field_names() {
+            assert_eq!(ev.field(name).map(|v| v.to_json()).as_ref(), body.get(*name), "{}: {name}", ev.kind());
+        }
+        assert_eq!(ProcDogEvent::TOPICS[ev.topic_index()].mask, ev.mask().bits(), "{}", ev.topic());
+        assert_eq!((ev.mask().bits() & KIND_BITS).count_ones(), 1, "{}", ev.topic());
+        assert_eq!(ev.mask().bits() & RESERVED_BITS, 0);
+        assert_eq!(EventFields::topic(ev), Some(ev.topic()));
+    }
+    let topics: Vec<&str> = samples.iter().map(ProcDogEvent::topic).collect();
+    assert_eq!(topics, ProcDogEvent::TOPICS.iter().map(|t| t.name).collect::<Vec<_>>());
+    assert_eq!(samples[0].field("env.LANG"), Some(FieldValue::str("C")));
+    assert_eq!(samples[0].field("env.HOME"), None);
+    assert_eq!(samples[2].field("pid"), None);
+    assert_eq!(samples[5].field("expected"), Some(FieldValue::str("at least 2")));
+    assert_eq!(samples[4].field("field"), None);
+    assert_eq!(samples[1].field("group_leader"), None);
+    let member = ProcDogEvent::Disappeared { name: "postgres".to_string(), pid: 101, group_leader: Some(100), labels: Labels::default() };
+    assert_eq!(member.field("group_leader"), Some(FieldValue::int(100)));
+    assert_eq!(serde_json::to_value(&member).unwrap()["Disappeared"]["group_leader"], 100);
+}
+
+/// A backend whose listing fails, as with `ps` missing.
+struct NoPs;
+
+#[async_trait]
+impl ProcBackend for NoPs {
+    async fn list(&self) -> std::io::Result<Vec<(i32, String)>> {
+        Err(std::io::Error::new(std::io::ErrorKind::NotFound, "ps: not found"))
+    }
+}
+
+#[tokio::test]
+async fn preflight_lists_once_and_checks_selectors() {
+    async fn found(dog: &ProcDog) -> Vec<(Severity, String)> {
+        dog.preflight().await.into_iter().map(|f| (f.severity, f.message)).collect()
+    }
+
+    let mut dog = ProcDog::new(None);
+    dog.set_backend(NoPs);
+    assert_eq!(
+        found(&dog).await,
+        [(Severity::Warning, "no process names watched".to_string()), (Severity::Critical, "cannot list processes: ps: not found".to_string())]
+    );
+
+    // the scripted backend cannot read environments
+    let mut dog = ProcDog::new(None);
+    dog.set_backend(ScriptBackend { script: vec![vec![(1, "init".to_string()), (812, "sshd".to_string())]], calls: Arc::default() });
+    for name in NAMES {
+        dog.watch(name);
+    }
+    dog.ignore("postgres");
+    dog.capture_env(&["LANG"]);
+    let got = found(&dog).await;
+    assert_eq!(
+        got[..2],
+        [
+            (Severity::Warning, "postgres is both watched and ignored, it is never reported".to_string()),
+            (Severity::Info, "nginx is not running now".to_string())
+        ]
+    );
+    assert_eq!(got[2].0, Severity::Warning);
+    assert!(got[2].1.starts_with("environment captures are set"), "{got:?}");
+
+    dog.watch_env("APP_ENV", "prod*").unwrap();
+    assert_eq!(found(&dog).await[2].0, Severity::Critical);
+
+    let mut dog = ProcDog::new(None);
+    dog.set_backend(ScriptBackend { script: vec![Vec::new()], calls: Arc::default() });
+    dog.watch("sshd");
+    assert_eq!(found(&dog).await, [(Severity::Critical, "the process list is empty".to_string())]);
+}
+
+#[tokio::test]
+async fn previews_rules_against_the_last_listing_without_applying_them() {
+    let mut listing: Snapshot = (0..12).map(|i| (100 + i, "nginx".to_string())).collect();
+    listing.extend([(200, "sshd".to_string()), (300, "cron".to_string()), (301, "cron".to_string())]);
+    let mut dog = ProcDog::new(Some(ProcDogConfig::default().interval(Duration::from_millis(5))));
+    dog.set_backend(ScriptBackend { script: vec![listing], calls: Arc::new(AtomicUsize::new(0)) });
+    dog.watch("nginx");
+    dog.watch("sshd");
+    dog.ignore("sshd");
+    let (state, previews) = (dog.state_handle(), dog.preview_handle());
+    let (handle, task) = spawn_sensor(dog, Arc::new(CallbackHub::new()));
+
+    let nginx = previews.preview_rule("ignore nginx".parse().unwrap()).await.unwrap();
+    assert_eq!((nginx.would_match, nginx.would_stop_matching), (12, 12));
+    assert_eq!(nginx.samples.len(), 10);
+    assert_eq!(nginx.samples[..2], ["nginx[100]", "nginx[101]"]);
+
+    let cron = previews.preview_rule(ProcDogRule::Watch("cron".into())).await.unwrap();
+    assert_eq!((cron.would_match, cron.would_stop_matching, cron.samples), (2, 0, vec!["cron[300]".to_string(), "cron[301]".to_string()]));
+    // ignored names stay ignored when watched
+    assert_eq!(previews.preview_rule(ProcDogRule::Watch("sshd".into())).await.unwrap().would_match, 0);
+
+    assert_eq!(state.pids("nginx").len(), 12);
+    assert!(state.pids("cron").is_empty());
+    handle.shutdown();
+    let _ = task.await;
+    assert!("kill nginx".parse::<ProcDogRule>().is_err());
+}
+
+/// Asks the state handle about the pid of every event, noting the answer and how long it took.
+struct AsksState {
+    state: crate::ProcDogState,
+    seen: Arc<Mutex<Vec<(ProcDogEvent, bool)>>>,
+    slowest: Arc<Mutex<Duration>>,
+}
+
+#[async_trait]
+impl Callback<ProcDogEvent> for AsksState {
+    fn mask(&self) -> u64 {
+        (ProcDogMask::APPEARED | ProcDogMask::DISAPPEARED).bits()
+    }
+
+    async fn call(&self, ev: &ProcDogEvent) -> Option<CallbackResult> {
+        let (ProcDogEvent::Appeared { name, pid, .. } | ProcDogEvent::Disappeared { name, pid, .. }) = ev else {
+            return None;
+        };
+        let started = std::time::Instant::now();
+        let tracked = self.state.pids(name).contains(pid);
+        let took = started.elapsed();
+        let mut slowest = self.slowest.lock().unwrap();
+        *slowest = (*slowest).max(took);
+        self.seen.lock().unwrap().push((ev.clone(), tracked));
+        None
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn callbacks_read_the_state_of_the_previous_poll_without_blocking() {
+    let script: Vec<Snapshot> = vec![vec![(10, "sshd".into())], vec![(10, "sshd".into()), (11, "sshd".into())], vec![(11, "sshd".into())]];
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut dog = ProcDog::new(Some(ProcDogConfig::default().interval(Duration::from_millis(2))));
+    dog.set_backend(ScriptBackend { script, calls: calls.clone() });
+    dog.watch("sshd");
+    let state = dog.state_handle();
+
+    // another writer keeps publishing big snapshots meanwhile
+    let stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let writer = {
+        let (state, stop) = (state.clone(), stop.clone());
+        std::thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                state.set("noise", (0..5000).collect());
+            }
+        })
+    };
+
+    let (seen, slowest) = (Arc::default(), Arc::default());
+    let mut hub = CallbackHub::new();
+    hub.add(AsksState { state: state.clone(), seen: Arc::clone(&seen), slowest: Arc::clone(&slowest) });
+    let (handle, task) = spawn_sensor(dog, Arc::new(hub));
+    while calls.load(Ordering::SeqCst) < 4 {
+        tokio::time::sleep(Duration::from_millis(2)).await;
+    }
+    handle.shutdown();
+    task.await.unwrap();
+    stop.store(true, Ordering::Relaxed);
+    writer.join().unwrap();
+
+    // the appeared pid is not tracked yet, the disappeared one still is
+    let seen = seen.lock().unwrap().iter().map(|(ev, tracked)| (ev.mask().bits(), *tracked)).collect::<Vec<_>>();
+    assert_eq!(seen, [(ProcDogMask::APPEARED.bits(), false), (ProcDogMask::DISAPPEARED.bits(), true)]);
+    assert_eq!(state.pids("sshd"), HashSet::from([11]));
+    assert!(*slowest.lock().unwrap() < Duration::from_millis(100), "{:?}", slowest.lock().unwrap());
+}
+
+/// Listing `n` has sshd on even polls, nginx on three out of six, and pid `1000 + n` to tell
+/// listings apart.
+struct Alternating(Arc<AtomicUsize>);
+
+#[async_trait]
+impl ProcBackend for Alternating {
+    async fn list(&self) -> std::io::Result<Vec<(i32, String)>> {
+        let n = self.0.fetch_add(1, Ordering::SeqCst);
+        let mut listing = vec![(1000 + n as i32, "marker".to_string())];
+        if n.is_multiple_of(2) {
+            listing.push((10, "sshd".to_string()));
+        }
+        if n % 6 < 3 {
+            listing.push((20, "nginx".to_string()));
+        }
+        Ok(listing)
+    }
+}
+
+/// Notes the markers of the listings an instance got.
+struct Tap(SharedBackend, Arc<Mutex<Vec<i32>>>);
+
+#[async_trait]
+impl ProcBackend for Tap {
+    async fn list(&self) -> std::io::Result<Vec<(i32, String)>> {
+        let listing = self.0.list().await?;
+        self.1.lock().unwrap().extend(listing.iter().filter(|(_, name)| name == "marker").map(|(pid, _)| *pid));
+        Ok(listing)
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn instances_sharing_a_backend_list_once_per_tick() {
+    let polls = Arc::new(AtomicUsize::new(0));
+    let shared = SharedBackend::new(Alternating(polls.clone()), Duration::from_millis(100));
+    let instance = |interval: u64, name: &str| {
+        let (seen, markers) = (Arc::new(Mutex::new(Vec::new())), Arc::new(Mutex::new(Vec::new())));
+        let mut dog = ProcDog::new(Some(ProcDogConfig::default().interval(Duration::from_millis(interval))));
+        dog.set_backend(Tap(shared.clone(), markers.clone()));
+        dog.watch(name);
+        let mut hub = CallbackHub::new();
+        hub.add(Collect(seen.clone()));
+        (spawn_sensor(dog, Arc::new(hub)), seen, markers)
+    };
+    let ((fast, fast_task), fast_seen, fast_markers) = instance(100, "sshd");
+    let ((slow, slow_task), slow_seen, slow_markers) = instance(300, "nginx");
+    tokio::time::sleep(Duration::from_millis(1250)).await;
+    fast.shutdown();
+    slow.shutdown();
+    let _ = (fast_task.await, slow_task.await);
+
+    let (fast_markers, slow_markers) = (fast_markers.lock().unwrap().clone(), slow_markers.lock().unwrap().clone());
+    // one read per beat; the slow instance got the listings the fast one got at its ticks
+    let beats: HashSet<_> = fast_markers.iter().collect();
+    assert_eq!(polls.load(Ordering::SeqCst), beats.len());
+    assert_eq!(shared.source().reads() + shared.source().shared(), (fast_markers.len() + slow_markers.len()) as u64);
+    assert!(slow_markers.len() >= 4, "{slow_markers:?}");
+    for marker in &slow_markers {
+        assert!(fast_markers.contains(marker), "{marker} not in {fast_markers:?}");
+    }
+
+    // each reported after its own rules
+    let names = |seen: &Mutex<Vec<ProcDogEvent>>| seen.lock().unwrap().iter().map(|ev| ev.name().to_string()).collect::<HashSet<_>>();
+    assert_eq!(names(&fast_seen), HashSet::from(["sshd".to_string()]));
+    assert_eq!(names(&slow_seen), HashSet::from(["nginx".to_string()]));
+}
+
+#[test]
+fn events_format_as_pinned_lines() {
+    let env = ProcEnv::Vars([("LANG".to_string(), "C".to_string())].into_iter().collect());
+    let samples = [
+        ProcDogEvent::Appeared { name: "sshd".to_string(), pid: 812, group_leader: None, env: Some(env), labels: Labels::default() },
+        ProcDogEvent::Missing { name: "cron".to_string(), labels: Labels::default() },
+    ];
+    let lines: Vec<String> = samples.iter().flat_map(|ev| [format_short(ev), format_long(ev)]).collect();
+    assert_eq!(
+        lines,
+        [
+            // the captured environment is left to the JSON
+            "appeared name=sshd pid=812",
+            "appeared name=sshd pid=812",
+            "missing name=cron",
+            "missing name=cron",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn history_keeps_the_last_events_per_name_and_pid() {
+    let workers = |base: i32| -> Snapshot { (0..20).map(|i| (base + i, "nginx".to_string())).collect() };
+    let script = vec![vec![(300, "sshd".to_string())], workers(1000), workers(2000)];
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    let cfg = ProcDogConfig::default().interval(Duration::from_millis(1)).history(HistoryConfig::new(8, 1000));
+    let mut dog = ProcDog::new(Some(cfg));
+    dog.set_backend(ScriptBackend { script: script.clone(), calls: calls.clone() });
+    for name in NAMES {
+        dog.watch(name);
+    }
+    let history = dog.history().unwrap();
+    let debug = dog.debug_handle();
+    let (handle, task) = spawn_sensor(dog, Arc::new(CallbackHub::new()));
+    while calls.load(Ordering::SeqCst) <= script.len() {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    handle.shutdown();
+    let _ = task.await;
+
+    // 20 appeared, then 20 appeared and 20 disappeared: the last 8, oldest first
+    let nginx: Vec<i32> = history
+        .history("nginx", 100)
+        .into_iter()
+        .map(|e| match e.event {
+            ProcDogEvent::Disappeared { pid, .. } => pid,
+            other => panic!("unexpected {other:?}"),
+        })
+        .collect();
+    assert_eq!(nginx, (1012..1020).collect::<Vec<_>>());
+    assert_eq!(history.history("nginx", 2).len(), 2);
+
+    let pid = history.history("pid:1003", 10);
+    assert!(
+        matches!(pid.as_slice(), [a, d] if matches!(a.event, ProcDogEvent::Appeared { .. }) && matches!(d.event, ProcDogEvent::Disappeared { .. }))
+    );
+    assert!(matches!(history.history("sshd", 10).as_slice(), [e] if matches!(e.event, ProcDogEvent::Disappeared { pid: 300, .. })));
+    assert_eq!(history.keys()[..2], ["pid:1019", "nginx"]);
+    assert_eq!(omnitrace_core::debug::Debuggable::debug_snapshot(&debug)["history"]["nginx"].as_array().unwrap().len(), 8);
+}
+
+#[test]
+fn history_is_off_by_default() {
+    let dog = ProcDog::new(None);
+    assert!(dog.history().is_none());
+}
+
+/// Plays back listings with process groups, one per poll.
+struct GroupScript {
+    script: Vec<Vec<ProcInfo>>,
+    calls: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl ProcBackend for GroupScript {
+    async fn list(&self) -> std::io::Result<Vec<(i32, String)>> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+
+    async fn list_info(&self) -> std::io::Result<Vec<ProcInfo>> {
+        let n = self.calls.fetch_add(1, Ordering::SeqCst);
+        Ok(self.script[n.min(self.script.len() - 1)].clone())
+    }
+}
+
+#[tokio::test]
+async fn process_groups_follow_members_and_leader_restarts() {
+    let pg = |pid: i32, name: &str, pgid: i32| ProcInfo::new(pid, name, pgid, 1);
+    let script = vec![
+        // postgres leads group 100; a stray postgres in bash's group is no leader
+        vec![pg(100, "postgres", 100), pg(101, "postgres", 100), pg(102, "postgres", 100), pg(200, "bash", 200), pg(201, "postgres", 200)],
+        // a worker joins, another leaves
+        vec![pg(100, "postgres", 100), pg(102, "postgres", 100), pg(103, "walwriter", 100), pg(200, "bash", 200)],
+        // the leader exits, its workers keep the group
+        vec![pg(102, "postgres", 100), pg(103, "walwriter", 100)],
+        // restarted as a new group
+        vec![pg(500, "postgres", 500), pg(501, "postgres", 500)],
+    ];
+    let mut dog = ProcDog::new(None);
+    dog.set_backend(GroupScript { script, calls: Arc::default() });
+    dog.watch_pgid_of("postgres").unwrap();
+    let state = dog.state_handle();
+    let debug = dog.debug_handle();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Collect(seen.clone()));
+
+    dog.prime(&hub).await;
+    assert!(seen.lock().unwrap().is_empty());
+    assert_eq!(state.pids("postgres"), HashSet::from([100, 101, 102]));
+
+    let mut polls = Vec::new();
+    for _ in 0..3 {
+        dog.tick_once(&hub).await;
+        let events = std::mem::take(&mut *seen.lock().unwrap());
+        polls.push(
+            events
+                .iter()
+                .map(|ev| match ev {
+                    ProcDogEvent::Appeared { name, pid, group_leader, .. } => (name.as_str(), "appeared", *pid, *group_leader),
+                    ProcDogEvent::Disappeared { name, pid, group_leader, .. } => (name.as_str(), "disappeared", *pid, *group_leader),
+                    ProcDogEvent::GroupGone { name, pgid, .. } => (name.as_str(), "group_gone", *pgid, None),
+                    other => panic!("unexpected {other:?}"),
+                })
+                .map(|(name, kind, pid, leader)| (name.to_string(), kind, pid, leader))
+                .collect::<Vec<_>>(),
+        );
+        if polls.len() == 2 {
+            assert_eq!(state.pids("postgres"), HashSet::from([102, 103]));
+            assert_eq!(omnitrace_core::debug::Debuggable::debug_snapshot(&debug)["groups"]["postgres"]["100"], serde_json::json!([102, 103]));
+        }
+    }
+    let p = "postgres".to_string();
+    assert_eq!(
+        polls,
+        [
+            vec![(p.clone(), "appeared", 103, Some(100)), (p.clone(), "disappeared", 101, Some(100))],
+            vec![(p.clone(), "disappeared", 100, Some(100))],
+            vec![
+                (p.clone(), "disappeared", 102, Some(100)),
+                (p.clone(), "disappeared", 103, Some(100)),
+                (p.clone(), "group_gone", 100, None),
+                (p.clone(), "appeared", 500, Some(500)),
+                (p.clone(), "appeared", 501, Some(500)),
+            ],
+        ]
+    );
+    assert_eq!(state.name_of(501).as_deref(), Some("postgres"));
+}
+
+#[tokio::test]
+async fn watched_groups_need_a_backend_listing_them() {
+    let mut dog = ProcDog::new(None);
+    dog.set_backend(ScriptBackend { script: vec![vec![(1, "init".to_string())]], calls: Arc::default() });
+    dog.watch("init");
+    dog.watch_pgid_of("postgres").unwrap();
+    let findings = dog.preflight().await;
+    assert!(findings.iter().any(|f| f.severity == Severity::Critical && f.message.contains("process groups")), "{findings:?}");
+
+    // each poll fails like a failed listing
+    dog.tick_once(&CallbackHub::new()).await;
+    assert!(dog.diagnostics().get("list").is_some());
+}
@@ -3,7 +3,6 @@ use crate::backends::netstat_cmd::NetstatBackend;
 use crate::events::SockKey;
 use std::collections::HashSet;
 use std::ffi::CString;
-use std::ffi::CStr;
 use std::io;
 use std::os::raw::{c_char, c_int};
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -72,8 +71,12 @@ pub fn supported() -> bool {
     true
 }
 
+/// `buf` up to its first NUL, or all of it: the C side does not promise to terminate a
+/// full field. Reinterpreted as bytes, since `c_char` is signed on some targets only.
 fn c_array_to_string(buf: &[c_char]) -> String {
-    unsafe { CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned() }
+    let bytes = unsafe { std::slice::from_raw_parts(buf.as_ptr().cast::<u8>(), buf.len()) };
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
 }
 
 fn proto_name(kind: c_int) -> Option<&'static str> {
@@ -1,4 +1,8 @@
 use bitflags::bitflags;
+use omnitrace_core::{
+    fields::{EventFields, FieldValue},
+    topics::{self, Topic, Topics},
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
@@ -34,4 +38,62 @@ impl SockTrayEvent {
             SockTrayEvent::Closed { .. } => SockTrayMask::CLOSED,
         }
     }
+
+    /// Dotted topic, see [`omnitrace_core::topics`].
+    pub fn topic(&self) -> &'static str {
+        topics::topic_of(self)
+    }
+}
+
+impl Topics for SockTrayEvent {
+    const TOPICS: &'static [Topic] =
+        &[Topic::new("sock.opened", SockTrayMask::OPENED.bits()), Topic::new("sock.closed", SockTrayMask::CLOSED.bits())];
+
+    fn topic_index(&self) -> usize {
+        match self {
+            SockTrayEvent::Opened { .. } => 0,
+            SockTrayEvent::Closed { .. } => 1,
+        }
+    }
+}
+
+/// The fields of [`SockKey`] by name, also under `sock.` (`sock.proto`).
+impl EventFields for SockTrayEvent {
+    fn kind(&self) -> &'static str {
+        match self {
+            SockTrayEvent::Opened { .. } => "opened",
+            SockTrayEvent::Closed { .. } => "closed",
+        }
+    }
+
+    fn field(&self, name: &str) -> Option<FieldValue<'_>> {
+        let (SockTrayEvent::Opened { sock } | SockTrayEvent::Closed { sock }) = self;
+        match name.strip_prefix("sock.").unwrap_or(name) {
+            "proto" => Some(FieldValue::str(&sock.proto)),
+            "local" => Some(FieldValue::str(&sock.local)),
+            "remote" => Some(FieldValue::str(&sock.remote)),
+            "state" => sock.state.as_deref().map(FieldValue::str),
+            "local_dec" => sock.local_dec.as_deref().map(FieldValue::str),
+            "remote_dec" => sock.remote_dec.as_deref().map(FieldValue::str),
+            "state_dec" => sock.state_dec.as_deref().map(FieldValue::str),
+            "remote_host" => sock.remote_host.as_deref().map(FieldValue::str),
+            _ => None,
+        }
+    }
+
+    fn field_names(&self) -> &'static [&'static str] {
+        &["proto", "local", "remote", "state", "local_dec", "remote_dec", "state_dec", "remote_host", "sock.proto"]
+    }
+
+    fn summary_fields(&self) -> &'static [&'static str] {
+        &["proto", "local_dec|local", "remote_dec|remote", "state_dec|state", "remote_host"]
+    }
+
+    fn detail_fields(&self) -> &'static [&'static str] {
+        &["proto", "local", "remote", "state", "local_dec", "remote_dec", "state_dec", "remote_host"]
+    }
+
+    fn topic(&self) -> Option<&'static str> {
+        Some(SockTrayEvent::topic(self))
+    }
 }
@@ -1,12 +1,15 @@
 pub mod backends;
 pub mod events;
+pub mod prelude;
 
 use crate::events::SockTrayEvent;
 use glob::Pattern;
 use omnitrace_core::{
     callbacks::CallbackHub,
+    debug::DebugCell,
     sensor::{Sensor, SensorCtx},
 };
+use serde::Serialize;
 use std::{
     collections::{HashMap, HashSet},
     ffi::CStr,
@@ -59,6 +62,19 @@ impl SockTrayConfig {
     }
 }
 
+/// What [`SockTray::debug_handle`] reports, refreshed every tick.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SockTrayDebug {
+    pub pulse_ms: u64,
+    pub dns: bool,
+    pub primed: bool,
+    /// Sockets in the last listing.
+    pub sockets: usize,
+    pub watch: Vec<String>,
+    pub ignore: Vec<String>,
+    pub dns_cache_entries: usize,
+}
+
 pub struct SockTray {
     cfg: SockTrayConfig,
     backend: Arc<dyn SockBackend>,
@@ -67,6 +83,7 @@ pub struct SockTray {
     watch: Vec<Pattern>,
     ignore: Vec<Pattern>,
     dns_cache: HashMap<IpAddr, (String, Instant)>,
+    debug: DebugCell<SockTrayDebug>,
 }
 
 impl Default for SockTray {
@@ -105,6 +122,7 @@ impl SockTray {
             watch: Vec::new(),
             ignore: Vec::new(),
             dns_cache: HashMap::new(),
+            debug: DebugCell::default(),
         }
     }
 
@@ -115,6 +133,26 @@ impl SockTray {
         self.backend = Arc::new(backend);
     }
 
+    /// Handle on a summary of the sensor's state, for [`omnitrace_core::debug::Snapshots`].
+    pub fn debug_handle(&self) -> DebugCell<SockTrayDebug> {
+        self.debug.clone()
+    }
+
+    fn publish_debug(&self) {
+        let strs = |v: &[Pattern]| v.iter().map(|p| p.as_str().to_string()).collect();
+        self.debug.update(|d| {
+            *d = SockTrayDebug {
+                pulse_ms: self.cfg.pulse.as_millis() as u64,
+                dns: self.cfg.dns,
+                primed: self.primed,
+                sockets: self.last.len(),
+                watch: strs(&self.watch),
+                ignore: strs(&self.ignore),
+                dns_cache_entries: self.dns_cache.len(),
+            }
+        });
+    }
+
     pub fn add(&mut self, pat: &str) {
         if let Ok(p) = Pattern::new(pat) {
             self.watch.push(p);
@@ -127,7 +165,7 @@ impl SockTray {
         }
     }
 
-    async fn fire(hub: &CallbackHub<SockTrayEvent>, ev: SockTrayEvent) {
+    async fn fire<R: Send + 'static>(hub: &CallbackHub<SockTrayEvent, R>, ev: SockTrayEvent) {
         hub.fire(ev.mask().bits(), &ev).await;
     }
 
@@ -277,10 +315,11 @@ impl SockTray {
         s.remote_host = self.dns_cached(ip);
     }
 
-    pub async fn run(mut self, ctx: SensorCtx<SockTrayEvent>) {
+    pub async fn run<R: Send + 'static>(mut self, ctx: SensorCtx<SockTrayEvent, R>) {
         let mut ticker = tokio::time::interval(self.cfg.pulse);
 
         loop {
+            ctx.beat();
             tokio::select! {
                 _ = ctx.cancel.cancelled() => break,
                 _ = ticker.tick() => {}
@@ -297,6 +336,7 @@ impl SockTray {
             if !self.primed {
                 self.last = now;
                 self.primed = true;
+                self.publish_debug();
                 continue;
             }
 
@@ -317,6 +357,7 @@ impl SockTray {
             }
 
             self.last = now;
+            self.publish_debug();
         }
     }
 }
@@ -324,7 +365,7 @@ impl SockTray {
 impl Sensor for SockTray {
     type Event = SockTrayEvent;
 
-    fn run(self, ctx: SensorCtx<Self::Event>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+    fn run<R: Send + 'static>(self, ctx: SensorCtx<Self::Event, R>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
         Box::pin(async move { SockTray::run(self, ctx).await })
     }
 }
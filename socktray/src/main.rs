@@ -1,13 +1,13 @@
-use async_trait::async_trait;
-use omnitrace_core::callbacks::{Callback, CallbackHub, CallbackResult};
-use omnitrace_core::sensor::spawn_sensor;
+use omnitrace_core::{
+    filter::Filter,
+    format::Style,
+    preflight::{self, PreflightArgs},
+};
+use socktray::prelude::*;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc::channel;
 
-use socktray::events::{SockTrayEvent, SockTrayMask};
-use socktray::{SockTray, SockTrayConfig};
-
 struct PrintCb;
 
 #[async_trait]
@@ -17,49 +17,32 @@ impl Callback<SockTrayEvent> for PrintCb {
     }
 
     async fn call(&self, ev: &SockTrayEvent) -> Option<CallbackResult> {
-        let (kind, s) = match ev {
-            SockTrayEvent::Opened { sock } => ("opened", sock),
-            SockTrayEvent::Closed { sock } => ("closed", sock),
-        };
-
-        let remote_pretty = match (&s.remote_dec, &s.remote_host) {
-            (Some(ip_port), Some(host)) => format!("{ip_port} ({host})"),
-            (Some(ip_port), None) => ip_port.clone(),
-            (None, Some(host)) => format!("{} ({host})", s.remote),
-            (None, None) => s.remote.clone(),
-        };
-
-        println!(
-            "{} {} {} -> {} [{}]",
-            kind,
-            s.proto,
-            s.local_dec.as_deref().unwrap_or(&s.local),
-            remote_pretty,
-            s.state_dec.as_deref().or(s.state.as_deref()).unwrap_or("-")
-        );
-
+        println!("{}", Style::for_stdout().short(ev));
         None
     }
 }
 
 #[tokio::main]
 async fn main() {
+    let filter = Filter::from_env_args();
+
     // Use a tighter pulse in demo mode so short-lived client sockets are less likely to be missed.
     let mut sensor = SockTray::new(Some(
-        SockTrayConfig::default()
-            .pulse(Duration::from_millis(250))
-            .dns(true)
-            .dns_ttl(Duration::from_secs(30))
-            .skip_reverse_dns(true),
+        SockTrayConfig::default().pulse(Duration::from_millis(250)).dns(true).dns_ttl(Duration::from_secs(30)).skip_reverse_dns(true),
     ));
     sensor.add("*");
     sensor.ignore("udp * * *");
 
+    preflight::gate("socktray", &sensor, PreflightArgs::from_env_args()).await;
+
     let (tx, mut rx) = channel::<CallbackResult>(0xfff);
 
     let mut hub = CallbackHub::<SockTrayEvent>::new();
     hub.add(PrintCb);
     hub.set_result_channel(tx);
+    if let Some(f) = &filter {
+        hub.set_filter(f.predicate());
+    }
     let hub = Arc::new(hub);
 
     let rx_task = tokio::spawn(async move {
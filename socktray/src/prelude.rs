@@ -0,0 +1,6 @@
+//! `use socktray::prelude::*;` brings in the SockTray sensor, its config and events,
+//! and the core prelude (callbacks, hub, `spawn_sensor`).
+
+pub use crate::events::{SockKey, SockTrayEvent, SockTrayMask};
+pub use crate::{SockTray, SockTrayConfig};
+pub use omnitrace_core::prelude::*;
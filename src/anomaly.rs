@@ -0,0 +1,107 @@
+//! Raw capture of the lines a sensor's parser rejects, for when a kernel adds a field or
+//! emits something unexpected.
+//!
+//! Sensors reading text tables (xmount its mountinfo, netpacket the `/proc/net` connection
+//! tables) skip the lines they cannot parse and always count them in their [`ParseStats`].
+//! With capture on, the first ones of each read, up to a cap, are also fired as a
+//! `ParseAnomaly` event with a [`ParseAnomaly`]: the raw line, the file it came from, and
+//! where and why parsing failed. Those over the cap are only counted, as `dropped`.
+
+use crate::fields::FieldValue;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Lines captured per read by default.
+pub const DEFAULT_CAPTURE_PER_TICK: usize = 16;
+
+/// Where and why a parser rejected a line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseFailure {
+    /// 1-based whitespace-separated column, one past the last for a missing one.
+    pub column: usize,
+    pub reason: String,
+}
+
+impl ParseFailure {
+    pub fn new<S: Into<String>>(column: usize, reason: S) -> Self {
+        Self { column, reason: reason.into() }
+    }
+}
+
+/// A rejected line as captured.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParseAnomaly {
+    /// The file the line was read from.
+    #[serde(with = "crate::paths")]
+    pub source: PathBuf,
+    /// The line as read, invalid UTF-8 replaced.
+    pub raw: String,
+    pub column: usize,
+    pub reason: String,
+}
+
+impl ParseAnomaly {
+    pub fn new<P: Into<PathBuf>, S: Into<String>>(source: P, raw: S, failure: ParseFailure) -> Self {
+        Self { source: source.into(), raw: raw.into(), column: failure.column, reason: failure.reason }
+    }
+
+    /// Its fields by name, for the [`crate::fields::EventFields`] of the events carrying it.
+    pub const FIELDS: &'static [&'static str] = &["source", "raw", "column", "reason"];
+
+    pub fn field(&self, name: &str) -> Option<FieldValue<'_>> {
+        Some(match name {
+            "source" => FieldValue::Path(&self.source),
+            "raw" => FieldValue::str(&self.raw),
+            "column" => FieldValue::count(self.column as u64),
+            "reason" => FieldValue::str(&self.reason),
+            _ => return None,
+        })
+    }
+}
+
+/// Lines rejected since the sensor started.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ParseStats {
+    pub failures: u64,
+    /// Fired as events.
+    pub captured: u64,
+    /// Over the cap while capturing.
+    pub dropped: u64,
+}
+
+/// Counts a sensor's parse failures and picks the ones to capture.
+#[derive(Clone, Debug, Default)]
+pub struct AnomalyCapture {
+    per_tick: Option<usize>,
+    stats: ParseStats,
+}
+
+impl AnomalyCapture {
+    /// Counting only with `None`, else also capturing up to `per_tick` lines a read.
+    pub fn new(per_tick: Option<usize>) -> Self {
+        Self { per_tick, stats: ParseStats::default() }
+    }
+
+    pub fn capturing(&self) -> bool {
+        self.per_tick.is_some()
+    }
+
+    /// Count the failures of one read, returning those to capture, in order.
+    pub fn read<T, I: IntoIterator<Item = T>>(&mut self, failures: I) -> Vec<T> {
+        let mut out = Vec::new();
+        for f in failures {
+            self.stats.failures += 1;
+            match self.per_tick {
+                Some(cap) if out.len() < cap => out.push(f),
+                Some(_) => self.stats.dropped += 1,
+                None => {}
+            }
+        }
+        self.stats.captured += out.len() as u64;
+        out
+    }
+
+    pub fn stats(&self) -> ParseStats {
+        self.stats
+    }
+}
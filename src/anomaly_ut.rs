@@ -0,0 +1,24 @@
+use crate::anomaly::{AnomalyCapture, ParseAnomaly, ParseFailure, ParseStats};
+use serde_json::json;
+
+#[test]
+fn counts_always_and_captures_up_to_the_cap() {
+    let mut off = AnomalyCapture::default();
+    assert!(!off.capturing());
+    assert!(off.read([1, 2, 3]).is_empty());
+    assert_eq!(off.stats(), ParseStats { failures: 3, captured: 0, dropped: 0 });
+
+    let mut on = AnomalyCapture::new(Some(2));
+    assert_eq!(on.read([1, 2, 3]), [1, 2]);
+    assert_eq!(on.read([4]), [4], "the cap is per read");
+    assert_eq!(on.stats(), ParseStats { failures: 4, captured: 3, dropped: 1 });
+}
+
+#[test]
+fn anomalies_serialize_with_the_raw_line() {
+    let a = ParseAnomaly::new("/proc/self/mountinfo", "41 22 truncated", ParseFailure::new(4, "bad root"));
+    assert_eq!(
+        serde_json::to_value(&a).unwrap(),
+        json!({ "source": "/proc/self/mountinfo", "raw": "41 22 truncated", "column": 4, "reason": "bad root" })
+    );
+}
@@ -0,0 +1,486 @@
+//! Tamper-evident JSONL event log.
+//!
+//! Every line is one record carrying the hash of the record before it, and its own hash
+//! over that previous hash plus its canonical JSON (keys sorted, no whitespace):
+//!
+//! ```text
+//! hash = blake3(prev_hash || canonical(record without "hash"))
+//! ```
+//!
+//! Changing, dropping or reordering a record breaks the chain at that record, which
+//! [`verify`] reports. The latest hash is also kept in a separate head file
+//! (`<log>.head`), so cutting records off the end is caught as well.
+//!
+//! When the log is rotated, the old file ends with a `"sealed": true` record whose hash is
+//! the final chain value, and the new file starts with a record `"continues"` pointing at
+//! the old file, chained to that value. [`verify_files`] checks such a sequence.
+//!
+//! With [`AuditSink::compression`] the log is written as a zstd or gzip stream, e.g.
+//! `audit.jsonl.zst`, flushed after every record. The hash is over the canonical record,
+//! not over the bytes on disk, so [`verify`] checks a log in either form, and a chain
+//! stays intact across a switch between them.
+
+use crate::{
+    callbacks::CallbackResult,
+    clock::{self, SharedClock},
+    compress::{self, Algorithm, Compression, Encoder},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, Read, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+/// `prev` of the very first record.
+pub const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One line of the log.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Counts up across rotations.
+    pub seq: u64,
+    /// Unix time in milliseconds.
+    pub ts: u64,
+    pub prev: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<Value>,
+    /// Last record of a rotated file.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sealed: bool,
+    /// First record of a file after rotation: name of the sealed file it follows.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub continues: Option<String>,
+    #[serde(default)]
+    pub hash: String,
+}
+
+impl AuditRecord {
+    /// Hash of this record chained to `prev`, ignoring the stored `hash`.
+    pub fn compute_hash(&self) -> String {
+        let mut body = serde_json::to_value(self).unwrap_or(Value::Null);
+        if let Value::Object(map) = &mut body {
+            map.remove("hash");
+        }
+        let mut h = blake3::Hasher::new();
+        h.update(blake3::Hash::from_hex(&self.prev).map(|p| *p.as_bytes()).unwrap_or_default().as_slice());
+        h.update(canonical(&body).as_bytes());
+        h.finalize().to_hex().to_string()
+    }
+}
+
+/// Chain head, persisted next to the log after every write.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChainHead {
+    pub seq: u64,
+    pub hash: String,
+}
+
+/// Where and why [`verify`] stopped trusting a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainBreak {
+    pub path: PathBuf,
+    /// 1-based line of the first bad record.
+    pub line: usize,
+    /// Its sequence number, if it parsed at all.
+    pub seq: Option<u64>,
+    pub reason: String,
+}
+
+impl fmt::Display for ChainBreak {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.reason)?;
+        if let Some(seq) = self.seq {
+            write!(f, " (seq {seq})")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ChainBreak {}
+
+/// What a clean [`verify`] found.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChainSummary {
+    pub records: usize,
+    /// `prev` of the first record: [`GENESIS`] or the final hash of the file it continues.
+    pub start: String,
+    /// Hash of the last record.
+    pub head: String,
+    pub last_seq: Option<u64>,
+    pub sealed: bool,
+    pub continues: Option<String>,
+}
+
+/// Re-walk one log file and report the first record that does not chain up: unparsable,
+/// out of sequence, not linked to its predecessor, or with a hash that does not match its
+/// content. Also checks the head file if there is one.
+pub fn verify<P: AsRef<Path>>(path: P) -> Result<ChainSummary, ChainBreak> {
+    let path = path.as_ref();
+    let brk = |line: usize, seq: Option<u64>, reason: String| ChainBreak { path: path.to_path_buf(), line, seq, reason };
+    let file = File::open(path).map_err(|e| brk(0, None, format!("cannot open: {e}")))?;
+    let (file, _) = compress::reader(file).map_err(|e| brk(0, None, format!("cannot read: {e}")))?;
+
+    let mut sum = ChainSummary::default();
+    for (i, line) in file.lines().enumerate() {
+        let line_no = i + 1;
+        let line = line.map_err(|e| brk(line_no, None, format!("cannot read: {e}")))?;
+        let rec: AuditRecord = serde_json::from_str(&line).map_err(|e| brk(line_no, None, format!("not a record: {e}")))?;
+        let seq = Some(rec.seq);
+
+        if sum.sealed {
+            return Err(brk(line_no, seq, "record after the seal".to_string()));
+        }
+        match sum.last_seq {
+            None => {
+                if rec.prev != GENESIS && rec.continues.is_none() {
+                    return Err(brk(line_no, seq, "first record neither starts nor continues a chain".to_string()));
+                }
+                sum.start = rec.prev.clone();
+                sum.continues = rec.continues.clone();
+            }
+            Some(last) => {
+                if rec.seq != last + 1 {
+                    return Err(brk(line_no, seq, format!("sequence jumps from {last}")));
+                }
+                if rec.prev != sum.head {
+                    return Err(brk(line_no, seq, "not linked to the previous record".to_string()));
+                }
+                if rec.continues.is_some() {
+                    return Err(brk(line_no, seq, "continuation record inside a file".to_string()));
+                }
+            }
+        }
+        if rec.compute_hash() != rec.hash {
+            return Err(brk(line_no, seq, "hash does not match the record".to_string()));
+        }
+
+        sum.records += 1;
+        sum.head = rec.hash;
+        sum.last_seq = seq;
+        sum.sealed = rec.sealed;
+    }
+
+    if let Some(head) = read_head(&head_path(path)) {
+        if sum.last_seq.is_none_or(|s| s < head.seq) {
+            return Err(brk(sum.records + 1, Some(head.seq), "records missing at the end, the head file is further".to_string()));
+        }
+        if sum.last_seq == Some(head.seq) && sum.head != head.hash {
+            return Err(brk(sum.records, sum.last_seq, "last record does not match the head file".to_string()));
+        }
+    }
+    Ok(sum)
+}
+
+/// Verify rotated files, oldest first, and that each continues the seal of the one before.
+pub fn verify_files<P: AsRef<Path>>(paths: &[P]) -> Result<ChainSummary, ChainBreak> {
+    let mut prev: Option<ChainSummary> = None;
+    for path in paths {
+        let sum = verify(path)?;
+        if let Some(p) = &prev
+            && (!p.sealed || sum.start != p.head)
+        {
+            let path = path.as_ref().to_path_buf();
+            return Err(ChainBreak { path, line: 1, seq: None, reason: "does not continue the sealed file before it".to_string() });
+        }
+        prev = Some(sum);
+    }
+    Ok(prev.unwrap_or_default())
+}
+
+/// Appends events to a hash-chained JSONL file, see the module docs.
+///
+/// Opening an existing log verifies it first and continues its chain, in the form it was
+/// written in; a broken log is refused with [`io::ErrorKind::InvalidData`] rather than
+/// extended.
+pub struct AuditSink {
+    path: PathBuf,
+    file: LogFile,
+    /// Size with no events in it yet: 0, or the continuation record after a rotation.
+    empty_size: u64,
+    max_bytes: Option<u64>,
+    compression: Option<Compression>,
+    head: ChainHead,
+    next_seq: u64,
+    clock: SharedClock,
+}
+
+impl AuditSink {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut head = ChainHead { seq: 0, hash: GENESIS.to_string() };
+        let mut next_seq = 0;
+        if path.exists() {
+            let sum = verify(&path).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            if sum.sealed {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} is sealed", path.display())));
+            }
+            if let Some(seq) = sum.last_seq {
+                head = ChainHead { seq, hash: sum.head };
+                next_seq = seq + 1;
+            }
+        }
+        let file = LogFile::open(&path)?;
+        let compression = file.algorithm().map(Compression::new);
+        Ok(Self { path, file, empty_size: 0, max_bytes: None, compression, head, next_seq, clock: clock::system() })
+    }
+
+    /// Rotate before a write would take the file past this size. For a compressed log this is
+    /// the size on disk, with the next record estimated at the file's compression ratio so
+    /// far, so a file can end up a little larger.
+    pub fn max_bytes(mut self, bytes: u64) -> Self {
+        self.max_bytes = Some(bytes);
+        self
+    }
+
+    /// Write the log compressed (`None`: plain JSONL). A log already holding records in
+    /// another form is rotated at the next append, so the form changes between files.
+    pub fn compression(mut self, compression: Option<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Time source for record timestamps and rotated file names (default: the system clock).
+    pub fn clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn head(&self) -> &ChainHead {
+        &self.head
+    }
+
+    /// Append one event.
+    pub fn append(&mut self, event: &Value) -> io::Result<()> {
+        if self.file.algorithm() != self.compression.map(|c| c.algorithm) {
+            if self.file.size() > 0 {
+                self.rotate()?;
+            } else {
+                self.file = LogFile::create(&self.path, self.compression)?;
+            }
+        }
+        let rec = self.record(Some(event.clone()), false, None);
+        let line = line(&rec)?;
+        // leave room for the seal that would follow this record
+        let seal = AuditRecord { seq: rec.seq + 1, prev: rec.hash.clone(), event: None, sealed: true, ..rec.clone() };
+        let size = self.file.size();
+        if let Some(max) = self.max_bytes
+            && size > self.empty_size
+            && size + self.file.estimate((line.len() + self::line(&seal)?.len()) as u64) > max
+        {
+            self.rotate()?;
+            return self.append(event);
+        }
+        self.write(&rec, line)
+    }
+
+    /// Seal the current file, move it to `<log>.<unix millis>` and continue in a new file.
+    /// Returns the path of the sealed file.
+    pub fn rotate(&mut self) -> io::Result<PathBuf> {
+        let seal = self.record(None, true, None);
+        self.write(&seal, line(&seal)?)?;
+
+        let mut sealed = self.path.as_os_str().to_owned();
+        sealed.push(format!(".{}", unix_millis(self.clock.now_system())));
+        let mut sealed = PathBuf::from(sealed);
+        while sealed.exists() {
+            let mut s = sealed.into_os_string();
+            s.push("_");
+            sealed = PathBuf::from(s);
+        }
+        std::fs::rename(&self.path, &sealed)?;
+        let old = std::mem::replace(&mut self.file, LogFile::create(&self.path, self.compression)?);
+        old.finish()?;
+
+        let cont = self.record(None, false, sealed.file_name().map(|n| n.to_string_lossy().into_owned()));
+        self.write(&cont, line(&cont)?)?;
+        self.empty_size = self.file.size();
+        Ok(sealed)
+    }
+
+    /// The next record, hashed and chained to the current head.
+    fn record(&self, event: Option<Value>, sealed: bool, continues: Option<String>) -> AuditRecord {
+        let prev = self.head.hash.clone();
+        let ts = unix_millis(self.clock.now_system());
+        let mut rec = AuditRecord { seq: self.next_seq, ts, prev, event, sealed, continues, hash: String::new() };
+        rec.hash = rec.compute_hash();
+        rec
+    }
+
+    fn write(&mut self, rec: &AuditRecord, line: String) -> io::Result<()> {
+        self.file.write_line(&line)?;
+        self.head = ChainHead { seq: rec.seq, hash: rec.hash.clone() };
+        self.next_seq = rec.seq + 1;
+        write_head(&head_path(&self.path), &self.head)
+    }
+
+    /// Append everything received on `rx` until all senders are gone, e.g. a
+    /// [`crate::router::Router`] sink.
+    pub fn spawn(mut self, mut rx: mpsc::Receiver<CallbackResult>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            while let Some(ev) = rx.recv().await {
+                if let Err(e) = self.append(&ev) {
+                    log::error!("audit: failed to write {}: {e}", self.path.display());
+                }
+            }
+        })
+    }
+}
+
+/// The log file being written, plain or compressed.
+enum LogFile {
+    Plain { file: File, size: u64 },
+    Compressed { enc: Encoder<File>, raw: u64 },
+}
+
+impl LogFile {
+    /// Open for appending, in the form the file is in. A compressed file is rewritten first:
+    /// the stream it holds may be unfinished, and cannot be appended to.
+    fn open(path: &Path) -> io::Result<Self> {
+        let mut file = OpenOptions::new().create(true).append(true).read(true).open(path)?;
+        let size = file.metadata()?.len();
+        let (mut content, algorithm) = compress::reader(&mut file)?;
+        let Some(algorithm) = algorithm else {
+            drop(content);
+            return Ok(LogFile::Plain { file, size });
+        };
+        let mut raw = Vec::new();
+        content.read_to_end(&mut raw)?;
+        drop(content);
+
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut log = Self::create(Path::new(&tmp), Some(Compression::new(algorithm)))?;
+        log.write_line(std::str::from_utf8(&raw).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(log)
+    }
+
+    /// A new, empty file.
+    fn create(path: &Path, compression: Option<Compression>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).write(true).truncate(true).open(path)?;
+        Ok(match compression {
+            Some(c) => LogFile::Compressed { enc: c.writer(file)?, raw: 0 },
+            None => LogFile::Plain { file, size: 0 },
+        })
+    }
+
+    fn algorithm(&self) -> Option<Algorithm> {
+        match self {
+            LogFile::Plain { .. } => None,
+            LogFile::Compressed { enc, .. } => Some(enc.algorithm()),
+        }
+    }
+
+    /// Bytes on disk.
+    fn size(&self) -> u64 {
+        match self {
+            LogFile::Plain { size, .. } => *size,
+            LogFile::Compressed { enc, .. } => enc.compressed_bytes(),
+        }
+    }
+
+    /// What `raw` bytes of records will take on disk.
+    fn estimate(&self, raw_bytes: u64) -> u64 {
+        match self {
+            LogFile::Plain { .. } => raw_bytes,
+            LogFile::Compressed { raw: 0, .. } => raw_bytes,
+            LogFile::Compressed { enc, raw } => (raw_bytes * enc.compressed_bytes()).div_ceil(*raw),
+        }
+    }
+
+    /// Write and flush `line`, so it is readable right away.
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        match self {
+            LogFile::Plain { file, size } => {
+                file.write_all(line.as_bytes())?;
+                file.flush()?;
+                *size += line.len() as u64;
+            }
+            LogFile::Compressed { enc, raw } => {
+                enc.write_all(line.as_bytes())?;
+                enc.flush()?;
+                *raw += line.len() as u64;
+            }
+        }
+        Ok(())
+    }
+
+    /// End a compressed stream.
+    fn finish(self) -> io::Result<()> {
+        if let LogFile::Compressed { enc, .. } = self {
+            enc.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// `<log>.head`
+pub fn head_path(log: &Path) -> PathBuf {
+    let mut p = log.as_os_str().to_owned();
+    p.push(".head");
+    PathBuf::from(p)
+}
+
+fn read_head(path: &Path) -> Option<ChainHead> {
+    serde_json::from_slice(&std::fs::read(path).ok()?).ok()
+}
+
+fn write_head(path: &Path, head: &ChainHead) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, serde_json::to_vec(head)?)?;
+    std::fs::rename(tmp, path)
+}
+
+fn line(rec: &AuditRecord) -> io::Result<String> {
+    let mut s = serde_json::to_string(rec)?;
+    s.push('\n');
+    Ok(s)
+}
+
+/// JSON with object keys sorted at every level and no whitespace, independent of how the
+/// value was built or whether serde_json preserves insertion order.
+pub fn canonical(v: &Value) -> String {
+    let mut out = String::new();
+    write_canonical(v, &mut out);
+    out
+}
+
+fn write_canonical(v: &Value, out: &mut String) {
+    match v {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, k) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(k.clone()).to_string());
+                out.push(':');
+                write_canonical(&map[k], out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+fn unix_millis(now: SystemTime) -> u64 {
+    now.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
@@ -0,0 +1,205 @@
+use crate::{
+    audit::{self, AuditSink, GENESIS},
+    compress::{self, Algorithm, Compression},
+};
+use serde_json::{Value, json};
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+use tempfile::TempDir;
+
+fn write_events(sink: &mut AuditSink, n: usize) {
+    for i in 0..n {
+        sink.append(&json!({ "Mounted": { "target": format!("/mnt/disk{i}"), "fstype": "ext4" } })).unwrap();
+    }
+}
+
+fn lines(path: &Path) -> Vec<String> {
+    std::fs::read_to_string(path).unwrap().lines().map(str::to_string).collect()
+}
+
+#[test]
+fn written_log_verifies_and_matches_the_head() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let log = dir.join("events.jsonl");
+    let mut sink = AuditSink::open(&log).unwrap();
+    write_events(&mut sink, 5);
+
+    let sum = audit::verify(&log).unwrap();
+    assert_eq!((sum.records, sum.last_seq, sum.start.as_str(), sum.sealed), (5, Some(4), GENESIS, false));
+    assert_eq!(sum.head, sink.head().hash);
+    let head: Value = serde_json::from_slice(&std::fs::read(audit::head_path(&log)).unwrap()).unwrap();
+    assert_eq!(head, json!({ "seq": 4, "hash": sum.head }));
+
+    // reopening continues the chain
+    drop(sink);
+    let mut sink = AuditSink::open(&log).unwrap();
+    write_events(&mut sink, 2);
+    assert_eq!(audit::verify(&log).unwrap().last_seq, Some(6));
+}
+
+#[test]
+fn corrupted_byte_is_pinpointed() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let log = dir.join("events.jsonl");
+    write_events(&mut AuditSink::open(&log).unwrap(), 7);
+
+    // flip one character inside the event of the fourth record
+    let mut bytes = std::fs::read(&log).unwrap();
+    let start: usize = lines(&log)[..3].iter().map(|l| l.len() + 1).sum();
+    let at = start + lines(&log)[3].find("disk3").unwrap() + 4;
+    bytes[at] = b'9';
+    std::fs::write(&log, bytes).unwrap();
+
+    let brk = audit::verify(&log).unwrap_err();
+    assert_eq!((brk.line, brk.seq), (4, Some(3)), "{brk}");
+    assert!(brk.reason.contains("hash"), "{brk}");
+    // refused rather than extended
+    assert_eq!(AuditSink::open(&log).err().map(|e| e.kind()), Some(std::io::ErrorKind::InvalidData));
+}
+
+#[test]
+fn dropped_and_truncated_records_break_the_chain() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let log = dir.join("events.jsonl");
+    write_events(&mut AuditSink::open(&log).unwrap(), 5);
+    let orig = lines(&log);
+
+    let mut dropped = orig.clone();
+    dropped.remove(2);
+    std::fs::write(&log, dropped.join("\n") + "\n").unwrap();
+    let brk = audit::verify(&log).unwrap_err();
+    assert_eq!((brk.line, brk.seq), (3, Some(3)), "{brk}");
+
+    // cutting the tail keeps the chain intact, but the head file knows better
+    std::fs::write(&log, orig[..4].join("\n") + "\n").unwrap();
+    let brk = audit::verify(&log).unwrap_err();
+    assert_eq!((brk.line, brk.seq), (5, Some(4)), "{brk}");
+}
+
+#[test]
+fn rotation_seals_and_the_next_file_continues() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let log = dir.join("events.jsonl");
+    let mut sink = AuditSink::open(&log).unwrap();
+    write_events(&mut sink, 3);
+    let sealed = sink.rotate().unwrap();
+    write_events(&mut sink, 2);
+
+    let old = audit::verify(&sealed).unwrap();
+    assert!(old.sealed);
+    let last: Value = serde_json::from_str(lines(&sealed).last().unwrap()).unwrap();
+    assert_eq!((last["sealed"].clone(), last["hash"].as_str()), (json!(true), Some(old.head.as_str())));
+
+    let new = audit::verify(&log).unwrap();
+    assert_eq!(new.start, old.head);
+    assert_eq!(new.continues.as_deref(), sealed.file_name().and_then(|n| n.to_str()));
+    assert_eq!(audit::verify_files(&[&sealed, &log]).unwrap().head, sink.head().hash);
+
+    // a sealed file takes no more records, and files out of order do not link up
+    assert!(AuditSink::open(&sealed).is_err());
+    assert!(audit::verify_files(&[&log, &sealed]).is_err());
+}
+
+#[test]
+fn size_limit_rotates_with_room_for_the_seal() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let log = dir.join("events.jsonl");
+    let mut sink = AuditSink::open(&log).unwrap().max_bytes(800);
+    write_events(&mut sink, 10);
+
+    let mut files: Vec<PathBuf> =
+        std::fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()).filter(|p| !p.to_string_lossy().ends_with(".head")).collect();
+    files.sort();
+    files.rotate_left(1); // events.jsonl last
+    assert!(files.len() > 2, "{files:?}");
+    assert!(files.iter().all(|f| std::fs::metadata(f).unwrap().len() <= 800));
+    assert_eq!(audit::verify_files(&files).unwrap().last_seq, Some(sink.head().seq));
+
+    // a record larger than the limit still gets written, one per file
+    let tiny = dir.join("tiny.jsonl");
+    let mut sink = AuditSink::open(&tiny).unwrap().max_bytes(10);
+    write_events(&mut sink, 3);
+    assert_eq!(lines(&tiny).len(), 2, "continuation and one event");
+}
+
+fn decompressed(path: &Path) -> Vec<u8> {
+    let (mut r, _) = compress::reader(std::fs::File::open(path).unwrap()).unwrap();
+    let mut out = Vec::new();
+    r.read_to_end(&mut out).unwrap();
+    out
+}
+
+#[test]
+fn compressed_log_chains_like_the_plain_one() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let log = dir.join("events.jsonl.zst");
+    let mut sink = AuditSink::open(&log).unwrap().compression(Some(Compression::zstd()));
+    write_events(&mut sink, 5);
+    assert_eq!(Algorithm::sniff(&std::fs::read(&log).unwrap()), Some(Algorithm::Zstd));
+
+    // verified while still being written, and the same chain once decompressed
+    let sum = audit::verify(&log).unwrap();
+    assert_eq!((sum.records, sum.head.as_str()), (5, sink.head().hash.as_str()));
+    let plain = dir.join("events.jsonl");
+    std::fs::write(&plain, decompressed(&log)).unwrap();
+    assert_eq!(audit::verify(&plain).unwrap(), sum);
+
+    // reopening keeps the form, even after a crash left the stream unfinished
+    std::mem::forget(sink);
+    let mut sink = AuditSink::open(&log).unwrap();
+    write_events(&mut sink, 2);
+    drop(sink);
+    assert_eq!(Algorithm::sniff(&std::fs::read(&log).unwrap()), Some(Algorithm::Zstd));
+    assert_eq!(audit::verify(&log).unwrap().last_seq, Some(6));
+}
+
+#[test]
+fn switching_form_rotates_and_compressed_size_bounds_files() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let log = dir.join("events.jsonl");
+    write_events(&mut AuditSink::open(&log).unwrap(), 3);
+    let mut sink = AuditSink::open(&log).unwrap().compression(Some(Compression::gzip()));
+    write_events(&mut sink, 1);
+    let sealed: Vec<PathBuf> =
+        std::fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()).filter(|p| p.extension().is_some_and(|e| e != "head" && e != "jsonl")).collect();
+    assert_eq!(sealed.len(), 1, "{sealed:?}");
+    assert_eq!(Algorithm::sniff(&std::fs::read(&sealed[0]).unwrap()), None);
+    assert_eq!(Algorithm::sniff(&std::fs::read(&log).unwrap()), Some(Algorithm::Gzip));
+    assert_eq!(audit::verify_files(&[&sealed[0], &log]).unwrap().last_seq, Some(sink.head().seq));
+    drop(sink);
+
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let log = dir.join("events.jsonl.zst");
+    let mut sink = AuditSink::open(&log).unwrap().compression(Some(Compression::zstd())).max_bytes(1500);
+    write_events(&mut sink, 200);
+    drop(sink);
+    let mut files: Vec<PathBuf> =
+        std::fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()).filter(|p| !p.to_string_lossy().ends_with(".head")).collect();
+    files.sort();
+    files.rotate_left(1);
+    assert!(files.len() > 2, "{files:?}");
+    // the limit is on disk, far fewer bytes than the records hold, give or take an estimate
+    let on_disk: Vec<u64> = files.iter().map(|f| std::fs::metadata(f).unwrap().len()).collect();
+    assert!(on_disk.iter().all(|n| *n <= 1500 + 150), "{on_disk:?}");
+    assert!(on_disk[..on_disk.len() - 1].iter().all(|n| *n > 1500 - 300), "{on_disk:?}");
+    assert!(decompressed(&files[0]).len() > 3 * 1500);
+    assert_eq!(audit::verify_files(&files).unwrap().last_seq, Some(200 + 2 * (files.len() as u64 - 1) - 1));
+}
+
+#[test]
+fn canonical_form_ignores_key_order() {
+    let a: Value = serde_json::from_str(r#"{"b": 1, "a": {"y": [1, {"d": 2, "c": 3}], "x": "q\"uote"}}"#).unwrap();
+    let b: Value = serde_json::from_str(r#"{"a": {"x": "q\"uote", "y": [1, {"c": 3, "d": 2}]}, "b": 1}"#).unwrap();
+    assert_eq!(audit::canonical(&a), r#"{"a":{"x":"q\"uote","y":[1,{"c":3,"d":2}]},"b":1}"#);
+    assert_eq!(audit::canonical(&a), audit::canonical(&b));
+}
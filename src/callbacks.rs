@@ -1,53 +1,1275 @@
+use crate::{
+    filter::Filter,
+    quiet::QuietHours,
+    topics::{TopicError, TopicSet, Topics},
+};
 use async_trait::async_trait;
-use serde_json::Value;
-use std::sync::Arc;
-use tokio::sync::mpsc;
+use futures_util::stream::{self, StreamExt};
+use serde::{Serialize, Serializer};
+use serde_json::{Value, json};
+use std::{
+    any::Any,
+    collections::{BTreeMap, HashMap, VecDeque, hash_map::Entry},
+    hash::Hash,
+    panic::{AssertUnwindSafe, catch_unwind},
+    sync::{
+        Arc, Mutex, OnceLock, RwLock,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    sync::{Notify, broadcast, mpsc},
+    time::Instant,
+};
+#[cfg(feature = "tracing")]
+use {crate::spans, tracing::Instrument};
 
-/// What callbacks can optionally return (goes to the results channel).
+/// What callbacks return by default (goes to the results channel). Hubs can carry any other
+/// result type instead, see [`CallbackHub`].
 pub type CallbackResult = Value;
 
-/// A generic async callback over event type `E`.
+/// A hub whose callbacks return JSON, what sensors, routers and sinks exchange.
+pub type JsonCallbackHub<E> = CallbackHub<E, CallbackResult>;
+
+/// Key set to `true` on results of injected events, see [`CallbackHub::inject`].
+pub const INJECTED_FIELD: &str = "injected";
+
+/// Key set to `true` on results of events in a maintenance window, see
+/// [`CallbackHub::set_quiet_hours`].
+pub const SUPPRESSED_FIELD: &str = "suppressed";
+
+/// Variant name of the record sent for a callback cut off by [`CallbackHub::set_callback_timeout`].
+pub const CALLBACK_TIMED_OUT: &str = "CallbackTimedOut";
+
+tokio::task_local! {
+    static INJECTED: bool;
+    static QUIET: bool;
+    static STAMP: Stamp;
+    // debug builds only
+    static IN_CALLBACK: bool;
+}
+
+/// What [`Envelope`] adds to the event being fired.
+struct Stamp {
+    seq: u64,
+    ts: SystemTime,
+    sensor: Arc<str>,
+}
+
+/// True while callbacks run for an event passed to [`CallbackHub::inject`], so forwarding
+/// callbacks (routers, exporters) can mark or skip synthetic events.
+pub fn is_injected() -> bool {
+    INJECTED.try_with(|i| *i).unwrap_or(false)
+}
+
+/// True while callbacks run for an event in a maintenance window of the hub's
+/// [`QuietHours`], so a pager can skip it (see [`SkipQuiet`]) while a log records it.
+pub fn is_quiet() -> bool {
+    QUIET.try_with(|q| *q).unwrap_or(false)
+}
+
+/// True while a hub runs a callback, in debug builds; always false in release builds. Calls
+/// that would wait on a sensor's loop assert it is false, see [`crate::state`].
+pub fn in_callback() -> bool {
+    IN_CALLBACK.try_with(|c| *c).unwrap_or(false)
+}
+
+/// Mask bits of event kinds, one per variant, in every sensor's mask type.
+pub const KIND_BITS: u64 = 0xffff;
+
+/// Mask bits of sub-kinds (e.g. an options-only remount). An event's mask has the bit of its
+/// kind and those of its sub-kinds, so a callback masking the kind gets all of them and one
+/// masking a sub-kind only those.
+pub const SUBKIND_BITS: u64 = 0xffff_0000;
+
+/// Mask bits no sensor uses yet, kept free for future kinds and sub-kinds.
+pub const RESERVED_BITS: u64 = !(KIND_BITS | SUBKIND_BITS);
+
+/// A generic async callback over event type `E`, returning results of type `R`.
 #[async_trait]
-pub trait Callback<E>: Send + Sync {
-    /// Return a bitmask defining which events you care about.
+pub trait Callback<E, R = CallbackResult>: Send + Sync {
+    /// Return a bitmask defining which events you care about, see [`KIND_BITS`] and [`SUBKIND_BITS`].
     fn mask(&self) -> u64;
 
     /// Called when an event fires.
-    /// Return Some(Value) to send it to the result channel, or None to ignore.
-    async fn call(&self, ev: &E) -> Option<CallbackResult>;
+    /// Return Some(result) to send it to the result channel, or None to ignore.
+    async fn call(&self, ev: &E) -> Option<R>;
+}
+
+/// A callback made of a mask and a closure, for handlers not worth a type of their own, see
+/// [`CallbackHub::add_fn`]. The closure gets the event by reference and returns a future
+/// that cannot borrow it: take what the future needs from the event first.
+pub struct FnCallback<F> {
+    mask: u64,
+    f: F,
+}
+
+impl<F> FnCallback<F> {
+    pub fn new(mask: u64, f: F) -> Self {
+        Self { mask, f }
+    }
+}
+
+#[async_trait]
+impl<E, R, F, Fut> Callback<E, R> for FnCallback<F>
+where
+    E: Sync,
+    F: Fn(&E) -> Fut + Send + Sync,
+    Fut: Future<Output = Option<R>> + Send,
+{
+    fn mask(&self) -> u64 {
+        self.mask
+    }
+
+    async fn call(&self, ev: &E) -> Option<R> {
+        (self.f)(ev).await
+    }
+}
+
+/// Runs the wrapped callback for the first event it is called with only, e.g.
+/// `hub.add(Once::new(cb))` for a one-shot notification. Mask and predicates still apply
+/// first, so the event that uses it up is the first one the callback would have seen.
+pub struct Once<C> {
+    inner: C,
+    fired: AtomicBool,
+}
+
+impl<C> Once<C> {
+    pub fn new(inner: C) -> Self {
+        Self { inner, fired: AtomicBool::new(false) }
+    }
+
+    /// True once the inner callback was called.
+    pub fn fired(&self) -> bool {
+        self.fired.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl<E, R, C> Callback<E, R> for Once<C>
+where
+    E: Sync,
+    R: Send,
+    C: Callback<E, R>,
+{
+    fn mask(&self) -> u64 {
+        if self.fired() { 0 } else { self.inner.mask() }
+    }
+
+    async fn call(&self, ev: &E) -> Option<R> {
+        if self.fired.swap(true, Ordering::Relaxed) {
+            return None;
+        }
+        self.inner.call(ev).await
+    }
+}
+
+/// Runs the wrapped callback only for events outside maintenance windows, see [`is_quiet`],
+/// e.g. `hub.add(SkipQuiet(pager))`.
+pub struct SkipQuiet<C>(pub C);
+
+#[async_trait]
+impl<E, R, C> Callback<E, R> for SkipQuiet<C>
+where
+    E: Sync,
+    R: Send,
+    C: Callback<E, R>,
+{
+    fn mask(&self) -> u64 {
+        self.0.mask()
+    }
+
+    async fn call(&self, ev: &E) -> Option<R> {
+        if is_quiet() {
+            return None;
+        }
+        self.0.call(ev).await
+    }
+}
+
+/// Runs the wrapped callback only for events passing `pred`, e.g.
+/// `Filtered::new(cb, |ev: &XMountEvent| matches!(ev, XMountEvent::Mounted { info, .. } if info.fstype == "nfs"))`.
+/// Unlike [`CallbackHub::add_filtered`] it nests in other wrappers: outside a `RateLimited`,
+/// the events it rejects take none of the allowance. Rejected events do not reach the inner
+/// callback's future at all.
+pub struct Filtered<C, F> {
+    inner: C,
+    pred: F,
+}
+
+impl<C, F> Filtered<C, F> {
+    pub fn new(inner: C, pred: F) -> Self {
+        Self { inner, pred }
+    }
+}
+
+#[async_trait]
+impl<E, R, C, F> Callback<E, R> for Filtered<C, F>
+where
+    E: Sync,
+    R: Send,
+    C: Callback<E, R>,
+    F: Fn(&E) -> bool + Send + Sync,
+{
+    fn mask(&self) -> u64 {
+        self.inner.mask()
+    }
+
+    async fn call(&self, ev: &E) -> Option<R> {
+        if !(self.pred)(ev) {
+            return None;
+        }
+        self.inner.call(ev).await
+    }
+}
+
+/// Runs the wrapped callback for an event only if none with the same key went to it within
+/// `window`, e.g. `Debounced::new(cb, Duration::from_secs(5), |ev: &XMountEvent| ev.target().to_owned())`
+/// for mounts that bounce. The first event of a key goes through and starts the window;
+/// repeats within it are dropped and counted, the first one after it goes through again.
+pub struct Debounced<C, F, K> {
+    inner: C,
+    window: Duration,
+    key: F,
+    // key -> when an event of it last went through
+    last: Mutex<(HashMap<K, Instant>, Instant)>,
+    suppressed: AtomicU64,
+}
+
+impl<C, F, K> Debounced<C, F, K> {
+    pub fn new(inner: C, window: Duration, key: F) -> Self {
+        Self { inner, window, key, last: Mutex::new((HashMap::new(), Instant::now() + window)), suppressed: AtomicU64::new(0) }
+    }
+
+    /// Events dropped as repeats so far.
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl<E, R, C, F, K> Callback<E, R> for Debounced<C, F, K>
+where
+    E: Sync,
+    R: Send,
+    C: Callback<E, R>,
+    F: Fn(&E) -> K + Send + Sync,
+    K: Hash + Eq + Send,
+{
+    fn mask(&self) -> u64 {
+        self.inner.mask()
+    }
+
+    async fn call(&self, ev: &E) -> Option<R> {
+        let now = Instant::now();
+        {
+            let mut guard = self.last.lock().unwrap_or_else(|e| e.into_inner());
+            let (last, prune_at) = &mut *guard;
+            // keys past their window say nothing any more, forget them once a window
+            if now >= *prune_at {
+                last.retain(|_, at| now.duration_since(*at) < self.window);
+                *prune_at = now + self.window;
+            }
+            match last.entry((self.key)(ev)) {
+                Entry::Occupied(at) if now.duration_since(*at.get()) < self.window => {
+                    self.suppressed.fetch_add(1, Ordering::Relaxed);
+                    return None;
+                }
+                entry => {
+                    entry.insert_entry(now);
+                }
+            }
+        }
+        self.inner.call(ev).await
+    }
+}
+
+/// Runs the wrapped callback for at most `per_second` events a second, dropping and counting
+/// the rest. Bursts up to `per_second` go through at once, after that events pass as the
+/// allowance refills.
+pub struct RateLimited<C> {
+    inner: C,
+    per_second: f64,
+    // allowance left, and when it was last refilled
+    bucket: Mutex<(f64, Instant)>,
+    dropped: AtomicU64,
+}
+
+impl<C> RateLimited<C> {
+    pub fn new(inner: C, per_second: u32) -> Self {
+        let per_second = f64::from(per_second);
+        Self { inner, per_second, bucket: Mutex::new((per_second, Instant::now())), dropped: AtomicU64::new(0) }
+    }
+
+    /// Events dropped over the limit so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl<E, R, C> Callback<E, R> for RateLimited<C>
+where
+    E: Sync,
+    R: Send,
+    C: Callback<E, R>,
+{
+    fn mask(&self) -> u64 {
+        self.inner.mask()
+    }
+
+    async fn call(&self, ev: &E) -> Option<R> {
+        let now = Instant::now();
+        {
+            let mut guard = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+            let (allowance, refilled) = &mut *guard;
+            *allowance = (*allowance + now.duration_since(*refilled).as_secs_f64() * self.per_second).min(self.per_second);
+            *refilled = now;
+            if *allowance < 1.0 {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+            *allowance -= 1.0;
+        }
+        self.inner.call(ev).await
+    }
+}
+
+/// An event with when and from which sensor it was fired, for callbacks added with
+/// [`CallbackHub::add_enveloped`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Envelope<E> {
+    /// Position of the event among those the hub fired, from 0 up, without repeats.
+    /// Suppressed events (see [`CallbackHub::set_suppress_real`]) leave gaps.
+    pub seq: u64,
+    /// When the hub fired it; serialized as Unix seconds.
+    #[serde(serialize_with = "unix_secs")]
+    pub ts: SystemTime,
+    /// See [`CallbackHub::set_sensor_name`].
+    pub sensor: String,
+    pub event: E,
+}
+
+pub(crate) fn unix_secs<S: Serializer>(ts: &SystemTime, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_f64(ts.duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0))
+}
+
+/// Hands a callback of [`Envelope`]s the stamp `fire()` set up.
+struct Enveloped<C>(C);
+
+#[async_trait]
+impl<E, R, C> Callback<E, R> for Enveloped<C>
+where
+    E: Clone + Send + Sync,
+    R: Send,
+    C: Callback<Envelope<E>, R>,
+{
+    fn mask(&self) -> u64 {
+        self.0.mask()
+    }
+
+    async fn call(&self, ev: &E) -> Option<R> {
+        let env = STAMP.try_with(|s| Envelope { seq: s.seq, ts: s.ts, sensor: s.sensor.to_string(), event: ev.clone() }).ok()?;
+        self.0.call(&env).await
+    }
+}
+
+/// Returned by [`CallbackHub::fire_and_wait_all`] when some callbacks did not finish in time.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("callbacks {timed_out:?} did not complete before the barrier timeout")]
+pub struct BarrierTimeout {
+    /// Positions (in firing order, among the callbacks registered when the event fired) of
+    /// the callbacks that timed out.
+    pub timed_out: Vec<usize>,
+}
+
+/// Identifies a registered callback, returned by [`CallbackHub::add`] and friends to
+/// [`CallbackHub::remove`] it later. Ids are not reused within a hub.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CallbackId(u64);
+
+/// How [`CallbackHub::fire`] awaits the callbacks matching an event, see
+/// [`CallbackHub::set_concurrency`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Concurrency {
+    /// One after another, in firing order.
+    #[default]
+    Sequential,
+    /// All of a priority at once, see [`CallbackHub::add_with_priority`].
+    Parallel,
+    /// At most this many of a priority at once, started in firing order.
+    Bounded(usize),
+}
+
+/// What the hub does with a result when the result channel is full, see
+/// [`CallbackHub::set_result_channel_with_policy`]. Dropped results are counted as
+/// `results_dropped` in [`CallbackHub::stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ResultPolicy {
+    /// Wait for room, holding up the callbacks and with them the sensor.
+    #[default]
+    Block,
+    /// Drop the result that does not fit.
+    DropNewest,
+    /// Keep the newest results: they wait in a buffer of the channel's capacity, from which
+    /// a task moves them into the channel as it gets room, and the oldest waiting one is
+    /// dropped for a new one. Results already in the channel are not touched.
+    DropOldest,
+    /// Like `DropNewest`, but once this many results were dropped the hub lets go of the
+    /// channel, so the receiver sees it closed (when no other sender is left) and can tell a
+    /// consumer too slow to keep up from a quiet one.
+    CloseAfterNDrops(u64),
+}
+
+/// Results waiting for room in the channel, for [`ResultPolicy::DropOldest`].
+struct ResultRing<R> {
+    queue: Mutex<VecDeque<R>>,
+    ready: Notify,
+    forwarding: AtomicBool,
+    // the hub let go of it: forward what is left, then stop
+    closed: AtomicBool,
+}
+
+impl<R> Default for ResultRing<R> {
+    fn default() -> Self {
+        Self { queue: Mutex::default(), ready: Notify::new(), forwarding: AtomicBool::new(false), closed: AtomicBool::new(false) }
+    }
+}
+
+impl<R: Send + 'static> ResultRing<R> {
+    /// Queue `r` for `tx`, dropping the oldest waiting result if the buffer is full. Returns
+    /// whether one was dropped. The forwarding task starts with the first result.
+    fn push(self: &Arc<Self>, r: R, tx: &mpsc::Sender<R>) -> bool {
+        if !self.forwarding.swap(true, Ordering::Relaxed) {
+            tokio::spawn(self.clone().forward(tx.clone()));
+        }
+        let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        let dropped = queue.len() >= tx.max_capacity() && queue.pop_front().is_some();
+        queue.push_back(r);
+        drop(queue);
+        self.ready.notify_one();
+        dropped
+    }
+
+    fn pop(&self) -> Option<R> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner()).pop_front()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner()).is_empty()
+    }
+
+    /// Move results into the channel as it gets room, oldest first. Only this task pops, so
+    /// a result is there once the room is.
+    async fn forward(self: Arc<Self>, tx: mpsc::Sender<R>) {
+        loop {
+            if self.is_empty() {
+                if self.closed.load(Ordering::Relaxed) {
+                    return;
+                }
+                self.ready.notified().await;
+                continue;
+            }
+            let Ok(permit) = tx.reserve().await else {
+                return;
+            };
+            if let Some(r) = self.pop() {
+                permit.send(r);
+            }
+        }
+    }
+}
+
+/// The hub's hold on a [`ResultRing`]; dropping it lets the forwarding task finish.
+struct RingHandle<R>(Arc<ResultRing<R>>);
+
+impl<R> Drop for RingHandle<R> {
+    fn drop(&mut self) {
+        self.0.closed.store(true, Ordering::Relaxed);
+        self.0.ready.notify_one();
+    }
+}
+
+/// Per-event predicate, see [`CallbackHub::add_filtered`].
+pub type Predicate<E> = Box<dyn Fn(&E) -> bool + Send + Sync>;
+
+/// The sender of [`CallbackHub::subscribe_broadcast`], with `E::clone` for `fire()`, which
+/// cannot require `E: Clone`.
+type Broadcast<E> = (broadcast::Sender<E>, fn(&E) -> E);
+
+/// The windows of [`CallbackHub::set_quiet_hours`], with how to match a window's filter
+/// against an event, which cannot require `E: Serialize` in `fire()`.
+type Quiet<E> = (QuietHours, fn(&Filter, &E) -> bool);
+
+/// Why callbacks were or were not invoked, counted per (event, callback) pair.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct HubStats {
+    pub called: u64,
+    /// The callback's mask, or its topics for [`CallbackHub::subscribe`], did not match the event.
+    pub mask_mismatch: u64,
+    /// The mask matched but the predicate rejected the event.
+    pub filtered_out: u64,
+    /// Skipped because the predicate panicked earlier and the callback got disabled.
+    pub disabled: u64,
+    /// Called, but abandoned after [`CallbackHub::set_callback_timeout`] or the
+    /// [`CallbackHub::fire_and_wait_all`] timeout.
+    pub timed_out: u64,
+    /// Results the result channel had no room for, see [`ResultPolicy`].
+    pub results_dropped: u64,
+}
+
+/// Dispatch counters of a hub, see [`CallbackHub::metrics`]. Clones share the counters, so
+/// a handle taken before the hub moves into a sensor keeps following it.
+#[derive(Clone, Default)]
+pub struct HubMetrics(Arc<HubCounters>);
+
+struct HubCounters {
+    fired: AtomicU64,
+    // per mask bit
+    fired_by_bit: [AtomicU64; 64],
+    called: AtomicU64,
+    mask_mismatch: AtomicU64,
+    filtered_out: AtomicU64,
+    disabled: AtomicU64,
+    timed_out: AtomicU64,
+    errors: AtomicU64,
+    results_delivered: AtomicU64,
+    results_dropped: AtomicU64,
+}
+
+impl Default for HubCounters {
+    fn default() -> Self {
+        Self {
+            fired: AtomicU64::default(),
+            fired_by_bit: std::array::from_fn(|_| AtomicU64::default()),
+            called: AtomicU64::default(),
+            mask_mismatch: AtomicU64::default(),
+            filtered_out: AtomicU64::default(),
+            disabled: AtomicU64::default(),
+            timed_out: AtomicU64::default(),
+            errors: AtomicU64::default(),
+            results_delivered: AtomicU64::default(),
+            results_dropped: AtomicU64::default(),
+        }
+    }
+}
+
+/// [`HubMetrics`] at one point in time, e.g. to hand to a metrics pipeline. Counts are totals
+/// since the hub was made; take differences between snapshots for rates.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct HubMetricsSnapshot {
+    /// Events fired, suppressed ones included (see [`CallbackHub::set_suppress_real`]).
+    pub fired: u64,
+    /// Events fired per mask bit set in them, e.g. `{1: 3, 4: 1}` for three events of kind
+    /// `0b001` and one of `0b100`. Bits never fired are left out.
+    pub fired_by_mask: BTreeMap<u64, u64>,
+    /// Callbacks invoked, one per (event, callback) pair.
+    pub callbacks_invoked: u64,
+    /// Callbacks abandoned after a timeout, see [`HubStats::timed_out`].
+    pub callbacks_timed_out: u64,
+    /// Callback predicates and hub filters that panicked, see [`CallbackHub::add_filtered`].
+    pub callback_errors: u64,
+    /// Results handed to the result channel, or for [`ResultPolicy::DropOldest`] to its buffer.
+    pub results_delivered: u64,
+    /// Results the result channel had no room for, see [`ResultPolicy`].
+    pub results_dropped: u64,
+}
+
+impl HubMetrics {
+    pub fn snapshot(&self) -> HubMetricsSnapshot {
+        let c = &self.0;
+        let fired_by_mask = (0..64)
+            .filter_map(|bit| match c.fired_by_bit[bit].load(Ordering::Relaxed) {
+                0 => None,
+                n => Some((1u64 << bit, n)),
+            })
+            .collect();
+        HubMetricsSnapshot {
+            fired: c.fired.load(Ordering::Relaxed),
+            fired_by_mask,
+            callbacks_invoked: c.called.load(Ordering::Relaxed),
+            callbacks_timed_out: c.timed_out.load(Ordering::Relaxed),
+            callback_errors: c.errors.load(Ordering::Relaxed),
+            results_delivered: c.results_delivered.load(Ordering::Relaxed),
+            results_dropped: c.results_dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Count an event of `mask`, returning its sequence number.
+    fn fired(&self, mask: u64) -> u64 {
+        let mut bits = mask;
+        while bits != 0 {
+            self.0.fired_by_bit[bits.trailing_zeros() as usize].fetch_add(1, Ordering::Relaxed);
+            bits &= bits - 1;
+        }
+        self.0.fired.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+impl std::fmt::Debug for HubMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("HubMetrics").field(&self.snapshot()).finish()
+    }
+}
+
+// Serialized as a snapshot, so it can sit in debug structs and reports.
+impl Serialize for HubMetrics {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        self.snapshot().serialize(s)
+    }
+}
+
+struct Registered<E, R> {
+    id: CallbackId,
+    priority: i32,
+    cb: Arc<dyn Callback<E, R>>,
+    filter: Option<Predicate<E>>,
+    // checked instead of the callback's mask
+    topics: Option<Subscription<E>>,
+    disabled: AtomicBool,
+    // set by remove(), so a fire() already past the registry skips it
+    removed: AtomicBool,
+}
+
+/// Topics a callback subscribed to, and how to find an event's, see [`CallbackHub::subscribe`].
+struct Subscription<E> {
+    set: TopicSet,
+    index_of: fn(&E) -> usize,
+}
+
+impl<E, R> Registered<E, R> {
+    fn new(cb: Arc<dyn Callback<E, R>>, filter: Option<Predicate<E>>) -> Self {
+        Self { id: CallbackId(0), priority: 0, cb, filter, topics: None, disabled: AtomicBool::new(false), removed: AtomicBool::new(false) }
+    }
+
+    fn wants(&self, ev_mask: u64, ev: &E) -> bool {
+        match &self.topics {
+            Some(sub) => sub.set.contains((sub.index_of)(ev)),
+            None => self.cb.mask() & ev_mask != 0,
+        }
+    }
 }
 
 /// Shared callback registry (order-preserving) + optional result channel.
-#[derive(Default)]
-pub struct CallbackHub<E> {
-    callbacks: Vec<Arc<dyn Callback<E>>>,
-    results_tx: Option<mpsc::Sender<CallbackResult>>,
+///
+/// Callbacks are added while the hub is being set up, and can be removed at any time through
+/// a shared reference, e.g. from the `Arc` a running sensor holds. They fire by priority,
+/// then in registration order, see [`CallbackHub::add_with_priority`].
+///
+/// Callbacks return JSON ([`CallbackResult`]) unless the hub is made for another result type,
+/// e.g. `CallbackHub::<XMountEvent, Alert>::default()`, whose callbacks return `Option<Alert>`
+/// to an `mpsc::Sender<Alert>`. Typed results go out as returned: the `"injected"` field and
+/// the [`CALLBACK_TIMED_OUT`] records are only added to JSON ones.
+pub struct CallbackHub<E, R = CallbackResult> {
+    callbacks: RwLock<Vec<Arc<Registered<E, R>>>>,
+    next_id: AtomicU64,
+    // taken out by ResultPolicy::CloseAfterNDrops
+    results_tx: RwLock<Option<mpsc::Sender<R>>>,
+    result_policy: ResultPolicy,
+    ring: Option<RingHandle<R>>,
+    metrics: HubMetrics,
+    filter: Option<Predicate<E>>,
+    filter_disabled: AtomicBool,
+    suppress_real: AtomicBool,
+    concurrency: Concurrency,
+    callback_timeout: Option<Duration>,
+    // stamp events for Envelope callbacks
+    enveloped: bool,
+    sensor: RwLock<Option<Arc<str>>>,
+    // made by the first subscribe_broadcast()
+    broadcast: OnceLock<Broadcast<E>>,
+    quiet: Option<Quiet<E>>,
 }
 
+/// Events a [`CallbackHub::subscribe_broadcast`] receiver may fall behind by before it lags.
+pub const BROADCAST_CAPACITY: usize = 1024;
+
 impl<E> CallbackHub<E> {
+    /// A hub for JSON results; use `default()` for another result type.
     pub fn new() -> Self {
-        Self { callbacks: Vec::new(), results_tx: None }
+        Self::default()
+    }
+}
+
+impl<E, R: Send + 'static> Default for CallbackHub<E, R> {
+    fn default() -> Self {
+        Self {
+            callbacks: RwLock::default(),
+            next_id: AtomicU64::new(0),
+            results_tx: RwLock::default(),
+            result_policy: ResultPolicy::Block,
+            ring: None,
+            metrics: HubMetrics::default(),
+            filter: None,
+            filter_disabled: AtomicBool::new(false),
+            suppress_real: AtomicBool::new(false),
+            concurrency: Concurrency::Sequential,
+            callback_timeout: None,
+            enveloped: false,
+            sensor: RwLock::default(),
+            broadcast: OnceLock::new(),
+            quiet: None,
+        }
+    }
+}
+
+impl<E, R: Send + 'static> CallbackHub<E, R> {
+    /// Register `cb` after the callbacks already there, at priority 0.
+    pub fn add<C: Callback<E, R> + 'static>(&mut self, cb: C) -> CallbackId {
+        self.register(Registered::new(Arc::new(cb), None))
+    }
+
+    /// Register `cb` to fire before the callbacks of higher `priority` numbers and after
+    /// those of lower ones, e.g. an audit callback at -10 ahead of remediation at 0 (the
+    /// priority of [`CallbackHub::add`] and friends). Equal priorities fire in registration
+    /// order.
+    ///
+    /// Under [`Concurrency::Parallel`] and [`Concurrency::Bounded`], priorities are barriers:
+    /// every callback of one priority completes (or times out) before the next priority
+    /// starts. Removing a callback leaves the others' order as it was; one that an earlier
+    /// callback removes while an event is being fired is not called for it.
+    pub fn add_with_priority<C: Callback<E, R> + 'static>(&mut self, cb: C, priority: i32) -> CallbackId {
+        let mut r = Registered::new(Arc::new(cb), None);
+        r.priority = priority;
+        self.register(r)
+    }
+
+    /// Register `f` for the events `mask` selects, as a [`FnCallback`], e.g.
+    /// `hub.add_fn(XMountMask::all().bits(), |ev| { println!("{ev:?}"); async { None } })`.
+    pub fn add_fn<F, Fut>(&mut self, mask: u64, f: F) -> CallbackId
+    where
+        E: Sync,
+        F: Fn(&E) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<R>> + Send,
+    {
+        self.add(FnCallback::new(mask, f))
+    }
+
+    /// Register `cb` to get each event in an [`Envelope`] with its sequence number, time and
+    /// sensor name, e.g. to order events across sensors. Envelopes cost a clone of the event
+    /// and a clock read per fired event, in hubs with such callbacks only.
+    pub fn add_enveloped<C>(&mut self, cb: C) -> CallbackId
+    where
+        E: Clone + Send + Sync + 'static,
+        C: Callback<Envelope<E>, R> + 'static,
+    {
+        self.enveloped = true;
+        self.register(Registered::new(Arc::new(Enveloped(cb)), None))
+    }
+
+    /// Name the sensor firing into this hub, for [`Envelope::sensor`]. [`crate::sensor::spawn_sensor`]
+    /// names an unnamed hub after the sensor's crate, e.g. `xmount`; empty until then.
+    pub fn set_sensor_name(&self, name: &str) {
+        *self.sensor.write().unwrap_or_else(|e| e.into_inner()) = Some(name.into());
+    }
+
+    /// See [`CallbackHub::set_sensor_name`].
+    pub fn sensor_name(&self) -> Option<Arc<str>> {
+        self.sensor.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn register(&mut self, r: Registered<E, R>) -> CallbackId {
+        let id = CallbackId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        Self::insert(self.callbacks.get_mut().unwrap_or_else(|e| e.into_inner()), id, r);
+        id
+    }
+
+    /// Register `cb` at `priority` through a shared reference, for a hub already handed to a
+    /// sensor, see [`crate::scope::ScopedHub`]. A `fire()` in flight does not call it.
+    pub(crate) fn register_shared<C: Callback<E, R> + 'static>(&self, cb: C, priority: i32) -> CallbackId {
+        let mut r = Registered::new(Arc::new(cb), None);
+        r.priority = priority;
+        let id = CallbackId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        Self::insert(&mut self.callbacks.write().unwrap_or_else(|e| e.into_inner()), id, r);
+        id
+    }
+
+    fn insert(callbacks: &mut Vec<Arc<Registered<E, R>>>, id: CallbackId, mut r: Registered<E, R>) {
+        r.id = id;
+        let at = callbacks.partition_point(|c| c.priority <= r.priority);
+        callbacks.insert(at, Arc::new(r));
+    }
+
+    /// Unregister the callback `id`, returning false if it is not (or no longer) registered.
+    ///
+    /// Safe while events are being fired: a `fire()` in flight finishes the call it is in, if
+    /// that is the removed callback's, and does not call it afterwards.
+    pub fn remove(&self, id: CallbackId) -> bool {
+        let mut callbacks = self.callbacks.write().unwrap_or_else(|e| e.into_inner());
+        let Some(pos) = callbacks.iter().position(|r| r.id == id) else {
+            return false;
+        };
+        callbacks.remove(pos).removed.store(true, Ordering::Relaxed);
+        true
+    }
+
+    /// Unregister every callback, with the same guarantee as [`CallbackHub::remove`].
+    pub fn clear(&self) {
+        for r in self.callbacks.write().unwrap_or_else(|e| e.into_inner()).drain(..) {
+            r.removed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Callbacks currently registered.
+    pub fn len(&self) -> usize {
+        self.callbacks.read().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The registered callbacks, in firing order, for one event: removals from here on do not shift
+    /// the iteration, and the lock is not held across the calls.
+    fn snapshot(&self) -> Vec<Arc<Registered<E, R>>> {
+        self.callbacks.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Add a callback that is only called for events matching its mask *and* `pred`, e.g.
+    /// `hub.add_filtered(cb, |ev| matches!(ev, XMountEvent::Mounted { info, .. } if info.fstype == "nfs"))`.
+    ///
+    /// The predicate runs inline in `fire()` for every event passing the mask, so keep it cheap.
+    /// If it panics, the callback is disabled (with an error logged) and `fire()` carries on.
+    pub fn add_filtered<C, F>(&mut self, cb: C, pred: F) -> CallbackId
+    where
+        C: Callback<E, R> + 'static,
+        F: Fn(&E) -> bool + Send + Sync + 'static,
+    {
+        self.register(Registered::new(Arc::new(cb), Some(Box::new(pred))))
+    }
+
+    /// Only deliver events passing `pred`, to any callback. It runs once per fired event, before
+    /// the callbacks' masks and own predicates, so it composes with both and with the sensor's
+    /// own watch/ignore rules, e.g. `hub.set_filter(Filter::parse(expr)?.predicate())` for a
+    /// CLI's `--filter` (see [`crate::filter`]). Rejections count as `filtered_out` for every
+    /// callback whose mask matched. If it panics, it is dropped (logged) and events pass.
+    pub fn set_filter<F>(&mut self, pred: F)
+    where
+        F: Fn(&E) -> bool + Send + Sync + 'static,
+    {
+        self.filter = Some(Box::new(pred));
+        self.filter_disabled.store(false, Ordering::Relaxed);
+    }
+
+    /// True unless the hub-wide filter rejects `ev`.
+    fn passes_filter(&self, ev: &E) -> bool {
+        let Some(pred) = &self.filter else {
+            return true;
+        };
+        if self.filter_disabled.load(Ordering::Relaxed) {
+            return true;
+        }
+        catch_unwind(AssertUnwindSafe(|| pred(ev))).unwrap_or_else(|_| {
+            log::error!("hub filter panicked, filter disabled");
+            self.metrics.0.errors.fetch_add(1, Ordering::Relaxed);
+            self.filter_disabled.store(true, Ordering::Relaxed);
+            true
+        })
+    }
+
+    /// Drop the events sensors fire, delivering injected ones only, until switched off again,
+    /// e.g. while a failure scenario plays so consumers do not mix it up with the real state.
+    /// Dropped events still count as fired.
+    pub fn set_suppress_real(&self, on: bool) {
+        self.suppress_real.store(on, Ordering::Relaxed);
+    }
+
+    /// True if `fire()` is to drop the event at hand, see [`CallbackHub::set_suppress_real`].
+    fn suppressed(&self) -> bool {
+        self.suppress_real.load(Ordering::Relaxed) && !is_injected()
     }
 
-    pub fn add<C: Callback<E> + 'static>(&mut self, cb: C) {
-        self.callbacks.push(Arc::new(cb));
+    /// Events fired so far, whether or not a callback took them. Sensors compare it across a
+    /// tick to tell idle ticks, see [`crate::pulse`].
+    pub fn fired(&self) -> u64 {
+        self.metrics.0.fired.load(Ordering::Relaxed)
     }
 
-    pub fn set_result_channel(&mut self, tx: mpsc::Sender<CallbackResult>) {
-        self.results_tx = Some(tx);
+    /// Counters of what the hub dispatched, updated as it fires and sends results. The handle
+    /// stays valid after the hub moved into a sensor; take [`HubMetrics::snapshot`]s of it.
+    pub fn metrics(&self) -> HubMetrics {
+        self.metrics.clone()
     }
 
-    /// Fire an event to callbacks whose mask matches `ev_mask`.
+    pub fn stats(&self) -> HubStats {
+        let c = &self.metrics.0;
+        HubStats {
+            called: c.called.load(Ordering::Relaxed),
+            mask_mismatch: c.mask_mismatch.load(Ordering::Relaxed),
+            filtered_out: c.filtered_out.load(Ordering::Relaxed),
+            disabled: c.disabled.load(Ordering::Relaxed),
+            timed_out: c.timed_out.load(Ordering::Relaxed),
+            results_dropped: c.results_dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Mask (or topic) check, then the hub filter's verdict (`passed`), then the predicate. Counts the outcome.
+    /// A callback removed since the snapshot is skipped without counting.
+    fn admits(&self, idx: usize, r: &Registered<E, R>, ev_mask: u64, ev: &E, passed: bool) -> bool {
+        if r.removed.load(Ordering::Relaxed) {
+            return false;
+        }
+        let c = &self.metrics.0;
+        if !r.wants(ev_mask, ev) {
+            c.mask_mismatch.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        if r.disabled.load(Ordering::Relaxed) {
+            c.disabled.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        if !passed {
+            c.filtered_out.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        let pass = match &r.filter {
+            None => true,
+            Some(pred) => match catch_unwind(AssertUnwindSafe(|| pred(ev))) {
+                Ok(pass) => pass,
+                Err(_) => {
+                    log::error!("callback #{idx}: filter predicate panicked, callback disabled");
+                    r.disabled.store(true, Ordering::Relaxed);
+                    c.disabled.fetch_add(1, Ordering::Relaxed);
+                    c.errors.fetch_add(1, Ordering::Relaxed);
+                    return false;
+                }
+            },
+        };
+
+        if pass {
+            c.called.fetch_add(1, Ordering::Relaxed);
+        } else {
+            c.filtered_out.fetch_add(1, Ordering::Relaxed);
+        }
+        pass
+    }
+
+    /// Await the callbacks matching an event concurrently instead of one after another, so a
+    /// slow one (e.g. an HTTP POST) does not hold up the others.
+    ///
+    /// `fire()` still resolves once all of them are done, so each callback sees the events in
+    /// the order they were fired and the sensor's next tick still waits. Results reach the
+    /// result channel in the order the callbacks complete; concurrency drops none of them, the
+    /// [`ResultPolicy`] still does when the channel is full.
+    pub fn set_concurrency(&mut self, concurrency: Concurrency) {
+        self.concurrency = concurrency;
+    }
+
+    /// Callbacks that may run at once, out of `matching`.
+    fn in_flight(&self, matching: usize) -> usize {
+        match self.concurrency {
+            Concurrency::Sequential => 1,
+            Concurrency::Parallel => matching.max(1),
+            Concurrency::Bounded(n) => n.max(1),
+        }
+    }
+
+    /// Give each callback at most `timeout` per event, so a hung one cannot hang the sensor.
+    /// A callback running longer is dropped mid-call (not left running in the background),
+    /// logged, counted as `timed_out` in [`CallbackHub::stats`] and reported on the result
+    /// channel as `{"CallbackTimedOut": {"callback": <position>, "id": <id>, "timeout_ms": <ms>}}`;
+    /// the next callback then runs. No timeout by default.
+    pub fn set_callback_timeout(&mut self, timeout: Duration) {
+        self.callback_timeout = Some(timeout);
+    }
+
+    /// Call `r` (at position `idx`) within the callback timeout, or `limit` if shorter.
+    /// `Err` if it timed out; the callback timeout is reported here, `limit` by the caller.
+    async fn call(&self, idx: usize, r: &Registered<E, R>, ev: &E, limit: Option<Duration>) -> Result<Option<R>, ()> {
+        let call = self.call_within(idx, r, ev, limit);
+        #[cfg(feature = "tracing")]
+        let call = spans::callback(idx, r.id.0, call);
+        call.await
+    }
+
+    /// [`CallbackHub::call`] past the tracing.
+    async fn call_within(&self, idx: usize, r: &Registered<E, R>, ev: &E, limit: Option<Duration>) -> Result<Option<R>, ()> {
+        let own = self.callback_timeout.filter(|t| limit.is_none_or(|l| *t <= l));
+        let Some(timeout) = own.or(limit) else {
+            return Ok(Self::invoke(r, ev).await);
+        };
+        let Ok(res) = tokio::time::timeout(timeout, Self::invoke(r, ev)).await else {
+            self.metrics.0.timed_out.fetch_add(1, Ordering::Relaxed);
+            if own.is_some() {
+                log::warn!("callback #{idx}: timed out after {timeout:?}, abandoned");
+                let ms = u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX);
+                let record: Box<dyn Any + Send> = Box::new(json!({ CALLBACK_TIMED_OUT: { "callback": idx, "id": r.id.0, "timeout_ms": ms } }));
+                if let Ok(record) = record.downcast::<R>() {
+                    self.send_result(*record).await;
+                }
+            }
+            return Err(());
+        };
+        Ok(res)
+    }
+
+    /// Run the callback, marked for [`in_callback`] in debug builds.
+    async fn invoke(r: &Registered<E, R>, ev: &E) -> Option<R> {
+        if cfg!(debug_assertions) { IN_CALLBACK.scope(true, r.cb.call(ev)).await } else { r.cb.call(ev).await }
+    }
+
+    /// Send what callbacks return to `tx`, waiting for room when it is full
+    /// ([`ResultPolicy::Block`]).
+    pub fn set_result_channel(&mut self, tx: mpsc::Sender<R>) {
+        self.set_result_channel_with_policy(tx, ResultPolicy::Block);
+    }
+
+    /// Send what callbacks return to `tx`, doing as `policy` says when it is full.
+    /// [`ResultPolicy::DropOldest`] needs a tokio runtime when the first result is sent.
+    pub fn set_result_channel_with_policy(&mut self, tx: mpsc::Sender<R>, policy: ResultPolicy) {
+        *self.results_tx.get_mut().unwrap_or_else(|e| e.into_inner()) = Some(tx);
+        self.result_policy = policy;
+        self.ring = (policy == ResultPolicy::DropOldest).then(|| RingHandle(Arc::default()));
+    }
+
+    /// The results channel, if set, e.g. to send results of handlers a sensor runs itself
+    /// to the same consumer. Those are sent as the sender they get sends them, not by policy.
+    pub fn result_channel(&self) -> Option<mpsc::Sender<R>> {
+        self.results_tx.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// The policy the results channel was set with.
+    pub fn result_policy(&self) -> ResultPolicy {
+        self.result_policy
+    }
+
+    async fn send_result(&self, mut r: R) {
+        let Some(tx) = self.result_channel() else {
+            return;
+        };
+        if is_injected()
+            && let Some(Value::Object(map)) = (&mut r as &mut dyn Any).downcast_mut::<Value>()
+        {
+            map.insert(INJECTED_FIELD.to_string(), Value::Bool(true));
+        }
+        if is_quiet()
+            && let Some(Value::Object(map)) = (&mut r as &mut dyn Any).downcast_mut::<Value>()
+        {
+            map.insert(SUPPRESSED_FIELD.to_string(), Value::Bool(true));
+        }
+        // whether `r` got in, and whether a result was dropped for want of room
+        let (delivered, dropped) = match (self.result_policy, &self.ring) {
+            (ResultPolicy::DropOldest, Some(ring)) => (true, ring.0.push(r, &tx)),
+            (ResultPolicy::DropNewest | ResultPolicy::CloseAfterNDrops(_), _) => match tx.try_send(r) {
+                Ok(()) => (true, false),
+                Err(e) => (false, matches!(e, mpsc::error::TrySendError::Full(_))),
+            },
+            _ => (tx.send(r).await.is_ok(), false),
+        };
+        if delivered {
+            self.metrics.0.results_delivered.fetch_add(1, Ordering::Relaxed);
+        }
+        if !dropped {
+            return;
+        }
+
+        let total = self.metrics.0.results_dropped.fetch_add(1, Ordering::Relaxed) + 1;
+        if total == 1 {
+            log::warn!("result channel full, dropping results ({:?})", self.result_policy);
+        }
+        if let ResultPolicy::CloseAfterNDrops(n) = self.result_policy
+            && total >= n
+            && self.results_tx.write().unwrap_or_else(|e| e.into_inner()).take().is_some()
+        {
+            log::error!("result channel closed after {total} dropped results");
+        }
+    }
+
+    /// Fire an event to callbacks whose mask matches `ev_mask` (and predicate, if any).
+    ///
+    /// Callbacks run one after another in priority, then registration order (unless set otherwise with
+    /// [`CallbackHub::set_concurrency`]), and this resolves once all are done. Sensors await
+    /// it for each event, which is what keeps ticks from overlapping and events about one
+    /// entity in order (see "Ordering and delivery guarantees" in the README).
     pub async fn fire(&self, ev_mask: u64, ev: &E) {
-        for cb in &self.callbacks {
-            if (cb.mask() & ev_mask) == 0 {
+        let seq = self.metrics.fired(ev_mask);
+        if self.suppressed() {
+            return;
+        }
+        let deliver = QUIET.scope(self.quiet(ev_mask, ev), self.deliver(ev_mask, ev));
+        #[cfg(feature = "tracing")]
+        let deliver = deliver.instrument(spans::fire(self.sensor_name().as_deref(), ev_mask, seq));
+        match self.stamp(seq) {
+            Some(stamp) => STAMP.scope(stamp, deliver).await,
+            None => deliver.await,
+        }
+    }
+
+    /// Whether the event is in a maintenance window, see [`CallbackHub::set_quiet_hours`].
+    fn quiet(&self, ev_mask: u64, ev: &E) -> bool {
+        let Some((quiet, matches)) = &self.quiet else {
+            return false;
+        };
+        quiet.check(self.sensor_name().as_deref(), ev_mask, |f| matches(f, ev))
+    }
+
+    /// The stamp for the event `seq`, if any callback takes envelopes.
+    fn stamp(&self, seq: u64) -> Option<Stamp> {
+        if !self.enveloped {
+            return None;
+        }
+        Some(Stamp { seq, ts: SystemTime::now(), sensor: self.sensor_name().unwrap_or_else(|| "".into()) })
+    }
+
+    /// `fire()` past the counting and suppression.
+    async fn deliver(&self, ev_mask: u64, ev: &E) {
+        let passed = self.passes_filter(ev);
+        self.broadcast(ev, passed);
+        if self.concurrency != Concurrency::Sequential {
+            self.dispatch(self.matching(ev_mask, ev, passed), ev, None).await;
+            return;
+        }
+        for (idx, r) in self.snapshot().iter().enumerate() {
+            if !self.admits(idx, r, ev_mask, ev, passed) {
                 continue;
             }
-            if let Some(r) = cb.call(ev).await
-                && let Some(tx) = &self.results_tx
-            {
-                let _ = tx.send(r).await;
+            if let Ok(Some(r)) = self.call(idx, r, ev, None).await {
+                self.send_result(r).await;
             }
         }
     }
+
+    /// Hand `ev` to the [`CallbackHub::subscribe_broadcast`] receivers, if it passed the filter.
+    fn broadcast(&self, ev: &E, passed: bool) {
+        if let Some((tx, clone)) = self.broadcast.get()
+            && passed
+            && tx.receiver_count() > 0
+        {
+            let _ = tx.send(clone(ev));
+        }
+    }
+
+    /// The callbacks admitting `ev`, with their positions, for concurrent dispatch.
+    fn matching(&self, ev_mask: u64, ev: &E, passed: bool) -> Vec<(usize, Arc<Registered<E, R>>)> {
+        self.snapshot().into_iter().enumerate().filter(|(idx, r)| self.admits(*idx, r, ev_mask, ev, passed)).collect()
+    }
+
+    /// Run `matching` as [`CallbackHub::set_concurrency`] allows, one priority after another,
+    /// each within `limit` (and the callback timeout), sending results as they come. Returns
+    /// the positions of those that timed out.
+    async fn dispatch(&self, matching: Vec<(usize, Arc<Registered<E, R>>)>, ev: &E, limit: Option<Duration>) -> Vec<usize> {
+        let mut timed_out = Vec::new();
+        for group in matching.chunk_by(|(_, a), (_, b)| a.priority == b.priority) {
+            timed_out.extend(self.dispatch_group(group.to_vec(), ev, limit).await);
+        }
+        timed_out.sort_unstable();
+        timed_out
+    }
+
+    /// [`CallbackHub::dispatch`] for the callbacks of one priority.
+    async fn dispatch_group(&self, matching: Vec<(usize, Arc<Registered<E, R>>)>, ev: &E, limit: Option<Duration>) -> Vec<usize> {
+        let at_once = self.in_flight(matching.len());
+        let mut calls = stream::iter(matching)
+            .map(|(idx, r)| async move {
+                // removed while earlier ones ran, under Bounded
+                if r.removed.load(Ordering::Relaxed) {
+                    return (idx, Ok(None));
+                }
+                (idx, self.call(idx, &r, ev, limit).await)
+            })
+            .buffer_unordered(at_once);
+
+        let mut timed_out = Vec::new();
+        while let Some((idx, done)) = calls.next().await {
+            match done {
+                Ok(Some(r)) => self.send_result(r).await,
+                Ok(None) => {}
+                Err(()) => timed_out.push(idx),
+            }
+        }
+        timed_out
+    }
+
+    /// Fire a caller-made event exactly like a sensor would, for testing handlers without
+    /// provoking real mounts, processes or connections. Callbacks see [`is_injected`] return
+    /// true, and object results get `"injected": true` so consumers can discard them.
+    pub async fn inject(&self, ev_mask: u64, ev: &E) {
+        INJECTED.scope(true, self.fire(ev_mask, ev)).await
+    }
+
+    /// Like [`CallbackHub::fire`], but each callback gets at most `timeout` to complete.
+    ///
+    /// Callbacks run one after another in priority, then registration order, or as set with
+    /// [`CallbackHub::set_concurrency`], and this resolves only after every matching callback
+    /// completed or timed out. A sensor awaiting it therefore does not proceed (e.g. to its
+    /// next tick) before e.g. a "stop service" callback is done.
+    /// A timed-out callback is abandoned (its future is dropped) and the rest still run. If
+    /// [`CallbackHub::set_callback_timeout`] is shorter, it applies and reports as usual.
+    ///
+    /// This orders omnitrace's own work only; it cannot hold back the kernel or whatever
+    /// triggered the event.
+    pub async fn fire_and_wait_all(&self, ev_mask: u64, ev: &E, timeout: Duration) -> Result<(), BarrierTimeout> {
+        let seq = self.metrics.fired(ev_mask);
+        if self.suppressed() {
+            return Ok(());
+        }
+        let deliver = QUIET.scope(self.quiet(ev_mask, ev), self.deliver_all(ev_mask, ev, timeout));
+        #[cfg(feature = "tracing")]
+        let deliver = deliver.instrument(spans::fire(self.sensor_name().as_deref(), ev_mask, seq));
+        match self.stamp(seq) {
+            Some(stamp) => STAMP.scope(stamp, deliver).await,
+            None => deliver.await,
+        }
+    }
+
+    /// `fire_and_wait_all()` past the counting and suppression.
+    async fn deliver_all(&self, ev_mask: u64, ev: &E, timeout: Duration) -> Result<(), BarrierTimeout> {
+        let passed = self.passes_filter(ev);
+        self.broadcast(ev, passed);
+        let timed_out = if self.concurrency != Concurrency::Sequential {
+            self.dispatch(self.matching(ev_mask, ev, passed), ev, Some(timeout)).await
+        } else {
+            let mut timed_out = Vec::new();
+            for (idx, r) in self.snapshot().iter().enumerate() {
+                if !self.admits(idx, r, ev_mask, ev, passed) {
+                    continue;
+                }
+
+                match self.call(idx, r, ev, Some(timeout)).await {
+                    Ok(Some(r)) => self.send_result(r).await,
+                    Ok(None) => {}
+                    Err(()) => timed_out.push(idx),
+                }
+            }
+            timed_out
+        };
+
+        if timed_out.is_empty() { Ok(()) } else { Err(BarrierTimeout { timed_out }) }
+    }
+}
+
+impl<E: Clone + Send + 'static, R: Send + 'static> CallbackHub<E, R> {
+    /// Receive every event fired from now on, for consuming them as a stream
+    /// (`while let Ok(ev) = rx.recv().await`) rather than in a callback. Events go out as
+    /// `fire()` starts on them, past the hub filter but regardless of masks, before the
+    /// callbacks have run. `fire()` never waits for a receiver: one more than
+    /// [`BROADCAST_CAPACITY`] events behind gets `RecvError::Lagged` and skips to the oldest
+    /// it still has.
+    pub fn subscribe_broadcast(&self) -> broadcast::Receiver<E> {
+        self.broadcast.get_or_init(|| (broadcast::channel(BROADCAST_CAPACITY).0, E::clone)).0.subscribe()
+    }
+}
+
+impl<E: Serialize, R: Send + 'static> CallbackHub<E, R> {
+    /// Check events against the maintenance windows of `quiet` as they fire. Those an active
+    /// window selects are delivered as usual, with [`is_quiet`] true for the callbacks and
+    /// `"suppressed": true` on their JSON object results. See [`crate::quiet`].
+    pub fn set_quiet_hours(&mut self, quiet: QuietHours) {
+        self.quiet = Some((quiet, |f, ev| f.matches_event(ev)));
+    }
+}
+
+impl<E: Topics, R: Send + 'static> CallbackHub<E, R> {
+    /// Add a callback for the events whose topic `pattern` selects, e.g. `"mount.*"`,
+    /// `"mount.changed"` or `"{proc.missing,proc.disappeared}"` (see [`crate::topics`]), in
+    /// place of its mask. The pattern is compiled once here; per event it costs a bit test.
+    /// A pattern selecting no topic of `E` is an error.
+    pub fn subscribe<C: Callback<E, R> + 'static>(&mut self, pattern: &str, cb: C) -> Result<CallbackId, TopicError> {
+        let mut r = Registered::new(Arc::new(cb), None);
+        r.topics = Some(Subscription { set: TopicSet::parse::<E>(pattern)?, index_of: E::topic_index });
+        Ok(self.register(r))
+    }
 }
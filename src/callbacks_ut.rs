@@ -0,0 +1,722 @@
+use crate::callbacks::{
+    BarrierTimeout, CALLBACK_TIMED_OUT, Callback, CallbackHub, CallbackId, CallbackResult, Concurrency, Debounced, Envelope, Filtered, FnCallback,
+    HubStats, Once, RateLimited, ResultPolicy, is_injected,
+};
+use async_trait::async_trait;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::mpsc::channel;
+
+type Log = Arc<Mutex<Vec<String>>>;
+
+struct SlowCb {
+    name: &'static str,
+    delay: Duration,
+    log: Log,
+}
+
+#[async_trait]
+impl Callback<u32> for SlowCb {
+    fn mask(&self) -> u64 {
+        0b1
+    }
+
+    async fn call(&self, ev: &u32) -> Option<CallbackResult> {
+        self.log.lock().unwrap().push(format!("{} start {ev}", self.name));
+        tokio::time::sleep(self.delay).await;
+        self.log.lock().unwrap().push(format!("{} done {ev}", self.name));
+        Some(serde_json::json!(self.name))
+    }
+}
+
+fn hub(delays: &[(&'static str, u64)], log: &Log) -> CallbackHub<u32> {
+    let mut hub = CallbackHub::new();
+    for (name, ms) in delays {
+        hub.add(SlowCb { name, delay: Duration::from_millis(*ms), log: log.clone() });
+    }
+    hub
+}
+
+#[tokio::test]
+async fn barrier_waits_for_slow_callbacks_in_order() {
+    let log = Log::default();
+    let hub = hub(&[("stop-service", 50), ("notify", 0)], &log);
+
+    hub.fire_and_wait_all(0b1, &1, Duration::from_secs(1)).await.unwrap();
+    log.lock().unwrap().push("next tick".into());
+
+    assert_eq!(*log.lock().unwrap(), vec!["stop-service start 1", "stop-service done 1", "notify start 1", "notify done 1", "next tick"]);
+}
+
+#[tokio::test]
+async fn barrier_reports_timed_out_callbacks() {
+    let log = Log::default();
+    let mut hub = hub(&[("fast", 0), ("stuck", 1000), ("after", 0)], &log);
+    let (tx, mut rx) = channel(8);
+    hub.set_result_channel(tx);
+
+    let err = hub.fire_and_wait_all(0b1, &7, Duration::from_millis(20)).await.unwrap_err();
+    assert_eq!(err, BarrierTimeout { timed_out: vec![1] });
+
+    // the stuck callback was abandoned, the others ran and reported
+    assert_eq!(*log.lock().unwrap(), vec!["fast start 7", "fast done 7", "stuck start 7", "after start 7", "after done 7"]);
+    assert_eq!(rx.try_recv().unwrap(), "fast");
+    assert_eq!(rx.try_recv().unwrap(), "after");
+    assert!(rx.try_recv().is_err());
+}
+
+struct SeesInjection;
+
+#[async_trait]
+impl Callback<u32> for SeesInjection {
+    fn mask(&self) -> u64 {
+        0b1
+    }
+
+    async fn call(&self, ev: &u32) -> Option<CallbackResult> {
+        Some(serde_json::json!({ "ev": ev, "seen": is_injected() }))
+    }
+}
+
+#[tokio::test]
+async fn injected_events_are_marked() {
+    let (tx, mut rx) = channel(4);
+    let mut hub = CallbackHub::new();
+    hub.add(SeesInjection);
+    hub.set_result_channel(tx);
+
+    hub.fire(0b1, &1).await;
+    hub.inject(0b1, &2).await;
+    hub.inject(0b10, &3).await; // masked out like any other event
+    assert!(!is_injected());
+
+    assert_eq!(rx.try_recv().unwrap(), serde_json::json!({ "ev": 1, "seen": false }));
+    assert_eq!(rx.try_recv().unwrap(), serde_json::json!({ "ev": 2, "seen": true, "injected": true }));
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn predicates_filter_after_the_mask() {
+    let log = Log::default();
+    let mut hub = CallbackHub::new();
+    hub.add_filtered(SlowCb { name: "even", delay: Duration::ZERO, log: log.clone() }, |ev: &u32| ev.is_multiple_of(2));
+    hub.add(SlowCb { name: "all", delay: Duration::ZERO, log: log.clone() });
+
+    for ev in 1..=4 {
+        hub.fire(0b1, &ev).await;
+    }
+    hub.fire(0b10, &6).await; // mask mismatch, the predicate is not even asked
+
+    let calls: Vec<String> = log.lock().unwrap().iter().filter(|l| l.contains("start")).cloned().collect();
+    assert_eq!(calls, vec!["all start 1", "even start 2", "all start 2", "all start 3", "even start 4", "all start 4"]);
+    assert_eq!(hub.stats(), HubStats { called: 6, mask_mismatch: 2, filtered_out: 2, disabled: 0, timed_out: 0, results_dropped: 0 });
+}
+
+#[tokio::test]
+async fn closures_are_callbacks() {
+    let log = Log::default();
+    let mut hub = CallbackHub::<u32>::new();
+    let (tx, mut rx) = channel(8);
+    hub.set_result_channel(tx);
+    let odd = log.clone();
+    hub.add_fn(0b1, move |ev: &u32| {
+        let (ev, log) = (*ev, odd.clone());
+        async move {
+            log.lock().unwrap().push(format!("odd {ev}"));
+            (ev % 2 == 1).then(|| serde_json::json!(ev))
+        }
+    });
+    hub.add(Once::new(FnCallback::new(0b10, |ev: &u32| std::future::ready(Some(serde_json::json!(ev * 100))))));
+
+    for (mask, ev) in [(0b1, 1), (0b1, 2), (0b10, 3), (0b10, 4)] {
+        hub.fire(mask, &ev).await;
+    }
+    assert_eq!(*log.lock().unwrap(), ["odd 1", "odd 2"]);
+    assert_eq!(rx.try_recv().unwrap(), serde_json::json!(1));
+    assert_eq!(rx.try_recv().unwrap(), serde_json::json!(300));
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn once_runs_for_the_first_admitted_event_only() {
+    let log = Log::default();
+    let mut hub = CallbackHub::new();
+    hub.add_filtered(Once::new(SlowCb { name: "once", delay: Duration::ZERO, log: log.clone() }), |ev: &u32| *ev > 1);
+
+    for ev in 1..=4 {
+        hub.fire(0b1, &ev).await;
+    }
+    assert_eq!(*log.lock().unwrap(), ["once start 2", "once done 2"]);
+    assert_eq!(hub.stats(), HubStats { called: 1, mask_mismatch: 2, filtered_out: 1, disabled: 0, timed_out: 0, results_dropped: 0 });
+}
+
+#[tokio::test]
+async fn panicking_predicate_disables_only_its_callback() {
+    let log = Log::default();
+    let mut hub = CallbackHub::new();
+    hub.add_filtered(SlowCb { name: "fragile", delay: Duration::ZERO, log: log.clone() }, |ev: &u32| {
+        assert!(*ev < 2, "bad event");
+        true
+    });
+    hub.add(SlowCb { name: "sturdy", delay: Duration::ZERO, log: log.clone() });
+
+    for ev in 1..=3 {
+        hub.fire_and_wait_all(0b1, &ev, Duration::from_secs(1)).await.unwrap();
+    }
+
+    let calls: Vec<String> = log.lock().unwrap().iter().filter(|l| l.contains("start")).cloned().collect();
+    assert_eq!(calls, vec!["fragile start 1", "sturdy start 1", "sturdy start 2", "sturdy start 3"]);
+    assert_eq!(hub.stats(), HubStats { called: 4, mask_mismatch: 0, filtered_out: 0, disabled: 2, timed_out: 0, results_dropped: 0 });
+}
+
+#[tokio::test]
+async fn hub_filter_runs_before_callback_predicates() {
+    let log = Log::default();
+    let mut hub = CallbackHub::new();
+    hub.add_filtered(SlowCb { name: "even", delay: Duration::ZERO, log: log.clone() }, |ev: &u32| ev.is_multiple_of(2));
+    hub.add(SlowCb { name: "all", delay: Duration::ZERO, log: log.clone() });
+    hub.set_filter(|ev: &u32| *ev > 2);
+
+    for ev in 1..=4 {
+        hub.fire(0b1, &ev).await;
+    }
+
+    let calls: Vec<String> = log.lock().unwrap().iter().filter(|l| l.contains("start")).cloned().collect();
+    assert_eq!(calls, vec!["all start 3", "even start 4", "all start 4"]);
+    assert_eq!(hub.stats(), HubStats { called: 3, mask_mismatch: 0, filtered_out: 5, disabled: 0, timed_out: 0, results_dropped: 0 });
+}
+
+#[tokio::test(start_paused = true)]
+async fn removal_takes_effect_during_an_active_fire() {
+    let log = Log::default();
+    let mut hub = CallbackHub::new();
+    let slow = hub.add(SlowCb { name: "slow", delay: Duration::from_millis(50), log: log.clone() });
+    let next = hub.add(SlowCb { name: "next", delay: Duration::ZERO, log: log.clone() });
+    hub.add(SlowCb { name: "last", delay: Duration::ZERO, log: log.clone() });
+    let hub = Arc::new(hub);
+
+    let firing = tokio::spawn({
+        let hub = hub.clone();
+        async move { hub.fire(0b1, &1).await }
+    });
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    // the callback in flight completes, the one not reached yet is not called
+    assert!(hub.remove(slow));
+    assert!(hub.remove(next));
+    firing.await.unwrap();
+    hub.fire(0b1, &2).await;
+
+    assert_eq!(*log.lock().unwrap(), ["slow start 1", "slow done 1", "last start 1", "last done 1", "last start 2", "last done 2"]);
+    assert_eq!(hub.len(), 1);
+    assert_eq!(hub.stats(), HubStats { called: 3, mask_mismatch: 0, filtered_out: 0, disabled: 0, timed_out: 0, results_dropped: 0 });
+}
+
+#[tokio::test]
+async fn removed_ids_stay_removed() {
+    let log = Log::default();
+    let mut hub = CallbackHub::new();
+    let first = hub.add(SlowCb { name: "first", delay: Duration::ZERO, log: log.clone() });
+    assert!(hub.remove(first));
+    assert!(!hub.remove(first));
+
+    // ids are not reused, so the stale one cannot take out a newer callback
+    let second = hub.add(SlowCb { name: "second", delay: Duration::ZERO, log: log.clone() });
+    assert_ne!(first, second);
+    assert!(!hub.remove(first));
+    hub.fire(0b1, &1).await;
+
+    hub.add(SlowCb { name: "third", delay: Duration::ZERO, log: log.clone() });
+    hub.clear();
+    assert!(hub.is_empty());
+    assert!(!hub.remove(second));
+    hub.fire(0b1, &2).await;
+
+    assert_eq!(*log.lock().unwrap(), ["second start 1", "second done 1"]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn parallel_dispatch_does_not_wait_on_slow_callbacks() {
+    let log = Log::default();
+    let mut hub = hub(&[("post", 100), ("notify", 0)], &log);
+    hub.set_concurrency(Concurrency::Parallel);
+    let (tx, mut rx) = channel(8);
+    hub.set_result_channel(tx);
+
+    let started = tokio::time::Instant::now();
+    hub.fire(0b1, &1).await;
+    assert_eq!(started.elapsed(), Duration::from_millis(100));
+
+    assert_eq!(*log.lock().unwrap(), vec!["post start 1", "notify start 1", "notify done 1", "post done 1"]);
+    // in completion order, none dropped
+    assert_eq!(rx.try_recv().unwrap(), "notify");
+    assert_eq!(rx.try_recv().unwrap(), "post");
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test(start_paused = true)]
+async fn bounded_dispatch_limits_callbacks_in_flight() {
+    let log = Log::default();
+    let mut hub = hub(&[("a", 30), ("b", 10), ("c", 10)], &log);
+    hub.set_concurrency(Concurrency::Bounded(2));
+
+    let started = tokio::time::Instant::now();
+    hub.fire(0b1, &1).await;
+    assert_eq!(started.elapsed(), Duration::from_millis(30));
+    // c starts once b is done
+    assert_eq!(*log.lock().unwrap(), vec!["a start 1", "b start 1", "b done 1", "c start 1", "c done 1", "a done 1"]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn parallel_barrier_reports_timed_out_callbacks() {
+    let log = Log::default();
+    let mut hub = hub(&[("stuck", 1000), ("slow", 50), ("fast", 0)], &log);
+    hub.set_concurrency(Concurrency::Parallel);
+
+    let started = tokio::time::Instant::now();
+    let err = hub.fire_and_wait_all(0b1, &3, Duration::from_millis(80)).await.unwrap_err();
+    assert_eq!(err, BarrierTimeout { timed_out: vec![0] });
+    assert_eq!(started.elapsed(), Duration::from_millis(80));
+    assert!(log.lock().unwrap().contains(&"slow done 3".to_string()));
+}
+
+#[tokio::test(start_paused = true)]
+async fn callback_timeout_drops_the_call_and_reports_it() {
+    let log = Log::default();
+    let mut hub = hub(&[("hung", 1000), ("after", 0)], &log);
+    hub.set_callback_timeout(Duration::from_millis(50));
+    let (tx, mut rx) = channel(8);
+    hub.set_result_channel(tx);
+
+    let started = tokio::time::Instant::now();
+    hub.fire(0b1, &4).await;
+    assert_eq!(started.elapsed(), Duration::from_millis(50));
+
+    // dropped, not detached: it never finishes even once its sleep would have ended
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    assert_eq!(*log.lock().unwrap(), vec!["hung start 4", "after start 4", "after done 4"]);
+    assert_eq!(rx.try_recv().unwrap(), serde_json::json!({ CALLBACK_TIMED_OUT: { "callback": 0, "id": 0, "timeout_ms": 50 } }));
+    assert_eq!(rx.try_recv().unwrap(), "after");
+    assert!(rx.try_recv().is_err());
+    assert_eq!(hub.stats().timed_out, 1);
+    assert_eq!(hub.stats().called, 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn callback_timeout_applies_under_parallel_dispatch_and_barriers() {
+    let log = Log::default();
+    let mut hub = hub(&[("hung", 1000), ("slow", 30)], &log);
+    hub.set_callback_timeout(Duration::from_millis(50));
+    hub.set_concurrency(Concurrency::Parallel);
+
+    let started = tokio::time::Instant::now();
+    hub.fire(0b1, &5).await;
+    assert_eq!(started.elapsed(), Duration::from_millis(50));
+
+    // the shorter of the two wins
+    let err = hub.fire_and_wait_all(0b1, &6, Duration::from_secs(5)).await.unwrap_err();
+    assert_eq!(err, BarrierTimeout { timed_out: vec![0] });
+    assert_eq!(started.elapsed(), Duration::from_millis(100));
+    assert_eq!(hub.stats().timed_out, 2);
+    assert!(log.lock().unwrap().contains(&"slow done 6".to_string()));
+}
+
+#[tokio::test]
+async fn no_callback_timeout_by_default() {
+    let log = Log::default();
+    let hub = hub(&[("slow", 60)], &log);
+    hub.fire(0b1, &8).await;
+    assert_eq!(*log.lock().unwrap(), vec!["slow start 8", "slow done 8"]);
+    assert_eq!(hub.stats().timed_out, 0);
+}
+
+/// Returns the event, so results tell which event they came from.
+struct EchoCb;
+
+#[async_trait]
+impl Callback<u32> for EchoCb {
+    fn mask(&self) -> u64 {
+        0b1
+    }
+
+    async fn call(&self, ev: &u32) -> Option<CallbackResult> {
+        Some(serde_json::json!(ev))
+    }
+}
+
+fn echo_hub(capacity: usize, policy: ResultPolicy) -> (CallbackHub<u32>, tokio::sync::mpsc::Receiver<CallbackResult>) {
+    let mut hub = CallbackHub::new();
+    hub.add(EchoCb);
+    let (tx, rx) = channel(capacity);
+    hub.set_result_channel_with_policy(tx, policy);
+    (hub, rx)
+}
+
+#[tokio::test]
+async fn drop_newest_keeps_the_pipeline_moving() {
+    let (hub, mut rx) = echo_hub(2, ResultPolicy::DropNewest);
+    tokio::time::timeout(Duration::from_secs(1), async {
+        for ev in 0..5 {
+            hub.fire(0b1, &ev).await;
+        }
+    })
+    .await
+    .expect("a full channel must not block");
+
+    assert_eq!((rx.recv().await.unwrap(), rx.recv().await.unwrap()), (0.into(), 1.into()));
+    assert!(rx.try_recv().is_err());
+    assert_eq!(hub.stats().results_dropped, 3);
+    assert_eq!(hub.result_policy(), ResultPolicy::DropNewest);
+}
+
+fn echo(mask: u64) -> impl Callback<u32> {
+    FnCallback::new(mask, |ev: &u32| std::future::ready(Some(serde_json::json!(ev))))
+}
+
+/// Which of `evs` the callback passed on, calling it `every` apart.
+async fn passed<C: Callback<u32>>(cb: &C, evs: &[u32], every: Duration) -> Vec<u32> {
+    let mut out = Vec::new();
+    for ev in evs {
+        if cb.call(ev).await.is_some() {
+            out.push(*ev);
+        }
+        tokio::time::advance(every).await;
+    }
+    out
+}
+
+#[tokio::test(start_paused = true)]
+async fn debounced_drops_repeats_of_a_key_within_the_window() {
+    let cb = Debounced::new(echo(0b110), Duration::from_secs(1), |ev: &u32| ev % 10);
+    assert_eq!(cb.mask(), 0b110);
+
+    // key 1 flaps every 300ms: through at 0, 1.2s and 2.4s
+    assert_eq!(passed(&cb, &[1, 11, 21, 31, 41, 51, 61, 71, 81], Duration::from_millis(300)).await, [1, 41, 81]);
+    assert_eq!(cb.suppressed(), 6);
+    // other keys have windows of their own
+    assert_eq!(passed(&cb, &[2, 3, 12], Duration::ZERO).await, [2, 3]);
+
+    // right at the end of the window it passes again
+    tokio::time::advance(Duration::from_secs(1)).await;
+    assert_eq!(passed(&cb, &[22, 91], Duration::ZERO).await, [22, 91]);
+    assert_eq!(cb.suppressed(), 7);
+}
+
+#[tokio::test(start_paused = true)]
+async fn rate_limited_caps_events_per_second_and_counts_drops() {
+    let cb = RateLimited::new(echo(0b1), 5);
+    assert_eq!(cb.mask(), 0b1);
+
+    // a burst of up to 5 at once
+    assert_eq!(passed(&cb, &[1, 2, 3, 4, 5, 6, 7, 8], Duration::ZERO).await, [1, 2, 3, 4, 5]);
+    assert_eq!(cb.dropped(), 3);
+    // then one per 200ms
+    assert_eq!(passed(&cb, &[9, 10, 11, 12, 13, 14], Duration::from_millis(100)).await, [11, 13]);
+    assert_eq!(cb.dropped(), 7);
+
+    // idle time refills no more than a second's worth
+    tokio::time::advance(Duration::from_secs(60)).await;
+    assert_eq!(passed(&cb, &[0; 7], Duration::ZERO).await.len(), 5);
+    assert_eq!(cb.dropped(), 9);
+}
+
+#[tokio::test(start_paused = true)]
+async fn throttles_compose_with_other_callbacks_in_a_hub() {
+    let mut hub = CallbackHub::new();
+    hub.add(Debounced::new(RateLimited::new(echo(0b1), 2), Duration::from_secs(10), |ev: &u32| *ev));
+    let (tx, mut rx) = channel(16);
+    hub.set_result_channel(tx);
+    for ev in [1, 1, 2, 3, 2] {
+        hub.fire(0b1, &ev).await;
+    }
+    let mut got = Vec::new();
+    while let Ok(v) = rx.try_recv() {
+        got.push(v);
+    }
+    // 1 and 2 pass the debounce and the limit, 3 passes the debounce only
+    assert_eq!(got, [serde_json::json!(1), serde_json::json!(2)]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn filtered_skips_the_inner_callback_for_rejected_events() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let seen = calls.clone();
+    let cb = Filtered::new(
+        FnCallback::new(0b101, move |ev: &u32| {
+            seen.lock().unwrap().push(*ev);
+            std::future::ready(Some(serde_json::json!(ev)))
+        }),
+        |ev: &u32| ev.is_multiple_of(2),
+    );
+    assert_eq!(cb.mask(), 0b101);
+    assert_eq!(passed(&cb, &[1, 2, 3, 4], Duration::ZERO).await, [2, 4]);
+    assert_eq!(*calls.lock().unwrap(), [2, 4]);
+
+    // outside a limit, rejected events use none of its allowance
+    let cb = Filtered::new(RateLimited::new(echo(0b1), 2), |ev: &u32| *ev > 10);
+    assert_eq!(passed(&cb, &[1, 2, 3, 11, 12, 13], Duration::ZERO).await, [11, 12]);
+}
+
+#[tokio::test]
+async fn metrics_count_dispatch_and_results() {
+    let (mut hub, _rx) = echo_hub(2, ResultPolicy::DropNewest);
+    hub.add_filtered(EchoCb, |_| panic!("bad predicate"));
+    hub.add(SlowCb { name: "slow", delay: Duration::from_millis(200), log: Log::default() });
+    hub.set_callback_timeout(Duration::from_millis(10));
+    let metrics = hub.metrics();
+
+    let hub = Arc::new(hub);
+    for ev in 0..3 {
+        hub.fire(0b1, &ev).await;
+    }
+    hub.fire(0b110, &9).await;
+
+    let m = metrics.snapshot();
+    assert_eq!((m.fired, m.fired_by_mask.into_iter().collect::<Vec<_>>()), (4, vec![(0b1, 3), (0b10, 1), (0b100, 1)]));
+    // echo three times, the slow one three times, the filtered one never
+    assert_eq!((m.callbacks_invoked, m.callbacks_timed_out, m.callback_errors), (6, 3, 1));
+    // 3 echoes and 3 timeout records for a channel of 2
+    assert_eq!((m.results_delivered, m.results_dropped), (2, 4));
+    assert_eq!(hub.stats().results_dropped, 4);
+
+    let json = serde_json::to_value(&metrics).unwrap();
+    assert_eq!(json["fired_by_mask"]["4"], 1);
+    assert_eq!(json["results_delivered"], 2);
+}
+
+#[tokio::test]
+async fn drop_oldest_evicts_waiting_results_for_new_ones() {
+    let mut hub = CallbackHub::new();
+    hub.add(EchoCb);
+    let (tx, mut rx) = channel(2);
+    // full before the first result, so the forwarding task cannot move any of them whenever it runs
+    for _ in 0..2 {
+        tx.try_send(CallbackResult::Null).unwrap();
+    }
+    hub.set_result_channel_with_policy(tx, ResultPolicy::DropOldest);
+    for ev in 0..6 {
+        hub.fire(0b1, &ev).await;
+    }
+    let stats = hub.stats();
+    drop(hub);
+
+    let mut got = Vec::new();
+    while let Some(r) = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await.expect("closed once the hub is gone") {
+        got.push(r.as_u64());
+    }
+    // only the newest waited, behind what already filled the channel
+    assert_eq!(got, [None, None, Some(4), Some(5)]);
+    assert_eq!(stats.results_dropped, 4);
+}
+
+#[tokio::test]
+async fn drop_oldest_forwards_everything_to_a_consumer_keeping_up() {
+    let (hub, mut rx) = echo_hub(2, ResultPolicy::DropOldest);
+    for ev in 0..10 {
+        hub.fire(0b1, &ev).await;
+        assert_eq!(rx.recv().await.unwrap(), ev);
+    }
+    assert_eq!(hub.stats().results_dropped, 0);
+}
+
+#[tokio::test]
+async fn close_after_n_drops_closes_the_channel() {
+    let (hub, mut rx) = echo_hub(1, ResultPolicy::CloseAfterNDrops(2));
+    for ev in 0..4 {
+        hub.fire(0b1, &ev).await;
+    }
+    assert!(hub.result_channel().is_none());
+    assert_eq!(rx.recv().await.unwrap(), 0);
+    assert_eq!(rx.recv().await, None);
+    // what comes after the close is not counted
+    assert_eq!(hub.stats().results_dropped, 2);
+}
+
+#[tokio::test]
+async fn suppressing_real_events_lets_injected_ones_through() {
+    let (hub, mut rx) = echo_hub(8, ResultPolicy::Block);
+    hub.set_suppress_real(true);
+    hub.fire(0b1, &1).await;
+    hub.inject(0b1, &2).await;
+    hub.set_suppress_real(false);
+    hub.fire(0b1, &3).await;
+
+    assert_eq!(rx.recv().await.unwrap(), 2);
+    assert_eq!(rx.recv().await.unwrap(), 3);
+    assert!(rx.try_recv().is_err());
+    assert_eq!(hub.fired(), 3);
+}
+
+#[derive(Debug, PartialEq)]
+struct Doubled(u32);
+
+/// Returns a typed result instead of JSON.
+struct DoubleCb {
+    delay: Duration,
+}
+
+#[async_trait]
+impl Callback<u32, Doubled> for DoubleCb {
+    fn mask(&self) -> u64 {
+        0b1
+    }
+
+    async fn call(&self, ev: &u32) -> Option<Doubled> {
+        tokio::time::sleep(self.delay).await;
+        Some(Doubled(ev * 2))
+    }
+}
+
+#[tokio::test]
+async fn typed_results_go_out_as_returned() {
+    let mut hub = CallbackHub::<u32, Doubled>::default();
+    hub.add(DoubleCb { delay: Duration::ZERO });
+    hub.add(Once::new(DoubleCb { delay: Duration::ZERO }));
+    let (tx, mut rx) = channel(8);
+    hub.set_result_channel(tx);
+
+    hub.fire(0b1, &2).await;
+    hub.inject(0b1, &3).await;
+    assert_eq!(rx.try_recv().unwrap(), Doubled(4));
+    assert_eq!(rx.try_recv().unwrap(), Doubled(4));
+    assert_eq!(rx.try_recv().unwrap(), Doubled(6));
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test(start_paused = true)]
+async fn typed_results_get_no_timeout_records() {
+    let mut hub = CallbackHub::<u32, Doubled>::default();
+    hub.add(DoubleCb { delay: Duration::from_secs(1) });
+    hub.add(DoubleCb { delay: Duration::ZERO });
+    hub.set_callback_timeout(Duration::from_millis(50));
+    let (tx, mut rx) = channel(8);
+    hub.set_result_channel_with_policy(tx, ResultPolicy::DropOldest);
+
+    hub.fire(0b1, &5).await;
+    assert_eq!(rx.recv().await.unwrap(), Doubled(10));
+    assert!(rx.try_recv().is_err());
+    assert_eq!(hub.stats().timed_out, 1);
+}
+
+/// Returns what the envelope says about the event.
+struct StampCb;
+
+#[async_trait]
+impl Callback<Envelope<u32>> for StampCb {
+    fn mask(&self) -> u64 {
+        0b1
+    }
+
+    async fn call(&self, env: &Envelope<u32>) -> Option<CallbackResult> {
+        Some(serde_json::json!([env.seq, env.sensor, env.event]))
+    }
+}
+
+#[tokio::test]
+async fn enveloped_callbacks_get_sequence_time_and_sensor() {
+    let (mut hub, mut rx) = echo_hub(8, ResultPolicy::Block);
+    hub.add_enveloped(StampCb);
+    hub.set_sensor_name("db-mounts");
+
+    let before = std::time::SystemTime::now();
+    hub.fire(0b1, &7).await;
+    hub.set_suppress_real(true);
+    hub.fire(0b1, &8).await;
+    hub.set_suppress_real(false);
+    hub.fire_and_wait_all(0b1, &9, Duration::from_secs(1)).await.unwrap();
+
+    let results: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+    assert_eq!(results, [serde_json::json!(7), serde_json::json!([0, "db-mounts", 7]), serde_json::json!(9), serde_json::json!([2, "db-mounts", 9])]);
+
+    let env = Envelope { seq: 3, ts: before, sensor: "x".into(), event: 1 };
+    let secs = before.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs_f64();
+    assert_eq!(serde_json::to_value(&env).unwrap(), serde_json::json!({ "seq": 3, "ts": secs, "sensor": "x", "event": 1 }));
+}
+
+#[tokio::test]
+async fn lower_priorities_fire_first() {
+    let log = Log::default();
+    let mut hub = hub(&[("notify", 0)], &log);
+    let cb = |name| SlowCb { name, delay: Duration::ZERO, log: log.clone() };
+    hub.add_with_priority(cb("remediate"), 10);
+    hub.add_with_priority(cb("audit"), -10);
+    hub.add(cb("metrics"));
+    let last = hub.add_with_priority(cb("audit-copy"), -10);
+
+    hub.fire(0b1, &1).await;
+    let calls = |log: &Log| log.lock().unwrap().drain(..).filter(|l| l.ends_with("done 1")).map(|l| l.replace(" done 1", "")).collect::<Vec<_>>();
+    assert_eq!(calls(&log), ["audit", "audit-copy", "notify", "metrics", "remediate"]);
+
+    // removal leaves the rest in order; re-adding goes last among equals
+    assert!(hub.remove(last));
+    hub.add_with_priority(cb("audit-copy"), 0);
+    hub.fire(0b1, &1).await;
+    assert_eq!(calls(&log), ["audit", "notify", "metrics", "audit-copy", "remediate"]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn parallel_priorities_are_barriers() {
+    let log = Log::default();
+    let mut hub = CallbackHub::new();
+    hub.set_concurrency(Concurrency::Parallel);
+    let cb = |name, ms| SlowCb { name, delay: Duration::from_millis(ms), log: log.clone() };
+    hub.add(cb("remediate-a", 10));
+    hub.add(cb("remediate-b", 0));
+    hub.add_with_priority(cb("audit-slow", 50), -1);
+    hub.add_with_priority(cb("audit-hung", 1000), -1);
+
+    let started = tokio::time::Instant::now();
+    let err = hub.fire_and_wait_all(0b1, &1, Duration::from_millis(80)).await.unwrap_err();
+    assert_eq!(started.elapsed(), Duration::from_millis(90));
+    assert_eq!(err, BarrierTimeout { timed_out: vec![1] });
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            "audit-slow start 1",
+            "audit-hung start 1",
+            "audit-slow done 1",
+            "remediate-a start 1",
+            "remediate-b start 1",
+            "remediate-b done 1",
+            "remediate-a done 1"
+        ]
+    );
+}
+
+/// Removes the callback in its slot, once, like a kill switch ahead of remediation.
+struct Disarm(Arc<Mutex<Option<Target>>>);
+
+type Target = (Arc<CallbackHub<u32>>, CallbackId);
+
+#[async_trait]
+impl Callback<u32> for Disarm {
+    fn mask(&self) -> u64 {
+        0b1
+    }
+
+    async fn call(&self, _: &u32) -> Option<CallbackResult> {
+        let (hub, id) = self.0.lock().unwrap().take()?;
+        assert!(hub.remove(id));
+        None
+    }
+}
+
+#[tokio::test]
+async fn callbacks_removed_by_an_earlier_priority_are_skipped() {
+    for concurrency in [Concurrency::Sequential, Concurrency::Parallel] {
+        let log = Log::default();
+        let slot = Arc::new(Mutex::new(None));
+        let mut hub = hub(&[("notify", 0)], &log);
+        hub.set_concurrency(concurrency);
+        let remediate = hub.add_with_priority(SlowCb { name: "remediate", delay: Duration::ZERO, log: log.clone() }, 1);
+        hub.add_with_priority(Disarm(slot.clone()), -1);
+        let hub = Arc::new(hub);
+        *slot.lock().unwrap() = Some((hub.clone(), remediate));
+
+        hub.fire(0b1, &1).await;
+        hub.fire(0b1, &2).await;
+        assert_eq!(*log.lock().unwrap(), ["notify start 1", "notify done 1", "notify start 2", "notify done 2"], "{concurrency:?}");
+        assert_eq!(hub.len(), 2);
+    }
+}
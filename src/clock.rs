@@ -0,0 +1,130 @@
+//! Time source for time-based sensor logic.
+//!
+//! `tokio::time::pause` only stops tokio's timers; TTLs, windows and timestamps computed from
+//! `Instant::now()` and `SystemTime::now()` keep following the wall clock. Sensors with such
+//! logic read the time through a [`Clock`] from their config instead (e.g.
+//! `NetNotifyConfig::clock`), which defaults to [`SystemClock`]. Tests pass a [`ManualClock`]
+//! and advance it by hand, covering hours of simulated time in milliseconds.
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+pub trait Clock: Send + Sync {
+    /// Monotonic time, for TTLs and windows.
+    fn now_instant(&self) -> Instant;
+
+    /// Wall-clock time, for timestamps.
+    fn now_system(&self) -> SystemTime;
+
+    /// Time since boot, counting time spent suspended, where the platform tells (Linux
+    /// `CLOCK_BOOTTIME`). The monotonic clock stops while suspended, this one does not.
+    fn since_boot(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Clock as held by configs.
+pub type SharedClock = Arc<dyn Clock>;
+
+/// The real clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_instant(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn now_system(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn since_boot(&self) -> Option<Duration> {
+        let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        // SAFETY: clock_gettime only writes the timespec it is given
+        (unsafe { libc::clock_gettime(libc::CLOCK_BOOTTIME, &mut ts) } == 0).then(|| Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+    }
+}
+
+/// The real clock, shared.
+pub fn system() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+/// Clock that only moves when told to. Clones share the time.
+#[derive(Clone)]
+pub struct ManualClock(Arc<Mutex<(Instant, SystemTime, Duration)>>);
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for ManualClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ManualClock").field(&self.now_system()).finish()
+    }
+}
+
+impl ManualClock {
+    /// Start at the current time.
+    pub fn new() -> Self {
+        Self::at(SystemTime::now())
+    }
+
+    /// Start with the wall clock at `system`, e.g. `UNIX_EPOCH + Duration::from_secs(..)`
+    /// for reproducible timestamps.
+    pub fn at(system: SystemTime) -> Self {
+        Self(Arc::new(Mutex::new((Instant::now(), system, Duration::from_secs(3600)))))
+    }
+
+    /// Move all clocks forward.
+    pub fn advance(&self, d: Duration) {
+        if let Ok(mut t) = self.0.lock() {
+            t.0 += d;
+            t.1 += d;
+            t.2 += d;
+        }
+    }
+
+    /// Simulate a suspend of `d`: the wall clock and the time since boot move on, the
+    /// monotonic clock does not, as on Linux.
+    pub fn suspend(&self, d: Duration) {
+        if let Ok(mut t) = self.0.lock() {
+            t.1 += d;
+            t.2 += d;
+        }
+    }
+
+    /// Set the wall clock only, e.g. to simulate it being stepped back. The monotonic clock
+    /// is not affected.
+    pub fn set_system(&self, system: SystemTime) {
+        if let Ok(mut t) = self.0.lock() {
+            t.1 = system;
+        }
+    }
+
+    /// Handle for a config, sharing the time with this one.
+    pub fn shared(&self) -> SharedClock {
+        Arc::new(self.clone())
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_instant(&self) -> Instant {
+        self.0.lock().map(|t| t.0).unwrap_or_else(|_| Instant::now())
+    }
+
+    fn now_system(&self) -> SystemTime {
+        self.0.lock().map(|t| t.1).unwrap_or_else(|_| SystemTime::now())
+    }
+
+    fn since_boot(&self) -> Option<Duration> {
+        self.0.lock().map(|t| t.2).ok()
+    }
+}
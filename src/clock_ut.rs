@@ -0,0 +1,46 @@
+use crate::{
+    audit::{AuditRecord, AuditSink},
+    clock::{Clock, ManualClock},
+};
+use serde_json::json;
+use std::time::{Duration, UNIX_EPOCH};
+
+#[test]
+fn manual_clock_moves_only_when_told() {
+    let clock = ManualClock::at(UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    let shared = clock.shared();
+    let (i0, s0) = (clock.now_instant(), clock.now_system());
+    std::thread::sleep(Duration::from_millis(5));
+    assert_eq!((shared.now_instant(), shared.now_system()), (i0, s0));
+
+    // six hours in no time, seen through every handle
+    clock.advance(Duration::from_secs(6 * 3600));
+    assert_eq!(shared.now_instant() - i0, Duration::from_secs(6 * 3600));
+    assert_eq!(shared.now_system().duration_since(s0).unwrap(), Duration::from_secs(6 * 3600));
+
+    // a wall clock step leaves the monotonic time alone
+    clock.set_system(s0 - Duration::from_secs(60));
+    assert_eq!(shared.now_instant() - i0, Duration::from_secs(6 * 3600));
+    assert!(shared.now_system() < s0);
+}
+
+#[test]
+fn audit_timestamps_follow_the_clock() {
+    let dir = std::env::temp_dir().join(format!("omnitrace-clock-ut-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let log = dir.join("events.jsonl");
+
+    let clock = ManualClock::at(UNIX_EPOCH + Duration::from_millis(1_700_000_000_250));
+    let mut sink = AuditSink::open(&log).unwrap().clock(clock.shared());
+    sink.append(&json!({ "Mounted": { "target": "/mnt/a" } })).unwrap();
+    clock.advance(Duration::from_secs(90));
+    sink.append(&json!({ "Mounted": { "target": "/mnt/b" } })).unwrap();
+    let sealed = sink.rotate().unwrap();
+
+    let ts: Vec<u64> = std::fs::read_to_string(&sealed).unwrap().lines().map(|l| serde_json::from_str::<AuditRecord>(l).unwrap().ts).collect();
+    assert_eq!(ts, [1_700_000_000_250, 1_700_000_090_250, 1_700_000_090_250]);
+    assert_eq!(sealed.file_name().and_then(|n| n.to_str()), Some("events.jsonl.1700000090250"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
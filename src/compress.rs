@@ -0,0 +1,261 @@
+//! Compression for high-volume sinks.
+//!
+//! A [`Compression`] says how: zstd or gzip, at which level, and from which batch size on.
+//! Sinks that send batches ([`Compression::encode`]) leave small ones as they are, where the
+//! framing would cost more than it saves, and label the others with their
+//! `Content-Encoding`. Sinks that write files stream through an [`Encoder`] and flush it
+//! after every record, so what is on disk can always be read back up to the last record,
+//! and [`reader`] reads such a file whether it is compressed or not, finished or still
+//! being written.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+/// zstd frames start with `28 b5 2f fd`, gzip members with `1f 8b`.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    Zstd,
+    Gzip,
+}
+
+impl Algorithm {
+    /// The HTTP `Content-Encoding`: `"zstd"` or `"gzip"`.
+    pub fn content_encoding(self) -> &'static str {
+        match self {
+            Algorithm::Zstd => "zstd",
+            Algorithm::Gzip => "gzip",
+        }
+    }
+
+    /// File name extension, without the dot: `"zst"` or `"gz"`.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Algorithm::Zstd => "zst",
+            Algorithm::Gzip => "gz",
+        }
+    }
+
+    pub fn from_content_encoding(encoding: &str) -> Option<Self> {
+        match encoding.trim().to_ascii_lowercase().as_str() {
+            "zstd" => Some(Algorithm::Zstd),
+            "gzip" | "x-gzip" => Some(Algorithm::Gzip),
+            _ => None,
+        }
+    }
+
+    /// The algorithm `bytes` were compressed with, by their magic number. None for anything
+    /// else, e.g. plain JSON.
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&ZSTD_MAGIC) {
+            Some(Algorithm::Zstd)
+        } else if bytes.starts_with(&GZIP_MAGIC) {
+            Some(Algorithm::Gzip)
+        } else {
+            None
+        }
+    }
+
+    /// zstd 3 and gzip 6, the defaults of their command line tools.
+    pub fn default_level(self) -> i32 {
+        match self {
+            Algorithm::Zstd => 3,
+            Algorithm::Gzip => 6,
+        }
+    }
+}
+
+/// How a sink compresses, see the [module docs](self).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Compression {
+    pub algorithm: Algorithm,
+    /// zstd 1..=22, gzip 0..=9; out of range levels are clamped.
+    pub level: i32,
+    /// Batches smaller than this many bytes are sent uncompressed. Files ignore it.
+    pub threshold: usize,
+}
+
+impl Compression {
+    pub fn new(algorithm: Algorithm) -> Self {
+        Self { algorithm, level: algorithm.default_level(), threshold: 1024 }
+    }
+
+    pub fn zstd() -> Self {
+        Self::new(Algorithm::Zstd)
+    }
+
+    pub fn gzip() -> Self {
+        Self::new(Algorithm::Gzip)
+    }
+
+    pub fn level(mut self, level: i32) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn threshold(mut self, bytes: usize) -> Self {
+        self.threshold = bytes;
+        self
+    }
+
+    /// A batch body to send: compressed when it is at least [`Compression::threshold`] bytes,
+    /// else as it is, with no `Content-Encoding`.
+    pub fn encode(&self, body: Vec<u8>) -> io::Result<Encoded> {
+        if body.len() < self.threshold {
+            return Ok(Encoded { content_encoding: None, body });
+        }
+        let mut enc = self.writer(Vec::with_capacity(body.len() / 4))?;
+        enc.write_all(&body)?;
+        Ok(Encoded { content_encoding: Some(self.algorithm.content_encoding()), body: enc.finish()? })
+    }
+
+    /// A streaming encoder into `w`.
+    pub fn writer<W: Write>(&self, w: W) -> io::Result<Encoder<W>> {
+        let w = Counted { inner: w, bytes: 0 };
+        let inner = match self.algorithm {
+            Algorithm::Zstd => {
+                let range = zstd::compression_level_range();
+                Inner::Zstd(zstd::stream::write::Encoder::new(w, self.level.clamp(*range.start(), *range.end()))?)
+            }
+            Algorithm::Gzip => Inner::Gzip(flate2::write::GzEncoder::new(w, flate2::Compression::new(self.level.clamp(0, 9) as u32))),
+        };
+        Ok(Encoder { inner: Some(inner), algorithm: self.algorithm })
+    }
+}
+
+/// A batch body as [`Compression::encode`] made it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Encoded {
+    /// None for an uncompressed (identity) body.
+    pub content_encoding: Option<&'static str>,
+    pub body: Vec<u8>,
+}
+
+/// The body of a request or response with `content_encoding`, decompressed.
+pub fn decode(content_encoding: Option<&str>, body: &[u8]) -> io::Result<Vec<u8>> {
+    let algorithm = match content_encoding.map(str::trim) {
+        None | Some("") | Some("identity") => return Ok(body.to_vec()),
+        Some(e) => Algorithm::from_content_encoding(e)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("unsupported content encoding {e:?}")))?,
+    };
+    let mut out = Vec::with_capacity(body.len() * 4);
+    match algorithm {
+        Algorithm::Zstd => zstd::stream::read::Decoder::new(body)?.read_to_end(&mut out)?,
+        Algorithm::Gzip => flate2::read::MultiGzDecoder::new(body).read_to_end(&mut out)?,
+    };
+    Ok(out)
+}
+
+/// Streaming compression into a writer, see [`Compression::writer`].
+///
+/// [`Write::flush`] pushes everything written so far out as complete blocks, which a reader
+/// can decompress even though the stream is not finished. Dropping the encoder finishes it.
+pub struct Encoder<W: Write> {
+    inner: Option<Inner<Counted<W>>>,
+    algorithm: Algorithm,
+}
+
+enum Inner<W: Write> {
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+    Gzip(flate2::write::GzEncoder<W>),
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Compressed bytes handed to the writer so far.
+    pub fn compressed_bytes(&self) -> u64 {
+        match &self.inner {
+            Some(Inner::Zstd(e)) => e.get_ref().bytes,
+            Some(Inner::Gzip(e)) => e.get_ref().bytes,
+            None => 0,
+        }
+    }
+
+    /// End the stream and return the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let w = match self.inner.take() {
+            Some(Inner::Zstd(e)) => e.finish()?,
+            Some(Inner::Gzip(e)) => e.finish()?,
+            None => unreachable!("an encoder is only taken apart by finish"),
+        };
+        Ok(w.inner)
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            Some(Inner::Zstd(e)) => e.write(buf),
+            Some(Inner::Gzip(e)) => e.write(buf),
+            None => Err(io::Error::other("encoder finished")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.inner {
+            Some(Inner::Zstd(e)) => e.flush(),
+            Some(Inner::Gzip(e)) => e.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Drop for Encoder<W> {
+    fn drop(&mut self) {
+        let _ = match &mut self.inner {
+            Some(Inner::Zstd(e)) => e.do_finish(),
+            Some(Inner::Gzip(e)) => e.try_finish(),
+            None => Ok(()),
+        };
+    }
+}
+
+/// Counts what goes through to the underlying writer.
+struct Counted<W> {
+    inner: W,
+    bytes: u64,
+}
+
+impl<W: Write> Write for Counted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// `r` decompressed if it starts with a zstd or gzip magic number, else as it is, and the
+/// algorithm found.
+///
+/// A stream that ends inside a frame, as a file still being written does, reads up to its
+/// last flushed block and then ends; whether that was the end is for the caller to decide.
+pub fn reader<'a, R: Read + 'a>(r: R) -> io::Result<(Box<dyn BufRead + 'a>, Option<Algorithm>)> {
+    let mut r = BufReader::new(r);
+    let algorithm = Algorithm::sniff(r.fill_buf()?);
+    let out: Box<dyn BufRead + 'a> = match algorithm {
+        None => Box::new(r),
+        Some(Algorithm::Zstd) => Box::new(BufReader::new(Unfinished(zstd::stream::read::Decoder::with_buffer(r)?))),
+        Some(Algorithm::Gzip) => Box::new(BufReader::new(Unfinished(flate2::bufread::MultiGzDecoder::new(r)))),
+    };
+    Ok((out, algorithm))
+}
+
+/// Ends quietly where the compressed stream was cut off.
+struct Unfinished<R>(R);
+
+impl<R: Read> Read for Unfinished<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(0),
+            other => other,
+        }
+    }
+}
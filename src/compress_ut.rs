@@ -0,0 +1,49 @@
+use crate::compress::{self, Algorithm, Compression};
+use std::io::{BufRead, Write};
+
+fn jsonl(n: usize) -> Vec<u8> {
+    (0..n).map(|i| format!("{{\"Mounted\":{{\"target\":\"/mnt/disk{i}\",\"fstype\":\"ext4\"}}}}\n")).collect::<String>().into_bytes()
+}
+
+#[test]
+fn batches_round_trip_and_small_ones_stay_identity() {
+    let small = jsonl(2);
+    let enc = Compression::zstd().threshold(small.len() + 1).encode(small.clone()).unwrap();
+    assert_eq!((enc.content_encoding, &enc.body), (None, &small));
+    assert_eq!(compress::decode(None, &enc.body).unwrap(), small);
+
+    let batch = jsonl(500);
+    for c in [Compression::zstd(), Compression::zstd().level(99), Compression::gzip().level(1), Compression::gzip().level(12)] {
+        let enc = c.encode(batch.clone()).unwrap();
+        assert_eq!(enc.content_encoding, Some(c.algorithm.content_encoding()));
+        assert!(enc.body.len() * 5 < batch.len(), "{c:?}: {} of {}", enc.body.len(), batch.len());
+        assert_eq!(Algorithm::sniff(&enc.body), Some(c.algorithm));
+        assert_eq!(compress::decode(enc.content_encoding, &enc.body).unwrap(), batch, "{c:?}");
+    }
+    assert!(compress::decode(Some("br"), &batch).is_err());
+}
+
+#[test]
+fn unfinished_stream_reads_up_to_the_last_flush() {
+    for c in [Compression::zstd(), Compression::gzip()] {
+        // what a file being written holds: a stream with no end yet
+        let mut live = Vec::new();
+        let mut enc = c.writer(&mut live).unwrap();
+        enc.write_all(b"one\ntwo\n").unwrap();
+        enc.flush().unwrap();
+        assert!(enc.compressed_bytes() > 0);
+        std::mem::forget(enc);
+
+        let (r, algorithm) = compress::reader(&live[..]).unwrap();
+        assert_eq!(algorithm, Some(c.algorithm));
+        assert_eq!(r.lines().map(Result::unwrap).collect::<Vec<_>>(), ["one", "two"]);
+
+        let mut enc = c.writer(Vec::new()).unwrap();
+        enc.write_all(b"one\ntwo\n").unwrap();
+        let done = enc.finish().unwrap();
+        assert_eq!(compress::reader(&done[..]).unwrap().0.lines().count(), 2);
+    }
+
+    let (r, algorithm) = compress::reader(&b"plain\n"[..]).unwrap();
+    assert_eq!((algorithm, r.lines().map(Result::unwrap).collect::<Vec<_>>()), (None, vec!["plain".to_string()]));
+}
@@ -0,0 +1,173 @@
+//! Deadlines for "absent (or over a threshold) for longer than D" logic.
+//!
+//! A [`DeadlineSet`] holds one deadline per key, e.g. a process name or a mount target:
+//! [`DeadlineSet::arm`] it when the condition starts, [`DeadlineSet::cancel`] it when it ends,
+//! and take what ran out from [`DeadlineSet::poll_expired`] on every tick. Time is read
+//! through the set's [`crate::clock::Clock`], so tests drive it with a `ManualClock`.
+//!
+//! Deadlines count time since boot where the clock tells it (Linux `CLOCK_BOOTTIME`), so a
+//! suspend counts towards them, and the monotonic time otherwise. Wall clock steps never
+//! move them. Two switches change what counts:
+//!
+//! - [`DeadlineSet::freeze_while_paused`]: time between [`DeadlineSet::pause`] and
+//!   [`DeadlineSet::resume`] does not count, e.g. while a sensor is in standby.
+//! - [`DeadlineSet::skip_gaps`]: a suspend or stall the sensor reports (see
+//!   [`crate::pulse::TimeAnomaly`]) does not count, passed in with [`DeadlineSet::note_gap`].
+
+use crate::{
+    clock::SharedClock,
+    pulse::{TimeAnomaly, TimeGapKind},
+};
+use std::{collections::HashMap, hash::Hash, time::Duration, time::Instant};
+
+/// Clock readings the set measures elapsed time between.
+#[derive(Clone, Copy)]
+struct Reading {
+    mono: Instant,
+    boot: Option<Duration>,
+}
+
+/// One deadline per key, see the [module docs](self).
+pub struct DeadlineSet<K> {
+    clock: SharedClock,
+    freeze: bool,
+    skip_gaps: bool,
+    paused: bool,
+    // time that counted so far; deadlines are points on it
+    elapsed: Duration,
+    last: Reading,
+    // deadline, and the arm order to break ties
+    deadlines: HashMap<K, (Duration, u64)>,
+    armed: u64,
+}
+
+impl<K: Eq + Hash + Clone> DeadlineSet<K> {
+    pub fn new(clock: SharedClock) -> Self {
+        let last = Reading { mono: clock.now_instant(), boot: clock.since_boot() };
+        Self { clock, freeze: false, skip_gaps: false, paused: false, elapsed: Duration::ZERO, last, deadlines: HashMap::new(), armed: 0 }
+    }
+
+    /// Stop the deadlines while paused (off by default: they keep running).
+    pub fn freeze_while_paused(mut self, on: bool) -> Self {
+        self.freeze = on;
+        self
+    }
+
+    /// Leave suspends and stalls passed to [`DeadlineSet::note_gap`] out (off by default).
+    pub fn skip_gaps(mut self, on: bool) -> Self {
+        self.skip_gaps = on;
+        self
+    }
+
+    /// Time counted up to a reading taken now, and the reading.
+    fn read(&self) -> (Duration, Reading) {
+        let now = Reading { mono: self.clock.now_instant(), boot: self.clock.since_boot() };
+        if self.paused && self.freeze {
+            return (self.elapsed, now);
+        }
+        let delta = match now.boot.zip(self.last.boot) {
+            Some((now, last)) => now.saturating_sub(last),
+            None => now.mono.saturating_duration_since(self.last.mono),
+        };
+        (self.elapsed + delta, now)
+    }
+
+    fn now(&self) -> Duration {
+        self.read().0
+    }
+
+    /// Count the time since the last reading.
+    fn advance(&mut self) {
+        (self.elapsed, self.last) = self.read();
+    }
+
+    /// Start a deadline `after` from now for `key`, unless one is running already (which is
+    /// kept). Returns whether it started.
+    pub fn arm(&mut self, key: K, after: Duration) -> bool {
+        if self.deadlines.contains_key(&key) {
+            return false;
+        }
+        self.rearm(key, after);
+        true
+    }
+
+    /// Start a deadline `after` from now for `key`, replacing a running one. Returns whether
+    /// one was running.
+    pub fn rearm(&mut self, key: K, after: Duration) -> bool {
+        self.advance();
+        self.armed += 1;
+        self.deadlines.insert(key, (self.elapsed.saturating_add(after), self.armed)).is_some()
+    }
+
+    /// Drop the deadline of `key`, returning whether one was running.
+    pub fn cancel(&mut self, key: &K) -> bool {
+        self.deadlines.remove(key).is_some()
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.deadlines.contains_key(key)
+    }
+
+    /// Time left until the deadline of `key` runs out, zero once it did.
+    pub fn remaining(&self, key: &K) -> Option<Duration> {
+        let (at, _) = self.deadlines.get(key)?;
+        Some(at.saturating_sub(self.now()))
+    }
+
+    /// Time left until the first deadline runs out, e.g. to sleep until then.
+    pub fn next_in(&self) -> Option<Duration> {
+        let now = self.now();
+        self.deadlines.values().map(|(at, _)| at.saturating_sub(now)).min()
+    }
+
+    pub fn len(&self) -> usize {
+        self.deadlines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deadlines.is_empty()
+    }
+
+    /// Remove and return the keys whose deadline ran out, the earliest first and those
+    /// running out at once in the order they were armed.
+    pub fn poll_expired(&mut self) -> Vec<K> {
+        self.advance();
+        let mut expired: Vec<(Duration, u64, K)> =
+            self.deadlines.iter().filter(|(_, (at, _))| *at <= self.elapsed).map(|(k, (at, order))| (*at, *order, k.clone())).collect();
+        expired.sort_unstable_by_key(|(at, order, _)| (*at, *order));
+        for (_, _, key) in &expired {
+            self.deadlines.remove(key);
+        }
+        expired.into_iter().map(|(_, _, key)| key).collect()
+    }
+
+    /// The sensor paused: with [`DeadlineSet::freeze_while_paused`] the deadlines stop
+    /// until [`DeadlineSet::resume`].
+    pub fn pause(&mut self) {
+        self.advance();
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.advance();
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// The sensor noticed a time gap. With [`DeadlineSet::skip_gaps`] a suspend or stall
+    /// pushes every deadline back by its length; call it before polling, so the gap does not
+    /// expire anything first. Clock steps are ignored either way.
+    pub fn note_gap(&mut self, gap: &TimeAnomaly) {
+        self.advance();
+        // frozen time does not count in the first place
+        if !self.skip_gaps || gap.kind != TimeGapKind::Suspend || (self.paused && self.freeze) {
+            return;
+        }
+        for (at, _) in self.deadlines.values_mut() {
+            *at = at.saturating_add(gap.magnitude);
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use crate::{
+    clock::{Clock, ManualClock},
+    deadline::DeadlineSet,
+    pulse::{TimeAnomaly, TimeGapKind},
+};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+fn secs(n: u64) -> Duration {
+    Duration::from_secs(n)
+}
+
+fn suspend(magnitude: Duration) -> TimeAnomaly {
+    TimeAnomaly { kind: TimeGapKind::Suspend, magnitude, backward: false }
+}
+
+#[test]
+fn deadlines_expire_earliest_first_ties_in_arm_order() {
+    let clock = ManualClock::new();
+    let mut set = DeadlineSet::new(clock.shared());
+    set.arm("c", secs(30));
+    set.arm("a", secs(10));
+    set.arm("b", secs(30));
+    set.arm("d", secs(60));
+    assert_eq!(set.next_in(), Some(secs(10)));
+
+    clock.advance(secs(9));
+    assert!(set.poll_expired().is_empty());
+    clock.advance(secs(1));
+    assert_eq!(set.poll_expired(), ["a"]);
+    assert!(set.poll_expired().is_empty(), "expired keys are removed");
+
+    clock.advance(secs(45));
+    assert_eq!(set.poll_expired(), ["c", "b"]);
+    assert_eq!(set.remaining(&"d"), Some(secs(5)));
+    assert_eq!((set.len(), set.contains(&"c")), (1, false));
+}
+
+#[test]
+fn arm_keeps_a_running_deadline_and_rearm_replaces_it() {
+    let clock = ManualClock::new();
+    let mut set = DeadlineSet::new(clock.shared());
+    assert!(set.arm("sshd", secs(10)));
+    clock.advance(secs(6));
+    assert!(!set.arm("sshd", secs(10)));
+    assert_eq!(set.remaining(&"sshd"), Some(secs(4)));
+
+    assert!(set.rearm("sshd", secs(10)));
+    assert_eq!(set.remaining(&"sshd"), Some(secs(10)));
+    assert!(!set.rearm("cron", secs(1)));
+
+    // a rearmed key runs out after one armed later but due sooner
+    clock.advance(secs(10));
+    assert_eq!(set.poll_expired(), ["cron", "sshd"]);
+
+    // expired or cancelled, a key arms afresh
+    assert!(set.arm("sshd", secs(5)));
+    assert!(set.cancel(&"sshd"));
+    assert!(!set.cancel(&"sshd"));
+    clock.advance(secs(5));
+    assert!(set.poll_expired().is_empty());
+    assert!(set.arm("sshd", secs(5)));
+    assert_eq!(set.remaining(&"sshd"), Some(secs(5)));
+}
+
+#[test]
+fn frozen_deadlines_stop_while_paused() {
+    let clock = ManualClock::new();
+    let mut frozen = DeadlineSet::new(clock.shared()).freeze_while_paused(true);
+    let mut running = DeadlineSet::new(clock.shared());
+    for set in [&mut frozen, &mut running] {
+        set.arm("/data", secs(10));
+        set.pause();
+    }
+    clock.advance(secs(4));
+    assert_eq!(frozen.remaining(&"/data"), Some(secs(10)));
+    assert_eq!(running.remaining(&"/data"), Some(secs(6)));
+
+    clock.advance(secs(60));
+    assert!(frozen.poll_expired().is_empty(), "nothing runs out while frozen");
+    assert_eq!(running.poll_expired(), ["/data"]);
+
+    // armed while paused: starts counting on resume
+    frozen.arm("/srv", secs(3));
+    clock.advance(secs(60));
+    frozen.resume();
+    assert!(!frozen.is_paused());
+    clock.advance(secs(3));
+    assert_eq!(frozen.poll_expired(), ["/srv"]);
+    clock.advance(secs(6));
+    assert!(frozen.poll_expired().is_empty());
+    clock.advance(secs(1));
+    assert_eq!(frozen.poll_expired(), ["/data"]);
+}
+
+#[test]
+fn suspends_count_unless_gaps_are_skipped() {
+    let clock = ManualClock::new();
+    let mut counting = DeadlineSet::new(clock.shared());
+    let mut skipping = DeadlineSet::new(clock.shared()).skip_gaps(true);
+    for set in [&mut counting, &mut skipping] {
+        set.arm("postgres", secs(120));
+        set.arm("nginx", secs(3600));
+    }
+
+    // an hour asleep: the monotonic clock stands still, the boot clock does not
+    clock.advance(secs(10));
+    clock.suspend(secs(3600));
+    for set in [&mut counting, &mut skipping] {
+        set.note_gap(&suspend(secs(3600)));
+    }
+    assert_eq!(counting.poll_expired(), ["postgres", "nginx"]);
+    assert!(skipping.poll_expired().is_empty());
+    assert_eq!(skipping.remaining(&"postgres"), Some(secs(110)));
+
+    // a stall moves the monotonic clock too, skipped the same
+    clock.advance(secs(600));
+    skipping.note_gap(&suspend(secs(590)));
+    assert_eq!(skipping.remaining(&"postgres"), Some(secs(100)));
+    clock.advance(secs(100));
+    assert_eq!(skipping.poll_expired(), ["postgres"]);
+}
+
+#[test]
+fn clock_steps_move_nothing() {
+    let clock = ManualClock::at(UNIX_EPOCH + secs(1_700_000_000));
+    let mut set = DeadlineSet::new(clock.shared()).skip_gaps(true);
+    set.arm(1, secs(30));
+    clock.set_system(UNIX_EPOCH);
+    set.note_gap(&TimeAnomaly { kind: TimeGapKind::ClockStep, magnitude: secs(1_700_000_000), backward: true });
+    clock.set_system(UNIX_EPOCH + secs(1_800_000_000));
+    assert_eq!(set.remaining(&1), Some(secs(30)));
+    clock.advance(secs(30));
+    assert_eq!(set.poll_expired(), [1]);
+}
+
+/// A [`ManualClock`] without the time since boot, like clocks on other platforms.
+struct MonotonicOnly(ManualClock);
+
+impl Clock for MonotonicOnly {
+    fn now_instant(&self) -> Instant {
+        self.0.now_instant()
+    }
+
+    fn now_system(&self) -> SystemTime {
+        self.0.now_system()
+    }
+}
+
+#[test]
+fn without_a_boot_clock_suspends_do_not_count() {
+    let clock = ManualClock::new();
+    let mut set = DeadlineSet::new(std::sync::Arc::new(MonotonicOnly(clock.clone())));
+    set.arm("a", secs(60));
+    clock.suspend(secs(3600));
+    assert!(set.poll_expired().is_empty());
+    clock.advance(secs(60));
+    assert_eq!(set.poll_expired(), ["a"]);
+}
+
+/// Random arms, rearms, cancels, pauses and time steps against a plain map of due times.
+#[test]
+fn random_operations_match_a_model() {
+    let mut rng = fastrand::Rng::with_seed(7572);
+    for _ in 0..200 {
+        let clock = ManualClock::new();
+        let freeze = rng.bool();
+        let mut set = DeadlineSet::new(clock.shared()).freeze_while_paused(freeze);
+        let (mut now, mut paused) = (0u64, false);
+        let mut model: HashMap<u8, (u64, u64)> = HashMap::new();
+        let mut armed = 0u64;
+
+        for _ in 0..100 {
+            let key = rng.u8(0..6);
+            let after = rng.u64(0..50);
+            match rng.u8(0..6) {
+                0 => {
+                    let fresh = !model.contains_key(&key);
+                    if fresh {
+                        armed += 1;
+                        model.insert(key, (now + after, armed));
+                    }
+                    assert_eq!(set.arm(key, secs(after)), fresh);
+                }
+                1 => {
+                    armed += 1;
+                    assert_eq!(set.rearm(key, secs(after)), model.insert(key, (now + after, armed)).is_some());
+                }
+                2 => assert_eq!(set.cancel(&key), model.remove(&key).is_some()),
+                3 => {
+                    paused = !paused;
+                    if paused { set.pause() } else { set.resume() }
+                }
+                _ => {
+                    clock.advance(secs(after));
+                    if !(paused && freeze) {
+                        now += after;
+                    }
+                }
+            }
+
+            let mut due: Vec<(u64, u64, u8)> = model.iter().filter(|(_, (at, _))| *at <= now).map(|(k, (at, order))| (*at, *order, *k)).collect();
+            due.sort_unstable();
+            model.retain(|_, (at, _)| *at > now);
+            assert_eq!(set.poll_expired(), due.into_iter().map(|(_, _, k)| k).collect::<Vec<_>>());
+            assert_eq!(set.next_in(), model.values().map(|(at, _)| secs(at - now)).min());
+        }
+    }
+}
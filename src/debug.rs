@@ -0,0 +1,196 @@
+//! On-demand dumps of sensor internals, for debugging a running agent.
+//!
+//! Sensors keep a small summary of their state (watched sets, last snapshot sizes, caches,
+//! health counters, the relevant config) in a [`DebugCell`], exposed by a `debug_handle()`
+//! method. Register the handles in a [`Snapshots`] registry before spawning the sensors, then
+//! call [`Snapshots::dump`] or let [`Snapshots::dump_on_sigusr1`] write one per `SIGUSR1`.
+
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Something that can describe its internal state as JSON.
+pub trait Debuggable: Send + Sync {
+    fn debug_snapshot(&self) -> Value;
+}
+
+/// Shared state summary a sensor updates as it runs; only serialized when a dump is taken.
+/// Cheap to clone.
+#[derive(Debug, Default)]
+pub struct DebugCell<T>(Arc<RwLock<T>>);
+
+impl<T> Clone for DebugCell<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> DebugCell<T> {
+    pub fn update<F: FnOnce(&mut T)>(&self, f: F) {
+        if let Ok(mut st) = self.0.write() {
+            f(&mut st);
+        }
+    }
+
+    pub fn get(&self) -> T
+    where
+        T: Clone + Default,
+    {
+        self.0.read().map(|st| st.clone()).unwrap_or_default()
+    }
+}
+
+impl<T: Serialize + Send + Sync> Debuggable for DebugCell<T> {
+    fn debug_snapshot(&self) -> Value {
+        match self.0.read() {
+            Ok(st) => serde_json::to_value(&*st).unwrap_or_else(|e| json!({ "error": e.to_string() })),
+            Err(_) => json!({ "error": "state lock poisoned" }),
+        }
+    }
+}
+
+/// Replacement for redacted values.
+pub const REDACTED: &str = "<redacted>";
+
+/// Keys whose values [`Snapshots::redact`] hides: host names, addresses, paths and command lines.
+pub const SENSITIVE_KEYS: &[&str] = &[
+    // host names and address patterns
+    "host",
+    "hostname",
+    "local_host",
+    "remote_host",
+    "remote_sni",
+    "watch_ip",
+    "ignore_ip",
+    "watch_host",
+    "ignore_host",
+    "watch_local_host",
+    "ignore_local_host",
+    "route_lookup_targets",
+    // paths
+    "roots",
+    "suspended",
+    "files_per_root",
+    "targets",
+    "advised",
+    "mount_point",
+    "root",
+    "source",
+    "cmdline",
+    "watch_env",
+    // per-entity counters: mountpoints, roots, process names, remote hosts
+    "entity",
+    // recent events per entity, keyed by the same
+    "history",
+];
+
+/// Registry of debuggable components, dumped together.
+#[derive(Default)]
+pub struct Snapshots {
+    components: Vec<(String, Arc<dyn Debuggable>)>,
+    redact: HashSet<String>,
+}
+
+impl Snapshots {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a component under `name` (e.g. `"xmount"`). Dumps list components in registration order.
+    pub fn register<S: Into<String>, D: Debuggable + 'static>(&mut self, name: S, component: D) {
+        self.components.push((name.into(), Arc::new(component)));
+    }
+
+    /// Hide the values of [`SENSITIVE_KEYS`] in dumps, e.g. before attaching one to a ticket.
+    pub fn redact(self) -> Self {
+        self.redact_keys(SENSITIVE_KEYS.iter().copied())
+    }
+
+    /// Hide the values under these keys, at any depth. Strings are replaced, numbers and
+    /// booleans kept, and keys of nested maps (e.g. paths mapping to counts) replaced too.
+    pub fn redact_keys<I, S>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.redact.extend(keys.into_iter().map(Into::into));
+        self
+    }
+
+    /// Snapshot every component: `{ "taken_at": <unix secs>, "components": { name: .. } }`.
+    pub fn dump(&self) -> Value {
+        let mut components = Map::new();
+        for (name, c) in &self.components {
+            let mut snap = c.debug_snapshot();
+            if !self.redact.is_empty() {
+                redact_in(&mut snap, &self.redact);
+            }
+            components.insert(name.clone(), snap);
+        }
+        let taken_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0);
+        json!({ "taken_at": taken_at, "components": components })
+    }
+
+    /// Write a dump to `dir/omnitrace-debug-<unix millis>.json` and return its path.
+    pub fn write_dump(&self, dir: &Path) -> io::Result<PathBuf> {
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+        let path = dir.join(format!("omnitrace-debug-{millis}.json"));
+        std::fs::write(&path, serde_json::to_vec_pretty(&self.dump())?)?;
+        Ok(path)
+    }
+
+    /// Write a dump into `dir` on every `SIGUSR1` until `cancel` fires.
+    #[cfg(all(unix, feature = "runtime"))]
+    pub async fn dump_on_sigusr1(self: Arc<Self>, dir: PathBuf, cancel: tokio_util::sync::CancellationToken) -> io::Result<()> {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut usr1 = signal(SignalKind::user_defined1())?;
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => return Ok(()),
+                _ = usr1.recv() => {}
+            }
+            match self.write_dump(&dir) {
+                Ok(path) => log::info!("debug dump written to {}", path.display()),
+                Err(e) => log::error!("debug dump to {} failed: {e}", dir.display()),
+            }
+        }
+    }
+}
+
+fn redact_in(v: &mut Value, keys: &HashSet<String>) {
+    match v {
+        Value::Object(map) => {
+            for (k, child) in map.iter_mut() {
+                if keys.contains(k) {
+                    redact_all(child);
+                } else {
+                    redact_in(child, keys);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|i| redact_in(i, keys)),
+        _ => {}
+    }
+}
+
+fn redact_all(v: &mut Value) {
+    match v {
+        Value::String(s) => *s = REDACTED.to_string(),
+        Value::Array(items) => items.iter_mut().for_each(redact_all),
+        Value::Object(map) => {
+            let old = std::mem::take(map);
+            for (i, (_, mut child)) in old.into_iter().enumerate() {
+                redact_all(&mut child);
+                map.insert(format!("{REDACTED}#{i}"), child);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,130 @@
+use crate::debug::{DebugCell, Debuggable, REDACTED, Snapshots};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Default, Serialize)]
+struct Demo {
+    primed: bool,
+    roots: Vec<String>,
+    files_per_root: BTreeMap<String, usize>,
+    conn: Option<Conn>,
+}
+
+#[derive(Clone, Default, Serialize)]
+struct Conn {
+    remote_host: Option<String>,
+    port: u16,
+}
+
+struct Fixed;
+
+impl Debuggable for Fixed {
+    fn debug_snapshot(&self) -> Value {
+        json!({ "answer": 42 })
+    }
+}
+
+fn registry() -> (Snapshots, DebugCell<Demo>) {
+    let cell = DebugCell::<Demo>::default();
+    let mut s = Snapshots::new();
+    s.register("demo", cell.clone());
+    s.register("fixed", Fixed);
+    (s, cell)
+}
+
+fn fill(cell: &DebugCell<Demo>) {
+    cell.update(|d| {
+        d.primed = true;
+        d.roots = vec!["/srv/data".into()];
+        d.files_per_root.insert("/srv/data".into(), 3);
+        d.conn = Some(Conn { remote_host: Some("db.internal".into()), port: 5432 });
+    });
+}
+
+#[test]
+fn dump_collects_components_by_name() {
+    let (s, cell) = registry();
+    assert_eq!(s.dump()["components"]["demo"]["primed"], false);
+
+    fill(&cell);
+    let dump = s.dump();
+    assert!(dump["taken_at"].as_f64().unwrap() > 0.0);
+    assert_eq!(dump["components"]["demo"]["roots"], json!(["/srv/data"]));
+    assert_eq!(dump["components"]["demo"]["conn"]["remote_host"], "db.internal");
+    assert_eq!(dump["components"]["fixed"], json!({ "answer": 42 }));
+    assert!(cell.get().primed);
+}
+
+#[test]
+fn redaction_hides_sensitive_values_at_any_depth() {
+    let (s, cell) = registry();
+    fill(&cell);
+    let demo = s.redact().dump()["components"]["demo"].clone();
+
+    assert_eq!(demo["roots"], json!([REDACTED]));
+    assert_eq!(demo["conn"]["remote_host"], REDACTED);
+    assert_eq!(demo["conn"]["port"], 5432);
+    assert_eq!(demo["primed"], true);
+
+    // map keys are paths too, the counts stay
+    let files: Vec<(&String, &Value)> = demo["files_per_root"].as_object().unwrap().iter().collect();
+    assert_eq!(files.len(), 1);
+    assert!(files[0].0.starts_with(REDACTED));
+    assert_eq!(files[0].1, 3);
+}
+
+#[test]
+fn custom_redaction_keys() {
+    let (s, cell) = registry();
+    fill(&cell);
+    let dump = s.redact_keys(["port"]).dump();
+    assert_eq!(dump["components"]["demo"]["conn"]["port"], 5432, "numbers are kept");
+    assert_eq!(dump["components"]["demo"]["conn"]["remote_host"], "db.internal");
+}
+
+#[test]
+fn write_dump_creates_timestamped_file() {
+    let dir = std::env::temp_dir().join(format!("omnitrace-debug-ut-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let (s, cell) = registry();
+    fill(&cell);
+    let path = s.write_dump(&dir).unwrap();
+    let name = path.file_name().unwrap().to_string_lossy().into_owned();
+    assert!(name.starts_with("omnitrace-debug-") && name.ends_with(".json"), "{name}");
+
+    let back: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(back["components"]["fixed"]["answer"], 42);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn sigusr1_writes_a_dump() {
+    use std::{sync::Arc, time::Duration};
+    use tokio_util::sync::CancellationToken;
+
+    let dir = std::env::temp_dir().join(format!("omnitrace-debug-sig-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (s, _) = registry();
+    let cancel = CancellationToken::new();
+    let task = tokio::spawn(Arc::new(s).dump_on_sigusr1(dir.clone(), cancel.clone()));
+
+    // the handler is installed once the task ran
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    unsafe { libc::raise(libc::SIGUSR1) };
+
+    let mut found = false;
+    for _ in 0..100 {
+        if std::fs::read_dir(&dir).unwrap().next().is_some() {
+            found = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    cancel.cancel();
+    task.await.unwrap().unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(found, "no dump written");
+}
@@ -0,0 +1,322 @@
+//! Degraded fidelity under sustained overload.
+//!
+//! When the host melts down (fork bombs, connection storms) the agent must not add to the
+//! load, yet that is when some signal matters most. A [`DegradeController`] samples pipeline
+//! pressure from sources the agent registers (queue fill, tick overruns, drops, the agent's
+//! own CPU) and, once a limit has been exceeded for `enter_after_secs`, flips a shared
+//! [`ProfileSwitch`] to [`Profile::Degraded`]. Sensors following the switch apply their
+//! degraded profile on their next tick:
+//!
+//! - filescream doubles its pulse and pauses content hashing (metadata hashes only)
+//! - netpacket doubles its pulse and answers reverse DNS from its cache only
+//! - procdog doubles its interval
+//!
+//! Full fidelity comes back once every indicator stayed below `exit_ratio` of its limit for
+//! `exit_after_secs`. Each transition sends a marker record on the controller's channel, so
+//! dashboards know the fidelity changed:
+//!
+//! ```json
+//! {"DegradedMode": {"state": "entered", "reason": "drops_per_sec", "indicators": {"drops_per_sec": 1200.0, ...}, "at": 1718000000.25}}
+//! ```
+
+use crate::{
+    callbacks::CallbackResult,
+    clock::{self, SharedClock},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+
+/// Variant name of the marker record.
+pub const DEGRADED_MODE: &str = "DegradedMode";
+
+/// How much sensors stretch their polling interval while degraded.
+pub const DEGRADED_PULSE_FACTOR: u32 = 2;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    #[default]
+    Full,
+    Degraded,
+}
+
+impl Profile {
+    /// `pulse` as the profile runs it.
+    pub fn pulse(self, pulse: Duration) -> Duration {
+        match self {
+            Profile::Full => pulse,
+            Profile::Degraded => pulse * DEGRADED_PULSE_FACTOR,
+        }
+    }
+}
+
+/// Shared current profile, set by a [`DegradeController`] (or by hand) and followed by
+/// sensors, e.g. through `FileScreamConfig::profile_switch`. Cheap to clone.
+#[derive(Clone, Debug, Default)]
+pub struct ProfileSwitch(Arc<AtomicBool>);
+
+impl ProfileSwitch {
+    pub fn profile(&self) -> Profile {
+        if self.0.load(Ordering::Relaxed) { Profile::Degraded } else { Profile::Full }
+    }
+
+    /// The current profile, if it differs from `applied`.
+    pub fn changed_from(&self, applied: Profile) -> Option<Profile> {
+        Some(self.profile()).filter(|p| *p != applied)
+    }
+
+    /// Switch to `profile`. Returns false if already there.
+    pub fn set(&self, profile: Profile) -> bool {
+        self.0.swap(profile == Profile::Degraded, Ordering::Relaxed) != (profile == Profile::Degraded)
+    }
+}
+
+/// One sample of pipeline pressure. Counters are cumulative, the controller works on their rates.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Pressure {
+    /// Fill of the fullest queue between sensors and sinks, from 0 to 1.
+    pub queue_fill: f64,
+    /// Ticks that took longer than their interval.
+    pub tick_overruns: u64,
+    /// Events dropped anywhere in the pipeline.
+    pub drops: u64,
+    /// CPU time used by the agent, see [`cpu_time`].
+    pub cpu_time: Duration,
+}
+
+impl Pressure {
+    /// Combine samples of several sources: the fullest queue, the summed counters.
+    pub fn merge(self, other: Pressure) -> Pressure {
+        Pressure {
+            queue_fill: self.queue_fill.max(other.queue_fill),
+            tick_overruns: self.tick_overruns + other.tick_overruns,
+            drops: self.drops + other.drops,
+            cpu_time: self.cpu_time + other.cpu_time,
+        }
+    }
+}
+
+/// What the limits are compared against, derived from two [`Pressure`] samples.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Indicators {
+    pub queue_fill: f64,
+    pub overruns_per_sec: f64,
+    pub drops_per_sec: f64,
+    /// Percent of one core.
+    pub cpu_percent: f64,
+}
+
+impl Indicators {
+    fn between(prev: &Pressure, now: &Pressure, elapsed: Duration) -> Self {
+        let secs = elapsed.as_secs_f64();
+        if secs <= 0.0 {
+            return Self { queue_fill: now.queue_fill, ..Self::default() };
+        }
+        Self {
+            queue_fill: now.queue_fill,
+            overruns_per_sec: now.tick_overruns.saturating_sub(prev.tick_overruns) as f64 / secs,
+            drops_per_sec: now.drops.saturating_sub(prev.drops) as f64 / secs,
+            cpu_percent: now.cpu_time.saturating_sub(prev.cpu_time).as_secs_f64() / secs * 100.0,
+        }
+    }
+}
+
+/// Limits and timing of a [`DegradeController`]. Unset limits are not checked.
+///
+/// ```json
+/// { "max_queue_fill": 0.8, "max_drops_per_sec": 100, "max_cpu_percent": 50,
+///   "enter_after_secs": 10, "exit_after_secs": 60 }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DegradeConfig {
+    #[serde(default)]
+    pub max_queue_fill: Option<f64>,
+    #[serde(default, deserialize_with = "crate::units::per_sec::opt")]
+    pub max_overruns_per_sec: Option<f64>,
+    #[serde(default, deserialize_with = "crate::units::per_sec::opt")]
+    pub max_drops_per_sec: Option<f64>,
+    #[serde(default)]
+    pub max_cpu_percent: Option<f64>,
+    /// How long a limit must stay exceeded before degrading.
+    #[serde(default = "default_enter_after", deserialize_with = "crate::units::secs::deserialize")]
+    pub enter_after_secs: f64,
+    /// How long every indicator must stay below `exit_ratio` of its limit before recovering.
+    #[serde(default = "default_exit_after", deserialize_with = "crate::units::secs::deserialize")]
+    pub exit_after_secs: f64,
+    #[serde(default = "default_exit_ratio")]
+    pub exit_ratio: f64,
+    /// Sampling interval of [`DegradeController::spawn`].
+    #[serde(default = "default_sample", deserialize_with = "crate::units::secs::deserialize")]
+    pub sample_secs: f64,
+}
+
+impl Default for DegradeConfig {
+    fn default() -> Self {
+        Self {
+            max_queue_fill: None,
+            max_overruns_per_sec: None,
+            max_drops_per_sec: None,
+            max_cpu_percent: None,
+            enter_after_secs: default_enter_after(),
+            exit_after_secs: default_exit_after(),
+            exit_ratio: default_exit_ratio(),
+            sample_secs: default_sample(),
+        }
+    }
+}
+
+fn default_enter_after() -> f64 {
+    10.0
+}
+
+fn default_exit_after() -> f64 {
+    60.0
+}
+
+fn default_exit_ratio() -> f64 {
+    0.5
+}
+
+fn default_sample() -> f64 {
+    1.0
+}
+
+impl DegradeConfig {
+    /// `(name, value, limit)` for every set limit.
+    fn checks(&self, ind: &Indicators) -> Vec<(&'static str, f64, f64)> {
+        [
+            ("queue_fill", ind.queue_fill, self.max_queue_fill),
+            ("overruns_per_sec", ind.overruns_per_sec, self.max_overruns_per_sec),
+            ("drops_per_sec", ind.drops_per_sec, self.max_drops_per_sec),
+            ("cpu_percent", ind.cpu_percent, self.max_cpu_percent),
+        ]
+        .into_iter()
+        .filter_map(|(name, v, limit)| Some((name, v, limit?)))
+        .collect()
+    }
+}
+
+type Source = Box<dyn Fn() -> Pressure + Send + Sync>;
+
+/// Flips a [`ProfileSwitch`] on sustained pressure, with hysteresis. See the module docs.
+pub struct DegradeController {
+    cfg: DegradeConfig,
+    switch: ProfileSwitch,
+    clock: SharedClock,
+    sources: Vec<Source>,
+    tx: Option<mpsc::Sender<CallbackResult>>,
+    last: Option<(Pressure, Instant)>,
+    // since when the current profile's exit condition holds
+    since: Option<Instant>,
+}
+
+impl DegradeController {
+    pub fn new(cfg: DegradeConfig) -> Self {
+        Self { cfg, switch: ProfileSwitch::default(), clock: clock::system(), sources: Vec::new(), tx: None, last: None, since: None }
+    }
+
+    /// Read the time from `clock` instead of the system clock, see [`crate::clock`].
+    pub fn clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// The switch to hand to the sensors.
+    pub fn switch(&self) -> ProfileSwitch {
+        self.switch.clone()
+    }
+
+    /// Sampled on every tick of [`DegradeController::spawn`]; samples of all sources are merged.
+    pub fn add_source<F: Fn() -> Pressure + Send + Sync + 'static>(&mut self, f: F) {
+        self.sources.push(Box::new(f));
+    }
+
+    /// Where the `DegradedMode` markers go, e.g. a sink's channel.
+    pub fn set_channel(&mut self, tx: mpsc::Sender<CallbackResult>) {
+        self.tx = Some(tx);
+    }
+
+    /// Feed one sample. Returns the marker if the profile changed.
+    pub fn observe(&mut self, p: Pressure) -> Option<Value> {
+        let now = self.clock.now_instant();
+        let ind = match self.last.replace((p, now)) {
+            Some((prev, at)) => Indicators::between(&prev, &p, now.saturating_duration_since(at)),
+            None => Indicators { queue_fill: p.queue_fill, ..Indicators::default() },
+        };
+        let checks = self.cfg.checks(&ind);
+
+        let (exit, wait, reason) = match self.switch.profile() {
+            Profile::Full => {
+                let over = checks.iter().find(|(_, v, limit)| v > limit).map(|(name, ..)| *name);
+                (over.is_some(), self.cfg.enter_after_secs, over.unwrap_or_default())
+            }
+            Profile::Degraded => {
+                let calm = checks.iter().all(|(_, v, limit)| *v < limit * self.cfg.exit_ratio);
+                (calm, self.cfg.exit_after_secs, "recovered")
+            }
+        };
+        if !exit {
+            self.since = None;
+            return None;
+        }
+        let since = *self.since.get_or_insert(now);
+        if now.saturating_duration_since(since) < Duration::from_secs_f64(wait.max(0.0)) {
+            return None;
+        }
+
+        self.since = None;
+        let (profile, state) = match self.switch.profile() {
+            Profile::Full => (Profile::Degraded, "entered"),
+            Profile::Degraded => (Profile::Full, "exited"),
+        };
+        self.switch.set(profile);
+        log::warn!("degrade: {state} degraded mode ({reason})");
+        let at = self.clock.now_system().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0);
+        Some(json!({ DEGRADED_MODE: { "state": state, "reason": reason, "indicators": ind, "at": at } }))
+    }
+
+    /// Sample the sources every `sample_secs` until `cancel` fires, sending markers on the channel.
+    pub fn spawn(mut self, cancel: CancellationToken) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_secs_f64(self.cfg.sample_secs.max(0.001)));
+            loop {
+                tokio::select! {
+                    _ = cancel.cancelled() => break,
+                    _ = ticker.tick() => {}
+                }
+                let p = self.sources.iter().fold(Pressure::default(), |acc, s| acc.merge(s()));
+                if let Some(marker) = self.observe(p)
+                    && let Some(tx) = &self.tx
+                {
+                    let _ = tx.send(marker).await;
+                }
+            }
+        })
+    }
+}
+
+/// CPU time (user and system) used by this process so far.
+#[cfg(unix)]
+pub fn cpu_time() -> Option<Duration> {
+    let mut ru: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut ru) } != 0 {
+        return None;
+    }
+    let tv = |t: libc::timeval| Duration::from_secs(t.tv_sec as u64) + Duration::from_micros(t.tv_usec as u64);
+    Some(tv(ru.ru_utime) + tv(ru.ru_stime))
+}
+
+#[cfg(not(unix))]
+pub fn cpu_time() -> Option<Duration> {
+    None
+}
@@ -0,0 +1,135 @@
+use crate::{
+    clock::ManualClock,
+    degrade::{DEGRADED_MODE, DegradeConfig, DegradeController, Pressure, Profile, cpu_time},
+};
+use serde_json::Value;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+
+/// Drives a controller one simulated second per sample, with a drop counter growing at the given rate.
+struct Driver {
+    ctl: DegradeController,
+    clock: ManualClock,
+    drops: u64,
+    secs: u64,
+}
+
+impl Driver {
+    fn new(cfg: DegradeConfig) -> Self {
+        let clock = ManualClock::new();
+        let mut ctl = DegradeController::new(cfg).clock(clock.shared());
+        assert!(ctl.observe(Pressure::default()).is_none());
+        Self { ctl, clock, drops: 0, secs: 0 }
+    }
+
+    /// Run `secs` seconds at `per_sec` drops; the markers with the second they came at.
+    fn run(&mut self, secs: u64, per_sec: u64) -> Vec<(u64, Value)> {
+        let mut out = Vec::new();
+        for _ in 0..secs {
+            self.clock.advance(Duration::from_secs(1));
+            self.secs += 1;
+            self.drops += per_sec;
+            if let Some(m) = self.ctl.observe(Pressure { drops: self.drops, ..Pressure::default() }) {
+                out.push((self.secs, m));
+            }
+        }
+        out
+    }
+}
+
+fn drops_limit() -> DegradeConfig {
+    DegradeConfig { max_drops_per_sec: Some(100.0), enter_after_secs: 10.0, exit_after_secs: 30.0, ..DegradeConfig::default() }
+}
+
+#[test]
+fn sustained_pressure_degrades_and_recovery_needs_calm() {
+    let mut d = Driver::new(drops_limit());
+    let switch = d.ctl.switch();
+
+    // bursts shorter than enter_after do not count, and a calm second restarts the wait
+    assert!(d.run(8, 500).is_empty());
+    assert!(d.run(1, 10).is_empty());
+    assert!(d.run(8, 500).is_empty());
+    assert_eq!(switch.profile(), Profile::Full);
+
+    let entered = d.run(5, 500);
+    assert_eq!(entered.len(), 1);
+    let (at, m) = &entered[0];
+    assert_eq!(*at, 20, "10s after the streak started at second 10");
+    assert_eq!(m[DEGRADED_MODE]["state"], "entered");
+    assert_eq!(m[DEGRADED_MODE]["reason"], "drops_per_sec");
+    assert_eq!(m[DEGRADED_MODE]["indicators"]["drops_per_sec"], 500.0);
+    assert_eq!(switch.profile(), Profile::Degraded);
+
+    // below the limit but above half of it: hysteresis keeps the profile
+    assert!(d.run(120, 80).is_empty());
+    assert_eq!(switch.profile(), Profile::Degraded);
+
+    // calm, interrupted once, then long enough
+    assert!(d.run(20, 20).is_empty());
+    assert!(d.run(1, 60).is_empty());
+    let calm_from = d.secs + 1;
+    let exited = d.run(40, 20);
+    assert_eq!(exited.iter().map(|(at, m)| (*at, m[DEGRADED_MODE]["state"].clone())).collect::<Vec<_>>(), [(calm_from + 30, "exited".into())]);
+    assert_eq!(switch.profile(), Profile::Full);
+}
+
+#[test]
+fn queue_fill_and_cpu_are_checked_too() {
+    let clock = ManualClock::new();
+    let cfg = DegradeConfig { max_queue_fill: Some(0.8), max_cpu_percent: Some(50.0), enter_after_secs: 0.0, ..DegradeConfig::default() };
+    let mut ctl = DegradeController::new(cfg.clone()).clock(clock.shared());
+    assert_eq!(ctl.observe(Pressure { queue_fill: 0.95, ..Pressure::default() }).unwrap()[DEGRADED_MODE]["reason"], "queue_fill");
+
+    let mut ctl = DegradeController::new(cfg).clock(clock.shared());
+    assert!(ctl.observe(Pressure { cpu_time: Duration::from_secs(10), ..Pressure::default() }).is_none());
+    clock.advance(Duration::from_secs(2));
+    let m = ctl.observe(Pressure { cpu_time: Duration::from_millis(11_500), ..Pressure::default() }).unwrap();
+    assert_eq!((m[DEGRADED_MODE]["reason"].as_str(), m[DEGRADED_MODE]["indicators"]["cpu_percent"].as_f64()), (Some("cpu_percent"), Some(75.0)));
+    assert!(cpu_time().is_some());
+}
+
+#[tokio::test]
+async fn spawned_controller_samples_sources_and_sends_markers() {
+    let cfg =
+        DegradeConfig { max_drops_per_sec: Some(100.0), enter_after_secs: 0.0, exit_after_secs: 0.0, sample_secs: 0.005, ..DegradeConfig::default() };
+    let mut ctl = DegradeController::new(cfg);
+    // a storm drops 1000 events per sample, in one of two sources
+    let storm = Arc::new(AtomicBool::new(false));
+    let (drops, s) = (Arc::new(AtomicU64::new(0)), storm.clone());
+    ctl.add_source(move || {
+        let n = if s.load(Ordering::Relaxed) { 1000 } else { 0 };
+        Pressure { drops: drops.fetch_add(n, Ordering::Relaxed) + n, ..Pressure::default() }
+    });
+    ctl.add_source(|| Pressure { queue_fill: 0.1, ..Pressure::default() });
+    let (tx, mut rx) = tokio::sync::mpsc::channel(4);
+    ctl.set_channel(tx);
+    let switch = ctl.switch();
+    let cancel = CancellationToken::new();
+    let task = ctl.spawn(cancel.clone());
+
+    storm.store(true, Ordering::Relaxed);
+    let m = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+    assert_eq!(m[DEGRADED_MODE]["state"], "entered");
+    assert_eq!(switch.profile(), Profile::Degraded);
+    storm.store(false, Ordering::Relaxed);
+    let m = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+    assert_eq!(m[DEGRADED_MODE]["state"], "exited");
+    cancel.cancel();
+    task.await.unwrap();
+}
+
+#[test]
+fn config_takes_units() {
+    let cfg: DegradeConfig =
+        serde_json::from_str(r#"{ "max_drops_per_sec": "600/m", "enter_after_secs": "1m", "exit_after_secs": 90, "sample_secs": "250ms" }"#).unwrap();
+    assert_eq!(cfg.max_drops_per_sec, Some(10.0));
+    assert_eq!((cfg.enter_after_secs, cfg.exit_after_secs, cfg.sample_secs), (60.0, 90.0, 0.25));
+    assert_eq!(cfg.max_overruns_per_sec, None);
+}
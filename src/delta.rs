@@ -0,0 +1,85 @@
+//! Compact form of Changed events for sinks.
+//!
+//! Events carrying a full `old` and `new` object (xmount `Changed { target, old, new }`)
+//! serialize both, although usually one or two fields differ. [`encode`] rewrites such a
+//! payload so that only the differing fields remain:
+//!
+//! ```json
+//! {"Changed": {"target": "/data", "changed": {"mount_opts": {"old": "rw,relatime", "new": "ro,relatime"}}}}
+//! ```
+//!
+//! Nested objects are compared field by field and reported under dotted keys
+//! (`"info.size"`); arrays and scalars are compared as a whole. A field present on one side
+//! only has `null` on the other. All other fields of the variant stay as they are, and so do
+//! events without both `old` and `new` objects. The in-memory events are not affected: this
+//! only runs where events are serialized for a sink, see `RouterConfig::delta_changes`.
+
+use serde_json::{Map, Value, json};
+
+/// Key replacing `old` and `new` in the encoded variant.
+pub const CHANGED_FIELD: &str = "changed";
+
+/// Delta-encode `payload` if it is an externally tagged variant with `old` and `new`
+/// objects, see the module docs. Returns None for anything else. Fields next to the
+/// variant, such as `severity` added by the router, are kept.
+pub fn encode(payload: &Value) -> Option<Value> {
+    let Value::Object(outer) = payload else { return None };
+    let (variant, old, new) = outer.iter().find_map(|(variant, body)| match (body.get("old"), body.get("new")) {
+        (Some(Value::Object(old)), Some(Value::Object(new))) => Some((variant, old, new)),
+        _ => None,
+    })?;
+
+    let mut changed = Map::new();
+    diff_objects("", old, new, &mut changed);
+
+    let mut out = outer.clone();
+    if let Some(Value::Object(body)) = out.get_mut(variant) {
+        body.remove("old");
+        body.remove("new");
+        body.insert(CHANGED_FIELD.to_string(), Value::Object(changed));
+    }
+    Some(Value::Object(out))
+}
+
+/// Apply the `changed` map of an encoded event to the prior state, giving the new state.
+/// Fields that are `null` on the new side are removed.
+pub fn apply(prior: &Value, changed: &Map<String, Value>) -> Value {
+    let mut out = prior.clone();
+    for (key, change) in changed {
+        let new = change.get("new").cloned().unwrap_or(Value::Null);
+        set_path(&mut out, key, new);
+    }
+    out
+}
+
+fn diff_objects(prefix: &str, old: &Map<String, Value>, new: &Map<String, Value>, out: &mut Map<String, Value>) {
+    let mut keys: Vec<&String> = old.keys().chain(new.keys().filter(|k| !old.contains_key(*k))).collect();
+    keys.sort();
+    for key in keys {
+        let path = if prefix.is_empty() { key.clone() } else { format!("{prefix}.{key}") };
+        match (old.get(key), new.get(key)) {
+            (Some(Value::Object(a)), Some(Value::Object(b))) => diff_objects(&path, a, b, out),
+            (a, b) if a != b => {
+                out.insert(path, json!({ "old": a.cloned().unwrap_or(Value::Null), "new": b.cloned().unwrap_or(Value::Null) }));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn set_path(target: &mut Value, path: &str, value: Value) {
+    let (head, rest) = match path.split_once('.') {
+        Some((head, rest)) => (head, Some(rest)),
+        None => (path, None),
+    };
+    let Value::Object(map) = target else { return };
+    match rest {
+        Some(rest) => set_path(map.entry(head.to_string()).or_insert_with(|| Value::Object(Map::new())), rest, value),
+        None if value.is_null() => {
+            map.remove(head);
+        }
+        None => {
+            map.insert(head.to_string(), value);
+        }
+    }
+}
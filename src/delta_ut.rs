@@ -0,0 +1,74 @@
+use crate::{
+    delta,
+    router::{RouteRule, Router},
+};
+use serde_json::{Value, json};
+use tokio::sync::mpsc::channel;
+
+fn changed_event() -> Value {
+    json!({ "Changed": {
+        "target": "/data",
+        "old": { "mount_id": 41, "fstype": "ext4", "mount_opts": "rw,relatime", "extra": { "a": 1, "b": [1, 2] } },
+        "new": { "mount_id": 41, "fstype": "ext4", "mount_opts": "ro,relatime", "extra": { "a": 1, "b": [1, 3] }, "label": "x" },
+    }})
+}
+
+#[test]
+fn encode_keeps_only_differing_fields() {
+    let enc = delta::encode(&changed_event()).unwrap();
+    assert_eq!(
+        enc,
+        json!({ "Changed": {
+            "target": "/data",
+            "changed": {
+                "extra.b": { "old": [1, 2], "new": [1, 3] },
+                "label": { "old": null, "new": "x" },
+                "mount_opts": { "old": "rw,relatime", "new": "ro,relatime" },
+            },
+        }})
+    );
+}
+
+#[test]
+fn prior_state_plus_delta_gives_the_new_state() {
+    let ev = changed_event();
+    let enc = delta::encode(&ev).unwrap();
+    let changed = enc["Changed"]["changed"].as_object().unwrap();
+    assert_eq!(delta::apply(&ev["Changed"]["old"], changed), ev["Changed"]["new"]);
+
+    // and back: a field only on the old side is dropped again
+    let back = json!({ "Changed": { "target": "/data", "old": ev["Changed"]["new"], "new": ev["Changed"]["old"] } });
+    let enc = delta::encode(&back).unwrap();
+    assert_eq!(delta::apply(&ev["Changed"]["new"], enc["Changed"]["changed"].as_object().unwrap()), ev["Changed"]["old"]);
+}
+
+#[test]
+fn other_events_are_left_alone() {
+    for ev in [
+        json!({ "Mounted": { "target": "/data", "info": { "fstype": "ext4" } } }),
+        // scalar old/new, e.g. netpacket LimitChanged
+        json!({ "LimitChanged": { "name": "net/core/somaxconn", "old": "128", "new": "4096" } }),
+        json!("Tick"),
+    ] {
+        assert_eq!(delta::encode(&ev), None, "{ev}");
+    }
+}
+
+#[tokio::test]
+async fn router_delivers_deltas_but_matches_full_events() {
+    let mut router = Router::new();
+    let (tx, mut rx) = channel(4);
+    router.add_sink("jsonl", tx);
+    router.add_rule(RouteRule::new("ro").when(crate::router::FieldMatch::new("Changed.new.mount_opts", "ro,relatime")).to("jsonl"));
+    router.set_delta_changes(true);
+
+    let mut ev = changed_event();
+    ev["severity"] = json!("warning");
+    router.dispatch("xmount", 0b100, &ev).await;
+
+    let got = rx.try_recv().unwrap();
+    assert_eq!(got["severity"], "warning");
+    assert!(got["Changed"].get("old").is_none() && got["Changed"].get("new").is_none());
+    assert_eq!(got["Changed"]["changed"]["mount_opts"]["new"], "ro,relatime");
+    assert_eq!(router.stats()[0].routed, 1);
+}
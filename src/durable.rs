@@ -0,0 +1,477 @@
+//! Durable sink queue for at-least-once delivery.
+//!
+//! Events for a sink are first appended to an on-disk queue of segment files; the sink
+//! reads them through a [`DurableConsumer`] and acknowledges what it delivered. Whatever
+//! was not acknowledged when the sink (or the whole agent) went down is delivered again
+//! by the next consumer, so nothing is lost and duplicates are bounded by the window of
+//! events read but not yet acked.
+//!
+//! ```ignore
+//! let queue = DurableQueue::open("/var/lib/omnitrace/queue/collector", DurableConfig::default())?;
+//! let (tx, rx) = mpsc::channel(1024);
+//! router.add_sink("collector", tx);
+//! queue.spawn(rx);
+//!
+//! let mut c = queue.consumer();
+//! while let Some(d) = c.next().await {
+//!     if send(&d.event).await.is_ok() {
+//!         c.ack(d.seq)?;
+//!     }
+//! }
+//! ```
+//!
+//! Layout of the queue directory:
+//!
+//! - `<first seq>.seg`: records `len: u32 | check: u32 | seq: u64 | ts: u64 | json`, little
+//!   endian, `check` being the first four bytes of the blake3 of the rest. A torn record at
+//!   the end of the last segment (crash mid-write) is cut off on open. Records lost in a
+//!   torn earlier segment are skipped; the ones after them keep their sequence numbers.
+//! - `cursor`: the first sequence number not acknowledged yet, replaced atomically.
+//!
+//! Disk use is capped by `max_bytes`: once over, the oldest segment is dropped, acked or
+//! not, and the unacked events in it are counted in [`DurableStats::dropped`].
+
+use crate::{
+    callbacks::CallbackResult,
+    clock::{self, SharedClock},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    sync::{Notify, mpsc},
+    task::JoinHandle,
+};
+
+const HEADER: usize = 24;
+const CURSOR: &str = "cursor";
+
+/// When appended records are fsynced.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FsyncPolicy {
+    /// After every record and every ack: nothing acknowledged as written is lost on power loss.
+    Always,
+    /// At most every `fsync_interval_ms`: a power loss can lose that much.
+    #[default]
+    Interval,
+    /// Leave it to the OS. Survives a crash of the agent, not of the host.
+    Never,
+}
+
+/// ```json
+/// { "max_bytes": "64MiB", "segment_bytes": "4MiB", "fsync": "interval", "fsync_interval_ms": "1s" }
+/// ```
+///
+/// Sizes and the interval also take plain numbers, of bytes and milliseconds.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DurableConfig {
+    /// Disk use above which the oldest segment is dropped.
+    #[serde(default = "default_max_bytes", deserialize_with = "crate::units::bytes::deserialize")]
+    pub max_bytes: u64,
+    /// Size at which a new segment is started.
+    #[serde(default = "default_segment_bytes", deserialize_with = "crate::units::bytes::deserialize")]
+    pub segment_bytes: u64,
+    #[serde(default)]
+    pub fsync: FsyncPolicy,
+    #[serde(default = "default_fsync_interval", deserialize_with = "crate::units::ms::deserialize")]
+    pub fsync_interval_ms: u64,
+}
+
+impl Default for DurableConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: default_max_bytes(),
+            segment_bytes: default_segment_bytes(),
+            fsync: FsyncPolicy::default(),
+            fsync_interval_ms: default_fsync_interval(),
+        }
+    }
+}
+
+fn default_max_bytes() -> u64 {
+    64 << 20
+}
+
+fn default_segment_bytes() -> u64 {
+    4 << 20
+}
+
+fn default_fsync_interval() -> u64 {
+    1000
+}
+
+/// Queue health, see [`DurableQueue::stats`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct DurableStats {
+    /// Events appended but not acknowledged.
+    pub depth: u64,
+    /// Age of the oldest unacknowledged event.
+    pub oldest_unacked_secs: Option<f64>,
+    /// Unacknowledged events dropped to stay within `max_bytes`.
+    pub dropped: u64,
+    pub bytes: u64,
+    pub segments: usize,
+}
+
+/// One event as read from the queue.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Delivery {
+    pub seq: u64,
+    pub event: Value,
+}
+
+struct Segment {
+    first: u64,
+    path: PathBuf,
+    len: u64,
+}
+
+/// Where an unacked record is and when it was appended.
+#[derive(Clone, Copy)]
+struct Entry {
+    seq: u64,
+    segment: u64,
+    offset: u64,
+    ts: u64,
+}
+
+struct State {
+    segments: VecDeque<Segment>,
+    file: Option<File>,
+    next_seq: u64,
+    // first seq not acked; entries are the intact records from it on, in seq order
+    cursor: u64,
+    entries: VecDeque<Entry>,
+    dropped: u64,
+    last_sync: Instant,
+    clock: SharedClock,
+}
+
+struct Inner {
+    dir: PathBuf,
+    cfg: DurableConfig,
+    state: Mutex<State>,
+    appended: Notify,
+}
+
+/// Handle to a queue directory. Cheap to clone.
+#[derive(Clone)]
+pub struct DurableQueue {
+    inner: Arc<Inner>,
+}
+
+impl DurableQueue {
+    /// Open (or create) the queue in `dir`, recovering what a previous run left.
+    pub fn open<P: AsRef<Path>>(dir: P, cfg: DurableConfig) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+        let mut cursor = read_cursor(&dir)?;
+
+        let mut paths: Vec<(u64, PathBuf)> = std::fs::read_dir(&dir)?
+            .filter_map(|e| {
+                let path = e.ok()?.path();
+                let first = path.file_name()?.to_str()?.strip_suffix(".seg")?.parse().ok()?;
+                Some((first, path))
+            })
+            .collect();
+        paths.sort();
+
+        let (mut segments, mut entries, mut next_seq) = (VecDeque::new(), VecDeque::new(), None);
+        let last = paths.len().saturating_sub(1);
+        for (i, (first, path)) in paths.into_iter().enumerate() {
+            let (records, good) = scan(&path, first)?;
+            let len = std::fs::metadata(&path)?.len();
+            if good < len {
+                log::warn!("durable: {} ends in a torn record, cutting {} bytes", path.display(), len - good);
+                if i == last {
+                    OpenOptions::new().write(true).open(&path)?.set_len(good)?;
+                }
+            }
+            // a torn segment before this one lost the records up to its first
+            if let (Some(expected), Some(e)) = (next_seq, records.first())
+                && e.seq > expected
+            {
+                log::warn!("durable: {} follows a gap, events {expected}..{} are lost", path.display(), e.seq);
+            }
+            if let Some(e) = records.last() {
+                next_seq = Some(e.seq + 1);
+            }
+            if records.last().is_some_and(|e| e.seq < cursor) && i != last {
+                std::fs::remove_file(&path)?;
+                continue;
+            }
+            entries.extend(records.into_iter().filter(|e| e.seq >= cursor));
+            segments.push_back(Segment { first, path, len: good });
+        }
+        let next_seq = next_seq.unwrap_or(cursor).max(cursor);
+        // events lost with a torn segment or removed segments are skipped
+        cursor = entries.front().map_or(next_seq, |e| e.seq);
+
+        let state = State { segments, file: None, next_seq, cursor, entries, dropped: 0, last_sync: Instant::now(), clock: clock::system() };
+        Ok(Self { inner: Arc::new(Inner { dir, cfg, state: Mutex::new(state), appended: Notify::new() }) })
+    }
+
+    /// Time source for record timestamps and the oldest-unacked age (default: the system clock).
+    pub fn clock(self, clock: SharedClock) -> Self {
+        self.lock().clock = clock;
+        self
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.inner.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Append one event, returning its sequence number.
+    pub fn push(&self, event: &Value) -> io::Result<u64> {
+        let body = serde_json::to_vec(event)?;
+        let cfg = &self.inner.cfg;
+        let mut st = self.lock();
+        let seq = st.next_seq;
+        let ts = unix_millis(st.clock.now_system());
+        let rec = encode(seq, ts, &body);
+
+        let full = st.segments.back().is_none_or(|s| s.len > 0 && s.len + rec.len() as u64 > cfg.segment_bytes.min(cfg.max_bytes));
+        if full || st.file.is_none() {
+            if full {
+                let path = self.inner.dir.join(format!("{seq:020}.seg"));
+                st.segments.push_back(Segment { first: seq, path, len: 0 });
+            }
+            let seg = st.segments.back().map(|s| s.path.clone()).unwrap_or_default();
+            if let Some(f) = st.file.take() {
+                f.sync_all()?;
+            }
+            st.file = Some(OpenOptions::new().create(true).append(true).open(seg)?);
+        }
+
+        if let Some(file) = st.file.as_mut() {
+            file.write_all(&rec)?;
+        }
+        if let Some(seg) = st.segments.back_mut() {
+            let entry = Entry { seq, segment: seg.first, offset: seg.len, ts };
+            seg.len += rec.len() as u64;
+            st.entries.push_back(entry);
+        }
+        st.next_seq += 1;
+        self.sync(&mut st, false)?;
+        self.enforce_cap(&mut st)?;
+        drop(st);
+        self.inner.appended.notify_waiters();
+        Ok(seq)
+    }
+
+    fn sync(&self, st: &mut State, force: bool) -> io::Result<()> {
+        let due = match self.inner.cfg.fsync {
+            FsyncPolicy::Always => true,
+            FsyncPolicy::Interval => force || st.last_sync.elapsed() >= Duration::from_millis(self.inner.cfg.fsync_interval_ms),
+            FsyncPolicy::Never => false,
+        };
+        if due && let Some(f) = &st.file {
+            f.sync_data()?;
+            st.last_sync = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// Drop the oldest segments until the queue fits `max_bytes` again.
+    fn enforce_cap(&self, st: &mut State) -> io::Result<()> {
+        while st.segments.len() > 1 && st.segments.iter().map(|s| s.len).sum::<u64>() > self.inner.cfg.max_bytes {
+            let Some(old) = st.segments.pop_front() else { break };
+            let end = st.segments.front().map_or(st.next_seq, |s| s.first);
+            if end > st.cursor {
+                let lost = st.entries.partition_point(|e| e.seq < end);
+                log::warn!("durable: {} over its disk limit, dropping {lost} unacked events", self.inner.dir.display());
+                st.dropped += lost as u64;
+                st.entries.drain(..lost);
+                st.cursor = end;
+                write_cursor(&self.inner.dir, end, self.inner.cfg.fsync == FsyncPolicy::Always)?;
+            }
+            std::fs::remove_file(&old.path)?;
+        }
+        Ok(())
+    }
+
+    /// Acknowledge every event up to and including `seq`. Segments holding only acked events
+    /// are removed.
+    pub fn ack(&self, seq: u64) -> io::Result<()> {
+        let mut st = self.lock();
+        let cursor = (seq + 1).min(st.next_seq);
+        if cursor <= st.cursor {
+            return Ok(());
+        }
+        let acked = st.entries.partition_point(|e| e.seq < cursor);
+        st.entries.drain(..acked);
+        st.cursor = cursor;
+        write_cursor(&self.inner.dir, cursor, self.inner.cfg.fsync == FsyncPolicy::Always)?;
+
+        while st.segments.len() > 1 && st.segments.get(1).is_some_and(|next| next.first <= cursor) {
+            if let Some(old) = st.segments.pop_front() {
+                std::fs::remove_file(&old.path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fsync appended records now, whatever the policy.
+    pub fn flush(&self) -> io::Result<()> {
+        let mut st = self.lock();
+        self.sync(&mut st, true)
+    }
+
+    /// Reader starting at the oldest unacknowledged event.
+    pub fn consumer(&self) -> DurableConsumer {
+        let next = self.lock().cursor;
+        DurableConsumer { queue: self.clone(), next, reader: None }
+    }
+
+    pub fn stats(&self) -> DurableStats {
+        let st = self.lock();
+        let now = unix_millis(st.clock.now_system());
+        DurableStats {
+            depth: st.entries.len() as u64,
+            oldest_unacked_secs: st.entries.front().map(|e| now.saturating_sub(e.ts) as f64 / 1000.0),
+            dropped: st.dropped,
+            bytes: st.segments.iter().map(|s| s.len).sum(),
+            segments: st.segments.len(),
+        }
+    }
+
+    /// Append everything received on `rx` until all senders are gone, e.g. a
+    /// [`crate::router::Router`] sink.
+    pub fn spawn(self, mut rx: mpsc::Receiver<CallbackResult>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            while let Some(ev) = rx.recv().await {
+                if let Err(e) = self.push(&ev) {
+                    log::error!("durable: failed to append to {}: {e}", self.inner.dir.display());
+                }
+            }
+            let _ = self.flush();
+        })
+    }
+}
+
+/// Reads a [`DurableQueue`] in order. Dropping it without acking leaves the events for the
+/// next consumer.
+pub struct DurableConsumer {
+    queue: DurableQueue,
+    next: u64,
+    reader: Option<(u64, File)>,
+}
+
+impl DurableConsumer {
+    /// The next event, waiting for one to be appended if needed.
+    pub async fn next(&mut self) -> Option<Delivery> {
+        loop {
+            let queue = self.queue.inner.clone();
+            let appended = queue.appended.notified();
+            match self.try_next() {
+                Ok(Some(d)) => return Some(d),
+                Ok(None) => appended.await,
+                Err(e) => {
+                    log::error!("durable: failed to read {}: {e}", self.queue.inner.dir.display());
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// The next event if one is there.
+    pub fn try_next(&mut self) -> io::Result<Option<Delivery>> {
+        let (entry, path) = {
+            let st = self.queue.lock();
+            // dropped over the cap, or acked by someone else meanwhile
+            self.next = self.next.max(st.cursor);
+            let Some(entry) = st.entries.get(st.entries.partition_point(|e| e.seq < self.next)).copied() else {
+                return Ok(None);
+            };
+            let path = st.segments.iter().find(|s| s.first == entry.segment).map(|s| s.path.clone()).unwrap_or_default();
+            (entry, path)
+        };
+
+        let file = match &mut self.reader {
+            Some((segment, f)) if *segment == entry.segment => f,
+            reader => &mut reader.insert((entry.segment, File::open(path)?)).1,
+        };
+        file.seek(SeekFrom::Start(entry.offset))?;
+        let mut header = [0u8; HEADER];
+        file.read_exact(&mut header)?;
+        let mut body = vec![0u8; u32::from_le_bytes(header[0..4].try_into().unwrap_or_default()) as usize];
+        file.read_exact(&mut body)?;
+
+        self.next = entry.seq + 1;
+        Ok(Some(Delivery { seq: entry.seq, event: serde_json::from_slice(&body)? }))
+    }
+
+    /// Acknowledge every event up to and including `seq`, see [`DurableQueue::ack`].
+    pub fn ack(&self, seq: u64) -> io::Result<()> {
+        self.queue.ack(seq)
+    }
+}
+
+fn check(rest: &[u8], body: &[u8]) -> u32 {
+    let mut h = blake3::Hasher::new();
+    h.update(rest);
+    h.update(body);
+    u32::from_le_bytes(h.finalize().as_bytes()[..4].try_into().unwrap_or_default())
+}
+
+fn encode(seq: u64, ts: u64, body: &[u8]) -> Vec<u8> {
+    let mut rest = [0u8; 16];
+    rest[..8].copy_from_slice(&seq.to_le_bytes());
+    rest[8..].copy_from_slice(&ts.to_le_bytes());
+    let mut rec = Vec::with_capacity(HEADER + body.len());
+    rec.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    rec.extend_from_slice(&check(&rest, body).to_le_bytes());
+    rec.extend_from_slice(&rest);
+    rec.extend_from_slice(body);
+    rec
+}
+
+/// Entry of every intact record in `segment`, and the length of the intact prefix.
+fn scan(path: &Path, segment: u64) -> io::Result<(Vec<Entry>, u64)> {
+    let data = std::fs::read(path)?;
+    let (mut records, mut pos) = (Vec::new(), 0usize);
+    while data.len() - pos >= HEADER {
+        let h = &data[pos..pos + HEADER];
+        let len = u32::from_le_bytes(h[0..4].try_into().unwrap_or_default()) as usize;
+        let Some(body) = data.get(pos + HEADER..pos + HEADER + len) else { break };
+        if u32::from_le_bytes(h[4..8].try_into().unwrap_or_default()) != check(&h[8..], body) {
+            break;
+        }
+        let seq = u64::from_le_bytes(h[8..16].try_into().unwrap_or_default());
+        let ts = u64::from_le_bytes(h[16..24].try_into().unwrap_or_default());
+        records.push(Entry { seq, segment, offset: pos as u64, ts });
+        pos += HEADER + len;
+    }
+    Ok((records, pos as u64))
+}
+
+fn read_cursor(dir: &Path) -> io::Result<u64> {
+    match std::fs::read_to_string(dir.join(CURSOR)) {
+        Ok(s) => s.trim().parse().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {e}", dir.join(CURSOR).display()))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_cursor(dir: &Path, cursor: u64, fsync: bool) -> io::Result<()> {
+    let tmp = dir.join(format!("{CURSOR}.tmp"));
+    let mut f = File::create(&tmp)?;
+    f.write_all(cursor.to_string().as_bytes())?;
+    if fsync {
+        f.sync_all()?;
+    }
+    std::fs::rename(tmp, dir.join(CURSOR))
+}
+
+fn unix_millis(now: SystemTime) -> u64 {
+    now.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
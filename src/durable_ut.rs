@@ -0,0 +1,169 @@
+use crate::{
+    clock::ManualClock,
+    durable::{DurableConfig, DurableConsumer, DurableQueue, FsyncPolicy},
+};
+use serde_json::json;
+use std::{collections::HashMap, io::Write, time::Duration};
+use tempfile::TempDir;
+use tokio::sync::mpsc;
+
+fn small() -> DurableConfig {
+    DurableConfig { segment_bytes: 1024, fsync: FsyncPolicy::Never, ..DurableConfig::default() }
+}
+
+/// Mock sink: delivers `n` events into `seen`, acking every `every`th sequence number.
+async fn deliver(c: &mut DurableConsumer, n: usize, every: u64, seen: &mut Vec<u64>) {
+    for _ in 0..n {
+        let d = tokio::time::timeout(Duration::from_secs(5), c.next()).await.unwrap().unwrap();
+        seen.push(d.event["n"].as_u64().unwrap());
+        if (d.seq + 1).is_multiple_of(every) {
+            c.ack(d.seq).unwrap();
+        }
+    }
+}
+
+#[tokio::test]
+async fn restarted_sinks_get_the_unacked_events_again() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let (total, every) = (500u64, 10u64);
+    let queue = DurableQueue::open(dir, small()).unwrap();
+    let (tx, rx) = mpsc::channel(64);
+    let writer = queue.clone().spawn(rx);
+    let producer = tokio::spawn(async move {
+        for n in 0..total {
+            tx.send(json!({ "n": n })).await.unwrap();
+        }
+    });
+
+    let mut seen = Vec::new();
+    // the sink dies mid-window, then comes back
+    let mut c = queue.consumer();
+    deliver(&mut c, 237, every, &mut seen).await;
+    drop(c);
+    let mut c = queue.consumer();
+    deliver(&mut c, 100, every, &mut seen).await;
+    drop(c);
+
+    // the whole agent restarts: reopen the queue from disk
+    producer.await.unwrap();
+    writer.await.unwrap();
+    drop(queue);
+    let queue = DurableQueue::open(dir, small()).unwrap();
+    let mut c = queue.consumer();
+    while let Some(d) = c.try_next().unwrap() {
+        seen.push(d.event["n"].as_u64().unwrap());
+        c.ack(d.seq).unwrap();
+    }
+    assert_eq!(queue.stats().depth, 0);
+
+    let mut counts: HashMap<u64, u64> = HashMap::new();
+    for n in &seen {
+        *counts.entry(*n).or_default() += 1;
+    }
+    assert!((0..total).all(|n| counts.contains_key(&n)), "lost events");
+    let dups = seen.len() as u64 - total;
+    assert!(dups > 0 && dups <= 2 * (every - 1), "{dups} duplicates");
+}
+
+#[test]
+fn torn_tail_is_cut_on_recovery() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let queue = DurableQueue::open(dir, small()).unwrap();
+    for n in 0..5 {
+        queue.push(&json!({ "n": n })).unwrap();
+    }
+    drop(queue);
+
+    // crash in the middle of a record
+    let seg = dir.join(format!("{:020}.seg", 0));
+    let mut f = std::fs::OpenOptions::new().append(true).open(&seg).unwrap();
+    f.write_all(&[42, 0, 0, 0, 1, 2, 3]).unwrap();
+
+    let queue = DurableQueue::open(dir, small()).unwrap();
+    assert_eq!(queue.push(&json!({ "n": 5 })).unwrap(), 5);
+    let mut c = queue.consumer();
+    let got: Vec<u64> = std::iter::from_fn(|| c.try_next().unwrap()).map(|d| d.event["n"].as_u64().unwrap()).collect();
+    assert_eq!(got, [0, 1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn torn_middle_segment_keeps_the_later_sequence_numbers() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let queue = DurableQueue::open(dir, small()).unwrap();
+    for n in 0..60 {
+        queue.push(&json!({ "n": n, "pad": "x".repeat(40) })).unwrap();
+    }
+    assert!(queue.stats().segments >= 3);
+    drop(queue);
+
+    // the second segment loses its second half
+    let mut segs: Vec<_> = std::fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()).filter(|p| p.extension().is_some_and(|x| x == "seg")).collect();
+    segs.sort();
+    let mid = std::fs::read(&segs[1]).unwrap();
+    std::fs::write(&segs[1], &mid[..mid.len() / 2 + 3]).unwrap();
+
+    let queue = DurableQueue::open(dir, small()).unwrap();
+    let mut c = queue.consumer();
+    let got: Vec<_> = std::iter::from_fn(|| c.try_next().unwrap()).collect();
+    assert!(got.len() < 60 && got.last().unwrap().seq == 59);
+    assert!(got.iter().all(|d| d.event["n"].as_u64() == Some(d.seq)));
+    assert_eq!(queue.stats().depth, got.len() as u64);
+
+    // acking past the gap holds across a restart
+    let after = got.windows(2).find(|w| w[1].seq > w[0].seq + 1).unwrap()[1].seq;
+    queue.ack(after).unwrap();
+    drop((c, queue));
+    let queue = DurableQueue::open(dir, small()).unwrap();
+    assert_eq!(queue.consumer().try_next().unwrap().unwrap().seq, after + 1);
+    assert_eq!(queue.push(&json!({ "n": 60 })).unwrap(), 60);
+}
+
+#[test]
+fn disk_cap_drops_the_oldest_unacked_segments() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let queue = DurableQueue::open(dir, DurableConfig { max_bytes: 4096, ..small() }).unwrap();
+    for n in 0..200 {
+        queue.push(&json!({ "n": n, "pad": "x".repeat(40) })).unwrap();
+    }
+    let stats = queue.stats();
+    assert!(stats.bytes <= 4096 && stats.dropped > 0);
+    assert_eq!(stats.depth + stats.dropped, 200);
+
+    let mut c = queue.consumer();
+    let first = c.try_next().unwrap().unwrap();
+    assert_eq!(first.seq, stats.dropped);
+
+    // acking removes the segments that are done
+    queue.ack(198).unwrap();
+    assert_eq!((queue.stats().depth, queue.stats().segments), (1, 1));
+    let segs = std::fs::read_dir(dir).unwrap().filter(|e| e.as_ref().unwrap().path().extension().is_some_and(|x| x == "seg")).count();
+    assert_eq!(segs, 1);
+}
+
+#[test]
+fn stats_report_depth_and_oldest_unacked_age() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let clock = ManualClock::new();
+    let queue = DurableQueue::open(dir, small()).unwrap().clock(clock.shared());
+    assert_eq!((queue.stats().depth, queue.stats().oldest_unacked_secs), (0, None));
+
+    queue.push(&json!({ "n": 0 })).unwrap();
+    clock.advance(Duration::from_secs(30));
+    queue.push(&json!({ "n": 1 })).unwrap();
+    clock.advance(Duration::from_secs(15));
+    assert_eq!((queue.stats().depth, queue.stats().oldest_unacked_secs), (2, Some(45.0)));
+
+    queue.ack(0).unwrap();
+    assert_eq!((queue.stats().depth, queue.stats().oldest_unacked_secs), (1, Some(15.0)));
+}
+
+#[test]
+fn config_takes_units() {
+    let cfg: DurableConfig = serde_json::from_str(r#"{ "max_bytes": "64MiB", "segment_bytes": 4096, "fsync_interval_ms": "2s" }"#).unwrap();
+    assert_eq!((cfg.max_bytes, cfg.segment_bytes, cfg.fsync_interval_ms), (64 << 20, 4096, 2000));
+}
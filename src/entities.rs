@@ -0,0 +1,165 @@
+//! Per-entity event counters kept by the sensors.
+//!
+//! Every sensor counts the events it emits per watched entity and kind: xmount per
+//! mountpoint, procdog per watched name, filescream per root, netpacket per rule
+//! (watermark, limit, counter) and per remote host for connection events. The handle,
+//! e.g. `XMount::entity_counters()`, is part of the debug snapshot and can be rendered by
+//! [`crate::prom::PromTextfile::entity_counters`].
+//!
+//! The table is bounded: at capacity a new entity evicts the one with the lowest recent
+//! activity, which is an exponentially decayed count with the window as time constant.
+//! Entities that were busy long ago fade out, so high-cardinality keys (remotes) keep the
+//! current top talkers instead of growing without limit.
+
+use crate::debug::Debuggable;
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Entities kept by default, see [`EntityCounters::new`].
+pub const DEFAULT_CAPACITY: usize = 256;
+
+/// Default time constant of the recent activity.
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Name of the (first) flag set in an event mask, e.g. `"MOUNTED"`, as the kind to record.
+pub fn mask_name<F: bitflags::Flags>(mask: F) -> &'static str {
+    mask.iter_names().next().map_or("", |(name, _)| name)
+}
+
+/// Counts of one entity, as reported.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct EntityStat {
+    pub entity: String,
+    /// Events since the entity entered the table.
+    pub total: u64,
+    /// Events per kind (lowercase mask name, e.g. `"mounted"`), since the entity entered the table.
+    pub by_kind: BTreeMap<String, u64>,
+    /// Events over about the last window, decayed exponentially.
+    pub recent: f64,
+    /// `recent` per second of window.
+    pub rate_per_sec: f64,
+}
+
+/// All entities, busiest first.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct EntityReport {
+    pub window_secs: f64,
+    pub capacity: usize,
+    /// Entities dropped to make room for new ones so far.
+    pub evicted: u64,
+    pub entities: Vec<EntityStat>,
+}
+
+impl EntityReport {
+    pub fn get(&self, entity: &str) -> Option<&EntityStat> {
+        self.entities.iter().find(|e| e.entity == entity)
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    total: u64,
+    by_kind: BTreeMap<String, u64>,
+    score: f64,
+    at: Instant,
+}
+
+impl Entry {
+    fn score_at(&self, now: Instant, window: Duration) -> f64 {
+        let elapsed = now.saturating_duration_since(self.at).as_secs_f64();
+        self.score * (-elapsed / window.as_secs_f64()).exp()
+    }
+}
+
+#[derive(Debug)]
+struct Table {
+    capacity: usize,
+    window: Duration,
+    entries: HashMap<String, Entry>,
+    evicted: u64,
+}
+
+/// Shared handle on a sensor's per-entity counters. Cheap to clone.
+#[derive(Clone, Debug)]
+pub struct EntityCounters(Arc<Mutex<Table>>);
+
+impl Default for EntityCounters {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY, DEFAULT_WINDOW)
+    }
+}
+
+impl EntityCounters {
+    /// Keep at most `capacity` entities (at least one), with recent activity decaying over `window`.
+    pub fn new(capacity: usize, window: Duration) -> Self {
+        let window = window.max(Duration::from_millis(1));
+        Self(Arc::new(Mutex::new(Table { capacity: capacity.max(1), window, entries: HashMap::new(), evicted: 0 })))
+    }
+
+    /// Count one event of `kind` for `entity`. The kind is lowercased.
+    pub fn record(&self, entity: &str, kind: &str) {
+        self.record_at(entity, kind, Instant::now());
+    }
+
+    pub(crate) fn record_at(&self, entity: &str, kind: &str, now: Instant) {
+        let Ok(mut t) = self.0.lock() else { return };
+        let window = t.window;
+        if !t.entries.contains_key(entity) && t.entries.len() >= t.capacity {
+            let coldest = t.entries.iter().min_by(|a, b| a.1.score_at(now, window).total_cmp(&b.1.score_at(now, window))).map(|(k, _)| k.clone());
+            if let Some(k) = coldest {
+                t.entries.remove(&k);
+                t.evicted += 1;
+            }
+        }
+
+        let e = t.entries.entry(entity.to_string()).or_insert_with(|| Entry { total: 0, by_kind: BTreeMap::new(), score: 0.0, at: now });
+        e.score = e.score_at(now, window) + 1.0;
+        e.at = now;
+        e.total += 1;
+        *e.by_kind.entry(kind.to_ascii_lowercase()).or_default() += 1;
+    }
+
+    pub fn report(&self) -> EntityReport {
+        self.report_at(Instant::now())
+    }
+
+    pub(crate) fn report_at(&self, now: Instant) -> EntityReport {
+        let Ok(t) = self.0.lock() else { return EntityReport::default() };
+        let window = t.window.as_secs_f64();
+        let mut entities: Vec<EntityStat> = t
+            .entries
+            .iter()
+            .map(|(entity, e)| {
+                let recent = e.score_at(now, t.window);
+                EntityStat { entity: entity.clone(), total: e.total, by_kind: e.by_kind.clone(), recent, rate_per_sec: recent / window }
+            })
+            .collect();
+        entities.sort_by(|a, b| b.recent.total_cmp(&a.recent).then_with(|| a.entity.cmp(&b.entity)));
+        EntityReport { window_secs: window, capacity: t.capacity, evicted: t.evicted, entities }
+    }
+
+    /// The `n` busiest entities.
+    pub fn top(&self, n: usize) -> Vec<EntityStat> {
+        let mut r = self.report();
+        r.entities.truncate(n);
+        r.entities
+    }
+}
+
+// Serialized as the report, so sensor debug structs can hold the handle itself.
+impl Serialize for EntityCounters {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        self.report().serialize(s)
+    }
+}
+
+impl Debuggable for EntityCounters {
+    fn debug_snapshot(&self) -> Value {
+        serde_json::to_value(self.report()).unwrap_or(Value::Null)
+    }
+}
@@ -0,0 +1,83 @@
+use crate::{debug::Debuggable, entities::EntityCounters, prom::PromTextfile};
+use std::time::{Duration, Instant};
+
+#[test]
+fn burst_is_counted_per_entity_and_kind() {
+    let c = EntityCounters::new(8, Duration::from_secs(60));
+    let t0 = Instant::now();
+    for i in 0..30 {
+        c.record_at("/mnt/busy", if i % 3 == 0 { "MOUNTED" } else { "UNMOUNTED" }, t0);
+    }
+    c.record_at("/mnt/quiet", "MOUNTED", t0);
+
+    let r = c.report_at(t0);
+    assert_eq!(r.entities.iter().map(|e| e.entity.as_str()).collect::<Vec<_>>(), ["/mnt/busy", "/mnt/quiet"]);
+    let busy = r.get("/mnt/busy").unwrap();
+    assert_eq!((busy.total, busy.by_kind["mounted"], busy.by_kind["unmounted"]), (30, 10, 20));
+    assert!((busy.recent - 30.0).abs() < 1e-9);
+    assert!((busy.rate_per_sec - 0.5).abs() < 1e-9);
+
+    // a window later the recent count has decayed to 1/e, the totals stay
+    let later = c.report_at(t0 + Duration::from_secs(60));
+    let busy = later.get("/mnt/busy").unwrap();
+    assert!((busy.recent - 30.0 / std::f64::consts::E).abs() < 1e-6, "{busy:?}");
+    assert_eq!(busy.total, 30);
+    assert_eq!(c.debug_snapshot()["entities"][0]["entity"], "/mnt/busy");
+}
+
+#[test]
+fn full_table_evicts_the_least_active_entity() {
+    let c = EntityCounters::new(3, Duration::from_secs(10));
+    let t0 = Instant::now();
+    for _ in 0..5 {
+        c.record_at("10.0.0.1", "opened", t0);
+    }
+    c.record_at("10.0.0.2", "opened", t0);
+    for _ in 0..3 {
+        c.record_at("10.0.0.3", "opened", t0);
+    }
+
+    // a newcomer replaces the quietest
+    c.record_at("10.0.0.4", "opened", t0);
+    let r = c.report_at(t0);
+    assert_eq!(r.evicted, 1);
+    assert!(r.get("10.0.0.2").is_none());
+    assert_eq!(r.entities.len(), 3);
+
+    // busy long ago loses against a little activity now
+    let t1 = t0 + Duration::from_secs(60);
+    c.record_at("10.0.0.4", "opened", t1);
+    c.record_at("10.0.0.3", "closed", t1);
+    c.record_at("10.0.0.5", "opened", t1);
+    let r = c.report_at(t1);
+    assert_eq!(r.evicted, 2);
+    assert!(r.get("10.0.0.1").is_none(), "{r:?}");
+    assert_eq!(r.entities.iter().map(|e| e.entity.as_str()).collect::<Vec<_>>(), ["10.0.0.3", "10.0.0.4", "10.0.0.5"]);
+}
+
+#[test]
+fn many_distinct_entities_stay_bounded() {
+    let c = EntityCounters::new(16, Duration::from_secs(1));
+    let t0 = Instant::now();
+    for i in 0..10_000u64 {
+        c.record_at(&format!("198.51.100.{}", i % 5000), "opened", t0 + Duration::from_millis(i));
+    }
+    let r = c.report_at(t0 + Duration::from_secs(10));
+    assert_eq!(r.entities.len(), 16);
+    assert_eq!(r.evicted, 10_000 - 16);
+    assert_eq!(c.top(2).len(), 2);
+}
+
+#[test]
+fn prom_renders_entity_counters() {
+    let c = EntityCounters::default();
+    c.record("/data", "MOUNTED");
+    c.record("/data", "CHANGED");
+    c.record("/data", "CHANGED");
+    let prom = PromTextfile::new("/nonexistent/x.prom").entity_counters("xmount", c);
+
+    let text = prom.render();
+    assert!(text.contains("omnitrace_entity_events_total{sensor=\"xmount\",entity=\"/data\",kind=\"changed\"} 2"), "{text}");
+    assert!(text.contains("omnitrace_entity_event_rate{sensor=\"xmount\",entity=\"/data\"} 0.049"), "{text}");
+    assert!(text.contains("omnitrace_entity_evictions_total{sensor=\"xmount\"} 0"), "{text}");
+}
@@ -0,0 +1,114 @@
+//! Sensor errors, and where the ones a sensor runs past go.
+//!
+//! Each sensor crate has its own error enum (`XMountError`, `NetNotifyError`, `FileScreamError`,
+//! `ProcDogError`) that converts into the [`SensorError`] umbrella. Errors a sensor cannot return
+//! because it keeps polling (an unreadable table, a malformed line, a failed listing) are
+//! recorded in its [`Diagnostics`]: logged, counted per key and kept for debug dumps, instead of
+//! being dropped on the floor.
+
+use crate::debug::Debuggable;
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    io,
+    sync::{Arc, Mutex},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SensorError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[cfg(feature = "runtime")]
+    #[error(transparent)]
+    Time(#[from] crate::pulse::TimeAnomaly),
+    /// A sensor's own error, e.g. a `netpacket::error::NetNotifyError`.
+    #[error("{sensor}: {source}")]
+    Sensor {
+        sensor: &'static str,
+        #[source]
+        source: Box<dyn Error + Send + Sync>,
+    },
+}
+
+impl SensorError {
+    pub fn sensor<E: Error + Send + Sync + 'static>(sensor: &'static str, err: E) -> Self {
+        SensorError::Sensor { sensor, source: Box::new(err) }
+    }
+
+    /// The sensor's own error (or the [`crate::pulse::TimeAnomaly`]), if it is an `E`.
+    pub fn downcast_ref<E: Error + 'static>(&self) -> Option<&E> {
+        match self {
+            SensorError::Sensor { source, .. } => source.downcast_ref(),
+            #[cfg(feature = "runtime")]
+            SensorError::Time(t) => (t as &dyn Error).downcast_ref(),
+            SensorError::Io(_) => None,
+        }
+    }
+}
+
+/// One kind of recurring error, see [`Diagnostics`].
+#[derive(Clone, Debug, Serialize)]
+pub struct Diagnostic {
+    pub key: String,
+    /// Occurrences since the key was last cleared.
+    pub count: u64,
+    pub message: String,
+    #[serde(skip)]
+    pub last: Arc<SensorError>,
+}
+
+/// Errors a sensor recovered from, by key (e.g. `"table:tcp6"`). Cheap to clone.
+#[derive(Clone, Debug, Default)]
+pub struct Diagnostics(Arc<Mutex<BTreeMap<String, Diagnostic>>>);
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record and log an error that is worth a line every time, e.g. a read that failed.
+    pub fn report<E: Into<SensorError>>(&self, key: &str, err: E) {
+        let d = self.record(key, err.into());
+        log::warn!("{}", d.message);
+    }
+
+    /// Record an error, but log it only the first time since [`Diagnostics::clear`]: for
+    /// conditions that persist and are often fine, e.g. no IPv6 table on a v4-only kernel.
+    pub fn report_once<E: Into<SensorError>>(&self, key: &str, err: E) {
+        let d = self.record(key, err.into());
+        if d.count == 1 {
+            log::warn!("{}", d.message);
+        }
+    }
+
+    fn record(&self, key: &str, err: SensorError) -> Diagnostic {
+        let mut map = self.0.lock().unwrap_or_else(|p| p.into_inner());
+        let (message, last) = (err.to_string(), Arc::new(err));
+        let count = map.get(key).map_or(0, |d| d.count) + 1;
+        let d = Diagnostic { key: key.to_string(), count, message, last };
+        map.insert(key.to_string(), d.clone());
+        d
+    }
+
+    /// Forget `key`, e.g. once the file is back. Returns false if nothing was recorded.
+    pub fn clear(&self, key: &str) -> bool {
+        self.0.lock().unwrap_or_else(|p| p.into_inner()).remove(key).is_some()
+    }
+
+    pub fn get(&self, key: &str) -> Option<Diagnostic> {
+        self.0.lock().unwrap_or_else(|p| p.into_inner()).get(key).cloned()
+    }
+
+    /// Everything recorded, by key.
+    pub fn all(&self) -> Vec<Diagnostic> {
+        self.0.lock().unwrap_or_else(|p| p.into_inner()).values().cloned().collect()
+    }
+}
+
+impl Debuggable for Diagnostics {
+    fn debug_snapshot(&self) -> Value {
+        serde_json::to_value(self.all()).unwrap_or_default()
+    }
+}
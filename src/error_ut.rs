@@ -0,0 +1,44 @@
+use crate::error::{Diagnostics, SensorError};
+use std::io;
+
+#[derive(Debug, thiserror::Error)]
+enum ProbeError {
+    #[error("probe {0} failed")]
+    Failed(u32),
+}
+
+impl From<ProbeError> for SensorError {
+    fn from(e: ProbeError) -> Self {
+        SensorError::sensor("probe", e)
+    }
+}
+
+#[test]
+fn sensor_errors_keep_their_type() {
+    let e: SensorError = ProbeError::Failed(7).into();
+    assert_eq!(e.to_string(), "probe: probe 7 failed");
+    assert!(matches!(e.downcast_ref::<ProbeError>(), Some(ProbeError::Failed(7))));
+    assert!(e.downcast_ref::<io::Error>().is_none());
+
+    let io: SensorError = io::Error::from(io::ErrorKind::NotFound).into();
+    assert!(io.downcast_ref::<ProbeError>().is_none());
+}
+
+#[test]
+fn diagnostics_count_per_key_until_cleared() {
+    let d = Diagnostics::new();
+    d.report_once("missing", ProbeError::Failed(1));
+    d.report_once("missing", ProbeError::Failed(2));
+    d.report("read", io::Error::other("boom"));
+
+    let missing = d.get("missing").unwrap();
+    assert_eq!((missing.count, missing.message.as_str()), (2, "probe: probe 2 failed"));
+    assert!(matches!(missing.last.downcast_ref::<ProbeError>(), Some(ProbeError::Failed(2))));
+    assert_eq!(d.all().iter().map(|x| x.key.as_str()).collect::<Vec<_>>(), ["missing", "read"]);
+
+    // shared between clones, like the handles sensors hand out
+    assert!(d.clone().clear("missing"));
+    assert!(!d.clear("missing"));
+    assert!(d.get("missing").is_none());
+    assert_eq!(crate::debug::Debuggable::debug_snapshot(&d)[0]["message"], "boom");
+}
@@ -0,0 +1,52 @@
+//! Declared state for sensors to prime against, e.g. from a configuration management system
+//! that knows what should be mounted, running and on disk.
+//!
+//! Each sensor takes its own specs (`xmount::expected::ExpectedMount`,
+//! `procdog::expected::ExpectedProcess`, `filescream::expected::ExpectedFiles`), plain serde
+//! structs an agent loads with [`load`]. When the sensor primes it compares what it finds with
+//! them and fires a `Deviation` event per difference, before any change is reported; from then
+//! on it detects changes as usual.
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{fmt, io, path::Path};
+
+/// How the state found differs from the declared one.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "deviation", rename_all = "snake_case")]
+pub enum Deviation {
+    /// Declared, but not there.
+    Missing,
+    /// There, but declared absent, or not declared where the declaration is exhaustive.
+    Unexpected,
+    /// There, with `field` other than declared.
+    Mismatch { field: String, expected: String, actual: String },
+}
+
+impl Deviation {
+    pub fn mismatch<F: Into<String>, E: Into<String>, A: Into<String>>(field: F, expected: E, actual: A) -> Self {
+        Deviation::Mismatch { field: field.into(), expected: expected.into(), actual: actual.into() }
+    }
+
+    /// `"missing"`, `"unexpected"` or `"mismatch"`, the last part of the sensors' deviation topics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Deviation::Missing => "missing",
+            Deviation::Unexpected => "unexpected",
+            Deviation::Mismatch { .. } => "mismatch",
+        }
+    }
+}
+
+impl fmt::Display for Deviation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Deviation::Mismatch { field, expected, actual } => write!(f, "{field} is {actual:?}, expected {expected:?}"),
+            other => f.write_str(other.name()),
+        }
+    }
+}
+
+/// Read specs from a JSON file, e.g. `load::<Vec<ExpectedMount>>(path)`.
+pub fn load<T: DeserializeOwned>(path: &Path) -> io::Result<T> {
+    serde_json::from_slice(&std::fs::read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
@@ -0,0 +1,25 @@
+use crate::expected::{self, Deviation};
+use serde_json::json;
+use std::io;
+
+#[test]
+fn deviation_is_tagged_for_flattening() {
+    assert_eq!(serde_json::to_value(Deviation::Missing).unwrap(), json!({ "deviation": "missing" }));
+    let mismatch = Deviation::mismatch("fstype", "xfs", "ext4");
+    let v = serde_json::to_value(&mismatch).unwrap();
+    assert_eq!(v, json!({ "deviation": "mismatch", "field": "fstype", "expected": "xfs", "actual": "ext4" }));
+    assert_eq!(serde_json::from_value::<Deviation>(v).unwrap(), mismatch);
+    assert_eq!(mismatch.name(), "mismatch");
+    assert_eq!(mismatch.to_string(), r#"fstype is "ext4", expected "xfs""#);
+    assert_eq!(Deviation::Unexpected.to_string(), "unexpected");
+}
+
+#[test]
+fn bad_spec_file_is_invalid_data() {
+    let path = std::env::temp_dir().join(format!("omnitrace-expected-ut-{}.json", std::process::id()));
+    std::fs::write(&path, "[{").unwrap();
+    let err = expected::load::<Vec<String>>(&path).unwrap_err();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(expected::load::<Vec<String>>(&path).unwrap_err().kind(), io::ErrorKind::NotFound);
+}
@@ -0,0 +1,145 @@
+//! Looking inside events without serializing them.
+//!
+//! Generic layers (the [`crate::router::Router`], [`crate::severity::SeverityMapper`] rules,
+//! [`crate::filter::Filter`]) only need a few fields of an event to decide what to do with it.
+//! An [`EventFields`] event hands them out by name, so the event is serialized only once a
+//! sink actually receives it, or not at all when it is dropped.
+//!
+//! Field names are those of the serialized event, inside the variant: `"target"`, `"pid"`,
+//! `"info.fstype"`. Fields of a nested object are found under their own name too (`"fstype"`),
+//! and socket addresses expose `.ip` and `.port` (`"remote.ip"`). Each sensor documents its
+//! names on its event type, and [`EventFields::field_names`] lists them per kind.
+
+use serde_json::Value;
+use std::{borrow::Cow, net::SocketAddr, path::Path};
+
+/// A field value, borrowed from the event where possible.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FieldValue<'a> {
+    Str(Cow<'a, str>),
+    Int(i64),
+    Path(&'a Path),
+}
+
+impl<'a> FieldValue<'a> {
+    pub fn str<S: Into<Cow<'a, str>>>(s: S) -> Self {
+        FieldValue::Str(s.into())
+    }
+
+    pub fn int<N: Into<i64>>(n: N) -> Self {
+        FieldValue::Int(n.into())
+    }
+
+    /// A `u64` counter; the rare value beyond `i64::MAX` saturates.
+    pub fn count(n: u64) -> Self {
+        FieldValue::Int(i64::try_from(n).unwrap_or(i64::MAX))
+    }
+
+    /// Text of strings and paths (lossy for non-UTF-8 paths).
+    pub fn as_str(&self) -> Option<Cow<'_, str>> {
+        match self {
+            FieldValue::Str(s) => Some(Cow::Borrowed(s)),
+            FieldValue::Path(p) => Some(p.to_string_lossy()),
+            FieldValue::Int(_) => None,
+        }
+    }
+
+    /// The value as it serializes, for comparing with JSON rule values.
+    pub fn to_json(&self) -> Value {
+        match self {
+            FieldValue::Str(s) => Value::String(s.to_string()),
+            FieldValue::Int(n) => Value::from(*n),
+            FieldValue::Path(p) => Value::String(p.to_string_lossy().into_owned()),
+        }
+    }
+}
+
+impl From<bool> for FieldValue<'_> {
+    fn from(b: bool) -> Self {
+        FieldValue::Int(b.into())
+    }
+}
+
+/// Dynamic access to an event's kind and fields, see the [module docs](self).
+pub trait EventFields {
+    /// Variant in snake case, e.g. `"mounted"` or `"will_unmount"`.
+    fn kind(&self) -> &'static str;
+
+    /// Field by its dotted name, None if this kind has no such field or it is unset.
+    fn field(&self, name: &str) -> Option<FieldValue<'_>>;
+
+    /// The names [`EventFields::field`] knows for this event's kind.
+    fn field_names(&self) -> &'static [&'static str];
+
+    /// The fields [`crate::format::format_short`] shows, in order: what happened to what.
+    /// [`EventFields::detail_fields`] by default.
+    fn summary_fields(&self) -> &'static [&'static str] {
+        self.detail_fields()
+    }
+
+    /// The fields [`crate::format::format_long`] shows, in order; `a|b` is the first of them
+    /// that is set. [`EventFields::field_names`] by default, which may list aliases.
+    fn detail_fields(&self) -> &'static [&'static str] {
+        self.field_names()
+    }
+
+    /// Dotted topic, e.g. `"mount.changed.remount"`, for types implementing
+    /// [`crate::topics::Topics`]. Rules and filters match `topic` patterns on it.
+    fn topic(&self) -> Option<&'static str> {
+        None
+    }
+}
+
+/// Whether `kind` (as returned by [`EventFields::kind`]) names the variant `name`, in either
+/// spelling: `"will_unmount"` is `"WillUnmount"`.
+pub fn same_kind(kind: &str, name: &str) -> bool {
+    let mut a = kind.chars().filter(|c| *c != '_');
+    let mut b = name.chars().filter(|c| *c != '_');
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return true,
+            (Some(x), Some(y)) if x.eq_ignore_ascii_case(&y) => {}
+            _ => return false,
+        }
+    }
+}
+
+/// `"will_unmount"` as `"WillUnmount"`, the variant name of the serialized event.
+pub fn variant_name(kind: &str) -> String {
+    kind.split('_')
+        .map(|w| {
+            let mut c = w.chars();
+            c.next().map(|f| f.to_ascii_uppercase().to_string() + c.as_str()).unwrap_or_default()
+        })
+        .collect()
+}
+
+/// [`EventFields::field`], with `.ip` and `.port` of fields holding a socket address.
+pub fn get<'a>(ev: &'a dyn EventFields, name: &str) -> Option<FieldValue<'a>> {
+    if let Some(v) = ev.field(name) {
+        return Some(v);
+    }
+    let (base, part) = name.rsplit_once('.')?;
+    let addr: SocketAddr = ev.field(base)?.as_str()?.parse().ok()?;
+    match part {
+        "ip" => Some(FieldValue::str(addr.ip().to_string())),
+        "port" => Some(FieldValue::int(addr.port())),
+        _ => None,
+    }
+}
+
+/// A dotted path (`"Opened.conn.proto"`) as a JSON pointer into the serialized event, each
+/// segment escaped as RFC 6901 wants, so `~` and `/` in a key match as written.
+pub fn pointer(path: &str) -> String {
+    path.split('.').map(|seg| format!("/{}", seg.replace('~', "~0").replace('/', "~1"))).collect()
+}
+
+/// A field addressed from the top of the serialized event, as rules do:
+/// `"Unmounted.target"` is the target of Unmounted events, and nothing for other kinds.
+pub fn get_in_variant<'a>(ev: &'a dyn EventFields, path: &str) -> Option<FieldValue<'a>> {
+    let (variant, name) = path.split_once('.')?;
+    if !same_kind(ev.kind(), variant) {
+        return None;
+    }
+    get(ev, name)
+}
@@ -0,0 +1,64 @@
+use crate::fields::{self, EventFields, FieldValue};
+use std::path::Path;
+
+struct Conn {
+    remote: String,
+    path: &'static Path,
+}
+
+impl EventFields for Conn {
+    fn kind(&self) -> &'static str {
+        "watermark_exceeded"
+    }
+
+    fn field(&self, name: &str) -> Option<FieldValue<'_>> {
+        match name {
+            "remote" => Some(FieldValue::str(&self.remote)),
+            "path" => Some(FieldValue::Path(self.path)),
+            _ => None,
+        }
+    }
+
+    fn field_names(&self) -> &'static [&'static str] {
+        &["remote", "path"]
+    }
+}
+
+#[test]
+fn kinds_match_either_spelling() {
+    assert!(fields::same_kind("will_unmount", "WillUnmount"));
+    assert!(fields::same_kind("mounted", "Mounted"));
+    assert!(!fields::same_kind("mounted", "Unmounted"));
+    assert!(!fields::same_kind("opened", "Open"));
+    assert_eq!(fields::variant_name("watermark_exceeded"), "WatermarkExceeded");
+    assert_eq!(fields::variant_name("opened"), "Opened");
+}
+
+#[test]
+fn paths_become_escaped_pointers() {
+    assert_eq!(fields::pointer("Opened.conn.proto"), "/Opened/conn/proto");
+    assert_eq!(fields::pointer("Changed.a/b.~c"), "/Changed/a~1b/~0c");
+    // `~` first, or `/` would come out as `~01`
+    assert_eq!(fields::pointer("x.~/"), "/x/~0~1");
+}
+
+#[test]
+fn socket_fields_split_into_ip_and_port() {
+    let ev = Conn { remote: "[2001:db8::1]:443".to_string(), path: Path::new("/srv/a") };
+    assert_eq!(fields::get(&ev, "remote.ip"), Some(FieldValue::str("2001:db8::1")));
+    assert_eq!(fields::get(&ev, "remote.port"), Some(FieldValue::int(443)));
+    assert_eq!(fields::get(&ev, "remote.host"), None);
+    assert_eq!(fields::get(&ev, "path.ip"), None, "not an address");
+
+    assert_eq!(fields::get_in_variant(&ev, "WatermarkExceeded.remote.port"), Some(FieldValue::int(443)));
+    assert_eq!(fields::get_in_variant(&ev, "WatermarkCleared.remote.port"), None);
+    assert_eq!(fields::get(&ev, "path").unwrap().to_json(), "/srv/a");
+}
+
+#[test]
+fn values_convert() {
+    assert_eq!(FieldValue::count(u64::MAX), FieldValue::Int(i64::MAX));
+    assert_eq!(FieldValue::from(true), FieldValue::Int(1));
+    assert_eq!(FieldValue::int(7u16).as_str(), None);
+    assert_eq!(FieldValue::Path(Path::new("/a")).as_str().as_deref(), Some("/a"));
+}
@@ -0,0 +1,576 @@
+//! A small filter expression language over serialized events, shared by the CLIs' `--filter`.
+//!
+//! ```text
+//! fstype == "nfs" && target ~ "/mnt/*"
+//! remote_addr.port == 443 || remote_host ~ "*.corp"
+//! kind == "Appeared" && !(name ~ "kworker*")
+//! ```
+//!
+//! Identifiers are dotted paths into the event as serialized to JSON, starting inside the
+//! variant (`{"Mounted": {"target": ..}}` is matched as `target`, the variant name is `kind`).
+//! A path not found there is looked up in nested objects, breadth first, so `fstype` finds
+//! `info.fstype`. Strings holding a socket address have `.ip` and `.port`.
+//!
+//! Operators: `==`, `!=`, `~` / `!~` (glob, string pattern only), `<`, `<=`, `>`, `>=`
+//! (numbers), `&&`, `||`, `!` and parentheses. Literals are `"strings"`, numbers, `true` and
+//! `false`. Numbers may carry a size unit (`rx_bytes_per_sec > 8MiB`, see [`crate::units`]). A bare identifier is true if the field is set and not `false`, `0`, `""` or null.
+//! Comparisons against a missing field are false (so `!(x == "a")` is not `x != "a"`), and
+//! against an array, true if any element matches.
+//!
+//! [`Filter::predicate`] evaluates typed events through their [`EventFields`] instead, with
+//! the names documented there (`fstype`, `remote.ip`, `pid`), and without serializing them.
+//! `kind` compares equal in either spelling there and here (`"Appeared"`, `"appeared"`).
+//! Typed events also have `topic` (see [`crate::topics`]), where `~` selects child topics
+//! too: `topic ~ "mount.changed"` matches `mount.changed.remount`.
+
+use crate::{
+    fields::{self, EventFields},
+    topics, units,
+};
+use globset::{Glob, GlobMatcher};
+use serde::Serialize;
+use serde_json::Value;
+use std::{collections::VecDeque, fmt, net::SocketAddr, sync::Arc};
+
+/// A parse error, displayed with the expression and a caret under the offending position.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("{message} at column {}\n  {input}\n  {}^", pos + 1, " ".repeat(*pos))]
+pub struct FilterError {
+    pub input: String,
+    /// Character offset into `input`.
+    pub pos: usize,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Glob,
+    NotGlob,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug)]
+enum Literal {
+    Str(String),
+    Num(f64),
+    Bool(bool),
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Truthy(FieldPath),
+    Cmp(FieldPath, Op, Literal),
+    Glob(FieldPath, GlobMatcher, bool),
+}
+
+#[derive(Clone, Debug)]
+struct FieldPath {
+    segs: Vec<String>,
+    // as written, for EventFields::field
+    dotted: String,
+}
+
+impl FieldPath {
+    fn is_kind(&self) -> bool {
+        self.dotted == "kind"
+    }
+
+    fn is_topic(&self) -> bool {
+        self.dotted == "topic"
+    }
+}
+
+/// A compiled filter expression. Cheap to clone.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    source: Arc<str>,
+    expr: Arc<Expr>,
+}
+
+impl Filter {
+    pub fn parse(input: &str) -> Result<Self, FilterError> {
+        let tokens = lex(input)?;
+        let mut p = Parser { input, tokens, at: 0 };
+        let expr = p.or()?;
+        if let Some(t) = p.peek() {
+            return Err(p.error(t.pos, format!("unexpected {} after the expression", t.kind)));
+        }
+        Ok(Self { source: input.into(), expr: Arc::new(expr) })
+    }
+
+    /// The expression as given.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Evaluate against an already serialized event.
+    pub fn matches(&self, ev: &Value) -> bool {
+        eval(&self.expr, &Event::new(ev))
+    }
+
+    /// Serialize `ev` and evaluate. Events that fail to serialize do not match.
+    pub fn matches_event<E: Serialize>(&self, ev: &E) -> bool {
+        serde_json::to_value(ev).is_ok_and(|v| self.matches(&v))
+    }
+
+    /// Evaluate against the fields of a typed event, see [`crate::fields`].
+    pub fn matches_fields<E: EventFields>(&self, ev: &E) -> bool {
+        eval(&self.expr, &Typed(ev))
+    }
+
+    /// Predicate for [`crate::callbacks::CallbackHub::set_filter`] or `add_filtered`, over
+    /// [`Filter::matches_fields`].
+    pub fn predicate<E: EventFields>(&self) -> impl Fn(&E) -> bool + Send + Sync + 'static {
+        let f = self.clone();
+        move |ev| f.matches_fields(ev)
+    }
+
+    /// The `--filter <expr>` (or `--filter=<expr>`) of a command line, if given.
+    pub fn from_args<I, S>(args: I) -> Result<Option<Self>, FilterError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut args = args.into_iter();
+        while let Some(a) = args.next() {
+            let a = a.as_ref();
+            if let Some(expr) = a.strip_prefix("--filter=") {
+                return Self::parse(expr).map(Some);
+            }
+            if a == "--filter" {
+                let Some(expr) = args.next() else {
+                    return Err(FilterError { input: a.to_string(), pos: a.len(), message: "--filter needs an expression".to_string() });
+                };
+                return Self::parse(expr.as_ref()).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// [`Filter::from_args`] on the process arguments, for a CLI's `main`: prints the
+    /// error and exits with status 2 if the expression does not parse.
+    pub fn from_env_args() -> Option<Self> {
+        match Self::from_args(std::env::args().skip(1)) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("invalid --filter: {e}");
+                std::process::exit(2);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+// ---- lexer ----
+
+#[derive(Clone, Debug, PartialEq)]
+enum Kind {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Ident(s) => write!(f, "`{s}`"),
+            Kind::Str(s) => write!(f, "\"{s}\""),
+            Kind::Num(n) => write!(f, "`{n}`"),
+            Kind::Op(op) => write!(f, "`{}`", op_str(*op)),
+            Kind::And => f.write_str("`&&`"),
+            Kind::Or => f.write_str("`||`"),
+            Kind::Not => f.write_str("`!`"),
+            Kind::Open => f.write_str("`(`"),
+            Kind::Close => f.write_str("`)`"),
+        }
+    }
+}
+
+fn op_str(op: Op) -> &'static str {
+    match op {
+        Op::Eq => "==",
+        Op::Ne => "!=",
+        Op::Glob => "~",
+        Op::NotGlob => "!~",
+        Op::Lt => "<",
+        Op::Le => "<=",
+        Op::Gt => ">",
+        Op::Ge => ">=",
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: Kind,
+    /// Character offset
+    pos: usize,
+}
+
+fn lex(input: &str) -> Result<Vec<Token>, FilterError> {
+    let err = |pos: usize, message: String| FilterError { input: input.to_string(), pos, message };
+    let chars: Vec<char> = input.chars().collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let pos = i;
+        let (kind, len) = match (c, next) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('&', Some('&')) => (Kind::And, 2),
+            ('|', Some('|')) => (Kind::Or, 2),
+            ('=', Some('=')) => (Kind::Op(Op::Eq), 2),
+            ('!', Some('=')) => (Kind::Op(Op::Ne), 2),
+            ('!', Some('~')) => (Kind::Op(Op::NotGlob), 2),
+            ('<', Some('=')) => (Kind::Op(Op::Le), 2),
+            ('>', Some('=')) => (Kind::Op(Op::Ge), 2),
+            ('&', _) => return Err(err(pos, "expected `&&`".to_string())),
+            ('|', _) => return Err(err(pos, "expected `||`".to_string())),
+            ('=', _) => return Err(err(pos, "expected `==`".to_string())),
+            ('!', _) => (Kind::Not, 1),
+            ('~', _) => (Kind::Op(Op::Glob), 1),
+            ('<', _) => (Kind::Op(Op::Lt), 1),
+            ('>', _) => (Kind::Op(Op::Gt), 1),
+            ('(', _) => (Kind::Open, 1),
+            (')', _) => (Kind::Close, 1),
+            ('"', _) => {
+                let mut s = String::new();
+                let mut j = i + 1;
+                loop {
+                    match chars.get(j) {
+                        None => return Err(err(pos, "unterminated string".to_string())),
+                        Some('"') => break,
+                        Some('\\') => match chars.get(j + 1) {
+                            Some(e @ ('"' | '\\')) => {
+                                s.push(*e);
+                                j += 1;
+                            }
+                            _ => return Err(err(j, "unknown escape, only \\\" and \\\\ are supported".to_string())),
+                        },
+                        Some(c) => s.push(*c),
+                    }
+                    j += 1;
+                }
+                (Kind::Str(s), j + 1 - i)
+            }
+            (c, _) if c.is_ascii_digit() || (c == '-' && next.is_some_and(|n| n.is_ascii_digit())) => {
+                let len = chars[i + 1..].iter().take_while(|c| c.is_ascii_digit() || **c == '.').count() + 1;
+                let unit = chars[i + len..].iter().take_while(|c| c.is_alphabetic()).count();
+                let text: String = chars[i..i + len + unit].iter().collect();
+                if unit > 0 {
+                    match units::parse_size(&text) {
+                        Ok(n) => (Kind::Num(n as f64), len + unit),
+                        Err(e) => return Err(err(pos + e.pos, format!("invalid size `{text}`: {}", e.message))),
+                    }
+                } else {
+                    match text.parse() {
+                        Ok(n) => (Kind::Num(n), len),
+                        Err(_) => return Err(err(pos, format!("invalid number `{text}`"))),
+                    }
+                }
+            }
+            (c, _) if c.is_alphabetic() || c == '_' => {
+                let len = chars[i..].iter().take_while(|c| c.is_alphanumeric() || **c == '_' || **c == '.').count();
+                let text: String = chars[i..i + len].iter().collect();
+                if text.ends_with('.') || text.contains("..") {
+                    return Err(err(pos, format!("invalid field path `{text}`")));
+                }
+                (Kind::Ident(text), len)
+            }
+            (c, _) => return Err(err(pos, format!("unexpected character `{c}`"))),
+        };
+        out.push(Token { kind, pos });
+        i += len;
+    }
+    Ok(out)
+}
+
+// ---- parser ----
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<Token>,
+    at: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, pos: usize, message: String) -> FilterError {
+        FilterError { input: self.input.to_string(), pos, message }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at)
+    }
+
+    /// Position of the next token, or the end of the input.
+    fn here(&self) -> usize {
+        self.peek().map_or(self.input.chars().count(), |t| t.pos)
+    }
+
+    fn eat(&mut self, kind: &Kind) -> bool {
+        if self.peek().is_some_and(|t| &t.kind == kind) {
+            self.at += 1;
+            return true;
+        }
+        false
+    }
+
+    fn or(&mut self) -> Result<Expr, FilterError> {
+        let mut left = self.and()?;
+        while self.eat(&Kind::Or) {
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, FilterError> {
+        let mut left = self.unary()?;
+        while self.eat(&Kind::And) {
+            left = Expr::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, FilterError> {
+        if self.eat(&Kind::Not) {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        let Some(t) = self.peek().cloned() else {
+            return Err(self.error(self.here(), "expected an expression".to_string()));
+        };
+        self.at += 1;
+        match t.kind {
+            Kind::Open => {
+                let e = self.or()?;
+                if !self.eat(&Kind::Close) {
+                    return Err(self.error(self.here(), format!("expected `)` to close the `(` at column {}", t.pos + 1)));
+                }
+                Ok(e)
+            }
+            Kind::Ident(name) => self.comparison(FieldPath { segs: name.split('.').map(str::to_string).collect(), dotted: name }),
+            other => Err(self.error(t.pos, format!("expected a field name, found {other}"))),
+        }
+    }
+
+    fn comparison(&mut self, path: FieldPath) -> Result<Expr, FilterError> {
+        let Some(Token { kind: Kind::Op(op), pos: op_pos }) = self.peek().cloned() else {
+            return Ok(Expr::Truthy(path));
+        };
+        self.at += 1;
+        let Some(t) = self.peek().cloned() else {
+            return Err(self.error(self.here(), format!("expected a value after `{}`", op_str(op))));
+        };
+        self.at += 1;
+        let lit = match t.kind {
+            Kind::Str(s) => Literal::Str(s),
+            Kind::Num(n) => Literal::Num(n),
+            Kind::Ident(b) if b == "true" || b == "false" => Literal::Bool(b == "true"),
+            other => return Err(self.error(t.pos, format!("expected a value after `{}`, found {other}", op_str(op)))),
+        };
+
+        match (op, lit) {
+            (Op::Glob | Op::NotGlob, Literal::Str(pat)) => match Glob::new(&pat) {
+                Ok(g) => Ok(Expr::Glob(path, g.compile_matcher(), op == Op::Glob)),
+                Err(e) => Err(self.error(t.pos, format!("invalid glob pattern: {}", e.kind()))),
+            },
+            (Op::Glob | Op::NotGlob, _) => Err(self.error(t.pos, format!("`{}` needs a string pattern", op_str(op)))),
+            (Op::Lt | Op::Le | Op::Gt | Op::Ge, l @ Literal::Num(_)) => Ok(Expr::Cmp(path, op, l)),
+            (Op::Lt | Op::Le | Op::Gt | Op::Ge, _) => Err(self.error(op_pos, format!("`{}` compares numbers only", op_str(op)))),
+            (op, l) => Ok(Expr::Cmp(path, op, l)),
+        }
+    }
+}
+
+// ---- evaluation ----
+
+/// Where an expression's fields come from.
+trait Resolve {
+    fn resolve(&self, path: &FieldPath) -> Option<Value>;
+}
+
+/// A serialized event split into its variant name and body.
+struct Event<'a> {
+    kind: Option<&'a str>,
+    body: &'a Value,
+}
+
+impl<'a> Event<'a> {
+    fn new(v: &'a Value) -> Self {
+        if let Value::Object(map) = v
+            && map.len() == 1
+            && let Some((kind, body)) = map.iter().next()
+            && body.is_object()
+        {
+            return Self { kind: Some(kind), body };
+        }
+        Self { kind: None, body: v }
+    }
+
+    fn find(&self, path: &[String]) -> Option<Value> {
+        if let Some(v) = lookup(self.body, path) {
+            return Some(v);
+        }
+        if path.len() == 1
+            && path[0] == "kind"
+            && let Some(kind) = self.kind
+        {
+            return Some(Value::String(kind.to_string()));
+        }
+
+        let mut queue: VecDeque<&Value> = VecDeque::from([self.body]);
+        while let Some(v) = queue.pop_front() {
+            if let Some(found) = lookup(v, path) {
+                return Some(found);
+            }
+            if let Value::Object(map) = v {
+                queue.extend(map.values().filter(|c| c.is_object()));
+            }
+        }
+        None
+    }
+}
+
+impl Resolve for Event<'_> {
+    fn resolve(&self, path: &FieldPath) -> Option<Value> {
+        self.find(&path.segs)
+    }
+}
+
+/// A typed event, see [`Filter::matches_fields`].
+struct Typed<'a, E>(&'a E);
+
+impl<E: EventFields> Resolve for Typed<'_, E> {
+    fn resolve(&self, path: &FieldPath) -> Option<Value> {
+        match fields::get(self.0, &path.dotted) {
+            Some(v) => Some(v.to_json()),
+            None if path.is_kind() => Some(Value::String(fields::variant_name(self.0.kind()))),
+            None if path.is_topic() => self.0.topic().map(|t| Value::String(t.to_string())),
+            None => None,
+        }
+    }
+}
+
+fn lookup(v: &Value, path: &[String]) -> Option<Value> {
+    let mut cur = v;
+    for (i, seg) in path.iter().enumerate() {
+        cur = match cur {
+            Value::Object(map) => map.get(seg)?,
+            Value::Array(items) => items.get(seg.parse::<usize>().ok()?)?,
+            Value::String(s) if i + 1 == path.len() => return socket_part(s, seg),
+            _ => return None,
+        };
+    }
+    Some(cur.clone())
+}
+
+/// `.ip` or `.port` of a string holding a socket address.
+fn socket_part(s: &str, part: &str) -> Option<Value> {
+    let addr: SocketAddr = s.parse().ok()?;
+    match part {
+        "ip" => Some(Value::String(addr.ip().to_string())),
+        "port" => Some(Value::from(addr.port())),
+        _ => None,
+    }
+}
+
+/// Scalars as text; non-UTF-8 paths (see [`crate::paths`]) as their lossy form.
+fn text(v: &Value) -> Option<String> {
+    match v {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Object(map) => map.get("lossy").and_then(Value::as_str).map(str::to_string),
+        _ => None,
+    }
+}
+
+fn number(v: &Value) -> Option<f64> {
+    match v {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn truthy(v: &Value) -> bool {
+    match v {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(_) => true,
+    }
+}
+
+fn compare(v: &Value, op: Op, lit: &Literal) -> bool {
+    if let Value::Array(items) = v {
+        return items.iter().any(|i| compare(i, op, lit));
+    }
+    let eq = match lit {
+        Literal::Str(s) => text(v).is_some_and(|t| &t == s),
+        Literal::Num(n) => number(v) == Some(*n),
+        Literal::Bool(b) => v.as_bool() == Some(*b),
+    };
+    let ord = |f: fn(f64, f64) -> bool| match lit {
+        Literal::Num(n) => number(v).is_some_and(|x| f(x, *n)),
+        _ => false,
+    };
+    match op {
+        Op::Eq => eq,
+        Op::Ne => !v.is_null() && !eq,
+        Op::Lt => ord(|a, b| a < b),
+        Op::Le => ord(|a, b| a <= b),
+        Op::Gt => ord(|a, b| a > b),
+        Op::Ge => ord(|a, b| a >= b),
+        Op::Glob | Op::NotGlob => false,
+    }
+}
+
+fn glob(v: &Value, m: &GlobMatcher, want: bool) -> bool {
+    if let Value::Array(items) = v {
+        return items.iter().any(|i| glob(i, m, want));
+    }
+    text(v).is_some_and(|t| m.is_match(t) == want)
+}
+
+fn eval(e: &Expr, ev: &dyn Resolve) -> bool {
+    match e {
+        Expr::Or(a, b) => eval(a, ev) || eval(b, ev),
+        Expr::And(a, b) => eval(a, ev) && eval(b, ev),
+        Expr::Not(a) => !eval(a, ev),
+        Expr::Truthy(path) => ev.resolve(path).is_some_and(|v| truthy(&v)),
+        // the variant name, or the kind of a typed event
+        Expr::Cmp(path, op @ (Op::Eq | Op::Ne), Literal::Str(s)) if path.is_kind() => {
+            ev.resolve(path).and_then(|v| v.as_str().map(|k| fields::same_kind(k, s) == (*op == Op::Eq))).unwrap_or(false)
+        }
+        Expr::Cmp(path, op, lit) => ev.resolve(path).is_some_and(|v| compare(&v, *op, lit)),
+        Expr::Glob(path, m, want) if path.is_topic() => {
+            ev.resolve(path).and_then(|v| v.as_str().map(|t| topics::glob_matches(m, t) == *want)).unwrap_or(false)
+        }
+        Expr::Glob(path, m, want) => ev.resolve(path).is_some_and(|v| glob(&v, m, *want)),
+    }
+}
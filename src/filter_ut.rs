@@ -0,0 +1,149 @@
+use crate::filter::Filter;
+use serde_json::{Value, json};
+
+fn mounted(target: &str, fstype: &str) -> Value {
+    json!({ "Mounted": { "target": target, "info": { "fstype": fstype, "source": "srv:/export", "mount_id": 41 } } })
+}
+
+fn opened(remote: &str, host: Option<&str>) -> Value {
+    json!({ "Opened": { "conn": { "proto": "tcp", "remote_addr": remote, "remote_host": host, "state_dec": "ESTABLISHED" }, "offline": false } })
+}
+
+fn matches(expr: &str, ev: &Value) -> bool {
+    Filter::parse(expr).unwrap_or_else(|e| panic!("{e}")).matches(ev)
+}
+
+#[test]
+fn fields_resolve_inside_the_variant_and_nested_objects() {
+    let ev = mounted("/mnt/data", "nfs");
+    assert!(matches(r#"target == "/mnt/data""#, &ev));
+    assert!(matches(r#"fstype == "nfs""#, &ev), "found under info");
+    assert!(matches(r#"info.fstype == "nfs""#, &ev));
+    assert!(matches(r#"kind == "Mounted""#, &ev));
+    assert!(!matches(r#"kind == "Unmounted""#, &ev));
+    assert!(!matches(r#"nosuch == "x""#, &ev));
+    assert!(!matches(r#"nosuch != "x""#, &ev), "missing fields never compare");
+    assert!(matches(r#"!(nosuch == "x")"#, &ev));
+}
+
+#[test]
+fn operators_and_precedence() {
+    let ev = mounted("/mnt/data", "nfs");
+    assert!(matches(r#"fstype == "nfs" && target ~ "/mnt/*""#, &ev));
+    assert!(!matches(r#"fstype == "nfs" && target !~ "/mnt/*""#, &ev));
+    assert!(matches(r#"fstype == "ext4" || fstype == "nfs" && target ~ "/mnt/*""#, &ev), "&& binds tighter");
+    assert!(!matches(r#"(fstype == "ext4" || fstype == "nfs") && target ~ "/srv/*""#, &ev));
+    assert!(matches(r#"!fstype == "ext4""#, &ev));
+    assert!(matches("mount_id > 40 && mount_id <= 41 && mount_id != 7", &ev));
+    assert!(!matches("mount_id < 41", &ev));
+    assert!(matches(r#"mount_id == "41""#, &ev), "numbers compare as text against strings");
+}
+
+#[test]
+fn socket_addresses_expose_ip_and_port() {
+    let ev = opened("93.184.216.34:443", Some("edge.corp"));
+    assert!(matches("remote_addr.port == 443", &ev));
+    assert!(matches(r#"remote_addr.ip == "93.184.216.34""#, &ev));
+    assert!(matches(r#"remote_addr.port == 80 || remote_host ~ "*.corp""#, &ev));
+    assert!(matches(r#"conn.remote_addr.ip ~ "93.184.*""#, &ev));
+    assert!(!matches("proto.port == 1", &ev));
+
+    let v6 = opened("[2001:db8::1]:8443", None);
+    assert!(matches(r#"remote_addr.port >= 8000 && remote_addr.ip == "2001:db8::1""#, &v6));
+    assert!(!matches("remote_host", &v6), "null is not truthy");
+    assert!(matches("!offline", &v6));
+}
+
+#[test]
+fn arrays_match_any_element_and_paths_match_lossy() {
+    let ev = json!({ "OverBudget": { "metadata_only": ["/srv/a", "/srv/b"], "budget": 10 } });
+    assert!(matches(r#"metadata_only ~ "/srv/b""#, &ev));
+    assert!(matches(r#"metadata_only.0 == "/srv/a""#, &ev));
+    assert!(!matches(r#"metadata_only ~ "/tmp/*""#, &ev));
+
+    let ev = json!({ "Created": { "path": { "lossy": "/data/caf\u{fffd}", "bytes": [47] } } });
+    assert!(matches(r#"path ~ "/data/*""#, &ev));
+}
+
+#[test]
+fn numbers_take_size_units() {
+    let ev = json!({ "Throughput": { "rx_bytes_per_sec": 9_000_000, "tx_bytes_per_sec": 1500 } });
+    assert!(matches("rx_bytes_per_sec > 8MiB", &ev));
+    assert!(!matches("rx_bytes_per_sec > 9MB", &ev));
+    assert!(matches("tx_bytes_per_sec == 1.5kB", &ev));
+}
+
+#[test]
+fn parse_errors_point_at_the_problem() {
+    let cases = [
+        (r#"fstype == && x"#, 10, "expected a value after `==`"),
+        (r#"fstype = "nfs""#, 7, "expected `==`"),
+        (r#"(fstype == "nfs""#, 16, "expected `)`"),
+        (r#"target ~ 42"#, 9, "needs a string pattern"),
+        (r#"target ~ "[""#, 9, "invalid glob pattern"),
+        (r#"port < "x""#, 5, "compares numbers only"),
+        (r#"fstype == "nfs" fstype"#, 16, "unexpected `fstype`"),
+        (r#"target == "/mnt"#, 10, "unterminated string"),
+        (r#"x == 1 # y"#, 7, "unexpected character `#`"),
+        ("", 0, "expected an expression"),
+        ("a &&", 4, "expected an expression"),
+        ("== 1", 0, "expected a field name"),
+        ("size > 8gib", 8, "invalid size `8gib`: unknown unit \"gib\""),
+    ];
+    for (expr, pos, msg) in cases {
+        let err = Filter::parse(expr).expect_err(expr);
+        assert_eq!(err.pos, pos, "{expr}: {err}");
+        assert!(err.message.contains(msg), "{expr}: {err}");
+    }
+
+    let err = Filter::parse(r#"fstype == && x"#).unwrap_err();
+    assert_eq!(err.to_string(), "expected a value after `==`, found `&&` at column 11\n  fstype == && x\n            ^");
+}
+
+#[test]
+fn from_args_finds_the_filter_flag() {
+    assert!(Filter::from_args(["--verbose"]).unwrap().is_none());
+    let f = Filter::from_args(["-v", "--filter", r#"fstype == "nfs""#]).unwrap().unwrap();
+    assert_eq!(f.source(), r#"fstype == "nfs""#);
+    assert!(Filter::from_args([r#"--filter=kind == "Mounted""#]).unwrap().is_some());
+    assert!(Filter::from_args(["--filter"]).unwrap_err().message.contains("needs an expression"));
+    assert_eq!(Filter::from_args(["--filter", "a =="]).unwrap_err().pos, 4);
+}
+
+#[test]
+fn predicate_reads_event_fields() {
+    use crate::fields::{EventFields, FieldValue};
+
+    #[derive(serde::Serialize)]
+    enum Ev {
+        Appeared { name: String, pid: u32 },
+    }
+    impl EventFields for Ev {
+        fn kind(&self) -> &'static str {
+            "appeared"
+        }
+        fn field(&self, name: &str) -> Option<FieldValue<'_>> {
+            let Ev::Appeared { name: n, pid } = self;
+            match name {
+                "name" => Some(FieldValue::str(n)),
+                "pid" => Some(FieldValue::int(*pid)),
+                _ => None,
+            }
+        }
+        fn field_names(&self) -> &'static [&'static str] {
+            &["name", "pid"]
+        }
+    }
+
+    let f = Filter::parse(r#"kind == "Appeared" && !(name ~ "kworker*") && pid > 1"#).unwrap();
+    let keep = f.predicate::<Ev>();
+    assert!(keep(&Ev::Appeared { name: "sshd".into(), pid: 812 }));
+    assert!(!keep(&Ev::Appeared { name: "kworker/0:1".into(), pid: 9 }));
+
+    // the same answers as from the serialized event, for either spelling of the kind
+    for expr in [r#"kind == "appeared""#, r#"kind != "Appeared""#, r#"kind ~ "App*""#, "pid >= 812", r#"name == "sshd" || pid < 0"#, "nosuch > 1"] {
+        let f = Filter::parse(expr).unwrap();
+        let ev = Ev::Appeared { name: "sshd".into(), pid: 812 };
+        assert_eq!(f.matches_fields(&ev), f.matches_event(&ev), "{expr}");
+    }
+}
@@ -0,0 +1,103 @@
+//! Events as one line of text, for people and for log greps.
+//!
+//! Both forms are the event's kind, then `name=value` pairs in a fixed order:
+//!
+//! ```text
+//! mounted target=/mnt/nas source=//nas/share fstype=cifs
+//! opened proto=tcp local_dec=10.0.0.5:51234 remote_dec=93.184.216.34:443 state_dec=ESTABLISHED remote_host=example.com
+//! ```
+//!
+//! [`format_short`] has the fields telling what happened to what
+//! ([`EventFields::summary_fields`]), [`format_long`] all of them
+//! ([`EventFields::detail_fields`]). Fields an event does not have, such as an address that
+//! could not be decoded, are left out. A name like `remote_dec|remote` in those lists is
+//! the first of them that is set, shown under its own name. Values are as
+//! [`FieldValue::as_str`] has them, non-UTF-8 paths lossily; empty ones and those with
+//! whitespace, `"`, `=` or control characters are quoted and escaped like Rust strings.
+//!
+//! The output does not depend on the locale or the terminal, and changes only with a
+//! sensor's field lists. [`Style::colored`] adds ANSI colors on top, see
+//! [`Style::for_stdout`].
+
+use crate::fields::{self, EventFields, FieldValue};
+use std::{
+    borrow::Cow,
+    fmt::Write,
+    io::{self, IsTerminal},
+};
+
+const KIND: &str = "\x1b[1;36m";
+const NAME: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// `ev` as [`EventFields::kind`] and its summary fields, see the [module docs](self).
+pub fn format_short(ev: &dyn EventFields) -> String {
+    Style::plain().short(ev)
+}
+
+/// `ev` as [`EventFields::kind`] and its detail fields, see the [module docs](self).
+pub fn format_long(ev: &dyn EventFields) -> String {
+    Style::plain().long(ev)
+}
+
+/// Plain or colored output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Style {
+    color: bool,
+}
+
+impl Style {
+    pub fn plain() -> Self {
+        Self { color: false }
+    }
+
+    /// The kind in bold cyan and the field names dimmed.
+    pub fn colored() -> Self {
+        Self { color: true }
+    }
+
+    /// Colored when stdout is a terminal and `NO_COLOR` is unset or empty.
+    pub fn for_stdout() -> Self {
+        Self { color: io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) }
+    }
+
+    /// [`format_short`] in this style.
+    pub fn short(self, ev: &dyn EventFields) -> String {
+        self.render(ev, ev.summary_fields())
+    }
+
+    /// [`format_long`] in this style.
+    pub fn long(self, ev: &dyn EventFields) -> String {
+        self.render(ev, ev.detail_fields())
+    }
+
+    fn render(self, ev: &dyn EventFields, names: &[&str]) -> String {
+        let mut out = String::new();
+        self.paint(&mut out, KIND, ev.kind());
+        for spec in names {
+            let Some((name, value)) = spec.split('|').find_map(|n| fields::get(ev, n).map(|v| (n, v))) else {
+                continue;
+            };
+            out.push(' ');
+            self.paint(&mut out, NAME, &format!("{name}="));
+            out.push_str(&quoted(&value));
+        }
+        out
+    }
+
+    fn paint(self, out: &mut String, code: &str, text: &str) {
+        if self.color {
+            let _ = write!(out, "{code}{text}{RESET}");
+        } else {
+            out.push_str(text);
+        }
+    }
+}
+
+fn quoted<'a>(value: &'a FieldValue<'_>) -> Cow<'a, str> {
+    let s = match value {
+        FieldValue::Int(n) => return Cow::Owned(n.to_string()),
+        other => other.as_str().unwrap_or_default(),
+    };
+    if s.is_empty() || s.chars().any(|c| c.is_whitespace() || c.is_control() || c == '"' || c == '=') { Cow::Owned(format!("{s:?}")) } else { s }
+}
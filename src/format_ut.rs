@@ -0,0 +1,70 @@
+use crate::{
+    fields::{EventFields, FieldValue},
+    format::{Style, format_long, format_short},
+};
+use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path};
+
+struct Opened {
+    remote: &'static str,
+    remote_dec: Option<&'static str>,
+    comm: &'static str,
+    path: &'static Path,
+}
+
+impl EventFields for Opened {
+    fn kind(&self) -> &'static str {
+        "opened"
+    }
+
+    fn field(&self, name: &str) -> Option<FieldValue<'_>> {
+        match name {
+            "remote" => Some(FieldValue::str(self.remote)),
+            "remote_dec" => self.remote_dec.map(FieldValue::str),
+            "comm" => Some(FieldValue::str(self.comm)),
+            "path" => Some(FieldValue::Path(self.path)),
+            "pid" => Some(FieldValue::int(4242)),
+            _ => None,
+        }
+    }
+
+    fn field_names(&self) -> &'static [&'static str] {
+        &["remote", "remote_dec", "pid", "comm", "path"]
+    }
+
+    fn summary_fields(&self) -> &'static [&'static str] {
+        &["remote_dec|remote", "pid"]
+    }
+}
+
+fn opened() -> Opened {
+    Opened { remote: "0100007F:01BB", remote_dec: Some("127.0.0.1:443"), comm: "curl", path: Path::new("/usr/bin/curl") }
+}
+
+#[test]
+fn short_and_long_lines() {
+    let ev = opened();
+    assert_eq!(format_short(&ev), "opened remote_dec=127.0.0.1:443 pid=4242");
+    assert_eq!(format_long(&ev), "opened remote=0100007F:01BB remote_dec=127.0.0.1:443 pid=4242 comm=curl path=/usr/bin/curl");
+
+    // not decoded: the raw address under its own name
+    let ev = Opened { remote_dec: None, ..opened() };
+    assert_eq!(format_short(&ev), "opened remote=0100007F:01BB pid=4242");
+    assert_eq!(format_long(&ev), "opened remote=0100007F:01BB pid=4242 comm=curl path=/usr/bin/curl");
+}
+
+#[test]
+fn awkward_values_are_quoted() {
+    let path = Path::new(OsStr::from_bytes(b"/tmp/caf\xe9 menu"));
+    let ev = Opened { comm: "", path, ..opened() };
+    assert_eq!(format_long(&ev), "opened remote=0100007F:01BB remote_dec=127.0.0.1:443 pid=4242 comm=\"\" path=\"/tmp/caf\u{fffd} menu\"");
+
+    let ev = Opened { comm: "a=b \"c\"\n", path: Path::new("/tmp/caf\u{e9}"), ..opened() };
+    assert_eq!(format_long(&ev), r#"opened remote=0100007F:01BB remote_dec=127.0.0.1:443 pid=4242 comm="a=b \"c\"\n" path=/tmp/café"#);
+}
+
+#[test]
+fn colors_only_when_asked() {
+    let ev = opened();
+    assert_eq!(Style::plain().short(&ev), format_short(&ev));
+    assert_eq!(Style::colored().short(&ev), "\x1b[1;36mopened\x1b[0m \x1b[2mremote_dec=\x1b[0m127.0.0.1:443 \x1b[2mpid=\x1b[0m4242");
+}
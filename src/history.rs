@@ -0,0 +1,270 @@
+//! Recent events per entity, for looking back at what happened to one mountpoint, process,
+//! connection or path without a log search.
+//!
+//! A sensor with history on records every event it fires under the entity keys it belongs
+//! to (xmount its mountpoint, procdog its name and `pid:<pid>`, filescream its path and
+//! root, netpacket its connection and remote host) and publishes the rings once a tick,
+//! like a state handle (see [`crate::state`]): [`HistoryHandle::history`] never locks and
+//! sees the events up to the previous completed tick.
+//!
+//! History is bounded twice: each key keeps its last `per_key` events, older ones are
+//! overwritten, and all keys together at most `total`; over it, the least recently
+//! recorded keys are dropped whole. Off (the default), sensors keep no [`History`] at all
+//! and recording is a branch on `None`.
+
+use crate::{callbacks::unix_secs, debug::Debuggable, memory, state::Published};
+use serde::{Serialize, Serializer, ser::SerializeMap};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+/// Events kept per key by default.
+pub const DEFAULT_PER_KEY: usize = 32;
+
+/// Events kept over all keys by default.
+pub const DEFAULT_TOTAL: usize = 4096;
+
+/// How much history a sensor keeps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HistoryConfig {
+    per_key: usize,
+    total: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self::new(DEFAULT_PER_KEY, DEFAULT_TOTAL)
+    }
+}
+
+impl HistoryConfig {
+    /// The last `per_key` events of each key (at least one), at most `total` over all keys
+    /// (at least `per_key`).
+    pub fn new(per_key: usize, total: usize) -> Self {
+        let per_key = per_key.max(1);
+        Self { per_key, total: total.max(per_key) }
+    }
+
+    pub fn per_key(&self) -> usize {
+        self.per_key
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+}
+
+/// One recorded event.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct HistoryEntry<E> {
+    /// When it was recorded; serialized as Unix seconds.
+    #[serde(serialize_with = "unix_secs")]
+    pub ts: SystemTime,
+    pub event: E,
+}
+
+type Ring<E> = Arc<VecDeque<Arc<HistoryEntry<E>>>>;
+
+/// What readers see: the rings as of the last [`History::publish`].
+struct Rings<E> {
+    rings: HashMap<String, Ring<E>>,
+    // keys by last recorded, oldest first
+    order: Vec<String>,
+    events: usize,
+    evicted_keys: u64,
+}
+
+impl<E> Default for Rings<E> {
+    fn default() -> Self {
+        Self { rings: HashMap::new(), order: Vec::new(), events: 0, evicted_keys: 0 }
+    }
+}
+
+struct Writer<E> {
+    config: HistoryConfig,
+    rings: HashMap<String, (u64, Ring<E>)>,
+    lru: BTreeMap<u64, String>,
+    stamp: u64,
+    events: usize,
+    evicted_keys: u64,
+    dirty: bool,
+}
+
+/// The recording side, held by the sensor. Clones share it.
+pub struct History<E> {
+    writer: Arc<Mutex<Writer<E>>>,
+    published: Published<Rings<E>>,
+}
+
+impl<E> Clone for History<E> {
+    fn clone(&self) -> Self {
+        Self { writer: self.writer.clone(), published: self.published.clone() }
+    }
+}
+
+impl<E: Clone> History<E> {
+    pub fn new(config: HistoryConfig) -> Self {
+        let writer = Writer { config, rings: HashMap::new(), lru: BTreeMap::new(), stamp: 0, events: 0, evicted_keys: 0, dirty: false };
+        Self { writer: Arc::new(Mutex::new(writer)), published: Published::default() }
+    }
+
+    /// Record `ev` under each of `keys`, sharing one copy of it.
+    pub fn record<I, K>(&self, keys: I, ev: &E)
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        let Ok(mut w) = self.writer.lock() else { return };
+        let entry = Arc::new(HistoryEntry { ts: SystemTime::now(), event: ev.clone() });
+        for key in keys {
+            w.push(key.as_ref(), entry.clone());
+        }
+    }
+
+    /// Make what was recorded since the last call visible to readers, at the end of a tick.
+    pub fn publish(&self) {
+        let Ok(mut w) = self.writer.lock() else { return };
+        if !w.dirty {
+            return;
+        }
+        w.dirty = false;
+        self.published.publish(Rings {
+            rings: w.rings.iter().map(|(k, (_, ring))| (k.clone(), ring.clone())).collect(),
+            order: w.lru.values().cloned().collect(),
+            events: w.events,
+            evicted_keys: w.evicted_keys,
+        });
+    }
+
+    /// Forget everything, e.g. to shed memory; readers see it on the next [`History::publish`].
+    pub fn clear(&self) {
+        let Ok(mut w) = self.writer.lock() else { return };
+        w.evicted_keys += w.rings.len() as u64;
+        w.rings.clear();
+        w.lru.clear();
+        w.events = 0;
+        w.dirty = true;
+    }
+
+    /// Estimated size of what is recorded, for a [`crate::memory::MemoryReport`]: the
+    /// entries at the event's fixed size, the text behind it not counted.
+    pub fn bytes(&self) -> u64 {
+        let Ok(w) = self.writer.lock() else { return 0 };
+        let keys: u64 = w.rings.keys().map(|k| 2 * memory::entry(size_of::<(String, u64, Ring<E>)>(), k.len())).sum();
+        keys + (w.events * size_of::<Arc<HistoryEntry<E>>>()) as u64 + (w.unique_entries() * size_of::<HistoryEntry<E>>()) as u64
+    }
+
+    pub fn handle(&self) -> HistoryHandle<E> {
+        HistoryHandle(self.published.clone())
+    }
+}
+
+impl<E: Clone> Writer<E> {
+    fn push(&mut self, key: &str, entry: Arc<HistoryEntry<E>>) {
+        self.stamp += 1;
+        let stamp = self.stamp;
+        let per_key = self.config.per_key;
+        match self.rings.get_mut(key) {
+            Some((at, ring)) => {
+                self.lru.remove(at);
+                *at = stamp;
+                let ring = Arc::make_mut(ring);
+                if ring.len() >= per_key {
+                    ring.pop_front();
+                    self.events -= 1;
+                }
+                ring.push_back(entry);
+            }
+            None => {
+                self.rings.insert(key.to_string(), (stamp, Arc::new(VecDeque::from([entry]))));
+            }
+        }
+        self.lru.insert(stamp, key.to_string());
+        self.events += 1;
+        self.dirty = true;
+
+        // the key just recorded is the newest and, as per_key <= total, never dropped here
+        while self.events > self.config.total
+            && let Some((_, oldest)) = self.lru.pop_first()
+        {
+            if let Some((_, ring)) = self.rings.remove(&oldest) {
+                self.events -= ring.len();
+                self.evicted_keys += 1;
+            }
+        }
+    }
+
+    // an event recorded under several keys is stored once
+    fn unique_entries(&self) -> usize {
+        let mut seen = HashSet::new();
+        self.rings.values().flat_map(|(_, ring)| ring.iter()).filter(|e| seen.insert(Arc::as_ptr(e))).count()
+    }
+}
+
+/// The read side of a sensor's history, e.g. `XMount::history`. Clones share it.
+pub struct HistoryHandle<E>(Published<Rings<E>>);
+
+impl<E> Clone for HistoryHandle<E> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<E: Clone> HistoryHandle<E> {
+    /// The last `n` events recorded under `key`, oldest first.
+    pub fn history(&self, key: &str, n: usize) -> Vec<HistoryEntry<E>> {
+        let rings = self.0.load();
+        let Some(ring) = rings.rings.get(key) else { return Vec::new() };
+        ring.iter().skip(ring.len().saturating_sub(n)).map(|e| HistoryEntry::clone(e)).collect()
+    }
+
+    /// Keys with history, the most recently recorded first.
+    pub fn keys(&self) -> Vec<String> {
+        self.0.load().order.iter().rev().cloned().collect()
+    }
+
+    /// Events kept over all keys, those recorded under several keys counted for each.
+    pub fn len(&self) -> usize {
+        self.0.load().events
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Keys dropped so far to stay within the total, or cleared.
+    pub fn evicted_keys(&self) -> u64 {
+        self.0.load().evicted_keys
+    }
+}
+
+// Serialized as the keys, most recent first, each with its events oldest first, so sensor
+// debug structs can hold the handle itself.
+impl<E: Serialize> Serialize for HistoryHandle<E> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let rings = self.0.load();
+        let mut map = s.serialize_map(Some(rings.order.len()))?;
+        for key in rings.order.iter().rev() {
+            if let Some(ring) = rings.rings.get(key) {
+                map.serialize_entry(key, &ring.iter().map(|e| &**e).collect::<Vec<_>>())?;
+            }
+        }
+        map.end()
+    }
+}
+
+impl<E: Serialize + Send + Sync> Debuggable for HistoryHandle<E> {
+    fn debug_snapshot(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+}
+
+impl<E> std::fmt::Debug for HistoryHandle<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rings = self.0.load();
+        f.debug_struct("HistoryHandle").field("keys", &rings.order.len()).field("events", &rings.events).finish()
+    }
+}
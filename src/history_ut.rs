@@ -0,0 +1,101 @@
+use crate::{
+    debug::{DebugCell, Debuggable, REDACTED, Snapshots},
+    history::{History, HistoryConfig, HistoryHandle},
+};
+use serde::Serialize;
+
+#[derive(Clone, Default, Serialize)]
+struct SensorDebug {
+    history: Option<HistoryHandle<u32>>,
+}
+
+fn events(h: &History<u32>, key: &str, n: usize) -> Vec<u32> {
+    h.handle().history(key, n).into_iter().map(|e| e.event).collect()
+}
+
+#[test]
+fn rings_keep_the_last_events_oldest_first() {
+    let h = History::new(HistoryConfig::new(4, 100));
+    for i in 0..10 {
+        h.record(["/mnt/a"], &i);
+    }
+    h.record(["/mnt/b", "/mnt/a"], &100);
+
+    // nothing before the first publish
+    assert!(h.handle().is_empty());
+    h.publish();
+
+    assert_eq!(events(&h, "/mnt/a", 10), [7, 8, 9, 100]);
+    assert_eq!(events(&h, "/mnt/a", 2), [9, 100]);
+    assert_eq!(events(&h, "/mnt/b", 10), [100]);
+    assert!(events(&h, "/mnt/c", 10).is_empty());
+    assert_eq!(h.handle().keys(), ["/mnt/a", "/mnt/b"]);
+    assert_eq!(h.handle().len(), 5);
+}
+
+#[test]
+fn over_the_total_the_least_recent_keys_go() {
+    let h = History::new(HistoryConfig::new(3, 7));
+    for key in ["p1", "p2", "p3"] {
+        h.record([key], &1);
+        h.record([key], &2);
+    }
+    // p1 recorded again: p2 is now the least recent
+    h.record(["p1"], &3);
+    assert_eq!(h.handle().len(), 0);
+    h.publish();
+    assert_eq!(h.handle().len(), 7);
+
+    h.record(["p4"], &1);
+    h.publish();
+    let handle = h.handle();
+    assert_eq!(handle.keys(), ["p4", "p1", "p3"]);
+    assert_eq!(handle.evicted_keys(), 1);
+    assert!(handle.history("p2", 10).is_empty());
+    assert_eq!(events(&h, "p1", 10), [1, 2, 3]);
+    assert_eq!(handle.len(), 6);
+
+    // a reader keeps its snapshot across publishes
+    let before = handle.history("p3", 10);
+    h.clear();
+    h.publish();
+    assert!(handle.is_empty());
+    assert_eq!(before.len(), 2);
+    assert_eq!(handle.evicted_keys(), 4);
+}
+
+#[test]
+fn debug_snapshot_lists_recent_keys_first_and_redacts() {
+    let h = History::new(HistoryConfig::default());
+    h.record(["/mnt/a"], &1);
+    h.record(["/mnt/b"], &2);
+    h.publish();
+
+    let v = h.handle().debug_snapshot();
+    let keys: Vec<&String> = v.as_object().unwrap().keys().collect();
+    assert_eq!(keys.len(), 2);
+    assert_eq!(v["/mnt/b"][0]["event"], 2);
+    assert!(v["/mnt/a"][0]["ts"].as_f64().unwrap() > 0.0);
+
+    // as part of a sensor's debug struct, keys are redacted like entity names
+    let cell = DebugCell::<SensorDebug>::default();
+    cell.update(|d| d.history = Some(h.handle()));
+    let mut s = Snapshots::new();
+    s.register("demo", cell);
+    let dump = s.redact().dump();
+    let history = dump["components"]["demo"]["history"].as_object().unwrap();
+    assert!(history.keys().all(|k| k.starts_with(REDACTED)), "{history:?}");
+}
+
+#[test]
+fn memory_estimate_counts_shared_events_once() {
+    let h = History::new(HistoryConfig::default());
+    assert_eq!(h.bytes(), 0);
+    h.record(["a"], &1u32);
+    let one_key = h.bytes();
+    h.record(["b", "c"], &2u32);
+    let three_keys = h.bytes();
+    assert!(three_keys > one_key);
+    h.clear();
+    assert_eq!(h.bytes(), 0);
+}
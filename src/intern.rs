@@ -0,0 +1,278 @@
+//! Shared strings for event fields that repeat across entities and ticks.
+//!
+//! Filesystem types, mount options, protocols, TCP states and process names take a handful of
+//! distinct values on a host, yet parsers would allocate each of them afresh for every line
+//! of every tick. [`Interned`] holds such a field as an `Arc<str>` taken from an [`Interner`],
+//! so equal values share one allocation. It derefs to `str` and serializes as a plain string.
+//!
+//! The cache is bounded: strings longer than [`MAX_LEN`] are not cached, and once it holds
+//! its maximum of entries, those no field refers to any more are pruned. If that frees
+//! nothing, new strings are handed out uncached.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    borrow::{Borrow, Cow},
+    collections::HashSet,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Deref,
+    path::Path,
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+/// Entries of the interner [`Interned::new`] and the sensors' parsers use.
+pub const DEFAULT_MAX_ENTRIES: usize = 4096;
+
+/// Longer strings are shared by clones only, not cached.
+pub const MAX_LEN: usize = 256;
+
+/// Misses between two prunes of an interner that is not full.
+const PRUNE_EVERY: u64 = 1 << 14;
+
+/// A string shared with equal ones from the same [`Interner`]. Cheap to clone.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Interned(Arc<str>);
+
+impl Interned {
+    /// `s` from the global interner.
+    pub fn new(s: &str) -> Self {
+        global().intern(s)
+    }
+
+    /// `bytes` as UTF-8 (invalid sequences replaced) from the global interner.
+    pub fn from_utf8_lossy(bytes: &[u8]) -> Self {
+        Self::new(&String::from_utf8_lossy(bytes))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether `a` and `b` share one allocation.
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        Arc::ptr_eq(&a.0, &b.0)
+    }
+}
+
+impl Default for Interned {
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
+impl Deref for Interned {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Interned {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for Interned {
+    fn as_ref(&self) -> &Path {
+        Path::new(self.as_str())
+    }
+}
+
+impl<'a> From<&'a Interned> for Cow<'a, str> {
+    fn from(s: &'a Interned) -> Self {
+        Cow::Borrowed(s)
+    }
+}
+
+impl Borrow<str> for Interned {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+// by content, like `str`, so `Borrow<str>` lookups work
+impl Hash for Interned {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl fmt::Debug for Interned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for Interned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl From<&str> for Interned {
+    fn from(s: &str) -> Self {
+        Self::new(s)
+    }
+}
+
+impl From<String> for Interned {
+    fn from(s: String) -> Self {
+        Self::new(&s)
+    }
+}
+
+impl From<&String> for Interned {
+    fn from(s: &String) -> Self {
+        Self::new(s)
+    }
+}
+
+impl From<Interned> for String {
+    fn from(s: Interned) -> Self {
+        s.as_str().to_string()
+    }
+}
+
+impl From<&Interned> for String {
+    fn from(s: &Interned) -> Self {
+        s.as_str().to_string()
+    }
+}
+
+impl PartialEq<str> for Interned {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Interned {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Interned {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<Interned> for str {
+    fn eq(&self, other: &Interned) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<Interned> for String {
+    fn eq(&self, other: &Interned) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<Interned> for &str {
+    fn eq(&self, other: &Interned) -> bool {
+        *self == other.as_str()
+    }
+}
+
+impl Serialize for Interned {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Interned {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let s = Cow::<'de, str>::deserialize(d)?;
+        Ok(Self::new(&s))
+    }
+}
+
+/// How an [`Interner`] has been doing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct InternStats {
+    pub entries: usize,
+    /// Lookups answered from the cache.
+    pub hits: u64,
+    /// Lookups that allocated, cached or not.
+    pub misses: u64,
+    /// Entries dropped by pruning.
+    pub pruned: u64,
+}
+
+/// Bounded cache of shared strings, see the module docs.
+pub struct Interner {
+    set: Mutex<HashSet<Arc<str>>>,
+    max_entries: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    pruned: AtomicU64,
+}
+
+impl Interner {
+    pub fn new(max_entries: usize) -> Self {
+        Self { set: Mutex::default(), max_entries, hits: AtomicU64::new(0), misses: AtomicU64::new(0), pruned: AtomicU64::new(0) }
+    }
+
+    /// The cached string equal to `s`, cached now if there is room.
+    pub fn intern(&self, s: &str) -> Interned {
+        if s.len() > MAX_LEN {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return Interned(Arc::from(s));
+        }
+        let mut set = self.set.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(hit) = set.get(s) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Interned(hit.clone());
+        }
+
+        let misses = self.misses.fetch_add(1, Ordering::Relaxed) + 1;
+        if set.len() >= self.max_entries || misses.is_multiple_of(PRUNE_EVERY) {
+            self.prune_locked(&mut set);
+        }
+        let new: Arc<str> = Arc::from(s);
+        if set.len() < self.max_entries {
+            set.insert(new.clone());
+        }
+        Interned(new)
+    }
+
+    /// Drop the entries nothing else refers to. Returns how many.
+    pub fn prune(&self) -> usize {
+        self.prune_locked(&mut self.set.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    fn prune_locked(&self, set: &mut HashSet<Arc<str>>) -> usize {
+        let before = set.len();
+        set.retain(|s| Arc::strong_count(s) > 1);
+        let pruned = before - set.len();
+        self.pruned.fetch_add(pruned as u64, Ordering::Relaxed);
+        pruned
+    }
+
+    pub fn stats(&self) -> InternStats {
+        InternStats {
+            entries: self.set.lock().unwrap_or_else(|e| e.into_inner()).len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            pruned: self.pruned.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ENTRIES)
+    }
+}
+
+/// The process-wide interner behind [`Interned::new`], of [`DEFAULT_MAX_ENTRIES`].
+pub fn global() -> &'static Interner {
+    static GLOBAL: OnceLock<Interner> = OnceLock::new();
+    GLOBAL.get_or_init(Interner::default)
+}
@@ -0,0 +1,58 @@
+use crate::intern::{InternStats, Interned, Interner, MAX_LEN};
+use std::collections::HashSet;
+
+#[test]
+fn equal_strings_share_one_allocation() {
+    let interner = Interner::new(16);
+    let a = interner.intern("ext4");
+    let b = interner.intern(&String::from("ext4"));
+    let c = interner.intern("xfs");
+
+    assert!(Interned::ptr_eq(&a, &b));
+    assert!(!Interned::ptr_eq(&a, &c));
+    assert_eq!(a, b);
+    assert_eq!(a, "ext4");
+    assert_eq!(&*c, "xfs");
+    assert_eq!(interner.stats(), InternStats { entries: 2, hits: 1, misses: 2, pruned: 0 });
+}
+
+#[test]
+fn bounded_by_entries_and_length() {
+    let interner = Interner::new(2);
+    let keep = interner.intern("tcp");
+    drop(interner.intern("udp"));
+
+    // full: "udp" is referenced by nothing else and makes room
+    let tcp6 = interner.intern("tcp6");
+    assert_eq!(interner.stats().pruned, 1);
+    assert!(Interned::ptr_eq(&tcp6, &interner.intern("tcp6")));
+
+    // full of live strings: handed out uncached
+    let udp6 = interner.intern("udp6");
+    assert!(!Interned::ptr_eq(&udp6, &interner.intern("udp6")));
+    assert_eq!(interner.stats().entries, 2);
+
+    let long = "x".repeat(MAX_LEN + 1);
+    assert!(!Interned::ptr_eq(&interner.intern(&long), &interner.intern(&long)));
+    assert_eq!(interner.intern(&long), long);
+    drop(keep);
+    assert_eq!(interner.prune(), 1);
+}
+
+#[test]
+fn serializes_as_a_plain_string() {
+    let s = Interned::new("rw,relatime");
+    assert_eq!(serde_json::to_string(&s).unwrap(), r#""rw,relatime""#);
+    let back: Interned = serde_json::from_str(r#""rw,relatime""#).unwrap();
+    assert!(Interned::ptr_eq(&s, &back), "deserialized strings are interned too");
+    assert_eq!(format!("{s} {s:?}"), r#"rw,relatime "rw,relatime""#);
+}
+
+#[test]
+fn looks_up_like_str() {
+    let set: HashSet<Interned> = ["tcp", "udp"].into_iter().map(Interned::new).collect();
+    assert!(set.contains("tcp"));
+    assert!(!set.contains("tcp6"));
+    assert_eq!(Interned::default(), "");
+    assert!(Interned::new("a") < Interned::new("b"));
+}
@@ -0,0 +1,92 @@
+//! Business context attached to a watch, carried on the events it matches.
+//!
+//! Whoever registers a watch often knows what it is about: the postgres data volume, the
+//! billing service. Registered with labels (`XMount::add_labeled`, `ProcDog::watch_labeled`,
+//! `FileScream::watch_with`, `NetNotify::add_labeled`), the watch hands them to every event
+//! it matches, serialized under a `"labels"` key, so consumers need no lookup table of their
+//! own. Events without labels serialize as before.
+//!
+//! Labels are opaque to the sensors: nothing is matched or filtered on them. When several
+//! watches match one event their labels are merged, the later one winning for a key both
+//! set; which one is later is up to the sensor.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+
+/// Labels of a watch. Cheap to clone: every event of the watch shares one map.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Labels(Option<Arc<Map<String, Value>>>);
+
+static EMPTY: Labels = Labels(None);
+
+impl Labels {
+    pub fn new(map: Map<String, Value>) -> Self {
+        Self((!map.is_empty()).then(|| Arc::new(map)))
+    }
+
+    /// No labels, for events that have none.
+    pub fn empty() -> &'static Labels {
+        &EMPTY
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_none()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.0.as_ref()?.get(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.0.iter().flat_map(|m| m.iter())
+    }
+
+    /// Add the labels of `other`, its values replacing those of keys set in both.
+    pub fn merge(&mut self, other: &Labels) {
+        match (&mut self.0, &other.0) {
+            (_, None) => {}
+            (None, Some(_)) => self.0 = other.0.clone(),
+            (Some(mine), Some(theirs)) => {
+                let mine = Arc::make_mut(mine);
+                mine.extend(theirs.iter().map(|(k, v)| (k.clone(), v.clone())));
+            }
+        }
+    }
+
+    /// `all` merged in order, see [`Labels::merge`].
+    pub fn merged<'a, I: IntoIterator<Item = &'a Labels>>(all: I) -> Labels {
+        let mut out = Labels::default();
+        for labels in all {
+            out.merge(labels);
+        }
+        out
+    }
+}
+
+impl From<Map<String, Value>> for Labels {
+    fn from(map: Map<String, Value>) -> Self {
+        Self::new(map)
+    }
+}
+
+impl<K: Into<String>, V: Into<Value>> FromIterator<(K, V)> for Labels {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self::new(iter.into_iter().map(|(k, v)| (k.into(), v.into())).collect())
+    }
+}
+
+impl Serialize for Labels {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match &self.0 {
+            Some(map) => map.serialize(s),
+            None => Map::new().serialize(s),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Labels {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        Ok(Self::new(Map::deserialize(d)?))
+    }
+}
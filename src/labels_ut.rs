@@ -0,0 +1,23 @@
+use crate::labels::Labels;
+use serde_json::json;
+
+#[test]
+fn later_labels_win_on_merge() {
+    let volume: Labels = [("service", "postgres"), ("tier", "data")].into_iter().collect();
+    let backup: Labels = [("tier", "backup"), ("owner", "dba")].into_iter().collect();
+    let merged = Labels::merged([&volume, &Labels::default(), &backup]);
+    assert_eq!(serde_json::to_value(&merged).unwrap(), json!({"service": "postgres", "tier": "backup", "owner": "dba"}));
+    // the merged-in labels are left alone
+    assert_eq!(volume.get("tier"), Some(&json!("data")));
+}
+
+#[test]
+fn an_empty_map_is_no_labels() {
+    let labels = Labels::new(serde_json::Map::new());
+    assert!(labels.is_empty());
+    assert_eq!(&labels, Labels::empty());
+    let back: Labels = serde_json::from_value(json!({})).unwrap();
+    assert!(back.is_empty());
+    let back: Labels = serde_json::from_value(json!({"n": 1})).unwrap();
+    assert_eq!(back.get("n"), Some(&json!(1)));
+}
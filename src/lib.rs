@@ -1,2 +1,157 @@
+// the let-chains throughout need 1.88; cargo says so itself unless run with --ignore-rust-version
+#[rustversion::before(1.88)]
+compile_error!("omnitrace needs Rust 1.88 or newer, see \"Minimum supported Rust version\" in README.md");
+
+pub mod anomaly;
+#[cfg(feature = "runtime")]
+pub mod audit;
+#[cfg(feature = "runtime")]
 pub mod callbacks;
+pub mod clock;
+#[cfg(feature = "runtime")]
+pub mod compress;
+#[cfg(feature = "runtime")]
+pub mod deadline;
+pub mod debug;
+#[cfg(feature = "runtime")]
+pub mod degrade;
+pub mod delta;
+#[cfg(feature = "runtime")]
+pub mod durable;
+pub mod entities;
+pub mod error;
+pub mod expected;
+pub mod fields;
+pub mod filter;
+pub mod format;
+#[cfg(feature = "runtime")]
+pub mod history;
+pub mod intern;
+pub mod labels;
+pub mod memory;
+pub mod paths;
+#[cfg(feature = "runtime")]
+pub mod preflight;
+#[cfg(feature = "runtime")]
+pub mod prelude;
+#[cfg(feature = "runtime")]
+pub mod preview;
+#[cfg(feature = "runtime")]
+pub mod prom;
+#[cfg(feature = "runtime")]
+pub mod pulse;
+#[cfg(feature = "runtime")]
+pub mod quiet;
+#[cfg(feature = "runtime")]
+pub mod router;
+#[cfg(feature = "runtime")]
+pub mod scope;
+#[cfg(feature = "runtime")]
 pub mod sensor;
+#[cfg(feature = "runtime")]
+pub mod severity;
+#[cfg(feature = "runtime")]
+pub mod shared;
+#[cfg(feature = "runtime")]
+pub mod sink;
+pub mod soak;
+#[cfg(feature = "tracing")]
+pub mod spans;
+#[cfg(feature = "runtime")]
+pub mod standby;
+#[cfg(feature = "runtime")]
+pub mod state;
+#[cfg(all(feature = "syslog", unix))]
+pub mod syslog;
+pub mod tombstones;
+pub mod topics;
+pub mod units;
+#[cfg(feature = "http")]
+pub mod webhook;
+
+#[cfg(test)]
+mod anomaly_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod audit_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod callbacks_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod clock_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod compress_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod deadline_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod debug_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod degrade_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod delta_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod durable_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod entities_ut;
+#[cfg(test)]
+mod error_ut;
+#[cfg(test)]
+mod expected_ut;
+#[cfg(test)]
+mod fields_ut;
+#[cfg(test)]
+mod filter_ut;
+#[cfg(test)]
+mod format_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod history_ut;
+#[cfg(test)]
+mod intern_ut;
+#[cfg(test)]
+mod labels_ut;
+#[cfg(test)]
+mod memory_ut;
+#[cfg(test)]
+mod msrv_ut;
+#[cfg(test)]
+mod paths_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod preflight_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod prelude_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod preview_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod prom_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod pulse_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod quiet_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod router_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod scope_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod sensor_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod severity_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod shared_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod sink_ut;
+#[cfg(test)]
+mod soak_ut;
+#[cfg(all(test, feature = "tracing"))]
+mod spans_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod standby_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod state_ut;
+#[cfg(all(test, feature = "syslog", unix))]
+mod syslog_ut;
+#[cfg(test)]
+mod tombstones_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod topics_ut;
+#[cfg(test)]
+mod units_ut;
+#[cfg(all(test, feature = "http"))]
+mod webhook_ut;
@@ -1,3 +1,19 @@
+use omnitrace_core::audit;
+
 fn main() {
-    println!("Hello, world!");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.split_first() {
+        // `verify <oldest>.. <live>`: check audit logs, rotated files oldest first
+        Some((cmd, files)) if cmd == "verify" && !files.is_empty() => match audit::verify_files(files) {
+            Ok(sum) => println!("ok: {} records in the last file, head {}", sum.records, sum.head),
+            Err(e) => {
+                eprintln!("chain broken: {e}");
+                std::process::exit(1);
+            }
+        },
+        _ => {
+            eprintln!("usage: omnitrace-core verify <audit.jsonl>...");
+            std::process::exit(2);
+        }
+    }
 }
@@ -0,0 +1,99 @@
+//! Approximate memory accounting for sensor state.
+//!
+//! Sensors with state that grows with the watched system (connection sets, caches, file
+//! tables) estimate its size every tick from entry counts and key lengths, publish it in a
+//! [`MemoryStats`] handle and, given a soft budget, shed what they can rebuild when over it.
+//! Estimates are meant to show trends and the biggest consumers, not to match the allocator.
+
+use crate::debug::Debuggable;
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// Per-entry overhead assumed for hash maps and sets: control bytes and load-factor slack.
+pub const MAP_ENTRY_OVERHEAD: u64 = 16;
+
+/// Estimated size of one map entry with a path key and `value` bytes of fixed-size value.
+pub fn path_entry(path: &Path, value: usize) -> u64 {
+    (size_of::<std::path::PathBuf>() + path.as_os_str().len() + value) as u64 + MAP_ENTRY_OVERHEAD
+}
+
+/// Estimated size of one map entry of `fixed` bytes plus heap-allocated text.
+pub fn entry(fixed: usize, heap: usize) -> u64 {
+    (fixed + heap) as u64 + MAP_ENTRY_OVERHEAD
+}
+
+/// One sensor's estimated memory use.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct MemoryReport {
+    pub estimated_bytes: u64,
+    /// Soft budget from the sensor config, if any.
+    pub budget: Option<u64>,
+    /// Estimate per collection, e.g. `"dns_cache"`.
+    pub parts: BTreeMap<String, u64>,
+    pub over_budget: bool,
+    /// Times the sensor had to shed state to get back under budget.
+    pub sheds: u64,
+}
+
+impl MemoryReport {
+    pub fn new(budget: Option<u64>) -> Self {
+        Self { budget, ..Self::default() }
+    }
+
+    /// Account `bytes` for the collection `name`.
+    pub fn add(&mut self, name: &str, bytes: u64) {
+        *self.parts.entry(name.to_string()).or_default() += bytes;
+        self.estimated_bytes += bytes;
+    }
+
+    /// True if a budget is set and the estimate exceeds it.
+    pub fn exceeds(&self) -> bool {
+        self.budget.is_some_and(|b| self.estimated_bytes > b)
+    }
+}
+
+/// Shared handle on a sensor's last [`MemoryReport`], e.g. `NetNotify::memory()`. Cheap to clone.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStats(Arc<Mutex<MemoryReport>>);
+
+impl MemoryStats {
+    pub fn report(&self) -> MemoryReport {
+        self.0.lock().map(|r| r.clone()).unwrap_or_default()
+    }
+
+    pub fn estimated_bytes(&self) -> u64 {
+        self.0.lock().map(|r| r.estimated_bytes).unwrap_or(0)
+    }
+
+    /// Publish a new report, carrying the shed counter over.
+    pub fn publish(&self, mut report: MemoryReport, shed: bool) {
+        if let Ok(mut r) = self.0.lock() {
+            report.sheds = r.sheds + u64::from(shed);
+            *r = report;
+        }
+    }
+}
+
+impl Debuggable for MemoryStats {
+    fn debug_snapshot(&self) -> Value {
+        serde_json::to_value(self.report()).unwrap_or(Value::Null)
+    }
+}
+
+/// Estimated bytes per sensor plus their `"total"`, for self-monitoring output.
+pub fn totals<'a, I: IntoIterator<Item = (&'a str, &'a MemoryStats)>>(sensors: I) -> BTreeMap<String, u64> {
+    let mut out = BTreeMap::new();
+    let mut total = 0;
+    for (name, stats) in sensors {
+        let bytes = stats.estimated_bytes();
+        total += bytes;
+        out.insert(name.to_string(), bytes);
+    }
+    out.insert("total".to_string(), total);
+    out
+}
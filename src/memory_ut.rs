@@ -0,0 +1,56 @@
+use crate::{
+    debug::Debuggable,
+    memory::{self, MAP_ENTRY_OVERHEAD, MemoryReport, MemoryStats},
+};
+use std::path::Path;
+
+#[test]
+fn estimates_grow_with_keys() {
+    let short = memory::path_entry(Path::new("/a"), 32);
+    let long = memory::path_entry(Path::new("/srv/data/some/deeper/file.txt"), 32);
+    assert!(long > short);
+    assert_eq!(memory::path_entry(Path::new("/a"), 64) - short, 32);
+    assert_eq!(memory::entry(8, 0), 8 + MAP_ENTRY_OVERHEAD);
+}
+
+#[test]
+fn report_adds_parts_and_checks_budget() {
+    let mut r = MemoryReport::new(None);
+    r.add("dns_cache", 600);
+    r.add("connections", 400);
+    r.add("dns_cache", 100);
+    assert_eq!(r.estimated_bytes, 1100);
+    assert_eq!(r.parts["dns_cache"], 700);
+    assert!(!r.exceeds(), "no budget, never over");
+
+    r.budget = Some(1100);
+    assert!(!r.exceeds());
+    r.budget = Some(1000);
+    assert!(r.exceeds());
+}
+
+#[test]
+fn publish_keeps_shed_count() {
+    let stats = MemoryStats::default();
+    stats.publish(MemoryReport::new(Some(10)), true);
+    stats.publish(MemoryReport::new(Some(10)), false);
+    stats.publish(MemoryReport::new(Some(10)), true);
+    assert_eq!(stats.report().sheds, 2);
+    assert_eq!(stats.debug_snapshot()["sheds"], 2);
+}
+
+#[test]
+fn totals_sum_sensors() {
+    let (a, b) = (MemoryStats::default(), MemoryStats::default());
+    let mut r = MemoryReport::new(None);
+    r.add("files", 300);
+    a.publish(r, false);
+    let mut r = MemoryReport::new(None);
+    r.add("connections", 200);
+    b.publish(r, false);
+
+    let t = memory::totals([("filescream", &a), ("netpacket", &b)]);
+    assert_eq!(t["filescream"], 300);
+    assert_eq!(t["netpacket"], 200);
+    assert_eq!(t["total"], 500);
+}
@@ -0,0 +1,53 @@
+//! The declared minimum supported Rust version, kept in one place and actually met.
+
+use std::{path::Path, process::Command};
+
+const ROOT: &str = env!("CARGO_MANIFEST_DIR");
+
+/// The value of `key = "..."` in `manifest`, or of `key = [...]` as its quoted items.
+fn values(manifest: &str, key: &str) -> Vec<String> {
+    let Some(at) = manifest.find(&format!("\n{key} = ")) else {
+        return Vec::new();
+    };
+    let rest = &manifest[at + key.len() + 4..];
+    let end = if rest.starts_with('[') { rest.find(']').unwrap() } else { rest.find('\n').unwrap_or(rest.len()) };
+    rest[..end].split('"').skip(1).step_by(2).map(str::to_string).collect()
+}
+
+fn msrv() -> String {
+    let root = std::fs::read_to_string(Path::new(ROOT).join("Cargo.toml")).unwrap();
+    values(&root, "rust-version").pop().expect("rust-version in [workspace.package]")
+}
+
+#[test]
+fn every_crate_declares_the_workspace_msrv() {
+    let root = std::fs::read_to_string(Path::new(ROOT).join("Cargo.toml")).unwrap();
+    let members = values(&root, "members");
+    assert!(members.len() > 5, "{members:?}");
+    for member in members {
+        let manifest = std::fs::read_to_string(Path::new(ROOT).join(&member).join("Cargo.toml")).unwrap();
+        assert!(manifest.contains("\nrust-version.workspace = true\n"), "{member}/Cargo.toml does not inherit rust-version");
+    }
+}
+
+#[test]
+fn the_compile_time_check_matches_the_manifests() {
+    let msrv = msrv();
+    assert!(include_str!("lib.rs").contains(&format!("#[rustversion::before({msrv})]")), "src/lib.rs checks another version than {msrv}");
+    assert!(include_str!("../README.md").contains(&format!("Rust {msrv}")), "README.md names another version than {msrv}");
+}
+
+/// Builds everything with the declared toolchain, which must be installed:
+/// `rustup toolchain install 1.88 && cargo test msrv -- --ignored`.
+#[test]
+#[ignore = "needs the MSRV toolchain installed"]
+fn the_workspace_builds_with_its_msrv() {
+    let status = Command::new("cargo")
+        .arg(format!("+{}", msrv()))
+        .args(["check", "--workspace", "--all-features", "--all-targets"])
+        .env("CARGO_TARGET_DIR", Path::new(ROOT).join("target/msrv"))
+        .current_dir(ROOT)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
@@ -0,0 +1,67 @@
+//! Lossless path handling shared by the sensors.
+//!
+//! Paths are OS strings, not UTF-8: matching should go through `Path`/bytes, never through
+//! `to_string_lossy()`, and serialization goes through this module:
+//!
+//! - UTF-8 paths serialize as a plain string, as before.
+//! - Other paths serialize as `{ "lossy": "<display string>", "bytes": [..] }`, so consumers that
+//!   only display paths have a string, and nothing is lost on a round trip.
+//!
+//! Use it with `#[serde(with = "omnitrace_core::paths")]` on `PathBuf` fields.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
+
+/// Raw bytes of a path. Lossless on unix, UTF-8 (lossy) elsewhere.
+pub fn as_bytes(p: &Path) -> Cow<'_, [u8]> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Cow::Borrowed(p.as_os_str().as_bytes())
+    }
+    #[cfg(not(unix))]
+    {
+        match p.to_string_lossy() {
+            Cow::Borrowed(s) => Cow::Borrowed(s.as_bytes()),
+            Cow::Owned(s) => Cow::Owned(s.into_bytes()),
+        }
+    }
+}
+
+/// Path from raw bytes, e.g. as read from a kernel table. Lossless on unix.
+pub fn from_bytes(b: Vec<u8>) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        PathBuf::from(std::ffi::OsString::from_vec(b))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(&b).into_owned())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Repr<'a> {
+    Utf8(Cow<'a, str>),
+    Raw { lossy: Cow<'a, str>, bytes: Cow<'a, [u8]> },
+}
+
+pub fn serialize<S: Serializer>(p: &Path, s: S) -> Result<S::Ok, S::Error> {
+    match p.to_str() {
+        Some(utf8) => Repr::Utf8(Cow::Borrowed(utf8)),
+        None => Repr::Raw { lossy: p.to_string_lossy(), bytes: as_bytes(p) },
+    }
+    .serialize(s)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<PathBuf, D::Error> {
+    Ok(match Repr::deserialize(d)? {
+        Repr::Utf8(s) => PathBuf::from(s.into_owned()),
+        Repr::Raw { bytes, .. } => from_bytes(bytes.into_owned()),
+    })
+}
@@ -0,0 +1,33 @@
+use crate::paths;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::PathBuf;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Ev {
+    #[serde(with = "paths")]
+    path: PathBuf,
+}
+
+#[test]
+fn utf8_path_is_a_plain_string() {
+    let ev = Ev { path: PathBuf::from("/etc/passwd") };
+    let v = serde_json::to_value(&ev).unwrap();
+    assert_eq!(v, json!({ "path": "/etc/passwd" }));
+    assert_eq!(serde_json::from_value::<Ev>(v).unwrap(), ev);
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_path_round_trips() {
+    let ev = Ev { path: paths::from_bytes(b"/srv/caf\xe9/x".to_vec()) };
+    assert!(ev.path.to_str().is_none());
+
+    let v = serde_json::to_value(&ev).unwrap();
+    assert_eq!(v["path"]["lossy"], "/srv/caf\u{fffd}/x");
+    assert_eq!(v["path"]["bytes"].as_array().unwrap().len(), 11);
+
+    let back: Ev = serde_json::from_str(&v.to_string()).unwrap();
+    assert_eq!(back, ev);
+    assert_eq!(paths::as_bytes(&back.path).as_ref(), b"/srv/caf\xe9/x");
+}
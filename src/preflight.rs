@@ -0,0 +1,145 @@
+//! Startup self-test.
+//!
+//! Most "it doesn't work" reports are about the environment: `/proc/net` unreadable in a
+//! sandbox, a wrong mountinfo path, no `ps`, a watched directory that does not exist. Every
+//! [`Sensor`] can check its configured data sources, permissions and watch targets without
+//! starting its loop ([`Sensor::preflight`]) and says what it found as [`PreflightFinding`]s.
+//!
+//! The CLIs run it at startup through [`gate`]: findings are printed, a `Critical` one stops
+//! the CLI unless `--force` is given, and `--check` exits after printing, with status 1 if
+//! something is critical.
+
+use crate::{sensor::Sensor, severity::Severity};
+use serde::Serialize;
+use std::{fmt, io};
+
+/// Something a sensor found wrong (or worth knowing) about its environment.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PreflightFinding {
+    /// `Critical`: the sensor cannot work like this. `Warning`: it works, but probably not
+    /// as intended. `Info`: for the record.
+    pub severity: Severity,
+    pub message: String,
+    /// What to do about it, e.g. `"mount /proc or set NetNotifyConfig::proc_net"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remedy: Option<String>,
+}
+
+impl PreflightFinding {
+    pub fn new<S: Into<String>>(severity: Severity, message: S) -> Self {
+        Self { severity, message: message.into(), remedy: None }
+    }
+
+    pub fn critical<S: Into<String>>(message: S) -> Self {
+        Self::new(Severity::Critical, message)
+    }
+
+    pub fn warning<S: Into<String>>(message: S) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    pub fn info<S: Into<String>>(message: S) -> Self {
+        Self::new(Severity::Info, message)
+    }
+
+    pub fn remedy<S: Into<String>>(mut self, hint: S) -> Self {
+        self.remedy = Some(hint.into());
+        self
+    }
+}
+
+impl fmt::Display for PreflightFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Debug => "debug",
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "CRITICAL",
+        };
+        write!(f, "{severity}: {}", self.message)?;
+        if let Some(r) = &self.remedy {
+            write!(f, " (hint: {r})")?;
+        }
+        Ok(())
+    }
+}
+
+/// The `--check` and `--force` flags of a CLI.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PreflightArgs {
+    /// Print the findings and exit.
+    pub check: bool,
+    /// Start even with critical findings.
+    pub force: bool,
+}
+
+/// What a CLI does after preflight, see [`PreflightArgs::verdict`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Run,
+    Exit(i32),
+}
+
+impl PreflightArgs {
+    pub fn from_args<I, S>(args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut out = Self::default();
+        for a in args {
+            match a.as_ref() {
+                "--check" => out.check = true,
+                "--force" => out.force = true,
+                _ => {}
+            }
+        }
+        out
+    }
+
+    pub fn from_env_args() -> Self {
+        Self::from_args(std::env::args().skip(1))
+    }
+
+    /// Run, unless a finding is critical and not forced, or this is a `--check` (status 0
+    /// when nothing is critical or `--force` is given, 1 otherwise).
+    pub fn verdict(&self, findings: &[PreflightFinding]) -> Verdict {
+        let fatal = !self.force && findings.iter().any(|f| f.severity == Severity::Critical);
+        match (self.check, fatal) {
+            (true, fatal) => Verdict::Exit(fatal.into()),
+            (false, true) => Verdict::Exit(1),
+            (false, false) => Verdict::Run,
+        }
+    }
+}
+
+/// One line per finding, prefixed with the sensor name; `"<sensor>: preflight ok"` if none.
+pub fn write_report<W: io::Write>(mut w: W, sensor: &str, findings: &[PreflightFinding]) -> io::Result<()> {
+    if findings.is_empty() {
+        return writeln!(w, "{sensor}: preflight ok");
+    }
+    for f in findings {
+        writeln!(w, "{sensor}: {f}")?;
+    }
+    Ok(())
+}
+
+/// Preflight for a CLI's `main`: runs [`Sensor::preflight`], prints the findings (to
+/// stdout under `--check`, else to stderr) and exits as [`PreflightArgs::verdict`] says.
+pub async fn gate<S: Sensor>(name: &str, sensor: &S, args: PreflightArgs) {
+    let findings = sensor.preflight().await;
+    let verdict = args.verdict(&findings);
+    let _ = if args.check {
+        write_report(io::stdout(), name, &findings)
+    } else if !findings.is_empty() {
+        write_report(io::stderr(), name, &findings)
+    } else {
+        Ok(())
+    };
+    if let Verdict::Exit(code) = verdict {
+        if code != 0 && !args.check {
+            eprintln!("{name}: critical preflight findings, not starting (use --force to start anyway)");
+        }
+        std::process::exit(code);
+    }
+}
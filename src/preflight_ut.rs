@@ -0,0 +1,54 @@
+use crate::{
+    preflight::{PreflightArgs, PreflightFinding, Verdict, write_report},
+    prelude::*,
+};
+use std::{future::Future, pin::Pin};
+
+#[test]
+fn critical_findings_stop_unless_forced() {
+    let warn = [PreflightFinding::warning("tcp6 table missing")];
+    let crit = [PreflightFinding::critical("cannot read /proc/net/tcp"), PreflightFinding::info("x")];
+    let args = |a: &[&str]| PreflightArgs::from_args(a.iter().copied());
+
+    assert_eq!(args(&[]).verdict(&warn), Verdict::Run);
+    assert_eq!(args(&[]).verdict(&crit), Verdict::Exit(1));
+    assert_eq!(args(&["--force"]).verdict(&crit), Verdict::Run);
+
+    assert_eq!(args(&["--check"]).verdict(&[]), Verdict::Exit(0));
+    assert_eq!(args(&["--check"]).verdict(&warn), Verdict::Exit(0));
+    assert_eq!(args(&["--filter", "pid > 1", "--check"]).verdict(&crit), Verdict::Exit(1));
+    assert_eq!(args(&["--check", "--force"]).verdict(&crit), Verdict::Exit(0));
+}
+
+#[test]
+fn report_has_a_line_per_finding() {
+    let mut out = Vec::new();
+    write_report(&mut out, "xmount", &[]).unwrap();
+    let findings = [
+        PreflightFinding::critical("cannot read /nope: No such file or directory").remedy("set XMountConfig::mountinfo_path"),
+        PreflightFinding::warning("/mnt/usb does not exist"),
+    ];
+    write_report(&mut out, "xmount", &findings).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "xmount: preflight ok\n\
+         xmount: CRITICAL: cannot read /nope: No such file or directory (hint: set XMountConfig::mountinfo_path)\n\
+         xmount: warning: /mnt/usb does not exist\n"
+    );
+    assert_eq!(serde_json::to_value(&findings[1]).unwrap(), serde_json::json!({ "severity": "warning", "message": "/mnt/usb does not exist" }));
+}
+
+struct Idle;
+
+impl Sensor for Idle {
+    type Event = ();
+
+    fn run<R: Send + 'static>(self, _ctx: SensorCtx<(), R>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async {})
+    }
+}
+
+#[tokio::test]
+async fn sensors_have_nothing_to_check_by_default() {
+    assert!(Idle.preflight().await.is_empty());
+}
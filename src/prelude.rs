@@ -0,0 +1,12 @@
+//! The types nearly every callback or sensor implementation needs:
+//!
+//! ```ignore
+//! use omnitrace_core::prelude::*;
+//! ```
+//!
+//! Sensor crates have their own `prelude`, which includes this one.
+
+pub use crate::callbacks::{Callback, CallbackHub, CallbackResult, Envelope, FnCallback, JsonCallbackHub};
+pub use crate::labels::Labels;
+pub use crate::sensor::{Sensor, SensorCtx, SensorGroup, SensorHandle, spawn_sensor, spawn_sensor_as, supervise_sensor};
+pub use async_trait::async_trait;
@@ -0,0 +1,100 @@
+// Only the prelude is imported: this file failing to compile means the prelude lost
+// something every sensor and callback implementation needs.
+use crate::prelude::*;
+use std::{future::Future, pin::Pin, sync::Arc};
+
+struct Once;
+
+impl Sensor for Once {
+    type Event = u32;
+
+    fn run<R: Send + 'static>(self, ctx: SensorCtx<u32, R>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async move {
+            ctx.hub.fire(0b1, &7).await;
+            ctx.cancel.cancelled().await;
+        })
+    }
+}
+
+struct Echo;
+
+#[async_trait]
+impl Callback<u32> for Echo {
+    fn mask(&self) -> u64 {
+        0b1
+    }
+
+    async fn call(&self, ev: &u32) -> Option<CallbackResult> {
+        Some(serde_json::json!(ev))
+    }
+}
+
+#[tokio::test]
+async fn prelude_covers_a_sensor_and_a_callback() {
+    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+    let mut hub = CallbackHub::new();
+    hub.add(Echo);
+    hub.set_result_channel(tx);
+
+    let (handle, task): (SensorHandle, _) = spawn_sensor(Once, Arc::new(hub));
+    assert_eq!(rx.recv().await, Some(serde_json::json!(7)));
+    handle.shutdown();
+    task.await.unwrap();
+}
+
+struct Doubled;
+
+#[async_trait]
+impl Callback<u32, u64> for Doubled {
+    fn mask(&self) -> u64 {
+        0b1
+    }
+
+    async fn call(&self, ev: &u32) -> Option<u64> {
+        Some(u64::from(*ev) * 2)
+    }
+}
+
+#[tokio::test]
+async fn sensors_fire_into_hubs_of_any_result_type() {
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<u64>(1);
+    let mut hub = CallbackHub::<u32, u64>::default();
+    hub.add(Doubled);
+    hub.set_result_channel(tx);
+
+    let (handle, task) = spawn_sensor(Once, Arc::new(hub));
+    assert_eq!(rx.recv().await, Some(14));
+    handle.shutdown();
+    task.await.unwrap();
+}
+
+struct Named;
+
+#[async_trait]
+impl Callback<Envelope<u32>> for Named {
+    fn mask(&self) -> u64 {
+        0b1
+    }
+
+    async fn call(&self, env: &Envelope<u32>) -> Option<CallbackResult> {
+        Some(serde_json::json!(env.sensor))
+    }
+}
+
+#[tokio::test]
+async fn spawned_sensors_name_their_envelopes() {
+    for name in [None, Some("primary")] {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let mut hub = CallbackHub::new();
+        hub.add_enveloped(Named);
+        hub.set_result_channel(tx);
+
+        let (handle, task) = match name {
+            Some(name) => spawn_sensor_as(name, Once, Arc::new(hub)),
+            None => spawn_sensor(Once, Arc::new(hub)),
+        };
+        assert_eq!(rx.recv().await, Some(serde_json::json!(name.unwrap_or("omnitrace_core"))));
+        handle.shutdown();
+        task.await.unwrap();
+    }
+}
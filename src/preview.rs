@@ -0,0 +1,200 @@
+//! Dry runs of rule edits: what a new watch or ignore rule would match in a sensor's current
+//! state, and what would stop being reported, without changing its configuration.
+//!
+//! Each sensor has a rule type (e.g. `xmount::preview::XMountRule`), a `preview_rule` method
+//! answering from its latest snapshot, and a `preview_handle()` for asking a running sensor,
+//! which answers on its next tick. [`listen`] serves the handles on a unix socket, one
+//! `<sensor> <rule>` per line:
+//!
+//! ```text
+//! $ echo 'filescream ignore /var/cache/**' | nc -U /run/omnitrace/preview.sock
+//! {"would_match":1234,"would_stop_matching":1234,"samples":["/var/cache/apt/pkgcache.bin",...]}
+//! ```
+
+use async_trait::async_trait;
+use serde::Serialize;
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::{Arc, Mutex, Weak},
+};
+use tokio::sync::oneshot;
+
+/// Entities a [`PreviewReport`] names at most.
+pub const MAX_SAMPLES: usize = 10;
+
+/// What a rule would do to the entities a sensor sees now.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct PreviewReport {
+    /// Entities of the snapshot the rule matches, whatever the other rules say.
+    pub would_match: usize,
+    /// Entities reported now that would no longer be with the rule in place.
+    pub would_stop_matching: usize,
+    /// The first [`MAX_SAMPLES`] matched entities, sorted.
+    pub samples: Vec<String>,
+}
+
+impl PreviewReport {
+    /// Tally `(entity, matches, stops matching)` for every entity of a snapshot.
+    pub fn tally<I: IntoIterator<Item = (String, bool, bool)>>(entities: I) -> Self {
+        let mut report = Self::default();
+        for (entity, matches, stops) in entities {
+            report.would_stop_matching += usize::from(stops);
+            if matches {
+                report.would_match += 1;
+                report.samples.push(entity);
+            }
+        }
+        report.samples.sort_unstable();
+        report.samples.truncate(MAX_SAMPLES);
+        report
+    }
+}
+
+/// A rule that does not parse, see the sensors' `FromStr` impls.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum RuleError {
+    #[error("unknown rule {verb:?}, expected one of: {expected}")]
+    UnknownVerb { verb: String, expected: &'static str },
+    #[error("{verb} needs an argument")]
+    MissingArgument { verb: String },
+    #[error("invalid argument to {verb}: {reason}")]
+    InvalidArgument { verb: String, reason: String },
+}
+
+/// `<verb> <argument>`, split at the first whitespace. The argument is trimmed and non-empty.
+pub fn split_rule(s: &str) -> Result<(&str, &str), RuleError> {
+    let (verb, arg) = s.trim().split_once(char::is_whitespace).unwrap_or((s.trim(), ""));
+    match arg.trim() {
+        "" => Err(RuleError::MissingArgument { verb: verb.to_string() }),
+        arg => Ok((verb, arg)),
+    }
+}
+
+/// A preview asked through a [`PreviewHandle`], answered by the sensor.
+pub struct PreviewRequest<R> {
+    pub rule: R,
+    reply: oneshot::Sender<PreviewReport>,
+}
+
+impl<R> PreviewRequest<R> {
+    pub fn answer(self, report: PreviewReport) {
+        let _ = self.reply.send(report);
+    }
+}
+
+/// The requests a sensor owns and answers every tick. Dropping it (with the sensor)
+/// answers those still queued with `None`.
+pub struct PreviewQueue<R>(Arc<Mutex<Vec<PreviewRequest<R>>>>);
+
+impl<R> Default for PreviewQueue<R> {
+    fn default() -> Self {
+        Self(Arc::default())
+    }
+}
+
+impl<R> PreviewQueue<R> {
+    pub fn handle(&self) -> PreviewHandle<R> {
+        PreviewHandle(Arc::downgrade(&self.0))
+    }
+
+    /// The requests queued since the last call.
+    pub fn take(&self) -> Vec<PreviewRequest<R>> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+/// Cloneable handle for previewing rules against a running sensor.
+pub struct PreviewHandle<R>(Weak<Mutex<Vec<PreviewRequest<R>>>>);
+
+impl<R> Clone for PreviewHandle<R> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<R> PreviewHandle<R> {
+    /// The report for `rule`, from the sensor's next tick. None if the sensor is gone.
+    ///
+    /// Not from a callback: the sensor that fired it cannot tick until it returns (debug
+    /// builds panic, see [`crate::state`]).
+    pub async fn preview_rule(&self, rule: R) -> Option<PreviewReport> {
+        debug_assert!(!crate::callbacks::in_callback(), "PreviewHandle::preview_rule awaited from a callback, which stalls the sensor");
+        let (reply, rx) = oneshot::channel();
+        self.0.upgrade()?.lock().unwrap_or_else(|e| e.into_inner()).push(PreviewRequest { rule, reply });
+        rx.await.ok()
+    }
+}
+
+/// A [`PreviewHandle`] taking its rule as text, for [`listen`].
+#[async_trait]
+pub trait Previewer: Send + Sync {
+    async fn preview_str(&self, rule: &str) -> Result<PreviewReport, String>;
+}
+
+#[async_trait]
+impl<R> Previewer for PreviewHandle<R>
+where
+    R: FromStr + Send + 'static,
+    R::Err: Display,
+{
+    async fn preview_str(&self, rule: &str) -> Result<PreviewReport, String> {
+        let rule = rule.parse::<R>().map_err(|e| e.to_string())?;
+        self.preview_rule(rule).await.ok_or_else(|| "sensor stopped".to_string())
+    }
+}
+
+/// Answer `<sensor> <rule>` lines on a unix socket at `path` with a [`PreviewReport`] as
+/// JSON (or `error: ...`), until `cancel` fires. `sensors` are by the name the lines use.
+/// A stale socket file at `path` is replaced.
+#[cfg(unix)]
+pub fn listen<P: AsRef<std::path::Path>>(
+    path: P, sensors: Vec<(String, Arc<dyn Previewer>)>, cancel: tokio_util::sync::CancellationToken,
+) -> std::io::Result<tokio::task::JoinHandle<()>> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let path = path.as_ref().to_path_buf();
+    let _ = std::fs::remove_file(&path);
+    let listener = tokio::net::UnixListener::bind(&path)?;
+    let sensors = Arc::new(sensors);
+    Ok(tokio::spawn(async move {
+        loop {
+            let stream = tokio::select! {
+                _ = cancel.cancelled() => break,
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        log::warn!("preview: accept on {} failed: {e}", path.display());
+                        continue;
+                    }
+                },
+            };
+            let (sensors, cancel) = (sensors.clone(), cancel.clone());
+            tokio::spawn(async move {
+                let (rd, mut wr) = stream.into_split();
+                let mut lines = BufReader::new(rd).lines();
+                loop {
+                    let line = tokio::select! {
+                        _ = cancel.cancelled() => break,
+                        line = lines.next_line() => match line {
+                            Ok(Some(line)) => line,
+                            _ => break,
+                        },
+                    };
+                    let (name, rule) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+                    let reply = match sensors.iter().find(|(n, _)| n == name) {
+                        Some((_, p)) => match p.preview_str(rule.trim()).await {
+                            Ok(report) => serde_json::to_string(&report).unwrap_or_default(),
+                            Err(e) => format!("error: {e}"),
+                        },
+                        None => format!("error: unknown sensor {name:?}"),
+                    };
+                    if wr.write_all(format!("{reply}\n").as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        let _ = std::fs::remove_file(&path);
+    }))
+}
@@ -0,0 +1,106 @@
+use crate::preview::{MAX_SAMPLES, PreviewHandle, PreviewQueue, PreviewReport, Previewer, RuleError, split_rule};
+use std::{str::FromStr, sync::Arc, time::Duration};
+use tokio_util::sync::CancellationToken;
+
+/// Rule of a fake sensor whose entities are the numbers 0..100.
+#[derive(Debug)]
+struct Below(usize);
+
+impl FromStr for Below {
+    type Err = RuleError;
+
+    fn from_str(s: &str) -> Result<Self, RuleError> {
+        match split_rule(s)? {
+            ("below", n) => n.parse().map(Below).map_err(|e| RuleError::InvalidArgument { verb: "below".into(), reason: format!("{e}") }),
+            (verb, _) => Err(RuleError::UnknownVerb { verb: verb.into(), expected: "below" }),
+        }
+    }
+}
+
+/// Answer the queue every few ms, as a sensor's ticks would, until cancelled.
+fn spawn_sensor(queue: PreviewQueue<Below>, cancel: CancellationToken) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        while !cancel.is_cancelled() {
+            for req in queue.take() {
+                let n = req.rule.0;
+                // even numbers are reported now
+                req.answer(PreviewReport::tally((0..100).map(|i| (format!("{i:03}"), i < n, i < n && i % 2 == 0))));
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+}
+
+#[test]
+fn tally_counts_and_keeps_the_first_samples_sorted() {
+    let report = PreviewReport::tally((0..30).rev().map(|i| (format!("e{i:02}"), i >= 5, i >= 25)));
+    assert_eq!(report.would_match, 25);
+    assert_eq!(report.would_stop_matching, 5);
+    assert_eq!(report.samples.len(), MAX_SAMPLES);
+    assert_eq!(report.samples[0], "e05");
+    assert!(report.samples.is_sorted());
+}
+
+#[test]
+fn rules_split_into_verb_and_argument() {
+    assert_eq!(split_rule("  ignore   /var/cache/** "), Ok(("ignore", "/var/cache/**")));
+    assert_eq!(split_rule("watch"), Err(RuleError::MissingArgument { verb: "watch".into() }));
+    assert!("below x".parse::<Below>().unwrap_err().to_string().starts_with("invalid argument to below"));
+    assert!("above 3".parse::<Below>().unwrap_err().to_string().contains("expected one of: below"));
+}
+
+#[tokio::test]
+async fn handles_are_answered_by_the_sensor_and_none_once_it_is_gone() {
+    let queue = PreviewQueue::default();
+    let handle: PreviewHandle<Below> = queue.handle();
+    let cancel = CancellationToken::new();
+    let task = spawn_sensor(queue, cancel.clone());
+
+    let report = handle.preview_rule(Below(7)).await.unwrap();
+    assert_eq!(report, PreviewReport { would_match: 7, would_stop_matching: 4, samples: (0..7).map(|i| format!("{i:03}")).collect() });
+
+    cancel.cancel();
+    task.await.unwrap();
+    assert_eq!(handle.preview_rule(Below(7)).await, None);
+}
+
+#[tokio::test]
+async fn queued_requests_are_dropped_with_the_sensor() {
+    let queue = PreviewQueue::default();
+    let handle: PreviewHandle<Below> = queue.handle();
+    let asked = tokio::spawn(async move { handle.preview_rule(Below(1)).await });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    drop(queue);
+    assert_eq!(asked.await.unwrap(), None);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn socket_answers_reports_and_errors() {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let queue = PreviewQueue::default();
+    let cancel = CancellationToken::new();
+    let sensors: Vec<(String, Arc<dyn Previewer>)> = vec![("numbers".into(), Arc::new(queue.handle()))];
+    let sensor = spawn_sensor(queue, cancel.clone());
+
+    let path = std::env::temp_dir().join(format!("omnitrace-preview-ut-{}.sock", std::process::id()));
+    let task = crate::preview::listen(&path, sensors, cancel.clone()).unwrap();
+
+    let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+    let (rd, mut wr) = stream.into_split();
+    let mut lines = BufReader::new(rd).lines();
+    let mut ask = async |cmd: &str| {
+        wr.write_all(format!("{cmd}\n").as_bytes()).await.unwrap();
+        lines.next_line().await.unwrap().unwrap()
+    };
+    let report: serde_json::Value = serde_json::from_str(&ask("numbers below 2").await).unwrap();
+    assert_eq!(report, serde_json::json!({"would_match": 2, "would_stop_matching": 1, "samples": ["000", "001"]}));
+    assert!(ask("numbers above 2").await.starts_with("error: unknown rule"));
+    assert_eq!(ask("letters below 2").await, "error: unknown sensor \"letters\"");
+
+    cancel.cancel();
+    let _ = task.await;
+    sensor.await.unwrap();
+    assert!(!path.exists());
+}
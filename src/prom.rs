@@ -0,0 +1,274 @@
+use crate::{
+    callbacks::{Callback, CallbackResult},
+    entities::EntityCounters,
+};
+use async_trait::async_trait;
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// What a single event contributes to the textfile metrics.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PromObservation {
+    /// Event kind, used as the `kind` label (e.g. `"mounted"`).
+    pub kind: &'static str,
+    /// Watched entity and whether it is currently up (mounted, present, ...).
+    pub entity: Option<(String, bool)>,
+}
+
+impl PromObservation {
+    pub fn kind(kind: &'static str) -> Self {
+        Self { kind, entity: None }
+    }
+
+    pub fn entity<S: Into<String>>(mut self, entity: S, up: bool) -> Self {
+        self.entity = Some((entity.into(), up));
+        self
+    }
+}
+
+#[derive(Default)]
+struct PromState {
+    events: BTreeMap<(String, String), (u64, SystemTime)>,    // (sensor, kind) -> (count, last seen)
+    entities: BTreeMap<(String, String), (bool, SystemTime)>, // (sensor, entity) -> (up, last update)
+    health: BTreeMap<String, f64>,
+    flushes: u64,
+    flush_errors: u64,
+}
+
+/// Sink for node_exporter's textfile collector.
+///
+/// Keeps counters and gauges derived from events and periodically rewrites a `.prom` file
+/// atomically (write to a temp file in the same directory, then rename).
+/// Entities not updated within the TTL are dropped, so removed watches do not linger.
+///
+/// Exported metrics:
+///
+/// - `omnitrace_events_total{sensor,kind}` (counter)
+/// - `omnitrace_last_event_timestamp_seconds{sensor,kind}` (gauge)
+/// - `omnitrace_entity_up{sensor,entity}` (gauge, 1/0)
+/// - `omnitrace_pipeline_<name>` (gauge, see [`PromTextfile::set_health`])
+/// - `omnitrace_entity_events_total{sensor,entity,kind}` (counter), `omnitrace_entity_event_rate{sensor,entity}`
+///   (gauge, events per second over the window), `omnitrace_entity_evictions_total{sensor}` (counter),
+///   see [`PromTextfile::entity_counters`]
+/// - `omnitrace_textfile_flushes_total`, `omnitrace_textfile_flush_errors_total` (counters)
+pub struct PromTextfile {
+    path: PathBuf,
+    interval: Duration,
+    entity_ttl: Duration,
+    entity_counters: Vec<(String, EntityCounters)>,
+    state: Mutex<PromState>,
+}
+
+impl PromTextfile {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            interval: Duration::from_secs(15),
+            entity_ttl: Duration::from_secs(600),
+            entity_counters: Vec::new(),
+            state: Mutex::new(PromState::default()),
+        }
+    }
+
+    /// How often the file is rewritten (default: 15s).
+    pub fn interval(mut self, d: Duration) -> Self {
+        self.interval = d;
+        self
+    }
+
+    /// Drop entities not updated for this long (default: 10m).
+    pub fn entity_ttl(mut self, d: Duration) -> Self {
+        self.entity_ttl = d;
+        self
+    }
+
+    /// Export a sensor's per-entity counters, e.g. `XMount::entity_counters()`.
+    /// They are read when the file is rendered.
+    pub fn entity_counters(mut self, sensor: &str, counters: EntityCounters) -> Self {
+        self.entity_counters.push((sensor.to_string(), counters));
+        self
+    }
+
+    /// Count one event of `kind` from `sensor`.
+    pub fn record(&self, sensor: &str, obs: &PromObservation) {
+        self.record_at(sensor, obs, SystemTime::now());
+    }
+
+    pub(crate) fn record_at(&self, sensor: &str, obs: &PromObservation, at: SystemTime) {
+        let mut st = self.state.lock().unwrap();
+        let e = st.events.entry((sensor.to_string(), obs.kind.to_string())).or_insert((0, at));
+        e.0 += 1;
+        e.1 = at;
+
+        if let Some((entity, up)) = &obs.entity {
+            st.entities.insert((sensor.to_string(), entity.clone()), (*up, at));
+        }
+    }
+
+    /// Set the state of a watched entity directly, e.g. from a startup snapshot.
+    pub fn set_entity(&self, sensor: &str, entity: &str, up: bool) {
+        self.state.lock().unwrap().entities.insert((sensor.to_string(), entity.to_string()), (up, SystemTime::now()));
+    }
+
+    /// Set a pipeline health gauge, exported as `omnitrace_pipeline_<name>`.
+    /// Characters not valid in a metric name are replaced by `_`.
+    pub fn set_health(&self, name: &str, value: f64) {
+        self.state.lock().unwrap().health.insert(sanitize_name(name), value);
+    }
+
+    /// Render the exposition text as of now.
+    pub fn render(&self) -> String {
+        self.render_at(SystemTime::now())
+    }
+
+    pub(crate) fn render_at(&self, now: SystemTime) -> String {
+        let mut st = self.state.lock().unwrap();
+        let ttl = self.entity_ttl;
+        st.entities.retain(|_, (_, at)| now.duration_since(*at).map(|age| age <= ttl).unwrap_or(true));
+
+        let mut out = String::new();
+
+        family(&mut out, "omnitrace_events_total", "counter", "Events seen per sensor and kind.");
+        for ((sensor, kind), (count, _)) in &st.events {
+            let _ = writeln!(out, "omnitrace_events_total{{sensor=\"{}\",kind=\"{}\"}} {count}", escape(sensor), escape(kind));
+        }
+
+        family(&mut out, "omnitrace_last_event_timestamp_seconds", "gauge", "Unix time of the last event per sensor and kind.");
+        for ((sensor, kind), (_, at)) in &st.events {
+            let _ =
+                writeln!(out, "omnitrace_last_event_timestamp_seconds{{sensor=\"{}\",kind=\"{}\"}} {}", escape(sensor), escape(kind), unix_secs(*at));
+        }
+
+        family(&mut out, "omnitrace_entity_up", "gauge", "Watched entity state (1 = mounted/present, 0 = not).");
+        for ((sensor, entity), (up, _)) in &st.entities {
+            let _ = writeln!(out, "omnitrace_entity_up{{sensor=\"{}\",entity=\"{}\"}} {}", escape(sensor), escape(entity), u8::from(*up));
+        }
+
+        if !self.entity_counters.is_empty() {
+            let reports: Vec<_> = self.entity_counters.iter().map(|(sensor, c)| (escape(sensor), c.report())).collect();
+            family(&mut out, "omnitrace_entity_events_total", "counter", "Events per sensor, watched entity and kind.");
+            for (sensor, r) in &reports {
+                for e in &r.entities {
+                    for (kind, n) in &e.by_kind {
+                        let _ = writeln!(
+                            out,
+                            "omnitrace_entity_events_total{{sensor=\"{sensor}\",entity=\"{}\",kind=\"{}\"}} {n}",
+                            escape(&e.entity),
+                            escape(kind)
+                        );
+                    }
+                }
+            }
+            family(&mut out, "omnitrace_entity_event_rate", "gauge", "Recent events per second per sensor and watched entity.");
+            for (sensor, r) in &reports {
+                for e in &r.entities {
+                    let _ = writeln!(out, "omnitrace_entity_event_rate{{sensor=\"{sensor}\",entity=\"{}\"}} {}", escape(&e.entity), e.rate_per_sec);
+                }
+            }
+            family(&mut out, "omnitrace_entity_evictions_total", "counter", "Entities dropped from the bounded per-entity counters.");
+            for (sensor, r) in &reports {
+                let _ = writeln!(out, "omnitrace_entity_evictions_total{{sensor=\"{sensor}\"}} {}", r.evicted);
+            }
+        }
+
+        for (name, value) in &st.health {
+            let metric = format!("omnitrace_pipeline_{name}");
+            family(&mut out, &metric, "gauge", "Pipeline health.");
+            let _ = writeln!(out, "{metric} {value}");
+        }
+
+        family(&mut out, "omnitrace_textfile_flushes_total", "counter", "Successful rewrites of this file, this one included.");
+        let _ = writeln!(out, "omnitrace_textfile_flushes_total {}", st.flushes);
+        family(&mut out, "omnitrace_textfile_flush_errors_total", "counter", "Failed rewrites of this file.");
+        let _ = writeln!(out, "omnitrace_textfile_flush_errors_total {}", st.flush_errors);
+
+        out
+    }
+
+    /// Rewrite the file now (atomically). The file counts itself among the flushes.
+    pub fn flush(&self) -> io::Result<()> {
+        self.state.lock().unwrap().flushes += 1;
+        let text = self.render();
+        let tmp = self.path.with_extension("prom.tmp");
+        let res = std::fs::write(&tmp, text).and_then(|_| std::fs::rename(&tmp, &self.path));
+
+        if res.is_err() {
+            let mut st = self.state.lock().unwrap();
+            st.flushes -= 1;
+            st.flush_errors += 1;
+        }
+        res
+    }
+
+    /// Flush on every interval until `cancel` fires, then flush one last time.
+    pub fn spawn(self: Arc<Self>, cancel: CancellationToken) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.interval);
+            loop {
+                tokio::select! {
+                    _ = cancel.cancelled() => break,
+                    _ = ticker.tick() => {}
+                }
+                if let Err(e) = self.flush() {
+                    log::error!("prom: failed to write {}: {e}", self.path.display());
+                }
+            }
+            if let Err(e) = self.flush() {
+                log::error!("prom: failed to write {}: {e}", self.path.display());
+            }
+        })
+    }
+
+    /// Create a callback feeding a sensor hub into this sink.
+    /// `observe` maps an event to its kind and (optionally) the entity state it implies.
+    pub fn callback<E>(self: &Arc<Self>, sensor: &str, observe: fn(&E) -> PromObservation) -> PromCallback<E> {
+        PromCallback { sink: self.clone(), sensor: sensor.to_string(), observe }
+    }
+}
+
+/// Callback recording every event it receives into a [`PromTextfile`].
+pub struct PromCallback<E> {
+    sink: Arc<PromTextfile>,
+    sensor: String,
+    observe: fn(&E) -> PromObservation,
+}
+
+#[async_trait]
+impl<E> Callback<E> for PromCallback<E>
+where
+    E: Send + Sync,
+{
+    fn mask(&self) -> u64 {
+        u64::MAX
+    }
+
+    async fn call(&self, ev: &E) -> Option<CallbackResult> {
+        self.sink.record(&self.sensor, &(self.observe)(ev));
+        None
+    }
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn escape(v: &str) -> String {
+    v.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn sanitize_name(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' }).collect()
+}
+
+fn unix_secs(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
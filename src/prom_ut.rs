@@ -0,0 +1,139 @@
+use crate::{
+    callbacks::CallbackHub,
+    prom::{PromObservation, PromTextfile},
+};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Minimal text exposition format checker: every sample belongs to a family declared
+/// by a preceding `# TYPE`, names and label names are valid, label values are quoted
+/// and escaped, values parse as floats. Returns samples as `name{labels}` -> value.
+fn check_exposition(text: &str) -> HashMap<String, f64> {
+    fn valid_name(s: &str) -> bool {
+        let mut chars = s.chars();
+        chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+    }
+
+    let mut types: HashMap<String, String> = HashMap::new();
+    let mut samples = HashMap::new();
+
+    for line in text.lines() {
+        if let Some(rest) = line.strip_prefix("# TYPE ") {
+            let (name, kind) = rest.split_once(' ').expect("TYPE without kind");
+            assert!(valid_name(name), "bad metric name {name}");
+            assert!(["counter", "gauge", "histogram", "summary", "untyped"].contains(&kind), "bad type {kind}");
+            assert!(types.insert(name.to_string(), kind.to_string()).is_none(), "duplicate TYPE for {name}");
+            continue;
+        }
+        if line.starts_with("# HELP ") || line.is_empty() {
+            continue;
+        }
+        assert!(!line.starts_with('#'), "stray comment {line}");
+
+        let (series, value) = line.rsplit_once(' ').expect("sample without value");
+        let value: f64 = value.parse().unwrap_or_else(|_| panic!("bad value in {line}"));
+
+        let name = match series.split_once('{') {
+            None => series,
+            Some((name, labels)) => {
+                let mut labels = labels.strip_suffix('}').expect("unterminated labels");
+                while !labels.is_empty() {
+                    let (lname, rest) = labels.split_once("=\"").expect("label without value");
+                    assert!(valid_name(lname), "bad label name {lname}");
+
+                    // scan to the closing, unescaped quote
+                    let mut end = None;
+                    let mut escaped = false;
+                    for (i, c) in rest.char_indices() {
+                        match (escaped, c) {
+                            (true, '\\' | '"' | 'n') => escaped = false,
+                            (true, _) => panic!("bad escape in {line}"),
+                            (false, '\\') => escaped = true,
+                            (false, '"') => {
+                                end = Some(i);
+                                break;
+                            }
+                            (false, '\n') => panic!("raw newline in {line}"),
+                            _ => {}
+                        }
+                    }
+                    let end = end.expect("unterminated label value");
+                    labels = rest[end + 1..].strip_prefix(',').unwrap_or(&rest[end + 1..]);
+                }
+                name
+            }
+        };
+
+        assert!(types.contains_key(name), "sample {name} without preceding TYPE");
+        samples.insert(series.to_string(), value);
+    }
+
+    samples
+}
+
+fn t(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+#[test]
+fn renders_valid_exposition() {
+    let sink = PromTextfile::new("/nonexistent/omnitrace.prom");
+
+    sink.record_at("xmount", &PromObservation::kind("mounted").entity("/mnt/data", true), t(1000));
+    sink.record_at("xmount", &PromObservation::kind("unmounted").entity("/mnt/usb", false), t(1010));
+    sink.record_at("xmount", &PromObservation::kind("mounted").entity("/mnt/data", true), t(1020));
+    sink.record_at("procdog", &PromObservation::kind("missing").entity("ss\"h\\d", false), t(1030));
+    sink.set_health("results dropped", 3.0);
+
+    let samples = check_exposition(&sink.render_at(t(1040)));
+
+    assert_eq!(samples["omnitrace_events_total{sensor=\"xmount\",kind=\"mounted\"}"], 2.0);
+    assert_eq!(samples["omnitrace_events_total{sensor=\"xmount\",kind=\"unmounted\"}"], 1.0);
+    assert_eq!(samples["omnitrace_last_event_timestamp_seconds{sensor=\"xmount\",kind=\"mounted\"}"], 1020.0);
+    assert_eq!(samples["omnitrace_entity_up{sensor=\"xmount\",entity=\"/mnt/data\"}"], 1.0);
+    assert_eq!(samples["omnitrace_entity_up{sensor=\"xmount\",entity=\"/mnt/usb\"}"], 0.0);
+    assert_eq!(samples["omnitrace_entity_up{sensor=\"procdog\",entity=\"ss\\\"h\\\\d\"}"], 0.0);
+    assert_eq!(samples["omnitrace_pipeline_results_dropped"], 3.0);
+    assert_eq!(samples["omnitrace_textfile_flushes_total"], 0.0);
+}
+
+#[test]
+fn stale_entities_age_out() {
+    let sink = PromTextfile::new("/nonexistent/omnitrace.prom").entity_ttl(Duration::from_secs(60));
+
+    sink.record_at("xmount", &PromObservation::kind("mounted").entity("/mnt/old", true), t(1000));
+    sink.record_at("xmount", &PromObservation::kind("mounted").entity("/mnt/new", true), t(1100));
+
+    let samples = check_exposition(&sink.render_at(t(1120)));
+
+    assert!(!samples.contains_key("omnitrace_entity_up{sensor=\"xmount\",entity=\"/mnt/old\"}"));
+    assert_eq!(samples["omnitrace_entity_up{sensor=\"xmount\",entity=\"/mnt/new\"}"], 1.0);
+    // event counters do not age out
+    assert_eq!(samples["omnitrace_events_total{sensor=\"xmount\",kind=\"mounted\"}"], 2.0);
+}
+
+#[tokio::test]
+async fn callback_feeds_sink_and_flush_is_atomic() {
+    let path = std::env::temp_dir().join(format!("omnitrace-prom-ut-{}.prom", std::process::id()));
+    let sink = Arc::new(PromTextfile::new(&path));
+
+    let mut hub = CallbackHub::<(&'static str, bool)>::new();
+    hub.add(
+        sink.callback("procdog", |ev: &(&'static str, bool)| PromObservation::kind(if ev.1 { "appeared" } else { "missing" }).entity(ev.0, ev.1)),
+    );
+    hub.fire(1, &("sshd", false)).await;
+    hub.fire(1, &("sshd", true)).await;
+
+    sink.flush().unwrap();
+    let samples = check_exposition(&std::fs::read_to_string(&path).unwrap());
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(samples["omnitrace_entity_up{sensor=\"procdog\",entity=\"sshd\"}"], 1.0);
+    assert_eq!(samples["omnitrace_events_total{sensor=\"procdog\",kind=\"missing\"}"], 1.0);
+    assert_eq!(samples["omnitrace_textfile_flushes_total"], 1.0, "the file counts itself");
+    assert!(!path.with_extension("prom.tmp").exists());
+}
@@ -0,0 +1,282 @@
+//! Sensor pacing: adaptive polling intervals and time gaps.
+//!
+//! A fixed pulse wastes CPU on idle hosts and adds latency on busy ones. With an
+//! [`AdaptivePulse`] a sensor polls at `min` while something happens, and stretches its
+//! pulse by `growth` after every `idle_ticks` ticks in a row that fired no event, up to
+//! `max`. The first tick that fires an event snaps it back to `min`.
+//!
+//! Sensors keep their pulse in a [`Pacer`], which also applies the degraded profile (see
+//! [`crate::degrade`]) and publishes the pulse in use as an [`EffectivePulse`].
+//!
+//! Given a clock, the pacer also notices ticks that came much later than due or whose wall
+//! clock moved unlike the monotonic one: a suspend and resume, a stalled process or an NTP
+//! step. Diffs spanning such a gap bunch up hours of changes, and durations measured across
+//! it are wrong, so sensors report it as a [`TimeAnomaly`] and can react, see [`TimeGaps`].
+
+use crate::{clock::SharedClock, degrade::Profile};
+use serde::Serialize;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
+use tokio::time::{self, Instant, Interval};
+
+/// Adaptive pulse policy, e.g. `XMountConfig::default().adaptive(Duration::from_millis(200), Duration::from_secs(5))`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AdaptivePulse {
+    pub min: Duration,
+    pub max: Duration,
+    /// Ticks in a row without events before the pulse grows.
+    pub idle_ticks: u32,
+    /// Factor the pulse grows by, at least 2.
+    pub growth: u32,
+}
+
+impl AdaptivePulse {
+    /// Double the pulse after every 3 idle ticks, from `min` up to `max`.
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self { min, max: max.max(min), idle_ticks: 3, growth: 2 }
+    }
+
+    pub fn idle_ticks(mut self, n: u32) -> Self {
+        self.idle_ticks = n.max(1);
+        self
+    }
+
+    pub fn growth(mut self, factor: u32) -> Self {
+        self.growth = factor.max(2);
+        self
+    }
+}
+
+/// The pulse a sensor currently polls at, e.g. `ProcDog::effective_pulse`. Cheap to clone.
+#[derive(Clone, Debug, Default)]
+pub struct EffectivePulse(Arc<AtomicU64>);
+
+impl EffectivePulse {
+    pub fn get(&self) -> Duration {
+        Duration::from_nanos(self.0.load(Ordering::Relaxed))
+    }
+
+    fn set(&self, pulse: Duration) {
+        self.0.store(pulse.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum TimeGapKind {
+    /// The host was suspended, or the sensor did not run (stopped, VM paused).
+    Suspend,
+    /// The wall clock was set, e.g. by NTP.
+    ClockStep,
+}
+
+/// A gap between two ticks, recorded as `"time"` in the sensor's diagnostics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, thiserror::Error)]
+#[error("{}", self.describe())]
+pub struct TimeAnomaly {
+    pub kind: TimeGapKind,
+    pub magnitude: Duration,
+    /// The wall clock went back (`ClockStep` only).
+    pub backward: bool,
+}
+
+impl TimeAnomaly {
+    fn describe(&self) -> String {
+        match (self.kind, self.backward) {
+            (TimeGapKind::Suspend, _) => format!("suspended or stalled for {:?}", self.magnitude),
+            (TimeGapKind::ClockStep, false) => format!("wall clock stepped forward by {:?}", self.magnitude),
+            (TimeGapKind::ClockStep, true) => format!("wall clock stepped back by {:?}", self.magnitude),
+        }
+    }
+}
+
+/// Time gap detection and what a sensor does about one, e.g. `XMountConfig::time_gaps`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeGaps {
+    /// Smallest gap reported: how much later than due a tick may come, or how far the wall
+    /// clock may drift from the monotonic one between two ticks (default 30s).
+    pub threshold: Duration,
+    /// Take the state after the gap as the new baseline instead of reporting the difference.
+    pub reprime: bool,
+}
+
+impl Default for TimeGaps {
+    fn default() -> Self {
+        Self { threshold: Duration::from_secs(30), reprime: false }
+    }
+}
+
+impl TimeGaps {
+    pub fn new(threshold: Duration) -> Self {
+        Self { threshold, ..Self::default() }
+    }
+
+    pub fn reprime(mut self, on: bool) -> Self {
+        self.reprime = on;
+        self
+    }
+}
+
+/// Clock readings at a tick.
+#[derive(Clone, Copy)]
+struct Stamp {
+    mono: std::time::Instant,
+    wall: SystemTime,
+    boot: Option<Duration>,
+}
+
+struct GapWatch {
+    clock: SharedClock,
+    threshold: Duration,
+    last: Option<Stamp>,
+    // when the sensor went back to waiting after the last tick's work
+    waiting: Option<std::time::Instant>,
+}
+
+impl GapWatch {
+    fn check(&mut self, expected: Duration) -> Option<TimeAnomaly> {
+        let now = Stamp { mono: self.clock.now_instant(), wall: self.clock.now_system(), boot: self.clock.since_boot() };
+        let waiting = self.waiting.take();
+        let last = self.last.replace(now)?;
+        let mono = now.mono.saturating_duration_since(last.mono);
+        let boot = now.boot.zip(last.boot).map(|(n, l)| n.saturating_sub(l));
+        let suspend = |magnitude| Some(TimeAnomaly { kind: TimeGapKind::Suspend, magnitude, backward: false });
+
+        if let Some(boot) = boot
+            && boot > mono + self.threshold
+        {
+            return suspend(boot - mono);
+        }
+        // a slow tick is not late: only the wait for this one counts
+        let late = now.mono.saturating_duration_since(waiting.unwrap_or(last.mono));
+        if late > expected + self.threshold {
+            return suspend(late - expected);
+        }
+
+        // the wall clock against a clock nobody sets
+        let reference = boot.unwrap_or(mono);
+        let (magnitude, backward) = match now.wall.duration_since(last.wall) {
+            Ok(wall) => (wall.abs_diff(reference), wall < reference),
+            Err(e) => (e.duration() + reference, true),
+        };
+        (magnitude > self.threshold).then_some(TimeAnomaly { kind: TimeGapKind::ClockStep, magnitude, backward })
+    }
+}
+
+/// A sensor's pulse: fixed or adaptive, stretched while degraded.
+pub struct Pacer {
+    adaptive: Option<AdaptivePulse>,
+    // fixed pulse, or the adaptive one
+    base: Duration,
+    idle: u32,
+    // hub total at the last observe_fired()
+    fired: Option<u64>,
+    profile: Profile,
+    shared: EffectivePulse,
+    gaps: Option<GapWatch>,
+}
+
+impl Pacer {
+    /// Poll at `pulse`, or adaptively starting at `adaptive.min`.
+    pub fn new(pulse: Duration, adaptive: Option<AdaptivePulse>) -> Self {
+        let p = Self {
+            adaptive,
+            base: adaptive.map_or(pulse, |a| a.min),
+            idle: 0,
+            fired: None,
+            profile: Profile::Full,
+            shared: EffectivePulse::default(),
+            gaps: None,
+        };
+        p.shared.set(p.pulse());
+        p
+    }
+
+    /// Watch for time gaps of at least `threshold` on `clock`, see [`Pacer::check_gap`].
+    pub fn detect_gaps(mut self, clock: SharedClock, threshold: Duration) -> Self {
+        self.gaps = Some(GapWatch { clock, threshold, last: None, waiting: None });
+        self
+    }
+
+    /// Compare the clocks with the last call. Sensors call it right after each tick; the
+    /// first call only takes the readings.
+    pub fn check_gap(&mut self) -> Option<TimeAnomaly> {
+        let expected = self.pulse();
+        self.gaps.as_mut()?.check(expected)
+    }
+
+    /// Note that the tick's work is done. Sensors call it before waiting for the next tick,
+    /// so the next [`Pacer::check_gap`] measures how late that tick came from here and a
+    /// slow tick, e.g. one waiting on callbacks, is not taken for a suspend.
+    pub fn waiting(&mut self) {
+        if let Some(g) = self.gaps.as_mut() {
+            g.waiting = Some(g.clock.now_instant());
+        }
+    }
+
+    /// The pulse in use.
+    pub fn pulse(&self) -> Duration {
+        self.profile.pulse(self.base)
+    }
+
+    pub fn handle(&self) -> EffectivePulse {
+        self.shared.clone()
+    }
+
+    /// Follow `profile`. Returns true if the pulse changed, see [`Pacer::restart`].
+    pub fn set_profile(&mut self, profile: Profile) -> bool {
+        let before = self.pulse();
+        self.profile = profile;
+        self.publish(before)
+    }
+
+    /// Account a tick that fired `events`. Returns true if the pulse changed, see [`Pacer::restart`].
+    pub fn observe(&mut self, events: u64) -> bool {
+        let Some(a) = self.adaptive else {
+            return false;
+        };
+        let before = self.pulse();
+        if events > 0 {
+            self.idle = 0;
+            self.base = a.min;
+        } else {
+            self.idle += 1;
+            if self.idle >= a.idle_ticks {
+                self.idle = 0;
+                self.base = (self.base * a.growth).min(a.max);
+            }
+        }
+        self.publish(before)
+    }
+
+    /// [`Pacer::observe`] the tick since the last call, given the hub's running total of
+    /// fired events (see [`crate::callbacks::CallbackHub::fired`]). The first call only takes
+    /// the baseline. Sensors call it before waiting for the next tick.
+    pub fn observe_fired(&mut self, fired: u64) -> bool {
+        match self.fired.replace(fired) {
+            Some(before) => self.observe(fired.saturating_sub(before)),
+            None => false,
+        }
+    }
+
+    fn publish(&self, before: Duration) -> bool {
+        self.shared.set(self.pulse());
+        self.pulse() != before
+    }
+
+    /// A ticker at the pulse in use, ticking at once.
+    pub fn ticker(&self) -> Interval {
+        time::interval(self.pulse())
+    }
+
+    /// Restart `ticker` at the pulse in use, one pulse from now. Its missed tick behavior is kept.
+    pub fn restart(&self, ticker: &mut Interval) {
+        let behavior = ticker.missed_tick_behavior();
+        *ticker = time::interval_at(Instant::now() + self.pulse(), self.pulse());
+        ticker.set_missed_tick_behavior(behavior);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{
+    clock::{Clock, ManualClock},
+    degrade::Profile,
+    error::Diagnostics,
+    pulse::{AdaptivePulse, Pacer, TimeAnomaly, TimeGapKind},
+};
+use std::time::Duration;
+use tokio::time::{Instant, MissedTickBehavior};
+
+const MS: Duration = Duration::from_millis(1);
+
+#[test]
+fn idle_ticks_back_off_and_activity_snaps_back() {
+    let mut p = Pacer::new(MS * 1000, Some(AdaptivePulse::new(MS * 10, MS * 70).idle_ticks(2)));
+    let shared = p.handle();
+    assert_eq!(shared.get(), MS * 10, "adaptive starts at min, not at the fixed pulse");
+
+    let mut curve = Vec::new();
+    for _ in 0..8 {
+        p.observe(0);
+        curve.push(p.pulse().as_millis());
+    }
+    assert_eq!(curve, [10, 20, 20, 40, 40, 70, 70, 70]);
+    assert_eq!(shared.get(), MS * 70);
+
+    assert!(p.observe(3));
+    assert_eq!(shared.get(), MS * 10);
+    // a busy tick also restarts the idle count
+    assert!(!p.observe(0));
+    assert!(p.observe(0));
+    assert_eq!(p.pulse(), MS * 20);
+
+    // from the hub's running total: the first call takes the baseline
+    assert!(!p.observe_fired(40));
+    assert!(p.observe_fired(41));
+    assert_eq!(p.pulse(), MS * 10);
+}
+
+#[test]
+fn degraded_profile_stretches_either_pulse() {
+    let mut fixed = Pacer::new(MS * 100, None);
+    assert!(!fixed.observe(0));
+    assert!(fixed.set_profile(Profile::Degraded));
+    assert_eq!(fixed.handle().get(), MS * 200);
+
+    let mut adaptive = Pacer::new(MS, Some(AdaptivePulse::new(MS * 10, MS * 40).idle_ticks(1).growth(4)));
+    adaptive.set_profile(Profile::Degraded);
+    adaptive.observe(0);
+    assert_eq!(adaptive.pulse(), MS * 80);
+    assert!(adaptive.set_profile(Profile::Full));
+    assert_eq!(adaptive.pulse(), MS * 40);
+}
+
+#[tokio::test(start_paused = true)]
+async fn restart_keeps_the_missed_tick_behavior() {
+    let mut p = Pacer::new(MS, Some(AdaptivePulse::new(MS * 10, MS * 40).idle_ticks(1)));
+    let mut ticker = p.ticker();
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    ticker.tick().await;
+
+    let start = Instant::now();
+    p.observe(0);
+    p.restart(&mut ticker);
+    ticker.tick().await;
+    assert_eq!(start.elapsed(), MS * 20);
+    assert_eq!(ticker.missed_tick_behavior(), MissedTickBehavior::Skip);
+}
+
+#[test]
+fn gaps_are_told_apart_by_the_clocks_that_moved() {
+    let clock = ManualClock::new();
+    let mut p = Pacer::new(MS * 1000, None).detect_gaps(clock.shared(), Duration::from_secs(30));
+    let gap = |kind, secs, backward| Some(TimeAnomaly { kind, magnitude: Duration::from_secs(secs), backward });
+    assert_eq!(p.check_gap(), None, "the first call takes the readings");
+
+    // late ticks within the threshold are fine
+    clock.advance(Duration::from_secs(20));
+    assert_eq!(p.check_gap(), None);
+
+    // a laptop lid closed overnight: only the monotonic clock stood still
+    clock.suspend(Duration::from_secs(9 * 3600));
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(p.check_gap(), gap(TimeGapKind::Suspend, 9 * 3600, false));
+
+    // SIGSTOP or a paused VM: the tick came 2 minutes late by every clock
+    clock.advance(Duration::from_secs(121));
+    assert_eq!(p.check_gap(), gap(TimeGapKind::Suspend, 120, false));
+
+    // NTP steps, either way
+    clock.advance(Duration::from_secs(1));
+    clock.set_system(clock.now_system() + Duration::from_secs(300));
+    assert_eq!(p.check_gap(), gap(TimeGapKind::ClockStep, 300, false));
+    clock.advance(Duration::from_secs(1));
+    clock.set_system(clock.now_system() - Duration::from_secs(3600));
+    let back = p.check_gap().unwrap();
+    assert_eq!(back, gap(TimeGapKind::ClockStep, 3600, true).unwrap());
+
+    let d = Diagnostics::new();
+    d.report("time", back);
+    assert_eq!(d.get("time").unwrap().message, "wall clock stepped back by 3600s");
+}
+
+#[test]
+fn a_slow_tick_is_not_a_suspend() {
+    let clock = ManualClock::new();
+    let mut p = Pacer::new(MS * 1000, None).detect_gaps(clock.shared(), Duration::from_secs(30));
+    assert_eq!(p.check_gap(), None);
+
+    // the tick's work takes 5 minutes, e.g. a barrier waiting on a service stop; the next
+    // tick is due right after it
+    clock.advance(Duration::from_secs(300));
+    p.waiting();
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(p.check_gap(), None);
+
+    // late after the wait is still a suspend
+    p.waiting();
+    clock.advance(Duration::from_secs(121));
+    assert_eq!(p.check_gap(), Some(TimeAnomaly { kind: TimeGapKind::Suspend, magnitude: Duration::from_secs(120), backward: false }));
+
+    // and so is a suspend during the work, seen by the boot clock
+    clock.suspend(Duration::from_secs(3600));
+    p.waiting();
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(p.check_gap(), Some(TimeAnomaly { kind: TimeGapKind::Suspend, magnitude: Duration::from_secs(3600), backward: false }));
+}
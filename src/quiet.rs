@@ -0,0 +1,470 @@
+//! Maintenance windows ("quiet hours"): events fired within one are delivered as usual, but
+//! tagged suppressed, so a pager can skip them while logs keep them.
+//!
+//! A [`QuietWindow`] is a time span, either explicit (`start` to `end`) or recurring (a cron
+//! expression for when it starts, in UTC, and how long it lasts), and a selector: the
+//! sensor, the mask bits and a filter expression (see [`crate::filter`]) the events must
+//! match, each optional. A hub with [`crate::callbacks::CallbackHub::set_quiet_hours`]
+//! checks every event against the active windows. For one selected by any of them, callbacks
+//! see [`crate::callbacks::is_quiet`] return true and JSON object results get
+//! `"suppressed": true`; [`crate::callbacks::SkipQuiet`] wraps a callback that should not
+//! run at all then.
+//!
+//! Entering and leaving a window are [`QuietMarker`]s, sent to [`QuietHours::subscribe`]rs
+//! as the hub or [`QuietHours::poll`] notices them; leaving one with `digest` on carries a
+//! [`QuietDigest`] of what it suppressed. Windows are added, replaced and removed while hubs
+//! use them, e.g. from a control endpoint, or loaded from JSON:
+//!
+//! ```json
+//! [
+//!   {"name": "backup", "cron": "0 2 * * 0", "duration_secs": 7200, "sensor": "xmount", "digest": true},
+//!   {"name": "db-move", "start": 1767225600, "end": 1767240000, "filter": "target ~ \"/srv/db*\""}
+//! ]
+//! ```
+
+use crate::{
+    callbacks::unix_secs,
+    clock::{self, SharedClock},
+    filter::{Filter, FilterError},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{sync::broadcast, task::JoinHandle};
+
+/// Markers a [`QuietHours::subscribe`]r can fall behind by before it lags.
+pub const MARKER_CAPACITY: usize = 64;
+
+#[derive(Debug, thiserror::Error)]
+pub enum QuietError {
+    #[error("window {window}: bad cron expression {expr:?}: {reason}")]
+    Cron { window: String, expr: String, reason: String },
+    #[error("window {window}: bad filter: {source}")]
+    Filter {
+        window: String,
+        #[source]
+        source: FilterError,
+    },
+    #[error("window {window}: needs either cron and duration_secs, or start and end")]
+    Schedule { window: String },
+    #[error("bad quiet windows: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// A maintenance window, see the [module docs](self).
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "WindowSpec")]
+pub struct QuietWindow {
+    name: String,
+    schedule: Schedule,
+    sensor: Option<String>,
+    mask: u64,
+    filter: Option<Filter>,
+    digest: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Schedule {
+    Between(SystemTime, SystemTime),
+    // starts, and minutes it lasts
+    Cron(Cron, u64),
+}
+
+impl QuietWindow {
+    /// From `start` until (not including) `end`.
+    pub fn between<S: Into<String>>(name: S, start: SystemTime, end: SystemTime) -> Self {
+        Self::with(name.into(), Schedule::Between(start, end))
+    }
+
+    /// Starting at every minute the five-field cron expression `expr` (minute, hour, day of
+    /// month, month, day of week; `*`, lists, ranges and steps) matches in UTC, for
+    /// `duration`, rounded up to whole minutes.
+    pub fn cron<S: Into<String>>(name: S, expr: &str, duration: Duration) -> Result<Self, QuietError> {
+        let name = name.into();
+        let cron = Cron::parse(expr).map_err(|reason| QuietError::Cron { window: name.clone(), expr: expr.to_string(), reason })?;
+        Ok(Self::with(name, Schedule::Cron(cron, duration.as_secs().div_ceil(60))))
+    }
+
+    fn with(name: String, schedule: Schedule) -> Self {
+        Self { name, schedule, sensor: None, mask: 0, filter: None, digest: false }
+    }
+
+    /// Only events of the hub with this sensor name, see
+    /// [`crate::callbacks::CallbackHub::set_sensor_name`].
+    pub fn sensor<S: Into<String>>(mut self, name: S) -> Self {
+        self.sensor = Some(name.into());
+        self
+    }
+
+    /// Only events with any of these mask bits; 0 (the default) for all.
+    pub fn mask(mut self, mask: u64) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    /// Only events the filter matches, evaluated on the serialized event.
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Summarize what was suppressed in the marker for leaving the window.
+    pub fn digest(mut self, on: bool) -> Self {
+        self.digest = on;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn active(&self, now: SystemTime) -> bool {
+        match &self.schedule {
+            Schedule::Between(start, end) => *start <= now && now < *end,
+            Schedule::Cron(cron, minutes) => {
+                let minute = now.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() / 60);
+                (0..*minutes).take_while(|back| *back <= minute).any(|back| cron.matches(minute - back))
+            }
+        }
+    }
+
+    fn selects(&self, sensor: Option<&str>, mask: u64) -> bool {
+        self.sensor.as_deref().is_none_or(|s| sensor == Some(s)) && (self.mask == 0 || self.mask & mask != 0)
+    }
+}
+
+/// A window as written in JSON.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WindowSpec {
+    name: String,
+    cron: Option<String>,
+    duration_secs: Option<u64>,
+    /// Unix seconds.
+    start: Option<u64>,
+    end: Option<u64>,
+    sensor: Option<String>,
+    #[serde(default)]
+    mask: u64,
+    filter: Option<String>,
+    #[serde(default)]
+    digest: bool,
+}
+
+impl TryFrom<WindowSpec> for QuietWindow {
+    type Error = QuietError;
+
+    fn try_from(spec: WindowSpec) -> Result<Self, QuietError> {
+        let at = |secs: u64| UNIX_EPOCH + Duration::from_secs(secs);
+        let mut w = match (spec.cron, spec.duration_secs, spec.start, spec.end) {
+            (Some(expr), Some(secs), None, None) => QuietWindow::cron(spec.name, &expr, Duration::from_secs(secs))?,
+            (None, None, Some(start), Some(end)) => QuietWindow::between(spec.name, at(start), at(end)),
+            _ => return Err(QuietError::Schedule { window: spec.name }),
+        };
+        if let Some(expr) = spec.filter {
+            let filter = Filter::parse(&expr).map_err(|source| QuietError::Filter { window: w.name.clone(), source })?;
+            w = w.filter(filter);
+        }
+        w.sensor = spec.sensor;
+        Ok(w.mask(spec.mask).digest(spec.digest))
+    }
+}
+
+/// Entering or leaving a window.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum QuietMarker {
+    Entered {
+        window: String,
+        /// Serialized as Unix seconds.
+        #[serde(serialize_with = "unix_secs")]
+        at: SystemTime,
+    },
+    /// Also sent for an active window removed or replaced.
+    Left {
+        window: String,
+        #[serde(serialize_with = "unix_secs")]
+        at: SystemTime,
+        /// If the window has `digest` on.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        digest: Option<QuietDigest>,
+    },
+}
+
+/// What a window suppressed while it was active.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct QuietDigest {
+    /// When the window was entered; serialized as Unix seconds.
+    #[serde(serialize_with = "unix_secs")]
+    pub since: SystemTime,
+    pub suppressed: u64,
+    pub by_sensor: BTreeMap<String, u64>,
+    /// Per single mask bit, as in [`crate::callbacks::HubMetricsSnapshot::fired_by_mask`].
+    pub by_mask: BTreeMap<u64, u64>,
+}
+
+impl QuietDigest {
+    fn new(since: SystemTime) -> Self {
+        Self { since, suppressed: 0, by_sensor: BTreeMap::new(), by_mask: BTreeMap::new() }
+    }
+}
+
+struct Slot {
+    window: QuietWindow,
+    // minute of the last check of a cron window, and whether it was active then
+    cached: Option<(u64, bool)>,
+    // while active
+    entered: Option<QuietDigest>,
+}
+
+impl Slot {
+    fn active(&mut self, now: SystemTime) -> bool {
+        if !matches!(self.window.schedule, Schedule::Cron(..)) {
+            return self.window.active(now);
+        }
+        let minute = now.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() / 60);
+        match self.cached {
+            Some((at, active)) if at == minute => active,
+            _ => {
+                let active = self.window.active(now);
+                self.cached = Some((minute, active));
+                active
+            }
+        }
+    }
+
+    fn leave(&mut self, at: SystemTime) -> Option<QuietMarker> {
+        let digest = self.entered.take()?;
+        Some(QuietMarker::Left { window: self.window.name.clone(), at, digest: self.window.digest.then_some(digest) })
+    }
+}
+
+struct Inner {
+    clock: SharedClock,
+    slots: Mutex<Vec<Slot>>,
+    markers: broadcast::Sender<QuietMarker>,
+}
+
+/// The windows hubs check their events against. Clones share them.
+#[derive(Clone)]
+pub struct QuietHours(Arc<Inner>);
+
+impl Default for QuietHours {
+    fn default() -> Self {
+        Self::new(clock::system())
+    }
+}
+
+impl QuietHours {
+    /// No windows yet, reading the time from `clock`.
+    pub fn new(clock: SharedClock) -> Self {
+        Self(Arc::new(Inner { clock, slots: Mutex::new(Vec::new()), markers: broadcast::channel(MARKER_CAPACITY).0 }))
+    }
+
+    /// Add `window`, replacing one of the same name.
+    pub fn add(&self, window: QuietWindow) {
+        self.edit(|slots, out, now| {
+            out.extend(slots.iter_mut().filter(|s| s.window.name == window.name).filter_map(|s| s.leave(now)));
+            slots.retain(|s| s.window.name != window.name);
+            slots.push(Slot { window, cached: None, entered: None });
+        });
+    }
+
+    /// Remove the window `name`, false if there is none.
+    pub fn remove(&self, name: &str) -> bool {
+        let mut found = false;
+        self.edit(|slots, out, now| {
+            out.extend(slots.iter_mut().filter(|s| s.window.name == name).filter_map(|s| s.leave(now)));
+            found = slots.iter().any(|s| s.window.name == name);
+            slots.retain(|s| s.window.name != name);
+        });
+        found
+    }
+
+    /// Replace all windows with those of a JSON array, see the [module docs](self). On an
+    /// error, the windows stay as they were.
+    pub fn load(&self, json: &str) -> Result<(), QuietError> {
+        let windows: Vec<QuietWindow> = serde_json::from_str(json)?;
+        self.edit(|slots, out, now| {
+            out.extend(slots.iter_mut().filter_map(|s| s.leave(now)));
+            *slots = windows.into_iter().map(|window| Slot { window, cached: None, entered: None }).collect();
+        });
+        Ok(())
+    }
+
+    /// Names of the windows, in the order they were added.
+    pub fn windows(&self) -> Vec<String> {
+        self.lock().iter().map(|s| s.window.name.clone()).collect()
+    }
+
+    /// Names of the windows active now.
+    pub fn active(&self) -> Vec<String> {
+        let now = self.0.clock.now_system();
+        self.lock().iter_mut().filter_map(|s| s.active(now).then(|| s.window.name.clone())).collect()
+    }
+
+    /// Markers from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<QuietMarker> {
+        self.0.markers.subscribe()
+    }
+
+    /// Enter and leave windows as of now, returning the markers (also sent to subscribers).
+    /// Hubs do it for every event they check; call it, or [`QuietHours::spawn`], to have
+    /// markers on time while no events come.
+    pub fn poll(&self) -> Vec<QuietMarker> {
+        self.edit(|slots, markers, now| Self::advance(slots, markers, now))
+    }
+
+    /// [`QuietHours::poll`] every `every` on a task of its own, until the last clone of this
+    /// handle is dropped.
+    pub fn spawn(&self, every: Duration) -> JoinHandle<()> {
+        let weak: Weak<Inner> = Arc::downgrade(&self.0);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(every);
+            loop {
+                ticker.tick().await;
+                let Some(inner) = weak.upgrade() else { break };
+                QuietHours(inner).poll();
+            }
+        })
+    }
+
+    /// Whether an event of `sensor` with `mask` is in an active window; `matches` evaluates
+    /// a window's filter on it. Counted in the digests of all windows selecting it.
+    pub(crate) fn check(&self, sensor: Option<&str>, mask: u64, matches: impl Fn(&Filter) -> bool) -> bool {
+        let mut quiet = false;
+        self.edit(|slots, markers, now| {
+            Self::advance(slots, markers, now);
+            for slot in slots.iter_mut() {
+                let Some(digest) = slot.entered.as_mut() else { continue };
+                if !slot.window.selects(sensor, mask) || slot.window.filter.as_ref().is_some_and(|f| !matches(f)) {
+                    continue;
+                }
+                quiet = true;
+                digest.suppressed += 1;
+                *digest.by_sensor.entry(sensor.unwrap_or_default().to_string()).or_default() += 1;
+                for bit in (0..64).map(|b| 1u64 << b).filter(|b| mask & b != 0) {
+                    *digest.by_mask.entry(bit).or_default() += 1;
+                }
+            }
+        });
+        quiet
+    }
+
+    fn advance(slots: &mut [Slot], markers: &mut Vec<QuietMarker>, now: SystemTime) {
+        for slot in slots.iter_mut() {
+            match (slot.active(now), slot.entered.is_some()) {
+                (true, false) => {
+                    slot.entered = Some(QuietDigest::new(now));
+                    markers.push(QuietMarker::Entered { window: slot.window.name.clone(), at: now });
+                }
+                (false, true) => markers.extend(slot.leave(now)),
+                _ => {}
+            }
+        }
+    }
+
+    /// Run `f` on the windows at the current time, then send the markers it produced.
+    fn edit(&self, f: impl FnOnce(&mut Vec<Slot>, &mut Vec<QuietMarker>, SystemTime)) -> Vec<QuietMarker> {
+        let mut markers = Vec::new();
+        f(&mut self.lock(), &mut markers, self.0.clock.now_system());
+        for m in &markers {
+            let _ = self.0.markers.send(m.clone());
+        }
+        markers
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Slot>> {
+        self.0.slots.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A parsed cron expression, each field as a bit set.
+#[derive(Clone, Debug)]
+struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // a restricted day of month and day of week match either, as in cron
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    fn parse(expr: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!("expected 5 fields, got {}", fields.len()));
+        };
+        let mut weekdays = field(weekdays, 0, 7)?;
+        // 7 is Sunday too
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Self {
+            minutes: field(minutes, 0, 59)?,
+            hours: field(hours, 0, 23)?,
+            days: field(days, 1, 31)?,
+            months: field(months, 1, 12)?,
+            weekdays,
+            any_day: days == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+
+    /// Whether the minute `minute` (since the epoch, UTC) is one the expression names.
+    fn matches(&self, minute: u64) -> bool {
+        let days = minute / 1440;
+        let (_, month, day) = civil(days);
+        let weekday = (days + 4) % 7;
+        let on = |set: u64, v: u64| set & (1 << v) != 0;
+        let day_ok = match (self.any_day, self.any_weekday) {
+            (false, false) => on(self.days, day) || on(self.weekdays, weekday),
+            _ => on(self.days, day) && on(self.weekdays, weekday),
+        };
+        on(self.minutes, minute % 60) && on(self.hours, minute / 60 % 24) && on(self.months, month) && day_ok
+    }
+}
+
+/// One cron field, `min..=max`, as a bit set.
+fn field(s: &str, min: u64, max: u64) -> Result<u64, String> {
+    let mut set = 0;
+    for part in s.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u64>().ok().filter(|s| *s > 0).ok_or_else(|| format!("bad step in {part:?}"))?),
+            None => (part, 1),
+        };
+        let num = |v: &str| v.parse::<u64>().ok().filter(|v| (min..=max).contains(v)).ok_or_else(|| format!("{v:?} is not in {min}-{max}"));
+        let (from, to) = match range {
+            "*" => (min, max),
+            r => match r.split_once('-') {
+                Some((a, b)) => (num(a)?, num(b)?),
+                None if step > 1 => (num(r)?, max),
+                None => (num(r)?, num(r)?),
+            },
+        };
+        if from > to {
+            return Err(format!("empty range {range:?}"));
+        }
+        set |= (from..=to).step_by(step as usize).fold(0, |acc, v| acc | 1 << v);
+    }
+    Ok(set)
+}
+
+/// Year, month (1-12) and day (1-31) of the day `days` since 1970-01-01.
+pub(crate) fn civil(days: u64) -> (u64, u64, u64) {
+    // Howard Hinnant's civil_from_days, for days after the epoch
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + u64::from(month <= 2), month, day)
+}
@@ -0,0 +1,206 @@
+use crate::{
+    callbacks::{CallbackHub, CallbackResult, FnCallback, SkipQuiet, is_quiet},
+    clock::ManualClock,
+    filter::Filter,
+    quiet::{QuietError, QuietHours, QuietMarker, QuietWindow},
+};
+use serde_json::{Value, json};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::mpsc::channel;
+
+// Monday 2024-01-01 00:00 UTC
+const MONDAY: u64 = 1_704_067_200;
+
+fn at(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+fn hours(start: u64) -> (ManualClock, QuietHours) {
+    let clock = ManualClock::at(at(start));
+    let quiet = QuietHours::new(clock.shared());
+    (clock, quiet)
+}
+
+fn entered(window: &str, secs: u64) -> QuietMarker {
+    QuietMarker::Entered { window: window.into(), at: at(secs) }
+}
+
+fn left(window: &str, secs: u64) -> QuietMarker {
+    QuietMarker::Left { window: window.into(), at: at(secs), digest: None }
+}
+
+#[test]
+fn explicit_window_starts_inclusive_and_ends_exclusive() {
+    let (clock, quiet) = hours(MONDAY);
+    quiet.add(QuietWindow::between("move", at(MONDAY + 10), at(MONDAY + 20)));
+
+    assert!(quiet.poll().is_empty());
+    clock.advance(Duration::from_secs(9));
+    assert!(quiet.active().is_empty());
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(quiet.active(), ["move"]);
+    assert_eq!(quiet.poll(), [entered("move", MONDAY + 10)]);
+    clock.advance(Duration::from_secs(9));
+    assert!(quiet.poll().is_empty());
+    clock.advance(Duration::from_secs(1));
+    assert!(quiet.active().is_empty());
+    assert_eq!(quiet.poll(), [left("move", MONDAY + 20)]);
+}
+
+#[test]
+fn cron_window_lasts_its_duration_from_each_start() {
+    // 02:00 on Sundays and on the 1st, for 90 minutes
+    let (clock, quiet) = hours(MONDAY + 2 * 3600 - 1);
+    quiet.add(QuietWindow::cron("backup", "0 2 1 * 0", Duration::from_secs(90 * 60)).unwrap());
+
+    assert!(quiet.active().is_empty());
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(quiet.active(), ["backup"], "the 1st, though a Monday");
+    clock.advance(Duration::from_secs(90 * 60 - 1));
+    assert_eq!(quiet.active(), ["backup"]);
+    clock.advance(Duration::from_secs(1));
+    assert!(quiet.active().is_empty());
+
+    // not on the Tuesday, again on the Sunday
+    clock.set_system(at(MONDAY + 86400 + 2 * 3600 + 60));
+    assert!(quiet.active().is_empty());
+    clock.set_system(at(MONDAY + 6 * 86400 + 2 * 3600 + 60));
+    assert_eq!(quiet.active(), ["backup"]);
+
+    // both restricted fields must be valid; 7 is Sunday too
+    assert!(QuietWindow::cron("x", "0 2 * *", Duration::ZERO).is_err());
+    assert!(matches!(QuietWindow::cron("x", "0 24 * * *", Duration::ZERO), Err(QuietError::Cron { .. })));
+    let sunday = QuietWindow::cron("sunday", "*/15 * * * 7", Duration::from_secs(60)).unwrap();
+    let (_, q) = hours(MONDAY + 6 * 86400 + 45 * 60);
+    q.add(sunday);
+    assert_eq!(q.active(), ["sunday"]);
+}
+
+#[test]
+fn overlapping_windows_each_count_the_event() {
+    let (clock, quiet) = hours(MONDAY);
+    quiet.add(QuietWindow::between("all", at(MONDAY), at(MONDAY + 100)).digest(true));
+    quiet.add(QuietWindow::between("mounts", at(MONDAY + 50), at(MONDAY + 200)).sensor("xmount").mask(0b10).digest(true));
+
+    assert!(quiet.check(Some("xmount"), 0b11, |_| true));
+    assert!(quiet.check(Some("procdog"), 0b10, |_| true));
+    clock.advance(Duration::from_secs(50));
+    assert!(quiet.check(Some("xmount"), 0b11, |_| true), "in both");
+    assert!(quiet.check(Some("xmount"), 0b01, |_| true), "in all only");
+    clock.advance(Duration::from_secs(50));
+    assert!(quiet.check(Some("xmount"), 0b10, |_| true), "in mounts only");
+    assert!(!quiet.check(Some("procdog"), 0b10, |_| true));
+    clock.advance(Duration::from_secs(100));
+    let markers = quiet.poll();
+
+    let digest = |m: &QuietMarker| match m {
+        QuietMarker::Left { digest: Some(d), .. } => (d.suppressed, d.by_sensor.clone(), d.by_mask.clone()),
+        other => panic!("{other:?}"),
+    };
+    let sensors = |pairs: &[(&str, u64)]| pairs.iter().map(|(s, n)| (s.to_string(), *n)).collect::<BTreeMap<_, _>>();
+    assert_eq!(quiet.windows(), ["all", "mounts"]);
+    assert_eq!(markers.len(), 1);
+    assert_eq!(digest(&markers[0]), (2, sensors(&[("xmount", 2)]), BTreeMap::from([(0b01, 1), (0b10, 2)])));
+    let QuietMarker::Left { window, at: when, .. } = &markers[0] else { unreachable!() };
+    assert_eq!((window.as_str(), *when), ("mounts", at(MONDAY + 200)));
+}
+
+#[tokio::test]
+async fn hub_tags_results_and_skip_quiet_holds_back_pagers() {
+    let (clock, quiet) = hours(MONDAY);
+    quiet.add(QuietWindow::between("db", at(MONDAY + 60), at(MONDAY + 120)).filter(Filter::parse("id >= 2").unwrap()));
+    let mut markers = quiet.subscribe();
+
+    let mut hub: CallbackHub<Value> = CallbackHub::new();
+    hub.set_sensor_name("xmount");
+    hub.set_quiet_hours(quiet.clone());
+    hub.add(FnCallback::new(0b1, |ev: &Value| {
+        let id = ev["id"].clone();
+        async move { Some::<CallbackResult>(json!({ "log": id, "quiet": is_quiet() })) }
+    }));
+    hub.add(SkipQuiet(FnCallback::new(0b1, |ev: &Value| {
+        let id = ev["id"].clone();
+        async move { Some::<CallbackResult>(json!({ "page": id })) }
+    })));
+    let (tx, mut rx) = channel(16);
+    hub.set_result_channel(tx);
+
+    hub.fire(0b1, &json!({ "id": 1 })).await;
+    clock.advance(Duration::from_secs(60));
+    hub.fire(0b1, &json!({ "id": 1 })).await;
+    hub.fire_and_wait_all(0b1, &json!({ "id": 2 }), Duration::from_secs(1)).await.unwrap();
+    clock.advance(Duration::from_secs(60));
+    hub.fire(0b1, &json!({ "id": 3 })).await;
+
+    let mut results = Vec::new();
+    while let Ok(r) = rx.try_recv() {
+        results.push(r);
+    }
+    assert_eq!(
+        results,
+        [
+            json!({ "log": 1, "quiet": false }),
+            json!({ "page": 1 }),
+            json!({ "log": 1, "quiet": false }),
+            json!({ "page": 1 }),
+            json!({ "log": 2, "quiet": true, "suppressed": true }),
+            json!({ "log": 3, "quiet": false }),
+            json!({ "page": 3 }),
+        ]
+    );
+    assert_eq!(markers.try_recv().unwrap(), entered("db", MONDAY + 60));
+    assert_eq!(markers.try_recv().unwrap(), left("db", MONDAY + 120));
+    assert!(markers.try_recv().is_err());
+}
+
+#[test]
+fn removing_or_replacing_an_active_window_leaves_it() {
+    let (_, quiet) = hours(MONDAY);
+    quiet.add(QuietWindow::between("a", at(MONDAY), at(MONDAY + 10)).digest(true));
+    quiet.add(QuietWindow::between("b", at(MONDAY), at(MONDAY + 10)));
+    assert!(quiet.check(None, 0b1, |_| true));
+
+    quiet.add(QuietWindow::between("b", at(MONDAY + 5), at(MONDAY + 10)));
+    assert_eq!(quiet.poll(), []);
+    assert!(quiet.remove("a"));
+    assert!(!quiet.remove("a"));
+    assert_eq!(quiet.windows(), ["b"]);
+    assert!(!quiet.check(None, 0b1, |_| true));
+
+    let mut markers = quiet.subscribe();
+    quiet.add(QuietWindow::between("c", at(MONDAY), at(MONDAY + 10)));
+    quiet.poll();
+    quiet.remove("c");
+    assert_eq!(markers.try_recv().unwrap(), entered("c", MONDAY));
+    assert_eq!(markers.try_recv().unwrap(), left("c", MONDAY));
+}
+
+#[test]
+fn windows_load_from_json_or_not_at_all() {
+    let (_, quiet) = hours(MONDAY + 2 * 3600);
+    quiet
+        .load(
+            r#"[
+                {"name": "backup", "cron": "0 2 * * 1", "duration_secs": 3600, "sensor": "xmount", "digest": true},
+                {"name": "db-move", "start": 1704067200, "end": 1704070800, "mask": 2, "filter": "target ~ \"/srv/db*\""}
+            ]"#,
+        )
+        .unwrap();
+    assert_eq!(quiet.windows(), ["backup", "db-move"]);
+    assert_eq!(quiet.active(), ["backup"]);
+
+    for (json, bad) in [
+        (r#"[{"name": "x", "cron": "0 2 * * 1"}]"#, "window x: needs either cron and duration_secs, or start and end"),
+        (r#"[{"name": "x", "start": 1, "end": 2, "cron": "* * * * *"}]"#, "window x: needs either cron and duration_secs, or start and end"),
+        (r#"[{"name": "x", "cron": "61 * * * *", "duration_secs": 60}]"#, "window x: bad cron expression"),
+        (r#"[{"name": "x", "start": 1, "end": 2, "filter": "target =="}]"#, "window x: bad filter"),
+        (r#"[{"name": "x", "start": 1, "end": 2, "pager": true}]"#, "unknown field `pager`"),
+    ] {
+        let err = quiet.load(json).unwrap_err().to_string();
+        assert!(err.contains(bad), "{err}");
+    }
+    assert_eq!(quiet.windows(), ["backup", "db-move"]);
+}
@@ -0,0 +1,470 @@
+use crate::{
+    callbacks::{self, Callback, CallbackResult, INJECTED_FIELD},
+    delta,
+    fields::{self, EventFields},
+    severity::{SEVERITY_FIELD, Severity, SeverityConfig, SeverityMapper},
+    standby::{Role, RoleSwitch},
+    topics::{TopicError, TopicPattern},
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+use tokio::{
+    sync::{Mutex, mpsc},
+    task::JoinHandle,
+};
+use tokio_util::sync::CancellationToken;
+
+/// Match on a field of the serialized event, addressed by a dotted path
+/// (e.g. `"Unmounted.target"` or `"Opened.conn.proto"`). Typed events are matched on their
+/// [`EventFields`] with the same paths, see [`fields::get_in_variant`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FieldMatch {
+    pub field: String,
+    pub equals: Value,
+}
+
+impl FieldMatch {
+    pub fn new<S: Into<String>, V: Into<Value>>(field: S, equals: V) -> Self {
+        Self { field: field.into(), equals: equals.into() }
+    }
+
+    pub fn matches(&self, payload: &Value) -> bool {
+        payload.pointer(&fields::pointer(&self.field)) == Some(&self.equals)
+    }
+
+    pub fn matches_fields(&self, ev: &dyn EventFields) -> bool {
+        fields::get_in_variant(ev, &self.field).is_some_and(|v| v.to_json() == self.equals)
+    }
+
+    pub(crate) fn matches_payload(&self, payload: Payload<'_>) -> bool {
+        match payload {
+            Payload::Json(v) => self.matches(v),
+            Payload::Typed(ev) => self.matches_fields(ev),
+        }
+    }
+}
+
+/// An event as rules see it: serialized already, or typed and serialized only on delivery.
+#[derive(Clone, Copy)]
+pub(crate) enum Payload<'a> {
+    Json(&'a Value),
+    Typed(&'a (dyn EventFields + Sync)),
+}
+
+impl Payload<'_> {
+    /// Serialized events carry no topic, so `topic` selectors only match typed ones.
+    pub(crate) fn topic_matches(&self, pattern: &TopicPattern) -> bool {
+        match self {
+            Payload::Json(_) => false,
+            Payload::Typed(ev) => ev.topic().is_some_and(|t| pattern.matches(t)),
+        }
+    }
+}
+
+/// One routing rule. Unset selectors match anything.
+/// A rule without sinks is a drop route: matching events go nowhere.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RouteRule {
+    pub name: String,
+    #[serde(default)]
+    pub sensor: Option<String>,
+    #[serde(default)]
+    pub mask: Option<u64>,
+    #[serde(default)]
+    pub topic: Option<TopicPattern>,
+    #[serde(default)]
+    pub when: Option<FieldMatch>,
+    #[serde(default)]
+    pub sinks: Vec<String>,
+}
+
+impl RouteRule {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self { name: name.into(), ..Default::default() }
+    }
+
+    /// Only match events coming from this sensor.
+    pub fn sensor<S: Into<String>>(mut self, sensor: S) -> Self {
+        self.sensor = Some(sensor.into());
+        self
+    }
+
+    /// Only match events whose mask intersects these bits.
+    pub fn mask(mut self, mask: u64) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+
+    /// Only match typed events whose topic `pattern` selects, see [`crate::topics`].
+    pub fn topic(mut self, pattern: &str) -> Result<Self, TopicError> {
+        self.topic = Some(TopicPattern::parse(pattern)?);
+        Ok(self)
+    }
+
+    /// Only match events whose payload has `field` equal to the given value.
+    pub fn when(mut self, m: FieldMatch) -> Self {
+        self.when = Some(m);
+        self
+    }
+
+    /// Deliver matching events to the named sink (can be repeated).
+    pub fn to<S: Into<String>>(mut self, sink: S) -> Self {
+        self.sinks.push(sink.into());
+        self
+    }
+
+    fn matches(&self, sensor: &str, mask: u64, payload: Payload<'_>) -> bool {
+        self.sensor.as_deref().is_none_or(|s| s == sensor)
+            && self.mask.is_none_or(|m| m & mask != 0)
+            && self.topic.as_ref().is_none_or(|t| payload.topic_matches(t))
+            && self.when.as_ref().is_none_or(|w| w.matches_payload(payload))
+    }
+}
+
+/// Serializable router setup.
+///
+/// ```json
+/// {
+///   "routes": [
+///     { "name": "root-unmount", "sensor": "xmount", "mask": 2,
+///       "when": { "field": "Unmounted.target", "equals": "/" }, "sinks": ["webhook", "syslog"] },
+///     { "name": "sshd-missing", "sensor": "procdog", "mask": 4,
+///       "when": { "field": "Missing.name", "equals": "sshd" }, "sinks": ["webhook", "syslog"] },
+///     { "name": "net-noise", "sensor": "netpacket", "sinks": ["jsonl"] },
+///     { "name": "remounts", "topic": "mount.changed.remount", "sinks": ["syslog"] },
+///     { "name": "drop-udp", "sensor": "socktray", "when": { "field": "Opened.sock.proto", "equals": "udp" } }
+///   ],
+///   "default": ["jsonl"],
+///   "severity": { "rules": [] },
+///   "min_severity": { "webhook": "warning" }
+/// }
+/// ```
+///
+/// A `topic` selector (see [`crate::topics`]) only matches events routed with
+/// [`Router::dispatch_event`], serialized ones have no topic.
+///
+/// With `severity` set, every routed event gets a `severity` field (see [`SeverityConfig`]),
+/// and sinks listed in `min_severity` only receive events at or above that level.
+///
+/// Events fired with [`crate::callbacks::CallbackHub::inject`] are routed with an
+/// `"injected": true` field, or dropped with `"drop_injected": true`.
+///
+/// With `"delta_changes": true`, events carrying full `old` and `new` objects reach the
+/// sinks in the compact form of [`crate::delta`]. Rules still match the full event.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RouterConfig {
+    #[serde(default)]
+    pub routes: Vec<RouteRule>,
+    #[serde(default)]
+    pub default: Vec<String>,
+    #[serde(default)]
+    pub severity: Option<SeverityConfig>,
+    #[serde(default)]
+    pub min_severity: HashMap<String, Severity>,
+    #[serde(default)]
+    pub drop_injected: bool,
+    #[serde(default)]
+    pub delta_changes: bool,
+}
+
+/// Per-rule delivery counters. `routed` counts deliveries to sinks, `dropped` counts events
+/// hitting a drop route or injected while dropping injected events, plus deliveries that
+/// failed (unknown or closed sink) or were below the sink's minimum severity.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct RouteStats {
+    pub rule: String,
+    pub routed: u64,
+    pub dropped: u64,
+}
+
+#[derive(Default)]
+struct RouteCounters {
+    routed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+struct Route {
+    rule: RouteRule,
+    counters: RouteCounters,
+}
+
+/// Routes serialized events from several sensors to named sinks.
+///
+/// Rules are evaluated in order and the first matching rule wins. Events matching no rule
+/// go to the default route (dropped if it has no sinks).
+pub struct Router {
+    sinks: HashMap<String, mpsc::Sender<CallbackResult>>,
+    routes: Vec<Route>,
+    default: Route,
+    severity: Option<SeverityMapper>,
+    min_severity: HashMap<String, Severity>,
+    drop_injected: bool,
+    delta_changes: bool,
+    role: Option<RoleSwitch>,
+    // held while delivering role markers, so no event overtakes them
+    markers: Mutex<()>,
+    suppressed: AtomicU64,
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self {
+            sinks: HashMap::new(),
+            routes: Vec::new(),
+            default: Route { rule: RouteRule::new("default"), counters: RouteCounters::default() },
+            severity: None,
+            min_severity: HashMap::new(),
+            drop_injected: false,
+            delta_changes: false,
+            role: None,
+            markers: Mutex::new(()),
+            suppressed: AtomicU64::new(0),
+        }
+    }
+
+    pub fn from_config(cfg: RouterConfig) -> Self {
+        let mut router = Self::new();
+        for rule in cfg.routes {
+            router.add_rule(rule);
+        }
+        router.set_default(cfg.default);
+        if let Some(sev) = cfg.severity {
+            router.set_severity(SeverityMapper::from_config(sev));
+        }
+        router.min_severity = cfg.min_severity;
+        router.drop_injected = cfg.drop_injected;
+        router.delta_changes = cfg.delta_changes;
+        router
+    }
+
+    /// Register a named sink.
+    pub fn add_sink<S: Into<String>>(&mut self, name: S, tx: mpsc::Sender<CallbackResult>) {
+        self.sinks.insert(name.into(), tx);
+    }
+
+    pub fn add_rule(&mut self, rule: RouteRule) {
+        self.routes.push(Route { rule, counters: RouteCounters::default() });
+    }
+
+    /// Sinks for events no rule matched.
+    pub fn set_default<S: Into<String>>(&mut self, sinks: Vec<S>) {
+        self.default.rule.sinks = sinks.into_iter().map(Into::into).collect();
+    }
+
+    /// Tag every routed event with a severity.
+    pub fn set_severity(&mut self, mapper: SeverityMapper) {
+        self.severity = Some(mapper);
+    }
+
+    /// Only deliver events at or above `min` to the named sink.
+    /// Has no effect unless a severity mapper is set.
+    pub fn set_min_severity<S: Into<String>>(&mut self, sink: S, min: Severity) {
+        self.min_severity.insert(sink.into(), min);
+    }
+
+    /// Drop injected test events instead of routing them, e.g. in production.
+    pub fn set_drop_injected(&mut self, on: bool) {
+        self.drop_injected = on;
+    }
+
+    /// Deliver Changed-type events delta-encoded, see [`crate::delta`].
+    pub fn set_delta_changes(&mut self, on: bool) {
+        self.delta_changes = on;
+    }
+
+    /// Only deliver while `role` is active, see [`crate::standby`]. Role markers go to
+    /// every registered sink ahead of the events following them.
+    pub fn set_role_switch(&mut self, role: RoleSwitch) {
+        self.role = Some(role);
+    }
+
+    /// Events dropped because the role switch was on standby.
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed.load(Ordering::Relaxed)
+    }
+
+    /// Deliver role markers as soon as the role changes, not only ahead of the next event,
+    /// until `cancel` fires. Does nothing without a role switch.
+    pub fn spawn_role_markers(self: &Arc<Self>, cancel: CancellationToken) -> JoinHandle<()> {
+        let router = self.clone();
+        tokio::spawn(async move {
+            let Some(role) = router.role.clone() else { return };
+            loop {
+                tokio::select! {
+                    _ = cancel.cancelled() => break,
+                    _ = role.changed() => {}
+                }
+                router.deliver_markers(&role).await;
+            }
+            router.deliver_markers(&role).await;
+        })
+    }
+
+    /// Send pending role markers to all sinks. Returns whether the role they lead up to is active.
+    async fn deliver_markers(&self, role: &RoleSwitch) -> bool {
+        let _gate = self.markers.lock().await;
+        let (markers, now) = role.take_markers();
+        if !markers.is_empty() {
+            let mut names: Vec<&String> = self.sinks.keys().collect();
+            names.sort();
+            for marker in markers {
+                for name in &names {
+                    let _ = self.sinks[*name].send(marker.clone()).await;
+                }
+            }
+        }
+        now == Role::Active
+    }
+
+    /// Sink names referenced by rules but never registered.
+    pub fn unknown_sinks(&self) -> Vec<String> {
+        let mut out: Vec<String> = self
+            .routes
+            .iter()
+            .chain(std::iter::once(&self.default))
+            .flat_map(|r| r.rule.sinks.iter())
+            .filter(|s| !self.sinks.contains_key(*s))
+            .cloned()
+            .collect();
+        out.sort();
+        out.dedup();
+        out
+    }
+
+    /// Route one event from `sensor` with event mask `mask`.
+    pub async fn dispatch(&self, sensor: &str, mask: u64, payload: &Value) {
+        let injected = payload.get(INJECTED_FIELD).and_then(Value::as_bool).unwrap_or(false);
+        self.route(sensor, mask, Payload::Json(payload), injected, || Some(payload.clone())).await
+    }
+
+    /// Route a typed event: rules and severity look at its [`EventFields`], and it is only
+    /// serialized if some sink receives it.
+    pub async fn dispatch_event<E: EventFields + Serialize + Sync>(&self, sensor: &str, mask: u64, ev: &E) {
+        let serialize = || match serde_json::to_value(ev) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                log::error!("router: failed to serialize {sensor} event: {e}");
+                None
+            }
+        };
+        self.route(sensor, mask, Payload::Typed(ev), false, serialize).await
+    }
+
+    async fn route(&self, sensor: &str, mask: u64, payload: Payload<'_>, injected: bool, serialize: impl FnOnce() -> Option<Value>) {
+        if let Some(role) = &self.role
+            && !self.deliver_markers(role).await
+        {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        let route = self.routes.iter().find(|r| r.rule.matches(sensor, mask, payload)).unwrap_or(&self.default);
+
+        let injected = injected || callbacks::is_injected();
+        if route.rule.sinks.is_empty() || (injected && self.drop_injected) {
+            route.counters.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        let sev = self.severity.as_ref().map(|m| m.severity_of(sensor, mask, payload));
+        let wanted: Vec<&String> = route
+            .rule
+            .sinks
+            .iter()
+            .filter(|name| match (&sev, self.min_severity.get(*name)) {
+                (Some(sev), Some(min)) => sev >= min,
+                _ => true,
+            })
+            .collect();
+        let unwanted = (route.rule.sinks.len() - wanted.len()) as u64;
+        route.counters.dropped.fetch_add(unwanted, Ordering::Relaxed);
+
+        // only now the event is needed as JSON
+        let payload = if wanted.is_empty() { None } else { serialize() };
+        let Some(mut payload) = payload else {
+            route.counters.dropped.fetch_add(wanted.len() as u64, Ordering::Relaxed);
+            return;
+        };
+        if let Value::Object(map) = &mut payload {
+            if injected {
+                map.insert(INJECTED_FIELD.to_string(), Value::Bool(true));
+            }
+            if let Some(sev) = sev {
+                map.insert(SEVERITY_FIELD.to_string(), serde_json::to_value(sev).unwrap_or_default());
+            }
+        }
+        if self.delta_changes
+            && let Some(p) = delta::encode(&payload)
+        {
+            payload = p;
+        }
+
+        for name in wanted {
+            let delivered = match self.sinks.get(name) {
+                Some(tx) => tx.send(payload.clone()).await.is_ok(),
+                None => false,
+            };
+
+            if delivered {
+                route.counters.routed.fetch_add(1, Ordering::Relaxed);
+            } else {
+                route.counters.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Counters for every rule, in evaluation order, followed by the default route.
+    pub fn stats(&self) -> Vec<RouteStats> {
+        self.routes
+            .iter()
+            .chain(std::iter::once(&self.default))
+            .map(|r| RouteStats {
+                rule: r.rule.name.clone(),
+                routed: r.counters.routed.load(Ordering::Relaxed),
+                dropped: r.counters.dropped.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// Create a callback feeding a sensor hub into this router.
+    /// `mask_of` maps an event to its mask bits (usually `|ev| ev.mask().bits()`).
+    pub fn callback<E>(self: &Arc<Self>, sensor: &str, mask_of: fn(&E) -> u64) -> RouteCallback<E> {
+        RouteCallback { router: self.clone(), sensor: sensor.to_string(), mask_of }
+    }
+}
+
+/// Callback handing every event it receives to a [`Router`], see [`Router::dispatch_event`].
+pub struct RouteCallback<E> {
+    router: Arc<Router>,
+    sensor: String,
+    mask_of: fn(&E) -> u64,
+}
+
+#[async_trait]
+impl<E> Callback<E> for RouteCallback<E>
+where
+    E: EventFields + Serialize + Send + Sync,
+{
+    fn mask(&self) -> u64 {
+        u64::MAX
+    }
+
+    async fn call(&self, ev: &E) -> Option<CallbackResult> {
+        self.router.dispatch_event(&self.sensor, (self.mask_of)(ev), ev).await;
+        None
+    }
+}
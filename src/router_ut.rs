@@ -0,0 +1,213 @@
+use crate::{
+    callbacks::{CallbackHub, CallbackResult},
+    fields::{EventFields, FieldValue},
+    router::{FieldMatch, RouteRule, RouteStats, Router, RouterConfig},
+};
+use serde::{Serialize, Serializer};
+use serde_json::json;
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+use tokio::sync::mpsc::{Receiver, channel};
+
+#[derive(Serialize)]
+enum MountEv {
+    Mounted { target: String },
+    Unmounted { target: String },
+}
+
+impl MountEv {
+    fn mask(&self) -> u64 {
+        match self {
+            MountEv::Mounted { .. } => 0b01,
+            MountEv::Unmounted { .. } => 0b10,
+        }
+    }
+}
+
+impl EventFields for MountEv {
+    fn kind(&self) -> &'static str {
+        match self {
+            MountEv::Mounted { .. } => "mounted",
+            MountEv::Unmounted { .. } => "unmounted",
+        }
+    }
+
+    fn field(&self, name: &str) -> Option<FieldValue<'_>> {
+        let (MountEv::Mounted { target } | MountEv::Unmounted { target }) = self;
+        (name == "target").then(|| FieldValue::str(target))
+    }
+
+    fn field_names(&self) -> &'static [&'static str] {
+        &["target"]
+    }
+}
+
+fn drain(rx: &mut Receiver<CallbackResult>) -> Vec<CallbackResult> {
+    let mut out = Vec::new();
+    while let Ok(v) = rx.try_recv() {
+        out.push(v);
+    }
+    out
+}
+
+fn router() -> (Router, Receiver<CallbackResult>, Receiver<CallbackResult>, Receiver<CallbackResult>) {
+    let cfg: RouterConfig = serde_json::from_value(json!({
+        "routes": [
+            { "name": "root-unmount", "sensor": "xmount", "mask": 2,
+              "when": { "field": "Unmounted.target", "equals": "/" }, "sinks": ["webhook", "syslog"] },
+            { "name": "net", "sensor": "netpacket", "sinks": ["jsonl"] },
+            { "name": "drop-udp", "sensor": "socktray", "when": { "field": "Opened.proto", "equals": "udp" } }
+        ],
+        "default": ["jsonl"]
+    }))
+    .unwrap();
+
+    let mut router = Router::from_config(cfg);
+    let (webhook_tx, webhook_rx) = channel(16);
+    let (syslog_tx, syslog_rx) = channel(16);
+    let (jsonl_tx, jsonl_rx) = channel(16);
+    router.add_sink("webhook", webhook_tx);
+    router.add_sink("syslog", syslog_tx);
+    router.add_sink("jsonl", jsonl_tx);
+    (router, webhook_rx, syslog_rx, jsonl_rx)
+}
+
+#[tokio::test]
+async fn routes_events_per_rule() {
+    let (router, mut webhook, mut syslog, mut jsonl) = router();
+    assert!(router.unknown_sinks().is_empty());
+
+    router.dispatch("xmount", 0b10, &json!({ "Unmounted": { "target": "/" } })).await;
+    router.dispatch("xmount", 0b10, &json!({ "Unmounted": { "target": "/mnt/usb" } })).await;
+    router.dispatch("netpacket", 0b01, &json!({ "Opened": { "proto": "tcp" } })).await;
+    router.dispatch("socktray", 0b01, &json!({ "Opened": { "proto": "udp" } })).await;
+    router.dispatch("socktray", 0b01, &json!({ "Opened": { "proto": "tcp" } })).await;
+
+    assert_eq!(drain(&mut webhook), vec![json!({ "Unmounted": { "target": "/" } })]);
+    assert_eq!(drain(&mut syslog), vec![json!({ "Unmounted": { "target": "/" } })]);
+    assert_eq!(
+        drain(&mut jsonl),
+        vec![json!({ "Unmounted": { "target": "/mnt/usb" } }), json!({ "Opened": { "proto": "tcp" } }), json!({ "Opened": { "proto": "tcp" } }),]
+    );
+
+    let stats = router.stats();
+    let stat = |name: &str| stats.iter().find(|s| s.rule == name).cloned().unwrap();
+    assert_eq!(stat("root-unmount"), RouteStats { rule: "root-unmount".into(), routed: 2, dropped: 0 });
+    assert_eq!(stat("net"), RouteStats { rule: "net".into(), routed: 1, dropped: 0 });
+    assert_eq!(stat("drop-udp"), RouteStats { rule: "drop-udp".into(), routed: 0, dropped: 1 });
+    assert_eq!(stat("default"), RouteStats { rule: "default".into(), routed: 2, dropped: 0 });
+}
+
+#[tokio::test]
+async fn unknown_sink_counts_as_dropped() {
+    let mut router = Router::new();
+    router.add_rule(RouteRule::new("audit").mask(0b10).to("nowhere"));
+
+    assert_eq!(router.unknown_sinks(), vec!["nowhere".to_string()]);
+
+    router.dispatch("xmount", 0b10, &json!({})).await;
+    router.dispatch("xmount", 0b01, &json!({})).await;
+
+    assert_eq!(router.stats()[0].dropped, 1);
+    assert_eq!(router.stats()[1].dropped, 1);
+}
+
+#[tokio::test]
+async fn hub_feeds_router_through_callback() {
+    let mut router = Router::new();
+    let (audit_tx, mut audit) = channel(16);
+    let (jsonl_tx, mut jsonl) = channel(16);
+    router.add_sink("audit", audit_tx);
+    router.add_sink("jsonl", jsonl_tx);
+    router.add_rule(RouteRule::new("root").sensor("xmount").mask(0b10).when(FieldMatch::new("Unmounted.target", "/")).to("audit"));
+    router.set_default(vec!["jsonl"]);
+    let router = Arc::new(router);
+
+    let mut hub = CallbackHub::<MountEv>::new();
+    hub.add(router.callback("xmount", MountEv::mask));
+
+    for ev in [MountEv::Mounted { target: "/".into() }, MountEv::Unmounted { target: "/".into() }] {
+        hub.fire(ev.mask(), &ev).await;
+    }
+
+    assert_eq!(drain(&mut audit), vec![json!({ "Unmounted": { "target": "/" } })]);
+    assert_eq!(drain(&mut jsonl), vec![json!({ "Mounted": { "target": "/" } })]);
+}
+
+#[tokio::test]
+async fn injected_events_are_tagged_or_dropped() {
+    let mut router = Router::new();
+    let (jsonl_tx, mut jsonl) = channel(16);
+    router.add_sink("jsonl", jsonl_tx);
+    router.set_default(vec!["jsonl"]);
+    let router = Arc::new(router);
+
+    let mut hub = CallbackHub::<MountEv>::new();
+    hub.add(router.callback("xmount", MountEv::mask));
+    hub.fire(0b01, &MountEv::Mounted { target: "/a".into() }).await;
+    hub.inject(0b01, &MountEv::Mounted { target: "/b".into() }).await;
+    assert_eq!(drain(&mut jsonl), vec![json!({ "Mounted": { "target": "/a" } }), json!({ "Mounted": { "target": "/b" }, "injected": true })]);
+
+    let cfg: RouterConfig = serde_json::from_value(json!({ "default": ["jsonl"], "drop_injected": true })).unwrap();
+    let mut router = Router::from_config(cfg);
+    let (jsonl_tx, mut jsonl) = channel(16);
+    router.add_sink("jsonl", jsonl_tx);
+    let router = Arc::new(router);
+
+    let mut hub = CallbackHub::<MountEv>::new();
+    hub.add(router.callback("xmount", MountEv::mask));
+    hub.inject(0b01, &MountEv::Mounted { target: "/b".into() }).await;
+    router.dispatch("xmount", 0b01, &json!({ "Mounted": { "target": "/c" }, "injected": true })).await;
+    hub.fire(0b01, &MountEv::Mounted { target: "/a".into() }).await;
+
+    assert_eq!(drain(&mut jsonl), vec![json!({ "Mounted": { "target": "/a" } })]);
+    assert_eq!(router.stats(), vec![RouteStats { rule: "default".into(), routed: 1, dropped: 2 }]);
+}
+
+/// A [`MountEv`] counting how often it gets serialized.
+struct Counted(MountEv);
+
+static SERIALIZED: AtomicUsize = AtomicUsize::new(0);
+
+impl Serialize for Counted {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        SERIALIZED.fetch_add(1, Ordering::Relaxed);
+        self.0.serialize(s)
+    }
+}
+
+impl EventFields for Counted {
+    fn kind(&self) -> &'static str {
+        self.0.kind()
+    }
+
+    fn field(&self, name: &str) -> Option<FieldValue<'_>> {
+        self.0.field(name)
+    }
+
+    fn field_names(&self) -> &'static [&'static str] {
+        self.0.field_names()
+    }
+}
+
+#[tokio::test]
+async fn typed_events_are_serialized_only_for_a_sink() {
+    let mut router = Router::new();
+    let (audit_tx, mut audit) = channel(16);
+    router.add_sink("audit", audit_tx);
+    router.add_rule(RouteRule::new("root").when(FieldMatch::new("Unmounted.target", "/")).to("audit"));
+    router.add_rule(RouteRule::new("quiet").sensor("xmount"));
+    let router = Arc::new(router);
+
+    let mut hub = CallbackHub::<Counted>::new();
+    hub.add(router.callback("xmount", |ev: &Counted| ev.0.mask()));
+    for ev in [MountEv::Mounted { target: "/".into() }, MountEv::Unmounted { target: "/mnt".into() }, MountEv::Unmounted { target: "/".into() }] {
+        hub.fire(ev.mask(), &Counted(ev)).await;
+    }
+
+    assert_eq!(drain(&mut audit), vec![json!({ "Unmounted": { "target": "/" } })]);
+    assert_eq!(SERIALIZED.load(Ordering::Relaxed), 1, "the two dropped events were never serialized");
+    assert_eq!(router.stats()[1], RouteStats { rule: "quiet".into(), routed: 0, dropped: 2 });
+}
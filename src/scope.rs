@@ -0,0 +1,187 @@
+//! Scoped views of a hub, for one hub serving several tenants.
+//!
+//! A [`ScopedHub`] registers callbacks on a shared [`CallbackHub`] behind a filter and a set
+//! of labels of its own. Its callbacks only ever get events the filter passed, the results
+//! they return carry the labels under [`SCOPE_FIELD`], and [`ScopedHub::revoke`] takes all of
+//! them off the hub at once.
+//!
+//! ```ignore
+//! let billing = ScopedHub::new(hub.clone(), |ev: &XMountEvent| ev.target().starts_with("/srv/billing"), Labels::from_iter([("tenant", "billing")]));
+//! billing.add(AlertOnUnmount)?;
+//! // the tenant is gone
+//! billing.revoke();
+//! ```
+//!
+//! The filter runs before the callback is called, so there is nothing for the callback to
+//! get around; one that panics rejects the event. Once `revoke()` returns, no call starts
+//! for the scope, and the results of calls that finish after it are dropped: a call's filter
+//! check and its first poll run under a read lock `revoke()` takes for writing, so it waits
+//! for calls that are starting. A callback revoking its own scope before its first `.await`
+//! would wait on itself; it has to do it from a task of its own.
+
+use crate::{
+    callbacks::{Callback, CallbackHub, CallbackId, CallbackResult, Predicate},
+    labels::Labels,
+};
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+use std::{
+    future::poll_fn,
+    panic::{AssertUnwindSafe, catch_unwind},
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::Poll,
+};
+
+/// Key the scope's labels are set under in the results of its callbacks, replacing whatever
+/// the callback put there.
+pub const SCOPE_FIELD: &str = "scope";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("scope revoked")]
+pub struct ScopeRevoked;
+
+struct Scope<E> {
+    filter: Predicate<E>,
+    labels: Value,
+    // registered through the scope, taken off the hub by revoke()
+    ids: Mutex<Vec<CallbackId>>,
+    revoked: AtomicBool,
+    // read while a call is admitted and polled the first time, written by revoke()
+    starting: RwLock<()>,
+}
+
+impl<E> Scope<E> {
+    fn admits(&self, ev: &E) -> bool {
+        !self.revoked.load(Ordering::Acquire)
+            && catch_unwind(AssertUnwindSafe(|| (self.filter)(ev))).unwrap_or_else(|_| {
+                log::error!("scope filter panicked, event not delivered");
+                false
+            })
+    }
+
+    /// `r` with the scope's labels; other than object results are wrapped as `{"result": r}`.
+    fn tag(&self, r: Value) -> Value {
+        let mut map = match r {
+            Value::Object(map) => map,
+            other => Map::from_iter([("result".to_string(), other)]),
+        };
+        map.insert(SCOPE_FIELD.to_string(), self.labels.clone());
+        Value::Object(map)
+    }
+}
+
+/// A callback registered through a scope.
+struct Scoped<C, E> {
+    inner: C,
+    scope: Arc<Scope<E>>,
+}
+
+#[async_trait]
+impl<C, E> Callback<E> for Scoped<C, E>
+where
+    C: Callback<E>,
+    E: Send + Sync + 'static,
+{
+    fn mask(&self) -> u64 {
+        self.inner.mask()
+    }
+
+    async fn call(&self, ev: &E) -> Option<CallbackResult> {
+        let mut call = self.inner.call(ev);
+        let first = poll_fn(|cx| {
+            let _starting = self.scope.starting.read().unwrap_or_else(|e| e.into_inner());
+            if !self.scope.admits(ev) {
+                return Poll::Ready(None);
+            }
+            Poll::Ready(Some(call.as_mut().poll(cx)))
+        })
+        .await;
+        let r = match first? {
+            Poll::Ready(r) => r,
+            Poll::Pending => call.await,
+        }?;
+        // revoked while it ran
+        if self.scope.revoked.load(Ordering::Acquire) {
+            return None;
+        }
+        Some(self.scope.tag(r))
+    }
+}
+
+/// A view of a hub for one tenant, see the module docs. Clones are the same scope.
+pub struct ScopedHub<E> {
+    hub: Arc<CallbackHub<E>>,
+    scope: Arc<Scope<E>>,
+}
+
+impl<E> Clone for ScopedHub<E> {
+    fn clone(&self) -> Self {
+        Self { hub: self.hub.clone(), scope: self.scope.clone() }
+    }
+}
+
+impl<E: Send + Sync + 'static> ScopedHub<E> {
+    pub fn new<F>(hub: Arc<CallbackHub<E>>, filter: F, labels: Labels) -> Self
+    where
+        F: Fn(&E) -> bool + Send + Sync + 'static,
+    {
+        let labels = serde_json::to_value(&labels).unwrap_or_default();
+        let scope = Scope { filter: Box::new(filter), labels, ids: Mutex::default(), revoked: AtomicBool::new(false), starting: RwLock::new(()) };
+        Self { hub, scope: Arc::new(scope) }
+    }
+
+    /// Register `cb` on the hub, for the events of its mask the scope's filter passes.
+    pub fn add<C: Callback<E> + 'static>(&self, cb: C) -> Result<CallbackId, ScopeRevoked> {
+        self.add_with_priority(cb, 0)
+    }
+
+    /// [`ScopedHub::add`] at a priority, see [`CallbackHub::add_with_priority`].
+    pub fn add_with_priority<C: Callback<E> + 'static>(&self, cb: C, priority: i32) -> Result<CallbackId, ScopeRevoked> {
+        let mut ids = self.scope.ids.lock().unwrap_or_else(|e| e.into_inner());
+        if self.is_revoked() {
+            return Err(ScopeRevoked);
+        }
+        let id = self.hub.register_shared(Scoped { inner: cb, scope: self.scope.clone() }, priority);
+        ids.push(id);
+        Ok(id)
+    }
+
+    /// Unregister a callback of this scope; false for one it did not register.
+    pub fn remove(&self, id: CallbackId) -> bool {
+        let mut ids = self.scope.ids.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(pos) = ids.iter().position(|i| *i == id) else {
+            return false;
+        };
+        ids.remove(pos);
+        self.hub.remove(id)
+    }
+
+    /// Unregister every callback of the scope and refuse new ones, after the calls being
+    /// started right now got going.
+    pub fn revoke(&self) {
+        let mut ids = self.scope.ids.lock().unwrap_or_else(|e| e.into_inner());
+        {
+            let _starting = self.scope.starting.write().unwrap_or_else(|e| e.into_inner());
+            self.scope.revoked.store(true, Ordering::Release);
+        }
+        for id in ids.drain(..) {
+            self.hub.remove(id);
+        }
+    }
+
+    pub fn is_revoked(&self) -> bool {
+        self.scope.revoked.load(Ordering::Acquire)
+    }
+
+    /// Callbacks registered through the scope.
+    pub fn len(&self) -> usize {
+        self.scope.ids.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{
+    callbacks::{CallbackHub, CallbackId, FnCallback},
+    labels::Labels,
+    scope::{SCOPE_FIELD, ScopeRevoked, ScopedHub},
+    sensor::{Sensor, SensorCtx, spawn_sensor},
+};
+use serde_json::{Value, json};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::{Notify, Semaphore, mpsc, oneshot};
+
+/// Fires the paths sent to it, one event each, and says when the hub is done with one.
+struct Feed(mpsc::Receiver<(String, oneshot::Sender<()>)>);
+
+type FeedTx = mpsc::Sender<(String, oneshot::Sender<()>)>;
+
+impl Sensor for Feed {
+    type Event = String;
+
+    fn run<R: Send + 'static>(mut self, ctx: SensorCtx<String, R>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async move {
+            loop {
+                tokio::select! {
+                    _ = ctx.cancel.cancelled() => return,
+                    Some((path, done)) = self.0.recv() => {
+                        ctx.hub.fire(0b1, &path).await;
+                        let _ = done.send(());
+                    }
+                }
+            }
+        })
+    }
+}
+
+type Seen = Arc<Mutex<Vec<String>>>;
+
+fn tenant(hub: &Arc<CallbackHub<String>>, name: &str) -> ScopedHub<String> {
+    let prefix = format!("/{name}/");
+    ScopedHub::new(hub.clone(), move |path: &String| path.starts_with(&prefix), Labels::from_iter([("tenant", name)]))
+}
+
+/// A callback recording the paths it gets and returning what `result` makes of them.
+fn recorder(seen: &Seen, result: fn(&str) -> Value) -> FnCallback<impl Fn(&String) -> std::future::Ready<Option<Value>> + Send + Sync + use<>> {
+    let seen = seen.clone();
+    FnCallback::new(0b1, move |path: &String| {
+        seen.lock().unwrap().push(path.clone());
+        std::future::ready(Some(result(path)))
+    })
+}
+
+fn seen(s: &Seen) -> Vec<String> {
+    s.lock().unwrap().clone()
+}
+
+fn drain(rx: &mut mpsc::Receiver<Value>) -> Vec<Value> {
+    let mut out = Vec::new();
+    while let Ok(r) = rx.try_recv() {
+        out.push(r);
+    }
+    out
+}
+
+/// Fire `path`; the receiver completes once every callback returned.
+async fn fire(tx: &FeedTx, path: &str) -> oneshot::Receiver<()> {
+    let (done, fired) = oneshot::channel();
+    tx.send((path.to_string(), done)).await.unwrap();
+    fired
+}
+
+async fn feed(tx: &FeedTx, paths: &[&str]) {
+    for p in paths {
+        fire(tx, p).await.await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn scopes_see_only_their_events_and_tag_their_results() {
+    let (results_tx, mut results) = mpsc::channel(64);
+    let mut hub = CallbackHub::<String>::new();
+    hub.set_result_channel(results_tx);
+    let everything: Seen = Seen::default();
+    hub.add(recorder(&everything, |_| Value::Null));
+    let hub = Arc::new(hub);
+    let (a, b) = (tenant(&hub, "a"), tenant(&hub, "b"));
+    let (seen_a, seen_b) = (Seen::default(), Seen::default());
+    // one trying to pass for the other tenant
+    a.add(recorder(&seen_a, |p| json!({ "path": p, SCOPE_FIELD: { "tenant": "b" } }))).unwrap();
+    b.add(recorder(&seen_b, |p| json!(p))).unwrap();
+
+    let (tx, rx) = mpsc::channel(16);
+    let (handle, task) = spawn_sensor(Feed(rx), hub.clone());
+    feed(&tx, &["/a/1", "/b/1", "/c/1", "/a/2"]).await;
+
+    assert_eq!(seen(&everything), ["/a/1", "/b/1", "/c/1", "/a/2"]);
+    assert_eq!(seen(&seen_a), ["/a/1", "/a/2"]);
+    assert_eq!(seen(&seen_b), ["/b/1"]);
+    let scoped: Vec<Value> = drain(&mut results).into_iter().filter(|r| !r.is_null()).collect();
+    assert_eq!(
+        scoped,
+        [
+            json!({ "path": "/a/1", "scope": { "tenant": "a" } }),
+            json!({ "result": "/b/1", "scope": { "tenant": "b" } }),
+            json!({ "path": "/a/2", "scope": { "tenant": "a" } }),
+        ]
+    );
+
+    // a filter that panics lets nothing through, the next event goes on as usual
+    let picky = ScopedHub::new(hub.clone(), |path: &String| if path == "/boom" { panic!("no") } else { true }, Labels::default());
+    let seen_picky = Seen::default();
+    picky.add(recorder(&seen_picky, |_| Value::Null)).unwrap();
+    feed(&tx, &["/boom", "/b/2"]).await;
+    assert_eq!(seen(&seen_picky), ["/b/2"]);
+    assert_eq!(seen(&seen_b), ["/b/1", "/b/2"]);
+
+    handle.shutdown();
+    task.await.unwrap();
+}
+
+#[tokio::test]
+async fn revoking_detaches_the_whole_scope_even_mid_call() {
+    let (results_tx, mut results) = mpsc::channel(64);
+    let mut hub = CallbackHub::<String>::new();
+    hub.set_result_channel(results_tx);
+    let hub = Arc::new(hub);
+    let (a, b) = (tenant(&hub, "a"), tenant(&hub, "b"));
+    let (seen_a, seen_b) = (Seen::default(), Seen::default());
+    // the second callback of a blocks on /a/slow until let go
+    let (gate, entered) = (Arc::new(Semaphore::new(0)), Arc::new(Notify::new()));
+    let (held, entering) = (gate.clone(), entered.clone());
+    a.add(recorder(&seen_a, |p| json!(p))).unwrap();
+    a.add(FnCallback::new(0b1, move |path: &String| {
+        let (held, entering, path) = (held.clone(), entering.clone(), path.clone());
+        async move {
+            if path == "/a/slow" {
+                entering.notify_one();
+                let _ = held.acquire().await;
+            }
+            Some(json!({ "slow": path }))
+        }
+    }))
+    .unwrap();
+    let b_id = b.add(recorder(&seen_b, |p| json!(p))).unwrap();
+    assert_eq!((a.len(), b.len(), hub.len()), (2, 1, 3));
+    assert!(!a.remove(b_id), "not a's to remove");
+
+    let (tx, rx) = mpsc::channel(16);
+    let (handle, task) = spawn_sensor(Feed(rx), hub.clone());
+    let slow = fire(&tx, "/a/slow").await;
+    entered.notified().await;
+    assert_eq!(drain(&mut results).len(), 1, "the first callback of a answered, the second is stuck");
+
+    a.revoke();
+    assert!(a.is_revoked() && a.is_empty());
+    assert_eq!(hub.len(), 1);
+    assert_eq!(a.add(recorder(&seen_a, |_| Value::Null)), Err::<CallbackId, _>(ScopeRevoked));
+    gate.add_permits(1);
+    slow.await.unwrap();
+    feed(&tx, &["/a/1", "/b/1"]).await;
+
+    assert_eq!(drain(&mut results), [json!({ "result": "/b/1", "scope": { "tenant": "b" } })], "the stuck call's result is dropped");
+    assert_eq!(seen(&seen_a), ["/a/slow"]);
+    assert_eq!(seen(&seen_b), ["/b/1"]);
+    assert!(b.remove(b_id));
+    assert!(hub.is_empty());
+
+    handle.shutdown();
+    task.await.unwrap();
+}
@@ -1,26 +1,65 @@
-use std::{future::Future, pin::Pin, sync::Arc};
-use tokio::task::JoinHandle;
+use std::{
+    any::Any,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    sync::mpsc,
+    task::{JoinError, JoinHandle},
+    time::Instant,
+};
 use tokio_util::sync::CancellationToken;
+#[cfg(feature = "tracing")]
+use {crate::spans, tracing::Instrument};
 
-use crate::callbacks::CallbackHub;
+use crate::{
+    callbacks::{CallbackHub, CallbackResult},
+    preflight::PreflightFinding,
+};
 
 pub trait Sensor: Send + 'static {
     type Event: Send + Sync + 'static;
 
-    fn run(self, ctx: SensorCtx<Self::Event>) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+    /// Fire events to `ctx.hub` until `ctx.cancel` is cancelled. Sensors fire into hubs of any
+    /// result type `R`, see [`CallbackHub`].
+    fn run<R: Send + 'static>(self, ctx: SensorCtx<Self::Event, R>) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+
+    /// Check the configured data sources, permissions and watch targets without starting,
+    /// see [`crate::preflight`]. Nothing to check by default.
+    fn preflight(&self) -> Pin<Box<dyn Future<Output = Vec<PreflightFinding>> + Send + '_>> {
+        Box::pin(async { Vec::new() })
+    }
 }
 
-pub struct SensorCtx<E>
+pub struct SensorCtx<E, R = CallbackResult>
 where
     E: Send + Sync + 'static,
 {
     pub cancel: CancellationToken,
-    pub hub: Arc<CallbackHub<E>>,
+    pub hub: Arc<CallbackHub<E, R>>,
+    heartbeat: Heartbeat,
 }
 
 #[derive(Clone)]
 pub struct SensorHandle {
     cancel: CancellationToken,
+    heartbeat: Heartbeat,
+}
+
+/// When the sensor was started and last went round its loop, shared by its ctx and handles.
+#[derive(Clone)]
+struct Heartbeat(Arc<(Instant, Mutex<Option<Instant>>)>);
+
+impl Heartbeat {
+    fn new() -> Self {
+        Self(Arc::new((Instant::now(), Mutex::new(None))))
+    }
+
+    fn last(&self) -> Option<Instant> {
+        *self.0.1.lock().unwrap_or_else(|e| e.into_inner())
+    }
 }
 
 impl SensorHandle {
@@ -30,24 +69,364 @@ impl SensorHandle {
     pub async fn cancelled(&self) {
         self.cancel.cancelled().await;
     }
+
+    /// When the sensor last called [`SensorCtx::beat`], `None` before the first time.
+    pub fn last_beat(&self) -> Option<Instant> {
+        self.heartbeat.last()
+    }
+
+    /// No beat for longer than `threshold`, counting from the start before the first one: the
+    /// task may be alive, but its loop is stuck, e.g. on a blocked read. A sensor shut down or
+    /// ended stops beating as well.
+    pub fn is_stalled(&self, threshold: Duration) -> bool {
+        self.heartbeat.last().unwrap_or(self.heartbeat.0.0).elapsed() > threshold
+    }
 }
 
-impl<E> SensorCtx<E>
+impl<E, R> SensorCtx<E, R>
 where
     E: Send + Sync + 'static,
 {
-    pub fn new(hub: Arc<CallbackHub<E>>) -> (Self, SensorHandle) {
-        let cancel = CancellationToken::new();
-        let handle = SensorHandle { cancel: cancel.clone() };
-        (Self { cancel, hub }, handle)
+    pub fn new(hub: Arc<CallbackHub<E, R>>) -> (Self, SensorHandle) {
+        let handle = SensorHandle { cancel: CancellationToken::new(), heartbeat: Heartbeat::new() };
+        (Self::for_handle(&handle, hub), handle)
+    }
+
+    fn for_handle(handle: &SensorHandle, hub: Arc<CallbackHub<E, R>>) -> Self {
+        Self { cancel: handle.cancel.clone(), hub, heartbeat: handle.heartbeat.clone() }
+    }
+
+    /// Note that the sensor's loop is going round, see [`SensorHandle::is_stalled`]. Sensors
+    /// call it at the top of every poll iteration.
+    pub fn beat(&self) {
+        *self.heartbeat.0.1.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
     }
 }
 
-pub fn spawn_sensor<S>(sensor: S, hub: Arc<CallbackHub<S::Event>>) -> (SensorHandle, JoinHandle<()>)
+/// Run `sensor` on its own task, firing into `hub`. A hub without a sensor name (see
+/// [`CallbackHub::set_sensor_name`]) is named after the sensor's crate, e.g. `procdog`.
+pub fn spawn_sensor<S, R>(sensor: S, hub: Arc<CallbackHub<S::Event, R>>) -> (SensorHandle, JoinHandle<()>)
 where
     S: Sensor,
+    R: Send + 'static,
 {
+    name_after_crate::<S, R>(&hub);
+    start(sensor, hub)
+}
+
+fn name_after_crate<S, R>(hub: &CallbackHub<S::Event, R>)
+where
+    S: Sensor,
+    R: Send + 'static,
+{
+    if hub.sensor_name().is_none() {
+        let path = std::any::type_name::<S>();
+        hub.set_sensor_name(path.split("::").next().unwrap_or(path));
+    }
+}
+
+/// Like [`spawn_sensor`], naming the hub's sensor `name`, e.g. to tell two XMount instances
+/// apart in [`crate::callbacks::Envelope`]s.
+pub fn spawn_sensor_as<S, R>(name: &str, sensor: S, hub: Arc<CallbackHub<S::Event, R>>) -> (SensorHandle, JoinHandle<()>)
+where
+    S: Sensor,
+    R: Send + 'static,
+{
+    hub.set_sensor_name(name);
+    start(sensor, hub)
+}
+
+fn start<S, R>(sensor: S, hub: Arc<CallbackHub<S::Event, R>>) -> (SensorHandle, JoinHandle<()>)
+where
+    S: Sensor,
+    R: Send + 'static,
+{
+    #[cfg(feature = "tracing")]
+    let span = spans::sensor(&hub.sensor_name().unwrap_or_default(), None);
     let (ctx, handle) = SensorCtx::new(hub);
-    let jh = tokio::spawn(sensor.run(ctx));
+    let run = sensor.run(ctx);
+    #[cfg(feature = "tracing")]
+    let run = run.instrument(span);
+    (handle, tokio::spawn(run))
+}
+
+/// Where a [`SensorGroup`] member is at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SensorStatus {
+    Running,
+    /// Ended, on its own or after a shutdown.
+    Finished,
+    Panicked,
+}
+
+/// Several sensors, of any event types, run under one shutdown: each is spawned like
+/// [`spawn_sensor`] with its own hub, [`SensorGroup::handle`] shuts them all down and
+/// [`SensorGroup::join`] waits for the last. A member that ends early leaves the others
+/// running; [`SensorGroupHandle::status`] tells.
+///
+/// ```ignore
+/// let mut group = SensorGroup::new();
+/// group.spawn(XMount::new(None), Arc::new(mount_hub));
+/// group.spawn(NetNotify::new(None), Arc::new(conn_hub));
+/// let handle = group.handle();
+/// tokio::spawn(async move {
+///     let _ = tokio::signal::ctrl_c().await;
+///     handle.shutdown();
+/// });
+/// group.join().await;
+/// ```
+pub struct SensorGroup {
+    handle: SensorGroupHandle,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+/// Shuts down a [`SensorGroup`] and reports on its members.
+#[derive(Clone)]
+pub struct SensorGroupHandle {
+    cancel: CancellationToken,
+    members: Arc<Mutex<Vec<(String, SensorStatus)>>>,
+}
+
+impl Default for SensorGroup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SensorGroup {
+    pub fn new() -> Self {
+        Self { handle: SensorGroupHandle { cancel: CancellationToken::new(), members: Arc::default() }, tasks: Vec::new() }
+    }
+
+    /// Start `sensor` firing into `hub`, named like [`spawn_sensor`] names it. After a
+    /// shutdown it is cancelled right away.
+    pub fn spawn<S, R>(&mut self, sensor: S, hub: Arc<CallbackHub<S::Event, R>>) -> &mut Self
+    where
+        S: Sensor,
+        R: Send + 'static,
+    {
+        name_after_crate::<S, R>(&hub);
+        self.start(sensor, hub)
+    }
+
+    /// Like [`SensorGroup::spawn`], naming the hub's sensor `name` as [`spawn_sensor_as`].
+    pub fn spawn_as<S, R>(&mut self, name: &str, sensor: S, hub: Arc<CallbackHub<S::Event, R>>) -> &mut Self
+    where
+        S: Sensor,
+        R: Send + 'static,
+    {
+        hub.set_sensor_name(name);
+        self.start(sensor, hub)
+    }
+
+    fn start<S, R>(&mut self, sensor: S, hub: Arc<CallbackHub<S::Event, R>>) -> &mut Self
+    where
+        S: Sensor,
+        R: Send + 'static,
+    {
+        let name = hub.sensor_name().map(|n| n.to_string()).unwrap_or_default();
+        #[cfg(feature = "tracing")]
+        let span = spans::sensor(&name, None);
+        let members = self.handle.members.clone();
+        let idx = {
+            let mut members = members.lock().unwrap();
+            members.push((name, SensorStatus::Running));
+            members.len() - 1
+        };
+        let ctx = SensorCtx { cancel: self.handle.cancel.child_token(), hub, heartbeat: Heartbeat::new() };
+        let run = sensor.run(ctx);
+        #[cfg(feature = "tracing")]
+        let run = run.instrument(span);
+        let sensor = tokio::spawn(run);
+        self.tasks.push(tokio::spawn(async move {
+            let status = if sensor.await.is_ok() { SensorStatus::Finished } else { SensorStatus::Panicked };
+            members.lock().unwrap()[idx].1 = status;
+        }));
+        self
+    }
+
+    pub fn handle(&self) -> SensorGroupHandle {
+        self.handle.clone()
+    }
+
+    /// Wait until every member has ended.
+    pub async fn join(self) {
+        futures_util::future::join_all(self.tasks).await;
+    }
+}
+
+impl SensorGroupHandle {
+    /// Cancel every member, see [`SensorHandle::shutdown`].
+    pub fn shutdown(&self) {
+        self.cancel.cancel();
+    }
+
+    pub async fn cancelled(&self) {
+        self.cancel.cancelled().await;
+    }
+
+    /// Each member's sensor name and status, in the order spawned.
+    pub fn status(&self) -> Vec<(String, SensorStatus)> {
+        self.members.lock().unwrap().clone()
+    }
+}
+
+/// When [`supervise_sensor`] starts a sensor again. The delay doubles from `initial` up to
+/// `max_delay` with every restart in a row; a sensor that ran for `max_delay` or longer
+/// before it ended counts as recovered, and the next restart is the first again.
+#[derive(Clone, Debug)]
+pub struct RestartPolicy {
+    pub initial: Duration,
+    pub max_delay: Duration,
+    /// Restarts in a row before giving up, `None` never gives up.
+    pub max_retries: Option<u32>,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self { initial: Duration::from_secs(1), max_delay: Duration::from_secs(60), max_retries: None }
+    }
+}
+
+impl RestartPolicy {
+    pub fn initial(mut self, delay: Duration) -> Self {
+        self.initial = delay;
+        self
+    }
+
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = Some(retries);
+        self
+    }
+
+    /// The delay before restart `retry` in a row, from 0.
+    fn delay(&self, retry: u32) -> Duration {
+        self.initial.saturating_mul(2u32.saturating_pow(retry)).min(self.max_delay)
+    }
+}
+
+/// What [`supervise_sensor`] reports about the sensor it runs. Attempts count from 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Lifecycle {
+    Started {
+        attempt: u32,
+    },
+    /// The sensor ended, or panicked, without a shutdown.
+    Crashed {
+        attempt: u32,
+        reason: String,
+    },
+    /// `attempt` starts after `delay`.
+    Restarting {
+        attempt: u32,
+        delay: Duration,
+    },
+    /// Out of retries, see [`RestartPolicy::max_retries`]; the supervisor has ended.
+    GaveUp {
+        attempts: u32,
+    },
+}
+
+/// Run the sensor `factory` makes on its own task like [`spawn_sensor`], and make a new one
+/// whenever it ends (or panics) before [`SensorHandle::shutdown`], after a delay as `policy`
+/// has it. Each start, crash, restart and giving up goes to `lifecycle`; the supervisor waits
+/// for room on it, and carries on once it is closed. The returned task ends with the last
+/// sensor. The handle's heartbeat is that of whichever sensor is running.
+pub fn supervise_sensor<S, F, R>(
+    mut factory: F, hub: Arc<CallbackHub<S::Event, R>>, policy: RestartPolicy, lifecycle: mpsc::Sender<Lifecycle>,
+) -> (SensorHandle, JoinHandle<()>)
+where
+    S: Sensor,
+    F: FnMut() -> S + Send + 'static,
+    R: Send + 'static,
+{
+    name_after_crate::<S, R>(&hub);
+    let handle = SensorHandle { cancel: CancellationToken::new(), heartbeat: Heartbeat::new() };
+    let (cancel, beats) = (handle.cancel.clone(), handle.clone());
+    let jh = tokio::spawn(async move {
+        let mut retry = 0;
+        for attempt in 1.. {
+            let _ = lifecycle.send(Lifecycle::Started { attempt }).await;
+            let started = Instant::now();
+            let ctx = SensorCtx::for_handle(&beats, hub.clone());
+            let run = factory().run(ctx);
+            #[cfg(feature = "tracing")]
+            let run = run.instrument(spans::sensor(&hub.sensor_name().unwrap_or_default(), Some(attempt)));
+            let ended = tokio::spawn(run).await;
+            if cancel.is_cancelled() {
+                break;
+            }
+
+            let reason = match ended {
+                Ok(()) => "ended before shutdown".to_string(),
+                Err(e) => crash_reason(e),
+            };
+            let _ = lifecycle.send(Lifecycle::Crashed { attempt, reason }).await;
+            if started.elapsed() >= policy.max_delay {
+                retry = 0;
+            }
+            if policy.max_retries.is_some_and(|max| retry >= max) {
+                let _ = lifecycle.send(Lifecycle::GaveUp { attempts: attempt }).await;
+                break;
+            }
+
+            let delay = policy.delay(retry);
+            retry += 1;
+            let _ = lifecycle.send(Lifecycle::Restarting { attempt: attempt + 1, delay }).await;
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                _ = tokio::time::sleep(delay) => {}
+            }
+        }
+    });
     (handle, jh)
 }
+
+fn crash_reason(e: JoinError) -> String {
+    let Ok(payload) = e.try_into_panic() else {
+        return "aborted".to_string();
+    };
+    let msg = |p: &(dyn Any + Send)| p.downcast_ref::<&str>().map(|s| s.to_string()).or_else(|| p.downcast_ref::<String>().cloned());
+    match msg(payload.as_ref()) {
+        Some(m) => format!("panicked: {m}"),
+        None => "panicked".to_string(),
+    }
+}
+
+/// The glue of a CLI or example: run `sensor` with `hub` until `stop` resolves (e.g.
+/// `tokio::signal::ctrl_c()`), or until the sensor ends on its own, handing every callback
+/// result to `on_result`. Returns once the sensor has shut down and the results it produced
+/// are handed over; true if `stop` ended it.
+pub async fn run_until<S, F, R, H>(sensor: S, mut hub: CallbackHub<S::Event, R>, stop: F, mut on_result: H) -> bool
+where
+    S: Sensor,
+    F: Future,
+    R: Send + 'static,
+    H: FnMut(R),
+{
+    let (tx, mut rx) = mpsc::channel::<R>(0xfff);
+    hub.set_result_channel(tx);
+    let (handle, mut task) = spawn_sensor(sensor, Arc::new(hub));
+
+    tokio::pin!(stop);
+    let mut stopped = false;
+    loop {
+        tokio::select! {
+            _ = &mut stop, if !stopped => {
+                stopped = true;
+                handle.shutdown();
+            }
+            Some(r) = rx.recv() => on_result(r),
+            _ = &mut task => break,
+        }
+    }
+    while let Ok(r) = rx.try_recv() {
+        on_result(r);
+    }
+    stopped
+}
@@ -0,0 +1,202 @@
+use crate::{
+    callbacks::CallbackHub,
+    sensor::{Lifecycle, RestartPolicy, Sensor, SensorCtx, SensorGroup, SensorStatus, spawn_sensor, supervise_sensor},
+};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::Duration,
+};
+use tokio::sync::mpsc::{Receiver, channel};
+
+/// Ends (or panics) on its own for the first `crashes` runs, then runs until shut down.
+struct Flaky {
+    run: u32,
+    crashes: u32,
+    panics: bool,
+}
+
+impl Sensor for Flaky {
+    type Event = u32;
+
+    fn run<R: Send + 'static>(self, ctx: SensorCtx<u32, R>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async move {
+            if self.run > self.crashes {
+                ctx.cancel.cancelled().await;
+            } else if self.panics {
+                panic!("run {} failed", self.run);
+            }
+        })
+    }
+}
+
+fn flaky(crashes: u32, panics: bool) -> impl FnMut() -> Flaky + Send + 'static {
+    let runs = Arc::new(AtomicU32::new(0));
+    move || Flaky { run: runs.fetch_add(1, Ordering::SeqCst) + 1, crashes, panics }
+}
+
+fn drain(rx: &mut Receiver<Lifecycle>) -> Vec<Lifecycle> {
+    let mut out = Vec::new();
+    while let Ok(ev) = rx.try_recv() {
+        out.push(ev);
+    }
+    out
+}
+
+fn delays(events: &[Lifecycle]) -> Vec<Duration> {
+    events.iter().filter_map(|ev| if let Lifecycle::Restarting { delay, .. } = ev { Some(*delay) } else { None }).collect()
+}
+
+fn crashed(attempt: u32) -> Lifecycle {
+    Lifecycle::Crashed { attempt, reason: "ended before shutdown".into() }
+}
+
+#[tokio::test(start_paused = true)]
+async fn restarts_with_doubling_delays_until_it_stays_up() {
+    let (tx, mut rx) = channel(64);
+    let (handle, task) = supervise_sensor(flaky(3, false), Arc::new(CallbackHub::<u32>::new()), RestartPolicy::default(), tx);
+    tokio::time::sleep(Duration::from_secs(60)).await;
+    handle.shutdown();
+    task.await.unwrap();
+
+    let secs = Duration::from_secs;
+    assert_eq!(
+        drain(&mut rx),
+        [
+            Lifecycle::Started { attempt: 1 },
+            crashed(1),
+            Lifecycle::Restarting { attempt: 2, delay: secs(1) },
+            Lifecycle::Started { attempt: 2 },
+            crashed(2),
+            Lifecycle::Restarting { attempt: 3, delay: secs(2) },
+            Lifecycle::Started { attempt: 3 },
+            crashed(3),
+            Lifecycle::Restarting { attempt: 4, delay: secs(4) },
+            Lifecycle::Started { attempt: 4 },
+        ]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn gives_up_after_max_retries_with_capped_delays() {
+    let (tx, mut rx) = channel(64);
+    let policy = RestartPolicy::default().initial(Duration::from_secs(4)).max_delay(Duration::from_secs(5)).max_retries(2);
+    let (_handle, task) = supervise_sensor(flaky(u32::MAX, true), Arc::new(CallbackHub::<u32>::new()), policy, tx);
+    task.await.unwrap();
+
+    let events = drain(&mut rx);
+    assert_eq!(delays(&events), [Duration::from_secs(4), Duration::from_secs(5)]);
+    assert_eq!(events[1], Lifecycle::Crashed { attempt: 1, reason: "panicked: run 1 failed".into() });
+    assert_eq!(events.last(), Some(&Lifecycle::GaveUp { attempts: 3 }));
+}
+
+#[tokio::test(start_paused = true)]
+async fn shutdown_during_the_delay_does_not_restart() {
+    let (tx, mut rx) = channel(64);
+    let (handle, task) = supervise_sensor(flaky(1, false), Arc::new(CallbackHub::<u32>::new()), RestartPolicy::default(), tx);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    handle.shutdown();
+    task.await.unwrap();
+    assert_eq!(drain(&mut rx).last(), Some(&Lifecycle::Restarting { attempt: 2, delay: Duration::from_secs(1) }));
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_long_run_resets_the_backoff() {
+    /// Crashes after running for a while, every time.
+    struct Tired;
+
+    impl Sensor for Tired {
+        type Event = u32;
+
+        fn run<R: Send + 'static>(self, _: SensorCtx<u32, R>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+            Box::pin(tokio::time::sleep(Duration::from_secs(10)))
+        }
+    }
+
+    let (tx, mut rx) = channel(64);
+    let policy = RestartPolicy::default().max_delay(Duration::from_secs(10)).max_retries(1);
+    let (handle, task) = supervise_sensor(|| Tired, Arc::new(CallbackHub::<u32>::new()), policy, tx);
+    tokio::time::sleep(Duration::from_secs(60)).await;
+    handle.shutdown();
+    task.await.unwrap();
+    let events = drain(&mut rx);
+    assert!(!events.iter().any(|ev| matches!(ev, Lifecycle::GaveUp { .. })), "{events:?}");
+    let delays = delays(&events);
+    assert!(delays.len() >= 4 && delays.iter().all(|d| *d == Duration::from_secs(1)), "{delays:?}");
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_group_reports_a_member_that_ended_and_shuts_down_the_rest() {
+    /// Events of another type than Flaky's.
+    struct Quiet;
+
+    impl Sensor for Quiet {
+        type Event = String;
+
+        fn run<R: Send + 'static>(self, ctx: SensorCtx<String, R>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+            Box::pin(async move { ctx.cancel.cancelled().await })
+        }
+    }
+
+    let mut group = SensorGroup::new();
+    group
+        .spawn_as("early", Flaky { run: 1, crashes: 1, panics: false }, Arc::new(CallbackHub::<u32>::new()))
+        .spawn_as("broken", Flaky { run: 1, crashes: 1, panics: true }, Arc::new(CallbackHub::<u32>::new()))
+        .spawn(Quiet, Arc::new(CallbackHub::<String>::new()));
+    let handle = group.handle();
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let name = |s: &str| s.to_string();
+    assert_eq!(
+        handle.status(),
+        [(name("early"), SensorStatus::Finished), (name("broken"), SensorStatus::Panicked), (name("omnitrace_core"), SensorStatus::Running)]
+    );
+
+    handle.shutdown();
+    group.join().await;
+    assert_eq!(handle.status()[2], (name("omnitrace_core"), SensorStatus::Finished));
+}
+
+/// Beats every 100ms, and blocks for good after `ticks` of them.
+struct Stalling {
+    ticks: u32,
+}
+
+impl Sensor for Stalling {
+    type Event = u32;
+
+    fn run<R: Send + 'static>(self, ctx: SensorCtx<u32, R>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async move {
+            for _ in 0..self.ticks {
+                ctx.beat();
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            std::future::pending::<()>().await;
+        })
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_stuck_loop_stops_beating() {
+    let (handle, task) = spawn_sensor(Stalling { ticks: 5 }, Arc::new(CallbackHub::<u32>::new()));
+    assert_eq!(handle.last_beat(), None);
+    assert!(!handle.is_stalled(Duration::from_secs(1)));
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    let beat = handle.last_beat().unwrap();
+    assert_eq!(tokio::time::Instant::now() - beat, Duration::from_millis(50));
+    assert!(!handle.is_stalled(Duration::from_secs(1)));
+
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    assert!(handle.is_stalled(Duration::from_secs(1)));
+    assert!(!handle.is_stalled(Duration::from_secs(3)));
+    task.abort();
+
+    // never beating counts from the start
+    let (handle, task) = spawn_sensor(Stalling { ticks: 0 }, Arc::new(CallbackHub::<u32>::new()));
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!((handle.last_beat(), handle.is_stalled(Duration::from_secs(1))), (None, true));
+    task.abort();
+}
@@ -0,0 +1,223 @@
+use crate::{
+    fields::{self, EventFields},
+    router::{FieldMatch, Payload},
+    topics::{TopicError, TopicPattern},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Key added to serialized events by [`SeverityMapper::tag`].
+pub const SEVERITY_FIELD: &str = "severity";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Debug,
+    #[default]
+    Info,
+    Warning,
+    Critical,
+}
+
+/// Condition on a field of the serialized event: exact value or string prefix. Typed events
+/// are matched on their [`EventFields`], see [`FieldMatch`].
+///
+/// ```json
+/// { "field": "Missing.name", "equals": "sshd" }
+/// { "field": "Changed.path", "prefix": "/etc/" }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Predicate {
+    Equals(FieldMatch),
+    Prefix { field: String, prefix: String },
+}
+
+impl Predicate {
+    pub fn prefix<S: Into<String>, P: Into<String>>(field: S, prefix: P) -> Self {
+        Predicate::Prefix { field: field.into(), prefix: prefix.into() }
+    }
+
+    pub fn matches(&self, payload: &Value) -> bool {
+        match self {
+            Predicate::Equals(m) => m.matches(payload),
+            Predicate::Prefix { field, prefix } => {
+                payload.pointer(&fields::pointer(field)).and_then(Value::as_str).is_some_and(|s| s.starts_with(prefix.as_str()))
+            }
+        }
+    }
+
+    pub fn matches_fields(&self, ev: &dyn EventFields) -> bool {
+        match self {
+            Predicate::Equals(m) => m.matches_fields(ev),
+            Predicate::Prefix { field, prefix } => {
+                fields::get_in_variant(ev, field).is_some_and(|v| v.as_str().is_some_and(|s| s.starts_with(prefix.as_str())))
+            }
+        }
+    }
+
+    fn matches_payload(&self, payload: Payload<'_>) -> bool {
+        match payload {
+            Payload::Json(v) => self.matches(v),
+            Payload::Typed(ev) => self.matches_fields(ev),
+        }
+    }
+}
+
+/// One severity rule. Unset selectors match anything.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SeverityRule {
+    #[serde(default)]
+    pub sensor: Option<String>,
+    #[serde(default)]
+    pub mask: Option<u64>,
+    /// Only typed events have a topic, see [`crate::router::RouterConfig`].
+    #[serde(default)]
+    pub topic: Option<TopicPattern>,
+    #[serde(default)]
+    pub when: Option<Predicate>,
+    pub severity: Severity,
+}
+
+impl SeverityRule {
+    pub fn new(severity: Severity) -> Self {
+        Self { sensor: None, mask: None, topic: None, when: None, severity }
+    }
+
+    /// Only match events coming from this sensor.
+    pub fn sensor<S: Into<String>>(mut self, sensor: S) -> Self {
+        self.sensor = Some(sensor.into());
+        self
+    }
+
+    /// Only match events whose mask intersects these bits.
+    pub fn mask(mut self, mask: u64) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+
+    /// Only match typed events whose topic `pattern` selects, see [`crate::topics`].
+    pub fn topic(mut self, pattern: &str) -> Result<Self, TopicError> {
+        self.topic = Some(TopicPattern::parse(pattern)?);
+        Ok(self)
+    }
+
+    /// Only match events satisfying the predicate.
+    pub fn when(mut self, p: Predicate) -> Self {
+        self.when = Some(p);
+        self
+    }
+
+    fn matches(&self, sensor: &str, mask: u64, payload: Payload<'_>) -> bool {
+        self.sensor.as_deref().is_none_or(|s| s == sensor)
+            && self.mask.is_none_or(|m| m & mask != 0)
+            && self.topic.as_ref().is_none_or(|t| payload.topic_matches(t))
+            && self.when.as_ref().is_none_or(|w| w.matches_payload(payload))
+    }
+}
+
+/// Serializable severity setup. Rules are tried before the built-in defaults
+/// (unless `defaults` is false), the first match wins.
+///
+/// ```json
+/// {
+///   "rules": [
+///     { "sensor": "procdog", "mask": 4, "when": { "field": "Missing.name", "equals": "cron" }, "severity": "info" },
+///     { "sensor": "filescream", "when": { "field": "Changed.path", "prefix": "/srv/www/" }, "severity": "warning" },
+///     { "topic": "mount.health.faulted", "severity": "critical" }
+///   ],
+///   "fallback": "info"
+/// }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SeverityConfig {
+    #[serde(default)]
+    pub rules: Vec<SeverityRule>,
+    #[serde(default = "default_true")]
+    pub defaults: bool,
+    #[serde(default)]
+    pub fallback: Severity,
+}
+
+impl Default for SeverityConfig {
+    fn default() -> Self {
+        Self { rules: Vec::new(), defaults: true, fallback: Severity::default() }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Maps events to a [`Severity`], so "what is alertable" lives in one place
+/// instead of in every callback.
+#[derive(Clone, Debug)]
+pub struct SeverityMapper {
+    rules: Vec<SeverityRule>,
+    fallback: Severity,
+}
+
+impl Default for SeverityMapper {
+    fn default() -> Self {
+        Self::from_config(SeverityConfig::default())
+    }
+}
+
+impl SeverityMapper {
+    /// Empty mapper: everything maps to `fallback`.
+    pub fn new(fallback: Severity) -> Self {
+        Self { rules: Vec::new(), fallback }
+    }
+
+    pub fn from_config(cfg: SeverityConfig) -> Self {
+        let mut m = Self::new(cfg.fallback);
+        m.rules = cfg.rules;
+        if cfg.defaults {
+            m.rules.extend(Self::default_rules());
+        }
+        m
+    }
+
+    /// The built-in table (mask bits are those of the respective sensor event types):
+    ///
+    /// - xmount `Unmounted` (watched mounts only) => Warning
+    /// - procdog `Missing` => Critical
+    /// - netpacket `Opened` => Info
+    /// - filescream `Changed` under `/etc/` => Warning
+    pub fn default_rules() -> Vec<SeverityRule> {
+        vec![
+            SeverityRule::new(Severity::Warning).sensor("xmount").mask(0b0010),
+            SeverityRule::new(Severity::Critical).sensor("procdog").mask(0b0100),
+            SeverityRule::new(Severity::Info).sensor("netpacket").mask(0b0001),
+            SeverityRule::new(Severity::Warning).sensor("filescream").mask(0b0010).when(Predicate::prefix("Changed.path", "/etc/")),
+        ]
+    }
+
+    /// Add a rule. Rules added later are tried after the ones already present.
+    pub fn add_rule(&mut self, rule: SeverityRule) {
+        self.rules.push(rule);
+    }
+
+    pub fn severity(&self, sensor: &str, mask: u64, payload: &Value) -> Severity {
+        self.severity_of(sensor, mask, Payload::Json(payload))
+    }
+
+    /// [`SeverityMapper::severity`] of a typed event, without serializing it.
+    pub fn severity_of_event(&self, sensor: &str, mask: u64, ev: &(dyn EventFields + Sync)) -> Severity {
+        self.severity_of(sensor, mask, Payload::Typed(ev))
+    }
+
+    pub(crate) fn severity_of(&self, sensor: &str, mask: u64, payload: Payload<'_>) -> Severity {
+        self.rules.iter().find(|r| r.matches(sensor, mask, payload)).map(|r| r.severity).unwrap_or(self.fallback)
+    }
+
+    /// Compute the severity and store it in the payload under [`SEVERITY_FIELD`]
+    /// (object payloads only).
+    pub fn tag(&self, sensor: &str, mask: u64, payload: &mut Value) -> Severity {
+        let sev = self.severity(sensor, mask, payload);
+        if let Value::Object(map) = payload {
+            map.insert(SEVERITY_FIELD.to_string(), serde_json::to_value(sev).unwrap_or_default());
+        }
+        sev
+    }
+}
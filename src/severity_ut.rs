@@ -0,0 +1,84 @@
+use crate::{
+    router::{FieldMatch, Router, RouterConfig},
+    severity::{Predicate, Severity, SeverityConfig, SeverityMapper, SeverityRule},
+};
+use serde_json::json;
+use tokio::sync::mpsc::channel;
+
+#[test]
+fn default_table() {
+    let m = SeverityMapper::default();
+
+    assert_eq!(m.severity("xmount", 0b0010, &json!({ "Unmounted": { "target": "/data" } })), Severity::Warning);
+    assert_eq!(m.severity("xmount", 0b0001, &json!({ "Mounted": { "target": "/data" } })), Severity::Info);
+    assert_eq!(m.severity("procdog", 0b0100, &json!({ "Missing": { "name": "sshd" } })), Severity::Critical);
+    assert_eq!(m.severity("netpacket", 0b0001, &json!({ "Opened": { "conn": {} } })), Severity::Info);
+    assert_eq!(m.severity("filescream", 0b0010, &json!({ "Changed": { "path": "/etc/passwd" } })), Severity::Warning);
+    assert_eq!(m.severity("filescream", 0b0010, &json!({ "Changed": { "path": "/tmp/etc/x" } })), Severity::Info);
+    assert_eq!(m.severity("filescream", 0b0001, &json!({ "Created": { "path": "/etc/x" } })), Severity::Info);
+}
+
+#[test]
+fn rule_precedence() {
+    let cfg: SeverityConfig = serde_json::from_value(json!({
+        "rules": [
+            { "sensor": "procdog", "mask": 4, "when": { "field": "Missing.name", "equals": "cron" }, "severity": "debug" },
+            { "sensor": "filescream", "when": { "field": "Changed.path", "prefix": "/etc/motd" }, "severity": "info" }
+        ],
+        "fallback": "debug"
+    }))
+    .unwrap();
+    let m = SeverityMapper::from_config(cfg);
+
+    // user rules win over defaults, first match wins
+    assert_eq!(m.severity("procdog", 0b0100, &json!({ "Missing": { "name": "cron" } })), Severity::Debug);
+    assert_eq!(m.severity("procdog", 0b0100, &json!({ "Missing": { "name": "sshd" } })), Severity::Critical);
+    assert_eq!(m.severity("filescream", 0b0010, &json!({ "Changed": { "path": "/etc/motd" } })), Severity::Info);
+    assert_eq!(m.severity("filescream", 0b0010, &json!({ "Changed": { "path": "/etc/shadow" } })), Severity::Warning);
+    // nothing matched
+    assert_eq!(m.severity("iface", 0b0001, &json!({})), Severity::Debug);
+
+    // defaults can be turned off
+    let m = SeverityMapper::from_config(serde_json::from_value(json!({ "defaults": false })).unwrap());
+    assert_eq!(m.severity("procdog", 0b0100, &json!({})), Severity::Info);
+
+    let mut m = SeverityMapper::new(Severity::Info);
+    m.add_rule(SeverityRule::new(Severity::Critical).when(Predicate::Equals(FieldMatch::new("Unmounted.target", "/"))));
+    m.add_rule(SeverityRule::new(Severity::Warning).mask(0b0010));
+    assert_eq!(m.severity("xmount", 0b0010, &json!({ "Unmounted": { "target": "/" } })), Severity::Critical);
+    assert_eq!(m.severity("xmount", 0b0010, &json!({ "Unmounted": { "target": "/mnt" } })), Severity::Warning);
+
+    // keys with `/` or `~` in them match as written
+    let mut m = SeverityMapper::new(Severity::Info);
+    m.add_rule(SeverityRule::new(Severity::Critical).when(Predicate::prefix("Changed.a/b~c", "/etc/")));
+    assert_eq!(m.severity("filescream", 0b0010, &json!({ "Changed": { "a/b~c": "/etc/shadow" } })), Severity::Critical);
+    assert_eq!(m.severity("filescream", 0b0010, &json!({ "Changed": { "a": { "b~c": "/etc/shadow" } } })), Severity::Info);
+}
+
+#[tokio::test]
+async fn router_tags_and_filters_by_min_severity() {
+    let cfg: RouterConfig = serde_json::from_value(json!({
+        "default": ["jsonl", "webhook"],
+        "severity": {},
+        "min_severity": { "webhook": "warning" }
+    }))
+    .unwrap();
+    let mut router = Router::from_config(cfg);
+    let (jsonl_tx, mut jsonl) = channel(16);
+    let (webhook_tx, mut webhook) = channel(16);
+    router.add_sink("jsonl", jsonl_tx);
+    router.add_sink("webhook", webhook_tx);
+
+    router.dispatch("xmount", 0b0001, &json!({ "Mounted": { "target": "/data" } })).await;
+    router.dispatch("xmount", 0b0010, &json!({ "Unmounted": { "target": "/data" } })).await;
+
+    assert_eq!(jsonl.try_recv().unwrap()["severity"], "info");
+    assert_eq!(jsonl.try_recv().unwrap()["severity"], "warning");
+
+    let alert = webhook.try_recv().unwrap();
+    assert_eq!(alert, json!({ "Unmounted": { "target": "/data" }, "severity": "warning" }));
+    assert!(webhook.try_recv().is_err());
+
+    let stats = router.stats();
+    assert_eq!((stats[0].routed, stats[0].dropped), (3, 1));
+}
@@ -0,0 +1,91 @@
+//! One read of a data source for several sensor instances.
+//!
+//! Two instances of a sensor over the same source (a fast ProcDog for security next to a slow
+//! one for metrics, each with its own rules and hub) would each read it on their own ticks,
+//! doubling the cost. Wrapped in a [`SharedSource`] with a base interval, the source is read
+//! at most once per tick: an instance whose pulse is a multiple of the base interval gets the
+//! snapshot another instance took for the same tick, or takes it for the others.
+//!
+//! A snapshot serves every read within half the base interval after it, so ticks on the same
+//! beat share it despite timer jitter, and the next beat reads anew. Pulses that are not
+//! multiples of the base still work, they just share less. Failed reads are not kept: the
+//! next instance tries again. Clones share the source; it is freed with the last one.
+
+use std::{
+    future::Future,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{sync::Mutex, time::Instant};
+
+/// A source read at most once per tick, see the [module docs](self).
+pub struct SharedSource<T> {
+    inner: Arc<Inner<T>>,
+}
+
+struct Inner<T> {
+    base: Duration,
+    last: Mutex<Option<(Instant, T)>>,
+    reads: AtomicU64,
+    shared: AtomicU64,
+}
+
+impl<T> Clone for SharedSource<T> {
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone() }
+    }
+}
+
+impl<T: Clone + Send> SharedSource<T> {
+    pub fn new(base: Duration) -> Self {
+        Self { inner: Arc::new(Inner { base, last: Mutex::new(None), reads: AtomicU64::new(0), shared: AtomicU64::new(0) }) }
+    }
+
+    pub fn base(&self) -> Duration {
+        self.inner.base
+    }
+
+    /// The snapshot of this tick, calling `read` for it if no instance has yet. Instances
+    /// asking while a read is under way wait for it rather than reading themselves.
+    pub async fn get<F, Fut, E>(&self, read: F) -> Result<T, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut last = self.inner.last.lock().await;
+        if let Some((at, snapshot)) = last.as_ref()
+            && at.elapsed() < self.inner.base / 2
+        {
+            self.inner.shared.fetch_add(1, Ordering::Relaxed);
+            return Ok(snapshot.clone());
+        }
+        let snapshot = read().await?;
+        self.inner.reads.fetch_add(1, Ordering::Relaxed);
+        *last = Some((Instant::now(), snapshot.clone()));
+        Ok(snapshot)
+    }
+
+    /// Drop the snapshot, so the next [`SharedSource::get`] reads, e.g. after the source was
+    /// reconfigured.
+    pub async fn invalidate(&self) {
+        *self.inner.last.lock().await = None;
+    }
+
+    /// Reads of the source so far.
+    pub fn reads(&self) -> u64 {
+        self.inner.reads.load(Ordering::Relaxed)
+    }
+
+    /// Snapshots handed out without a read.
+    pub fn shared(&self) -> u64 {
+        self.inner.shared.load(Ordering::Relaxed)
+    }
+
+    /// Clones of this source alive, i.e. the instances sharing it.
+    pub fn users(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+}
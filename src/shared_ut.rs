@@ -0,0 +1,51 @@
+use crate::shared::SharedSource;
+use std::{
+    io,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::Duration,
+};
+
+async fn read(source: &SharedSource<u32>, counter: &AtomicU32) -> u32 {
+    source.get(|| async { Ok::<_, io::Error>(counter.fetch_add(1, Ordering::SeqCst)) }).await.unwrap()
+}
+
+#[tokio::test(start_paused = true)]
+async fn instances_on_the_same_beat_share_one_read() {
+    let counter = AtomicU32::new(0);
+    let fast = SharedSource::<u32>::new(Duration::from_secs(1));
+    let slow = fast.clone();
+    assert_eq!(fast.users(), 2);
+
+    // fast ticks every second, slow every third; slow's ticks come a bit later
+    let mut seen = Vec::new();
+    for beat in 0..6 {
+        let fast_saw = read(&fast, &counter).await;
+        if beat % 3 == 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            seen.push((fast_saw, read(&slow, &counter).await));
+            tokio::time::sleep(Duration::from_millis(995)).await;
+        } else {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+    assert_eq!(seen, [(0, 0), (3, 3)]);
+    assert_eq!((fast.reads(), fast.shared()), (6, 2));
+
+    fast.invalidate().await;
+    assert_eq!(read(&slow, &counter).await, 6);
+    drop(slow);
+    assert_eq!(fast.users(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn failed_reads_are_not_shared() {
+    let source = SharedSource::<u32>::new(Duration::from_secs(1));
+    let failed = source.get(|| async { Err::<u32, _>(io::Error::other("busy")) }).await;
+    assert!(failed.is_err());
+    let counter = Arc::new(AtomicU32::new(7));
+    assert_eq!(read(&source, &counter).await, 7);
+    assert_eq!(source.reads(), 1);
+}
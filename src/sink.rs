@@ -0,0 +1,377 @@
+//! Event sinks: where serialized events end up, behind one trait.
+//!
+//! An [`EventSink`] takes events as JSON. [`SinkCallback`] puts one on a [`CallbackHub`]
+//! like any callback, and [`JsonlFileSink`] is the one shipped here: newline-delimited JSON
+//! appended to a file, buffered, fsynced by a [`FsyncPolicy`] and rotated by size.
+//!
+//! ```ignore
+//! let sink = JsonlFileSink::open("/var/log/omnitrace/events.jsonl", JsonlConfig::default())?;
+//! hub.add(SinkCallback::new(XMountMask::all().bits(), sink));
+//! ```
+//!
+//! Its [`EventSink`] methods do the file I/O on tokio's blocking threads, so a hub firing into
+//! it does not stall on a slow disk. With [`FsyncPolicy::Interval`] a timer started by the
+//! first write flushes and syncs what is buffered every `fsync_interval_ms`, writes or not.
+//!
+//! Rotation moves `events.jsonl` to `events.jsonl.1`, the `.1` before it to `.2` and so on,
+//! keeping `keep` of them. A line torn by a crash mid-write is cut off the end of the file
+//! when it is opened again, so the file always holds whole lines.
+//!
+//! [`CallbackHub`]: crate::callbacks::CallbackHub
+
+use crate::{
+    callbacks::{Callback, CallbackResult},
+    durable::FsyncPolicy,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, MutexGuard, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{Mutex, mpsc},
+    task::JoinHandle,
+    time::MissedTickBehavior,
+};
+
+/// Somewhere events are written to.
+#[async_trait]
+pub trait EventSink: Send {
+    async fn write(&mut self, ev: &Value) -> io::Result<()>;
+
+    /// Push out whatever is buffered.
+    async fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes every event its mask matches to a sink, and returns no result. Failed writes are
+/// logged and counted, see [`SinkCallback::errors`]. Clones write to the same sink, so one
+/// can be kept to flush it after the other went to a hub.
+pub struct SinkCallback<S> {
+    mask: u64,
+    sink: Arc<Mutex<S>>,
+    errors: Arc<AtomicU64>,
+}
+
+impl<S> Clone for SinkCallback<S> {
+    fn clone(&self) -> Self {
+        Self { mask: self.mask, sink: self.sink.clone(), errors: self.errors.clone() }
+    }
+}
+
+impl<S: EventSink> SinkCallback<S> {
+    pub fn new(mask: u64, sink: S) -> Self {
+        Self { mask, sink: Arc::new(Mutex::new(sink)), errors: Arc::default() }
+    }
+
+    /// Events that could not be serialized or written so far.
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// Flush the sink, e.g. before shutting down.
+    pub async fn flush(&self) -> io::Result<()> {
+        self.sink.lock().await.flush().await
+    }
+}
+
+#[async_trait]
+impl<S, E, R> Callback<E, R> for SinkCallback<S>
+where
+    S: EventSink,
+    E: Serialize + Sync,
+    R: Send,
+{
+    fn mask(&self) -> u64 {
+        self.mask
+    }
+
+    async fn call(&self, ev: &E) -> Option<R> {
+        let res = match serde_json::to_value(ev) {
+            Ok(v) => self.sink.lock().await.write(&v).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = res {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+            log::error!("sink: failed to write an event: {e}");
+        }
+        None
+    }
+}
+
+/// ```json
+/// { "max_bytes": "64MiB", "keep": 5, "buffer_bytes": "64KiB", "fsync": "interval", "fsync_interval_ms": "1s" }
+/// ```
+///
+/// Sizes and the interval also take plain numbers, of bytes and milliseconds.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonlConfig {
+    /// Rotate before a line would take the file past this size; 0: never.
+    #[serde(default = "default_max_bytes", deserialize_with = "crate::units::bytes::deserialize")]
+    pub max_bytes: u64,
+    /// Rotated files kept; 0 drops the old file at rotation.
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+    /// Lines are written to the file once this much is buffered, or when the fsync policy
+    /// syncs, the sink is flushed or it rotates.
+    #[serde(default = "default_buffer_bytes", deserialize_with = "crate::units::bytes::deserialize")]
+    pub buffer_bytes: usize,
+    #[serde(default)]
+    pub fsync: FsyncPolicy,
+    #[serde(default = "default_fsync_interval", deserialize_with = "crate::units::ms::deserialize")]
+    pub fsync_interval_ms: u64,
+}
+
+impl Default for JsonlConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: default_max_bytes(),
+            keep: default_keep(),
+            buffer_bytes: default_buffer_bytes(),
+            fsync: FsyncPolicy::default(),
+            fsync_interval_ms: default_fsync_interval(),
+        }
+    }
+}
+
+fn default_max_bytes() -> u64 {
+    64 << 20
+}
+
+fn default_keep() -> usize {
+    5
+}
+
+fn default_buffer_bytes() -> usize {
+    64 << 10
+}
+
+fn default_fsync_interval() -> u64 {
+    1000
+}
+
+/// Appends events as JSON lines to a file, see the module docs.
+pub struct JsonlFileSink {
+    path: PathBuf,
+    file: Arc<std::sync::Mutex<JsonlFile>>,
+    // the interval sync timer was started, if the policy has one
+    syncing: bool,
+}
+
+impl JsonlFileSink {
+    /// Open `path` for appending, creating it if missing and cutting off a torn last line.
+    pub fn open<P: AsRef<Path>>(path: P, cfg: JsonlConfig) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new().create(true).read(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+        let good = whole_lines(&mut file, len)?;
+        if good < len {
+            log::warn!("sink: {} ends in a torn line, cutting {} bytes", path.display(), len - good);
+            file.set_len(good)?;
+        }
+        let file = BufWriter::with_capacity(cfg.buffer_bytes, file);
+        let file = JsonlFile { path: path.clone(), cfg, file, size: good, last_sync: Instant::now(), dirty: false };
+        Ok(Self { path, file: Arc::new(std::sync::Mutex::new(file)), syncing: false })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append one line; `line` must not contain a newline. Blocks, as do [`Self::sync_now`]
+    /// and [`Self::rotate`]; async code writes through [`EventSink`].
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        lock(&self.file).write_line(line)
+    }
+
+    /// Write out the buffer and fsync, whatever the policy.
+    pub fn sync_now(&mut self) -> io::Result<()> {
+        lock(&self.file).sync(true)
+    }
+
+    /// Move the file to `<path>.1`, shifting older ones up to `keep`, and start a new one.
+    pub fn rotate(&mut self) -> io::Result<()> {
+        lock(&self.file).rotate()
+    }
+
+    /// Write everything received on `rx` until all senders are gone, e.g. a
+    /// [`crate::router::Router`] sink. The buffer is written out whenever `rx` runs empty.
+    pub fn spawn(mut self, mut rx: mpsc::Receiver<CallbackResult>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            while let Some(ev) = rx.recv().await {
+                let mut res = self.write(&ev).await;
+                if res.is_ok() && rx.is_empty() {
+                    res = self.flush().await;
+                }
+                if let Err(e) = res {
+                    log::error!("sink: failed to write {}: {e}", self.path.display());
+                }
+            }
+            if let Err(e) = self.blocking(|f| f.sync(true)).await {
+                log::error!("sink: failed to sync {}: {e}", self.path.display());
+            }
+        })
+    }
+
+    /// Run `op` on the file on a blocking thread.
+    async fn blocking<T, F>(&self, op: F) -> io::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut JsonlFile) -> io::Result<T> + Send + 'static,
+    {
+        let file = self.file.clone();
+        tokio::task::spawn_blocking(move || op(&mut lock(&file))).await.map_err(io::Error::other)?
+    }
+
+    /// With [`FsyncPolicy::Interval`], sync what is buffered every interval until the sink is
+    /// dropped, so lines do not wait for the next write.
+    fn start_syncing(&mut self) {
+        if self.syncing {
+            return;
+        }
+        self.syncing = true;
+        let (fsync, interval) = {
+            let f = lock(&self.file);
+            (f.cfg.fsync, f.cfg.fsync_interval_ms)
+        };
+        if fsync != FsyncPolicy::Interval {
+            return;
+        }
+        let period = Duration::from_millis(interval.max(1));
+        let file: Weak<_> = Arc::downgrade(&self.file);
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tick.tick().await;
+                let Some(file) = file.upgrade() else {
+                    return;
+                };
+                let synced = tokio::task::spawn_blocking(move || {
+                    let mut f = lock(&file);
+                    if f.dirty { f.sync(false).map_err(|e| (f.path.clone(), e)) } else { Ok(()) }
+                });
+                if let Ok(Err((path, e))) = synced.await {
+                    log::error!("sink: failed to sync {}: {e}", path.display());
+                }
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl EventSink for JsonlFileSink {
+    async fn write(&mut self, ev: &Value) -> io::Result<()> {
+        let line = serde_json::to_string(ev)?;
+        self.start_syncing();
+        self.blocking(move |f| f.write_line(&line)).await
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        self.blocking(|f| f.file.flush()).await
+    }
+}
+
+fn lock(file: &std::sync::Mutex<JsonlFile>) -> MutexGuard<'_, JsonlFile> {
+    file.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The open file of a [`JsonlFileSink`].
+struct JsonlFile {
+    path: PathBuf,
+    cfg: JsonlConfig,
+    file: BufWriter<File>,
+    /// Bytes written, buffered ones included.
+    size: u64,
+    last_sync: Instant,
+    // written to since the last sync
+    dirty: bool,
+}
+
+impl JsonlFile {
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.cfg.max_bytes > 0 && self.size > 0 && self.size + len > self.cfg.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.file.write_all(b"\n")?;
+        self.size += len;
+        self.dirty = true;
+        self.sync(false)
+    }
+
+    fn sync(&mut self, force: bool) -> io::Result<()> {
+        let due = match self.cfg.fsync {
+            FsyncPolicy::Always => true,
+            FsyncPolicy::Interval => force || self.last_sync.elapsed() >= Duration::from_millis(self.cfg.fsync_interval_ms),
+            FsyncPolicy::Never => force,
+        };
+        if due {
+            self.file.flush()?;
+            self.file.get_ref().sync_data()?;
+            self.last_sync = Instant::now();
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.cfg.fsync != FsyncPolicy::Never {
+            self.file.get_ref().sync_data()?;
+        }
+        let rotated = |n: usize| {
+            let mut p = self.path.as_os_str().to_owned();
+            p.push(format!(".{n}"));
+            PathBuf::from(p)
+        };
+        if self.cfg.keep == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            missing_ok(std::fs::remove_file(rotated(self.cfg.keep)))?;
+            for n in (1..self.cfg.keep).rev() {
+                missing_ok(std::fs::rename(rotated(n), rotated(n + 1)))?;
+            }
+            std::fs::rename(&self.path, rotated(1))?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.file = BufWriter::with_capacity(self.cfg.buffer_bytes, file);
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// Length of the part of the file up to and including its last newline.
+fn whole_lines(file: &mut File, len: u64) -> io::Result<u64> {
+    let mut end = len;
+    let mut buf = [0u8; 4096];
+    while end > 0 {
+        let start = end.saturating_sub(buf.len() as u64);
+        let chunk = &mut buf[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(chunk)?;
+        if let Some(i) = chunk.iter().rposition(|&b| b == b'\n') {
+            return Ok(start + i as u64 + 1);
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+fn missing_ok(res: io::Result<()>) -> io::Result<()> {
+    match res {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,143 @@
+use crate::{
+    callbacks::CallbackHub,
+    durable::FsyncPolicy,
+    sink::{EventSink, JsonlConfig, JsonlFileSink, SinkCallback},
+};
+use serde_json::{Value, json};
+use std::{
+    io::Write,
+    path::Path,
+    time::{Duration, Instant},
+};
+use tempfile::TempDir;
+
+fn events(path: &Path) -> Vec<Value> {
+    std::fs::read_to_string(path).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+}
+
+fn ids(path: &Path) -> Vec<u64> {
+    events(path).iter().map(|ev| ev["id"].as_u64().unwrap()).collect()
+}
+
+fn unbuffered() -> JsonlConfig {
+    JsonlConfig { fsync: FsyncPolicy::Always, ..JsonlConfig::default() }
+}
+
+#[tokio::test]
+async fn rotation_shifts_files_and_keeps_the_newest() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let path = dir.join("events.jsonl");
+    // {"id":N} plus newline is 9 bytes: three to a file
+    let mut sink = JsonlFileSink::open(&path, JsonlConfig { max_bytes: 30, keep: 2, ..unbuffered() }).unwrap();
+    for id in 0..10 {
+        sink.write(&json!({ "id": id })).await.unwrap();
+    }
+
+    assert_eq!(ids(&path), [9]);
+    assert_eq!(ids(&dir.join("events.jsonl.1")), [6, 7, 8]);
+    assert_eq!(ids(&dir.join("events.jsonl.2")), [3, 4, 5]);
+    assert!(!dir.join("events.jsonl.3").exists());
+
+    // a line over the limit still goes into a file of its own
+    let big = json!({ "id": 10, "pad": "x".repeat(40) });
+    sink.write(&big).await.unwrap();
+    sink.write(&json!({ "id": 11 })).await.unwrap();
+    assert_eq!(events(&dir.join("events.jsonl.1")), [big]);
+    assert_eq!(ids(&path), [11]);
+
+    // keep 0 drops the file
+    let path = dir.join("only.jsonl");
+    let mut sink = JsonlFileSink::open(&path, JsonlConfig { max_bytes: 10, keep: 0, ..unbuffered() }).unwrap();
+    for id in 0..3 {
+        sink.write(&json!({ "id": id })).await.unwrap();
+    }
+    assert_eq!(ids(&path), [2]);
+    assert!(!dir.join("only.jsonl.1").exists());
+}
+
+#[tokio::test]
+async fn reopening_cuts_a_torn_line_and_continues_the_size() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let path = dir.join("events.jsonl");
+    let mut sink = JsonlFileSink::open(&path, unbuffered()).unwrap();
+    sink.write(&json!({ "id": 0 })).await.unwrap();
+    sink.write(&json!({ "id": 1 })).await.unwrap();
+    drop(sink);
+    // a crash in the middle of the next line
+    std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(br#"{"id":2,"pa"#).unwrap();
+
+    let mut sink = JsonlFileSink::open(&path, JsonlConfig { max_bytes: 30, keep: 1, ..unbuffered() }).unwrap();
+    sink.write(&json!({ "id": 3 })).await.unwrap();
+    assert_eq!(ids(&path), [0, 1, 3]);
+    // the two lines kept count toward the limit
+    sink.write(&json!({ "id": 4 })).await.unwrap();
+    assert_eq!(ids(&dir.join("events.jsonl.1")), [0, 1, 3]);
+    assert_eq!(ids(&path), [4]);
+
+    // nothing but a torn line
+    let path = dir.join("torn-only.jsonl");
+    std::fs::write(&path, br#"{"id":"#).unwrap();
+    JsonlFileSink::open(&path, unbuffered()).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"");
+}
+
+#[tokio::test]
+async fn buffered_lines_reach_the_file_on_flush_or_when_due() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let path = dir.join("events.jsonl");
+    let cfg = JsonlConfig { fsync: FsyncPolicy::Never, buffer_bytes: 20, ..JsonlConfig::default() };
+    let mut sink = JsonlFileSink::open(&path, cfg).unwrap();
+    sink.write(&json!({ "id": 0 })).await.unwrap();
+    assert_eq!(ids(&path), [] as [u64; 0]);
+    sink.write(&json!({ "id": 1 })).await.unwrap();
+    sink.write(&json!({ "id": 2 })).await.unwrap();
+    assert_eq!(ids(&path), [0, 1], "the buffer filled");
+    sink.flush().await.unwrap();
+    assert_eq!(ids(&path), [0, 1, 2]);
+
+    let path = dir.join("interval.jsonl");
+    let cfg = JsonlConfig { fsync: FsyncPolicy::Interval, fsync_interval_ms: 0, ..JsonlConfig::default() };
+    let mut sink = JsonlFileSink::open(&path, cfg).unwrap();
+    sink.write(&json!({ "id": 0 })).await.unwrap();
+    assert_eq!(ids(&path), [0]);
+
+    // synced on the interval without another write
+    let path = dir.join("timer.jsonl");
+    let cfg = JsonlConfig { fsync: FsyncPolicy::Interval, fsync_interval_ms: 50, ..JsonlConfig::default() };
+    let mut sink = JsonlFileSink::open(&path, cfg).unwrap();
+    sink.write(&json!({ "id": 0 })).await.unwrap();
+    assert_eq!(ids(&path), [] as [u64; 0]);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while ids(&path).is_empty() {
+        assert!(Instant::now() < deadline, "not synced on the interval");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(ids(&path), [0]);
+}
+
+#[tokio::test]
+async fn sink_callback_writes_what_its_mask_matches() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let path = dir.join("events.jsonl");
+    let cb = SinkCallback::new(0b1, JsonlFileSink::open(&path, JsonlConfig::default()).unwrap());
+    let mut hub: CallbackHub<Value> = CallbackHub::new();
+    hub.add(cb.clone());
+    hub.fire(0b1, &json!({ "id": 0 })).await;
+    hub.fire(0b10, &json!({ "id": 1 })).await;
+    hub.fire(0b11, &json!({ "id": 2 })).await;
+    cb.flush().await.unwrap();
+
+    assert_eq!(ids(&path), [0, 2]);
+    assert_eq!(cb.errors(), 0);
+}
+
+#[test]
+fn config_takes_units() {
+    let cfg: JsonlConfig = serde_json::from_value(json!({ "max_bytes": "1MiB", "keep": 3, "buffer_bytes": 4096, "fsync": "always" })).unwrap();
+    assert_eq!((cfg.max_bytes, cfg.keep, cfg.buffer_bytes, cfg.fsync, cfg.fsync_interval_ms), (1 << 20, 3, 4096, FsyncPolicy::Always, 1000));
+    assert!(serde_json::from_value::<JsonlConfig>(json!({ "rotate": true })).is_err());
+}
@@ -0,0 +1,117 @@
+//! Leak checks for long runs: sample a sensor's [`MemoryReport`]s over many ticks and fail
+//! on any collection still growing once warmed up.
+//!
+//! A soak test drives a sensor through a high-churn script on simulated time and hands its
+//! memory report to a [`Soak`] every so often. Up to the end of the warm-up, each collection
+//! (a report part, e.g. `"dns_cache"`) sets its high-water mark; afterwards it must stay
+//! within that mark plus some slack, or [`Soak::check`] names it in a [`Leak`]. A bounded
+//! cache fills up during the warm-up and stays put; one that forgets to evict keeps growing
+//! and crosses its mark sooner or later, the sooner the more ticks run.
+//!
+//! The sensor crates' soak tests run with their `soak` feature, e.g.
+//! `cargo test --release -p netpacket --features soak soak`, for as many ticks as
+//! `OMNITRACE_SOAK_TICKS` says, or a default that takes a minute or two. Sensors run their
+//! real loop against fixture files on paused time, so a tick costs what a tick costs: leave
+//! one running with a few million for a night.
+
+use crate::memory::MemoryReport;
+use std::{collections::BTreeMap, fmt};
+
+/// `OMNITRACE_SOAK_TICKS`, or `default`.
+pub fn ticks(default: u64) -> u64 {
+    std::env::var("OMNITRACE_SOAK_TICKS").ok().and_then(|t| t.parse().ok()).unwrap_or(default)
+}
+
+/// Memory samples of one run, see the [module docs](self).
+#[derive(Clone, Debug)]
+pub struct Soak {
+    warmup: u64,
+    slack_fraction: f64,
+    slack_bytes: u64,
+    // per part: high-water mark of the warm-up, then the peak after it and its tick
+    marks: BTreeMap<String, u64>,
+    peaks: BTreeMap<String, (u64, u64)>,
+    last: BTreeMap<String, u64>,
+    samples: u64,
+}
+
+/// A collection that outgrew its warm-up mark.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Growth {
+    pub part: String,
+    /// High-water mark of the warm-up, in estimated bytes.
+    pub warmed_up: u64,
+    pub peak: u64,
+    pub peak_tick: u64,
+    /// At the last sample.
+    pub last: u64,
+}
+
+/// What [`Soak::check`] found growing.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub struct Leak {
+    pub growth: Vec<Growth>,
+}
+
+impl fmt::Display for Leak {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, g) in self.growth.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{} grew from {} bytes after warm-up to {} at tick {} ({} at the end)", g.part, g.warmed_up, g.peak, g.peak_tick, g.last)?;
+        }
+        Ok(())
+    }
+}
+
+impl Soak {
+    /// Leak check of a run warming up for `warmup` ticks, with a slack of 10% plus 4 KiB.
+    pub fn new(warmup: u64) -> Self {
+        Self { warmup, slack_fraction: 0.1, slack_bytes: 4096, marks: BTreeMap::new(), peaks: BTreeMap::new(), last: BTreeMap::new(), samples: 0 }
+    }
+
+    /// How far past its warm-up mark a collection may go: `fraction` of the mark plus `bytes`,
+    /// for churn that does not settle on one size.
+    pub fn slack(mut self, fraction: f64, bytes: u64) -> Self {
+        self.slack_fraction = fraction;
+        self.slack_bytes = bytes;
+        self
+    }
+
+    /// Note the report of `tick`. Sample the warm-up as well, or no collection has a mark.
+    pub fn sample(&mut self, tick: u64, report: &MemoryReport) {
+        self.samples += 1;
+        for (part, &bytes) in &report.parts {
+            self.last.insert(part.clone(), bytes);
+            if tick < self.warmup {
+                let mark = self.marks.entry(part.clone()).or_default();
+                *mark = (*mark).max(bytes);
+            } else {
+                let peak = self.peaks.entry(part.clone()).or_default();
+                if bytes > peak.0 {
+                    *peak = (bytes, tick);
+                }
+            }
+        }
+    }
+
+    /// Samples taken so far.
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// The collections past their warm-up mark and slack, if any.
+    pub fn check(&self) -> Result<(), Leak> {
+        let growth: Vec<Growth> = self
+            .peaks
+            .iter()
+            .filter_map(|(part, &(peak, peak_tick))| {
+                let warmed_up = self.marks.get(part).copied().unwrap_or(0);
+                let bound = warmed_up + (warmed_up as f64 * self.slack_fraction) as u64 + self.slack_bytes;
+                (peak > bound).then(|| Growth { part: part.clone(), warmed_up, peak, peak_tick, last: self.last[part] })
+            })
+            .collect();
+        if growth.is_empty() { Ok(()) } else { Err(Leak { growth }) }
+    }
+}
@@ -0,0 +1,74 @@
+use crate::{
+    memory::{self, MemoryReport},
+    soak::{Growth, Soak},
+};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// A sensor's state under churn: a bounded cache evicting its oldest entry, and a pending map
+/// that is meant to drop entries once they are resolved.
+struct Fixture {
+    cache: VecDeque<String>,
+    pending: HashMap<u64, String>,
+    forget_to_resolve: bool,
+}
+
+impl Fixture {
+    fn tick(&mut self, n: u64) {
+        self.cache.push_back(format!("host-{}.example.com", n % 5000));
+        if self.cache.len() > 256 {
+            self.cache.pop_front();
+        }
+        self.pending.insert(n, format!("req-{n}"));
+        if !self.forget_to_resolve {
+            self.pending.remove(&n.saturating_sub(8));
+        }
+    }
+
+    fn report(&self) -> MemoryReport {
+        let mut r = MemoryReport::new(None);
+        r.add("cache", self.cache.iter().map(|h| memory::entry(size_of::<String>(), h.len())).sum());
+        r.add("pending", self.pending.values().map(|v| memory::entry(size_of::<(u64, String)>(), v.len())).sum());
+        r
+    }
+}
+
+fn soak(forget_to_resolve: bool) -> Soak {
+    let mut fixture = Fixture { cache: VecDeque::new(), pending: HashMap::new(), forget_to_resolve };
+    let mut soak = Soak::new(10_000);
+    for n in 0..100_000 {
+        fixture.tick(n);
+        if n % 500 == 0 {
+            soak.sample(n, &fixture.report());
+        }
+    }
+    soak
+}
+
+#[test]
+fn bounded_collections_pass() {
+    let soak = soak(false);
+    assert_eq!(soak.samples(), 200);
+    soak.check().unwrap();
+}
+
+#[test]
+fn a_collection_growing_after_warm_up_is_named() {
+    let leak = soak(true).check().unwrap_err();
+    assert_eq!(leak.growth.len(), 1, "{leak}");
+    let Growth { part, warmed_up, peak, peak_tick, last } = &leak.growth[0];
+    assert_eq!(part, "pending");
+    assert!(peak > &(warmed_up * 9), "{leak}");
+    assert_eq!((*peak_tick, peak), (99_500, last));
+    assert!(leak.to_string().starts_with("pending grew from "), "{leak}");
+}
+
+#[test]
+fn slack_absorbs_churn_that_does_not_settle() {
+    let report = |bytes| MemoryReport { parts: BTreeMap::from([("files".to_string(), bytes)]), ..MemoryReport::default() };
+    let mut soak = Soak::new(10).slack(0.5, 0);
+    soak.sample(0, &report(1000));
+    soak.sample(20, &report(1400));
+    soak.check().unwrap();
+    soak.sample(30, &report(1600));
+    assert_eq!(soak.check().unwrap_err().growth[0].peak, 1600);
+}
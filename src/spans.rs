@@ -0,0 +1,36 @@
+//! `tracing` spans around event dispatch, with the `tracing` feature, for subscribers such as
+//! `tracing-subscriber` or an OpenTelemetry layer.
+//!
+//! - `sensor` (INFO), for the whole life of a sensor's task, with its `sensor` name and,
+//!   under [`crate::sensor::supervise_sensor`], the `attempt`.
+//! - `fire` (DEBUG), per event a hub fires, with the event's `mask` and `seq` and the
+//!   hub's `sensor` name. Events the hub suppresses get none.
+//! - `callback` (DEBUG), per callback invocation within `fire`, with its position
+//!   (`callback`), its `id`, the `duration_us` it ran and whether it `timed_out`.
+//!
+//! Their targets are `omnitrace_core::sensor` and `omnitrace_core::callbacks`. Without the
+//! feature nothing here is compiled and dispatch is unchanged.
+
+use std::future::Future;
+use tokio::time::Instant;
+use tracing::{Instrument, Span, field};
+
+pub(crate) fn sensor(name: &str, attempt: Option<u32>) -> Span {
+    tracing::info_span!(target: "omnitrace_core::sensor", "sensor", sensor = name, attempt)
+}
+
+pub(crate) fn fire(sensor: Option<&str>, mask: u64, seq: u64) -> Span {
+    tracing::debug_span!(target: "omnitrace_core::callbacks", "fire", sensor = sensor.unwrap_or_default(), mask, seq)
+}
+
+/// Run a callback invocation in its span, recording how long it took and whether it timed
+/// out (`Err`).
+pub(crate) async fn callback<T, F: Future<Output = Result<T, ()>>>(idx: usize, id: u64, call: F) -> Result<T, ()> {
+    let span =
+        tracing::debug_span!(target: "omnitrace_core::callbacks", "callback", callback = idx, id, duration_us = field::Empty, timed_out = false);
+    let started = Instant::now();
+    let out = call.instrument(span.clone()).await;
+    span.record("duration_us", u64::try_from(started.elapsed().as_micros()).unwrap_or(u64::MAX));
+    span.record("timed_out", out.is_err());
+    out
+}
@@ -0,0 +1,159 @@
+use crate::{
+    callbacks::{Callback, CallbackHub, CallbackResult},
+    sensor::{Sensor, SensorCtx, spawn_sensor_as},
+};
+use async_trait::async_trait;
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing::{
+    Event, Metadata, Subscriber,
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    subscriber::Interest,
+};
+
+/// A span as recorded: its name, its parent's name and its fields.
+#[derive(Clone, Debug, Default)]
+struct Recorded {
+    name: &'static str,
+    parent: Option<&'static str>,
+    fields: BTreeMap<&'static str, String>,
+}
+
+/// Keeps every span created while it is the default subscriber, on a single thread.
+#[derive(Clone, Default)]
+struct Spans {
+    // span ID n is spans[n - 1]
+    spans: Arc<Mutex<Vec<Recorded>>>,
+    entered: Arc<Mutex<Vec<u64>>>,
+}
+
+impl Spans {
+    fn named(&self, name: &str) -> Vec<Recorded> {
+        self.spans.lock().unwrap().iter().filter(|s| s.name == name).cloned().collect()
+    }
+}
+
+struct Fields<'a>(&'a mut BTreeMap<&'static str, String>);
+
+impl Visit for Fields<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name(), format!("{value:?}"));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name(), value.to_string());
+    }
+}
+
+impl Subscriber for Spans {
+    fn register_callsite(&self, _: &'static Metadata<'static>) -> Interest {
+        Interest::always()
+    }
+
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+        let mut spans = self.spans.lock().unwrap();
+        let parent = match attrs.parent() {
+            Some(id) => Some(id.into_u64()),
+            None if attrs.is_contextual() => self.entered.lock().unwrap().last().copied(),
+            None => None,
+        };
+        let parent = parent.map(|id| spans[id as usize - 1].name);
+        let mut span = Recorded { name: attrs.metadata().name(), parent, fields: BTreeMap::new() };
+        attrs.record(&mut Fields(&mut span.fields));
+        spans.push(span);
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, id: &Id, values: &Record<'_>) {
+        if let Some(span) = self.spans.lock().unwrap().get_mut(id.into_u64() as usize - 1) {
+            values.record(&mut Fields(&mut span.fields));
+        }
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, _: &Event<'_>) {}
+
+    fn enter(&self, id: &Id) {
+        self.entered.lock().unwrap().push(id.into_u64());
+    }
+
+    fn exit(&self, _: &Id) {
+        self.entered.lock().unwrap().pop();
+    }
+}
+
+struct Sleeps(Duration);
+
+#[async_trait]
+impl Callback<u32> for Sleeps {
+    fn mask(&self) -> u64 {
+        0b1
+    }
+
+    async fn call(&self, _: &u32) -> Option<CallbackResult> {
+        tokio::time::sleep(self.0).await;
+        None
+    }
+}
+
+/// Fires one event and stops.
+struct Once;
+
+impl Sensor for Once {
+    type Event = u32;
+
+    fn run<R: Send + 'static>(self, ctx: SensorCtx<u32, R>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async move { ctx.hub.fire(0b1, &7).await })
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn sensor_event_and_callback_spans_nest() {
+    let spans = Spans::default();
+    let _default = tracing::subscriber::set_default(spans.clone());
+
+    let mut hub = CallbackHub::new();
+    hub.add(Sleeps(Duration::from_millis(400)));
+    hub.add(Sleeps(Duration::from_secs(10)));
+    hub.set_callback_timeout(Duration::from_secs(1));
+    let (_, task) = spawn_sensor_as("xmount", Once, Arc::new(hub));
+    task.await.unwrap();
+
+    let sensor = spans.named("sensor");
+    assert_eq!(sensor.len(), 1);
+    assert_eq!(sensor[0].fields["sensor"], "xmount");
+
+    let fire = spans.named("fire");
+    assert_eq!(fire.len(), 1);
+    assert_eq!(fire[0].parent, Some("sensor"));
+    assert_eq!((fire[0].fields["sensor"].as_str(), fire[0].fields["mask"].as_str(), fire[0].fields["seq"].as_str()), ("xmount", "1", "0"));
+
+    let calls = spans.named("callback");
+    let seen: Vec<(&str, &str, &str)> =
+        calls.iter().map(|c| (c.fields["callback"].as_str(), c.fields["duration_us"].as_str(), c.fields["timed_out"].as_str())).collect();
+    assert_eq!(seen, [("0", "400000", "false"), ("1", "1000000", "true")]);
+    assert!(calls.iter().all(|c| c.parent == Some("fire")));
+}
+
+#[tokio::test]
+async fn suppressed_events_get_no_span() {
+    let spans = Spans::default();
+    let _default = tracing::subscriber::set_default(spans.clone());
+    let hub = CallbackHub::<u32>::new();
+    hub.set_suppress_real(true);
+    hub.fire(0b1, &1).await;
+    assert!(spans.named("fire").is_empty());
+    hub.inject(0b1, &2).await;
+    assert_eq!(spans.named("fire").len(), 1);
+}
@@ -0,0 +1,195 @@
+//! Warm standby for active/standby agent pairs.
+//!
+//! Both agents of a pair run their sensors, so the standby one keeps its baselines warm
+//! (primed snapshots, caches, file tables). A [`RoleSwitch`] given to the [`crate::router::Router`]
+//! decides whether routed events reach the sinks: while standby the router drops them all,
+//! in one place, so sinks need no logic of their own. Callbacks registered directly on the
+//! hubs still run.
+//!
+//! Promotion flips the switch atomically; the next event a sensor fires goes out, with no
+//! re-prime and no replay of what changed while standby. Every transition queues a marker
+//! record that the router delivers to every sink before any later event:
+//!
+//! ```json
+//! {"RoleChanged": {"instance": "node-a", "role": "active", "previous": "standby", "reason": "file", "at": 1718000000.25}}
+//! ```
+//!
+//! The role can be set through the API ([`RoleSwitch::promote`], [`RoleSwitch::demote`]),
+//! from a flag file ([`RoleSwitch::follow_file`]) or over a unix socket ([`RoleSwitch::listen`]).
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{
+    fmt,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{sync::Notify, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+
+/// Variant name of the marker record.
+pub const ROLE_CHANGED: &str = "RoleChanged";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Active,
+    Standby,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Role::Active => "active",
+            Role::Standby => "standby",
+        })
+    }
+}
+
+struct State {
+    role: Role,
+    markers: Vec<Value>,
+}
+
+/// Shared active/standby flag of one agent instance. Cheap to clone.
+#[derive(Clone)]
+pub struct RoleSwitch {
+    instance: Arc<str>,
+    state: Arc<Mutex<State>>,
+    changed: Arc<Notify>,
+}
+
+impl RoleSwitch {
+    /// `instance` names this agent in the markers, e.g. the host name.
+    pub fn new(instance: &str, initial: Role) -> Self {
+        Self {
+            instance: instance.into(),
+            state: Arc::new(Mutex::new(State { role: initial, markers: Vec::new() })),
+            changed: Arc::new(Notify::new()),
+        }
+    }
+
+    pub fn instance(&self) -> &str {
+        &self.instance
+    }
+
+    pub fn role(&self) -> Role {
+        self.state.lock().map(|s| s.role).unwrap_or(Role::Standby)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.role() == Role::Active
+    }
+
+    /// Start emitting. Returns false if already active.
+    pub fn promote(&self, reason: &str) -> bool {
+        self.set(Role::Active, reason)
+    }
+
+    /// Stop emitting. Returns false if already standby.
+    pub fn demote(&self, reason: &str) -> bool {
+        self.set(Role::Standby, reason)
+    }
+
+    /// Switch to `role` and queue a marker, unless the role is already `role`.
+    pub fn set(&self, role: Role, reason: &str) -> bool {
+        let Ok(mut st) = self.state.lock() else { return false };
+        if st.role == role {
+            return false;
+        }
+        let previous = std::mem::replace(&mut st.role, role);
+        let at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0);
+        st.markers.push(json!({
+            ROLE_CHANGED: { "instance": &*self.instance, "role": role, "previous": previous, "reason": reason, "at": at }
+        }));
+        drop(st);
+        log::info!("standby: {} is now {role} ({reason})", self.instance);
+        self.changed.notify_one();
+        true
+    }
+
+    /// Queued markers and the role they lead up to, taken together.
+    pub(crate) fn take_markers(&self) -> (Vec<Value>, Role) {
+        match self.state.lock() {
+            Ok(mut st) => (std::mem::take(&mut st.markers), st.role),
+            Err(_) => (Vec::new(), Role::Standby),
+        }
+    }
+
+    /// Resolves after the next transition.
+    pub(crate) async fn changed(&self) {
+        self.changed.notified().await;
+    }
+
+    /// Active while `path` exists, standby otherwise, checked every `interval` until `cancel`
+    /// fires. The first check happens right away.
+    pub fn follow_file<P: AsRef<Path>>(&self, path: P, interval: Duration, cancel: CancellationToken) -> JoinHandle<()> {
+        let (switch, path) = (self.clone(), path.as_ref().to_path_buf());
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                tokio::select! {
+                    _ = cancel.cancelled() => break,
+                    _ = ticker.tick() => {}
+                }
+                let role = if path.exists() { Role::Active } else { Role::Standby };
+                switch.set(role, "file");
+            }
+        })
+    }
+
+    /// Accept commands on a unix socket at `path` until `cancel` fires: one line each,
+    /// `promote`, `demote` or `status`, answered with the resulting role (or `error: ...`).
+    /// A stale socket file at `path` is replaced.
+    #[cfg(unix)]
+    pub fn listen<P: AsRef<Path>>(&self, path: P, cancel: CancellationToken) -> std::io::Result<JoinHandle<()>> {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let path = path.as_ref().to_path_buf();
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path)?;
+        let switch = self.clone();
+        Ok(tokio::spawn(async move {
+            loop {
+                let stream = tokio::select! {
+                    _ = cancel.cancelled() => break,
+                    accepted = listener.accept() => match accepted {
+                        Ok((stream, _)) => stream,
+                        Err(e) => {
+                            log::warn!("standby: accept on {} failed: {e}", path.display());
+                            continue;
+                        }
+                    },
+                };
+                let (rd, mut wr) = stream.into_split();
+                let mut lines = BufReader::new(rd).lines();
+                loop {
+                    let line = tokio::select! {
+                        _ = cancel.cancelled() => break,
+                        line = lines.next_line() => match line {
+                            Ok(Some(line)) => line,
+                            _ => break,
+                        },
+                    };
+                    let reply = match line.trim() {
+                        "promote" => {
+                            switch.promote("socket");
+                            switch.role().to_string()
+                        }
+                        "demote" => {
+                            switch.demote("socket");
+                            switch.role().to_string()
+                        }
+                        "status" => switch.role().to_string(),
+                        other => format!("error: unknown command {other:?}"),
+                    };
+                    if wr.write_all(format!("{reply}\n").as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            let _ = std::fs::remove_file(&path);
+        }))
+    }
+}
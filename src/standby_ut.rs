@@ -0,0 +1,130 @@
+use crate::{
+    callbacks::CallbackResult,
+    router::Router,
+    standby::{ROLE_CHANGED, Role, RoleSwitch},
+};
+use serde_json::json;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc::{Receiver, channel};
+use tokio_util::sync::CancellationToken;
+
+fn drain(rx: &mut Receiver<CallbackResult>) -> Vec<CallbackResult> {
+    let mut out = Vec::new();
+    while let Ok(v) = rx.try_recv() {
+        out.push(v);
+    }
+    out
+}
+
+fn router(role: &RoleSwitch) -> (Router, Receiver<CallbackResult>, Receiver<CallbackResult>) {
+    let mut router = Router::new();
+    let (jsonl_tx, jsonl) = channel(16);
+    let (webhook_tx, webhook) = channel(16);
+    router.add_sink("jsonl", jsonl_tx);
+    router.add_sink("webhook", webhook_tx);
+    router.set_default(vec!["jsonl"]);
+    router.set_role_switch(role.clone());
+    (router, jsonl, webhook)
+}
+
+#[tokio::test]
+async fn standby_suppresses_and_markers_lead_the_events() {
+    let role = RoleSwitch::new("node-b", Role::Standby);
+    let (router, mut jsonl, mut webhook) = router(&role);
+
+    for i in 0..3 {
+        router.dispatch("xmount", 1, &json!({ "Mounted": { "target": format!("/mnt/{i}") } })).await;
+    }
+    assert!(drain(&mut jsonl).is_empty());
+    assert_eq!(router.suppressed(), 3);
+
+    assert!(role.promote("api"));
+    assert!(!role.promote("api"), "already active");
+    router.dispatch("xmount", 1, &json!({ "Mounted": { "target": "/mnt/3" } })).await;
+    let got = drain(&mut jsonl);
+    assert_eq!(got.len(), 2);
+    let marker = &got[0][ROLE_CHANGED];
+    assert_eq!(
+        (&marker["instance"], &marker["role"], &marker["previous"], &marker["reason"]),
+        (&json!("node-b"), &json!("active"), &json!("standby"), &json!("api"))
+    );
+    assert_eq!(got[1]["Mounted"]["target"], "/mnt/3");
+    // every sink learns about the switch, whatever the routes
+    assert_eq!(drain(&mut webhook).len(), 1);
+
+    // the demotion marker is the last thing to go out
+    role.demote("api");
+    router.dispatch("xmount", 1, &json!({ "Mounted": { "target": "/mnt/4" } })).await;
+    let got = drain(&mut jsonl);
+    assert_eq!(got.len(), 1);
+    assert_eq!(got[0][ROLE_CHANGED]["role"], "standby");
+    assert_eq!(router.suppressed(), 4);
+}
+
+#[tokio::test]
+async fn markers_go_out_without_waiting_for_an_event() {
+    let role = RoleSwitch::new("node-a", Role::Standby);
+    let (router, mut jsonl, _webhook) = router(&role);
+    let router = Arc::new(router);
+    let cancel = CancellationToken::new();
+    let task = router.spawn_role_markers(cancel.clone());
+
+    role.promote("api");
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(drain(&mut jsonl).len(), 1);
+    cancel.cancel();
+    let _ = task.await;
+}
+
+#[tokio::test]
+async fn flag_file_sets_the_role() {
+    let dir = std::env::temp_dir().join(format!("omnitrace-standby-ut-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let flag = dir.join("active");
+    let _ = std::fs::remove_file(&flag);
+
+    let role = RoleSwitch::new("node-a", Role::Active);
+    let cancel = CancellationToken::new();
+    let task = role.follow_file(&flag, Duration::from_millis(5), cancel.clone());
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(role.role(), Role::Standby, "no flag file, no authority");
+
+    std::fs::write(&flag, "").unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(role.is_active());
+    let (markers, _) = role.take_markers();
+    assert_eq!(markers.iter().map(|m| m[ROLE_CHANGED]["role"].clone()).collect::<Vec<_>>(), [json!("standby"), json!("active")]);
+    assert!(markers.iter().all(|m| m[ROLE_CHANGED]["reason"] == "file"));
+
+    cancel.cancel();
+    let _ = task.await;
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn socket_commands_switch_the_role() {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let path = std::env::temp_dir().join(format!("omnitrace-standby-ut-{}.sock", std::process::id()));
+    let role = RoleSwitch::new("node-a", Role::Standby);
+    let cancel = CancellationToken::new();
+    let task = role.listen(&path, cancel.clone()).unwrap();
+
+    let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+    let (rd, mut wr) = stream.into_split();
+    let mut lines = BufReader::new(rd).lines();
+    let mut ask = async |cmd: &str| {
+        wr.write_all(format!("{cmd}\n").as_bytes()).await.unwrap();
+        lines.next_line().await.unwrap().unwrap()
+    };
+    assert_eq!(ask("status").await, "standby");
+    assert_eq!(ask("promote").await, "active");
+    assert!(role.is_active());
+    assert_eq!(ask("demote").await, "standby");
+    assert!(ask("reboot").await.starts_with("error"));
+
+    cancel.cancel();
+    let _ = task.await;
+    assert!(!path.exists());
+}
@@ -0,0 +1,70 @@
+//! State handles: what a running sensor lets others, its own callbacks included, ask about
+//! its state (e.g. `ProcDogState::pids`).
+//!
+//! A callback querying the sensor that fired it runs while the sensor's loop awaits it, so
+//! every state handle keeps to this contract:
+//!
+//! - **Readers never lock.** The handle holds the latest snapshot in a [`Published`]; a read
+//!   is a few atomic operations and cannot wait on the sensor, however long its tick takes.
+//! - **Snapshots are as of the previous completed tick.** The sensor publishes once a tick
+//!   fired all its events, so a callback for an event of tick N sees the state before tick
+//!   N (e.g. no pid for the `Appeared` event of that pid yet, still the pid of a
+//!   `Disappeared` one), and nothing before the first tick completed.
+//! - **The loop never waits for readers.** Publishing swaps a pointer; readers holding an
+//!   older snapshot keep it alive, they do not hold up the next one.
+//!
+//! Handles the loop has to answer, like [`crate::preview::PreviewHandle`], are not state
+//! handles: awaiting one from a callback stalls the sensor that fired it until it can no
+//! longer answer. Debug builds catch that, see [`crate::callbacks::in_callback`].
+
+use arc_swap::ArcSwap;
+use std::{fmt, sync::Arc};
+
+/// The read side of a sensor's state, see the [module docs](self). Clones share it.
+pub struct Published<T>(Arc<ArcSwap<T>>);
+
+impl<T> Clone for Published<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: Default> Default for Published<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Published<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Published").field(&*self.load()).finish()
+    }
+}
+
+impl<T> Published<T> {
+    pub fn new(value: T) -> Self {
+        Self(Arc::new(ArcSwap::from_pointee(value)))
+    }
+
+    /// The latest snapshot. It does not change under the caller; load again for a newer one.
+    pub fn load(&self) -> Arc<T> {
+        self.0.load_full()
+    }
+
+    /// Replace the snapshot, at the end of a tick.
+    pub fn publish(&self, value: T) {
+        self.0.store(Arc::new(value));
+    }
+
+    /// Publish a changed copy of the snapshot. `f` may run more than once when updates race.
+    pub fn update<F: FnMut(&mut T)>(&self, mut f: F)
+    where
+        T: Clone,
+    {
+        self.0.rcu(|cur| {
+            let mut next = T::clone(cur);
+            f(&mut next);
+            next
+        });
+    }
+}
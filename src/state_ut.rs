@@ -0,0 +1,82 @@
+use crate::{
+    callbacks::{Callback, CallbackHub, CallbackResult, in_callback},
+    preview::{PreviewHandle, PreviewQueue},
+    state::Published,
+};
+use async_trait::async_trait;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+#[test]
+fn snapshots_stay_put_until_loaded_again() {
+    let state = Published::new(vec![1]);
+    let reader = state.clone();
+    let before = reader.load();
+    state.publish(vec![1, 2]);
+    state.update(|v| v.push(3));
+    assert_eq!(*before, [1]);
+    assert_eq!(*reader.load(), [1, 2, 3]);
+}
+
+#[test]
+fn readers_do_not_wait_for_a_busy_publisher() {
+    let state: Published<BTreeMap<u32, String>> = Published::default();
+    let stop = Arc::new(AtomicBool::new(false));
+    let writer = {
+        let (state, stop) = (state.clone(), stop.clone());
+        std::thread::spawn(move || {
+            let mut n = 0;
+            while !stop.load(Ordering::Relaxed) {
+                n += 1;
+                state.publish((0..2000).map(|i| (i, format!("{n}-{i}"))).collect());
+            }
+        })
+    };
+
+    let started = Instant::now();
+    let mut slowest = Duration::ZERO;
+    while started.elapsed() < Duration::from_millis(200) {
+        let t = Instant::now();
+        let snap = state.load();
+        slowest = slowest.max(t.elapsed());
+        // a snapshot is one publication, not a mix of two
+        let first = snap.values().next().map(|v| v.split('-').next().unwrap().to_string());
+        assert!(snap.values().all(|v| Some(v.split('-').next().unwrap().to_string()) == first));
+    }
+    stop.store(true, Ordering::Relaxed);
+    writer.join().unwrap();
+    assert!(slowest < Duration::from_millis(50), "{slowest:?}");
+}
+
+/// Awaits a preview from inside a callback.
+struct Previews(PreviewHandle<u32>);
+
+#[async_trait]
+impl Callback<u32> for Previews {
+    fn mask(&self) -> u64 {
+        0b1
+    }
+
+    async fn call(&self, _: &u32) -> Option<CallbackResult> {
+        assert!(in_callback() == cfg!(debug_assertions));
+        self.0.preview_rule(1).await.map(|r| serde_json::json!(r.would_match))
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(debug_assertions, should_panic(expected = "awaited from a callback"))]
+async fn awaiting_a_sensor_loop_from_a_callback_is_caught() {
+    assert!(!in_callback());
+    let queue = PreviewQueue::default();
+    let mut hub = CallbackHub::new();
+    hub.add(Previews(queue.handle()));
+    // release builds wait for the answer, which no loop gives while the hub awaits it
+    let fire = tokio::time::timeout(Duration::from_millis(50), hub.fire(0b1, &1));
+    assert!(fire.await.is_err());
+}
@@ -0,0 +1,294 @@
+//! Syslog sink: events as RFC 5424 messages on the local syslog socket, for hosts without a
+//! log shipper. Behind the `syslog` feature, unix only.
+//!
+//! ```ignore
+//! let cfg = SyslogConfig { facility: Facility::Local3, severities: vec![SeverityByMask::new(0b0100, SyslogSeverity::Crit)], ..SyslogConfig::default() };
+//! hub.add(SyslogCallback::new(ProcDogMask::all().bits(), SyslogSink::open(cfg)));
+//! ```
+//!
+//! A message is `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID - MSG`, the event kind (the
+//! variant name) as MSGID and the event as JSON as MSG. The socket is connected again when a
+//! send fails (syslog restarted); if that fails too, the message goes to stderr.
+
+use crate::{
+    callbacks::Callback,
+    quiet::civil,
+    severity::{SEVERITY_FIELD, Severity},
+    sink::EventSink,
+    topics::Topics,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    io::{self, Write},
+    os::unix::net::UnixDatagram,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::Mutex;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Facility {
+    Kern,
+    User,
+    Mail,
+    #[default]
+    Daemon,
+    Auth,
+    Syslog,
+    Lpr,
+    News,
+    Uucp,
+    Cron,
+    Authpriv,
+    Ftp,
+    Local0 = 16,
+    Local1,
+    Local2,
+    Local3,
+    Local4,
+    Local5,
+    Local6,
+    Local7,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogSeverity {
+    Emerg,
+    Alert,
+    Crit,
+    Err,
+    Warning,
+    #[default]
+    Notice,
+    Info,
+    Debug,
+}
+
+impl From<Severity> for SyslogSeverity {
+    fn from(s: Severity) -> Self {
+        match s {
+            Severity::Debug => SyslogSeverity::Debug,
+            Severity::Info => SyslogSeverity::Info,
+            Severity::Warning => SyslogSeverity::Warning,
+            Severity::Critical => SyslogSeverity::Crit,
+        }
+    }
+}
+
+/// Events whose mask bit intersects `mask` are sent with `severity`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SeverityByMask {
+    pub mask: u64,
+    pub severity: SyslogSeverity,
+}
+
+impl SeverityByMask {
+    pub fn new(mask: u64, severity: SyslogSeverity) -> Self {
+        Self { mask, severity }
+    }
+}
+
+/// ```json
+/// { "socket": "/dev/log", "facility": "local3", "app_name": "omnitrace", "severities": [{ "mask": 4, "severity": "crit" }], "default_severity": "notice" }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SyslogConfig {
+    #[serde(default = "default_socket")]
+    pub socket: PathBuf,
+    #[serde(default)]
+    pub facility: Facility,
+    /// Cut to the 48 characters RFC 5424 allows.
+    #[serde(default = "default_app_name")]
+    pub app_name: String,
+    /// Tried in order, the first match wins. Events matching none take the severity a
+    /// [`crate::severity::SeverityMapper`] tagged them with, else `default_severity`.
+    #[serde(default)]
+    pub severities: Vec<SeverityByMask>,
+    #[serde(default)]
+    pub default_severity: SyslogSeverity,
+}
+
+impl Default for SyslogConfig {
+    fn default() -> Self {
+        Self {
+            socket: default_socket(),
+            facility: Facility::default(),
+            app_name: default_app_name(),
+            severities: Vec::new(),
+            default_severity: SyslogSeverity::default(),
+        }
+    }
+}
+
+fn default_socket() -> PathBuf {
+    PathBuf::from("/dev/log")
+}
+
+fn default_app_name() -> String {
+    "omnitrace".to_string()
+}
+
+/// Sends events to syslog, see the module docs.
+pub struct SyslogSink {
+    cfg: SyslogConfig,
+    hostname: String,
+    socket: Option<UnixDatagram>,
+    fallback: Box<dyn Write + Send>,
+    fallbacks: u64,
+}
+
+impl SyslogSink {
+    /// Connects on the first event, so a syslog not running yet is not an error.
+    pub fn open(mut cfg: SyslogConfig) -> Self {
+        cfg.app_name = header_field(&cfg.app_name, 48);
+        Self { cfg, hostname: header_field(&hostname(), 255), socket: None, fallback: Box::new(io::stderr()), fallbacks: 0 }
+    }
+
+    /// Write messages syslog does not take here instead of to stderr.
+    pub fn fallback<W: Write + Send + 'static>(mut self, w: W) -> Self {
+        self.fallback = Box::new(w);
+        self
+    }
+
+    /// Messages that went to the fallback so far.
+    pub fn fallbacks(&self) -> u64 {
+        self.fallbacks
+    }
+
+    /// The severity an event with mask bit `mask` is sent with; 0 if not known.
+    pub fn severity(&self, mask: u64, ev: &Value) -> SyslogSeverity {
+        if let Some(r) = self.cfg.severities.iter().find(|r| r.mask & mask != 0) {
+            return r.severity;
+        }
+        ev.get(SEVERITY_FIELD).and_then(|s| Severity::deserialize(s).ok()).map_or(self.cfg.default_severity, SyslogSeverity::from)
+    }
+
+    /// The RFC 5424 message for an event.
+    pub fn format(&self, severity: SyslogSeverity, ev: &Value, now: SystemTime) -> String {
+        let pri = self.cfg.facility as u8 * 8 + severity as u8;
+        let msgid = match ev {
+            Value::Object(m) if m.len() == 1 => m.keys().next().map_or("-".to_string(), |k| header_field(k, 32)),
+            _ => "-".to_string(),
+        };
+        format!("<{pri}>1 {} {} {} {} {msgid} - {ev}", timestamp(now), self.hostname, self.cfg.app_name, std::process::id())
+    }
+
+    /// Send an event with mask bit `mask` (0 if not known). Errors only if neither syslog
+    /// nor the fallback took it.
+    pub fn send(&mut self, mask: u64, ev: &Value) -> io::Result<()> {
+        let msg = self.format(self.severity(mask, ev), ev, SystemTime::now());
+        // once on the socket there is, once more on a new one
+        for _ in 0..2 {
+            let sent = match &self.socket {
+                Some(s) => s.send(msg.as_bytes()).map(drop),
+                None => self.connect().and_then(|s| s.send(msg.as_bytes()).map(drop)),
+            };
+            match sent {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    log::debug!("syslog: send to {} failed: {e}", self.cfg.socket.display());
+                    self.socket = None;
+                }
+            }
+        }
+        self.fallbacks += 1;
+        writeln!(self.fallback, "{msg}")
+    }
+
+    fn connect(&mut self) -> io::Result<&UnixDatagram> {
+        let s = UnixDatagram::unbound()?;
+        s.connect(&self.cfg.socket)?;
+        // a syslog that does not keep up loses messages to the fallback rather than blocking
+        s.set_nonblocking(true)?;
+        Ok(self.socket.insert(s))
+    }
+}
+
+#[async_trait]
+impl EventSink for SyslogSink {
+    async fn write(&mut self, ev: &Value) -> io::Result<()> {
+        self.send(0, ev)
+    }
+}
+
+/// Sends every event its mask matches to a [`SyslogSink`], with the severity of the event's
+/// mask bit, and returns no result. Clones send to the same sink.
+#[derive(Clone)]
+pub struct SyslogCallback {
+    mask: u64,
+    sink: Arc<Mutex<SyslogSink>>,
+    errors: Arc<AtomicU64>,
+}
+
+impl SyslogCallback {
+    pub fn new(mask: u64, sink: SyslogSink) -> Self {
+        Self { mask, sink: Arc::new(Mutex::new(sink)), errors: Arc::default() }
+    }
+
+    /// Events neither syslog nor the fallback took so far.
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// Messages that went to the fallback so far.
+    pub async fn fallbacks(&self) -> u64 {
+        self.sink.lock().await.fallbacks()
+    }
+}
+
+#[async_trait]
+impl<E, R> Callback<E, R> for SyslogCallback
+where
+    E: Serialize + Topics + Sync,
+    R: Send,
+{
+    fn mask(&self) -> u64 {
+        self.mask
+    }
+
+    async fn call(&self, ev: &E) -> Option<R> {
+        let mask = E::TOPICS[ev.topic_index()].mask;
+        let res = match serde_json::to_value(ev) {
+            Ok(v) => self.sink.lock().await.send(mask, &v),
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = res {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+            log::error!("syslog: failed to send an event: {e}");
+        }
+        None
+    }
+}
+
+/// `s` as a header field: printable ASCII without spaces, at most `max` characters, `-` if
+/// empty.
+fn header_field(s: &str, max: usize) -> String {
+    let s: String = s.chars().filter(|c| c.is_ascii_graphic()).take(max).collect();
+    if s.is_empty() { "-".to_string() } else { s }
+}
+
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast::<libc::c_char>(), buf.len()) } != 0 {
+        return String::new();
+    }
+    String::from_utf8_lossy(&buf[..buf.iter().position(|b| *b == 0).unwrap_or(buf.len())]).into_owned()
+}
+
+/// `2026-10-17T08:30:00.123456Z`
+fn timestamp(t: SystemTime) -> String {
+    let d = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = d.as_secs();
+    let (year, month, day) = civil(secs / 86_400);
+    format!("{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:06}Z", secs / 3_600 % 24, secs / 60 % 60, secs % 60, d.subsec_micros())
+}
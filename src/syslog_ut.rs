@@ -0,0 +1,170 @@
+use crate::{
+    callbacks::CallbackHub,
+    severity::{Severity, SeverityMapper},
+    sink::EventSink,
+    syslog::{Facility, SeverityByMask, SyslogCallback, SyslogConfig, SyslogSeverity, SyslogSink},
+    topics::{Topic, Topics},
+};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::{
+    io::Write,
+    os::unix::net::UnixDatagram,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, UNIX_EPOCH},
+};
+use tempfile::TempDir;
+
+/// A syslog daemon: a datagram socket at `path`.
+fn daemon(path: &Path) -> UnixDatagram {
+    let _ = std::fs::remove_file(path);
+    let s = UnixDatagram::bind(path).unwrap();
+    s.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    s
+}
+
+fn recv(s: &UnixDatagram) -> String {
+    let mut buf = [0u8; 4096];
+    let n = s.recv(&mut buf).unwrap();
+    String::from_utf8(buf[..n].to_vec()).unwrap()
+}
+
+/// What went to the fallback, shared with the test.
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Captured {
+    fn lines(&self) -> Vec<String> {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap().lines().map(str::to_string).collect()
+    }
+}
+
+/// The part of a message after the timestamp and host name.
+fn tail(msg: &str) -> String {
+    msg.splitn(4, ' ').nth(3).unwrap().to_string()
+}
+
+#[test]
+fn messages_follow_rfc_5424() {
+    let cfg = SyslogConfig { facility: Facility::Local3, app_name: "omni trace".to_string(), ..SyslogConfig::default() };
+    let sink = SyslogSink::open(cfg);
+    let ev = json!({ "Missing": { "name": "sshd" } });
+    let at = UNIX_EPOCH + Duration::from_micros(1_792_224_000_123_456);
+    let msg = sink.format(SyslogSeverity::Crit, &ev, at);
+
+    let fields: Vec<&str> = msg.splitn(8, ' ').collect();
+    // local3 is 19, crit 2
+    assert_eq!(fields[0], "<154>1");
+    assert_eq!(fields[1], "2026-10-17T08:00:00.123456Z");
+    assert!(!fields[2].is_empty() && !fields[2].contains(' '));
+    assert_eq!(fields[3..6], ["omnitrace", &std::process::id().to_string(), "Missing"]);
+    assert_eq!(fields[6], "-");
+    assert_eq!(serde_json::from_str::<Value>(fields[7]).unwrap(), ev);
+    assert!(tail(&sink.format(SyslogSeverity::Info, &json!([1]), at)).ends_with(" - - [1]"), "no kind, no MSGID");
+}
+
+#[test]
+fn severity_comes_from_the_mask_then_the_mapper() {
+    let cfg = SyslogConfig {
+        severities: vec![SeverityByMask::new(0b100, SyslogSeverity::Alert), SeverityByMask::new(0b110, SyslogSeverity::Err)],
+        ..SyslogConfig::default()
+    };
+    let sink = SyslogSink::open(cfg);
+    let mut ev = json!({ "Faulted": {} });
+    assert_eq!(sink.severity(0b100, &ev), SyslogSeverity::Alert, "first match wins");
+    assert_eq!(sink.severity(0b010, &ev), SyslogSeverity::Err);
+    assert_eq!(sink.severity(0b001, &ev), SyslogSeverity::Notice);
+    SeverityMapper::new(Severity::Critical).tag("xmount", 0b001, &mut ev);
+    assert_eq!(sink.severity(0b001, &ev), SyslogSeverity::Crit);
+    assert_eq!(sink.severity(0b100, &ev), SyslogSeverity::Alert);
+
+    let cfg: SyslogConfig = serde_json::from_value(json!({ "facility": "local7", "severities": [{ "mask": 4, "severity": "crit" }] })).unwrap();
+    assert_eq!((cfg.facility, cfg.socket, cfg.default_severity), (Facility::Local7, PathBuf::from("/dev/log"), SyslogSeverity::Notice));
+    assert!(serde_json::from_value::<SyslogConfig>(json!({ "facility": "local8" })).is_err());
+}
+
+#[tokio::test]
+async fn reconnects_after_a_restart_and_falls_back_to_the_fallback() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let path = dir.join("log");
+    let captured = Captured::default();
+    let mut sink = SyslogSink::open(SyslogConfig { socket: path.clone(), ..SyslogConfig::default() }).fallback(captured.clone());
+
+    // not running yet
+    sink.write(&json!({ "A": 0 })).await.unwrap();
+    assert_eq!(captured.lines().len(), 1);
+    assert!(tail(&captured.lines()[0]).ends_with(r#"A - {"A":0}"#));
+
+    let syslog = daemon(&path);
+    sink.write(&json!({ "B": 1 })).await.unwrap();
+    assert!(recv(&syslog).ends_with(r#"B - {"B":1}"#));
+
+    // restarted: a new socket at the same path
+    drop(syslog);
+    let syslog = daemon(&path);
+    sink.write(&json!({ "C": 2 })).await.unwrap();
+    assert!(recv(&syslog).ends_with(r#"C - {"C":2}"#));
+
+    // gone
+    drop(syslog);
+    std::fs::remove_file(&path).unwrap();
+    sink.write(&json!({ "D": 3 })).await.unwrap();
+    assert_eq!(sink.fallbacks(), 2);
+    assert!(captured.lines()[1].ends_with(r#"D - {"D":3}"#));
+}
+
+#[derive(Serialize)]
+enum Ev {
+    Opened { port: u16 },
+    Missing { name: String },
+}
+
+impl Topics for Ev {
+    const TOPICS: &'static [Topic] = &[Topic::new("ev.opened", 0b01), Topic::new("ev.missing", 0b10)];
+
+    fn topic_index(&self) -> usize {
+        match self {
+            Ev::Opened { .. } => 0,
+            Ev::Missing { .. } => 1,
+        }
+    }
+}
+
+#[tokio::test]
+async fn callback_sends_with_the_severity_of_the_event_mask() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path();
+    let path = dir.join("log");
+    let syslog = daemon(&path);
+    let cfg = SyslogConfig {
+        socket: path,
+        facility: Facility::User,
+        severities: vec![SeverityByMask::new(0b10, SyslogSeverity::Crit)],
+        default_severity: SyslogSeverity::Info,
+        ..SyslogConfig::default()
+    };
+    let cb = SyslogCallback::new(0b11, SyslogSink::open(cfg));
+    let mut hub: CallbackHub<Ev> = CallbackHub::new();
+    hub.add(cb.clone());
+    hub.fire(0b01, &Ev::Opened { port: 22 }).await;
+    hub.fire(0b10, &Ev::Missing { name: "sshd".into() }).await;
+
+    // user is 1: 8 + info (6), 8 + crit (2)
+    let first = recv(&syslog);
+    assert!(first.starts_with("<14>1 ") && first.ends_with(r#"Opened - {"Opened":{"port":22}}"#), "{first}");
+    assert!(recv(&syslog).starts_with("<10>1 "));
+    assert_eq!((cb.errors(), cb.fallbacks().await), (0, 0));
+}
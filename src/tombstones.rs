@@ -0,0 +1,67 @@
+//! Last known state of entities that stopped being watched.
+//!
+//! A sensor whose watch set is edited while it runs (`XMountControl::remove`,
+//! `ProcDogControl::unwatch`, `NetNotifyControl::remove`) drops the baseline of what it no
+//! longer watches: nothing is reported for it from then on, and watching it again re-primes
+//! it silently, as if it had been watched from the start. With the sensor's
+//! `report_unwatched(ttl)` the dropped state is kept here instead, and watching it again within
+//! `ttl` diffs against it, reporting what happened while it was not watched.
+//!
+//! The table is bounded: entries expire after `ttl`, and at capacity a new entry evicts the
+//! oldest one.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+/// Entries kept by default, see [`Tombstones::new`].
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+#[derive(Clone, Debug)]
+pub struct Tombstones<K, V> {
+    ttl: Duration,
+    capacity: usize,
+    entries: HashMap<K, (Instant, V)>,
+}
+
+impl<K: Eq + Hash + Clone, V> Tombstones<K, V> {
+    /// Keep entries for `ttl`, at most `capacity` of them (at least one).
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self { ttl, capacity: capacity.max(1), entries: HashMap::new() }
+    }
+
+    /// Remember `value` as the last state of `key`, unwatched at `now`. Replaces an older entry.
+    pub fn bury(&mut self, key: K, value: V, now: Instant) {
+        self.expire(now);
+        if !self.entries.contains_key(&key)
+            && self.entries.len() >= self.capacity
+            && let Some(oldest) = self.entries.iter().min_by_key(|(_, (at, _))| *at).map(|(k, _)| k.clone())
+        {
+            self.entries.remove(&oldest);
+        }
+        self.entries.insert(key, (now, value));
+    }
+
+    /// The state `key` had when it was unwatched, if that was less than the ttl before `now`.
+    /// The entry is gone afterwards.
+    pub fn take(&mut self, key: &K, now: Instant) -> Option<V> {
+        let (at, value) = self.entries.remove(key)?;
+        (now.saturating_duration_since(at) < self.ttl).then_some(value)
+    }
+
+    /// Drop the entries older than the ttl.
+    pub fn expire(&mut self, now: Instant) {
+        let ttl = self.ttl;
+        self.entries.retain(|_, (at, _)| now.saturating_duration_since(*at) < ttl);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
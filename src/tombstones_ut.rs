@@ -0,0 +1,35 @@
+use crate::tombstones::Tombstones;
+use std::time::{Duration, Instant};
+
+#[test]
+fn entries_expire_after_the_ttl() {
+    let t0 = Instant::now();
+    let mut t = Tombstones::new(Duration::from_secs(60), 8);
+    t.bury("/data", 40, t0);
+    t.bury("/backup", 41, t0);
+    assert_eq!(t.take(&"/data", t0 + Duration::from_secs(59)), Some(40));
+    // taken once
+    assert_eq!(t.take(&"/data", t0 + Duration::from_secs(59)), None);
+    assert_eq!(t.take(&"/backup", t0 + Duration::from_secs(60)), None);
+    assert!(t.is_empty());
+
+    t.bury("/data", 42, t0);
+    t.expire(t0 + Duration::from_secs(61));
+    assert!(t.is_empty());
+}
+
+#[test]
+fn the_oldest_entry_makes_room() {
+    let t0 = Instant::now();
+    let mut t = Tombstones::new(Duration::from_secs(60), 2);
+    t.bury("a", 1, t0);
+    t.bury("b", 2, t0 + Duration::from_secs(1));
+    // replacing does not evict
+    t.bury("a", 3, t0 + Duration::from_secs(2));
+    assert_eq!(t.len(), 2);
+    t.bury("c", 4, t0 + Duration::from_secs(3));
+    assert_eq!(t.len(), 2);
+    assert_eq!(t.take(&"b", t0 + Duration::from_secs(3)), None);
+    assert_eq!(t.take(&"a", t0 + Duration::from_secs(3)), Some(3));
+    assert_eq!(t.take(&"c", t0 + Duration::from_secs(3)), Some(4));
+}
@@ -0,0 +1,156 @@
+//! Dotted, hierarchical names for events, to subscribe by instead of raw mask bits.
+//!
+//! Every event type lists its topics once, e.g. `"mount.mounted"`, `"mount.changed.remount"`,
+//! `"net.conn.opened"`, each with the mask bit of the variant it is about. Callbacks, route and
+//! severity rules and `--filter` expressions then select events by pattern:
+//!
+//! ```ignore
+//! hub.subscribe("mount.changed", OnRemount)?;   // mount.changed.remount, .replaced, .other
+//! hub.subscribe("mount.{mounted,unmounted}", OnMount)?;
+//! hub.subscribe("*.opened", OnOpen)?;           // net.conn.opened, sock.opened
+//! ```
+//!
+//! A pattern is a glob over the whole topic where `*` also spans dots, and it selects a topic
+//! if it matches the topic or one of its parents: `"mount.changed"` selects
+//! `"mount.changed.remount"`, `"mount.*"` every mount topic. Topics are `&'static str` in one
+//! table per event type, so an event's topic is an index into it and a pattern compiles
+//! against the table once, into a [`TopicSet`] checked with a bit test on every event.
+
+use globset::{Glob, GlobMatcher};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// Most topics an event type can have, the bits of a [`TopicSet`].
+pub const MAX_TOPICS: usize = 128;
+
+/// One entry of [`Topics::TOPICS`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Topic {
+    pub name: &'static str,
+    /// Mask bit of the variant, as passed to `fire`. Several topics can share one.
+    pub mask: u64,
+}
+
+impl Topic {
+    pub const fn new(name: &'static str, mask: u64) -> Self {
+        Self { name, mask }
+    }
+}
+
+/// An event type with topics.
+pub trait Topics {
+    /// Every topic the type emits, at most [`MAX_TOPICS`].
+    const TOPICS: &'static [Topic];
+
+    /// Position of this event's topic in [`Topics::TOPICS`].
+    fn topic_index(&self) -> usize;
+}
+
+/// This event's topic name.
+pub fn topic_of<E: Topics>(ev: &E) -> &'static str {
+    E::TOPICS[ev.topic_index()].name
+}
+
+/// Mask bits of the topics of `E` selected by `pattern`, for APIs taking masks, e.g.
+/// `Router::add_rule(RouteRule::new("x").mask(topics::mask::<XMountEvent>("mount.health")?))`.
+pub fn mask<E: Topics>(pattern: &str) -> Result<u64, TopicError> {
+    Ok(TopicSet::parse::<E>(pattern)?.mask::<E>())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum TopicError {
+    #[error("invalid topic pattern {0:?}: {1}")]
+    Invalid(String, String),
+    #[error("topic pattern {0:?} matches no topic")]
+    NoMatch(String),
+}
+
+/// A compiled topic pattern, see the [module docs](self). (De)serializes as the pattern.
+#[derive(Clone, Debug)]
+pub struct TopicPattern {
+    source: String,
+    glob: GlobMatcher,
+}
+
+impl TopicPattern {
+    pub fn parse(pattern: &str) -> Result<Self, TopicError> {
+        let glob = Glob::new(pattern).map_err(|e| TopicError::Invalid(pattern.to_string(), e.kind().to_string()))?;
+        Ok(Self { source: pattern.to_string(), glob: glob.compile_matcher() })
+    }
+
+    /// The pattern as given.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the pattern selects `topic`.
+    pub fn matches(&self, topic: &str) -> bool {
+        glob_matches(&self.glob, topic)
+    }
+}
+
+/// `topic` or one of its parents matches `m`.
+pub(crate) fn glob_matches(m: &GlobMatcher, topic: &str) -> bool {
+    m.is_match(topic) || topic.match_indices('.').any(|(at, _)| m.is_match(&topic[..at]))
+}
+
+impl fmt::Display for TopicPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Serialize for TopicPattern {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for TopicPattern {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(d)?;
+        Self::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Topics of one event type selected by a pattern, a bit per entry of [`Topics::TOPICS`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TopicSet(u128);
+
+impl TopicSet {
+    /// The topics of `E` that `pattern` selects, possibly none.
+    pub fn of<E: Topics>(pattern: &TopicPattern) -> Self {
+        debug_assert!(E::TOPICS.len() <= MAX_TOPICS, "more than {MAX_TOPICS} topics");
+        Self(E::TOPICS.iter().take(MAX_TOPICS).enumerate().filter(|(_, t)| pattern.matches(t.name)).fold(0, |set, (i, _)| set | 1 << i))
+    }
+
+    /// Compile `pattern` against the topics of `E`. A pattern selecting none of them is an
+    /// error, it is a typo more often than not.
+    pub fn parse<E: Topics>(pattern: &str) -> Result<Self, TopicError> {
+        let set = Self::of::<E>(&TopicPattern::parse(pattern)?);
+        if set.is_empty() { Err(TopicError::NoMatch(pattern.to_string())) } else { Ok(set) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether the topic at `index` in [`Topics::TOPICS`] is in the set.
+    pub fn contains(&self, index: usize) -> bool {
+        index < MAX_TOPICS && self.0 & 1 << index != 0
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// Names of the selected topics of `E`, in table order.
+    pub fn names<E: Topics>(&self) -> Vec<&'static str> {
+        E::TOPICS.iter().enumerate().filter(|(i, _)| self.contains(*i)).map(|(_, t)| t.name).collect()
+    }
+
+    /// Mask bits of the selected topics of `E`. Coarser than the set where topics share a bit.
+    pub fn mask<E: Topics>(&self) -> u64 {
+        E::TOPICS.iter().enumerate().filter(|(i, _)| self.contains(*i)).fold(0, |m, (_, t)| m | t.mask)
+    }
+}
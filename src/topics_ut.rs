@@ -0,0 +1,200 @@
+use crate::{
+    callbacks::{Callback, CallbackHub, CallbackResult},
+    fields::{EventFields, FieldValue},
+    filter::Filter,
+    router::{RouteRule, Router, RouterConfig},
+    severity::{Severity, SeverityConfig, SeverityMapper, SeverityRule},
+    topics::{self, Topic, TopicError, TopicPattern, TopicSet, Topics},
+};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::channel;
+
+#[derive(Clone, Debug, Serialize)]
+enum MountEv {
+    Mounted { target: String },
+    Remounted { target: String },
+    Replaced { target: String },
+    Faulted { target: String },
+}
+
+impl MountEv {
+    fn mask(&self) -> u64 {
+        match self {
+            MountEv::Mounted { .. } => 0b001,
+            MountEv::Remounted { .. } | MountEv::Replaced { .. } => 0b010,
+            MountEv::Faulted { .. } => 0b100,
+        }
+    }
+
+    fn target(&self) -> &str {
+        let (MountEv::Mounted { target } | MountEv::Remounted { target } | MountEv::Replaced { target } | MountEv::Faulted { target }) = self;
+        target
+    }
+}
+
+impl Topics for MountEv {
+    const TOPICS: &'static [Topic] = &[
+        Topic::new("mount.mounted", 0b001),
+        Topic::new("mount.changed.remount", 0b010),
+        Topic::new("mount.changed.replaced", 0b010),
+        Topic::new("mount.health.faulted", 0b100),
+    ];
+
+    fn topic_index(&self) -> usize {
+        match self {
+            MountEv::Mounted { .. } => 0,
+            MountEv::Remounted { .. } => 1,
+            MountEv::Replaced { .. } => 2,
+            MountEv::Faulted { .. } => 3,
+        }
+    }
+}
+
+impl EventFields for MountEv {
+    fn kind(&self) -> &'static str {
+        match self {
+            MountEv::Mounted { .. } => "mounted",
+            MountEv::Remounted { .. } => "remounted",
+            MountEv::Replaced { .. } => "replaced",
+            MountEv::Faulted { .. } => "faulted",
+        }
+    }
+
+    fn field(&self, name: &str) -> Option<FieldValue<'_>> {
+        (name == "target").then(|| FieldValue::str(self.target()))
+    }
+
+    fn field_names(&self) -> &'static [&'static str] {
+        &["target"]
+    }
+
+    fn topic(&self) -> Option<&'static str> {
+        Some(topics::topic_of(self))
+    }
+}
+
+fn all() -> Vec<MountEv> {
+    vec![
+        MountEv::Mounted { target: "/mnt/a".into() },
+        MountEv::Remounted { target: "/mnt/a".into() },
+        MountEv::Replaced { target: "/mnt/a".into() },
+        MountEv::Faulted { target: "/mnt/a".into() },
+    ]
+}
+
+#[test]
+fn patterns_select_topics_and_their_children() {
+    let selects = |p: &str, t: &str| TopicPattern::parse(p).unwrap().matches(t);
+    assert!(selects("mount.changed", "mount.changed.remount"));
+    assert!(selects("mount.changed.remount", "mount.changed.remount"));
+    assert!(selects("mount.*", "mount.changed.remount"));
+    assert!(selects("*.remount", "mount.changed.remount"));
+    assert!(selects("mount.{mounted,health}", "mount.health.faulted"));
+    assert!(!selects("mount.change", "mount.changed.remount"), "parents end at a dot");
+    assert!(!selects("mount.changed.remount", "mount.changed"), "children do not select parents");
+    assert!(!selects("mount.*", "mount"));
+    assert!(!selects("net.*", "mount.mounted"));
+}
+
+#[test]
+fn sets_compile_against_the_table_and_derive_masks() {
+    let set = TopicSet::parse::<MountEv>("mount.changed").unwrap();
+    assert_eq!(set.names::<MountEv>(), vec!["mount.changed.remount", "mount.changed.replaced"]);
+    assert_eq!(set.mask::<MountEv>(), 0b010);
+    assert_eq!(topics::mask::<MountEv>("mount.{mounted,health}").unwrap(), 0b101);
+    assert_eq!(topics::mask::<MountEv>("*").unwrap(), 0b111);
+
+    assert_eq!(TopicSet::parse::<MountEv>("net.*"), Err(TopicError::NoMatch("net.*".into())));
+    assert!(matches!(TopicSet::parse::<MountEv>("mount.{a"), Err(TopicError::Invalid(..))));
+    assert!(TopicSet::of::<MountEv>(&TopicPattern::parse("net.*").unwrap()).is_empty());
+
+    for ev in all() {
+        assert_eq!(MountEv::TOPICS[ev.topic_index()].mask, ev.mask(), "{ev:?}");
+    }
+}
+
+struct Recorder(Arc<Mutex<Vec<String>>>, u64);
+
+#[async_trait]
+impl Callback<MountEv> for Recorder {
+    fn mask(&self) -> u64 {
+        self.1
+    }
+
+    async fn call(&self, ev: &MountEv) -> Option<CallbackResult> {
+        self.0.lock().unwrap().push(topics::topic_of(ev).to_string());
+        None
+    }
+}
+
+#[tokio::test]
+async fn subscriptions_replace_the_callback_mask() {
+    let (remounts, everything, masked) = (Arc::default(), Arc::default(), Arc::default());
+    let mut hub = CallbackHub::new();
+    // the mask is ignored for subscriptions
+    hub.subscribe("mount.changed.remount", Recorder(Arc::clone(&remounts), 0)).unwrap();
+    hub.subscribe("mount", Recorder(Arc::clone(&everything), 0)).unwrap();
+    hub.add(Recorder(Arc::clone(&masked), 0b010));
+    assert_eq!(hub.subscribe("mount.unmounted", Recorder(Arc::default(), 0)), Err(TopicError::NoMatch("mount.unmounted".into())));
+
+    for ev in all() {
+        hub.fire(ev.mask(), &ev).await;
+    }
+    assert_eq!(*remounts.lock().unwrap(), vec!["mount.changed.remount"]);
+    assert_eq!(everything.lock().unwrap().len(), 4);
+    assert_eq!(*masked.lock().unwrap(), vec!["mount.changed.remount", "mount.changed.replaced"]);
+    let stats = hub.stats();
+    assert_eq!((stats.called, stats.mask_mismatch), (7, 5));
+}
+
+#[tokio::test]
+async fn route_and_severity_rules_match_typed_topics() {
+    let mut router = Router::new();
+    let (tx, mut rx) = channel(8);
+    router.add_sink("syslog", tx);
+    router.add_rule(RouteRule::new("replaced").topic("mount.changed.replaced").unwrap().to("syslog"));
+    router.add_rule(RouteRule::new("rest"));
+
+    for ev in all() {
+        router.dispatch_event("xmount", ev.mask(), &ev).await;
+    }
+    let routed = rx.try_recv().unwrap();
+    assert_eq!(routed["Replaced"]["target"], "/mnt/a");
+    assert!(rx.try_recv().is_err());
+
+    let mapper = SeverityMapper::from_config(SeverityConfig {
+        rules: vec![SeverityRule::new(Severity::Critical).topic("mount.health").unwrap()],
+        ..Default::default()
+    });
+    let faulted = MountEv::Faulted { target: "/".into() };
+    assert_eq!(mapper.severity_of_event("xmount", faulted.mask(), &faulted), Severity::Critical);
+    // serialized events have no topic
+    assert_eq!(mapper.severity("xmount", faulted.mask(), &serde_json::to_value(&faulted).unwrap()), Severity::Info);
+}
+
+#[test]
+fn topic_patterns_come_from_config() {
+    let cfg: RouterConfig = serde_json::from_value(serde_json::json!({
+        "routes": [{ "name": "remounts", "topic": "mount.changed.remount", "sinks": ["syslog"] }]
+    }))
+    .unwrap();
+    assert_eq!(cfg.routes[0].topic.as_ref().map(TopicPattern::as_str), Some("mount.changed.remount"));
+    assert_eq!(serde_json::to_value(&cfg.routes[0]).unwrap()["topic"], "mount.changed.remount");
+
+    let bad = serde_json::from_value::<RouterConfig>(serde_json::json!({ "routes": [{ "name": "x", "topic": "mount.{" }] }));
+    assert!(bad.unwrap_err().to_string().contains("invalid topic pattern"));
+}
+
+#[test]
+fn filters_match_topics_hierarchically() {
+    let keep = |expr: &str| {
+        let f = Filter::parse(expr).unwrap();
+        all().iter().filter(|ev| f.matches_fields(*ev)).map(|ev| ev.kind()).collect::<Vec<_>>()
+    };
+    assert_eq!(keep(r#"topic ~ "mount.changed""#), vec!["remounted", "replaced"]);
+    assert_eq!(keep(r#"topic !~ "mount.changed""#), vec!["mounted", "faulted"]);
+    assert_eq!(keep(r#"topic == "mount.health.faulted""#), vec!["faulted"]);
+    assert_eq!(keep(r#"topic ~ "*.faulted" && target ~ "/mnt/*""#), vec!["faulted"]);
+}
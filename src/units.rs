@@ -0,0 +1,444 @@
+//! Durations, sizes and rates as people write them, for config files, CLI flags and the
+//! filter language.
+//!
+//! - Durations: one or more `<number><unit>` groups, largest unit first, no spaces:
+//!   `500ms`, `2m`, `1h30m`, `1.5s`. Units are `d`, `h`, `m`, `s`, `ms`, `us` (or `µs`) and
+//!   `ns`. `0` needs no unit.
+//! - Sizes: a number with an optional unit: `512`, `512B`, `512k`, `8GiB`, `1.5MiB`. `k`/`K`,
+//!   `M`, `G`, `T`, `P` and `KiB`..`PiB` are powers of 1024, `kB`/`KB`, `MB`, `GB`, `TB`, `PB`
+//!   powers of 1000. The result must be a whole number of bytes.
+//! - Rates: `<count>/<duration>`, where a duration of one unit may leave the 1 out: `10/s`,
+//!   `600/m`, `5/30s`. They are kept as events per second.
+//!
+//! Numbers are plain decimals (no sign, exponent or separators). Errors name the offending
+//! token and its column. [`HumanDuration`], [`ByteSize`] and [`Rate`] display values in the
+//! same syntax, so what the debug snapshot or a CLI prints can be pasted back into a config.
+//!
+//! Config fields that took plain numbers keep their unit for them, through the
+//! `deserialize_with` helpers [`ms`], [`us`], [`secs`], [`bytes`] and [`per_sec`]:
+//!
+//! ```ignore
+//! #[serde(default = "default_interval", deserialize_with = "omnitrace_core::units::ms::deserialize")]
+//! pub fsync_interval_ms: u64, // 1000 or "1s"
+//! ```
+
+use serde::{Deserialize, Deserializer, de::Error as _};
+use std::{fmt, str::FromStr, time::Duration};
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// Largest unit first; a duration's groups must follow this order.
+const DURATION_UNITS: &[(&str, u128)] = &[
+    ("d", 86_400 * NANOS_PER_SEC),
+    ("h", 3_600 * NANOS_PER_SEC),
+    ("m", 60 * NANOS_PER_SEC),
+    ("s", NANOS_PER_SEC),
+    ("ms", 1_000_000),
+    ("us", 1_000),
+    ("µs", 1_000),
+    ("ns", 1),
+];
+
+const SIZE_UNITS: &[(&str, u128)] = &[
+    ("B", 1),
+    ("k", 1 << 10),
+    ("K", 1 << 10),
+    ("KiB", 1 << 10),
+    ("M", 1 << 20),
+    ("MiB", 1 << 20),
+    ("G", 1 << 30),
+    ("GiB", 1 << 30),
+    ("T", 1 << 40),
+    ("TiB", 1 << 40),
+    ("P", 1 << 50),
+    ("PiB", 1 << 50),
+    ("kB", 1_000),
+    ("KB", 1_000),
+    ("MB", 1_000_000),
+    ("GB", 1_000_000_000),
+    ("TB", 1_000_000_000_000),
+    ("PB", 1_000_000_000_000_000),
+];
+
+/// A duration, size or rate that does not parse.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("invalid {what} {input:?}: {message} at column {}", pos + 1)]
+pub struct UnitError {
+    /// `"duration"`, `"size"` or `"rate"`.
+    pub what: &'static str,
+    pub input: String,
+    /// Character offset of the offending token into `input`.
+    pub pos: usize,
+    pub message: String,
+}
+
+/// A decimal number as written: integer digits and fraction digits.
+struct Number {
+    int: u128,
+    /// Fraction digits, trailing zeros trimmed.
+    frac: String,
+}
+
+impl Number {
+    /// `self * unit`, if that is a whole number.
+    fn times(&self, unit: u128) -> Option<u128> {
+        let whole = self.int.checked_mul(unit)?;
+        if self.frac.is_empty() {
+            return Some(whole);
+        }
+        let scale = 10u128.checked_pow(self.frac.len() as u32)?;
+        let part = self.frac.parse::<u128>().ok()?.checked_mul(unit)?;
+        if part % scale != 0 {
+            return None;
+        }
+        whole.checked_add(part / scale)
+    }
+}
+
+struct Scanner<'a> {
+    what: &'static str,
+    input: &'a str,
+    chars: Vec<char>,
+    at: usize,
+    /// Added to positions, for scanning a part of the input.
+    offset: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(what: &'static str, input: &'a str) -> Self {
+        Self { what, input, chars: input.chars().collect(), at: 0, offset: 0 }
+    }
+
+    fn error(&self, pos: usize, message: String) -> UnitError {
+        UnitError { what: self.what, input: self.input.to_string(), pos: self.offset + pos, message }
+    }
+
+    fn done(&self) -> bool {
+        self.at >= self.chars.len()
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> String {
+        let start = self.at;
+        while self.chars.get(self.at).is_some_and(|c| f(*c)) {
+            self.at += 1;
+        }
+        self.chars[start..self.at].iter().collect()
+    }
+
+    fn number(&mut self) -> Result<Number, UnitError> {
+        let pos = self.at;
+        let int = self.take_while(|c| c.is_ascii_digit());
+        if int.is_empty() {
+            let found = self.chars.get(pos).map_or("the end".to_string(), |c| format!("{c:?}"));
+            return Err(self.error(pos, format!("expected a number, found {found}")));
+        }
+        let mut frac = String::new();
+        if self.chars.get(self.at) == Some(&'.') {
+            self.at += 1;
+            frac = self.take_while(|c| c.is_ascii_digit());
+            if frac.is_empty() {
+                return Err(self.error(self.at, "expected digits after the decimal point".to_string()));
+            }
+        }
+        let too_long = || self.error(pos, "number too large".to_string());
+        let int = int.parse::<u128>().map_err(|_| too_long())?;
+        let frac = frac.trim_end_matches('0').to_string();
+        if frac.len() > 30 {
+            return Err(self.error(pos, "too many decimals".to_string()));
+        }
+        Ok(Number { int, frac })
+    }
+
+    /// The unit at the current position, from `units`, or None if there is no unit.
+    fn unit(&mut self, units: &[(&'static str, u128)]) -> Result<Option<(&'static str, u128, usize)>, UnitError> {
+        let pos = self.at;
+        let text = self.take_while(|c| c.is_alphabetic());
+        if text.is_empty() {
+            return Ok(None);
+        }
+        match units.iter().find(|(name, _)| *name == text) {
+            Some((name, scale)) => Ok(Some((name, *scale, pos))),
+            None => Err(self.error(pos, format!("unknown unit {text:?}"))),
+        }
+    }
+}
+
+/// Parse a duration, see the [module docs](self).
+pub fn parse_duration(s: &str) -> Result<Duration, UnitError> {
+    let mut sc = Scanner::new("duration", s);
+    duration_nanos(&mut sc).map(duration_from_nanos)
+}
+
+fn duration_from_nanos(nanos: u128) -> Duration {
+    Duration::new((nanos / NANOS_PER_SEC) as u64, (nanos % NANOS_PER_SEC) as u32)
+}
+
+fn duration_nanos(sc: &mut Scanner<'_>) -> Result<u128, UnitError> {
+    if sc.chars.is_empty() {
+        return Err(sc.error(0, "empty".to_string()));
+    }
+    if sc.chars == ['0'] {
+        return Ok(0);
+    }
+    let (mut total, mut last): (u128, Option<u128>) = (0, None);
+    while !sc.done() {
+        let pos = sc.at;
+        let n = sc.number()?;
+        let Some((name, scale, unit_pos)) = sc.unit(DURATION_UNITS)? else {
+            let message = if sc.done() { "missing unit" } else { "expected a unit" };
+            return Err(sc.error(sc.at, message.to_string()));
+        };
+        if last.is_some_and(|l| scale >= l) {
+            return Err(sc.error(unit_pos, format!("unit {name:?} out of order, largest unit first")));
+        }
+        last = Some(scale);
+        let nanos = n.times(scale).ok_or_else(|| sc.error(pos, "finer than a nanosecond, or too large".to_string()))?;
+        total = total.checked_add(nanos).ok_or_else(|| sc.error(pos, "too large".to_string()))?;
+    }
+    if total / NANOS_PER_SEC > u128::from(u64::MAX) {
+        return Err(sc.error(0, "too large".to_string()));
+    }
+    Ok(total)
+}
+
+/// Parse a size in bytes, see the [module docs](self).
+pub fn parse_size(s: &str) -> Result<u64, UnitError> {
+    let mut sc = Scanner::new("size", s);
+    if sc.chars.is_empty() {
+        return Err(sc.error(0, "empty".to_string()));
+    }
+    let n = sc.number()?;
+    let (name, scale) = sc.unit(SIZE_UNITS)?.map_or(("B", 1), |(name, scale, _)| (name, scale));
+    if !sc.done() {
+        return Err(sc.error(sc.at, format!("unexpected {:?} after the unit", sc.chars[sc.at])));
+    }
+    n.times(scale).and_then(|b| u64::try_from(b).ok()).ok_or_else(|| sc.error(0, format!("not a whole number of bytes in {name}, or too large")))
+}
+
+/// Parse a rate, in events per second, see the [module docs](self).
+pub fn parse_rate(s: &str) -> Result<f64, UnitError> {
+    let sc = Scanner::new("rate", s);
+    let Some(slash) = sc.chars.iter().position(|c| *c == '/') else {
+        return Err(sc.error(sc.chars.len(), "expected `/` and a duration, e.g. `10/s`".to_string()));
+    };
+    let count_text: String = sc.chars[..slash].iter().collect();
+    let mut count_sc = Scanner::new("rate", &count_text);
+    count_sc.input = s;
+    let n = count_sc.number()?;
+    if !count_sc.done() {
+        return Err(sc.error(count_sc.at, format!("unexpected {:?} in the count", count_sc.chars[count_sc.at])));
+    }
+    let count: f64 = format!("{}.{}0", n.int, n.frac).parse().map_err(|_| sc.error(0, "number too large".to_string()))?;
+
+    let per: String = sc.chars[slash + 1..].iter().collect();
+    // `10/s` is `10/1s`; positions in the error are of the input as written
+    let implied_one = per.starts_with(char::is_alphabetic);
+    let per = if implied_one { format!("1{per}") } else { per };
+    let mut per_sc = Scanner::new("rate", &per);
+    per_sc.input = s;
+    per_sc.offset = slash + 1;
+    let nanos = duration_nanos(&mut per_sc).map_err(|mut e| {
+        e.pos -= usize::from(implied_one && e.pos > slash + 1);
+        e
+    })?;
+    if nanos == 0 {
+        return Err(sc.error(slash + 1, "zero duration".to_string()));
+    }
+    Ok(count / (nanos as f64 / NANOS_PER_SEC as f64))
+}
+
+/// A bare number counts in `unit`, anything else parses as a duration. For flags and config
+/// fields that took plain numbers before.
+pub fn parse_duration_or(s: &str, unit: Duration) -> Result<Duration, UnitError> {
+    match Scanner::new("duration", s).number_only() {
+        Some(n) => n.times(unit.as_nanos()).filter(|nanos| nanos / NANOS_PER_SEC <= u128::from(u64::MAX)).map(duration_from_nanos).ok_or_else(|| {
+            UnitError { what: "duration", input: s.to_string(), pos: 0, message: "finer than a nanosecond, or too large".to_string() }
+        }),
+        None => parse_duration(s),
+    }
+}
+
+impl Scanner<'_> {
+    /// The input as a number, if that is all it is.
+    fn number_only(mut self) -> Option<Number> {
+        let n = self.number().ok()?;
+        self.done().then_some(n)
+    }
+}
+
+/// A duration displayed as [`parse_duration`] reads it, e.g. `1h30m` or `1s500ms`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HumanDuration(pub Duration);
+
+impl fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut nanos = self.0.as_nanos();
+        if nanos == 0 {
+            return f.write_str("0s");
+        }
+        for (name, scale) in DURATION_UNITS.iter().filter(|(name, _)| *name != "µs") {
+            if nanos >= *scale {
+                write!(f, "{}{name}", nanos / scale)?;
+                nanos %= scale;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for HumanDuration {
+    type Err = UnitError;
+
+    fn from_str(s: &str) -> Result<Self, UnitError> {
+        parse_duration(s).map(Self)
+    }
+}
+
+impl From<Duration> for HumanDuration {
+    fn from(d: Duration) -> Self {
+        Self(d)
+    }
+}
+
+/// A size displayed as [`parse_size`] reads it: in the largest binary unit it is a whole
+/// number of (`8GiB`), else decimal (`1500kB`), else bytes (`1234B`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ByteSize(pub u64);
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let b = u128::from(self.0);
+        let binary = ["PiB", "TiB", "GiB", "MiB", "KiB"];
+        let decimal = ["PB", "TB", "GB", "MB", "kB"];
+        let unit = |name: &str| SIZE_UNITS.iter().find(|(n, _)| *n == name).map_or(1, |(_, s)| *s);
+        if b > 0 {
+            for name in binary.into_iter().chain(decimal) {
+                if b.is_multiple_of(unit(name)) {
+                    return write!(f, "{}{name}", b / unit(name));
+                }
+            }
+        }
+        write!(f, "{b}B")
+    }
+}
+
+impl FromStr for ByteSize {
+    type Err = UnitError;
+
+    fn from_str(s: &str) -> Result<Self, UnitError> {
+        parse_size(s).map(Self)
+    }
+}
+
+/// Events per second, displayed as [`parse_rate`] reads it: per second, minute, hour or
+/// day, whichever gives a whole count first (`10/s`, `30/m`), else per second.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Rate(pub f64);
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, secs) in [("s", 1.0), ("m", 60.0), ("h", 3_600.0), ("d", 86_400.0)] {
+            let n = self.0 * secs;
+            if n.fract() == 0.0 && n / secs == self.0 {
+                return write!(f, "{n}/{name}");
+            }
+        }
+        write!(f, "{}/s", self.0)
+    }
+}
+
+impl FromStr for Rate {
+    type Err = UnitError;
+
+    fn from_str(s: &str) -> Result<Self, UnitError> {
+        parse_rate(s).map(Self)
+    }
+}
+
+/// A config value as written: a plain number in the field's unit, or a string with units.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Raw {
+    Int(u64),
+    Float(f64),
+    Str(String),
+}
+
+fn duration_field<'de, D: Deserializer<'de>>(d: D, unit: Duration) -> Result<Duration, D::Error> {
+    match Raw::deserialize(d)? {
+        Raw::Int(n) => Number { int: u128::from(n), frac: String::new() }
+            .times(unit.as_nanos())
+            .filter(|nanos| nanos / NANOS_PER_SEC <= u128::from(u64::MAX))
+            .map(duration_from_nanos)
+            .ok_or_else(|| D::Error::custom("duration too large")),
+        Raw::Float(x) if x.is_finite() && x >= 0.0 => Duration::try_from_secs_f64(x * unit.as_secs_f64()).map_err(D::Error::custom),
+        Raw::Float(x) => Err(D::Error::custom(format!("invalid duration {x}"))),
+        Raw::Str(s) => parse_duration_or(&s, unit).map_err(D::Error::custom),
+    }
+}
+
+/// `deserialize_with` for `u64` milliseconds: `1000` or `"1s"`.
+pub mod ms {
+    use super::*;
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<u64, D::Error> {
+        let d = duration_field(d, Duration::from_millis(1))?;
+        u64::try_from(d.as_millis()).map_err(D::Error::custom)
+    }
+}
+
+/// `deserialize_with` for `u64` microseconds: `20000` or `"20ms"`.
+pub mod us {
+    use super::*;
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<u64, D::Error> {
+        let d = duration_field(d, Duration::from_micros(1))?;
+        u64::try_from(d.as_micros()).map_err(D::Error::custom)
+    }
+}
+
+/// `deserialize_with` for `f64` seconds: `90`, `1.5` or `"1m30s"`.
+pub mod secs {
+    use super::*;
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<f64, D::Error> {
+        duration_field(d, Duration::from_secs(1)).map(|d| d.as_secs_f64())
+    }
+}
+
+/// `deserialize_with` for byte counts of any integer type: `4194304` or `"4MiB"`.
+pub mod bytes {
+    use super::*;
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T: TryFrom<u64>>(d: D) -> Result<T, D::Error> {
+        let n = match Raw::deserialize(d)? {
+            Raw::Int(n) => n,
+            Raw::Float(x) => return Err(D::Error::custom(format!("invalid size {x}, expected whole bytes"))),
+            Raw::Str(s) => parse_size(&s).map_err(D::Error::custom)?,
+        };
+        T::try_from(n).map_err(|_| D::Error::custom(format!("size {} too large", ByteSize(n))))
+    }
+}
+
+/// `deserialize_with` for `f64` (or, with `opt`, `Option<f64>`) events per second: `10` or `"600/m"`.
+pub mod per_sec {
+    use super::*;
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<f64, D::Error> {
+        match Raw::deserialize(d)? {
+            Raw::Int(n) => Ok(n as f64),
+            Raw::Float(x) => Ok(x),
+            Raw::Str(s) => parse_rate(&s).map_err(D::Error::custom),
+        }
+    }
+
+    pub fn opt<'de, D: Deserializer<'de>>(d: D) -> Result<Option<f64>, D::Error> {
+        Option::<Raw>::deserialize(d)?
+            .map(|raw| match raw {
+                Raw::Int(n) => Ok(n as f64),
+                Raw::Float(x) => Ok(x),
+                Raw::Str(s) => parse_rate(&s).map_err(D::Error::custom),
+            })
+            .transpose()
+    }
+}
@@ -0,0 +1,179 @@
+use crate::units::{self, ByteSize, HumanDuration, Rate, parse_duration, parse_duration_or, parse_rate, parse_size};
+use serde::Deserialize;
+use std::time::Duration;
+
+#[test]
+fn accepted_forms() {
+    let ms = Duration::from_millis;
+    for (input, want) in [
+        ("0", Duration::ZERO),
+        ("0s", Duration::ZERO),
+        ("500ms", ms(500)),
+        ("2m", ms(120_000)),
+        ("1h30m", ms(5_400_000)),
+        ("1d2h3m4s5ms6us7ns", Duration::new(93_784, 5_006_007)),
+        ("1.5s", ms(1500)),
+        ("0.25h", ms(900_000)),
+        ("250us", Duration::from_micros(250)),
+        ("250µs", Duration::from_micros(250)),
+        (" 10s ", ms(10_000)),
+    ] {
+        assert_eq!(parse_duration(input.trim()), Ok(want), "{input}");
+    }
+    for (input, want) in [
+        ("0", 0),
+        ("512", 512),
+        ("512B", 512),
+        ("512k", 512 << 10),
+        ("512K", 512 << 10),
+        ("8GiB", 8 << 30),
+        ("1.5MiB", 3 << 19),
+        ("2kB", 2000),
+        ("3MB", 3_000_000),
+        ("1P", 1 << 50),
+    ] {
+        assert_eq!(parse_size(input), Ok(want), "{input}");
+    }
+    for (input, want) in [("10/s", 10.0), ("600/m", 10.0), ("5/30s", 1.0 / 6.0), ("1.5/ms", 1500.0), ("0/h", 0.0), ("36/1h", 0.01)] {
+        assert_eq!(parse_rate(input), Ok(want), "{input}");
+    }
+}
+
+#[test]
+fn rejected_forms_cite_the_token() {
+    for (input, message, column) in [
+        ("", "empty", 1),
+        ("10", "missing unit", 3),
+        ("1h30", "missing unit", 5),
+        ("1h30x", "unknown unit \"x\"", 5),
+        ("30m1h", "unit \"h\" out of order, largest unit first", 5),
+        ("1m1m", "unit \"m\" out of order, largest unit first", 4),
+        ("-5s", "expected a number, found '-'", 1),
+        ("1 s", "expected a unit", 2),
+        ("1.s", "expected digits after the decimal point", 3),
+        (".5s", "expected a number, found '.'", 1),
+        ("1e3s", "unknown unit \"e\"", 2),
+        ("1.5ns", "finer than a nanosecond, or too large", 1),
+        ("99999999999999999999999d", "too large", 1),
+    ] {
+        let e = parse_duration(input).unwrap_err();
+        assert_eq!((e.message.as_str(), e.pos + 1), (message, column), "{input}");
+    }
+    for (input, message, column) in [
+        ("8gib", "unknown unit \"gib\"", 2),
+        ("512m", "unknown unit \"m\"", 4),
+        ("1.3k", "not a whole number of bytes in k, or too large", 1),
+        ("16EiB", "unknown unit \"EiB\"", 3),
+        ("20000P", "not a whole number of bytes in P, or too large", 1),
+        ("1k ", "unexpected ' ' after the unit", 3),
+        ("k", "expected a number, found 'k'", 1),
+    ] {
+        let e = parse_size(input).unwrap_err();
+        assert_eq!((e.message.as_str(), e.pos + 1), (message, column), "{input}");
+    }
+    for (input, message, column) in [
+        ("10", "expected `/` and a duration, e.g. `10/s`", 3),
+        ("10/", "empty", 4),
+        ("10/x", "unknown unit \"x\"", 4),
+        ("10/0s", "zero duration", 4),
+        ("ten/s", "expected a number, found 't'", 1),
+        ("10/1h30", "missing unit", 8),
+    ] {
+        let e = parse_rate(input).unwrap_err();
+        assert_eq!((e.message.as_str(), e.pos + 1), (message, column), "{input}");
+    }
+    assert_eq!(parse_duration("1h30x").unwrap_err().to_string(), "invalid duration \"1h30x\": unknown unit \"x\" at column 5");
+}
+
+#[test]
+fn display_uses_the_same_syntax() {
+    assert_eq!(HumanDuration(Duration::ZERO).to_string(), "0s");
+    assert_eq!(HumanDuration(Duration::from_millis(5_400_000)).to_string(), "1h30m");
+    assert_eq!(HumanDuration(Duration::from_millis(1500)).to_string(), "1s500ms");
+    assert_eq!(HumanDuration(Duration::new(93_784, 5_006_007)).to_string(), "1d2h3m4s5ms6us7ns");
+    assert_eq!(ByteSize(0).to_string(), "0B");
+    assert_eq!(ByteSize(8 << 30).to_string(), "8GiB");
+    assert_eq!(ByteSize(1536).to_string(), "1536B");
+    assert_eq!(ByteSize(3 << 19).to_string(), "1536KiB");
+    assert_eq!(ByteSize(2000).to_string(), "2kB");
+    assert_eq!(ByteSize(1234).to_string(), "1234B");
+    assert_eq!(Rate(10.0).to_string(), "10/s");
+    assert_eq!(Rate(0.5).to_string(), "30/m");
+    assert_eq!(Rate(1.0 / 3600.0).to_string(), "1/h");
+}
+
+#[test]
+fn random_values_round_trip() {
+    let mut rng = fastrand::Rng::with_seed(756);
+    for _ in 0..10_000 {
+        let d = match rng.u8(0..3) {
+            0 => Duration::new(rng.u64(0..100_000), rng.u32(0..1_000_000_000)),
+            1 => Duration::from_millis(rng.u64(0..10_000_000)),
+            _ => Duration::new(rng.u64(..), rng.u32(0..1_000_000_000)),
+        };
+        let text = HumanDuration(d).to_string();
+        assert_eq!(parse_duration(&text), Ok(d), "{text}");
+
+        let b = match rng.u8(0..3) {
+            0 => rng.u64(0..1 << 20),
+            1 => rng.u64(0..1 << 20) << rng.u32(0..40),
+            _ => rng.u64(..),
+        };
+        let text = ByteSize(b).to_string();
+        assert_eq!(parse_size(&text), Ok(b), "{text}");
+
+        let r = match rng.u8(0..3) {
+            0 => f64::from(rng.u32(..)),
+            1 => f64::from(rng.u32(0..1000)) / 60.0,
+            _ => rng.f64() * 10f64.powi(rng.i32(-6..9)),
+        };
+        let text = Rate(r).to_string();
+        assert_eq!(parse_rate(&text), Ok(r), "{text}");
+    }
+}
+
+#[test]
+fn bare_numbers_count_in_the_given_unit() {
+    assert_eq!(parse_duration_or("250", Duration::from_millis(1)), Ok(Duration::from_millis(250)));
+    assert_eq!(parse_duration_or("1.5", Duration::from_secs(1)), Ok(Duration::from_millis(1500)));
+    assert_eq!(parse_duration_or("2m", Duration::from_millis(1)), Ok(Duration::from_secs(120)));
+    assert!(parse_duration_or("2x", Duration::from_millis(1)).is_err());
+}
+
+#[test]
+fn config_fields_take_numbers_or_units() {
+    #[derive(Deserialize)]
+    struct Cfg {
+        #[serde(deserialize_with = "units::ms::deserialize")]
+        interval_ms: u64,
+        #[serde(deserialize_with = "units::us::deserialize")]
+        delay_us: u64,
+        #[serde(deserialize_with = "units::secs::deserialize")]
+        window_secs: f64,
+        #[serde(deserialize_with = "units::bytes::deserialize")]
+        max_bytes: u64,
+        #[serde(deserialize_with = "units::bytes::deserialize")]
+        payload: u16,
+        #[serde(deserialize_with = "units::per_sec::deserialize")]
+        rate: f64,
+        #[serde(default, deserialize_with = "units::per_sec::opt")]
+        limit: Option<f64>,
+    }
+
+    let plain: Cfg = serde_json::from_str(
+        r#"{ "interval_ms": 1000, "delay_us": 20, "window_secs": 1.5, "max_bytes": 4096, "payload": 64, "rate": 2.5, "limit": 3 }"#,
+    )
+    .unwrap();
+    let units: Cfg = serde_json::from_str(
+        r#"{ "interval_ms": "1s", "delay_us": "20us", "window_secs": "1s500ms", "max_bytes": "4KiB", "payload": "64B", "rate": "150/m", "limit": "3/s" }"#,
+    )
+    .unwrap();
+    for c in [plain, units] {
+        assert_eq!((c.interval_ms, c.delay_us, c.window_secs, c.max_bytes, c.payload), (1000, 20, 1.5, 4096, 64));
+        assert_eq!((c.rate, c.limit), (2.5, Some(3.0)));
+    }
+
+    let err = |json: &str| serde_json::from_str::<Cfg>(json).err().map(|e| e.to_string()).unwrap_or_default();
+    assert!(err(r#"{ "interval_ms": "1 s" }"#).starts_with("invalid duration \"1 s\": expected a unit at column 2"));
+    assert!(err(r#"{ "interval_ms": 1, "delay_us": 1, "window_secs": 1, "max_bytes": 1, "payload": "1MiB" }"#).starts_with("size 1MiB too large"));
+}
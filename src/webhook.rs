@@ -0,0 +1,326 @@
+//! Webhook sink: events POSTed in batches to an HTTP endpoint, as a JSON array per request.
+//! Behind the `http` feature.
+//!
+//! ```ignore
+//! let mut cfg = WebhookConfig::new("https://incidents.example.com/omnitrace");
+//! cfg.headers.insert("authorization".into(), format!("Bearer {token}"));
+//! let sink = WebhookSink::start(cfg)?;
+//! let stats = sink.stats();
+//! hub.add(SinkCallback::new(XMountMask::all().bits(), sink));
+//! ```
+//!
+//! Events wait in a bounded queue. A batch goes out once `batch_size` of them are queued, and
+//! whatever is queued every `flush_interval_ms` and on [`EventSink::flush`]. A batch that fails
+//! (connection error, timeout, 408, 429 or 5xx) is retried with exponential backoff, new events
+//! queueing up behind it; one still failing after `max_retries`, or rejected with another
+//! status, is dropped and counted as [`WebhookStats::failed`]. When the queue is full the
+//! oldest event makes room for the new one and counts as [`WebhookStats::dropped`].
+
+use crate::{callbacks::CallbackResult, sink::EventSink};
+use async_trait::async_trait;
+use reqwest::{
+    Client, StatusCode, Url,
+    header::{CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::{Notify, mpsc, oneshot},
+    task::JoinHandle,
+    time::MissedTickBehavior,
+};
+
+/// ```json
+/// { "url": "https://incidents.example.com/omnitrace", "headers": { "authorization": "Bearer ..." }, "batch_size": 100, "flush_interval_ms": "1s" }
+/// ```
+///
+/// Durations also take plain numbers of milliseconds.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Sent with every request, besides `content-type: application/json`.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    #[serde(default = "default_flush_interval", deserialize_with = "crate::units::ms::deserialize")]
+    pub flush_interval_ms: u64,
+    /// Events queued at most, the batch being sent not included.
+    #[serde(default = "default_queue_capacity")]
+    pub queue_capacity: usize,
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled for each one after it up to `max_backoff_ms`.
+    #[serde(default = "default_initial_backoff", deserialize_with = "crate::units::ms::deserialize")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_max_backoff", deserialize_with = "crate::units::ms::deserialize")]
+    pub max_backoff_ms: u64,
+    /// Per request.
+    #[serde(default = "default_timeout", deserialize_with = "crate::units::ms::deserialize")]
+    pub timeout_ms: u64,
+}
+
+impl WebhookConfig {
+    pub fn new<S: Into<String>>(url: S) -> Self {
+        Self {
+            url: url.into(),
+            headers: BTreeMap::new(),
+            batch_size: default_batch_size(),
+            flush_interval_ms: default_flush_interval(),
+            queue_capacity: default_queue_capacity(),
+            max_retries: default_max_retries(),
+            initial_backoff_ms: default_initial_backoff(),
+            max_backoff_ms: default_max_backoff(),
+            timeout_ms: default_timeout(),
+        }
+    }
+}
+
+fn default_batch_size() -> usize {
+    100
+}
+
+fn default_flush_interval() -> u64 {
+    1000
+}
+
+fn default_queue_capacity() -> usize {
+    10_000
+}
+
+fn default_max_retries() -> u32 {
+    8
+}
+
+fn default_initial_backoff() -> u64 {
+    500
+}
+
+fn default_max_backoff() -> u64 {
+    30_000
+}
+
+fn default_timeout() -> u64 {
+    10_000
+}
+
+struct Shared {
+    queue: Mutex<VecDeque<Value>>,
+    // a full batch is queued
+    wake: Notify,
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    failed: AtomicU64,
+}
+
+/// Counters of a [`WebhookSink`], kept after the sink went to a hub.
+#[derive(Clone)]
+pub struct WebhookStats(Arc<Shared>);
+
+impl WebhookStats {
+    /// Events the endpoint accepted.
+    pub fn sent(&self) -> u64 {
+        self.0.sent.load(Ordering::Relaxed)
+    }
+
+    /// Events pushed out of the full queue.
+    pub fn dropped(&self) -> u64 {
+        self.0.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Events of batches given up on.
+    pub fn failed(&self) -> u64 {
+        self.0.failed.load(Ordering::Relaxed)
+    }
+
+    /// Events waiting to be sent.
+    pub fn queued(&self) -> usize {
+        self.0.queue.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+/// Sends events to a webhook, see the module docs. Dropping it sends what is queued and
+/// stops the task doing the sending.
+pub struct WebhookSink {
+    shared: Arc<Shared>,
+    capacity: usize,
+    batch_size: usize,
+    flushes: mpsc::Sender<oneshot::Sender<()>>,
+}
+
+impl WebhookSink {
+    /// Start the task sending the batches; needs a tokio runtime. Errors for a URL or header
+    /// that is not valid.
+    pub fn start(cfg: WebhookConfig) -> io::Result<Self> {
+        let invalid = |what: &str, e: &dyn std::fmt::Display| io::Error::new(io::ErrorKind::InvalidInput, format!("webhook {what}: {e}"));
+        let url = Url::parse(&cfg.url).map_err(|e| invalid("url", &e))?;
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        for (k, v) in &cfg.headers {
+            let name = HeaderName::from_bytes(k.as_bytes()).map_err(|e| invalid("header name", &e))?;
+            headers.insert(name, HeaderValue::from_str(v).map_err(|e| invalid("header value", &e))?);
+        }
+        let client = Client::builder()
+            .default_headers(headers)
+            .timeout(Duration::from_millis(cfg.timeout_ms))
+            .build()
+            .map_err(|e| io::Error::other(format!("webhook client: {e}")))?;
+
+        let shared = Arc::new(Shared {
+            queue: Mutex::default(),
+            wake: Notify::new(),
+            sent: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+        });
+        let (flushes, rx) = mpsc::channel(16);
+        let (capacity, batch_size) = (cfg.queue_capacity.max(1), cfg.batch_size.max(1));
+        let sender = Sender { shared: shared.clone(), client, url, cfg };
+        tokio::spawn(sender.run(rx));
+        Ok(Self { shared, capacity, batch_size, flushes })
+    }
+
+    pub fn stats(&self) -> WebhookStats {
+        WebhookStats(self.shared.clone())
+    }
+
+    /// Queue an event, making room by dropping the oldest one if the queue is full.
+    pub fn push(&self, ev: Value) {
+        let mut queue = self.shared.queue.lock().unwrap_or_else(|e| e.into_inner());
+        if queue.len() >= self.capacity {
+            queue.pop_front();
+            if self.shared.dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                log::warn!("webhook: queue full, dropping the oldest events");
+            }
+        }
+        queue.push_back(ev);
+        if queue.len() >= self.batch_size {
+            self.shared.wake.notify_one();
+        }
+    }
+
+    /// Queue everything received on `rx` until all senders are gone, e.g. a hub's result
+    /// channel or a [`crate::router::Router`] sink, then send what is left.
+    pub fn spawn(mut self, mut rx: mpsc::Receiver<CallbackResult>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            while let Some(ev) = rx.recv().await {
+                self.push(ev);
+            }
+            if let Err(e) = self.flush().await {
+                log::error!("webhook: {e}");
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl EventSink for WebhookSink {
+    async fn write(&mut self, ev: &Value) -> io::Result<()> {
+        self.push(ev.clone());
+        Ok(())
+    }
+
+    /// Send everything queued, returning once it was sent or given up on.
+    async fn flush(&mut self) -> io::Result<()> {
+        let (tx, rx) = oneshot::channel();
+        let stopped = || io::Error::other("webhook sender stopped");
+        self.flushes.send(tx).await.map_err(|_| stopped())?;
+        rx.await.map_err(|_| stopped())
+    }
+}
+
+/// The task sending the batches.
+struct Sender {
+    shared: Arc<Shared>,
+    client: Client,
+    url: Url,
+    cfg: WebhookConfig,
+}
+
+impl Sender {
+    async fn run(self, mut flushes: mpsc::Receiver<oneshot::Sender<()>>) {
+        let batch_size = self.cfg.batch_size.max(1);
+        let period = Duration::from_millis(self.cfg.flush_interval_ms.max(1));
+        let mut tick = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = self.shared.wake.notified() => self.send_while(batch_size).await,
+                _ = tick.tick() => self.send_while(1).await,
+                flush = flushes.recv() => {
+                    self.send_while(1).await;
+                    match flush {
+                        Some(done) => {
+                            let _ = done.send(());
+                        }
+                        // the sink is gone
+                        None => return,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Send batches while at least `min` events are queued.
+    async fn send_while(&self, min: usize) {
+        loop {
+            let batch: Vec<Value> = {
+                let mut queue = self.shared.queue.lock().unwrap_or_else(|e| e.into_inner());
+                if queue.len() < min || queue.is_empty() {
+                    return;
+                }
+                let n = queue.len().min(self.cfg.batch_size.max(1));
+                queue.drain(..n).collect()
+            };
+            let n = batch.len() as u64;
+            if self.post(&batch).await {
+                self.shared.sent.fetch_add(n, Ordering::Relaxed);
+            } else {
+                self.shared.failed.fetch_add(n, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// POST one batch, retrying as configured; false if given up on.
+    async fn post(&self, batch: &[Value]) -> bool {
+        let body = match serde_json::to_vec(batch) {
+            Ok(b) => b,
+            Err(e) => {
+                log::error!("webhook: cannot serialize a batch: {e}");
+                return false;
+            }
+        };
+        let mut backoff = Duration::from_millis(self.cfg.initial_backoff_ms);
+        for attempt in 0..=self.cfg.max_retries {
+            if attempt > 0 {
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(Duration::from_millis(self.cfg.max_backoff_ms));
+            }
+            match self.client.post(self.url.clone()).body(body.clone()).send().await {
+                Ok(r) if r.status().is_success() => return true,
+                Ok(r) if !retryable(r.status()) => {
+                    log::error!("webhook: {} rejected a batch of {}: {}", self.url, batch.len(), r.status());
+                    return false;
+                }
+                Ok(r) => log::warn!("webhook: {} answered {} (attempt {})", self.url, r.status(), attempt + 1),
+                Err(e) => log::warn!("webhook: post to {} failed (attempt {}): {e}", self.url, attempt + 1),
+            }
+        }
+        log::error!("webhook: giving up on a batch of {} after {} attempts", batch.len(), self.cfg.max_retries + 1);
+        false
+    }
+}
+
+fn retryable(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::REQUEST_TIMEOUT || status == StatusCode::TOO_MANY_REQUESTS
+}
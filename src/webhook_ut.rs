@@ -0,0 +1,172 @@
+use crate::{
+    callbacks::CallbackHub,
+    sink::EventSink,
+    webhook::{WebhookConfig, WebhookSink, WebhookStats},
+};
+use axum::{Json, Router, extract::State, http::HeaderMap, http::StatusCode, routing::post};
+use serde_json::{Value, json};
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+
+/// A webhook endpoint recording the batches it accepts, failing the first `fail` requests
+/// with `status`.
+#[derive(Clone)]
+struct Endpoint {
+    batches: Arc<Mutex<Vec<Value>>>,
+    auth: Arc<Mutex<Vec<String>>>,
+    requests: Arc<AtomicU32>,
+    fail: u32,
+    status: StatusCode,
+}
+
+impl Endpoint {
+    fn failing(fail: u32, status: StatusCode) -> Self {
+        Self { batches: Arc::default(), auth: Arc::default(), requests: Arc::default(), fail, status }
+    }
+
+    /// Serve on a free port, returning the URL to post to.
+    async fn serve(&self) -> String {
+        let app = Router::new().route("/hook", post(accept)).with_state(self.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{addr}/hook")
+    }
+
+    fn batches(&self) -> Vec<Value> {
+        self.batches.lock().unwrap().clone()
+    }
+}
+
+/// Wait until the endpoint accepted `n` events.
+async fn sent(stats: &WebhookStats, n: u64) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while stats.sent() < n {
+        assert!(Instant::now() < deadline, "{n} events not sent");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+async fn accept(State(ep): State<Endpoint>, headers: HeaderMap, Json(batch): Json<Value>) -> StatusCode {
+    if ep.requests.fetch_add(1, Ordering::SeqCst) < ep.fail {
+        return ep.status;
+    }
+    let auth = headers.get("authorization").and_then(|v| v.to_str().ok()).unwrap_or("").to_string();
+    ep.auth.lock().unwrap().push(auth);
+    ep.batches.lock().unwrap().push(batch);
+    StatusCode::NO_CONTENT
+}
+
+fn ids(n: std::ops::Range<u32>) -> Value {
+    n.map(|id| json!({ "id": id })).collect()
+}
+
+#[tokio::test]
+async fn batches_go_out_when_full_and_on_the_interval() {
+    let ep = Endpoint::failing(0, StatusCode::OK);
+    let mut cfg = WebhookConfig::new(ep.serve().await);
+    cfg.headers.insert("authorization".into(), "Bearer t0ken".into());
+    // no interval to speak of: only full batches go out
+    (cfg.batch_size, cfg.flush_interval_ms) = (3, 3_600_000);
+    let mut sink = WebhookSink::start(cfg.clone()).unwrap();
+    for id in 0..7 {
+        sink.write(&json!({ "id": id })).await.unwrap();
+    }
+
+    sent(&sink.stats(), 6).await;
+    assert_eq!(ep.batches(), [ids(0..3), ids(3..6)], "two full batches right away");
+    assert_eq!(sink.stats().queued(), 1);
+    assert_eq!(*ep.auth.lock().unwrap(), ["Bearer t0ken"; 2]);
+
+    // the rest on the interval
+    let ep = Endpoint::failing(0, StatusCode::OK);
+    let mut sink = WebhookSink::start(WebhookConfig { url: ep.serve().await, flush_interval_ms: 50, ..cfg }).unwrap();
+    sink.write(&json!({ "id": 6 })).await.unwrap();
+    sent(&sink.stats(), 1).await;
+    assert_eq!(ep.batches(), [ids(6..7)]);
+}
+
+#[tokio::test]
+async fn failed_batches_are_retried_with_backoff() {
+    let ep = Endpoint::failing(2, StatusCode::SERVICE_UNAVAILABLE);
+    let mut cfg = WebhookConfig::new(ep.serve().await);
+    (cfg.initial_backoff_ms, cfg.flush_interval_ms) = (40, 3_600_000);
+    let mut sink = WebhookSink::start(cfg.clone()).unwrap();
+    sink.write(&json!({ "id": 0 })).await.unwrap();
+    sink.write(&json!({ "id": 1 })).await.unwrap();
+    let start = Instant::now();
+    sink.flush().await.unwrap();
+
+    assert!(start.elapsed() >= Duration::from_millis(40 + 80), "{:?}", start.elapsed());
+    assert_eq!(ep.requests.load(Ordering::SeqCst), 3);
+    assert_eq!(ep.batches(), [ids(0..2)]);
+    assert_eq!((sink.stats().sent(), sink.stats().failed()), (2, 0));
+
+    // given up on: rejected outright, or still failing after the retries
+    let ep = Endpoint::failing(u32::MAX, StatusCode::BAD_REQUEST);
+    let mut sink = WebhookSink::start(WebhookConfig { url: ep.serve().await, ..cfg.clone() }).unwrap();
+    sink.write(&json!({ "id": 0 })).await.unwrap();
+    sink.flush().await.unwrap();
+    assert_eq!((ep.requests.load(Ordering::SeqCst), sink.stats().failed()), (1, 1));
+    let ep = Endpoint::failing(u32::MAX, StatusCode::TOO_MANY_REQUESTS);
+    let mut sink = WebhookSink::start(WebhookConfig { url: ep.serve().await, max_retries: 2, initial_backoff_ms: 1, ..cfg }).unwrap();
+    sink.write(&json!({ "id": 0 })).await.unwrap();
+    sink.flush().await.unwrap();
+    assert_eq!((ep.requests.load(Ordering::SeqCst), sink.stats().failed()), (3, 1));
+}
+
+#[tokio::test]
+async fn a_full_queue_drops_the_oldest_events() {
+    let ep = Endpoint::failing(0, StatusCode::OK);
+    let cfg = WebhookConfig { queue_capacity: 4, flush_interval_ms: 3_600_000, ..WebhookConfig::new(ep.serve().await) };
+    let mut sink = WebhookSink::start(cfg).unwrap();
+    for id in 0..6 {
+        sink.write(&json!({ "id": id })).await.unwrap();
+    }
+    let stats = sink.stats();
+    assert_eq!((stats.dropped(), stats.queued()), (2, 4));
+    sink.flush().await.unwrap();
+    assert_eq!(ep.batches(), [ids(2..6)]);
+    assert_eq!(stats.sent(), 4);
+}
+
+#[tokio::test]
+async fn callback_results_are_sent_until_the_channel_closes() {
+    let ep = Endpoint::failing(0, StatusCode::OK);
+    let sink = WebhookSink::start(WebhookConfig { flush_interval_ms: 3_600_000, ..WebhookConfig::new(ep.serve().await) }).unwrap();
+    let (tx, rx) = mpsc::channel(16);
+    let task = sink.spawn(rx);
+    let mut hub = CallbackHub::<u32>::new();
+    hub.set_result_channel(tx);
+    hub.add_fn(0b1, |n: &u32| {
+        let n = *n;
+        async move { Some(json!({ "id": n })) }
+    });
+    for n in 0..3 {
+        hub.fire(0b1, &n).await;
+    }
+    drop(hub);
+    task.await.unwrap();
+    assert_eq!(ep.batches(), [ids(0..3)]);
+}
+
+#[test]
+fn config_takes_units_and_checks_the_url() {
+    let cfg: WebhookConfig =
+        serde_json::from_value(json!({ "url": "http://127.0.0.1/x", "flush_interval_ms": "5s", "max_backoff_ms": "1m" })).unwrap();
+    assert_eq!((cfg.flush_interval_ms, cfg.max_backoff_ms, cfg.batch_size, cfg.max_retries), (5000, 60_000, 100, 8));
+    assert!(serde_json::from_value::<WebhookConfig>(json!({ "url": "http://x", "retries": 3 })).is_err());
+
+    let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let _guard = rt.enter();
+    assert!(WebhookSink::start(WebhookConfig::new("not a url")).is_err());
+    let mut cfg = WebhookConfig::new("http://127.0.0.1/x");
+    cfg.headers.insert("bad header".into(), "x".into());
+    assert!(WebhookSink::start(cfg).is_err());
+}
@@ -0,0 +1,28 @@
+use omnitrace_core::{callbacks::Filtered, sensor};
+use xmount::demo::{self, DemoConfig, JsonCb};
+use xmount::prelude::*;
+
+/// The filesystem type of the mount an event is about, if it carries one.
+fn fstype(ev: &XMountEvent) -> Option<&str> {
+    match ev {
+        XMountEvent::Mounted { info, .. } | XMountEvent::WillUnmount { info, .. } | XMountEvent::AutomountArmed { info, .. } => Some(&info.fstype),
+        XMountEvent::Unmounted { last, .. } => Some(&last.fstype),
+        XMountEvent::Changed { new, .. } => Some(&new.fstype),
+        _ => None,
+    }
+}
+
+// Only mounts of one filesystem type, e.g. NFS shares coming and going:
+// XMOUNT_FSTYPE=nfs4 XMOUNT_TARGETS=/mnt/share cargo run -p xmount --example fstype
+#[tokio::main]
+async fn main() {
+    let x = demo::sensor(&DemoConfig::from_env());
+    let want = std::env::var("XMOUNT_FSTYPE").unwrap_or_else(|_| "nfs".into());
+
+    let mut hub = CallbackHub::<XMountEvent>::new();
+    hub.add(Filtered::new(JsonCb, move |ev: &XMountEvent| fstype(ev) == Some(want.as_str())));
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    sensor::run_until(x, hub, ctrl_c, |r| println!("RESULT: {r}")).await;
+}
@@ -0,0 +1,23 @@
+use omnitrace_core::{
+    filter::Filter,
+    preflight::{self, PreflightArgs},
+};
+use xmount::demo::{self, DemoConfig};
+use xmount::prelude::*;
+
+// XMOUNT_MOUNTINFO=/proc/self/mountinfo XMOUNT_TARGETS=/mnt/usb cargo run -p xmount --example xmount
+#[tokio::main]
+async fn main() {
+    let x = demo::sensor(&DemoConfig::from_env());
+    preflight::gate("xmount", &x, PreflightArgs::from_env_args()).await;
+
+    let mut hub = CallbackHub::<XMountEvent>::new();
+    if let Some(f) = Filter::from_env_args() {
+        hub.set_filter(f.predicate());
+    }
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+        println!("\nShutting down on Ctrl-C...");
+    };
+    demo::run(x, hub, ctrl_c).await;
+}
@@ -0,0 +1,79 @@
+use crate::{
+    events::{MountClass, MountInfo},
+    ignore::PSEUDO_FSTYPES,
+};
+use globset::{Glob, GlobMatcher};
+use std::path::Path;
+
+/// Mount point prefixes owned by container runtimes (overlay roots, sandbox shm, etc.).
+const CONTAINER_PREFIXES: &[&str] = &["/var/lib/docker/", "/var/lib/containerd/", "/run/containerd/", "/var/lib/containers/", "/run/containers/"];
+
+/// Kubelet places every pod volume (projected, secret, empty-dir, CSI) under this prefix.
+const KUBELET_PREFIX: &str = "/var/lib/kubelet/pods/";
+
+const TMPFS_TYPES: &[&str] = &["tmpfs", "ramfs"];
+
+const NETWORK_TYPES: &[&str] =
+    &["nfs", "nfs4", "cifs", "smb3", "smbfs", "ceph", "glusterfs", "9p", "afs", "fuse.sshfs", "fuse.glusterfs", "fuse.rclone"];
+
+/// Placeholder of an automount, a pseudo filesystem though not ignored with the others.
+const AUTOFS: &str = "autofs";
+
+/// Source of a local Windows volume (volume GUID path).
+const WINDOWS_VOLUME_PREFIX: &str = "\\\\?\\Volume{";
+
+/// A FUSE filesystem type: `fuse`, `fuseblk`, or `fuse.<daemon>` as mounted by sshfs, rclone,
+/// gvfs and the like.
+pub fn is_fuse(fstype: &str) -> bool {
+    matches!(fstype, "fuse" | "fuseblk") || fstype.starts_with("fuse.")
+}
+
+/// `\\server\share`, the source of a mapped Windows network drive.
+fn is_unc(source: &str) -> bool {
+    source.starts_with("\\\\") && !source.starts_with("\\\\?\\")
+}
+
+/// Derives a [`MountClass`] from fstype, source and mount point heuristics.
+///
+/// User rules (mount point glob → class) are checked first, in the order they were added.
+#[derive(Clone, Default)]
+pub struct MountClassifier {
+    rules: Vec<(GlobMatcher, MountClass)>,
+}
+
+impl MountClassifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Classify mount points matching `glob` as `class`.
+    pub fn rule(&mut self, glob: &str, class: MountClass) -> Result<(), globset::Error> {
+        self.rules.push((Glob::new(glob)?.compile_matcher(), class));
+        Ok(())
+    }
+
+    pub fn classify(&self, mi: &MountInfo) -> MountClass {
+        if let Some((_, class)) = self.rules.iter().find(|(p, _)| p.is_match(&mi.mount_point)) {
+            return *class;
+        }
+
+        let under = |prefix: &str| mi.mount_point.starts_with(Path::new(prefix));
+        let fstype = mi.fstype.as_str();
+
+        if under(KUBELET_PREFIX) {
+            MountClass::KubeletVolume
+        } else if CONTAINER_PREFIXES.iter().any(|p| under(p)) {
+            MountClass::ContainerOverlay
+        } else if TMPFS_TYPES.contains(&fstype) {
+            MountClass::Tmpfs
+        } else if NETWORK_TYPES.contains(&fstype) || is_unc(&mi.source) {
+            MountClass::NetworkFs
+        } else if fstype == AUTOFS || PSEUDO_FSTYPES.contains(&fstype) {
+            MountClass::Pseudo
+        } else if mi.source.starts_with("/dev/") || mi.source.starts_with(WINDOWS_VOLUME_PREFIX) {
+            MountClass::BlockDevice
+        } else {
+            MountClass::Other
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! The wiring of `examples/xmount.rs` as functions a program or a test can call: the
+//! sensor configured from the environment, a callback printing events (see
+//! [`omnitrace_core::format`]), and [`run`].
+//!
+//! - `XMOUNT_MOUNTINFO`: the mount table to read (default: the bundled
+//!   `fixtures/workstation.mountinfo`, `/proc/self/mountinfo` watches this machine)
+//! - `XMOUNT_TARGETS`: mountpoints to watch, comma separated (default:
+//!   `/mnt/nas,/media/alice/USBSTICK`, both in the fixture)
+//! - `XMOUNT_PULSE_MS`: the pulse (default: 500)
+//!
+//! Edit a copy of the fixture while the example runs to see events.
+
+use crate::events::{XMountEvent, XMountMask};
+use crate::health::BtrfsSysfs;
+use crate::{XMount, XMountConfig};
+use async_trait::async_trait;
+use omnitrace_core::{
+    callbacks::{Callback, CallbackHub, CallbackResult},
+    format::Style,
+    sensor,
+};
+use serde_json::json;
+use std::{future::Future, path::PathBuf, time::Duration};
+
+/// Where the example reads from and what it watches, see the [module docs](self).
+#[derive(Clone, Debug)]
+pub struct DemoConfig {
+    pub mountinfo: PathBuf,
+    pub targets: Vec<PathBuf>,
+    pub pulse: Duration,
+}
+
+impl Default for DemoConfig {
+    fn default() -> Self {
+        Self {
+            mountinfo: PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/workstation.mountinfo")),
+            targets: vec![PathBuf::from("/mnt/nas"), PathBuf::from("/media/alice/USBSTICK")],
+            pulse: Duration::from_millis(500),
+        }
+    }
+}
+
+impl DemoConfig {
+    /// The defaults, overridden by the variables that are set.
+    pub fn from_env() -> Self {
+        let var = |name| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let mut cfg = Self::default();
+        if let Some(p) = var("XMOUNT_MOUNTINFO") {
+            cfg.mountinfo = PathBuf::from(p);
+        }
+        if let Some(t) = var("XMOUNT_TARGETS") {
+            cfg.targets = t.split(',').map(str::trim).filter(|t| !t.is_empty()).map(PathBuf::from).collect();
+        }
+        if let Some(ms) = var("XMOUNT_PULSE_MS").and_then(|v| v.parse().ok()) {
+            cfg.pulse = Duration::from_millis(ms);
+        }
+        cfg
+    }
+}
+
+/// The sensor as configured, with the btrfs health probe.
+pub fn sensor(cfg: &DemoConfig) -> XMount {
+    let mut x = XMount::new(XMountConfig::default().pulse(cfg.pulse).mountinfo_path(&cfg.mountinfo));
+    for t in &cfg.targets {
+        x.add(t);
+    }
+    x.add_health_probe(BtrfsSysfs::new());
+    x
+}
+
+/// Prints every event and returns it as JSON.
+pub struct JsonCb;
+
+#[async_trait]
+impl Callback<XMountEvent> for JsonCb {
+    fn mask(&self) -> u64 {
+        XMountMask::all().bits()
+    }
+
+    async fn call(&self, ev: &XMountEvent) -> Option<CallbackResult> {
+        println!("{}", Style::for_stdout().short(ev));
+        match ev {
+            XMountEvent::Mounted { target, info, .. } => Some(json!({
+                "event": "mounted",
+                "target": target.to_string_lossy().to_string(),
+                "source": info.source,
+                "fstype": info.fstype,
+                "opts": info.mount_opts,
+                "class": info.class,
+            })),
+            XMountEvent::Unmounted { target, last, reason, .. } => Some(json!({
+                "event": "unmounted",
+                "target": target.to_string_lossy().to_string(),
+                "last_source": last.source,
+                "last_fstype": last.fstype,
+                "reason": reason,
+            })),
+            XMountEvent::Changed { target, old, new, .. } => Some(json!({
+                "event": "changed",
+                "target": target.to_string_lossy().to_string(),
+                "old": { "source": old.source, "fstype": old.fstype, "opts": old.mount_opts },
+                "new": { "source": new.source, "fstype": new.fstype, "opts": new.mount_opts },
+            })),
+            XMountEvent::WillUnmount { target, info, reason, .. } => Some(json!({
+                "event": "will_unmount",
+                "target": target.to_string_lossy().to_string(),
+                "source": info.source,
+                "reason": reason,
+            })),
+            XMountEvent::AutomountArmed { target, info, .. } => Some(json!({
+                "event": "automount_armed",
+                "target": target.to_string_lossy().to_string(),
+                "source": info.source,
+            })),
+            XMountEvent::FsHealthChanged { target, fstype, old, new, details, .. } => Some(json!({
+                "event": "fs_health_changed",
+                "target": target.to_string_lossy().to_string(),
+                "fstype": fstype,
+                "old": old,
+                "new": new,
+                "details": details,
+            })),
+            XMountEvent::Deviation { target, deviation, .. } => Some(json!({
+                "event": "deviation",
+                "target": target.to_string_lossy().to_string(),
+                "deviation": deviation,
+            })),
+            XMountEvent::ParseAnomaly { anomaly, .. } => Some(json!({
+                "event": "parse_anomaly",
+                "source": anomaly.source.to_string_lossy().to_string(),
+                "raw": anomaly.raw,
+                "column": anomaly.column,
+                "reason": anomaly.reason,
+            })),
+        }
+    }
+}
+
+/// Run `sensor` with [`JsonCb`] added to `hub`, printing the results, until `stop`
+/// resolves or the sensor ends, see [`sensor::run_until`].
+pub async fn run<F: Future>(sensor: XMount, mut hub: CallbackHub<XMountEvent>, stop: F) -> bool {
+    hub.add(JsonCb);
+    sensor::run_until(sensor, hub, stop, |r| println!("RESULT: {r}")).await
+}
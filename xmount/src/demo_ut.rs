@@ -0,0 +1,66 @@
+use crate::{
+    demo::{self, DemoConfig},
+    events::{XMountEvent, XMountMask},
+};
+use async_trait::async_trait;
+use omnitrace_core::callbacks::{Callback, CallbackHub, CallbackResult};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+struct Recorder(Arc<Mutex<Vec<XMountEvent>>>);
+
+#[async_trait]
+impl Callback<XMountEvent> for Recorder {
+    fn mask(&self) -> u64 {
+        XMountMask::all().bits()
+    }
+
+    async fn call(&self, ev: &XMountEvent) -> Option<CallbackResult> {
+        self.0.lock().unwrap().push(ev.clone());
+        None
+    }
+}
+
+#[test]
+fn defaults_read_the_bundled_fixture() {
+    let cfg = DemoConfig::default();
+    let mounts = std::fs::read_to_string(&cfg.mountinfo).unwrap();
+    for t in &cfg.targets {
+        assert!(mounts.contains(&format!(" {} ", t.display())), "{} not in the fixture", t.display());
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn example_wiring_reports_an_unmount_and_stops_in_time() {
+    let mountinfo = std::env::temp_dir().join(format!("xmount-demo-ut-{}.mountinfo", std::process::id()));
+    let fixture = std::fs::read_to_string(DemoConfig::default().mountinfo).unwrap();
+    std::fs::write(&mountinfo, &fixture).unwrap();
+
+    let cfg = DemoConfig { mountinfo: mountinfo.clone(), targets: vec![PathBuf::from("/mnt/nas")], pulse: Duration::from_millis(10) };
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(seen.clone()));
+
+    let path = mountinfo.clone();
+    let stop = async move {
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let without: String = fixture.lines().filter(|l| !l.contains(" /mnt/nas ")).map(|l| format!("{l}\n")).collect();
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, without).unwrap();
+        std::fs::rename(&tmp, &path).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    };
+    let stopped = tokio::time::timeout(Duration::from_secs(5), demo::run(demo::sensor(&cfg), hub, stop)).await;
+    let _ = std::fs::remove_file(&mountinfo);
+
+    assert!(stopped.expect("shut down in time"));
+    let seen = seen.lock().unwrap();
+    assert!(
+        seen.iter().any(|ev| matches!(ev, XMountEvent::Unmounted { target, .. } if target.as_path() == std::path::Path::new("/mnt/nas"))),
+        "{seen:?}"
+    );
+}
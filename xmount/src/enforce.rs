@@ -0,0 +1,354 @@
+//! Enforcement: act on mounts that violate a policy.
+//!
+//! For kiosks and appliances, [`EnforcementCallback`] unmounts (or remounts read-only) what
+//! xmount reports as `Mounted` when it is not on the allowlist or has a denied fstype:
+//!
+//! ```ignore
+//! let enforce = EnforcementCallback::new(EnforcementAction::Unmount)
+//!     .allow_target("/")?
+//!     .allow_target("/boot/**")?
+//!     .deny_fstype("vfat")?
+//!     .deny_fstype("exfat")?
+//!     .enable_enforcement(true);
+//! hub.add(enforce);
+//! ```
+//!
+//! Nothing is touched unless [`EnforcementCallback::enable_enforcement`] was set; before that,
+//! and in [`EnforcementCallback::dry_run`] mode, violations are only reported. Every decision
+//! is returned as an `{"Enforcement": {...}}` record with the attempted operations and their
+//! outcome, errors (EBUSY, EPERM) included.
+
+use crate::events::{MountInfo, XMountEvent, XMountMask};
+use async_trait::async_trait;
+use globset::{Glob, GlobMatcher};
+use omnitrace_core::{
+    callbacks::{Callback, CallbackResult},
+    clock::{self, SharedClock},
+    deadline::DeadlineSet,
+};
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// Variant name of the audit records.
+pub const ENFORCEMENT: &str = "Enforcement";
+
+/// What to do with a violating mount.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnforcementAction {
+    /// `umount2`, retried with `MNT_DETACH` if the mount is busy.
+    Unmount,
+    RemountReadOnly,
+}
+
+/// Why a mount violates the policy.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Violation {
+    DeniedFstype(String),
+    /// Neither target nor source is on the allowlist.
+    NotAllowed,
+}
+
+/// A syscall the callback ran or would run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnforcementOp {
+    Umount,
+    UmountDetach,
+    RemountReadOnly,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnforcementOutcome {
+    /// Enforcement is not enabled, nothing was attempted.
+    Disabled,
+    DryRun,
+    /// The target was acted on less than the cooldown ago.
+    Cooldown,
+    Done,
+    Failed,
+    /// The last operation did not return in time. It is left running and may still take
+    /// effect, so whether the target is still mounted is not known.
+    TimedOut,
+}
+
+/// The error of an attempt that timed out.
+const TIMED_OUT: &str = "timed out, outcome unknown";
+
+/// One attempted operation and its result.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Attempt {
+    pub op: EnforcementOp,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errno: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Attempt {
+    fn of(op: EnforcementOp, r: &io::Result<()>) -> Self {
+        match r {
+            Ok(()) => Self { op, ok: true, errno: None, error: None },
+            Err(e) => Self { op, ok: false, errno: e.raw_os_error(), error: Some(e.to_string()) },
+        }
+    }
+}
+
+/// Audit record of one decision, sent as `{"Enforcement": record}`.
+#[derive(Clone, Debug, Serialize)]
+pub struct EnforcementRecord {
+    #[serde(with = "omnitrace_core::paths")]
+    pub target: PathBuf,
+    pub source: String,
+    pub fstype: String,
+    pub violation: Violation,
+    pub action: EnforcementAction,
+    pub outcome: EnforcementOutcome,
+    pub attempts: Vec<Attempt>,
+    pub at: f64,
+}
+
+type Ops = Arc<dyn Fn(EnforcementOp, &Path) -> io::Result<()> + Send + Sync>;
+
+/// Callback unmounting or remounting read-only the mounts that violate its policy.
+/// See the module docs.
+pub struct EnforcementCallback {
+    action: EnforcementAction,
+    allow_targets: Vec<GlobMatcher>,
+    allow_sources: Vec<GlobMatcher>,
+    deny_fstypes: Vec<GlobMatcher>,
+    enabled: bool,
+    dry_run: bool,
+    cooldown: Duration,
+    timeout: Duration,
+    clock: SharedClock,
+    ops: Ops,
+    // targets acted on within the cooldown
+    cooling: Mutex<DeadlineSet<PathBuf>>,
+}
+
+impl EnforcementCallback {
+    /// Policy with empty lists: nothing violates it until rules are added.
+    pub fn new(action: EnforcementAction) -> Self {
+        Self {
+            action,
+            allow_targets: Vec::new(),
+            allow_sources: Vec::new(),
+            deny_fstypes: Vec::new(),
+            enabled: false,
+            dry_run: false,
+            cooldown: Duration::from_secs(30),
+            timeout: Duration::from_secs(5),
+            clock: clock::system(),
+            ops: Arc::new(run_op),
+            cooling: Mutex::new(DeadlineSet::new(clock::system())),
+        }
+    }
+
+    /// Allow mounts on targets matching `glob`. Once any allow rule exists, mounts matching
+    /// none of them violate the policy.
+    pub fn allow_target(mut self, glob: &str) -> Result<Self, globset::Error> {
+        self.allow_targets.push(Glob::new(glob)?.compile_matcher());
+        Ok(self)
+    }
+
+    /// Allow mounts whose source (device, share) matches `glob`.
+    pub fn allow_source(mut self, glob: &str) -> Result<Self, globset::Error> {
+        self.allow_sources.push(Glob::new(glob)?.compile_matcher());
+        Ok(self)
+    }
+
+    /// Mounts with an fstype matching `glob` violate the policy even when allowed.
+    pub fn deny_fstype(mut self, glob: &str) -> Result<Self, globset::Error> {
+        self.deny_fstypes.push(Glob::new(glob)?.compile_matcher());
+        Ok(self)
+    }
+
+    /// Actually run the action. Off by default: violations are only reported.
+    pub fn enable_enforcement(mut self, on: bool) -> Self {
+        self.enabled = on;
+        self
+    }
+
+    /// Report what would be done without doing it.
+    pub fn dry_run(mut self, on: bool) -> Self {
+        self.dry_run = on;
+        self
+    }
+
+    /// Don't act on the same target again within `cooldown` (default 30s), so a mount that
+    /// keeps coming back does not turn into a loop of syscalls.
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Give up waiting for a syscall after `timeout` (default 5s), e.g. on a hung NFS server.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Read the time from `clock` instead of the system clock, see [`omnitrace_core::clock`].
+    pub fn clock(mut self, clock: SharedClock) -> Self {
+        self.cooling = Mutex::new(DeadlineSet::new(clock.clone()));
+        self.clock = clock;
+        self
+    }
+
+    #[cfg(test)]
+    pub(crate) fn ops<F: Fn(EnforcementOp, &Path) -> io::Result<()> + Send + Sync + 'static>(mut self, f: F) -> Self {
+        self.ops = Arc::new(f);
+        self
+    }
+
+    /// Whether `mi` violates the policy, and why.
+    pub fn violation(&self, mi: &MountInfo) -> Option<Violation> {
+        if self.deny_fstypes.iter().any(|g| g.is_match(&mi.fstype)) {
+            return Some(Violation::DeniedFstype(mi.fstype.to_string()));
+        }
+        if self.allow_targets.is_empty() && self.allow_sources.is_empty() {
+            return None;
+        }
+        let allowed = self.allow_targets.iter().any(|g| g.is_match(&mi.mount_point)) || self.allow_sources.iter().any(|g| g.is_match(&mi.source));
+        (!allowed).then_some(Violation::NotAllowed)
+    }
+
+    /// Reserve `target` for an action, unless it was acted on within the cooldown.
+    fn take_slot(&self, target: &Path) -> bool {
+        let mut cooling = self.cooling.lock().unwrap_or_else(|e| e.into_inner());
+        cooling.poll_expired();
+        cooling.arm(target.to_path_buf(), self.cooldown)
+    }
+
+    /// Run `op` on a blocking thread, within the timeout.
+    async fn attempt(&self, op: EnforcementOp, target: &Path) -> Result<Attempt, EnforcementOp> {
+        let (ops, path) = (self.ops.clone(), target.to_path_buf());
+        match tokio::time::timeout(self.timeout, tokio::task::spawn_blocking(move || ops(op, &path))).await {
+            Ok(Ok(r)) => Ok(Attempt::of(op, &r)),
+            Ok(Err(e)) => Ok(Attempt::of(op, &Err(io::Error::other(e)))),
+            Err(_) => Err(op),
+        }
+    }
+
+    async fn enforce(&self, mi: &MountInfo) -> (EnforcementOutcome, Vec<Attempt>) {
+        if !self.enabled {
+            return (EnforcementOutcome::Disabled, Vec::new());
+        }
+        if !self.take_slot(&mi.mount_point) {
+            return (EnforcementOutcome::Cooldown, Vec::new());
+        }
+        if self.dry_run {
+            return (EnforcementOutcome::DryRun, Vec::new());
+        }
+
+        let mut attempts = Vec::new();
+        let first = match self.action {
+            EnforcementAction::Unmount => EnforcementOp::Umount,
+            EnforcementAction::RemountReadOnly => EnforcementOp::RemountReadOnly,
+        };
+        let mut next = Some(first);
+        while let Some(op) = next.take() {
+            let a = match self.attempt(op, &mi.mount_point).await {
+                Ok(a) => a,
+                Err(op) => {
+                    attempts.push(Attempt { op, ok: false, errno: None, error: Some(TIMED_OUT.to_string()) });
+                    return (EnforcementOutcome::TimedOut, attempts);
+                }
+            };
+            if op == EnforcementOp::Umount && a.errno == Some(libc::EBUSY) {
+                next = Some(EnforcementOp::UmountDetach);
+            }
+            attempts.push(a);
+        }
+
+        let ok = attempts.last().is_some_and(|a| a.ok);
+        (if ok { EnforcementOutcome::Done } else { EnforcementOutcome::Failed }, attempts)
+    }
+}
+
+#[async_trait]
+impl Callback<XMountEvent> for EnforcementCallback {
+    fn mask(&self) -> u64 {
+        XMountMask::MOUNTED.bits()
+    }
+
+    async fn call(&self, ev: &XMountEvent) -> Option<CallbackResult> {
+        let XMountEvent::Mounted { target, info, .. } = ev else {
+            return None;
+        };
+        let violation = self.violation(info)?;
+        let (outcome, attempts) = self.enforce(info).await;
+        match outcome {
+            EnforcementOutcome::Done => log::warn!("enforce: {:?} on {} ({violation:?})", self.action, target.display()),
+            EnforcementOutcome::Failed | EnforcementOutcome::TimedOut => {
+                log::error!("enforce: {:?} on {} {outcome:?}: {attempts:?}", self.action, target.display())
+            }
+            _ => log::info!("enforce: {} violates the policy ({violation:?}), {outcome:?}", target.display()),
+        }
+
+        let at = self.clock.now_system().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0);
+        let record = EnforcementRecord {
+            target: target.clone(),
+            source: info.source.to_string(),
+            fstype: info.fstype.to_string(),
+            violation,
+            action: self.action,
+            outcome,
+            attempts,
+            at,
+        };
+        Some(json!({ ENFORCEMENT: record }))
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn run_op(op: EnforcementOp, target: &Path) -> io::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(target.as_os_str().as_bytes()).map_err(io::Error::other)?;
+    let rc = match op {
+        EnforcementOp::Umount => unsafe { libc::umount2(path.as_ptr(), 0) },
+        EnforcementOp::UmountDetach => unsafe { libc::umount2(path.as_ptr(), libc::MNT_DETACH) },
+        EnforcementOp::RemountReadOnly => {
+            // a remount resets the per-mount flags it is not given, nosuid and noexec included
+            let flags = libc::MS_REMOUNT | libc::MS_RDONLY | mount_flags(&path)?;
+            unsafe { libc::mount(std::ptr::null(), path.as_ptr(), std::ptr::null(), flags, std::ptr::null()) }
+        }
+    };
+    if rc == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+/// The per-mount `MS_*` flags the mount at `path` has now.
+#[cfg(target_os = "linux")]
+fn mount_flags(path: &std::ffi::CStr) -> io::Result<libc::c_ulong> {
+    const KEPT: [(libc::c_ulong, libc::c_ulong); 7] = [
+        (libc::ST_NOSUID, libc::MS_NOSUID),
+        (libc::ST_NODEV, libc::MS_NODEV),
+        (libc::ST_NOEXEC, libc::MS_NOEXEC),
+        (libc::ST_SYNCHRONOUS, libc::MS_SYNCHRONOUS),
+        (libc::ST_MANDLOCK, libc::MS_MANDLOCK),
+        (libc::ST_NOATIME, libc::MS_NOATIME),
+        (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+    ];
+    let mut st = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    if unsafe { libc::statvfs(path.as_ptr(), st.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let f_flag = unsafe { st.assume_init() }.f_flag;
+    Ok(KEPT.iter().filter(|(st, _)| f_flag & st != 0).fold(0, |flags, (_, ms)| flags | ms))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn run_op(_op: EnforcementOp, _target: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "mount enforcement is only implemented on Linux"))
+}
@@ -0,0 +1,159 @@
+use crate::{
+    enforce::{EnforcementAction, EnforcementCallback, EnforcementOp, Violation},
+    events::{MountInfo, XMountEvent},
+};
+use omnitrace_core::{callbacks::Callback, clock::ManualClock, labels::Labels};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+fn usb(target: &str, fstype: &str) -> MountInfo {
+    MountInfo { fstype: fstype.into(), source: "/dev/sdb1".into(), ..MountInfo::test(target) }
+}
+
+fn mounted(mi: MountInfo) -> XMountEvent {
+    XMountEvent::Mounted { target: mi.mount_point.clone(), info: mi, labels: Labels::default() }
+}
+
+fn policy(action: EnforcementAction) -> EnforcementCallback {
+    EnforcementCallback::new(action)
+        .allow_target("/")
+        .unwrap()
+        .allow_target("/boot/**")
+        .unwrap()
+        .deny_fstype("vfat")
+        .unwrap()
+        .deny_fstype("exfat")
+        .unwrap()
+}
+
+type Calls = Arc<Mutex<Vec<(EnforcementOp, PathBuf)>>>;
+
+/// Records the operations instead of running them; `fail` decides their errors.
+fn recording(cb: EnforcementCallback, fail: fn(EnforcementOp) -> Option<i32>) -> (EnforcementCallback, Calls) {
+    let calls = Calls::default();
+    let seen = calls.clone();
+    let cb = cb.ops(move |op, path: &Path| {
+        seen.lock().unwrap().push((op, path.to_path_buf()));
+        fail(op).map_or(Ok(()), |errno| Err(io::Error::from_raw_os_error(errno)))
+    });
+    (cb, calls)
+}
+
+#[test]
+fn policy_decides_violations() {
+    let cb = policy(EnforcementAction::Unmount);
+    assert_eq!(cb.violation(&usb("/", "ext4")), None);
+    assert_eq!(cb.violation(&usb("/boot/efi", "ext4")), None);
+    assert_eq!(cb.violation(&usb("/boot/efi", "vfat")), Some(Violation::DeniedFstype("vfat".to_string())));
+    assert_eq!(cb.violation(&usb("/media/stick", "ext4")), Some(Violation::NotAllowed));
+
+    let by_source = EnforcementCallback::new(EnforcementAction::Unmount).allow_source("/dev/nvme*").unwrap();
+    assert_eq!(by_source.violation(&MountInfo { source: "/dev/nvme0n1p2".into(), ..MountInfo::test("/data") }), None);
+    assert_eq!(by_source.violation(&usb("/data", "ext4")), Some(Violation::NotAllowed));
+
+    // no rules, no violations
+    assert_eq!(EnforcementCallback::new(EnforcementAction::Unmount).violation(&usb("/media/stick", "vfat")), None);
+    assert!(EnforcementCallback::new(EnforcementAction::Unmount).allow_target("[").is_err());
+}
+
+#[tokio::test]
+async fn nothing_is_touched_unless_enabled_and_not_dry_run() {
+    let (cb, calls) = recording(policy(EnforcementAction::Unmount), |_| None);
+    let rec = cb.call(&mounted(usb("/media/stick", "ext4"))).await.unwrap();
+    assert_eq!(rec["Enforcement"]["outcome"], "disabled");
+    assert_eq!(rec["Enforcement"]["violation"], "not_allowed");
+
+    let (cb, calls2) = recording(policy(EnforcementAction::Unmount).enable_enforcement(true).dry_run(true), |_| None);
+    let rec = cb.call(&mounted(usb("/media/stick", "vfat"))).await.unwrap();
+    assert_eq!(rec["Enforcement"]["outcome"], "dry_run");
+    assert_eq!(rec["Enforcement"]["violation"]["denied_fstype"], "vfat");
+
+    assert!(cb.call(&mounted(usb("/boot/efi", "ext4"))).await.is_none());
+    assert!(calls.lock().unwrap().is_empty() && calls2.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn busy_unmount_falls_back_to_detach() {
+    let (cb, calls) =
+        recording(policy(EnforcementAction::Unmount).enable_enforcement(true), |op| (op == EnforcementOp::Umount).then_some(libc::EBUSY));
+    let rec = cb.call(&mounted(usb("/media/stick", "ext4"))).await.unwrap();
+    let rec = &rec["Enforcement"];
+    assert_eq!(rec["outcome"], "done");
+    assert_eq!(rec["action"], "unmount");
+    assert_eq!((rec["attempts"][0]["op"].as_str(), rec["attempts"][0]["errno"].as_i64()), (Some("umount"), Some(libc::EBUSY as i64)));
+    assert_eq!((rec["attempts"][1]["op"].as_str(), rec["attempts"][1]["ok"].as_bool()), (Some("umount_detach"), Some(true)));
+    let ops: Vec<EnforcementOp> = calls.lock().unwrap().iter().map(|(op, _)| *op).collect();
+    assert_eq!(ops, [EnforcementOp::Umount, EnforcementOp::UmountDetach]);
+}
+
+#[tokio::test]
+async fn failures_are_reported_and_cooldown_applies() {
+    let clock = ManualClock::new();
+    let (cb, calls) = recording(
+        policy(EnforcementAction::RemountReadOnly).enable_enforcement(true).cooldown(Duration::from_secs(30)).clock(clock.shared()),
+        |_| Some(libc::EPERM),
+    );
+    let ev = mounted(usb("/media/stick", "ext4"));
+
+    let rec = cb.call(&ev).await.unwrap();
+    assert_eq!(rec["Enforcement"]["outcome"], "failed");
+    assert_eq!(rec["Enforcement"]["attempts"][0]["op"], "remount_read_only");
+    assert_eq!(rec["Enforcement"]["attempts"][0]["errno"], libc::EPERM);
+
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(cb.call(&ev).await.unwrap()["Enforcement"]["outcome"], "cooldown");
+    clock.advance(Duration::from_secs(21));
+    assert_eq!(cb.call(&ev).await.unwrap()["Enforcement"]["outcome"], "failed");
+    assert_eq!(calls.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn hung_syscalls_time_out() {
+    let cb = policy(EnforcementAction::Unmount).enable_enforcement(true).timeout(Duration::from_millis(20)).ops(|_, _: &Path| {
+        std::thread::sleep(Duration::from_millis(200));
+        Ok(())
+    });
+    let rec = cb.call(&mounted(usb("/mnt/nfs", "ext4"))).await.unwrap();
+    assert_eq!(rec["Enforcement"]["outcome"], "timed_out");
+    assert_eq!(rec["Enforcement"]["attempts"][0]["error"], "timed out, outcome unknown");
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+#[ignore = "mounts a tmpfs, needs root; run with --ignored"]
+async fn unmounts_a_real_tmpfs() {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    if unsafe { libc::geteuid() } != 0 {
+        return;
+    }
+    let dir = std::env::temp_dir().join(format!("xmount-enforce-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (src, target, fstype) = (c"omnitrace", CString::new(dir.as_os_str().as_bytes()).unwrap(), c"tmpfs");
+    let flags = libc::MS_NOEXEC | libc::MS_NOSUID;
+    let rc = unsafe { libc::mount(src.as_ptr(), target.as_ptr(), fstype.as_ptr(), flags, std::ptr::null()) };
+    assert_eq!(rc, 0, "mount tmpfs: {}", io::Error::last_os_error());
+    let info = MountInfo { fstype: "tmpfs".into(), source: "omnitrace".into(), ..MountInfo::test(&dir) };
+
+    // read-only, still noexec and nosuid
+    let cb = EnforcementCallback::new(EnforcementAction::RemountReadOnly).deny_fstype("tmpfs").unwrap().enable_enforcement(true);
+    let remounted = cb.call(&mounted(info.clone())).await.unwrap();
+    let mut st = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    assert_eq!(unsafe { libc::statvfs(target.as_ptr(), st.as_mut_ptr()) }, 0);
+    let f_flag = unsafe { st.assume_init() }.f_flag;
+
+    let cb = EnforcementCallback::new(EnforcementAction::Unmount).deny_fstype("tmpfs").unwrap().enable_enforcement(true);
+    let rec = cb.call(&mounted(info)).await.unwrap();
+    let mounts = std::fs::read_to_string("/proc/self/mountinfo").unwrap();
+    let _ = std::fs::remove_dir(&dir);
+
+    assert_eq!(remounted["Enforcement"]["outcome"], "done", "{remounted}");
+    let want = libc::ST_RDONLY | libc::ST_NOEXEC | libc::ST_NOSUID;
+    assert_eq!(f_flag & want, want, "flags after the remount: {f_flag:#x}");
+    assert_eq!(rec["Enforcement"]["outcome"], "done", "{rec}");
+    assert!(!mounts.contains(&format!(" {} ", dir.display())));
+}
@@ -0,0 +1,464 @@
+//! Mount change detection without a runtime: parse the mount table, pick the mounts of
+//! interest and diff them against the last table.
+//!
+//! [`crate::XMount`] runs this on every tick. A program without tokio (built with
+//! `default-features = false`) drives a [`MountDiffer`] from its own loop instead and gets
+//! the same `Mounted`, `Unmounted`, `Changed` and `AutomountArmed` events:
+//!
+//! ```ignore
+//! let mut differ = MountDiffer::new();
+//! differ.watch("/mnt/backup");
+//! loop {
+//!     let (table, _malformed) = engine::read_mountinfo(Path::new("/proc/self/mountinfo"))?;
+//!     for ev in differ.feed(&table) {
+//!         println!("{}", ev.topic());
+//!     }
+//!     std::thread::sleep(Duration::from_secs(1));
+//! }
+//! ```
+//!
+//! What needs the sensor loop stays with the sensor: `WillUnmount` precursors, health
+//! probes, expected mounts, tombstones and time gap handling.
+
+use crate::{
+    classify::{self, MountClassifier},
+    error::XMountError,
+    events::{MountClass, MountInfo, UnmountReason, XMountEvent},
+    ignore::{Exclusion, IgnoreRules},
+};
+use omnitrace_core::{intern::Interned, labels::Labels, paths};
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    io,
+    path::{Path, PathBuf},
+};
+
+#[cfg(target_os = "windows")]
+use crate::winvol;
+
+/// Diffs successive mount tables, see the [module docs](self).
+pub struct MountDiffer {
+    // watch keys, or every mount point, and the keys watched since the last table
+    watched: Option<HashSet<PathBuf>>,
+    added: HashSet<PathBuf>,
+    pub(crate) automounts: bool,
+    pub(crate) classifier: MountClassifier,
+    pub(crate) ignored_classes: HashSet<MountClass>,
+    pub(crate) ignore: IgnoreRules,
+
+    // last known per target, and whether that is a baseline to diff against yet
+    pub(crate) last: HashMap<PathBuf, MountInfo>,
+    pub(crate) primed: bool,
+}
+
+impl Default for MountDiffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MountDiffer {
+    /// A differ over every mount point, telling automount placeholders apart.
+    pub fn new() -> Self {
+        Self {
+            watched: None,
+            added: HashSet::new(),
+            automounts: true,
+            classifier: MountClassifier::new(),
+            ignored_classes: HashSet::new(),
+            ignore: IgnoreRules::new(),
+            last: HashMap::new(),
+            primed: false,
+        }
+    }
+
+    /// Only report `mountpoint` (and the other watched ones). Takes effect with the next
+    /// table, without events for mounts that were there all along.
+    pub fn watch<P: AsRef<Path>>(&mut self, mountpoint: P) {
+        let key = watch_key(mountpoint.as_ref());
+        if self.watched.is_none() {
+            // the mount points tracked so far are no longer watched
+            self.last.clear();
+        }
+        if self.watched.get_or_insert_with(HashSet::new).insert(key.clone()) {
+            self.added.insert(key);
+        }
+    }
+
+    /// Stop reporting `mountpoint`; its last state is dropped without an event.
+    pub fn unwatch<P: AsRef<Path>>(&mut self, mountpoint: P) {
+        let key = watch_key(mountpoint.as_ref());
+        if let Some(w) = self.watched.as_mut() {
+            w.remove(&key);
+        }
+        self.added.remove(&key);
+        self.last.remove(&key);
+    }
+
+    /// Tell autofs placeholders apart from the filesystems mounted over them (on by default),
+    /// see [`crate::XMountConfig::automounts`].
+    pub fn automounts(&mut self, on: bool) {
+        self.automounts = on;
+    }
+
+    /// Classify mount points matching `glob` as `class`, see [`crate::XMount::classify`].
+    pub fn classify(&mut self, glob: &str, class: MountClass) -> Result<(), globset::Error> {
+        self.classifier.rule(glob, class)
+    }
+
+    /// Don't report mounts of this class.
+    pub fn ignore_class(&mut self, class: MountClass) {
+        self.ignored_classes.insert(class);
+    }
+
+    /// Add ignore rules, e.g. `IgnoreRules::profile(ignore::NO_PSEUDO)`.
+    pub fn ignore_rules(&mut self, rules: IgnoreRules) {
+        self.ignore.merge(rules);
+    }
+
+    /// Which rule leaves `mi` out, if any.
+    pub fn excluded_by(&self, mi: &MountInfo) -> Option<Exclusion> {
+        self.excluded(mi, self.classifier.classify(mi))
+    }
+
+    pub(crate) fn excluded(&self, mi: &MountInfo, class: MountClass) -> Option<Exclusion> {
+        self.ignore.matches(mi).or_else(|| self.ignored_classes.contains(&class).then_some(Exclusion::Class(class)))
+    }
+
+    /// Take the next table as a new baseline instead of diffing it, e.g. after a suspend.
+    pub fn reprime(&mut self) {
+        self.primed = false;
+    }
+
+    /// The events for the mount table going from the last one fed to `table`, as read by
+    /// [`read_mountinfo`]. The first table is the baseline and yields none.
+    pub fn feed(&mut self, table: &[MountInfo]) -> Vec<XMountEvent> {
+        let now = self.select(self.watched.as_ref(), table);
+        for key in self.added.drain() {
+            match now.get(&key) {
+                Some(mi) => self.last.insert(key, mi.clone()),
+                None => self.last.remove(&key),
+            };
+        }
+        let events = if self.primed { diff(&self.last, &now, self.automounts) } else { Vec::new() };
+        self.last = now;
+        self.primed = true;
+        events
+    }
+
+    /// The classified, not excluded mounts of `all` by the `watched` target they belong to
+    /// (by mount point if `None`). Of mounts stacked on one target the last listed counts,
+    /// but for a filesystem an automount placeholder triggered, which wins over it.
+    pub(crate) fn select(&self, watched: Option<&HashSet<PathBuf>>, all: &[MountInfo]) -> HashMap<PathBuf, MountInfo> {
+        let mut map = HashMap::new();
+        for mi in all {
+            let target = match watched {
+                Some(watched) => match watched_target(watched, &mi.mount_point) {
+                    Some(target) => target,
+                    None => continue,
+                },
+                None => mi.mount_point.clone(),
+            };
+
+            // an automount triggered: the real filesystem stacks over the placeholder
+            if self.automounts && is_autofs(mi) && map.contains_key(&target) {
+                continue;
+            }
+
+            let class = self.classifier.classify(mi);
+            if self.excluded(mi, class).is_some() {
+                continue;
+            }
+            map.insert(target, MountInfo { class, ..mi.clone() });
+        }
+        map
+    }
+}
+
+/// An automount placeholder rather than a mounted filesystem.
+pub(crate) fn is_autofs(mi: &MountInfo) -> bool {
+    mi.fstype == "autofs"
+}
+
+/// Canonicalize if possible; for mountpoints it’s usually fine either way
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn watch_key(mountpoint: &Path) -> PathBuf {
+    mountpoint.canonicalize().unwrap_or_else(|_| mountpoint.to_path_buf())
+}
+
+/// On Windows also spelled like the mount table (`d:` and `\\?\D:\` are `D:\`);
+/// matching against it ignores case, see [`watched_target`].
+#[cfg(target_os = "windows")]
+pub(crate) fn watch_key(mountpoint: &Path) -> PathBuf {
+    let p = mountpoint.canonicalize().unwrap_or_else(|_| mountpoint.to_path_buf());
+    PathBuf::from(winvol::normalize(&p.to_string_lossy()))
+}
+
+/// The watched path a mount point belongs to, if any.
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn watched_target(watched: &HashSet<PathBuf>, mount_point: &Path) -> Option<PathBuf> {
+    watched.get(mount_point).cloned()
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) fn watched_target(watched: &HashSet<PathBuf>, mount_point: &Path) -> Option<PathBuf> {
+    watched.iter().find(|w| winvol::same_mount_point(w, mount_point)).cloned()
+}
+
+/// Linux mountinfo escapes spaces as \040 etc. Other bytes are passed through as-is,
+/// so fields are bytes, not UTF-8.
+fn unescape_mount_field(s: &[u8]) -> Vec<u8> {
+    // minimal: handle \040 \011 \012 \134
+    let mut out = Vec::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        if s[i] == b'\\' && i + 3 < s.len() {
+            let (a, b, c) = (s[i + 1], s[i + 2], s[i + 3]);
+            if a.is_ascii_digit() && b.is_ascii_digit() && c.is_ascii_digit() {
+                let oct = ((a - b'0') as u32) * 64 + ((b - b'0') as u32) * 8 + ((c - b'0') as u32);
+                if let Ok(byte) = u8::try_from(oct) {
+                    out.push(byte);
+                    i += 4;
+                    continue;
+                }
+            }
+        }
+        out.push(s[i]);
+        i += 1;
+    }
+    out
+}
+
+/// Parse a line from mountinfo into a MountInfo struct.
+pub fn parse_mountinfo_line<L: AsRef<[u8]>>(line: L) -> Result<MountInfo, XMountError> {
+    // format: mountID parentID major:minor root mount_point options optional_fields... - fstype source super_options
+    let line = line.as_ref();
+    let mut parts = line.split(|b| b.is_ascii_whitespace()).filter(|p| !p.is_empty());
+    let text = |p: &[u8]| String::from_utf8_lossy(p).into_owned();
+    let seen = Cell::new(0);
+    let malformed = |field, column| XMountError::MalformedLine { field, column, line: text(line) };
+    let mut next = |field| {
+        let p = parts.next().ok_or_else(|| malformed(field, seen.get() + 1))?;
+        seen.set(seen.get() + 1);
+        Ok::<_, XMountError>(p)
+    };
+    let id = |field, p: &[u8]| std::str::from_utf8(p).ok().and_then(|p| p.parse::<u32>().ok()).ok_or_else(|| malformed(field, seen.get()));
+
+    let mount_id = id("mount ID", next("mount ID")?)?;
+    let parent_id = id("parent ID", next("parent ID")?)?;
+    let _majmin = next("major:minor")?; // ignore
+
+    let root = unescape_mount_field(next("root")?);
+    let mount_point = unescape_mount_field(next("mount point")?);
+    let mount_opts = Interned::from_utf8_lossy(next("mount options")?);
+
+    // skip optional fields until "-"
+    while next("fstype")? != b"-" {}
+
+    let fstype = Interned::from_utf8_lossy(next("fstype")?);
+    let source = Interned::from_utf8_lossy(&unescape_mount_field(next("source")?));
+    let super_opts = next("super options").map(Interned::from_utf8_lossy).unwrap_or_default();
+
+    Ok(MountInfo {
+        mount_id,
+        parent_id,
+        mount_point: paths::from_bytes(mount_point),
+        root: paths::from_bytes(root),
+        fuse: classify::is_fuse(&fstype),
+        fstype,
+        source,
+        mount_opts,
+        super_opts,
+        class: MountClass::Other,
+    })
+}
+
+/// The mounts of a whole mountinfo file, and the malformed lines that were skipped.
+pub fn parse_mountinfo(raw: &[u8]) -> (Vec<MountInfo>, Vec<XMountError>) {
+    let (mut out, mut malformed) = (Vec::new(), Vec::new());
+    for line in raw.split(|b| *b == b'\n').filter(|l| !l.is_empty()) {
+        match parse_mountinfo_line(line) {
+            Ok(mi) => out.push(mi),
+            Err(e) => malformed.push(e),
+        }
+    }
+    (out, malformed)
+}
+
+/// The mounts, and the malformed lines that were skipped.
+#[cfg(target_os = "linux")]
+pub fn read_mountinfo(path: &Path) -> io::Result<(Vec<MountInfo>, Vec<XMountError>)> {
+    // not read_to_string: mount points need not be UTF-8
+    Ok(parse_mountinfo(&std::fs::read(path)?))
+}
+
+#[cfg(target_os = "netbsd")]
+pub fn read_mountinfo(_path: &Path) -> io::Result<(Vec<MountInfo>, Vec<XMountError>)> {
+    netbsd_mounts::read_mounts().map(|m| (m, Vec::new()))
+}
+
+#[cfg(target_os = "windows")]
+pub fn read_mountinfo(_path: &Path) -> io::Result<(Vec<MountInfo>, Vec<XMountError>)> {
+    winvol::read_mounts().map(|m| (m, Vec::new()))
+}
+
+fn materially_diff(a: &MountInfo, b: &MountInfo) -> bool {
+    #[cfg(any(target_os = "netbsd", target_os = "windows"))]
+    {
+        a.fstype != b.fstype || a.source != b.source || a.mount_opts != b.mount_opts
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        a.mount_id != b.mount_id
+            || a.parent_id != b.parent_id
+            || a.root != b.root
+            || a.fstype != b.fstype
+            || a.source != b.source
+            || a.mount_opts != b.mount_opts
+            || a.super_opts != b.super_opts
+    }
+}
+
+/// A different mount now sits on the same target: the old one went away (on Linux,
+/// the mount ID changed), as opposed to e.g. `mount -o remount,ro` changing options.
+fn replaced(a: &MountInfo, b: &MountInfo) -> bool {
+    a.mount_id != b.mount_id
+}
+
+/// Events for one tick, in delivery order: all Unmounted first, then Mounted and Changed,
+/// each sorted by target. A target whose mount got replaced yields Unmounted then Mounted,
+/// so callbacks never see a second Mounted without the Unmounted in between. With
+/// `automounts` (see [`crate::XMountConfig::automounts`]), autofs placeholders arriving are
+/// AutomountArmed, and a trigger or expiry over a placeholder is a lone Mounted or Unmounted.
+pub fn diff(last: &HashMap<PathBuf, MountInfo>, now: &HashMap<PathBuf, MountInfo>, automounts: bool) -> Vec<XMountEvent> {
+    let mut gone = Vec::new();
+    let mut came = Vec::new();
+    let unmounted =
+        |mp: &PathBuf, old: &MountInfo, reason| XMountEvent::Unmounted { target: mp.clone(), last: old.clone(), reason, labels: Labels::default() };
+    let arrived = |mp: &PathBuf, new: &MountInfo| {
+        if automounts && is_autofs(new) {
+            XMountEvent::AutomountArmed { target: mp.clone(), info: new.clone(), labels: Labels::default() }
+        } else {
+            XMountEvent::Mounted { target: mp.clone(), info: new.clone(), labels: Labels::default() }
+        }
+    };
+    let disarmed = |old: &MountInfo| (automounts && is_autofs(old)).then_some(UnmountReason::AutomountDisarmed);
+
+    for (mp, old) in last {
+        match now.get(mp) {
+            None => gone.push(unmounted(mp, old, disarmed(old))),
+            // triggered: the placeholder stays, the filesystem arrives
+            Some(new) if automounts && is_autofs(old) && !is_autofs(new) => came.push(arrived(mp, new)),
+            // expired: only the placeholder is left
+            Some(new) if automounts && !is_autofs(old) && is_autofs(new) => gone.push(unmounted(mp, old, Some(UnmountReason::AutomountExpired))),
+            Some(new) if replaced(old, new) => {
+                gone.push(unmounted(mp, old, disarmed(old)));
+                came.push(arrived(mp, new));
+            }
+            Some(new) if materially_diff(old, new) => {
+                came.push(XMountEvent::Changed { target: mp.clone(), old: old.clone(), new: new.clone(), labels: Labels::default() })
+            }
+            Some(_) => {}
+        }
+    }
+    for (mp, new) in now {
+        if !last.contains_key(mp) {
+            came.push(arrived(mp, new));
+        }
+    }
+
+    gone.sort_by(|a, b| a.target().cmp(b.target()));
+    came.sort_by(|a, b| a.target().cmp(b.target()));
+    gone.extend(came);
+    gone
+}
+
+/// `buf` up to its first NUL, or all of it. `c_char` is `i8` on some targets and `u8` on
+/// others (e.g. aarch64), so the bytes are reinterpreted rather than cast.
+#[cfg(target_os = "netbsd")]
+fn c_char_array_to_string(buf: &[libc::c_char]) -> String {
+    let bytes = unsafe { std::slice::from_raw_parts(buf.as_ptr().cast::<u8>(), buf.len()) };
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+#[cfg(target_os = "netbsd")]
+fn mount_flags_to_opts(flags: u64) -> String {
+    // NetBSD statvfs flags are ST_*; we only map the obvious ones.
+    // If you want the full list, expand it.
+    let mut out = Vec::new();
+
+    // These names come from NetBSD statvfs docs. :contentReference[oaicite:3]{index=3}
+    const ST_RDONLY: u64 = 0x0000_0001;
+    const ST_NOEXEC: u64 = 0x0000_0002;
+    const ST_NOSUID: u64 = 0x0000_0008;
+    const ST_NODEV: u64 = 0x0000_0010;
+
+    out.push(if (flags & ST_RDONLY) != 0 { "ro" } else { "rw" });
+
+    if (flags & ST_NOEXEC) != 0 {
+        out.push("noexec");
+    }
+    if (flags & ST_NOSUID) != 0 {
+        out.push("nosuid");
+    }
+    if (flags & ST_NODEV) != 0 {
+        out.push("nodev");
+    }
+
+    out.join(",")
+}
+
+#[cfg(target_os = "netbsd")]
+mod netbsd_mounts {
+    use super::*;
+    use std::{io, ptr};
+
+    // NetBSD uses versioned symbols; this avoids ABI mismatch pain. :contentReference[oaicite:4]{index=4}
+    extern "C" {
+        #[link_name = "__getmntinfo13"]
+        fn getmntinfo(mntbufp: *mut *mut libc::statvfs, flags: libc::c_int) -> libc::c_int;
+    }
+
+    // NetBSD flags for getmntinfo forward to getvfsstat(2). :contentReference[oaicite:5]{index=5}
+    const MNT_NOWAIT: libc::c_int = 2;
+
+    pub fn read_mounts() -> io::Result<Vec<MountInfo>> {
+        unsafe {
+            let mut buf: *mut libc::statvfs = ptr::null_mut();
+            let n = getmntinfo(&mut buf as *mut *mut libc::statvfs, MNT_NOWAIT);
+            if n < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let slice = std::slice::from_raw_parts(buf, n as usize);
+            let mut out = Vec::with_capacity(slice.len());
+
+            for sv in slice {
+                // Field layout is defined by NetBSD statvfs(5). :contentReference[oaicite:6]{index=6}
+                let fstype = c_char_array_to_string(&sv.f_fstypename);
+                let target = c_char_array_to_string(&sv.f_mntonname);
+                let source = c_char_array_to_string(&sv.f_mntfromname);
+
+                let mount_opts = mount_flags_to_opts(sv.f_flag as u64);
+
+                out.push(MountInfo {
+                    mount_id: 0,
+                    parent_id: 0,
+                    mount_point: PathBuf::from(target),
+                    root: PathBuf::from("/"),
+                    fstype,
+                    source,
+                    mount_opts,
+                    super_opts: String::new(),
+                    class: MountClass::Other,
+                    fuse: false,
+                });
+            }
+
+            Ok(out)
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use crate::{
+    engine::{self, MountDiffer},
+    error::XMountError,
+    events::{MountInfo, XMountEvent, XMountMask},
+};
+use omnitrace_core::intern::Interned;
+use std::{collections::HashMap, path::PathBuf};
+
+pub(crate) const ROOT_LINE: &str = "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw";
+
+#[test]
+fn parsed_mounts_share_repeated_fields() {
+    let a = engine::parse_mountinfo_line("40 22 0:50 / /run/pod/1 rw,nosuid,nodev shared:5 - tmpfs tmpfs rw,size=65536k").unwrap();
+    let b = engine::parse_mountinfo_line("41 22 0:51 / /run/pod/2 rw,nosuid,nodev shared:6 - tmpfs tmpfs rw,size=65536k").unwrap();
+    assert!(Interned::ptr_eq(&a.fstype, &b.fstype));
+    assert!(Interned::ptr_eq(&a.mount_opts, &b.mount_opts));
+    assert!(Interned::ptr_eq(&a.super_opts, &b.super_opts));
+
+    // and serialize as before
+    let json = serde_json::to_value(&a).unwrap();
+    assert_eq!((&json["fstype"], &json["source"], &json["mount_opts"]), (&"tmpfs".into(), &"tmpfs".into(), &"rw,nosuid,nodev".into()));
+    assert!(json.get("fuse").is_none());
+}
+
+#[test]
+fn fuse_mounts_are_flagged() {
+    let fuse = |line: &str| engine::parse_mountinfo_line(line).unwrap().fuse;
+    assert!(fuse("60 22 0:60 / /mnt/share rw,nosuid,nodev - fuse.sshfs user@host:/srv rw,user_id=1000"));
+    assert!(fuse("61 22 0:61 / /run/user/1000/gvfs rw - fuse gvfsd-fuse rw"));
+    assert!(fuse("62 22 8:33 / /media/ntfs rw - fuseblk /dev/sdc1 rw"));
+    assert!(!fuse(ROOT_LINE));
+    assert!(!fuse("63 22 0:63 / /mnt/fusebox rw - ext4 /dev/fuse0 rw"));
+
+    let json = serde_json::to_value(engine::parse_mountinfo_line("60 22 0:60 / /mnt/share rw - fuse.rclone remote: rw").unwrap()).unwrap();
+    assert_eq!(json["fuse"], true);
+}
+
+#[test]
+fn malformed_lines_name_the_bad_field() {
+    let err = |line: &str| engine::parse_mountinfo_line(line).unwrap_err();
+    assert!(matches!(err("x 22 8:1 / / rw - ext4 /dev/sda1 rw"), XMountError::MalformedLine { field: "mount ID", column: 1, .. }));
+    assert!(matches!(err("40 y 8:1 / / rw - ext4 /dev/sda1 rw"), XMountError::MalformedLine { field: "parent ID", column: 2, .. }));
+    assert!(matches!(err("40 22 8:17 / /mnt/a rw,relatime"), XMountError::MalformedLine { field: "fstype", column: 7, .. }));
+    assert!(matches!(err("40 22 8:17 / /mnt/a rw shared:1 - ext4"), XMountError::MalformedLine { field: "source", column: 10, .. }));
+    assert_eq!(err("40 22").to_string(), "malformed mountinfo line, bad major:minor: 40 22");
+}
+
+// -------------------------
+// ordering guarantees
+// -------------------------
+
+pub(crate) const ORDER_TARGETS: [&str; 4] = ["/mnt/xmount-ut-o1", "/mnt/xmount-ut-o2", "/mnt/xmount-ut-o3", "/mnt/xmount-ut-o4"];
+
+/// Random state per target: absent, or mounted with one of two mount IDs and rw or ro.
+pub(crate) fn random_mounts(rng: &mut fastrand::Rng) -> Vec<(&'static str, u32, &'static str)> {
+    let mut out = Vec::new();
+    for t in ORDER_TARGETS {
+        if rng.bool() {
+            out.push((t, rng.u32(40..42), if rng.bool() { "rw" } else { "ro" }));
+        }
+    }
+    out
+}
+
+pub(crate) fn as_map(mounts: &[(&str, u32, &str)]) -> HashMap<PathBuf, MountInfo> {
+    mounts
+        .iter()
+        .map(|(t, id, opts)| {
+            let mut mi = MountInfo::test(*t);
+            mi.mount_id = *id;
+            mi.mount_opts = (*opts).into();
+            (mi.mount_point.clone(), mi)
+        })
+        .collect()
+}
+
+/// Apply `ev` to the model, failing on events that are out of order for their target.
+pub(crate) fn apply(model: &mut HashMap<PathBuf, MountInfo>, ev: &XMountEvent) -> Result<(), String> {
+    match ev {
+        XMountEvent::Mounted { target, info, .. } => match model.insert(target.clone(), info.clone()) {
+            None => Ok(()),
+            Some(_) => Err(format!("Mounted {} while mounted", target.display())),
+        },
+        XMountEvent::Unmounted { target, .. } => model.remove(target).map(|_| ()).ok_or(format!("Unmounted {} while not mounted", target.display())),
+        XMountEvent::Changed { target, new, .. } => {
+            model.get_mut(target).map(|mi| *mi = new.clone()).ok_or(format!("Changed {} while not mounted", target.display()))
+        }
+        XMountEvent::WillUnmount { .. }
+        | XMountEvent::AutomountArmed { .. }
+        | XMountEvent::FsHealthChanged { .. }
+        | XMountEvent::Deviation { .. }
+        | XMountEvent::ParseAnomaly { .. } => Ok(()),
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn diff_orders_events_for_random_snapshot_sequences() {
+    for seed in 0..300 {
+        let mut rng = fastrand::Rng::with_seed(seed);
+        let mut last = as_map(&random_mounts(&mut rng));
+        let mut model = last.clone();
+
+        for tick in 0..30 {
+            let now = as_map(&random_mounts(&mut rng));
+            let events = engine::diff(&last, &now, false);
+
+            let first_arrival = events.iter().position(|e| !matches!(e, XMountEvent::Unmounted { .. })).unwrap_or(events.len());
+            assert!(
+                events[first_arrival..].iter().all(|e| !matches!(e, XMountEvent::Unmounted { .. })),
+                "seed {seed} tick {tick}: Unmounted after Mounted/Changed: {events:?}"
+            );
+            for ev in &events {
+                apply(&mut model, ev).unwrap_or_else(|e| panic!("seed {seed} tick {tick}: {e}"));
+            }
+            assert_eq!(
+                model.iter().map(|(k, v)| (k.clone(), (v.mount_id, v.mount_opts.clone()))).collect::<HashMap<_, _>>(),
+                now.iter().map(|(k, v)| (k.clone(), (v.mount_id, v.mount_opts.clone()))).collect::<HashMap<_, _>>(),
+                "seed {seed} tick {tick}"
+            );
+            last = now;
+        }
+    }
+}
+
+#[test]
+fn remount_is_unmounted_then_mounted() {
+    let old = as_map(&[("/mnt/x", 40, "rw")]);
+    let new = as_map(&[("/mnt/x", 41, "rw")]);
+    let events = engine::diff(&old, &new, false);
+    let kinds: Vec<_> = events.iter().map(|e| e.mask()).collect();
+    assert_eq!(format!("{kinds:?}"), format!("{:?}", [XMountMask::UNMOUNTED, XMountMask::MOUNTED]));
+
+    // options changing on the same mount stay a Changed
+    let events = engine::diff(&old, &as_map(&[("/mnt/x", 40, "ro")]), false);
+    assert!(matches!(events.as_slice(), [XMountEvent::Changed { .. }]));
+}
+
+// -------------------------
+// delta-encoded Changed events
+// -------------------------
+
+/// Changed events of the k8s node fixture remounted read-only, with the superblock options
+/// of every other mount changing too, as a sink would receive them.
+fn captured_changes() -> Vec<serde_json::Value> {
+    let before: HashMap<PathBuf, MountInfo> = include_str!("../fixtures/k8s-node.mountinfo")
+        .lines()
+        .filter_map(|l| engine::parse_mountinfo_line(l).ok())
+        .map(|mi| (mi.mount_point.clone(), mi))
+        .collect();
+    let mut after = before.clone();
+    let mut mounts: Vec<&mut MountInfo> = after.values_mut().collect();
+    mounts.sort_by(|a, b| a.mount_point.cmp(&b.mount_point));
+    for (i, mi) in mounts.into_iter().enumerate() {
+        mi.mount_opts = mi.mount_opts.replacen("rw", "ro", 1).into();
+        if i % 2 == 0 {
+            mi.super_opts = format!("{},errors=remount-ro", mi.super_opts).into();
+        }
+    }
+    engine::diff(&before, &after, false).iter().map(|ev| serde_json::to_value(ev).unwrap()).collect()
+}
+
+#[test]
+fn delta_changes_reconstruct_and_shrink_the_stream() {
+    let events = captured_changes();
+    assert!(events.len() > 20, "{}", events.len());
+
+    let (mut full, mut compact) = (0, 0);
+    for ev in &events {
+        let enc = omnitrace_core::delta::encode(ev).unwrap();
+        let changed = enc["Changed"]["changed"].as_object().unwrap();
+        assert!(changed.keys().all(|k| k == "mount_opts" || k == "super_opts"), "{changed:?}");
+        assert_eq!(omnitrace_core::delta::apply(&ev["Changed"]["old"], changed), ev["Changed"]["new"]);
+
+        full += ev.to_string().len();
+        compact += enc.to_string().len();
+    }
+    println!("{} Changed events: {full} bytes full, {compact} bytes delta-encoded ({:.0}%)", events.len(), 100.0 * compact as f64 / full as f64);
+    assert!(compact * 100 < full * 40, "{compact} vs {full}");
+}
+
+// -------------------------
+// automounts
+// -------------------------
+
+const AUTOFS_LINE: &str = "40 22 0:50 / /srv/data rw,relatime shared:20 - autofs systemd-1 rw,fd=47,pgrp=1,timeout=60,minproto=5,maxproto=5,direct";
+
+fn nfs_line(id: u32) -> String {
+    format!("{id} 40 0:{id} / /srv/data rw,relatime shared:{id} - nfs4 fs:/data rw,vers=4.2")
+}
+
+/// Events of a systemd automount going through arm, trigger, expire, trigger, expire and
+/// disarm, as `kind[:reason]` per poll.
+fn automount_cycle(automounts: bool) -> Vec<Vec<String>> {
+    let mut differ = MountDiffer::new();
+    differ.automounts(automounts);
+    differ.watch("/srv/data");
+    // an empty baseline, so the placeholder arriving shows
+    differ.feed(&[]);
+    let (nfs41, nfs42) = (nfs_line(41), nfs_line(42));
+    let polls: [&[&str]; 6] = [
+        &[ROOT_LINE, AUTOFS_LINE],
+        &[ROOT_LINE, AUTOFS_LINE, &nfs41],
+        &[ROOT_LINE, AUTOFS_LINE],
+        &[ROOT_LINE, AUTOFS_LINE, &nfs42],
+        &[ROOT_LINE, AUTOFS_LINE],
+        &[ROOT_LINE],
+    ];
+
+    let mut out = Vec::new();
+    for lines in polls {
+        let (all, _) = engine::parse_mountinfo(lines.join("\n").as_bytes());
+        out.push(
+            differ
+                .feed(&all)
+                .iter()
+                .map(|ev| {
+                    let v = serde_json::to_value(ev).unwrap();
+                    let (kind, body) = v.as_object().unwrap().iter().next().unwrap();
+                    match body["reason"].as_str() {
+                        Some(r) => format!("{kind}:{r}"),
+                        None => kind.clone(),
+                    }
+                })
+                .collect(),
+        );
+    }
+    out
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn automount_placeholders_are_armed_and_expire() {
+    assert_eq!(
+        automount_cycle(true),
+        [
+            vec!["AutomountArmed"],
+            vec!["Mounted"],
+            vec!["Unmounted:automount_expired"],
+            vec!["Mounted"],
+            vec!["Unmounted:automount_expired"],
+            vec!["Unmounted:automount_disarmed"],
+        ]
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn automount_tracking_can_be_turned_off() {
+    let replaced = vec!["Unmounted", "Mounted"];
+    assert_eq!(automount_cycle(false), [vec!["Mounted"], replaced.clone(), replaced.clone(), replaced.clone(), replaced, vec!["Unmounted"]]);
+}
@@ -0,0 +1,61 @@
+#[cfg(feature = "runtime")]
+use omnitrace_core::callbacks::BarrierTimeout;
+use omnitrace_core::{
+    anomaly::{ParseAnomaly, ParseFailure},
+    error::SensorError,
+};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// What XMount runs past, recorded in [`crate::XMount::diagnostics`].
+#[derive(Debug, thiserror::Error)]
+pub enum XMountError {
+    #[error("cannot read {}: {source}", path.display())]
+    Mountinfo {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// A mountinfo line missing `field`, or with an unparsable one, at the 1-based
+    /// whitespace-separated `column`. The line is skipped.
+    #[error("malformed mountinfo line, bad {field}: {line}")]
+    MalformedLine { field: &'static str, column: usize, line: String },
+    /// Handlers did not finish an ordered event in time, see [`crate::XMountConfig::barrier`].
+    /// `target` is set for the [`crate::XMount::on_target`] handlers, unset for the hub.
+    #[cfg(feature = "runtime")]
+    #[error("barrier{}: {source}", target.as_ref().map(|t| format!(" on {}", t.display())).unwrap_or_default())]
+    Barrier {
+        target: Option<PathBuf>,
+        #[source]
+        source: BarrierTimeout,
+    },
+    /// A [`crate::health::HealthProbe`] failed; the mount's health is `Unknown` until it succeeds.
+    #[error("{fstype} health of {}: {source}", target.display())]
+    Health {
+        target: PathBuf,
+        fstype: String,
+        #[source]
+        source: io::Error,
+    },
+}
+
+impl XMountError {
+    /// A [`XMountError::MalformedLine`] of the mountinfo file `source` as captured, see
+    /// [`crate::XMountConfig::capture_parse_anomalies`].
+    pub fn anomaly(&self, source: &Path) -> Option<ParseAnomaly> {
+        match self {
+            XMountError::MalformedLine { field, column, line } => {
+                Some(ParseAnomaly::new(source, line.as_str(), ParseFailure::new(*column, format!("bad {field}"))))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<XMountError> for SensorError {
+    fn from(e: XMountError) -> Self {
+        SensorError::sensor("xmount", e)
+    }
+}
@@ -1,41 +1,490 @@
 use bitflags::bitflags;
+use omnitrace_core::{
+    anomaly::ParseAnomaly,
+    expected::Deviation,
+    fields::{EventFields, FieldValue},
+    intern::Interned,
+    labels::Labels,
+    topics::{self, Topic, Topics},
+};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// Coarse classification of a mount, so callbacks can cheaply ignore or group container noise.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MountClass {
+    /// Overlay roots and other mounts owned by a container runtime (docker, containerd, podman)
+    ContainerOverlay,
+    /// Pod volumes mounted by the kubelet
+    KubeletVolume,
+    Tmpfs,
+    NetworkFs,
+    BlockDevice,
+    /// Kernel pseudo filesystems (proc, sysfs, cgroup2, ...)
+    Pseudo,
+    #[default]
+    Other,
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MountInfo {
     pub mount_id: u32,
     pub parent_id: u32,
+    #[serde(with = "omnitrace_core::paths")]
     pub mount_point: PathBuf,
+    #[serde(with = "omnitrace_core::paths")]
     pub root: PathBuf,
-    pub fstype: String,
-    pub source: String,
-    pub mount_opts: String,
-    pub super_opts: String,
+    pub fstype: Interned,
+    pub source: Interned,
+    pub mount_opts: Interned,
+    pub super_opts: Interned,
+    #[serde(default)]
+    pub class: MountClass,
+    /// Served by a FUSE daemon (`fuse`, `fuseblk`, `fuse.*`), which can stall or die with the
+    /// mount still in place, see [`crate::health::FuseLiveness`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fuse: bool,
+}
+
+impl MountInfo {
+    /// Plausible block device mount at `mount_point`, for injecting synthetic events
+    /// (see `CallbackHub::inject`).
+    pub fn test<P: Into<PathBuf>>(mount_point: P) -> Self {
+        Self {
+            mount_id: 1000,
+            parent_id: 1,
+            mount_point: mount_point.into(),
+            root: PathBuf::from("/"),
+            fstype: "ext4".into(),
+            source: "/dev/test".into(),
+            mount_opts: "rw,relatime".into(),
+            super_opts: "rw".into(),
+            class: MountClass::BlockDevice,
+            fuse: false,
+        }
+    }
+}
+
+/// Signal that a watched mount is about to go away, opted into per watch.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UnmountPrecursor {
+    /// The mount was remounted read-only (a common first step of a clean unmount).
+    ReadOnly,
+    /// systemd reports the `.mount` unit as `deactivating`.
+    SystemdDeactivating,
+}
+
+/// Why an `Unmounted` is not a plain unmount, see [`crate::XMountConfig::automounts`].
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnmountReason {
+    /// The automounted filesystem expired; the autofs placeholder is back and will mount it
+    /// again on the next access.
+    AutomountExpired,
+    /// The autofs placeholder itself went away (e.g. the `.automount` unit was stopped).
+    AutomountDisarmed,
+}
+
+/// Filesystem health as reported by a [`crate::health::HealthProbe`].
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FsHealth {
+    Healthy,
+    /// Still serving, with redundancy lost or errors recorded (a missing btrfs device, a
+    /// DEGRADED zpool).
+    Degraded,
+    /// Not serving writes: forced read-only after an error, a FAULTED or SUSPENDED zpool.
+    Faulted,
+    /// The probe failed, see [`crate::XMount::diagnostics`].
+    #[default]
+    Unknown,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum XMountEvent {
-    Mounted { target: PathBuf, info: MountInfo },
-    Unmounted { target: PathBuf, last: MountInfo },
-    Changed { target: PathBuf, old: MountInfo, new: MountInfo },
+    Mounted {
+        #[serde(with = "omnitrace_core::paths")]
+        target: PathBuf,
+        info: MountInfo,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    Unmounted {
+        #[serde(with = "omnitrace_core::paths")]
+        target: PathBuf,
+        last: MountInfo,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<UnmountReason>,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    Changed {
+        #[serde(with = "omnitrace_core::paths")]
+        target: PathBuf,
+        old: MountInfo,
+        new: MountInfo,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    /// Advisory fired before `Unmounted` when a precursor was seen. There is no guarantee the
+    /// unmount follows, nor that it waits for callbacks: it only comes first in omnitrace's order.
+    WillUnmount {
+        #[serde(with = "omnitrace_core::paths")]
+        target: PathBuf,
+        info: MountInfo,
+        reason: UnmountPrecursor,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    /// An autofs placeholder appeared on the target: the filesystem gets mounted on first
+    /// access, reported as `Mounted` then.
+    AutomountArmed {
+        #[serde(with = "omnitrace_core::paths")]
+        target: PathBuf,
+        info: MountInfo,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    /// The health a probe reports for a watched mount changed, see [`crate::XMount::add_health_probe`].
+    FsHealthChanged {
+        #[serde(with = "omnitrace_core::paths")]
+        target: PathBuf,
+        fstype: String,
+        old: FsHealth,
+        new: FsHealth,
+        /// What the probe found, e.g. `"device 2 missing"`.
+        details: Vec<String>,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    /// The mount at `target` is not as declared, see [`crate::XMount::expect`]. Fired when the
+    /// sensor primes, before any change. `info` is the mount found there, if any.
+    Deviation {
+        #[serde(with = "omnitrace_core::paths")]
+        target: PathBuf,
+        #[serde(flatten)]
+        deviation: Deviation,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        info: Option<MountInfo>,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
+    /// A mountinfo line that did not parse and was skipped, with
+    /// [`crate::XMountConfig::capture_parse_anomalies`]. Its target is the mountinfo file.
+    ParseAnomaly {
+        #[serde(flatten)]
+        anomaly: ParseAnomaly,
+        #[serde(default, skip_serializing_if = "Labels::is_empty")]
+        labels: Labels,
+    },
 }
 
 bitflags! {
+    /// Kinds in the low 16 bits, sub-kinds of `Changed` from bit 16 (see
+    /// `omnitrace_core::callbacks::SUBKIND_BITS`). A `Changed` event has `CHANGED` and exactly
+    /// one of `CHANGED_OPTIONS`, `CHANGED_SOURCE` and `CHANGED_OTHER`, as its topic tells.
     #[derive(Copy, Clone, Debug)]
     pub struct XMountMask: u64 {
         const MOUNTED   = 0b0001;
         const UNMOUNTED = 0b0010;
         const CHANGED   = 0b0100;
+        const WILL_UNMOUNT = 0b1000;
+        const AUTOMOUNT_ARMED = 0b1_0000;
+        const FS_HEALTH_CHANGED = 0b10_0000;
+        const DEVIATION = 0b100_0000;
+        const PARSE_ANOMALY = 0b1000_0000;
+
+        /// Remounted with other options, `mount.changed.remount`.
+        const CHANGED_OPTIONS = 1 << 16;
+        /// Another filesystem (source, type or root) mounted there, `mount.changed.replaced`.
+        const CHANGED_SOURCE = 1 << 17;
+        /// Anything else, e.g. the mount class, `mount.changed.other`.
+        const CHANGED_OTHER = 1 << 18;
     }
 }
 
 impl XMountEvent {
+    /// Synthetic `Mounted` event, see [`MountInfo::test`].
+    pub fn test_mounted<P: Into<PathBuf>>(target: P) -> Self {
+        let info = MountInfo::test(target);
+        XMountEvent::Mounted { target: info.mount_point.clone(), info, labels: Labels::default() }
+    }
+
+    /// Synthetic `Unmounted` event, see [`MountInfo::test`].
+    pub fn test_unmounted<P: Into<PathBuf>>(target: P) -> Self {
+        let last = MountInfo::test(target);
+        XMountEvent::Unmounted { target: last.mount_point.clone(), last, reason: None, labels: Labels::default() }
+    }
+
+    /// The mountpoint the event is about.
+    pub fn target(&self) -> &Path {
+        match self {
+            XMountEvent::Mounted { target, .. }
+            | XMountEvent::Unmounted { target, .. }
+            | XMountEvent::Changed { target, .. }
+            | XMountEvent::WillUnmount { target, .. }
+            | XMountEvent::AutomountArmed { target, .. }
+            | XMountEvent::FsHealthChanged { target, .. }
+            | XMountEvent::Deviation { target, .. } => target,
+            XMountEvent::ParseAnomaly { anomaly, .. } => &anomaly.source,
+        }
+    }
+
+    /// Labels of the watched mountpoint, see [`crate::XMount::add_labeled`].
+    pub fn labels(&self) -> &Labels {
+        match self {
+            XMountEvent::Mounted { labels, .. }
+            | XMountEvent::Unmounted { labels, .. }
+            | XMountEvent::Changed { labels, .. }
+            | XMountEvent::WillUnmount { labels, .. }
+            | XMountEvent::AutomountArmed { labels, .. }
+            | XMountEvent::FsHealthChanged { labels, .. }
+            | XMountEvent::Deviation { labels, .. }
+            | XMountEvent::ParseAnomaly { labels, .. } => labels,
+        }
+    }
+
+    #[cfg(feature = "runtime")]
+    pub(crate) fn labels_mut(&mut self) -> &mut Labels {
+        match self {
+            XMountEvent::Mounted { labels, .. }
+            | XMountEvent::Unmounted { labels, .. }
+            | XMountEvent::Changed { labels, .. }
+            | XMountEvent::WillUnmount { labels, .. }
+            | XMountEvent::AutomountArmed { labels, .. }
+            | XMountEvent::FsHealthChanged { labels, .. }
+            | XMountEvent::Deviation { labels, .. }
+            | XMountEvent::ParseAnomaly { labels, .. } => labels,
+        }
+    }
+
     pub fn mask(&self) -> XMountMask {
         match self {
             XMountEvent::Mounted { .. } => XMountMask::MOUNTED,
             XMountEvent::Unmounted { .. } => XMountMask::UNMOUNTED,
-            XMountEvent::Changed { .. } => XMountMask::CHANGED,
+            // with the sub-kind bit, classified as for the topic
+            XMountEvent::Changed { .. } => XMountMask::from_bits_retain(Self::TOPICS[self.topic_index()].mask),
+            XMountEvent::WillUnmount { .. } => XMountMask::WILL_UNMOUNT,
+            XMountEvent::AutomountArmed { .. } => XMountMask::AUTOMOUNT_ARMED,
+            XMountEvent::FsHealthChanged { .. } => XMountMask::FS_HEALTH_CHANGED,
+            XMountEvent::Deviation { .. } => XMountMask::DEVIATION,
+            XMountEvent::ParseAnomaly { .. } => XMountMask::PARSE_ANOMALY,
+        }
+    }
+
+    /// Dotted topic, see [`omnitrace_core::topics`]. `Changed` is `mount.changed.remount` when
+    /// only options changed, `.replaced` when another filesystem (source, type or root) is
+    /// mounted there, `.other` otherwise; `FsHealthChanged` is `mount.health.<new health>`,
+    /// `Deviation` is `mount.deviation.<missing|unexpected|mismatch>`.
+    pub fn topic(&self) -> &'static str {
+        topics::topic_of(self)
+    }
+}
+
+impl Topics for XMountEvent {
+    const TOPICS: &'static [Topic] = &[
+        Topic::new("mount.mounted", XMountMask::MOUNTED.bits()),
+        Topic::new("mount.unmounted", XMountMask::UNMOUNTED.bits()),
+        Topic::new("mount.changed.remount", XMountMask::CHANGED.union(XMountMask::CHANGED_OPTIONS).bits()),
+        Topic::new("mount.changed.replaced", XMountMask::CHANGED.union(XMountMask::CHANGED_SOURCE).bits()),
+        Topic::new("mount.changed.other", XMountMask::CHANGED.union(XMountMask::CHANGED_OTHER).bits()),
+        Topic::new("mount.will_unmount", XMountMask::WILL_UNMOUNT.bits()),
+        Topic::new("mount.automount.armed", XMountMask::AUTOMOUNT_ARMED.bits()),
+        Topic::new("mount.health.healthy", XMountMask::FS_HEALTH_CHANGED.bits()),
+        Topic::new("mount.health.degraded", XMountMask::FS_HEALTH_CHANGED.bits()),
+        Topic::new("mount.health.faulted", XMountMask::FS_HEALTH_CHANGED.bits()),
+        Topic::new("mount.health.unknown", XMountMask::FS_HEALTH_CHANGED.bits()),
+        Topic::new("mount.deviation.missing", XMountMask::DEVIATION.bits()),
+        Topic::new("mount.deviation.unexpected", XMountMask::DEVIATION.bits()),
+        Topic::new("mount.deviation.mismatch", XMountMask::DEVIATION.bits()),
+        Topic::new("mount.parse_anomaly", XMountMask::PARSE_ANOMALY.bits()),
+    ];
+
+    fn topic_index(&self) -> usize {
+        match self {
+            XMountEvent::Mounted { .. } => 0,
+            XMountEvent::Unmounted { .. } => 1,
+            XMountEvent::Changed { old, new, .. } if (&old.source, &old.fstype, &old.root) != (&new.source, &new.fstype, &new.root) => 3,
+            XMountEvent::Changed { old, new, .. } if (&old.mount_opts, &old.super_opts) != (&new.mount_opts, &new.super_opts) => 2,
+            XMountEvent::Changed { .. } => 4,
+            XMountEvent::WillUnmount { .. } => 5,
+            XMountEvent::AutomountArmed { .. } => 6,
+            XMountEvent::FsHealthChanged { new, .. } => match new {
+                FsHealth::Healthy => 7,
+                FsHealth::Degraded => 8,
+                FsHealth::Faulted => 9,
+                FsHealth::Unknown => 10,
+            },
+            XMountEvent::Deviation { deviation, .. } => match deviation {
+                Deviation::Missing => 11,
+                Deviation::Unexpected => 12,
+                Deviation::Mismatch { .. } => 13,
+            },
+            XMountEvent::ParseAnomaly { .. } => 14,
+        }
+    }
+}
+
+impl MountInfo {
+    /// `name`, bare (`fstype`) or under `prefix` (`info.fstype`).
+    fn field_in(&self, prefix: &str, name: &str) -> Option<FieldValue<'_>> {
+        let name = name.strip_prefix(prefix).and_then(|n| n.strip_prefix('.')).unwrap_or(name);
+        Some(match name {
+            "mount_id" => FieldValue::int(self.mount_id),
+            "parent_id" => FieldValue::int(self.parent_id),
+            "mount_point" => FieldValue::Path(&self.mount_point),
+            "root" => FieldValue::Path(&self.root),
+            "fstype" => FieldValue::str(&self.fstype),
+            "source" => FieldValue::str(&self.source),
+            "mount_opts" => FieldValue::str(&self.mount_opts),
+            "super_opts" => FieldValue::str(&self.super_opts),
+            "class" => FieldValue::str(format!("{:?}", self.class)),
+            _ => return None,
+        })
+    }
+}
+
+/// `target`, and the [`MountInfo`] fields by name (`fstype`) or under the variant's field
+/// holding it (`info.fstype`, `last.fstype`, `old.fstype`, `new.fstype`). In `Changed`, bare
+/// names are those of `new`. `Unmounted` and `WillUnmount` have a `reason`, `FsHealthChanged`
+/// its `fstype`, `old` and `new` health, `Deviation` its `deviation` (`missing`, `unexpected`,
+/// `mismatch`), the mismatched `field` with `expected` and `actual`, and the mount found.
+/// `ParseAnomaly` has the `source` file, the `raw` line, and the `column` and `reason`.
+impl EventFields for XMountEvent {
+    fn kind(&self) -> &'static str {
+        match self {
+            XMountEvent::Mounted { .. } => "mounted",
+            XMountEvent::Unmounted { .. } => "unmounted",
+            XMountEvent::Changed { .. } => "changed",
+            XMountEvent::WillUnmount { .. } => "will_unmount",
+            XMountEvent::AutomountArmed { .. } => "automount_armed",
+            XMountEvent::FsHealthChanged { .. } => "fs_health_changed",
+            XMountEvent::Deviation { .. } => "deviation",
+            XMountEvent::ParseAnomaly { .. } => "parse_anomaly",
+        }
+    }
+
+    fn field(&self, name: &str) -> Option<FieldValue<'_>> {
+        if name == "target" {
+            return Some(FieldValue::Path(self.target()));
         }
+        match self {
+            XMountEvent::Mounted { info, .. } | XMountEvent::AutomountArmed { info, .. } => info.field_in("info", name),
+            XMountEvent::Unmounted { last, reason, .. } => match (name, reason) {
+                ("reason", Some(UnmountReason::AutomountExpired)) => Some(FieldValue::str("automount_expired")),
+                ("reason", Some(UnmountReason::AutomountDisarmed)) => Some(FieldValue::str("automount_disarmed")),
+                ("reason", None) => None,
+                _ => last.field_in("last", name),
+            },
+            XMountEvent::WillUnmount { info, reason, .. } => match name {
+                "reason" => Some(FieldValue::str(format!("{reason:?}"))),
+                _ => info.field_in("info", name),
+            },
+            XMountEvent::Changed { old, new, .. } => match name.strip_prefix("old.") {
+                Some(n) => old.field_in("", n),
+                None => new.field_in("new", name),
+            },
+            XMountEvent::FsHealthChanged { fstype, old, new, .. } => match name {
+                "fstype" => Some(FieldValue::str(fstype)),
+                "old" => Some(FieldValue::str(format!("{old:?}"))),
+                "new" => Some(FieldValue::str(format!("{new:?}"))),
+                _ => None,
+            },
+            XMountEvent::Deviation { deviation, info, .. } => match (name, deviation) {
+                ("deviation", d) => Some(FieldValue::str(d.name())),
+                ("field", Deviation::Mismatch { field, .. }) => Some(FieldValue::str(field)),
+                ("expected", Deviation::Mismatch { expected, .. }) => Some(FieldValue::str(expected)),
+                ("actual", Deviation::Mismatch { actual, .. }) => Some(FieldValue::str(actual)),
+                ("field" | "expected" | "actual", _) => None,
+                _ => info.as_ref()?.field_in("info", name),
+            },
+            XMountEvent::ParseAnomaly { anomaly, .. } => anomaly.field(name),
+        }
+    }
+
+    fn field_names(&self) -> &'static [&'static str] {
+        const MOUNT: &[&str] = &["target", "mount_id", "parent_id", "mount_point", "root", "fstype", "source", "mount_opts", "super_opts", "class"];
+        const REASON: &[&str] =
+            &["target", "mount_id", "parent_id", "mount_point", "root", "fstype", "source", "mount_opts", "super_opts", "class", "reason"];
+        match self {
+            XMountEvent::Mounted { .. } | XMountEvent::AutomountArmed { .. } | XMountEvent::Changed { .. } => MOUNT,
+            XMountEvent::Unmounted { .. } | XMountEvent::WillUnmount { .. } => REASON,
+            XMountEvent::FsHealthChanged { .. } => &["target", "fstype", "old", "new"],
+            XMountEvent::ParseAnomaly { .. } => &["target", "source", "raw", "column", "reason"],
+            XMountEvent::Deviation { .. } => &[
+                "target",
+                "deviation",
+                "field",
+                "expected",
+                "actual",
+                "mount_id",
+                "parent_id",
+                "mount_point",
+                "root",
+                "fstype",
+                "source",
+                "mount_opts",
+                "super_opts",
+                "class",
+            ],
+        }
+    }
+
+    fn summary_fields(&self) -> &'static [&'static str] {
+        match self {
+            XMountEvent::Mounted { .. } | XMountEvent::AutomountArmed { .. } => &["target", "source", "fstype"],
+            XMountEvent::Unmounted { .. } => &["target", "source", "fstype", "reason"],
+            XMountEvent::WillUnmount { .. } => &["target", "reason"],
+            XMountEvent::Changed { .. } => &["target", "old.source", "new.source", "old.fstype", "new.fstype"],
+            XMountEvent::FsHealthChanged { .. } => &["target", "fstype", "old", "new"],
+            XMountEvent::Deviation { .. } => &["target", "deviation", "field", "expected", "actual"],
+            XMountEvent::ParseAnomaly { .. } => &["source", "column", "reason"],
+        }
+    }
+
+    fn detail_fields(&self) -> &'static [&'static str] {
+        const MOUNT: &[&str] = &["target", "mount_point", "source", "fstype", "mount_opts", "super_opts", "class", "mount_id", "parent_id", "root"];
+        const REASON: &[&str] =
+            &["target", "mount_point", "source", "fstype", "mount_opts", "super_opts", "class", "mount_id", "parent_id", "root", "reason"];
+        match self {
+            XMountEvent::Mounted { .. } | XMountEvent::AutomountArmed { .. } => MOUNT,
+            XMountEvent::Unmounted { .. } | XMountEvent::WillUnmount { .. } => REASON,
+            XMountEvent::Changed { .. } => &[
+                "target",
+                "mount_point",
+                "old.source",
+                "new.source",
+                "old.fstype",
+                "new.fstype",
+                "old.mount_opts",
+                "new.mount_opts",
+                "old.super_opts",
+                "new.super_opts",
+                "class",
+                "mount_id",
+                "parent_id",
+                "root",
+            ],
+            XMountEvent::FsHealthChanged { .. } | XMountEvent::ParseAnomaly { .. } => self.field_names(),
+            XMountEvent::Deviation { .. } => &[
+                "target",
+                "deviation",
+                "field",
+                "expected",
+                "actual",
+                "mount_point",
+                "source",
+                "fstype",
+                "mount_opts",
+                "super_opts",
+                "class",
+                "mount_id",
+                "parent_id",
+                "root",
+            ],
+        }
+    }
+
+    fn topic(&self) -> Option<&'static str> {
+        Some(XMountEvent::topic(self))
     }
 }
@@ -0,0 +1,80 @@
+//! Mounts declared by configuration management, compared when the sensor primes. See
+//! [`crate::XMount::expect`] and [`omnitrace_core::expected`].
+
+use crate::events::MountInfo;
+use omnitrace_core::expected::Deviation;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// What should (or should not) be mounted at `mount_point`. Unset attributes are not checked.
+///
+/// ```json
+/// [
+///   { "mount_point": "/data", "fstype": "xfs", "source": "/dev/sdb1", "options": ["nosuid"] },
+///   { "mount_point": "/media/usb", "absent": true }
+/// ]
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExpectedMount {
+    #[serde(with = "omnitrace_core::paths")]
+    pub mount_point: PathBuf,
+    /// Nothing should be mounted there.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub absent: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fstype: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Options the mount must have, per mount or per superblock, e.g. `"ro"`, `"nosuid"`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+}
+
+impl ExpectedMount {
+    pub fn new<P: AsRef<Path>>(mount_point: P) -> Self {
+        Self { mount_point: mount_point.as_ref().to_path_buf(), absent: false, fstype: None, source: None, options: Vec::new() }
+    }
+
+    /// Nothing should be mounted at `mount_point`.
+    pub fn absent<P: AsRef<Path>>(mount_point: P) -> Self {
+        Self { absent: true, ..Self::new(mount_point) }
+    }
+
+    pub fn fstype(mut self, fstype: &str) -> Self {
+        self.fstype = Some(fstype.to_string());
+        self
+    }
+
+    pub fn source(mut self, source: &str) -> Self {
+        self.source = Some(source.to_string());
+        self
+    }
+
+    pub fn option(mut self, opt: &str) -> Self {
+        self.options.push(opt.to_string());
+        self
+    }
+
+    /// How `found`, the mount at `mount_point` if any, differs. One Mismatch per attribute,
+    /// `options` listing the declared ones against the mount options found.
+    pub(crate) fn deviations(&self, found: Option<&MountInfo>) -> Vec<Deviation> {
+        let mi = match (found, self.absent) {
+            (None, false) => return vec![Deviation::Missing],
+            (Some(_), true) => return vec![Deviation::Unexpected],
+            (None, true) => return Vec::new(),
+            (Some(mi), false) => mi,
+        };
+        let mut out = Vec::new();
+        if let Some(fstype) = self.fstype.as_ref().filter(|f| **f != mi.fstype) {
+            out.push(Deviation::mismatch("fstype", fstype, &mi.fstype));
+        }
+        if let Some(source) = self.source.as_ref().filter(|s| **s != mi.source) {
+            out.push(Deviation::mismatch("source", source, &mi.source));
+        }
+        let has = |opt: &str| mi.mount_opts.split(',').chain(mi.super_opts.split(',')).any(|o| o == opt);
+        if !self.options.iter().all(|o| has(o)) {
+            out.push(Deviation::mismatch("options", self.options.join(","), &mi.mount_opts));
+        }
+        out
+    }
+}
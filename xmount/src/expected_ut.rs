@@ -0,0 +1,122 @@
+use crate::{
+    XMount, XMountConfig,
+    events::{MountInfo, XMountEvent, XMountMask},
+    expected::ExpectedMount,
+};
+use async_trait::async_trait;
+use omnitrace_core::{
+    callbacks::{Callback, CallbackHub, CallbackResult},
+    expected::{self, Deviation},
+    fields::{EventFields, FieldValue},
+    sensor::spawn_sensor,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+const ROOT: &str = "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw";
+const DATA: &str = "40 22 8:17 / /data rw,relatime - ext4 /dev/sdb1 rw";
+const USB: &str = "41 22 8:33 / /media/usb rw,nosuid - vfat /dev/sdc1 rw";
+const BACKUP: &str = "42 22 8:49 / /backup rw,relatime - xfs /dev/sdd1 rw";
+
+fn write_mountinfo(path: &Path, lines: &[&str]) {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, lines.join("\n") + "\n").unwrap();
+    std::fs::rename(&tmp, path).unwrap();
+}
+
+struct Recorder(Arc<Mutex<Vec<XMountEvent>>>);
+
+#[async_trait]
+impl Callback<XMountEvent> for Recorder {
+    fn mask(&self) -> u64 {
+        XMountMask::all().bits()
+    }
+
+    async fn call(&self, ev: &XMountEvent) -> Option<CallbackResult> {
+        self.0.lock().unwrap().push(ev.clone());
+        None
+    }
+}
+
+fn deviation(ev: &XMountEvent) -> Option<(&Path, &Deviation)> {
+    match ev {
+        XMountEvent::Deviation { target, deviation, .. } => Some((target, deviation)),
+        _ => None,
+    }
+}
+
+#[test]
+fn specs_compare_each_declared_attribute() {
+    let mi = MountInfo { mount_opts: "rw,nosuid".into(), super_opts: "rw,noquota".into(), ..MountInfo::test("/data") };
+    assert!(ExpectedMount::new("/data").fstype("ext4").source("/dev/test").option("nosuid").option("noquota").deviations(Some(&mi)).is_empty());
+    assert_eq!(ExpectedMount::new("/data").deviations(None), vec![Deviation::Missing]);
+    assert_eq!(ExpectedMount::absent("/data").deviations(Some(&mi)), vec![Deviation::Unexpected]);
+    assert!(ExpectedMount::absent("/data").deviations(None).is_empty());
+    assert_eq!(
+        ExpectedMount::new("/data").fstype("xfs").option("ro").option("nosuid").deviations(Some(&mi)),
+        vec![Deviation::mismatch("fstype", "xfs", "ext4"), Deviation::mismatch("options", "ro,nosuid", "rw,nosuid")]
+    );
+}
+
+#[test]
+fn specs_load_from_json() {
+    let path = std::env::temp_dir().join(format!("xmount-expected-ut-{}.json", std::process::id()));
+    std::fs::write(&path, r#"[{ "mount_point": "/data", "fstype": "xfs", "options": ["nosuid"] }, { "mount_point": "/media/usb", "absent": true }]"#)
+        .unwrap();
+    let specs: Vec<ExpectedMount> = expected::load(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(specs, vec![ExpectedMount::new("/data").fstype("xfs").option("nosuid"), ExpectedMount::absent("/media/usb")]);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn deviations_fire_on_prime_before_changes() {
+    let mountinfo = std::env::temp_dir().join(format!("xmount-expected-ut-{}-prime", std::process::id()));
+    write_mountinfo(&mountinfo, &[ROOT, DATA, USB]);
+    let mut sensor = XMount::new(XMountConfig::default().pulse(Duration::from_millis(10)).mountinfo_path(&mountinfo));
+    sensor.expect(vec![
+        ExpectedMount::new("/").fstype("ext4"),
+        ExpectedMount::new("/data").fstype("xfs").option("ro"),
+        ExpectedMount::new("/backup"),
+        ExpectedMount::absent("/media/usb"),
+        ExpectedMount::absent("/mnt/scratch"),
+    ]);
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(events.clone()));
+    let (handle, task) = spawn_sensor(sensor, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    write_mountinfo(&mountinfo, &[ROOT, DATA, USB, BACKUP]);
+    tokio::time::sleep(Duration::from_millis(80)).await;
+    handle.shutdown();
+    let _ = task.await;
+    let _ = std::fs::remove_file(&mountinfo);
+
+    let events = events.lock().unwrap();
+    let mut found: Vec<(PathBuf, Deviation)> = events.iter().filter_map(deviation).map(|(t, d)| (t.to_path_buf(), d.clone())).collect();
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        found,
+        vec![
+            (PathBuf::from("/backup"), Deviation::Missing),
+            (PathBuf::from("/data"), Deviation::mismatch("fstype", "xfs", "ext4")),
+            (PathBuf::from("/data"), Deviation::mismatch("options", "ro", "rw,relatime")),
+            (PathBuf::from("/media/usb"), Deviation::Unexpected),
+        ]
+    );
+    // then the live loop, with the declared mount points watched
+    assert!(events[..4].iter().all(|ev| deviation(ev).is_some()));
+    assert!(matches!(&events[4..], [XMountEvent::Mounted { target, .. }] if target == Path::new("/backup")));
+
+    let usb = events.iter().find(|ev| ev.target() == Path::new("/media/usb")).unwrap();
+    assert_eq!(usb.topic(), "mount.deviation.unexpected");
+    assert_eq!(usb.field("deviation"), Some(FieldValue::str("unexpected")));
+    assert_eq!(usb.field("fstype"), Some(FieldValue::str("vfat")));
+    let json = serde_json::to_value(usb).unwrap();
+    assert_eq!(json["Deviation"]["deviation"], "unexpected");
+    assert_eq!(json["Deviation"]["info"]["source"], "/dev/sdc1");
+}
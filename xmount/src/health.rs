@@ -0,0 +1,358 @@
+//! Filesystem health of watched mounts.
+//!
+//! The mount table only shows a btrfs that lost a device or a zpool gone DEGRADED once it gets
+//! remounted or unmounted, if ever. A [`HealthProbe`] asks the filesystem itself: registered
+//! with [`crate::XMount::add_health_probe`], it runs for the watched mounts of its fstypes
+//! every [`crate::XMountConfig::health_every`] ticks, on a blocking thread, and
+//! `FsHealthChanged` fires when the health it reports moves. The first report of a mount is
+//! its baseline. A failing probe reports `Unknown`, and its error is recorded once in
+//! [`crate::XMount::diagnostics`] as `"health:<target>"` until the probe succeeds again.
+//!
+//! [`BtrfsSysfs`] reads `/sys/fs/btrfs`; `Zfs` (feature `zfs`) reads the pool state from
+//! `/proc/spl/kstat/zfs`. [`FuseLiveness`] checks that FUSE daemons still answer, added for all
+//! FUSE mounts with [`crate::XMountConfig::fuse_liveness`]. Other filesystems can bring their
+//! own probe.
+
+use crate::{
+    error::XMountError,
+    events::{FsHealth, MountInfo, XMountEvent},
+};
+use omnitrace_core::{error::Diagnostics, labels::Labels, units::HumanDuration};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    time::Duration,
+};
+
+/// What a probe found: the health, and why.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HealthReport {
+    pub health: FsHealth,
+    /// Human readable findings, e.g. `"devid 2 missing"`. Empty when healthy.
+    pub details: Vec<String>,
+}
+
+impl HealthReport {
+    pub fn new(health: FsHealth, details: Vec<String>) -> Self {
+        Self { health, details }
+    }
+
+    pub fn healthy() -> Self {
+        Self::new(FsHealth::Healthy, Vec::new())
+    }
+}
+
+/// Asks a filesystem how it is doing, see [`crate::health`].
+pub trait HealthProbe: Send + Sync {
+    /// Filesystem types probed, as spelled in the mount table (e.g. `"btrfs"`).
+    fn fstypes(&self) -> &[&str];
+
+    /// Whether `mount` is probed: by default, if its fstype is one of [`HealthProbe::fstypes`].
+    fn takes(&self, mount: &MountInfo) -> bool {
+        self.fstypes().contains(&mount.fstype.as_str())
+    }
+
+    /// Health of the filesystem mounted as `mount`. Runs on a blocking thread, so it may read
+    /// files or run commands.
+    fn probe(&self, mount: &MountInfo) -> io::Result<HealthReport>;
+}
+
+/// btrfs, from `/sys/fs/btrfs/<fsid>`. The filesystem is found by the kernel name of its
+/// mount source among `devices/`. It is
+/// - `Faulted` when the kernel forced it read-only (superblock `ro` under a `rw` mount),
+/// - `Degraded` when mounted `degraded`, a device is `missing`, or a device's `error_stats`
+///   are not all zero. These counters persist until reset with `btrfs device stats -z`.
+pub struct BtrfsSysfs {
+    root: PathBuf,
+}
+
+impl Default for BtrfsSysfs {
+    fn default() -> Self {
+        Self::at("/sys/fs/btrfs")
+    }
+}
+
+impl BtrfsSysfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read another sysfs tree, e.g. a fixture.
+    pub fn at<P: AsRef<Path>>(root: P) -> Self {
+        Self { root: root.as_ref().to_path_buf() }
+    }
+
+    /// The `/sys/fs/btrfs/<fsid>` directory holding `source` among its devices.
+    fn fs_dir(&self, source: &str) -> io::Result<PathBuf> {
+        // /dev/mapper/x is the kernel's dm-N
+        let dev = fs::canonicalize(source).unwrap_or_else(|_| PathBuf::from(source));
+        let name = dev.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("no device in {source:?}")))?;
+        for fsid in fs::read_dir(&self.root)? {
+            let fsid = fsid?.path();
+            if fsid.join("devices").join(name).exists() {
+                return Ok(fsid);
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::NotFound, format!("no btrfs filesystem with device {}", name.to_string_lossy())))
+    }
+}
+
+impl HealthProbe for BtrfsSysfs {
+    fn fstypes(&self) -> &[&str] {
+        &["btrfs"]
+    }
+
+    fn probe(&self, mount: &MountInfo) -> io::Result<HealthReport> {
+        let has = |opts: &str, o: &str| opts.split(',').any(|x| x == o);
+        if has(&mount.super_opts, "ro") && has(&mount.mount_opts, "rw") {
+            return Ok(HealthReport::new(FsHealth::Faulted, vec!["forced read-only".to_string()]));
+        }
+
+        let mut details = Vec::new();
+        if has(&mount.super_opts, "degraded") {
+            details.push("mounted degraded".to_string());
+        }
+
+        let mut devids: Vec<(u64, PathBuf)> = Vec::new();
+        for d in fs::read_dir(self.fs_dir(&mount.source)?.join("devinfo"))? {
+            let d = d?;
+            if let Some(id) = d.file_name().to_str().and_then(|n| n.parse().ok()) {
+                devids.push((id, d.path()));
+            }
+        }
+        devids.sort();
+
+        for (id, dir) in devids {
+            if fs::read_to_string(dir.join("missing")).is_ok_and(|m| m.trim() == "1") {
+                details.push(format!("devid {id} missing"));
+                continue;
+            }
+            // error_stats needs Linux 5.14
+            let Ok(stats) = fs::read_to_string(dir.join("error_stats")) else {
+                continue;
+            };
+            for line in stats.lines() {
+                if let Some((name, n)) = line.split_once(' ')
+                    && n.trim().parse::<u64>().is_ok_and(|n| n > 0)
+                {
+                    details.push(format!("devid {id} {name} {}", n.trim()));
+                }
+            }
+        }
+
+        Ok(if details.is_empty() { HealthReport::healthy() } else { HealthReport::new(FsHealth::Degraded, details) })
+    }
+}
+
+/// ZFS, from the pool state in `/proc/spl/kstat/zfs/<pool>/state`. `ONLINE` is `Healthy`,
+/// `DEGRADED` is `Degraded`, `FAULTED`, `SUSPENDED`, `UNAVAIL`, `REMOVED` and `OFFLINE` are
+/// `Faulted`.
+#[cfg(feature = "zfs")]
+pub struct Zfs {
+    root: PathBuf,
+}
+
+#[cfg(feature = "zfs")]
+impl Default for Zfs {
+    fn default() -> Self {
+        Self::at("/proc/spl/kstat/zfs")
+    }
+}
+
+#[cfg(feature = "zfs")]
+impl Zfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read another kstat tree, e.g. a fixture.
+    pub fn at<P: AsRef<Path>>(root: P) -> Self {
+        Self { root: root.as_ref().to_path_buf() }
+    }
+}
+
+#[cfg(feature = "zfs")]
+impl HealthProbe for Zfs {
+    fn fstypes(&self) -> &[&str] {
+        &["zfs"]
+    }
+
+    fn probe(&self, mount: &MountInfo) -> io::Result<HealthReport> {
+        // the source is the dataset, e.g. tank/home
+        let pool = mount.source.split('/').next().unwrap_or_default();
+        let state = fs::read_to_string(self.root.join(pool).join("state"))?;
+        let state = state.trim();
+        let health = match state {
+            "ONLINE" => return Ok(HealthReport::healthy()),
+            "DEGRADED" => FsHealth::Degraded,
+            "FAULTED" | "SUSPENDED" | "UNAVAIL" | "REMOVED" | "OFFLINE" => FsHealth::Faulted,
+            _ => FsHealth::Unknown,
+        };
+        Ok(HealthReport::new(health, vec![format!("pool {pool} {state}")]))
+    }
+}
+
+type StatFn = dyn Fn(&Path) -> io::Result<()> + Send + Sync;
+
+/// FUSE mounts, by a `stat` of the mount point answered within `timeout`. The daemon serving a
+/// FUSE mount can hang or die with the mount still in place, and every access blocks then, or
+/// fails with "transport endpoint is not connected": both are `Faulted`. The stat runs on a
+/// thread of its own, left behind when it does not return; until it does, the mount is
+/// reported `Faulted` again without another one.
+pub struct FuseLiveness {
+    timeout: Duration,
+    stat: Arc<StatFn>,
+    // mount point -> set once its stat that timed out returns
+    pending: Mutex<HashMap<PathBuf, Arc<AtomicBool>>>,
+}
+
+impl FuseLiveness {
+    pub fn new(timeout: Duration) -> Self {
+        Self::stat_with(timeout, |p| fs::metadata(p).map(drop))
+    }
+
+    /// Check with `stat` instead of a `stat(2)` of the mount point, e.g. to simulate a stall.
+    pub fn stat_with<F: Fn(&Path) -> io::Result<()> + Send + Sync + 'static>(timeout: Duration, stat: F) -> Self {
+        Self { timeout, stat: Arc::new(stat), pending: Mutex::new(HashMap::new()) }
+    }
+
+    fn stalled(&self) -> HealthReport {
+        HealthReport::new(FsHealth::Faulted, vec![format!("no answer within {}", HumanDuration(self.timeout))])
+    }
+}
+
+impl HealthProbe for FuseLiveness {
+    fn fstypes(&self) -> &[&str] {
+        &["fuse", "fuseblk"]
+    }
+
+    fn takes(&self, mount: &MountInfo) -> bool {
+        mount.fuse
+    }
+
+    fn probe(&self, mount: &MountInfo) -> io::Result<HealthReport> {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(done) = pending.get(&mount.mount_point) {
+            if !done.load(Ordering::Acquire) {
+                return Ok(self.stalled());
+            }
+            pending.remove(&mount.mount_point);
+        }
+
+        let (tx, rx) = mpsc::sync_channel(1);
+        let done = Arc::new(AtomicBool::new(false));
+        let (stat, path, flag) = (self.stat.clone(), mount.mount_point.clone(), done.clone());
+        std::thread::Builder::new().name("xmount-fuse-stat".into()).spawn(move || {
+            let res = stat(&path);
+            flag.store(true, Ordering::Release);
+            let _ = tx.send(res);
+        })?;
+        match rx.recv_timeout(self.timeout) {
+            Ok(Ok(())) => Ok(HealthReport::healthy()),
+            Ok(Err(e)) if e.raw_os_error() == Some(libc::ENOTCONN) => {
+                Ok(HealthReport::new(FsHealth::Faulted, vec!["transport endpoint is not connected".to_string()]))
+            }
+            Ok(Err(e)) => Err(e),
+            Err(_) => {
+                pending.insert(mount.mount_point.clone(), done);
+                Ok(self.stalled())
+            }
+        }
+    }
+}
+
+/// A probe's outcome for one watched mount.
+pub(crate) struct Probed {
+    target: PathBuf,
+    fstype: String,
+    report: io::Result<HealthReport>,
+}
+
+/// A probe to run on a blocking thread, see [`HealthWatch::jobs`].
+pub(crate) type Job = (PathBuf, MountInfo, Arc<dyn HealthProbe>);
+
+/// Run `jobs`, blocking.
+pub(crate) fn run(jobs: Vec<Job>) -> Vec<Probed> {
+    jobs.into_iter().map(|(target, mi, probe)| Probed { report: probe.probe(&mi), fstype: mi.fstype.to_string(), target }).collect()
+}
+
+/// The probes and the last health per target.
+pub(crate) struct HealthWatch {
+    probes: Vec<Arc<dyn HealthProbe>>,
+    every: u32,
+    ticks: u32,
+    last: HashMap<PathBuf, FsHealth>,
+}
+
+impl HealthWatch {
+    pub(crate) fn new(every: u32) -> Self {
+        Self { probes: Vec::new(), every: every.max(1), ticks: 0, last: HashMap::new() }
+    }
+
+    pub(crate) fn add(&mut self, probe: Arc<dyn HealthProbe>) {
+        self.probes.push(probe);
+    }
+
+    /// Count a tick; true on the ones probes run at, starting with the first.
+    pub(crate) fn due(&mut self) -> bool {
+        let due = self.ticks.is_multiple_of(self.every);
+        self.ticks = (self.ticks + 1) % self.every;
+        due && !self.probes.is_empty()
+    }
+
+    /// The first probe taking each mount's fstype. Targets no longer mounted are forgotten.
+    pub(crate) fn jobs(&mut self, mounted: &HashMap<PathBuf, MountInfo>) -> Vec<Job> {
+        self.last.retain(|t, _| mounted.contains_key(t));
+        let mut jobs = Vec::new();
+        for (target, mi) in mounted {
+            if let Some(p) = self.probes.iter().find(|p| p.takes(mi)) {
+                jobs.push((target.clone(), mi.clone(), p.clone()));
+            }
+        }
+        jobs.sort_by(|a, b| a.0.cmp(&b.0));
+        jobs
+    }
+
+    /// Events for the targets whose health moved. Failures count as `Unknown` and are recorded
+    /// in `diagnostics`, logged once.
+    pub(crate) fn update(&mut self, probed: Vec<Probed>, diagnostics: &Diagnostics) -> Vec<XMountEvent> {
+        let mut out = Vec::new();
+        for Probed { target, fstype, report } in probed {
+            let key = format!("health:{}", target.display());
+            let report = match report {
+                Ok(r) => {
+                    diagnostics.clear(&key);
+                    r
+                }
+                Err(source) => {
+                    let details = vec![source.to_string()];
+                    diagnostics.report_once(&key, XMountError::Health { target: target.clone(), fstype: fstype.clone(), source });
+                    HealthReport::new(FsHealth::Unknown, details)
+                }
+            };
+            match self.last.insert(target.clone(), report.health) {
+                Some(old) if old != report.health => out.push(XMountEvent::FsHealthChanged {
+                    target,
+                    fstype,
+                    old,
+                    new: report.health,
+                    details: report.details,
+                    labels: Labels::default(),
+                }),
+                _ => {}
+            }
+        }
+        out
+    }
+
+    /// Last health per target, for the debug state.
+    pub(crate) fn states(&self) -> BTreeMap<String, FsHealth> {
+        self.last.iter().map(|(t, h)| (t.display().to_string(), *h)).collect()
+    }
+}
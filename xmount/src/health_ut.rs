@@ -0,0 +1,214 @@
+use crate::{
+    XMount, XMountConfig,
+    events::{FsHealth, MountInfo, XMountEvent, XMountMask},
+    health::{self, BtrfsSysfs, FuseLiveness, HealthProbe, HealthReport, HealthWatch},
+};
+use async_trait::async_trait;
+use omnitrace_core::{
+    callbacks::{Callback, CallbackHub, CallbackResult},
+    error::Diagnostics,
+    sensor::spawn_sensor,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc::channel;
+
+fn btrfs(target: &str, source: &str, mount_opts: &str, super_opts: &str) -> MountInfo {
+    MountInfo {
+        fstype: "btrfs".into(),
+        source: source.into(),
+        mount_opts: mount_opts.into(),
+        super_opts: super_opts.into(),
+        ..MountInfo::test(target)
+    }
+}
+
+fn sysfs() -> BtrfsSysfs {
+    BtrfsSysfs::at(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/btrfs-sysfs"))
+}
+
+#[test]
+fn btrfs_health_from_sysfs_fixtures() {
+    let probe = |mi: MountInfo| sysfs().probe(&mi);
+
+    assert_eq!(probe(btrfs("/data", "/dev/sdb", "rw,relatime", "rw,ssd,space_cache=v2")).unwrap(), HealthReport::healthy());
+
+    // raid1 with its second device gone and errors recorded on the first
+    let pool = probe(btrfs("/pool", "/dev/sdc", "rw,relatime", "rw,degraded,space_cache=v2")).unwrap();
+    assert_eq!(pool.health, FsHealth::Degraded);
+    assert_eq!(pool.details, ["mounted degraded", "devid 1 write_errs 3", "devid 1 corruption_errs 1", "devid 2 missing"]);
+
+    let aborted = probe(btrfs("/data", "/dev/sdb", "rw,relatime", "ro,ssd,space_cache=v2")).unwrap();
+    assert_eq!(aborted, HealthReport::new(FsHealth::Faulted, vec!["forced read-only".to_string()]));
+
+    assert_eq!(probe(btrfs("/other", "/dev/sde", "rw", "rw")).unwrap_err().kind(), io::ErrorKind::NotFound);
+}
+
+#[cfg(feature = "zfs")]
+#[test]
+fn zfs_health_from_pool_state() {
+    let zfs = health::Zfs::at(Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/zfs-kstat"));
+    let dataset = |source: &str| MountInfo { fstype: "zfs".into(), source: source.into(), ..MountInfo::test("/tank") };
+
+    assert_eq!(zfs.probe(&dataset("tank/home")).unwrap(), HealthReport::healthy());
+    let backup = zfs.probe(&dataset("backup")).unwrap();
+    assert_eq!((backup.health, backup.details), (FsHealth::Degraded, vec!["pool backup DEGRADED".to_string()]));
+    assert!(zfs.probe(&dataset("gone/data")).is_err());
+}
+
+#[test]
+fn fuse_liveness_faults_stalled_and_disconnected_daemons() {
+    // the stat blocks while `gate` is held, and fails with `errno` if set
+    let gate = Arc::new(Mutex::new(()));
+    let errno = Arc::new(Mutex::new(None::<i32>));
+    let calls = Arc::new(AtomicUsize::new(0));
+    let (g, e, c) = (gate.clone(), errno.clone(), calls.clone());
+    let probe = FuseLiveness::stat_with(Duration::from_millis(50), move |_| {
+        c.fetch_add(1, Ordering::Relaxed);
+        drop(g.lock().unwrap());
+        e.lock().unwrap().map_or(Ok(()), |n| Err(io::Error::from_raw_os_error(n)))
+    });
+    let sshfs = MountInfo { fstype: "fuse.sshfs".into(), fuse: true, ..MountInfo::test("/mnt/share") };
+    assert!(probe.takes(&sshfs) && !probe.takes(&MountInfo::test("/boot")));
+    assert_eq!(probe.probe(&sshfs).unwrap(), HealthReport::healthy());
+
+    *errno.lock().unwrap() = Some(libc::ENOTCONN);
+    assert_eq!(probe.probe(&sshfs).unwrap(), HealthReport::new(FsHealth::Faulted, vec!["transport endpoint is not connected".to_string()]));
+    *errno.lock().unwrap() = Some(libc::EACCES);
+    assert_eq!(probe.probe(&sshfs).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    *errno.lock().unwrap() = None;
+
+    // a daemon that does not answer: one stat is left waiting, however often it is probed
+    let held = gate.lock().unwrap();
+    let started = Instant::now();
+    let stalled = HealthReport::new(FsHealth::Faulted, vec!["no answer within 50ms".to_string()]);
+    assert_eq!(probe.probe(&sshfs).unwrap(), stalled);
+    assert_eq!(probe.probe(&sshfs).unwrap(), stalled);
+    assert!(started.elapsed() < Duration::from_millis(500));
+    assert_eq!(calls.load(Ordering::Relaxed), 4);
+
+    // healthy again once it answers
+    drop(held);
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while probe.probe(&sshfs).unwrap() != HealthReport::healthy() {
+        assert!(Instant::now() < deadline, "still stalled");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Hands out one scripted health per probe (`None` fails), the last one forever.
+struct Scripted(Mutex<VecDeque<Option<FsHealth>>>);
+
+impl Scripted {
+    fn new(script: &[Option<FsHealth>]) -> Self {
+        Self(Mutex::new(script.iter().copied().collect()))
+    }
+}
+
+impl HealthProbe for Scripted {
+    fn fstypes(&self) -> &[&str] {
+        &["btrfs"]
+    }
+
+    fn probe(&self, _: &MountInfo) -> io::Result<HealthReport> {
+        let mut q = self.0.lock().unwrap();
+        let next = if q.len() > 1 { q.pop_front().unwrap() } else { q.front().copied().flatten() };
+        next.map(|h| HealthReport::new(h, Vec::new())).ok_or_else(|| io::Error::other("sysfs gone"))
+    }
+}
+
+/// Probe once, as a due tick does.
+fn step(w: &mut HealthWatch, mounted: &HashMap<PathBuf, MountInfo>, d: &Diagnostics) -> Vec<(String, String, FsHealth, FsHealth)> {
+    let jobs = w.jobs(mounted);
+    w.update(health::run(jobs), d)
+        .into_iter()
+        .map(|ev| match ev {
+            XMountEvent::FsHealthChanged { target, fstype, old, new, .. } => (target.display().to_string(), fstype, old, new),
+            other => panic!("unexpected {other:?}"),
+        })
+        .collect()
+}
+
+#[test]
+fn only_transitions_are_reported_and_failures_are_unknown() {
+    use FsHealth::*;
+
+    let mut w = HealthWatch::new(1);
+    w.add(Arc::new(Scripted::new(&[Some(Healthy), Some(Healthy), Some(Degraded), None, None, Some(Healthy), Some(Faulted)])));
+    let mut mounted: HashMap<PathBuf, MountInfo> =
+        [btrfs("/pool", "/dev/sdc", "rw", "rw"), MountInfo::test("/boot")].into_iter().map(|mi| (mi.mount_point.clone(), mi)).collect();
+    let d = Diagnostics::new();
+    let moved = |old, new| vec![("/pool".to_string(), "btrfs".to_string(), old, new)];
+
+    // the first report is the baseline, the ext4 /boot is not probed
+    assert_eq!(w.jobs(&mounted).len(), 1);
+    assert_eq!(step(&mut w, &mounted, &d), []);
+    assert_eq!(step(&mut w, &mounted, &d), []);
+    assert_eq!(step(&mut w, &mounted, &d), moved(Healthy, Degraded));
+
+    assert_eq!(step(&mut w, &mounted, &d), moved(Degraded, Unknown));
+    assert_eq!(step(&mut w, &mounted, &d), []);
+    let failing = d.get("health:/pool").unwrap();
+    assert_eq!((failing.count, failing.message.as_str()), (2, "xmount: btrfs health of /pool: sysfs gone"));
+    assert_eq!(step(&mut w, &mounted, &d), moved(Unknown, Healthy));
+    assert!(d.get("health:/pool").is_none());
+
+    // unmounted and back: a new baseline
+    mounted.remove(Path::new("/pool"));
+    assert_eq!(step(&mut w, &mounted, &d), []);
+    assert!(w.states().is_empty());
+    mounted.insert(PathBuf::from("/pool"), btrfs("/pool", "/dev/sdc", "rw", "rw"));
+    assert_eq!(step(&mut w, &mounted, &d), []);
+    assert_eq!(w.states().get("/pool"), Some(&Faulted));
+
+    let mut every3 = HealthWatch::new(3);
+    every3.add(Arc::new(Scripted::new(&[])));
+    assert_eq!((0..5).map(|_| every3.due()).collect::<Vec<_>>(), [true, false, false, true, false]);
+}
+
+struct HealthCb;
+
+#[async_trait]
+impl Callback<XMountEvent> for HealthCb {
+    fn mask(&self) -> u64 {
+        XMountMask::FS_HEALTH_CHANGED.bits()
+    }
+
+    async fn call(&self, ev: &XMountEvent) -> Option<CallbackResult> {
+        serde_json::to_value(ev).ok()
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn sensor_fires_health_changes_of_watched_mounts() {
+    let mountinfo = std::env::temp_dir().join(format!("xmount-health-ut-{}", std::process::id()));
+    std::fs::write(&mountinfo, "22 1 8:1 / / rw,relatime - ext4 /dev/sda1 rw\n41 22 0:45 / /mnt/xmount-ut-pool rw,relatime - btrfs /dev/sdc rw\n")
+        .unwrap();
+
+    let mut sensor = XMount::new(XMountConfig::default().pulse(Duration::from_millis(10)).mountinfo_path(&mountinfo).health_every(2));
+    sensor.add("/mnt/xmount-ut-pool");
+    sensor.add_health_probe(Scripted::new(&[Some(FsHealth::Healthy), Some(FsHealth::Degraded)]));
+
+    let (tx, mut rx) = channel::<CallbackResult>(4);
+    let mut hub = CallbackHub::<XMountEvent>::new();
+    hub.add(HealthCb);
+    hub.set_result_channel(tx);
+    let (handle, task) = spawn_sensor(sensor, Arc::new(hub));
+    let ev = tokio::time::timeout(Duration::from_millis(500), rx.recv()).await.unwrap().unwrap();
+    handle.shutdown();
+    let _ = task.await;
+    let _ = std::fs::remove_file(&mountinfo);
+
+    let h = &ev["FsHealthChanged"];
+    assert_eq!((h["target"].as_str(), h["fstype"].as_str()), (Some("/mnt/xmount-ut-pool"), Some("btrfs")));
+    assert_eq!((h["old"].as_str(), h["new"].as_str()), (Some("Healthy"), Some("Degraded")));
+}
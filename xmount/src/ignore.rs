@@ -0,0 +1,208 @@
+//! Mounts to leave out of the picture, by fstype or mount point glob.
+//!
+//! A full mount table is mostly kernel pseudo filesystems and container plumbing. The
+//! built-in [`NO_PSEUDO`] profile ignores them; it is kept here, as data, so the agent
+//! config can name it and single entries can be taken back out:
+//!
+//! ```json
+//! { "profile": "no-pseudo", "unignore_fstypes": ["cgroup2"], "paths": ["/snap/**"] }
+//! ```
+
+use crate::events::{MountClass, MountInfo};
+use globset::{Glob, GlobMatcher};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, fmt, io};
+
+/// Name of the built-in pseudo filesystem profile.
+pub const NO_PSEUDO: &str = "no-pseudo";
+
+/// Kernel pseudo filesystems. `autofs` is not among them: its placeholders are reported as
+/// `AutomountArmed`, see [`crate::XMountConfig::automounts`].
+pub const PSEUDO_FSTYPES: &[&str] = &[
+    "proc",
+    "sysfs",
+    "devtmpfs",
+    "devpts",
+    "cgroup",
+    "cgroup2",
+    "mqueue",
+    "debugfs",
+    "tracefs",
+    "securityfs",
+    "pstore",
+    "bpf",
+    "configfs",
+    "fusectl",
+    "hugetlbfs",
+    "binfmt_misc",
+    "efivarfs",
+    "nsfs",
+    "rpc_pipefs",
+    "selinuxfs",
+    "nfsd",
+    "rootfs",
+    "fuse.lxcfs",
+    "fuse.gvfsd-fuse",
+    "fuse.portal",
+];
+
+/// Mount points of [`NO_PSEUDO`] besides its fstypes: what the kernel, systemd and container
+/// runtimes mount for themselves (overlay layers, sandbox shm, namespaces, runtime dirs).
+pub const PSEUDO_PATHS: &[&str] = &[
+    "/dev/shm",
+    "/proc/sys/fs/binfmt_misc",
+    "/run",
+    "/run/lock",
+    "/run/user/*",
+    "/run/credentials/**",
+    "/run/netns/*",
+    "/run/docker/netns/*",
+    "/run/snapd/ns/*",
+    "/var/lib/docker/**",
+    "/var/lib/containerd/**",
+    "/run/containerd/**",
+    "/var/lib/containers/**",
+    "/run/containers/**",
+];
+
+/// Why a mount was left out, see [`crate::XMount::excluded_by`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Exclusion {
+    Fstype(String),
+    /// The mount point glob that matched.
+    Path(String),
+    /// Its class is ignored, see [`crate::XMount::ignore_class`].
+    Class(MountClass),
+}
+
+impl fmt::Display for Exclusion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Exclusion::Fstype(t) => write!(f, "fstype {t} is ignored"),
+            Exclusion::Path(g) => write!(f, "mount point matches ignored path {g}"),
+            Exclusion::Class(c) => write!(f, "class {c:?} is ignored"),
+        }
+    }
+}
+
+/// Ignored fstypes (exact names) and mount point globs.
+#[derive(Clone, Debug, Default)]
+pub struct IgnoreRules {
+    fstypes: BTreeSet<String>,
+    paths: Vec<(String, GlobMatcher)>,
+}
+
+impl IgnoreRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A built-in profile by name; only [`NO_PSEUDO`] so far.
+    pub fn profile(name: &str) -> Option<Self> {
+        if name != NO_PSEUDO {
+            return None;
+        }
+        let mut rules = Self::new();
+        for t in PSEUDO_FSTYPES {
+            rules.fstype(t);
+        }
+        for p in PSEUDO_PATHS {
+            rules.path(p).ok()?;
+        }
+        Some(rules)
+    }
+
+    pub fn fstype(&mut self, fstype: &str) {
+        self.fstypes.insert(fstype.to_string());
+    }
+
+    /// Take an fstype back out. Returns false if it was not ignored.
+    pub fn unignore_fstype(&mut self, fstype: &str) -> bool {
+        self.fstypes.remove(fstype)
+    }
+
+    pub fn path(&mut self, glob: &str) -> Result<(), globset::Error> {
+        if !self.paths.iter().any(|(g, _)| g == glob) {
+            self.paths.push((glob.to_string(), Glob::new(glob)?.compile_matcher()));
+        }
+        Ok(())
+    }
+
+    /// Take a mount point glob back out, by its exact spelling. Returns false if it was not there.
+    pub fn unignore_path(&mut self, glob: &str) -> bool {
+        let before = self.paths.len();
+        self.paths.retain(|(g, _)| g != glob);
+        self.paths.len() != before
+    }
+
+    /// Add all of `other`'s rules.
+    pub fn merge(&mut self, other: IgnoreRules) {
+        self.fstypes.extend(other.fstypes);
+        for (glob, m) in other.paths {
+            if !self.paths.iter().any(|(g, _)| *g == glob) {
+                self.paths.push((glob, m));
+            }
+        }
+    }
+
+    /// The rule leaving `mi` out, if any.
+    pub fn matches(&self, mi: &MountInfo) -> Option<Exclusion> {
+        if self.fstypes.contains(mi.fstype.as_str()) {
+            return Some(Exclusion::Fstype(mi.fstype.to_string()));
+        }
+        self.paths.iter().find(|(_, m)| m.is_match(&mi.mount_point)).map(|(g, _)| Exclusion::Path(g.clone()))
+    }
+
+    pub fn fstypes(&self) -> Vec<String> {
+        self.fstypes.iter().cloned().collect()
+    }
+
+    pub fn paths(&self) -> Vec<String> {
+        self.paths.iter().map(|(g, _)| g.clone()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fstypes.is_empty() && self.paths.is_empty()
+    }
+}
+
+/// Serializable form of [`IgnoreRules`]: a profile plus additions and exceptions.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IgnoreConfig {
+    #[serde(default)]
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub fstypes: Vec<String>,
+    #[serde(default)]
+    pub paths: Vec<String>,
+    #[serde(default)]
+    pub unignore_fstypes: Vec<String>,
+    #[serde(default)]
+    pub unignore_paths: Vec<String>,
+}
+
+impl IgnoreConfig {
+    /// The rules, or [`io::ErrorKind::InvalidInput`] for an unknown profile or a bad glob.
+    pub fn rules(&self) -> io::Result<IgnoreRules> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+        let mut rules = match &self.profile {
+            Some(name) => IgnoreRules::profile(name).ok_or_else(|| invalid(format!("unknown ignore profile {name:?}")))?,
+            None => IgnoreRules::new(),
+        };
+        for t in &self.fstypes {
+            rules.fstype(t);
+        }
+        for p in &self.paths {
+            rules.path(p).map_err(|e| invalid(e.to_string()))?;
+        }
+        for t in &self.unignore_fstypes {
+            rules.unignore_fstype(t);
+        }
+        for p in &self.unignore_paths {
+            rules.unignore_path(p);
+        }
+        Ok(rules)
+    }
+}
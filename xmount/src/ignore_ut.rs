@@ -0,0 +1,76 @@
+use crate::{
+    XMount, XMountConfig, engine,
+    events::{MountClass, MountInfo},
+    ignore::{Exclusion, IgnoreConfig, IgnoreRules, NO_PSEUDO, PSEUDO_FSTYPES},
+};
+use std::{collections::HashSet, path::PathBuf};
+
+fn workstation() -> Vec<MountInfo> {
+    include_str!("../fixtures/workstation.mountinfo").lines().map(|l| engine::parse_mountinfo_line(l).unwrap()).collect()
+}
+
+fn kept(x: &XMount) -> Vec<String> {
+    workstation().iter().filter(|mi| x.excluded_by(mi).is_none()).map(|mi| mi.mount_point.display().to_string()).collect()
+}
+
+#[test]
+fn no_pseudo_reduces_a_full_mount_table_to_real_mounts() {
+    let mut x = XMount::new(XMountConfig::default());
+    assert_eq!(kept(&x).len(), workstation().len());
+
+    x.ignore_pseudo_filesystems();
+    assert_eq!(kept(&x), ["/", "/boot/efi", "/home", "/tmp", "/mnt/nas", "/media/alice/USBSTICK", "/data"]);
+}
+
+#[test]
+fn single_entries_can_be_unignored() {
+    let mut x = XMount::new(XMountConfig::default());
+    x.ignore_pseudo_filesystems();
+    assert!(x.unignore_fstype("cgroup2"));
+    assert!(!x.unignore_fstype("ext4"));
+    assert!(kept(&x).contains(&"/sys/fs/cgroup".to_string()));
+
+    x.ignore_fstype("nfs4");
+    assert!(!kept(&x).contains(&"/mnt/nas".to_string()));
+}
+
+#[test]
+fn exclusions_name_the_rule() {
+    let mut x = XMount::new(XMountConfig::default());
+    x.ignore_pseudo_filesystems();
+    x.ignore_class(MountClass::NetworkFs);
+    x.ignore_path("/media/*/*").unwrap();
+
+    let why = |mp: &str| x.excluded_by(workstation().iter().find(|mi| mi.mount_point == std::path::Path::new(mp)).unwrap());
+    assert_eq!(why("/sys/fs/bpf"), Some(Exclusion::Fstype("bpf".to_string())));
+    assert_eq!(why("/run/user/1000"), Some(Exclusion::Path("/run/user/*".to_string())));
+    assert_eq!(why("/mnt/nas"), Some(Exclusion::Class(MountClass::NetworkFs)));
+    assert_eq!(why("/media/alice/USBSTICK").map(|e| e.to_string()).as_deref(), Some("mount point matches ignored path /media/*/*"));
+    assert_eq!(why("/data"), None);
+}
+
+#[test]
+fn ignored_mounts_are_not_tracked() {
+    let mut x = XMount::new(XMountConfig::default());
+    x.ignore_pseudo_filesystems();
+    let watched: HashSet<PathBuf> = ["/sys/fs/cgroup", "/run/lock", "/data"].into_iter().map(PathBuf::from).collect();
+    let snap = x.engine.select(Some(&watched), &workstation());
+    assert_eq!(snap.keys().collect::<Vec<_>>(), [&PathBuf::from("/data")]);
+}
+
+#[test]
+fn config_names_the_profile() {
+    let cfg: IgnoreConfig = serde_json::from_str(r#"{ "profile": "no-pseudo", "unignore_fstypes": ["cgroup2"], "paths": ["/snap/**"] }"#).unwrap();
+    let rules = cfg.rules().unwrap();
+    assert!(!rules.fstypes().contains(&"cgroup2".to_string()));
+    assert_eq!(rules.fstypes().len(), PSEUDO_FSTYPES.len() - 1);
+    assert!(rules.paths().contains(&"/snap/**".to_string()));
+
+    let mut x = XMount::new(XMountConfig::default());
+    x.ignore_rules(rules);
+    assert!(kept(&x).contains(&"/sys/fs/cgroup".to_string()));
+
+    assert!(IgnoreRules::profile(NO_PSEUDO).is_some());
+    let unknown: IgnoreConfig = serde_json::from_str(r#"{ "profile": "no-such" }"#).unwrap();
+    assert_eq!(unknown.rules().unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+}
@@ -1,68 +1,484 @@
+pub mod classify;
+#[cfg(feature = "runtime")]
+pub mod demo;
+#[cfg(feature = "runtime")]
+pub mod enforce;
+pub mod engine;
+pub mod error;
 pub mod events;
-use crate::events::{MountInfo, XMountEvent};
-use omnitrace_core::sensor::{Sensor, SensorCtx};
+#[cfg(feature = "runtime")]
+pub mod expected;
+#[cfg(feature = "runtime")]
+pub mod health;
+pub mod ignore;
+#[cfg(feature = "runtime")]
+pub mod prelude;
+#[cfg(feature = "runtime")]
+pub mod preview;
+#[cfg(any(target_os = "windows", test))]
+mod winvol;
+
+#[cfg(all(test, feature = "runtime"))]
+mod demo_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod enforce_ut;
+#[cfg(test)]
+mod engine_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod expected_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod health_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod ignore_ut;
+#[cfg(test)]
+mod winvol_ut;
+#[cfg(all(test, feature = "runtime"))]
+mod xmount_ut;
+
+#[cfg(feature = "runtime")]
+use crate::{
+    engine::MountDiffer,
+    error::XMountError,
+    events::{FsHealth, MountClass, MountInfo, UnmountPrecursor, XMountEvent, XMountMask},
+    expected::ExpectedMount,
+    health::{FuseLiveness, HealthProbe, HealthWatch},
+    ignore::{Exclusion, IgnoreRules},
+    preview::XMountRule,
+};
+#[cfg(feature = "runtime")]
+use async_trait::async_trait;
+#[cfg(feature = "runtime")]
+use omnitrace_core::{
+    anomaly::{AnomalyCapture, ParseAnomaly, ParseStats},
+    callbacks::{Callback, CallbackHub, CallbackResult},
+    clock::{self, SharedClock},
+    debug::DebugCell,
+    entities::{self, EntityCounters},
+    error::Diagnostics,
+    history::{History, HistoryConfig, HistoryHandle},
+    labels::Labels,
+    paths,
+    preflight::PreflightFinding,
+    preview::{PreviewHandle, PreviewQueue, PreviewReport},
+    pulse::{AdaptivePulse, EffectivePulse, Pacer, TimeAnomaly, TimeGaps},
+    sensor::{Sensor, SensorCtx},
+    severity::Severity,
+    tombstones::{self, Tombstones},
+};
+#[cfg(feature = "runtime")]
+use serde::Serialize;
+#[cfg(feature = "runtime")]
 use std::{
-    collections::{HashMap, HashSet},
+    any::Any,
+    collections::{BTreeMap, HashMap, HashSet},
     io,
     path::{Path, PathBuf},
     pin::Pin,
+    sync::{Arc, Mutex},
     time::Duration,
 };
-use tokio::time;
+#[cfg(feature = "runtime")]
+use tokio::sync::mpsc;
 
 /// Configuration for the XMount monitor.
 ///
 /// Controls polling interval and the path to the mountinfo file to read.
+#[cfg(feature = "runtime")]
 pub struct XMountConfig {
     /// Time interval between polling mountinfo for changes
     pulse: Duration,
 
+    /// Adaptive polling between a min and a max pulse, replacing `pulse`
+    adaptive: Option<AdaptivePulse>,
+
     /// Path to the mountinfo file (typically /proc/self/mountinfo)
     mountinfo_path: PathBuf,
+
+    /// Stop the sensor right away if nothing is watched at startup (legacy behaviour)
+    exit_if_empty: bool,
+
+    /// Fire WillUnmount/Unmounted through a barrier with this per-callback timeout
+    barrier_timeout: Option<Duration>,
+
+    /// Tell autofs placeholders apart from the filesystems mounted over them
+    automounts: bool,
+
+    /// What counts as a time gap between ticks, and whether to re-prime after one
+    time_gaps: TimeGaps,
+
+    /// Time source for the time gap detection
+    clock: SharedClock,
+
+    /// Run the health probes every this many ticks
+    health_every: u32,
+
+    /// Keep the state of unwatched mountpoints this long, to diff against when watched again
+    unwatched_ttl: Option<Duration>,
+
+    /// Keep the last events per mountpoint
+    history: Option<HistoryConfig>,
+
+    /// Fire up to this many malformed mountinfo lines a tick as ParseAnomaly
+    capture_parse_anomalies: Option<usize>,
+
+    /// Probe FUSE mounts for a stalled daemon with this timeout
+    fuse_liveness: Option<Duration>,
 }
 
 /// Main struct for monitoring mount events.
+#[cfg(feature = "runtime")]
 impl Default for XMountConfig {
     fn default() -> Self {
-        Self { pulse: Duration::from_secs(1), mountinfo_path: PathBuf::from("/proc/self/mountinfo") }
+        Self {
+            pulse: Duration::from_secs(1),
+            adaptive: None,
+            mountinfo_path: PathBuf::from("/proc/self/mountinfo"),
+            exit_if_empty: false,
+            barrier_timeout: None,
+            automounts: true,
+            time_gaps: TimeGaps::default(),
+            clock: clock::system(),
+            health_every: 10,
+            unwatched_ttl: None,
+            history: None,
+            capture_parse_anomalies: None,
+            fuse_liveness: None,
+        }
     }
 }
 
+#[cfg(feature = "runtime")]
 impl XMountConfig {
     pub fn pulse(mut self, pulse: Duration) -> Self {
         self.pulse = pulse;
         self
     }
 
+    /// Poll every `min` while mounts change, backing off up to `max` while nothing is
+    /// reported, instead of every `pulse`. See [`omnitrace_core::pulse`].
+    pub fn adaptive(self, min: Duration, max: Duration) -> Self {
+        self.adaptive_pulse(AdaptivePulse::new(min, max))
+    }
+
+    /// [`XMountConfig::adaptive`] with its own backoff policy.
+    pub fn adaptive_pulse(mut self, policy: AdaptivePulse) -> Self {
+        self.adaptive = Some(policy);
+        self
+    }
+
     pub fn mountinfo_path<P: AsRef<Path>>(mut self, p: P) -> Self {
         self.mountinfo_path = p.as_ref().to_path_buf();
         self
     }
+
+    /// Return from run() immediately when no mountpoint is watched at startup.
+    /// By default the sensor stays alive and idles until the first mountpoint is added
+    /// through an [`XMountControl`], then primes on the next tick.
+    pub fn exit_if_empty(mut self, on: bool) -> Self {
+        self.exit_if_empty = on;
+        self
+    }
+
+    /// Fire WillUnmount and Unmounted events with [`CallbackHub::fire_and_wait_all`], giving each
+    /// callback at most `timeout`. The sensor does not move on to the next tick before these
+    /// callbacks completed (or timed out), so e.g. a "stop service" callback is done before
+    /// anything else is reported. Timed-out callbacks are recorded in [`XMount::diagnostics`].
+    ///
+    /// This only orders omnitrace's own work: the kernel does not wait for callbacks, and the
+    /// unmount may well be complete by the time the event is seen.
+    pub fn barrier(mut self, timeout: Duration) -> Self {
+        self.barrier_timeout = Some(timeout);
+        self
+    }
+
+    /// Recognize autofs placeholders (systemd `.automount` units, autofs maps). On by default:
+    /// the placeholder appearing is `AutomountArmed`, the real filesystem mounted over it on
+    /// first access is `Mounted`, and falling back to the placeholder is `Unmounted` with
+    /// reason `automount_expired`. Off, the autofs entry is reported like any other mount.
+    pub fn automounts(mut self, on: bool) -> Self {
+        self.automounts = on;
+        self
+    }
+
+    /// Gaps between ticks (suspend and resume, NTP steps) are recorded in [`XMount::diagnostics`]
+    /// as `"time"`. With `reprime`, the mount table after the gap is taken as the new baseline
+    /// instead of reporting everything that changed meanwhile, at once.
+    pub fn time_gaps(mut self, gaps: TimeGaps) -> Self {
+        self.time_gaps = gaps;
+        self
+    }
+
+    /// Read the time from `clock` instead of the system clock, see [`omnitrace_core::clock`].
+    pub fn clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Run the probes added with [`XMount::add_health_probe`] every `ticks` ticks (default 10),
+    /// starting with the first.
+    pub fn health_every(mut self, ticks: u32) -> Self {
+        self.health_every = ticks.max(1);
+        self
+    }
+
+    /// Keep the last state of a mountpoint removed from the watched set for `ttl`: added back
+    /// within it, what happened meanwhile is reported (e.g. Unmounted if it got unmounted),
+    /// instead of taking its state then as the baseline. See [`omnitrace_core::tombstones`].
+    pub fn report_unwatched(mut self, ttl: Duration) -> Self {
+        self.unwatched_ttl = Some(ttl);
+        self
+    }
+
+    /// Keep the last events of each mountpoint, see [`XMount::history`].
+    pub fn history(mut self, config: HistoryConfig) -> Self {
+        self.history = Some(config);
+        self
+    }
+
+    /// Fire the first `per_tick` mountinfo lines of a read that do not parse as
+    /// [`XMountEvent::ParseAnomaly`], with the raw line, e.g.
+    /// [`omnitrace_core::anomaly::DEFAULT_CAPTURE_PER_TICK`]. Skipped lines are counted in the
+    /// debug snapshot's `parse` either way. See [`omnitrace_core::anomaly`].
+    pub fn capture_parse_anomalies(mut self, per_tick: usize) -> Self {
+        self.capture_parse_anomalies = Some(per_tick);
+        self
+    }
+
+    /// Probe every watched FUSE mount (see [`MountInfo::fuse`]) with a [`health::FuseLiveness`]
+    /// of this timeout, reporting a daemon that stopped answering as `FsHealthChanged` to
+    /// `Faulted`. It is the first probe, taking FUSE mounts before any added later.
+    pub fn fuse_liveness(mut self, timeout: Duration) -> Self {
+        self.fuse_liveness = Some(timeout);
+        self
+    }
+}
+
+/// Cloneable handle to the watched set of a (possibly already running) XMount.
+///
+/// Obtained via [`XMount::control`] before the sensor is spawned. Mountpoints added or
+/// removed through it are picked up on the next tick.
+#[cfg(feature = "runtime")]
+#[derive(Clone, Default)]
+pub struct XMountControl {
+    watched: Arc<Mutex<HashSet<PathBuf>>>,
+    precursors: Arc<Mutex<HashMap<PathBuf, Vec<UnmountPrecursor>>>>,
+    labels: Arc<Mutex<HashMap<PathBuf, Labels>>>,
+}
+
+#[cfg(feature = "runtime")]
+impl XMountControl {
+    /// Add a mountpoint (target) to watch. See [`XMount::add`].
+    pub fn add<P: AsRef<Path>>(&self, mountpoint: P) {
+        self.watched.lock().unwrap().insert(engine::watch_key(mountpoint.as_ref()));
+    }
+
+    /// Add a mountpoint and fire a WillUnmount advisory when one of the `precursors` is seen.
+    /// See [`XMount::add_with_precursors`].
+    pub fn add_with_precursors<P: AsRef<Path>>(&self, mountpoint: P, precursors: &[UnmountPrecursor]) {
+        let key = engine::watch_key(mountpoint.as_ref());
+        self.precursors.lock().unwrap().insert(key.clone(), precursors.to_vec());
+        self.watched.lock().unwrap().insert(key);
+    }
+
+    /// Add a mountpoint whose events carry `labels`. See [`XMount::add_labeled`].
+    pub fn add_labeled<P: AsRef<Path>>(&self, mountpoint: P, labels: Labels) {
+        let key = engine::watch_key(mountpoint.as_ref());
+        self.labels.lock().unwrap().entry(key.clone()).or_default().merge(&labels);
+        self.watched.lock().unwrap().insert(key);
+    }
+
+    /// Remove a mountpoint from being watched. See [`XMount::remove`].
+    pub fn remove<P: AsRef<Path>>(&self, mountpoint: P) {
+        let mut watched = self.watched.lock().unwrap();
+        let mut precursors = self.precursors.lock().unwrap();
+        let mut labels = self.labels.lock().unwrap();
+        let key = engine::watch_key(mountpoint.as_ref());
+        if watched.remove(&key) {
+            precursors.remove(&key);
+            labels.remove(&key);
+        } else {
+            watched.remove(mountpoint.as_ref());
+            precursors.remove(mountpoint.as_ref());
+            labels.remove(mountpoint.as_ref());
+        }
+    }
+
+    /// Copy of the currently watched mountpoints.
+    pub fn watched(&self) -> HashSet<PathBuf> {
+        self.watched.lock().unwrap().clone()
+    }
+
+    fn precursors(&self) -> HashMap<PathBuf, Vec<UnmountPrecursor>> {
+        self.precursors.lock().unwrap().clone()
+    }
+
+    fn labels_of(&self, target: &Path) -> Option<Labels> {
+        self.labels.lock().unwrap().get(target).cloned()
+    }
+}
+
+/// What [`XMount::debug_handle`] reports, refreshed every tick.
+#[cfg(feature = "runtime")]
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct XMountDebug {
+    pub pulse_ms: u64,
+    pub mountinfo_path: String,
+    pub primed: bool,
+    /// Watched mountpoints, sorted.
+    pub targets: Vec<String>,
+    /// Last known state of the watched mountpoints that are mounted.
+    pub mounts: Vec<MountInfo>,
+    pub ignored_classes: Vec<MountClass>,
+    pub ignored_fstypes: Vec<String>,
+    pub ignored_paths: Vec<String>,
+    /// Mountpoints a WillUnmount was fired for.
+    pub advised: Vec<String>,
+    /// Malformed lines skipped in the last mountinfo read.
+    pub malformed_lines: usize,
+    /// Malformed lines skipped since the start, and captured, see
+    /// [`XMountConfig::capture_parse_anomalies`].
+    pub parse: ParseStats,
+    /// Events per mountpoint, see [`XMount::entity_counters`].
+    pub entities: EntityCounters,
+    /// Recent events per mountpoint, if [`XMountConfig::history`] is set.
+    pub history: Option<HistoryHandle<XMountEvent>>,
+    /// Last health of the probed mountpoints, see [`XMount::add_health_probe`].
+    pub health: BTreeMap<String, FsHealth>,
 }
 
 /// Main struct for monitoring mount events.
+#[cfg(feature = "runtime")]
 pub struct XMount {
-    watched: HashSet<PathBuf>,
+    watched: XMountControl,
     config: XMountConfig,
 
-    // last known per watched mountpoint
-    last: HashMap<PathBuf, MountInfo>,
-    is_primed: bool,
+    // classification, ignore rules and the last known state per watched mountpoint
+    engine: MountDiffer,
+    // the whole mount table as last read, for previews
+    table: Vec<MountInfo>,
+    previews: PreviewQueue<XMountRule>,
+    // the watched set `engine.last` was taken for, and the state of mountpoints removed from it since
+    applied: HashSet<PathBuf>,
+    tombstones: Option<Tombstones<PathBuf, Option<MountInfo>>>,
+
+    // targets a WillUnmount was fired for, until the precursor clears or they unmount
+    advised: HashSet<PathBuf>,
+
+    // declared mounts, compared once when the sensor primes
+    expected: Vec<ExpectedMount>,
+
+    // handlers registered with on_target*, per watch key
+    targets: HashMap<PathBuf, CallbackHub<XMountEvent>>,
+
+    entities: EntityCounters,
+    history: Option<History<XMountEvent>>,
+    debug: DebugCell<XMountDebug>,
+    diagnostics: Diagnostics,
+    malformed_lines: usize,
+    parse: AnomalyCapture,
+    // malformed lines of the last read to fire
+    anomalies: Vec<ParseAnomaly>,
+    pacer: Pacer,
+    health: HealthWatch,
+}
+
+/// Closure registered with [`XMount::on_target`].
+#[cfg(feature = "runtime")]
+struct FnHandler<F> {
+    mask: XMountMask,
+    f: F,
+}
+
+#[cfg(feature = "runtime")]
+#[async_trait]
+impl<F, Fut> Callback<XMountEvent> for FnHandler<F>
+where
+    F: Fn(XMountEvent) -> Fut + Send + Sync,
+    Fut: Future<Output = Option<CallbackResult>> + Send,
+{
+    fn mask(&self) -> u64 {
+        self.mask.bits()
+    }
+
+    async fn call(&self, ev: &XMountEvent) -> Option<CallbackResult> {
+        (self.f)(ev.clone()).await
+    }
 }
 
+#[cfg(feature = "runtime")]
 impl Default for XMount {
     fn default() -> Self {
         Self::new(XMountConfig::default())
     }
 }
 
+#[cfg(feature = "runtime")]
 impl XMount {
     /// Create a new XMount monitor with the given configuration.
-    /// The monitor won't start until you call run(), and you can still add watched mountpoints and callbacks after that.
+    /// The monitor won't start until you call run(), and you can still add watched mountpoints after that via [`XMount::control`].
+    /// With nothing watched the sensor idles (unless [`XMountConfig::exit_if_empty`] is set) and primes once a mountpoint is added.
     /// The configuration controls the polling interval and the path to the mountinfo file to read.
     /// The default configuration polls every 1 second and reads from /proc/self/mountinfo, which is usually what you want.
     pub fn new(config: XMountConfig) -> Self {
-        Self { watched: HashSet::new(), config, last: HashMap::new(), is_primed: false }
+        let mut engine = MountDiffer::new();
+        engine.automounts(config.automounts);
+        let mut health = HealthWatch::new(config.health_every);
+        if let Some(timeout) = config.fuse_liveness {
+            health.add(Arc::new(FuseLiveness::new(timeout)));
+        }
+        Self {
+            watched: XMountControl::default(),
+            pacer: Pacer::new(config.pulse, config.adaptive).detect_gaps(config.clock.clone(), config.time_gaps.threshold),
+            health,
+            tombstones: config.unwatched_ttl.map(|ttl| Tombstones::new(ttl, tombstones::DEFAULT_CAPACITY)),
+            history: config.history.map(History::new),
+            parse: AnomalyCapture::new(config.capture_parse_anomalies),
+            config,
+            engine,
+            table: Vec::new(),
+            previews: PreviewQueue::default(),
+            applied: HashSet::new(),
+            advised: HashSet::new(),
+            expected: Vec::new(),
+            targets: HashMap::new(),
+            entities: EntityCounters::default(),
+            debug: DebugCell::default(),
+            diagnostics: Diagnostics::new(),
+            malformed_lines: 0,
+            anomalies: Vec::new(),
+        }
+    }
+
+    /// Get a handle for adding/removing watched mountpoints after the sensor was spawned.
+    pub fn control(&self) -> XMountControl {
+        self.watched.clone()
+    }
+
+    /// Handle on a summary of the sensor's state, for [`omnitrace_core::debug::Snapshots`].
+    pub fn debug_handle(&self) -> DebugCell<XMountDebug> {
+        self.debug.clone()
+    }
+
+    /// Errors the sensor ran past: unreadable mountinfo, malformed lines (`"mountinfo:malformed"`,
+    /// counted per line), barrier timeouts, failing health probes (`"health:<target>"`).
+    pub fn diagnostics(&self) -> Diagnostics {
+        self.diagnostics.clone()
+    }
+
+    /// The pulse in use, see [`XMountConfig::adaptive`].
+    pub fn effective_pulse(&self) -> EffectivePulse {
+        self.pacer.handle()
+    }
+
+    /// Events emitted per mountpoint and kind, with their recent rate.
+    pub fn entity_counters(&self) -> EntityCounters {
+        self.entities.clone()
+    }
+
+    /// The last events per mountpoint (as watched, e.g. `"/mnt/nas"`), as of the previous
+    /// tick. None unless [`XMountConfig::history`] is set.
+    pub fn history(&self) -> Option<HistoryHandle<XMountEvent>> {
+        self.history.as_ref().map(History::handle)
     }
 
     /// Add a mountpoint (target) to watch.
@@ -78,290 +494,682 @@ impl XMount {
     /// If a watched mountpoint is missing from mountinfo, it will be treated as unmounted (but won't trigger an
     /// Unmounted event until it was previously seen as mounted).
     pub fn add<P: AsRef<Path>>(&mut self, mountpoint: P) {
-        // canonicalize if possible; for mountpoints it’s usually fine either way
-        if let Ok(p) = mountpoint.as_ref().canonicalize() {
-            self.watched.insert(p);
-        } else {
-            self.watched.insert(mountpoint.as_ref().to_path_buf());
-        }
+        self.watched.add(mountpoint);
+    }
+
+    /// Add a mountpoint to watch, with an early warning: when one of `precursors` is seen for it
+    /// (e.g. it got remounted read-only), a WillUnmount event fires before anything else is
+    /// reported for that tick. Each precursor episode is reported once; precursors already
+    /// present when the sensor primes are not reported.
+    ///
+    /// Whether an unmount follows is up to whoever triggered it. Combine with
+    /// [`XMountConfig::barrier`] to have callbacks complete before the sensor moves on.
+    pub fn add_with_precursors<P: AsRef<Path>>(&mut self, mountpoint: P, precursors: &[UnmountPrecursor]) {
+        self.watched.add_with_precursors(mountpoint, precursors);
+    }
+
+    /// Add a mountpoint to watch, like [`XMount::add`], whose events all carry `labels`, e.g.
+    /// `{"volume": "postgres-data"}`. Labeling a mountpoint again merges the labels, the new
+    /// values replacing those of keys set before; [`XMount::remove`] drops them.
+    pub fn add_labeled<P: AsRef<Path>>(&mut self, mountpoint: P, labels: Labels) {
+        self.watched.add_labeled(mountpoint, labels);
     }
 
     /// Remove a mountpoint from being watched.
-    /// If the mountpoint was previously seen as mounted, it will be treated as unmounted (but won't trigger an Unmounted event since it's no longer watched).
+    /// Its last known state is dropped without any event, whether it was mounted or not. Added back later, it is primed again
+    /// silently: its state then is the new baseline, and nothing that happened while it was not watched is reported, unless
+    /// [`XMountConfig::report_unwatched`] is set.
     /// The library will canonicalize paths if possible, so removing "/mnt/usb" and "/mnt/./usb" will remove the same thing.
     /// If you remove a mountpoint that wasn't being watched, nothing happens.
-    /// If you remove a mountpoint that was being watched but is currently missing from mountinfo, it will just stop being watched without any events.
     /// In general, you can add and remove mountpoints at any time, even after run() has started, and the library will handle it gracefully.
     pub fn remove<P: AsRef<Path>>(&mut self, mountpoint: P) {
-        if let Ok(p) = mountpoint.as_ref().canonicalize() {
-            self.watched.remove(&p);
-        } else {
-            self.watched.remove(mountpoint.as_ref());
+        self.watched.remove(mountpoint);
+    }
+
+    /// Declare what should and should not be mounted, e.g. as loaded with
+    /// [`omnitrace_core::expected::load`]. The mount points get watched. When the sensor primes
+    /// it fires a Deviation for each difference, before reporting any change; mounts excluded
+    /// by the ignore rules count as not mounted.
+    pub fn expect(&mut self, mounts: Vec<ExpectedMount>) {
+        for m in &mounts {
+            self.watched.add(&m.mount_point);
+        }
+        self.expected.extend(mounts);
+    }
+
+    /// Deviations of the primed state from [`XMount::expect`].
+    fn deviations(&self) -> Vec<XMountEvent> {
+        let mut out = Vec::new();
+        for e in &self.expected {
+            let target = engine::watch_key(&e.mount_point);
+            let found = self.engine.last.get(&target);
+            out.extend(e.deviations(found).into_iter().map(|deviation| XMountEvent::Deviation {
+                target: target.clone(),
+                deviation,
+                info: found.cloned(),
+                labels: Labels::default(),
+            }));
+        }
+        out
+    }
+
+    /// Probe the health of watched mounts of the probe's fstypes, e.g. `BtrfsSysfs::new()`, and
+    /// fire `FsHealthChanged` when it moves. See [`health`]. A mount is probed by the first
+    /// probe added for its fstype.
+    pub fn add_health_probe<P: HealthProbe + 'static>(&mut self, probe: P) {
+        self.health.add(Arc::new(probe));
+    }
+
+    /// Classify mount points matching `glob` as `class`, taking precedence over the built-in heuristics.
+    pub fn classify(&mut self, glob: &str, class: MountClass) -> Result<(), globset::Error> {
+        self.engine.classifier.rule(glob, class)
+    }
+
+    /// Don't report events for mounts of this class (e.g. `MountClass::ContainerOverlay` on docker hosts).
+    pub fn ignore_class(&mut self, class: MountClass) {
+        self.engine.ignored_classes.insert(class);
+    }
+
+    /// Don't report kernel pseudo filesystems (proc, sysfs, cgroup2, bpf, ...) and the mounts
+    /// systemd and container runtimes make for themselves: the [`ignore::NO_PSEUDO`] profile.
+    /// Single entries can be taken back out with [`XMount::unignore_fstype`].
+    pub fn ignore_pseudo_filesystems(&mut self) {
+        self.engine.ignore.merge(IgnoreRules::profile(ignore::NO_PSEUDO).unwrap_or_default());
+    }
+
+    /// Add ignore rules, e.g. from an [`ignore::IgnoreConfig`] naming a profile.
+    pub fn ignore_rules(&mut self, rules: IgnoreRules) {
+        self.engine.ignore.merge(rules);
+    }
+
+    /// Don't report mounts of this fstype.
+    pub fn ignore_fstype(&mut self, fstype: &str) {
+        self.engine.ignore.fstype(fstype);
+    }
+
+    /// Report mounts of this fstype again, e.g. `unignore_fstype("cgroup2")` after
+    /// [`XMount::ignore_pseudo_filesystems`].
+    pub fn unignore_fstype(&mut self, fstype: &str) -> bool {
+        self.engine.ignore.unignore_fstype(fstype)
+    }
+
+    /// Don't report mounts whose mount point matches `glob`.
+    pub fn ignore_path(&mut self, glob: &str) -> Result<(), globset::Error> {
+        self.engine.ignore.path(glob)
+    }
+
+    /// Which rule leaves `mi` out, if any: an ignored fstype, mount point glob or class.
+    /// For answering "why didn't I get an event".
+    pub fn excluded_by(&self, mi: &MountInfo) -> Option<Exclusion> {
+        self.engine.excluded_by(mi)
+    }
+
+    /// Every mount now, classified, without the excluded ones, sorted by mount point: a
+    /// one-shot read of the mount table for baselines, without starting the sensor. Unlike the
+    /// sensor's view it is not limited to the watched targets.
+    pub fn snapshot(&mut self) -> io::Result<Vec<MountInfo>> {
+        let mut out: Vec<MountInfo> = self
+            .read_all()?
+            .into_iter()
+            .filter_map(|mi| {
+                let class = self.engine.classifier.classify(&mi);
+                self.engine.excluded(&mi, class).is_none().then_some(MountInfo { class, ..mi })
+            })
+            .collect();
+        // stable: stacked mounts keep their mountinfo order
+        out.sort_by(|a, b| a.mount_point.cmp(&b.mount_point));
+        Ok(out)
+    }
+
+    /// What adding `rule` would do, against the mount table as the sensor last read it and
+    /// the mounts it tracks: `would_match` counts the mounts of the table the rule names,
+    /// `would_stop_matching` the tracked ones an ignore rule would drop. Nothing is changed.
+    pub fn preview_rule(&self, rule: &XMountRule) -> PreviewReport {
+        let glob = match rule {
+            XMountRule::IgnorePath(g) => Some(g.compile_matcher()),
+            _ => None,
+        };
+        let names = |mi: &MountInfo| match rule {
+            XMountRule::Watch(mp) => engine::watched_target(&HashSet::from([engine::watch_key(mp)]), &mi.mount_point).is_some(),
+            XMountRule::IgnoreFstype(fstype) => mi.fstype == *fstype,
+            XMountRule::IgnorePath(_) => glob.as_ref().is_some_and(|g| g.is_match(&mi.mount_point)),
+            XMountRule::IgnoreClass(class) => self.engine.classifier.classify(mi) == *class,
+        };
+        let watched = matches!(rule, XMountRule::Watch(_));
+        let mut report = PreviewReport::tally(
+            self.table
+                .iter()
+                .filter(|mi| !watched || self.excluded_by(mi).is_none())
+                .map(|mi| (mi.mount_point.display().to_string(), names(mi), false)),
+        );
+        if !watched {
+            report.would_stop_matching = self.engine.last.values().filter(|mi| names(mi)).count();
+        }
+        report
+    }
+
+    /// Handle for previewing rules against the running sensor, answered on its next tick.
+    pub fn preview_handle(&self) -> PreviewHandle<XMountRule> {
+        self.previews.handle()
+    }
+
+    fn answer_previews(&mut self) {
+        let requests = self.previews.take();
+        // an idle sensor does not read the table
+        if !requests.is_empty()
+            && !self.engine.primed
+            && let Ok(all) = self.read_all()
+        {
+            self.table = all;
+        }
+        for req in requests {
+            let report = self.preview_rule(&req.rule);
+            req.answer(report);
+        }
+    }
+
+    /// The events a tick would report for the mount table going from `old` to `new`, both as
+    /// returned by [`XMount::snapshot`]. Of mounts stacked on one mount point, the last listed
+    /// counts, as for watched targets.
+    pub fn diff_snapshots(old: &[MountInfo], new: &[MountInfo]) -> Vec<XMountEvent> {
+        let by_target = |mounts: &[MountInfo]| mounts.iter().map(|mi| (mi.mount_point.clone(), mi.clone())).collect::<HashMap<_, _>>();
+        engine::diff(&by_target(old), &by_target(new), false)
+    }
+
+    /// Run `f` for the events about `mountpoint` whose kind is in `mask`, without a hub
+    /// callback of your own:
+    ///
+    /// ```ignore
+    /// x.on_target("/mnt/backup", XMountMask::UNMOUNTED, |ev| async move {
+    ///     eprintln!("backup disk gone: {:?}", ev.target());
+    ///     None
+    /// });
+    /// ```
+    ///
+    /// The mountpoint gets watched. Handlers of a mountpoint run in registration order before
+    /// the hub's callbacks (through the barrier too, if configured) and never see other
+    /// mountpoints' events; what they return goes to the hub's results channel, if set. A
+    /// hub with a results channel of typed results (not [`CallbackResult`]) does not take
+    /// them, and the sensor stops with an error instead. Register them before the sensor is
+    /// spawned.
+    pub fn on_target<P, F, Fut>(&mut self, mountpoint: P, mask: XMountMask, f: F)
+    where
+        P: AsRef<Path>,
+        F: Fn(XMountEvent) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<CallbackResult>> + Send + 'static,
+    {
+        self.on_target_callback(mountpoint, FnHandler { mask, f });
+    }
+
+    /// Like [`XMount::on_target`], with any callback, e.g. a closure wrapped in
+    /// [`omnitrace_core::callbacks::Once`].
+    pub fn on_target_callback<P: AsRef<Path>, C: Callback<XMountEvent> + 'static>(&mut self, mountpoint: P, cb: C) {
+        self.target_hub(mountpoint.as_ref()).add(cb);
+    }
+
+    /// Like [`XMount::on_target_callback`], for events passing `pred` only (see
+    /// [`CallbackHub::add_filtered`]).
+    pub fn on_target_filtered<P, C, F>(&mut self, mountpoint: P, cb: C, pred: F)
+    where
+        P: AsRef<Path>,
+        C: Callback<XMountEvent> + 'static,
+        F: Fn(&XMountEvent) -> bool + Send + Sync + 'static,
+    {
+        self.target_hub(mountpoint.as_ref()).add_filtered(cb, pred);
+    }
+
+    fn target_hub(&mut self, mountpoint: &Path) -> &mut CallbackHub<XMountEvent> {
+        self.add(mountpoint);
+        self.targets.entry(engine::watch_key(mountpoint)).or_default()
+    }
+
+    fn publish_debug(&self) {
+        let sorted = |paths: &mut dyn Iterator<Item = &PathBuf>| {
+            let mut out: Vec<String> = paths.map(|p| p.display().to_string()).collect();
+            out.sort();
+            out
+        };
+        let mut mounts: Vec<MountInfo> = self.engine.last.values().cloned().collect();
+        mounts.sort_by(|a, b| a.mount_point.cmp(&b.mount_point));
+        let mut ignored_classes: Vec<MountClass> = self.engine.ignored_classes.iter().copied().collect();
+        ignored_classes.sort_by_key(|c| format!("{c:?}"));
+
+        self.debug.update(|d| {
+            *d = XMountDebug {
+                pulse_ms: self.pacer.pulse().as_millis() as u64,
+                mountinfo_path: self.config.mountinfo_path.display().to_string(),
+                primed: self.engine.primed,
+                targets: sorted(&mut self.watched.watched().iter()),
+                mounts,
+                ignored_classes,
+                ignored_fstypes: self.engine.ignore.fstypes(),
+                ignored_paths: self.engine.ignore.paths(),
+                advised: sorted(&mut self.advised.iter()),
+                malformed_lines: self.malformed_lines,
+                parse: self.parse.stats(),
+                entities: self.entities.clone(),
+                history: self.history(),
+                health: self.health.states(),
+            }
+        });
+        if let Some(h) = &self.history {
+            h.publish();
         }
     }
 
     /// Check if an event matches the callback's mask.
     /// For example, if the callback's mask is MOUNTED | UNMOUNTED, it will match Mounted and Unmounted events but not Changed events.
-    async fn fire(hub: &omnitrace_core::callbacks::CallbackHub<XMountEvent>, ev: XMountEvent) {
+    async fn fire<R: Send + 'static>(&self, hub: &CallbackHub<XMountEvent, R>, ev: XMountEvent) {
+        let ev = self.labeled(ev);
+        self.record(&ev);
+        if let Some(handlers) = self.targets.get(ev.target()) {
+            handlers.fire(ev.mask().bits(), &ev).await;
+        }
         hub.fire(ev.mask().bits(), &ev).await;
     }
 
-    /// Linux mountinfo escapes spaces as \040 etc.
-    fn unescape_mount_field(s: &str) -> String {
-        // minimal: handle \040 \011 \012 \134
-        let mut out = String::with_capacity(s.len());
-        let bytes = s.as_bytes();
-        let mut i = 0;
-        while i < bytes.len() {
-            if bytes[i] == b'\\' && i + 3 < bytes.len() {
-                let a = bytes[i + 1];
-                let b = bytes[i + 2];
-                let c = bytes[i + 3];
-                if a.is_ascii_digit() && b.is_ascii_digit() && c.is_ascii_digit() {
-                    let oct = ((a - b'0') as u32) * 64 + ((b - b'0') as u32) * 8 + ((c - b'0') as u32);
-                    if let Some(ch) = char::from_u32(oct) {
-                        out.push(ch);
-                        i += 4;
-                        continue;
-                    }
-                }
-            }
-            out.push(bytes[i] as char);
-            i += 1;
+    fn record(&self, ev: &XMountEvent) {
+        let target = ev.target().display().to_string();
+        self.entities.record(&target, entities::mask_name(ev.mask()));
+        if let Some(h) = &self.history {
+            h.record([target], ev);
         }
-        out
     }
 
-    /// Parse a line from mountinfo into a MountInfo struct.
-    fn parse_mountinfo_line(line: &str) -> Option<MountInfo> {
-        // format: mountID parentID major:minor root mount_point options optional_fields... - fstype source super_options
-        let mut parts = line.split_whitespace();
+    /// `ev` with the labels of its target, see [`XMount::add_labeled`].
+    fn labeled(&self, mut ev: XMountEvent) -> XMountEvent {
+        if let Some(labels) = self.watched.labels_of(ev.target()) {
+            *ev.labels_mut() = labels;
+        }
+        ev
+    }
+
+    /// Fire the malformed lines captured in the last read.
+    async fn fire_anomalies<R: Send + 'static>(&mut self, hub: &CallbackHub<XMountEvent, R>) {
+        for anomaly in std::mem::take(&mut self.anomalies) {
+            self.fire(hub, XMountEvent::ParseAnomaly { anomaly, labels: Labels::default() }).await;
+        }
+    }
 
-        let mount_id: u32 = parts.next()?.parse().ok()?;
-        let parent_id: u32 = parts.next()?.parse().ok()?;
-        let _majmin = parts.next()?; // ignore
+    /// Fire through the barrier, if configured.
+    async fn fire_ordered<R: Send + 'static>(&self, hub: &CallbackHub<XMountEvent, R>, ev: XMountEvent) {
+        let Some(timeout) = self.config.barrier_timeout else {
+            return self.fire(hub, ev).await;
+        };
 
-        let root = Self::unescape_mount_field(parts.next()?);
-        let mount_point = Self::unescape_mount_field(parts.next()?);
-        let mount_opts = parts.next()?.to_string();
+        let ev = self.labeled(ev);
+        self.record(&ev);
+        if let Some(handlers) = self.targets.get(ev.target())
+            && let Err(source) = handlers.fire_and_wait_all(ev.mask().bits(), &ev, timeout).await
+        {
+            self.diagnostics.report("barrier", XMountError::Barrier { target: Some(ev.target().to_path_buf()), source });
+        }
+        if let Err(source) = hub.fire_and_wait_all(ev.mask().bits(), &ev, timeout).await {
+            self.diagnostics.report("barrier", XMountError::Barrier { target: None, source });
+        }
+    }
 
-        // skip optional fields until "-"
-        for p in &mut parts {
-            if p == "-" {
-                break;
+    /// Run the health probes on a blocking thread and report what moved.
+    async fn check_health<R: Send + 'static>(&mut self, hub: &CallbackHub<XMountEvent, R>) {
+        let jobs = self.health.jobs(&self.engine.last);
+        if jobs.is_empty() {
+            return;
+        }
+        let probed = match tokio::task::spawn_blocking(move || health::run(jobs)).await {
+            Ok(p) => p,
+            Err(e) => {
+                log::error!("xmount: health probes: {e}");
+                return;
             }
+        };
+        for ev in self.health.update(probed, &self.diagnostics) {
+            self.fire(hub, ev).await;
         }
+    }
 
-        let fstype = parts.next()?.to_string();
-        let source = Self::unescape_mount_field(parts.next()?);
-        let super_opts = parts.next().unwrap_or("").to_string();
+    /// systemd unit name of a mountpoint, as `systemd-escape --path --suffix=mount` does it.
+    pub(crate) fn systemd_mount_unit(mountpoint: &Path) -> String {
+        let raw = paths::as_bytes(mountpoint);
+        let parts: Vec<&[u8]> = raw.split(|b| *b == b'/').filter(|p| !p.is_empty()).collect();
+        if parts.is_empty() {
+            return "-.mount".to_string();
+        }
 
-        Some(MountInfo {
-            mount_id,
-            parent_id,
-            mount_point: PathBuf::from(mount_point),
-            root: PathBuf::from(root),
-            fstype,
-            source,
-            mount_opts,
-            super_opts,
-        })
+        let mut out = String::new();
+        for (i, part) in parts.iter().enumerate() {
+            if i > 0 {
+                out.push('-');
+            }
+            for (j, &b) in part.iter().enumerate() {
+                if b.is_ascii_alphanumeric() || b == b':' || b == b'_' || (b == b'.' && !(i == 0 && j == 0)) {
+                    out.push(b as char);
+                } else {
+                    out.push_str(&format!("\\x{b:02x}"));
+                }
+            }
+        }
+        out + ".mount"
     }
 
-    #[cfg(target_os = "linux")]
-    fn read_mountinfo(path: &Path) -> io::Result<Vec<MountInfo>> {
-        let txt = std::fs::read_to_string(path)?;
-        let mut out = Vec::new();
-        for line in txt.lines() {
-            if let Some(mi) = Self::parse_mountinfo_line(line) {
-                out.push(mi);
+    /// ActiveState of the mount units of the mountpoints in `now` watched for
+    /// [`UnmountPrecursor::SystemdDeactivating`], by mountpoint, from one `systemctl show`
+    /// for all of them.
+    async fn systemd_active_states(
+        precursors: &HashMap<PathBuf, Vec<UnmountPrecursor>>, now: &HashMap<PathBuf, MountInfo>,
+    ) -> HashMap<PathBuf, String> {
+        let units: HashMap<String, &PathBuf> = precursors
+            .iter()
+            .filter(|(mp, wanted)| wanted.contains(&UnmountPrecursor::SystemdDeactivating) && now.contains_key(*mp))
+            .map(|(mp, _)| (Self::systemd_mount_unit(mp), mp))
+            .collect();
+        if units.is_empty() {
+            return HashMap::new();
+        }
+        let out = match tokio::process::Command::new("systemctl").args(["show", "-p", "Id", "-p", "ActiveState"]).args(units.keys()).output().await {
+            Ok(out) if out.status.success() => out,
+            _ => return HashMap::new(),
+        };
+        Self::parse_active_states(&String::from_utf8_lossy(&out.stdout))
+            .into_iter()
+            .filter_map(|(unit, state)| Some(((*units.get(&unit)?).clone(), state)))
+            .collect()
+    }
+
+    /// `Id=` and `ActiveState=` of each unit block of `systemctl show`, blocks apart by an
+    /// empty line.
+    pub(crate) fn parse_active_states(out: &str) -> HashMap<String, String> {
+        let mut states = HashMap::new();
+        for block in out.split("\n\n") {
+            let field = |name: &str| block.lines().find_map(|l| l.strip_prefix(name)).map(str::to_string);
+            if let (Some(id), Some(state)) = (field("Id="), field("ActiveState=")) {
+                states.insert(id, state);
             }
         }
-        Ok(out)
+        states
+    }
+
+    fn precursor_seen(info: &MountInfo, wanted: &[UnmountPrecursor], units: &HashMap<PathBuf, String>) -> Option<UnmountPrecursor> {
+        wanted.iter().copied().find(|p| match p {
+            UnmountPrecursor::ReadOnly => info.mount_opts.split(',').any(|o| o == "ro"),
+            UnmountPrecursor::SystemdDeactivating => units.get(&info.mount_point).is_some_and(|s| s == "deactivating"),
+        })
+    }
+
+    /// The last snapshot is hours old or its timing is off: record it, and with
+    /// [`XMountConfig::time_gaps`] `reprime` diff the next one against nothing.
+    fn on_time_gap(&mut self, gap: TimeAnomaly) {
+        self.diagnostics.report("time", gap);
+        if self.config.time_gaps.reprime {
+            self.engine.primed = false;
+        }
     }
 
-    #[cfg(target_os = "netbsd")]
-    fn read_mountinfo(_path: &Path) -> io::Result<Vec<MountInfo>> {
-        netbsd_mounts::read_mounts()
+    /// Bring `last` in line with the edits of the watched set since it was taken. Mountpoints no
+    /// longer watched are dropped without events, into the tombstones if kept. Mountpoints watched
+    /// again take their tombstone, returned as restored, or else their state in `now`, precursors
+    /// included, so only what changed while unwatched is reported.
+    fn apply_watch_edits(
+        &mut self, watched: &HashSet<PathBuf>, now: &HashMap<PathBuf, MountInfo>, units: &HashMap<PathBuf, String>,
+    ) -> HashSet<PathBuf> {
+        let at = self.config.clock.now_instant();
+        let removed: Vec<PathBuf> = self.applied.difference(watched).cloned().collect();
+        for target in removed {
+            let last = self.engine.last.remove(&target);
+            self.advised.remove(&target);
+            if let Some(t) = self.tombstones.as_mut() {
+                t.bury(target, last, at);
+            }
+        }
+
+        let mut restored = HashSet::new();
+        let precursors = self.watched.precursors();
+        let added: Vec<PathBuf> = watched.difference(&self.applied).cloned().collect();
+        for target in added {
+            let state = match self.tombstones.as_mut().and_then(|t| t.take(&target, at)) {
+                Some(last) => {
+                    restored.insert(target.clone());
+                    last
+                }
+                None => {
+                    // priming, prime_advised() covers them all
+                    let info = now.get(&target);
+                    if let (true, Some(info), Some(wanted)) = (self.engine.primed, info, precursors.get(&target))
+                        && Self::precursor_seen(info, wanted, units).is_some()
+                    {
+                        self.advised.insert(target.clone());
+                    }
+                    info.cloned()
+                }
+            };
+            match state {
+                Some(info) => self.engine.last.insert(target, info),
+                None => self.engine.last.remove(&target),
+            };
+        }
+        self.applied.clone_from(watched);
+        restored
     }
 
-    fn snapshot_for_watched(&self, all: &[MountInfo]) -> HashMap<PathBuf, MountInfo> {
-        let mut map = HashMap::new();
-        for mi in all {
-            // watch by mount_point
-            if self.watched.contains(&mi.mount_point) {
-                map.insert(mi.mount_point.clone(), mi.clone());
+    /// Remember precursors present at priming time, so they are not reported as news.
+    fn prime_advised(&mut self, now: &HashMap<PathBuf, MountInfo>, units: &HashMap<PathBuf, String>) {
+        self.advised.clear();
+        for (mp, wanted) in self.watched.precursors() {
+            if let Some(info) = now.get(&mp)
+                && Self::precursor_seen(info, &wanted, units).is_some()
+            {
+                self.advised.insert(mp);
             }
         }
-        map
     }
 
-    fn materially_diff(a: &MountInfo, b: &MountInfo) -> bool {
-        #[cfg(target_os = "netbsd")]
-        {
-            a.fstype != b.fstype || a.source != b.source || a.mount_opts != b.mount_opts
+    /// Read mountinfo, recording skipped lines in the diagnostics and keeping those to
+    /// capture for [`XMount::fire_anomalies`].
+    fn read_all(&mut self) -> io::Result<Vec<MountInfo>> {
+        let (all, malformed) = engine::read_mountinfo(&self.config.mountinfo_path)?;
+        self.malformed_lines = malformed.len();
+        let path = &self.config.mountinfo_path;
+        self.anomalies = self.parse.read(malformed.iter().filter_map(|e| e.anomaly(path)));
+        if malformed.is_empty() {
+            self.diagnostics.clear("mountinfo:malformed");
         }
+        for e in malformed {
+            self.diagnostics.report_once("mountinfo:malformed", e);
+        }
+        Ok(all)
+    }
 
-        #[cfg(target_os = "linux")]
-        {
-            a.mount_id != b.mount_id
-                || a.parent_id != b.parent_id
-                || a.root != b.root
-                || a.fstype != b.fstype
-                || a.source != b.source
-                || a.mount_opts != b.mount_opts
-                || a.super_opts != b.super_opts
+    /// The mount table must be readable and parse; watched paths should exist.
+    fn preflight_findings(&self) -> Vec<PreflightFinding> {
+        let path = &self.config.mountinfo_path;
+        let mut out = Vec::new();
+        let mounts = match engine::read_mountinfo(path) {
+            Ok((mounts, malformed)) => {
+                if let Some(first) = malformed.first() {
+                    out.push(PreflightFinding::warning(format!("{} malformed lines in {} skipped, e.g. {first}", malformed.len(), path.display())));
+                }
+                if mounts.is_empty() {
+                    out.push(
+                        PreflightFinding::critical(format!("no mounts in {}", path.display()))
+                            .remedy("XMountConfig::mountinfo_path must name a mountinfo file, e.g. /proc/self/mountinfo"),
+                    );
+                }
+                mounts
+            }
+            Err(e) => {
+                out.push(
+                    PreflightFinding::critical(format!("cannot read {}: {e}", path.display()))
+                        .remedy("mount /proc, or point XMountConfig::mountinfo_path at the mount table"),
+                );
+                Vec::new()
+            }
+        };
+
+        let mut watched: Vec<PathBuf> = self.watched.watched().into_iter().collect();
+        watched.sort();
+        if watched.is_empty() {
+            let severity = if self.config.exit_if_empty { Severity::Critical } else { Severity::Warning };
+            out.push(PreflightFinding::new(severity, "no mount points watched").remedy("add them with XMount::add"));
+        }
+        let mounted: HashSet<PathBuf> = mounts.iter().map(|mi| mi.mount_point.clone()).collect();
+        for target in watched {
+            if !target.exists() {
+                out.push(
+                    PreflightFinding::warning(format!("watched {} does not exist", target.display()))
+                        .remedy("check the path; it is reported once something gets mounted there"),
+                );
+            } else if !mounts.is_empty() && engine::watched_target(&mounted, &target).is_none() {
+                out.push(PreflightFinding::info(format!("{} is not mounted now", target.display())));
+            }
         }
+        out
     }
 
-    pub async fn run(mut self, ctx: SensorCtx<XMountEvent>) -> io::Result<()> {
-        if self.watched.is_empty() {
+    pub async fn run<R: Send + 'static>(mut self, ctx: SensorCtx<XMountEvent, R>) -> io::Result<()> {
+        let watched = self.watched.watched();
+        if watched.is_empty() && self.config.exit_if_empty {
             return Ok(());
         }
+        // per-target handlers return JSON, so their results only fit a JSON results channel
+        if !self.targets.is_empty()
+            && let Some(tx) = ctx.hub.result_channel()
+        {
+            let tx: Box<dyn Any> = Box::new(tx);
+            let Ok(tx) = tx.downcast::<mpsc::Sender<CallbackResult>>() else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "on_target handlers return JSON, the hub's results channel takes typed results",
+                ));
+            };
+            for handlers in self.targets.values_mut() {
+                handlers.set_result_channel_with_policy((*tx).clone(), ctx.hub.result_policy());
+            }
+        }
 
         // prime snapshot
-        let all = Self::read_mountinfo(&self.config.mountinfo_path)?;
-        self.last = self.snapshot_for_watched(&all);
-        self.is_primed = true;
+        if !watched.is_empty() {
+            let all = self.read_all()?;
+            self.fire_anomalies(&ctx.hub).await;
+            let now = self.engine.select(Some(&watched), &all);
+            self.table = all;
+            let units = Self::systemd_active_states(&self.watched.precursors(), &now).await;
+            self.apply_watch_edits(&watched, &now, &units);
+            self.prime_advised(&now, &units);
+            self.engine.primed = true;
+            for ev in self.deviations() {
+                self.fire(&ctx.hub, ev).await;
+            }
+        }
+        self.publish_debug();
 
-        let mut ticker = time::interval(self.config.pulse);
+        let mut ticker = self.pacer.ticker();
+        let mut idle_reported = false;
 
         loop {
+            ctx.beat();
+            if self.pacer.observe_fired(ctx.hub.fired()) {
+                self.pacer.restart(&mut ticker);
+            }
+            self.pacer.waiting();
             tokio::select! {
                 _ = ctx.cancel.cancelled() => break Ok(()),
                 _ = ticker.tick() => {}
             }
+            self.answer_previews();
+            if let Some(gap) = self.pacer.check_gap() {
+                self.on_time_gap(gap);
+            }
 
-            let all = match Self::read_mountinfo(&self.config.mountinfo_path) {
-                Ok(v) => v,
-                Err(e) => {
-                    log::error!("xmount: failed to read mountinfo: {e}");
+            // No rules: stay alive, but don't diff. Prime again once something gets watched.
+            let watched = self.watched.watched();
+            if watched.is_empty() {
+                if !idle_reported {
+                    log::warn!("xmount: no mountpoints watched, sensor is idle until one is added");
+                    idle_reported = true;
+                }
+                self.apply_watch_edits(&watched, &HashMap::new(), &HashMap::new());
+                self.engine.primed = false;
+                self.publish_debug();
+                continue;
+            }
+            idle_reported = false;
+
+            let all = match self.read_all() {
+                Ok(v) => {
+                    self.diagnostics.clear("mountinfo");
+                    v
+                }
+                Err(source) => {
+                    self.diagnostics.report("mountinfo", XMountError::Mountinfo { path: self.config.mountinfo_path.clone(), source });
                     continue;
                 }
             };
 
-            let now = self.snapshot_for_watched(&all);
+            self.fire_anomalies(&ctx.hub).await;
+            let now = self.engine.select(Some(&watched), &all);
+            self.table = all;
+            let units = Self::systemd_active_states(&self.watched.precursors(), &now).await;
+            let restored = self.apply_watch_edits(&watched, &now, &units);
+            if !self.engine.primed {
+                self.prime_advised(&now, &units);
+                self.engine.primed = true;
+                // a new baseline, but for what comes back from the tombstones
+                self.engine.last.retain(|target, _| restored.contains(target));
+                self.engine.last.extend(now.iter().filter(|(target, _)| !restored.contains(*target)).map(|(t, mi)| (t.clone(), mi.clone())));
+                if restored.is_empty() {
+                    self.publish_debug();
+                    continue;
+                }
+            }
 
-            // Mounted / Changed
-            for (mp, new_info) in &now {
-                match self.last.get(mp) {
-                    None => {
-                        if self.is_primed {
-                            Self::fire(&ctx.hub, XMountEvent::Mounted { target: mp.clone(), info: new_info.clone() }).await;
-                        }
+            // WillUnmount advisories go first
+            for (mp, wanted) in self.watched.precursors() {
+                let Some(info) = now.get(&mp) else {
+                    continue;
+                };
+                match Self::precursor_seen(info, &wanted, &units) {
+                    Some(reason) if self.advised.insert(mp.clone()) => {
+                        self.fire_ordered(&ctx.hub, XMountEvent::WillUnmount { target: mp, info: info.clone(), reason, labels: Labels::default() })
+                            .await;
                     }
-                    Some(old_info) => {
-                        if Self::materially_diff(old_info, new_info) {
-                            Self::fire(&ctx.hub, XMountEvent::Changed { target: mp.clone(), old: old_info.clone(), new: new_info.clone() }).await;
-                        }
+                    Some(_) => {}
+                    None => {
+                        self.advised.remove(&mp);
                     }
                 }
             }
 
-            // Unmounted
-            for (mp, old_info) in &self.last {
-                if !now.contains_key(mp) {
-                    Self::fire(&ctx.hub, XMountEvent::Unmounted { target: mp.clone(), last: old_info.clone() }).await;
+            for ev in engine::diff(&self.engine.last, &now, self.engine.automounts) {
+                if let XMountEvent::Unmounted { target, .. } = &ev {
+                    self.advised.remove(target);
+                    self.fire_ordered(&ctx.hub, ev).await;
+                } else {
+                    self.fire(&ctx.hub, ev).await;
                 }
             }
 
-            self.last = now;
+            self.engine.last = now;
+            if self.health.due() {
+                self.check_health(&ctx.hub).await;
+            }
+            self.publish_debug();
         }
     }
 }
 
+#[cfg(feature = "runtime")]
 impl Sensor for XMount {
     type Event = XMountEvent;
 
-    fn run(self, ctx: SensorCtx<Self::Event>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+    fn run<R: Send + 'static>(self, ctx: SensorCtx<Self::Event, R>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
         Box::pin(async move {
             if let Err(e) = XMount::run(self, ctx).await {
                 log::error!("xmount: sensors stopped: {e}");
             }
         })
     }
-}
-
-#[cfg(target_os = "netbsd")]
-fn c_char_array_to_string(buf: &[libc::c_char]) -> String {
-    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
-    let bytes: Vec<u8> = buf[..len].iter().map(|&c| c as u8).collect();
-    String::from_utf8_lossy(&bytes).into_owned()
-}
-
-#[cfg(target_os = "netbsd")]
-fn mount_flags_to_opts(flags: u64) -> String {
-    // NetBSD statvfs flags are ST_*; we only map the obvious ones.
-    // If you want the full list, expand it.
-    let mut out = Vec::new();
-
-    // These names come from NetBSD statvfs docs. :contentReference[oaicite:3]{index=3}
-    const ST_RDONLY: u64 = 0x0000_0001;
-    const ST_NOEXEC: u64 = 0x0000_0002;
-    const ST_NOSUID: u64 = 0x0000_0008;
-    const ST_NODEV: u64 = 0x0000_0010;
-
-    out.push(if (flags & ST_RDONLY) != 0 { "ro" } else { "rw" });
-
-    if (flags & ST_NOEXEC) != 0 {
-        out.push("noexec");
-    }
-    if (flags & ST_NOSUID) != 0 {
-        out.push("nosuid");
-    }
-    if (flags & ST_NODEV) != 0 {
-        out.push("nodev");
-    }
-
-    out.join(",")
-}
-
-#[cfg(target_os = "netbsd")]
-mod netbsd_mounts {
-    use super::*;
-    use std::{io, ptr};
-
-    // NetBSD uses versioned symbols; this avoids ABI mismatch pain. :contentReference[oaicite:4]{index=4}
-    extern "C" {
-        #[link_name = "__getmntinfo13"]
-        fn getmntinfo(mntbufp: *mut *mut libc::statvfs, flags: libc::c_int) -> libc::c_int;
-    }
-
-    // NetBSD flags for getmntinfo forward to getvfsstat(2). :contentReference[oaicite:5]{index=5}
-    const MNT_NOWAIT: libc::c_int = 2;
-
-    pub fn read_mounts() -> io::Result<Vec<MountInfo>> {
-        unsafe {
-            let mut buf: *mut libc::statvfs = ptr::null_mut();
-            let n = getmntinfo(&mut buf as *mut *mut libc::statvfs, MNT_NOWAIT);
-            if n < 0 {
-                return Err(io::Error::last_os_error());
-            }
-
-            let slice = std::slice::from_raw_parts(buf, n as usize);
-            let mut out = Vec::with_capacity(slice.len());
-
-            for sv in slice {
-                // Field layout is defined by NetBSD statvfs(5). :contentReference[oaicite:6]{index=6}
-                let fstype = c_char_array_to_string(&sv.f_fstypename);
-                let target = c_char_array_to_string(&sv.f_mntonname);
-                let source = c_char_array_to_string(&sv.f_mntfromname);
-
-                let mount_opts = mount_flags_to_opts(sv.f_flag as u64);
-
-                out.push(MountInfo {
-                    mount_id: 0,
-                    parent_id: 0,
-                    mount_point: PathBuf::from(target),
-                    root: PathBuf::from("/"),
-                    fstype,
-                    source,
-                    mount_opts,
-                    super_opts: String::new(),
-                });
-            }
 
-            Ok(out)
-        }
+    fn preflight(&self) -> Pin<Box<dyn Future<Output = Vec<PreflightFinding>> + Send + '_>> {
+        let findings = self.preflight_findings();
+        Box::pin(async move { findings })
     }
 }
@@ -0,0 +1,9 @@
+//! `use xmount::prelude::*;` brings in the XMount sensor, its config and events,
+//! and the core prelude (callbacks, hub, `spawn_sensor`).
+
+pub use crate::enforce::{EnforcementAction, EnforcementCallback};
+pub use crate::events::{FsHealth, MountClass, MountInfo, UnmountReason, XMountEvent, XMountMask};
+pub use crate::health::{BtrfsSysfs, FuseLiveness, HealthProbe, HealthReport};
+pub use crate::ignore::{Exclusion, IgnoreConfig, IgnoreRules};
+pub use crate::{XMount, XMountConfig, XMountControl};
+pub use omnitrace_core::prelude::*;
@@ -0,0 +1,38 @@
+//! Rules [`crate::XMount::preview_rule`] answers for, see [`omnitrace_core::preview`].
+
+use crate::events::MountClass;
+use globset::Glob;
+use omnitrace_core::preview::{RuleError, split_rule};
+use std::{path::PathBuf, str::FromStr};
+
+const VERBS: &str = "watch, ignore-fstype, ignore-path, ignore-class";
+
+/// A watch or ignore rule, as [`crate::XMount::add`] and the `ignore_*` methods take them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum XMountRule {
+    /// `watch <mountpoint>`
+    Watch(PathBuf),
+    /// `ignore-fstype <fstype>`
+    IgnoreFstype(String),
+    /// `ignore-path <glob>`, a parsed glob so a bad one is an error before any preview.
+    IgnorePath(Glob),
+    /// `ignore-class <class>`, e.g. `ignore-class ContainerOverlay`
+    IgnoreClass(MountClass),
+}
+
+impl FromStr for XMountRule {
+    type Err = RuleError;
+
+    fn from_str(s: &str) -> Result<Self, RuleError> {
+        let invalid = |verb: &str, reason: String| RuleError::InvalidArgument { verb: verb.to_string(), reason };
+        match split_rule(s)? {
+            ("watch", mp) => Ok(Self::Watch(PathBuf::from(mp))),
+            ("ignore-fstype", fstype) => Ok(Self::IgnoreFstype(fstype.to_string())),
+            ("ignore-path", glob) => Glob::new(glob).map(Self::IgnorePath).map_err(|e| invalid("ignore-path", e.to_string())),
+            ("ignore-class", class) => serde_json::from_value(serde_json::Value::from(class))
+                .map(Self::IgnoreClass)
+                .map_err(|_| invalid("ignore-class", format!("no mount class {class:?}"))),
+            (verb, _) => Err(RuleError::UnknownVerb { verb: verb.to_string(), expected: VERBS }),
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! Windows volumes as [`MountInfo`]: drive letters and mounted folders of local volumes
+//! (FindFirstVolumeW enumeration) plus mapped network drives (GetLogicalDrives).
+//!
+//! The conversion and path normalization below is plain string work, so it is compiled
+//! and tested on every platform; only [`read_mounts`] talks to Win32.
+
+use crate::events::{MountClass, MountInfo};
+use omnitrace_core::intern::Interned;
+use std::path::{Path, PathBuf};
+
+// GetDriveTypeW
+pub(crate) const DRIVE_REMOVABLE: u32 = 2;
+pub(crate) const DRIVE_REMOTE: u32 = 4;
+pub(crate) const DRIVE_CDROM: u32 = 5;
+
+// GetVolumeInformationW file system flags
+pub(crate) const FILE_READ_ONLY_VOLUME: u32 = 0x0008_0000;
+
+/// What Win32 reports about one volume or mapped network drive.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RawVolume {
+    /// Volume GUID path (`\\?\Volume{...}\`), none for network drives
+    pub guid: Option<String>,
+    /// `\\server\share` of a mapped network drive
+    pub unc: Option<String>,
+    /// Drive letters and mounted folders, as from GetVolumePathNamesForVolumeNameW
+    pub paths: Vec<String>,
+    pub fs_name: String,
+    pub serial: u32,
+    pub fs_flags: u32,
+    pub drive_type: u32,
+}
+
+/// One MountInfo per path of the volume. The volume serial number stands in for the
+/// mount ID, so another stick plugged in as `E:` is a replaced mount, not a Changed one.
+pub(crate) fn mount_infos(v: &RawVolume) -> Vec<MountInfo> {
+    let source = Interned::new(v.unc.as_deref().or(v.guid.as_deref()).unwrap_or_default());
+    v.paths
+        .iter()
+        .map(|p| MountInfo {
+            mount_id: v.serial,
+            parent_id: 0,
+            mount_point: PathBuf::from(normalize(p)),
+            root: PathBuf::from("\\"),
+            fstype: (&v.fs_name).into(),
+            source: source.clone(),
+            mount_opts: mount_opts(v.fs_flags, v.drive_type).into(),
+            super_opts: Interned::default(),
+            class: MountClass::Other,
+            fuse: false,
+        })
+        .collect()
+}
+
+/// `ro`/`rw`, then `remote`, `removable` or `cdrom` from the drive type.
+pub(crate) fn mount_opts(fs_flags: u32, drive_type: u32) -> String {
+    let mut out = vec![if fs_flags & FILE_READ_ONLY_VOLUME != 0 { "ro" } else { "rw" }];
+    match drive_type {
+        DRIVE_REMOTE => out.push("remote"),
+        DRIVE_REMOVABLE => out.push("removable"),
+        DRIVE_CDROM => out.push("cdrom"),
+        _ => {}
+    }
+    out.join(",")
+}
+
+/// Spell a mount point the way Win32 returns it: backslashes, upper-case drive letter,
+/// no `\\?\` prefix and a trailing backslash. `d:`, `d:/` and `\\?\D:\` all become `D:\`.
+pub(crate) fn normalize(path: &str) -> String {
+    let mut s = path.replace('/', "\\");
+    if let Some(unc) = s.strip_prefix("\\\\?\\UNC\\") {
+        s = format!("\\\\{unc}");
+    } else if let Some(rest) = s.strip_prefix("\\\\?\\")
+        && rest.as_bytes().get(1) == Some(&b':')
+    {
+        s = rest.to_string();
+    }
+    if s.as_bytes().get(1) == Some(&b':') {
+        s[..1].make_ascii_uppercase();
+    }
+    if !s.ends_with('\\') {
+        s.push('\\');
+    }
+    s
+}
+
+/// Windows paths compare case-insensitively, after [`normalize`].
+pub(crate) fn same_mount_point(a: &Path, b: &Path) -> bool {
+    normalize(&a.to_string_lossy()).to_uppercase() == normalize(&b.to_string_lossy()).to_uppercase()
+}
+
+/// Root paths (`C:\`) of the drive letters set in a GetLogicalDrives mask.
+pub(crate) fn drive_roots(mask: u32) -> Vec<String> {
+    (0..26u8).filter(|i| mask & (1 << i) != 0).map(|i| format!("{}:\\", (b'A' + i) as char)).collect()
+}
+
+/// Text up to the first NUL of a wide-char buffer.
+pub(crate) fn from_wide(buf: &[u16]) -> String {
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    String::from_utf16_lossy(&buf[..len])
+}
+
+/// Strings of a NUL-separated, double-NUL-terminated list (REG_MULTI_SZ layout).
+pub(crate) fn multi_sz(buf: &[u16]) -> Vec<String> {
+    buf.split(|&c| c == 0).take_while(|s| !s.is_empty()).map(String::from_utf16_lossy).collect()
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) fn read_mounts() -> std::io::Result<Vec<MountInfo>> {
+    Ok(win32::volumes()?.iter().flat_map(mount_infos).collect())
+}
+
+#[cfg(target_os = "windows")]
+mod win32 {
+    use super::*;
+    use std::{ffi::c_void, io, ptr};
+
+    type Handle = *mut c_void;
+    const INVALID_HANDLE_VALUE: Handle = -1isize as Handle;
+    const ERROR_MORE_DATA: u32 = 234;
+    const MAX_PATH: usize = 260;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetLogicalDrives() -> u32;
+        fn GetDriveTypeW(root: *const u16) -> u32;
+        fn GetVolumeInformationW(
+            root: *const u16, name: *mut u16, name_len: u32, serial: *mut u32, max_component_len: *mut u32, fs_flags: *mut u32, fs_name: *mut u16,
+            fs_name_len: u32,
+        ) -> i32;
+        fn FindFirstVolumeW(name: *mut u16, len: u32) -> Handle;
+        fn FindNextVolumeW(find: Handle, name: *mut u16, len: u32) -> i32;
+        fn FindVolumeClose(find: Handle) -> i32;
+        fn GetVolumePathNamesForVolumeNameW(volume: *const u16, paths: *mut u16, len: u32, needed: *mut u32) -> i32;
+        fn GetLastError() -> u32;
+    }
+
+    #[link(name = "mpr")]
+    unsafe extern "system" {
+        fn WNetGetConnectionW(local: *const u16, remote: *mut u16, len: *mut u32) -> u32;
+    }
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(Some(0)).collect()
+    }
+
+    /// Fill in file system name, serial and flags. None if the volume is not ready
+    /// (empty card reader or optical drive), which counts as not mounted.
+    fn query(root: &str, v: &mut RawVolume) -> Option<()> {
+        let mut fs_name = [0u16; MAX_PATH + 1];
+        let ok = unsafe {
+            GetVolumeInformationW(
+                wide(root).as_ptr(),
+                ptr::null_mut(),
+                0,
+                &mut v.serial,
+                ptr::null_mut(),
+                &mut v.fs_flags,
+                fs_name.as_mut_ptr(),
+                fs_name.len() as u32,
+            )
+        };
+        if ok == 0 {
+            return None;
+        }
+        v.fs_name = from_wide(&fs_name);
+        Some(())
+    }
+
+    fn path_names(guid: &str) -> Vec<String> {
+        let name = wide(guid);
+        let mut buf = vec![0u16; MAX_PATH + 1];
+        loop {
+            let mut needed = 0u32;
+            if unsafe { GetVolumePathNamesForVolumeNameW(name.as_ptr(), buf.as_mut_ptr(), buf.len() as u32, &mut needed) } != 0 {
+                return multi_sz(&buf);
+            }
+            if unsafe { GetLastError() } != ERROR_MORE_DATA || needed as usize <= buf.len() {
+                return Vec::new();
+            }
+            buf.resize(needed as usize, 0);
+        }
+    }
+
+    fn local_volumes(out: &mut Vec<RawVolume>) -> io::Result<()> {
+        let mut name = [0u16; MAX_PATH + 1];
+        let find = unsafe { FindFirstVolumeW(name.as_mut_ptr(), name.len() as u32) };
+        if find == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        loop {
+            let guid = from_wide(&name);
+            let paths = path_names(&guid);
+            // volumes without a drive letter or folder (recovery, EFI) are not mounted anywhere
+            if let Some(first) = paths.first() {
+                let mut v = RawVolume { drive_type: unsafe { GetDriveTypeW(wide(first).as_ptr()) }, ..RawVolume::default() };
+                if query(&guid, &mut v).is_some() {
+                    v.guid = Some(guid);
+                    v.paths = paths;
+                    out.push(v);
+                }
+            }
+            if unsafe { FindNextVolumeW(find, name.as_mut_ptr(), name.len() as u32) } == 0 {
+                break;
+            }
+        }
+        unsafe { FindVolumeClose(find) };
+        Ok(())
+    }
+
+    fn network_drives(out: &mut Vec<RawVolume>) {
+        for root in drive_roots(unsafe { GetLogicalDrives() }) {
+            if unsafe { GetDriveTypeW(wide(&root).as_ptr()) } != DRIVE_REMOTE {
+                continue;
+            }
+            let mut unc = [0u16; 1024];
+            let mut len = unc.len() as u32;
+            let local = wide(root.trim_end_matches('\\'));
+            let unc = match unsafe { WNetGetConnectionW(local.as_ptr(), unc.as_mut_ptr(), &mut len) } {
+                0 => Some(from_wide(&unc)),
+                _ => None,
+            };
+            let mut v = RawVolume { unc, drive_type: DRIVE_REMOTE, ..RawVolume::default() };
+            // a disconnected share keeps its letter but has no file system to query
+            if query(&root, &mut v).is_some() {
+                v.paths = vec![root];
+                out.push(v);
+            }
+        }
+    }
+
+    pub(super) fn volumes() -> io::Result<Vec<RawVolume>> {
+        let mut out = Vec::new();
+        local_volumes(&mut out)?;
+        network_drives(&mut out);
+        Ok(out)
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{
+    classify::MountClassifier,
+    engine,
+    events::{MountClass, XMountEvent},
+    winvol::{self, DRIVE_REMOTE, DRIVE_REMOVABLE, FILE_READ_ONLY_VOLUME, RawVolume},
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+const USB_GUID: &str = "\\\\?\\Volume{4c1b02c1-d990-11dc-99ae-806e6f6e6963}\\";
+
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().collect()
+}
+
+fn usb(serial: u32, fs_flags: u32) -> RawVolume {
+    RawVolume {
+        guid: Some(USB_GUID.to_string()),
+        unc: None,
+        paths: vec!["E:\\".to_string(), "C:\\mnt\\usb\\".to_string()],
+        fs_name: "exFAT".to_string(),
+        serial,
+        fs_flags,
+        drive_type: DRIVE_REMOVABLE,
+    }
+}
+
+#[test]
+fn normalizes_drive_letters_and_prefixes() {
+    assert_eq!(winvol::normalize("d:"), "D:\\");
+    assert_eq!(winvol::normalize("d:/"), "D:\\");
+    assert_eq!(winvol::normalize("D:\\"), "D:\\");
+    assert_eq!(winvol::normalize("\\\\?\\D:\\"), "D:\\");
+    assert_eq!(winvol::normalize("c:/mnt/Data"), "C:\\mnt\\Data\\");
+    assert_eq!(winvol::normalize("\\\\?\\UNC\\fs01\\share"), "\\\\fs01\\share\\");
+    // volume GUID paths keep their prefix
+    assert_eq!(winvol::normalize(USB_GUID), USB_GUID);
+}
+
+#[test]
+fn mount_points_compare_case_insensitively() {
+    assert!(winvol::same_mount_point(Path::new("c:\\MNT\\data"), Path::new("C:\\mnt\\Data\\")));
+    assert!(winvol::same_mount_point(Path::new("\\\\?\\e:\\"), Path::new("E:\\")));
+    assert!(!winvol::same_mount_point(Path::new("C:\\mnt\\data"), Path::new("C:\\mnt\\data2\\")));
+    assert!(!winvol::same_mount_point(Path::new("D:\\"), Path::new("E:\\")));
+}
+
+#[test]
+fn decodes_win32_buffers() {
+    assert_eq!(winvol::drive_roots(0b1_0101), vec!["A:\\", "C:\\", "E:\\"]);
+    assert_eq!(winvol::drive_roots(1 << 25), vec!["Z:\\"]);
+
+    let mut buf = wide("NTFS");
+    buf.extend([0, 0x41, 0x42]);
+    assert_eq!(winvol::from_wide(&buf), "NTFS");
+
+    let mut buf = wide("E:\\");
+    buf.push(0);
+    buf.extend(wide("C:\\mnt\\usb\\"));
+    buf.extend([0, 0, 0x5a]);
+    assert_eq!(winvol::multi_sz(&buf), vec!["E:\\", "C:\\mnt\\usb\\"]);
+    assert!(winvol::multi_sz(&[0, 0]).is_empty());
+}
+
+#[test]
+fn volumes_map_to_one_mount_per_path() {
+    let infos = winvol::mount_infos(&usb(0x1234_abcd, 0));
+    assert_eq!(infos.len(), 2);
+    assert_eq!(infos[0].mount_point, PathBuf::from("E:\\"));
+    assert_eq!(infos[1].mount_point, PathBuf::from("C:\\mnt\\usb\\"));
+    for mi in &infos {
+        assert_eq!(mi.mount_id, 0x1234_abcd);
+        assert_eq!(mi.source, USB_GUID);
+        assert_eq!(mi.fstype, "exFAT");
+        assert_eq!(mi.mount_opts, "rw,removable");
+        assert_eq!(MountClassifier::new().classify(mi), MountClass::BlockDevice);
+    }
+    assert_eq!(winvol::mount_infos(&usb(1, FILE_READ_ONLY_VOLUME))[0].mount_opts, "ro,removable");
+
+    let share = RawVolume {
+        unc: Some("\\\\fs01\\projects".to_string()),
+        paths: vec!["z:\\".to_string()],
+        fs_name: "NTFS".to_string(),
+        drive_type: DRIVE_REMOTE,
+        ..RawVolume::default()
+    };
+    let mi = &winvol::mount_infos(&share)[0];
+    assert_eq!(mi.mount_point, PathBuf::from("Z:\\"));
+    assert_eq!(mi.source, "\\\\fs01\\projects");
+    assert_eq!(mi.mount_opts, "rw,remote");
+    assert_eq!(MountClassifier::new().classify(mi), MountClass::NetworkFs);
+}
+
+#[test]
+fn another_volume_on_the_same_letter_is_a_replacement() {
+    let snap = |v: &RawVolume| -> HashMap<PathBuf, _> { winvol::mount_infos(v).into_iter().take(1).map(|mi| (mi.mount_point.clone(), mi)).collect() };
+    let target = PathBuf::from("E:\\");
+
+    let evs = engine::diff(&snap(&usb(1, 0)), &snap(&usb(2, 0)), false);
+    assert!(matches!(&evs[..], [XMountEvent::Unmounted { .. }, XMountEvent::Mounted { .. }]), "{evs:?}");
+    assert!(evs.iter().all(|e| e.target() == target));
+
+    let evs = engine::diff(&snap(&usb(1, 0)), &snap(&usb(1, FILE_READ_ONLY_VOLUME)), false);
+    assert!(matches!(&evs[..], [XMountEvent::Changed { .. }]), "{evs:?}");
+}
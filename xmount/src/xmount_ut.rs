@@ -0,0 +1,1175 @@
+use crate::{
+    XMount, XMountConfig,
+    classify::MountClassifier,
+    engine::{self, MountDiffer},
+    engine_ut::{ORDER_TARGETS, ROOT_LINE, apply, as_map, random_mounts},
+    error::XMountError,
+    events::{FsHealth, MountClass, MountInfo, UnmountPrecursor, UnmountReason, XMountEvent, XMountMask},
+    preview::XMountRule,
+};
+use async_trait::async_trait;
+use omnitrace_core::{
+    anomaly::{ParseAnomaly, ParseFailure},
+    callbacks::{Callback, CallbackHub, CallbackResult, KIND_BITS, Once, RESERVED_BITS, SUBKIND_BITS},
+    clock::ManualClock,
+    debug::Snapshots,
+    expected::Deviation,
+    fields::{self, EventFields, FieldValue},
+    format::{format_long, format_short},
+    labels::Labels,
+    preflight::PreflightFinding,
+    pulse::{TimeAnomaly, TimeGapKind, TimeGaps},
+    router::Router,
+    sensor::{Sensor, SensorCtx, spawn_sensor},
+    severity::{Severity, SeverityMapper},
+    topics::{self, Topics},
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::mpsc::channel;
+
+struct JsonCb;
+
+#[async_trait]
+impl Callback<XMountEvent> for JsonCb {
+    fn mask(&self) -> u64 {
+        (XMountMask::MOUNTED | XMountMask::UNMOUNTED | XMountMask::CHANGED).bits()
+    }
+
+    async fn call(&self, ev: &XMountEvent) -> Option<CallbackResult> {
+        match ev {
+            XMountEvent::Mounted { target, info, .. } => Some(serde_json::json!({ "event": "mounted", "target": target, "fstype": info.fstype })),
+            XMountEvent::Unmounted { target, .. } => Some(serde_json::json!({ "event": "unmounted", "target": target })),
+            XMountEvent::Changed { target, .. } => Some(serde_json::json!({ "event": "changed", "target": target })),
+            XMountEvent::WillUnmount { target, reason, .. } => {
+                Some(serde_json::json!({ "event": "will_unmount", "target": target, "reason": reason }))
+            }
+            XMountEvent::AutomountArmed { target, .. } => Some(serde_json::json!({ "event": "automount_armed", "target": target })),
+            XMountEvent::FsHealthChanged { target, new, .. } => {
+                Some(serde_json::json!({ "event": "fs_health_changed", "target": target, "new": new }))
+            }
+            XMountEvent::Deviation { target, deviation, .. } => {
+                Some(serde_json::json!({ "event": "deviation", "target": target, "deviation": deviation }))
+            }
+            XMountEvent::ParseAnomaly { anomaly, .. } => {
+                Some(serde_json::json!({ "event": "parse_anomaly", "raw": anomaly.raw, "column": anomaly.column, "reason": anomaly.reason }))
+            }
+        }
+    }
+}
+
+fn fixture_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("xmount-ut-{}-{name}", std::process::id()))
+}
+
+/// Replace the fixture atomically, so the sensor never reads a half-written file.
+fn write_mountinfo(path: &Path, lines: &[&str]) {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, lines.join("\n") + "\n").unwrap();
+    std::fs::rename(&tmp, path).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn idles_without_watches_and_primes_after_first_add() {
+    let mountinfo = fixture_path("idle");
+    write_mountinfo(&mountinfo, &[ROOT_LINE]);
+
+    let sensor = XMount::new(XMountConfig::default().pulse(Duration::from_millis(10)).mountinfo_path(&mountinfo));
+    let control = sensor.control();
+
+    let (tx, mut rx) = channel::<CallbackResult>(4);
+    let mut hub = CallbackHub::<XMountEvent>::new();
+    hub.add(JsonCb);
+    hub.set_result_channel(tx);
+
+    let (handle, sensor_task) = spawn_sensor(sensor, Arc::new(hub));
+
+    // Nothing watched yet: the sensor must stay alive.
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!sensor_task.is_finished());
+
+    control.add("/mnt/xmount-ut-idle");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    write_mountinfo(&mountinfo, &[ROOT_LINE, "40 22 8:17 / /mnt/xmount-ut-idle rw,relatime - vfat /dev/sdb1 rw"]);
+    let event = tokio::time::timeout(Duration::from_millis(500), rx.recv()).await.unwrap().unwrap();
+
+    handle.shutdown();
+    let _ = sensor_task.await;
+    let _ = std::fs::remove_file(&mountinfo);
+
+    assert_eq!(event["event"], "mounted");
+    assert_eq!(event["target"], "/mnt/xmount-ut-idle");
+    assert_eq!(event["fstype"], "vfat");
+}
+
+#[tokio::test]
+async fn exit_if_empty_keeps_legacy_semantics() {
+    let sensor = XMount::new(XMountConfig::default().pulse(Duration::from_millis(10)).exit_if_empty(true));
+    let (_handle, sensor_task) = spawn_sensor(sensor, Arc::new(CallbackHub::<XMountEvent>::new()));
+
+    tokio::time::timeout(Duration::from_millis(200), sensor_task).await.unwrap().unwrap();
+}
+
+fn class_counts(classifier: &MountClassifier) -> HashMap<MountClass, usize> {
+    let mut counts = HashMap::new();
+    for line in include_str!("../fixtures/k8s-node.mountinfo").lines() {
+        let mi = engine::parse_mountinfo_line(line).unwrap();
+        *counts.entry(classifier.classify(&mi)).or_insert(0) += 1;
+    }
+    counts
+}
+
+#[test]
+fn classifies_k8s_node_mounts() {
+    let counts = class_counts(&MountClassifier::new());
+
+    assert_eq!(counts.get(&MountClass::ContainerOverlay), Some(&4));
+    assert_eq!(counts.get(&MountClass::KubeletVolume), Some(&4));
+    assert_eq!(counts.get(&MountClass::Pseudo), Some(&7));
+    assert_eq!(counts.get(&MountClass::Tmpfs), Some(&3));
+    assert_eq!(counts.get(&MountClass::BlockDevice), Some(&3));
+    assert_eq!(counts.get(&MountClass::NetworkFs), Some(&1));
+    assert_eq!(counts.get(&MountClass::Other), None);
+}
+
+#[test]
+fn user_rules_take_precedence() {
+    let mut classifier = MountClassifier::new();
+    classifier.rule("/mnt/*", MountClass::Other).unwrap();
+    assert!(classifier.rule("/mnt/[", MountClass::Other).is_err(), "a typo is reported, not kept as a rule matching nothing");
+
+    let counts = class_counts(&classifier);
+
+    // /mnt/shared (nfs4) and /mnt/cache (tmpfs) are reclassified
+    assert_eq!(counts.get(&MountClass::Other), Some(&2));
+    assert_eq!(counts.get(&MountClass::NetworkFs), None);
+    assert_eq!(counts.get(&MountClass::Tmpfs), Some(&2));
+}
+
+/// Records event order; WillUnmount handling is slow, like stopping a service.
+struct StopServiceCb {
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl Callback<XMountEvent> for StopServiceCb {
+    fn mask(&self) -> u64 {
+        u64::MAX
+    }
+
+    async fn call(&self, ev: &XMountEvent) -> Option<CallbackResult> {
+        let name = match ev {
+            XMountEvent::Mounted { .. } => "mounted",
+            XMountEvent::Unmounted { .. } => "unmounted",
+            XMountEvent::Changed { .. } => "changed",
+            XMountEvent::WillUnmount { .. } => {
+                self.log.lock().unwrap().push("stopping service".into());
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                "service stopped"
+            }
+            XMountEvent::AutomountArmed { .. } => "automount armed",
+            XMountEvent::FsHealthChanged { .. } => "fs health changed",
+            XMountEvent::Deviation { .. } => "deviation",
+            XMountEvent::ParseAnomaly { .. } => "parse anomaly",
+        };
+        self.log.lock().unwrap().push(name.into());
+        None
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn read_only_precursor_fires_will_unmount_before_unmount() {
+    const RW: &str = "40 22 8:17 / /mnt/xmount-ut-ro rw,relatime - ext4 /dev/sdb1 rw";
+    const RO: &str = "40 22 8:17 / /mnt/xmount-ut-ro ro,relatime - ext4 /dev/sdb1 ro";
+
+    let mountinfo = fixture_path("ro");
+    write_mountinfo(&mountinfo, &[ROOT_LINE, RW]);
+
+    let mut sensor = XMount::new(XMountConfig::default().pulse(Duration::from_millis(10)).mountinfo_path(&mountinfo).barrier(Duration::from_secs(1)));
+    sensor.add_with_precursors("/mnt/xmount-ut-ro", &[UnmountPrecursor::ReadOnly]);
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::<XMountEvent>::new();
+    hub.add(StopServiceCb { log: log.clone() });
+    let (handle, sensor_task) = spawn_sensor(sensor, Arc::new(hub));
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    write_mountinfo(&mountinfo, &[ROOT_LINE, RO]);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    // gone while the WillUnmount callback is still busy
+    write_mountinfo(&mountinfo, &[ROOT_LINE]);
+    tokio::time::sleep(Duration::from_millis(250)).await;
+
+    handle.shutdown();
+    let _ = sensor_task.await;
+    let _ = std::fs::remove_file(&mountinfo);
+
+    assert_eq!(*log.lock().unwrap(), vec!["stopping service", "service stopped", "changed", "unmounted"]);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn precursor_present_at_startup_is_not_reported() {
+    let mountinfo = fixture_path("ro-startup");
+    write_mountinfo(&mountinfo, &[ROOT_LINE, "41 22 11:0 / /mnt/xmount-ut-cdrom ro,relatime - iso9660 /dev/sr0 ro"]);
+
+    let mut sensor = XMount::new(XMountConfig::default().pulse(Duration::from_millis(10)).mountinfo_path(&mountinfo));
+    sensor.add_with_precursors("/mnt/xmount-ut-cdrom", &[UnmountPrecursor::ReadOnly]);
+
+    let (tx, mut rx) = channel::<CallbackResult>(4);
+    let mut hub = CallbackHub::<XMountEvent>::new();
+    hub.add(JsonCb);
+    hub.set_result_channel(tx);
+    let (handle, sensor_task) = spawn_sensor(sensor, Arc::new(hub));
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    handle.shutdown();
+    let _ = sensor_task.await;
+    let _ = std::fs::remove_file(&mountinfo);
+
+    assert!(rx.try_recv().is_err());
+}
+
+#[test]
+fn systemd_mount_unit_names() {
+    assert_eq!(XMount::systemd_mount_unit(Path::new("/")), "-.mount");
+    assert_eq!(XMount::systemd_mount_unit(Path::new("/mnt/data")), "mnt-data.mount");
+    assert_eq!(XMount::systemd_mount_unit(Path::new("/mnt/data/")), "mnt-data.mount");
+    assert_eq!(XMount::systemd_mount_unit(Path::new("/srv/my disk")), "srv-my\\x20disk.mount");
+    assert_eq!(XMount::systemd_mount_unit(Path::new("/var/lib-docker")), "var-lib\\x2ddocker.mount");
+    assert_eq!(XMount::systemd_mount_unit(Path::new("/.snapshots")), "\\x2esnapshots.mount");
+    assert_eq!(XMount::systemd_mount_unit(Path::new("/mnt/host:share")), "mnt-host:share.mount");
+}
+
+#[test]
+fn systemd_active_states_are_read_per_unit() {
+    let out = "Id=mnt-data.mount\nActiveState=deactivating\n\nActiveState=active\nId=srv-nfs.mount\n\nId=gone.mount\n";
+    let states = XMount::parse_active_states(out);
+    assert_eq!(states.len(), 2);
+    assert_eq!(states["mnt-data.mount"], "deactivating");
+    assert_eq!(states["srv-nfs.mount"], "active");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn non_utf8_mount_points_are_kept_byte_for_byte() {
+    use omnitrace_core::paths;
+
+    // mountinfo escapes whitespace and backslashes only, other bytes come through raw
+    let line = b"40 22 8:2 / /srv/caf\xe9\\040bar rw,relatime shared:2 - ext4 /dev/sdb1 rw".to_vec();
+    let mi = engine::parse_mountinfo_line(&line).unwrap();
+    assert_eq!(paths::as_bytes(&mi.mount_point).as_ref(), b"/srv/caf\xe9 bar");
+    assert_eq!(mi.fstype, "ext4");
+
+    let mut classifier = MountClassifier::new();
+    classifier.rule("/srv/*", MountClass::NetworkFs).unwrap();
+    assert_eq!(classifier.classify(&mi), MountClass::NetworkFs);
+
+    assert_eq!(XMount::systemd_mount_unit(&mi.mount_point), "srv-caf\\xe9\\x20bar.mount");
+
+    let expected = mi.mount_point.clone();
+    let ev = XMountEvent::Mounted { target: mi.mount_point.clone(), info: mi, labels: Labels::default() };
+    let back: XMountEvent = serde_json::from_value(serde_json::to_value(&ev).unwrap()).unwrap();
+    let XMountEvent::Mounted { target, info, .. } = back else { panic!("wrong variant") };
+    assert_eq!(target, expected);
+    assert_eq!(info.mount_point, expected);
+}
+
+struct Unmounts(Arc<Mutex<Vec<PathBuf>>>);
+
+#[async_trait]
+impl Callback<XMountEvent> for Unmounts {
+    fn mask(&self) -> u64 {
+        XMountMask::UNMOUNTED.bits()
+    }
+
+    async fn call(&self, ev: &XMountEvent) -> Option<CallbackResult> {
+        if let XMountEvent::Unmounted { target, .. } = ev {
+            self.0.lock().unwrap().push(target.clone());
+        }
+        None
+    }
+}
+
+#[tokio::test]
+async fn injected_events_drive_the_whole_pipeline() {
+    // router with severity tagging in front of a JSONL-style sink
+    let mut router = Router::new();
+    let (jsonl_tx, mut jsonl) = channel(16);
+    router.add_sink("jsonl", jsonl_tx);
+    router.set_default(vec!["jsonl"]);
+    router.set_severity(SeverityMapper::default());
+    let router = Arc::new(router);
+
+    let unmounts = Arc::new(Mutex::new(Vec::new()));
+    let (res_tx, mut results) = channel(16);
+    let mut hub = CallbackHub::new();
+    hub.add(JsonCb);
+    hub.add(Unmounts(unmounts.clone()));
+    hub.add(router.callback("xmount", |ev: &XMountEvent| ev.mask().bits()));
+    hub.set_result_channel(res_tx);
+
+    for ev in [XMountEvent::test_mounted("/mnt/x"), XMountEvent::test_unmounted("/mnt/x")] {
+        hub.inject(ev.mask().bits(), &ev).await;
+    }
+
+    assert_eq!(*unmounts.lock().unwrap(), vec![PathBuf::from("/mnt/x")]);
+
+    let r = results.try_recv().unwrap();
+    assert_eq!(r, serde_json::json!({ "event": "mounted", "target": "/mnt/x", "fstype": "ext4", "injected": true }));
+    assert_eq!(results.try_recv().unwrap()["injected"], true);
+
+    let mounted = jsonl.try_recv().unwrap();
+    assert_eq!(mounted["Mounted"]["info"]["class"], "BlockDevice");
+    assert_eq!(mounted["injected"], true);
+    let unmounted = jsonl.try_recv().unwrap();
+    assert_eq!(unmounted["Unmounted"]["target"], "/mnt/x");
+    assert_eq!(unmounted["severity"], "warning");
+    assert_eq!(unmounted["injected"], true);
+}
+
+#[tokio::test]
+async fn mask_and_predicate_select_nfs_mounts() {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add_filtered(Unmounts(seen.clone()), |ev: &XMountEvent| matches!(ev, XMountEvent::Unmounted { last, .. } if last.fstype == "nfs"));
+
+    let nfs = |target: &str| {
+        let mut last = MountInfo::test(target);
+        last.fstype = "nfs".into();
+        XMountEvent::Unmounted { target: last.mount_point.clone(), last, reason: None, labels: Labels::default() }
+    };
+    for ev in [nfs("/mnt/share"), XMountEvent::test_unmounted("/mnt/usb"), XMountEvent::test_mounted("/mnt/nas"), nfs("/mnt/home")] {
+        hub.fire(ev.mask().bits(), &ev).await;
+    }
+
+    assert_eq!(*seen.lock().unwrap(), vec![PathBuf::from("/mnt/share"), PathBuf::from("/mnt/home")]);
+    let stats = hub.stats();
+    assert_eq!((stats.called, stats.filtered_out, stats.mask_mismatch), (2, 1, 1));
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn target_handlers_only_see_their_own_mountpoint() {
+    let mountinfo = fixture_path("targets");
+    write_mountinfo(&mountinfo, &[ROOT_LINE]);
+    let (a, b) = ("/mnt/xmount-ut-target-a", "/mnt/xmount-ut-target-b");
+    let (line_a, line_b) = (format!("40 22 8:17 / {a} rw,relatime - vfat /dev/sdb1 rw"), format!("41 22 8:33 / {b} rw,relatime - vfat /dev/sdc1 rw"));
+
+    let mut sensor = XMount::new(XMountConfig::default().pulse(Duration::from_millis(10)).mountinfo_path(&mountinfo));
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    for (name, mp) in [("a", a), ("b", b)] {
+        let seen = seen.clone();
+        sensor.on_target(mp, XMountMask::MOUNTED | XMountMask::UNMOUNTED, move |ev| {
+            let seen = seen.clone();
+            async move {
+                seen.lock().unwrap().push((name, ev.mask().bits(), ev.target().to_path_buf()));
+                Some(serde_json::json!({ "handler": name }))
+            }
+        });
+    }
+    sensor.on_target_callback(b, Once::new(JsonCb));
+    assert_eq!(sensor.control().watched().len(), 2);
+
+    let (tx, mut rx) = channel::<CallbackResult>(16);
+    let mut hub = CallbackHub::<XMountEvent>::new();
+    hub.set_result_channel(tx);
+    let (handle, sensor_task) = spawn_sensor(sensor, Arc::new(hub));
+
+    // a mounts, b mounts, a goes away
+    for table in [vec![ROOT_LINE, &line_a], vec![ROOT_LINE, &line_a, &line_b], vec![ROOT_LINE, &line_b]] {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        write_mountinfo(&mountinfo, &table);
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    handle.shutdown();
+    let _ = sensor_task.await;
+    let _ = std::fs::remove_file(&mountinfo);
+
+    let (mounted, unmounted) = (XMountMask::MOUNTED.bits(), XMountMask::UNMOUNTED.bits());
+    assert_eq!(*seen.lock().unwrap(), vec![("a", mounted, PathBuf::from(a)), ("b", mounted, PathBuf::from(b)), ("a", unmounted, PathBuf::from(a))]);
+    let mut results = Vec::new();
+    while let Ok(r) = rx.try_recv() {
+        results.push(r);
+    }
+    assert_eq!(
+        results,
+        vec![
+            serde_json::json!({ "handler": "a" }),
+            serde_json::json!({ "handler": "b" }),
+            serde_json::json!({ "event": "mounted", "target": b, "fstype": "vfat" }),
+            serde_json::json!({ "handler": "a" }),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn target_handlers_need_a_json_results_channel() {
+    let mut sensor = XMount::new(XMountConfig::default().mountinfo_path(fixture_path("typed-targets")));
+    sensor.on_target("/mnt/xmount-ut-typed", XMountMask::MOUNTED, |_| async { None });
+    let (tx, _rx) = channel::<u32>(1);
+    let mut hub = CallbackHub::<XMountEvent, u32>::default();
+    hub.set_result_channel(tx);
+    let (ctx, _handle) = SensorCtx::new(Arc::new(hub));
+
+    let err = sensor.run(ctx).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn events_carry_the_labels_of_their_mountpoint() {
+    let mountinfo = fixture_path("labels");
+    write_mountinfo(&mountinfo, &[ROOT_LINE]);
+    let (pg, usb) = ("/mnt/xmount-ut-labels-pg", "/mnt/xmount-ut-labels-usb");
+    let (line_pg, line_usb) =
+        (format!("40 22 8:17 / {pg} rw,relatime - ext4 /dev/sdb1 rw"), format!("41 22 8:33 / {usb} rw,relatime - vfat /dev/sdc1 rw"));
+
+    let mut sensor = XMount::new(XMountConfig::default().pulse(Duration::from_millis(10)).mountinfo_path(&mountinfo));
+    sensor.add_labeled(pg, [("volume", "postgres-data"), ("tier", "ssd")].into_iter().collect());
+    // labeled again: merged, the new tier wins
+    sensor.add_labeled(pg, [("tier", "nvme"), ("owner", "dba")].into_iter().collect());
+    sensor.add(usb);
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::<XMountEvent>::new();
+    hub.add(Recorder(seen.clone()));
+    let (handle, sensor_task) = spawn_sensor(sensor, Arc::new(hub));
+    for table in [vec![ROOT_LINE, &line_pg, &line_usb], vec![ROOT_LINE]] {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        write_mountinfo(&mountinfo, &table);
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    handle.shutdown();
+    let _ = sensor_task.await;
+    let _ = std::fs::remove_file(&mountinfo);
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 4, "{seen:?}");
+    for ev in seen.iter() {
+        let json = serde_json::to_value(ev).unwrap();
+        let body = json.as_object().unwrap().values().next().unwrap();
+        if ev.target() == Path::new(pg) {
+            assert_eq!(body["labels"], serde_json::json!({ "volume": "postgres-data", "tier": "nvme", "owner": "dba" }));
+        } else {
+            assert!(ev.labels().is_empty());
+            assert!(body.get("labels").is_none(), "{json}");
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn broadcast_subscribers_see_the_events_callbacks_see() {
+    let mountinfo = fixture_path("broadcast");
+    write_mountinfo(&mountinfo, &[ROOT_LINE]);
+    let (data, usb) = ("/mnt/xmount-ut-broadcast-data", "/mnt/xmount-ut-broadcast-usb");
+    let (line_data, line_usb) =
+        (format!("40 22 8:17 / {data} rw,relatime - ext4 /dev/sdb1 rw"), format!("41 22 8:33 / {usb} rw,relatime - vfat /dev/sdc1 rw"));
+
+    let mut sensor = XMount::new(XMountConfig::default().pulse(Duration::from_millis(10)).mountinfo_path(&mountinfo));
+    sensor.add(data);
+    sensor.add(usb);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::<XMountEvent>::new();
+    hub.add(Recorder(seen.clone()));
+    let (mut first, mut second) = (hub.subscribe_broadcast(), hub.subscribe_broadcast());
+    let (handle, sensor_task) = spawn_sensor(sensor, Arc::new(hub));
+    for table in [vec![ROOT_LINE, &line_data], vec![ROOT_LINE, &line_data, &line_usb], vec![ROOT_LINE]] {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        write_mountinfo(&mountinfo, &table);
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    handle.shutdown();
+    let _ = sensor_task.await;
+    let _ = std::fs::remove_file(&mountinfo);
+
+    let json = |ev: &XMountEvent| serde_json::to_value(ev).unwrap();
+    let seen: Vec<_> = seen.lock().unwrap().iter().map(json).collect();
+    assert_eq!(seen.len(), 4, "{seen:?}");
+    for rx in [&mut first, &mut second] {
+        let mut got = Vec::new();
+        while let Ok(ev) = rx.try_recv() {
+            got.push(json(&ev));
+        }
+        assert_eq!(got, seen);
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn debug_snapshot_reports_watched_mounts() {
+    let mountinfo = fixture_path("debug");
+    write_mountinfo(&mountinfo, &[ROOT_LINE, "40 22 8:17 / /mnt/xmount-ut-debug rw,relatime - vfat /dev/sdb1 rw"]);
+
+    let mut sensor = XMount::new(XMountConfig::default().pulse(Duration::from_millis(10)).mountinfo_path(&mountinfo));
+    sensor.add("/mnt/xmount-ut-debug");
+    sensor.add("/mnt/xmount-ut-absent");
+    sensor.ignore_class(MountClass::ContainerOverlay);
+    let mut snapshots = Snapshots::new();
+    snapshots.register("xmount", sensor.debug_handle());
+
+    let (handle, sensor_task) = spawn_sensor(sensor, Arc::new(CallbackHub::<XMountEvent>::new()));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let dump = snapshots.dump();
+    let redacted = snapshots.redact().dump();
+    handle.shutdown();
+    let _ = sensor_task.await;
+    let _ = std::fs::remove_file(&mountinfo);
+
+    let x = &dump["components"]["xmount"];
+    for key in ["pulse_ms", "mountinfo_path", "primed", "targets", "mounts", "ignored_classes", "advised"] {
+        assert!(x.get(key).is_some(), "missing {key} in {x}");
+    }
+    assert_eq!(x["primed"], true);
+    assert_eq!(x["targets"], serde_json::json!(["/mnt/xmount-ut-absent", "/mnt/xmount-ut-debug"]));
+    assert_eq!(x["mounts"].as_array().unwrap().len(), 1);
+    assert_eq!(x["mounts"][0]["fstype"], "vfat");
+    assert_eq!(x["ignored_classes"], serde_json::json!(["ContainerOverlay"]));
+
+    let r = &redacted["components"]["xmount"];
+    assert_eq!(r["targets"][0], omnitrace_core::debug::REDACTED);
+    assert_eq!(r["mounts"][0]["mount_point"], omnitrace_core::debug::REDACTED);
+    assert_eq!(r["mounts"][0]["fstype"], "vfat");
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn skipped_lines_and_read_errors_surface_in_diagnostics() {
+    let mountinfo = fixture_path("diagnostics");
+    write_mountinfo(&mountinfo, &[ROOT_LINE, "40 22 8:17 / /mnt/xmount-ut-diag rw,relatime - vfat /dev/sdb1 rw", "41 22 truncated", "garbage"]);
+
+    let mut sensor = XMount::new(XMountConfig::default().pulse(Duration::from_millis(10)).mountinfo_path(&mountinfo));
+    sensor.add("/mnt/xmount-ut-diag");
+    let (diag, debug) = (sensor.diagnostics(), sensor.debug_handle());
+    let (handle, sensor_task) = spawn_sensor(sensor, Arc::new(CallbackHub::<XMountEvent>::new()));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let malformed = diag.get("mountinfo:malformed").unwrap();
+    assert_eq!(debug.get().malformed_lines, 2);
+    assert!(malformed.count >= 2 && malformed.count.is_multiple_of(2), "two per read, {}", malformed.count);
+    assert!(matches!(malformed.last.downcast_ref::<XMountError>(), Some(XMountError::MalformedLine { field: "mount ID", .. })));
+    assert_eq!(debug.get().mounts.len(), 1, "the good lines still count");
+
+    std::fs::remove_file(&mountinfo).unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    handle.shutdown();
+    let _ = sensor_task.await;
+
+    let unreadable = diag.get("mountinfo").unwrap();
+    assert!(matches!(unreadable.last.downcast_ref::<XMountError>(), Some(XMountError::Mountinfo { .. })));
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn captured_parse_anomalies_carry_the_raw_line() {
+    let mountinfo = fixture_path("anomalies");
+    let lines =
+        [ROOT_LINE, "41 22 truncated", "40 22 8:17 / /mnt/xmount-ut-anomaly rw,relatime - vfat /dev/sdb1 rw", "x 22 8:1 / /a rw - ext4 /dev/a rw"];
+    write_mountinfo(&mountinfo, &lines);
+
+    let mut sensor = XMount::new(XMountConfig::default().pulse(Duration::from_millis(10)).mountinfo_path(&mountinfo).capture_parse_anomalies(1));
+    sensor.add("/mnt/xmount-ut-anomaly");
+    let debug = sensor.debug_handle();
+    let mut hub = CallbackHub::<XMountEvent>::new();
+    hub.add_fn(XMountMask::PARSE_ANOMALY.bits(), |ev| {
+        let ev = serde_json::to_value(ev).unwrap();
+        async move { Some(ev) }
+    });
+    let (tx, mut rx) = channel(16);
+    hub.set_result_channel(tx);
+    let (handle, sensor_task) = spawn_sensor(sensor, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(55)).await;
+    handle.shutdown();
+    let _ = sensor_task.await;
+    let _ = std::fs::remove_file(&mountinfo);
+
+    // one a read, the first; the other counted as dropped
+    let first = rx.try_recv().unwrap();
+    assert_eq!(
+        first,
+        serde_json::json!({ "ParseAnomaly": { "source": mountinfo.to_str().unwrap(), "raw": "41 22 truncated", "column": 4, "reason": "bad root" } })
+    );
+    let mut reads = 1;
+    while let Ok(ev) = rx.try_recv() {
+        assert_eq!(ev, first);
+        reads += 1;
+    }
+    let (d, parse) = (debug.get(), debug.get().parse);
+    assert_eq!(d.mounts.len(), 1, "the good lines still parse");
+    assert_eq!(d.malformed_lines, 2);
+    assert!(reads >= 2 && parse.captured == reads, "{parse:?}, {reads} reads");
+    assert_eq!((parse.failures, parse.dropped), (2 * reads, reads));
+}
+
+// -------------------------
+// ordering guarantees
+// -------------------------
+
+struct Recorder(Arc<Mutex<Vec<XMountEvent>>>);
+
+#[async_trait]
+impl Callback<XMountEvent> for Recorder {
+    fn mask(&self) -> u64 {
+        XMountMask::all().bits()
+    }
+
+    async fn call(&self, ev: &XMountEvent) -> Option<CallbackResult> {
+        self.0.lock().unwrap().push(ev.clone());
+        None
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn random_mountinfo_scripts_keep_per_target_order() {
+    let mountinfo = fixture_path("order");
+    let line = |(t, id, opts): &(&str, u32, &str)| format!("{id} 22 8:17 / {t} {opts},relatime - ext4 /dev/sdb1 rw");
+    let write = |mounts: &[(&str, u32, &str)]| {
+        let mut lines = vec![ROOT_LINE.to_string()];
+        lines.extend(mounts.iter().map(line));
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        write_mountinfo(&mountinfo, &lines);
+    };
+
+    let mut rng = fastrand::Rng::with_seed(715);
+    let start = random_mounts(&mut rng);
+    write(&start);
+
+    let mut sensor = XMount::new(XMountConfig::default().pulse(Duration::from_millis(3)).mountinfo_path(&mountinfo));
+    for t in ORDER_TARGETS {
+        sensor.add(t);
+    }
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(seen.clone()));
+    let (handle, sensor_task) = spawn_sensor(sensor, Arc::new(hub));
+    tokio::time::sleep(Duration::from_millis(30)).await;
+
+    let mut script = start.clone();
+    for _ in 0..40 {
+        script = random_mounts(&mut rng);
+        write(&script);
+        tokio::time::sleep(Duration::from_millis(rng.u64(1..8))).await;
+    }
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    handle.shutdown();
+    let _ = sensor_task.await;
+    let _ = std::fs::remove_file(&mountinfo);
+
+    // whatever ticks saw, every target's events form a valid sequence ending in the final state
+    let mut model: HashMap<PathBuf, MountInfo> = as_map(&start);
+    for ev in seen.lock().unwrap().iter() {
+        apply(&mut model, ev).unwrap();
+    }
+    let ids = |m: &HashMap<PathBuf, MountInfo>| m.iter().map(|(k, v)| (k.clone(), v.mount_id)).collect::<HashMap<_, _>>();
+    assert_eq!(ids(&model), ids(&as_map(&script)));
+}
+
+/// Watched mounts, sorted, as they show in a table.
+fn mount_state(mounts: &[MountInfo]) -> Vec<(String, u32, String)> {
+    let mut v: Vec<_> = mounts
+        .iter()
+        .filter(|mi| mi.mount_point != Path::new("/"))
+        .map(|mi| (mi.mount_point.display().to_string(), mi.mount_id, mi.mount_opts.to_string()))
+        .collect();
+    v.sort();
+    v
+}
+
+/// The sensor, stepped one table per tick, and a [`MountDiffer`] fed the same tables report
+/// the same events.
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn sensor_reports_what_the_engine_does() {
+    let mountinfo = fixture_path("engine");
+    let table = |mounts: &[(&str, u32, &str)]| {
+        let mut lines = vec![ROOT_LINE.to_string()];
+        lines.extend(mounts.iter().map(|(t, id, opts)| format!("{id} 22 8:17 / {t} {opts},relatime - ext4 /dev/sdb1 rw")));
+        lines
+    };
+    let mut rng = fastrand::Rng::with_seed(759);
+    let script: Vec<Vec<String>> = (0..40).map(|_| table(&random_mounts(&mut rng))).collect();
+
+    let mut differ = MountDiffer::new();
+    let mut sensor = XMount::new(XMountConfig::default().pulse(Duration::from_millis(3)).mountinfo_path(&mountinfo));
+    for t in ORDER_TARGETS {
+        differ.watch(t);
+        sensor.add(t);
+    }
+    let mut expected = Vec::new();
+    for lines in &script {
+        let (all, _) = engine::parse_mountinfo(lines.join("\n").as_bytes());
+        expected.extend(differ.feed(&all));
+    }
+
+    let debug = sensor.debug_handle();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(seen.clone()));
+    write_mountinfo(&mountinfo, &script[0].iter().map(String::as_str).collect::<Vec<_>>());
+    let (handle, sensor_task) = spawn_sensor(sensor, Arc::new(hub));
+    for lines in &script {
+        write_mountinfo(&mountinfo, &lines.iter().map(String::as_str).collect::<Vec<_>>());
+        // one tick per table: wait until the sensor holds it
+        let (all, _) = engine::parse_mountinfo(lines.join("\n").as_bytes());
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !debug.get().primed || mount_state(&debug.get().mounts) != mount_state(&all) {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+    handle.shutdown();
+    let _ = sensor_task.await;
+    let _ = std::fs::remove_file(&mountinfo);
+
+    let json = |evs: &[XMountEvent]| evs.iter().map(|ev| serde_json::to_value(ev).unwrap()).collect::<Vec<_>>();
+    assert!(expected.len() > 20, "{}", expected.len());
+    assert_eq!(json(&seen.lock().unwrap()), json(&expected));
+}
+
+#[tokio::test]
+async fn cli_filter_selects_synthetic_events() {
+    // what `xmount --filter '...'` sets up
+    let args = ["--filter", r#"fstype == "nfs" && target ~ "/mnt/*" || kind == "Unmounted""#];
+    let filter = omnitrace_core::filter::Filter::from_args(args).unwrap().unwrap();
+    let (tx, mut rx) = channel::<CallbackResult>(16);
+    let mut hub = CallbackHub::<XMountEvent>::new();
+    hub.add(JsonCb);
+    hub.set_result_channel(tx);
+    hub.set_filter(filter.predicate());
+
+    let nfs = |target: &str| XMountEvent::Mounted {
+        target: target.into(),
+        info: MountInfo { fstype: "nfs".into(), ..MountInfo::test(target) },
+        labels: Labels::default(),
+    };
+    for ev in [nfs("/mnt/share"), nfs("/srv/share"), XMountEvent::test_mounted("/mnt/usb"), XMountEvent::test_unmounted("/media/cd")] {
+        hub.inject(ev.mask().bits(), &ev).await;
+    }
+    drop(hub);
+
+    let mut got = Vec::new();
+    while let Some(r) = rx.recv().await {
+        got.push((r["event"].as_str().unwrap().to_string(), r["target"].as_str().unwrap().to_string()));
+    }
+    assert_eq!(got, vec![("mounted".to_string(), "/mnt/share".to_string()), ("unmounted".to_string(), "/media/cd".to_string())]);
+}
+
+/// Mounts `/mnt/xmount-ut-gap-{n}` during a simulated 9h suspend, then one more after it.
+async fn mount_across_a_suspend(reprime: bool) -> (Vec<serde_json::Value>, Option<TimeAnomaly>) {
+    let mountinfo = fixture_path(&format!("gap-{reprime}"));
+    let mount = |n| format!("{} 22 8:17 / /mnt/xmount-ut-gap-{n} rw,relatime - vfat /dev/sdb1 rw", 40 + n);
+    write_mountinfo(&mountinfo, &[ROOT_LINE]);
+    let clock = ManualClock::new();
+    let cfg = XMountConfig::default().pulse(Duration::from_millis(10)).mountinfo_path(&mountinfo).clock(clock.shared());
+    let sensor = XMount::new(cfg.time_gaps(TimeGaps::default().reprime(reprime)));
+    for n in 1..=2 {
+        sensor.control().add(format!("/mnt/xmount-ut-gap-{n}"));
+    }
+    let diagnostics = sensor.diagnostics();
+    let (tx, mut rx) = channel::<CallbackResult>(4);
+    let mut hub = CallbackHub::<XMountEvent>::new();
+    hub.add(JsonCb);
+    hub.set_result_channel(tx);
+    let (handle, sensor_task) = spawn_sensor(sensor, Arc::new(hub));
+
+    let tick = || async {
+        clock.advance(Duration::from_millis(10));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+    for _ in 0..3 {
+        tick().await;
+    }
+    // the sensor only runs while the test awaits: both land in the same tick
+    clock.suspend(Duration::from_secs(9 * 3600));
+    write_mountinfo(&mountinfo, &[ROOT_LINE, &mount(1)]);
+    tick().await;
+    write_mountinfo(&mountinfo, &[ROOT_LINE, &mount(1), &mount(2)]);
+    tick().await;
+    handle.shutdown();
+    let _ = sensor_task.await;
+    let _ = std::fs::remove_file(&mountinfo);
+
+    let mut events = Vec::new();
+    while let Ok(ev) = rx.try_recv() {
+        events.push(ev["target"].clone());
+    }
+    let gap = diagnostics.get("time").and_then(|d| d.last.downcast_ref::<TimeAnomaly>().copied());
+    (events, gap)
+}
+
+#[tokio::test(start_paused = true)]
+async fn suspend_gaps_are_diagnosed_and_can_reprime() {
+    let suspend = TimeAnomaly { kind: TimeGapKind::Suspend, magnitude: Duration::from_secs(9 * 3600), backward: false };
+
+    let (events, gap) = mount_across_a_suspend(false).await;
+    assert_eq!(events, ["/mnt/xmount-ut-gap-1", "/mnt/xmount-ut-gap-2"]);
+    assert_eq!(gap, Some(suspend));
+
+    // re-primed: what changed during the suspend is the new baseline
+    let (events, gap) = mount_across_a_suspend(true).await;
+    assert_eq!(events, ["/mnt/xmount-ut-gap-2"]);
+    assert_eq!(gap, Some(suspend));
+}
+
+#[derive(Clone, Copy)]
+enum Edit {
+    Keep,
+    Remove,
+    Add,
+}
+
+/// Drive `/mnt/xmount-ut-edit` through `steps`, one tick each: an edit of the watched set, and
+/// whether it is mounted. The first step is the state at startup. With `anchored`, `/` stays
+/// watched throughout, so removing the mountpoint does not idle the sensor. Returns the topics
+/// fired for it.
+async fn watch_edits(name: &str, anchored: bool, cfg: XMountConfig, steps: &[(Edit, bool)]) -> Vec<&'static str> {
+    const TARGET: &str = "/mnt/xmount-ut-edit";
+    let mountinfo = fixture_path(&format!("edit-{name}"));
+    let write = |mounted: bool| {
+        let line = "40 22 8:17 / /mnt/xmount-ut-edit rw,relatime - ext4 /dev/sdb1 rw";
+        write_mountinfo(&mountinfo, &[ROOT_LINE, line][..if mounted { 2 } else { 1 }]);
+    };
+    write(steps[0].1);
+    let clock = ManualClock::new();
+    let sensor = XMount::new(cfg.pulse(Duration::from_millis(10)).mountinfo_path(&mountinfo).clock(clock.shared()));
+    let control = sensor.control();
+    control.add(TARGET);
+    if anchored {
+        control.add("/");
+    }
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut hub = CallbackHub::new();
+    hub.add(Recorder(seen.clone()));
+    let (handle, sensor_task) = spawn_sensor(sensor, Arc::new(hub));
+
+    for (edit, mounted) in steps {
+        match edit {
+            Edit::Keep => {}
+            Edit::Remove => control.remove(TARGET),
+            Edit::Add => control.add(TARGET),
+        }
+        write(*mounted);
+        clock.advance(Duration::from_millis(10));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    handle.shutdown();
+    let _ = sensor_task.await;
+    let _ = std::fs::remove_file(&mountinfo);
+
+    let seen = seen.lock().unwrap();
+    seen.iter().filter(|ev| ev.target() == Path::new(TARGET)).map(XMountEvent::topic).collect()
+}
+
+#[tokio::test(start_paused = true)]
+async fn removing_a_watch_drops_its_state_without_events() {
+    use Edit::*;
+    for anchored in [true, false] {
+        // unmounted while not watched: no Unmounted, then or when watched again
+        let steps = [(Keep, true), (Remove, true), (Keep, false), (Add, false), (Keep, true)];
+        assert_eq!(watch_edits("present", anchored, XMountConfig::default(), &steps).await, ["mount.mounted"], "anchored {anchored}");
+
+        // mounted while not watched: watched again, that is the baseline, not a Mounted
+        let steps = [(Keep, false), (Remove, false), (Keep, true), (Add, true), (Keep, false)];
+        assert_eq!(watch_edits("absent", anchored, XMountConfig::default(), &steps).await, ["mount.unmounted"], "anchored {anchored}");
+
+        // removed and added back in one tick: nothing happened
+        let steps = [(Keep, true), (Remove, true), (Add, true), (Keep, true), (Keep, false)];
+        assert_eq!(watch_edits("readd", anchored, XMountConfig::default(), &steps).await, ["mount.unmounted"], "anchored {anchored}");
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn tombstones_report_what_happened_while_unwatched() {
+    use Edit::*;
+    let cfg = || XMountConfig::default().report_unwatched(Duration::from_millis(100));
+    for anchored in [true, false] {
+        let steps = [(Keep, true), (Remove, true), (Keep, false), (Add, false), (Keep, true)];
+        assert_eq!(watch_edits("tomb-present", anchored, cfg(), &steps).await, ["mount.unmounted", "mount.mounted"], "anchored {anchored}");
+
+        let steps = [(Keep, false), (Remove, false), (Keep, true), (Add, true), (Keep, false)];
+        assert_eq!(watch_edits("tomb-absent", anchored, cfg(), &steps).await, ["mount.mounted", "mount.unmounted"], "anchored {anchored}");
+
+        // nothing happened meanwhile
+        let steps = [(Keep, true), (Remove, true), (Keep, true), (Add, true)];
+        assert!(watch_edits("tomb-same", anchored, cfg(), &steps).await.is_empty(), "anchored {anchored}");
+    }
+
+    // past the ttl the tombstone is gone: a silent re-prime
+    let mut steps = vec![(Keep, true), (Remove, true), (Keep, false)];
+    steps.extend([(Keep, false); 12]);
+    steps.push((Add, false));
+    assert!(watch_edits("tomb-expired", true, cfg(), &steps).await.is_empty());
+}
+
+#[test]
+fn every_documented_field_resolves() {
+    let info = MountInfo { fstype: "nfs".into(), source: "srv:/export".into(), ..MountInfo::test("/mnt/data") };
+    let target = PathBuf::from("/mnt/data");
+    let samples = [
+        XMountEvent::Mounted { target: target.clone(), info: info.clone(), labels: Labels::default() },
+        XMountEvent::Unmounted {
+            target: target.clone(),
+            last: info.clone(),
+            reason: Some(UnmountReason::AutomountExpired),
+            labels: Labels::default(),
+        },
+        XMountEvent::Changed {
+            target: target.clone(),
+            old: MountInfo { fstype: "ext4".into(), ..info.clone() },
+            new: info.clone(),
+            labels: Labels::default(),
+        },
+        XMountEvent::WillUnmount {
+            target: target.clone(),
+            info: info.clone(),
+            reason: UnmountPrecursor::SystemdDeactivating,
+            labels: Labels::default(),
+        },
+        XMountEvent::AutomountArmed { target: target.clone(), info: info.clone(), labels: Labels::default() },
+        XMountEvent::FsHealthChanged {
+            target: target.clone(),
+            fstype: "btrfs".into(),
+            old: FsHealth::Healthy,
+            new: FsHealth::Degraded,
+            details: Vec::new(),
+            labels: Labels::default(),
+        },
+    ];
+
+    for ev in &samples {
+        let json = serde_json::to_value(ev).unwrap();
+        let (variant, body) = json.as_object().unwrap().iter().next().unwrap();
+        assert!(fields::same_kind(ev.kind(), variant));
+        for name in ev.field_names() {
+            let v = ev.field(name).unwrap_or_else(|| panic!("{}: {name}", ev.kind()));
+            // the same value as serialized, found where the filter would look
+            if let Some(plain) = body.get(*name).or_else(|| body.as_object().unwrap().values().find_map(|o| o.get(*name))) {
+                assert_eq!(&v.to_json(), plain, "{}: {name}", ev.kind());
+            }
+        }
+    }
+
+    let changed = &samples[2];
+    assert_eq!(changed.field("fstype"), Some(FieldValue::str("nfs")));
+    assert_eq!(changed.field("new.fstype"), Some(FieldValue::str("nfs")));
+    assert_eq!(changed.field("old.fstype"), Some(FieldValue::str("ext4")));
+    assert_eq!(samples[0].field("info.source"), Some(FieldValue::str("srv:/export")));
+    assert_eq!(samples[1].field("reason"), Some(FieldValue::str("automount_expired")));
+    assert_eq!(samples[3].field("reason"), Some(FieldValue::str("SystemdDeactivating")));
+    assert_eq!(XMountEvent::test_unmounted("/a").field("reason"), None);
+}
+
+#[test]
+fn every_topic_is_emitted_with_the_variant_mask() {
+    let info = MountInfo::test("/mnt/data");
+    let target = PathBuf::from("/mnt/data");
+    let changed = |new: MountInfo| XMountEvent::Changed { target: target.clone(), old: info.clone(), new, labels: Labels::default() };
+    let health = |new| XMountEvent::FsHealthChanged {
+        target: target.clone(),
+        fstype: "btrfs".into(),
+        old: FsHealth::Healthy,
+        new,
+        details: vec![],
+        labels: Labels::default(),
+    };
+    let deviation = |deviation| XMountEvent::Deviation { target: target.clone(), deviation, info: None, labels: Labels::default() };
+    let samples = [
+        XMountEvent::test_mounted("/mnt/data"),
+        XMountEvent::test_unmounted("/mnt/data"),
+        changed(MountInfo { mount_opts: "ro,relatime".into(), ..info.clone() }),
+        changed(MountInfo { source: "/dev/other".into(), mount_opts: "ro".into(), ..info.clone() }),
+        changed(MountInfo { class: MountClass::Other, ..info.clone() }),
+        XMountEvent::WillUnmount { target: target.clone(), info: info.clone(), reason: UnmountPrecursor::ReadOnly, labels: Labels::default() },
+        XMountEvent::AutomountArmed { target: target.clone(), info: info.clone(), labels: Labels::default() },
+        health(FsHealth::Healthy),
+        health(FsHealth::Degraded),
+        health(FsHealth::Faulted),
+        health(FsHealth::Unknown),
+        deviation(Deviation::Missing),
+        deviation(Deviation::Unexpected),
+        deviation(Deviation::mismatch("fstype", "xfs", "ext4")),
+        XMountEvent::ParseAnomaly {
+            anomaly: ParseAnomaly::new("/proc/self/mountinfo", "41 22 truncated", ParseFailure::new(4, "bad root")),
+            labels: Labels::default(),
+        },
+    ];
+
+    let topics: Vec<&str> = samples.iter().map(XMountEvent::topic).collect();
+    let table: Vec<&str> = XMountEvent::TOPICS.iter().map(|t| t.name).collect();
+    assert_eq!(topics, table);
+    for ev in &samples {
+        assert_eq!(XMountEvent::TOPICS[ev.topic_index()].mask, ev.mask().bits(), "{}", ev.topic());
+        assert_eq!(EventFields::topic(ev), Some(ev.topic()));
+    }
+    let changed = XMountMask::CHANGED | XMountMask::CHANGED_OPTIONS | XMountMask::CHANGED_SOURCE | XMountMask::CHANGED_OTHER;
+    assert_eq!(topics::mask::<XMountEvent>("mount.changed").unwrap(), changed.bits());
+    assert_eq!(topics::mask::<XMountEvent>("mount.{mounted,unmounted}").unwrap(), (XMountMask::MOUNTED | XMountMask::UNMOUNTED).bits());
+}
+
+#[test]
+fn masks_follow_the_documented_bit_layout() {
+    let info = MountInfo::test("/mnt/data");
+    let target = PathBuf::from("/mnt/data");
+    let changed = |new: MountInfo| XMountEvent::Changed { target: target.clone(), old: info.clone(), new, labels: Labels::default() };
+    let cases = [
+        (XMountEvent::test_mounted("/mnt/data"), XMountMask::MOUNTED),
+        (XMountEvent::test_unmounted("/mnt/data"), XMountMask::UNMOUNTED),
+        (changed(MountInfo { mount_opts: "ro".into(), ..info.clone() }), XMountMask::CHANGED | XMountMask::CHANGED_OPTIONS),
+        (changed(MountInfo { super_opts: "ro".into(), ..info.clone() }), XMountMask::CHANGED | XMountMask::CHANGED_OPTIONS),
+        (changed(MountInfo { source: "/dev/other".into(), ..info.clone() }), XMountMask::CHANGED | XMountMask::CHANGED_SOURCE),
+        (changed(MountInfo { fstype: "xfs".into(), mount_opts: "ro".into(), ..info.clone() }), XMountMask::CHANGED | XMountMask::CHANGED_SOURCE),
+        (changed(MountInfo { root: "/sub".into(), ..info.clone() }), XMountMask::CHANGED | XMountMask::CHANGED_SOURCE),
+        (changed(MountInfo { class: MountClass::Other, ..info.clone() }), XMountMask::CHANGED | XMountMask::CHANGED_OTHER),
+        (
+            XMountEvent::WillUnmount { target: target.clone(), info: info.clone(), reason: UnmountPrecursor::ReadOnly, labels: Labels::default() },
+            XMountMask::WILL_UNMOUNT,
+        ),
+        (XMountEvent::AutomountArmed { target: target.clone(), info: info.clone(), labels: Labels::default() }, XMountMask::AUTOMOUNT_ARMED),
+        (
+            XMountEvent::FsHealthChanged {
+                target: target.clone(),
+                fstype: "btrfs".into(),
+                old: FsHealth::Healthy,
+                new: FsHealth::Faulted,
+                details: vec![],
+                labels: Labels::default(),
+            },
+            XMountMask::FS_HEALTH_CHANGED,
+        ),
+        (
+            XMountEvent::Deviation { target: target.clone(), deviation: Deviation::Missing, info: None, labels: Labels::default() },
+            XMountMask::DEVIATION,
+        ),
+    ];
+
+    for (ev, want) in &cases {
+        let bits = ev.mask().bits();
+        assert_eq!(bits, want.bits(), "{}", ev.topic());
+        assert_eq!((bits & KIND_BITS).count_ones(), 1, "one kind: {}", ev.topic());
+        assert_eq!(bits & RESERVED_BITS, 0);
+    }
+    assert_eq!(XMountMask::all().bits() & RESERVED_BITS, 0);
+    assert_eq!(XMountMask::CHANGED_OPTIONS.bits() & SUBKIND_BITS, XMountMask::CHANGED_OPTIONS.bits());
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn preflight_checks_the_table_and_the_watches() {
+    let severities = |f: &[PreflightFinding]| f.iter().map(|f| f.severity).collect::<Vec<_>>();
+
+    let missing = XMount::new(XMountConfig::default().mountinfo_path("/nonexistent/mountinfo"));
+    let findings = missing.preflight().await;
+    assert_eq!(severities(&findings), [Severity::Critical, Severity::Warning], "{findings:?}");
+    assert!(findings[0].message.starts_with("cannot read /nonexistent/mountinfo"));
+    assert_eq!(findings[1].message, "no mount points watched");
+
+    let empty = fixture_path("preflight-empty");
+    std::fs::write(&empty, "").unwrap();
+    let findings = XMount::new(XMountConfig::default().mountinfo_path(&empty).exit_if_empty(true)).preflight().await;
+    assert_eq!(severities(&findings), [Severity::Critical, Severity::Critical]);
+    assert!(findings[0].remedy.as_deref().unwrap().contains("mountinfo_path"));
+
+    let table = fixture_path("preflight");
+    write_mountinfo(&table, &[ROOT_LINE, "garbage"]);
+    let mut sensor = XMount::new(XMountConfig::default().mountinfo_path(&table));
+    sensor.add("/");
+    sensor.add("/nonexistent/usb");
+    sensor.add(std::env::temp_dir());
+    let findings = sensor.preflight().await;
+    let _ = std::fs::remove_file(&empty);
+    let _ = std::fs::remove_file(&table);
+
+    assert_eq!(severities(&findings), [Severity::Warning, Severity::Warning, Severity::Info], "{findings:?}");
+    assert!(findings[0].message.starts_with("1 malformed lines in"));
+    assert_eq!(findings[1].message, "watched /nonexistent/usb does not exist");
+    assert!(findings[2].message.ends_with("is not mounted now"));
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn previews_rules_against_the_last_table_without_applying_them() {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/k8s-node.mountinfo");
+    let mut sensor = XMount::new(XMountConfig::default().pulse(Duration::from_millis(10)).mountinfo_path(fixture));
+    for target in ["/mnt/shared", "/mnt/cache", "/data"] {
+        sensor.add(target);
+    }
+    let (control, previews) = (sensor.control(), sensor.preview_handle());
+    let (handle, task) = spawn_sensor(sensor, Arc::new(CallbackHub::<XMountEvent>::new()));
+
+    let preview = async |rule: &str| previews.preview_rule(rule.parse::<XMountRule>().unwrap()).await.unwrap();
+    let tmpfs = preview("ignore-fstype tmpfs").await;
+    assert_eq!((tmpfs.would_match, tmpfs.would_stop_matching), (6, 1));
+    assert_eq!(tmpfs.samples[..2], ["/dev/shm", "/mnt/cache"]);
+
+    let network = preview("ignore-class NetworkFs").await;
+    // the CSI volume is the kubelet's
+    assert_eq!((network.would_match, network.would_stop_matching), (1, 1));
+    assert_eq!(network.samples, ["/mnt/shared"]);
+
+    let mnt = preview("ignore-path /mnt/*").await;
+    assert_eq!((mnt.would_match, mnt.would_stop_matching), (2, 2));
+
+    let docker = preview("watch /var/lib/docker/overlay2/9d8e/merged").await;
+    assert_eq!((docker.would_match, docker.would_stop_matching, docker.samples.len()), (1, 0, 1));
+    assert_eq!(preview("watch /mnt/usb").await.would_match, 0);
+
+    // nothing was applied
+    assert_eq!(control.watched().len(), 3);
+    assert_eq!(preview("ignore-fstype tmpfs").await, tmpfs);
+
+    handle.shutdown();
+    let _ = task.await;
+    assert_eq!(previews.preview_rule(XMountRule::IgnoreFstype("nfs4".into())).await, None);
+}
+
+#[test]
+fn preview_rules_parse() {
+    assert_eq!("watch /mnt/usb".parse(), Ok(XMountRule::Watch("/mnt/usb".into())));
+    assert_eq!("ignore-class ContainerOverlay".parse(), Ok(XMountRule::IgnoreClass(MountClass::ContainerOverlay)));
+    assert!("ignore-class Floppy".parse::<XMountRule>().unwrap_err().to_string().contains("no mount class \"Floppy\""));
+    assert_eq!("ignore-path /mnt/*".parse(), Ok(XMountRule::IgnorePath(globset::Glob::new("/mnt/*").unwrap())));
+    assert!("ignore-path /mnt/[".parse::<XMountRule>().unwrap_err().to_string().starts_with("invalid argument to ignore-path: "));
+    assert!("mount /dev/sdb1".parse::<XMountRule>().unwrap_err().to_string().starts_with("unknown rule \"mount\""));
+}
+
+#[test]
+fn events_format_as_pinned_lines() {
+    let info = MountInfo { fstype: "nfs".into(), source: "srv:/export".into(), ..MountInfo::test("/mnt/data") };
+    let target = PathBuf::from("/mnt/data");
+    let mounted = XMountEvent::Mounted { target: target.clone(), info: info.clone(), labels: Labels::default() };
+    let changed = XMountEvent::Changed {
+        target: target.clone(),
+        old: MountInfo { fstype: "ext4".into(), source: "/dev/sdb1".into(), ..info.clone() },
+        new: info.clone(),
+        labels: Labels::default(),
+    };
+    let unmounted = XMountEvent::Unmounted { target: target.clone(), last: info.clone(), reason: None, labels: Labels::default() };
+    let lines: Vec<String> = [&mounted, &changed, &unmounted].into_iter().flat_map(|ev| [format_short(ev), format_long(ev)]).collect();
+    assert_eq!(
+        lines,
+        [
+            "mounted target=/mnt/data source=srv:/export fstype=nfs",
+            "mounted target=/mnt/data mount_point=/mnt/data source=srv:/export fstype=nfs mount_opts=rw,relatime super_opts=rw \
+             class=BlockDevice mount_id=1000 parent_id=1 root=/",
+            "changed target=/mnt/data old.source=/dev/sdb1 new.source=srv:/export old.fstype=ext4 new.fstype=nfs",
+            "changed target=/mnt/data mount_point=/mnt/data old.source=/dev/sdb1 new.source=srv:/export old.fstype=ext4 new.fstype=nfs \
+             old.mount_opts=rw,relatime new.mount_opts=rw,relatime old.super_opts=rw new.super_opts=rw class=BlockDevice mount_id=1000 \
+             parent_id=1 root=/",
+            // no reason: left out
+            "unmounted target=/mnt/data source=srv:/export fstype=nfs",
+            "unmounted target=/mnt/data mount_point=/mnt/data source=srv:/export fstype=nfs mount_opts=rw,relatime super_opts=rw \
+             class=BlockDevice mount_id=1000 parent_id=1 root=/",
+        ]
+    );
+}